@@ -0,0 +1,106 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/06
+*/
+
+//解析性能基准: cargo bench --bench parse
+//  header_scan: 只读取事件头
+//  row_decode:  BinlogReader完整解析与parse_noalloc的对比
+//  json:        行数据按--outformat json格式序列化
+//测试数据由synthesize生成, 写入临时目录
+
+use std::env;
+use std::fs;
+use std::io::{self, Cursor};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use mytest::noalloc::parse_noalloc;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn synth_file() -> Vec<u8> {
+    let opts = SynthOptions{
+        database: String::from("bench"),
+        tables: 4,
+        columns: vec![ColumnKind::BigInt, ColumnKind::Int, ColumnKind::Varchar(64), ColumnKind::Double,
+                      ColumnKind::Datetime, ColumnKind::Text],
+        transactions: 20000,
+        rows: (1, 10),
+        rate: 1000,
+        mix: [6, 3, 1],
+        start_time: 1572998400,
+        seed: 1,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-bench-{}.binlog", std::process::id()));
+    let path_str = path.to_str().unwrap();
+    synthesize::write_file(path_str, &opts).unwrap();
+    let data = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    data
+}
+
+fn header_scan(c: &mut Criterion, data: &[u8]) {
+    let mut group = c.benchmark_group("header_scan");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("noalloc", |b| b.iter(|| {
+        parse_noalloc(data).unwrap().map(|e| e.unwrap().header.event_length as u64).sum::<u64>()
+    }));
+    group.finish();
+}
+
+fn row_decode(c: &mut Criterion, data: &[u8]) {
+    let mut group = c.benchmark_group("row_decode");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("reader", |b| b.iter(|| {
+        let reader = BinlogReader::new(Cursor::new(data)).unwrap();
+        reader.filter_map(|e| match e.unwrap().data {
+            Traction::RowEvent(_, rows) => Some(rows.rows().len()),
+            _ => None
+        }).sum::<usize>()
+    }));
+    group.bench_function("noalloc", |b| b.iter(|| {
+        let mut map = None;
+        let mut cells = 0usize;
+        for event in parse_noalloc(data).unwrap() {
+            let event = event.unwrap();
+            match event.header.type_code {
+                BinlogEvent::TableMapEvent => map = Some(event.table_map().unwrap()),
+                BinlogEvent::WriteEvent |
+                BinlogEvent::UpdateEvent |
+                BinlogEvent::DeleteEvent => {
+                    for cell in event.rows(map.as_ref().unwrap()).unwrap() {
+                        cell.unwrap();
+                        cells += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        cells
+    }));
+    group.finish();
+}
+
+fn json(c: &mut Criterion, data: &[u8]) {
+    let events: Vec<Event> = BinlogReader::new(Cursor::new(data)).unwrap().map(|e| e.unwrap()).collect();
+    let mut group = c.benchmark_group("json");
+    group.throughput(Throughput::Elements(events.len() as u64));
+    group.bench_function("json_lines_sink", |b| b.iter(|| {
+        let mut sink = JsonLinesSink::new(io::sink(), ValueFormat::default());
+        for event in &events {
+            sink.write_event(event).unwrap();
+        }
+        sink.flush().unwrap();
+    }));
+    group.finish();
+}
+
+fn benches(c: &mut Criterion) {
+    let data = synth_file();
+    header_scan(c, &data);
+    row_decode(c, &data);
+    json(c, &data);
+}
+
+criterion_group!(parse, benches);
+criterion_main!(parse);
@@ -0,0 +1,22 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/09
+*/
+
+//根据src/lib.rs中的extern "C"函数生成include/binlogal.h
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("生成binlogal.h失败")
+        .write_to_file(crate_dir.join("include").join("binlogal.h"));
+}
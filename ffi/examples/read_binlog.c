@@ -0,0 +1,33 @@
+/*
+ * 读取binlog文件并逐行输出事件的json
+ *
+ *   cargo build --release
+ *   cc examples/read_binlog.c -Iinclude -Ltarget/release -lbinlogal -o read_binlog
+ *   LD_LIBRARY_PATH=target/release ./read_binlog bin.000001
+ */
+#include <stdio.h>
+#include "binlogal.h"
+
+int main(int argc, char **argv) {
+    if (argc < 2) {
+        fprintf(stderr, "usage: %s <binlog file>\n", argv[0]);
+        return 2;
+    }
+    BinlogalReader *reader = binlogal_reader_open(argv[1], 0, "all");
+    if (reader == NULL) {
+        fprintf(stderr, "%s\n", binlogal_last_error());
+        return 1;
+    }
+    char *event;
+    size_t len;
+    int ret;
+    while ((ret = binlogal_next_event(reader, &event, &len)) == 1) {
+        printf("%.*s\n", (int)len, event);
+        binlogal_buffer_free(event);
+    }
+    if (ret < 0) {
+        fprintf(stderr, "%s\n", binlogal_last_error());
+    }
+    binlogal_reader_free(reader);
+    return ret < 0 ? 1 : 0;
+}
@@ -0,0 +1,67 @@
+#ifndef BINLOGAL_H
+#define BINLOGAL_H
+
+/* 由cbindgen根据ffi/src/lib.rs生成, 不要手动修改 */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// 读取binlog的句柄, 由binlogal_reader_open或binlogal_reader_from_buffer创建, binlogal_reader_free释放
+typedef struct BinlogalReader BinlogalReader;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// 打开binlog文件, relaylog不为0时按relay log读取, verify_checksum与命令行的--verify-checksum相同(all、none、rows,table_map...), 为NULL时不校验
+// 失败返回NULL
+//
+// # Safety
+// path、verify_checksum为NULL或以\0结尾的字符串
+struct BinlogalReader *binlogal_reader_open(const char *path,
+                                            int relaylog,
+                                            const char *verify_checksum);
+
+// 从内存中的binlog数据读取, 数据会被复制, 调用之后可以释放data
+// 失败返回NULL
+//
+// # Safety
+// data指向至少len字节的内存, verify_checksum为NULL或以\0结尾的字符串
+struct BinlogalReader *binlogal_reader_from_buffer(const uint8_t *data,
+                                                   size_t len,
+                                                   int relaylog,
+                                                   const char *verify_checksum);
+
+// 读取下一个事件, 以json格式写入*out(以\0结尾, *out_len不包含\0), 使用完后调用binlogal_buffer_free释放
+// 返回1: 读取到事件, 0: 读取结束, -1: 发生错误(文件损坏、checksum错误等), 错误之后不能继续读取
+//
+// # Safety
+// reader为binlogal_reader_*返回的有效句柄, out、out_len为有效的指针
+int binlogal_next_event(struct BinlogalReader *reader,
+                        char **out,
+                        size_t *out_len);
+
+// 释放binlogal_next_event返回的数据, data为NULL时不做任何操作
+//
+// # Safety
+// data为binlogal_next_event返回且未释放过的指针
+void binlogal_buffer_free(char *data);
+
+// 关闭并释放句柄, reader为NULL时不做任何操作
+//
+// # Safety
+// reader为binlogal_reader_*返回且未释放过的句柄
+void binlogal_reader_free(struct BinlogalReader *reader);
+
+// 当前线程最后一次调用失败的错误信息, 没有错误时返回NULL
+// 返回的指针在当前线程下一次调用binlogal_*函数之前有效, 不需要释放
+const char *binlogal_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BINLOGAL_H */
@@ -0,0 +1,178 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/09
+*/
+
+//c接口: 打开binlog文件或内存中的binlog数据, 逐个读取事件, 每个事件序列化为一个json对象
+//  返回的指针都需要调用对应的free函数释放
+//  失败时返回NULL或-1, 通过binlogal_last_error获取错误信息(每个线程单独记录)
+//  事件的json格式见mytest::stdout::outjson::event_json
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use mytest::prelude::*;
+use mytest::stdout::outjson;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(msg: String) {
+    let msg = CString::new(msg.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+fn clear_error() {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+}
+
+//panic不能跨越c接口, 转换为错误返回
+fn guard<T>(f: impl FnOnce() -> Result<T, String>) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(t)) => Some(t),
+        Ok(Err(e)) => {
+            set_error(e);
+            None
+        }
+        Err(_) => {
+            set_error(String::from("解析过程中发生panic"));
+            None
+        }
+    }
+}
+
+unsafe fn c_str(value: *const c_char, default: &str) -> Result<&str, String> {
+    if value.is_null() {
+        return Ok(default);
+    }
+    CStr::from_ptr(value).to_str().map_err(|_| String::from("参数不是有效的utf8字符串"))
+}
+
+/// 读取binlog的句柄, 由binlogal_reader_open或binlogal_reader_from_buffer创建, binlogal_reader_free释放
+pub struct BinlogalReader{
+    events: Box<dyn Iterator<Item = io::Result<Event>>>,
+    format: ValueFormat,
+}
+
+fn reader<R: io::Read + io::Seek + 'static>(reader: BinlogReader<R>, relaylog: c_int, verify_checksum: &str) -> Result<*mut BinlogalReader, String> {
+    let classes = ChecksumClass::parse(verify_checksum)?;
+    let events = reader.relaylog(relaylog != 0).verify_checksum(classes);
+    Ok(Box::into_raw(Box::new(BinlogalReader{ events: Box::new(events), format: ValueFormat::default() })))
+}
+
+/// 打开binlog文件, relaylog不为0时按relay log读取, verify_checksum与命令行的--verify-checksum相同(all、none、rows,table_map...), 为NULL时不校验
+/// 失败返回NULL
+///
+/// # Safety
+/// path、verify_checksum为NULL或以\0结尾的字符串
+#[no_mangle]
+pub unsafe extern "C" fn binlogal_reader_open(path: *const c_char, relaylog: c_int, verify_checksum: *const c_char) -> *mut BinlogalReader {
+    clear_error();
+    guard(|| {
+        if path.is_null() {
+            return Err(String::from("path不能为NULL"));
+        }
+        let path = c_str(path, "")?;
+        let verify_checksum = c_str(verify_checksum, "none")?;
+        let file: BinlogReader<BufReader<File>> = BinlogReader::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        reader(file, relaylog, verify_checksum)
+    }).unwrap_or(ptr::null_mut())
+}
+
+/// 从内存中的binlog数据读取, 数据会被复制, 调用之后可以释放data
+/// 失败返回NULL
+///
+/// # Safety
+/// data指向至少len字节的内存, verify_checksum为NULL或以\0结尾的字符串
+#[no_mangle]
+pub unsafe extern "C" fn binlogal_reader_from_buffer(data: *const u8, len: usize, relaylog: c_int, verify_checksum: *const c_char) -> *mut BinlogalReader {
+    clear_error();
+    guard(|| {
+        if data.is_null() {
+            return Err(String::from("data不能为NULL"));
+        }
+        let verify_checksum = c_str(verify_checksum, "none")?;
+        let buf = slice::from_raw_parts(data, len).to_vec();
+        let buffer = BinlogReader::new(Cursor::new(buf)).map_err(|e| e.to_string())?;
+        reader(buffer, relaylog, verify_checksum)
+    }).unwrap_or(ptr::null_mut())
+}
+
+/// 读取下一个事件, 以json格式写入*out(以\0结尾, *out_len不包含\0), 使用完后调用binlogal_buffer_free释放
+/// 返回1: 读取到事件, 0: 读取结束, -1: 发生错误(文件损坏、checksum错误等), 错误之后不能继续读取
+///
+/// # Safety
+/// reader为binlogal_reader_*返回的有效句柄, out、out_len为有效的指针
+#[no_mangle]
+pub unsafe extern "C" fn binlogal_next_event(reader: *mut BinlogalReader, out: *mut *mut c_char, out_len: *mut usize) -> c_int {
+    clear_error();
+    if reader.is_null() || out.is_null() || out_len.is_null() {
+        set_error(String::from("reader、out、out_len不能为NULL"));
+        return -1;
+    }
+    *out = ptr::null_mut();
+    *out_len = 0;
+    let reader = &mut *reader;
+    let result = guard(|| {
+        match reader.events.next() {
+            Some(Ok(event)) => {
+                let json = outjson::event_json(&event, &reader.format).to_string();
+                Ok(Some(json))
+            }
+            Some(Err(e)) => {
+                //出错之后不再读取
+                reader.events = Box::new(std::iter::empty());
+                Err(e.to_string())
+            }
+            None => Ok(None)
+        }
+    });
+    match result {
+        Some(Some(json)) => {
+            *out_len = json.len();
+            //json中的字符串都经过转义, 不会包含\0
+            *out = CString::new(json).unwrap_or_default().into_raw();
+            1
+        }
+        Some(None) => 0,
+        None => -1
+    }
+}
+
+/// 释放binlogal_next_event返回的数据, data为NULL时不做任何操作
+///
+/// # Safety
+/// data为binlogal_next_event返回且未释放过的指针
+#[no_mangle]
+pub unsafe extern "C" fn binlogal_buffer_free(data: *mut c_char) {
+    if !data.is_null() {
+        drop(CString::from_raw(data));
+    }
+}
+
+/// 关闭并释放句柄, reader为NULL时不做任何操作
+///
+/// # Safety
+/// reader为binlogal_reader_*返回且未释放过的句柄
+#[no_mangle]
+pub unsafe extern "C" fn binlogal_reader_free(reader: *mut BinlogalReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// 当前线程最后一次调用失败的错误信息, 没有错误时返回NULL
+/// 返回的指针在当前线程下一次调用binlogal_*函数之前有效, 不需要释放
+#[no_mangle]
+pub extern "C" fn binlogal_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match &*e.borrow() {
+        Some(t) => t.as_ptr(),
+        None => ptr::null()
+    })
+}
@@ -0,0 +1,104 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/09
+*/
+
+//通过c接口读取golden binlog文件
+
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::raw::c_char;
+use std::path::PathBuf;
+use std::ptr;
+use binlogal::*;
+
+fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("..").join("tests").join("fixtures").join(name)
+}
+
+fn last_error() -> String {
+    let err = binlogal_last_error();
+    assert!(!err.is_null());
+    unsafe { CStr::from_ptr(err) }.to_str().unwrap().to_string()
+}
+
+//读取全部事件, 返回事件的json以及最后一次调用的返回值
+fn read_all(reader: *mut BinlogalReader) -> (Vec<serde_json::Value>, i32) {
+    let mut events = vec![];
+    loop {
+        let mut out: *mut c_char = ptr::null_mut();
+        let mut len = 0;
+        let ret = unsafe { binlogal_next_event(reader, &mut out, &mut len) };
+        if ret != 1 {
+            assert!(out.is_null());
+            unsafe { binlogal_reader_free(reader) };
+            return (events, ret);
+        }
+        let json = unsafe { CStr::from_ptr(out) }.to_str().unwrap();
+        assert_eq!(json.len(), len);
+        events.push(serde_json::from_str(json).unwrap());
+        unsafe { binlogal_buffer_free(out) };
+    }
+}
+
+#[test]
+fn open_file() {
+    let path = CString::new(fixture_path("mysql57.binlog").to_str().unwrap()).unwrap();
+    let checksum = CString::new("all").unwrap();
+    let reader = unsafe { binlogal_reader_open(path.as_ptr(), 0, checksum.as_ptr()) };
+    assert!(!reader.is_null());
+    let (events, ret) = read_all(reader);
+    assert_eq!(ret, 0);
+    assert!(binlogal_last_error().is_null());
+    assert_eq!(events[0]["log_pos"], 4);
+    assert!(events.iter().any(|e| e["type"] == "gtid" && e["gtid"].as_str().unwrap().ends_with(":1")));
+
+    let insert = events.iter().find(|e| e["type"] == "insert").unwrap();
+    assert_eq!(insert["database"], "golden");
+    assert_eq!(insert["table"], "all_types");
+    assert_eq!(insert["rows"][0][0], 1);
+    assert_eq!(insert["rows"][0][13], "varchar 1");
+    let update = events.iter().find(|e| e["type"] == "update").unwrap();
+    assert_eq!(update["rows"][0]["before"][0], 1);
+    assert!(update["rows"][0]["after"].is_array());
+}
+
+#[test]
+fn from_buffer() {
+    let mut data = fs::read(fixture_path("mysql80.binlog")).unwrap();
+    let reader = unsafe { binlogal_reader_from_buffer(data.as_ptr(), data.len(), 0, ptr::null()) };
+    let (events, ret) = read_all(reader);
+    assert_eq!(ret, 0);
+
+    //截断之后返回已读取的事件及错误
+    data.truncate(data.len() - 3);
+    let reader = unsafe { binlogal_reader_from_buffer(data.as_ptr(), data.len(), 0, ptr::null()) };
+    drop(data);
+    let (truncated, ret) = read_all(reader);
+    assert_eq!(ret, -1);
+    assert_eq!(truncated.len(), events.len() - 1);
+    assert!(!last_error().is_empty());
+}
+
+#[test]
+fn errors() {
+    let path = CString::new("/nonexistent/bin.000001").unwrap();
+    assert!(unsafe { binlogal_reader_open(path.as_ptr(), 0, ptr::null()) }.is_null());
+    assert!(last_error().contains("/nonexistent/bin.000001"));
+
+    let data = b"not a binlog";
+    assert!(unsafe { binlogal_reader_from_buffer(data.as_ptr(), data.len(), 0, ptr::null()) }.is_null());
+
+    let path = CString::new(fixture_path("mysql57.binlog").to_str().unwrap()).unwrap();
+    let checksum = CString::new("rows,xid").unwrap();
+    assert!(unsafe { binlogal_reader_open(path.as_ptr(), 0, checksum.as_ptr()) }.is_null());
+    assert!(last_error().contains("xid"));
+
+    let mut out: *mut c_char = ptr::null_mut();
+    let mut len = 0;
+    assert_eq!(unsafe { binlogal_next_event(ptr::null_mut(), &mut out, &mut len) }, -1);
+    unsafe {
+        binlogal_reader_free(ptr::null_mut());
+        binlogal_buffer_free(ptr::null_mut());
+    }
+}
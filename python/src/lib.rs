@@ -0,0 +1,321 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/08
+*/
+
+//python模块binlogal: 读取binlog文件、通过主从协议拉取binlog, 事件以dict返回
+//  行数据按字段顺序返回, 与BinlogReader相同不包含字段名
+//  varchar/char/text/blob在binlog中都是字节, decode_strings为True时能够按utf8解码的返回str, 否则返回bytes
+
+//pyo3 0.22的create_exception!、#[pymethods]展开后产生的警告
+#![allow(unexpected_cfgs, clippy::useless_conversion)]
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyOSError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use mytest::Config;
+use mytest::prelude::*;
+use mytest::secret::Secret;
+
+create_exception!(binlogal, BinlogError, PyException, "binlog解析、校验或主从拉取发生错误");
+
+fn to_py_err(err: io::Error) -> PyErr {
+    match err.kind() {
+        io::ErrorKind::InvalidData |
+        io::ErrorKind::InvalidInput |
+        io::ErrorKind::UnexpectedEof |
+        io::ErrorKind::Other => BinlogError::new_err(err.to_string()),
+        _ => PyOSError::new_err(err.to_string())
+    }
+}
+
+//binlog中的小数部分为按存储位数保存的整数: fsp 1-2为2位, 3-4为4位, 5-6为6位, 转换为微秒; 不知道fsp时按微秒
+fn microsecond(subsecond: u32, fsp: usize) -> u32 {
+    match fsp {
+        1..=6 => subsecond * 10u32.pow((6 - fsp.div_ceil(2) * 2) as u32),
+        _ => subsecond
+    }
+}
+
+//fsp为时间类型的小数位数(TABLE_MAP中的column_meta[0])
+fn value_to_py(py: Python<'_>, value: &Option<MySQLValue>, fsp: usize, decode_strings: bool) -> PyResult<PyObject> {
+    let datetime = || py.import_bound("datetime");
+    Ok(match value {
+        None | Some(MySQLValue::Null) => py.None(),
+        Some(MySQLValue::SignedInteger(v)) => v.into_py(py),
+        //按最短表示转换, 避免float扩展为double后出现多余的小数位
+        Some(MySQLValue::Float(v)) => v.to_string().parse::<f64>().unwrap_or(*v as f64).into_py(py),
+        Some(MySQLValue::Double(v)) => v.into_py(py),
+        Some(MySQLValue::String(v)) => v.into_py(py),
+        Some(MySQLValue::Enum(v)) => v.into_py(py),
+        Some(MySQLValue::Year(v)) => v.into_py(py),
+        Some(MySQLValue::Blob(v)) => {
+            match std::str::from_utf8(v) {
+                Ok(t) if decode_strings => t.into_py(py),
+                _ => PyBytes::new_bound(py, v).into_py(py)
+            }
+        }
+        Some(MySQLValue::Decimal(v)) => py.import_bound("decimal")?.getattr("Decimal")?.call1((v.to_string(),))?.into_py(py),
+        Some(MySQLValue::Json(v)) => py.import_bound("json")?.getattr("loads")?.call1((v.to_string(),))?.into_py(py),
+        //未知类型的字段按原始字节返回
+        Some(MySQLValue::Opaque{ bytes, .. }) => PyBytes::new_bound(py, bytes).into_py(py),
+        //0000-00-00等python不支持的日期以字符串返回
+        Some(MySQLValue::Date{ year, month, day }) => {
+            match datetime()?.getattr("date")?.call1((*year, *month, *day)) {
+                Ok(t) => t.into_py(py),
+                Err(_) => format!("{:04}-{:02}-{:02}", year, month, day).into_py(py)
+            }
+        }
+        Some(MySQLValue::DateTime{ year, month, day, hour, minute, second, subsecond }) => {
+            match datetime()?.getattr("datetime")?.call1((*year, *month, *day, *hour, *minute, *second, microsecond(*subsecond, fsp))) {
+                Ok(t) => t.into_py(py),
+                Err(_) => format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, hour, minute, second).into_py(py)
+            }
+        }
+        Some(MySQLValue::Time{ negative, hours, minutes, seconds, subseconds }) => {
+            let kwargs = PyDict::new_bound(py);
+            kwargs.set_item("hours", *hours)?;
+            kwargs.set_item("minutes", *minutes)?;
+            kwargs.set_item("seconds", *seconds)?;
+            kwargs.set_item("microseconds", microsecond(*subseconds, fsp))?;
+            let delta = datetime()?.getattr("timedelta")?.call((), Some(&kwargs))?;
+            if *negative { delta.neg()?.into_py(py) } else { delta.into_py(py) }
+        }
+        //TIMESTAMP记录的是utc时间戳
+        Some(MySQLValue::Timestamp{ unix_time, subsecond }) => {
+            let module = datetime()?;
+            let utc = module.getattr("timezone")?.getattr("utc")?;
+            let ts = *unix_time as f64 + microsecond(*subsecond, fsp) as f64 / 1_000_000.0;
+            module.getattr("datetime")?.call_method1("fromtimestamp", (ts, utc))?.into_py(py)
+        }
+    })
+}
+
+fn row_to_py(py: Python<'_>, row: &[Option<MySQLValue>], map: Option<&TableMap>, decode_strings: bool) -> PyResult<PyObject> {
+    let fsp = |idx: usize| map.and_then(|t| t.column_info.get(idx)).and_then(|t| t.column_meta.first().copied()).unwrap_or(0);
+    let values = row.iter().enumerate().map(|(idx, v)| value_to_py(py, v, fsp(idx), decode_strings)).collect::<PyResult<Vec<_>>>()?;
+    Ok(PyList::new_bound(py, values).into_py(py))
+}
+
+fn event_to_py(py: Python<'_>, event: &Event, decode_strings: bool) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("event_type", format!("{:?}", event.header.event_type))?;
+    dict.set_item("timestamp", event.header.timestamp)?;
+    dict.set_item("server_id", event.header.server_id)?;
+    dict.set_item("log_pos", event.log_pos)?;
+    dict.set_item("next_position", event.header.next_position)?;
+    let kind = match &event.data {
+        Traction::GtidEvent(t) => {
+            dict.set_item("gtid", format!("{}:{}", t.gtid, t.gno_id))?;
+            dict.set_item("last_committed", t.last_committed)?;
+            dict.set_item("sequence_number", t.sequence_number)?;
+            "gtid"
+        }
+        Traction::QueryEvent(t) => {
+            dict.set_item("thread_id", t.thread_id)?;
+            dict.set_item("database", &t.database)?;
+            dict.set_item("query", &t.command)?;
+            dict.set_item("execute_seconds", t.execute_seconds)?;
+            "query"
+        }
+        Traction::TableMapEvent(t) => {
+            dict.set_item("database", &t.database_name)?;
+            dict.set_item("table", &t.table_name)?;
+            "table_map"
+        }
+        Traction::RowEvent(code, rows) => {
+            if let Some(map) = &event.table {
+                dict.set_item("database", &map.database_name)?;
+                dict.set_item("table", &map.table_name)?;
+            }
+            let rows = rows.rows();
+            let (kind, values) = match code {
+                BinlogEvent::UpdateEvent => {
+                    //前后镜像依次排列
+                    let mut values = vec![];
+                    for pair in rows.chunks(2) {
+                        let image = PyDict::new_bound(py);
+                        image.set_item("before", row_to_py(py, &pair[0], event.table.as_ref(), decode_strings)?)?;
+                        image.set_item("after", match pair.get(1) {
+                            Some(t) => row_to_py(py, t, event.table.as_ref(), decode_strings)?,
+                            None => py.None()
+                        })?;
+                        values.push(image.into_py(py));
+                    }
+                    ("update", values)
+                }
+                BinlogEvent::WriteEvent => ("insert", rows.iter().map(|r| row_to_py(py, r, event.table.as_ref(), decode_strings)).collect::<PyResult<Vec<_>>>()?),
+                _ => ("delete", rows.iter().map(|r| row_to_py(py, r, event.table.as_ref(), decode_strings)).collect::<PyResult<Vec<_>>>()?),
+            };
+            dict.set_item("rows", PyList::new_bound(py, values))?;
+            kind
+        }
+        Traction::XidEvent(t) => {
+            dict.set_item("xid", t.xid)?;
+            "xid"
+        }
+        Traction::RotateLogEvent(t) => {
+            dict.set_item("binlog_file", &t.binlog_file)?;
+            dict.set_item("position", t.position)?;
+            "rotate"
+        }
+        Traction::StopEvent => "stop",
+        Traction::RowsQueryEvent(t) => {
+            dict.set_item("query", &t.query)?;
+            "rows_query"
+        }
+        _ => "other"
+    };
+    dict.set_item("type", kind)?;
+    Ok(dict.into_py(py))
+}
+
+/// 读取binlog/relay log文件, 按事件迭代
+#[pyclass(module = "binlogal")]
+struct BinlogFile{
+    reader: Option<BinlogReader<BufReader<File>>>,
+    decode_strings: bool,
+}
+
+#[pymethods]
+impl BinlogFile {
+    #[new]
+    #[pyo3(signature = (path, relaylog=false, decode_strings=true, verify_checksum="none"))]
+    fn new(path: &str, relaylog: bool, decode_strings: bool, verify_checksum: &str) -> PyResult<BinlogFile> {
+        let classes = ChecksumClass::parse(verify_checksum).map_err(BinlogError::new_err)?;
+        let reader = BinlogReader::open(path).map_err(to_py_err)?.relaylog(relaylog).verify_checksum(classes);
+        Ok(BinlogFile{ reader: Some(reader), decode_strings })
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let reader = match self.reader.as_mut() {
+            Some(t) => t,
+            None => return Err(BinlogError::new_err("已经通过transactions()按事务读取")),
+        };
+        match reader.next() {
+            Some(event) => Ok(Some(event_to_py(py, &event.map_err(to_py_err)?, self.decode_strings)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// 按事务迭代, 返回{"gtid": ..., "complete": ..., "events": [...]}, complete为false时为文件末尾未完成的事务, 之后不能再按事件迭代
+    fn transactions(&mut self) -> PyResult<TransactionIter> {
+        match self.reader.take() {
+            Some(t) => Ok(TransactionIter{ transactions: t.transactions(), decode_strings: self.decode_strings }),
+            None => Err(BinlogError::new_err("已经通过transactions()按事务读取")),
+        }
+    }
+
+    /// 读取结束的原因: stop、rotate、eof、truncated, 未读取到文件末尾时为None
+    fn file_end(&self) -> Option<&'static str> {
+        self.reader.as_ref().and_then(|r| r.file_end()).map(|t| t.reason())
+    }
+}
+
+#[pyclass(module = "binlogal")]
+struct TransactionIter{
+    transactions: Transactions<BufReader<File>>,
+    decode_strings: bool,
+}
+
+#[pymethods]
+impl TransactionIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let trx = match self.transactions.next() {
+            Some(t) => t.map_err(to_py_err)?,
+            None => return Ok(None)
+        };
+        let dict = PyDict::new_bound(py);
+        dict.set_item("gtid", trx.gtid.as_ref().map(|t| format!("{}:{}", t.gtid, t.gno_id)))?;
+        dict.set_item("complete", trx.complete)?;
+        let events = trx.events.iter().map(|e| event_to_py(py, e, self.decode_strings)).collect::<PyResult<Vec<_>>>()?;
+        dict.set_item("events", PyList::new_bound(py, events))?;
+        Ok(Some(dict.into_py(py)))
+    }
+}
+
+/// 通过主从协议从mysql拉取binlog, 按事件迭代, 不会主动结束
+#[pyclass(module = "binlogal")]
+struct Stream{
+    stream: BinlogStream,
+    decode_strings: bool,
+}
+
+impl Stream {
+    //等待数据时释放GIL, 其他python线程可以继续执行
+    fn read(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let stream = &mut self.stream;
+        let event = py.allow_threads(|| stream.read_event()).map_err(to_py_err)?;
+        event_to_py(py, &event, self.decode_strings)
+    }
+}
+
+#[pymethods]
+impl Stream {
+    #[new]
+    #[pyo3(signature = (host, user, password, gtid=None, binlogfile=None, position=4, server_id=133, decode_strings=true, verify_checksum="none"))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(py: Python<'_>, host: &str, user: &str, password: &str, gtid: Option<String>, binlogfile: Option<String>,
+           position: u32, server_id: u32, decode_strings: bool, verify_checksum: &str) -> PyResult<Stream> {
+        let mut conf = Config{
+            program_name: String::from("binlogal-python"),
+            host_info: host.to_string(),
+            user_name: user.to_string(),
+            password: Secret::new(password.to_string()),
+            position: position.to_string(),
+            serverid: server_id.to_string(),
+            verifychecksum: verify_checksum.to_string(),
+            ..Config::default()
+        };
+        match gtid {
+            None => {},
+            Some(t) => conf.gtid = t,
+        }
+        match binlogfile {
+            None => {},
+            Some(t) => conf.binlogfile = t,
+        }
+        let stream = py.allow_threads(|| BinlogStream::connect(&conf)).map_err(to_py_err)?;
+        Ok(Stream{ stream, decode_strings })
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        Ok(Some(self.read(py)?))
+    }
+
+    /// 对每个事件调用callback(event), callback返回False时结束
+    fn run(&mut self, py: Python<'_>, callback: PyObject) -> PyResult<()> {
+        loop {
+            py.check_signals()?;
+            let event = self.read(py)?;
+            let result = callback.call1(py, (event,))?;
+            if let Ok(false) = result.extract::<bool>(py) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[pymodule]
+fn binlogal(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<BinlogFile>()?;
+    m.add_class::<TransactionIter>()?;
+    m.add_class::<Stream>()?;
+    m.add("BinlogError", m.py().get_type_bound::<BinlogError>())?;
+    Ok(())
+}
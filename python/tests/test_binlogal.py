@@ -0,0 +1,79 @@
+# @author: xiao cai niao
+# @datetime: 2019/11/08
+#
+# 需要先安装模块: cd python && maturin develop
+# python -m unittest discover -s python/tests
+
+import datetime
+import decimal
+import os
+import unittest
+
+import binlogal
+
+FIXTURES = os.path.join(os.path.dirname(__file__), "..", "..", "tests", "fixtures")
+
+
+def fixture(name):
+    return os.path.join(FIXTURES, name)
+
+
+class BinlogFileTest(unittest.TestCase):
+    def test_events(self):
+        f = binlogal.BinlogFile(fixture("mysql57.binlog"))
+        events = list(f)
+        self.assertEqual(f.file_end(), "rotate")
+        types = [e["type"] for e in events if e["type"] in ("insert", "update", "delete")]
+        self.assertEqual(types, ["insert", "update", "delete"])
+
+        insert = next(e for e in events if e["type"] == "insert")
+        self.assertEqual((insert["database"], insert["table"]), ("golden", "all_types"))
+        row = insert["rows"][0]
+        self.assertEqual(row[0], 1)
+        self.assertEqual(row[7], decimal.Decimal("-1234.01"))
+        self.assertEqual(row[9], datetime.date(2019, 11, 4))
+        self.assertEqual(row[10], datetime.timedelta(hours=838, minutes=59, seconds=58))
+        self.assertEqual(row[11], datetime.datetime(2019, 11, 4, 23, 59, 59, 123000))
+        self.assertEqual(row[12], datetime.datetime(2019, 11, 4, 15, 59, 59, 123456, tzinfo=datetime.timezone.utc))
+        self.assertEqual(row[14], "char 中文")
+        self.assertEqual(row[15], b"\x00\x01\x02\xfe\xff")
+        self.assertEqual(row[18]["tags"], ["a", "b"])
+        self.assertEqual(insert["rows"][1][1:], [None] * 18)
+
+        update = next(e for e in events if e["type"] == "update")
+        self.assertEqual(update["rows"][0]["before"], row)
+        self.assertEqual(update["rows"][0]["after"][1], -128)
+
+    def test_decode_strings(self):
+        insert = next(e for e in binlogal.BinlogFile(fixture("mysql57.binlog"), decode_strings=False) if e["type"] == "insert")
+        self.assertEqual(insert["rows"][0][14], "char 中文".encode())
+
+    def test_transactions(self):
+        trxs = list(binlogal.BinlogFile(fixture("mysql80.binlog")).transactions())
+        self.assertEqual([t["gtid"].split(":")[1] for t in trxs], ["2", "3", "4"])
+        self.assertEqual(trxs[0]["events"][-1]["type"], "xid")
+        self.assertTrue(all(t["complete"] for t in trxs))
+
+    def test_errors(self):
+        with self.assertRaises(OSError):
+            binlogal.BinlogFile(fixture("missing.binlog"))
+        with self.assertRaises(binlogal.BinlogError):
+            binlogal.BinlogFile(fixture("mysql57.txt"))
+        with self.assertRaises(binlogal.BinlogError):
+            binlogal.BinlogFile(fixture("mysql57.binlog"), verify_checksum="bad")
+
+
+class StreamTest(unittest.TestCase):
+    def test_invalid_args(self):
+        with self.assertRaises(binlogal.BinlogError):
+            binlogal.Stream("127.0.0.1:1", "root", "", gtid="bad gtid")
+        with self.assertRaises(binlogal.BinlogError):
+            binlogal.Stream("127.0.0.1:1", "root", "")
+
+    def test_connect_refused(self):
+        with self.assertRaises(OSError):
+            binlogal.Stream("127.0.0.1:1", "root", "", binlogfile="mysql-bin.000001")
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,238 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//多个来源的归档binlog合并读取(如主从切换前旧主库、切换后新主库的归档目录), 每个事务只处理一次
+//  相同的文件: 大小及sha256都相同的文件只读取第一个, 同名但内容不同的文件都保留
+//  读取顺序: 按PREVIOUS_GTIDS_LOG_EVENT中的事务数从少到多, 相同时按文件开头的时间、路径排序
+//  重叠的事务: 文件中的gtid与之前的文件重叠的部分在读取时按gtid跳过(GtidDedup), 全部重叠的文件不再读取
+//  没有gtid的事务(gtid_mode=OFF)无法判断是否重复, 只能按相同的文件去除
+//  archive子命令输出读取顺序、相同的文件及重叠的gtid, -o写入index文件用于backfill等的--index
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use uuid::Uuid;
+use crate::gtid::GtidSet;
+use crate::locate;
+use crate::reader::BinlogReader;
+
+const BINLOG_MAGIC: [u8; 4] = [0xfe, 0x62, 0x69, 0x6e];
+
+/// 一个归档的binlog文件
+#[derive(Debug, Clone)]
+pub struct ArchiveFile{
+    pub path: String,
+    pub size: u64,
+    pub digest: String,                 //sha256
+    pub start_time: u32,                //FORMAT_DESCRIPTION_EVENT的时间
+    pub previous_gtids: GtidSet,
+    pub gtids: GtidSet,                 //文件中的事务
+    pub anonymous: u64,                 //没有gtid的事务数
+}
+
+impl ArchiveFile {
+    /// 读取整个文件, 计算sha256及其中事务的gtid集合
+    pub fn scan(path: &str) -> io::Result<ArchiveFile> {
+        let (size, digest) = crate::manifest::sha256_file(path)?;
+        let probe = locate::probe_file(path)?;
+        let (gtids, anonymous) = file_gtids(path, 0)?;
+        Ok(ArchiveFile{
+            path: path.to_string(),
+            size,
+            digest,
+            start_time: probe.start_time,
+            previous_gtids: probe.previous_gtids.unwrap_or_default(),
+            gtids,
+            anonymous,
+        })
+    }
+}
+
+/// 文件中事务的gtid集合及没有gtid的事务数, stop大于0时只读取到该position为止
+pub fn file_gtids(path: &str, stop: u64) -> io::Result<(GtidSet, u64)> {
+    let mut gtids = GtidSet::new();
+    let mut anonymous = 0;
+    for trx in BinlogReader::open(path)?.transactions() {
+        let trx = trx?;
+        if !trx.complete {
+            continue;
+        }
+        if stop > 0 && trx.events.last().map(|e| e.header.next_position as u64 > stop).unwrap_or(false) {
+            break;
+        }
+        match &trx.gtid {
+            Some(t) => gtids.add(t.gtid, t.gno_id),
+            None => anonymous += 1,
+        }
+    }
+    Ok((gtids, anonymous))
+}
+
+/// 目录中的binlog文件(以binlog magic开头), 指定的文件直接返回, 按路径排序
+pub fn list(paths: &[String]) -> io::Result<Vec<String>> {
+    let mut files = vec![];
+    for path in paths.iter().filter(|t| !t.is_empty()) {
+        if !Path::new(path).is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut entries = vec![];
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let mut magic = [0u8; 4];
+            if File::open(entry.path()).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && magic == BINLOG_MAGIC {
+                entries.push(entry.path().to_string_lossy().to_string());
+            }
+        }
+        entries.sort();
+        files.extend(entries);
+    }
+    Ok(files)
+}
+
+/// 合并读取的计划
+#[derive(Debug, Clone, Default)]
+pub struct ArchivePlan{
+    pub files: Vec<ArchiveFile>,                //按顺序读取的文件
+    pub identical: Vec<(String, String)>,       //相同的文件及保留的文件
+    pub redundant: Vec<String>,                 //所有事务都已包含在之前的文件中
+    pub overlaps: Vec<(String, GtidSet)>,       //与之前的文件重叠的事务, 读取时跳过
+}
+
+impl ArchivePlan {
+    pub fn new(scanned: Vec<ArchiveFile>) -> ArchivePlan {
+        let mut plan = ArchivePlan::default();
+        let mut unique: Vec<ArchiveFile> = vec![];
+        for file in scanned {
+            match unique.iter().find(|t| t.size == file.size && t.digest == file.digest) {
+                Some(t) => plan.identical.push((file.path.clone(), t.path.clone())),
+                None => unique.push(file),
+            }
+        }
+        unique.sort_by(|a, b| {
+            gtid_count(&a.previous_gtids).cmp(&gtid_count(&b.previous_gtids))
+                .then(a.start_time.cmp(&b.start_time))
+                .then(a.path.cmp(&b.path))
+        });
+        let mut seen = GtidSet::new();
+        for file in unique {
+            let overlap = file.gtids.subtract(&file.gtids.subtract(&seen));
+            if !file.gtids.is_empty() && file.anonymous == 0 && overlap == file.gtids {
+                plan.redundant.push(file.path.clone());
+                continue;
+            }
+            if !overlap.is_empty() {
+                plan.overlaps.push((file.path.clone(), overlap));
+            }
+            for set in &file.gtids.sets {
+                for interval in &set.intervals {
+                    seen.add_interval(set.sid, *interval);
+                }
+            }
+            plan.files.push(file);
+        }
+        plan
+    }
+
+    /// 扫描目录或文件后生成计划
+    pub fn scan(paths: &[String]) -> io::Result<ArchivePlan> {
+        let mut scanned = vec![];
+        for path in list(paths)? {
+            scanned.push(ArchiveFile::scan(&path)?);
+        }
+        Ok(ArchivePlan::new(scanned))
+    }
+
+    pub fn paths(&self) -> Vec<String> {
+        self.files.iter().map(|t| t.path.clone()).collect()
+    }
+
+    /// 可读的计划, 每行一条
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = vec![];
+        for file in &self.files {
+            lines.push(format!("read      {}  {}", file.path, file.gtids));
+        }
+        for (path, original) in &self.identical {
+            lines.push(format!("identical {}  same as {}", path, original));
+        }
+        for path in &self.redundant {
+            lines.push(format!("redundant {}", path));
+        }
+        for (path, overlap) in &self.overlaps {
+            lines.push(format!("overlap   {}  {}", path, overlap));
+        }
+        lines
+    }
+}
+
+fn gtid_count(set: &GtidSet) -> u64 {
+    set.sets.iter().flat_map(|t| t.intervals.iter()).map(|i| i.stop - i.start).sum()
+}
+
+/// 按gtid去重, 每个事务只处理一次, 没有gtid的事务都处理
+#[derive(Debug, Clone, Default)]
+pub struct GtidDedup{
+    seen: GtidSet,
+    duplicates: u64,
+}
+
+impl GtidDedup {
+    pub fn new() -> GtidDedup {
+        GtidDedup::default()
+    }
+
+    /// 第一次出现时返回true并记录
+    pub fn first(&mut self, sid: &Uuid, gno: u64) -> bool {
+        if self.seen.contains(sid, gno) {
+            self.duplicates += 1;
+            return false;
+        }
+        self.seen.add(*sid, gno);
+        true
+    }
+
+    /// 已处理的事务, 例如从checkpoint继续时之前的文件中的事务
+    pub fn extend(&mut self, set: &GtidSet) {
+        for uuid_set in &set.sets {
+            for interval in &uuid_set.intervals {
+                self.seen.add_interval(uuid_set.sid, *interval);
+            }
+        }
+    }
+
+    pub fn seen(&self) -> &GtidSet {
+        &self.seen
+    }
+
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+}
+
+/// archive子命令: 扫描--dir中的目录, 输出读取顺序、相同的文件及重叠的gtid, -o写入index文件
+pub fn run(conf: &crate::Config) {
+    use std::process;
+    use crate::exitcode;
+    let dirs: Vec<String> = conf.archivedirs.split(',').map(|t| t.trim().to_string()).collect();
+    let plan = ArchivePlan::scan(&dirs).unwrap_or_else(|err|{
+        println!("读取归档文件发生错误: {}", err);
+        process::exit(exitcode::PARSE);
+    });
+    for line in plan.report() {
+        println!("{}", line);
+    }
+    if !conf.output.is_empty() {
+        let mut content = plan.paths().join("\n");
+        content.push('\n');
+        fs::write(&conf.output, content).unwrap_or_else(|err|{
+            println!("写入index文件({})发生错误: {}", conf.output, err);
+            process::exit(exitcode::SINK);
+        });
+    }
+}
@@ -0,0 +1,390 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//作为库使用时生成Config的辅助方法: 按读取方式(Mode)区分必需的参数, build()时检查参数的组合, 不合法的配置不会进入解析流程
+//  ConfigBuilder只是在Config之上增加的一层, 生成的仍是字符串字段的Config(runtype为repl、file等),
+//  解析流程及其余接口使用的都是Config, Mode、Start、Format只在构造时使用, 不会保存到Config中
+//  Config中的字段都是字符串, 命令行以外的调用方容易填错(例如同时设置gtid与binlogfile、对复制流设置rollback),
+//  错误要到解析时才发现(parse().unwrap()时panic), 通过ConfigBuilder可以在构造时得到错误信息
+//  ConfigBuilder实现了Deserialize, 可以直接从配置文件读取, 例如json:
+//    {"mode": "replication", "host": "127.0.0.1:3306", "user": "repl", "password": "...",
+//     "start": {"gtid": "3e11fa47-71ca-11e1-9e33-c80aa9429562:1-5"}, "format": "json", "tables": ["shop.orders"]}
+//    {"mode": "file", "path": "mysql-bin.000003", "rollback": true, "start_position": 4, "stop_position": 1024}
+
+use std::collections::BTreeMap;
+use serde::Deserialize;
+use crate::Config;
+use crate::gtid::GtidSet;
+use crate::secret::Secret;
+use crate::rowfilter::RowFilter;
+use crate::stdout::format::ValueFormat;
+
+/// 读取binlog的方式
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum Mode {
+    /// 模拟slave从mysql拉取binlog, host为ip:port
+    Replication{
+        host: String,
+        user: String,
+        #[serde(default)]
+        password: Secret,
+        start: Start,
+    },
+    /// 读取本地的binlog文件
+    File{
+        path: String,
+        /// 文件为relay log
+        #[serde(default)]
+        relay_log: bool,
+        /// 生成回滚语句
+        #[serde(default)]
+        rollback: bool,
+    },
+}
+
+/// 注册同步的起始位置
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Start {
+    /// 已执行的gtid集合
+    Gtid{ gtid: String },
+    /// binlog文件及position, 配置文件中不指定position时为4
+    Position{
+        binlog_file: String,
+        #[serde(default = "first_position")]
+        position: u64,
+    },
+}
+
+fn first_position() -> u64 {
+    4
+}
+
+/// 行数据的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Pretty,
+    Text,
+    Json,
+    Csv,
+    /// 提取sql语句(--getsql)
+    Sql,
+}
+
+impl Format {
+    fn name(&self) -> &'static str {
+        match self {
+            Format::Pretty => "pretty",
+            Format::Text => "text",
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::Sql => "sql",
+        }
+    }
+}
+
+/// 生成Config, 未设置的参数与命令行的默认值相同
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ConfigBuilder {
+    #[serde(flatten)]
+    mode: Mode,
+    #[serde(default)]
+    server_id: Option<u32>,
+    #[serde(default)]
+    heartbeat: Option<f64>,
+    #[serde(default)]
+    format: Option<Format>,
+    #[serde(default)]
+    tables: Vec<String>,
+    #[serde(default)]
+    thread_id: Option<u64>,
+    #[serde(default)]
+    start_position: Option<u64>,
+    #[serde(default)]
+    stop_position: Option<u64>,
+    #[serde(default)]
+    start_datetime: Option<u64>,
+    #[serde(default)]
+    stop_datetime: Option<u64>,
+    #[serde(default)]
+    row_filter: Option<String>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    verify_checksum: Option<String>,
+    #[serde(default)]
+    strict: bool,
+    #[serde(default)]
+    lenient_checksum: bool,
+    #[serde(default)]
+    until_gtid: Option<String>,
+    #[serde(default)]
+    until_position: Option<String>,
+}
+
+impl ConfigBuilder {
+    pub fn new(mode: Mode) -> ConfigBuilder {
+        ConfigBuilder{
+            mode,
+            server_id: None,
+            heartbeat: None,
+            format: None,
+            tables: vec![],
+            thread_id: None,
+            start_position: None,
+            stop_position: None,
+            start_datetime: None,
+            stop_datetime: None,
+            row_filter: None,
+            labels: BTreeMap::new(),
+            verify_checksum: None,
+            strict: false,
+            lenient_checksum: false,
+            until_gtid: None,
+            until_position: None,
+        }
+    }
+
+    /// 从json配置文件的内容读取, 其他格式(toml、yaml等)可以通过对应的serde库读取
+    pub fn from_json(content: &str) -> Result<ConfigBuilder, String> {
+        serde_json::from_str(content).map_err(|e| format!("配置格式错误: {}", e))
+    }
+
+    /// 注册同步使用的server_id, 只用于Replication, 默认133
+    pub fn server_id(mut self, server_id: u32) -> Self {
+        self.server_id = Some(server_id);
+        self
+    }
+
+    /// 心跳间隔(秒), 只用于Replication
+    pub fn heartbeat(mut self, seconds: f64) -> Self {
+        self.heartbeat = Some(seconds);
+        self
+    }
+
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// 只输出该表的数据, 格式为db.tbl, db.*为库中所有的表, 可以调用多次
+    pub fn table(mut self, table: &str) -> Self {
+        self.tables.push(table.to_string());
+        self
+    }
+
+    pub fn thread_id(mut self, thread_id: u64) -> Self {
+        self.thread_id = Some(thread_id);
+        self
+    }
+
+    /// 读取文件的position范围, 只用于File
+    pub fn positions(mut self, start: Option<u64>, stop: Option<u64>) -> Self {
+        self.start_position = start;
+        self.stop_position = stop;
+        self
+    }
+
+    /// 事务的时间范围(时间戳)
+    pub fn datetimes(mut self, start: Option<u64>, stop: Option<u64>) -> Self {
+        self.start_datetime = start;
+        self.stop_datetime = stop;
+        self
+    }
+
+    /// 行数据的过滤条件, 与--where相同
+    pub fn row_filter(mut self, expr: &str) -> Self {
+        self.row_filter = Some(expr.to_string());
+        self
+    }
+
+    /// 静态标签, 输出时按标签名排序
+    pub fn label(mut self, key: &str, value: &str) -> Self {
+        self.labels.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// 校验checksum的事件类型, 与--verify-checksum相同
+    pub fn verify_checksum(mut self, classes: &str) -> Self {
+        self.verify_checksum = Some(classes.to_string());
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// checksum错误只记录警告, 与--lenient-checksum相同
+    pub fn lenient_checksum(mut self, lenient: bool) -> Self {
+        self.lenient_checksum = lenient;
+        self
+    }
+
+    /// 读取完该gtid集合中的所有事务后停止, 只用于Replication
+    pub fn until_gtid(mut self, gtid: &str) -> Self {
+        self.until_gtid = Some(gtid.to_string());
+        self
+    }
+
+    /// 已执行位置到达或超过该文件及position时停止, 只用于Replication
+    pub fn until_position(mut self, binlog_file: &str, position: u64) -> Self {
+        self.until_position = Some(format!("{}:{}", binlog_file, position));
+        self
+    }
+
+    /// 检查参数的组合并生成Config, 之后按Config的字符串字段使用
+    pub fn build(self) -> Result<Config, String> {
+        let mut conf = Config{
+            program_name: String::from("binlogAL"),
+            serverid: 133.to_string(),
+            strict: self.strict,
+            lenientchecksum: self.lenient_checksum,
+            ..Config::default()
+        };
+        let file_mode = match self.mode {
+            Mode::Replication{ host, user, password, start } => {
+                if host.is_empty() {
+                    return Err(String::from("host 不能为空！！"));
+                }
+                if user.is_empty() {
+                    return Err(String::from("user 不能为空！！"));
+                }
+                conf.runtype = String::from("repl");
+                conf.host_info = host;
+                conf.user_name = user;
+                conf.password = password;
+                match start {
+                    Start::Gtid{ gtid } => {
+                        gtid.parse::<GtidSet>()?;
+                        conf.gtid = gtid;
+                    }
+                    Start::Position{ binlog_file, position } => {
+                        if binlog_file.is_empty() {
+                            return Err(String::from("binlog_file 不能为空"));
+                        }
+                        if position < 4 {
+                            return Err(format!("无效的position: {}, 最小为4", position));
+                        }
+                        conf.binlogfile = binlog_file;
+                        conf.position = position.to_string();
+                    }
+                }
+                false
+            }
+            Mode::File{ path, relay_log, rollback } => {
+                if path.is_empty() {
+                    return Err(String::from("path 不能为空"));
+                }
+                if rollback && relay_log {
+                    return Err(String::from("rollback不能用于relay log"));
+                }
+                if rollback && self.format.is_some() {
+                    return Err(String::from("rollback输出回滚的sql文件, 不能同时指定format"));
+                }
+                conf.runtype = String::from("file");
+                conf.file = path;
+                conf.relaylog = relay_log;
+                conf.rollback = rollback;
+                true
+            }
+        };
+
+        match self.server_id {
+            Some(_) if file_mode => return Err(String::from("server_id只用于Replication")),
+            Some(0) => return Err(String::from("server_id不能为0")),
+            Some(t) => conf.serverid = t.to_string(),
+            None => {}
+        }
+        match self.heartbeat {
+            Some(_) if file_mode => return Err(String::from("heartbeat只用于Replication")),
+            Some(t) if t.is_nan() || t <= 0.0 => return Err(format!("无效的heartbeat: {}", t)),
+            Some(t) => conf.heartbeat = t.to_string(),
+            None => {}
+        }
+        if file_mode && (self.until_gtid.is_some() || self.until_position.is_some()) {
+            return Err(String::from("until_gtid、until_position只用于Replication, File通过stop_position、stop_datetime指定结束位置"));
+        }
+        conf.untilgtid = self.until_gtid.unwrap_or_default();
+        conf.untilposition = self.until_position.unwrap_or_default();
+        crate::gtid::Until::from_config(&conf)?;
+        if !file_mode && (self.start_position.is_some() || self.stop_position.is_some()) {
+            return Err(String::from("start_position、stop_position只用于File, Replication的起始位置通过start指定"));
+        }
+        if let (Some(start), Some(stop)) = (self.start_position, self.stop_position) {
+            if start >= stop {
+                return Err(format!("start_position({})需要小于stop_position({})", start, stop));
+            }
+        }
+        if let (Some(start), Some(stop)) = (self.start_datetime, self.stop_datetime) {
+            if start > stop {
+                return Err(format!("start_datetime({})不能大于stop_datetime({})", start, stop));
+            }
+        }
+        conf.startposition = self.start_position.map(|t| t.to_string()).unwrap_or_default();
+        conf.stopposition = self.stop_position.map(|t| t.to_string()).unwrap_or_default();
+        conf.startdatetime = self.start_datetime.map(|t| t.to_string()).unwrap_or_default();
+        conf.stopdatetime = self.stop_datetime.map(|t| t.to_string()).unwrap_or_default();
+        conf.threadid = self.thread_id.map(|t| t.to_string()).unwrap_or_default();
+
+        match self.format {
+            Some(Format::Sql) => conf.getsql = true,
+            Some(t) => conf.outformat = t.name().to_string(),
+            None => {}
+        }
+        if !self.tables.is_empty() {
+            conf.greptbl = greptbl(&self.tables)?;
+        }
+        if let Some(expr) = self.row_filter {
+            RowFilter::new(&expr, ValueFormat::default())?;
+            conf.rowfilter = expr;
+        }
+        conf.labels = self.labels.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<String>>().join(",");
+        conf.verifychecksum = self.verify_checksum.unwrap_or_default();
+        conf.validate()?;
+        Ok(conf)
+    }
+}
+
+//db.tbl转换为greptbl的格式: {"db":["tbl"]}, db.*为{"db":"all"}
+pub(crate) fn greptbl(tables: &[String]) -> Result<String, String> {
+    let mut dbs: BTreeMap<String, Option<Vec<String>>> = BTreeMap::new();
+    for table in tables {
+        let (db, tbl) = match table.split_once('.') {
+            Some((db, tbl)) if !db.is_empty() && !tbl.is_empty() => (db, tbl),
+            _ => return Err(format!("无效的表名: {}, 格式为db.tbl或db.*", table)),
+        };
+        let entry = dbs.entry(db.to_string()).or_insert_with(|| Some(vec![]));
+        match (tbl, entry.as_mut()) {
+            ("*", _) => *entry = None,
+            (_, Some(tbls)) => tbls.push(tbl.to_string()),
+            (_, None) => {}
+        }
+    }
+    let value: serde_json::Map<String, serde_json::Value> = dbs.into_iter().map(|(db, tbls)| {
+        let tbls = match tbls {
+            Some(t) => serde_json::json!(t),
+            None => serde_json::json!("all"),
+        };
+        (db, tbls)
+    }).collect();
+    Ok(serde_json::Value::Object(value).to_string())
+}
+
+impl Config {
+    /// 检查各字段的格式, 命令行及ConfigBuilder生成配置后调用
+    pub fn validate(&self) -> Result<(), String> {
+        if (self.runtype == "repl" || self.runtype == "backup") && self.gtid.is_empty() && self.binlogfile.is_empty() {
+            return Err(String::from("需要通过--gtid或--binlogfile指定注册同步的位置"));
+        }
+        crate::replication::verify::ChecksumClass::parse(&self.verifychecksum)?;
+        crate::compat::Profile::parse(&self.compat)?;
+        crate::exclude::Exclusion::new(&self.excludetables)?;
+        crate::labels::Labels::parse(&self.labels)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//故障注入, 需要开启test-support特性, 用于在上线前验证断线重连、输出失败重试、checksum错误等恢复逻辑
+//  按配置的概率注入: 连接断开、数据包延迟、输出目标(Sink)写入失败、事件checksum损坏
+//  同一个Faults可以同时用于BinlogStream::with_faults及FaultySink, seed相同时注入的位置相同
+//
+//  let faults = Faults::parse("disconnect=0.01,delay=0.05,delay-ms=200,sink=0.02,corrupt=0.001,seed=7")?;
+//  let stream = BinlogStream::connect(&conf)?.with_faults(faults.clone());
+//  let sink = faults.sink(JsonLinesSink::new(out, ValueFormat::default()));
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use crate::reader::Event;
+use crate::sink::{Heartbeat, Sink, Watermark};
+
+/// 已注入的故障数量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultCounts{
+    pub disconnects: u64,
+    pub delays: u64,
+    pub sink_errors: u64,
+    pub corruptions: u64,
+}
+
+//splitmix64, 只要求相同的seed生成相同的序列
+struct Rng(u64);
+
+impl Rng{
+    fn chance(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        ((z >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+struct State{
+    rng: Rng,
+    counts: FaultCounts,
+}
+
+/// 故障注入的配置及状态, clone后共享同一个随机序列及计数
+#[derive(Clone)]
+pub struct Faults{
+    disconnect: f64,
+    delay: f64,
+    delay_time: Duration,
+    sink_error: f64,
+    corrupt: f64,
+    state: Arc<Mutex<State>>,
+}
+
+impl Faults {
+    /// 不注入任何故障, 通过disconnect、delay、sink_error、corrupt设置概率
+    pub fn new(seed: u64) -> Faults {
+        Faults{
+            disconnect: 0.0,
+            delay: 0.0,
+            delay_time: Duration::from_millis(100),
+            sink_error: 0.0,
+            corrupt: 0.0,
+            state: Arc::new(Mutex::new(State{ rng: Rng(seed), counts: FaultCounts::default() })),
+        }
+    }
+
+    /// 逗号分隔的key=value: disconnect、delay、sink、corrupt为0到1之间的概率, delay-ms为延迟的毫秒数(默认100), seed默认为1
+    pub fn parse(spec: &str) -> Result<Faults, String> {
+        let mut faults = Faults::new(1);
+        for item in spec.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+            let (key, value) = item.split_once('=').ok_or_else(|| format!("无效的故障配置: {}, 格式为key=value", item))?;
+            let probability = || match value.trim().parse::<f64>() {
+                Ok(t) if (0.0..=1.0).contains(&t) => Ok(t),
+                _ => Err(format!("无效的概率: {}={}, 需要为0到1之间的小数", key, value))
+            };
+            match key.trim() {
+                "disconnect" => faults.disconnect = probability()?,
+                "delay" => faults.delay = probability()?,
+                "sink" => faults.sink_error = probability()?,
+                "corrupt" => faults.corrupt = probability()?,
+                "delay-ms" => faults.delay_time = Duration::from_millis(value.trim().parse().map_err(|_| format!("无效的delay-ms: {}", value))?),
+                "seed" => faults.state = Arc::new(Mutex::new(State{
+                    rng: Rng(value.trim().parse().map_err(|_| format!("无效的seed: {}", value))?),
+                    counts: FaultCounts::default()
+                })),
+                t => return Err(format!("无效的故障类型: {}, 可选值为disconnect、delay、delay-ms、sink、corrupt、seed", t))
+            }
+        }
+        Ok(faults)
+    }
+
+    /// 读取每个数据包前断开连接的概率, 断开后BinlogStream一直返回ConnectionReset, 需要按executed()重新连接
+    pub fn disconnect(mut self, probability: f64) -> Faults {
+        self.disconnect = probability;
+        self
+    }
+
+    /// 每个数据包延迟的概率及时长
+    pub fn delay(mut self, probability: f64, time: Duration) -> Faults {
+        self.delay = probability;
+        self.delay_time = time;
+        self
+    }
+
+    /// FaultySink每次写入、flush失败的概率
+    pub fn sink_error(mut self, probability: f64) -> Faults {
+        self.sink_error = probability;
+        self
+    }
+
+    /// 损坏事件末尾checksum的概率(不包括FORMAT_DESCRIPTION_EVENT), 开启verifychecksum时该事件返回InvalidData
+    pub fn corrupt(mut self, probability: f64) -> Faults {
+        self.corrupt = probability;
+        self
+    }
+
+    /// 已注入的故障数量
+    pub fn counts(&self) -> FaultCounts {
+        self.state.lock().unwrap().counts
+    }
+
+    /// 包装输出目标, 按sink_error的概率写入失败, 失败时不写入inner
+    pub fn sink<S: Sink>(&self, inner: S) -> FaultySink<S> {
+        FaultySink{ inner, faults: self.clone() }
+    }
+
+    fn inject(&self, probability: f64, count: fn(&mut FaultCounts) -> &mut u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.rng.chance(probability) {
+            return false;
+        }
+        *count(&mut state.counts) += 1;
+        true
+    }
+
+    //读取数据包前调用, 返回是否断开连接
+    pub(crate) fn before_packet(&self) -> bool {
+        if self.inject(self.delay, |c| &mut c.delays) {
+            thread::sleep(self.delay_time);
+        }
+        self.inject(self.disconnect, |c| &mut c.disconnects)
+    }
+
+    //按概率修改事件(包头+内容)的最后一个字节(启用checksum时为crc32)
+    //FORMAT_DESCRIPTION_EVENT的checksum用于判断是否启用checksum, 不修改
+    pub(crate) fn corrupt_event(&self, buf: &mut [u8]) {
+        if buf.len() <= 19 || buf[4] == 15 {
+            return;
+        }
+        if self.inject(self.corrupt, |c| &mut c.corruptions) {
+            let last = buf.len() - 1;
+            buf[last] ^= 0xff;
+        }
+    }
+
+    fn sink_fault(&self, op: &str) -> io::Result<()> {
+        if self.inject(self.sink_error, |c| &mut c.sink_errors) {
+            return Err(io::Error::other(format!("注入的输出错误: {}", op)));
+        }
+        Ok(())
+    }
+}
+
+/// 按概率写入失败的输出目标, 见Faults::sink
+pub struct FaultySink<S>{
+    inner: S,
+    faults: Faults,
+}
+
+impl<S> FaultySink<S> {
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Sink> Sink for FaultySink<S> {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        self.faults.sink_fault("write_event")?;
+        self.inner.write_event(event)
+    }
+
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        self.faults.sink_fault("write_heartbeat")?;
+        self.inner.write_heartbeat(heartbeat)
+    }
+
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        self.faults.sink_fault("write_watermark")?;
+        self.inner.write_watermark(watermark)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.faults.sink_fault("flush")?;
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,1998 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/9/17
+*/
+
+//命令行参数解析及各运行模式的入口
+//每个子命令对应一种运行模式, 解析后统一转换为Config, 内部仍通过runtype区分
+
+use std::io as stdio;
+use std::process;
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use crate::{Config, io, replication, create_conn, exitcode};
+use crate::mycnf::ClientOptions;
+use crate::stdout::summary;
+use crate::secret::{Secret, SecretFetcher, FileSecret, CommandSecret};
+
+#[derive(Debug, Parser)]
+#[command(name = "mytest", version, about = "mysql binlog解析、过滤、统计、回滚工具", disable_help_subcommand = true)]
+pub struct Opt {
+    #[command(subcommand)]
+    pub command: Command,
+
+    #[arg(long = "strict", global = true, help = "严格解析模式: 遇到未知的事件类型、与字段类型不符的metadata、事件末尾未解析的数据时以解析错误退出, 默认输出警告后继续")]
+    pub strict: bool,
+
+    #[arg(long = "lenient-checksum", global = true, help = "配置了--verify-checksum时checksum错误只输出警告并计数(summary中的warnings), 继续解析, 默认以解析错误退出")]
+    pub lenientchecksum: bool,
+
+    #[arg(long = "memory-limit", global = true, help = "队列、批量及合并输出时缓存数据的内存上限, 可以带K、M、G后缀, 默认不限制; 按事件的大小估算, 需要留出余量")]
+    pub memorylimit: Option<String>,
+
+    #[arg(long = "memory-policy", global = true, help = "超过内存上限时的处理方式: block(默认, 提前写出或等待写出后再读取)、spill(行事件写入系统临时目录)、spill:目录")]
+    pub memorypolicy: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// 模拟slave从mysql拉取binlog并解析
+    #[command(disable_help_flag = true)]
+    Stream(Box<StreamArgs>),
+
+    /// 从binlog文件解析数据
+    #[command(disable_help_flag = true)]
+    Parse(Box<ParseArgs>),
+
+    /// 统计binlog文件中每个事务的大小
+    #[command(disable_help_flag = true)]
+    Analyze(AnalyzeArgs),
+
+    /// 模拟slave从mysql拉取binlog, 原样保存到本地目录
+    #[command(disable_help_flag = true)]
+    Backup(BackupArgs),
+
+    /// 反转binlog文件中的数据为回滚日志
+    #[command(disable_help_flag = true)]
+    Flashback(FlashbackArgs),
+
+    /// 校验binlog文件是否完整, checksum、position是否正确, 不需要连接数据库
+    Verify(VerifyArgs),
+
+    /// 从部分被覆盖的binlog文件或磁盘镜像中扫描事件, 提取完整的事务写入新的binlog文件, 用于数据恢复
+    Carve(CarveArgs),
+
+    /// 将binlog文件中满足过滤条件的事件写入新的binlog文件, 不需要连接数据库
+    Split(SplitArgs),
+
+    /// 生成合成的binlog文件, 用于压测解析及输出目标, 不需要连接数据库
+    Synthesize(SynthesizeArgs),
+
+    /// 建立事务索引后交互式查询binlog文件, 例如: show transactions where table='orders' and ts > '2019-11-01 10:00'
+    Inspect(InspectArgs),
+
+    /// 交互式浏览binlog文件: 按事务列出, 展开查看行数据, 按表过滤, 跳转到position/时间
+    #[cfg(feature = "tui")]
+    Tui(TuiArgs),
+
+    /// 在一个进程中运行配置文件中定义的多条管道(连接、过滤条件、输出文件、checkpoint), 每条管道可以单独暂停、停止、重新启动
+    #[cfg(feature = "json-sink")]
+    Pipelines(PipelinesArgs),
+
+    /// 重放stream --record录制的数据包, 经过与拉取时相同的处理后以json行输出, 不需要连接数据库(没有列名, 按@1、@2...输出)
+    #[cfg(feature = "json-sink")]
+    Replay(ReplayArgs),
+
+    /// 按配置文件中的一条管道回填归档的binlog文件(gtid或时间范围), 使用该管道的过滤条件及输出目标, checkpoint与实时拉取分开保存
+    #[cfg(feature = "json-sink")]
+    Backfill(BackfillArgs),
+
+    /// 检查多个来源的归档binlog目录: 相同的文件、gtid重叠的事务, 输出读取顺序, -o写入去重后的index文件
+    Archive(ArchiveArgs),
+
+    /// 按split、carve、backup等--manifest写入的清单校验输出文件的大小及sha256, 不需要重新解析文件
+    Manifest(ManifestArgs),
+
+    /// 读取管道的重放日志(journal), 列出已输出但没有保存checkpoint的事务, 用于崩溃后人工核对
+    Doctor(DoctorArgs),
+
+    /// 根据gtid或时间找到事务所在的binlog文件及position, 读取-f/--index指定的文件, 或连接mysql通过SHOW BINARY LOGS获取
+    #[command(disable_help_flag = true)]
+    Locate(LocateArgs),
+
+    /// 按主键还原一行数据的变更历史: 每次insert、update、delete的时间、gtid、position及前后镜像, 读取-f/--index指定的文件或连接mysql拉取
+    #[command(disable_help_flag = true)]
+    History(HistoryArgs),
+
+    /// 不能使用主从协议拉取时通过SHOW BINLOG EVENTS分页读取, 按事务输出语句(DDL、statement格式的DML、原始语句)及写入的表, 用于审计; 没有行数据及事件时间
+    #[command(disable_help_flag = true)]
+    Events(EventsArgs),
+
+    /// 在基础数据(csv)上应用binlog中的变更, 输出某个时间点表中的数据(csv), 不需要完整的时间点恢复
+    #[command(disable_help_flag = true)]
+    Snapshot(SnapshotArgs),
+
+    /// 识别mysql的版本及分支(MySQL、Percona、MariaDB、Aurora), 输出可以解析的事件类型及metadata, 读取-f指定的文件或连接mysql
+    #[command(disable_help_flag = true)]
+    Capabilities(CapabilitiesArgs),
+
+    /// 连接mysql查询表结构, 输出json输出(--outformat json)中每个表的行数据对应的JSON Schema, 用于下游校验及生成代码
+    #[command(disable_help_flag = true)]
+    Schema(JsonSchemaArgs),
+
+    /// 实验性: 通过主从协议提供备份的binlog文件(模拟master), mysql从库可以CHANGE MASTER到该地址进行恢复演练
+    Serve(ServeArgs),
+
+    /// 在mysql上执行sql语句
+    #[command(disable_help_flag = true)]
+    Apply(ApplyArgs),
+
+    /// 应用binlog后校验目标库: 按主键取binlog中每行最终的数据, 到目标库查询并比较行校验和
+    #[command(disable_help_flag = true)]
+    Rowcheck(RowcheckArgs),
+
+    /// 生成shell补全脚本, 例如: mytest completions bash > /etc/bash_completion.d/mytest
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+//-h用于指定host, 需要连接数据库的子命令只保留--help
+#[derive(Debug, Args)]
+pub struct ConnArgs {
+    #[arg(short = 'u', long = "user", help = "mysql用户名")]
+    pub user: Option<String>,
+
+    #[arg(short = 'p', long = "password", help = "mysql密码, 建议通过MYSQL_PWD环境变量或配置文件设置, 避免在ps中看到")]
+    pub password: Option<String>,
+
+    #[arg(long = "password-file", conflicts_with_all = ["password", "password_command"], help = "从文件读取mysql密码, 比如k8s挂载的secret文件")]
+    pub password_file: Option<String>,
+
+    #[arg(long = "password-command", conflicts_with = "password", help = "执行命令获取mysql密码, 使用命令的标准输出, 例如: vault kv get -field=password secret/mysql")]
+    pub password_command: Option<String>,
+
+    #[arg(short = 'h', long = "host", help = "ip地址加端口, ip:port 例如127.0.0.1:3306, ipv6为[::1]:3306")]
+    pub host: Option<String>,
+
+    #[arg(short = 'S', long = "socket", conflicts_with_all = ["host", "ssh"], help = "通过本地的unix socket连接mysql, 例如/var/lib/mysql/mysql.sock")]
+    pub socket: Option<String>,
+
+    #[arg(long = "ssh", help = "通过ssh隧道连接mysql, user@host[:port], -h为从ssh主机访问mysql的地址; 使用系统的ssh客户端, 需要密钥或ssh-agent认证")]
+    pub ssh: Option<String>,
+
+    #[arg(long = "ssh-key", requires = "ssh", help = "ssh隧道使用的私钥文件")]
+    pub ssh_key: Option<String>,
+
+    #[arg(long = "defaults-file", help = "读取连接信息的配置文件, 使用其中的[client]、[mytest]组, 默认读取/etc/my.cnf、/etc/mysql/my.cnf、~/.my.cnf")]
+    pub defaults_file: Option<String>,
+
+    #[arg(long = "compress", num_args = 0..=1, default_missing_value = "zlib", help = "使用压缩协议连接mysql, 跨机房拉取binlog时减少流量, 值为zlib或off, 只指定--compress时为zlib; 压缩比记录在--summary及控制接口的/status中")]
+    pub compress: Option<String>,
+
+    #[arg(long = "auth-plugin", help = "认证方式, mysql_native_password、caching_sha2_password、sha256_password或aws_iam, 默认使用服务端握手包中的方式; aws_iam为RDS/Aurora的IAM认证, 不需要密码, 每次连接时用aws访问密钥生成token")]
+    pub auth_plugin: Option<String>,
+
+    #[arg(long = "aws-region", help = "aws_iam认证生成token使用的region, 默认读取AWS_REGION环境变量, 没有设置时从RDS地址中获取")]
+    pub aws_region: Option<String>,
+
+    #[arg(long = "aws-endpoint", help = "aws_iam认证生成token使用的RDS地址(host:port), 通过stunnel等ssl隧道连接时指定, 默认为-h的地址")]
+    pub aws_endpoint: Option<String>,
+
+    #[arg(long = "aws-profile", help = "aws_iam认证没有设置AWS_ACCESS_KEY_ID等环境变量时读取~/.aws/credentials中的该组, 默认AWS_PROFILE环境变量或default; 都没有时使用ECS任务角色或EC2实例角色的临时密钥")]
+    pub aws_profile: Option<String>,
+
+    #[arg(long = "connect-attr", action = ArgAction::Append, help = "握手时发送的连接属性key=value, 可以指定多次, 在performance_schema.session_connect_attrs中查看; 默认发送_client_name=binlogAL、_client_version、_os、_pid、program_name、host(本机主机名), 同名时替换")]
+    pub connect_attr: Vec<String>,
+
+    #[arg(long = "proxy-protocol", help = "连接开头发送PROXY protocol头, v1或v2, 用于要求PROXY头的负载均衡、代理(haproxy、ProxySQL等)")]
+    pub proxy_protocol: Option<String>,
+
+    #[arg(long = "init-command", action = ArgAction::Append, help = "连接认证之后、注册同步之前执行的语句, 可以指定多次, 例如SET NAMES utf8mb4; 只能是SET等不返回结果集的语句")]
+    pub init_command: Vec<String>,
+
+    #[arg(long = "packet-trace", help = "把与mysql之间收发的数据包(握手、capability flags、dump命令、每个包的前N个字节)追加写入该文件, 用于排查与代理、分支版本的协议兼容问题; 认证数据、连接属性及语句中的密码已隐藏")]
+    pub packet_trace: Option<String>,
+
+    #[arg(long = "packet-trace-bytes", requires = "packet_trace", help = "--packet-trace中每个包输出的字节数(包括4字节包头), 默认64")]
+    pub packet_trace_bytes: Option<String>,
+
+    #[arg(long = "dry-run", help = "只检查配置、连接、mysql的binlog设置、权限及起始位置, 输出执行计划, 不拉取或解析数据")]
+    pub dry_run: bool,
+
+    #[arg(long = "help", action = ArgAction::Help, help = "显示帮助信息")]
+    pub help: Option<bool>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReplArgs {
+    #[arg(long = "gtid", conflicts_with_all = ["binlogfile", "position"], help = "使用gtid注册同步, 值为已执行的gtid集合")]
+    pub gtid: Option<String>,
+
+    #[arg(long = "binlogfile", help = "使用binlog文件及position注册同步")]
+    pub binlogfile: Option<String>,
+
+    #[arg(long = "position", requires = "binlogfile", help = "注册同步使用的position, 默认4")]
+    pub position: Option<String>,
+
+    #[arg(long = "serverid", help = "注册用的server_id，不能与已经存在的同步线程重复, 默认为133")]
+    pub serverid: Option<String>,
+
+    #[arg(long = "until-gtid", conflicts_with = "until_position", help = "读取完该gtid集合中的所有事务后停止(与START REPLICA UNTIL SQL_AFTER_GTIDS相同), 在事务结束后断开连接并以0退出, 没有输出任何数据时退出码为7")]
+    pub until_gtid: Option<String>,
+
+    #[arg(long = "until-position", help = "已执行位置到达或超过该位置(文件:position, 如mysql-bin.000003:1234)时停止, 边界在事务中间时读取完该事务")]
+    pub until_position: Option<String>,
+
+    #[arg(long = "verify-checksum", help = "校验checksum的事件类型, rows、table_map、query、control以逗号分隔, 或all、none, 默认none")]
+    pub verify_checksum: Option<String>,
+
+    #[arg(long = "heartbeat", help = "心跳间隔(秒), 没有新事件时mysql按该间隔发送心跳, --outformat json时输出{\"type\":\"heartbeat\"...}记录(当前位置、gtid、时间), 用于区分没有数据变化与同步中断")]
+    pub heartbeat: Option<String>,
+
+    #[arg(long = "watermark", help = "事件时间水位的输出间隔(秒), --outformat json时在事务之间输出{\"type\":\"watermark\",\"watermark\":...}记录, 为已提交事务的最大提交时间(只增不减), 按事件时间开窗的下游(Flink、Kafka Streams)据此关闭窗口")]
+    pub watermark: Option<String>,
+
+    #[arg(long = "watermark-delay", requires = "watermark", help = "水位允许的乱序秒数, 水位为最大提交时间减去该值, 默认0")]
+    pub watermark_delay: Option<String>,
+
+    #[arg(long = "control", help = "http控制接口的监听地址(如127.0.0.1:8090), 不重启进程暂停/恢复读取(POST /pause、/resume)、回退到之前的gtid/position(POST /rewind?gtid=或?file=&position=)、跳过指定事务(POST /skip?gtid=), GET /status查看当前状态")]
+    pub control: Option<String>,
+
+    #[arg(long = "health-timeout", help = "健康检查超时(秒), 读取到的事务超过该时间仍未输出时控制接口的/healthz返回503, 默认60; 配置了--heartbeat时超过3倍心跳间隔没有任何事件同样返回503, /readyz另外检查连接、暂停状态")]
+    pub health_timeout: Option<String>,
+
+    #[arg(long = "sd-notify", help = "通过NOTIFY_SOCKET通知systemd: 注册成功后发送READY=1, 服务配置了WatchdogSec时在健康检查通过时定期发送WATCHDOG=1")]
+    pub sd_notify: bool,
+
+    #[arg(long = "status-interval", help = "按该间隔(秒)在stderr输出已执行的gtid集合及对应的binlog文件、position(事务之间), 格式可以直接用于CHANGE REPLICATION SOURCE; --control的GET /status中的executed同样为该位置")]
+    pub status_interval: Option<String>,
+
+    #[arg(long = "exclude-tables", help = "不输出的库表, 逗号分隔的db.tbl, 可以使用*, none为不排除; 默认为mysql、sys、information_schema、performance_schema库及gh-ost(_tbl_gho/_ghc/_del)、pt-online-schema-change(_tbl_new/_old)的影子表")]
+    pub exclude_tables: Option<String>,
+
+    #[arg(long = "osc-remap", help = "gh-ost(_tbl_gho)、pt-online-schema-change(_tbl_new)影子表的行数据按原表名输出, 改表期间下游持续接收新表结构的数据; 影子表的数据与原表重复, 下游需要按主键覆盖写入")]
+    pub osc_remap: bool,
+
+    #[arg(long = "compat", help = "托管mysql及中间件的兼容模式, rds或aurora: 不输出mysql.rds_*等内部表、权限检查不通过时只提示、连接到只读实例(集群地址尚未切换)时退出等待重新启动; proxysql、maxscale: 经过中间件连接, 不支持的组合在连接时给出原因")]
+    pub compat: Option<String>,
+
+    #[arg(long = "pause-on-switch", requires = "control", help = "检测到主库切换(server_id或gtid的server_uuid变化)时暂停读取, 检查/status中的master_switch后POST /resume继续")]
+    pub pause_on_switch: bool,
+
+    #[arg(long = "record", help = "把收到的每个数据包连同接收时间写入该文件, 之后通过replay子命令复现解析过程, 用于排查生产环境的问题; 控制接口回退后的数据包同样追加写入")]
+    pub record: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct FileArgs {
+    #[arg(short = 'f', long = "file", required = true, help = "binlog文件")]
+    pub file: Option<String>,
+
+    #[arg(long = "relaylog", help = "读取的文件为relay log, position按relay log文件偏移量计算, 并跳过slave自身生成的事件")]
+    pub relaylog: bool,
+
+    #[arg(short = 'q', long = "quiet", help = "不在stderr输出读取进度")]
+    pub quiet: bool,
+
+    #[arg(long = "verify-checksum", help = "校验checksum的事件类型, rows、table_map、query、control以逗号分隔, 或all、none, verify默认all, 其余默认none")]
+    pub verify_checksum: Option<String>,
+}
+
+//读取binlog文件时的范围
+#[derive(Debug, Args)]
+pub struct RangeArgs {
+    #[arg(long = "startposition", help = "从binlog文件提取数据时的position起始位置")]
+    pub startposition: Option<String>,
+
+    #[arg(long = "stopposition", help = "从binlog文件提取数据时停止的位置")]
+    pub stopposition: Option<String>,
+
+    #[arg(long = "startdatetime", help = "读取binlog文件数据的时间范围(时间戳格式)")]
+    pub startdatetime: Option<String>,
+
+    #[arg(long = "stopdatetime", help = "读取binlog文件数据的时间范围(时间戳格式)")]
+    pub stopdatetime: Option<String>,
+
+    #[arg(long = "gtid", help = "提取对应gtid的数据")]
+    pub gtid: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct FilterArgs {
+    #[arg(long = "threadid", help = "过滤出某个线程id所产生的binlog数据")]
+    pub threadid: Option<String>,
+
+    #[arg(long = "greptbl", help = "过滤出某个表所产生的binlog数据，格式为{'db1':['tb1','tb2'],'db2':[]...}}, 如果要提取一个库的所有表格式为{'db1':'all',....}")]
+    pub greptbl: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SqlArgs {
+    #[arg(long = "getsql", conflicts_with = "outformat", help = "提取sql语句")]
+    pub getsql: bool,
+
+    #[arg(long = "batchsize", requires = "getsql", help = "将连续插入同一个表的数据合并为多行insert语句, 设置每个insert语句最多包含的行数")]
+    pub batchsize: Option<String>,
+
+    #[arg(long = "insertmode", requires = "getsql", help = "insert语句的生成方式: insert、replace(REPLACE INTO)、upsert(INSERT ... ON DUPLICATE KEY UPDATE), 默认insert")]
+    pub insertmode: Option<String>,
+
+    #[arg(long = "no-key", requires = "getsql", help = "没有主键及非空唯一键的表的处理方式: warn(默认, 按所有字段匹配并输出一次警告)、full-row(按所有字段匹配, mysql方言追加LIMIT 1, 重复的行只修改一行)、skip(不输出该表的行数据)")]
+    pub no_key: Option<String>,
+
+    #[arg(long = "dialect", requires = "getsql", help = "生成的sql语句的方言: mysql(默认)、ansi、sqlite、postgresql, 决定标识符的引号、字符串及二进制、时间、布尔(tinyint(1))字面值及upsert的语法, 用于应用到mysql以外的目标库")]
+    pub dialect: Option<String>,
+
+    #[arg(long = "ddl-fence", requires = "getsql", help = "DDL修改之前输出过DML的表时, 在DDL前后输出-- DDL FENCE BEGIN/END标记, 拆分或并行回放时据此隔离DML与DDL: mark只输出标记, wait:秒数为标记之后再等待(DO SLEEP或pg_sleep)")]
+    pub ddl_fence: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct FormatArgs {
+    #[arg(long = "timezone", help = "TIMESTAMP类型的输出方式, epoch(默认)保持时间戳输出, 或配置时区名称(如UTC、Asia/Shanghai)转换为该时区的时间")]
+    pub timezone: Option<String>,
+
+    #[arg(long = "timeformat", help = "日期时间类型的输出格式: mysql(默认, 2019-10-14 10:00:00)、iso8601(2019-10-14T10:00:00)")]
+    pub timeformat: Option<String>,
+
+    #[arg(long = "floatprecision", help = "float/double类型输出时保留的小数位数, 默认按最短表示输出")]
+    pub floatprecision: Option<String>,
+
+    #[arg(long = "nonfinite", help = "float/double为NaN、Infinity时的输出方式: null(默认)、string(NaN、Infinity、-Infinity)、error(停止解析)")]
+    pub nonfinite: Option<String>,
+
+    #[arg(long = "zerodate", help = "0000-00-00等零值及无效日期时间的输出方式: literal(默认, 原样输出)、null、error(停止解析)")]
+    pub zerodate: Option<String>,
+
+    #[arg(long = "quotedecimal", help = "decimal类型以字符串输出, 避免下游按浮点数解析丢失精度")]
+    pub quotedecimal: bool,
+
+    #[arg(long = "lobmaxsize", help = "BLOB/TEXT类型超过该字节数时按lobmode截断或替换为hash值输出")]
+    pub lobmaxsize: Option<String>,
+
+    #[arg(long = "lobmode", requires = "lobmaxsize", help = "BLOB/TEXT类型超过lobmaxsize时的输出方式: truncate(默认, 输出前lobmaxsize个字节并标记原始长度)、hash(输出sha256值及原始长度)")]
+    pub lobmode: Option<String>,
+
+    #[arg(long = "outformat", help = "行数据的输出格式: pretty(默认, 每个事件一行: 时间、gtid、库表、操作、行数、字节数)、text(逐个事件输出全部内容)、json(每行一个json对象)、csv")]
+    pub outformat: Option<String>,
+
+    #[arg(long = "template", conflicts_with_all = ["outformat", "getsql", "audit", "compact", "count"], help = "按模板输出(兼容handlebars常用语法), 例如: \"{{datetime}}|{{type}}|{{database}}.{{table}}|{{data.id}}\", 可以使用行数据、语句、事务提交的字段, @文件名为从文件读取模板")]
+    pub template: Option<String>,
+
+    #[arg(short = 'v', long = "verbose", help = "pretty输出时逐行输出数据, update只输出主键及变化的字段")]
+    pub verbose: bool,
+
+    #[arg(long = "color", help = "pretty输出的颜色: auto(默认, stdout为终端且没有设置NO_COLOR时)、always、never")]
+    pub color: Option<String>,
+
+    #[arg(long = "binaryencoding", help = "binary、varbinary、blob等二进制类型的输出编码: hex(默认)、base64、escaped")]
+    pub binaryencoding: Option<String>,
+
+    #[arg(long = "binarycolumns", help = "单独设置某些字段的二进制编码, 格式: {\"db.tbl.col\":\"base64\"}")]
+    pub binarycolumns: Option<String>,
+
+    #[arg(long = "includecolumns", help = "text/json/csv输出时每个表只输出这些字段, 格式: {\"db.tbl\":[\"id\",\"name\"]}, 表可以写为db.*或*")]
+    pub includecolumns: Option<String>,
+
+    #[arg(long = "excludecolumns", help = "text/json/csv输出时不输出这些字段(敏感或较大的字段), 格式与includecolumns相同")]
+    pub excludecolumns: Option<String>,
+
+    #[arg(long = "spatialformat", help = "空间类型(geometry、point、polygon等)json输出的格式: wkt(默认, srid不为0时为SRID=4326;POINT(1 2))、geojson, getsql时为ST_GeomFromText(...)")]
+    pub spatialformat: Option<String>,
+
+    #[arg(long = "labels", help = "静态标签, 逗号分隔的key=value(如env=prod,cluster=order,shard=3), json输出的每条记录及--summary、控制接口/status中带labels对象, 用于多个分片输出到同一个下游时汇总及路由")]
+    pub labels: Option<String>,
+
+    #[arg(long = "boundaries", help = "json输出时每个事务的行数据前后输出begin、commit记录(gtid、xid、行数), 下游据此按事务还原原子性, 没有输出行数据的事务不输出")]
+    pub boundaries: bool,
+
+    #[arg(long = "row-hash", help = "json输出时每行带row_hash(update另有old_row_hash), 应用后可通过rowcheck子命令在目标库校验")]
+    pub row_hash: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct AuditArgs {
+    #[arg(long = "audit", conflicts_with_all = ["getsql", "outformat"], help = "审计输出: 每行数据一个json对象, 包含时间、gtid、thread_id、用户、原始sql(需开启binlog_rows_query_log_events)及修改的数据")]
+    pub audit: bool,
+
+    #[arg(long = "audit-enrich", requires = "audit", help = "binlog中没有记录用户时, 通过performance_schema.threads查询thread_id对应的用户, 只能查到仍然存在的连接")]
+    pub audit_enrich: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SummaryArgs {
+    #[arg(long = "summary", help = "结束或被中断时输出json格式的汇总信息(事件数、事务数、字节数、涉及的表、首尾gtid及时间、错误), -为输出到stderr, 否则写入指定文件")]
+    pub summary: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct CompactArgs {
+    #[arg(long = "compact", conflicts_with_all = ["getsql", "audit"], help = "同一主键的多次修改合并为最终数据(删除为删除标记), 按表、主键排序以json输出, 用于只需要最终状态的批量同步")]
+    pub compact: bool,
+
+    #[arg(long = "compactwindow", requires = "compact", help = "--compact每N个事务输出一次并清空, 默认读取结束时一次输出")]
+    pub compactwindow: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct CountArgs {
+    #[arg(long = "count", conflicts_with_all = ["getsql", "audit", "compact", "outformat"], help = "不输出数据, 结束时只输出通过过滤条件的行数(update计为1行, 非BEGIN/COMMIT的语句计为1行), 没有匹配时输出0并以exit code 7退出")]
+    pub count: bool,
+
+    #[arg(long = "countby", requires = "count", help = "--count按逗号分隔的db、table、op(insert/update/delete/query)分组, 每组输出一行: 分组的值及行数, tab分隔")]
+    pub countby: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SchemaArgs {
+    #[arg(long = "schemaout", help = "表结构变化时输出表结构文档(字段、类型、主键、DDL、位置), 每行一个json对象, -为输出到stdout, 否则写入指定文件")]
+    pub schemaout: Option<String>,
+
+    #[arg(long = "create-table", help = "根据TABLE_MAP中的字段信息(需要binlog_row_metadata=FULL)生成CREATE TABLE语句, 用于初始化目标库的表结构, 不需要连接源库, -为输出到stdout, 否则写入指定文件")]
+    pub createtable: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    #[arg(long = "watch", help = "监控危险操作的规则文件(json), DROP/TRUNCATE等语句或单个事务删除/更新的行数超过阈值时发送webhook、slack、邮件通知")]
+    pub watch: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ColumnStatsArgs {
+    #[arg(long = "column-stats", help = "按表统计行数/秒、每个字段的NULL比例及平均值大小, 与之前的数据比较发现NULL激增、大量删除等异常(输出到stderr), 读取结束时写入报告, -为输出到stderr; 统计结果在--summary及控制接口/status的column_stats中")]
+    pub columnstats: Option<String>,
+
+    #[arg(long = "column-stats-window", requires = "columnstats", help = "--column-stats的滚动窗口, 单位秒, 默认60")]
+    pub columnstatswindow: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RowFilterArgs {
+    #[arg(long = "where", help = "按字段值过滤行数据, 例如: \"orders.status != 'test' and users.country in ('DE','FR')\", 只对条件中出现的表生效")]
+    pub rowfilter: Option<String>,
+
+    #[arg(long = "sample", help = "按表抽样输出行数据, 格式: {\"db.tbl\":\"1/100\",\"db.*\":\"key:3/16\"}, 1/N为每N行输出1行, key:B/N为主键hash对N取余等于B的行")]
+    pub sample: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct StreamArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+    #[command(flatten)]
+    pub summary: SummaryArgs,
+    #[command(flatten)]
+    pub repl: ReplArgs,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+    #[command(flatten)]
+    pub sql: SqlArgs,
+    #[command(flatten)]
+    pub format: FormatArgs,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub rowfilter: RowFilterArgs,
+    #[command(flatten)]
+    pub compact: CompactArgs,
+    #[command(flatten)]
+    pub count: CountArgs,
+    #[command(flatten)]
+    pub schema: SchemaArgs,
+    #[command(flatten)]
+    pub watch: WatchArgs,
+    #[command(flatten)]
+    pub colstats: ColumnStatsArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ParseArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+    #[command(flatten)]
+    pub summary: SummaryArgs,
+    #[command(flatten)]
+    pub file: FileArgs,
+    #[command(flatten)]
+    pub range: RangeArgs,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+    #[command(flatten)]
+    pub sql: SqlArgs,
+    #[command(flatten)]
+    pub format: FormatArgs,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub rowfilter: RowFilterArgs,
+    #[command(flatten)]
+    pub compact: CompactArgs,
+    #[command(flatten)]
+    pub count: CountArgs,
+    #[command(flatten)]
+    pub schema: SchemaArgs,
+    #[command(flatten)]
+    pub watch: WatchArgs,
+    #[command(flatten)]
+    pub colstats: ColumnStatsArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+    #[command(flatten)]
+    pub summary: SummaryArgs,
+    #[command(flatten)]
+    pub file: FileArgs,
+    #[command(flatten)]
+    pub range: RangeArgs,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+    #[command(flatten)]
+    pub hotspot: HotspotArgs,
+    #[command(flatten)]
+    pub retention: RetentionArgs,
+    #[command(flatten)]
+    pub colstats: ColumnStatsArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct RetentionArgs {
+    #[arg(long = "retention", help = "按分析文件的写入速度及mysql上binlog的保留设置(实时查询), 估算最早可以恢复/回滚到的时间, 以及需要的binlog文件预计何时被清理")]
+    pub retention: bool,
+
+    #[arg(long = "need-binlog", requires = "retention", help = "--retention检查的binlog文件名, 例如下游checkpoint所在的文件, 默认为分析的文件")]
+    pub need_binlog: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct HotspotArgs {
+    #[arg(long = "hotspot", help = "按时间段统计每个表的insert/update/delete行数及字节数, 并输出修改次数最多的主键")]
+    pub hotspot: bool,
+
+    #[arg(long = "interval", help = "--hotspot、--groupcommit统计的时间段长度, 单位秒, 默认300、60")]
+    pub interval: Option<String>,
+
+    #[arg(long = "topkeys", requires = "hotspot", help = "--hotspot输出修改次数最多的主键数量, 默认10")]
+    pub topkeys: Option<String>,
+
+    #[arg(long = "deps", conflicts_with_all = ["hotspot", "ddl"], help = "导出事务的last_committed/sequence_number依赖关系, 用于分析并行复制的并行度, -为输出到stdout")]
+    pub deps: Option<String>,
+
+    #[arg(long = "depsformat", requires = "deps", help = "--deps的输出格式: dot(默认, graphviz)、json")]
+    pub depsformat: Option<String>,
+
+    #[arg(long = "groupcommit", conflicts_with_all = ["hotspot", "deps", "ddl"], help = "按last_committed及提交时间统计binlog组提交的大小、fsync间隔及其随时间的变化, 用于调整binlog_group_commit_sync_delay")]
+    pub groupcommit: bool,
+
+    #[arg(long = "ddl", conflicts_with = "hotspot", help = "只提取表结构变更语句(CREATE/ALTER/DROP/RENAME/TRUNCATE), 连同时间、gtid、执行时长按顺序写入指定文件, -为输出到stdout")]
+    pub ddl: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct BackupArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+    #[command(flatten)]
+    pub repl: ReplArgs,
+
+    #[arg(long = "output", required = true, help = "保存binlog文件的目录")]
+    pub output: Option<String>,
+
+    #[arg(long = "manifest", help = "写入manifest文件: 每个结束(rotate)的binlog文件的大小、sha256、gtid集合、事务数及行数, 已存在时追加")]
+    pub manifest: Option<String>,
+
+    #[arg(long = "output-compress", help = "压缩保存的binlog文件: gzip[:1-9]、zstd[:级别][,dict=字典文件,window-log=N,checksum]、lz4[,block=64k|256k|1m|4m,checksum], 均可加workers=N并行压缩; 文件名加上.gz、.zst、.lz4")]
+    pub output_compress: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct FlashbackArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+    #[command(flatten)]
+    pub summary: SummaryArgs,
+    #[command(flatten)]
+    pub file: FileArgs,
+    #[command(flatten)]
+    pub range: RangeArgs,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    #[arg(long = "rfilesize", help = "单个回滚日志文件大小, 可以不用设置, 默认1G, 设置值是以字节为单位")]
+    pub rfilesize: Option<String>,
+
+    #[arg(long = "maxrows", help = "回滚的总行数超过该值时拒绝生成回滚文件(退出码9), 默认不限制")]
+    pub maxrows: Option<String>,
+
+    #[arg(long = "chunkrows", help = "将回滚事务按行数切分为多个较小的事务, 只在行事件之间切分, 默认不切分")]
+    pub chunkrows: Option<String>,
+
+    #[arg(long = "force", help = "回滚超过--maxrows或范围内包含DDL时仍然生成回滚文件, DDL会被跳过")]
+    pub force: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    #[command(flatten)]
+    pub file: FileArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    #[command(flatten)]
+    pub file: FileArgs,
+
+    #[arg(short = 'e', long = "execute", help = "只执行一条命令后退出, 不进入交互模式")]
+    pub execute: Option<String>,
+}
+
+#[cfg(feature = "json-sink")]
+#[derive(Debug, Args)]
+pub struct PipelinesArgs {
+    #[arg(long = "config", required = true, help = "管道配置文件, [pipeline]组为默认值, 每个[pipeline.名称]组为一条管道, 配置项见README")]
+    pub config: Option<String>,
+
+    #[arg(long = "control", help = "http控制接口的监听地址, GET /pipelines查看各管道的状态及指标, POST /pipelines/{名称}/start|stop|restart|pause|resume等")]
+    pub control: Option<String>,
+}
+
+#[cfg(feature = "json-sink")]
+#[derive(Debug, Args)]
+pub struct BackfillArgs {
+    #[arg(long = "config", required = true, help = "管道配置文件, 与pipelines子命令相同")]
+    pub config: Option<String>,
+
+    #[arg(long = "pipeline", required = true, help = "回填使用的管道名称, 使用其tables、where、output、checkpoint、journal等配置, checkpoint保存为{名称}.backfill")]
+    pub pipeline: Option<String>,
+
+    #[arg(short = 'f', long = "file", required_unless_present_any = ["index", "dir"], conflicts_with_all = ["index", "dir"], help = "归档的binlog文件, 多个文件以逗号分隔, 按顺序读取")]
+    pub file: Option<String>,
+
+    #[arg(long = "index", conflicts_with = "dir", help = "binlog的index文件(mysql-bin.index), 相对路径按index文件所在的目录")]
+    pub index: Option<String>,
+
+    #[arg(long = "dir", help = "多个来源的归档目录, 以逗号分隔, 相同的文件只读取一次, 按previous_gtids排序, 重叠的gtid只回填一次")]
+    pub dir: Option<String>,
+
+    #[arg(long = "startdatetime", help = "跳过该时间之前的事务, unix时间戳或utc时间(%Y-%m-%d %H:%M:%S)")]
+    pub startdatetime: Option<String>,
+
+    #[arg(long = "stopdatetime", help = "回填到该时间为止, 事务的时间晚于该时间时结束, unix时间戳或utc时间(%Y-%m-%d %H:%M:%S)")]
+    pub stopdatetime: Option<String>,
+
+    #[arg(long = "gtid", help = "只回填该gtid集合中的事务, 格式为uuid:1-100")]
+    pub gtid: Option<String>,
+}
+
+#[cfg(feature = "json-sink")]
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+    #[arg(short = 'f', long = "file", required = true, help = "stream --record录制的文件")]
+    pub file: Option<String>,
+
+    #[arg(short = 'o', long = "output", help = "输出的文件, 默认stdout")]
+    pub output: Option<String>,
+
+    #[arg(long = "realtime", help = "按录制时数据包之间的间隔重放, 默认不等待")]
+    pub realtime: bool,
+
+    #[arg(long = "verify-checksum", help = "校验checksum的事件类型, rows、table_map、query、control以逗号分隔, 或all、none, 默认none")]
+    pub verify_checksum: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct DoctorArgs {
+    #[arg(long = "journal", required = true, help = "管道配置中journal指定的文件")]
+    pub journal: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ArchiveArgs {
+    #[arg(long = "dir", required = true, help = "归档的binlog目录或文件, 多个以逗号分隔, 如切换前后的旧主库、新主库")]
+    pub dir: Option<String>,
+
+    #[arg(short = 'o', long = "output", help = "写入去重后按顺序排列的文件, 格式与mysql-bin.index相同, 用于backfill等的--index")]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ManifestArgs {
+    #[arg(short = 'f', long = "file", required = true, help = "--manifest写入的清单文件, 其中的相对路径按清单所在的目录")]
+    pub file: Option<String>,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Debug, Args)]
+pub struct TuiArgs {
+    #[command(flatten)]
+    pub file: FileArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct SplitArgs {
+    #[command(flatten)]
+    pub summary: SummaryArgs,
+    #[command(flatten)]
+    pub file: FileArgs,
+    #[command(flatten)]
+    pub range: RangeArgs,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    #[arg(long = "output", required = true, help = "写入的新binlog文件")]
+    pub output: Option<String>,
+
+    #[arg(long = "manifest", help = "写入manifest文件: 新binlog文件的大小、sha256、gtid集合、事务数及行数, 已存在时按文件名替换或追加")]
+    pub manifest: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct CarveArgs {
+    #[arg(short = 'f', long = "file", required = true, help = "扫描的文件, 可以是损坏的binlog文件或恢复出的磁盘镜像, 读取全部内容到内存")]
+    pub file: Option<String>,
+
+    #[arg(short = 'o', long = "output", required = true, help = "提取出的事务写入的新binlog文件")]
+    pub output: Option<String>,
+
+    #[arg(long = "manifest", help = "写入manifest文件: 新binlog文件的大小、sha256、gtid集合、事务数及行数, 已存在时按文件名替换或追加")]
+    pub manifest: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SynthesizeArgs {
+    #[arg(short = 'o', long = "output", required = true, help = "生成的binlog文件")]
+    pub output: Option<String>,
+
+    #[arg(short = 'D', long = "database", help = "库名, 默认synth")]
+    pub database: Option<String>,
+
+    #[arg(long = "tables", help = "表的数量, 表名为t1..tN, 默认4")]
+    pub tables: Option<String>,
+
+    #[arg(long = "columns", help = "除主键id外各列的类型, 逗号分隔, 可选tinyint、int、bigint、double、varchar(n)、text、datetime, 默认int,varchar(64),double,datetime")]
+    pub columns: Option<String>,
+
+    #[arg(long = "transactions", help = "生成的事务数, 默认1000")]
+    pub transactions: Option<String>,
+
+    #[arg(long = "rows", help = "每个事务修改的行数, n或min-max, 默认10")]
+    pub rows: Option<String>,
+
+    #[arg(long = "rate", help = "每秒的事务数, 决定事件的时间戳, 默认100")]
+    pub rate: Option<String>,
+
+    #[arg(long = "mix", help = "insert:update:delete的比例, 默认6:3:1")]
+    pub mix: Option<String>,
+
+    #[arg(long = "startdatetime", help = "第一个事件的时间(时间戳格式), 默认为当前时间")]
+    pub startdatetime: Option<String>,
+
+    #[arg(long = "seed", help = "随机数种子, 相同的参数及种子生成相同的文件, 默认1")]
+    pub seed: Option<String>,
+
+    #[arg(long = "serverid", help = "事件中的server_id, 默认133")]
+    pub serverid: Option<String>,
+
+    #[arg(long = "manifest", help = "写入manifest文件: 生成的binlog文件的大小、sha256、gtid集合、事务数及行数, 已存在时按文件名替换或追加")]
+    pub manifest: Option<String>,
+}
+
+impl SynthesizeArgs {
+    fn fill(self, conf: &mut Config) {
+        match self.output {
+            None => {},
+            Some(t) => conf.output = t,
+        }
+        conf.manifest = self.manifest.unwrap_or_default();
+        match self.database {
+            None => {},
+            Some(t) => conf.database = t,
+        }
+        match self.tables {
+            None => {},
+            Some(t) => conf.tables = t,
+        }
+        match self.columns {
+            None => {},
+            Some(t) => conf.columns = t,
+        }
+        match self.transactions {
+            None => {},
+            Some(t) => conf.transactions = t,
+        }
+        match self.rows {
+            None => {},
+            Some(t) => conf.rows = t,
+        }
+        match self.rate {
+            None => {},
+            Some(t) => conf.rate = t,
+        }
+        match self.mix {
+            None => {},
+            Some(t) => conf.mix = t,
+        }
+        match self.startdatetime {
+            None => {},
+            Some(t) => conf.startdatetime = t,
+        }
+        match self.seed {
+            None => {},
+            Some(t) => conf.seed = t,
+        }
+        match self.serverid {
+            None => {},
+            Some(t) => conf.serverid = t,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ApplyArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+
+    #[arg(short = 'D', long = "database", help = "执行sql语句时连接的默认数据库")]
+    pub database: Option<String>,
+
+    #[arg(short = 'c', long = "command", required = true, help = "sql语句")]
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RowcheckArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+
+    #[arg(short = 'f', long = "file", required = true, help = "已应用到目标库的binlog文件, 多个文件以逗号分隔, 按顺序读取")]
+    pub file: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct LocateArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+
+    #[arg(long = "gtid", required_unless_present = "datetime", conflicts_with = "datetime", help = "查找的事务, 格式为uuid:N")]
+    pub gtid: Option<String>,
+
+    #[arg(long = "datetime", help = "查找该时间之后的第一个事务, unix时间戳或utc时间(%Y-%m-%d %H:%M:%S)")]
+    pub datetime: Option<String>,
+
+    #[arg(short = 'f', long = "file", conflicts_with = "index", help = "按顺序排列的binlog文件, 多个文件以逗号分隔")]
+    pub file: Option<String>,
+
+    #[arg(long = "index", help = "binlog的index文件(mysql-bin.index), 相对路径按index文件所在的目录")]
+    pub index: Option<String>,
+
+    #[arg(long = "serverid", help = "连接mysql按主从协议读取时注册用的server_id，不能与已经存在的同步线程重复, 默认为133")]
+    pub serverid: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct CapabilitiesArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+
+    #[arg(short = 'f', long = "file", help = "按binlog文件中FORMAT_DESCRIPTION_EVENT的版本识别, 不连接mysql")]
+    pub file: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct JsonSchemaArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+
+    #[arg(long = "tables", required = true, help = "输出schema的表, 逗号分隔的db.tbl, db.*为库中所有的表")]
+    pub tables: Option<String>,
+
+    #[arg(long = "timezone", help = "与stream/parse相同, epoch(默认)时TIMESTAMP为number, 否则为string")]
+    pub timezone: Option<String>,
+
+    #[arg(long = "nonfinite", help = "与stream/parse相同, string时float/double可以为string")]
+    pub nonfinite: Option<String>,
+
+    #[arg(long = "zerodate", help = "与stream/parse相同, null时日期时间可以为null")]
+    pub zerodate: Option<String>,
+
+    #[arg(long = "quotedecimal", help = "与stream/parse相同, decimal为string")]
+    pub quotedecimal: bool,
+
+    #[arg(long = "spatialformat", help = "与stream/parse相同, geojson时空间类型为object")]
+    pub spatialformat: Option<String>,
+
+    #[arg(long = "includecolumns", help = "与stream/parse相同, 只包含这些字段")]
+    pub includecolumns: Option<String>,
+
+    #[arg(long = "excludecolumns", help = "与stream/parse相同, 不包含这些字段")]
+    pub excludecolumns: Option<String>,
+
+    #[arg(long = "labels", help = "与stream/parse相同, 带labels")]
+    pub labels: Option<String>,
+
+    #[arg(long = "row-hash", help = "与stream/parse相同, 带row_hash、old_row_hash")]
+    pub row_hash: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+
+    #[arg(long = "table", required = true, help = "行所在的表, 格式为db.tbl")]
+    pub table: Option<String>,
+
+    #[arg(long = "key", required = true, help = "主键的值, 联合主键以逗号分隔按主键字段的顺序, 也可以指定字段名: id=42,region='eu'; 没有表结构时字段名为@1、@2...")]
+    pub key: Option<String>,
+
+    #[arg(short = 'f', long = "file", conflicts_with = "index", help = "按顺序排列的binlog文件, 多个文件以逗号分隔; 同时指定-u时连接mysql查询表结构")]
+    pub file: Option<String>,
+
+    #[arg(long = "index", help = "binlog的index文件(mysql-bin.index), 相对路径按index文件所在的目录")]
+    pub index: Option<String>,
+
+    #[arg(long = "binlogfile", conflicts_with_all = ["file", "index"], help = "连接mysql拉取时开始的binlog文件, 默认为SHOW BINARY LOGS中的第一个文件")]
+    pub binlogfile: Option<String>,
+
+    #[arg(long = "startdatetime", help = "只输出该时间之后的变更, unix时间戳或utc时间(%Y-%m-%d %H:%M:%S)")]
+    pub startdatetime: Option<String>,
+
+    #[arg(long = "stopdatetime", help = "读取到该时间为止, unix时间戳或utc时间(%Y-%m-%d %H:%M:%S)")]
+    pub stopdatetime: Option<String>,
+
+    #[arg(long = "outformat", help = "输出格式: text(默认)或json(每次变更一行)")]
+    pub outformat: Option<String>,
+
+    #[arg(long = "serverid", help = "连接mysql按主从协议读取时注册用的server_id，不能与已经存在的同步线程重复, 默认为133")]
+    pub serverid: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct EventsArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+
+    #[arg(long = "binlogfile", help = "开始的binlog文件, 默认为SHOW BINARY LOGS中的第一个文件")]
+    pub binlogfile: Option<String>,
+
+    #[arg(long = "position", requires = "binlogfile", help = "开始的position, 需要为事件的起始位置, 默认为4")]
+    pub position: Option<String>,
+
+    #[arg(long = "page-size", help = "每次SHOW BINLOG EVENTS读取的事件数, 默认1000")]
+    pub page_size: Option<String>,
+
+    #[arg(long = "follow", help = "读取到最后一个文件的末尾后按该间隔(秒)重新查询, 不退出")]
+    pub follow: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SnapshotArgs {
+    #[command(flatten)]
+    pub conn: ConnArgs,
+
+    #[arg(long = "table", required = true, help = "还原的表, 格式为db.tbl")]
+    pub table: Option<String>,
+
+    #[arg(long = "base", required = true, help = "基础数据, 带表头的csv, 字段值的格式与--outformat csv相同(NULL为空字段或\\N, 空字符串为\"\")")]
+    pub base: Option<String>,
+
+    #[arg(short = 'f', long = "file", required_unless_present = "index", conflicts_with = "index", help = "基础数据之后按顺序排列的binlog文件, 多个文件以逗号分隔")]
+    pub file: Option<String>,
+
+    #[arg(long = "index", help = "binlog的index文件(mysql-bin.index), 相对路径按index文件所在的目录")]
+    pub index: Option<String>,
+
+    #[arg(long = "startposition", help = "第一个文件中开始应用的position, 即基础数据备份时的位置")]
+    pub startposition: Option<String>,
+
+    #[arg(long = "startdatetime", help = "跳过该时间之前的事务, unix时间戳或utc时间(%Y-%m-%d %H:%M:%S)")]
+    pub startdatetime: Option<String>,
+
+    #[arg(long = "stopdatetime", required = true, help = "还原到的时间点, 应用该时间及之前提交的事务, unix时间戳或utc时间(%Y-%m-%d %H:%M:%S)")]
+    pub stopdatetime: Option<String>,
+
+    #[arg(long = "key", help = "对应行使用的字段, 多个以逗号分隔, 默认为表结构中的主键(需要-u连接mysql), 都没有时按整行对应")]
+    pub key: Option<String>,
+
+    #[arg(short = 'o', long = "output", help = "输出的文件, 默认stdout")]
+    pub output: Option<String>,
+
+    #[arg(long = "manifest", requires = "output", help = "写入manifest文件: -o输出的csv的大小、sha256、应用的事务数及行数, 需要-o, 已存在时按文件名替换或追加")]
+    pub manifest: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    #[arg(long = "dir", required_unless_present = "index", conflicts_with = "index", help = "binlog文件所在的目录, 例如backup子命令的--output目录, 按文件名排序")]
+    pub dir: Option<String>,
+
+    #[arg(long = "index", help = "binlog的index文件(mysql-bin.index), 相对路径按index文件所在的目录")]
+    pub index: Option<String>,
+
+    #[arg(long = "listen", help = "监听的地址, 默认为127.0.0.1:3307")]
+    pub listen: Option<String>,
+
+    #[arg(short = 'u', long = "user", required = true, help = "从库连接使用的用户名(CHANGE MASTER的MASTER_USER)")]
+    pub user: Option<String>,
+
+    #[arg(short = 'p', long = "password", help = "从库连接使用的密码, 认证方式为mysql_native_password, 也可以通过MYSQL_PWD环境变量设置")]
+    pub password: Option<String>,
+
+    #[arg(long = "serverid", help = "@@server_id, 不能与从库相同, 默认为第一个binlog文件的server_id")]
+    pub serverid: Option<String>,
+
+    #[arg(long = "server-uuid", help = "@@server_uuid, 默认按server_id生成")]
+    pub server_uuid: Option<String>,
+}
+
+impl ConnArgs {
+    //未通过命令行指定的连接信息从环境变量、配置文件中获取
+    fn fill(self, conf: &mut Config) -> Result<(), String> {
+        let options = ClientOptions::load(self.defaults_file.as_deref())?;
+        conf.dry_run = self.dry_run;
+
+        match self.user.or(options.user.clone()) {
+            None => {
+                return Err(String::from("user 不能为空！！"));
+            },
+            Some(t) => conf.user_name = t,
+        }
+
+        //命令行指定host时不使用配置文件中的socket
+        let socket = match self.host {
+            None => self.socket.or(options.socket.clone()),
+            Some(_) => self.socket,
+        };
+        match socket {
+            Some(t) if self.ssh.is_none() => conf.socket = t,
+            _ => match self.host.or_else(|| options.host_info()) {
+                None => {
+                    return Err(String::from("host 不能为空！！"));
+                },
+                Some(t) => conf.host_info = crate::endpoint::host_port(&t, 3306),
+            }
+        }
+        //命令行指定时不使用配置文件中的init-command
+        if !self.init_command.is_empty() {
+            conf.initcommand = self.init_command.join("\n");
+        } else if let Some(t) = options.init_command.clone() {
+            conf.initcommand = t;
+        }
+        if !self.connect_attr.is_empty() {
+            conf.connectattrs = self.connect_attr.join("\n");
+            crate::io::response::parse_connect_attrs(&conf.connectattrs)?;
+        }
+        match self.proxy_protocol {
+            None => {},
+            Some(t) => {
+                crate::io::proxy::ProxyVersion::parse(&t)?;
+                conf.proxyprotocol = t;
+            }
+        }
+        conf.packettrace = self.packet_trace.unwrap_or_default();
+        match self.packet_trace_bytes {
+            None => {},
+            Some(t) => {
+                t.parse::<usize>().map_err(|_| format!("无效的packet-trace-bytes: {}", t))?;
+                conf.packettracebytes = t;
+            }
+        }
+        if let Some(ssh) = self.ssh {
+            conf.ssh = ssh;
+            conf.sshkey = self.ssh_key.unwrap_or_default();
+        }
+
+        match self.compress.or(options.compress.clone()) {
+            None => {},
+            Some(t) => {
+                crate::io::compress::Compression::parse(&t)?;
+                conf.compress = t;
+            }
+        }
+
+        match self.auth_plugin.or(options.auth_plugin.clone()) {
+            None => {},
+            Some(t) => conf.authplugin = t,
+        }
+        match self.aws_region {
+            None => {},
+            Some(t) => conf.awsregion = t,
+        }
+        match self.aws_endpoint {
+            None => {},
+            Some(t) => conf.awsendpoint = t,
+        }
+        match self.aws_profile {
+            None => {},
+            Some(t) => conf.awsprofile = t,
+        }
+        crate::io::auth::Plugins::from_config(conf)?;
+
+        let password = match (self.password, self.password_file, self.password_command) {
+            (Some(t), _, _) => Some(Secret::new(t)),
+            (None, Some(path), _) => Some(FileSecret{ path }.fetch()?),
+            (None, None, Some(command)) => Some(CommandSecret{ command }.fetch()?),
+            (None, None, None) => options.password,
+        };
+        match password {
+            //aws_iam的token在连接时生成
+            None if conf.authplugin == "aws_iam" => {},
+            None => {
+                return Err(String::from("password 不能为空！！"))
+            },
+            Some(t) => conf.password = t,
+        }
+        crate::endpoint::prepare(conf)
+    }
+}
+
+impl SummaryArgs {
+    fn fill(self, conf: &mut Config) {
+        match self.summary {
+            None => {},
+            Some(t) => conf.summary = t,
+        }
+    }
+}
+
+impl ReplArgs {
+    fn fill(self, conf: &mut Config) {
+        match self.gtid {
+            None => (),
+            Some(t) => conf.gtid = t,
+        }
+
+        match self.binlogfile {
+            None => (),
+            Some(t) => {
+                conf.binlogfile = t;
+                conf.position = String::from("4");
+            }
+        }
+
+        match self.position {
+            None => (),
+            Some(t) => conf.position = t
+        }
+
+        match self.serverid {
+            None => (),
+            Some(t) => conf.serverid = t,
+        }
+        match self.verify_checksum {
+            None => (),
+            Some(t) => conf.verifychecksum = t,
+        }
+        match self.heartbeat {
+            None => (),
+            Some(t) => conf.heartbeat = t,
+        }
+        match self.watermark {
+            None => (),
+            Some(t) => conf.watermark = t,
+        }
+        match self.watermark_delay {
+            None => (),
+            Some(t) => conf.watermarkdelay = t,
+        }
+        match self.until_gtid {
+            None => (),
+            Some(t) => conf.untilgtid = t,
+        }
+        match self.until_position {
+            None => (),
+            Some(t) => conf.untilposition = t,
+        }
+        match self.control {
+            None => (),
+            Some(t) => conf.control = t,
+        }
+        match self.health_timeout {
+            None => (),
+            Some(t) => conf.healthtimeout = t,
+        }
+        conf.sdnotify = self.sd_notify;
+        match self.status_interval {
+            None => (),
+            Some(t) => conf.statusinterval = t,
+        }
+        conf.pauseonswitch = self.pause_on_switch;
+        match self.record {
+            None => {},
+            Some(t) => conf.record = t,
+        }
+        match self.compat {
+            None => (),
+            Some(t) => conf.compat = t,
+        }
+        match self.exclude_tables {
+            None => (),
+            Some(t) => conf.excludetables = t,
+        }
+        conf.oscremap = self.osc_remap;
+    }
+}
+
+impl FileArgs {
+    fn fill(self, conf: &mut Config) {
+        match self.file {
+            None => (),
+            Some(t) => conf.file = t,
+        }
+        conf.relaylog = self.relaylog;
+        conf.quiet = self.quiet;
+        match self.verify_checksum {
+            None => (),
+            Some(t) => conf.verifychecksum = t,
+        }
+    }
+}
+
+impl RangeArgs {
+    fn fill(self, conf: &mut Config) {
+        match self.startposition {
+            None => {},
+            Some(t) => conf.startposition = t,
+        }
+        match self.stopposition {
+            None => {},
+            Some(t) => conf.stopposition = t,
+        }
+        match self.startdatetime {
+            None => {},
+            Some(t) => conf.startdatetime = t,
+        }
+        match self.stopdatetime {
+            None => {},
+            Some(t) => conf.stopdatetime = t,
+        }
+        match self.gtid {
+            None => (),
+            Some(t) => conf.gtid = t,
+        }
+    }
+}
+
+impl FilterArgs {
+    fn fill(self, conf: &mut Config) {
+        match self.threadid {
+            None => {},
+            Some(t) => conf.threadid = t,
+        }
+        match self.greptbl {
+            None => {},
+            Some(t) => conf.greptbl = t,
+        }
+    }
+}
+
+impl SqlArgs {
+    fn fill(self, conf: &mut Config) {
+        conf.getsql = self.getsql;
+        match self.batchsize {
+            None => {},
+            Some(t) => conf.batchsize = t,
+        }
+        match self.insertmode {
+            None => {},
+            Some(t) => conf.insertmode = t,
+        }
+        match self.no_key {
+            None => {},
+            Some(t) => conf.nokey = t,
+        }
+        match self.dialect {
+            None => {},
+            Some(t) => conf.sqldialect = t,
+        }
+        match self.ddl_fence {
+            None => {},
+            Some(t) => conf.ddlfence = t,
+        }
+    }
+}
+
+impl AuditArgs {
+    fn fill(self, conf: &mut Config) {
+        conf.audit = self.audit;
+        conf.audit_enrich = self.audit_enrich;
+    }
+}
+
+impl RowFilterArgs {
+    fn fill(self, conf: &mut Config) {
+        match self.rowfilter {
+            None => {},
+            Some(t) => conf.rowfilter = t,
+        }
+        match self.sample {
+            None => {},
+            Some(t) => conf.sample = t,
+        }
+    }
+}
+
+impl CountArgs {
+    fn fill(self, conf: &mut Config) {
+        conf.count = self.count;
+        match self.countby {
+            None => {},
+            Some(t) => conf.countby = t,
+        }
+    }
+}
+
+impl CompactArgs {
+    fn fill(self, conf: &mut Config) {
+        conf.compact = self.compact;
+        match self.compactwindow {
+            None => {},
+            Some(t) => conf.compactwindow = t,
+        }
+    }
+}
+
+impl SchemaArgs {
+    fn fill(self, conf: &mut Config) {
+        match self.schemaout {
+            None => {},
+            Some(t) => conf.schemaout = t,
+        }
+        match self.createtable {
+            None => {},
+            Some(t) => conf.createtable = t,
+        }
+    }
+}
+
+impl WatchArgs {
+    fn fill(self, conf: &mut Config) {
+        match self.watch {
+            None => {},
+            Some(t) => conf.watch = t,
+        }
+    }
+}
+
+impl ColumnStatsArgs {
+    fn fill(self, conf: &mut Config) {
+        conf.columnstats = self.columnstats.unwrap_or_default();
+        conf.columnstatswindow = self.columnstatswindow.unwrap_or_default();
+    }
+}
+
+impl RetentionArgs {
+    fn fill(self, conf: &mut Config) {
+        conf.retention = self.retention;
+        match self.need_binlog {
+            None => {},
+            Some(t) => conf.needbinlog = t,
+        }
+    }
+}
+
+impl HotspotArgs {
+    fn fill(self, conf: &mut Config) {
+        conf.hotspot = self.hotspot;
+        conf.groupcommit = self.groupcommit;
+        match self.interval {
+            None => {},
+            Some(t) => conf.interval = t,
+        }
+        match self.topkeys {
+            None => {},
+            Some(t) => conf.topkeys = t,
+        }
+        match self.ddl {
+            None => {},
+            Some(t) => conf.ddl = t,
+        }
+        match self.deps {
+            None => {},
+            Some(t) => conf.deps = t,
+        }
+        match self.depsformat {
+            None => {},
+            Some(t) => conf.depsformat = t,
+        }
+    }
+}
+
+impl FormatArgs {
+    fn fill(self, conf: &mut Config) {
+        conf.quotedecimal = self.quotedecimal;
+        conf.rowhash = self.row_hash;
+        conf.boundaries = self.boundaries;
+        match self.labels {
+            None => {},
+            Some(t) => conf.labels = t,
+        }
+        match self.spatialformat {
+            None => {},
+            Some(t) => conf.spatialformat = t,
+        }
+        match self.timezone {
+            None => {},
+            Some(t) => conf.timezone = t,
+        }
+        match self.timeformat {
+            None => {},
+            Some(t) => conf.timeformat = t,
+        }
+        match self.floatprecision {
+            None => {},
+            Some(t) => conf.floatprecision = t,
+        }
+        match self.nonfinite {
+            None => {},
+            Some(t) => conf.nonfinite = t,
+        }
+        match self.zerodate {
+            None => {},
+            Some(t) => conf.zerodate = t,
+        }
+        match self.lobmaxsize {
+            None => {},
+            Some(t) => conf.lobmaxsize = t,
+        }
+        match self.lobmode {
+            None => {},
+            Some(t) => conf.lobmode = t,
+        }
+        match self.outformat {
+            None => {},
+            Some(t) => conf.outformat = t,
+        }
+        conf.verbose = self.verbose;
+        match self.template {
+            None => {},
+            Some(t) => conf.template = t,
+        }
+        match self.color {
+            None => {},
+            Some(t) => conf.color = t,
+        }
+        match self.binaryencoding {
+            None => {},
+            Some(t) => conf.binaryencoding = t,
+        }
+        match self.binarycolumns {
+            None => {},
+            Some(t) => conf.binarycolumns = t,
+        }
+        match self.includecolumns {
+            None => {},
+            Some(t) => conf.includecolumns = t,
+        }
+        match self.excludecolumns {
+            None => {},
+            Some(t) => conf.excludecolumns = t,
+        }
+    }
+}
+
+impl Config{
+    pub fn new(args: Opt) -> Result<Config, String> {
+        let mut conf = Config{
+            program_name: String::from("binlogAL"),
+            serverid: 133.to_string(),
+            strict: args.strict,
+            lenientchecksum: args.lenientchecksum,
+            memorylimit: args.memorylimit.unwrap_or_default(),
+            memorypolicy: args.memorypolicy.unwrap_or_default(),
+            ..Config::default()
+        };
+
+        match args.command {
+            Command::Stream(a) => {
+                conf.runtype = String::from("repl");
+                a.conn.fill(&mut conf)?;
+                a.summary.fill(&mut conf);
+                a.repl.fill(&mut conf);
+                a.filter.fill(&mut conf);
+                a.sql.fill(&mut conf);
+                a.format.fill(&mut conf);
+                a.audit.fill(&mut conf);
+                a.rowfilter.fill(&mut conf);
+                a.compact.fill(&mut conf);
+                a.count.fill(&mut conf);
+                a.schema.fill(&mut conf);
+                a.watch.fill(&mut conf);
+                a.colstats.fill(&mut conf);
+            }
+            Command::Parse(a) => {
+                conf.runtype = String::from("file");
+                a.conn.fill(&mut conf)?;
+                a.summary.fill(&mut conf);
+                a.file.fill(&mut conf);
+                a.range.fill(&mut conf);
+                a.filter.fill(&mut conf);
+                a.sql.fill(&mut conf);
+                a.format.fill(&mut conf);
+                a.audit.fill(&mut conf);
+                a.rowfilter.fill(&mut conf);
+                a.compact.fill(&mut conf);
+                a.count.fill(&mut conf);
+                a.schema.fill(&mut conf);
+                a.watch.fill(&mut conf);
+                a.colstats.fill(&mut conf);
+            }
+            Command::Analyze(a) => {
+                conf.runtype = String::from("file");
+                conf.statisc = true;
+                a.conn.fill(&mut conf)?;
+                a.summary.fill(&mut conf);
+                a.file.fill(&mut conf);
+                a.range.fill(&mut conf);
+                a.filter.fill(&mut conf);
+                a.hotspot.fill(&mut conf);
+                a.retention.fill(&mut conf);
+                a.colstats.fill(&mut conf);
+            }
+            Command::Backup(a) => {
+                conf.runtype = String::from("backup");
+                a.conn.fill(&mut conf)?;
+                a.repl.fill(&mut conf);
+                match a.output {
+                    None => (),
+                    Some(t) => conf.output = t,
+                }
+                conf.manifest = a.manifest.unwrap_or_default();
+                if let Some(t) = a.output_compress {
+                    crate::sink::output_compression(&t)?;
+                    conf.outputcompress = t;
+                }
+            }
+            Command::Flashback(a) => {
+                conf.runtype = String::from("file");
+                conf.rollback = true;
+                a.conn.fill(&mut conf)?;
+                a.summary.fill(&mut conf);
+                a.file.fill(&mut conf);
+                a.range.fill(&mut conf);
+                a.filter.fill(&mut conf);
+                match a.rfilesize {
+                    None => {},
+                    Some(t) => conf.rfilesize = t,
+                }
+                match a.maxrows {
+                    None => {},
+                    Some(t) => conf.maxrows = t,
+                }
+                match a.chunkrows {
+                    None => {},
+                    Some(t) => conf.chunkrows = t,
+                }
+                conf.force = a.force;
+            }
+            Command::Verify(a) => {
+                conf.runtype = String::from("verify");
+                a.file.fill(&mut conf);
+                if conf.verifychecksum.is_empty() {
+                    conf.verifychecksum = String::from("all");
+                }
+            }
+            Command::Split(a) => {
+                conf.runtype = String::from("copy");
+                a.summary.fill(&mut conf);
+                a.file.fill(&mut conf);
+                a.range.fill(&mut conf);
+                a.filter.fill(&mut conf);
+                match a.output {
+                    None => {},
+                    Some(t) => conf.output = t,
+                }
+                conf.manifest = a.manifest.unwrap_or_default();
+            }
+            Command::Carve(a) => {
+                conf.runtype = String::from("carve");
+                match a.file {
+                    None => (),
+                    Some(t) => conf.file = t,
+                }
+                match a.output {
+                    None => (),
+                    Some(t) => conf.output = t,
+                }
+                conf.manifest = a.manifest.unwrap_or_default();
+            }
+            Command::Synthesize(a) => {
+                conf.runtype = String::from("synthesize");
+                a.fill(&mut conf);
+            }
+            Command::Locate(a) => {
+                conf.runtype = String::from("locate");
+                match (a.file, a.index) {
+                    (None, None) => a.conn.fill(&mut conf)?,
+                    (file, index) => {
+                        conf.file = file.unwrap_or_default();
+                        conf.index = index.unwrap_or_default();
+                    }
+                }
+                match a.gtid {
+                    None => {},
+                    Some(t) => conf.locategtid = t,
+                }
+                match a.datetime {
+                    None => {},
+                    Some(t) => conf.locatetime = t,
+                }
+                match a.serverid {
+                    None => {},
+                    Some(t) => conf.serverid = t,
+                }
+            }
+            Command::Capabilities(a) => {
+                conf.runtype = String::from("capabilities");
+                match a.file {
+                    None => a.conn.fill(&mut conf)?,
+                    Some(t) => conf.file = t,
+                }
+            }
+            Command::Schema(a) => {
+                conf.runtype = String::from("schema");
+                a.conn.fill(&mut conf)?;
+                let tables: Vec<String> = a.tables.unwrap_or_default().split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+                conf.greptbl = crate::builder::greptbl(&tables)?;
+                conf.quotedecimal = a.quotedecimal;
+                conf.rowhash = a.row_hash;
+                conf.timezone = a.timezone.unwrap_or_default();
+                conf.nonfinite = a.nonfinite.unwrap_or_default();
+                conf.zerodate = a.zerodate.unwrap_or_default();
+                conf.spatialformat = a.spatialformat.unwrap_or_default();
+                conf.includecolumns = a.includecolumns.unwrap_or_default();
+                conf.excludecolumns = a.excludecolumns.unwrap_or_default();
+                conf.labels = a.labels.unwrap_or_default();
+            }
+            Command::Events(a) => {
+                conf.runtype = String::from("events");
+                a.conn.fill(&mut conf)?;
+                conf.binlogfile = a.binlogfile.unwrap_or_default();
+                conf.position = a.position.unwrap_or_default();
+                conf.eventspage = a.page_size.unwrap_or_default();
+                conf.eventsfollow = a.follow.unwrap_or_default();
+            }
+            Command::History(a) => {
+                conf.runtype = String::from("history");
+                match (a.file, a.index) {
+                    (None, None) => a.conn.fill(&mut conf)?,
+                    (file, index) => {
+                        conf.file = file.unwrap_or_default();
+                        conf.index = index.unwrap_or_default();
+                        //读取文件时连接mysql只用于查询表结构
+                        if a.conn.user.is_some() {
+                            a.conn.fill(&mut conf)?;
+                        }
+                    }
+                }
+                conf.historytable = a.table.unwrap_or_default();
+                conf.historykey = a.key.unwrap_or_default();
+                match a.binlogfile {
+                    None => {},
+                    Some(t) => conf.binlogfile = t,
+                }
+                match a.startdatetime {
+                    None => {},
+                    Some(t) => conf.startdatetime = t,
+                }
+                match a.stopdatetime {
+                    None => {},
+                    Some(t) => conf.stopdatetime = t,
+                }
+                match a.outformat {
+                    None => {},
+                    Some(t) => conf.outformat = t,
+                }
+                match a.serverid {
+                    None => {},
+                    Some(t) => conf.serverid = t,
+                }
+            }
+            Command::Snapshot(a) => {
+                conf.runtype = String::from("snapshot");
+                //连接mysql只用于查询表结构
+                if a.conn.user.is_some() {
+                    a.conn.fill(&mut conf)?;
+                }
+                conf.snapshottable = a.table.unwrap_or_default();
+                conf.snapshotbase = a.base.unwrap_or_default();
+                conf.file = a.file.unwrap_or_default();
+                conf.index = a.index.unwrap_or_default();
+                match a.startposition {
+                    None => {},
+                    Some(t) => conf.startposition = t,
+                }
+                match a.startdatetime {
+                    None => {},
+                    Some(t) => conf.startdatetime = t,
+                }
+                match a.stopdatetime {
+                    None => {},
+                    Some(t) => conf.stopdatetime = t,
+                }
+                match a.key {
+                    None => {},
+                    Some(t) => conf.snapshotkey = t,
+                }
+                match a.output {
+                    None => {},
+                    Some(t) => conf.output = t,
+                }
+                conf.manifest = a.manifest.unwrap_or_default();
+            }
+            Command::Serve(a) => {
+                conf.runtype = String::from("serve");
+                conf.servedir = a.dir.unwrap_or_default();
+                conf.index = a.index.unwrap_or_default();
+                conf.listen = a.listen.unwrap_or(String::from("127.0.0.1:3307"));
+                conf.user_name = a.user.unwrap_or_default();
+                conf.password = a.password.or_else(|| std::env::var("MYSQL_PWD").ok()).unwrap_or_default().into();
+                //为空时使用binlog文件中的server_id
+                conf.serverid = a.serverid.unwrap_or_default();
+                match a.server_uuid {
+                    None => {},
+                    Some(t) => conf.serveruuid = t,
+                }
+            }
+            Command::Inspect(a) => {
+                conf.runtype = String::from("inspect");
+                a.file.fill(&mut conf);
+                match a.execute {
+                    None => (),
+                    Some(t) => conf.command = t,
+                }
+            }
+            #[cfg(feature = "tui")]
+            Command::Tui(a) => {
+                conf.runtype = String::from("tui");
+                a.file.fill(&mut conf);
+            }
+            #[cfg(feature = "json-sink")]
+            Command::Backfill(a) => {
+                conf.runtype = String::from("backfill");
+                conf.pipelines = a.config.unwrap_or_default();
+                conf.backfill = a.pipeline.unwrap_or_default();
+                conf.file = a.file.unwrap_or_default();
+                conf.index = a.index.unwrap_or_default();
+                conf.archivedirs = a.dir.unwrap_or_default();
+                conf.startdatetime = a.startdatetime.unwrap_or_default();
+                conf.stopdatetime = a.stopdatetime.unwrap_or_default();
+                conf.gtid = a.gtid.unwrap_or_default();
+            }
+            Command::Archive(a) => {
+                conf.runtype = String::from("archive");
+                conf.archivedirs = a.dir.unwrap_or_default();
+                conf.output = a.output.unwrap_or_default();
+            }
+            Command::Manifest(a) => {
+                conf.runtype = String::from("manifest");
+                conf.manifest = a.file.unwrap_or_default();
+            }
+            #[cfg(feature = "json-sink")]
+            Command::Pipelines(a) => {
+                conf.runtype = String::from("pipelines");
+                match a.config {
+                    None => (),
+                    Some(t) => conf.pipelines = t,
+                }
+                match a.control {
+                    None => (),
+                    Some(t) => conf.control = t,
+                }
+            }
+            #[cfg(feature = "json-sink")]
+            Command::Replay(a) => {
+                conf.runtype = String::from("replay");
+                match a.file {
+                    None => (),
+                    Some(t) => conf.file = t,
+                }
+                match a.output {
+                    None => (),
+                    Some(t) => conf.output = t,
+                }
+                conf.realtime = a.realtime;
+                match a.verify_checksum {
+                    None => (),
+                    Some(t) => conf.verifychecksum = t,
+                }
+            }
+            Command::Doctor(a) => {
+                conf.runtype = String::from("doctor");
+                match a.journal {
+                    None => (),
+                    Some(t) => conf.journal = t,
+                }
+            }
+            Command::Apply(a) => {
+                conf.runtype = String::from("command");
+                a.conn.fill(&mut conf)?;
+                match a.database {
+                    None => (),
+                    Some(t) => conf.database = t,
+                }
+                match a.command {
+                    None => (),
+                    Some(t) => conf.command = t,
+                }
+            }
+            Command::Rowcheck(a) => {
+                conf.runtype = String::from("rowcheck");
+                a.conn.fill(&mut conf)?;
+                match a.file {
+                    None => (),
+                    Some(t) => conf.file = t,
+                }
+            }
+            Command::Completions { .. } => {
+                return Err(String::from("completions 子命令不需要生成配置"));
+            }
+        }
+
+        conf.validate()?;
+        Ok(conf)
+    }
+}
+
+//输出shell补全脚本
+pub fn print_completions(shell: Shell) {
+    let mut cmd = Opt::command();
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, &mut stdio::stdout());
+}
+
+pub fn startop(config: &Config) {
+    //解析过程中的panic(数据格式错误等)统一以exitcode::PARSE退出, 与参数、连接错误区分开
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        process::exit(exitcode::PARSE);
+    }));
+    crate::readvalue::set_strict(config.strict);
+    crate::replication::verify::set_lenient(config.lenientchecksum);
+    if !config.memorylimit.is_empty() || !config.memorypolicy.is_empty() {
+        let limit = match config.memorylimit.as_str() {
+            "" => 0,
+            t => crate::memory::parse_size(t).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            })
+        };
+        let policy = crate::memory::MemoryPolicy::parse(&config.memorypolicy).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::USAGE);
+        });
+        crate::memory::configure(limit, policy);
+    }
+    crate::colstats::configure(config).unwrap_or_else(|err|{
+        println!("{}", err);
+        process::exit(exitcode::USAGE);
+    });
+    if config.dry_run {
+        if !replication::precheck::dry_run(config) {
+            process::exit(exitcode::SERVER);
+        }
+        return;
+    }
+    if !config.summary.is_empty() {
+        summary::start(config);
+        //被中断时同样输出汇总信息
+        ctrlc::set_handler(|| {
+            summary::finish("interrupted");
+            process::exit(exitcode::INTERRUPTED);
+        }).unwrap_or_else(|err|{
+            println!("设置信号处理发生错误:{}", err);
+            process::exit(exitcode::ERROR);
+        });
+    }
+    if config.runtype == "command"{
+        let mut conn = create_conn(config);
+        let values = io::command::execute(&mut conn,&config.command);
+        for row in values.iter(){
+            println!("{:#?}",row);
+        }
+    }else if config.runtype == "repl" || config.runtype == "backup" {
+        let mut conn = create_conn(config);
+        replication::repl_register(&mut conn,&config);
+    }else if config.runtype == "file" {
+        //println!("从binlog文件提取数据");
+        let mut conn = create_conn(config);
+        replication::repl_register(&mut conn,config);
+
+    }else if config.runtype == "copy" {
+        replication::copy_file(config);
+    }else if config.runtype == "verify" {
+        let classes = replication::verify::ChecksumClass::parse(&config.verifychecksum).unwrap_or(replication::verify::ChecksumClass::all());
+        let report = replication::verify::verify_file(&config.file, config.relaylog, classes).unwrap_or_else(|err|{
+            println!("读取文件({})发生错误:{}", config.file, err);
+            process::exit(exitcode::USAGE);
+        });
+        println!("events: {}, checksum: {}, verified: {}", report.events, report.checksum, report.verified);
+        for pos in &report.checksum_errors {
+            println!("position {} 处的事件checksum错误", pos);
+        }
+        for pos in &report.position_errors {
+            println!("position {} 处的事件next_position错误", pos);
+        }
+        if let Some(pos) = report.truncated {
+            println!("binlog文件在position {} 处被截断", pos);
+        }
+        if !report.is_ok() {
+            process::exit(exitcode::PARSE);
+        }
+        println!("binlog文件({})校验通过", config.file);
+    }else if config.runtype == "carve" {
+        let report = replication::carve::carve_file(&config.file, &config.output).unwrap_or_else(|err|{
+            println!("提取{}到{}发生错误:{}", config.file, config.output, err);
+            process::exit(exitcode::USAGE);
+        });
+        println!("{}: {} transactions, {} events, {} incomplete transactions discarded, {} of {} bytes skipped",
+                 config.output, report.transactions, report.events, report.incomplete, report.skipped, report.bytes);
+        if !report.format_description {
+            println!("没有找到FORMAT_DESCRIPTION_EVENT, 按mysql 5.7的格式生成(checksum: {}), 解析失败时需要人工确认版本", report.checksum);
+        }
+        crate::manifest::record(config, || crate::manifest::ManifestEntry::binlog(&config.output));
+        if report.transactions == 0 {
+            process::exit(exitcode::NO_EVENTS);
+        }
+    }else if config.runtype == "synthesize" {
+        let opts = replication::synthesize::SynthOptions::new(config).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::USAGE);
+        });
+        let report = replication::synthesize::write_file(&config.output, &opts).unwrap_or_else(|err|{
+            println!("写入文件({})发生错误:{}", config.output, err);
+            process::exit(exitcode::SINK);
+        });
+        println!("{}: {} transactions, {} rows, {} events, {} bytes", config.output, report.transactions, report.rows, report.events, report.bytes);
+        crate::manifest::record(config, || crate::manifest::ManifestEntry::binlog(&config.output));
+    }else if config.runtype == "locate" {
+        crate::locate::run(config);
+    }else if config.runtype == "capabilities" {
+        crate::fingerprint::run(config);
+    }else if config.runtype == "schema" {
+        crate::stdout::outjsonschema::run(config);
+    }else if config.runtype == "events" {
+        crate::showevents::run(config);
+    }else if config.runtype == "history" {
+        crate::history::run(config);
+    }else if config.runtype == "snapshot" {
+        crate::snapshot::run(config);
+    }else if config.runtype == "serve" {
+        crate::master::run(config);
+    }else if config.runtype == "rowcheck" {
+        crate::rowhash::run(config);
+    }else if config.runtype == "inspect" {
+        crate::inspect::run(config);
+    }else if config.runtype == "tui" {
+        #[cfg(feature = "tui")]
+        crate::tui::run(config);
+    }else if config.runtype == "archive" {
+        crate::archive::run(config);
+    }else if config.runtype == "manifest" {
+        crate::manifest::run(config);
+    }else if config.runtype == "doctor" {
+        crate::journal::run(config);
+    }else if config.runtype == "pipelines" {
+        #[cfg(feature = "json-sink")]
+        crate::pipeline::run_file(config);
+    }else if config.runtype == "backfill" {
+        #[cfg(feature = "json-sink")]
+        crate::pipeline::run_backfill(config);
+    }else if config.runtype == "replay" {
+        #[cfg(feature = "json-sink")]
+        crate::recording::run(config);
+    }else {
+        println!("无效的执行参数runtype: {}, --help提供参考",config.runtype);
+    }
+
+//    use std::{thread, time};
+//    let ten_millis = time::Duration::from_secs(100);
+//    thread::sleep(ten_millis);
+
+}
@@ -0,0 +1,419 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//行数据的列统计: 按表统计滚动窗口内的行数/秒、每个字段的NULL比例及平均值大小, 与窗口之前的数据(基线)比较发现异常
+//  时间为事件时间(秒), 读取文件与实时拉取的结果相同; update按后镜像、delete按前镜像统计字段值
+//  异常类型:
+//    null_flood   字段在窗口内的NULL比例比基线高出null_jump(默认0.5)以上, 例如应用bug写入大量NULL
+//    bulk_delete  窗口内的delete行数/秒为基线的factor(默认10)倍以上, 例如误删或没有条件的DELETE
+//    rate_spike   窗口内的行数/秒为基线的factor倍以上
+//    value_size   字段在窗口内的平均值大小为基线的factor倍以上或1/factor以下
+//  窗口内的行数少于min_rows(默认100)或基线不足一个窗口时不判断, 避免启动阶段及低流量的表误报
+//  表的字段变化(DDL)后重新统计该表
+//--column-stats开启, 统计结果在控制接口/status及--summary的column_stats中, 新出现的异常输出到stderr, 读取结束时写入报告
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use serde_json::{json, Value as JsonValue};
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::BinlogEvent;
+use crate::replication::parsevalue::MySQLValue;
+
+#[derive(Debug, Clone, Default)]
+struct Counts{
+    rows: u64,
+    deletes: u64,
+    nulls: Vec<u64>,
+    bytes: Vec<u64>,
+}
+
+impl Counts {
+    fn add(&mut self, other: &Counts) {
+        self.rows += other.rows;
+        self.deletes += other.deletes;
+        add_vec(&mut self.nulls, &other.nulls);
+        add_vec(&mut self.bytes, &other.bytes);
+    }
+
+    fn sub(&mut self, other: &Counts) {
+        self.rows -= other.rows;
+        self.deletes -= other.deletes;
+        for (a, b) in self.nulls.iter_mut().zip(&other.nulls) {
+            *a -= b;
+        }
+        for (a, b) in self.bytes.iter_mut().zip(&other.bytes) {
+            *a -= b;
+        }
+    }
+
+    fn null_ratio(&self, idx: usize) -> f64 {
+        ratio(self.nulls.get(idx).copied().unwrap_or(0) as f64, self.rows as f64)
+    }
+
+    fn avg_size(&self, idx: usize) -> f64 {
+        let values = self.rows - self.nulls.get(idx).copied().unwrap_or(0);
+        ratio(self.bytes.get(idx).copied().unwrap_or(0) as f64, values as f64)
+    }
+}
+
+fn add_vec(a: &mut Vec<u64>, b: &[u64]) {
+    if a.len() < b.len() {
+        a.resize(b.len(), 0);
+    }
+    for (a, b) in a.iter_mut().zip(b) {
+        *a += b;
+    }
+}
+
+fn ratio(a: f64, b: f64) -> f64 {
+    if b == 0.0 { 0.0 } else { a / b }
+}
+
+#[derive(Debug, Clone, Default)]
+struct TableStats{
+    columns: Vec<String>,
+    first: u32,
+    last: u32,
+    inserts: u64,
+    updates: u64,
+    total: Counts,
+    //窗口内每秒的统计及合计
+    buckets: VecDeque<(u32, Counts)>,
+    window: Counts,
+}
+
+impl TableStats {
+    fn new(columns: Vec<String>, timestamp: u32) -> TableStats {
+        TableStats{ columns, first: timestamp, last: timestamp, ..TableStats::default() }
+    }
+
+    fn record(&mut self, counts: Counts, timestamp: u32, window: u32) {
+        self.last = self.last.max(timestamp);
+        self.total.add(&counts);
+        self.window.add(&counts);
+        match self.buckets.back_mut() {
+            Some((second, bucket)) if *second == timestamp => bucket.add(&counts),
+            _ => self.buckets.push_back((timestamp, counts)),
+        }
+        //乱序的事件时间按最新的时间计算窗口
+        let start = self.window_start(window);
+        while let Some((second, _)) = self.buckets.front() {
+            if *second >= start {
+                break;
+            }
+            if let Some((_, bucket)) = self.buckets.pop_front() {
+                self.window.sub(&bucket);
+            }
+        }
+    }
+
+    fn window_start(&self, window: u32) -> u32 {
+        (self.last + 1).saturating_sub(window).max(self.first)
+    }
+
+    fn baseline(&self) -> Counts {
+        let mut baseline = self.total.clone();
+        baseline.sub(&self.window);
+        baseline
+    }
+}
+
+/// 一个异常
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly{
+    pub table: String,
+    /// null_flood、bulk_delete、rate_spike、value_size
+    pub kind: &'static str,
+    pub column: Option<String>,
+    /// 窗口内的值: NULL比例、行数/秒、平均字节数
+    pub window: f64,
+    /// 基线的值
+    pub baseline: f64,
+}
+
+impl Anomaly {
+    fn key(&self) -> String {
+        format!("{}\0{}\0{}", self.table, self.kind, self.column.as_deref().unwrap_or(""))
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "table": self.table,
+            "kind": self.kind,
+            "column": self.column,
+            "window": round(self.window),
+            "baseline": round(self.baseline),
+        })
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let target = match &self.column {
+            Some(col) => format!("{}.{}", self.table, col),
+            None => self.table.clone(),
+        };
+        let what = match self.kind {
+            "null_flood" => "NULL比例",
+            "bulk_delete" => "delete行数/秒",
+            "rate_spike" => "行数/秒",
+            _ => "平均字节数",
+        };
+        write!(f, "{} {}: {}窗口内为{}, 基线为{}", self.kind, target, what, round(self.window), round(self.baseline))
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+/// 按表统计行数据, 发现异常
+#[derive(Debug, Clone)]
+pub struct ColumnStats{
+    window: u32,
+    factor: f64,
+    null_jump: f64,
+    min_rows: u64,
+    tables: BTreeMap<String, TableStats>,
+    active: HashSet<String>,
+}
+
+impl ColumnStats {
+    /// window为滚动窗口的秒数
+    pub fn new(window: u32) -> ColumnStats {
+        ColumnStats{ window: window.max(1), factor: 10.0, null_jump: 0.5, min_rows: 100, tables: BTreeMap::new(), active: HashSet::new() }
+    }
+
+    /// bulk_delete、rate_spike、value_size的倍数, 默认10
+    pub fn factor(mut self, factor: f64) -> ColumnStats {
+        self.factor = factor;
+        self
+    }
+
+    /// null_flood的NULL比例增加量, 默认0.5
+    pub fn null_jump(mut self, null_jump: f64) -> ColumnStats {
+        self.null_jump = null_jump;
+        self
+    }
+
+    /// 窗口内少于该行数时不判断异常, 默认100
+    pub fn min_rows(mut self, min_rows: u64) -> ColumnStats {
+        self.min_rows = min_rows;
+        self
+    }
+
+    /// 统计一个行事件, cols为表结构(COLUMN_NAME), 返回新出现的异常(持续存在的异常只返回一次, 消失后再出现时重新返回)
+    pub fn record(&mut self, data: &Traction, cols: &[HashMap<String, String>], db_tbl: &str, timestamp: u32) -> Vec<Anomaly> {
+        let (code, rows) = match data {
+            Traction::RowEvent(code, rows) => (code, rows.rows()),
+            _ => return vec![]
+        };
+        let columns: Vec<String> = cols.iter().enumerate()
+            .map(|(idx, c)| c.get("COLUMN_NAME").cloned().unwrap_or_else(|| format!("@{}", idx + 1)))
+            .collect();
+        let images: Vec<&Vec<Option<MySQLValue>>> = match code {
+            BinlogEvent::UpdateEvent => rows.chunks(2).filter_map(|r| r.get(1)).collect(),
+            _ => rows.iter().collect()
+        };
+        let mut counts = Counts{ rows: images.len() as u64, nulls: vec![0; columns.len()], bytes: vec![0; columns.len()], ..Counts::default() };
+        for row in images {
+            for (idx, value) in row.iter().enumerate().take(columns.len()) {
+                match value {
+                    None | Some(MySQLValue::Null) => counts.nulls[idx] += 1,
+                    Some(v) => counts.bytes[idx] += value_size(v),
+                }
+            }
+        }
+        let window = self.window;
+        let table = self.tables.entry(db_tbl.to_string()).or_insert_with(|| TableStats::new(columns.clone(), timestamp));
+        if table.columns != columns {
+            *table = TableStats::new(columns, timestamp);
+        }
+        match code {
+            BinlogEvent::WriteEvent => table.inserts += counts.rows,
+            BinlogEvent::UpdateEvent => table.updates += counts.rows,
+            _ => counts.deletes = counts.rows,
+        }
+        table.record(counts, timestamp, window);
+
+        let found = self.check(db_tbl);
+        self.active.retain(|key| !key.starts_with(&format!("{}\0", db_tbl)) || found.iter().any(|a| &a.key() == key));
+        found.into_iter().filter(|a| self.active.insert(a.key())).collect()
+    }
+
+    //按当前窗口判断一个表的异常
+    fn check(&self, db_tbl: &str) -> Vec<Anomaly> {
+        let table = match self.tables.get(db_tbl) {
+            Some(t) => t,
+            None => return vec![]
+        };
+        let start = table.window_start(self.window);
+        let baseline_seconds = start - table.first;
+        if table.window.rows < self.min_rows || baseline_seconds < self.window {
+            return vec![];
+        }
+        let baseline = table.baseline();
+        let window_seconds = (table.last - start + 1) as f64;
+        let anomaly = |kind: &'static str, column: Option<&String>, window: f64, baseline: f64| Anomaly{
+            table: db_tbl.to_string(), kind, column: column.cloned(), window, baseline
+        };
+        let mut found = vec![];
+        let (rate, base_rate) = (table.window.rows as f64 / window_seconds, baseline.rows as f64 / baseline_seconds as f64);
+        if rate > base_rate * self.factor {
+            found.push(anomaly("rate_spike", None, rate, base_rate));
+        }
+        let (deletes, base_deletes) = (table.window.deletes as f64 / window_seconds, baseline.deletes as f64 / baseline_seconds as f64);
+        if table.window.deletes >= self.min_rows && deletes > base_deletes * self.factor {
+            found.push(anomaly("bulk_delete", None, deletes, base_deletes));
+        }
+        if baseline.rows == 0 {
+            return found;
+        }
+        for (idx, column) in table.columns.iter().enumerate() {
+            let (nulls, base_nulls) = (table.window.null_ratio(idx), baseline.null_ratio(idx));
+            if nulls - base_nulls >= self.null_jump {
+                found.push(anomaly("null_flood", Some(column), nulls, base_nulls));
+            }
+            let (size, base_size) = (table.window.avg_size(idx), baseline.avg_size(idx));
+            if size > 0.0 && base_size > 0.0 && (size > base_size * self.factor || size * self.factor < base_size) {
+                found.push(anomaly("value_size", Some(column), size, base_size));
+            }
+        }
+        found
+    }
+
+    /// 当前所有表的异常
+    pub fn anomalies(&self) -> Vec<Anomaly> {
+        self.tables.keys().flat_map(|t| self.check(t)).collect()
+    }
+
+    /// 每个表的累计及窗口内的统计, 以及当前的异常
+    pub fn to_json(&self) -> JsonValue {
+        let tables: BTreeMap<&String, JsonValue> = self.tables.iter().map(|(name, t)| {
+            let window_seconds = (t.last - t.window_start(self.window) + 1) as f64;
+            let columns: BTreeMap<&String, JsonValue> = t.columns.iter().enumerate().map(|(idx, col)| {
+                (col, json!({
+                    "nulls": t.total.nulls.get(idx).copied().unwrap_or(0),
+                    "null_ratio": round(t.total.null_ratio(idx)),
+                    "avg_size": round(t.total.avg_size(idx)),
+                    "window_null_ratio": round(t.window.null_ratio(idx)),
+                    "window_avg_size": round(t.window.avg_size(idx)),
+                }))
+            }).collect();
+            (name, json!({
+                "rows": t.total.rows,
+                "insert": t.inserts,
+                "update": t.updates,
+                "delete": t.total.deletes,
+                "first_timestamp": t.first,
+                "last_timestamp": t.last,
+                "rows_per_sec": round(t.window.rows as f64 / window_seconds),
+                "deletes_per_sec": round(t.window.deletes as f64 / window_seconds),
+                "columns": columns,
+            }))
+        }).collect();
+        json!({
+            "window": self.window,
+            "tables": tables,
+            "anomalies": self.anomalies().iter().map(|t| t.to_json()).collect::<Vec<_>>(),
+        })
+    }
+
+    /// 文本格式的报告
+    pub fn report(&self) -> String {
+        let mut out = format!("-- 列统计(窗口{}秒)\n", self.window);
+        out.push_str(&format!("{:<40}{:<32}{:>12}{:>12}{:>12}\n", "table", "column", "rows", "null_ratio", "avg_size"));
+        for (name, t) in &self.tables {
+            for (idx, col) in t.columns.iter().enumerate() {
+                out.push_str(&format!("{:<40}{:<32}{:>12}{:>12.3}{:>12.1}\n", name, col, t.total.rows, t.total.null_ratio(idx), t.total.avg_size(idx)));
+            }
+        }
+        let anomalies = self.anomalies();
+        out.push_str(&format!("\n-- 异常({})\n", anomalies.len()));
+        for anomaly in anomalies {
+            out.push_str(&format!("{}\n", anomaly));
+        }
+        out
+    }
+}
+
+/// 字段值的字节数: 字符串、二进制为长度, json、decimal为文本长度, 其余为存储的字节数
+pub fn value_size(value: &MySQLValue) -> u64 {
+    match value {
+        MySQLValue::String(t) => t.len() as u64,
+        MySQLValue::Blob(t) | MySQLValue::Opaque{ bytes: t, .. } => t.len() as u64,
+        MySQLValue::Json(t) => t.to_string().len() as u64,
+        MySQLValue::Decimal(t) => t.to_string().len() as u64,
+        MySQLValue::SignedInteger(_) | MySQLValue::Double(_) | MySQLValue::DateTime{..} => 8,
+        MySQLValue::Float(_) | MySQLValue::Timestamp{..} => 4,
+        MySQLValue::Date{..} | MySQLValue::Time{..} => 3,
+        MySQLValue::Enum(_) => 2,
+        MySQLValue::Year(_) => 1,
+        MySQLValue::Null => 0,
+    }
+}
+
+struct Global{
+    stats: ColumnStats,
+    output: String,
+}
+
+static STATS: Mutex<Option<Global>> = Mutex::new(None);
+
+/// 按--column-stats、--column-stats-window开始统计
+pub fn configure(conf: &crate::Config) -> Result<(), String> {
+    if conf.columnstats.is_empty() {
+        return Ok(());
+    }
+    let window = match conf.columnstatswindow.as_str() {
+        "" => 60,
+        t => t.parse::<u32>().ok().filter(|v| *v > 0).ok_or_else(|| format!("无效的column-stats-window: {}, 需要大于0的秒数", t))?
+    };
+    *STATS.lock().unwrap() = Some(Global{ stats: ColumnStats::new(window), output: conf.columnstats.clone() });
+    Ok(())
+}
+
+pub fn is_enabled() -> bool {
+    STATS.lock().map(|t| t.is_some()).unwrap_or(false)
+}
+
+//统计行事件, 新出现的异常输出到stderr
+pub(crate) fn record(data: &Traction, cols: &[HashMap<String, String>], db_tbl: &str, timestamp: u32) {
+    let mut guard = STATS.lock().unwrap();
+    if let Some(global) = guard.as_mut() {
+        for anomaly in global.stats.record(data, cols, db_tbl, timestamp) {
+            eprintln!("列统计异常: {}", anomaly);
+        }
+    }
+}
+
+/// 当前的统计, 用于控制接口/status及--summary
+pub fn stats_json() -> JsonValue {
+    match STATS.lock().unwrap().as_ref() {
+        Some(global) => global.stats.to_json(),
+        None => JsonValue::Null,
+    }
+}
+
+//读取结束时写入报告, -为stderr
+pub(crate) fn finish() {
+    let guard = STATS.lock().unwrap();
+    let global = match guard.as_ref() {
+        Some(t) => t,
+        None => return
+    };
+    let report = global.stats.report();
+    let result = if global.output == "-" {
+        write!(io::stderr(), "{}", report)
+    } else {
+        File::create(&global.output).and_then(|mut f| f.write_all(report.as_bytes()))
+    };
+    if let Err(e) = result {
+        eprintln!("写入列统计报告({})发生错误:{}", global.output, e);
+    }
+}
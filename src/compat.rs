@@ -0,0 +1,138 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//托管mysql及中间件的兼容模式(--compat rds|aurora|proxysql|maxscale)
+//  内部表: mysql.rds_*(rds_heartbeat2每隔几秒写入一次)、mysql.ha_health_check、mysql.aurora_*不输出, 也不查询表结构
+//  权限: 主账号的权限通过角色授予, SHOW GRANTS中看不到REPLICATION SLAVE等权限, --dry-run检查不通过时只提示
+//  集群地址: 故障切换后dns可能仍然指向原来的实例(已变为只读), 连接到只读实例时返回错误, 由管道或systemd等重新连接;
+//  切换后binlog文件及position不同, 需要使用gtid注册
+//中间件(ProxySQL、MaxScale)位于binlogAL与mysql之间:
+//  握手包的标识可能被修改(不声明CLIENT_DEPRECATE_EOF、CLIENT_PLUGIN_AUTH等), 连接时只使用双方都声明的标识
+//  ProxySQL: 只转发查询, 不转发复制协议(COM_BINLOG_DUMP), 拉取binlog需要直接连接mysql;
+//    select @@version_comment limit 1由ProxySQL自己返回(ProxySQL)
+//  MaxScale: binlogrouter按mysql主库的协议提供binlog, 只支持COM_BINLOG_DUMP(binlog文件、position),
+//    不支持COM_BINLOG_DUMP_GTID; 只能执行从库io线程使用的语句, 不能查询information_schema中的表结构及mysql配置;
+//    SHOW VARIABLES LIKE 'MAXSCALE%'返回MAXSCALE_VERSION
+//  注册同步之前检查不支持的组合, 给出原因后退出, 而不是在拉取过程中出现难以理解的错误
+
+#[cfg(feature = "network")]
+use std::net::TcpStream;
+use crate::Config;
+
+/// 兼容模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Rds,
+    Aurora,
+    ProxySql,
+    MaxScale,
+}
+
+impl Profile {
+    /// 空为不使用兼容模式
+    pub fn parse(value: &str) -> Result<Option<Profile>, String> {
+        match value.to_lowercase().as_str() {
+            "" | "none" => Ok(None),
+            "rds" => Ok(Some(Profile::Rds)),
+            "aurora" => Ok(Some(Profile::Aurora)),
+            "proxysql" => Ok(Some(Profile::ProxySql)),
+            "maxscale" => Ok(Some(Profile::MaxScale)),
+            t => Err(format!("无效的compat: {}, 可选值为rds、aurora、proxysql、maxscale", t)),
+        }
+    }
+
+    /// 配置已检查, 无效的值按不使用处理
+    pub fn from_config(conf: &Config) -> Option<Profile> {
+        Profile::parse(&conf.compat).ok().flatten()
+    }
+
+    /// 托管服务写入的内部表
+    pub fn internal_table(&self, db: &str, tbl: &str) -> bool {
+        self.managed() && db == "mysql" && (tbl.starts_with("rds_") || tbl == "ha_health_check" || tbl.starts_with("aurora_"))
+    }
+
+    /// 是否为托管mysql(rds、aurora), 否则为中间件
+    pub fn managed(&self) -> bool {
+        matches!(self, Profile::Rds | Profile::Aurora)
+    }
+
+    /// 是否可以通过该连接查询表结构及mysql配置, MaxScale binlogrouter不能查询
+    pub fn queryable(&self) -> bool {
+        *self != Profile::MaxScale
+    }
+
+    /// 拉取binlog时不支持的组合, 返回原因
+    pub fn check_dump_options(&self, conf: &Config) -> Result<(), String> {
+        match self {
+            Profile::ProxySql => Err(String::from(
+                "ProxySQL不转发复制协议(COM_BINLOG_DUMP), 拉取binlog需要直接连接mysql或MaxScale binlogrouter")),
+            Profile::MaxScale if !conf.gtid.is_empty() => Err(String::from(
+                "MaxScale binlogrouter不支持COM_BINLOG_DUMP_GTID, 需要使用--binlogfile、--position注册")),
+            _ => Ok(()),
+        }
+    }
+
+    //判断是否为只读实例的变量: aurora的reader为innodb_read_only, rds的只读实例为read_only, 中间件不检查
+    fn read_only_variable(&self) -> Option<&'static str> {
+        match self {
+            Profile::Rds => Some("read_only"),
+            Profile::Aurora => Some("innodb_read_only"),
+            Profile::ProxySql | Profile::MaxScale => None,
+        }
+    }
+
+    /// 检查连接到的是否为可写的实例, 集群地址切换期间可能连接到原来的实例
+    #[cfg(feature = "network")]
+    pub fn check_writer(&self, conn: &mut TcpStream, conf: &Config) -> Result<(), String> {
+        let var = match self.read_only_variable() {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        let values = crate::io::command::try_execute(conn, &format!("select @@global.{} as value;", var))?;
+        match values.first().and_then(|row| row.get("value")).map(|t| t.as_str()) {
+            Some("1") | Some("ON") => Err(format!("{}为只读实例({}=1), 集群地址可能尚未切换到新的主库, 稍后重试", conf.host_info, var)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 是否为兼容模式下不输出的内部表
+pub fn internal_table(conf: &Config, db: &str, tbl: &str) -> bool {
+    Profile::from_config(conf).is_some_and(|p| p.internal_table(db, tbl))
+}
+
+/// 识别连接到的中间件, mysql及不能识别的中间件返回None
+#[cfg(feature = "network")]
+pub fn detect(conn: &mut TcpStream) -> Option<Profile> {
+    let comment = crate::io::command::try_execute(conn, "select @@version_comment limit 1").ok()
+        .and_then(|values| values.into_iter().next())
+        .and_then(|row| row.into_iter().next())
+        .map(|(_, value)| value)
+        .unwrap_or_default();
+    if comment.to_lowercase().contains("proxysql") {
+        return Some(Profile::ProxySql);
+    }
+    match crate::io::command::try_execute(conn, "show variables like 'maxscale%'") {
+        Ok(values) if !values.is_empty() => Some(Profile::MaxScale),
+        _ => None,
+    }
+}
+
+/// 注册同步之前检查连接到的中间件及配置, 识别到中间件而没有指定对应的--compat或组合不支持时返回原因
+/// 返回实际使用的兼容模式
+#[cfg(feature = "network")]
+pub fn check_dump(conn: &mut TcpStream, conf: &Config) -> Result<Option<Profile>, String> {
+    let configured = Profile::parse(&conf.compat)?;
+    if let Some(profile) = configured {
+        profile.check_dump_options(conf)?;
+    }
+    match detect(conn) {
+        Some(detected) if configured != Some(detected) => {
+            detected.check_dump_options(conf)?;
+            Err(format!("{}为{:?}, 需要指定--compat {}", conf.host_info, detected, format!("{:?}", detected).to_lowercase()))
+        }
+        _ => Ok(configured),
+    }
+}
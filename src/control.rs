@@ -0,0 +1,566 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//http控制接口: 不重启进程的情况下暂停/恢复读取、回退到之前的gtid/position重新拉取、跳过指定gtid的事务
+//  GET  /status                            当前状态: 是否暂停、已完成事务的gtid、binlog文件及position、已跳过的事务,
+//                                          executed为已执行的gtid集合及对应的文件、position, change_source为可以直接使用的CHANGE REPLICATION SOURCE语句
+//  POST /pause                             暂停读取, 在事务之间生效
+//  POST /resume                            恢复读取
+//  POST /rewind?gtid=uuid:1-100            按gtid集合重新注册拉取(mysql返回集合之外的事务)
+//  POST /rewind?file=mysql-bin.000003&position=4   按binlog文件及position重新注册拉取
+//  POST /skip?gtid=uuid:101                跳过gtid集合中的事务(整个事务不输出), 用于绕过无法处理的事务
+//                                          master_switch为最后一次检测到的主库切换(见failover模块)
+//                                          compression为使用压缩协议(--compress)时的流量及压缩比
+//                                          memory为配置了内存上限(--memory-limit)时的使用情况(见memory模块)
+//                                          stages为读取、解析、字段值解析、序列化、输出各阶段的次数及耗时(见profile模块)
+//                                          warnings为各类型的警告数(见warning模块)
+//                                          column_stats为--column-stats的按表统计及当前的异常(见colstats模块)
+//  GET  /profile[?seconds=30]              各阶段按调用栈的自身耗时(微秒), 为flamegraph的折叠格式(text/plain),
+//                                          指定seconds时为之后该时间内的耗时(最长300秒), 否则为启动以来的累计值
+//  GET  /schema[?table=db.tbl]            已读取到的表的json输出对应的JSON Schema(见outjsonschema), 不指定table时返回所有表, 以db.tbl为key
+//  GET  /healthz                           存活检查: 读取到的事务超过health timeout仍未确认输出(checkpoint)、
+//                                          或配置了空闲超时时超过该时间没有任何事件(包括mysql的心跳), 返回503, 由k8s等重启进程
+//  GET  /readyz                            就绪检查: 已连接、已读取到事件、未暂停、输出目标正常且存活检查通过时返回200, 否则503
+//  返回json, 出错时为{"error":"..."}
+//回退只能到mysql上仍然保留的binlog; 暂停时不读取mysql发送的数据, 超过mysql的net_write_timeout后连接会被断开
+//systemd: 服务配置Type=notify时注册成功后发送READY=1, 配置WatchdogSec时按其一半的间隔在存活检查通过时发送WATCHDOG=1
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde_json::{json, Value as JsonValue};
+use crate::Config;
+use crate::failover::MasterSwitch;
+use crate::gtid::{Executed, ExecutedTracker, GtidSet};
+use crate::replication::readbinlog::Traction;
+use crate::sink::HeartbeatTracker;
+use crate::stdout::format::ValueFormat;
+use crate::stdout::outjsonschema;
+
+/// 重新注册拉取的位置
+#[derive(Debug, Clone, PartialEq)]
+pub enum Position{
+    Gtid(GtidSet),
+    File(String, u64),
+}
+
+impl Position{
+    /// 使用该位置注册的配置
+    pub fn apply(&self, conf: &Config) -> Config {
+        let mut conf = conf.clone();
+        match self {
+            Position::Gtid(t) => {
+                conf.gtid = t.to_string();
+                conf.binlogfile = String::new();
+                conf.position = String::new();
+            }
+            Position::File(file, position) => {
+                conf.gtid = String::new();
+                conf.binlogfile = file.clone();
+                conf.position = position.to_string();
+            }
+        }
+        conf
+    }
+}
+
+struct State{
+    paused: bool,
+    stopped: bool,
+    rewind: Option<Position>,
+    skip: GtidSet,
+    skipped: Vec<String>,
+    skipping: bool,
+    tracker: HeartbeatTracker,
+    executed: ExecutedTracker,
+    master_switch: Option<MasterSwitch>,
+    connected: bool,
+    connected_at: Option<Instant>,
+    last_event: Option<Instant>,
+    pending: Option<Instant>,           //最早一个未确认输出的事务开始的时间
+    sink_error: Option<String>,
+    stale_after: Duration,
+    idle_after: Option<Duration>,
+    labels: crate::labels::Labels,
+    format: ValueFormat,
+    tables: BTreeMap<String, Vec<HashMap<String, String>>>,    //已读取到的表的字段信息, 用于/schema
+}
+
+impl Default for State {
+    fn default() -> State {
+        State{
+            paused: false,
+            stopped: false,
+            rewind: None,
+            skip: GtidSet::new(),
+            skipped: vec![],
+            skipping: false,
+            tracker: HeartbeatTracker::new(),
+            executed: ExecutedTracker::new(),
+            master_switch: None,
+            connected: false,
+            connected_at: None,
+            last_event: None,
+            pending: None,
+            sink_error: None,
+            stale_after: Duration::from_secs(60),
+            idle_after: None,
+            labels: crate::labels::Labels::default(),
+            format: ValueFormat::default(),
+            tables: BTreeMap::new(),
+        }
+    }
+}
+
+/// 健康检查的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Health{
+    pub live: bool,
+    pub ready: bool,
+    /// 未通过的检查项
+    pub reasons: Vec<String>,
+}
+
+/// 控制状态, 由http接口修改, 读取binlog的线程在每个事件前后检查
+#[derive(Clone, Default)]
+pub struct Controller{
+    inner: Arc<(Mutex<State>, Condvar)>,
+}
+
+impl Controller{
+    pub fn new() -> Controller {
+        Controller::default()
+    }
+
+    /// 事务读取后超过stale_after仍未确认输出时存活检查失败, 默认60秒
+    /// idle_after为空闲超时, 配置了心跳时设置为心跳间隔的几倍, 超过该时间没有任何事件说明连接已经失效
+    pub fn with_health(self, stale_after: Duration, idle_after: Option<Duration>) -> Controller {
+        {
+            let mut state = self.state();
+            state.stale_after = stale_after;
+            state.idle_after = idle_after;
+        }
+        self
+    }
+
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.inner.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn pause(&self) {
+        self.state().paused = true;
+    }
+
+    pub fn resume(&self) {
+        self.state().paused = false;
+        self.inner.1.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state().paused
+    }
+
+    /// 请求停止读取, 暂停中的checkpoint立即返回, 读取的线程在事务之间检查is_stopped后退出
+    pub fn stop(&self) {
+        self.state().stopped = true;
+        self.inner.1.notify_all();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.state().stopped
+    }
+
+    /// 回退到指定位置, 在事务之间生效, 暂停中同样生效(回退后保持暂停)
+    pub fn rewind(&self, position: Position) {
+        self.state().rewind = Some(position);
+        self.inner.1.notify_all();
+    }
+
+    /// 跳过gtid集合中的事务
+    pub fn skip(&self, gtids: &GtidSet) {
+        let mut state = self.state();
+        for set in &gtids.sets {
+            for interval in &set.intervals {
+                state.skip.add_interval(set.sid, *interval);
+            }
+        }
+    }
+
+    /// 读取到事件后调用, 返回false时该事件属于被跳过的事务, 不需要输出
+    /// timestamp、next_position为事件头中的值
+    pub fn event(&self, data: &Traction, timestamp: u32, next_position: u64) -> bool {
+        let mut state = self.state();
+        state.tracker.observe(data, timestamp, next_position);
+        state.executed.observe(data, next_position);
+        let now = Instant::now();
+        state.last_event = Some(now);
+        if state.pending.is_none() && state.tracker.in_transaction() {
+            state.pending = Some(now);
+        }
+        match data {
+            Traction::GtidEvent(t) => {
+                state.skipping = state.skip.contains(&t.gtid, t.gno_id);
+                if state.skipping {
+                    state.skipped.push(format!("{}:{}", t.gtid, t.gno_id));
+                }
+                !state.skipping
+            }
+            _ if state.skipping => {
+                if !state.tracker.in_transaction() {
+                    state.skipping = false;
+                }
+                false
+            }
+            _ => true
+        }
+    }
+
+    /// 读取下一个事件之前调用: 处于事务之间时, 暂停中等待恢复, 有回退请求时返回回退的位置
+    pub fn checkpoint(&self) -> Option<Position> {
+        let mut state = self.state();
+        if state.tracker.in_transaction() {
+            return None;
+        }
+        while state.paused && state.rewind.is_none() && !state.stopped {
+            state = self.inner.1.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        let rewind = state.rewind.take();
+        if let Some(position) = &rewind {
+            state.skipping = false;
+            state.executed.restart(&position.apply(&Config::default()));
+        }
+        rewind
+    }
+
+    /// 已读取的事务都已确认输出(写入并flush), 读取binlog的线程在输出后调用
+    pub fn checkpointed(&self) {
+        let mut state = self.state();
+        if !state.tracker.in_transaction() {
+            state.pending = None;
+        }
+    }
+
+    /// 与mysql的连接状态, 注册成功后为true, 断开、重新注册期间为false
+    pub fn set_connected(&self, connected: bool) {
+        let mut state = self.state();
+        state.connected = connected;
+        state.connected_at = if connected { Some(Instant::now()) } else { None };
+    }
+
+    /// 注册时的位置(gtid或binlogfile、position), 作为已执行位置的初始值
+    pub fn set_start(&self, conf: &Config) {
+        let mut state = self.state();
+        state.executed = ExecutedTracker::from_config(conf);
+        state.labels = crate::labels::Labels::from_config(conf);
+        state.format = ValueFormat::new(conf).unwrap_or_default();
+    }
+
+    /// 读取到TABLE_MAP并获取字段信息后调用, 表结构变化时替换
+    pub fn set_table(&self, db_tbl: &str, cols: &[HashMap<String, String>]) {
+        let mut state = self.state();
+        if state.tables.get(db_tbl).map(|t| t.as_slice()) != Some(cols) {
+            state.tables.insert(db_tbl.to_string(), cols.to_vec());
+        }
+    }
+
+    /// 表的JSON Schema, 为None时返回所有已读取到的表, 以db.tbl为key
+    pub fn schema(&self, table: Option<&str>) -> Option<JsonValue> {
+        let state = self.state();
+        match table {
+            Some(t) => state.tables.get(t).map(|cols| outjsonschema::json_schema(t, cols, &state.format)),
+            None => Some(outjsonschema::json_schemas(state.tables.iter(), &state.format)),
+        }
+    }
+
+    /// 输出目标的状态, 如FanOutSink::failed()不为空时设置错误原因
+    pub fn set_sink_error(&self, err: Option<String>) {
+        self.state().sink_error = err;
+    }
+
+    /// 检测到主库切换, 记录在/status中, pause为true时暂停读取等待人工确认
+    pub fn switched(&self, switch: &MasterSwitch, pause: bool) {
+        let mut state = self.state();
+        state.master_switch = Some(switch.clone());
+        if pause {
+            state.paused = true;
+        }
+    }
+
+    /// 已执行的gtid集合及对应的binlog文件、position, 被跳过的事务同样计入
+    pub fn executed(&self) -> Executed {
+        self.state().executed.executed()
+    }
+
+    pub fn health(&self) -> Health {
+        let state = self.state();
+        let mut reasons = vec![];
+        if let Some(pending) = state.pending {
+            if pending.elapsed() > state.stale_after {
+                reasons.push(format!("事务读取后{}秒未确认输出", pending.elapsed().as_secs()));
+            }
+        }
+        if let (Some(idle), false) = (state.idle_after, state.paused) {
+            //注册之后还没有事件时从注册的时间开始计算
+            let since = state.last_event.max(state.connected_at).map(|t| t.elapsed()).unwrap_or_default();
+            if state.connected && since > idle {
+                reasons.push(format!("{}秒没有读取到事件", since.as_secs()));
+            }
+        }
+        let live = reasons.is_empty();
+        if !state.connected {
+            reasons.push(String::from("未连接"));
+        } else if state.last_event.is_none() {
+            reasons.push(String::from("未读取到事件"));
+        }
+        if state.paused {
+            reasons.push(String::from("已暂停"));
+        }
+        if let Some(err) = &state.sink_error {
+            reasons.push(format!("输出目标错误: {}", err));
+        }
+        Health{ live, ready: reasons.is_empty(), reasons }
+    }
+
+    pub fn status(&self) -> JsonValue {
+        let state = self.state();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_millis() as u64).unwrap_or(0);
+        let heartbeat = state.tracker.heartbeat(now);
+        let executed = state.executed.executed();
+        #[allow(unused_mut)]
+        let mut value = json!({
+            "paused": state.paused,
+            "gtid": heartbeat.gtid,
+            "log_file": heartbeat.log_file,
+            "position": heartbeat.log_pos,
+            "event_timestamp": heartbeat.event_timestamp,
+            "skip": state.skip.to_string(),
+            "skipped": state.skipped,
+            "rewind": state.rewind.as_ref().map(|t| format!("{:?}", t)),
+            "executed": {
+                "gtid_set": executed.gtid_set.to_string(),
+                "log_file": executed.log_file,
+                "position": executed.log_pos,
+                "change_source": executed.change_source(),
+            },
+            "master_switch": state.master_switch.as_ref().map(|t| t.to_json()),
+        });
+        #[cfg(feature = "network")]
+        {
+            let stats = crate::io::compress::stats();
+            if stats.wire_bytes > 0 {
+                value["compression"] = stats.to_json();
+            }
+        }
+        let memory = crate::memory::stats();
+        if memory.limit > 0 {
+            value["memory"] = memory.to_json();
+        }
+        if crate::profile::is_enabled() {
+            value["stages"] = crate::profile::stats_json();
+        }
+        if crate::warning::total() > 0 {
+            value["warnings"] = crate::warning::stats_json();
+        }
+        if crate::colstats::is_enabled() {
+            value["column_stats"] = crate::colstats::stats_json();
+        }
+        state.labels.attach(&mut value);
+        value
+    }
+
+    /// 处理一个http请求, 返回(状态码, json)
+    pub fn handle(&self, method: &str, path: &str, query: &HashMap<String, String>) -> (u16, JsonValue) {
+        let err = |code: u16, msg: String| (code, json!({"error": msg}));
+        match (method, path) {
+            ("GET", "/status") => (200, self.status()),
+            ("GET", "/profile") => profile(query),
+            ("GET", "/schema") => match self.schema(query.get("table").map(|t| t.as_str())) {
+                Some(t) => (200, t),
+                None => err(404, format!("还没有读取到表{}的数据", query["table"])),
+            },
+            ("GET", "/healthz") | ("GET", "/readyz") => {
+                let health = self.health();
+                let ok = if path == "/healthz" { health.live } else { health.ready };
+                (if ok { 200 } else { 503 }, json!({"live": health.live, "ready": health.ready, "reasons": health.reasons}))
+            }
+            ("POST", "/pause") => {
+                self.pause();
+                (200, self.status())
+            }
+            ("POST", "/resume") => {
+                self.resume();
+                (200, self.status())
+            }
+            ("POST", "/rewind") => {
+                let position = match (query.get("gtid"), query.get("file"), query.get("position")) {
+                    (Some(gtid), None, None) => match GtidSet::from_str(gtid) {
+                        Ok(t) => Position::Gtid(t),
+                        Err(e) => return err(400, format!("无效的gtid: {}", e)),
+                    },
+                    (None, Some(file), position) => match position.map(|t| t.parse::<u64>()).unwrap_or(Ok(4)) {
+                        Ok(t) if t >= 4 => Position::File(file.clone(), t),
+                        _ => return err(400, format!("无效的position: {}", position.cloned().unwrap_or_default())),
+                    },
+                    _ => return err(400, String::from("需要指定gtid或file、position")),
+                };
+                self.rewind(position);
+                (200, self.status())
+            }
+            ("POST", "/skip") => {
+                match query.get("gtid").map(|t| GtidSet::from_str(t)) {
+                    Some(Ok(t)) if !t.is_empty() => self.skip(&t),
+                    Some(Err(e)) => return err(400, format!("无效的gtid: {}", e)),
+                    _ => return err(400, String::from("需要指定gtid")),
+                }
+                (200, self.status())
+            }
+            (_, "/status") | (_, "/profile") | (_, "/schema") | (_, "/healthz") | (_, "/readyz") | (_, "/pause") | (_, "/resume") | (_, "/rewind") | (_, "/skip") => err(405, format!("不支持的方法: {}", method)),
+            _ => err(404, format!("不存在的路径: {}", path)),
+        }
+    }
+}
+
+//折叠格式的阶段耗时, 返回字符串时按text/plain输出
+pub(crate) fn profile(query: &HashMap<String, String>) -> (u16, JsonValue) {
+    match query.get("seconds").map(|t| t.parse::<f64>()) {
+        None => (200, JsonValue::String(crate::profile::folded())),
+        Some(Ok(t)) if t > 0.0 && t <= 300.0 => (200, JsonValue::String(crate::profile::sample(t))),
+        Some(_) => (400, json!({"error": format!("无效的seconds: {}, 需要为0到300之间的秒数", query["seconds"])})),
+    }
+}
+
+/// 在后台线程中启动http控制接口, 返回实际监听的地址(端口为0时由系统分配)
+pub fn serve(addr: &str, controller: Controller) -> io::Result<SocketAddr> {
+    serve_with(addr, move |method, path, query| controller.handle(method, path, query))
+}
+
+//按请求方法、路径及参数返回(状态码, json), 多管道时按路径转发到各自的Controller
+//每个连接一个线程, /profile等待采样时不影响健康检查; 开启控制接口时开启各阶段的计时
+pub(crate) fn serve_with<F>(addr: &str, handler: F) -> io::Result<SocketAddr>
+    where F: Fn(&str, &str, &HashMap<String, String>) -> (u16, JsonValue) + Send + Sync + 'static {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    crate::profile::set_enabled(true);
+    let handler = Arc::new(handler);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handler = handler.clone();
+            //单个请求出错不影响之后的请求
+            thread::spawn(move || handle_connection(stream, handler.as_ref()));
+        }
+    });
+    Ok(local)
+}
+
+fn handle_connection<F>(mut stream: TcpStream, handler: &F) -> io::Result<()>
+    where F: Fn(&str, &str, &HashMap<String, String>) -> (u16, JsonValue) {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    //跳过请求头, 读取并丢弃请求体
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((key, value)) = header.split_once(':') {
+            if key.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    io::copy(&mut reader.by_ref().take(length), &mut io::sink())?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (code, body) = handler(method, path, &parse_query(query));
+    let reason = match code {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        409 => "Conflict",
+        503 => "Service Unavailable",
+        _ => "Method Not Allowed",
+    };
+    let (content_type, body) = match body {
+        JsonValue::String(t) => ("text/plain; charset=utf-8", t),
+        t => ("application/json", t.to_string()),
+    };
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", code, reason, content_type, body.len(), body)?;
+    stream.flush()
+}
+
+/// 按NOTIFY_SOCKET向systemd发送状态(如READY=1), 没有该环境变量时返回false
+#[cfg(unix)]
+pub fn sd_notify(state: &str) -> io::Result<bool> {
+    use std::os::unix::net::UnixDatagram;
+    let path = match std::env::var("NOTIFY_SOCKET") {
+        Ok(t) if !t.is_empty() => t,
+        _ => return Ok(false)
+    };
+    let socket = UnixDatagram::unbound()?;
+    match path.strip_prefix('@') {
+        //抽象命名空间的地址
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), &path)?;
+        }
+    }
+    Ok(true)
+}
+
+/// 配置了WatchdogSec(WATCHDOG_USEC)时在后台线程中按其一半的间隔检查, 存活时发送WATCHDOG=1, 返回是否启动
+#[cfg(unix)]
+pub fn sd_watchdog(controller: Controller) -> bool {
+    let usec = match std::env::var("WATCHDOG_USEC").ok().and_then(|t| t.parse::<u64>().ok()) {
+        Some(t) if t > 0 => t,
+        _ => return false
+    };
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_micros(usec / 2));
+        if controller.health().live {
+            let _ = sd_notify("WATCHDOG=1");
+        }
+    });
+    true
+}
+
+//a=1&b=2, 值按%XX、+解码
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query.split('&').filter(|t| !t.is_empty()).map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (decode(key), decode(value))
+    }).collect()
+}
+
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = vec![];
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let hex = bytes.get(idx + 1..idx + 3).and_then(|t| std::str::from_utf8(t).ok()).and_then(|t| u8::from_str_radix(t, 16).ok());
+        match (bytes[idx], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                idx += 2;
+            }
+            (b'+', _) => out.push(b' '),
+            (b, _) => out.push(b),
+        }
+        idx += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
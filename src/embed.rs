@@ -0,0 +1,322 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//作为库使用时最简单的接口: stream_rows(&conf, |db, table, op, row| {...}), 只需要处理每一行的变更
+//  连接: 按conf中的host_info、user_name、password拉取, 没有serverid时为133, 没有heartbeat时为1秒(空闲时也能及时响应停止)
+//  起始位置: 有保存的checkpoint时从checkpoint继续, 否则为conf中的gtid/binlogfile, 都没有时从SHOW BINARY LOGS最后一个文件的末尾开始
+//  解码: 列名通过information_schema获取, 获取不到时为@1、@2..., 值的格式与--outformat json相同(ValueFormat::new(conf))
+//  过滤: conf中的greptbl、excludetables及rowfilter, 内部表(如gh-ost的影子表)不返回
+//  checkpoint: RowStream::with_checkpoint指定文件时, 每个事务的所有行都返回后按间隔(默认1秒)保存, 停止时保存
+//  停止: 回调返回false时立即停止, 该事务之后从头重新返回; RowStream::controller().stop()在事务之间停止
+//  conf.file不为空时读取本地的binlog文件, 读取到文件结尾结束, 不使用checkpoint
+//  conf中设置untilgtid/untilposition时读取到该位置后返回Ok
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde_json::Value as JsonValue;
+use crate::Config;
+use crate::control::Controller;
+use crate::exclude::Exclusion;
+use crate::position::{FileStore, Position, PositionStore};
+use crate::reader::{BinlogReader, Event};
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::{BinlogEvent, EventType};
+use crate::rowfilter::RowFilter;
+use crate::sink::{HeartbeatTracker, default_columns};
+use crate::stdout::format::ValueFormat;
+use crate::stream::BinlogStream;
+
+/// 行变更的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowOp{
+    Insert,
+    Update,
+    Delete,
+}
+
+impl RowOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RowOp::Insert => "insert",
+            RowOp::Update => "update",
+            RowOp::Delete => "delete",
+        }
+    }
+}
+
+/// 一行变更, data为列名到值的json对象
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row{
+    /// insert、update为变更后的值, delete为删除的行
+    pub data: JsonValue,
+    /// update之前的值
+    pub old: Option<JsonValue>,
+    /// 所在事务的gtid, 没有gtid时为空
+    pub gtid: String,
+    /// 事件的时间
+    pub timestamp: u32,
+}
+
+/// 按默认配置拉取binlog并逐行回调, 回调返回false时停止; 需要checkpoint或从其他线程停止时使用RowStream
+///
+/// ```ignore
+/// mytest::embed::stream_rows(&conf, |db, table, op, row| {
+///     println!("{}.{} {} {}", db, table, op.as_str(), row.data);
+///     true
+/// })?;
+/// ```
+pub fn stream_rows<F>(conf: &Config, f: F) -> io::Result<()>
+    where F: FnMut(&str, &str, RowOp, &Row) -> bool
+{
+    RowStream::new(conf).run(f)
+}
+
+/// stream_rows的可选配置: checkpoint及停止
+pub struct RowStream{
+    conf: Config,
+    checkpoint: Option<Arc<dyn PositionStore>>,
+    interval: Duration,
+    controller: Controller,
+}
+
+impl RowStream {
+    pub fn new(conf: &Config) -> RowStream {
+        RowStream{ conf: conf.clone(), checkpoint: None, interval: Duration::from_secs(1), controller: Controller::new() }
+    }
+
+    /// checkpoint保存在文件中, 文件存在时从保存的位置继续
+    pub fn with_checkpoint(self, path: &str) -> RowStream {
+        self.with_position_store(Arc::new(FileStore::new(path)))
+    }
+
+    /// checkpoint保存在共享存储中(见position模块)
+    pub fn with_position_store(mut self, store: Arc<dyn PositionStore>) -> RowStream {
+        self.checkpoint = Some(store);
+        self
+    }
+
+    /// 保存checkpoint的最小间隔, 默认1秒
+    pub fn with_checkpoint_interval(mut self, interval: Duration) -> RowStream {
+        self.interval = interval;
+        self
+    }
+
+    /// 使用已有的控制接口, 例如同时通过control::serve提供http接口
+    pub fn with_control(mut self, controller: Controller) -> RowStream {
+        self.controller = controller;
+        self
+    }
+
+    /// 用于从其他线程停止, stop()之后在事务之间停止, run返回Ok
+    pub fn controller(&self) -> Controller {
+        self.controller.clone()
+    }
+
+    /// 逐行回调, 停止或读取到文件结尾时返回; 连接、解码失败时返回错误, 已完成的事务保存在checkpoint中
+    pub fn run<F>(self, mut f: F) -> io::Result<()>
+        where F: FnMut(&str, &str, RowOp, &Row) -> bool
+    {
+        let mut decoder = RowDecoder::new(&self.conf)?;
+        if !self.conf.file.is_empty() {
+            for event in BinlogReader::open(&self.conf.file)? {
+                let event = event?;
+                if !decoder.rows(&event, &mut f)? || (self.controller.is_stopped() && !decoder.tracker.in_transaction()) {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+
+        let conf = self.start_conf()?;
+        decoder.conf = conf.clone();
+        let mut stream = BinlogStream::connect(&conf)?.with_control(self.controller.clone());
+        let mut done: Option<Position> = None;
+        let mut last_save = Instant::now();
+        let result = loop {
+            if self.controller.is_stopped() && !decoder.tracker.in_transaction() {
+                break Ok(());
+            }
+            let event = match stream.read_event() {
+                Ok(t) => t,
+                Err(_) if stream.until_reached() => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            match decoder.rows(&event, &mut f) {
+                Ok(true) => {}
+                Ok(false) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+            let trx_end = match &event.data {
+                Traction::XidEvent(_) => true,
+                Traction::QueryEvent(t) => t.command != "BEGIN",
+                _ => matches!(event.header.event_type, EventType::HeartbeatLogEvent | EventType::HeartbeatLogEventV2),
+            };
+            if trx_end && !decoder.tracker.in_transaction() {
+                let state = decoder.tracker.heartbeat(0);
+                done = Some(Position{ log_file: state.log_file, position: state.log_pos, gtid: state.gtid });
+            }
+            if done.is_some() && last_save.elapsed() >= self.interval {
+                self.save(done.take())?;
+                last_save = Instant::now();
+            } else if done.is_none() {
+                self.controller.checkpointed();
+            }
+        };
+        self.save(done)?;
+        result
+    }
+
+    //有保存的位置时从该位置继续, 没有起始位置时从主库当前的位置开始
+    fn start_conf(&self) -> io::Result<Config> {
+        let mut conf = self.conf.clone();
+        if conf.heartbeat.is_empty() {
+            conf.heartbeat = String::from("1");
+        }
+        if conf.serverid.is_empty() {
+            conf.serverid = String::from("133");
+        }
+        if let Some(store) = &self.checkpoint {
+            if let Some(saved) = store.load("")?.filter(|t| !t.log_file.is_empty()) {
+                conf.gtid = String::new();
+                conf.binlogfile = saved.log_file;
+                conf.position = saved.position.to_string();
+                return Ok(conf);
+            }
+        }
+        if conf.gtid.is_empty() && conf.binlogfile.is_empty() {
+            let mut conn = crate::io::connection::create_mysql_conn(&conf)
+                .map_err(|e| io::Error::new(io::ErrorKind::PermissionDenied, e))?;
+            let binlogs = crate::io::command::try_execute(&mut conn, "show binary logs;").map_err(io::Error::other)?;
+            let last = binlogs.last().ok_or_else(|| io::Error::other("SHOW BINARY LOGS没有返回binlog文件, 需要开启log_bin"))?;
+            conf.binlogfile = last.get("Log_name").cloned().unwrap_or_default();
+            conf.position = last.get("File_size").cloned().unwrap_or_else(|| String::from("4"));
+        }
+        Ok(conf)
+    }
+
+    fn save(&self, position: Option<Position>) -> io::Result<()> {
+        if let (Some(store), Some(position)) = (&self.checkpoint, position) {
+            store.save("", &position)?;
+        }
+        self.controller.checkpointed();
+        Ok(())
+    }
+}
+
+//按表过滤、解码行事件
+struct RowDecoder{
+    conf: Config,
+    format: ValueFormat,
+    tables: Option<JsonValue>,
+    exclusion: Exclusion,
+    filter: Option<RowFilter>,
+    table_cols_info: HashMap<String, Vec<HashMap<String, String>>>,
+    tracker: HeartbeatTracker,
+    gtid: String,
+}
+
+impl RowDecoder {
+    fn new(conf: &Config) -> io::Result<RowDecoder> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+        let format = ValueFormat::new(conf).map_err(invalid)?;
+        let tables = match conf.greptbl.as_str() {
+            "" => None,
+            t => {
+                serde_json::from_str::<JsonValue>(t).map_err(|e| invalid(format!("greptbl格式错误: {}", e)))?;
+                Some(crate::replication::grep::parse_greptbl(t))
+            }
+        };
+        let filter = match conf.rowfilter.as_str() {
+            "" => None,
+            t => Some(RowFilter::new(t, format.clone()).map_err(invalid)?),
+        };
+        Ok(RowDecoder{
+            conf: conf.clone(),
+            format,
+            tables,
+            exclusion: Exclusion::from_config(conf),
+            filter,
+            table_cols_info: HashMap::new(),
+            tracker: HeartbeatTracker::new(),
+            gtid: String::new(),
+        })
+    }
+
+    fn keep_table(&self, db: &str, tbl: &str) -> bool {
+        if self.exclusion.excluded(db, tbl) || crate::compat::internal_table(&self.conf, db, tbl) {
+            return false;
+        }
+        match self.tables.as_ref().map(|t| t.get(db)) {
+            None => true,
+            Some(Some(JsonValue::String(t))) => t == "all",
+            Some(Some(JsonValue::Array(t))) => t.iter().any(|v| v.as_str() == Some(tbl)),
+            Some(_) => false,
+        }
+    }
+
+    //回调返回false时返回false
+    fn rows<F>(&mut self, event: &Event, f: &mut F) -> io::Result<bool>
+        where F: FnMut(&str, &str, RowOp, &Row) -> bool
+    {
+        self.tracker.observe(&event.data, event.header.timestamp, event.header.next_position as u64);
+        let (code, map) = match (&event.data, &event.table) {
+            (Traction::GtidEvent(t), _) => {
+                self.gtid = format!("{}:{}", t.gtid, t.gno_id);
+                return Ok(true);
+            }
+            (Traction::QueryEvent(t), _) => {
+                //DDL之后重新获取表结构
+                if t.command != "BEGIN" {
+                    self.table_cols_info.clear();
+                    if let Some(filter) = self.filter.as_mut() {
+                        filter.reset();
+                    }
+                }
+                return Ok(true);
+            }
+            (Traction::RowEvent(code, _), Some(map)) => (code, map),
+            _ => return Ok(true),
+        };
+        if !self.keep_table(&map.database_name, &map.table_name) {
+            return Ok(true);
+        }
+        let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+        if !self.conf.host_info.is_empty() {
+            crate::meta::get_col(&self.conf, &map.database_name, &map.table_name, &mut self.table_cols_info);
+        }
+        let cols = self.table_cols_info.get(&db_tbl).cloned().unwrap_or_else(|| default_columns(map));
+        let data = match self.filter.as_mut() {
+            Some(filter) if filter.applies_to(&db_tbl) => {
+                match filter.filter(&event.data, &db_tbl, &cols).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))? {
+                    Some(t) => t,
+                    None => return Ok(true),
+                }
+            }
+            _ => Cow::Borrowed(&event.data),
+        };
+        let rows = match data.as_ref() {
+            Traction::RowEvent(_, rows) => rows.rows(),
+            _ => return Ok(true),
+        };
+        let get_row = |row: &[_]| crate::stdout::outjson::get_row(row, &cols, &db_tbl, &self.format);
+        let changes: Vec<(RowOp, JsonValue, Option<JsonValue>)> = match code {
+            BinlogEvent::UpdateEvent => rows.chunks(2).filter(|t| t.len() == 2)
+                .map(|t| (RowOp::Update, get_row(&t[1]), Some(get_row(&t[0])))).collect(),
+            BinlogEvent::WriteEvent => rows.iter().map(|t| (RowOp::Insert, get_row(t), None)).collect(),
+            BinlogEvent::DeleteEvent => rows.iter().map(|t| (RowOp::Delete, get_row(t), None)).collect(),
+            _ => vec![],
+        };
+        for (op, data, old) in changes {
+            let row = Row{ data, old, gtid: self.gtid.clone(), timestamp: event.header.timestamp };
+            if !f(&map.database_name, &map.table_name, op, &row) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,191 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//连接地址: 除ip:port外支持ipv6、本地unix socket以及通过ssh隧道连接mysql
+//  ipv6: [::1]:3306, 没有端口时可以直接写地址(::1), 使用默认端口
+//  unix socket、ssh隧道: 在127.0.0.1的随机端口上监听, 每个连接转发到unix socket或一个`ssh -W host:port`进程,
+//  host_info改为监听的地址, 之后的连接、注册仍然使用TcpStream
+//  ssh使用系统的ssh客户端, 不交互(BatchMode), 需要使用密钥或ssh-agent认证, 主机需要已在known_hosts中
+//  监听线程随进程退出, ssh进程在连接断开(标准输入关闭)时退出
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread;
+use crate::Config;
+
+/// 补全端口: 已有端口时不变, ipv6地址加上中括号
+pub fn host_port(host: &str, port: u16) -> String {
+    if host.starts_with('[') {
+        if host.contains("]:") { host.to_string() } else { format!("{}:{}", host, port) }
+    } else if host.matches(':').count() > 1 {
+        //没有中括号的ipv6地址不能带端口
+        format!("[{}]:{}", host, port)
+    } else if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// 使用unix socket或ssh隧道时启动本地转发, 并将host_info改为转发的地址
+pub fn prepare(conf: &mut Config) -> Result<(), String> {
+    if !conf.socket.is_empty() && !conf.ssh.is_empty() {
+        return Err(String::from("socket与ssh不能同时使用"));
+    }
+    if !conf.socket.is_empty() {
+        conf.host_info = unix_socket(&conf.socket)?;
+    } else if !conf.ssh.is_empty() {
+        if conf.host_info.is_empty() {
+            return Err(String::from("使用ssh隧道时需要指定host, 为从ssh主机访问mysql的地址"));
+        }
+        let args = ssh_args(&conf.ssh, &conf.sshkey, &conf.host_info)?;
+        let name = format!("ssh {}", conf.ssh);
+        conf.host_info = bridge(name, move || ssh(&args)).map_err(|e| format!("ssh隧道监听失败: {}", e))?;
+    }
+    Ok(())
+}
+
+/// 连接信息中的地址说明, 用于--dry-run等输出
+pub fn describe(conf: &Config) -> String {
+    if !conf.socket.is_empty() {
+        format!("{} (unix socket, 转发地址{})", conf.socket, conf.host_info)
+    } else if !conf.ssh.is_empty() {
+        format!("{} (ssh隧道{})", conf.host_info, conf.ssh)
+    } else {
+        conf.host_info.clone()
+    }
+}
+
+/// ssh的参数: user@host[:port]的ssh主机, 可选的密钥文件, mysql的地址为从ssh主机访问的host:port
+pub fn ssh_args(ssh: &str, key: &str, target: &str) -> Result<Vec<String>, String> {
+    let (user, rest) = match ssh.rsplit_once('@') {
+        Some((user, rest)) => (Some(user), rest),
+        None => (None, ssh),
+    };
+    let (host, port) = match rest.strip_prefix('[') {
+        Some(t) => match t.split_once(']') {
+            Some((host, "")) => (host, None),
+            Some((host, port)) => (host, Some(port.strip_prefix(':').ok_or_else(|| format!("无效的ssh地址: {}", ssh))?)),
+            None => return Err(format!("无效的ssh地址: {}", ssh)),
+        },
+        None if rest.matches(':').count() > 1 => (rest, None),
+        None => match rest.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (rest, None),
+        },
+    };
+    if host.is_empty() {
+        return Err(format!("无效的ssh地址: {}", ssh));
+    }
+    let mut args: Vec<String> = ["-o", "BatchMode=yes"].iter().map(|t| t.to_string()).collect();
+    if let Some(port) = port {
+        port.parse::<u16>().map_err(|_| format!("无效的ssh端口: {}", port))?;
+        args.extend([String::from("-p"), port.to_string()]);
+    }
+    if !key.is_empty() {
+        args.extend([String::from("-i"), key.to_string()]);
+    }
+    if let Some(user) = user {
+        args.extend([String::from("-l"), user.to_string()]);
+    }
+    args.extend([String::from("-W"), target.to_string(), host.to_string()]);
+    Ok(args)
+}
+
+type Upstream = (Box<dyn Read + Send>, Box<dyn Write + Send>);
+
+//在127.0.0.1的随机端口监听, 每个连接通过open打开对端并双向转发
+fn bridge<F>(name: String, open: F) -> io::Result<String>
+    where F: Fn() -> io::Result<Upstream> + Send + 'static {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?.to_string();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            match open() {
+                Ok((reader, writer)) => forward(stream, reader, writer),
+                Err(e) => eprintln!("{}: {}", name, e),
+            }
+        }
+    });
+    Ok(addr)
+}
+
+fn forward(stream: TcpStream, mut reader: Box<dyn Read + Send>, mut writer: Box<dyn Write + Send>) {
+    let mut input = match stream.try_clone() {
+        Ok(t) => t,
+        Err(_) => return,
+    };
+    //本地连接关闭时关闭对端的写入, ssh进程随之退出
+    thread::spawn(move || {
+        let _ = io::copy(&mut input, &mut writer);
+    });
+    thread::spawn(move || {
+        let mut output = stream;
+        let _ = io::copy(&mut reader, &mut output);
+        let _ = output.shutdown(Shutdown::Both);
+    });
+}
+
+#[cfg(unix)]
+fn unix_socket(path: &str) -> Result<String, String> {
+    use std::os::unix::net::UnixStream;
+    //写入端drop时关闭unix socket的写入, mysql随之关闭连接
+    struct Writer(UnixStream);
+    impl Write for Writer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+    impl Drop for Writer {
+        fn drop(&mut self) {
+            let _ = self.0.shutdown(Shutdown::Write);
+        }
+    }
+
+    //先检查是否可以连接, 避免之后每次连接时才报错
+    UnixStream::connect(path).map_err(|e| format!("连接{}失败: {}", path, e))?;
+    let socket = path.to_string();
+    bridge(format!("unix socket {}", path), move || -> io::Result<Upstream> {
+        let stream = UnixStream::connect(&socket)?;
+        Ok((Box::new(stream.try_clone()?), Box::new(Writer(stream))))
+    }).map_err(|e| format!("unix socket转发监听失败: {}", e))
+}
+
+#[cfg(not(unix))]
+fn unix_socket(path: &str) -> Result<String, String> {
+    Err(format!("{}: 当前系统不支持unix socket", path))
+}
+
+//ssh进程的标准输出, 读取结束后结束ssh进程
+struct SshReader{
+    stdout: ChildStdout,
+    child: Child,
+}
+
+impl Read for SshReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+impl Drop for SshReader {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn ssh(args: &[String]) -> io::Result<Upstream> {
+    let mut child = Command::new("ssh").args(args)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::inherit())
+        .spawn().map_err(|e| io::Error::new(e.kind(), format!("执行ssh失败: {}", e)))?;
+    let stdin: ChildStdin = child.stdin.take().ok_or_else(|| io::Error::other("ssh没有标准输入"))?;
+    let stdout = child.stdout.take().ok_or_else(|| io::Error::other("ssh没有标准输出"))?;
+    Ok((Box::new(SshReader{ stdout, child }), Box::new(stdin)))
+}
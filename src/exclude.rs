@@ -0,0 +1,74 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//默认不输出的库表: 系统库及在线改表工具的影子表, CDC的下游不需要这些数据
+//  mysql、sys、information_schema、performance_schema
+//  gh-ost: _tbl_gho(影子表)、_tbl_ghc(心跳及状态)、_tbl_del(切换后的原表)
+//  pt-online-schema-change: _tbl_new(影子表)、_tbl_old(切换后的原表)
+//  --exclude-tables修改, 逗号分隔的db.tbl, 库名、表名中可以使用*匹配任意字符, none为不排除
+//  只过滤行数据及TABLE_MAP, DDL语句仍然输出
+
+use crate::Config;
+
+/// 默认排除的库表
+pub const DEFAULT: &str = "mysql.*, sys.*, information_schema.*, performance_schema.*, *._*_gho, *._*_ghc, *._*_del, *._*_new, *._*_old";
+
+/// 排除的库表
+#[derive(Debug, Clone, Default)]
+pub struct Exclusion{
+    patterns: Vec<(String, String)>,
+}
+
+impl Exclusion {
+    /// 空为默认的排除列表, none为不排除
+    pub fn new(spec: &str) -> Result<Exclusion, String> {
+        let spec = match spec.trim() {
+            "" => DEFAULT,
+            t if t.eq_ignore_ascii_case("none") => return Ok(Exclusion::default()),
+            t => t,
+        };
+        let mut patterns = vec![];
+        for item in spec.split(',').map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            match crate::ident::split_qualified(item).as_slice() {
+                [db, tbl] if !db.is_empty() && !tbl.is_empty() => patterns.push((db.clone(), tbl.clone())),
+                _ => return Err(format!("无效的exclude-tables: {}, 格式为db.tbl, 可以使用*", item)),
+            }
+        }
+        Ok(Exclusion{ patterns })
+    }
+
+    /// 配置已检查, 无效时使用默认的排除列表
+    pub fn from_config(conf: &Config) -> Exclusion {
+        Exclusion::new(&conf.excludetables).or_else(|_| Exclusion::new("")).unwrap_or_default()
+    }
+
+    pub fn excluded(&self, db: &str, tbl: &str) -> bool {
+        self.patterns.iter().any(|(d, t)| glob(d, db) && glob(t, tbl))
+    }
+}
+
+//*匹配任意个字符
+fn glob(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    //最后一个*的位置及其匹配到的文本位置, 不匹配时回退
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
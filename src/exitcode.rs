@@ -0,0 +1,19 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/31
+*/
+
+//程序退出码, 外部脚本可以根据退出码判断失败原因
+
+pub const OK: i32 = 0;
+pub const ERROR: i32 = 1;           //其他错误
+pub const USAGE: i32 = 2;           //参数、配置错误, 与clap参数解析错误一致
+pub const CONNECT: i32 = 3;         //无法连接mysql
+pub const AUTH: i32 = 4;            //mysql认证失败
+pub const PARSE: i32 = 5;           //binlog文件不完整、格式错误或解析失败
+pub const SINK: i32 = 6;            //写入输出文件失败
+pub const NO_EVENTS: i32 = 7;       //过滤条件没有匹配到任何数据
+pub const SERVER: i32 = 8;          //mysql配置不满足要求、语句执行失败、注册slave失败
+pub const GUARD: i32 = 9;           //flashback超过安全限制(行数、范围内包含DDL), 需要--force
+pub const MISMATCH: i32 = 10;       //rowcheck发现目标库的数据与binlog不一致, doctor发现没有保存checkpoint的事务, manifest中文件的sha256不一致
+pub const INTERRUPTED: i32 = 130;   //被ctrl-c中断
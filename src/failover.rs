@@ -0,0 +1,146 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//主库切换检测: 通过VIP连接时故障切换后重新连接到的是新的主库, binlog继续读取但来自不同的server
+//  FORMAT_DESCRIPTION_EVENT的server_id(mysql在每次注册及每个文件开头发送)与之前不同时判断为主库切换,
+//  GTID_EVENT中出现之前没有见过的server_uuid时同样提示(多源复制、级联复制中新的上游写入时也会出现)
+//  切换后按新主库的PREVIOUS_GTIDS_LOG_EVENT检查checkpoint(已执行的gtid集合):
+//      新主库在当前文件之前已执行、但不在checkpoint中的事务不会再被读取, 需要人工确认(补数据或从其他位置重新拉取)
+//  stream时在stderr输出, 配置--pause-on-switch时通过控制接口暂停, 确认后POST /resume继续
+
+use serde_json::{json, Value as JsonValue};
+use uuid::Uuid;
+use crate::gtid::{ExecutedTracker, GtidSet};
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::{BinlogEvent, EventHeader};
+
+/// 判断为主库切换的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum SwitchReason{
+    ServerId{ old: u32, new: u32 },
+    ServerUuid{ old: Uuid, new: Uuid },
+}
+
+/// 一次主库切换
+#[derive(Debug, Clone, PartialEq)]
+pub struct MasterSwitch{
+    pub reason: SwitchReason,
+    /// 发现切换时的binlog文件及position(已执行位置)
+    pub log_file: String,
+    pub log_pos: u64,
+    /// 切换时的checkpoint
+    pub executed: GtidSet,
+    /// 新主库PREVIOUS_GTIDS中不在checkpoint中的事务, 没有PREVIOUS_GTIDS_LOG_EVENT或按server_uuid判断时为None
+    pub missing: Option<GtidSet>,
+}
+
+impl MasterSwitch{
+    /// checkpoint与新主库一致, 不会遗漏事务
+    pub fn is_consistent(&self) -> bool {
+        self.missing.as_ref().map(|t| t.is_empty()).unwrap_or(true)
+    }
+
+    pub fn message(&self) -> String {
+        let reason = match &self.reason {
+            SwitchReason::ServerId{ old, new } => format!("server_id由{}变为{}", old, new),
+            SwitchReason::ServerUuid{ old, new } => format!("gtid的server_uuid由{}变为{}", old, new),
+        };
+        let check = match &self.missing {
+            None => String::from("未校验checkpoint"),
+            Some(t) if t.is_empty() => String::from("checkpoint与新主库的PREVIOUS_GTIDS一致"),
+            Some(t) => format!("新主库当前文件之前的事务{}不在checkpoint中, 不会被读取", t),
+        };
+        format!("检测到主库切换: {}, 位置: {}:{}, checkpoint: {}, {}",
+                reason, self.log_file, self.log_pos, self.executed, check)
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let (reason, old, new) = match &self.reason {
+            SwitchReason::ServerId{ old, new } => ("server_id", json!(old), json!(new)),
+            SwitchReason::ServerUuid{ old, new } => ("server_uuid", json!(old.to_string()), json!(new.to_string())),
+        };
+        json!({
+            "reason": reason,
+            "old": old,
+            "new": new,
+            "log_file": self.log_file,
+            "position": self.log_pos,
+            "executed": self.executed.to_string(),
+            "missing": self.missing.as_ref().map(|t| t.to_string()),
+            "consistent": self.is_consistent(),
+        })
+    }
+}
+
+/// 按读取到的事件检测主库切换
+#[derive(Debug, Clone, Default)]
+pub struct MasterWatch{
+    server_id: Option<u32>,
+    uuids: Vec<Uuid>,
+    last_uuid: Option<Uuid>,
+    //按server_id发现切换后新主库的server_uuid不再单独提示
+    switched: bool,
+    pending: Option<MasterSwitch>,
+}
+
+impl MasterWatch{
+    pub fn new() -> MasterWatch {
+        MasterWatch::default()
+    }
+
+    /// 每个事件调用一次, body为事件头之后的数据, executed为调用方维护的已执行位置
+    /// 按server_id发现的切换在下一个事件(新主库的PREVIOUS_GTIDS_LOG_EVENT)时返回
+    pub fn observe(&mut self, header: &EventHeader, data: &Traction, body: &[u8], executed: &ExecutedTracker) -> Option<MasterSwitch> {
+        match header.type_code {
+            BinlogEvent::FormatDescriptionEvent => {
+                if let Some(old) = self.server_id.filter(|t| *t != header.server_id) {
+                    let executed = executed.executed();
+                    self.pending = Some(MasterSwitch{
+                        reason: SwitchReason::ServerId{ old, new: header.server_id },
+                        log_file: executed.log_file,
+                        log_pos: executed.log_pos,
+                        executed: executed.gtid_set,
+                        missing: None,
+                    });
+                    self.switched = true;
+                }
+                self.server_id = Some(header.server_id);
+                return None;
+            }
+            BinlogEvent::PreviousGtidsLogEvent => {
+                let mut switch = self.pending.take()?;
+                if let Ok(previous) = GtidSet::decode(body) {
+                    switch.missing = Some(previous.subtract(&switch.executed));
+                }
+                return Some(switch);
+            }
+            _ => {}
+        }
+        if let Some(switch) = self.pending.take() {
+            return Some(switch);
+        }
+        let sid = match data {
+            Traction::GtidEvent(t) => t.gtid,
+            _ => return None,
+        };
+        if self.uuids.contains(&sid) {
+            self.last_uuid = Some(sid);
+            return None;
+        }
+        self.uuids.push(sid);
+        let old = self.last_uuid.replace(sid);
+        if std::mem::take(&mut self.switched) {
+            return None;
+        }
+        let executed = executed.executed();
+        old.map(|old| MasterSwitch{
+            reason: SwitchReason::ServerUuid{ old, new: sid },
+            log_file: executed.log_file,
+            log_pos: executed.log_pos,
+            executed: executed.gtid_set,
+            missing: None,
+        })
+    }
+}
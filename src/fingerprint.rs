@@ -0,0 +1,255 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//识别写入binlog的mysql版本及分支(MySQL、Percona、MariaDB、Aurora), 给出解析时可用的事件类型及metadata
+//  读取文件时按FORMAT_DESCRIPTION_EVENT中的server_version, 连接时按@@version、@@version_comment及@@aurora_version
+//  Percona的版本号带有发行号(8.0.19-10), version_comment中包含Percona; Aurora的版本与社区版相同, 只能连接后通过@@aurora_version识别
+//  MariaDB的binlog格式从5.x分出: 解析时按5.x处理, GTID(162)、GTID_LIST(163)、ANNOTATE_ROWS(160)按未知事件跳过,
+//  行事件为v1格式(23-25), 不解析行数据, 只能使用backup保存原始文件
+//  mytest capabilities -f/-u输出报告, --dry-run时同样输出, stream注册之前不支持的组合给出原因后退出
+
+use std::convert::TryFrom;
+use std::fmt;
+use crate::replication::readevent::FormatDescriptionEvent;
+
+/// mysql的分支
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fork {
+    Mysql,
+    Percona,
+    MariaDb,
+    Aurora,
+}
+
+impl fmt::Display for Fork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Fork::Mysql => "MySQL",
+            Fork::Percona => "Percona Server",
+            Fork::MariaDb => "MariaDB",
+            Fork::Aurora => "Aurora MySQL",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 一项解析能力
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capability {
+    pub name: &'static str,
+    pub available: bool,
+    pub note: String,
+}
+
+impl Capability {
+    fn new(name: &'static str, available: bool, note: &str) -> Capability {
+        Capability{ name, available, note: note.to_string() }
+    }
+}
+
+/// 版本及分支
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerFingerprint {
+    pub server_version: String,
+    pub fork: Fork,
+    pub version: [u32; 3],
+    /// Aurora的版本(@@aurora_version)
+    pub aurora_version: Option<String>,
+}
+
+impl ServerFingerprint {
+    /// 按版本字符串及version_comment识别, 读取文件时没有version_comment
+    pub fn parse(server_version: &str, comment: &str) -> ServerFingerprint {
+        let lower = server_version.to_lowercase();
+        //MariaDB 10.x之前的复制协议中版本带有5.5.5-前缀
+        let raw = match lower.contains("mariadb") {
+            true => server_version.strip_prefix("5.5.5-").unwrap_or(server_version),
+            false => server_version,
+        };
+        let mut version = [0u32; 3];
+        let mut idx = 0;
+        let mut end = raw.len();
+        for (pos, c) in raw.char_indices() {
+            match c {
+                '0'..='9' => version[idx] = version[idx].saturating_mul(10).saturating_add(c as u32 - '0' as u32),
+                '.' if idx < 2 => idx += 1,
+                _ => {
+                    end = pos;
+                    break;
+                }
+            }
+        }
+        //Percona的发行号: 8.0.19-10、5.7.26-29-log
+        let release = raw[end..].strip_prefix('-').and_then(|t| t.split('-').next()).is_some_and(|t| !t.is_empty() && t.chars().all(|c| c.is_ascii_digit()));
+        let fork = if lower.contains("mariadb") || comment.to_lowercase().contains("mariadb") {
+            Fork::MariaDb
+        } else if lower.contains("percona") || comment.to_lowercase().contains("percona") || release {
+            Fork::Percona
+        } else {
+            Fork::Mysql
+        };
+        ServerFingerprint{ server_version: server_version.to_string(), fork, version, aurora_version: None }
+    }
+
+    /// 按FORMAT_DESCRIPTION_EVENT识别, 不能区分Aurora与社区版
+    pub fn from_fde(event: &FormatDescriptionEvent) -> ServerFingerprint {
+        ServerFingerprint::parse(&event.server_version, "")
+    }
+
+    /// 设置@@aurora_version, 为Aurora
+    pub fn with_aurora(mut self, aurora_version: &str) -> ServerFingerprint {
+        self.fork = Fork::Aurora;
+        self.aurora_version = Some(aurora_version.to_string());
+        self
+    }
+
+    fn at_least(&self, version: [u32; 3]) -> bool {
+        self.version >= version
+    }
+
+    fn mariadb(&self) -> bool {
+        self.fork == Fork::MariaDb
+    }
+
+    /// 解析事件时使用的主版本号, MariaDB 10.x按5.x的格式解析
+    pub fn parser_version(&self) -> u8 {
+        match self.version[0] {
+            0 => 5,
+            _ if self.mariadb() => 5,
+            t => u8::try_from(t).unwrap_or(u8::MAX),
+        }
+    }
+
+    /// 是否可以解析行数据, MariaDB及5.6之前的版本为v1格式的行事件
+    pub fn row_events(&self) -> bool {
+        !self.mariadb() && self.at_least([5, 6, 2])
+    }
+
+    /// 是否可以使用--gtid(COM_BINLOG_DUMP_GTID)注册
+    pub fn gtid(&self) -> bool {
+        !self.mariadb() && self.at_least([5, 6, 5])
+    }
+
+    /// 各项解析能力, 依赖mysql配置的在note中说明
+    pub fn capabilities(&self) -> Vec<Capability> {
+        let mysql = !self.mariadb();
+        let mut caps = vec![
+            Capability::new("checksum", self.at_least([5, 6, 1]),
+                            if self.at_least([5, 6, 1]) { "binlog_checksum=CRC32时校验每个事件" } else { "5.6.1之前的版本没有checksum" }),
+            Capability::new("row_events", self.row_events(), match (mysql, self.row_events()) {
+                (false, _) => "MariaDB的行事件为v1格式(23-25), 不解析行数据, 只能使用backup保存原始文件",
+                (true, false) => "5.6之前的行事件为v1格式, 不解析行数据",
+                (true, true) => "WRITE/UPDATE/DELETE_ROWS v2, 需要binlog_format=ROW",
+            }),
+            Capability::new("gtid", self.gtid(), match (mysql, self.gtid()) {
+                (false, _) => "MariaDB的GTID(162)、GTID_LIST(163)按未知事件跳过, 不能使用--gtid注册",
+                (true, false) => "5.6.5之前的版本没有gtid",
+                (true, true) => "GTID、PREVIOUS_GTIDS, 需要gtid_mode=ON",
+            }),
+            Capability::new("logical_clock", mysql && self.at_least([5, 7, 6]),
+                            if mysql && self.at_least([5, 7, 6]) { "GTID中的last_committed、sequence_number, 用于--deps、--groupcommit" } else { "没有last_committed、sequence_number" }),
+            Capability::new("commit_timestamp", mysql && self.at_least([8, 0, 1]),
+                            if mysql && self.at_least([8, 0, 1]) { "GTID中微秒的immediate_commit_timestamp" } else { "只有秒级的事件时间" }),
+            Capability::new("rows_query", mysql && self.at_least([5, 6, 2]), match (mysql, self.at_least([5, 6, 2])) {
+                (false, _) => "MariaDB的ANNOTATE_ROWS(160)按未知事件跳过",
+                (true, false) => "5.6之前的版本没有ROWS_QUERY_EVENT",
+                (true, true) => "ROWS_QUERY中的原始语句, 需要binlog_rows_query_log_events=ON",
+            }),
+        ];
+        //8.0.1、MariaDB 10.5之后TABLE_MAP中可以带有字段名及主键
+        let metadata = if mysql { self.at_least([8, 0, 1]) } else { self.at_least([10, 5, 0]) };
+        caps.push(Capability::new("table_metadata", metadata,
+                                  if metadata { "TABLE_MAP中的字段名、主键, 需要binlog_row_metadata=FULL" } else { "没有TABLE_MAP metadata, 字段名需要连接mysql查询" }));
+        if mysql && self.at_least([8, 0, 3]) {
+            caps.push(Capability::new("partial_json", false, "binlog_row_value_options=PARTIAL_JSON时的PARTIAL_UPDATE_ROWS(39)不解析, 需要设置为空"));
+        }
+        if mysql && self.at_least([8, 0, 20]) {
+            caps.push(Capability::new("transaction_compression", false, "binlog_transaction_compression=ON时的TRANSACTION_PAYLOAD(40)不解析, 需要设置为OFF"));
+        }
+        caps
+    }
+
+    /// 报告的每一行
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = vec![];
+        lines.push(format!("server_version: {}", self.server_version));
+        lines.push(format!("fork: {}{}, version: {}.{}.{}, 按{}.x的格式解析", self.fork,
+                           self.aurora_version.as_ref().map(|t| format!("({})", t)).unwrap_or_default(),
+                           self.version[0], self.version[1], self.version[2], self.parser_version()));
+        for cap in self.capabilities() {
+            lines.push(format!("[{}] {:<24}{}", if cap.available { "yes" } else { "no " }, cap.name, cap.note));
+        }
+        lines
+    }
+}
+
+/// 读取binlog文件的FORMAT_DESCRIPTION_EVENT识别
+#[cfg(feature = "os")]
+pub fn from_file(path: &str) -> std::io::Result<ServerFingerprint> {
+    use std::fs::File;
+    use std::io::{self, BufReader, Read};
+    use crate::replication::readevent::{BinlogEvent, EventHeader, InitValue};
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != [0xFE, 0x62, 0x69, 0x6E] {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} 不是binlog文件", path)));
+    }
+    let header = EventHeader::read(&mut reader)?;
+    if !matches!(header.type_code, BinlogEvent::FormatDescriptionEvent) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}的第一个事件不是FORMAT_DESCRIPTION_EVENT", path)));
+    }
+    let body = crate::readvalue::read_bytes(&mut reader, (header.event_length as usize).saturating_sub(header.header_length as usize))?;
+    let event = FormatDescriptionEvent::read_event(&header, &mut io::Cursor::new(body), &5)?;
+    Ok(ServerFingerprint::from_fde(&event))
+}
+
+/// 连接mysql识别, Aurora通过@@aurora_version识别
+#[cfg(feature = "network")]
+pub fn detect(conn: &mut std::net::TcpStream) -> Result<ServerFingerprint, String> {
+    use crate::io::command::try_execute;
+    let values = try_execute(conn, "select @@version as version, @@version_comment as comment;")?;
+    let row = values.first().ok_or_else(|| String::from("获取mysql版本信息失败"))?;
+    let version = row.get("version").cloned().unwrap_or_default();
+    if version.is_empty() {
+        return Err(String::from("获取mysql版本信息失败"));
+    }
+    let fingerprint = ServerFingerprint::parse(&version, row.get("comment").map(|t| t.as_str()).unwrap_or(""));
+    //不存在的变量返回错误
+    let aurora = try_execute(conn, "select @@aurora_version as value;").ok()
+        .and_then(|values| values.first().and_then(|row| row.get("value").cloned()))
+        .filter(|t| !t.is_empty());
+    Ok(match aurora {
+        Some(t) if fingerprint.fork != Fork::MariaDb => fingerprint.with_aurora(&t),
+        _ => fingerprint,
+    })
+}
+
+/// capabilities子命令: 读取-f指定的文件或连接mysql, 输出版本及可用的事件类型、metadata
+#[cfg(feature = "network")]
+pub fn run(conf: &crate::Config) {
+    use std::process;
+    use crate::exitcode;
+    let fingerprint = if conf.file.is_empty() {
+        let mut conn = crate::create_conn(conf);
+        detect(&mut conn).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::SERVER);
+        })
+    } else {
+        from_file(&conf.file).unwrap_or_else(|err|{
+            println!("读取文件({})发生错误:{}", conf.file, err);
+            process::exit(exitcode::USAGE);
+        })
+    };
+    for line in fingerprint.report() {
+        println!("{}", line);
+    }
+    if conf.file.is_empty() {
+        println!("-- 依赖mysql配置的项目需要同时满足对应的配置, 可以使用--dry-run检查");
+    } else {
+        println!("-- 按FORMAT_DESCRIPTION_EVENT识别, 不能区分Aurora与社区版");
+    }
+}
@@ -0,0 +1,354 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//gtid集合, 格式与mysql一致:
+//  19d69c1e-ae97-4b8c-a1ef-9e12ba966457:1-3:8-10,1c2aad49-ae92-409a-b4df-d05a03e4702e:42-47
+//已执行位置: 拉取时已完整读取的事务的gtid集合及对应的binlog文件、position, 用于CHANGE REPLICATION SOURCE重新开始同步
+
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::str::FromStr;
+use byteorder::{LittleEndian, ReadBytesExt};
+use uuid::Uuid;
+use crate::Config;
+use crate::readvalue;
+use crate::replication::readbinlog::Traction;
+
+/// gno区间, 与mysql内部表示一致为左闭右开 [start, stop)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GtidInterval{
+    pub start: u64,
+    pub stop: u64
+}
+
+/// 同一个server_uuid下的所有gno区间, 区间按start排序且互不重叠
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UuidSet{
+    pub sid: Uuid,
+    pub intervals: Vec<GtidInterval>
+}
+
+impl UuidSet{
+    fn contains(&self, gno: u64) -> bool {
+        self.intervals.iter().any(|i| i.start <= gno && gno < i.stop)
+    }
+
+    fn add_interval(&mut self, interval: GtidInterval) {
+        self.intervals.push(interval);
+        self.intervals.sort_by_key(|i| i.start);
+        let mut merged: Vec<GtidInterval> = vec![];
+        for i in &self.intervals {
+            match merged.last_mut() {
+                Some(last) if i.start <= last.stop => {
+                    if i.stop > last.stop {
+                        last.stop = i.stop;
+                    }
+                }
+                _ => merged.push(*i)
+            }
+        }
+        self.intervals = merged;
+    }
+}
+
+/// gtid集合, 可用于判断某个事务是否已执行、记录读取进度以及gtid模式注册
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GtidSet{
+    pub sets: Vec<UuidSet>
+}
+
+impl GtidSet{
+    pub fn new() -> GtidSet {
+        GtidSet{ sets: vec![] }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    pub fn contains(&self, sid: &Uuid, gno: u64) -> bool {
+        match self.sets.iter().find(|s| &s.sid == sid) {
+            Some(t) => t.contains(gno),
+            None => false
+        }
+    }
+
+    /// other中的所有事务都包含在当前集合中
+    pub fn contains_set(&self, other: &GtidSet) -> bool {
+        other.sets.iter().all(|o| {
+            match self.sets.iter().find(|s| s.sid == o.sid) {
+                Some(t) => o.intervals.iter().all(|i| {
+                    t.intervals.iter().any(|ti| ti.start <= i.start && i.stop <= ti.stop)
+                }),
+                None => o.intervals.is_empty()
+            }
+        })
+    }
+
+    /// 添加一个事务
+    pub fn add(&mut self, sid: Uuid, gno: u64) {
+        self.add_interval(sid, GtidInterval{ start: gno, stop: gno + 1 });
+    }
+
+    pub fn add_interval(&mut self, sid: Uuid, interval: GtidInterval) {
+        match self.sets.iter_mut().find(|s| s.sid == sid) {
+            Some(t) => t.add_interval(interval),
+            None => self.sets.push(UuidSet{ sid, intervals: vec![interval] })
+        }
+    }
+
+    /// 在当前集合中但不在other中的事务
+    pub fn subtract(&self, other: &GtidSet) -> GtidSet {
+        let mut result = GtidSet::new();
+        for set in &self.sets {
+            let removed: Vec<GtidInterval> = other.sets.iter().find(|o| o.sid == set.sid).map(|o| o.intervals.clone()).unwrap_or_default();
+            for interval in &set.intervals {
+                let mut start = interval.start;
+                for r in removed.iter().filter(|r| r.stop > interval.start && r.start < interval.stop) {
+                    if r.start > start {
+                        result.add_interval(set.sid, GtidInterval{ start, stop: r.start });
+                    }
+                    start = start.max(r.stop);
+                }
+                if start < interval.stop {
+                    result.add_interval(set.sid, GtidInterval{ start, stop: interval.stop });
+                }
+            }
+        }
+        result
+    }
+
+    /// 编码为COM_BINLOG_DUMP_GTID及PREVIOUS_GTIDS_LOG_EVENT中使用的格式
+    ///     n_sid: 8bytes
+    ///     | sid: 16bytes
+    ///     | n_intervals: 8bytes
+    ///     | | start: 8bytes
+    ///     | | stop: 8bytes
+    pub fn encode(&self) -> Vec<u8> {
+        let mut pack = vec![];
+        pack.extend(readvalue::write_u64(self.sets.len() as u64));
+        for uuid_set in &self.sets {
+            pack.extend(uuid_set.sid.as_bytes());
+            pack.extend(readvalue::write_u64(uuid_set.intervals.len() as u64));
+            for interval in &uuid_set.intervals {
+                pack.extend(readvalue::write_u64(interval.start));
+                pack.extend(readvalue::write_u64(interval.stop));
+            }
+        }
+        pack
+    }
+
+    /// 解析encode格式的数据, 比如PREVIOUS_GTIDS_LOG_EVENT的内容
+    pub fn decode(pack: &[u8]) -> Result<GtidSet, String> {
+        let mut buf = Cursor::new(pack);
+        let mut gtid_set = GtidSet::new();
+        let n_sid = buf.read_u64::<LittleEndian>().map_err(|e| e.to_string())?;
+        for _ in 0..n_sid {
+            let mut sid = [0u8; 16];
+            buf.read_exact(&mut sid).map_err(|e| e.to_string())?;
+            let n_intervals = buf.read_u64::<LittleEndian>().map_err(|e| e.to_string())?;
+            for _ in 0..n_intervals {
+                let start = buf.read_u64::<LittleEndian>().map_err(|e| e.to_string())?;
+                let stop = buf.read_u64::<LittleEndian>().map_err(|e| e.to_string())?;
+                //区间为左闭右开, gno从1开始
+                if start == 0 || stop <= start {
+                    return Err(format!("无效的gtid区间: {}:[{}, {})", Uuid::from_bytes(sid), start, stop));
+                }
+                gtid_set.add_interval(Uuid::from_bytes(sid), GtidInterval{ start, stop });
+            }
+        }
+        Ok(gtid_set)
+    }
+}
+
+impl FromStr for GtidSet {
+    type Err = String;
+
+    fn from_str(gtid: &str) -> Result<GtidSet, String> {
+        let mut gtid_set = GtidSet::new();
+        for uuid_set in gtid.split(',') {
+            let uuid_set = uuid_set.trim();
+            if uuid_set.is_empty() {
+                continue;
+            }
+            let gtid_info: Vec<&str> = uuid_set.split(':').collect();
+            let sid = match Uuid::parse_str(gtid_info[0].trim()) {
+                Ok(t) => t,
+                Err(_) => return Err(format!("无效的gtid: {}", uuid_set))
+            };
+            for interval in &gtid_info[1..] {
+                let values: Vec<&str> = interval.split('-').collect();
+                let parse = |v: &str| v.trim().parse::<u64>().map_err(|_| format!("无效的gtid: {}", uuid_set));
+                let (start, last) = match values.len() {
+                    1 => (parse(values[0])?, parse(values[0])?),
+                    2 => (parse(values[0])?, parse(values[1])?),
+                    _ => return Err(format!("无效的gtid: {}", uuid_set))
+                };
+                //gno从1开始, 结束的gno不能小于开始的gno, 转换为左闭右开时不能溢出
+                let stop = match last.checked_add(1) {
+                    Some(t) if start > 0 && last >= start => t,
+                    _ => return Err(format!("无效的gtid: {}", uuid_set))
+                };
+                gtid_set.add_interval(sid, GtidInterval{ start, stop });
+            }
+        }
+        Ok(gtid_set)
+    }
+}
+
+impl fmt::Display for GtidSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sets: Vec<String> = self.sets.iter().map(|uuid_set| {
+            let mut value = uuid_set.sid.to_string();
+            for interval in &uuid_set.intervals {
+                if interval.stop - interval.start == 1 {
+                    value.push_str(&format!(":{}", interval.start));
+                }else {
+                    value.push_str(&format!(":{}-{}", interval.start, interval.stop - 1));
+                }
+            }
+            value
+        }).collect();
+        write!(f, "{}", sets.join(","))
+    }
+}
+
+/// 已完整读取的事务的gtid集合及其结束的位置
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Executed{
+    pub gtid_set: GtidSet,
+    /// 读取文件或mysql还未发送ROTATE_EVENT时为空
+    pub log_file: String,
+    /// 最后一个已完成事务之后的位置, 即下一个事务的起始位置
+    pub log_pos: u64,
+}
+
+impl Executed{
+    /// 重新开始同步的语句(8.0.23之后的语法), 按position及gtid自动定位两种方式
+    ///     CHANGE REPLICATION SOURCE TO SOURCE_LOG_FILE='mysql-bin.000003', SOURCE_LOG_POS=1234;
+    ///     SET GLOBAL gtid_purged='uuid:1-100';
+    ///     CHANGE REPLICATION SOURCE TO SOURCE_AUTO_POSITION=1;
+    pub fn change_source(&self) -> Vec<String> {
+        let mut sql = vec![];
+        if !self.log_file.is_empty() {
+            sql.push(format!("CHANGE REPLICATION SOURCE TO SOURCE_LOG_FILE='{}', SOURCE_LOG_POS={};", self.log_file, self.log_pos));
+        }
+        if !self.gtid_set.is_empty() {
+            sql.push(format!("SET GLOBAL gtid_purged='{}';", self.gtid_set));
+            sql.push(String::from("CHANGE REPLICATION SOURCE TO SOURCE_AUTO_POSITION=1;"));
+        }
+        sql
+    }
+}
+
+/// 根据读取到的事件维护已执行位置, 事务结束(XID/COMMIT、DDL)时加入gtid并更新position, 事务中间不变
+#[derive(Debug, Clone, Default)]
+pub struct ExecutedTracker{
+    state: Executed,
+    pending: Option<(Uuid, u64)>,
+    in_transaction: bool,
+}
+
+impl ExecutedTracker{
+    pub fn new() -> ExecutedTracker {
+        ExecutedTracker::default()
+    }
+
+    /// 注册时的位置: gtid为已执行的集合, 或binlogfile、position
+    /// 按文件注册时gtid集合为空, 只包含之后读取到的事务
+    pub fn from_config(conf: &Config) -> ExecutedTracker {
+        let mut tracker = ExecutedTracker::new();
+        tracker.state.gtid_set = conf.gtid.parse::<GtidSet>().unwrap_or_default();
+        tracker.state.log_file = conf.binlogfile.clone();
+        tracker.state.log_pos = conf.position.parse::<u64>().unwrap_or(4);
+        tracker
+    }
+
+    /// 重新注册(回退)后的位置, 按文件回退时保留已执行的gtid集合, 重复读取的事务不改变集合
+    pub fn restart(&mut self, conf: &Config) {
+        let mut tracker = ExecutedTracker::from_config(conf);
+        if conf.gtid.is_empty() {
+            tracker.state.gtid_set = self.state.gtid_set.clone();
+        }
+        *self = tracker;
+    }
+
+    /// next_position为事件头中的值, artificial事件为0
+    pub fn observe(&mut self, data: &Traction, next_position: u64) {
+        match data {
+            Traction::GtidEvent(t) => {
+                self.pending = Some((t.gtid, t.gno_id));
+                self.in_transaction = true;
+            }
+            Traction::QueryEvent(t) if t.command == "BEGIN" => self.in_transaction = true,
+            Traction::XidEvent(_) | Traction::QueryEvent(_) => {
+                self.in_transaction = false;
+                if let Some((sid, gno)) = self.pending.take() {
+                    self.state.gtid_set.add(sid, gno);
+                }
+                if next_position > 0 {
+                    self.state.log_pos = next_position;
+                }
+            }
+            //ROTATE_EVENT只出现在事务之间
+            Traction::RotateLogEvent(t) => {
+                self.state.log_file = t.binlog_file.clone();
+                self.state.log_pos = t.position;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.in_transaction
+    }
+
+    pub fn executed(&self) -> Executed {
+        self.state.clone()
+    }
+}
+
+/// 拉取的结束位置, 与START REPLICA UNTIL相同; COM_BINLOG_DUMP没有UNTIL选项, 由客户端在包含边界的事务结束后停止
+#[derive(Debug, Clone, PartialEq)]
+pub enum Until{
+    /// SQL_AFTER_GTIDS: 集合中的事务都已读取(按gtid注册时包括注册的集合)
+    Gtids(GtidSet),
+    /// SOURCE_LOG_FILE/SOURCE_LOG_POS: 已执行位置到达或超过该位置
+    Position(String, u64),
+}
+
+impl Until{
+    /// conf中的untilgtid或untilposition(文件:position), 都为空时为None
+    pub fn from_config(conf: &Config) -> Result<Option<Until>, String> {
+        match (conf.untilgtid.as_str(), conf.untilposition.as_str()) {
+            ("", "") => Ok(None),
+            (gtid, "") => {
+                let set = gtid.parse::<GtidSet>().map_err(|e| format!("无效的until-gtid: {}, {}", gtid, e))?;
+                Ok(Some(Until::Gtids(set)))
+            }
+            ("", position) => match position.rsplit_once(':') {
+                Some((file, pos)) if !file.is_empty() => match pos.parse::<u64>() {
+                    Ok(pos) => Ok(Some(Until::Position(file.to_string(), pos))),
+                    Err(_) => Err(format!("无效的until-position: {}, 格式为文件:position", position)),
+                },
+                _ => Err(format!("无效的until-position: {}, 格式为文件:position", position)),
+            },
+            _ => Err(String::from("until-gtid与until-position不能同时指定")),
+        }
+    }
+
+    /// 不在事务中间且已执行位置满足条件
+    pub fn reached(&self, tracker: &ExecutedTracker) -> bool {
+        if tracker.in_transaction {
+            return false;
+        }
+        let state = &tracker.state;
+        match self {
+            Until::Gtids(set) => state.gtid_set.contains_set(set),
+            //binlog文件名的序号为定长, 按字符串比较
+            Until::Position(file, pos) => !state.log_file.is_empty() && (state.log_file.as_str() > file.as_str() || (&state.log_file == file && state.log_pos >= *pos)),
+        }
+    }
+}
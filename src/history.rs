@@ -0,0 +1,478 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//history: 按主键还原一行数据的变更历史, 用于排查数据在什么时候、被哪个事务修改
+//  --table db.tbl --key 42 或 --key id=42,region=eu, 只给出值时按主键字段的顺序对应
+//  按顺序扫描binlog文件(或连接mysql拉取), 每个insert、update、delete输出时间、gtid、文件及position、前后镜像
+//  update修改了主键时继续跟踪新的主键值, 之后使用原来主键值的行不再输出
+//  字段名: 连接mysql时查询表结构, 否则使用TABLE_MAP中的metadata(binlog_row_metadata=FULL), 都没有时为@1、@2...
+//  按字段值比较的规则与--where(rowfilter)相同: 不带引号的值按数字比较, 带引号时按输出的文本比较
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+use bigdecimal::BigDecimal;
+use serde_json::{json, Map, Value as JsonValue};
+use crate::reader::Event;
+use crate::replication::parsevalue::MySQLValue;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::{BinlogEvent, TableMap};
+use crate::rowfilter::RowFilter;
+use crate::stdout::format::{BinaryEncoding, ValueFormat};
+
+/// 跟踪的行: 库表及主键的值, 字段名为None时按主键字段的顺序对应
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowKey{
+    pub database: String,
+    pub table: String,
+    pub values: Vec<(Option<String>, String)>,
+}
+
+impl RowKey{
+    /// table为db.tbl, key为逗号分隔的值或col=value, 值可以带引号
+    pub fn parse(table: &str, key: &str) -> Result<RowKey, String> {
+        let (database, table) = table.trim().split_once('.')
+            .filter(|(db, tbl)| !db.is_empty() && !tbl.is_empty())
+            .ok_or_else(|| format!("无效的表名: {}, 格式为db.tbl", table))?;
+        let mut values = vec![];
+        for item in split_key(key) {
+            let value = match item.split_once('=') {
+                Some((col, value)) if !col.trim().is_empty() && !col.trim().starts_with(['\'', '"']) => {
+                    (Some(col.trim().trim_matches('`').to_string()), value.trim().to_string())
+                }
+                _ => (None, item.trim().to_string()),
+            };
+            if value.1.is_empty() {
+                return Err(format!("无效的主键: {}", key));
+            }
+            values.push(value);
+        }
+        if values.is_empty() {
+            return Err(String::from("主键不能为空"));
+        }
+        if values.iter().any(|(col, _)| col.is_some()) && values.iter().any(|(col, _)| col.is_none()) {
+            return Err(format!("无效的主键: {}, 需要都为值或都为col=value", key));
+        }
+        Ok(RowKey{ database: database.trim_matches('`').to_string(), table: table.trim_matches('`').to_string(), values })
+    }
+
+    pub fn db_tbl(&self) -> String {
+        format!("{}.{}", self.database, self.table)
+    }
+
+    //按表结构确定主键字段名, 只给出值时按COLUMN_KEY为PRI的字段顺序对应
+    fn resolve(&self, cols: &[HashMap<String, String>]) -> Result<Vec<(String, String)>, String> {
+        if let Some((None, _)) = self.values.first() {
+            let pk: Vec<String> = cols.iter()
+                .filter(|c| c.get("COLUMN_KEY").is_some_and(|k| k == "PRI"))
+                .filter_map(|c| c.get("COLUMN_NAME").cloned())
+                .collect();
+            if pk.len() != self.values.len() {
+                return Err(format!("{}的主键为{}个字段({}), 指定了{}个值; 没有表结构时需要使用col=value, 例如@1=42",
+                                   self.db_tbl(), pk.len(), pk.join(","), self.values.len()));
+            }
+            return Ok(pk.into_iter().zip(self.values.iter().map(|(_, v)| v.clone())).collect());
+        }
+        Ok(self.values.iter().map(|(col, v)| (col.clone().unwrap_or_default(), v.clone())).collect())
+    }
+}
+
+//逗号分隔, 引号中的逗号不分隔
+fn split_key(key: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let (mut quote, mut start) = (None, 0);
+    for (i, c) in key.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, ',') => {
+                items.push(&key[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&key[start..]);
+    items.into_iter().filter(|t| !t.trim().is_empty()).collect()
+}
+
+//rowfilter条件中的值: 数字及带引号的值原样使用, 其他按字符串加引号
+fn literal(value: &str) -> String {
+    let quoted = value.len() >= 2 && (value.starts_with('\'') && value.ends_with('\'') || value.starts_with('"') && value.ends_with('"'));
+    if quoted || BigDecimal::from_str(value).is_ok() {
+        value.to_string()
+    }else {
+        format!("'{}'", value.replace('\'', "''"))
+    }
+}
+
+//去掉引号后的值
+fn unquote(value: &str) -> String {
+    let quoted = value.len() >= 2 && (value.starts_with('\'') && value.ends_with('\'') || value.starts_with('"') && value.ends_with('"'));
+    if quoted {
+        let q = &value[..1];
+        value[1..value.len() - 1].replace(&format!("{}{}", q, q), q)
+    }else {
+        value.to_string()
+    }
+}
+
+//两个值是否相同, 都为数字时按数字比较
+fn same(a: &str, b: &str) -> bool {
+    match (BigDecimal::from_str(a), BigDecimal::from_str(b)) {
+        (Ok(x), Ok(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+/// 一次变更
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change{
+    pub file: String,
+    /// 行事件在文件中的起始位置
+    pub position: u64,
+    pub timestamp: u32,
+    pub gtid: Option<String>,
+    pub kind: &'static str,
+    /// 修改前的数据(update、delete), 按字段顺序
+    pub before: Option<Vec<(String, JsonValue)>>,
+    /// 修改后的数据(insert、update)
+    pub after: Option<Vec<(String, JsonValue)>>,
+}
+
+impl Change{
+    /// update中值有变化的字段
+    pub fn changed(&self) -> Vec<&str> {
+        match (&self.before, &self.after) {
+            (Some(before), Some(after)) => before.iter().zip(after.iter())
+                .filter(|(b, a)| b.1 != a.1)
+                .map(|(b, _)| b.0.as_str())
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let image = |row: &Option<Vec<(String, JsonValue)>>| match row {
+            Some(t) => JsonValue::Object(t.iter().cloned().collect::<Map<String, JsonValue>>()),
+            None => JsonValue::Null,
+        };
+        json!({
+            "file": self.file,
+            "position": self.position,
+            "timestamp": self.timestamp,
+            "datetime": crate::stdout::format::utc_datetime(self.timestamp),
+            "gtid": self.gtid,
+            "type": self.kind,
+            "before": image(&self.before),
+            "after": image(&self.after),
+            "changed": self.changed(),
+        })
+    }
+
+    /// 文本格式, seq为第几次变更
+    pub fn print(&self, seq: usize) {
+        println!("#{} {} UTC  {}  {}:{}  gtid: {}", seq, crate::stdout::format::utc_datetime(self.timestamp),
+                 self.kind.to_uppercase(), self.file, self.position, self.gtid.as_deref().unwrap_or("-"));
+        let image = |row: &[(String, JsonValue)]| row.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<String>>().join(", ");
+        if let Some(before) = &self.before {
+            println!("    before: {}", image(before));
+        }
+        if let Some(after) = &self.after {
+            println!("    after:  {}", image(after));
+        }
+        if self.kind == "update" {
+            println!("    changed: {}", self.changed().join(", "));
+        }
+    }
+}
+
+/// 行事件对应的字段信息, schema为查询到的表结构(字段数与binlog中一致时使用), 否则使用TABLE_MAP中的metadata
+pub fn columns(map: &TableMap, schema: Option<&Vec<HashMap<String, String>>>) -> Vec<HashMap<String, String>> {
+    if let Some(cols) = schema.filter(|t| t.len() == map.column_info.len()) {
+        return cols.clone();
+    }
+    let mut cols = crate::sink::default_columns(map);
+    if let Some(metadata) = &map.metadata {
+        for (idx, col) in cols.iter_mut().enumerate() {
+            if let Some(name) = metadata.columns.get(idx).and_then(|c| c.name.clone()) {
+                col.insert(String::from("COLUMN_NAME"), name);
+            }
+            if metadata.primary_key.iter().any(|(pk, _)| *pk == idx) {
+                col.insert(String::from("COLUMN_KEY"), String::from("PRI"));
+            }
+        }
+    }
+    cols
+}
+
+/// 按事件顺序找到跟踪的行的每次变更
+pub struct RowHistory{
+    key: RowKey,
+    format: ValueFormat,
+    filter: Option<(RowFilter, Vec<(String, String)>)>,
+    file: String,
+    gtid: Option<String>,
+}
+
+impl RowHistory{
+    /// file为第一个文件的文件名, 之后按ROTATE_EVENT切换
+    pub fn new(key: RowKey, format: ValueFormat, file: &str) -> RowHistory {
+        RowHistory{ key, format, filter: None, file: file.to_string(), gtid: None }
+    }
+
+    /// 当前跟踪的主键值, update修改主键之后为新的值
+    pub fn key(&self) -> &RowKey {
+        &self.key
+    }
+
+    /// 切换到下一个文件, 读取多个本地文件时使用
+    pub fn set_file(&mut self, file: &str) {
+        self.file = file.to_string();
+    }
+
+    /// 处理一个事件, 返回其中该行的变更; cols为该表的字段信息(见columns)
+    pub fn feed(&mut self, event: &Event, cols: &[HashMap<String, String>]) -> Result<Vec<Change>, String> {
+        let map = match &event.data {
+            Traction::GtidEvent(t) => {
+                self.gtid = Some(format!("{}:{}", t.gtid, t.gno_id));
+                return Ok(vec![]);
+            }
+            Traction::RotateLogEvent(t) => {
+                self.file = t.binlog_file.clone();
+                return Ok(vec![]);
+            }
+            //表结构可能变化, 重新按字段名编译
+            Traction::QueryEvent(t) if t.command != "BEGIN" => {
+                if let Some((filter, _)) = self.filter.as_mut() {
+                    filter.reset();
+                }
+                return Ok(vec![]);
+            }
+            Traction::RowEvent(..) => match &event.table {
+                Some(t) if t.database_name == self.key.database && t.table_name == self.key.table => t,
+                _ => return Ok(vec![]),
+            },
+            _ => return Ok(vec![]),
+        };
+        if self.filter.is_none() {
+            let resolved = self.key.resolve(cols)?;
+            let db_tbl = self.key.db_tbl();
+            let expr: Vec<String> = resolved.iter().map(|(col, value)| format!("{}.{} = {}", db_tbl, col, literal(value))).collect();
+            self.filter = Some((RowFilter::new(&expr.join(" and "), self.format.clone())?, resolved));
+        }
+        let (filter, resolved) = self.filter.as_mut().unwrap();
+        let data = match filter.filter(&event.data, &self.key.db_tbl(), cols)? {
+            Some(t) => t,
+            None => return Ok(vec![]),
+        };
+        let resolved = resolved.clone();
+        let (code, rows) = match data.as_ref() {
+            Traction::RowEvent(code, rows) => (code.clone(), rows.rows().to_vec()),
+            _ => return Ok(vec![]),
+        };
+        let key_index: Vec<usize> = resolved.iter()
+            .filter_map(|(col, _)| cols.iter().position(|c| c.get("COLUMN_NAME").is_some_and(|n| n.eq_ignore_ascii_case(col))))
+            .collect();
+        let key_values: Vec<String> = resolved.iter().map(|(_, v)| unquote(v)).collect();
+
+        let change = |kind: &'static str, before: Option<&Vec<Option<MySQLValue>>>, after: Option<&Vec<Option<MySQLValue>>>| Change{
+            file: self.file.clone(),
+            position: event.log_pos,
+            timestamp: event.header.timestamp,
+            gtid: self.gtid.clone(),
+            kind,
+            before: before.map(|row| self.image(row, cols, map)),
+            after: after.map(|row| self.image(row, cols, map)),
+        };
+        let mut changes = vec![];
+        let mut moved = None;
+        match code {
+            BinlogEvent::WriteEvent => changes.extend(rows.iter().map(|row| change("insert", None, Some(row)))),
+            BinlogEvent::DeleteEvent => changes.extend(rows.iter().map(|row| change("delete", Some(row), None))),
+            BinlogEvent::UpdateEvent => {
+                for pair in rows.chunks(2).filter(|pair| pair.len() == 2) {
+                    changes.push(change("update", Some(&pair[0]), Some(&pair[1])));
+                    //修改了跟踪的行的主键, 之后跟踪新的值
+                    let before = self.key_text(&pair[0], cols, &key_index);
+                    let after = self.key_text(&pair[1], cols, &key_index);
+                    let tracked = before.iter().zip(key_values.iter()).all(|(b, k)| b.as_deref().is_some_and(|b| same(b, k)));
+                    if tracked && before != after {
+                        moved = Some(after);
+                    }
+                }
+            }
+            _ => {}
+        }
+        if let Some(after) = moved {
+            self.key.values = resolved.iter().zip(after).map(|((col, _), value)| {
+                (Some(col.clone()), format!("'{}'", value.unwrap_or_default().replace('\'', "''")))
+            }).collect();
+            self.filter = None;
+        }
+        Ok(changes)
+    }
+
+    fn image(&self, row: &[Option<MySQLValue>], cols: &[HashMap<String, String>], map: &TableMap) -> Vec<(String, JsonValue)> {
+        let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+        row.iter().enumerate().map(|(idx, value)| {
+            let name = cols.get(idx).and_then(|c| c.get("COLUMN_NAME")).cloned().unwrap_or_else(|| format!("@{}", idx + 1));
+            let col_type = cols.get(idx).and_then(|c| c.get("COLUMN_TYPE")).map(|t| t.as_str()).unwrap_or("");
+            let value = self.format.json_value(value, col_type, self.format.binary_encoding(&db_tbl, &name));
+            (name, value)
+        }).collect()
+    }
+
+    //主键字段按rowfilter比较时使用的文本
+    fn key_text(&self, row: &[Option<MySQLValue>], cols: &[HashMap<String, String>], key_index: &[usize]) -> Vec<Option<String>> {
+        key_index.iter().map(|idx| {
+            let col_type = cols.get(*idx).and_then(|c| c.get("COLUMN_TYPE")).map(|t| t.as_str()).unwrap_or("");
+            self.format.text_value(row.get(*idx).unwrap_or(&None), col_type, BinaryEncoding::Hex)
+        }).collect()
+    }
+}
+
+/// 读取按顺序排列的binlog文件, start、stop为时间范围(unix时间戳, 0为不限制)
+pub fn history_files(files: &[String], key: &RowKey, format: &ValueFormat, range: (u32, u32),
+                     mut schema: impl FnMut(&TableMap) -> Option<Vec<HashMap<String, String>>>) -> std::io::Result<Vec<Change>> {
+    use std::io;
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let mut history = RowHistory::new(key.clone(), format.clone(), "");
+    let mut changes = vec![];
+    for path in files {
+        let name = std::path::Path::new(path).file_name().map(|t| t.to_string_lossy().to_string()).unwrap_or_else(|| path.clone());
+        history.set_file(&name);
+        for event in crate::reader::BinlogReader::open(path)? {
+            let event = event?;
+            if in_range(&event, range) == Some(Ordering::Greater) {
+                return Ok(changes);
+            }
+            let cols = event.table.as_ref().map(|map| columns(map, schema(map).as_ref())).unwrap_or_default();
+            let found = history.feed(&event, &cols).map_err(invalid)?;
+            if in_range(&event, range) == Some(Ordering::Equal) {
+                changes.extend(found);
+            }
+        }
+    }
+    Ok(changes)
+}
+
+//行事件的时间与范围比较: 早于开始时间为Less, 晚于结束时间为Greater, 其他事件为None
+fn in_range(event: &Event, (start, stop): (u32, u32)) -> Option<Ordering> {
+    if !matches!(event.data, Traction::RowEvent(..)) {
+        return None;
+    }
+    let timestamp = event.header.timestamp;
+    if stop > 0 && timestamp > stop {
+        Some(Ordering::Greater)
+    }else if timestamp < start {
+        Some(Ordering::Less)
+    }else {
+        Some(Ordering::Equal)
+    }
+}
+
+/// 连接mysql按主从协议从binlogfile(默认为SHOW BINARY LOGS中的第一个文件)开始读取, 到查询时最后一个文件的末尾结束
+#[cfg(feature = "network")]
+pub fn history_server(conf: &crate::Config, key: &RowKey, format: &ValueFormat, range: (u32, u32)) -> std::io::Result<Vec<Change>> {
+    use std::io;
+    use crate::io::command::try_execute;
+    use crate::stream::BinlogStream;
+
+    let mut conn = crate::create_conn(conf);
+    let binlogs: Vec<(String, u64)> = try_execute(&mut conn, "show binary logs;").map_err(io::Error::other)?.iter().map(|row| {
+        (row.get("Log_name").cloned().unwrap_or_default(), row.get("File_size").and_then(|t| t.parse().ok()).unwrap_or(0))
+    }).collect();
+    let (last_file, last_size) = match binlogs.last() {
+        Some(t) => t.clone(),
+        None => return Ok(vec![]),
+    };
+    let first = if conf.binlogfile.is_empty() { binlogs[0].0.clone() } else { conf.binlogfile.clone() };
+    let position = if conf.position.is_empty() { String::from("4") } else { conf.position.clone() };
+    let stream = BinlogStream::connect(&crate::Config{ gtid: String::new(), binlogfile: first.clone(), position, ..conf.clone() })?;
+
+    let mut table_cols_info = HashMap::new();
+    let mut history = RowHistory::new(key.clone(), format.clone(), &first);
+    let mut changes = vec![];
+    let mut file = first;
+    for event in stream {
+        let event = event?;
+        if let Traction::RotateLogEvent(t) = &event.data {
+            file = t.binlog_file.clone();
+        }
+        if in_range(&event, range) == Some(Ordering::Greater) {
+            break;
+        }
+        let cols = match &event.table {
+            Some(map) if map.database_name == key.database && map.table_name == key.table => {
+                crate::meta::get_col(conf, &map.database_name, &map.table_name, &mut table_cols_info);
+                columns(map, table_cols_info.get(&key.db_tbl()))
+            }
+            _ => vec![],
+        };
+        let found = history.feed(&event, &cols).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if in_range(&event, range) == Some(Ordering::Equal) {
+            changes.extend(found);
+        }
+        if file == last_file && event.log_pos + event.header.event_length as u64 >= last_size {
+            break;
+        }
+    }
+    Ok(changes)
+}
+
+/// history子命令: -f/--index指定文件时读取文件(同时指定-u时连接mysql查询表结构), 否则连接mysql拉取
+#[cfg(feature = "cli")]
+pub fn run(conf: &crate::Config) {
+    use std::process;
+    use crate::exitcode;
+    let exit = |err: String| -> ! {
+        println!("{}", err);
+        process::exit(exitcode::USAGE);
+    };
+    let key = RowKey::parse(&conf.historytable, &conf.historykey).unwrap_or_else(|e| exit(e));
+    let format = ValueFormat::new(conf).unwrap_or_else(|e| exit(e));
+    let time = |t: &str| if t.is_empty() { Ok(0) } else { crate::index::parse_time(t) };
+    let range = (time(&conf.startdatetime).unwrap_or_else(|e| exit(e)), time(&conf.stopdatetime).unwrap_or_else(|e| exit(e)));
+    let json = match conf.outformat.as_str() {
+        "" | "text" => false,
+        "json" => true,
+        t => exit(format!("无效的outformat: {}, history可选值为text、json", t)),
+    };
+
+    let result = if !conf.index.is_empty() || !conf.file.is_empty() {
+        let files = if conf.index.is_empty() {
+            Ok(conf.file.split(',').map(|t| t.to_string()).collect())
+        } else {
+            crate::locate::read_index(&conf.index)
+        };
+        let mut table_cols_info = HashMap::new();
+        files.and_then(|files| history_files(&files, &key, &format, range, |map| {
+            if conf.user_name.is_empty() {
+                return None;
+            }
+            crate::meta::get_col(conf, &map.database_name, &map.table_name, &mut table_cols_info);
+            table_cols_info.get(&format!("{}.{}", map.database_name, map.table_name)).cloned()
+        }))
+    } else {
+        history_server(conf, &key, &format, range)
+    };
+    let changes = result.unwrap_or_else(|err|{
+        println!("读取binlog发生错误: {}", err);
+        process::exit(exitcode::PARSE);
+    });
+    for (idx, change) in changes.iter().enumerate() {
+        if json {
+            println!("{}", change.to_json());
+        }else {
+            change.print(idx + 1);
+        }
+    }
+    if changes.is_empty() {
+        println!("没有找到{} ({})的变更", key.db_tbl(), conf.historykey);
+        process::exit(exitcode::NO_EVENTS);
+    }
+}
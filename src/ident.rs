@@ -0,0 +1,50 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//库名、表名等标识符
+//  binlog中TABLE_MAP的库名、表名及QUERY_EVENT的默认库以mysql的元数据字符集(character_set_system, 固定为utf8)记录,
+//  中文等非ascii字符为utf8编码; 不是合法utf8时(损坏或非常早期的版本)按latin1逐字节转换, 不丢弃整个名称
+//  过滤条件、配置中的表名可以带反引号或双引号(`订单库`.`明细`), 比较前统一转换为不带引号的db.tbl形式
+
+/// 解析binlog中记录的标识符
+pub fn decode(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(t) => t.to_string(),
+        Err(_) => bytes.iter().map(|b| *b as char).collect()
+    }
+}
+
+/// 按.拆分限定名, 反引号、双引号中的.不拆分, 去掉引号(两个连续的引号表示引号本身)及两端的空白
+/// `my.db`.`订单` -> ["my.db", "订单"]
+pub fn split_qualified(name: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut part = String::new();
+    let mut chars = name.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' | '"' => {
+                while let Some(t) = chars.next() {
+                    if t == c {
+                        if chars.peek() == Some(&c) {
+                            chars.next();
+                        }else {
+                            break;
+                        }
+                    }
+                    part.push(t);
+                }
+            }
+            '.' => parts.push(std::mem::take(&mut part).trim().to_string()),
+            _ => part.push(c)
+        }
+    }
+    parts.push(part.trim().to_string());
+    parts
+}
+
+/// 过滤条件、配置中的表名转换为与binlog中比较的形式: 不带引号, 以.连接
+pub fn normalize(name: &str) -> String {
+    split_qualified(name).join(".")
+}
@@ -0,0 +1,182 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/12
+*/
+
+//binlog文件的事务索引: 读取一次文件, 记录每个事务的起止position、时间、gtid、涉及的表及行数
+//  tui、inspect通过索引定位事务, 查看某个事务时按position重新读取, 不需要再次扫描整个文件
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use chrono::NaiveDateTime;
+use crate::reader::{BinlogReader, Event, Transaction};
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::BinlogEvent;
+use crate::replication::verify::ChecksumClass;
+use crate::stdout::format::{self, BinaryEncoding, ValueFormat};
+use crate::meta::ColumnTypeDict;
+
+/// 一个事务的汇总信息
+#[derive(Debug, Clone)]
+pub struct TrxSummary{
+    pub start: u64,                 //第一个事件的position
+    pub end: u64,                   //最后一个事件结束的position
+    pub timestamp: u32,
+    pub gtid: Option<String>,
+    pub tables: Vec<String>,        //db.tbl, 按出现顺序去重
+    pub rows: usize,
+    pub events: usize,
+    pub query: Option<String>,      //DDL等非BEGIN的语句
+}
+
+impl TrxSummary{
+    fn new(trx: &Transaction) -> TrxSummary {
+        let mut summary = TrxSummary{
+            start: trx.events.first().map(|e| e.log_pos).unwrap_or(0),
+            end: trx.events.last().map(|e| e.log_pos + e.header.event_length as u64).unwrap_or(0),
+            timestamp: trx.events.first().map(|e| e.header.timestamp).unwrap_or(0),
+            gtid: trx.gtid.as_ref().map(|t| format!("{}:{}", t.gtid, t.gno_id)),
+            tables: vec![],
+            rows: 0,
+            events: trx.events.len(),
+            query: None,
+        };
+        for event in &trx.events {
+            match &event.data {
+                Traction::RowEvent(code, rows) => {
+                    if let Some(map) = &event.table {
+                        let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+                        if !summary.tables.contains(&db_tbl) {
+                            summary.tables.push(db_tbl);
+                        }
+                    }
+                    summary.rows += match code {
+                        BinlogEvent::UpdateEvent => rows.rows().len() / 2,
+                        _ => rows.rows().len()
+                    };
+                }
+                Traction::QueryEvent(t) if t.command != "BEGIN" && t.command != "COMMIT" => {
+                    summary.query = Some(t.command.clone());
+                }
+                _ => {}
+            }
+        }
+        summary
+    }
+
+    /// 一行文本: position、时间、gtid、行数及表(DDL为语句)
+    pub fn line(&self) -> String {
+        let detail = match &self.query {
+            Some(t) => t.replace('\n', " "),
+            None => format!("{} rows  {}", self.rows, self.tables.join(",")),
+        };
+        format!("{:>10}  {}  {}  {}", self.start, format::utc_datetime(self.timestamp),
+                self.gtid.as_deref().unwrap_or("-"), detail)
+    }
+}
+
+pub struct PositionIndex{
+    pub path: String,
+    relaylog: bool,
+    classes: ChecksumClass,
+    pub transactions: Vec<TrxSummary>,
+    pub error: Option<String>,      //读取中途发生的错误, 之前的事务照常保留
+}
+
+impl PositionIndex{
+    pub fn build(path: &str, relaylog: bool, classes: ChecksumClass) -> io::Result<PositionIndex> {
+        let reader = BinlogReader::open(path)?.relaylog(relaylog).verify_checksum(classes);
+        let mut transactions = vec![];
+        let mut error = None;
+        for trx in reader.transactions() {
+            match trx {
+                Ok(t) if t.complete => transactions.push(TrxSummary::new(&t)),
+                Ok(_) => {}
+                Err(e) => {
+                    error = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+        Ok(PositionIndex{ path: path.to_string(), relaylog, classes, transactions, error })
+    }
+
+    /// 包含该position或在其之后的第一个事务
+    pub fn find_position(&self, position: u64) -> Option<usize> {
+        let idx = self.transactions.partition_point(|t| t.end <= position);
+        if idx < self.transactions.len() { Some(idx) } else { None }
+    }
+
+    /// 按position重新读取一个事务的全部事件
+    pub fn read_transaction(&self, trx: &TrxSummary) -> io::Result<Vec<Event>> {
+        let mut reader: BinlogReader<BufReader<File>> = BinlogReader::open(&self.path)?.relaylog(self.relaylog).verify_checksum(self.classes);
+        reader.seek(trx.start)?;
+        let mut events = vec![];
+        while let Some(event) = reader.read_event()? {
+            if event.log_pos >= trx.end {
+                break;
+            }
+            events.push(event);
+        }
+        Ok(events)
+    }
+}
+
+/// unix时间戳或utc时间(%Y-%m-%d %H:%M:%S, 也可以省略秒或时间部分)
+pub fn parse_time(time: &str) -> Result<u32, String> {
+    let time = time.trim();
+    if let Ok(t) = time.parse::<u32>() {
+        return Ok(t);
+    }
+    let full = match time.len() {
+        10 => format!("{} 00:00:00", time),
+        16 => format!("{}:00", time),
+        _ => time.to_string()
+    };
+    let t = NaiveDateTime::parse_from_str(&full, "%Y-%m-%d %H:%M:%S").map_err(|_| format!("无效的时间: {}", time))?;
+    Ok(t.and_utc().timestamp().max(0) as u32)
+}
+
+/// 事件及行数据的文本, 每个事件一行, 其后为缩进的内容
+pub fn event_lines(event: &Event, value_format: &ValueFormat) -> Vec<String> {
+    let mut lines = vec![format!("{:>10}  {:?}", event.log_pos, event.header.event_type)];
+    match &event.data {
+        Traction::GtidEvent(t) => lines.push(format!("            gtid: {}:{}", t.gtid, t.gno_id)),
+        Traction::QueryEvent(t) => lines.push(format!("            {}: {}", t.database, t.command)),
+        Traction::TableMapEvent(t) => lines.push(format!("            {}.{}", t.database_name, t.table_name)),
+        Traction::XidEvent(t) => lines.push(format!("            xid: {}", t.xid)),
+        Traction::RowsQueryEvent(t) => lines.push(format!("            {}", t.query)),
+        Traction::RowEvent(code, rows) => {
+            let col_types: Vec<&str> = match &event.table {
+                Some(map) => map.column_info.iter().map(|c| match c.column_type {
+                    ColumnTypeDict::MysqlTypeVarchar |
+                    ColumnTypeDict::MysqlTypeVarString |
+                    ColumnTypeDict::MysqlTypeString => "varchar",
+                    _ => "blob"
+                }).collect(),
+                None => vec![]
+            };
+            let row_text = |row: &[Option<_>]| -> String {
+                row.iter().enumerate().map(|(idx, value)| {
+                    let col_type = col_types.get(idx).copied().unwrap_or("blob");
+                    value_format.text_value(value, col_type, BinaryEncoding::Hex).unwrap_or_else(|| String::from("NULL"))
+                }).collect::<Vec<_>>().join(", ")
+            };
+            let rows = rows.rows();
+            match code {
+                BinlogEvent::UpdateEvent => {
+                    for pair in rows.chunks(2) {
+                        lines.push(format!("            before: ({})", row_text(&pair[0])));
+                        if let Some(after) = pair.get(1) {
+                            lines.push(format!("            after:  ({})", row_text(after)));
+                        }
+                    }
+                }
+                BinlogEvent::WriteEvent => lines.extend(rows.iter().map(|r| format!("            insert: ({})", row_text(r)))),
+                _ => lines.extend(rows.iter().map(|r| format!("            delete: ({})", row_text(r)))),
+            }
+        }
+        _ => {}
+    }
+    lines
+}
@@ -0,0 +1,333 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/12
+*/
+
+//inspect子命令: 打开binlog文件建立事务索引后, 交互式执行查询, 不需要每次重新扫描文件
+//  show transactions [where 条件 [and 条件...]] [limit n]
+//  count [transactions] [where ...]
+//  show trx <position>         显示包含该position的事务中的事件及行数据
+//  tables                      每个表的事务数及行数
+//  条件: table、database、ts、position、rows、gtid、type(ddl/dml), 比较符 = != > >= < <=
+//      例如: show transactions where table='orders' and ts > '2019-11-01 10:00'
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::process;
+use crate::{Config, exitcode};
+use crate::index::{self, PositionIndex, TrxSummary};
+use crate::replication::verify::ChecksumClass;
+use crate::stdout::format::ValueFormat;
+
+const HELP: &str = "\
+show transactions [where 条件 [and 条件...]] [limit n]
+count [transactions] [where 条件 [and 条件...]]
+show trx <position>
+tables
+help
+quit
+条件: table、database、ts、position、rows、gtid、type(ddl/dml), 比较符: = != > >= < <=
+    table不带库名时只比较表名, ts为unix时间戳或utc时间('2019-11-01 10:00:00'), position=n匹配包含该位置的事务
+    例如: show transactions where table='orders' and ts > '2019-11-01 10:00' limit 20";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token{
+    Word(String),
+    Str(String),
+    Op(String),
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some(t) if t == c => break,
+                    Some(t) => value.push(t),
+                    None => return Err(format!("引号不匹配: {}", line))
+                }
+            }
+            tokens.push(Token::Str(value));
+        } else if "=!<>".contains(c) {
+            let mut op = String::new();
+            while let Some(&t) = chars.peek() {
+                if !"=!<>".contains(t) {
+                    break;
+                }
+                op.push(t);
+                chars.next();
+            }
+            tokens.push(Token::Op(op));
+        } else {
+            let mut word = String::new();
+            while let Some(&t) = chars.peek() {
+                if t.is_whitespace() || "=!<>'\"".contains(t) {
+                    break;
+                }
+                word.push(t);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op{
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Op{
+    fn new(op: &str) -> Result<Op, String> {
+        match op {
+            "=" | "==" => Ok(Op::Eq),
+            "!=" | "<>" => Ok(Op::Ne),
+            ">" => Ok(Op::Gt),
+            ">=" => Ok(Op::Ge),
+            "<" => Ok(Op::Lt),
+            "<=" => Ok(Op::Le),
+            _ => Err(format!("无效的比较符: {}", op))
+        }
+    }
+
+    fn compare<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match self {
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+            Op::Gt => left > right,
+            Op::Ge => left >= right,
+            Op::Lt => left < right,
+            Op::Le => left <= right,
+        }
+    }
+}
+
+//where中的一个条件
+#[derive(Debug)]
+enum Cond{
+    Table(Op, String),
+    Database(Op, String),
+    Ts(Op, u32),
+    Position(Op, u64),
+    Rows(Op, usize),
+    Gtid(Op, String),
+    Ddl(Op, bool),
+}
+
+impl Cond{
+    fn new(field: &str, op: Op, value: &str) -> Result<Cond, String> {
+        let text_op = |op: Op| match op {
+            Op::Eq | Op::Ne => Ok(op),
+            _ => Err(format!("{}只支持=、!=", field))
+        };
+        match field.to_lowercase().as_str() {
+            "table" | "tbl" => Ok(Cond::Table(text_op(op)?, value.to_string())),
+            "database" | "db" => Ok(Cond::Database(text_op(op)?, value.to_string())),
+            "gtid" => Ok(Cond::Gtid(text_op(op)?, value.to_string())),
+            "ts" | "time" | "timestamp" => Ok(Cond::Ts(op, index::parse_time(value)?)),
+            "position" | "pos" => Ok(Cond::Position(op, value.parse().map_err(|_| format!("无效的position: {}", value))?)),
+            "rows" => Ok(Cond::Rows(op, value.parse().map_err(|_| format!("无效的rows: {}", value))?)),
+            "type" => match value.to_lowercase().as_str() {
+                "ddl" => Ok(Cond::Ddl(text_op(op)?, true)),
+                "dml" => Ok(Cond::Ddl(text_op(op)?, false)),
+                _ => Err(format!("无效的type: {}, 可选值为ddl、dml", value))
+            }
+            _ => Err(format!("无效的字段: {}, 可选值为table、database、ts、position、rows、gtid、type", field))
+        }
+    }
+
+    fn matches(&self, trx: &TrxSummary) -> bool {
+        //=时任意一个表满足即可, !=时所有表都不满足
+        let any_table = |op: &Op, f: &dyn Fn(&str) -> bool| match op {
+            Op::Ne => !trx.tables.iter().any(|t| f(t)),
+            _ => trx.tables.iter().any(|t| f(t)),
+        };
+        match self {
+            Cond::Table(op, name) => any_table(op, &|db_tbl: &str| {
+                if name.contains('.') {
+                    db_tbl == name
+                } else {
+                    db_tbl.split_once('.').map(|(_, tbl)| tbl) == Some(name.as_str())
+                }
+            }),
+            Cond::Database(op, name) => any_table(op, &|db_tbl: &str| db_tbl.split_once('.').map(|(db, _)| db) == Some(name.as_str())),
+            Cond::Gtid(op, gtid) => op.compare(trx.gtid.as_deref(), Some(gtid.as_str())),
+            Cond::Ts(op, ts) => op.compare(trx.timestamp, *ts),
+            Cond::Position(Op::Eq, pos) => trx.start <= *pos && *pos < trx.end,
+            Cond::Position(Op::Ne, pos) => !(trx.start <= *pos && *pos < trx.end),
+            Cond::Position(op, pos) => op.compare(trx.start, *pos),
+            Cond::Rows(op, rows) => op.compare(trx.rows, *rows),
+            Cond::Ddl(op, ddl) => op.compare(trx.query.is_some(), *ddl),
+        }
+    }
+}
+
+fn word_is(token: Option<&Token>, word: &str) -> bool {
+    matches!(token, Some(Token::Word(t)) if t.eq_ignore_ascii_case(word))
+}
+
+//解析where及limit, tokens为where开始的部分
+fn parse_filter(tokens: &[Token]) -> Result<(Vec<Cond>, Option<usize>), String> {
+    let mut conds = vec![];
+    let mut limit = None;
+    let mut idx = 0;
+    if word_is(tokens.first(), "where") {
+        idx = 1;
+        loop {
+            let (field, op, value) = match (tokens.get(idx), tokens.get(idx + 1), tokens.get(idx + 2)) {
+                (Some(Token::Word(f)), Some(Token::Op(o)), Some(Token::Word(v))) |
+                (Some(Token::Word(f)), Some(Token::Op(o)), Some(Token::Str(v))) => (f, o, v),
+                _ => return Err(String::from("条件格式错误, 例如: table='orders'"))
+            };
+            conds.push(Cond::new(field, Op::new(op)?, value)?);
+            idx += 3;
+            if word_is(tokens.get(idx), "and") {
+                idx += 1;
+            } else {
+                break;
+            }
+        }
+    }
+    if word_is(tokens.get(idx), "limit") {
+        limit = match tokens.get(idx + 1) {
+            Some(Token::Word(t)) => Some(t.parse().map_err(|_| format!("无效的limit: {}", t))?),
+            _ => return Err(String::from("limit需要指定数量"))
+        };
+        idx += 2;
+    }
+    if idx < tokens.len() {
+        return Err(format!("无法解析: {:?}", &tokens[idx]));
+    }
+    Ok((conds, limit))
+}
+
+/// 一个打开的binlog文件, 查询均通过事务索引完成
+pub struct Session{
+    index: PositionIndex,
+    value_format: ValueFormat,
+}
+
+impl Session{
+    pub fn open(path: &str, relaylog: bool, classes: ChecksumClass) -> io::Result<Session> {
+        Ok(Session{ index: PositionIndex::build(path, relaylog, classes)?, value_format: ValueFormat::default() })
+    }
+
+    pub fn index(&self) -> &PositionIndex {
+        &self.index
+    }
+
+    fn filter(&self, conds: &[Cond]) -> Vec<&TrxSummary> {
+        self.index.transactions.iter().filter(|t| conds.iter().all(|c| c.matches(t))).collect()
+    }
+
+    /// 执行一条命令, 返回输出的行
+    pub fn execute(&self, line: &str) -> Result<Vec<String>, String> {
+        let tokens = tokenize(line.trim().trim_end_matches(';'))?;
+        let first = match tokens.first() {
+            Some(Token::Word(t)) => t.to_lowercase(),
+            None => return Ok(vec![]),
+            Some(t) => return Err(format!("无法解析: {:?}", t))
+        };
+        match first.as_str() {
+            "help" => Ok(HELP.lines().map(String::from).collect()),
+            "tables" => Ok(self.tables()),
+            "count" => {
+                let rest = if word_is(tokens.get(1), "transactions") { &tokens[2..] } else { &tokens[1..] };
+                let (conds, _) = parse_filter(rest)?;
+                Ok(vec![self.filter(&conds).len().to_string()])
+            }
+            "show" if word_is(tokens.get(1), "transactions") => {
+                let (conds, limit) = parse_filter(&tokens[2..])?;
+                let matched = self.filter(&conds);
+                let shown = limit.unwrap_or(matched.len()).min(matched.len());
+                let mut lines: Vec<String> = matched[..shown].iter().map(|t| t.line()).collect();
+                lines.push(format!("({}/{} transactions)", shown, matched.len()));
+                Ok(lines)
+            }
+            "show" if word_is(tokens.get(1), "trx") => {
+                let position = match tokens.get(2) {
+                    Some(Token::Word(t)) if tokens.len() == 3 => t.parse::<u64>().map_err(|_| format!("无效的position: {}", t))?,
+                    _ => return Err(String::from("格式为: show trx <position>"))
+                };
+                let trx = match self.index.find_position(position) {
+                    Some(idx) if self.index.transactions[idx].start <= position => &self.index.transactions[idx],
+                    _ => return Err(format!("position {} 不在任何事务中", position))
+                };
+                let events = self.index.read_transaction(trx).map_err(|e| e.to_string())?;
+                let mut lines = vec![trx.line()];
+                lines.extend(events.iter().flat_map(|e| index::event_lines(e, &self.value_format)));
+                Ok(lines)
+            }
+            _ => Err(format!("无效的命令: {}, 输入help查看帮助", line.trim()))
+        }
+    }
+
+    fn tables(&self) -> Vec<String> {
+        let mut stats: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+        for trx in &self.index.transactions {
+            for table in &trx.tables {
+                stats.entry(table).or_default().0 += 1;
+            }
+            //行数只能按事务统计, 多表事务计入第一个表
+            if let Some(table) = trx.tables.first() {
+                stats.entry(table).or_default().1 += trx.rows;
+            }
+        }
+        stats.iter().map(|(table, (trx, rows))| format!("{}  transactions: {}  rows: {}", table, trx, rows)).collect()
+    }
+}
+
+pub fn run(conf: &Config) {
+    let classes = ChecksumClass::parse(&conf.verifychecksum).unwrap_or(ChecksumClass::empty());
+    let session = Session::open(&conf.file, conf.relaylog, classes).unwrap_or_else(|err|{
+        println!("读取文件({})发生错误:{}", conf.file, err);
+        process::exit(exitcode::USAGE);
+    });
+    if let Some(err) = &session.index().error {
+        println!("读取中止, 只包含之前的事务: {}", err);
+    }
+
+    //--execute只执行一条命令
+    if !conf.command.is_empty() {
+        match session.execute(&conf.command) {
+            Ok(lines) => lines.iter().for_each(|l| println!("{}", l)),
+            Err(e) => {
+                println!("{}", e);
+                process::exit(exitcode::USAGE);
+            }
+        }
+        return;
+    }
+
+    println!("{}: {} 个事务, 输入help查看帮助", conf.file, session.index().transactions.len());
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("inspect> ");
+        io::stdout().flush().ok();
+        let line = match lines.next() {
+            Some(Ok(t)) => t,
+            _ => break
+        };
+        let cmd = line.trim().trim_end_matches(';').to_lowercase();
+        if cmd == "quit" || cmd == "exit" {
+            break;
+        }
+        match session.execute(&line) {
+            Ok(out) => out.iter().for_each(|l| println!("{}", l)),
+            Err(e) => println!("{}", e)
+        }
+    }
+}
@@ -7,4 +7,8 @@ pub mod socketio;
 pub mod response;
 pub mod pack;
 pub mod scramble;
-pub mod command;
\ No newline at end of file
+pub mod auth;
+pub mod aws;
+pub mod proxy;
+pub mod command;pub mod compress;
+pub mod trace;
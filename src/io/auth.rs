@@ -0,0 +1,178 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/9/23
+*/
+
+//连接认证: 每种认证方式实现AuthPlugin, 连接过程(connection)只按服务端要求的插件名选择实现并转发数据
+//  mysql_native_password: SHA1(password) XOR SHA1(nonce, SHA1(SHA1(password)))
+//  caching_sha2_password: 先发送sha256的scramble, 服务端缓存中没有该用户时(AuthMoreData 0x04)
+//                         请求公钥, 用公钥加密(密码 XOR nonce)后发送
+//  sha256_password:       不使用ssl时先请求公钥, 同样用公钥加密后发送
+//  aws_iam:               RDS/Aurora的IAM认证, 每次连接时生成token(见aws模块), 通过mysql_clear_password明文发送,
+//                         RDS要求ssl, 需要通过stunnel等建立ssl隧道
+//  新的认证方式实现AuthPlugin后通过Plugins::register加入, 调用connection::create_mysql_conn_with
+
+use mysql_common::crypto::encrypt;
+use crate::Config;
+use crate::io::{aws, scramble};
+
+pub const NATIVE_PASSWORD: &str = "mysql_native_password";
+pub const CACHING_SHA2_PASSWORD: &str = "caching_sha2_password";
+pub const SHA256_PASSWORD: &str = "sha256_password";
+pub const CLEAR_PASSWORD: &str = "mysql_clear_password";
+
+/// 一种认证方式
+pub trait AuthPlugin: Send + Sync {
+    /// 插件名, 握手回包中发送, 服务端的AuthSwitchRequest按该名称选择
+    fn name(&self) -> &str;
+
+    /// 握手回包或切换认证方式后发送的认证数据, nonce为服务端发送的随机数
+    fn scramble(&self, nonce: &[u8], conf: &Config) -> Result<Vec<u8>, String>;
+
+    /// 服务端发送AuthMoreData(0x01开头, data不包含0x01)时的回应, None为不需要回应, 等待服务端的结果
+    fn more_data(&self, _data: &[u8], _nonce: &[u8], _conf: &Config) -> Result<Option<Vec<u8>>, String> {
+        Err(format!("{}不支持AuthMoreData", self.name()))
+    }
+}
+
+//服务端发送的随机数为20字节, 部分版本在结尾带0
+fn nonce20(nonce: &[u8]) -> &[u8] {
+    &nonce[..nonce.len().min(20)]
+}
+
+pub struct NativePassword;
+
+impl AuthPlugin for NativePassword {
+    fn name(&self) -> &str {
+        NATIVE_PASSWORD
+    }
+
+    //密码为空时不发送校验数据
+    fn scramble(&self, nonce: &[u8], conf: &Config) -> Result<Vec<u8>, String> {
+        Ok(scramble::scramble_native(nonce20(nonce), conf.password.as_bytes()).map(|t| t.to_vec()).unwrap_or_default())
+    }
+}
+
+//公钥加密: (密码 + 0) XOR nonce
+fn encrypt_password(key: &[u8], nonce: &[u8], conf: &Config) -> Vec<u8> {
+    let nonce = nonce20(nonce);
+    let mut password = conf.password.as_bytes().to_vec();
+    password.push(0);
+    for (i, b) in password.iter_mut().enumerate() {
+        *b ^= nonce[i % nonce.len()];
+    }
+    encrypt(&password, key)
+}
+
+fn is_public_key(data: &[u8]) -> bool {
+    data.starts_with(b"-----BEGIN")
+}
+
+pub struct CachingSha2Password;
+
+impl AuthPlugin for CachingSha2Password {
+    fn name(&self) -> &str {
+        CACHING_SHA2_PASSWORD
+    }
+
+    fn scramble(&self, nonce: &[u8], conf: &Config) -> Result<Vec<u8>, String> {
+        Ok(scramble::scramble_sha256(nonce20(nonce), conf.password.as_bytes()).map(|t| t.to_vec()).unwrap_or_default())
+    }
+
+    //0x03为缓存校验通过, 0x04为需要完整校验, 请求公钥(0x02)后服务端发送公钥
+    fn more_data(&self, data: &[u8], nonce: &[u8], conf: &Config) -> Result<Option<Vec<u8>>, String> {
+        match data {
+            [3] => Ok(None),
+            [4] => Ok(Some(vec![2])),
+            t if is_public_key(t) => Ok(Some(encrypt_password(t, nonce, conf))),
+            _ => Err(format!("{}返回的数据无法识别", CACHING_SHA2_PASSWORD)),
+        }
+    }
+}
+
+pub struct Sha256Password;
+
+impl AuthPlugin for Sha256Password {
+    fn name(&self) -> &str {
+        SHA256_PASSWORD
+    }
+
+    //密码为空时只发送0, 否则请求公钥(0x01)
+    fn scramble(&self, _nonce: &[u8], conf: &Config) -> Result<Vec<u8>, String> {
+        match conf.password.is_empty() {
+            true => Ok(vec![0]),
+            false => Ok(vec![1]),
+        }
+    }
+
+    fn more_data(&self, data: &[u8], nonce: &[u8], conf: &Config) -> Result<Option<Vec<u8>>, String> {
+        match is_public_key(data) {
+            true => Ok(Some(encrypt_password(data, nonce, conf))),
+            false => Err(format!("{}返回的数据无法识别", SHA256_PASSWORD)),
+        }
+    }
+}
+
+/// RDS/Aurora的IAM认证, token作为密码通过mysql_clear_password发送
+pub struct AwsIamToken;
+
+impl AuthPlugin for AwsIamToken {
+    fn name(&self) -> &str {
+        CLEAR_PASSWORD
+    }
+
+    fn scramble(&self, _nonce: &[u8], conf: &Config) -> Result<Vec<u8>, String> {
+        let mut data = aws::auth_token(conf)?.into_bytes();
+        data.push(0);
+        Ok(data)
+    }
+}
+
+/// 可用的认证方式, 默认使用服务端握手包中的插件
+pub struct Plugins{
+    plugins: Vec<Box<dyn AuthPlugin>>,
+    preferred: Option<String>,
+}
+
+impl Plugins {
+    /// mysql_native_password、caching_sha2_password、sha256_password
+    pub fn builtin() -> Plugins {
+        Plugins{ plugins: vec![Box::new(NativePassword), Box::new(CachingSha2Password), Box::new(Sha256Password)], preferred: None }
+    }
+
+    /// 按--auth-plugin选择首先使用的认证方式, aws_iam时加入mysql_clear_password
+    pub fn from_config(conf: &Config) -> Result<Plugins, String> {
+        let plugins = Plugins::builtin();
+        match conf.authplugin.as_str() {
+            "" => Ok(plugins),
+            "aws_iam" => Ok(plugins.register(Box::new(AwsIamToken)).prefer(CLEAR_PASSWORD)),
+            t if plugins.get(t).is_some() => Ok(plugins.prefer(t)),
+            t => Err(format!("无效的auth-plugin: {}, 可选值为{}、{}、{}、aws_iam", t, NATIVE_PASSWORD, CACHING_SHA2_PASSWORD, SHA256_PASSWORD)),
+        }
+    }
+
+    /// 加入新的认证方式, 同名时替换
+    pub fn register(mut self, plugin: Box<dyn AuthPlugin>) -> Plugins {
+        self.plugins.retain(|t| t.name() != plugin.name());
+        self.plugins.push(plugin);
+        self
+    }
+
+    /// 握手回包中使用该认证方式, 不使用服务端默认的插件
+    pub fn prefer(mut self, name: &str) -> Plugins {
+        self.preferred = Some(name.to_string());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn AuthPlugin> {
+        self.plugins.iter().find(|t| t.name() == name).map(|t| t.as_ref())
+    }
+
+    /// 握手回包使用的认证方式, 服务端的插件不支持时使用mysql_native_password, 等待服务端要求切换
+    pub fn initial(&self, server: &str) -> &dyn AuthPlugin {
+        self.preferred.as_deref().and_then(|t| self.get(t))
+            .or_else(|| self.get(server))
+            .or_else(|| self.get(NATIVE_PASSWORD))
+            .unwrap_or(&NativePassword)
+    }
+}
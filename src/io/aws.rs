@@ -0,0 +1,274 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/9/23
+*/
+
+//RDS/Aurora的IAM数据库认证(--auth-plugin aws_iam): 每次连接时按AWS签名v4生成token作为密码,
+//token有效期15分钟, 只用于建立连接, 断开重连、获取表结构等新的连接都重新生成, 不需要长期有效的密码
+//  访问密钥按以下顺序获取:
+//    环境变量AWS_ACCESS_KEY_ID、AWS_SECRET_ACCESS_KEY、AWS_SESSION_TOKEN
+//    共享凭证文件(AWS_SHARED_CREDENTIALS_FILE, 默认~/.aws/credentials)中--aws-profile(默认AWS_PROFILE或default)组
+//    ECS任务角色: AWS_CONTAINER_CREDENTIALS_RELATIVE_URI或AWS_CONTAINER_CREDENTIALS_FULL_URI
+//    EC2实例角色: IMDSv2(AWS_EC2_METADATA_SERVICE_ENDPOINT, 默认http://169.254.169.254), AWS_EC2_METADATA_DISABLED=true时不使用
+//  ECS、EC2的临时密钥缓存到过期前5分钟, 之后重新获取
+//  region: --aws-region > AWS_REGION、AWS_DEFAULT_REGION > RDS地址(xxx.<region>.rds.amazonaws.com)中的region
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use sha2::{Digest, Sha256};
+use chrono::{DateTime, Utc};
+use crate::Config;
+use crate::secret::Secret;
+
+//临时密钥在过期前该时间内重新获取
+const REFRESH_BEFORE: i64 = 300;
+
+static CACHE: Mutex<Option<AwsCredentials>> = Mutex::new(None);
+
+/// AWS的访问密钥
+#[derive(Debug, Clone)]
+pub struct AwsCredentials{
+    pub access_key_id: String,
+    pub secret_access_key: Secret,
+    pub session_token: Option<String>,
+    /// 临时密钥的过期时间
+    pub expiration: Option<DateTime<Utc>>,
+}
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|t| !t.is_empty())
+}
+
+impl AwsCredentials {
+    pub fn new(access_key_id: &str, secret_access_key: &str) -> AwsCredentials {
+        AwsCredentials{ access_key_id: access_key_id.to_string(), secret_access_key: secret_access_key.to_string().into(), session_token: None, expiration: None }
+    }
+
+    pub fn from_env() -> Option<AwsCredentials> {
+        match (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) {
+            (Some(id), Some(secret)) => Some(AwsCredentials{ session_token: var("AWS_SESSION_TOKEN"), ..AwsCredentials::new(&id, &secret) }),
+            _ => None,
+        }
+    }
+
+    /// 读取共享凭证文件中的profile组, 文件或组不存在时返回None
+    pub fn from_profile(path: &Path, profile: &str) -> Option<AwsCredentials> {
+        let content = fs::read_to_string(path).ok()?;
+        let (mut current, mut id, mut secret, mut token) = (String::new(), None, None, None);
+        for line in content.lines().map(|t| t.trim()) {
+            if line.starts_with('[') && line.ends_with(']') {
+                current = line[1..line.len() - 1].trim().to_string();
+                continue;
+            }
+            if current != profile {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim().to_string();
+                match key.trim() {
+                    "aws_access_key_id" => id = Some(value),
+                    "aws_secret_access_key" => secret = Some(value),
+                    "aws_session_token" => token = Some(value),
+                    _ => {}
+                }
+            }
+        }
+        Some(AwsCredentials{ session_token: token, ..AwsCredentials::new(&id?, &secret?) })
+    }
+
+    /// ECS任务角色, 没有设置AWS_CONTAINER_CREDENTIALS_*时返回None
+    pub fn from_container() -> Option<Result<AwsCredentials, String>> {
+        let url = match (var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI"), var("AWS_CONTAINER_CREDENTIALS_FULL_URI")) {
+            (Some(uri), _) => format!("http://169.254.170.2{}", uri),
+            (None, Some(url)) => url,
+            (None, None) => return None,
+        };
+        let headers: Vec<(&str, String)> = var("AWS_CONTAINER_AUTHORIZATION_TOKEN").map(|t| vec![("Authorization", t)]).unwrap_or_default();
+        Some(http_get(&url, &headers).and_then(|body| parse_metadata(&body)))
+    }
+
+    /// EC2实例角色(IMDSv2)
+    pub fn from_instance() -> Result<AwsCredentials, String> {
+        if var("AWS_EC2_METADATA_DISABLED").is_some_and(|t| t.eq_ignore_ascii_case("true")) {
+            return Err(String::from("AWS_EC2_METADATA_DISABLED"));
+        }
+        let base = var("AWS_EC2_METADATA_SERVICE_ENDPOINT").unwrap_or(String::from("http://169.254.169.254"));
+        let base = base.trim_end_matches('/');
+        let token = ureq::put(&format!("{}/latest/api/token", base)).timeout(Duration::from_secs(2))
+            .set("X-aws-ec2-metadata-token-ttl-seconds", "21600")
+            .call().map_err(|e| format!("{}: {}", base, e))?
+            .into_string().map_err(|e| e.to_string())?;
+        let headers = [("X-aws-ec2-metadata-token", token)];
+        let url = format!("{}/latest/meta-data/iam/security-credentials/", base);
+        let roles = http_get(&url, &headers)?;
+        let role = roles.lines().map(|t| t.trim()).find(|t| !t.is_empty()).ok_or_else(|| String::from("实例没有关联IAM角色"))?;
+        parse_metadata(&http_get(&format!("{}{}", url, role), &headers)?)
+    }
+
+    /// 按顺序查找访问密钥, profile为空时使用AWS_PROFILE或default
+    pub fn resolve(profile: &str) -> Result<AwsCredentials, String> {
+        if let Some(credentials) = AwsCredentials::from_env() {
+            return Ok(credentials);
+        }
+        let profile = match profile {
+            "" => var("AWS_PROFILE").unwrap_or(String::from("default")),
+            t => t.to_string(),
+        };
+        let path = credentials_file();
+        if let Some(credentials) = path.as_deref().and_then(|p| AwsCredentials::from_profile(p, &profile)) {
+            return Ok(credentials);
+        }
+        let mut cache = CACHE.lock().unwrap();
+        if let Some(credentials) = cache.as_ref().filter(|t| !t.expiring(Utc::now())) {
+            return Ok(credentials.clone());
+        }
+        let credentials = match AwsCredentials::from_container() {
+            Some(t) => t?,
+            None => AwsCredentials::from_instance().map_err(|e| {
+                format!("没有找到aws访问密钥(环境变量、{}中的[{}]、ECS任务角色、EC2实例角色): {}",
+                        path.map(|p| p.display().to_string()).unwrap_or_default(), profile, e)
+            })?,
+        };
+        *cache = Some(credentials.clone());
+        Ok(credentials)
+    }
+
+    /// 临时密钥即将过期
+    pub fn expiring(&self, now: DateTime<Utc>) -> bool {
+        self.expiration.is_some_and(|t| (t - now).num_seconds() < REFRESH_BEFORE)
+    }
+}
+
+fn credentials_file() -> Option<PathBuf> {
+    match var("AWS_SHARED_CREDENTIALS_FILE") {
+        Some(t) => Some(PathBuf::from(t)),
+        None => var("HOME").map(|home| Path::new(&home).join(".aws").join("credentials")),
+    }
+}
+
+fn http_get(url: &str, headers: &[(&str, String)]) -> Result<String, String> {
+    let mut request = ureq::get(url).timeout(Duration::from_secs(2));
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    match request.call() {
+        Ok(t) => t.into_string().map_err(|e| e.to_string()),
+        Err(ureq::Error::Status(code, t)) => Err(format!("{}: {} {}", url, code, t.into_string().unwrap_or_default())),
+        Err(e) => Err(format!("{}: {}", url, e)),
+    }
+}
+
+//ECS、EC2元数据返回的临时密钥: {"AccessKeyId": "", "SecretAccessKey": "", "Token": "", "Expiration": "2019-11-01T08:00:00Z"}
+fn parse_metadata(body: &str) -> Result<AwsCredentials, String> {
+    let value: serde_json::Value = serde_json::from_str(body).map_err(|e| format!("临时密钥格式错误: {}", e))?;
+    let field = |name: &str| value[name].as_str().filter(|t| !t.is_empty()).map(|t| t.to_string());
+    match (field("AccessKeyId"), field("SecretAccessKey")) {
+        (Some(id), Some(secret)) => Ok(AwsCredentials{
+            session_token: field("Token"),
+            expiration: field("Expiration").and_then(|t| DateTime::parse_from_rfc3339(&t).ok()).map(|t| t.with_timezone(&Utc)),
+            ..AwsCredentials::new(&id, &secret)
+        }),
+        _ => Err(String::from("临时密钥中没有AccessKeyId、SecretAccessKey")),
+    }
+}
+
+/// 签名使用的RDS地址(host:port), 默认为连接地址, 通过ssl隧道连接时由--aws-endpoint指定
+pub fn endpoint(conf: &Config) -> String {
+    match conf.awsendpoint.as_str() {
+        "" => conf.host_info.clone(),
+        t => crate::endpoint::host_port(t, 3306),
+    }
+}
+
+/// 签名使用的region
+pub fn region(conf: &Config, endpoint: &str) -> Result<String, String> {
+    if !conf.awsregion.is_empty() {
+        return Ok(conf.awsregion.clone());
+    }
+    if let Some(region) = var("AWS_REGION").or_else(|| var("AWS_DEFAULT_REGION")) {
+        return Ok(region);
+    }
+    let host = endpoint.rsplit_once(':').map(|(host, _)| host).unwrap_or(endpoint);
+    let labels: Vec<&str> = host.split('.').collect();
+    match labels.windows(3).find(|t| t[1] == "rds" && t[2] == "amazonaws") {
+        Some(t) => Ok(t[0].to_string()),
+        None => Err(String::from("aws_iam认证需要通过--aws-region或AWS_REGION环境变量指定region")),
+    }
+}
+
+/// 连接时使用的token
+pub fn auth_token(conf: &Config) -> Result<String, String> {
+    let endpoint = endpoint(conf);
+    let region = region(conf, &endpoint)?;
+    let credentials = AwsCredentials::resolve(&conf.awsprofile)?;
+    Ok(rds_auth_token(&endpoint, &conf.user_name, &region, &credentials, Utc::now()))
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::default();
+    hasher.input(data);
+    hex::encode(hasher.result())
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        let mut hasher = Sha256::default();
+        hasher.input(key);
+        block[..32].copy_from_slice(&hasher.result());
+    }else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::default();
+    inner.input(block.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+    inner.input(data);
+    let mut outer = Sha256::default();
+    outer.input(block.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+    outer.input(inner.result());
+    outer.result().to_vec()
+}
+
+//uri编码, 只保留A-Za-z0-9-_.~
+fn uri_encode(value: &str) -> String {
+    let mut out = String::new();
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            _ => { let _ = write!(out, "%{:02X}", b); }
+        }
+    }
+    out
+}
+
+/// 生成RDS的IAM认证token(AWS签名v4的预签名url, 去掉https://), 有效期15分钟, endpoint为host:port
+pub fn rds_auth_token(endpoint: &str, user: &str, region: &str, credentials: &AwsCredentials, now: DateTime<Utc>) -> String {
+    let date = now.format("%Y%m%d").to_string();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let scope = format!("{}/{}/rds-db/aws4_request", date, region);
+    let mut params = vec![
+        ("Action", String::from("connect")),
+        ("DBUser", user.to_string()),
+        ("X-Amz-Algorithm", String::from("AWS4-HMAC-SHA256")),
+        ("X-Amz-Credential", format!("{}/{}", credentials.access_key_id, scope)),
+        ("X-Amz-Date", amz_date.clone()),
+        ("X-Amz-Expires", String::from("900")),
+    ];
+    if let Some(token) = &credentials.session_token {
+        params.push(("X-Amz-Security-Token", token.clone()));
+    }
+    params.push(("X-Amz-SignedHeaders", String::from("host")));
+    let query = params.iter().map(|(k, v)| format!("{}={}", k, uri_encode(v))).collect::<Vec<String>>().join("&");
+
+    let canonical = format!("GET\n/\n{}\nhost:{}\n\nhost\n{}", query, endpoint, sha256_hex(b""));
+    let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, sha256_hex(canonical.as_bytes()));
+    let mut key = hmac_sha256(format!("AWS4{}", credentials.secret_access_key.expose()).as_bytes(), date.as_bytes());
+    for part in [region, "rds-db", "aws4_request"] {
+        key = hmac_sha256(&key, part.as_bytes());
+    }
+    let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+    format!("{}/?{}&X-Amz-Signature={}", endpoint, query, signature)
+}
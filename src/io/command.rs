@@ -10,6 +10,7 @@ use crate::io::pack;
 use std::process;
 use crate::readvalue;
 use std::collections::HashMap;
+use crate::exitcode;
 
 trait ColInit {
     fn new(buf: &Vec<u8>) -> Self;
@@ -35,32 +36,32 @@ impl ColInit for MetaColumn{
         let mut offset: usize = 0;
         let mut var_size = buf[0] as usize ; //字段信息所占长度
         offset += 1;
-        let catalog = readvalue::read_string_value(&buf[offset..offset+var_size]);
+        let catalog = crate::ident::decode(&buf[offset..offset+var_size]);
         offset += var_size;
 
         var_size = buf[offset] as usize;
         offset += 1;
-        let schema = readvalue::read_string_value(&buf[offset..offset+var_size]);
+        let schema = crate::ident::decode(&buf[offset..offset+var_size]);
         offset += var_size;
 
         var_size = buf[offset] as usize;
         offset += 1;
-        let table = readvalue::read_string_value(&buf[offset..offset+var_size]);
+        let table = crate::ident::decode(&buf[offset..offset+var_size]);
         offset += var_size;
 
         var_size = buf[offset] as usize;
         offset += 1;
-        let org_table = readvalue::read_string_value(&buf[offset..offset+var_size]);
+        let org_table = crate::ident::decode(&buf[offset..offset+var_size]);
         offset += var_size;
 
         var_size = buf[offset] as usize;
         offset += 1;
-        let name = readvalue::read_string_value(&buf[offset..offset+var_size]);
+        let name = crate::ident::decode(&buf[offset..offset+var_size]);
         offset += var_size;
 
         var_size = buf[offset] as usize;
         offset += 1;
-        let org_name = readvalue::read_string_value(&buf[offset..offset+var_size]);
+        let org_name = crate::ident::decode(&buf[offset..offset+var_size]);
         offset += var_size;
 
         offset += 1;
@@ -92,26 +93,47 @@ impl ColInit for MetaColumn{
 }
 
 
-pub fn execute(conn: &mut TcpStream,sql: &String) -> Vec<HashMap<String,String>>{
-    let pack = commquery(sql);
-    socketio::write_value(conn,&pack).unwrap_or_else(|err|{
-        println!("{}",err);
-        process::exit(1);
-    });
-
-    let a = unpack_text_packet(conn).unwrap_or_else(|err|{
+pub fn execute(conn: &mut TcpStream,sql: &str) -> Vec<HashMap<String,String>>{
+    let a = try_execute(conn, sql).unwrap_or_else(|err|{
         println!("{}",err);
-        process::exit(1);
+        process::exit(exitcode::SERVER);
     });
 
     return a;
 }
 
+//语句执行错误时返回错误信息, 不退出程序
+pub fn try_execute(conn: &mut TcpStream,sql: &str) -> Result<Vec<HashMap<String,String>>, String>{
+    let pack = commquery(sql);
+    socketio::write_value(conn,&pack).map_err(|err| err.to_string())?;
+    unpack_text_packet(conn)
+}
+
+//执行不返回结果集的语句, 返回影响的行数(连接时设置了CLIENT_FOUND_ROWS, update为匹配的行数), 错误时返回错误信息, 不退出程序
+//错误信息中包含sqlstate, 如#23000为违反唯一键等约束
+pub fn try_execute_update(conn: &mut TcpStream,sql: &str) -> Result<u64, String>{
+    let pack = commquery(sql);
+    socketio::write_value(conn,&pack).map_err(|err| err.to_string())?;
+    let (buf,_) = socketio::get_packet_from_stream(conn);
+    if pack::check_pack(&buf){
+        //ok包: 0x00 + affected_rows(lenenc)
+        Ok(match buf.get(1) {
+            Some(0xfc) => readvalue::read_u16(&buf[2..]) as u64,
+            Some(0xfd) => readvalue::read_u24(&buf[2..]) as u64,
+            Some(0xfe) => readvalue::read_u64(&buf[2..]),
+            Some(t) => *t as u64,
+            None => 0,
+        })
+    }else {
+        Err(format!("执行语句错误: {}",readvalue::read_string_value(&buf[3..])))
+    }
+}
+
 pub fn execute_update(conn: &mut TcpStream,sql: &String){
     let pack = commquery(sql);
     socketio::write_value(conn,&pack).unwrap_or_else(|err|{
         println!("{}",err);
-        process::exit(1);
+        process::exit(exitcode::SERVER);
     });
 
     let (buf,_) = socketio::get_packet_from_stream(conn);
@@ -120,24 +142,24 @@ pub fn execute_update(conn: &mut TcpStream,sql: &String){
     }else {
         let _err = readvalue::read_string_value(&buf[3..]);
         println!("执行语句错误: {}",_err);
-        process::exit(1);
+        process::exit(exitcode::SERVER);
     }
 
 }
 
 //组装COM_Query包
-fn commquery(sql: &String) -> Vec<u8>{
+fn commquery(sql: &str) -> Vec<u8>{
     let mut pack = vec![];
     let mut payload = vec![];
     payload.push(3); //0x03: COM_QUERY
-    payload.extend(sql.clone().into_bytes());
+    payload.extend(sql.as_bytes());
     let header = response::pack_header(&payload,0);
     pack.extend(header);
     pack.extend(payload);
     return pack;
 }
 
-fn unpack_text_packet(conn: &mut TcpStream) -> Result<Vec<HashMap<String,String>>,&'static str> {
+fn unpack_text_packet(conn: &mut TcpStream) -> Result<Vec<HashMap<String,String>>,String> {
     let (buf,_) = socketio::get_packet_from_stream(conn);
 
     if pack::check_pack(&buf){
@@ -150,10 +172,13 @@ fn unpack_text_packet(conn: &mut TcpStream) -> Result<Vec<HashMap<String,String>
             let column = MetaColumn::new(&buf);
             column_info.push(column);
         }
+        //没有协商CLIENT_DEPRECATE_EOF时字段信息之后为EOF包(0xFE, 5字节), 协商时直接为数据行,
+        //空结果集的结束包为0xFE开头的ok包(至少7字节)
+        let mut first = Some(socketio::get_packet_from_stream(conn)).filter(|(buf,_)| !(buf.len() == 5 && buf[0] == 0xfe));
 
         //开始获取返回数据
         loop {
-            let (mut buf,header) = socketio::get_packet_from_stream(conn);
+            let (mut buf,header) = first.take().unwrap_or_else(|| socketio::get_packet_from_stream(conn));
             while header.payload == 0xffffff{
                 println!("{}",header.payload);
                 let (buf_tmp,_) = socketio::get_packet_from_stream(conn);
@@ -170,8 +195,7 @@ fn unpack_text_packet(conn: &mut TcpStream) -> Result<Vec<HashMap<String,String>
         Ok(values_info)
     }else {
         let _err = readvalue::read_string_value(&buf[3..]);
-        println!("执行语句错误: {}",_err);
-        Err("退出程序")
+        Err(format!("执行语句错误: {}",_err))
     }
 }
 
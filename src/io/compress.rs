@@ -0,0 +1,224 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//压缩协议: 握手时设置CLIENT_COMPRESS, 认证完成之后该连接上的数据都按压缩包收发, 用于跨机房拉取binlog时节省带宽
+//  压缩包头7字节: 压缩后长度(3) + 压缩包序号(1) + 压缩前长度(3), 压缩前长度为0时内容未压缩
+//  压缩包的内容为一个或多个完整的mysql包(包括包头), 读取时解压到缓存中, 再按mysql包读取
+//  连接的类型仍然为TcpStream, 按本地地址登记是否压缩, 未登记的连接按原来的方式读写
+//  mysql 8.0.18的zstd(CLIENT_ZSTD_COMPRESSION_ALGORITHM)需要zstd解码, 没有包含该依赖, 只支持zlib
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use serde_json::{json, Value as JsonValue};
+
+/// 压缩算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zlib,
+}
+
+impl Compression {
+    /// 解析--compress的值, 与mysql客户端的compression-algorithms相同可以为逗号分隔的列表, 使用第一个支持的算法
+    /// 空、off、uncompressed为不压缩
+    pub fn parse(value: &str) -> Result<Option<Compression>, String> {
+        let mut unsupported = vec![];
+        for name in value.split(',').map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty()) {
+            match name.as_str() {
+                "zlib" | "on" => {
+                    if cfg!(feature = "compression") {
+                        return Ok(Some(Compression::Zlib));
+                    }
+                    unsupported.push(String::from("zlib(需要开启compression特性)"));
+                }
+                "off" | "uncompressed" => return Ok(None),
+                "zstd" => unsupported.push(String::from("zstd")),
+                _ => return Err(format!("无效的压缩算法: {}, 可选值为zlib、off", name)),
+            }
+        }
+        if unsupported.is_empty() {
+            Ok(None)
+        } else {
+            Err(format!("不支持的压缩算法: {}", unsupported.join(", ")))
+        }
+    }
+}
+
+/// 压缩协议的流量统计, 所有压缩连接累计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats{
+    /// 压缩连接上实际收发的字节数(包括压缩包头)
+    pub wire_bytes: u64,
+    /// 解压后的字节数
+    pub bytes: u64,
+}
+
+impl CompressionStats {
+    /// 压缩比, 解压后的字节数/实际传输的字节数
+    pub fn ratio(&self) -> f64 {
+        if self.wire_bytes == 0 {
+            return 1.0;
+        }
+        self.bytes as f64 / self.wire_bytes as f64
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "wire_bytes": self.wire_bytes,
+            "bytes": self.bytes,
+            "ratio": (self.ratio() * 100.0).round() / 100.0,
+        })
+    }
+}
+
+//压缩连接的解压缓存, 读写期间从表中取出, 避免等待网络时占用锁
+#[derive(Default)]
+struct State{
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+static CONNS: Mutex<Option<HashMap<SocketAddr, State>>> = Mutex::new(None);
+//没有压缩连接时不需要查表
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+static WIRE_BYTES: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// 当前的流量统计
+pub fn stats() -> CompressionStats {
+    CompressionStats{ wire_bytes: WIRE_BYTES.load(Ordering::Relaxed), bytes: BYTES.load(Ordering::Relaxed) }
+}
+
+/// 设置连接之后的读写是否压缩, 握手协商成功之后调用; 新建连接时以None清除相同本地地址的旧连接留下的状态
+pub fn enable(stream: &TcpStream, compression: Option<Compression>) {
+    let addr = match stream.local_addr() {
+        Ok(t) => t,
+        Err(_) => return,
+    };
+    let mut conns = CONNS.lock().unwrap();
+    let conns = conns.get_or_insert_with(HashMap::new);
+    let existed = match compression {
+        Some(_) => conns.insert(addr, State::default()).is_some(),
+        None => conns.remove(&addr).is_some(),
+    };
+    match (compression.is_some(), existed) {
+        (true, false) => { ACTIVE.fetch_add(1, Ordering::Relaxed); }
+        (false, true) => { ACTIVE.fetch_sub(1, Ordering::Relaxed); }
+        _ => {}
+    }
+}
+
+//压缩连接时取出状态执行f, 未压缩的连接返回None
+fn with_state<T, F: FnOnce(&mut State) -> io::Result<T>>(stream: &TcpStream, f: F) -> Option<io::Result<T>> {
+    if ACTIVE.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    let addr = stream.local_addr().ok()?;
+    let mut state = CONNS.lock().unwrap().as_mut()?.remove(&addr)?;
+    let result = f(&mut state);
+    if let Some(conns) = CONNS.lock().unwrap().as_mut() {
+        conns.insert(addr, state);
+    }
+    Some(result)
+}
+
+/// 压缩连接从解压缓存中读取, 不够时读取下一个压缩包
+pub(crate) fn read_exact(stream: &TcpStream, buf: &mut [u8]) -> Option<io::Result<()>> {
+    with_state(stream, |state| {
+        while state.buf.len() - state.pos < buf.len() {
+            let data = read_frame(stream)?;
+            state.buf.drain(..state.pos);
+            state.pos = 0;
+            state.buf.extend(data);
+        }
+        buf.copy_from_slice(&state.buf[state.pos..state.pos + buf.len()]);
+        state.pos += buf.len();
+        if state.pos == state.buf.len() {
+            state.buf.clear();
+            state.pos = 0;
+        }
+        Ok(())
+    })
+}
+
+/// 压缩连接将数据作为新命令的压缩包写入, 压缩包序号从0开始
+pub(crate) fn write_all(stream: &TcpStream, buf: &[u8]) -> Option<io::Result<()>> {
+    with_state(stream, |_| {
+        use std::io::Write;
+        let mut frames = vec![];
+        for (seq, chunk) in buf.chunks(0xffffff).enumerate() {
+            frames.extend(frame(chunk, seq as u8)?);
+        }
+        let mut writer: &TcpStream = stream;
+        writer.write_all(&frames)
+    })
+}
+
+fn read_frame(mut stream: &TcpStream) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+    let mut header = [0u8; 7];
+    stream.read_exact(&mut header)?;
+    let length = crate::readvalue::read_u24(&header[..3]) as usize;
+    let raw = crate::readvalue::read_u24(&header[4..]) as usize;
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload)?;
+    WIRE_BYTES.fetch_add(7 + length as u64, Ordering::Relaxed);
+    let data = if raw == 0 { payload } else { inflate(&payload, raw)? };
+    BYTES.fetch_add(data.len() as u64, Ordering::Relaxed);
+    Ok(data)
+}
+
+//小于该长度的包不压缩, 与mysql客户端相同
+const MIN_COMPRESS_LENGTH: usize = 50;
+
+fn frame(data: &[u8], seq: u8) -> io::Result<Vec<u8>> {
+    let (payload, raw) = if data.len() < MIN_COMPRESS_LENGTH {
+        (data.to_vec(), 0)
+    } else {
+        match deflate(data)? {
+            t if t.len() < data.len() => (t, data.len()),
+            _ => (data.to_vec(), 0),
+        }
+    };
+    let mut frame = crate::readvalue::write_u24(payload.len() as u32);
+    frame.push(seq);
+    frame.extend(crate::readvalue::write_u24(raw as u32));
+    frame.extend(payload);
+    WIRE_BYTES.fetch_add(frame.len() as u64, Ordering::Relaxed);
+    BYTES.fetch_add(data.len() as u64, Ordering::Relaxed);
+    Ok(frame)
+}
+
+#[cfg(feature = "compression")]
+fn inflate(payload: &[u8], raw: usize) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+    let mut data = Vec::with_capacity(raw);
+    flate2::read::ZlibDecoder::new(payload).read_to_end(&mut data)?;
+    if data.len() != raw {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("压缩包解压后的长度为{}, 包头中为{}", data.len(), raw)));
+    }
+    Ok(data)
+}
+
+#[cfg(feature = "compression")]
+fn deflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    use std::io::Write;
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+//没有开启compression特性时Compression::parse不会返回zlib, 不会有压缩连接
+#[cfg(not(feature = "compression"))]
+fn inflate(_payload: &[u8], _raw: usize) -> io::Result<Vec<u8>> {
+    Err(io::Error::other("没有开启compression特性"))
+}
+
+#[cfg(not(feature = "compression"))]
+fn deflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    Ok(data.to_vec())
+}
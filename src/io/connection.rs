@@ -10,9 +10,15 @@ use crate::meta;
 use crate::io::socketio;
 use crate::io::pack;
 use crate::io::response;
+use crate::io::compress;
+use crate::io::command;
+use crate::io::proxy;
+use crate::io::trace;
+use crate::io::auth::{AuthPlugin, Plugins};
 use std::time::Duration;
 use std::error::Error;
 use std::borrow::Borrow;
+use crate::exitcode;
 
 fn conn(host_info: &str) -> Result<TcpStream, Box<dyn Error>> {
     let tcp_conn = TcpStream::connect(host_info)?;
@@ -22,69 +28,130 @@ fn conn(host_info: &str) -> Result<TcpStream, Box<dyn Error>> {
 }
 
 pub fn create_mysql_conn(conf: &Config) -> Result<TcpStream, &'static str>{
+    let plugins = Plugins::from_config(conf).unwrap_or_else(|err|{
+        println!("{}",err);
+        process::exit(exitcode::USAGE);
+    });
+    create_mysql_conn_with(conf, &plugins)
+}
+
+/// 使用指定的认证方式连接, 用于加入新的认证方式(见auth模块)
+pub fn create_mysql_conn_with(conf: &Config, plugins: &Plugins) -> Result<TcpStream, &'static str>{
+    trace::open(conf).unwrap_or_else(|err|{
+        println!("{}",err);
+        process::exit(exitcode::USAGE);
+    });
     //这里是与mysql建立连接的整个过程
     let mut mysql_conn = conn(&conf.host_info).unwrap_or_else(|err|{
         println!("{}",err);
-        process::exit(1);
+        process::exit(exitcode::CONNECT);
+    });
+    //本地地址可能与已关闭的压缩连接相同
+    compress::enable(&mysql_conn, None);
+    //代理要求的PROXY头在mysql发送握手包之前发送
+    let proxy = proxy::ProxyVersion::parse(&conf.proxyprotocol).unwrap_or_else(|err|{
+        println!("{}",err);
+        process::exit(exitcode::USAGE);
     });
+    if let Some(version) = proxy {
+        proxy::send_header(&mut mysql_conn, version).unwrap_or_else(|err|{
+            println!("发送PROXY头发生错误:{}",err);
+            process::exit(exitcode::CONNECT);
+        });
+    }
 
+    trace::connect(&mysql_conn, &conf.host_info);
     let (packet_buf,_) = socketio::get_packet_from_stream(&mut mysql_conn);
     let handshake = pack::HandshakePacket::new(&packet_buf).unwrap_or_else(|err|{
         println!("{}",err);
-        process::exit(1);
+        process::exit(exitcode::CONNECT);
     });
 
     //根据服务端发送的hand_shake包组回报并发送
-    let handshake_response = response::LocalInfo::new(conf.program_name.borrow(), conf.database.len() as u8);
+    let mut handshake_response = response::LocalInfo::new(conf.program_name.borrow(), conf.database.len() as u8);
+    //ProxySQL、MaxScale等代理的握手包可能不声明CLIENT_DEPRECATE_EOF、CLIENT_CONNECT_ATTRS等, 只声明双方都支持的标识,
+    //否则代理按自己声明的标识回包, 结果集的格式与客户端预期的不同
+    if handshake.capability_flags & meta::FlagsMeta::new().protocol_41 as u32 == 0 {
+        println!("{}({})不支持4.1及以上的协议", conf.host_info, handshake.server_version);
+        process::exit(exitcode::CONNECT);
+    }
+    handshake_response.client_flag &= handshake.capability_flags as i32;
+    //服务端不支持压缩时不压缩
+    let compression = compress::Compression::parse(&conf.compress).unwrap_or_else(|err|{
+        println!("{}",err);
+        process::exit(exitcode::USAGE);
+    }).filter(|_| {
+        let supported = handshake.capability_flags & meta::FlagsMeta::new().client_compress as u32 > 0;
+        if !supported {
+            eprintln!("{}不支持压缩协议, 不压缩", conf.host_info);
+        }
+        supported
+    });
+    if compression.is_some() {
+        handshake_response.client_flag |= meta::FlagsMeta::new().client_compress;
+    }
     let packet_type = meta::PackType::HandShakeResponse;
+    let plugin = plugins.initial(&handshake.auth_plugin_name);
+    let auth = plugin.scramble(&handshake.auth_plugin_data, conf).unwrap_or_else(|err|{
+        println!("{}",err);
+        process::exit(exitcode::AUTH);
+    });
     let v = response::LocalInfo::pack_payload(
-        &handshake_response,&handshake,&packet_type,conf).unwrap_or_else(
+        &handshake_response,&handshake,&packet_type,conf,plugin.name(),auth).unwrap_or_else(
         |err|{
             println!("{}",err);
-            process::exit(1);
+            process::exit(exitcode::CONNECT);
         });
 
     socketio::write_value(&mut mysql_conn, v.as_ref()).unwrap_or_else(|err|{
         println!("{}",err);
-        process::exit(1);
+        process::exit(exitcode::CONNECT);
     });
 
     //检查服务端回包情况
-    let (packet_buf,_) = socketio::get_packet_from_stream(&mut mysql_conn);
-
-
-    let mut tmp_auth_data = vec![];
-    if packet_buf[0] == 0xFE {
-        //重新验证密码
-        let (auth_data, tmp) = response::authswitchrequest(&handshake, packet_buf.as_ref(), conf);
-        tmp_auth_data = tmp;
-        socketio::write_value(&mut mysql_conn, auth_data.as_ref()).unwrap_or_else(|err|{
-            println!("{}",err);
-            process::exit(1);
-        });
+    if let Err(err) = authenticate(&mut mysql_conn, plugins, plugin, handshake.auth_plugin_data.clone(), conf) {
+        println!("认证失败: {}",err);
+        return Err("connection failed");
     }
-
-    let (packet_buf,_) = socketio::get_packet_from_stream(&mut mysql_conn);
-    if pack::check_pack(&packet_buf) {
-//        use std::{thread, time};
-//        let ten_millis = time::Duration::from_secs(100);
-//        thread::sleep(ten_millis);
-        if packet_buf[1] == 4{
-            if !response::sha2_auth(&mut mysql_conn, &tmp_auth_data, conf){
-               return Err("connection failed");
-            }
-        }else if packet_buf[1] == 3 {
-            let (packet_buf,_) = socketio::get_packet_from_stream(&mut mysql_conn);
-            if !pack::check_pack(&packet_buf) {
-                return Err("connection failed");
-            }
+    compress::enable(&mysql_conn, compression);
+    //认证之后、注册之前执行的语句, 每行一条
+    for sql in init_commands(conf) {
+        if let Err(err) = command::try_execute_update(&mut mysql_conn, sql) {
+            println!("执行init-command({})发生错误: {}", sql, err);
+            return Err("init-command failed");
         }
-        Ok(mysql_conn)
-    } else {
-        Err("connection failed")
     }
+    Ok(mysql_conn)
 }
 
+//服务端返回OK时认证完成, AuthSwitchRequest(0xFE)时切换认证方式, AuthMoreData(0x01)由认证方式处理
+fn authenticate<'a>(conn: &mut TcpStream, plugins: &'a Plugins, mut plugin: &'a dyn AuthPlugin, mut nonce: Vec<u8>, conf: &Config) -> Result<(), String> {
+    loop {
+        let (packet_buf, header) = socketio::get_packet_from_stream(conn);
+        let reply = match packet_buf[0] {
+            0x00 => return Ok(()),
+            0xFF => return Err(pack::erro_pack(&packet_buf)),
+            0xFE => {
+                //插件名 + 0 + 新的随机数
+                let end = packet_buf[1..].iter().position(|b| *b == 0).map(|t| t + 1).unwrap_or(packet_buf.len());
+                let name = String::from_utf8_lossy(&packet_buf[1..end]).to_string();
+                plugin = plugins.get(&name).ok_or_else(|| format!("不支持的认证方式: {}", name))?;
+                nonce = packet_buf.get(end + 1..).unwrap_or_default().to_vec();
+                plugin.scramble(&nonce, conf)?
+            }
+            0x01 => match plugin.more_data(&packet_buf[1..], &nonce, conf)? {
+                Some(t) => t,
+                None => continue,
+            },
+            t => return Err(format!("认证时收到无法识别的数据包: {:#04x}", t)),
+        };
+        let mut packet = response::pack_header(&reply, header.seq_id.wrapping_add(1));
+        packet.extend(reply);
+        socketio::write_value(conn, &packet).map_err(|e| e.to_string())?;
+    }
+}
 
-
-
+/// 连接后执行的语句(--init-command), 只能是SET等不返回结果集的语句
+pub fn init_commands(conf: &Config) -> impl Iterator<Item = &str> {
+    conf.initcommand.lines().map(|t| t.trim().trim_end_matches(';').trim()).filter(|t| !t.is_empty())
+}
@@ -37,16 +37,17 @@ impl HandshakePacket{
         }
 
         offset += 1;
-        let mut index= 0 ;
-        for (b,item )in buf[1..].iter().enumerate() {
-            if item == &0x00 {
-                index = b;
-                break;
-            }
-        }
+        //代理(ProxySQL、MaxScale等)发送的握手包可能被截短或修改了标识, 长度不足时返回错误而不是越界
+        let index = match buf[1..].iter().position(|item| *item == 0x00) {
+            Some(t) => t,
+            None => return Err("handshake packet is truncated"),
+        };
 
         let server_version = readvalue::read_string_value(&buf[offset..=index]);
         offset += index + 1;
+        if buf.len() < offset + 4 + 8 + 1 + 2 {
+            return Err("handshake packet is truncated");
+        }
 
         let thread_id = readvalue::read_u32(&buf[offset..offset+4]);
         offset += 4;
@@ -58,28 +59,30 @@ impl HandshakePacket{
         let mut capability_flags = readvalue::read_u16(&buf[offset..offset+2]) as u32;
         offset += 2;
 
-        let character_set_id = buf[offset];
-        offset += 1;
-
-        let status_flags = readvalue::read_u16(&buf[offset..offset+2]);
-        offset += 2 ;
+        //4.1之前的握手包到这里结束
+        let (mut character_set_id, mut status_flags, mut capability_flags_2, mut auth_plugin_data_len) = (33, 0, 0, 0);
+        if buf.len() >= offset + 1 + 2 + 2 + 1 + 10 {
+            character_set_id = buf[offset];
+            offset += 1;
 
-        let capability_flags_2 = readvalue::read_u16(&buf[offset..offset + 2]) as u32;
-        offset += 2 ;
-        capability_flags |= capability_flags_2 << 16;
+            status_flags = readvalue::read_u16(&buf[offset..offset+2]);
+            offset += 2 ;
 
+            capability_flags_2 = readvalue::read_u16(&buf[offset..offset + 2]) as u32;
+            offset += 2 ;
+            capability_flags |= capability_flags_2 << 16;
 
-        let mut auth_plugin_data_len = buf[offset];
-        auth_plugin_data_len = max(auth_plugin_data_len - 8,13);
-        offset += 1 ;
-        offset += 10;
+            //没有CLIENT_PLUGIN_AUTH时为0
+            auth_plugin_data_len = max(buf[offset].saturating_sub(8),13);
+            offset += 1 ;
+            offset += 10;
+        }
 
         if buf.len() >= offset + auth_plugin_data_len as usize {
             auth_plugin_data.extend(buf[offset..offset+auth_plugin_data_len as usize].iter().clone());
             //auth_plugin_data.push_str(readvalue::read_string_value(&buf[offset..offset + auth_plugin_data_len as usize]).as_ref());
             offset += auth_plugin_data_len as usize;
         }
-
         let mut auth_plugin_name= "".to_string();
         if capability_flags & plugin_auth > 1{
             if buf.len() >= offset{
@@ -134,3 +137,11 @@ pub fn erro_pack(pack: &Vec<u8>) -> String {
     readvalue::read_string_value(&pack[3..])
 }
 
+///binlog dump返回的数据包去掉第一个字节的ok包标识, 之后为与binlog文件中相同的完整事件, 按文件的格式解析
+pub fn event_data(mut pack: Vec<u8>) -> Vec<u8> {
+    if !pack.is_empty() {
+        pack.remove(0);
+    }
+    pack
+}
+
@@ -0,0 +1,92 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/9/21
+*/
+
+//PROXY protocol(haproxy): 负载均衡、代理要求连接开头带PROXY头时(--proxy-protocol v1或v2),
+//tcp连接建立后、读取mysql握手包之前发送, 源地址为本地地址, 目标地址为连接的地址
+//  v1: PROXY TCP4 10.0.0.2 10.0.0.1 51234 3306\r\n
+//  v2: 12字节签名 + 0x21(版本2, PROXY) + 0x11(TCP4)或0x21(TCP6) + 2字节长度 + 地址、端口(大端)
+
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+
+const V2_SIGNATURE: [u8; 12] = [0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyVersion{
+    V1,
+    V2,
+}
+
+impl ProxyVersion {
+    /// 空或off时不发送
+    pub fn parse(value: &str) -> Result<Option<ProxyVersion>, String> {
+        match value.to_lowercase().as_str() {
+            "" | "off" => Ok(None),
+            "v1" | "1" => Ok(Some(ProxyVersion::V1)),
+            "v2" | "2" => Ok(Some(ProxyVersion::V2)),
+            _ => Err(format!("无效的proxy-protocol: {}, 可选值为v1、v2", value)),
+        }
+    }
+}
+
+//ipv4映射的ipv6地址按ipv4处理, 两端的地址族需要相同
+fn normalize(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => SocketAddr::new(IpAddr::V4(v4), addr.port()),
+            None => addr,
+        },
+        IpAddr::V4(_) => addr,
+    }
+}
+
+/// PROXY头, source为客户端(本地)地址, destination为服务端地址
+pub fn header(version: ProxyVersion, source: SocketAddr, destination: SocketAddr) -> Vec<u8> {
+    let (source, destination) = (normalize(source), normalize(destination));
+    match version {
+        ProxyVersion::V1 => {
+            let family = match (source.ip(), destination.ip()) {
+                (IpAddr::V4(_), IpAddr::V4(_)) => "TCP4",
+                (IpAddr::V6(_), IpAddr::V6(_)) => "TCP6",
+                _ => return b"PROXY UNKNOWN\r\n".to_vec(),
+            };
+            format!("PROXY {} {} {} {} {}\r\n", family, source.ip(), destination.ip(), source.port(), destination.port()).into_bytes()
+        }
+        ProxyVersion::V2 => {
+            let mut packet = V2_SIGNATURE.to_vec();
+            let mut addrs = vec![];
+            let family = match (source.ip(), destination.ip()) {
+                (IpAddr::V4(s), IpAddr::V4(d)) => {
+                    addrs.extend(s.octets());
+                    addrs.extend(d.octets());
+                    0x11
+                }
+                (IpAddr::V6(s), IpAddr::V6(d)) => {
+                    addrs.extend(s.octets());
+                    addrs.extend(d.octets());
+                    0x21
+                }
+                //地址族不同时为LOCAL, 代理使用连接本身的地址
+                _ => {
+                    packet.extend([0x20, 0x00, 0x00, 0x00]);
+                    return packet;
+                }
+            };
+            addrs.extend(source.port().to_be_bytes());
+            addrs.extend(destination.port().to_be_bytes());
+            packet.push(0x21);
+            packet.push(family);
+            packet.extend((addrs.len() as u16).to_be_bytes());
+            packet.extend(addrs);
+            packet
+        }
+    }
+}
+
+/// 在连接开头发送PROXY头
+pub fn send_header(stream: &mut TcpStream, version: ProxyVersion) -> io::Result<()> {
+    let packet = header(version, stream.local_addr()?, stream.peer_addr()?);
+    stream.write_all(&packet)
+}
@@ -6,14 +6,9 @@
 use crate::{readvalue, meta};
 use crate::io::pack::HandshakePacket;
 use crate::meta::PackType;
-use std::convert::TryInto;
 use std::process;
 use crate::Config;
-use crate::io::{scramble, socketio, pack};
-use std::net::TcpStream;
-use crate::io::socketio::write_value;
-use mysql_common::crypto::encrypt;
-use mysql_common;
+use crate::replication::encode::lenenc;
 use failure::_core::str::from_utf8;
 
 //
@@ -23,6 +18,8 @@ use failure::_core::str::from_utf8;
 //}
 
 
+const CLIENT_NAME: &str = "binlogAL";
+
 //handshakereponse回包基础信息
 #[derive(Debug)]
 pub struct LocalInfo{
@@ -42,23 +39,25 @@ impl LocalInfo {
             flags_meta.long_flag|flags_meta.protocol_41|flags_meta.transactions|
             flags_meta.secure_connection|flags_meta.multi_results|
             flags_meta.client_plugin_auth| flags_meta.client_plugin_auth_lenenc_client_data|
-            flags_meta.client_connect_attrs|flags_meta.client_deprecate_eof;
+            flags_meta.client_connect_attrs|flags_meta.client_deprecate_eof|
+            flags_meta.found_rows;
         client_flag |= capabilities;
         if database > 0{
             client_flag |= flags_meta.client_connect_with_db
         }
         client_flag |= flags_meta.multi_results;
         Self{
-            client_name: String::from("rust_client"),
-            pid:123,
-            client_version:String::from("5.7.20"),
+            client_name: String::from(CLIENT_NAME),
+            pid: std::process::id(),
+            client_version: String::from(env!("CARGO_PKG_VERSION")),
             program_name: program.clone(),
             client_flag,
             max_packet_size:16777215
         }
     }
 
-    pub unsafe fn pack_handshake(&self, buf: &HandshakePacket, config: &Config, database: u8) -> Vec<u8>{
+    //auth为认证方式(plugin)生成的认证数据
+    pub unsafe fn pack_handshake(&self, buf: &HandshakePacket, config: &Config, database: u8, plugin: &str, auth: Vec<u8>) -> Vec<u8>{
         //组包
         let mut rdr = Vec::new();
         let flags_meta = meta::FlagsMeta::new();
@@ -76,7 +75,7 @@ impl LocalInfo {
         rdr.extend(config.user_name.clone().into_bytes());
         rdr.push(0);
 
-        let sha1_pass= scramble::get_sha1_pass(config, &buf.auth_plugin_name, &buf.auth_plugin_data);
+        let sha1_pass= auth;
 
         if buf.capability_flags & (flags_meta.client_plugin_auth_lenenc_client_data as u32) > 0{
             rdr.push(sha1_pass.len() as u8);
@@ -89,37 +88,50 @@ impl LocalInfo {
             rdr.push(0);
         }
 
-        if buf.capability_flags & (flags_meta.client_connect_with_db as u32) > 0{
-            if database > 0{
-                rdr.extend(config.database.clone().into_bytes())
-            }
+        //客户端只在指定库时声明client_connect_with_db, 服务端按客户端的标识解析
+        if buf.capability_flags & (flags_meta.client_connect_with_db as u32) > 0 && database > 0{
+            rdr.extend(config.database.clone().into_bytes());
             rdr.push(0);
         }
         if buf.capability_flags & flags_meta.client_plugin_auth as u32 > 0{
-            //rdr.extend(String::from("").into_bytes());
-            rdr.push(0);
+            rdr.extend(plugin.as_bytes());
             rdr.push(0);
         }
 
-        let connect_attrs = Self::pack_connect_attrs(self);
         if buf.capability_flags & (flags_meta.client_connect_attrs as u32) > 0{
-            rdr.push(connect_attrs.len().try_into().unwrap());
+            let connect_attrs = pack_connect_attrs(&self.connect_attrs(config));
+            rdr.extend(lenenc(connect_attrs.len()));
             rdr.extend(connect_attrs);
         }
 
         return rdr;
     }
 
-    fn pack_connect_attrs(&self) -> Vec<u8> {
-        let mut connect_attrs = Vec::new();
-        connect_attrs.push(self.client_name.len() as u8);
-        connect_attrs.extend(self.client_name.clone().into_bytes());
-        connect_attrs.push(self.client_version.len() as u8);
-        connect_attrs.extend(self.client_version.clone().into_bytes());
-        connect_attrs.push(self.program_name.len() as u8);
-        connect_attrs.extend(self.program_name.clone().into_bytes());
-
-        return connect_attrs;
+    /// 握手时发送的连接属性, mysql中可以通过performance_schema.session_connect_attrs查看
+    /// --connect-attr指定的属性加在之后, 与内置的属性同名时替换
+    pub fn connect_attrs(&self, config: &Config) -> Vec<(String, String)> {
+        let program = match self.program_name.as_str() {
+            "" => CLIENT_NAME,
+            t => t,
+        };
+        let mut attrs = vec![
+            (String::from("_client_name"), self.client_name.clone()),
+            (String::from("_client_version"), self.client_version.clone()),
+            (String::from("_os"), String::from(std::env::consts::OS)),
+            (String::from("_platform"), String::from(std::env::consts::ARCH)),
+            (String::from("_pid"), self.pid.to_string()),
+            (String::from("program_name"), program.to_string()),
+        ];
+        if let Some(host) = hostname() {
+            attrs.push((String::from("host"), host));
+        }
+        for (key, value) in parse_connect_attrs(&config.connectattrs).unwrap_or_default() {
+            match attrs.iter_mut().find(|(k, _)| *k == key) {
+                Some(attr) => attr.1 = value,
+                None => attrs.push((key, value)),
+            }
+        }
+        attrs
     }
 
 
@@ -128,7 +140,7 @@ impl LocalInfo {
 
 impl LocalInfo {
 
-    pub fn pack_payload(&self, buf: &HandshakePacket, pack_type: &PackType, config: &Config) -> Result<Vec<u8> ,&'static str> {
+    pub fn pack_payload(&self, buf: &HandshakePacket, pack_type: &PackType, config: &Config, plugin: &str, auth: Vec<u8>) -> Result<Vec<u8> ,&'static str> {
         //组装handshakeresponse包从这里开始，返回一个完整的回包
         //所有数据都放于verctor类型中
         let mut _payload = Vec::new();
@@ -138,9 +150,9 @@ impl LocalInfo {
             PackType::HandShake => {},
             PackType::HandShakeResponse => unsafe {
                 if config.database.len() > 0 {
-                    payload_value = Self::pack_handshake(&self,buf, config, 1);
+                    payload_value = Self::pack_handshake(&self,buf, config, 1, plugin, auth);
                 }else {
-                    payload_value = Self::pack_handshake(&self,buf, config, 0) ;
+                    payload_value = Self::pack_handshake(&self,buf, config, 0, plugin, auth) ;
                 }
             }
             _ => payload_value = vec![]
@@ -157,63 +169,38 @@ impl LocalInfo {
     }
 }
 
-//auth_switch需再次验证密码方式
-pub fn authswitchrequest(handshake: &HandshakePacket,buf: &Vec<u8>,conf: &Config) -> (Vec<u8>,Vec<u8>) {
-    let mut packet: Vec<u8> = vec![];
-    let mut payload: Vec<u8> = vec![];
-    let mut offset = 1;
-    let mut auth_plugin_name = String::from("");
-    for (b,item )in buf[offset..].iter().enumerate(){
-        if item == &0x00 {
-            auth_plugin_name = readvalue::read_string_value(&buf[offset..offset+b]);
-            offset += b + 1;
-            break;
+/// --connect-attr的值, 每行一个key=value
+pub fn parse_connect_attrs(value: &str) -> Result<Vec<(String, String)>, String> {
+    let mut attrs = vec![];
+    for line in value.lines().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => attrs.push((key.trim().to_string(), value.trim().to_string())),
+            _ => return Err(format!("无效的connect-attr: {}, 格式为key=value", line)),
         }
     }
-    let auth_plugin_data = &buf[offset..];
-
-    if auth_plugin_name.len() > 0 {
-        let flags_meta = meta::FlagsMeta::new();
-        if handshake.capability_flags & flags_meta.client_plugin_auth as u32 > 0 {
-            payload = scramble::get_sha1_pass(conf, &auth_plugin_name, &auth_plugin_data.to_vec());
-        }
-    }
-
-    packet.extend(pack_header(payload.as_ref(), 3));
-    packet.extend(payload);
-    return (packet, auth_plugin_data.to_vec());
+    Ok(attrs)
 }
 
-pub fn sha2_auth(conn: &mut TcpStream, auth_data: &Vec<u8>, conf: &Config) -> bool {
-    let (payload, seq_id) = ([0x02],5);
-    let mut packet: Vec<u8> = vec![];
-    packet.extend(pack_header(&payload, seq_id));
-    packet.extend(payload.iter());
-    write_value(conn, &packet);
-
-    let (packet_buf,_) = socketio::get_packet_from_stream(conn);
-
-    let key = &packet_buf[1..];
-    let mut password = conf.password.as_bytes().to_vec();
-    password.push(0);
-    for i in 0..password.len() {
-        password[i] ^= auth_data[i % auth_data.len()];
+/// 连接属性的编码: 每个key、value为length encoded string
+pub fn pack_connect_attrs(attrs: &[(String, String)]) -> Vec<u8> {
+    let mut connect_attrs = Vec::new();
+    for (key, value) in attrs {
+        connect_attrs.extend(lenenc(key.len()));
+        connect_attrs.extend(key.as_bytes());
+        connect_attrs.extend(lenenc(value.len()));
+        connect_attrs.extend(value.as_bytes());
     }
-    let encrypted_pass = encrypt(&password, &key);
-    let mut packet: Vec<u8> = vec![];
-    packet.extend(pack_header(&encrypted_pass, 7));
-    packet.extend(encrypted_pass.iter());
-    write_value(conn, &packet);
-
-    let (packet_buf,_) = socketio::get_packet_from_stream(conn);
-    if pack::check_pack(&packet_buf) {
-        return true;
-    } else {
-        return false;
-    }
-    true
+    connect_attrs
 }
 
+//本机的主机名
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME").ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
 
 //组装heder部分
 pub fn pack_header(buf: &[u8], seq: u8) -> Vec<u8> {
@@ -5,28 +5,7 @@
 
 use sha1::Sha1;
 use sha2::{Digest, Sha256};
-use crate::Config;
 use crate::io::pack::HandshakePacket;
-use std::process;
-
-
-pub fn get_sha1_pass(conf: &Config, auth_plugin_name: &String, auth_plugin_data: &Vec<u8>) -> Vec<u8> {
-    if auth_plugin_name == &String::from("mysql_native_password"){
-        match scramble_native(&auth_plugin_data[..20], conf.password.as_bytes()){
-            Some(value) => {return value.to_vec();},
-            None => process::exit(1)
-
-        };
-    }else if auth_plugin_name == &String::from("caching_sha2_password") {
-        match scramble_sha256( &auth_plugin_data[..20], conf.password.as_bytes()){
-            Some(value) => {return value.to_vec()},
-            None => process::exit(1)
-
-        };
-    }
-    println!("不支持的密码校验方法：{}", auth_plugin_name);
-    process::exit(1);
-}
 
 
 fn xor<T, U>(mut left: T, right: U) -> T
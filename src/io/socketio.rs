@@ -7,6 +7,7 @@ use std::net::TcpStream;
 use std::io::{Read, Write, BufReader};
 use std::error::Error;
 use std::fs::File;
+use std::io;
 
 //包头部分
 #[derive(Debug)]
@@ -26,6 +27,14 @@ impl PacketHeader{
     }
 }
 
+//压缩连接(见compress模块)从解压后的数据中读取
+fn read_exact(stream: &mut TcpStream, buf: &mut [u8]) -> io::Result<()> {
+    match crate::io::compress::read_exact(stream, buf) {
+        Some(result) => result,
+        None => stream.read_exact(buf),
+    }
+}
+
 fn get_from_stream(stream: &mut TcpStream) -> (Vec<u8>, PacketHeader){
     //获取一个数据包
     //定义4个u8的vector接收包头4bytes数据
@@ -33,7 +42,7 @@ fn get_from_stream(stream: &mut TcpStream) -> (Vec<u8>, PacketHeader){
     let mut header_buf = vec![0 as u8; 4];
     let mut header: PacketHeader = PacketHeader { payload: 0, seq_id: 0 };
     loop {
-        match stream.read_exact(&mut header_buf){
+        match read_exact(stream, &mut header_buf){
             Ok(_) => {
                 header = PacketHeader::new(&header_buf);
                 if header.payload > 0 {
@@ -49,12 +58,13 @@ fn get_from_stream(stream: &mut TcpStream) -> (Vec<u8>, PacketHeader){
 
     //通过包头获取到的payload数据读取实际数据
     let mut packet_buf  = vec![0 as u8; header.payload as usize];
-    match stream.read_exact(&mut packet_buf) {
+    match read_exact(stream, &mut packet_buf) {
         Ok(_) =>{}
         Err(_) => {
             println!("read packet error");
         }
     }
+    crate::io::trace::received(stream, &header_buf, &packet_buf);
     return (packet_buf,header);
 }
 
@@ -70,7 +80,11 @@ pub fn get_packet_from_stream(stream: &mut TcpStream) -> (Vec<u8>, PacketHeader)
 
 //向连接写入数据
 pub fn write_value(stream: &mut TcpStream, buf: &Vec<u8>) -> Result<(),Box<dyn Error>> {
-    stream.write_all(buf)?;
+    crate::io::trace::sent(stream, buf);
+    match crate::io::compress::write_all(stream, buf) {
+        Some(result) => result?,
+        None => stream.write_all(buf)?,
+    }
     Ok(())
 }
 
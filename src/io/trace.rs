@@ -0,0 +1,295 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//协议跟踪(--packet-trace): 把与mysql之间收发的每个数据包写入文件, 用于排查与非标准服务端(代理、分支版本)的兼容问题
+//  每个包一行: 距开始的秒数、本地地址、方向、序号、长度、解析出的内容, 下一行为前N个字节(包括4字节包头)的十六进制
+//  按连接记录所处的阶段: 握手(解析版本、capability flags、认证方式) -> 握手回包 -> 认证 -> 命令 -> binlog dump
+//  隐藏敏感内容: 握手回包只记录capability flags、用户名, 认证数据、库名、连接属性只记录长度; 认证阶段客户端发送的包只记录长度;
+//  语句中包含PASSWORD、IDENTIFIED时隐藏所有字符串常量且不输出十六进制
+//  与压缩协议一起使用时记录的是解压后的mysql包
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use crate::Config;
+use crate::gtid::GtidSet;
+use crate::io::pack::HandshakePacket;
+use crate::readvalue;
+use crate::replication::readevent::EventType;
+
+/// 默认每个包输出的字节数
+pub const DEFAULT_BYTES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage{
+    Handshake,
+    Response,
+    Auth,
+    Command,
+    Dump,
+}
+
+struct Tracer{
+    path: String,
+    writer: BufWriter<File>,
+    bytes: usize,
+    start: Instant,
+    conns: HashMap<SocketAddr, Stage>,
+}
+
+static TRACER: Mutex<Option<Tracer>> = Mutex::new(None);
+//没有开启时读写数据包不需要加锁
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+//capability flags的名称, 按位的顺序
+const CAPABILITIES: [&str; 27] = [
+    "LONG_PASSWORD", "FOUND_ROWS", "LONG_FLAG", "CONNECT_WITH_DB", "NO_SCHEMA", "COMPRESS", "ODBC", "LOCAL_FILES",
+    "IGNORE_SPACE", "PROTOCOL_41", "INTERACTIVE", "SSL", "IGNORE_SIGPIPE", "TRANSACTIONS", "RESERVED", "SECURE_CONNECTION",
+    "MULTI_STATEMENTS", "MULTI_RESULTS", "PS_MULTI_RESULTS", "PLUGIN_AUTH", "CONNECT_ATTRS", "PLUGIN_AUTH_LENENC_CLIENT_DATA",
+    "CAN_HANDLE_EXPIRED_PASSWORDS", "SESSION_TRACK", "DEPRECATE_EOF", "OPTIONAL_RESULTSET_METADATA", "ZSTD_COMPRESSION_ALGORITHM",
+];
+
+/// capability flags按名称列出, 以|分隔
+pub fn capability_names(flags: u32) -> String {
+    let mut names: Vec<String> = CAPABILITIES.iter().enumerate()
+        .filter(|(bit, _)| flags & (1 << bit) != 0)
+        .map(|(_, name)| name.to_string()).collect();
+    let unknown = flags & !((1u32 << CAPABILITIES.len()) - 1);
+    if unknown != 0 {
+        names.push(format!("{:#x}", unknown));
+    }
+    names.join("|")
+}
+
+/// conf.packettrace不为空时打开跟踪文件(追加写入), 已打开相同文件时不重复打开
+pub fn open(conf: &Config) -> Result<(), String> {
+    if conf.packettrace.is_empty() {
+        return Ok(());
+    }
+    let bytes = match conf.packettracebytes.as_str() {
+        "" => DEFAULT_BYTES,
+        t => t.parse::<usize>().map_err(|_| format!("无效的packet-trace-bytes: {}", t))?,
+    };
+    let mut tracer = TRACER.lock().unwrap();
+    if tracer.as_ref().is_some_and(|t| t.path == conf.packettrace) {
+        return Ok(());
+    }
+    let file = OpenOptions::new().create(true).append(true).open(&conf.packettrace)
+        .map_err(|e| format!("打开packet-trace文件({})发生错误: {}", conf.packettrace, e))?;
+    *tracer = Some(Tracer{ path: conf.packettrace.clone(), writer: BufWriter::new(file), bytes, start: Instant::now(), conns: HashMap::new() });
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 关闭跟踪文件
+pub fn close() {
+    ENABLED.store(false, Ordering::Relaxed);
+    if let Some(mut tracer) = TRACER.lock().unwrap().take() {
+        let _ = tracer.writer.flush();
+    }
+}
+
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn with_tracer(stream: &TcpStream, f: impl FnOnce(&mut Tracer, SocketAddr) -> io::Result<()>) {
+    if !enabled() {
+        return;
+    }
+    let addr = match stream.local_addr() {
+        Ok(t) => t,
+        Err(_) => return,
+    };
+    let mut guard = TRACER.lock().unwrap();
+    let tracer = match guard.as_mut() {
+        Some(t) => t,
+        None => return,
+    };
+    //写入失败不影响读取binlog, 之后不再记录
+    if f(tracer, addr).and_then(|_| tracer.writer.flush()).is_err() {
+        eprintln!("写入packet-trace文件({})发生错误, 不再记录", tracer.path);
+        ENABLED.store(false, Ordering::Relaxed);
+        *guard = None;
+    }
+}
+
+/// 新建连接, 之后收到的第一个包按握手包解析
+pub(crate) fn connect(stream: &TcpStream, host: &str) {
+    with_tracer(stream, |tracer, addr| {
+        tracer.conns.insert(addr, Stage::Handshake);
+        let elapsed = tracer.start.elapsed().as_secs_f64();
+        writeln!(tracer.writer, "{:.6} {} connect {}", elapsed, addr, host)
+    });
+}
+
+/// 收到的数据包
+pub(crate) fn received(stream: &TcpStream, header: &[u8], payload: &[u8]) {
+    with_tracer(stream, |tracer, addr| tracer.packet(addr, "<-", header, payload));
+}
+
+/// 发送的数据包, 可以是连续的多个包
+pub(crate) fn sent(stream: &TcpStream, buf: &[u8]) {
+    with_tracer(stream, |tracer, addr| {
+        let mut offset = 0;
+        while offset + 4 <= buf.len() {
+            let end = (offset + 4 + readvalue::read_u24(&buf[offset..offset + 3]) as usize).min(buf.len());
+            tracer.packet(addr, "->", &buf[offset..offset + 4], &buf[offset + 4..end])?;
+            offset = end;
+        }
+        Ok(())
+    });
+}
+
+impl Tracer {
+    fn packet(&mut self, addr: SocketAddr, direction: &str, header: &[u8], payload: &[u8]) -> io::Result<()> {
+        let stage = self.conns.get(&addr).copied().unwrap_or(Stage::Command);
+        let (info, next, dump) = match direction {
+            "<-" => received_info(stage, payload),
+            _ => sent_info(stage, payload),
+        };
+        if next != stage {
+            self.conns.insert(addr, next);
+        }
+        let elapsed = self.start.elapsed().as_secs_f64();
+        writeln!(self.writer, "{:.6} {} {} seq={} len={} {}", elapsed, addr, direction, header[3], payload.len(), info)?;
+        if dump {
+            let n = self.bytes.min(header.len() + payload.len());
+            let hex: Vec<String> = header.iter().chain(payload).take(n).map(|b| format!("{:02x}", b)).collect();
+            let more = if header.len() + payload.len() > n { " ..." } else { "" };
+            writeln!(self.writer, "    {}{}", hex.join(" "), more)?;
+        }
+        Ok(())
+    }
+}
+
+//返回解析出的内容、之后的阶段、是否输出十六进制
+fn received_info(stage: Stage, payload: &[u8]) -> (String, Stage, bool) {
+    let first = payload.first().copied().unwrap_or(0);
+    match stage {
+        Stage::Handshake if first == 0xFF => (format!("ERR {}", error_message(payload)), Stage::Response, true),
+        Stage::Handshake => match HandshakePacket::new(&payload.to_vec()) {
+            Ok(t) => (format!("Handshake version={} connection_id={} charset={} status={:#06x} auth_plugin={} capabilities={:#010x}({})",
+                              t.server_version, t.thread_id, t.character_set_id, t.status_flags, t.auth_plugin_name,
+                              t.capability_flags, capability_names(t.capability_flags)), Stage::Response, true),
+            Err(e) => (format!("Handshake 无法解析: {}", e), Stage::Response, true),
+        },
+        Stage::Auth | Stage::Response => match first {
+            0x00 => (String::from("OK 认证完成"), Stage::Command, true),
+            0xFF => (format!("ERR {}", error_message(payload)), stage, true),
+            0xFE => {
+                let end = payload[1..].iter().position(|b| *b == 0).map(|t| t + 1).unwrap_or(payload.len());
+                (format!("AuthSwitchRequest plugin={}", String::from_utf8_lossy(&payload[1..end])), Stage::Auth, true)
+            }
+            0x01 => (String::from("AuthMoreData"), Stage::Auth, true),
+            t => (format!("认证阶段未知的包: {:#04x}", t), stage, true),
+        },
+        Stage::Dump => match first {
+            0x00 if payload.len() >= 20 => {
+                let event = &payload[1..];
+                let type_code = event[4];
+                let name = EventType::try_from(type_code).map(|t| format!("{:?}", t)).unwrap_or_else(|_| format!("UnknownEvent({})", type_code));
+                (format!("{} timestamp={} server_id={} event_length={} next_position={} flags={:#06x}", name,
+                         readvalue::read_u32(&event[0..4]), readvalue::read_u32(&event[5..9]), readvalue::read_u32(&event[9..13]),
+                         readvalue::read_u32(&event[13..17]), readvalue::read_u16(&event[17..19])), stage, true)
+            }
+            0xFF => (format!("ERR {}", error_message(payload)), Stage::Command, true),
+            0xFE if payload.len() < 9 => (String::from("EOF binlog dump结束"), Stage::Command, true),
+            _ => (String::from("数据"), stage, true),
+        },
+        Stage::Command => match first {
+            0x00 => (String::from("OK"), stage, true),
+            0xFF => (format!("ERR {}", error_message(payload)), stage, true),
+            0xFE if payload.len() < 9 => (String::from("EOF"), stage, true),
+            _ => (String::from("结果集"), stage, true),
+        },
+    }
+}
+
+fn sent_info(stage: Stage, payload: &[u8]) -> (String, Stage, bool) {
+    match stage {
+        //握手回包: capability flags(4) + max_packet(4) + charset(1) + 保留(23) + 用户名 + 0 + 认证数据、库名、插件、连接属性
+        Stage::Response if payload.len() >= 32 => {
+            let flags = readvalue::read_u32(&payload[0..4]);
+            let end = payload[32..].iter().position(|b| *b == 0).map(|t| t + 32).unwrap_or(payload.len());
+            (format!("HandshakeResponse capabilities={:#010x}({}) max_packet={} charset={} user={} 其余{}bytes(认证数据、库名、插件、连接属性)已隐藏",
+                     flags, capability_names(flags), readvalue::read_u32(&payload[4..8]), payload[8],
+                     String::from_utf8_lossy(&payload[32..end]), payload.len().saturating_sub(end + 1)), Stage::Auth, false)
+        }
+        Stage::Handshake | Stage::Response | Stage::Auth => (format!("认证数据{}bytes已隐藏", payload.len()), Stage::Auth, false),
+        Stage::Command | Stage::Dump => command_info(payload),
+    }
+}
+
+fn command_info(payload: &[u8]) -> (String, Stage, bool) {
+    let body = payload.get(1..).unwrap_or_default();
+    match payload.first().copied().unwrap_or(0) {
+        0x01 => (String::from("COM_QUIT"), Stage::Command, true),
+        0x0e => (String::from("COM_PING"), Stage::Command, true),
+        0x03 => {
+            let sql = String::from_utf8_lossy(body);
+            let upper = sql.to_uppercase();
+            if upper.contains("PASSWORD") || upper.contains("IDENTIFIED") {
+                return (format!("COM_QUERY {}", redact_literals(&sql)), Stage::Command, false);
+            }
+            (format!("COM_QUERY {}", sql), Stage::Command, true)
+        }
+        //position(4) + flags(2) + server_id(4) + 文件名
+        0x12 if body.len() >= 10 => (format!("COM_BINLOG_DUMP position={} flags={:#06x} server_id={} file={}",
+                                           readvalue::read_u32(&body[0..4]), readvalue::read_u16(&body[4..6]),
+                                           readvalue::read_u32(&body[6..10]), String::from_utf8_lossy(&body[10..])), Stage::Dump, true),
+        //flags(2) + server_id(4) + 文件名长度(4) + 文件名 + position(8) + gtid集合长度(4) + gtid集合
+        0x1e if body.len() >= 10 => {
+            let name_len = readvalue::read_u32(&body[6..10]) as usize;
+            let mut info = format!("COM_BINLOG_DUMP_GTID flags={:#06x} server_id={}", readvalue::read_u16(&body[0..2]), readvalue::read_u32(&body[2..6]));
+            let rest = &body[(10 + name_len).min(body.len())..];
+            if let Some(name) = body.get(10..10 + name_len) {
+                info.push_str(&format!(" file={}", String::from_utf8_lossy(name)));
+            }
+            if rest.len() >= 12 {
+                let gtids = GtidSet::decode(&rest[12..]).map(|t| t.to_string()).unwrap_or_else(|e| format!("无法解析({})", e));
+                info.push_str(&format!(" position={} gtid={}", readvalue::read_u64(&rest[0..8]), gtids));
+            }
+            (info, Stage::Dump, true)
+        }
+        0x15 => (String::from("COM_REGISTER_SLAVE"), Stage::Command, true),
+        t => (format!("COMMAND {:#04x}", t), Stage::Command, true),
+    }
+}
+
+fn error_message(payload: &[u8]) -> String {
+    if payload.len() < 3 {
+        return String::new();
+    }
+    format!("{} {}", readvalue::read_u16(&payload[1..3]), String::from_utf8_lossy(&payload[3..]))
+}
+
+//隐藏引号中的内容
+fn redact_literals(sql: &str) -> String {
+    let mut result = String::new();
+    let mut quote: Option<char> = None;
+    for c in sql.chars() {
+        match quote {
+            Some(q) if c == q => {
+                result.push_str("***");
+                result.push(c);
+                quote = None;
+            }
+            Some(_) => {}
+            None => {
+                if c == '\'' || c == '"' {
+                    quote = Some(c);
+                }
+                result.push(c);
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,234 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//管道的重放日志, 用于进程崩溃后确认哪些事务已经输出但没有保存checkpoint
+//  每行一条记录, 字段以tab分隔: 类型 毫秒时间戳 管道名 gtid binlog文件 position
+//    E: 事务已写入输出目标(可能还在输出目标的缓冲中)
+//    A: 输出目标flush成功, 之前写入的事务已确认输出
+//    C: checkpoint已保存, 之前的事务重新启动后不会再次输出
+//  只追加写入, A、C记录写入后fsync; 崩溃时最后一行可能不完整, 读取时忽略
+//  多条管道可以使用同一个文件, 按管道名区分; 文件不会自动清理, 可以在管道停止后删除
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 记录的类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordKind{
+    Emitted,
+    Acked,
+    Checkpointed,
+}
+
+impl RecordKind {
+    fn code(&self) -> &'static str {
+        match self {
+            RecordKind::Emitted => "E",
+            RecordKind::Acked => "A",
+            RecordKind::Checkpointed => "C",
+        }
+    }
+}
+
+/// 日志中的一条记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record{
+    pub kind: RecordKind,
+    /// unix毫秒
+    pub timestamp: u64,
+    pub pipeline: String,
+    /// 事务的gtid, 没有开启gtid时为空
+    pub gtid: String,
+    pub log_file: String,
+    /// 事务结束的位置
+    pub position: u64,
+}
+
+impl Record {
+    pub fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\t{}\t{}\t{}\n", self.kind.code(), self.timestamp, self.pipeline, self.gtid, self.log_file, self.position)
+    }
+
+    pub fn parse(line: &str) -> Result<Record, String> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 6 {
+            return Err(format!("无效的记录: {}", line));
+        }
+        let kind = match fields[0] {
+            "E" => RecordKind::Emitted,
+            "A" => RecordKind::Acked,
+            "C" => RecordKind::Checkpointed,
+            t => return Err(format!("无效的记录类型: {}", t)),
+        };
+        Ok(Record{
+            kind,
+            timestamp: fields[1].parse().map_err(|_| format!("无效的时间: {}", fields[1]))?,
+            pipeline: fields[2].to_string(),
+            gtid: fields[3].to_string(),
+            log_file: fields[4].to_string(),
+            position: fields[5].parse().map_err(|_| format!("无效的position: {}", fields[5]))?,
+        })
+    }
+}
+
+/// 追加写入的日志文件, 可以在管道间共享
+pub struct Journal{
+    file: Mutex<File>,
+}
+
+impl Journal {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Journal> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Journal{ file: Mutex::new(file) })
+    }
+
+    /// 追加一条记录, 时间为当前时间
+    pub fn append(&self, kind: RecordKind, pipeline: &str, gtid: &str, log_file: &str, position: u64) -> io::Result<()> {
+        let record = Record{ kind, timestamp: now_millis(), pipeline: pipeline.to_string(), gtid: gtid.to_string(), log_file: log_file.to_string(), position };
+        let mut file = self.file.lock().unwrap();
+        //一条记录一次写入, 多条管道共用时不会交错
+        file.write_all(record.to_line().as_bytes())?;
+        if kind != RecordKind::Emitted {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_millis() as u64).unwrap_or(0)
+}
+
+/// 读取日志文件, 忽略最后不完整的一行
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<Record>> {
+    let content = fs::read_to_string(path)?;
+    let complete = match content.rfind('\n') {
+        Some(t) => &content[..t],
+        None => return Ok(vec![]),
+    };
+    complete.lines().filter(|l| !l.is_empty())
+        .map(|l| Record::parse(l).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)))
+        .collect()
+}
+
+/// 一个已写入但没有保存checkpoint的事务
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unsettled{
+    pub record: Record,
+    /// 输出目标flush成功的时间(毫秒), None为崩溃时可能还在输出目标的缓冲中
+    pub acked: Option<u64>,
+}
+
+/// 一条管道的检查结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnosis{
+    pub pipeline: String,
+    /// 最后保存的checkpoint
+    pub checkpoint: Option<Record>,
+    /// 最后的checkpoint之后写入的事务, 按写入顺序
+    pub unsettled: Vec<Unsettled>,
+}
+
+impl Diagnosis {
+    /// 已确认输出, 重新启动后会重复输出的事务
+    pub fn acked(&self) -> Vec<&Record> {
+        self.unsettled.iter().filter(|t| t.acked.is_some()).map(|t| &t.record).collect()
+    }
+
+    /// 不确定是否已输出的事务
+    pub fn unacked(&self) -> Vec<&Record> {
+        self.unsettled.iter().filter(|t| t.acked.is_none()).map(|t| &t.record).collect()
+    }
+}
+
+/// 按管道检查最后的checkpoint之后写入的事务, 按管道第一次出现的顺序返回
+pub fn diagnose(records: &[Record]) -> Vec<Diagnosis> {
+    let mut result: Vec<Diagnosis> = vec![];
+    for record in records {
+        let idx = match result.iter().position(|d| d.pipeline == record.pipeline) {
+            Some(t) => t,
+            None => {
+                result.push(Diagnosis{ pipeline: record.pipeline.clone(), checkpoint: None, unsettled: vec![] });
+                result.len() - 1
+            }
+        };
+        let diagnosis = &mut result[idx];
+        match record.kind {
+            RecordKind::Emitted => diagnosis.unsettled.push(Unsettled{ record: record.clone(), acked: None }),
+            RecordKind::Acked => {
+                for t in diagnosis.unsettled.iter_mut().filter(|t| t.acked.is_none()) {
+                    t.acked = Some(record.timestamp);
+                }
+            }
+            RecordKind::Checkpointed => {
+                diagnosis.checkpoint = Some(record.clone());
+                diagnosis.unsettled.clear();
+            }
+        }
+    }
+    result
+}
+
+fn format_millis(millis: u64) -> String {
+    use chrono::TimeZone;
+    match chrono::Local.timestamp_millis_opt(millis as i64) {
+        chrono::LocalResult::Single(t) => t.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+        _ => millis.to_string(),
+    }
+}
+
+fn describe(record: &Record) -> String {
+    let gtid = if record.gtid.is_empty() { "-" } else { record.gtid.as_str() };
+    format!("{} {}:{}", gtid, record.log_file, record.position)
+}
+
+/// doctor子命令: 读取日志后按管道输出需要人工核对的事务, 有这样的事务时退出码为MISMATCH
+#[cfg(feature = "cli")]
+pub fn run(conf: &crate::Config) {
+    use std::process;
+    use crate::exitcode;
+    let records = read(&conf.journal).unwrap_or_else(|err|{
+        println!("读取日志文件({})发生错误:{}", conf.journal, err);
+        process::exit(if err.kind() == io::ErrorKind::NotFound { exitcode::USAGE } else { exitcode::PARSE });
+    });
+    let diagnoses = diagnose(&records);
+    if diagnoses.is_empty() {
+        println!("日志文件({})中没有记录", conf.journal);
+        process::exit(exitcode::NO_EVENTS);
+    }
+    let mut unsettled = false;
+    for diagnosis in &diagnoses {
+        println!("管道{}:", diagnosis.pipeline);
+        match &diagnosis.checkpoint {
+            Some(t) => println!("  最后的checkpoint: {} ({})", describe(t), format_millis(t.timestamp)),
+            None => println!("  没有保存过checkpoint, 重新启动后从配置的位置开始"),
+        }
+        if diagnosis.unsettled.is_empty() {
+            println!("  checkpoint之后没有写入的事务");
+            continue;
+        }
+        unsettled = true;
+        let acked = diagnosis.acked();
+        if !acked.is_empty() {
+            println!("  已确认输出但没有保存checkpoint的事务({}个), 重新启动后会重复输出, 下游需要去重:", acked.len());
+            for t in diagnosis.unsettled.iter().filter(|t| t.acked.is_some()) {
+                println!("    {} 写入 {} 确认 {}", describe(&t.record), format_millis(t.record.timestamp), format_millis(t.acked.unwrap_or_default()));
+            }
+        }
+        let unacked = diagnosis.unacked();
+        if !unacked.is_empty() {
+            println!("  已写入但没有确认输出的事务({}个), 可能部分或全部没有到达下游, 需要到下游核对:", unacked.len());
+            for t in &unacked {
+                println!("    {} 写入 {}", describe(t), format_millis(t.timestamp));
+            }
+        }
+    }
+    if unsettled {
+        process::exit(exitcode::MISMATCH);
+    }
+}
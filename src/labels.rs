@@ -0,0 +1,60 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//静态标签: --labels env=prod,cluster=order,shard=3
+//  json输出的每条记录(行数据、心跳)及指标(--summary、控制接口的/status、管道的状态)中带labels对象,
+//  多个分片输出到同一个下游时据此汇总及路由, 不需要另外包装
+//  标签名只能包含字母、数字、_、-、., 值不能包含逗号
+
+use serde_json::{Map, Value as JsonValue};
+use crate::Config;
+
+/// 按配置顺序保存的标签
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Labels(Vec<(String, String)>);
+
+impl Labels {
+    pub fn parse(spec: &str) -> Result<Labels, String> {
+        let mut labels: Vec<(String, String)> = vec![];
+        for item in spec.split(',').map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            let (key, value) = match item.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format!("无效的labels: {}, 格式为key=value, 逗号分隔", item)),
+            };
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.') {
+                return Err(format!("无效的标签名: {}, 只能包含字母、数字、_、-、.", key));
+            }
+            if labels.iter().any(|(k, _)| k == key) {
+                return Err(format!("标签{}重复", key));
+            }
+            labels.push((key.to_string(), value.to_string()));
+        }
+        Ok(Labels(labels))
+    }
+
+    /// 配置已检查, 无效时不带标签
+    pub fn from_config(conf: &Config) -> Labels {
+        Labels::parse(&conf.labels).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        JsonValue::Object(self.0.iter().map(|(k, v)| (k.clone(), JsonValue::from(v.as_str()))).collect::<Map<String, JsonValue>>())
+    }
+
+    /// 在json对象中加上labels, 没有标签时不变
+    pub fn attach(&self, value: &mut JsonValue) {
+        if let (false, JsonValue::Object(obj)) = (self.is_empty(), value) {
+            obj.insert(String::from("labels"), self.to_json());
+        }
+    }
+}
@@ -3,91 +3,170 @@
 @datetime: 2019/9/17
 */
 
+#[macro_use]
+extern crate bitflags;
+
 pub mod readvalue;
 pub mod meta;
 pub mod stdout;
+#[cfg(feature = "network")]
 pub mod io;
 pub mod replication;
-use std::str;
+pub mod gtid;
+pub mod failover;
+#[cfg(feature = "os")]
+pub mod mycnf;
+pub mod secret;
+pub mod reader;
+pub mod noalloc;
+pub mod rowfilter;
+pub mod template;
+pub mod sample;
+pub mod rowhash;
+pub mod profile;
+pub mod spatial;
+pub mod ident;
+#[cfg(feature = "os")]
+pub mod index;
+#[cfg(feature = "os")]
+pub mod control;
+#[cfg(feature = "os")]
+pub mod locate;
+#[cfg(feature = "os")]
+pub mod history;
+#[cfg(feature = "os")]
+pub mod snapshot;
+#[cfg(feature = "os")]
+pub mod archive;
+#[cfg(feature = "os")]
+pub mod manifest;
+#[cfg(feature = "os")]
+pub mod colstats;
+#[cfg(feature = "network")]
+pub mod stream;
+#[cfg(feature = "os")]
+pub mod relay;
+#[cfg(feature = "os")]
+pub mod memory;
+#[cfg(feature = "os")]
+pub mod endpoint;
+#[cfg(feature = "os")]
+pub mod position;
+#[cfg(feature = "os")]
+pub mod journal;
+#[cfg(feature = "os")]
+pub mod recording;
+#[cfg(feature = "network")]
+pub mod election;
+#[cfg(feature = "network")]
+pub mod master;
+#[cfg(feature = "network")]
+pub mod pipeline;
+#[cfg(feature = "network")]
+pub mod embed;
+#[cfg(feature = "network")]
+pub mod showevents;
+pub mod sink;
+pub mod exitcode;
+pub mod warning;
+pub mod nokey;
+pub mod compat;
+pub mod fingerprint;
+pub mod exclude;
+pub mod osc;
+pub mod labels;
+pub mod builder;
+#[cfg(feature = "test-support")]
+pub mod chaos;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "cli")]
+pub mod inspect;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+#[cfg(feature = "cli")]
+pub use cli::{Opt, startop};
+
+#[cfg(feature = "network")]
 use std::process;
-
-use structopt::StructOpt;
+#[cfg(feature = "network")]
 use std::net::TcpStream;
 
-#[derive(Debug, StructOpt)]
-#[structopt(name = "example", about = "An example of StructOpt usage.")]
-pub struct Opt {
-    #[structopt(long = "runtype",help = "程序运行模式, [repl: 模拟slave获取binlog数据, command: 执行sql语句, file: 从binlog文件获取数据]")]
-    pub runtype: Option<String>,
-
-    #[structopt(short = "u", long = "user",help = "mysql用户名")]
-    pub user: Option<String>,
-
-    #[structopt(short = "p", long = "password",help = "mysql密码")]
-    pub password: Option<String>,
-
-    #[structopt(short = "h",long = "host", help="ip地址加端口, ip:port 例如127.0.0.1:3306")]
-    pub host: Option<String>,
-
-    #[structopt(short = "D",long = "database", help="执行sql语句时连接的默认数据库")]
-    pub database: Option<String>,
-
-    #[structopt(short = "c",long = "command", help="sql语句")]
-    pub command: Option<String>,
-
-    #[structopt(short = "f",long = "file", help="binlog文件，用于文件读取与配置文件的指定")]
-    pub file: Option<String>,
-
-    #[structopt(long = "binlogfile", help="binlog文件名，用于replicaton注册同步")]
-    pub binlogfile: Option<String>,
-
-    #[structopt(long = "position", help="注册replication使用的pos")]
-    pub position: Option<String>,
-
-    #[structopt(long = "gtid", help="作用于gtid同步模式使用的gtid或者从文件读取时需要过滤的gtid")]
-    pub gtid: Option<String>,
-
-    #[structopt(long = "serverid", help="注册用的server_id，不能与已经存在的同步线程重复, 默认为133")]
-    pub serverid: Option<String>,
-
-    #[structopt(long = "getsql", help="从binlog文件或者数据流中提取sql语句")]
-    pub getsql: bool,
-
-    #[structopt(long = "rollback", help="反转binlog数据为回滚数据，写入二进制文件")]
-    pub rollback: bool,
-
-    #[structopt(long = "statisc", help="统计row_event数据大小")]
-    pub statisc: bool,
-
-    #[structopt(long = "startposition", help="从binlog文件提取数据时的position起始位置")]
-    pub startposition: Option<String>,
-
-    #[structopt(long = "stopposition", help="从binlog文件提取数据时停止的位置")]
-    pub stopposition: Option<String>,
-
-    #[structopt(long = "startdatetime", help="读取binlog文件数据的时间范围(时间戳格式)")]
-    pub startdatetime: Option<String>,
-
-    #[structopt(long = "stopdatetime", help="读取binlog文件数据的时间范围(时间戳格式)")]
-    pub stopdatetime: Option<String>,
-
-    #[structopt(long = "threadid", help="过滤出某个线程id所产生的binlog数据")]
-    pub threadid: Option<String>,
-
-    #[structopt(long = "greptbl", help="过滤出某个表所产生的binlog数据，格式为{'db1':['tb1','tb2'],'db2':[]...}}, 如果要提取一个库的所有表格式为{'db1':'all',....}")]
-    pub greptbl: Option<String>,
-
-    #[structopt(long = "rfilesize", help="单个回滚日志文件大小, 可以不用设置, 默认1G, 设置值是以字节为单位")]
-    pub rfilesize: Option<String>,
+/// 作为库使用时的公共接口, 读取binlog文件不需要连接数据库
+///
+/// 示例需要os及json-sink特性(默认开启)
+#[cfg_attr(all(feature = "os", feature = "json-sink"), doc = "```no_run")]
+#[cfg_attr(not(all(feature = "os", feature = "json-sink")), doc = "```ignore")]
+/// use mytest::prelude::*;
+///
+/// fn main() -> std::io::Result<()> {
+///     let reader = BinlogReader::open("bin.000001")?;
+///     let mut sink = JsonLinesSink::new(std::io::stdout(), ValueFormat::default());
+///     for trx in reader.transactions() {
+///         for event in &trx?.events {
+///             sink.write_event(event)?;
+///         }
+///     }
+///     sink.flush()
+/// }
+/// ```
+pub mod prelude {
+    pub use crate::reader::{BinlogReader, Event, Transaction, Transactions};
+    #[cfg(feature = "network")]
+    pub use crate::stream::BinlogStream;
+    #[cfg(feature = "os")]
+    pub use crate::relay::RelayTail;
+    pub use crate::replication::readbinlog::{Traction, FileEnd};
+    pub use crate::replication::readevent::{
+        EventHeader, EventType, EventFlags, BinlogEvent, TableMap,
+        GtidEvent, QueryEvent, XidEvent, RotateLog, FormatDescriptionEvent
+    };
+    pub use crate::replication::parsevalue::{MySQLValue, RowValue};
+    pub use crate::gtid::GtidSet;
+    pub use crate::replication::verify::ChecksumClass;
+    pub use crate::sink::{Sink, FanOutSink, FailurePolicy, Checkpoint, RouteTemplate, RoutedSink, Heartbeat, Watermark, Compressor};
+    #[cfg(feature = "os")]
+    pub use crate::sink::{QueuedSink, QueueConfig, QueueMetrics, BatchSink, BatchConfig, Batcher, HeartbeatSink, WatermarkSink};
+    #[cfg(feature = "os")]
+    pub use crate::control::Controller;
+    #[cfg(feature = "json-sink")]
+    pub use crate::sink::{JsonLinesSink, SchemaRegistry, RegistrySink};
+    #[cfg(all(feature = "json-sink", feature = "network"))]
+    pub use crate::sink::HttpSchemaRegistry;
+    pub use crate::stdout::format::ValueFormat;
+}
 
+/// 测试辅助, 需要开启test-support特性: 将事件编码为binlog格式, 用于往返测试及构造测试用的binlog文件
+///
+/// ```ignore
+/// use mytest::testing::{BinlogBuilder, ServerVersion};
+///
+/// let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+/// builder.timestamp(1572566400).gtid(&gtid).query(&begin);
+/// let reader = mytest::prelude::BinlogReader::new(std::io::Cursor::new(builder.into_bytes()))?;
+/// ```
+#[cfg(feature = "test-support")]
+pub mod testing {
+    pub use crate::replication::encode::*;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Config {
     pub runtype: String,
     pub host_info: String,
+    pub compress: String,
+    pub socket: String,
+    pub ssh: String,
+    pub sshkey: String,
+    pub initcommand: String,
+    pub compat: String,
+    pub excludetables: String,
+    pub oscremap: bool,
+    pub labels: String,
+    pub boundaries: bool,
     pub user_name: String,
-    pub password: String,
+    pub password: secret::Secret,
     pub database: String,
     pub program_name: String,
     pub command: String,
@@ -106,168 +185,119 @@ pub struct Config {
     pub threadid: String,
     pub greptbl: String,
     pub rfilesize: String,
+    pub relaylog: bool,
+    pub relaylogindex: String,
+    pub verifychecksum: String,
+    pub heartbeat: String,
+    pub watermark: String,
+    pub watermarkdelay: String,
+    pub control: String,
+    pub healthtimeout: String,
+    pub sdnotify: bool,
+    pub statusinterval: String,
+    pub pauseonswitch: bool,
+    pub pipelines: String,
+    pub backfill: String,
+    pub journal: String,
+    pub record: String,
+    pub authplugin: String,
+    pub awsregion: String,
+    pub awsendpoint: String,
+    pub awsprofile: String,
+    pub connectattrs: String,
+    pub proxyprotocol: String,
+    pub realtime: bool,
+    pub retention: bool,
+    pub needbinlog: String,
+    pub locategtid: String,
+    pub locatetime: String,
+    pub historytable: String,
+    pub historykey: String,
+    pub snapshottable: String,
+    pub snapshotbase: String,
+    pub snapshotkey: String,
+    pub index: String,
+    pub archivedirs: String,
+    pub eventspage: String,
+    pub eventsfollow: String,
+    pub untilgtid: String,
+    pub untilposition: String,
+    pub manifest: String,
+    pub packettrace: String,
+    pub packettracebytes: String,
+    pub servedir: String,
+    pub listen: String,
+    pub serveruuid: String,
+    pub output: String,
+    pub outputcompress: String,
+    pub batchsize: String,
+    pub insertmode: String,
+    pub nokey: String,
+    pub sqldialect: String,
+    pub ddlfence: String,
+    pub timezone: String,
+    pub timeformat: String,
+    pub floatprecision: String,
+    pub nonfinite: String,
+    pub zerodate: String,
+    pub quotedecimal: bool,
+    pub rowhash: bool,
+    pub spatialformat: String,
+    pub lobmaxsize: String,
+    pub lobmode: String,
+    pub outformat: String,
+    pub color: String,
+    pub template: String,
+    pub verbose: bool,
+    pub binaryencoding: String,
+    pub binarycolumns: String,
+    pub includecolumns: String,
+    pub excludecolumns: String,
+    pub rowfilter: String,
+    pub sample: String,
+    pub compact: bool,
+    pub compactwindow: String,
+    pub count: bool,
+    pub countby: String,
+    pub schemaout: String,
+    pub createtable: String,
+    pub dry_run: bool,
+    pub quiet: bool,
+    pub strict: bool,
+    pub lenientchecksum: bool,
+    pub memorylimit: String,
+    pub memorypolicy: String,
+    pub summary: String,
+    pub audit: bool,
+    pub audit_enrich: bool,
+    pub hotspot: bool,
+    pub columnstats: String,
+    pub columnstatswindow: String,
+    pub interval: String,
+    pub topkeys: String,
+    pub ddl: String,
+    pub deps: String,
+    pub depsformat: String,
+    pub groupcommit: bool,
+    pub tables: String,
+    pub columns: String,
+    pub transactions: String,
+    pub rows: String,
+    pub rate: String,
+    pub mix: String,
+    pub seed: String,
+    pub watch: String,
+    pub maxrows: String,
+    pub chunkrows: String,
+    pub force: bool,
 }
 
-impl Config{
-    pub fn new(args: Opt) -> Result<Config, &'static str> {
-        let mut host_info = String::from("");
-        let mut user_name = String::from("");
-        let mut password = String::from("");
-        let mut database = String::from("");
-        let mut command = String::from("");
-        let mut file = String::from("");
-        let mut binlogfile = String::from("");
-        let mut position = String::from("");
-        let mut gtid = String::from("");
-        let mut runtype = String::from("");
-        let mut serverid = String::from("");
-        let getsql = args.getsql;
-        let rollback = args.rollback;
-        let statisc = args.statisc;
-        let mut startposition = String::from("");
-        let mut stopposition = String::from("");
-        let mut startdatetime = String::from("");
-        let mut stopdatetime = String::from("");
-        let mut threadid = String::from("");
-        let mut greptbl = String::from("");
-        let mut rfilesize = String::from("");
-
-        match args.rfilesize {
-            None => {},
-            Some(t) => rfilesize = t,
-        }
-
-        match args.startposition {
-            None => {},
-            Some(t) => startposition = t,
-        }
-
-        match args.stopposition {
-            None => {},
-            Some(t) => stopposition = t,
-        }
-        match args.startdatetime {
-            None => {},
-            Some(t) => startdatetime = t,
-        }
-        match args.stopdatetime {
-            None => {},
-            Some(t) => stopdatetime = t,
-        }
-        match args.threadid {
-            None => {},
-            Some(t) => threadid = t,
-        }
-        match args.greptbl {
-            None => {},
-            Some(t) => greptbl = t,
-        }
-
-        match args.user {
-            None => {
-                return Err("user 不能为空！！");
-            },
-            Some(t) => user_name = t,
-        }
-
-        match args.host {
-            None => {
-                return Err("host 不能为空！！");
-            },
-            Some(t) => host_info = t,
-        }
-
-        match args.password {
-            None => {
-                return Err("password 不能为空！！")
-            },
-            Some(t) => password = t,
-        }
-
-        match args.database {
-            None => (),
-            Some(t) => database = t,
-        }
-
-        match args.command {
-            None => (),
-            Some(t) => command = t,
-        }
-
-        match args.file {
-            None => (),
-            Some(t) => file = t,
-        }
-
-        match args.binlogfile {
-            None => (),
-            Some(t) => binlogfile = t,
-        }
-
-        match args.position {
-            None => (),
-            Some(t) => position = t
-        }
-
-        match args.gtid {
-            None => (),
-            Some(t) => gtid = t,
-        }
-
-        match args.runtype {
-            None => (),
-            Some(t) => runtype = t,
-        }
-
-        match args.serverid {
-            None => (serverid = 133.to_string()),
-            Some(t) => serverid = t,
-        }
-
-
-        Ok(Config { program_name:String::from("rust_test"),statisc,rfilesize,
-            host_info, user_name ,getsql,rollback,startposition,stopposition,
-            password, database,serverid,startdatetime,stopdatetime,threadid,greptbl,
-            command,file,binlogfile,position,gtid,runtype})
-    }
-}
-
-pub fn startop(config: &Config) {
-    if config.runtype == String::from("command"){
-        let mut conn = create_conn(config);
-        let values = io::command::execute(&mut conn,&config.command);
-        for row in values.iter(){
-            println!("{:#?}",row);
-        }
-    }else if config.runtype == String::from("repl") {
-        let mut conn = create_conn(config);
-        replication::repl_register(&mut conn,&config);
-    }else if config.runtype == String::from("file") {
-        //println!("从binlog文件提取数据");
-        let mut conn = create_conn(config);
-        replication::repl_register(&mut conn,config);
-
-    }else {
-        println!("无效的执行参数runtype: {}, --help提供参考",config.runtype);
-    }
-
-
-
-//    use std::{thread, time};
-//    let ten_millis = time::Duration::from_secs(100);
-//    thread::sleep(ten_millis);
-
-}
-
-
-fn create_conn(config: &Config) -> TcpStream {
+#[cfg(feature = "network")]
+pub(crate) fn create_conn(config: &Config) -> TcpStream {
     let conn = io::connection::create_mysql_conn(config).unwrap_or_else(|err|{
         println!("创建连接时发生错误: {}",err);
-        process::exit(1);
+        process::exit(exitcode::AUTH);
     }) ;  //创建连接
 
     return conn;
 }
-
-
-
@@ -0,0 +1,314 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//locate: 根据gtid或时间找到事务所在的binlog文件及position, 用于CHANGE MASTER或按position截取数据
+//  按文件顺序从后往前检查每个文件的开头, 确定需要扫描的第一个文件后按顺序扫描:
+//      gtid: 第一个previous_gtids中不包含该gtid的文件(没有previous_gtids时从第一个文件开始), 最早的文件也包含时已被清理
+//      时间: 第一个FORMAT_DESCRIPTION_EVENT的时间不晚于该时间的文件, 返回第一个时间不早于该时间的事务
+//  文件来源: binlog文件、mysql-bin.index中记录的文件, 或连接mysql通过SHOW BINARY LOGS获取后按主从协议读取
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use uuid::Uuid;
+use crate::gtid::GtidSet;
+use crate::index;
+use crate::reader::{BinlogReader, Event};
+use crate::replication::readbinlog::Traction;
+
+/// 查找的目标, 单个gtid或时间(unix时间戳)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target{
+    Gtid(Uuid, u64),
+    Time(u32),
+}
+
+impl Target{
+    /// uuid:N
+    pub fn gtid(gtid: &str) -> Result<Target, String> {
+        let (sid, gno) = gtid.trim().split_once(':').ok_or_else(|| format!("无效的gtid: {}, 格式为uuid:N", gtid))?;
+        let sid = Uuid::parse_str(sid.trim()).map_err(|_| format!("无效的gtid: {}", gtid))?;
+        let gno = gno.trim().parse::<u64>().map_err(|_| format!("无效的gtid: {}, 只能指定一个事务", gtid))?;
+        Ok(Target::Gtid(sid, gno))
+    }
+
+    /// unix时间戳或utc时间
+    pub fn time(time: &str) -> Result<Target, String> {
+        Ok(Target::Time(index::parse_time(time)?))
+    }
+}
+
+/// 事务所在的文件及起止position, end为下一个事务的起始位置
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location{
+    pub file: String,
+    pub start: u64,
+    pub end: u64,
+    pub timestamp: u32,
+    pub gtid: Option<String>,
+}
+
+impl Location{
+    pub fn print(&self) {
+        println!("file: {}", self.file);
+        println!("start: {}", self.start);
+        println!("end: {}", self.end);
+        println!("timestamp: {} ({} UTC)", self.timestamp, crate::stdout::format::utc_datetime(self.timestamp));
+        println!("gtid: {}", self.gtid.as_deref().unwrap_or("-"));
+        println!("-- 从该事务开始:");
+        println!("CHANGE MASTER TO MASTER_LOG_FILE='{}', MASTER_LOG_POS={};", self.file, self.start);
+        println!("-- 从该事务之后开始:");
+        println!("CHANGE MASTER TO MASTER_LOG_FILE='{}', MASTER_LOG_POS={};", self.file, self.end);
+    }
+}
+
+/// 文件开头的信息, 用于确定需要扫描的第一个文件
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileProbe{
+    /// FORMAT_DESCRIPTION_EVENT的时间, 即文件创建的时间
+    pub start_time: u32,
+    /// PREVIOUS_GTIDS_LOG_EVENT, 没有开启gtid或5.6之前为None
+    pub previous_gtids: Option<GtidSet>,
+}
+
+/// 从后往前检查文件开头, 返回需要扫描的第一个文件, gtid已被清理时返回None
+pub fn first_file(target: &Target, files: usize, mut probe: impl FnMut(usize) -> io::Result<FileProbe>) -> io::Result<Option<usize>> {
+    for idx in (0..files).rev() {
+        let p = probe(idx)?;
+        match target {
+            Target::Time(t) if p.start_time <= *t => return Ok(Some(idx)),
+            Target::Time(_) => {}
+            Target::Gtid(sid, gno) => match p.previous_gtids {
+                None => return Ok(Some(0)),
+                Some(previous) if !previous.contains(sid, *gno) => return Ok(Some(idx)),
+                Some(_) => {}
+            }
+        }
+    }
+    Ok(match target {
+        Target::Time(_) => Some(0),
+        Target::Gtid(..) => None,
+    })
+}
+
+/// 按事件顺序找到目标事务, ROTATE_EVENT时切换文件名
+pub struct Scanner{
+    target: Target,
+    pub file: String,
+    start: Option<u64>,
+    timestamp: u32,
+    gtid: Option<(Uuid, u64)>,
+}
+
+impl Scanner{
+    pub fn new(target: Target, file: &str) -> Scanner {
+        Scanner{ target, file: file.to_string(), start: None, timestamp: 0, gtid: None }
+    }
+
+    /// 目标事务结束时返回其位置
+    pub fn feed(&mut self, event: &Event) -> Option<Location> {
+        match &event.data {
+            Traction::GtidEvent(t) => {
+                self.begin(event);
+                self.gtid = Some((t.gtid, t.gno_id));
+                return None;
+            }
+            Traction::QueryEvent(t) if t.command == "BEGIN" => {
+                if self.start.is_none() {
+                    self.begin(event);
+                }
+                return None;
+            }
+            Traction::RotateLogEvent(t) => {
+                self.file = t.binlog_file.clone();
+                return None;
+            }
+            Traction::QueryEvent(_) | Traction::XidEvent(_) => {}
+            _ => return None
+        }
+        //没有gtid的DDL
+        if self.start.is_none() {
+            self.begin(event);
+        }
+        let start = self.start.take().unwrap_or(event.log_pos);
+        let gtid = self.gtid.take();
+        let found = match &self.target {
+            Target::Gtid(sid, gno) => gtid == Some((*sid, *gno)),
+            Target::Time(t) => self.timestamp >= *t,
+        };
+        if !found {
+            return None;
+        }
+        Some(Location{
+            file: self.file.clone(),
+            start,
+            end: event.log_pos + event.header.event_length as u64,
+            timestamp: self.timestamp,
+            gtid: gtid.map(|(sid, gno)| format!("{}:{}", sid, gno)),
+        })
+    }
+
+    fn begin(&mut self, event: &Event) {
+        self.start = Some(event.log_pos);
+        self.timestamp = event.header.timestamp;
+        self.gtid = None;
+    }
+}
+
+/// 读取文件开头的FORMAT_DESCRIPTION_EVENT及PREVIOUS_GTIDS_LOG_EVENT
+pub fn probe_file(path: &str) -> io::Result<FileProbe> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    let mut probe = FileProbe::default();
+    let mut pos = 4;
+    for _ in 0..2 {
+        let mut header = [0u8; 19];
+        reader.seek(SeekFrom::Start(pos))?;
+        if reader.read_exact(&mut header).is_err() {
+            break;
+        }
+        let event_length = crate::readvalue::read_u32(&header[9..13]) as u64;
+        if event_length < 19 {
+            break;
+        }
+        match header[4] {
+            15 => probe.start_time = crate::readvalue::read_u32(&header[..4]),
+            35 => {
+                let mut payload = vec![];
+                (&mut reader).take(event_length - 19).read_to_end(&mut payload)?;
+                //末尾可能有4bytes的checksum, 按记录的个数解析
+                probe.previous_gtids = Some(GtidSet::decode(&payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?);
+            }
+            _ => break
+        }
+        pos += event_length;
+    }
+    Ok(probe)
+}
+
+/// mysql-bin.index中的文件, 相对路径按index文件所在的目录
+pub fn read_index(path: &str) -> io::Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    Ok(content.lines().map(|t| t.trim()).filter(|t| !t.is_empty()).map(|t| {
+        if Path::new(t).is_absolute() { t.to_string() } else { dir.join(t).to_string_lossy().to_string() }
+    }).collect())
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name().map(|t| t.to_string_lossy().to_string()).unwrap_or_else(|| path.to_string())
+}
+
+/// 在按顺序排列的binlog文件中查找
+pub fn locate_files(files: &[String], target: &Target) -> io::Result<Option<Location>> {
+    let first = match first_file(target, files.len(), |idx| probe_file(&files[idx]))? {
+        Some(t) => t,
+        None => return Ok(None),
+    };
+    for path in &files[first..] {
+        let mut scanner = Scanner::new(target.clone(), &file_name(path));
+        for event in BinlogReader::open(path)? {
+            if let Some(location) = scanner.feed(&event?) {
+                return Ok(Some(location));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// 连接mysql查找: SHOW BINARY LOGS获取文件列表, SHOW BINLOG EVENTS获取previous_gtids,
+/// 按主从协议读取文件开头的时间及扫描事务, 扫描到查询时最后一个文件的末尾时结束
+#[cfg(feature = "network")]
+pub fn locate_server(conf: &crate::Config, target: &Target) -> io::Result<Option<Location>> {
+    use crate::io::command::try_execute;
+    use crate::stream::BinlogStream;
+    use crate::replication::readevent::EventType;
+
+    let mut conn = crate::create_conn(conf);
+    let binlogs: Vec<(String, u64)> = try_execute(&mut conn, "show binary logs;").map_err(io::Error::other)?.iter().map(|row| {
+        (row.get("Log_name").cloned().unwrap_or_default(), row.get("File_size").and_then(|t| t.parse().ok()).unwrap_or(0))
+    }).collect();
+    let (last_file, last_size) = match binlogs.last() {
+        Some(t) => t.clone(),
+        None => return Ok(None),
+    };
+    let stream = |file: &str| {
+        BinlogStream::connect(&crate::Config{ gtid: String::new(), binlogfile: file.to_string(), position: String::from("4"), ..conf.clone() })
+    };
+    let probe = |idx: usize| -> io::Result<FileProbe> {
+        let file = &binlogs[idx].0;
+        let mut probe = FileProbe::default();
+        match target {
+            Target::Gtid(..) => {
+                let events = try_execute(&mut conn, &format!("show binlog events in '{}' limit 3;", file)).map_err(io::Error::other)?;
+                if let Some(row) = events.iter().find(|row| row.get("Event_type").map(|t| t.as_str()) == Some("Previous_gtids")) {
+                    let info = row.get("Info").cloned().unwrap_or_default();
+                    probe.previous_gtids = Some(info.parse::<GtidSet>().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?);
+                }
+            }
+            Target::Time(_) => {
+                let mut stream = stream(file)?;
+                loop {
+                    let event = stream.read_event()?;
+                    if event.header.event_type == EventType::FormatDescriptionEvent {
+                        probe.start_time = event.header.timestamp;
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(probe)
+    };
+    let first = match first_file(target, binlogs.len(), probe)? {
+        Some(t) => t,
+        None => return Ok(None),
+    };
+    let mut scanner = Scanner::new(target.clone(), &binlogs[first].0);
+    for event in stream(&binlogs[first].0)? {
+        let event = event?;
+        if let Some(location) = scanner.feed(&event) {
+            return Ok(Some(location));
+        }
+        if scanner.file == last_file && event.log_pos + event.header.event_length as u64 >= last_size {
+            return Ok(None);
+        }
+    }
+    Ok(None)
+}
+
+/// locate子命令: -f/--index指定文件时读取文件, 否则连接mysql
+#[cfg(feature = "cli")]
+pub fn run(conf: &crate::Config) {
+    use std::process;
+    use crate::exitcode;
+    let target = if conf.locategtid.is_empty() { Target::time(&conf.locatetime) } else { Target::gtid(&conf.locategtid) };
+    let target = target.unwrap_or_else(|err|{
+        println!("{}", err);
+        process::exit(exitcode::USAGE);
+    });
+    let result = if !conf.index.is_empty() {
+        read_index(&conf.index).and_then(|files| locate_files(&files, &target))
+    } else if !conf.file.is_empty() {
+        let files: Vec<String> = conf.file.split(',').map(|t| t.to_string()).collect();
+        locate_files(&files, &target)
+    } else {
+        locate_server(conf, &target)
+    };
+    match result {
+        Ok(Some(location)) => location.print(),
+        Ok(None) => {
+            match target {
+                Target::Gtid(..) => println!("没有找到该gtid, 可能已被清理或还未执行"),
+                Target::Time(_) => println!("没有找到该时间之后的事务"),
+            }
+            process::exit(exitcode::NO_EVENTS);
+        }
+        Err(err) => {
+            println!("查找发生错误: {}", err);
+            process::exit(exitcode::PARSE);
+        }
+    }
+}
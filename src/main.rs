@@ -1,12 +1,16 @@
 use mytest;
 use std::process;
-use structopt::StructOpt;
+use clap::Parser;
 
 fn main() {
-    let args = mytest::Opt::from_args();
+    let args = mytest::Opt::parse();
+    if let mytest::cli::Command::Completions { shell } = args.command {
+        mytest::cli::print_completions(shell);
+        return;
+    }
     let config = mytest::Config::new(args).unwrap_or_else(|err|{
         println!("Problem parsing arguments: {}", err);
-        process::exit(1);
+        process::exit(mytest::exitcode::USAGE);
     });
     //println!("{:?}",config);
     mytest::startop(&config);
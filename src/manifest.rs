@@ -0,0 +1,239 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//输出文件的清单(manifest): 每个文件的大小、sha256、包含的gtid集合、事务数及行数
+//  split、carve、synthesize、snapshot、backup通过--manifest写入, 已存在时按文件名替换或追加, 写入临时文件后rename
+//  下游传输、保留策略通过manifest子命令(或Manifest::check)只校验大小及sha256, 不需要重新解析文件
+//  文件在manifest所在目录之下时记录相对路径, 否则记录绝对路径
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use crate::gtid::GtidSet;
+use crate::reader::BinlogReader;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::BinlogEvent;
+
+/// manifest中的一个文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry{
+    pub file: String,
+    pub kind: String,                   //binlog、csv
+    pub size: u64,
+    pub sha256: String,
+    pub gtids: GtidSet,
+    pub transactions: u64,
+    pub rows: u64,
+}
+
+impl ManifestEntry {
+    /// 读取binlog文件, 计算sha256及其中事务的gtid集合、事务数、行数(update按修改的行数)
+    pub fn binlog(path: &str) -> io::Result<ManifestEntry> {
+        let mut entry = ManifestEntry::file(path, "binlog")?;
+        for trx in BinlogReader::open(path)?.transactions() {
+            let trx = trx?;
+            if !trx.complete {
+                continue;
+            }
+            if let Some(t) = &trx.gtid {
+                entry.gtids.add(t.gtid, t.gno_id);
+            }
+            entry.transactions += 1;
+            entry.rows += trx.events.iter().map(|e| match &e.data {
+                Traction::RowEvent(BinlogEvent::UpdateEvent, rows) => rows.rows().len() as u64 / 2,
+                Traction::RowEvent(_, rows) => rows.rows().len() as u64,
+                _ => 0
+            }).sum::<u64>();
+        }
+        Ok(entry)
+    }
+
+    /// 只计算大小及sha256, gtids、transactions、rows由调用方填写
+    pub fn file(path: &str, kind: &str) -> io::Result<ManifestEntry> {
+        let (size, sha256) = sha256_file(path)?;
+        Ok(ManifestEntry{ file: path.to_string(), kind: kind.to_string(), size, sha256, gtids: GtidSet::new(), transactions: 0, rows: 0 })
+    }
+
+    fn to_json(&self, file: &str) -> JsonValue {
+        json!({
+            "file": file,
+            "kind": self.kind,
+            "size": self.size,
+            "sha256": self.sha256,
+            "gtids": self.gtids.to_string(),
+            "transactions": self.transactions,
+            "rows": self.rows,
+        })
+    }
+
+    fn from_json(value: &JsonValue) -> Result<ManifestEntry, String> {
+        let text = |key: &str| value[key].as_str().map(|t| t.to_string()).ok_or_else(|| format!("缺少{}", key));
+        let number = |key: &str| value[key].as_u64().ok_or_else(|| format!("缺少{}", key));
+        Ok(ManifestEntry{
+            file: text("file")?,
+            kind: text("kind")?,
+            size: number("size")?,
+            sha256: text("sha256")?,
+            gtids: text("gtids")?.parse::<GtidSet>().map_err(|e| e.to_string())?,
+            transactions: number("transactions")?,
+            rows: number("rows")?,
+        })
+    }
+}
+
+/// 文件的大小及sha256(十六进制)
+pub fn sha256_file(path: &str) -> io::Result<(u64, String)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::default();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.input(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((size, hex::encode(hasher.result())))
+}
+
+/// 输出文件的清单, entries中的file为可以直接打开的路径
+#[derive(Debug, Clone, Default)]
+pub struct Manifest{
+    path: PathBuf,
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// 读取已存在的manifest, 不存在时为空
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Manifest> {
+        match fs::metadata(path.as_ref()) {
+            Ok(_) => Manifest::load(path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Manifest{ path: path.as_ref().to_path_buf(), entries: vec![] }),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Manifest> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, format!("{} {}", path.as_ref().display(), e));
+        let value: JsonValue = serde_json::from_str(&fs::read_to_string(path.as_ref())?).map_err(|e| invalid(e.to_string()))?;
+        let mut manifest = Manifest{ path: path.as_ref().to_path_buf(), entries: vec![] };
+        for item in value["files"].as_array().ok_or_else(|| invalid(String::from("缺少files")))? {
+            let mut entry = ManifestEntry::from_json(item).map_err(invalid)?;
+            entry.file = manifest.dir().join(&entry.file).to_string_lossy().to_string();
+            manifest.entries.push(entry);
+        }
+        Ok(manifest)
+    }
+
+    /// 追加一个文件, 与已有的文件相同时替换
+    pub fn add(&mut self, entry: ManifestEntry) {
+        let same = |t: &ManifestEntry| absolute(Path::new(&t.file)) == absolute(Path::new(&entry.file));
+        match self.entries.iter_mut().find(|t| same(t)) {
+            Some(t) => *t = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// 所有文件的gtid集合
+    pub fn gtids(&self) -> GtidSet {
+        let mut gtids = GtidSet::new();
+        for set in self.entries.iter().flat_map(|t| &t.gtids.sets) {
+            for interval in &set.intervals {
+                gtids.add_interval(set.sid, *interval);
+            }
+        }
+        gtids
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let dir = absolute(self.dir());
+        let files: Vec<JsonValue> = self.entries.iter().map(|t| {
+            let path = absolute(Path::new(&t.file));
+            let file = match path.strip_prefix(&dir) {
+                Ok(relative) => relative.to_string_lossy().to_string(),
+                Err(_) => path.to_string_lossy().to_string(),
+            };
+            t.to_json(&file)
+        }).collect();
+        json!({
+            "version": 1,
+            "gtids": self.gtids().to_string(),
+            "transactions": self.entries.iter().map(|t| t.transactions).sum::<u64>(),
+            "rows": self.entries.iter().map(|t| t.rows).sum::<u64>(),
+            "files": files,
+        })
+    }
+
+    /// 先写入临时文件再rename, 读取方不会读到写入一半的manifest
+    pub fn write(&self) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, format!("{:#}\n", self.to_json()))?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    /// 按大小及sha256校验每个文件, 返回不一致的文件及原因
+    pub fn check(&self) -> Vec<(String, String)> {
+        self.entries.iter().filter_map(|t| {
+            let reason = match sha256_file(&t.file) {
+                Err(e) => format!("读取发生错误: {}", e),
+                Ok((size, _)) if size != t.size => format!("大小不一致: {} bytes, manifest中为{} bytes", size, t.size),
+                Ok((_, sha256)) if sha256 != t.sha256 => format!("sha256不一致: {}", sha256),
+                Ok(_) => return None,
+            };
+            Some((t.file.clone(), reason))
+        }).collect()
+    }
+
+    fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new(""))
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    std::env::current_dir().map(|t| t.join(path)).unwrap_or_else(|_| path.to_path_buf())
+}
+
+//子命令写入文件后更新--manifest, 失败时退出
+#[cfg(feature = "network")]
+pub(crate) fn record(conf: &crate::Config, entry: impl FnOnce() -> io::Result<ManifestEntry>) {
+    if conf.manifest.is_empty() {
+        return;
+    }
+    let result = entry().and_then(|entry| {
+        let mut manifest = Manifest::open(&conf.manifest)?;
+        manifest.add(entry);
+        manifest.write()
+    });
+    if let Err(err) = result {
+        println!("写入manifest({})发生错误:{}", conf.manifest, err);
+        std::process::exit(crate::exitcode::SINK);
+    }
+}
+
+/// manifest子命令: 校验其中每个文件的大小及sha256, 不一致时以MISMATCH退出
+#[cfg(feature = "cli")]
+pub fn run(conf: &crate::Config) {
+    let manifest = Manifest::load(&conf.manifest).unwrap_or_else(|err|{
+        println!("读取manifest({})发生错误:{}", conf.manifest, err);
+        std::process::exit(crate::exitcode::USAGE);
+    });
+    let failed = manifest.check();
+    for (file, reason) in &failed {
+        println!("{}: {}", file, reason);
+    }
+    if !failed.is_empty() {
+        std::process::exit(crate::exitcode::MISMATCH);
+    }
+    println!("{} files, {} transactions, {} rows, gtids: {}, 校验通过", manifest.entries.len(),
+             manifest.entries.iter().map(|t| t.transactions).sum::<u64>(),
+             manifest.entries.iter().map(|t| t.rows).sum::<u64>(), manifest.gtids());
+}
@@ -0,0 +1,908 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//实验性的binlog server(模拟master): 通过主从协议重新提供备份的binlog文件(backup子命令的输出目录或index文件),
+//真实的mysql从库CHANGE MASTER到该地址后即可拉取, 用于恢复演练
+//  只实现复制需要的部分: mysql_native_password认证、从库io线程执行的SELECT/SET/SHOW VARIABLES、
+//  COM_REGISTER_SLAVE、COM_BINLOG_DUMP(按文件及position)、COM_BINLOG_DUMP_GTID(MASTER_AUTO_POSITION=1)
+//  事件按文件中的原样发送: 开始时发送artificial的ROTATE, 从文件中间开始时FORMAT_DESCRIPTION_EVENT的log_pos置为0,
+//  不以ROTATE结尾的文件(备份中断后重新开始)切换到下一个文件前补发artificial的ROTATE
+//  按gtid注册时从previous_gtids包含于从库已执行集合的最后一个文件开始, 跳过已执行的事务
+//  读到最后一个文件末尾时等待文件增长及新文件(备份仍在进行), 按从库设置的心跳间隔发送HEARTBEAT_LOG_EVENT,
+//  BINLOG_DUMP_NON_BLOCK时发送EOF包后结束
+//  server_id默认为第一个文件FORMAT_DESCRIPTION_EVENT中的server_id, 不能与从库相同, 与从库相同的事件会被从库跳过
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sha1::Sha1;
+use uuid::Uuid;
+use crate::{Config, readvalue};
+use crate::gtid::GtidSet;
+use crate::io::scramble;
+use crate::replication::encode;
+use crate::replication::readevent::{EventFlags, RotateLog};
+use crate::exitcode;
+
+const MAGIC: [u8; 4] = [0xFE, 0x62, 0x69, 0x6E];
+const NATIVE_PASSWORD: &str = "mysql_native_password";
+
+const CLIENT_CONNECT_WITH_DB: u32 = 0x8;
+const CLIENT_SECURE_CONNECTION: u32 = 0x8000;
+const CLIENT_PLUGIN_AUTH: u32 = 0x80000;
+const CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA: u32 = 0x200000;
+const CLIENT_DEPRECATE_EOF: u32 = 0x1000000;
+//long_password、found_rows、long_flag、protocol_41、transactions、multi_results、connect_attrs, 不支持压缩、ssl及连接时指定库
+const CAPABILITIES: u32 = 0x1 | 0x2 | 0x4 | 0x200 | 0x2000 | 0x20000 | 0x100000
+    | CLIENT_SECURE_CONNECTION | CLIENT_PLUGIN_AUTH | CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA | CLIENT_DEPRECATE_EOF;
+const SERVER_STATUS_AUTOCOMMIT: u16 = 0x2;
+const BINLOG_DUMP_NON_BLOCK: u16 = 0x1;
+
+//binlog文件来源, 每次注册及读到末尾时重新获取, 以发现备份新写入的文件
+enum Files{
+    Dir(PathBuf),
+    Index(String),
+}
+
+impl Files {
+    //按顺序排列的(文件名, 路径)
+    fn list(&self) -> io::Result<Vec<(String, PathBuf)>> {
+        let paths = match self {
+            Files::Dir(dir) => {
+                let mut paths = vec![];
+                for entry in fs::read_dir(dir)? {
+                    let path = entry?.path();
+                    if path.is_file() && is_binlog(&path) {
+                        paths.push(path);
+                    }
+                }
+                paths.sort();
+                paths
+            }
+            Files::Index(index) => crate::locate::read_index(index)?.into_iter().map(PathBuf::from).collect(),
+        };
+        Ok(paths.into_iter().map(|p| (file_name(&p), p)).collect())
+    }
+}
+
+fn is_binlog(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    File::open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && magic == MAGIC
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|t| t.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// 通过主从协议提供备份的binlog文件
+pub struct BinlogServer{
+    files: Files,
+    user: String,
+    password: String,
+    server_id: u32,
+    checksum: bool,
+    variables: BTreeMap<String, String>,
+    custom_uuid: bool,
+    poll: Duration,
+    capabilities: u32,
+    connections: AtomicU32,
+}
+
+impl BinlogServer {
+    /// 目录中以binlog magic开头的文件按文件名排序, 例如backup子命令的--output目录
+    pub fn open_dir<P: AsRef<Path>>(dir: P) -> io::Result<BinlogServer> {
+        BinlogServer::open(Files::Dir(dir.as_ref().to_path_buf()))
+    }
+
+    /// 按index文件(mysql-bin.index)中的顺序, 相对路径按index文件所在的目录
+    pub fn open_index(index: &str) -> io::Result<BinlogServer> {
+        BinlogServer::open(Files::Index(index.to_string()))
+    }
+
+    fn open(files: Files) -> io::Result<BinlogServer> {
+        let list = files.list()?;
+        let (name, path) = list.first().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "没有找到binlog文件"))?;
+        let fde = FileEvents::open(path)?.next()?
+            .filter(|e| e[4] == 15 && e.len() >= 19 + 2 + 50)
+            .ok_or_else(|| invalid(format!("binlog文件({})开头没有FORMAT_DESCRIPTION_EVENT", name)))?;
+        let server_id = readvalue::read_u32(&fde[5..9]);
+        //FORMAT_DESCRIPTION_EVENT末尾为checksum算法及crc32, 关闭checksum时crc32不匹配
+        let checksum = readvalue::crc32(&fde[..fde.len() - 4]) == readvalue::read_u32(&fde[fde.len() - 4..]);
+        let version: String = fde[21..71].iter().take_while(|b| **b != 0).map(|b| *b as char).collect();
+        let gtid_mode = if uses_gtid(&list)? { "ON" } else { "OFF" };
+        let purged = crate::locate::probe_file(&path.to_string_lossy())?.previous_gtids.unwrap_or_default();
+
+        let mut variables = BTreeMap::new();
+        for (name, value) in [
+            ("version", version.as_str()),
+            ("version_comment", "binlogAL binlog server"),
+            ("gtid_mode", gtid_mode),
+            ("enforce_gtid_consistency", gtid_mode),
+            ("gtid_purged", &purged.to_string()),
+            ("binlog_checksum", if checksum { "CRC32" } else { "NONE" }),
+            ("binlog_format", "ROW"),
+            ("log_bin", "ON"),
+            ("character_set_server", "utf8mb4"),
+            ("collation_server", "utf8mb4_general_ci"),
+            ("time_zone", "SYSTEM"),
+            ("system_time_zone", "UTC"),
+            ("max_allowed_packet", "1073741824"),
+            ("lower_case_table_names", "0"),
+        ].iter() {
+            variables.insert(name.to_string(), value.to_string());
+        }
+        let server = BinlogServer{
+            files,
+            user: String::new(),
+            password: String::new(),
+            server_id: 0,
+            checksum,
+            variables,
+            custom_uuid: false,
+            poll: Duration::from_millis(200),
+            capabilities: CAPABILITIES,
+            connections: AtomicU32::new(0),
+        };
+        Ok(server.server_id(server_id))
+    }
+
+    /// conf.index不为空时使用index文件, 否则使用conf.servedir目录, 从库使用conf.user_name、conf.password认证
+    pub fn from_config(conf: &Config) -> io::Result<BinlogServer> {
+        let mut server = if conf.index.is_empty() {
+            BinlogServer::open_dir(&conf.servedir)?
+        } else {
+            BinlogServer::open_index(&conf.index)?
+        };
+        server = server.credentials(&conf.user_name, conf.password.expose());
+        if !conf.serverid.is_empty() {
+            let server_id = conf.serverid.parse::<u32>().ok().filter(|t| *t > 0)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("无效的serverid: {}", conf.serverid)))?;
+            server = server.server_id(server_id);
+        }
+        if !conf.serveruuid.is_empty() {
+            Uuid::parse_str(&conf.serveruuid)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("无效的server-uuid: {}", conf.serveruuid)))?;
+            server = server.server_uuid(&conf.serveruuid);
+        }
+        Ok(server)
+    }
+
+    /// 从库连接使用的用户名、密码, 默认都为空
+    pub fn credentials(mut self, user: &str, password: &str) -> BinlogServer {
+        self.user = user.to_string();
+        self.password = password.to_string();
+        self
+    }
+
+    /// 同时修改@@server_id, 没有指定server_uuid时按server_id生成
+    pub fn server_id(mut self, server_id: u32) -> BinlogServer {
+        self.server_id = server_id;
+        self.variables.insert(String::from("server_id"), server_id.to_string());
+        if !self.custom_uuid {
+            self.variables.insert(String::from("server_uuid"), generated_uuid(server_id));
+        }
+        self
+    }
+
+    pub fn server_uuid(self, uuid: &str) -> BinlogServer {
+        self.variable("server_uuid", uuid)
+    }
+
+    /// 从库查询的系统变量(SELECT @@name、SHOW VARIABLES), 变量名不区分大小写
+    pub fn variable(mut self, name: &str, value: &str) -> BinlogServer {
+        self.custom_uuid |= name.eq_ignore_ascii_case("server_uuid");
+        self.variables.insert(name.to_lowercase(), value.to_string());
+        self
+    }
+
+    /// 读到最后一个文件末尾时检查文件增长的间隔, 默认200ms
+    pub fn poll_interval(mut self, interval: Duration) -> BinlogServer {
+        self.poll = interval;
+        self
+    }
+
+    /// 握手包中不声明的标识(如CLIENT_DEPRECATE_EOF 0x1000000、CLIENT_PLUGIN_AUTH 0x80000), 用于模拟修改了标识的代理;
+    /// 只使用双方都声明的标识
+    pub fn without_capabilities(mut self, flags: u32) -> BinlogServer {
+        self.capabilities &= !flags;
+        self
+    }
+
+    /// 在后台线程中监听, 每个连接一个线程, 返回实际监听的地址(端口为0时由系统分配)
+    pub fn serve(self, addr: &str) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        let server = Arc::new(self);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = server.clone();
+                thread::spawn(move || {
+                    //连接出错(从库断开等)只结束该连接
+                    let _ = Session::new(&server, stream).run();
+                });
+            }
+        });
+        Ok(local)
+    }
+
+    //dump开始、切换文件时的ROTATE及心跳, timestamp为0, 带LOG_EVENT_ARTIFICIAL_F
+    fn artificial(&self, type_code: u8, body: &[u8], next_position: u32) -> Vec<u8> {
+        let mut event = encode::event(0, type_code, self.server_id, body, self.checksum);
+        event[13..17].copy_from_slice(&readvalue::write_u32(next_position));
+        event[17..19].copy_from_slice(&readvalue::write_u16(EventFlags::LOG_EVENT_ARTIFICIAL_F.bits()));
+        if self.checksum {
+            reseal(&mut event);
+        }
+        event
+    }
+
+    fn rotate(&self, binlog_file: &str, position: u64) -> Vec<u8> {
+        let body = encode::rotate_body(&RotateLog{ binlog_file: binlog_file.to_string(), position, artificial: true });
+        self.artificial(4, &body, 0)
+    }
+}
+
+//没有指定server_uuid时按server_id生成
+fn generated_uuid(server_id: u32) -> String {
+    format!("{:08x}-0000-4000-8000-000000000000", server_id)
+}
+
+fn reseal(event: &mut [u8]) {
+    let len = event.len();
+    let crc = readvalue::crc32(&event[..len - 4]);
+    event[len - 4..].copy_from_slice(&readvalue::write_u32(crc));
+}
+
+//是否开启了gtid: 按事件头扫描, 遇到第一个GTID_LOG_EVENT或ANONYMOUS_GTID_LOG_EVENT为止
+fn uses_gtid(files: &[(String, PathBuf)]) -> io::Result<bool> {
+    for (_, path) in files {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut pos = 4;
+        let mut header = [0u8; 19];
+        while reader.seek(SeekFrom::Start(pos)).is_ok() && reader.read_exact(&mut header).is_ok() {
+            match header[4] {
+                33 => return Ok(true),
+                34 => return Ok(false),
+                _ => {}
+            }
+            let event_length = readvalue::read_u32(&header[9..13]) as u64;
+            if event_length < 19 {
+                break;
+            }
+            pos += event_length;
+        }
+    }
+    Ok(false)
+}
+
+//按gtid注册时的第一个文件: previous_gtids包含于从库已执行集合的最后一个文件, 需要的事务已被清理时为None
+fn start_file(files: &[(String, PathBuf)], executed: &GtidSet) -> io::Result<Option<usize>> {
+    for idx in (0..files.len()).rev() {
+        match crate::locate::probe_file(&files[idx].1.to_string_lossy())?.previous_gtids {
+            None => return Ok(Some(0)),
+            Some(previous) if executed.contains_set(&previous) => return Ok(Some(idx)),
+            Some(_) => {}
+        }
+    }
+    Ok(None)
+}
+
+//按event_length读取文件中的事件, 不完整的事件(文件正在写入)返回None并回到该事件开头
+struct FileEvents{
+    reader: BufReader<File>,
+    offset: u64,
+}
+
+impl FileEvents {
+    fn open(path: &Path) -> io::Result<FileEvents> {
+        let mut reader = BufReader::new(File::open(path)?);
+        reader.seek(SeekFrom::Start(4))?;
+        Ok(FileEvents{ reader, offset: 4 })
+    }
+
+    fn next(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut event = vec![0u8; 19];
+        if !self.read(&mut event)? {
+            return Ok(None);
+        }
+        let event_length = readvalue::read_u32(&event[9..13]) as usize;
+        if event_length < 19 {
+            return Err(invalid(format!("position {} 处的事件长度错误", self.offset)));
+        }
+        event.resize(event_length, 0);
+        if !self.read(&mut event[19..])? {
+            return Ok(None);
+        }
+        self.offset += event_length as u64;
+        Ok(Some(event))
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                self.reader.seek(SeekFrom::Start(self.offset))?;
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+//从库发送的HandshakeResponse41
+struct HandshakeResponse{
+    capabilities: u32,
+    user: String,
+    auth: Vec<u8>,
+    plugin: String,
+}
+
+impl HandshakeResponse {
+    fn parse(buf: &[u8]) -> Option<HandshakeResponse> {
+        let capabilities = readvalue::read_u32(buf.get(..4)?);
+        //max_packet_size、字符集、23bytes保留
+        let mut offset = 32;
+        let user = null_terminated(buf.get(offset..)?)?;
+        offset += user.len() + 1;
+        let auth = if capabilities & (CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA | CLIENT_SECURE_CONNECTION) > 0 {
+            let len = *buf.get(offset)? as usize;
+            if len >= 0xFB {
+                return None;
+            }
+            offset += 1 + len;
+            buf.get(offset - len..offset)?
+        } else {
+            let auth = null_terminated(buf.get(offset..)?)?;
+            offset += auth.len() + 1;
+            auth
+        };
+        if capabilities & CLIENT_CONNECT_WITH_DB > 0 {
+            offset += null_terminated(buf.get(offset..)?)?.len() + 1;
+        }
+        let plugin = match buf.get(offset..) {
+            Some(rest) if capabilities & CLIENT_PLUGIN_AUTH > 0 => null_terminated(rest).unwrap_or(rest),
+            _ => &[],
+        };
+        Some(HandshakeResponse{
+            capabilities,
+            user: String::from_utf8_lossy(user).to_string(),
+            auth: auth.to_vec(),
+            plugin: String::from_utf8_lossy(plugin).to_string(),
+        })
+    }
+}
+
+fn null_terminated(buf: &[u8]) -> Option<&[u8]> {
+    buf.iter().position(|b| *b == 0).map(|i| &buf[..i])
+}
+
+//从库的注册请求, executed不为None时为COM_BINLOG_DUMP_GTID
+struct DumpRequest{
+    file: String,
+    position: u64,
+    flags: u16,
+    executed: Option<GtidSet>,
+}
+
+impl DumpRequest {
+    //0x12, position(4) flags(2) server_id(4) 文件名
+    fn binlog(packet: &[u8]) -> Option<DumpRequest> {
+        Some(DumpRequest{
+            position: readvalue::read_u32(packet.get(1..5)?) as u64,
+            flags: readvalue::read_u16(packet.get(5..7)?),
+            file: String::from_utf8_lossy(packet.get(11..)?).trim_end_matches('\0').to_string(),
+            executed: None,
+        })
+    }
+
+    //0x1e, flags(2) server_id(4) 文件名长度(4) 文件名 position(8) gtid集合长度(4) gtid集合
+    fn gtid(packet: &[u8]) -> Option<DumpRequest> {
+        let flags = readvalue::read_u16(packet.get(1..3)?);
+        let name_len = readvalue::read_u32(packet.get(7..11)?) as usize;
+        let file = String::from_utf8_lossy(packet.get(11..11 + name_len)?).trim_end_matches('\0').to_string();
+        let mut offset = 11 + name_len;
+        let position = readvalue::read_u64(packet.get(offset..offset + 8)?);
+        offset += 8;
+        let executed = match packet.get(offset..offset + 4) {
+            Some(t) => {
+                let size = readvalue::read_u32(t) as usize;
+                GtidSet::decode(packet.get(offset + 4..offset + 4 + size)?).ok()?
+            }
+            None => GtidSet::new(),
+        };
+        Some(DumpRequest{ file, position, flags, executed: Some(executed) })
+    }
+}
+
+//一个从库连接
+struct Session<'a>{
+    server: &'a BinlogServer,
+    stream: TcpStream,
+    seq: u8,
+    capabilities: u32,
+    //SET @name = value设置的用户变量, 变量名为小写
+    user_vars: HashMap<String, String>,
+}
+
+impl<'a> Session<'a> {
+    fn new(server: &'a BinlogServer, stream: TcpStream) -> Session<'a> {
+        Session{ server, stream, seq: 0, capabilities: 0, user_vars: HashMap::new() }
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        if !self.handshake()? {
+            return Ok(());
+        }
+        loop {
+            let packet = self.read_packet()?;
+            match packet.first() {
+                //COM_QUIT
+                Some(0x01) => return Ok(()),
+                Some(0x03) => {
+                    let sql = String::from_utf8_lossy(&packet[1..]).to_string();
+                    self.query(&sql)?;
+                }
+                //COM_INIT_DB、COM_PING、COM_REGISTER_SLAVE
+                Some(0x02) | Some(0x0E) | Some(0x15) => self.ok()?,
+                Some(0x12) | Some(0x1E) => {
+                    let request = if packet[0] == 0x12 { DumpRequest::binlog(&packet) } else { DumpRequest::gtid(&packet) };
+                    match request {
+                        Some(request) => self.dump(request)?,
+                        None => self.error(1236, "HY000", "COM_BINLOG_DUMP包格式错误")?,
+                    }
+                }
+                _ => self.error(1047, "08S01", "Unknown command")?,
+            }
+        }
+    }
+
+    //包头: payload长度(3) + 序号(1), 超过16M的包分为多个
+    fn read_packet(&mut self) -> io::Result<Vec<u8>> {
+        let mut payload = vec![];
+        loop {
+            let mut header = [0u8; 4];
+            self.stream.read_exact(&mut header)?;
+            let len = readvalue::read_u24(&header[..3]) as usize;
+            self.seq = header[3].wrapping_add(1);
+            let start = payload.len();
+            payload.resize(start + len, 0);
+            self.stream.read_exact(&mut payload[start..])?;
+            if len < 0xFFFFFF {
+                return Ok(payload);
+            }
+        }
+    }
+
+    fn write_packet(&mut self, payload: &[u8]) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(payload.len() + 4);
+        let mut offset = 0;
+        loop {
+            let len = (payload.len() - offset).min(0xFFFFFF);
+            buf.extend(readvalue::write_u24(len as u32));
+            buf.push(self.seq);
+            self.seq = self.seq.wrapping_add(1);
+            buf.extend_from_slice(&payload[offset..offset + len]);
+            offset += len;
+            if len < 0xFFFFFF {
+                break;
+            }
+        }
+        self.stream.write_all(&buf)
+    }
+
+    fn ok(&mut self) -> io::Result<()> {
+        let mut payload = vec![0x00, 0x00, 0x00];
+        payload.extend(readvalue::write_u16(SERVER_STATUS_AUTOCOMMIT));
+        payload.extend(readvalue::write_u16(0));
+        self.write_packet(&payload)
+    }
+
+    //结果集结束: CLIENT_DEPRECATE_EOF时为0xFE开头的ok包
+    fn eof(&mut self) -> io::Result<()> {
+        let mut payload = vec![0xFE];
+        if self.capabilities & CLIENT_DEPRECATE_EOF > 0 {
+            payload.extend([0x00, 0x00]);
+            payload.extend(readvalue::write_u16(SERVER_STATUS_AUTOCOMMIT));
+            payload.extend(readvalue::write_u16(0));
+        } else {
+            payload.extend(readvalue::write_u16(0));
+            payload.extend(readvalue::write_u16(SERVER_STATUS_AUTOCOMMIT));
+        }
+        self.write_packet(&payload)
+    }
+
+    fn error(&mut self, code: u16, state: &str, message: &str) -> io::Result<()> {
+        let mut payload = vec![0xFF];
+        payload.extend(readvalue::write_u16(code));
+        payload.push(b'#');
+        payload.extend(state.as_bytes());
+        payload.extend(message.as_bytes());
+        self.write_packet(&payload)
+    }
+
+    //HandshakeV10, 只支持mysql_native_password, 客户端使用其他方式时发送AuthSwitchRequest
+    fn handshake(&mut self) -> io::Result<bool> {
+        let thread_id = self.server.connections.fetch_add(1, Ordering::SeqCst) + 1;
+        let nonce = nonce(thread_id);
+        let mut payload = vec![10];
+        payload.extend(self.server.variables.get("version").map(|t| t.as_bytes()).unwrap_or_default());
+        payload.push(0);
+        payload.extend(readvalue::write_u32(thread_id));
+        payload.extend_from_slice(&nonce[..8]);
+        payload.push(0);
+        let capabilities = self.server.capabilities;
+        payload.extend(readvalue::write_u16(capabilities as u16));
+        payload.push(33);   //utf8_general_ci
+        payload.extend(readvalue::write_u16(SERVER_STATUS_AUTOCOMMIT));
+        payload.extend(readvalue::write_u16((capabilities >> 16) as u16));
+        //没有CLIENT_PLUGIN_AUTH时为0, 也不发送插件名
+        payload.push(if capabilities & CLIENT_PLUGIN_AUTH > 0 { nonce.len() as u8 + 1 } else { 0 });
+        payload.extend([0u8; 10]);
+        payload.extend_from_slice(&nonce[8..]);
+        payload.push(0);
+        if capabilities & CLIENT_PLUGIN_AUTH > 0 {
+            payload.extend(NATIVE_PASSWORD.as_bytes());
+            payload.push(0);
+        }
+        self.seq = 0;
+        self.write_packet(&payload)?;
+
+        let packet = self.read_packet()?;
+        let response = HandshakeResponse::parse(&packet).ok_or_else(|| invalid(String::from("握手回包格式错误")))?;
+        self.capabilities = response.capabilities & capabilities;
+        let mut auth = response.auth;
+        //客户端没有指定认证方式(插件名为空)时与mysql一致同样要求切换
+        if self.capabilities & CLIENT_PLUGIN_AUTH > 0 && response.plugin != NATIVE_PASSWORD {
+            let mut switch = vec![0xFE];
+            switch.extend(NATIVE_PASSWORD.as_bytes());
+            switch.push(0);
+            switch.extend_from_slice(&nonce);
+            switch.push(0);
+            self.write_packet(&switch)?;
+            auth = self.read_packet()?;
+        }
+        //密码为空时客户端不发送校验数据
+        let expected = scramble::scramble_native(&nonce, self.server.password.as_bytes()).map(|t| t.to_vec()).unwrap_or_default();
+        if response.user != self.server.user || auth != expected {
+            self.error(1045, "28000", &format!("Access denied for user '{}'", response.user))?;
+            return Ok(false);
+        }
+        self.ok()?;
+        Ok(true)
+    }
+
+    //从库io线程执行的语句: SELECT常量/变量、SET、SHOW VARIABLES, 其余返回错误
+    fn query(&mut self, sql: &str) -> io::Result<()> {
+        let sql = sql.trim().trim_end_matches(';').trim();
+        let words: Vec<&str> = sql.split_whitespace().collect();
+        let keyword = words.first().map(|t| t.to_lowercase()).unwrap_or_default();
+        if keyword == "set" {
+            for item in split_list(sql[3..].trim()) {
+                //只记录用户变量, SET NAMES、会话变量忽略
+                if let Some((name, value)) = item.split_once('=') {
+                    if let Some(name) = name.trim().strip_prefix('@').filter(|t| !t.starts_with('@')) {
+                        match self.evaluate(value) {
+                            Ok(value) => self.user_vars.insert(name.trim_matches('`').to_lowercase(), value.unwrap_or_default()),
+                            Err((code, message)) => return self.error(code, "HY000", &message),
+                        };
+                    }
+                }
+            }
+            return self.ok();
+        }
+        if keyword == "select" {
+            let mut columns = vec![];
+            let mut row = vec![];
+            //select @@version_comment limit 1等只有一行, 忽略limit
+            let mut list = sql[6..].trim();
+            if let Some(i) = list.to_lowercase().rfind(" limit ").filter(|i| list[i + 7..].trim().parse::<u64>().is_ok()) {
+                list = list[..i].trim();
+            }
+            for item in split_list(list) {
+                let (expr, alias) = split_alias(item);
+                match self.evaluate(expr) {
+                    Ok(value) => row.push(value),
+                    Err((code, message)) => return self.error(code, "HY000", &message),
+                }
+                columns.push(alias.to_string());
+            }
+            return self.result_set(&columns, &[row]);
+        }
+        if keyword == "show" {
+            let mut i = 1;
+            if words.get(i).is_some_and(|t| t.eq_ignore_ascii_case("global") || t.eq_ignore_ascii_case("session")) {
+                i += 1;
+            }
+            if words.get(i).is_some_and(|t| t.eq_ignore_ascii_case("variables")) {
+                let pattern = match words.get(i + 1) {
+                    Some(t) if t.eq_ignore_ascii_case("like") => words[i + 2..].join(" ").trim_matches(|c| c == '\'' || c == '"').to_string(),
+                    _ => String::from("%"),
+                };
+                let rows: Vec<Vec<Option<String>>> = self.server.variables.iter()
+                    .filter(|(name, _)| like(&pattern, name))
+                    .map(|(name, value)| vec![Some(name.clone()), Some(value.clone())])
+                    .collect();
+                return self.result_set(&[String::from("Variable_name"), String::from("Value")], &rows);
+            }
+        }
+        self.error(1235, "42000", &format!("binlog server不支持该语句: {}", sql))
+    }
+
+    //表达式的值, NULL为None, 未知的系统变量返回(错误码, 错误信息)
+    fn evaluate(&self, expr: &str) -> Result<Option<String>, (u16, String)> {
+        let expr = expr.trim();
+        let lower = expr.to_lowercase();
+        if lower == "unix_timestamp()" {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+            return Ok(Some(now.to_string()));
+        }
+        if lower == "version()" {
+            return Ok(self.server.variables.get("version").cloned());
+        }
+        if let Some(name) = lower.strip_prefix("@@") {
+            let name = name.strip_prefix("global.").or_else(|| name.strip_prefix("session.")).unwrap_or(name);
+            return match self.server.variables.get(name) {
+                Some(value) => Ok(Some(value.clone())),
+                None => Err((1193, format!("Unknown system variable '{}'", name))),
+            };
+        }
+        if let Some(name) = lower.strip_prefix('@') {
+            return Ok(self.user_vars.get(name.trim_matches('`')).cloned());
+        }
+        if lower == "null" {
+            return Ok(None);
+        }
+        let quoted = expr.len() >= 2 && (expr.starts_with('\'') && expr.ends_with('\'') || expr.starts_with('"') && expr.ends_with('"'));
+        Ok(Some(if quoted { expr[1..expr.len() - 1].to_string() } else { expr.to_string() }))
+    }
+
+    //文本协议的结果集, 字段都为VAR_STRING
+    fn result_set(&mut self, columns: &[String], rows: &[Vec<Option<String>>]) -> io::Result<()> {
+        self.write_packet(&encode::lenenc(columns.len()))?;
+        for name in columns {
+            let mut payload = vec![];
+            for field in ["def", "", "", "", name, name].iter() {
+                payload.extend(encode::lenenc(field.len()));
+                payload.extend(field.as_bytes());
+            }
+            payload.push(0x0C);
+            payload.extend(readvalue::write_u16(33));
+            payload.extend(readvalue::write_u32(1024));
+            payload.push(0xFD);
+            payload.extend(readvalue::write_u16(0));
+            payload.push(0x1F);
+            payload.extend([0u8; 2]);
+            self.write_packet(&payload)?;
+        }
+        if self.capabilities & CLIENT_DEPRECATE_EOF == 0 {
+            self.eof()?;
+        }
+        for row in rows {
+            let mut payload = vec![];
+            for value in row {
+                match value {
+                    Some(v) => {
+                        payload.extend(encode::lenenc(v.len()));
+                        payload.extend(v.as_bytes());
+                    }
+                    None => payload.push(0xFB),
+                }
+            }
+            self.write_packet(&payload)?;
+        }
+        self.eof()
+    }
+
+    //发送一个事件, 每个事件为一个包: 0x00 + 事件
+    fn event(&mut self, event: &[u8]) -> io::Result<()> {
+        let mut payload = Vec::with_capacity(event.len() + 1);
+        payload.push(0x00);
+        payload.extend_from_slice(event);
+        self.write_packet(&payload)
+    }
+
+    //从库设置的@master_heartbeat_period(8.0.26之后为@source_heartbeat_period), 单位为纳秒
+    fn heartbeat_period(&self) -> Option<Duration> {
+        ["master_heartbeat_period", "source_heartbeat_period"].iter()
+            .find_map(|name| self.user_vars.get(*name))
+            .and_then(|t| t.parse::<f64>().ok())
+            .filter(|t| *t > 0.0)
+            .map(|t| Duration::from_nanos(t as u64))
+    }
+
+    //从库是否已断开连接, 等待文件增长期间没有心跳时据此结束
+    fn closed(&self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0u8; 1];
+        let closed = match self.stream.peek(&mut buf) {
+            Ok(0) => true,
+            Ok(_) => false,
+            Err(e) => e.kind() != io::ErrorKind::WouldBlock,
+        };
+        self.stream.set_nonblocking(false)?;
+        Ok(closed)
+    }
+
+    fn dump(&mut self, request: DumpRequest) -> io::Result<()> {
+        let server = self.server;
+        if server.checksum && !self.user_vars.contains_key("master_binlog_checksum") {
+            return self.error(1236, "HY000", "Slave can not handle replication events with the checksum that master is configured to log");
+        }
+        let mut files = server.files.list()?;
+        let (mut index, mut position) = match &request.executed {
+            Some(executed) => match start_file(&files, executed)? {
+                Some(t) => (t, 4),
+                None => return self.error(1236, "HY000", "The slave is connecting using CHANGE MASTER TO MASTER_AUTO_POSITION = 1, but the master has purged binary logs containing GTIDs that the slave requires."),
+            },
+            None if request.file.is_empty() => (0, 4),
+            None => match files.iter().position(|(name, _)| name == &request.file) {
+                Some(t) => (t, request.position.max(4)),
+                None => return self.error(1236, "HY000", "Could not find first log file name in binary log index file"),
+            },
+        };
+        let heartbeat = self.heartbeat_period();
+        let rotate = server.rotate(&files[index].0, position);
+        self.event(&rotate)?;
+        loop {
+            let (name, path) = files[index].clone();
+            let mut events = FileEvents::open(&path)?;
+            let mut log_pos = position;
+            let mut last_type = 0;
+            let mut skipping = false;
+            let mut idle = Instant::now();
+            loop {
+                let mut event = match events.next()? {
+                    Some(t) => t,
+                    None => {
+                        //当前文件已读完: 有下一个文件时切换, 否则等待文件增长
+                        let latest = server.files.list()?;
+                        let next = latest.iter().position(|(n, _)| n == &name).map(|i| i + 1).filter(|i| *i < latest.len());
+                        if let Some(next) = next {
+                            if fs::metadata(&path)?.len() <= events.offset {
+                                if last_type != 4 {
+                                    let rotate = server.rotate(&latest[next].0, 4);
+                                    self.event(&rotate)?;
+                                }
+                                files = latest;
+                                index = next;
+                                position = 4;
+                                break;
+                            }
+                            continue;
+                        }
+                        if request.flags & BINLOG_DUMP_NON_BLOCK > 0 {
+                            return self.eof();
+                        }
+                        if self.closed()? {
+                            return Ok(());
+                        }
+                        if heartbeat.is_some_and(|t| idle.elapsed() >= t) {
+                            let beat = server.artificial(27, name.as_bytes(), log_pos as u32);
+                            self.event(&beat)?;
+                            idle = Instant::now();
+                        }
+                        thread::sleep(server.poll);
+                        continue;
+                    }
+                };
+                let next_position = readvalue::read_u32(&event[13..17]) as u64;
+                match event[4] {
+                    //从文件中间开始时FORMAT_DESCRIPTION_EVENT的log_pos置为0, 从库不据此更新读取位置
+                    15 if position > 4 => {
+                        event[13..17].copy_from_slice(&readvalue::write_u32(0));
+                        if server.checksum {
+                            reseal(&mut event);
+                        }
+                    }
+                    _ if next_position <= position => continue,
+                    33 => {
+                        skipping = match (&request.executed, Uuid::from_slice(&event[20..36])) {
+                            (Some(executed), Ok(sid)) => executed.contains(&sid, readvalue::read_u64(&event[36..44])),
+                            _ => false,
+                        };
+                    }
+                    3 | 4 | 34 | 35 => skipping = false,
+                    _ => {}
+                }
+                if skipping {
+                    continue;
+                }
+                if next_position > 0 {
+                    log_pos = next_position;
+                }
+                last_type = event[4];
+                self.event(&event)?;
+                idle = Instant::now();
+            }
+        }
+    }
+}
+
+//认证用的20bytes随机数, 与mysql一致只使用可打印字符
+fn nonce(thread_id: u32) -> [u8; 20] {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut hasher = Sha1::new();
+    hasher.update(&now.as_nanos().to_le_bytes());
+    hasher.update(&thread_id.to_le_bytes());
+    let mut nonce = hasher.digest().bytes();
+    for b in nonce.iter_mut() {
+        *b = 0x21 + *b % 94;
+    }
+    nonce
+}
+
+//按逗号分隔, 不切分引号、括号中的内容
+fn split_list(list: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+    for (i, c) in list.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') | (None, '`') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                items.push(list[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(list[start..].trim());
+    items.into_iter().filter(|t| !t.is_empty()).collect()
+}
+
+//expr [AS] alias, 没有别名时字段名为表达式本身
+fn split_alias(item: &str) -> (&str, &str) {
+    match item.to_lowercase().rfind(" as ") {
+        Some(i) => (item[..i].trim(), item[i + 4..].trim().trim_matches(|c| c == '`' || c == '\'' || c == '"')),
+        None => (item, item),
+    }
+}
+
+//LIKE匹配, %为任意个字符, _为一个字符, 不区分大小写
+fn like(pattern: &str, value: &str) -> bool {
+    fn matches(p: &[char], v: &[char]) -> bool {
+        match p.split_first() {
+            None => v.is_empty(),
+            Some(('%', rest)) => (0..=v.len()).any(|i| matches(rest, &v[i..])),
+            Some(('_', rest)) => !v.is_empty() && matches(rest, &v[1..]),
+            Some((c, rest)) => v.first().is_some_and(|x| x.eq_ignore_ascii_case(c)) && matches(rest, &v[1..]),
+        }
+    }
+    let p: Vec<char> = pattern.chars().collect();
+    let v: Vec<char> = value.chars().collect();
+    matches(&p, &v)
+}
+
+/// serve子命令: 监听conf.listen, 直到进程退出
+pub fn run(conf: &Config) {
+    let server = BinlogServer::from_config(conf).unwrap_or_else(|err|{
+        println!("打开binlog文件发生错误: {}", err);
+        process::exit(exitcode::USAGE);
+    });
+    let files = server.files.list().map(|t| t.len()).unwrap_or(0);
+    let server_id = server.server_id;
+    let addr = server.serve(&conf.listen).unwrap_or_else(|err|{
+        println!("监听{}发生错误: {}", conf.listen, err);
+        process::exit(exitcode::ERROR);
+    });
+    println!("binlog server已启动: {}, server_id: {}, binlog文件: {}个", addr, server_id, files);
+    loop {
+        thread::park();
+    }
+}
@@ -0,0 +1,365 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//进程内缓存数据的内存上限及统计, 用于在内存受限的容器中运行
+//  计入上限的缓存: QueuedSink队列中的事件、Batcher缓存的批量、OrderedMerge等待输出的事务
+//  按事件的大小(event_length)估算, 解析后的实际占用会更大, 上限需要留出余量
+//  超过上限时的处理方式(MemoryPolicy):
+//    Block: 读取端等待缓存写出后再继续, 队列等待后台线程写出, Batcher、OrderedMerge提前写出缓存的数据(OrderedMerge不再等待其他源)
+//    Spill: 缓存中的行事件编码后写入临时文件, 输出时再读回, 不改变输出的时机及顺序; 队列中的事件不能写入文件, 与Block相同等待
+//  无法编码的行事件(bit、geometry等类型)不写入文件, 仍然保存在内存中
+//  通过stats()查看, --control的/status中为memory
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use serde_json::{json, Value as JsonValue};
+use crate::meta::ReadType;
+use crate::reader::Event;
+use crate::replication::encode;
+use crate::replication::parsevalue::RowValue;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::{BinlogEvent, EventHeader, TableMap};
+
+/// 超过内存上限时的处理方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryPolicy {
+    Block,
+    /// 写入该目录下的临时文件
+    Spill(PathBuf),
+}
+
+impl MemoryPolicy {
+    /// block(默认)、spill(系统临时目录)、spill:目录
+    pub fn parse(value: &str) -> Result<MemoryPolicy, String> {
+        match value.trim() {
+            "" | "block" => Ok(MemoryPolicy::Block),
+            "spill" => Ok(MemoryPolicy::Spill(std::env::temp_dir())),
+            t => match t.strip_prefix("spill:") {
+                Some(dir) if !dir.is_empty() => Ok(MemoryPolicy::Spill(PathBuf::from(dir))),
+                _ => Err(format!("无效的memory-policy: {}, 可选值为block、spill、spill:目录", t)),
+            }
+        }
+    }
+}
+
+/// 内存的使用情况
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats{
+    /// 上限的字节数, 0为不限制
+    pub limit: u64,
+    pub used: u64,
+    /// 出现过的最大使用量
+    pub peak: u64,
+    /// 超过上限的次数
+    pub exceeded: u64,
+    /// 因超过上限等待的次数
+    pub blocked: u64,
+    /// 写入临时文件的行事件数及字节数
+    pub spilled_events: u64,
+    pub spilled_bytes: u64,
+}
+
+impl MemoryStats {
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "limit": self.limit,
+            "used": self.used,
+            "peak": self.peak,
+            "exceeded": self.exceeded,
+            "blocked": self.blocked,
+            "spilled_events": self.spilled_events,
+            "spilled_bytes": self.spilled_bytes,
+        })
+    }
+}
+
+static LIMIT: AtomicU64 = AtomicU64::new(0);
+static SPILL_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+static USED: AtomicU64 = AtomicU64::new(0);
+static PEAK: AtomicU64 = AtomicU64::new(0);
+static EXCEEDED: AtomicU64 = AtomicU64::new(0);
+static BLOCKED: AtomicU64 = AtomicU64::new(0);
+static SPILLED_EVENTS: AtomicU64 = AtomicU64::new(0);
+static SPILLED_BYTES: AtomicU64 = AtomicU64::new(0);
+static SPILL_SEQ: AtomicU64 = AtomicU64::new(0);
+//释放内存时唤醒等待的线程
+static RELEASE_LOCK: Mutex<()> = Mutex::new(());
+static RELEASED: Condvar = Condvar::new();
+
+/// 设置内存上限(字节数, 0为不限制)及超过上限时的处理方式, 对之后缓存的数据生效
+pub fn configure(limit: u64, policy: MemoryPolicy) {
+    LIMIT.store(limit, Ordering::Relaxed);
+    if let Ok(mut dir) = SPILL_DIR.lock() {
+        *dir = match policy {
+            MemoryPolicy::Block => None,
+            MemoryPolicy::Spill(t) => Some(t),
+        };
+    }
+    RELEASED.notify_all();
+}
+
+/// 解析--memory-limit的值: 字节数, 可以带K、M、G后缀(1024进制)
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().last() {
+        Some((idx, c)) if c.is_ascii_alphabetic() => (&value[..idx], c.to_ascii_uppercase()),
+        _ => (value, 'B'),
+    };
+    let shift = match unit {
+        'B' => 0,
+        'K' => 10,
+        'M' => 20,
+        'G' => 30,
+        _ => return Err(format!("无效的memory-limit: {}, 单位可以为K、M、G", value)),
+    };
+    match number.trim().parse::<u64>() {
+        Ok(t) if t.leading_zeros() >= shift => Ok(t << shift),
+        _ => Err(format!("无效的memory-limit: {}", value)),
+    }
+}
+
+pub fn policy() -> MemoryPolicy {
+    match spill_dir() {
+        Some(t) => MemoryPolicy::Spill(t),
+        None => MemoryPolicy::Block,
+    }
+}
+
+fn spill_dir() -> Option<PathBuf> {
+    SPILL_DIR.lock().ok().and_then(|t| t.clone())
+}
+
+/// 当前的使用情况
+pub fn stats() -> MemoryStats {
+    MemoryStats{
+        limit: LIMIT.load(Ordering::Relaxed),
+        used: USED.load(Ordering::Relaxed),
+        peak: PEAK.load(Ordering::Relaxed),
+        exceeded: EXCEEDED.load(Ordering::Relaxed),
+        blocked: BLOCKED.load(Ordering::Relaxed),
+        spilled_events: SPILLED_EVENTS.load(Ordering::Relaxed),
+        spilled_bytes: SPILLED_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+/// 当前是否超过上限
+pub fn exceeded() -> bool {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    limit > 0 && USED.load(Ordering::Relaxed) > limit
+}
+
+//再使用bytes后是否超过上限
+fn would_exceed(bytes: u64) -> bool {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    limit > 0 && USED.load(Ordering::Relaxed) + bytes > limit
+}
+
+/// 事件计入的字节数
+pub fn event_size(event: &Event) -> u64 {
+    event.header.event_length as u64
+}
+
+/// 已计入使用量的内存, drop时释放
+#[derive(Debug, Default)]
+pub struct Reservation{
+    bytes: u64,
+}
+
+impl Reservation {
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    fn grow(&mut self, bytes: u64) {
+        let used = USED.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        PEAK.fetch_max(used, Ordering::Relaxed);
+        let limit = LIMIT.load(Ordering::Relaxed);
+        if limit > 0 && used > limit && used - bytes <= limit {
+            EXCEEDED.fetch_add(1, Ordering::Relaxed);
+        }
+        self.bytes += bytes;
+    }
+
+    fn release(&mut self) {
+        if self.bytes > 0 {
+            USED.fetch_sub(self.bytes, Ordering::Relaxed);
+            self.bytes = 0;
+            let _guard = RELEASE_LOCK.lock();
+            RELEASED.notify_all();
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// 计入使用量, 不等待
+pub fn reserve(bytes: u64) -> Reservation {
+    let mut reservation = Reservation::default();
+    reservation.grow(bytes);
+    reservation
+}
+
+/// 超过上限时等待其他线程释放后再计入, waiting()返回false(没有可以等待释放的数据)时不再等待, 避免单个事件大于上限时一直等待
+pub fn reserve_blocking<F: Fn() -> bool>(bytes: u64, waiting: F) -> Reservation {
+    if would_exceed(bytes) && waiting() {
+        BLOCKED.fetch_add(1, Ordering::Relaxed);
+        let mut guard = match RELEASE_LOCK.lock() {
+            Ok(t) => t,
+            Err(e) => e.into_inner(),
+        };
+        while would_exceed(bytes) && waiting() {
+            //释放时未持有锁的情况下可能错过通知, 定时重新检查
+            guard = match RELEASED.wait_timeout(guard, Duration::from_millis(100)) {
+                Ok((t, _)) => t,
+                Err(e) => e.into_inner().0,
+            };
+        }
+    }
+    reserve(bytes)
+}
+
+//写入临时文件的行事件, 表结构及包头保存在内存中
+struct Spilled{
+    header: EventHeader,
+    log_pos: u64,
+    code: BinlogEvent,
+    table: TableMap,
+    offset: u64,
+    len: usize,
+}
+
+enum Slot{
+    Memory(Event),
+    Spilled(Spilled),
+}
+
+//临时文件, drop时删除
+struct SpillFile{
+    path: PathBuf,
+    file: File,
+    len: u64,
+}
+
+impl SpillFile {
+    fn create(dir: &Path) -> io::Result<SpillFile> {
+        let path = dir.join(format!("binlogal-spill-{}-{}.tmp", std::process::id(), SPILL_SEQ.fetch_add(1, Ordering::Relaxed)));
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("创建临时文件{}发生错误: {}", path.display(), e)))?;
+        Ok(SpillFile{ path, file, len: 0 })
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// 计入内存上限的事件缓存, Spill策略下超过上限时行事件写入临时文件
+#[derive(Default)]
+pub struct EventBuffer{
+    slots: Vec<Slot>,
+    reserved: Reservation,
+    file: Option<SpillFile>,
+}
+
+impl EventBuffer {
+    pub fn new() -> EventBuffer {
+        EventBuffer::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// 在内存中的字节数
+    pub fn bytes(&self) -> u64 {
+        self.reserved.bytes()
+    }
+
+    pub fn push(&mut self, event: Event) -> io::Result<()> {
+        let size = event_size(&event);
+        if would_exceed(size) {
+            if let Some(dir) = spill_dir() {
+                if let Some(spilled) = self.spill(&event, &dir)? {
+                    self.slots.push(Slot::Spilled(spilled));
+                    return Ok(());
+                }
+            }
+        }
+        self.reserved.grow(size);
+        self.slots.push(Slot::Memory(event));
+        Ok(())
+    }
+
+    //行事件编码后写入临时文件, 不是行事件或无法编码时返回None
+    fn spill(&mut self, event: &Event, dir: &Path) -> io::Result<Option<Spilled>> {
+        let (code, rows, table) = match (&event.data, &event.table) {
+            (Traction::RowEvent(code, rows), Some(table)) => (code, rows, table),
+            _ => return Ok(None),
+        };
+        let body = match encode::rows_body(code, 0, table, rows.rows()) {
+            Ok(t) => t,
+            Err(_) => return Ok(None),
+        };
+        if self.file.is_none() {
+            self.file = Some(SpillFile::create(dir)?);
+        }
+        let file = self.file.as_mut().unwrap();
+        file.file.seek(SeekFrom::Start(file.len))?;
+        file.file.write_all(&body)?;
+        let offset = file.len;
+        file.len += body.len() as u64;
+        SPILLED_EVENTS.fetch_add(1, Ordering::Relaxed);
+        SPILLED_BYTES.fetch_add(body.len() as u64, Ordering::Relaxed);
+        Ok(Some(Spilled{ header: event.header.clone(), log_pos: event.log_pos, code: code.clone(), table: table.clone(), offset, len: body.len() }))
+    }
+
+    /// 取出全部事件, 写入临时文件的事件重新读取, 之后缓存为空并释放内存
+    pub fn take(&mut self) -> io::Result<Vec<Event>> {
+        let slots = std::mem::take(&mut self.slots);
+        let file = self.file.take();
+        let mut events = Vec::with_capacity(slots.len());
+        for slot in slots {
+            match slot {
+                Slot::Memory(event) => events.push(event),
+                Slot::Spilled(t) => {
+                    let file = file.as_ref().ok_or_else(|| io::Error::other("临时文件已删除"))?;
+                    events.push(read_spilled(&file.file, t)?);
+                }
+            }
+        }
+        self.reserved.release();
+        Ok(events)
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.file = None;
+        self.reserved.release();
+    }
+}
+
+fn read_spilled(mut file: &File, spilled: Spilled) -> io::Result<Event> {
+    let mut body = vec![0u8; spilled.len];
+    file.seek(SeekFrom::Start(spilled.offset))?;
+    file.read_exact(&mut body)?;
+    //按文件读取的方式解析, 行数据到event_length减去包头及checksum为止
+    let header = EventHeader{ type_code: spilled.code.clone(), event_length: spilled.len as u32 + 19 + 4, ..spilled.header.clone() };
+    let rows = RowValue::read_row_value(&mut Cursor::new(body), &spilled.table, &header, &ReadType::File)?;
+    Ok(Event{ header: spilled.header, log_pos: spilled.log_pos, data: Traction::RowEvent(spilled.code, rows), table: Some(spilled.table) })
+}
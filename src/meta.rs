@@ -4,8 +4,12 @@
 */
 
 use std::collections::HashMap;
+#[cfg(feature = "network")]
 use std::net::TcpStream;
+#[cfg(feature = "network")]
 use crate::{io, Config};
+#[cfg(feature = "network")]
+use crate::stdout::sql_escape;
 //use lazy_static;
 //
 //lazy_static!{
@@ -29,6 +33,8 @@ pub struct FlagsMeta{
     pub protocol_41: i32,
     pub transactions: i32,
     pub client_connect_with_db: i32,
+    pub found_rows: i32,
+    pub client_compress: i32,
 }
 
 impl FlagsMeta {
@@ -44,7 +50,9 @@ impl FlagsMeta {
             long_flag: 1 << 2,
             protocol_41: 1 << 9,
             transactions: 1 << 13,
-            client_connect_with_db: 9
+            client_connect_with_db: 9,
+            found_rows: 1 << 1,
+            client_compress: 1 << 5
         }
     }
 }
@@ -109,7 +117,8 @@ pub enum  ColumnTypeDict{
     MysqlTypeVarString,
     MysqlTypeString,
     MysqlTypeGeometry,
-    UnknowType,
+    /// 未知的类型码, 见replication::opaque
+    UnknowType(u8),
 }
 
 impl ColumnTypeDict {
@@ -146,7 +155,7 @@ impl ColumnTypeDict {
             253 => ColumnTypeDict::MysqlTypeVarString,
             254 => ColumnTypeDict::MysqlTypeString,
             255 => ColumnTypeDict::MysqlTypeGeometry,
-            _ => ColumnTypeDict::UnknowType,
+            t => ColumnTypeDict::UnknowType(*t),
         }
     }
 }
@@ -203,15 +212,28 @@ impl JsonType {
     }
 }
 
+//需要连接数据库获取表结构
+#[cfg(feature = "network")]
 pub fn get_col(conf: &Config, db: &String, tb: &String, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>) {
+    //MaxScale binlogrouter不能查询information_schema
+    if crate::compat::Profile::from_config(conf).is_some_and(|p| !p.queryable()) {
+        return;
+    }
     let db_tbl = format!("{}.{}",db,tb);
     match table_cols_info.get(&db_tbl) {
         None => {
             let mut conn = crate::create_conn(conf);
-            let sql = format!("select COLUMN_NAME,COLUMN_TYPE,COLUMN_KEY from information_schema.columns where table_schema = '{}' and table_name='{}'  order by ORDINAL_POSITION ;", db, tb);
-            let values = io::command::execute(&mut conn,&sql);
+            let sql = format!("select COLUMN_NAME,COLUMN_TYPE,COLUMN_KEY,EXTRA,IS_NULLABLE from information_schema.columns where table_schema = {} and table_name={}  order by ORDINAL_POSITION ;", sql_escape::quote_string(db), sql_escape::quote_string(tb));
+            let mut values = io::command::execute(&mut conn,&sql);
             //println!("{:?}",values);
             if values.len() > 0 {
+                //分区表标记分区键, 查询失败时按非分区表处理
+                let sql = format!("select PARTITION_EXPRESSION,SUBPARTITION_EXPRESSION from information_schema.partitions where table_schema = {} and table_name={} limit 1;", sql_escape::quote_string(db), sql_escape::quote_string(tb));
+                if let Some(row) = io::command::try_execute(&mut conn, &sql).ok().and_then(|t| t.into_iter().next()) {
+                    for field in ["PARTITION_EXPRESSION", "SUBPARTITION_EXPRESSION"] {
+                        mark_partition_key(&mut values, row.get(field).map(|t| t.as_str()).unwrap_or(""));
+                    }
+                }
                 table_cols_info.insert(db_tbl,values);
             }
         }
@@ -219,6 +241,51 @@ pub fn get_col(conf: &Config, db: &String, tb: &String, table_cols_info: &mut Ha
     }
 }
 
+//生成列(EXTRA为VIRTUAL GENERATED、STORED GENERATED)的值由mysql计算, insert、update时不能指定
+//8.0中表达式默认值的EXTRA为DEFAULT_GENERATED, 不是生成列
+pub fn is_generated(col: &HashMap<String, String>) -> bool {
+    match col.get("EXTRA") {
+        Some(t) => {
+            let extra = t.to_uppercase();
+            extra.contains("VIRTUAL GENERATED") || extra.contains("STORED GENERATED")
+        }
+        None => false
+    }
+}
+
+//分区表中分区表达式使用的字段, get_col中设置PARTITION_KEY
+pub fn is_partition_key(col: &HashMap<String, String>) -> bool {
+    col.get("PARTITION_KEY").map(|t| t == "YES").unwrap_or(false)
+}
+
+//按information_schema.partitions中的PARTITION_EXPRESSION标记分区键, 如`created`、year(`created`)、`a`,`b`
+//表达式中与字段名相同的标识符即为分区键, KEY()按主键分区时表达式为空, 主键已包含在WHERE条件中
+pub fn mark_partition_key(cols: &mut [HashMap<String, String>], expression: &str) {
+    let idents: Vec<String> = expression.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$' || c == '`'))
+        .map(|t| t.trim_matches('`').to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    for col in cols.iter_mut() {
+        let name = col.get("COLUMN_NAME").map(|t| t.to_lowercase()).unwrap_or_default();
+        if idents.contains(&name) {
+            col.insert(String::from("PARTITION_KEY"), String::from("YES"));
+        }
+    }
+}
+
+//--audit-enrich: 通过performance_schema.threads查询线程所属的用户, 只能查到仍然存在的连接
+#[cfg(feature = "network")]
+pub fn get_thread_user(conf: &Config, thread_id: u32) -> Option<String> {
+    let mut conn = crate::create_conn(conf);
+    let sql = format!("select PROCESSLIST_USER as user,PROCESSLIST_HOST as host from performance_schema.threads where PROCESSLIST_ID = {};", thread_id);
+    let values = io::command::try_execute(&mut conn, &sql).ok()?;
+    let row = values.first()?;
+    match (row.get("user"), row.get("host")) {
+        (Some(user), Some(host)) if !user.is_empty() => Some(format!("{}@{}", user, host)),
+        _ => None
+    }
+}
+
 pub enum ReadType {
     Repl,
     File
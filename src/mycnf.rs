@@ -0,0 +1,146 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/29
+*/
+
+//读取连接信息, 避免在命令行中传递密码被ps看到
+//优先级: 命令行参数 > 环境变量 > 配置文件
+//  环境变量: MYSQL_USER、MYSQL_PWD、MYSQL_PWD_FILE(从文件读取密码)、MYSQL_HOST、MYSQL_TCP_PORT、MYSQL_UNIX_PORT(unix socket)
+//  配置文件: --defaults-file指定的文件, 未指定时依次读取/etc/my.cnf、/etc/mysql/my.cnf、~/.my.cnf
+//            读取[client]和[mytest]两个组, 后读取的覆盖先读取的
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::secret::{Secret, SecretFetcher, FileSecret};
+
+const GROUPS: [&str; 2] = ["client", "mytest"];
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions{
+    pub user: Option<String>,
+    pub password: Option<Secret>,
+    pub host: Option<String>,
+    pub port: Option<String>,
+    pub compress: Option<String>,
+    pub socket: Option<String>,
+    pub init_command: Option<String>,
+    pub auth_plugin: Option<String>,
+}
+
+impl ClientOptions{
+    /// 读取配置文件及环境变量, defaults_file指定的文件不存在时返回错误
+    pub fn load(defaults_file: Option<&str>) -> Result<ClientOptions, String> {
+        let mut options = ClientOptions::default();
+        match defaults_file {
+            Some(path) => {
+                let content = fs::read_to_string(path).map_err(|e| format!("读取配置文件({})发生错误:{}", path, e))?;
+                options.merge_cnf(&content);
+            }
+            None => {
+                for path in default_files() {
+                    if let Ok(content) = fs::read_to_string(&path) {
+                        options.merge_cnf(&content);
+                    }
+                }
+            }
+        }
+        options.merge_env()?;
+        Ok(options)
+    }
+
+    /// ip:port形式的地址, 配置文件中未设置port时使用3306
+    pub fn host_info(&self) -> Option<String> {
+        match &self.host {
+            Some(host) => match self.port.as_deref().and_then(|t| t.parse::<u16>().ok()) {
+                Some(port) => Some(crate::endpoint::host_port(host, port)),
+                None => Some(crate::endpoint::host_port(host, 3306)),
+            },
+            None => None
+        }
+    }
+
+    fn merge_cnf(&mut self, content: &str) {
+        let mut in_group = false;
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') || line.starts_with('!') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                let group = line[1..line.len() - 1].trim();
+                in_group = GROUPS.contains(&group);
+                continue;
+            }
+            if !in_group {
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(idx) => (line[..idx].trim(), unquote(line[idx + 1..].trim())),
+                None => (line, String::from(""))
+            };
+            match key.replace('_', "-").as_str() {
+                "user" => self.user = Some(value),
+                "password" => self.password = Some(Secret::new(value)),
+                "host" => self.host = Some(value),
+                "port" => self.port = Some(value),
+                //compress不带值时为开启
+                "compress" => self.compress = Some(String::from(match value.to_lowercase().as_str() {
+                    "" | "1" | "true" | "on" => "zlib",
+                    "0" | "false" | "off" => "off",
+                    _ => value.as_str(),
+                })),
+                "compression-algorithms" => self.compress = Some(value),
+                "socket" => self.socket = Some(value),
+                "init-command" => self.init_command = Some(value),
+                "default-auth" => self.auth_plugin = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    fn merge_env(&mut self) -> Result<(), String> {
+        if let Ok(t) = env::var("MYSQL_USER") {
+            self.user = Some(t);
+        }
+        if let Ok(t) = env::var("MYSQL_PWD") {
+            self.password = Some(Secret::new(t));
+        }
+        if let Ok(t) = env::var("MYSQL_PWD_FILE") {
+            self.password = Some(FileSecret{ path: t }.fetch()?);
+        }
+        if let Ok(t) = env::var("MYSQL_HOST") {
+            self.host = Some(t);
+        }
+        if let Ok(t) = env::var("MYSQL_TCP_PORT") {
+            self.port = Some(t);
+        }
+        if let Ok(t) = env::var("MYSQL_UNIX_PORT") {
+            self.socket = Some(t);
+        }
+        Ok(())
+    }
+}
+
+fn default_files() -> Vec<PathBuf> {
+    let mut files = vec![PathBuf::from("/etc/my.cnf"), PathBuf::from("/etc/mysql/my.cnf")];
+    if let Some(home) = env::var_os("HOME") {
+        files.push(Path::new(&home).join(".my.cnf"));
+    }
+    files
+}
+
+//去掉值两侧的引号及行尾注释
+pub(crate) fn unquote(value: &str) -> String {
+    for quote in &['"', '\''] {
+        if value.starts_with(*quote) {
+            if let Some(end) = value[1..].find(*quote) {
+                return value[1..end + 1].to_string();
+            }
+        }
+    }
+    match value.find(" #") {
+        Some(idx) => value[..idx].trim().to_string(),
+        None => value.to_string()
+    }
+}
@@ -0,0 +1,460 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/06
+*/
+
+//不分配内存的解析路径, 用于每秒需要处理百万级事件的场景
+//  输入为完整的binlog文件内容(fs::read或mmap), 事件、表信息、字段值均借用输入的数据
+//  字符串类型不做utf8校验, decimal、json保留二进制格式, 需要时通过RawValue::to_value转换
+//  整数、浮点、时间类型的解析结果与BinlogReader相同
+//
+//  let data = std::fs::read("bin.000001")?;
+//  let mut map = None;
+//  for event in parse_noalloc(&data)? {
+//      let event = event?;
+//      match event.header.type_code {
+//          BinlogEvent::TableMapEvent => map = Some(event.table_map()?),
+//          BinlogEvent::WriteEvent => for cell in event.rows(map.as_ref().unwrap())? { let cell = cell?; ... },
+//          _ => {}
+//      }
+//  }
+
+use std::io::{self, Cursor};
+use byteorder::{ByteOrder, LittleEndian};
+use crate::meta::ColumnTypeDict;
+use crate::readvalue;
+use crate::warning::WarningKind;
+use crate::replication::{jsonb, opaque};
+use crate::replication::parsevalue::{DecimalMeta, MySQLValue, RowValue};
+use crate::replication::readevent::{self, BinlogEvent, EventHeader};
+use crate::replication::verify::{ChecksumClass, ChecksumVerifier};
+
+const BINLOG_MAGIC: [u8; 4] = [0xfe, 0x62, 0x69, 0x6e];
+const HEADER_LEN: usize = 19;
+
+/// 从完整的binlog文件内容开始按事件迭代, 会校验binlog文件的magic
+pub fn parse_noalloc(data: &[u8]) -> io::Result<RawEvents<'_>> {
+    RawEvents::new(data)
+}
+
+/// 借用输入数据的事件, body不包含事件头及checksum
+#[derive(Debug, Clone)]
+pub struct RawEvent<'a>{
+    pub header: EventHeader,
+    /// 事件在文件中的起始位置
+    pub log_pos: u64,
+    pub body: &'a [u8],
+}
+
+/// 按事件迭代, 只解析事件头
+pub struct RawEvents<'a>{
+    data: &'a [u8],
+    offset: usize,
+    checksum: bool,
+    done: bool,
+    verifier: ChecksumVerifier,
+}
+
+impl<'a> RawEvents<'a> {
+    pub fn new(data: &'a [u8]) -> io::Result<RawEvents<'a>> {
+        if data.len() < 4 || data[..4] != BINLOG_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "不是binlog文件"));
+        }
+        Ok(RawEvents{ data, offset: 4, checksum: false, done: false, verifier: ChecksumVerifier::new(ChecksumClass::empty()) })
+    }
+
+    /// 校验指定类型事件的crc32, 默认不校验
+    pub fn verify_checksum(mut self, classes: ChecksumClass) -> RawEvents<'a> {
+        self.verifier = ChecksumVerifier::new(classes);
+        self
+    }
+
+    /// 事件是否带有crc32 checksum, 读取到FORMAT_DESCRIPTION_EVENT之后有效
+    pub fn checksum(&self) -> bool {
+        self.checksum
+    }
+
+    fn read_event(&mut self) -> io::Result<Option<RawEvent<'a>>> {
+        let log_pos = self.offset;
+        let rest = &self.data[log_pos..];
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        if rest.len() < HEADER_LEN {
+            return Err(truncated(log_pos));
+        }
+        let header = EventHeader::read(&mut Cursor::new(&rest[..HEADER_LEN]))?;
+        let event_length = header.event_length as usize;
+        if event_length < HEADER_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("position {} 处的事件长度错误", log_pos)));
+        }
+        if rest.len() < event_length {
+            return Err(truncated(log_pos));
+        }
+        self.verifier.verify(&rest[..HEADER_LEN], &rest[HEADER_LEN..event_length], log_pos as u64)?;
+        let mut body = &rest[HEADER_LEN..event_length];
+        if let BinlogEvent::FormatDescriptionEvent = header.type_code {
+            self.checksum = fde_checksum(body);
+        }
+        if self.checksum {
+            body = &body[..body.len().saturating_sub(4)];
+        }
+        self.offset += event_length;
+        Ok(Some(RawEvent{ header, log_pos: log_pos as u64, body }))
+    }
+}
+
+impl<'a> Iterator for RawEvents<'a> {
+    type Item = io::Result<RawEvent<'a>>;
+
+    fn next(&mut self) -> Option<io::Result<RawEvent<'a>>> {
+        if self.done {
+            return None;
+        }
+        let event = self.read_event().transpose();
+        //出错后不再继续读取
+        match event {
+            Some(Ok(_)) => {}
+            _ => self.done = true
+        }
+        event
+    }
+}
+
+fn truncated(log_pos: usize) -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, format!("binlog文件在position {} 处被截断", log_pos))
+}
+
+//5.6.1之后FORMAT_DESCRIPTION_EVENT末尾为checksum_alg(1字节)及crc(4字节), checksum_alg为1表示crc32
+fn fde_checksum(body: &[u8]) -> bool {
+    if body.len() < 2 + 50 + 5 {
+        return false;
+    }
+    let mut version = [0u32; 3];
+    let mut idx = 0;
+    for b in body[2..52].iter() {
+        match b {
+            b'0'..=b'9' => version[idx] = version[idx] * 10 + (b - b'0') as u32,
+            b'.' if idx < 2 => idx += 1,
+            _ => break
+        }
+    }
+    version >= [5, 6, 1] && body[body.len() - 5] == 1
+}
+
+/// 借用输入数据的TABLE_MAP_EVENT
+#[derive(Debug, Clone, Copy)]
+pub struct RawTableMap<'a>{
+    pub table_id: u64,
+    pub database: &'a [u8],
+    pub table: &'a [u8],
+    pub column_types: &'a [u8],
+    metadata: &'a [u8],
+}
+
+impl<'a> RawTableMap<'a> {
+    pub fn column_count(&self) -> usize {
+        self.column_types.len()
+    }
+
+    /// 每个字段的类型及metadata, 与TableMap::column_info的解析结果相同
+    pub fn columns(&self) -> RawColumns<'a> {
+        RawColumns{ types: self.column_types, metadata: self.metadata }
+    }
+}
+
+/// 字段信息迭代, metadata按字段类型依次读取
+pub struct RawColumns<'a>{
+    types: &'a [u8],
+    metadata: &'a [u8],
+}
+
+impl<'a> Iterator for RawColumns<'a> {
+    type Item = io::Result<(ColumnTypeDict, [usize; 2])>;
+
+    fn next(&mut self) -> Option<io::Result<(ColumnTypeDict, [usize; 2])>> {
+        let (col_type, rest) = self.types.split_first()?;
+        self.types = rest;
+        let column_type = ColumnTypeDict::from_type_code(col_type);
+        let len = match column_type {
+            ColumnTypeDict::MysqlTypeVarString |
+            ColumnTypeDict::MysqlTypeVarchar |
+            ColumnTypeDict::MysqlTypeNewdecimal |
+            ColumnTypeDict::MysqlTypeString |
+            ColumnTypeDict::MysqlTypeEnum |
+            ColumnTypeDict::MysqlTypeSet => 2,
+            ColumnTypeDict::MysqlTypeBlob |
+            ColumnTypeDict::MysqlTypeMediumBlob |
+            ColumnTypeDict::MysqlTypeLongBlob |
+            ColumnTypeDict::MysqlTypeTinyBlob |
+            ColumnTypeDict::MysqlTypeJson |
+            ColumnTypeDict::MysqlTypeGeometry |
+            ColumnTypeDict::MysqlTypeTimestamp2 |
+            ColumnTypeDict::MysqlTypeDatetime2 |
+            ColumnTypeDict::MysqlTypeTime2 |
+            ColumnTypeDict::MysqlTypeFloat |
+            ColumnTypeDict::MysqlTypeDouble => 1,
+            ColumnTypeDict::UnknowType(code) => match opaque::meta_len(code) {
+                Ok(t) => t,
+                Err(e) => {
+                    self.types = &[];
+                    return Some(Err(e));
+                }
+            },
+            _ => 0
+        };
+        if self.metadata.len() < len {
+            self.types = &[];
+            return Some(Err(readvalue::invalid_data(format!("{:?}缺少metadata", column_type))));
+        }
+        let (meta, rest) = self.metadata.split_at(len);
+        self.metadata = rest;
+        Some(Ok(match column_type {
+            ColumnTypeDict::MysqlTypeVarString |
+            ColumnTypeDict::MysqlTypeVarchar => {
+                let length_bytes = if LittleEndian::read_u16(meta) > 255 { 2 } else { 1 };
+                (column_type, [length_bytes, 0])
+            }
+            ColumnTypeDict::MysqlTypeNewdecimal => (column_type, [meta[0] as usize, meta[1] as usize]),
+            ColumnTypeDict::MysqlTypeString |
+            ColumnTypeDict::MysqlTypeEnum |
+            ColumnTypeDict::MysqlTypeSet => {
+                //第一个字节为实际的类型, enum、set也记录为string
+                match readevent::string_meta(meta[0], meta[1]) {
+                    Some((code, length)) => (ColumnTypeDict::from_type_code(&code), [length, 0]),
+                    None => {
+                        if let Err(e) = readvalue::mismatch(WarningKind::Metadata, format!("string类型的metadata错误: {:?}", meta)) {
+                            self.types = &[];
+                            return Some(Err(e));
+                        }
+                        (ColumnTypeDict::MysqlTypeString, [65535, 0])
+                    }
+                }
+            }
+            //未知类型最多保留2bytes的metadata
+            ColumnTypeDict::UnknowType(_) => (column_type, [meta.first().copied().unwrap_or(0) as usize, meta.get(1).copied().unwrap_or(0) as usize]),
+            _ if len == 1 => (column_type, [meta[0] as usize, 0]),
+            _ => (column_type, [0, 0])
+        }))
+    }
+}
+
+/// 字段值, 借用输入数据
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawValue<'a>{
+    Null,
+    /// 整数、浮点、时间、enum、set等定长类型, 不分配内存
+    Fixed(MySQLValue),
+    /// varchar、char、binary、blob、text的原始字节, 不做utf8校验
+    Bytes(&'a [u8]),
+    /// decimal的二进制格式
+    Decimal{ precision: u8, scale: u8, bytes: &'a [u8] },
+    /// json的二进制格式
+    Json(&'a [u8]),
+}
+
+impl<'a> RawValue<'a> {
+    /// 转换为与BinlogReader解析结果相同的MySQLValue
+    pub fn to_value(&self) -> io::Result<MySQLValue> {
+        Ok(match self {
+            RawValue::Null => MySQLValue::Null,
+            RawValue::Fixed(v) => v.clone(),
+            RawValue::Bytes(v) => MySQLValue::Blob(v.to_vec()),
+            RawValue::Decimal{ precision, scale, bytes } => {
+                let value = RowValue::read_new_decimal(&bytes.to_vec(), &DecimalMeta::new(*precision, *scale)?)
+                    .map_err(|e| readvalue::invalid_data(format!("decimal 解析错误: {}", e)))?;
+                MySQLValue::Decimal(value)
+            }
+            RawValue::Json(v) => MySQLValue::Json(jsonb::read_binary_json(&mut Cursor::new(*v), &v.len())?),
+        })
+    }
+}
+
+/// 行事件中的一个字段值, update事件中前后镜像依次排列(row为偶数时为前镜像)
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawCell<'a>{
+    pub row: usize,
+    pub column: usize,
+    pub value: RawValue<'a>,
+}
+
+impl<'a> RawEvent<'a> {
+    /// 解析TABLE_MAP_EVENT, 库名、表名不做utf8校验
+    pub fn table_map(&self) -> io::Result<RawTableMap<'a>> {
+        if !matches!(self.header.type_code, BinlogEvent::TableMapEvent) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("position {} 处的事件不是TABLE_MAP_EVENT", self.log_pos)));
+        }
+        let mut buf = Slice::new(self.body);
+        let table_id = readvalue::read_u48(buf.take(6)?);
+        buf.take(2)?;
+        let database_len = buf.u8()? as usize;
+        let database = buf.take(database_len)?;
+        buf.take(1)?;
+        let table_len = buf.u8()? as usize;
+        let table = buf.take(table_len)?;
+        buf.take(1)?;
+        let column_count = buf.u8()? as usize;
+        let column_types = buf.take(column_count)?;
+        //跳过metadata长度, 按字段类型读取
+        buf.take(1)?;
+        Ok(RawTableMap{ table_id, database, table, column_types, metadata: buf.rest() })
+    }
+
+    /// 按字段迭代行事件中的所有值, map为该事件之前的TABLE_MAP_EVENT
+    pub fn rows<'m>(&self, map: &'m RawTableMap<'a>) -> io::Result<RawRows<'a, 'm>> {
+        let images = match self.header.type_code {
+            BinlogEvent::UpdateEvent => 2,
+            BinlogEvent::WriteEvent |
+            BinlogEvent::DeleteEvent => 1,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("position {} 处的事件不是行事件", self.log_pos)))
+        };
+        let mut buf = Slice::new(self.body);
+        buf.take(8)?;
+        let extra_len = LittleEndian::read_u16(buf.take(2)?) as usize;
+        if extra_len > 2 {
+            buf.take(extra_len - 2)?;
+        }
+        let col_count = buf.u8()? as usize;
+        if col_count != map.column_count() {
+            return Err(readvalue::invalid_data(format!("行事件的字段数({})与table_map({}.{})的字段数({})不一致",
+                                                       col_count, String::from_utf8_lossy(map.database),
+                                                       String::from_utf8_lossy(map.table), map.column_count())));
+        }
+        let bitmap_len = col_count.div_ceil(8);
+        buf.take(bitmap_len * images)?;
+        Ok(RawRows{
+            buf,
+            map,
+            columns: map.columns(),
+            null_bits: &[],
+            row: 0,
+            column: col_count,
+        })
+    }
+}
+
+/// 行事件的值迭代, 每行依次返回各个字段
+pub struct RawRows<'a, 'm>{
+    buf: Slice<'a>,
+    map: &'m RawTableMap<'a>,
+    columns: RawColumns<'a>,
+    null_bits: &'a [u8],
+    //已经开始读取的行数
+    row: usize,
+    column: usize,
+}
+
+impl<'a, 'm> RawRows<'a, 'm> {
+    fn read_value(&mut self) -> io::Result<RawCell<'a>> {
+        //开始新的一行
+        if self.column == self.map.column_count() {
+            self.row += 1;
+            self.null_bits = self.buf.take(self.map.column_count().div_ceil(8))?;
+            self.columns = self.map.columns();
+            self.column = 0;
+        }
+        let column = self.column;
+        let (column_type, meta) = match self.columns.next() {
+            Some(t) => t?,
+            None => return Err(readvalue::invalid_data(String::from("字段信息不完整")))
+        };
+        self.column += 1;
+        let value = if self.null_bits[column / 8] & (1 << (column % 8)) > 0 {
+            RawValue::Null
+        } else {
+            self.buf.value(&column_type, &meta)?
+        };
+        Ok(RawCell{ row: self.row - 1, column, value })
+    }
+}
+
+impl<'a, 'm> Iterator for RawRows<'a, 'm> {
+    type Item = io::Result<RawCell<'a>>;
+
+    fn next(&mut self) -> Option<io::Result<RawCell<'a>>> {
+        //没有字段的表不占用数据
+        if self.map.column_count() == 0 || (self.column == self.map.column_count() && self.buf.rest().is_empty()) {
+            return None;
+        }
+        let cell = self.read_value();
+        if cell.is_err() {
+            self.buf = Slice::new(&[]);
+            self.column = self.map.column_count();
+        }
+        Some(cell)
+    }
+}
+
+//在借用的数据上顺序读取
+#[derive(Debug, Clone, Copy)]
+struct Slice<'a>{
+    data: &'a [u8],
+}
+
+impl<'a> Slice<'a> {
+    fn new(data: &'a [u8]) -> Slice<'a> {
+        Slice{ data }
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        self.data
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "事件数据不完整"));
+        }
+        let (v, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(v)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn value(&mut self, column_type: &ColumnTypeDict, meta: &[usize; 2]) -> io::Result<RawValue<'a>> {
+        Ok(match column_type {
+            ColumnTypeDict::MysqlTypeVarString |
+            ColumnTypeDict::MysqlTypeVarchar |
+            ColumnTypeDict::MysqlTypeBlob |
+            ColumnTypeDict::MysqlTypeTinyBlob |
+            ColumnTypeDict::MysqlTypeLongBlob |
+            ColumnTypeDict::MysqlTypeMediumBlob |
+            ColumnTypeDict::MysqlTypeGeometry |
+            ColumnTypeDict::MysqlTypeBit => {
+                let len = self.length(meta[0])?;
+                RawValue::Bytes(self.take(len)?)
+            }
+            ColumnTypeDict::MysqlTypeString => {
+                let len = self.length(if meta[0] <= 255 { 1 } else { 2 })?;
+                RawValue::Bytes(self.take(len)?)
+            }
+            ColumnTypeDict::MysqlTypeJson => {
+                let len = self.length(meta[0])?;
+                RawValue::Json(self.take(len)?)
+            }
+            ColumnTypeDict::MysqlTypeNewdecimal => {
+                let (precision, scale) = (meta[0] as u8, meta[1] as u8);
+                if precision == 0 {
+                    return Err(readvalue::invalid_data(format!("无效的decimal metadata: ({}, {})", precision, scale)));
+                }
+                let bytes = self.take(DecimalMeta::new(precision, scale)?.bytes_to_read)?;
+                RawValue::Decimal{ precision, scale, bytes }
+            }
+            _ => {
+                let mut cur = Cursor::new(self.data);
+                let value = RowValue::parsevalue(&mut cur, column_type, meta)?;
+                self.take(cur.position() as usize)?;
+                RawValue::Fixed(value)
+            }
+        })
+    }
+
+    fn length(&mut self, bytes: usize) -> io::Result<usize> {
+        let mut cur = Cursor::new(self.data);
+        let len = RowValue::read_str_value_length(&mut cur, &bytes)?;
+        self.take(cur.position() as usize)?;
+        Ok(len)
+    }
+}
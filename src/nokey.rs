@@ -0,0 +1,101 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//没有主键的表: update/delete无法按主键定位行, 生成的sql按所有字段匹配时重复的行会被同时修改, 按主键分区时整张表只能分到同一个分区
+//  主键按表结构(COLUMN_KEY为PRI, 没有时为单个字段的非空唯一键UNI)或TABLE_MAP中的metadata确定
+//  每个输出目标分别配置处理方式:
+//    warn      默认, 按原来的方式输出(sql按所有字段匹配, 分区时整张表分到同一个分区), 每张表输出一次警告(no_primary_key)
+//    full-row  sql按所有字段匹配, mysql方言追加LIMIT 1, 有重复的行时只修改其中一行; 只用于生成sql的输出
+//    hash-key  按所有字段值的hash作为分区key, 同一行的变更在同一个分区, update拆分为delete及insert; 只用于分区的输出
+//    skip      不输出该表的行事件, 每张表输出一次警告
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use crate::replication::readevent::TableMap;
+use crate::warning::{self, WarningKind};
+
+/// 没有主键的表的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoKeyPolicy{
+    #[default]
+    Warn,
+    FullRow,
+    HashKey,
+    Skip,
+}
+
+impl NoKeyPolicy {
+    pub fn new(spec: &str) -> Result<NoKeyPolicy, String> {
+        match spec.trim() {
+            "" | "warn" => Ok(NoKeyPolicy::Warn),
+            "full-row" => Ok(NoKeyPolicy::FullRow),
+            "hash-key" => Ok(NoKeyPolicy::HashKey),
+            "skip" => Ok(NoKeyPolicy::Skip),
+            _ => Err(format!("无效的no-key: {}, 可选值为warn、full-row、hash-key、skip", spec))
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoKeyPolicy::Warn => "warn",
+            NoKeyPolicy::FullRow => "full-row",
+            NoKeyPolicy::HashKey => "hash-key",
+            NoKeyPolicy::Skip => "skip",
+        }
+    }
+
+    /// 生成sql的输出(getsql、ApplySink)可用的处理方式
+    pub fn for_sql(self) -> Result<NoKeyPolicy, String> {
+        match self {
+            NoKeyPolicy::HashKey => Err(String::from("hash-key只用于分区输出, 生成sql时可选值为warn、full-row、skip")),
+            t => Ok(t)
+        }
+    }
+
+    /// 分区输出(PartitionedSink)可用的处理方式
+    pub fn for_partition(self) -> Result<NoKeyPolicy, String> {
+        match self {
+            NoKeyPolicy::FullRow => Err(String::from("full-row只用于生成sql的输出, 分区时可选值为warn、hash-key、skip")),
+            t => Ok(t)
+        }
+    }
+
+    /// key为空(表没有主键)时按处理方式输出警告, 返回false时不输出该表的行事件; sink为输出目标的名称, 只用于警告
+    pub fn check(&self, sink: &str, db_tbl: &str, key: &[usize]) -> bool {
+        if !key.is_empty() {
+            return true;
+        }
+        let action = match self {
+            NoKeyPolicy::Warn => "按所有字段匹配, 有重复的行时update/delete可能修改多行或分到同一个分区",
+            NoKeyPolicy::Skip => "不输出该表的行事件",
+            _ => return true,
+        };
+        let mut warned = WARNED.lock().unwrap();
+        if warned.get_or_insert_with(HashSet::new).insert(format!("{}/{}", sink, db_tbl)) {
+            warning::emit(WarningKind::NoPrimaryKey, format!("{}: {}没有主键及非空的唯一键, {}", sink, db_tbl, action));
+        }
+        *self != NoKeyPolicy::Skip
+    }
+}
+
+//已经输出过警告的输出目标及表
+static WARNED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+/// 主键字段的序号: 表结构中COLUMN_KEY为PRI的字段, 没有时为第一个非空的唯一键字段, 都没有时为TABLE_MAP中metadata的主键
+pub fn key_columns(cols: &[HashMap<String, String>], map: &TableMap) -> Vec<usize> {
+    let column_key = |col: &HashMap<String, String>| col.get("COLUMN_KEY").cloned().unwrap_or_default();
+    let primary: Vec<usize> = cols.iter().enumerate().filter(|(_, col)| column_key(col) == "PRI").map(|(idx, _)| idx).collect();
+    if !primary.is_empty() {
+        return primary;
+    }
+    //多个字段的唯一键在information_schema中第一个字段为MUL, 这里只能识别单个字段的唯一键
+    if let Some(idx) = cols.iter().position(|col| column_key(col) == "UNI" && col.get("IS_NULLABLE").is_some_and(|t| t == "NO")) {
+        return vec![idx];
+    }
+    match &map.metadata {
+        Some(meta) => meta.primary_key.iter().map(|(idx, _)| *idx).collect(),
+        None => vec![]
+    }
+}
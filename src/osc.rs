@@ -0,0 +1,234 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//在线改表工具(gh-ost、pt-online-schema-change)的改表过程
+//  影子表: gh-ost为_tbl_gho, pt-osc为_tbl_new, 拷贝的数据及改表期间原表的变更都写入影子表
+//  切换(cut-over): RENAME TABLE tbl TO _tbl_del, _tbl_gho TO tbl(pt-osc为_tbl_old、_tbl_new), 之后影子表即为原表
+//  检测到创建影子表、切换、删除影子表(取消改表)时在stderr提示, 切换后重新获取原表的表结构
+//  --osc-remap: 影子表的行数据按原表名输出, 改表期间下游可以按原表持续接收新表结构的数据;
+//  影子表的数据包含拷贝的全部数据及原表变更的重放, 与原表的数据重复, 下游需要按主键覆盖写入
+
+use std::collections::HashSet;
+use crate::Config;
+
+/// 改表工具
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    GhOst,
+    PtOsc,
+}
+
+impl Tool {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::GhOst => "gh-ost",
+            Tool::PtOsc => "pt-online-schema-change",
+        }
+    }
+
+    fn shadow_suffix(&self) -> &'static str {
+        match self {
+            Tool::GhOst => "_gho",
+            Tool::PtOsc => "_new",
+        }
+    }
+}
+
+/// 影子表对应的工具及原表名, _orders_gho为gh-ost的orders
+pub fn shadow_of(tbl: &str) -> Option<(Tool, &str)> {
+    [Tool::GhOst, Tool::PtOsc].iter().find_map(|tool| {
+        tbl.strip_prefix('_')
+            .and_then(|t| t.strip_suffix(tool.shadow_suffix()))
+            .filter(|t| !t.is_empty())
+            .map(|t| (*tool, t))
+    })
+}
+
+/// 改表过程中的事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notice {
+    /// 创建了影子表
+    Started{ tool: Tool, database: String, table: String },
+    /// 影子表重命名为原表
+    CutOver{ tool: Tool, database: String, table: String },
+    /// 切换前删除了影子表
+    Aborted{ tool: Tool, database: String, table: String },
+}
+
+impl Notice {
+    /// 原表的库名、表名
+    pub fn table(&self) -> (&str, &str) {
+        match self {
+            Notice::Started{ database, table, .. } |
+            Notice::CutOver{ database, table, .. } |
+            Notice::Aborted{ database, table, .. } => (database, table),
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Notice::Started{ tool, database, table } =>
+                format!("{}开始修改{}.{}, 影子表为_{}{}", tool.name(), database, table, table, tool.shadow_suffix()),
+            Notice::CutOver{ tool, database, table } =>
+                format!("{}切换{}.{}: 影子表_{}{}已重命名为原表, 之后的数据为新的表结构", tool.name(), database, table, table, tool.shadow_suffix()),
+            Notice::Aborted{ tool, database, table } =>
+                format!("{}取消修改{}.{}, 影子表_{}{}已删除", tool.name(), database, table, table, tool.shadow_suffix()),
+        }
+    }
+}
+
+/// 根据DDL跟踪进行中的改表
+#[derive(Debug, Clone, Default)]
+pub struct Tracker{
+    remap: bool,
+    active: HashSet<(String, String)>,
+}
+
+impl Tracker {
+    /// remap为true时影子表的行数据按原表名输出
+    pub fn new(remap: bool) -> Tracker {
+        Tracker{ remap, active: HashSet::new() }
+    }
+
+    pub fn from_config(conf: &Config) -> Tracker {
+        Tracker::new(conf.oscremap)
+    }
+
+    /// 影子表的行数据输出时使用的原表名, 未开启--osc-remap或不是影子表时为None
+    pub fn remap<'a>(&self, tbl: &'a str) -> Option<&'a str> {
+        if !self.remap {
+            return None;
+        }
+        shadow_of(tbl).map(|(_, t)| t)
+    }
+
+    /// 检查QUERY_EVENT中的DDL, database为执行时的默认库
+    pub fn observe(&mut self, database: &str, sql: &str) -> Vec<Notice> {
+        let tokens = tokens(sql);
+        let keywords: Vec<String> = tokens.iter().map(|t| t.to_lowercase()).collect();
+        let mut notices = vec![];
+        match keywords.first().map(|t| t.as_str()) {
+            Some("create") => {
+                //CREATE [TEMPORARY] TABLE [IF NOT EXISTS] name
+                let mut i = match keywords.iter().position(|t| t == "table") {
+                    Some(i) if i <= 2 => i + 1,
+                    _ => return notices,
+                };
+                if keywords[i..].starts_with(&[String::from("if"), String::from("not"), String::from("exists")]) {
+                    i += 3;
+                }
+                if let Some((db, tbl)) = tokens.get(i).and_then(|t| qualified(t, database)) {
+                    if let Some((tool, table)) = shadow_of(&tbl) {
+                        self.active.insert((db.clone(), table.to_string()));
+                        notices.push(Notice::Started{ tool, database: db.clone(), table: table.to_string() });
+                    }
+                }
+            }
+            Some("drop") => {
+                //DROP [TEMPORARY] TABLE [IF EXISTS] name[, name]
+                let mut i = match keywords.iter().position(|t| t == "table") {
+                    Some(i) if i <= 2 => i + 1,
+                    _ => return notices,
+                };
+                if keywords[i..].starts_with(&[String::from("if"), String::from("exists")]) {
+                    i += 2;
+                }
+                for name in tokens[i.min(tokens.len())..].iter().filter(|t| *t != ",") {
+                    if let Some((db, tbl)) = qualified(name, database) {
+                        if let Some((tool, table)) = shadow_of(&tbl) {
+                            if self.active.remove(&(db.clone(), table.to_string())) {
+                                notices.push(Notice::Aborted{ tool, database: db.clone(), table: table.to_string() });
+                            }
+                        }
+                    }
+                }
+            }
+            Some("rename") if keywords.get(1).map(|t| t.as_str()) == Some("table") => {
+                //RENAME TABLE a TO b[, c TO d], gh-ost为原子的两组重命名, 旧版本分两条语句
+                for pair in tokens[2..].split(|t| t == ",") {
+                    if let [from, to, target] = pair {
+                        if !to.eq_ignore_ascii_case("to") {
+                            continue;
+                        }
+                        if let (Some((from_db, from_tbl)), Some((to_db, to_tbl))) = (qualified(from, database), qualified(target, database)) {
+                            match shadow_of(&from_tbl) {
+                                Some((tool, table)) if table == to_tbl && from_db == to_db => {
+                                    self.active.remove(&(to_db.clone(), to_tbl.clone()));
+                                    notices.push(Notice::CutOver{ tool, database: to_db, table: to_tbl });
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        notices
+    }
+}
+
+//库名.表名, 没有库名时使用默认库
+fn qualified(name: &str, database: &str) -> Option<(String, String)> {
+    match crate::ident::split_qualified(name).as_slice() {
+        [tbl] if !database.is_empty() => Some((database.to_string(), tbl.clone())),
+        [db, tbl] => Some((db.clone(), tbl.clone())),
+        _ => None,
+    }
+}
+
+//按空白、括号、分号切分, 逗号为单独的token, 反引号中的内容不切分, 去掉/* */注释
+fn tokens(sql: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            current.push(c);
+            if c == '`' {
+                if chars.peek() == Some(&'`') {
+                    current.push('`');
+                    chars.next();
+                } else {
+                    quoted = false;
+                }
+            }
+            continue;
+        }
+        match c {
+            '`' => {
+                quoted = true;
+                current.push(c);
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c if c.is_whitespace() || c == ',' || c == '(' || c == ')' || c == ';' => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                if c == ',' {
+                    tokens.push(String::from(","));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
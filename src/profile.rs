@@ -0,0 +1,185 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//各处理阶段的耗时统计, 用于在生产环境中定位性能问题
+//  阶段: read(从文件或连接读取事件)、parse(解析事件)、decode(解析行数据的字段值)、serialize(转换为json等输出格式)、sink(写入输出目标)
+//  阶段可以嵌套, 如parse中包含decode、sink中包含serialize, stats()中每个阶段的耗时包含嵌套的阶段
+//  folded()按调用栈输出各阶段自身的耗时(微秒), 格式与flamegraph.pl、inferno的折叠格式相同, 例如:
+//    curl -s 127.0.0.1:8090/profile?seconds=30 | inferno-flamegraph > stages.svg
+//  默认关闭, 开启http控制接口(--control)时开启, 关闭时计时器不读取时间
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+use serde_json::{json, Map, Value as JsonValue};
+
+/// 处理阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Read,
+    Parse,
+    Decode,
+    Serialize,
+    Sink,
+}
+
+const STAGES: [Stage; 5] = [Stage::Read, Stage::Parse, Stage::Decode, Stage::Serialize, Stage::Sink];
+//折叠格式中记录的最大嵌套层数, 调用栈按6进制编码(每层为阶段序号+1)
+const MAX_DEPTH: usize = 4;
+const STACKS: usize = 6 * 6 * 6 * 6;
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Read => "read",
+            Stage::Parse => "parse",
+            Stage::Decode => "decode",
+            Stage::Serialize => "serialize",
+            Stage::Sink => "sink",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// 一个阶段的累计耗时
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StageStats{
+    pub stage: Stage,
+    pub count: u64,
+    pub nanos: u64,
+}
+
+impl StageStats {
+    pub fn seconds(&self) -> f64 {
+        self.nanos as f64 / 1e9
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static COUNT: [AtomicU64; 5] = [const { AtomicU64::new(0) }; 5];
+static NANOS: [AtomicU64; 5] = [const { AtomicU64::new(0) }; 5];
+//按调用栈累计的自身耗时
+static FOLDED: [AtomicU64; STACKS] = [const { AtomicU64::new(0) }; STACKS];
+
+thread_local! {
+    //当前线程中进行中的阶段: (调用栈编码, 嵌套阶段的耗时)
+    static ACTIVE: RefCell<Vec<(usize, u64)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// 开启或关闭计时
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// 阶段的计时器, drop时记录耗时, 只能在创建的线程中使用
+pub struct Timer{
+    start: Option<Instant>,
+    stage: Stage,
+    _local: PhantomData<*const ()>,
+}
+
+/// 开始一个阶段的计时
+pub fn start(stage: Stage) -> Timer {
+    if !is_enabled() {
+        return Timer{ start: None, stage, _local: PhantomData };
+    }
+    ACTIVE.with(|active| {
+        let mut active = active.borrow_mut();
+        let parent = active.last().map(|t| t.0).unwrap_or(0);
+        let stack = if active.len() < MAX_DEPTH { parent * 6 + stage.index() + 1 } else { 0 };
+        active.push((stack, 0));
+    });
+    Timer{ start: Some(Instant::now()), stage, _local: PhantomData }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let start = match self.start {
+            Some(t) => t,
+            None => return,
+        };
+        let nanos = start.elapsed().as_nanos() as u64;
+        COUNT[self.stage.index()].fetch_add(1, Ordering::Relaxed);
+        NANOS[self.stage.index()].fetch_add(nanos, Ordering::Relaxed);
+        ACTIVE.with(|active| {
+            let mut active = active.borrow_mut();
+            let (stack, nested) = match active.pop() {
+                Some(t) => t,
+                None => return,
+            };
+            if let Some(parent) = active.last_mut() {
+                parent.1 += nanos;
+            }
+            if stack > 0 {
+                FOLDED[stack].fetch_add(nanos.saturating_sub(nested), Ordering::Relaxed);
+            }
+        });
+    }
+}
+
+/// 各阶段的累计耗时
+pub fn stats() -> Vec<StageStats> {
+    STAGES.iter().map(|stage| StageStats{
+        stage: *stage,
+        count: COUNT[stage.index()].load(Ordering::Relaxed),
+        nanos: NANOS[stage.index()].load(Ordering::Relaxed),
+    }).collect()
+}
+
+/// 各阶段的次数及耗时, 用于控制接口的/status
+pub fn stats_json() -> JsonValue {
+    let mut value = Map::new();
+    for t in stats() {
+        value.insert(t.stage.name().to_string(), json!({"count": t.count, "seconds": t.seconds()}));
+    }
+    JsonValue::Object(value)
+}
+
+/// 按调用栈的自身耗时(微秒), 每行为"阶段;嵌套的阶段 耗时", 没有耗时的调用栈不输出
+pub fn folded() -> String {
+    render(|stack| FOLDED[stack].load(Ordering::Relaxed))
+}
+
+/// 之后seconds秒内的折叠格式耗时, 为结束时与开始时累计值的差, 不影响stats()
+pub fn sample(seconds: f64) -> String {
+    let before: Vec<u64> = FOLDED.iter().map(|t| t.load(Ordering::Relaxed)).collect();
+    std::thread::sleep(std::time::Duration::from_secs_f64(seconds));
+    render(|stack| FOLDED[stack].load(Ordering::Relaxed).saturating_sub(before[stack]))
+}
+
+fn render<F: Fn(usize) -> u64>(nanos: F) -> String {
+    let mut lines = vec![];
+    for stack in 1..STACKS {
+        let micros = nanos(stack) / 1000;
+        if micros == 0 {
+            continue;
+        }
+        let mut names = vec![];
+        let mut code = stack;
+        while code > 0 {
+            names.push(STAGES[code % 6 - 1].name());
+            code /= 6;
+        }
+        names.reverse();
+        lines.push(format!("{} {}\n", names.join(";"), micros));
+    }
+    lines.sort();
+    lines.concat()
+}
+
+/// 清空累计的耗时
+pub fn reset() {
+    for t in COUNT.iter().chain(NANOS.iter()).chain(FOLDED.iter()) {
+        t.store(0, Ordering::Relaxed);
+    }
+}
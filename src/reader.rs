@@ -0,0 +1,335 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//不依赖数据库连接的binlog文件读取接口, 按事件或按事务迭代
+//行数据只包含字段值, 字段名需要调用方根据表结构自行对应
+
+#[cfg(feature = "os")]
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+#[cfg(feature = "os")]
+use std::io::BufReader;
+#[cfg(feature = "os")]
+use std::path::Path;
+use crate::replication::readbinlog::{Traction, FileEnd};
+use crate::replication::readevent::{
+    self, BinlogEvent, EventFlags, EventHeader, FormatDescriptionEvent, GtidEvent, InitValue, TableMap
+};
+use crate::replication::parsevalue::RowValue;
+use crate::replication::verify::{event_checksum_ok, ChecksumClass, ChecksumVerifier};
+use crate::meta::ReadType;
+use crate::profile::{self, Stage};
+
+const BINLOG_MAGIC: [u8; 4] = [0xfe, 0x62, 0x69, 0x6e];
+
+/// 一个binlog事件
+#[derive(Debug, Clone)]
+pub struct Event{
+    pub header: EventHeader,
+    /// 事件在文件中的起始位置
+    pub log_pos: u64,
+    pub data: Traction,
+    /// 行事件对应的表信息
+    pub table: Option<TableMap>,
+}
+
+/// 一个事务, 从GTID_EVENT(或BEGIN)开始, 到XID_EVENT、XA_PREPARE_LOG_EVENT或非BEGIN的QUERY_EVENT(DDL、COMMIT)结束
+#[derive(Debug, Clone)]
+pub struct Transaction{
+    pub gtid: Option<GtidEvent>,
+    pub events: Vec<Event>,
+    /// 没有读取到结束的事件时为false: 文件末尾正在写入的事务, 或之后直接是下一个GTID_EVENT
+    pub complete: bool,
+}
+
+/// binlog/relay log文件读取
+pub struct BinlogReader<R: Read + Seek>{
+    reader: R,
+    parser: EventParser,
+    relaylog: bool,
+    file_end: Option<FileEnd>,
+    verifier: ChecksumVerifier,
+}
+
+#[cfg(feature = "os")]
+impl BinlogReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<BinlogReader<BufReader<File>>> {
+        BinlogReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> BinlogReader<R> {
+    /// reader需位于文件开头, 会校验binlog文件的magic
+    pub fn new(mut reader: R) -> io::Result<BinlogReader<R>> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != BINLOG_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "不是binlog文件"));
+        }
+        Ok(BinlogReader{
+            reader,
+            parser: EventParser::new(),
+            relaylog: false,
+            file_end: None,
+            verifier: ChecksumVerifier::new(ChecksumClass::empty()),
+        })
+    }
+
+    /// 读取relay log时跳过slave自身生成的事件
+    pub fn relaylog(mut self, relaylog: bool) -> BinlogReader<R> {
+        self.relaylog = relaylog;
+        self
+    }
+
+    /// 校验指定类型事件的crc32, 文件未启用checksum时不校验, 默认不校验
+    pub fn verify_checksum(mut self, classes: ChecksumClass) -> BinlogReader<R> {
+        self.verifier = ChecksumVerifier::new(classes);
+        self
+    }
+
+    /// 写入该文件的mysql主版本号, 读取到FORMAT_DESCRIPTION_EVENT之后有效
+    pub fn version(&self) -> u8 {
+        self.parser.version
+    }
+
+    /// 最近一次读取到的TABLE_MAP_EVENT
+    pub fn table_map(&self) -> &TableMap {
+        &self.parser.table_map
+    }
+
+    /// 读取结束的原因, 未读取到文件末尾时为None
+    pub fn file_end(&self) -> Option<&FileEnd> {
+        self.file_end.as_ref()
+    }
+
+    pub fn position(&mut self) -> io::Result<u64> {
+        self.reader.stream_position()
+    }
+
+    /// 从指定位置开始读取, position必须为事件的起始位置
+    pub fn seek(&mut self, position: u64) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(position))?;
+        Ok(())
+    }
+
+    /// 读取下一个事件, 到达文件末尾返回None, 文件被截断返回UnexpectedEof错误
+    pub fn read_event(&mut self) -> io::Result<Option<Event>> {
+        loop {
+            let timer = profile::start(Stage::Read);
+            let log_pos = self.position()?;
+            let mut header_buf = vec![0u8; 19];
+            let read = read_full(&mut self.reader, &mut header_buf)?;
+            if read == 0 {
+                self.file_end = Some(self.parser.last_end.clone());
+                return Ok(None);
+            }
+            if read < header_buf.len() {
+                return Err(self.truncated(log_pos));
+            }
+            let header = EventHeader::read(&mut Cursor::new(&header_buf))?;
+            if (header.event_length as usize) < header_buf.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("position {} 处的事件长度错误", log_pos)));
+            }
+            //event_length可能已损坏, 按实际读取到的数据分配内存
+            let payload_len = header.event_length as usize - header_buf.len();
+            let mut payload = vec![];
+            (&mut self.reader).take(payload_len as u64).read_to_end(&mut payload)?;
+            if payload.len() < payload_len {
+                return Err(self.truncated(log_pos));
+            }
+            self.verifier.verify(&header_buf, &payload, log_pos)?;
+
+            drop(timer);
+            if self.relaylog && header.flags.contains(EventFlags::LOG_EVENT_RELAY_LOG_F) {
+                continue;
+            }
+            return self.parser.parse_event(header, log_pos, payload).map(Some).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("position {} 处的事件解析错误: {}", log_pos, e))
+            });
+        }
+    }
+
+    /// 按事务读取, 事务之外的事件(FORMAT_DESCRIPTION_EVENT、ROTATE_EVENT等)会被跳过
+    pub fn transactions(self) -> Transactions<R> {
+        Transactions{ reader: self, pending: None }
+    }
+
+    fn truncated(&mut self, log_pos: u64) -> io::Error {
+        self.file_end = Some(FileEnd::Truncated(log_pos));
+        io::Error::new(io::ErrorKind::UnexpectedEof, format!("binlog文件在position {} 处被截断", log_pos))
+    }
+}
+
+//事件解析, 记录FORMAT_DESCRIPTION_EVENT中的版本及最近的TABLE_MAP_EVENT, 文件读取与主从拉取共用
+pub(crate) struct EventParser{
+    version: u8,
+    table_map: TableMap,
+    last_end: FileEnd,
+    //FORMAT_DESCRIPTION_EVENT决定后续事件是否带有checksum, 从文件中间开始读取时未知
+    checksum: Option<bool>,
+}
+
+impl EventParser {
+    pub(crate) fn new() -> EventParser {
+        EventParser{ version: 5, table_map: TableMap::new(), last_end: FileEnd::Eof, checksum: None }
+    }
+
+    pub(crate) fn parse_event(&mut self, header: EventHeader, log_pos: u64, payload: Vec<u8>) -> io::Result<Event> {
+        let _timer = profile::start(Stage::Parse);
+        let version = self.version;
+        let payload_len = payload.len() as u64;
+        if let BinlogEvent::FormatDescriptionEvent = header.type_code {
+            self.checksum = Some(event_checksum_ok(&header.encode(), &payload));
+        }
+        let mut cur = Cursor::new(payload);
+        let mut table = None;
+        self.last_end = FileEnd::Eof;
+        let data = match header.type_code {
+            BinlogEvent::FormatDescriptionEvent => {
+                self.version = FormatDescriptionEvent::read_event(&header, &mut cur, &version)?.major_version();
+                Traction::Unknown
+            }
+            BinlogEvent::GtidEvent => Traction::GtidEvent(readevent::GtidEvent::read_event(&header, &mut cur, &version)?),
+            BinlogEvent::QueryEvent => Traction::QueryEvent(readevent::QueryEvent::read_event(&header, &mut cur, &version)?),
+            BinlogEvent::TableMapEvent => {
+                self.table_map = readevent::TableMap::read_event(&header, &mut cur, &version)?;
+                Traction::TableMapEvent(self.table_map.clone())
+            }
+            BinlogEvent::WriteEvent |
+            BinlogEvent::UpdateEvent |
+            BinlogEvent::DeleteEvent => {
+                table = Some(self.table_map.clone());
+                let v = RowValue::read_row_value(&mut cur, &self.table_map, &header, &ReadType::File)?;
+                Traction::RowEvent(header.type_code.clone(), v)
+            }
+            BinlogEvent::XidEvent => Traction::XidEvent(readevent::XidEvent::read_event(&header, &mut cur, &version)?),
+            BinlogEvent::RotateLogEvent => {
+                let v = readevent::RotateLog::read_event(&header, &mut cur, &version)?;
+                self.last_end = FileEnd::Rotate(v.binlog_file.clone());
+                Traction::RotateLogEvent(v)
+            }
+            BinlogEvent::StopEvent => {
+                self.last_end = FileEnd::Stop;
+                Traction::StopEvent
+            }
+            BinlogEvent::TransactionContextEvent => {
+                Traction::TransactionContextEvent(readevent::TransactionContextEvent::read_event(&header, &mut cur, &version)?)
+            }
+            BinlogEvent::ViewChangeEvent => {
+                Traction::ViewChangeEvent(readevent::ViewChangeEvent::read_event(&header, &mut cur, &version)?)
+            }
+            BinlogEvent::RowsQueryEvent => {
+                Traction::RowsQueryEvent(readevent::RowsQueryEvent::read_event(&header, &mut cur, &version)?)
+            }
+            _ if header.event_type == readevent::EventType::UnknownEvent => {
+                let mut bytes = cur.into_inner();
+                if self.checksum == Some(true) {
+                    bytes.truncate(bytes.len().saturating_sub(4));
+                }
+                cur = Cursor::new(vec![]);
+                Traction::UnknownEvent(readevent::UnknownEvent{ type_code: header.raw_type, bytes })
+            }
+            _ => Traction::Unknown
+        };
+        readevent::check_boundary(&header, log_pos, cur.position(), payload_len, self.checksum)?;
+        Ok(Event{ header, log_pos, data, table })
+    }
+}
+
+impl<R: Read + Seek> Iterator for BinlogReader<R> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        if self.file_end.is_some() {
+            return None;
+        }
+        self.read_event().transpose()
+    }
+}
+
+/// 按事务迭代
+pub struct Transactions<R: Read + Seek>{
+    reader: BinlogReader<R>,
+    //结束未完成的事务时读取到的下一个事务的GTID_EVENT
+    pending: Option<Event>,
+}
+
+impl<R: Read + Seek> Transactions<R> {
+    pub fn reader(&self) -> &BinlogReader<R> {
+        &self.reader
+    }
+}
+
+impl<R: Read + Seek> Iterator for Transactions<R> {
+    type Item = io::Result<Transaction>;
+
+    fn next(&mut self) -> Option<io::Result<Transaction>> {
+        let mut trx: Option<Transaction> = None;
+        loop {
+            let event = match self.pending.take() {
+                Some(t) => t,
+                None => match self.reader.next() {
+                    Some(Ok(t)) => t,
+                    Some(Err(e)) => return Some(Err(e)),
+                    //文件末尾未完成的事务同样返回
+                    None => return trx.map(Ok)
+                }
+            };
+            match &event.data {
+                Traction::GtidEvent(t) => {
+                    if trx.is_some() {
+                        self.pending = Some(event);
+                        return trx.map(Ok);
+                    }
+                    trx = Some(Transaction{ gtid: Some(t.clone()), events: vec![event], complete: false });
+                    continue;
+                }
+                Traction::QueryEvent(t) if trx.is_none() => {
+                    //未开启gtid时以BEGIN作为事务开始
+                    let complete = !in_transaction(&t.command);
+                    let cur = Transaction{ gtid: None, events: vec![event], complete };
+                    if !complete {
+                        trx = Some(cur);
+                        continue;
+                    }
+                    return Some(Ok(cur));
+                }
+                _ => {}
+            }
+
+            if let Some(cur) = trx.as_mut() {
+                let end = match &event.data {
+                    Traction::XidEvent(_) => true,
+                    Traction::QueryEvent(t) => !in_transaction(&t.command),
+                    _ => matches!(event.header.type_code, BinlogEvent::XAPREPARELOGEVENT)
+                };
+                cur.events.push(event);
+                if end {
+                    cur.complete = true;
+                    return trx.map(Ok);
+                }
+            }
+        }
+    }
+}
+
+//事务开始或XA事务中间的语句, 之后的事件属于同一个事务; XA事务以XA_PREPARE_LOG_EVENT结束
+fn in_transaction(command: &str) -> bool {
+    command == "BEGIN" || command.starts_with("XA START") || command.starts_with("XA BEGIN") || command.starts_with("XA END")
+}
+
+//读取到buf写满或文件末尾, 返回读取的字节数
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(read)
+}
@@ -7,19 +7,56 @@ use std::str::from_utf8;
 use std::io::{Cursor, Read};
 use byteorder::{ReadBytesExt, LittleEndian, WriteBytesExt, BigEndian};
 use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::warning::WarningKind;
 
-pub fn read_num_pack<R: Read, S: Into<usize>>(num: S, buf: &mut R) -> Vec<u8> {
-    let mut pack = vec![0u8; num.into()];
-    buf.read_exact(&mut pack).unwrap();
-    pack.to_vec()
+pub fn read_num_pack<R: Read, S: Into<usize>>(num: S, buf: &mut R) -> io::Result<Vec<u8>> {
+    read_bytes(buf, num.into())
 }
 
-pub fn read_string_value_from_len<R: Read, S: Into<usize>>(buf: &mut R,num: S) -> String {
-    let pack = read_num_pack(num,buf);
-    String::from_utf8_lossy(&pack).to_string()
+//长度来自事件内容, 可能是损坏或伪造的值: 只读取实际存在的数据, 不按长度预先分配内存
+pub fn read_bytes<R: Read>(buf: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut pack = vec![];
+    buf.take(len as u64).read_to_end(&mut pack)?;
+    if pack.len() < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("需要读取{}bytes, 事件中只剩{}bytes", len, pack.len())));
+    }
+    Ok(pack)
+}
+
+pub fn read_string_value_from_len<R: Read, S: Into<usize>>(buf: &mut R,num: S) -> io::Result<String> {
+    let pack = read_num_pack(num,buf)?;
+    Ok(String::from_utf8_lossy(&pack).to_string())
     //from_utf8(&pack).unwrap().parse().unwrap()
 }
 
+//事件内容不符合格式时返回的错误
+pub fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+//解析模式, 对所有读取binlog的地方生效:
+//  严格模式下未知的事件类型、与类型不符的metadata、事件末尾未解析的数据返回错误
+//  宽松模式(默认)输出警告后按原有方式继续解析
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+pub fn is_strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+//事件内容与预期不符但可以继续解析时调用, 严格模式下返回错误, 否则记录为该类型的警告
+pub fn mismatch(kind: WarningKind, msg: String) -> io::Result<()> {
+    if is_strict() {
+        return Err(invalid_data(msg));
+    }
+    crate::warning::emit(kind, msg);
+    Ok(())
+}
+
 pub fn read_string_value(pack: &[u8]) -> String{
     //String::from_utf8_lossy(&pack).to_string()
     match from_utf8(pack) {
@@ -35,6 +72,9 @@ pub fn read_string_lossy_value(pack: &[u8], version: &u8) -> String{
     if pack.len() == 9 {
         return String::from("BEGIN");
     } else {
+        if from_utf8(pack).is_err() {
+            crate::warning::emit(WarningKind::CharsetFallback, format!("语句不是合法的utf8({} bytes), 无法转换的字节按替换字符输出", pack.len()));
+        }
         return String::from_utf8_lossy(&pack).to_string();
 //        match from_utf8(pack) {
 //            Ok(t) => t.parse().unwrap(),
@@ -76,23 +116,23 @@ pub fn read_i32(pack: &[u8]) -> i32 {
     rdr.read_i32::<LittleEndian>().unwrap()
 }
 
-pub fn read_u40(pack: &[u8]) -> usize {
-    let a = pack[0] as usize;
-    let b = read_u32(&pack[1..]) as usize;
+pub fn read_u40(pack: &[u8]) -> u64 {
+    let a = pack[0] as u64;
+    let b = read_u32(&pack[1..]) as u64;
     a + (b << 8)
 }
 
-pub fn read_u48(pack: &[u8]) -> usize {
-    let a = read_u16(&pack[0..2]) as usize;
-    let b = read_u16(&pack[2..4]) as usize;
-    let c = read_u16(&pack[4..]) as usize;
+pub fn read_u48(pack: &[u8]) -> u64 {
+    let a = read_u16(&pack[0..2]) as u64;
+    let b = read_u16(&pack[2..4]) as u64;
+    let c = read_u16(&pack[4..]) as u64;
     a + (b << 16) + (c << 32)
 }
 
-pub fn read_u56(pack: &[u8]) -> usize {
-    let a = pack[0] as usize;
-    let b = read_u16(&pack[1..3]) as usize;
-    let c = read_u32(&pack[3..]) as usize;
+pub fn read_u56(pack: &[u8]) -> u64 {
+    let a = pack[0] as u64;
+    let b = read_u16(&pack[1..3]) as u64;
+    let c = read_u32(&pack[3..]) as u64;
     a + (b << 8) + (c << 24)
 }
 
@@ -154,8 +194,11 @@ pub fn read_f64(pack: &[u8]) -> f64 {
 }
 
 pub fn read_nbytes<R: Read, S: Into<usize>>(r: &mut R, desired_bytes: S) -> io::Result<Vec<u8>> {
-    let mut into = vec![0u8; desired_bytes.into()];
-    r.read_exact(&mut into)?;
-    Ok(into)
+    read_bytes(r, desired_bytes.into())
 }
 
+
+//binlog事件校验使用的crc32(IEEE), crc32fast在支持的cpu上使用pclmulqdq(x86_64)、crc指令(aarch64)加速
+pub fn crc32(pack: &[u8]) -> u32 {
+    crc32fast::hash(pack)
+}
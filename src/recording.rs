@@ -0,0 +1,215 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//主从协议数据包的录制与重放, 用于在测试中确定性地复现生产环境的解析问题
+//  stream --record(或BinlogStream::with_recorder)把mysql发送的每个数据包(包括开头的OK标识)连同接收时间追加写入文件,
+//  replay子命令(或BinlogStream::replay)按顺序读回, 经过与拉取时相同的校验、解析、主库切换检测及已执行位置的处理
+//  文件格式, 整数均为小端:
+//    MYTREC01 + 4字节长度 + json(注册的gtid、binlogfile、position, 重放时已执行位置从该位置开始)
+//    每个数据包: 8字节接收时间(unix微秒) + 4字节长度 + 数据包内容
+//  录制中断时最后一个数据包可能不完整, 读取时忽略
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde_json::json;
+use crate::Config;
+use crate::readvalue;
+
+const MAGIC: &[u8; 8] = b"MYTREC01";
+
+/// 录制开始时注册同步的位置
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Start{
+    pub gtid: String,
+    pub binlogfile: String,
+    pub position: String,
+}
+
+impl Start {
+    pub fn from_config(conf: &Config) -> Start {
+        Start{ gtid: conf.gtid.clone(), binlogfile: conf.binlogfile.clone(), position: conf.position.clone() }
+    }
+
+    /// 设置到conf中, 重放时已执行位置从该位置开始
+    pub fn apply(&self, conf: &mut Config) {
+        conf.gtid = self.gtid.clone();
+        conf.binlogfile = self.binlogfile.clone();
+        conf.position = self.position.clone();
+    }
+}
+
+/// 录制的一个数据包
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet{
+    /// 接收时间, unix微秒
+    pub micros: u64,
+    pub data: Vec<u8>,
+}
+
+/// 追加写入数据包, 可以在线程间共享
+pub struct Recorder{
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl Recorder {
+    /// 创建文件并写入文件头, 文件已存在时覆盖
+    pub fn create<P: AsRef<Path>>(path: P, start: &Start) -> io::Result<Recorder> {
+        let file = OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
+        let mut writer = BufWriter::new(file);
+        let meta = json!({"gtid": start.gtid, "binlogfile": start.binlogfile, "position": start.position}).to_string();
+        writer.write_all(MAGIC)?;
+        writer.write_all(&readvalue::write_u32(meta.len() as u32))?;
+        writer.write_all(meta.as_bytes())?;
+        writer.flush()?;
+        Ok(Recorder{ writer: Mutex::new(writer) })
+    }
+
+    /// 写入一个数据包, 时间为当前时间; 每个数据包写入后flush, 进程崩溃时最多丢失最后一个
+    pub fn record(&self, data: &[u8]) -> io::Result<()> {
+        let micros = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_micros() as u64).unwrap_or(0);
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_all(&micros.to_le_bytes())?;
+        writer.write_all(&readvalue::write_u32(data.len() as u32))?;
+        writer.write_all(data)?;
+        writer.flush()
+    }
+}
+
+/// 按顺序读取录制的数据包
+pub struct Recording{
+    reader: Box<dyn Read + Send>,
+    start: Start,
+    realtime: bool,
+    //第一个数据包的接收时间及重放开始的时间
+    origin: Option<(u64, Instant)>,
+}
+
+impl Recording {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Recording> {
+        Recording::new(BufReader::new(File::open(path)?))
+    }
+
+    pub fn new<R: Read + Send + 'static>(mut reader: R) -> io::Result<Recording> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("不是录制文件"));
+        }
+        let mut len = [0u8; 4];
+        reader.read_exact(&mut len)?;
+        let mut meta = vec![0u8; readvalue::read_u32(&len) as usize];
+        reader.read_exact(&mut meta)?;
+        let meta: serde_json::Value = serde_json::from_slice(&meta).map_err(|_| invalid("录制文件头格式错误"))?;
+        let field = |name: &str| meta[name].as_str().unwrap_or_default().to_string();
+        let start = Start{ gtid: field("gtid"), binlogfile: field("binlogfile"), position: field("position") };
+        Ok(Recording{ reader: Box::new(reader), start, realtime: false, origin: None })
+    }
+
+    /// 按录制时数据包之间的间隔重放, 默认不等待
+    pub fn realtime(mut self, realtime: bool) -> Recording {
+        self.realtime = realtime;
+        self
+    }
+
+    pub fn start(&self) -> &Start {
+        &self.start
+    }
+
+    /// 下一个数据包, 结束或最后一个数据包不完整时返回None
+    pub fn next_packet(&mut self) -> io::Result<Option<Packet>> {
+        let mut head = [0u8; 12];
+        if !read_full(&mut self.reader, &mut head)? {
+            return Ok(None);
+        }
+        let micros = u64::from_le_bytes([head[0], head[1], head[2], head[3], head[4], head[5], head[6], head[7]]);
+        let mut data = vec![0u8; readvalue::read_u32(&head[8..12]) as usize];
+        if !read_full(&mut self.reader, &mut data)? {
+            return Ok(None);
+        }
+        if self.realtime {
+            let (first, started) = *self.origin.get_or_insert((micros, Instant::now()));
+            let due = Duration::from_micros(micros.saturating_sub(first));
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+        Ok(Some(Packet{ micros, data }))
+    }
+}
+
+//读满buf, 没有数据或数据不完整时返回false
+fn read_full<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+impl Iterator for Recording {
+    type Item = io::Result<Packet>;
+
+    fn next(&mut self) -> Option<io::Result<Packet>> {
+        self.next_packet().transpose()
+    }
+}
+
+/// replay子命令: 读取录制文件, 经过BinlogStream的处理后以json行输出到stdout或--output指定的文件
+#[cfg(all(feature = "cli", feature = "json-sink"))]
+pub fn run(conf: &Config) {
+    use std::process;
+    use crate::exitcode;
+    use crate::sink::{JsonLinesSink, Sink};
+    use crate::stdout::format::ValueFormat;
+    use crate::stream::BinlogStream;
+    let recording = Recording::open(&conf.file).unwrap_or_else(|err|{
+        println!("读取录制文件({})发生错误:{}", conf.file, err);
+        process::exit(exitcode::USAGE);
+    });
+    let format = ValueFormat::new(conf).unwrap_or_else(|err|{
+        println!("{}", err);
+        process::exit(exitcode::USAGE);
+    });
+    let stream = BinlogStream::replay(recording.realtime(conf.realtime), conf).unwrap_or_else(|err|{
+        println!("{}", err);
+        process::exit(exitcode::USAGE);
+    });
+    let writer: Box<dyn Write> = match conf.output.as_str() {
+        "" | "-" => Box::new(io::stdout()),
+        t => Box::new(File::create(t).unwrap_or_else(|err|{
+            println!("创建文件({})发生错误:{}", t, err);
+            process::exit(exitcode::USAGE);
+        })),
+    };
+    let mut sink = JsonLinesSink::new(BufWriter::new(writer), format);
+    let mut events = 0u64;
+    for event in stream {
+        let event = event.unwrap_or_else(|err|{
+            println!("重放第{}个事件之后发生错误:{}", events, err);
+            process::exit(exitcode::PARSE);
+        });
+        events += 1;
+        sink.write_event(&event).unwrap_or_else(|err|{
+            println!("输出发生错误:{}", err);
+            process::exit(exitcode::SINK);
+        });
+    }
+    sink.flush().unwrap_or_else(|err|{
+        println!("输出发生错误:{}", err);
+        process::exit(exitcode::SINK);
+    });
+    if events == 0 {
+        process::exit(exitcode::NO_EVENTS);
+    }
+}
@@ -0,0 +1,341 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//读取从库本地的relay log(或开启log_slave_updates的binlog)进行CDC, 不需要连接主库, 也不需要REPLICATION SLAVE权限
+//  index为relay log或binlog的index文件(relay-bin.index、binlog.index), 从index中的第一个文件开始读取,
+//  到达文件末尾时等待新的数据, index中出现下一个文件后切换, 文件末尾写入中的事件等写完后再读取
+//  relay log中的事件:
+//    带LOG_EVENT_RELAY_LOG_F的事件(从库生成的FORMAT_DESCRIPTION_EVENT、PREVIOUS_GTIDS、切换relay log的ROTATE)跳过
+//    io线程收到的artificial ROTATE_EVENT记录主库的binlog文件, 与主从拉取时相同返回给调用方, 位置为主库的binlog文件及position
+//    io线程重新连接时relay log中可能留有不完整的事务, 之后主库从该事务开头重新发送, 不完整的事务丢弃
+//  binlog(log_slave_updates)每个文件开头返回一个artificial ROTATE_EVENT, 位置为从库的binlog文件及position
+//  事务完整读取后才返回, 事务在内存中缓存
+//  sql线程应用后会清理relay log(relay_log_purge), 读取落后于sql线程时文件可能已被删除, 返回NotFound错误
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::Config;
+use crate::control::Controller;
+use crate::gtid::GtidSet;
+use crate::reader::{BinlogReader, Event};
+use crate::readvalue;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::{BinlogEvent, EventFlags, EventHeader, RotateLog};
+use crate::replication::verify::ChecksumClass;
+
+/// 读取本地的relay log/binlog, 按事件返回, 不会主动结束
+pub struct RelayTail{
+    index: PathBuf,
+    conf: Config,
+    classes: ChecksumClass,
+    poll: Duration,
+    heartbeat: Option<Duration>,
+    control: Option<Controller>,
+    //当前读取的文件(index中的文件名)
+    file: String,
+    reader: Option<BinlogReader<BufReader<File>>>,
+    //当前文件是否为relay log、是否已返回文件对应的ROTATE_EVENT
+    relay: bool,
+    announced: bool,
+    //到达文件末尾且index中已有下一个文件, 再读取一次后切换
+    eof_checked: bool,
+    //事件对应的源端binlog文件
+    source_file: String,
+    pending: Vec<Event>,
+    ready: VecDeque<Event>,
+    skip_gtids: GtidSet,
+    skip_until: Option<(String, u64)>,
+    discarded: u64,
+}
+
+impl RelayTail {
+    /// index为relay log或binlog的index文件, 从其中的第一个文件开始读取
+    pub fn open<P: AsRef<Path>>(index: P) -> io::Result<RelayTail> {
+        let mut tail = RelayTail{
+            index: index.as_ref().to_path_buf(),
+            conf: Config::default(),
+            classes: ChecksumClass::empty(),
+            poll: Duration::from_millis(200),
+            heartbeat: None,
+            control: None,
+            file: String::new(),
+            reader: None,
+            relay: false,
+            announced: false,
+            eof_checked: false,
+            source_file: String::new(),
+            pending: vec![],
+            ready: VecDeque::new(),
+            skip_gtids: GtidSet::new(),
+            skip_until: None,
+            discarded: 0,
+        };
+        tail.restart(&Config::default())?;
+        Ok(tail)
+    }
+
+    /// relaylogindex为index文件, gtid或binlogfile、position为已输出的位置(主库的binlog文件, binlog模式下为从库的),
+    /// heartbeat为没有新事件时返回心跳事件的间隔, verifychecksum不为空时按该配置校验事件的crc32
+    pub fn from_config(conf: &Config) -> io::Result<RelayTail> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        let classes = ChecksumClass::parse(&conf.verifychecksum).map_err(invalid)?;
+        let mut tail = RelayTail::open(&conf.relaylogindex)?.verify_checksum(classes);
+        if !conf.heartbeat.is_empty() {
+            let secs = conf.heartbeat.parse::<f64>().ok().filter(|t| *t > 0.0).ok_or_else(|| invalid(format!("无效的heartbeat: {}", conf.heartbeat)))?;
+            tail = tail.heartbeat(Duration::from_secs_f64(secs));
+        }
+        tail.restart(conf)?;
+        Ok(tail)
+    }
+
+    /// 校验指定类型事件的crc32, 默认不校验
+    pub fn verify_checksum(mut self, classes: ChecksumClass) -> RelayTail {
+        self.classes = classes;
+        self
+    }
+
+    /// 没有新数据时检查文件的间隔, 默认200ms
+    pub fn poll_interval(mut self, interval: Duration) -> RelayTail {
+        self.poll = interval;
+        self
+    }
+
+    /// 没有新事件时按该间隔返回HEARTBEAT_LOG_EVENT, 默认不返回
+    pub fn heartbeat(mut self, interval: Duration) -> RelayTail {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    /// 跳过gtid在该集合中的事务
+    pub fn skip_executed(mut self, gtids: GtidSet) -> RelayTail {
+        self.skip_gtids = gtids;
+        self
+    }
+
+    /// 跳过在源端binlog文件及position之前(含)结束的事务
+    pub fn skip_until(mut self, file: &str, position: u64) -> RelayTail {
+        self.skip_until = Some((file.to_string(), position));
+        self
+    }
+
+    /// 使用控制接口暂停、回退、跳过事务, 回退时从index中的第一个文件重新读取并跳过回退位置之前的事务
+    pub fn with_control(mut self, controller: Controller) -> RelayTail {
+        controller.set_connected(true);
+        controller.set_start(&self.conf);
+        self.control = Some(controller);
+        self
+    }
+
+    /// 当前读取的文件
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// 丢弃的不完整事务数
+    pub fn discarded(&self) -> u64 {
+        self.discarded
+    }
+
+    /// 读取下一个事件, 没有新事件时等待
+    pub fn read_event(&mut self) -> io::Result<Event> {
+        let mut idle = Instant::now();
+        loop {
+            if let Some(position) = self.control.as_ref().and_then(|c| c.checkpoint()) {
+                let conf = position.apply(&self.conf);
+                self.restart(&conf)?;
+            }
+            let event = match self.try_read_event()? {
+                Some(event) => event,
+                None => match self.heartbeat {
+                    Some(interval) if idle.elapsed() >= interval => artificial(27, 19, Traction::Unknown)?,
+                    _ => {
+                        thread::sleep(self.poll);
+                        continue;
+                    }
+                }
+            };
+            idle = Instant::now();
+            match &self.control {
+                Some(controller) if !controller.event(&event.data, event.header.timestamp, event.header.next_position as u64) => continue,
+                _ => return Ok(event),
+            }
+        }
+    }
+
+    /// 读取已写入的下一个事件, 没有新的完整事务时返回None
+    pub fn try_read_event(&mut self) -> io::Result<Option<Event>> {
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Ok(Some(event));
+            }
+            let reader = match self.reader.as_mut() {
+                Some(t) => t,
+                None => return Ok(None),
+            };
+            let position = reader.position()?;
+            match reader.read_event() {
+                Ok(Some(event)) => {
+                    self.eof_checked = false;
+                    self.accept(event)?;
+                }
+                Ok(None) => match self.next_file()? {
+                    Some(file) if self.eof_checked => self.switch(&file)?,
+                    Some(_) => self.eof_checked = true,
+                    None => return Ok(None),
+                },
+                //事件还在写入
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    reader.seek(position)?;
+                    return Ok(None);
+                }
+                Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", self.file, e))),
+            }
+        }
+    }
+
+    //从index中的第一个文件重新读取, 跳过conf中的位置之前的事务
+    fn restart(&mut self, conf: &Config) -> io::Result<()> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        self.skip_gtids = match conf.gtid.as_str() {
+            "" => GtidSet::new(),
+            t => t.parse::<GtidSet>().map_err(|e| invalid(e.to_string()))?,
+        };
+        self.skip_until = match conf.binlogfile.as_str() {
+            "" => None,
+            t => Some((t.to_string(), conf.position.parse::<u64>().map_err(|_| invalid(format!("无效的position: {}", conf.position)))?)),
+        };
+        self.conf = Config{ relaylogindex: self.index.to_string_lossy().to_string(), ..conf.clone() };
+        self.pending.clear();
+        self.ready.clear();
+        self.source_file.clear();
+        let first = self.entries()?.into_iter().next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{}中没有文件", self.index.display())))?;
+        self.switch(&first.0)
+    }
+
+    //index中的文件名及路径, 相对路径相对于index所在的目录
+    fn entries(&self) -> io::Result<Vec<(String, PathBuf)>> {
+        let content = fs::read_to_string(&self.index)
+            .map_err(|e| io::Error::new(e.kind(), format!("读取{}发生错误: {}", self.index.display(), e)))?;
+        let dir = self.index.parent().unwrap_or_else(|| Path::new(""));
+        Ok(content.lines().map(|t| t.trim()).filter(|t| !t.is_empty()).map(|t| {
+            let path = Path::new(t);
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| t.to_string());
+            (name, if path.is_absolute() { path.to_path_buf() } else { dir.join(path) })
+        }).collect())
+    }
+
+    //index中当前文件之后的文件, 当前文件已被清理时为文件名更大的第一个文件
+    fn next_file(&self) -> io::Result<Option<String>> {
+        Ok(self.entries()?.into_iter().map(|(name, _)| name).find(|name| name.as_str() > self.file.as_str()))
+    }
+
+    fn switch(&mut self, file: &str) -> io::Result<()> {
+        let path = self.entries()?.into_iter().find(|(name, _)| name == file).map(|(_, path)| path).unwrap_or_else(|| PathBuf::from(file));
+        let reader = BinlogReader::open(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(e.kind(), format!("{}不存在, 可能已被sql线程清理(relay_log_purge)", path.display())),
+            _ => io::Error::new(e.kind(), format!("打开{}发生错误: {}", path.display(), e)),
+        })?;
+        self.reader = Some(reader.verify_checksum(self.classes));
+        self.file = file.to_string();
+        self.relay = false;
+        self.announced = false;
+        self.eof_checked = false;
+        Ok(())
+    }
+
+    fn accept(&mut self, event: Event) -> io::Result<()> {
+        if event.header.flags.contains(EventFlags::LOG_EVENT_RELAY_LOG_F) {
+            self.relay = true;
+            return Ok(());
+        }
+        match (&event.data, &event.header.type_code) {
+            (Traction::RotateLogEvent(t), _) => {
+                self.source_file = t.binlog_file.clone();
+                self.announced = true;
+            }
+            (_, BinlogEvent::FormatDescriptionEvent) | (_, BinlogEvent::PreviousGtidsLogEvent) => {}
+            _ if !self.relay && !self.announced => {
+                //binlog模式下与主从拉取时相同, 先返回文件对应的ROTATE_EVENT
+                self.announced = true;
+                self.source_file = self.file.clone();
+                let rotate = RotateLog{ binlog_file: self.file.clone(), position: event.log_pos, artificial: true };
+                let length = 19 + 8 + rotate.binlog_file.len() as u32;
+                self.ready.push_back(artificial(4, length, Traction::RotateLogEvent(rotate))?);
+            }
+            _ => {}
+        }
+        match &event.data {
+            Traction::GtidEvent(_) => {
+                self.discard();
+                self.pending.push(event);
+            }
+            Traction::QueryEvent(t) if t.command == "BEGIN" => {
+                if self.pending.iter().any(|e| matches!(&e.data, Traction::QueryEvent(q) if q.command == "BEGIN")) {
+                    self.discard();
+                }
+                self.pending.push(event);
+            }
+            Traction::XidEvent(_) | Traction::QueryEvent(_) => {
+                self.pending.push(event);
+                self.finish();
+            }
+            _ if !self.pending.is_empty() => self.pending.push(event),
+            _ => self.ready.push_back(event),
+        }
+        Ok(())
+    }
+
+    //事务开始时仍有未结束的事务, io线程重新连接后主库从事务开头重新发送
+    fn discard(&mut self) {
+        if !self.pending.is_empty() {
+            self.pending.clear();
+            self.discarded += 1;
+        }
+    }
+
+    fn finish(&mut self) {
+        let events = std::mem::take(&mut self.pending);
+        let skipped = events.iter().any(|e| match &e.data {
+            Traction::GtidEvent(t) => self.skip_gtids.contains(&t.gtid, t.gno_id),
+            _ => false,
+        });
+        let end = events.last().map(|e| e.header.next_position as u64).unwrap_or(0);
+        let before = match &self.skip_until {
+            Some((file, position)) if end > 0 => (self.source_file.as_str(), end) <= (file.as_str(), *position),
+            _ => false,
+        };
+        if !before && end > 0 {
+            self.skip_until = None;
+        }
+        if !skipped && !before {
+            self.ready.extend(events);
+        }
+    }
+}
+
+impl Iterator for RelayTail {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        Some(self.read_event())
+    }
+}
+
+//生成artificial事件(ROTATE、HEARTBEAT), timestamp、next_position为0
+fn artificial(type_code: u8, event_length: u32, data: Traction) -> io::Result<Event> {
+    let mut buf = readvalue::write_u32(0);
+    buf.push(type_code);
+    buf.extend(readvalue::write_u32(0));
+    buf.extend(readvalue::write_u32(event_length));
+    buf.extend(readvalue::write_u32(0));
+    buf.extend(readvalue::write_u16(EventFlags::LOG_EVENT_ARTIFICIAL_F.bits()));
+    let header = EventHeader::read(&mut Cursor::new(buf))?;
+    Ok(Event{ header, log_pos: 0, data, table: None })
+}
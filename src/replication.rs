@@ -3,25 +3,56 @@
 @datetime: 2019/9/25
 */
 
+//mysql连接及主从注册相关的部分需要开启network特性, 解析部分不依赖网络
+#[cfg(feature = "network")]
 use std::net::TcpStream;
+#[cfg(feature = "network")]
 use crate::{replication, Config, readvalue, io};
+#[cfg(feature = "network")]
 use crate::io::{response,socketio};
+#[cfg(feature = "network")]
 use std::process;
-use serde_json::from_str;
+#[cfg(feature = "network")]
 use std::io::{BufReader, Seek, SeekFrom};
+#[cfg(feature = "network")]
 use std::fs::File;
+#[cfg(feature = "network")]
+use crate::gtid::GtidSet;
+#[cfg(feature = "network")]
+use crate::exitcode;
 
 pub mod readbinlog;
 pub mod readevent;
 pub mod parsevalue;
 pub mod jsonb;
+pub mod tablemeta;
+pub mod opaque;
+#[cfg(feature = "os")]
 pub mod rollback;
+#[cfg(feature = "os")]
 pub mod grep;
+pub mod copy;
+pub mod verify;
+pub mod carve;
+#[cfg(feature = "os")]
+pub mod synthesize;
+#[cfg_attr(not(feature = "test-support"), allow(dead_code))]
+pub(crate) mod encode;
+#[cfg(feature = "network")]
+pub mod backup;
+#[cfg(feature = "network")]
+pub mod precheck;
 
+#[cfg(feature = "network")]
 pub fn repl_register(conn: &mut TcpStream, conf: &Config) {
     let version = get_version(conn);
-    if conf.runtype == String::from("repl"){
+    if conf.runtype == String::from("repl") || conf.runtype == "backup" {
+        replication::precheck::validate_server(conn, conf);
         check_sum(conn);
+        heartbeat_period(conn, conf).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::USAGE);
+        });
         let mut regist_pack= vec![];
         if conf.gtid.len() > 0 {
             regist_pack = gtid_dump_pack(conf);
@@ -29,30 +60,75 @@ pub fn repl_register(conn: &mut TcpStream, conf: &Config) {
             regist_pack = binlog_dump_pack(conf);
         } else {
             println!("主从同步配置项错误，gtid/binlog模式必须给定其一的参数");
-            process::exit(1);
+            process::exit(exitcode::USAGE);
         }
         socketio::write_value(conn, &regist_pack).unwrap_or_else(|err|{
             println!("{}",err);
-            process::exit(1);
-        });
-        replication::readbinlog::readbinlog(conn, conf,&version);
-    }else if conf.runtype == String::from("file") {
-        let f = File::open(&conf.file).unwrap_or_else(|err|{
-            println!("创建文件({})访问发生错误:{}",conf.file, err);
-            process::exit(1);
+            process::exit(exitcode::CONNECT);
         });
-        let mut reader = BufReader::new(f);
+        if conf.runtype == "backup" {
+            replication::backup::backup_binlog(conn, conf);
+        }else {
+            //readbinlog在到达--until-gtid/--until-position时返回true, 否则只在mysql返回错误包时返回
+            if !replication::readbinlog::readbinlog(conn, conf,&version) {
+                crate::stdout::summary::finish("error");
+                process::exit(exitcode::SERVER);
+            }
+            crate::stdout::summary::finish("until");
+            if crate::stdout::matched_count() == 0 {
+                process::exit(exitcode::NO_EVENTS);
+            }
+        }
+    }else if conf.runtype == String::from("file") || conf.runtype == "copy" {
+        read_file(conf, &version);
+    }
+
+}
+
+#[cfg(feature = "network")]
+//从binlog文件读取, version为写入该文件的mysql主版本号
+pub fn read_file(conf: &Config, version: &u8) {
+    let f = File::open(&conf.file).unwrap_or_else(|err|{
+        println!("创建文件({})访问发生错误:{}",conf.file, err);
+        process::exit(exitcode::USAGE);
+    });
+    let mut reader = BufReader::new(f);
 //        if conf.startposition.len() > 0 {
 //            reader.seek(SeekFrom::Current(conf.startposition.parse().unwrap()));
 //        }else { reader.seek(SeekFrom::Current(4)); }
-        replication::readbinlog::readbinlog_fromfile(conf, &version, &mut reader)
+    let file_end = replication::readbinlog::readbinlog_fromfile(conf, version, &mut reader);
+    if let replication::readbinlog::FileEnd::Truncated(pos) = file_end {
+        println!("binlog文件({})不完整, 在position {} 处被截断", conf.file, pos);
+        crate::stdout::summary::record_error(&format!("binlog文件在position {} 处被截断", pos));
+    }
+    crate::stdout::summary::finish(file_end.reason());
+    if let replication::readbinlog::FileEnd::Truncated(_) = file_end {
+        process::exit(exitcode::PARSE);
+    }
+    if conf.runtype == "copy" {
+        crate::manifest::record(conf, || crate::manifest::ManifestEntry::binlog(&conf.output));
     }
+    //回滚、复制模式输出到文件, 其余模式没有任何数据通过过滤条件时返回NO_EVENTS
+    if conf.runtype == "file" && !conf.rollback && crate::stdout::matched_count() == 0 {
+        process::exit(exitcode::NO_EVENTS);
+    }
+}
 
+#[cfg(feature = "network")]
+//复制模式按原始事件写入新文件, 不需要连接mysql获取表结构, 按文件中FDE的版本解析事件
+pub fn copy_file(conf: &Config) {
+    let version = crate::fingerprint::from_file(&conf.file).unwrap_or_else(|err|{
+        println!("读取文件({})发生错误:{}", conf.file, err);
+        process::exit(exitcode::USAGE);
+    }).parser_version();
+    read_file(conf, &version);
 }
 
-fn check_sum(conn: &mut TcpStream) {
+#[cfg(feature = "network")]
+pub(crate) fn check_sum(conn: &mut TcpStream) {
     let sql = String::from("select @@BINLOG_CHECKSUM as checksum;");
-    let values = io::command::execute(conn,&sql);
+    //5.5及部分中间件没有该变量, 不设置
+    let values = io::command::try_execute(conn,&sql).unwrap_or_default();
     for row in values.iter(){
         for (_, value) in row{
             if value.len() > 0 {
@@ -64,14 +140,45 @@ fn check_sum(conn: &mut TcpStream) {
     }
 }
 
-fn get_version(conn: &mut TcpStream) -> u8 {
+//配置了heartbeat(秒)时设置心跳间隔, 没有新事件时mysql按该间隔发送HEARTBEAT_LOG_EVENT
+#[cfg(feature = "network")]
+pub(crate) fn heartbeat_period(conn: &mut TcpStream, conf: &Config) -> Result<(), String> {
+    if conf.heartbeat.is_empty() {
+        return Ok(());
+    }
+    let seconds = match conf.heartbeat.parse::<f64>() {
+        Ok(t) if t > 0.0 && t <= 4294967.0 => t,
+        _ => return Err(format!("无效的heartbeat: {}, 需要为0到4294967之间的秒数", conf.heartbeat))
+    };
+    //单位为纳秒
+    let sql = format!("set @master_heartbeat_period = {};", (seconds * 1e9) as u64);
+    io::command::execute_update(conn, &sql);
+    Ok(())
+}
+
+//连接后发送dump包重新注册, 控制接口回退位置时使用
+#[cfg(feature = "network")]
+pub(crate) fn start_dump(conn: &mut TcpStream, conf: &Config) -> Result<(), String> {
+    check_sum(conn);
+    heartbeat_period(conn, conf)?;
+    let regist_pack = if !conf.gtid.is_empty() {
+        gtid_dump_pack(conf)
+    } else if !conf.binlogfile.is_empty() {
+        binlog_dump_pack(conf)
+    } else {
+        return Err(String::from("主从同步配置项错误，gtid/binlog模式必须给定其一的参数"));
+    };
+    socketio::write_value(conn, &regist_pack).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "network")]
+pub(crate) fn get_version(conn: &mut TcpStream) -> u8 {
     let sql = String::from("select @@version;");
     let mut v = 0 as u8;
     let values = io::command::execute(conn,&sql);
     for row in values.iter(){
         for (_, value) in row{
-            let a: Vec<&str> = value.split(".").collect();
-            v = from_str::<u8>(a[0]).unwrap();
+            v = crate::fingerprint::ServerFingerprint::parse(value, "").parser_version();
         }
     }
     v
@@ -88,7 +195,8 @@ com_binlog_dump:
     server_id: 4bytes
     log_file
 */
-fn binlog_dump_pack(conf: &Config) -> Vec<u8> {
+#[cfg(feature = "network")]
+pub(crate) fn binlog_dump_pack(conf: &Config) -> Vec<u8> {
     let mut pack = vec![];
     let com_binlog_dump = 0x12 as u8;
     let flags = 0;
@@ -135,7 +243,8 @@ fn binlog_dump_pack(conf: &Config) -> Vec<u8> {
     3 is the stop position of the first interval.
 */
 
-fn gtid_dump_pack(conf: &Config) -> Vec<u8> {
+#[cfg(feature = "network")]
+pub(crate) fn gtid_dump_pack(conf: &Config) -> Vec<u8> {
     let mut pack = vec![];
     let com_binlog_dump_gtid = 0x1e as u8;
     let flags = 0;  //BINLOG_DUMP_BLOCK
@@ -145,8 +254,12 @@ fn gtid_dump_pack(conf: &Config) -> Vec<u8> {
     pack.extend(readvalue::write_u32(3));   //binlognamesize
     pack.extend(&[0u8;3]);   //binlogname
     pack.extend(readvalue::write_u64(4));   //binlog_pos_info
-    let gtids = gtid_parse(&conf.gtid);
-    let (gtid_prue, encode_length) = gtid_encode(&gtids);
+    let gtids: GtidSet = conf.gtid.parse().unwrap_or_else(|err|{
+        println!("{}", err);
+        process::exit(exitcode::USAGE);
+    });
+    let gtid_prue = gtids.encode();
+    let encode_length = gtid_prue.len();
 
     pack.extend(readvalue::write_u32(encode_length as u32));
     pack.extend(gtid_prue);
@@ -154,82 +267,3 @@ fn gtid_dump_pack(conf: &Config) -> Vec<u8> {
     pack_all.extend(pack);
     pack_all
 }
-
-fn gtid_encode(gtids: &Vec<GtidSet>) -> (Vec<u8>, usize){
-    let mut gtid_prue = vec![];
-    let mut encode_length: usize = 8;  //n_sid 字节长度
-    let n_sid = gtids.len();
-    gtid_prue.extend(readvalue::write_u64(n_sid as u64));
-    for gtidset in gtids{
-        let sid = &gtidset.sid;
-        let a = uuid::Uuid::from(sid.parse().unwrap());
-        let interval_len = gtidset.intervals.len();
-        encode_length += 16 + 8 + (interval_len * (8 + 8)); //一个sid 加 多个pos起始、终止位置的长度
-        gtid_prue.extend(a.as_bytes());
-        gtid_prue.extend(readvalue::write_u64(gtidset.intervals.len() as u64));
-        for inter in &gtidset.intervals{
-            gtid_prue.extend(readvalue::write_u64(inter.interval_start as u64));
-            gtid_prue.extend(readvalue::write_u64(inter.interval_stop as u64));
-        }
-    }
-    (gtid_prue, encode_length)
-}
-
-struct GtidIntervals{
-    interval_start: usize,
-    interval_stop: usize
-}
-impl GtidIntervals{
-    fn new(interval: &str) -> GtidIntervals{
-        let mut interval_start: usize = 0;
-        let mut interval_stop: usize = 0;
-        let intervals: Vec<&str> = interval.split("-").collect();
-        match intervals.len() {
-            1 =>{
-                interval_start = intervals[0].parse::<usize>().unwrap();
-                interval_stop = interval_start + 1;
-            }
-            2 => {
-                interval_start = intervals[0].parse::<usize>().unwrap();
-                interval_stop = intervals[1].parse::<usize>().unwrap() + 1;
-            }
-            _ => {}
-        }
-
-        GtidIntervals{
-            interval_start,
-            interval_stop
-        }
-    }
-}
-
-struct GtidSet {
-    sid: String,
-    intervals: Vec<GtidIntervals>
-}
-impl GtidSet{
-    fn new(gtid: &str) -> GtidSet {
-        let mut intervals: Vec<GtidIntervals>= vec![];
-        let gtid_info: Vec<&str> = gtid.split(":").collect();
-        let sid = gtid_info[0].parse().unwrap();
-        for (idx,interval) in gtid_info.iter().enumerate(){
-            if idx > 0{
-                intervals.push(GtidIntervals::new(interval));
-            }
-        }
-
-        GtidSet{
-            sid ,
-            intervals
-        }
-    }
-}
-
-fn gtid_parse(gtid: &String) -> Vec<GtidSet> {
-    let mut gtids = vec![];
-    let conf_gtids: Vec<&str> = gtid.split(",").collect();
-    for gtid_info in conf_gtids{
-        gtids.push(GtidSet::new(gtid_info));
-    }
-    gtids
-}
\ No newline at end of file
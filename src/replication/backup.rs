@@ -0,0 +1,143 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/28
+*/
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process;
+use crate::{Config, readvalue};
+use crate::io::{socketio, pack};
+use crate::replication::copy;
+use crate::replication::verify::{ChecksumClass, ChecksumVerifier};
+use crate::replication::readevent::EventFlags;
+use crate::exitcode;
+use crate::sink::OutputCompression;
+
+/*
+将从master拉取的binlog原样写入--output指定的目录, 文件名与master一致:
+    master发送的ROTATE_EVENT带有LOG_EVENT_ARTIFICIAL_F时只用于通知当前读取的文件, 不写入文件
+    真实的ROTATE_EVENT写入当前文件后结束该文件
+    HEARTBEAT_LOG_EVENT不写入文件
+从文件中间的position开始拉取时, 写入的文件不完整, 需从position 4开始
+--output-compress时文件名加上压缩算法的扩展名, 每1MB压缩为一个独立的块写出, 文件结束时写出剩余部分,
+    需要解压后才能解析或通过serve提供, manifest中只记录压缩文件的大小及sha256
+*/
+pub fn backup_binlog(conn: &mut TcpStream, conf: &Config) {
+    let dir = Path::new(&conf.output);
+    fs::create_dir_all(dir).unwrap_or_else(|err|{
+        println!("创建备份目录({})发生错误:{}", conf.output, err);
+        process::exit(exitcode::SINK);
+    });
+
+    let compression = crate::sink::output_compression(&conf.outputcompress).unwrap_or_else(|err|{
+        println!("{}", err);
+        process::exit(exitcode::USAGE);
+    });
+    let mut file: Option<Box<dyn Write>> = None;
+    let mut cur_path = PathBuf::new();
+    let mut cur_file = String::from("");
+    let mut next_file = String::from("");
+    let mut verifier = ChecksumVerifier::new(ChecksumClass::parse(&conf.verifychecksum).unwrap_or(ChecksumClass::empty()));
+    loop {
+        let (buf, _) = socketio::get_packet_from_stream(conn);
+        if !pack::check_pack(&buf){
+            let err = pack::erro_pack(&buf);
+            println!("注册slave发生错误:{}",err);
+            process::exit(exitcode::SERVER);
+        }
+        let event = &buf[1..];
+        if event.len() < 19 {
+            continue;
+        }
+        if verifier.enabled() && !verifier.check(&event[..19], &event[19..]) {
+            println!("binlog文件({})中next_position {} 处的事件checksum错误", cur_file, readvalue::read_u32(&event[13..17]));
+            process::exit(exitcode::PARSE);
+        }
+        let flags = EventFlags::from_bits_truncate(readvalue::read_u16(&event[17..19]));
+        match event[4] {
+            4 => {
+                let binlog_file = rotate_file_name(event);
+                if flags.contains(EventFlags::LOG_EVENT_ARTIFICIAL_F) {
+                    if binlog_file != cur_file || file.is_none() {
+                        finish_file(&mut file, &cur_file);
+                        (file, cur_path) = create_binlog_file(dir, &binlog_file, &compression);
+                        cur_file = binlog_file;
+                    }
+                    continue;
+                }
+                write_event(&mut file, &cur_file, event);
+                finish_file(&mut file, &cur_file);
+                //文件已结束, 写入manifest
+                crate::manifest::record(conf, || {
+                    let path = cur_path.to_string_lossy();
+                    match &compression {
+                        Some(t) => crate::manifest::ManifestEntry::file(&path, &format!("binlog.{}", t.compressor.extension())),
+                        None => crate::manifest::ManifestEntry::binlog(&path),
+                    }
+                });
+                next_file = binlog_file;
+                continue;
+            }
+            27 => continue,
+            _ => {}
+        }
+        if file.is_none() {
+            if next_file.is_empty() {
+                println!("未获取到binlog文件名, 无法写入备份文件");
+                process::exit(exitcode::SINK);
+            }
+            (file, cur_path) = create_binlog_file(dir, &next_file, &compression);
+            cur_file = next_file.clone();
+        }
+        write_event(&mut file, &cur_file, event);
+    }
+}
+
+fn create_binlog_file(dir: &Path, binlog_file: &str, compression: &Option<OutputCompression>) -> (Option<Box<dyn Write>>, PathBuf) {
+    let path = match compression {
+        Some(t) => dir.join(format!("{}.{}", binlog_file, t.compressor.extension())),
+        None => dir.join(binlog_file),
+    };
+    let file = File::create(&path).unwrap_or_else(|err|{
+        println!("创建文件({})发生错误:{}", path.display(), err);
+        process::exit(exitcode::SINK);
+    });
+    let mut file: Box<dyn Write> = match compression {
+        Some(t) => Box::new(t.writer(file)),
+        None => Box::new(file),
+    };
+    file.write_all(&[0xFE,0x62,0x69,0x6E]).unwrap_or_else(|err|{
+        println!("写入文件({})发生错误:{}", path.display(), err);
+        process::exit(exitcode::SINK);
+    });
+    println!("备份binlog文件: {}", path.display());
+    (Some(file), path)
+}
+
+//结束当前文件, 压缩时写出剩余的数据
+fn finish_file(file: &mut Option<Box<dyn Write>>, binlog_file: &str) {
+    if let Some(mut f) = file.take() {
+        f.flush().unwrap_or_else(|err|{
+            println!("写入文件({})发生错误:{}", binlog_file, err);
+            process::exit(exitcode::SINK);
+        });
+    }
+}
+
+fn write_event(file: &mut Option<Box<dyn Write>>, binlog_file: &str, event: &[u8]) {
+    if let Some(f) = file {
+        f.write_all(event).unwrap_or_else(|err|{
+            println!("写入文件({})发生错误:{}", binlog_file, err);
+            process::exit(exitcode::SINK);
+        });
+    }
+}
+
+//ROTATE_EVENT: header(19bytes) + position(8bytes) + file_name, 启用checksum时最后4bytes为crc32
+fn rotate_file_name(event: &[u8]) -> String {
+    let end = if copy::has_checksum(event) { event.len() - 4 } else { event.len() };
+    String::from_utf8_lossy(&event[27.min(end)..end]).to_string()
+}
@@ -0,0 +1,288 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//从部分被覆盖的binlog文件或恢复出的磁盘镜像中提取完整的事务, 写入新的binlog文件, 用于数据恢复
+//  逐字节扫描可能的事件包头: 类型码、event_length、flags在合理范围内, next_position不小于event_length
+//    带checksum时crc32需要正确; 不带checksum时之后紧接着的也要是合理的包头或数据结束
+//  第一个FORMAT_DESCRIPTION_EVENT决定输出文件是否带checksum及各事件的post header长度, 没有找到时按5.7的格式生成
+//  之后遇到的FORMAT_DESCRIPTION_EVENT决定后续事件是否带checksum, 写入时统一为输出文件的格式
+//  连续的事件按GTID/BEGIN...XID/COMMIT组成事务, 中间有间隔或缺少开始、结束的事务丢弃
+//  只输出完整的事务, 不完整事务中已写入下游的部分需要另外核对
+
+#[cfg(feature = "os")]
+use std::fs;
+#[cfg(feature = "os")]
+use std::io;
+use crate::readvalue;
+use crate::replication::copy;
+use crate::replication::encode::{self, ServerVersion};
+
+//单个事件的最大长度, 与max_allowed_packet的上限相同
+const MAX_EVENT_LENGTH: usize = 1 << 30;
+
+/// 提取的结果
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CarveReport{
+    /// 扫描的字节数
+    pub bytes: u64,
+    /// 找到的事件数, 不含FORMAT_DESCRIPTION_EVENT
+    pub events: u64,
+    /// 输出的完整事务数
+    pub transactions: u64,
+    /// 丢弃的不完整事务数
+    pub incomplete: u64,
+    /// 不属于任何事件的字节数
+    pub skipped: u64,
+    /// 数据中是否有FORMAT_DESCRIPTION_EVENT, 没有时按5.7的格式生成
+    pub format_description: bool,
+    pub checksum: bool,
+}
+
+/// 提取出的FORMAT_DESCRIPTION_EVENT及完整的事务, 事务中的事件已统一为FORMAT_DESCRIPTION_EVENT的checksum格式
+#[derive(Debug, Default)]
+pub struct Carved{
+    pub format_description: Vec<u8>,
+    pub transactions: Vec<Vec<u8>>,
+    pub report: CarveReport,
+}
+
+//组装中的事务
+#[derive(Default)]
+struct Pending{
+    data: Vec<u8>,
+    gtid: bool,
+    begun: bool,
+}
+
+/// 扫描data中的事件, 提取完整的事务
+pub fn carve(data: &[u8]) -> Carved {
+    let first = (0..data.len()).find_map(|pos| format_description_at(data, pos).map(|len| &data[pos..pos + len]));
+    let mut checksum = match first {
+        Some(fde) => copy::has_checksum(fde),
+        None => (0..data.len()).any(|pos| header_at(data, pos, true).is_some()),
+    };
+    let output_checksum = checksum;
+    let mut carved = Carved{
+        format_description: match first {
+            Some(t) => t.to_vec(),
+            None => encode::format_description(ServerVersion::Mysql57.version_string(), ServerVersion::Mysql57.post_header_lengths(), 0, 1, checksum),
+        },
+        ..Carved::default()
+    };
+    carved.report.bytes = data.len() as u64;
+    carved.report.format_description = first.is_some();
+    carved.report.checksum = output_checksum;
+
+    let mut pending: Option<Pending> = None;
+    let mut last_end = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        if let Some(len) = format_description_at(data, pos) {
+            checksum = copy::has_checksum(&data[pos..pos + len]);
+            discard(&mut pending, &mut carved.report);
+            pos += len;
+            last_end = pos;
+            continue;
+        }
+        let len = match event_at(data, pos, checksum) {
+            Some(t) => t,
+            None => {
+                carved.report.skipped += 1;
+                pos += 1;
+                continue;
+            }
+        };
+        //与上一个事件之间有间隔时, 组装中的事务不完整
+        if pos != last_end {
+            discard(&mut pending, &mut carved.report);
+        }
+        carved.report.events += 1;
+        let event = normalize(&data[pos..pos + len], checksum, output_checksum);
+        if let Some(trx) = append(&mut pending, event, output_checksum, &mut carved.report) {
+            carved.transactions.push(trx);
+            carved.report.transactions += 1;
+        }
+        pos += len;
+        last_end = pos;
+    }
+    discard(&mut pending, &mut carved.report);
+    carved
+}
+
+/// 读取input的全部内容后提取, 写入output, output为合法的binlog文件
+#[cfg(feature = "os")]
+pub fn carve_file(input: &str, output: &str) -> io::Result<CarveReport> {
+    let data = fs::read(input)?;
+    let carved = carve(&data);
+    let mut writer = copy::BinlogWriter::new(output, &carved.format_description)?;
+    for trx in &carved.transactions {
+        writer.write_traction(trx)?;
+    }
+    Ok(carved.report)
+}
+
+fn discard(pending: &mut Option<Pending>, report: &mut CarveReport) {
+    if let Some(trx) = pending.take() {
+        if !trx.data.is_empty() {
+            report.incomplete += 1;
+        }
+    }
+}
+
+//将事件加入组装中的事务, 事务完整时返回
+fn append(pending: &mut Option<Pending>, event: Vec<u8>, checksum: bool, report: &mut CarveReport) -> Option<Vec<u8>> {
+    match event[4] {
+        //GTID_EVENT、ANONYMOUS_GTID_EVENT为新事务的开始
+        33 | 34 => {
+            discard(pending, report);
+            *pending = Some(Pending{ data: event, gtid: true, begun: false });
+            None
+        }
+        2 => {
+            let statement = query_statement(&event, checksum).to_uppercase();
+            let statement = statement.trim();
+            if statement == "BEGIN" || statement.starts_with("XA START") {
+                match pending {
+                    Some(trx) if trx.gtid && !trx.begun => {
+                        trx.data.extend(event);
+                        trx.begun = true;
+                    }
+                    _ => {
+                        discard(pending, report);
+                        *pending = Some(Pending{ data: event, gtid: false, begun: true });
+                    }
+                }
+                return None;
+            }
+            match pending.take() {
+                //COMMIT/ROLLBACK结束事务, 其余为事务中的语句(statement格式)
+                Some(mut trx) if trx.begun => {
+                    trx.data.extend(event);
+                    if statement == "COMMIT" || statement == "ROLLBACK" {
+                        return Some(trx.data);
+                    }
+                    *pending = Some(trx);
+                    None
+                }
+                //DDL、XA COMMIT为单独的事务
+                Some(mut trx) => {
+                    trx.data.extend(event);
+                    Some(trx.data)
+                }
+                None => Some(event),
+            }
+        }
+        //XID_EVENT、XA_PREPARE_LOG_EVENT
+        16 | 38 => match pending.take() {
+            Some(mut trx) if trx.begun => {
+                trx.data.extend(event);
+                Some(trx.data)
+            }
+            trx => {
+                *pending = trx;
+                discard(pending, report);
+                None
+            }
+        },
+        //8.0压缩的事务: GTID之后为一个TRANSACTION_PAYLOAD_EVENT
+        40 => match pending.take() {
+            Some(mut trx) if trx.gtid && !trx.begun => {
+                trx.data.extend(event);
+                Some(trx.data)
+            }
+            trx => {
+                *pending = trx;
+                discard(pending, report);
+                None
+            }
+        },
+        //TABLE_MAP、行事件、ROWS_QUERY、INTVAR、RAND、USER_VAR等只在事务中
+        5 | 13 | 14 | 19..=25 | 29..=32 | 39 => {
+            match pending {
+                Some(trx) if trx.begun => trx.data.extend(event),
+                _ => discard(pending, report),
+            }
+            None
+        }
+        //ROTATE、PREVIOUS_GTIDS、STOP等不属于事务
+        _ => None,
+    }
+}
+
+//QUERY_EVENT中的语句, post header为13bytes: thread_id、exec_time、db长度、error_code、status_vars长度
+fn query_statement(event: &[u8], checksum: bool) -> String {
+    let end = event.len() - if checksum { 4 } else { 0 };
+    if end < 19 + 13 {
+        return String::new();
+    }
+    let db_len = event[19 + 8] as usize;
+    let status_len = readvalue::read_u16(&event[19 + 11..19 + 13]) as usize;
+    let start = 19 + 13 + status_len + db_len + 1;
+    if start > end {
+        return String::new();
+    }
+    String::from_utf8_lossy(&event[start..end]).to_string()
+}
+
+//转换为输出文件的checksum格式, checksum由写入时重新计算
+fn normalize(event: &[u8], checksum: bool, output_checksum: bool) -> Vec<u8> {
+    let mut event = event.to_vec();
+    if checksum && !output_checksum {
+        event.truncate(event.len() - 4);
+    } else if !checksum && output_checksum {
+        event.extend([0u8; 4]);
+    }
+    let len = event.len() as u32;
+    event[9..13].copy_from_slice(&readvalue::write_u32(len));
+    event
+}
+
+//pos处为FORMAT_DESCRIPTION_EVENT时返回长度: binlog_version为4, 之后为以数字开头的server版本
+fn format_description_at(data: &[u8], pos: usize) -> Option<usize> {
+    if pos + 19 + 57 > data.len() || data[pos + 4] != 15 {
+        return None;
+    }
+    let len = readvalue::read_u32(&data[pos + 9..pos + 13]) as usize;
+    if !(19 + 57..=1024).contains(&len) || pos + len > data.len() {
+        return None;
+    }
+    let body = &data[pos + 19..pos + len];
+    if readvalue::read_u16(&body[0..2]) != 4 || !body[2].is_ascii_digit() || body[2 + 50 + 4] != 19 {
+        return None;
+    }
+    Some(len)
+}
+
+//只检查包头, 返回event_length
+fn header_at(data: &[u8], pos: usize, checksum: bool) -> Option<usize> {
+    if pos + 19 > data.len() {
+        return None;
+    }
+    let header = &data[pos..pos + 19];
+    if !(2..=41).contains(&header[4]) || readvalue::read_u16(&header[17..19]) >= 0x800 {
+        return None;
+    }
+    let len = readvalue::read_u32(&header[9..13]) as usize;
+    if len < 19 + if checksum { 4 } else { 0 } || len > MAX_EVENT_LENGTH || pos + len > data.len() {
+        return None;
+    }
+    let next_position = readvalue::read_u32(&header[13..17]) as usize;
+    if next_position != 0 && next_position < len {
+        return None;
+    }
+    if checksum && readvalue::crc32(&data[pos..pos + len - 4]) != readvalue::read_u32(&data[pos + len - 4..pos + len]) {
+        return None;
+    }
+    Some(len)
+}
+
+//不带checksum时包头的约束较弱, 要求之后紧接着的也是合理的包头
+fn event_at(data: &[u8], pos: usize, checksum: bool) -> Option<usize> {
+    let len = header_at(data, pos, checksum)?;
+    if checksum || pos + len == data.len() || header_at(data, pos + len, false).is_some() || format_description_at(data, pos + len).is_some() {
+        return Some(len);
+    }
+    None
+}
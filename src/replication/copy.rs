@@ -0,0 +1,171 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/25
+*/
+
+#[cfg(feature = "os")]
+use std::fs::File;
+#[cfg(feature = "os")]
+use std::io::{Cursor, Write, Result};
+#[cfg(feature = "os")]
+use uuid::Uuid;
+use crate::readvalue;
+#[cfg(feature = "os")]
+use crate::gtid::GtidSet;
+#[cfg(feature = "os")]
+use crate::replication::encode;
+#[cfg(feature = "os")]
+use crate::replication::readevent::{self, EventFlags, InitValue};
+
+/*
+将事件重新写入一个新的binlog文件:
+    每个事件包头中的next_position(13..17)按新文件中的位置重写
+    如果源文件带有checksum, 重新计算每个事件最后4bytes的crc32
+*/
+#[cfg(feature = "os")]
+pub struct BinlogWriter{
+    file: File,
+    position: u64,
+    checksum: bool,
+}
+
+#[cfg(feature = "os")]
+impl BinlogWriter{
+    pub fn new(path: &str, desc_format: &[u8]) -> Result<BinlogWriter> {
+        let mut file = File::create(path)?;
+        file.write_all(&[0xFE,0x62,0x69,0x6E])?;
+        let mut writer = BinlogWriter{
+            file,
+            position: 4,
+            checksum: has_checksum(desc_format),
+        };
+
+        //新文件写入完成后是正常关闭的状态, 去掉FDE中的LOG_EVENT_BINLOG_IN_USE_F
+        let mut fde = desc_format.to_vec();
+        let flags = EventFlags::from_bits_truncate(readvalue::read_u16(&fde[17..19]));
+        fde[17..19].copy_from_slice(&readvalue::write_u16((flags - EventFlags::LOG_EVENT_BINLOG_IN_USE_F).bits()));
+        writer.write_event(&fde)?;
+        Ok(writer)
+    }
+
+    pub fn write_event(&mut self, event: &[u8]) -> Result<()> {
+        let mut event = event.to_vec();
+        let event_length = event.len();
+        let next_position = self.position + event_length as u64;
+        event[13..17].copy_from_slice(&readvalue::write_u32(next_position as u32));
+        if self.checksum {
+            let crc = readvalue::crc32(&event[..event_length - 4]);
+            event[event_length - 4..].copy_from_slice(&readvalue::write_u32(crc));
+        }
+        self.file.write_all(&event)?;
+        self.position = next_position;
+        Ok(())
+    }
+
+    //写入一个完整事务, traction为连续存放的多个事件, event_length来自文件内容, 超出事务的范围时返回错误
+    pub fn write_traction(&mut self, traction: &[u8]) -> Result<()> {
+        let mut offset = 0;
+        while offset < traction.len() {
+            let event_length = match traction.get(offset + 9..offset + 13) {
+                Some(t) => readvalue::read_u32(t) as usize,
+                None => return Err(readvalue::invalid_data(format!("事务中offset {} 处的事件不完整", offset)))
+            };
+            if event_length < 19 || offset + event_length > traction.len() {
+                return Err(readvalue::invalid_data(format!("事务中offset {} 处的事件长度({})错误", offset, event_length)));
+            }
+            self.write_event(&traction[offset..offset + event_length])?;
+            offset += event_length;
+        }
+        Ok(())
+    }
+}
+
+/*
+复制模式(copy): 满足过滤条件的事务按原始事件写入新文件
+    previous_gtids_event按源文件的previous_gtids加上没有写入新文件的事务重新计算, 在写入第一个事务之前(或读取结束时)写入
+    没有xid_event的事务(ddl、xa prepare)在该事件之后结束
+*/
+#[cfg(feature = "os")]
+pub struct CopyTrac{
+    writer: BinlogWriter,
+    checksum: bool,
+    previous: Option<(Vec<u8>, GtidSet)>,     //待写入的previous_gtids_event(源文件中的事件及重新计算的gtid集合)
+    gtid: Option<(Uuid, u64)>,                //当前事务的gtid, 事务写入新文件后清空
+    pub end: bool,                            //当前事务没有xid_event, 在该事件之后结束
+}
+
+#[cfg(feature = "os")]
+impl CopyTrac{
+    pub fn new(path: &str, desc_format: &[u8], previous: Option<(Vec<u8>, GtidSet)>) -> Result<CopyTrac> {
+        Ok(CopyTrac{
+            writer: BinlogWriter::new(path, desc_format)?,
+            checksum: has_checksum(desc_format),
+            previous,
+            gtid: None,
+            end: false,
+        })
+    }
+
+    //读取到gtid_event, 上一个事务没有写入新文件时计入previous_gtids
+    pub fn observe(&mut self, event: &[u8]) {
+        if event.len() < 19 || event[4] != 33 {
+            return;
+        }
+        self.skip_gtid();
+        self.gtid = readevent::EventHeader::read(&mut Cursor::new(event))
+            .and_then(|event_header| readevent::GtidEvent::read_event(&event_header, &mut Cursor::new(&event[19..]), &0))
+            .ok().map(|v| (v.gtid, v.gno_id));
+    }
+
+    fn skip_gtid(&mut self) {
+        if let (Some((_, gtids)), Some((sid, gno))) = (&mut self.previous, self.gtid.take()) {
+            gtids.add(sid, gno);
+        }
+    }
+
+    //gtid之后的第一个query_event不是BEGIN(ddl、xa commit)时为一个完整的事务, command为None时为xa prepare
+    pub fn check_end(&mut self, traction: &[u8], command: Option<&str>) {
+        let only_gtid = traction.is_empty() ||
+            (is_gtid_event(traction) && readvalue::read_u32(&traction[9..13]) as usize == traction.len());
+        self.end = match command {
+            Some(command) => only_gtid && !["BEGIN", "XA START"].iter().any(|t| command.trim().to_uppercase().starts_with(t)),
+            None => true
+        };
+    }
+
+    //写入一个完整的事务, 第一个事务之前写入previous_gtids_event
+    pub fn write_traction(&mut self, traction: &[u8]) -> Result<()> {
+        self.gtid = None;
+        self.end = false;
+        self.write_previous_gtids()?;
+        self.writer.write_traction(traction)
+    }
+
+    //读取结束, 没有写入任何事务时写入previous_gtids_event
+    pub fn finish(&mut self) -> Result<()> {
+        self.skip_gtid();
+        self.write_previous_gtids()
+    }
+
+    fn write_previous_gtids(&mut self) -> Result<()> {
+        let Some((event, gtids)) = self.previous.take() else {
+            return Ok(());
+        };
+        let event = encode::event(readvalue::read_u32(&event[0..4]), 35, readvalue::read_u32(&event[5..9]), &gtids.encode(), self.checksum);
+        self.writer.write_event(&event)
+    }
+}
+
+//通过比对FDE最后4bytes是否为其内容的crc32来判断文件是否启用了checksum
+pub fn has_checksum(desc_format: &[u8]) -> bool {
+    let len = desc_format.len();
+    if len < 23 {
+        return false;
+    }
+    readvalue::crc32(&desc_format[..len - 4]) == readvalue::read_u32(&desc_format[len - 4..])
+}
+
+//判断事件是否为事务的开始(gtid_event/anonymous_gtid_event)
+pub fn is_gtid_event(event: &[u8]) -> bool {
+    event.len() > 4 && (event[4] == 33 || event[4] == 34)
+}
@@ -0,0 +1,749 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/04
+*/
+
+//将解析得到的事件重新编码为binlog格式, 用于往返测试(编码后再解析得到相同的值)以及构造测试用的binlog文件
+//  只支持解析时能够识别的字段类型, bit、geometry等类型或值与字段类型不匹配时返回InvalidInput错误
+//  BinlogBuilder在内存中生成完整的binlog文件, 追加事件时计算next_position及checksum
+//  开启test-support特性后通过mytest::testing使用
+
+use std::io;
+use serde_json::Value as JsonValue;
+use crate::readvalue;
+use crate::gtid::GtidSet;
+use crate::meta::ColumnTypeDict;
+use crate::replication::readevent::{BinlogEvent, ColumnInfo, GtidEvent, QueryEvent, RotateLog, RowsQueryEvent, TableMap, XidEvent};
+use crate::replication::parsevalue::{DecimalMeta, MySQLValue};
+
+//FORMAT_DESCRIPTION_EVENT记录的各事件类型post-header长度(类型1-N)
+const POST_HEADER_LENGTHS_56: [u8; 35] = [56, 13, 0, 8, 0, 18, 0, 4, 4, 4, 4, 18, 0, 0, 92, 0, 4, 26, 8, 0, 0, 0,
+    8, 8, 8, 2, 0, 0, 0, 10, 10, 10, 25, 25, 0];
+pub(crate) const POST_HEADER_LENGTHS_57: [u8; 38] = [56, 13, 0, 8, 0, 18, 0, 4, 4, 4, 4, 18, 0, 0, 95, 0, 4, 26, 8, 0, 0, 0,
+    8, 8, 8, 2, 0, 0, 0, 10, 10, 10, 42, 42, 0, 18, 52, 0];
+const POST_HEADER_LENGTHS_80: [u8; 41] = [0, 13, 0, 8, 0, 0, 0, 0, 4, 0, 4, 0, 0, 0, 98, 0, 4, 26, 8, 0, 0, 0,
+    8, 8, 8, 2, 0, 0, 0, 10, 10, 10, 42, 42, 0, 18, 52, 0, 10, 40, 0];
+
+/// 生成的binlog对应的mysql版本, 决定FORMAT_DESCRIPTION_EVENT及GTID_EVENT的格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServerVersion{
+    Mysql56,
+    Mysql57,
+    Mysql80,
+}
+
+impl ServerVersion{
+    pub fn version_string(&self) -> &'static str {
+        match self {
+            ServerVersion::Mysql56 => "5.6.51-log",
+            ServerVersion::Mysql57 => "5.7.30-log",
+            ServerVersion::Mysql80 => "8.0.30",
+        }
+    }
+
+    pub fn post_header_lengths(&self) -> &'static [u8] {
+        match self {
+            ServerVersion::Mysql56 => &POST_HEADER_LENGTHS_56,
+            ServerVersion::Mysql57 => &POST_HEADER_LENGTHS_57,
+            ServerVersion::Mysql80 => &POST_HEADER_LENGTHS_80,
+        }
+    }
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// length encoded integer
+pub fn lenenc(value: usize) -> Vec<u8> {
+    if value < 251 {
+        vec![value as u8]
+    }else if value < 1 << 16 {
+        let mut v = vec![0xfc];
+        v.extend(readvalue::write_u16(value as u16));
+        v
+    }else if value < 1 << 24 {
+        let mut v = vec![0xfd];
+        v.extend(readvalue::write_u24(value as u32));
+        v
+    }else {
+        let mut v = vec![0xfe];
+        v.extend(readvalue::write_u64(value as u64));
+        v
+    }
+}
+
+/// 按位记录的bitmap, 第idx位为set(idx)
+pub fn bitmap(bits: usize, set: impl Fn(usize) -> bool) -> Vec<u8> {
+    let mut map = vec![0u8; bits.div_ceil(8)];
+    for idx in (0..bits).filter(|idx| set(*idx)) {
+        map[idx / 8] |= 1 << (idx % 8);
+    }
+    map
+}
+
+/// 事件包头 + 内容, checksum为true时末尾预留4bytes, next_position及checksum由写入方填写
+pub fn event(timestamp: u32, type_code: u8, server_id: u32, body: &[u8], checksum: bool) -> Vec<u8> {
+    let event_length = 19 + body.len() + if checksum { 4 } else { 0 };
+    let mut event = Vec::with_capacity(event_length);
+    event.extend(readvalue::write_u32(timestamp));
+    event.push(type_code);
+    event.extend(readvalue::write_u32(server_id));
+    event.extend(readvalue::write_u32(event_length as u32));
+    event.extend(readvalue::write_u32(0));
+    event.extend(readvalue::write_u16(0));
+    event.extend_from_slice(body);
+    if checksum {
+        event.extend([0u8; 4]);
+    }
+    event
+}
+
+//填写next_position及checksum
+fn seal(event: &mut [u8], position: u64, checksum: bool) {
+    let len = event.len();
+    event[13..17].copy_from_slice(&readvalue::write_u32((position + len as u64) as u32));
+    if checksum {
+        let crc = readvalue::crc32(&event[..len - 4]);
+        event[len - 4..].copy_from_slice(&readvalue::write_u32(crc));
+    }
+}
+
+/// 完整的FORMAT_DESCRIPTION_EVENT, 位于文件开头(position 4)
+pub fn format_description(server_version: &str, post_header_lengths: &[u8], timestamp: u32, server_id: u32, checksum: bool) -> Vec<u8> {
+    let mut body = readvalue::write_u16(4);
+    let mut version = server_version.as_bytes().to_vec();
+    version.resize(50, 0);
+    body.extend(version);
+    body.extend(readvalue::write_u32(timestamp));
+    body.push(19);
+    body.extend_from_slice(post_header_lengths);
+    body.push(if checksum { 1 } else { 0 });      //checksum算法: 0为关闭, 1为crc32
+    let mut fde = event(timestamp, 15, server_id, &body, checksum);
+    seal(&mut fde, 4, checksum);
+    fde
+}
+
+/// 5.6只有flags、sid、gno, 8.0在sequence_number之后记录提交时间、事务长度及server版本
+pub fn gtid_body(event: &GtidEvent, version: ServerVersion, timestamp: u32) -> Vec<u8> {
+    let mut body = vec![0u8];
+    body.extend_from_slice(event.gtid.as_bytes());
+    body.extend(readvalue::write_u64(event.gno_id));
+    if version == ServerVersion::Mysql56 {
+        return body;
+    }
+    body.push(2);
+    body.extend(readvalue::write_u64(event.last_committed));
+    body.extend(readvalue::write_u64(event.sequence_number));
+    if version == ServerVersion::Mysql80 {
+        //immediate_commit_timestamp(7bytes, 最高位为0表示与original_commit_timestamp相同, 没有指定时为事件时间)、transaction_length、immediate_server_version
+        body.extend(&readvalue::write_u64(event.commit_timestamp.unwrap_or(timestamp as u64 * 1_000_000))[..7]);
+        body.extend(lenenc(0));
+        body.extend(readvalue::write_u32(80030));
+    }
+    body
+}
+
+/// status_vars包含Q_FLAGS2_CODE、Q_SQL_MODE_CODE、Q_CHARSET_CODE(utf8mb4_general_ci), invoker不为空时加上Q_INVOKER
+pub fn query_body(event: &QueryEvent) -> Vec<u8> {
+    let mut status = vec![0u8, 0, 0, 0, 0, 1];
+    status.extend([0u8; 8]);
+    status.extend([4u8, 45, 0, 45, 0, 45, 0]);
+    if let Some((user, host)) = event.invoker.rsplit_once('@') {
+        status.push(11);
+        status.push(user.len() as u8);
+        status.extend_from_slice(user.as_bytes());
+        status.push(host.len() as u8);
+        status.extend_from_slice(host.as_bytes());
+    }
+    let db = event.database.as_bytes();
+    let mut body = readvalue::write_u32(event.thread_id);
+    body.extend(readvalue::write_u32(event.execute_seconds));
+    body.push(db.len() as u8);
+    body.extend(readvalue::write_u16(0));
+    body.extend(readvalue::write_u16(status.len() as u16));
+    body.extend(status);
+    body.extend_from_slice(db);
+    body.push(0);
+    body.extend_from_slice(event.command.as_bytes());
+    body
+}
+
+pub fn xid_body(event: &XidEvent) -> Vec<u8> {
+    readvalue::write_u64(event.xid)
+}
+
+pub fn rotate_body(event: &RotateLog) -> Vec<u8> {
+    let mut body = readvalue::write_u64(event.position);
+    body.extend_from_slice(event.binlog_file.as_bytes());
+    body
+}
+
+pub fn rows_query_body(event: &RowsQueryEvent) -> Vec<u8> {
+    let mut body = vec![event.query.len() as u8];
+    body.extend_from_slice(event.query.as_bytes());
+    body
+}
+
+pub fn previous_gtids_body(gtids: &GtidSet) -> Vec<u8> {
+    gtids.encode()
+}
+
+fn type_code(column_type: &ColumnTypeDict) -> Option<u8> {
+    Some(match column_type {
+        ColumnTypeDict::MysqlTypeTiny => 1,
+        ColumnTypeDict::MysqlTypeShort => 2,
+        ColumnTypeDict::MysqlTypeLong => 3,
+        ColumnTypeDict::MysqlTypeFloat => 4,
+        ColumnTypeDict::MysqlTypeDouble => 5,
+        ColumnTypeDict::MysqlTypeLonglong => 8,
+        ColumnTypeDict::MysqlTypeInt24 => 9,
+        ColumnTypeDict::MysqlTypeTimestamp => 7,
+        ColumnTypeDict::MysqlTypeDate => 10,
+        ColumnTypeDict::MysqlTypeTime => 11,
+        ColumnTypeDict::MysqlTypeDatetime => 12,
+        ColumnTypeDict::MysqlTypeYear => 13,
+        ColumnTypeDict::MysqlTypeNewdate => 14,
+        ColumnTypeDict::MysqlTypeVarchar => 15,
+        ColumnTypeDict::MysqlTypeTimestamp2 => 17,
+        ColumnTypeDict::MysqlTypeDatetime2 => 18,
+        ColumnTypeDict::MysqlTypeTime2 => 19,
+        ColumnTypeDict::MysqlTypeJson => 245,
+        ColumnTypeDict::MysqlTypeNewdecimal => 246,
+        ColumnTypeDict::MysqlTypeEnum => 247,
+        ColumnTypeDict::MysqlTypeSet => 248,
+        ColumnTypeDict::MysqlTypeTinyBlob => 249,
+        ColumnTypeDict::MysqlTypeMediumBlob => 250,
+        ColumnTypeDict::MysqlTypeLongBlob => 251,
+        ColumnTypeDict::MysqlTypeBlob => 252,
+        ColumnTypeDict::MysqlTypeVarString => 253,
+        ColumnTypeDict::MysqlTypeString => 254,
+        ColumnTypeDict::MysqlTypeGeometry => 255,
+        ColumnTypeDict::UnknowType(t) => *t,
+        _ => return None
+    })
+}
+
+//table_map中记录的字段类型及metadata, char、enum、set的类型记录为string, 实际类型保存在metadata中
+fn column_type_meta(col: &ColumnInfo) -> io::Result<(u8, Vec<u8>)> {
+    let code = type_code(&col.column_type)
+        .ok_or_else(|| invalid_input(format!("不支持编码的字段类型: {:?}", col.column_type)))?;
+    let meta = |idx: usize| col.column_meta.get(idx).copied()
+        .ok_or_else(|| invalid_input(format!("{:?}缺少metadata", col.column_type)));
+    Ok(match col.column_type {
+        ColumnTypeDict::MysqlTypeVarchar |
+        ColumnTypeDict::MysqlTypeVarString => {
+            //解析时只保留了长度占用的字节数
+            let max_length: u16 = if meta(0)? > 1 { 65535 } else { 255 };
+            (code, readvalue::write_u16(max_length))
+        }
+        ColumnTypeDict::MysqlTypeNewdecimal => (code, vec![meta(0)? as u8, meta(1)? as u8]),
+        ColumnTypeDict::MysqlTypeEnum |
+        ColumnTypeDict::MysqlTypeSet => {
+            if meta(0)? > 255 {
+                return Err(invalid_input(format!("不支持编码长度大于255的{:?}", col.column_type)));
+            }
+            (254, vec![code, meta(0)? as u8])
+        }
+        ColumnTypeDict::MysqlTypeString => {
+            //长度大于255时高2位取反后存放在第一个字节, 见readevent::string_meta
+            let length = meta(0)?;
+            if length > 1023 {
+                return Err(invalid_input(format!("不支持编码长度大于1023的{:?}", col.column_type)));
+            }
+            (254, vec![code ^ ((length & 0x300) >> 4) as u8, length as u8])
+        }
+        ColumnTypeDict::MysqlTypeTinyBlob |
+        ColumnTypeDict::MysqlTypeMediumBlob |
+        ColumnTypeDict::MysqlTypeLongBlob |
+        ColumnTypeDict::MysqlTypeBlob |
+        ColumnTypeDict::MysqlTypeJson |
+        ColumnTypeDict::MysqlTypeGeometry |
+        ColumnTypeDict::MysqlTypeTimestamp2 |
+        ColumnTypeDict::MysqlTypeDatetime2 |
+        ColumnTypeDict::MysqlTypeTime2 |
+        ColumnTypeDict::MysqlTypeFloat |
+        ColumnTypeDict::MysqlTypeDouble => (code, vec![meta(0)? as u8]),
+        ColumnTypeDict::UnknowType(_) => (code, col.column_meta.iter().map(|t| *t as u8).collect()),
+        _ => (code, vec![])
+    })
+}
+
+/// table_id为6bytes, 字段数最多250
+pub fn table_map_body(table_id: u64, map: &TableMap) -> io::Result<Vec<u8>> {
+    let cols = map.column_info.len();
+    if cols > 250 {
+        return Err(invalid_input(format!("字段数({})超过250", cols)));
+    }
+    let mut types = vec![];
+    let mut meta = vec![];
+    for (idx, col) in map.column_info.iter().enumerate() {
+        let (code, mut m) = column_type_meta(col)?;
+        //有可选metadata时使用其中记录的varchar最大字节数
+        let length = map.metadata.as_ref().and_then(|t| t.columns.get(idx)).map(|c| c.length).unwrap_or(0);
+        if matches!(col.column_type, ColumnTypeDict::MysqlTypeVarchar | ColumnTypeDict::MysqlTypeVarString) && length > 0 {
+            m = readvalue::write_u16(length as u16);
+        }
+        types.push(code);
+        meta.extend(m);
+    }
+    let mut body = readvalue::write_u64(table_id)[..6].to_vec();
+    body.extend(readvalue::write_u16(1));
+    body.push(map.database_name.len() as u8);
+    body.extend_from_slice(map.database_name.as_bytes());
+    body.push(0);
+    body.push(map.table_name.len() as u8);
+    body.extend_from_slice(map.table_name.as_bytes());
+    body.push(0);
+    body.extend(lenenc(cols));
+    body.extend(types);
+    body.extend(lenenc(meta.len()));
+    body.extend(meta);
+    match &map.metadata {
+        Some(metadata) => body.extend(metadata.write(&map.column_info)),
+        None => body.extend(bitmap(cols, |_| true)),
+    }
+    Ok(body)
+}
+
+/// 行事件(v2)的类型码
+pub fn rows_type_code(event_type: &BinlogEvent) -> io::Result<u8> {
+    match event_type {
+        BinlogEvent::WriteEvent => Ok(30),
+        BinlogEvent::UpdateEvent => Ok(31),
+        BinlogEvent::DeleteEvent => Ok(32),
+        _ => Err(invalid_input(format!("{:?}不是行事件", event_type)))
+    }
+}
+
+/// rows与RowValue::rows()相同, update事件中前后镜像依次排列, None与Some(Null)都编码为NULL
+pub fn rows_body(event_type: &BinlogEvent, table_id: u64, map: &TableMap, rows: &[Vec<Option<MySQLValue>>]) -> io::Result<Vec<u8>> {
+    let is_update = rows_type_code(event_type)? == 31;
+    if rows.is_empty() || (is_update && !rows.len().is_multiple_of(2)) {
+        return Err(invalid_input(format!("行事件的行数({})错误", rows.len())));
+    }
+    let cols = map.column_info.len();
+    let mut body = readvalue::write_u64(table_id)[..6].to_vec();
+    body.extend(readvalue::write_u16(1));          //STMT_END_F
+    body.extend(readvalue::write_u16(2));          //extra_data_length, 包含自身
+    body.extend(lenenc(cols));
+    body.extend(bitmap(cols, |_| true));
+    if is_update {
+        body.extend(bitmap(cols, |_| true));
+    }
+    for row in rows {
+        if row.len() != cols {
+            return Err(invalid_input(format!("行的字段数({})与table_map的字段数({})不一致", row.len(), cols)));
+        }
+        let is_null = |idx: usize| matches!(row[idx], None | Some(MySQLValue::Null));
+        body.extend(bitmap(cols, is_null));
+        for (idx, col) in map.column_info.iter().enumerate() {
+            if let Some(v) = &row[idx] {
+                if !is_null(idx) {
+                    body.extend(value(v, col)?);
+                }
+            }
+        }
+    }
+    Ok(body)
+}
+
+//小数部分按fsp占用(fsp+1)/2 bytes, big endian
+fn fsp_bytes(subsecond: u32, fsp: usize) -> Vec<u8> {
+    let len = fsp.div_ceil(2).min(3);
+    subsecond.to_be_bytes()[4 - len..].to_vec()
+}
+
+fn length_prefixed(bytes: &[u8], length_bytes: usize) -> io::Result<Vec<u8>> {
+    if length_bytes == 0 || length_bytes > 4 || (length_bytes < 4 && bytes.len() >= 1 << (length_bytes * 8)) {
+        return Err(invalid_input(format!("长度({})超过{}bytes能够表示的范围", bytes.len(), length_bytes)));
+    }
+    let mut v = readvalue::write_u32(bytes.len() as u32)[..length_bytes].to_vec();
+    v.extend_from_slice(bytes);
+    Ok(v)
+}
+
+/// 单个字段值的编码, 与RowValue解析时的格式一致
+pub fn value(value: &MySQLValue, col: &ColumnInfo) -> io::Result<Vec<u8>> {
+    let mismatch = || invalid_input(format!("{:?}与字段类型{:?}不匹配", value, col.column_type));
+    let meta = |idx: usize| col.column_meta.get(idx).copied()
+        .ok_or_else(|| invalid_input(format!("{:?}缺少metadata", col.column_type)));
+    Ok(match (&col.column_type, value) {
+        (ColumnTypeDict::MysqlTypeTiny, MySQLValue::SignedInteger(v)) => vec![*v as u8],
+        (ColumnTypeDict::MysqlTypeShort, MySQLValue::SignedInteger(v)) => readvalue::write_u16(*v as u16),
+        (ColumnTypeDict::MysqlTypeInt24, MySQLValue::SignedInteger(v)) => readvalue::write_u24(*v as u32 & 0xff_ffff),
+        (ColumnTypeDict::MysqlTypeLong, MySQLValue::SignedInteger(v)) => readvalue::write_u32(*v as u32),
+        (ColumnTypeDict::MysqlTypeLonglong, MySQLValue::SignedInteger(v)) => readvalue::write_u64(*v as u64),
+        (ColumnTypeDict::MysqlTypeFloat, MySQLValue::Float(v)) |
+        (ColumnTypeDict::MysqlTypeDouble, MySQLValue::Float(v)) if meta(0)? == 4 => v.to_le_bytes().to_vec(),
+        (ColumnTypeDict::MysqlTypeFloat, MySQLValue::Double(v)) |
+        (ColumnTypeDict::MysqlTypeDouble, MySQLValue::Double(v)) if meta(0)? == 8 => v.to_le_bytes().to_vec(),
+        (ColumnTypeDict::MysqlTypeNewdecimal, MySQLValue::Decimal(v)) => decimal(v, meta(0)?, meta(1)?)?,
+        (ColumnTypeDict::MysqlTypeTimestamp2, MySQLValue::Timestamp { unix_time, subsecond }) => {
+            let mut v = unix_time.to_be_bytes().to_vec();
+            v.extend(fsp_bytes(*subsecond, meta(0)?));
+            v
+        }
+        (ColumnTypeDict::MysqlTypeDatetime2, MySQLValue::DateTime { year, month, day, hour, minute, second, subsecond }) => {
+            let year_month = *year as u64 * 13 + *month as u64;
+            let packed = (1u64 << 39) | (year_month << 22) | ((*day as u64) << 17)
+                | ((*hour as u64) << 12) | ((*minute as u64) << 6) | *second as u64;
+            let mut v = packed.to_be_bytes()[3..].to_vec();
+            v.extend(fsp_bytes(*subsecond, meta(0)?));
+            v
+        }
+        (ColumnTypeDict::MysqlTypeYear, MySQLValue::Year(y)) => vec![y.checked_sub(1900).ok_or_else(mismatch)? as u8],
+        (ColumnTypeDict::MysqlTypeDate, MySQLValue::Date { year, month, day }) |
+        (ColumnTypeDict::MysqlTypeNewdate, MySQLValue::Date { year, month, day }) => {
+            readvalue::write_u24((year << 9) | (month << 5) | day)
+        }
+        (ColumnTypeDict::MysqlTypeTimestamp, MySQLValue::Timestamp { unix_time, subsecond: 0 }) => unix_time.to_le_bytes().to_vec(),
+        (ColumnTypeDict::MysqlTypeDatetime, MySQLValue::DateTime { year, month, day, hour, minute, second, subsecond: 0 }) => {
+            let date = *year as u64 * 10000 + *month as u64 * 100 + *day as u64;
+            readvalue::write_u64(date * 1000000 + *hour as u64 * 10000 + *minute as u64 * 100 + *second as u64)
+        }
+        (ColumnTypeDict::MysqlTypeTime, MySQLValue::Time { negative, hours, minutes, seconds, subseconds: 0 }) => {
+            let value = (hours * 10000 + minutes * 100 + seconds) as i32;
+            readvalue::write_u24((if *negative { -value } else { value }) as u32 & 0xffffff)
+        }
+        (ColumnTypeDict::MysqlTypeTime2, MySQLValue::Time { negative, hours, minutes, seconds, subseconds }) => {
+            //与解析相反, 负数的小数部分不为0时整数部分减1、小数部分存储为补数
+            let fsp = meta(0)?;
+            let frac_max = 1u32 << (fsp.div_ceil(2).min(3) * 8);
+            if *subseconds >= frac_max {
+                return Err(mismatch());
+            }
+            let value = ((hours << 12) | (minutes << 6) | seconds) as i32;
+            let (intpart, frac_part) = match (*negative, *subseconds) {
+                (false, frac_part) => (value, frac_part),
+                (true, 0) => (-value, 0),
+                (true, frac_part) => (-value - 1, frac_max - frac_part)
+            };
+            let mut v = ((intpart + 0x800000) as u32).to_be_bytes()[1..].to_vec();
+            v.extend(fsp_bytes(frac_part, fsp));
+            v
+        }
+        (ColumnTypeDict::MysqlTypeVarString, MySQLValue::Blob(b)) |
+        (ColumnTypeDict::MysqlTypeVarchar, MySQLValue::Blob(b)) |
+        (ColumnTypeDict::MysqlTypeBlob, MySQLValue::Blob(b)) |
+        (ColumnTypeDict::MysqlTypeTinyBlob, MySQLValue::Blob(b)) |
+        (ColumnTypeDict::MysqlTypeMediumBlob, MySQLValue::Blob(b)) |
+        (ColumnTypeDict::MysqlTypeLongBlob, MySQLValue::Blob(b)) |
+        (ColumnTypeDict::MysqlTypeGeometry, MySQLValue::Blob(b)) => length_prefixed(b, meta(0)?)?,
+        (ColumnTypeDict::MysqlTypeVarString, MySQLValue::String(s)) |
+        (ColumnTypeDict::MysqlTypeVarchar, MySQLValue::String(s)) |
+        (ColumnTypeDict::MysqlTypeBlob, MySQLValue::String(s)) |
+        (ColumnTypeDict::MysqlTypeTinyBlob, MySQLValue::String(s)) |
+        (ColumnTypeDict::MysqlTypeMediumBlob, MySQLValue::String(s)) |
+        (ColumnTypeDict::MysqlTypeLongBlob, MySQLValue::String(s)) => length_prefixed(s.as_bytes(), meta(0)?)?,
+        (ColumnTypeDict::MysqlTypeString, MySQLValue::Blob(b)) => length_prefixed(b, if meta(0)? > 255 { 2 } else { 1 })?,
+        (ColumnTypeDict::MysqlTypeString, MySQLValue::String(s)) => length_prefixed(s.as_bytes(), if meta(0)? > 255 { 2 } else { 1 })?,
+        (ColumnTypeDict::MysqlTypeEnum, MySQLValue::SignedInteger(v)) |
+        (ColumnTypeDict::MysqlTypeSet, MySQLValue::SignedInteger(v)) => {
+            match meta(0)? {
+                pack @ 1..=8 => v.to_le_bytes()[..pack].to_vec(),
+                _ => return Err(mismatch())
+            }
+        }
+        (ColumnTypeDict::MysqlTypeJson, MySQLValue::Json(v)) => length_prefixed(&json_value(v)?, meta(0)?)?,
+        (ColumnTypeDict::UnknowType(code), MySQLValue::Opaque{type_code, bytes}) if code == type_code => {
+            crate::replication::opaque::encode_value(*code, &col.column_meta, bytes)?
+        }
+        _ => return Err(mismatch())
+    })
+}
+
+/*
+decimal: 整数部分从小数点向左、小数部分从小数点向右每9位数字占用4bytes, 不足9位的部分按compressed_byte_map占用1-4bytes, 均为big endian
+    负数时所有bytes按位取反, 最后将第一个byte的最高位取反
+*/
+pub fn decimal(value: &bigdecimal::BigDecimal, precision: usize, scale: usize) -> io::Result<Vec<u8>> {
+    if precision == 0 || precision > 65 || scale > precision {
+        return Err(invalid_input(format!("无效的decimal metadata: ({}, {})", precision, scale)));
+    }
+    let meta = DecimalMeta::new(precision as u8, scale as u8)?;
+    let digits = value.with_scale(scale as i64).as_bigint_and_exponent().0.to_string();
+    let negative = digits.starts_with('-');
+    let mut digits = digits.trim_start_matches('-').to_string();
+    let integral_len = meta.compressed_integers + meta.uncompressed_integers * 9;
+    if digits.len() < scale + integral_len {
+        digits = format!("{}{}", "0".repeat(scale + integral_len - digits.len()), digits);
+    }
+    let (integral, fraction) = digits.split_at(digits.len() - scale);
+    if integral.len() > integral_len && integral[..integral.len() - integral_len].bytes().any(|b| b != b'0') {
+        return Err(invalid_input(format!("{}超过decimal({}, {})的范围", value, precision, scale)));
+    }
+    let integral = &integral[integral.len() - integral_len..];
+
+    let mut bytes = vec![];
+    let mut push = |part: &str, len: usize| {
+        let v: u32 = part.parse().unwrap_or(0);
+        bytes.extend_from_slice(&v.to_be_bytes()[4 - len..]);
+    };
+    if meta.compressed_integers > 0 {
+        push(&integral[..meta.compressed_integers], meta.compressed_byte_map[meta.compressed_integers]);
+    }
+    for idx in 0..meta.uncompressed_integers {
+        let start = meta.compressed_integers + idx * 9;
+        push(&integral[start..start + 9], 4);
+    }
+    for idx in 0..meta.uncompressed_decimals {
+        push(&fraction[idx * 9..idx * 9 + 9], 4);
+    }
+    if meta.compressed_decimals > 0 {
+        push(&fraction[meta.uncompressed_decimals * 9..], meta.compressed_byte_map[meta.compressed_decimals]);
+    }
+    if negative {
+        for b in bytes.iter_mut() {
+            *b = !*b;
+        }
+    }
+    bytes[0] ^= 0x80;
+    Ok(bytes)
+}
+
+/*
+json二进制格式: 1byte类型 + 值
+    object/array: 元素数、总长度(small为2bytes, large为4bytes), key entry(offset + 2bytes长度), value entry(1byte类型 + offset或内联的值), keys, values
+    offset相对于元素数所在的位置, literal、int16、uint16(large时还包括int32、uint32)直接内联在value entry中
+    object的key按长度、内容排序
+*/
+pub fn json_value(value: &JsonValue) -> io::Result<Vec<u8>> {
+    let (type_code, body) = json_body(value)?;
+    let mut v = vec![type_code];
+    v.extend(body);
+    Ok(v)
+}
+
+fn json_body(value: &JsonValue) -> io::Result<(u8, Vec<u8>)> {
+    Ok(match value {
+        JsonValue::Null => (0x4, vec![0]),
+        JsonValue::Bool(true) => (0x4, vec![1]),
+        JsonValue::Bool(false) => (0x4, vec![2]),
+        JsonValue::Number(n) => {
+            if let Some(v) = n.as_i64() {
+                if v >= i16::MIN as i64 && v <= i16::MAX as i64 {
+                    (0x5, (v as i16).to_le_bytes().to_vec())
+                }else if v >= i32::MIN as i64 && v <= i32::MAX as i64 {
+                    (0x7, (v as i32).to_le_bytes().to_vec())
+                }else {
+                    (0x9, v.to_le_bytes().to_vec())
+                }
+            }else if let Some(v) = n.as_u64() {
+                (0xA, v.to_le_bytes().to_vec())
+            }else {
+                (0xB, n.as_f64().unwrap_or(0.0).to_le_bytes().to_vec())
+            }
+        }
+        JsonValue::String(s) => {
+            let mut v = vec![];
+            let mut len = s.len();
+            loop {
+                let byte = (len & 0x7f) as u8;
+                len >>= 7;
+                if len == 0 {
+                    v.push(byte);
+                    break;
+                }
+                v.push(byte | 0x80);
+            }
+            v.extend_from_slice(s.as_bytes());
+            (0xC, v)
+        }
+        JsonValue::Array(items) => {
+            let entries: Vec<(Option<&str>, &JsonValue)> = items.iter().map(|v| (None, v)).collect();
+            match json_container(&entries, false)? {
+                Some(v) => (0x2, v),
+                None => (0x3, json_container(&entries, true)?.unwrap_or_default())
+            }
+        }
+        JsonValue::Object(map) => {
+            let mut entries: Vec<(Option<&str>, &JsonValue)> = map.iter().map(|(k, v)| (Some(k.as_str()), v)).collect();
+            entries.sort_by(|a, b| (a.0.map(str::len), a.0).cmp(&(b.0.map(str::len), b.0)));
+            match json_container(&entries, false)? {
+                Some(v) => (0x0, v),
+                None => (0x1, json_container(&entries, true)?.unwrap_or_default())
+            }
+        }
+    })
+}
+
+//small格式放不下时返回None
+fn json_container(entries: &[(Option<&str>, &JsonValue)], large: bool) -> io::Result<Option<Vec<u8>>> {
+    let size_bytes = if large { 4 } else { 2 };
+    let is_object = entries.first().map(|e| e.0.is_some()).unwrap_or(false);
+    let key_entry = if is_object { size_bytes + 2 } else { 0 };
+    let value_entry = 1 + size_bytes;
+    let header = 2 * size_bytes + entries.len() * (key_entry + value_entry);
+
+    let mut keys = vec![];
+    let mut key_entries = vec![];
+    let mut offset = header;
+    for (key, _) in entries {
+        if let Some(key) = key {
+            key_entries.push((offset, key.len()));
+            keys.extend_from_slice(key.as_bytes());
+            offset += key.len();
+        }
+    }
+    let mut values = vec![];
+    let mut value_entries = vec![];
+    for (_, v) in entries {
+        let (type_code, body) = json_body(v)?;
+        let inline = match type_code {
+            0x4 | 0x5 => true,
+            0x7 => large,
+            _ => false
+        };
+        if inline {
+            let mut inlined = body.clone();
+            inlined.resize(size_bytes, 0);
+            value_entries.push((type_code, inlined));
+        }else {
+            value_entries.push((type_code, (offset as u32).to_le_bytes()[..size_bytes].to_vec()));
+            offset += body.len();
+            values.extend(body);
+        }
+    }
+    if !large && (offset > u16::MAX as usize || entries.len() > u16::MAX as usize) {
+        return Ok(None);
+    }
+
+    let write = |v: usize| (v as u32).to_le_bytes()[..size_bytes].to_vec();
+    let mut buf = write(entries.len());
+    buf.extend(write(offset));
+    for (key_offset, key_len) in key_entries {
+        buf.extend(write(key_offset));
+        buf.extend(readvalue::write_u16(key_len as u16));
+    }
+    for (type_code, v) in value_entries {
+        buf.push(type_code);
+        buf.extend(v);
+    }
+    buf.extend(keys);
+    buf.extend(values);
+    Ok(Some(buf))
+}
+
+/// 在内存中生成完整的binlog文件
+///
+/// ```ignore
+/// let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+/// builder.gtid(&gtid).query(&begin).table_map(100, &map)?;
+/// builder.rows(&BinlogEvent::WriteEvent, 100, &map, &rows)?.xid(&XidEvent{ xid: 1 });
+/// std::fs::write("bin.000001", builder.into_bytes())?;
+/// ```
+pub struct BinlogBuilder{
+    version: ServerVersion,
+    checksum: bool,
+    server_id: u32,
+    timestamp: u32,
+    flags: u16,
+    data: Vec<u8>,
+}
+
+impl BinlogBuilder{
+    /// 默认开启checksum, server_id为1, 时间戳为0
+    pub fn new(version: ServerVersion) -> BinlogBuilder {
+        BinlogBuilder{
+            version,
+            checksum: true,
+            server_id: 1,
+            timestamp: 0,
+            flags: 0,
+            data: vec![],
+        }
+    }
+
+    /// 只能在追加事件之前设置
+    pub fn checksum(mut self, checksum: bool) -> BinlogBuilder {
+        self.checksum = checksum;
+        self
+    }
+
+    pub fn server_id(mut self, server_id: u32) -> BinlogBuilder {
+        self.server_id = server_id;
+        self
+    }
+
+    /// 之后追加的事件使用的时间戳
+    pub fn timestamp(&mut self, timestamp: u32) -> &mut BinlogBuilder {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// 之后追加的事件使用的flags, 如relay log中从库生成的事件带LOG_EVENT_RELAY_LOG_F(0x40)
+    pub fn flags(&mut self, flags: u16) -> &mut BinlogBuilder {
+        self.flags = flags;
+        self
+    }
+
+    //第一个事件之前写入magic及FORMAT_DESCRIPTION_EVENT
+    fn start(&mut self) {
+        if self.data.is_empty() {
+            self.data.extend([0xFE, 0x62, 0x69, 0x6E]);
+            self.data.extend(format_description(self.version.version_string(), self.version.post_header_lengths(),
+                                                self.timestamp, self.server_id, self.checksum));
+        }
+    }
+
+    /// 追加任意类型的事件, body为不含包头及checksum的内容
+    pub fn event(&mut self, type_code: u8, body: &[u8]) -> &mut BinlogBuilder {
+        self.start();
+        let mut event = event(self.timestamp, type_code, self.server_id, body, self.checksum);
+        event[17..19].copy_from_slice(&readvalue::write_u16(self.flags));
+        seal(&mut event, self.data.len() as u64, self.checksum);
+        self.data.extend(event);
+        self
+    }
+
+    pub fn previous_gtids(&mut self, gtids: &GtidSet) -> &mut BinlogBuilder {
+        self.event(35, &previous_gtids_body(gtids))
+    }
+
+    pub fn gtid(&mut self, event: &GtidEvent) -> &mut BinlogBuilder {
+        let body = gtid_body(event, self.version, self.timestamp);
+        self.event(33, &body)
+    }
+
+    pub fn query(&mut self, event: &QueryEvent) -> &mut BinlogBuilder {
+        self.event(2, &query_body(event))
+    }
+
+    pub fn rows_query(&mut self, event: &RowsQueryEvent) -> &mut BinlogBuilder {
+        self.event(29, &rows_query_body(event))
+    }
+
+    pub fn table_map(&mut self, table_id: u64, map: &TableMap) -> io::Result<&mut BinlogBuilder> {
+        let body = table_map_body(table_id, map)?;
+        Ok(self.event(19, &body))
+    }
+
+    pub fn rows(&mut self, event_type: &BinlogEvent, table_id: u64, map: &TableMap, rows: &[Vec<Option<MySQLValue>>]) -> io::Result<&mut BinlogBuilder> {
+        let body = rows_body(event_type, table_id, map, rows)?;
+        Ok(self.event(rows_type_code(event_type)?, &body))
+    }
+
+    pub fn xid(&mut self, event: &XidEvent) -> &mut BinlogBuilder {
+        self.event(16, &xid_body(event))
+    }
+
+    pub fn rotate(&mut self, event: &RotateLog) -> &mut BinlogBuilder {
+        self.event(4, &rotate_body(event))
+    }
+
+    pub fn stop(&mut self) -> &mut BinlogBuilder {
+        self.event(3, &[])
+    }
+
+    /// 下一个事件的起始位置
+    pub fn position(&mut self) -> u64 {
+        self.start();
+        self.data.len() as u64
+    }
+
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        self.start();
+        self.data
+    }
+}
@@ -9,6 +9,8 @@ use crate::replication::rollback::RollBackTrac;
 use crate::replication::readevent::{EventHeader, GtidEvent, QueryEvent, TableMap};
 use crate::replication::readevent;
 use std::collections::HashMap;
+use crate::stdout::OutState;
+use crate::exitcode;
 
 pub trait UpdateState {
     fn start(&mut self) {}
@@ -132,6 +134,29 @@ impl GrepPosition {
     }
 }
 
+//greptbl中的库名、表名可以带引号(`订单库`), 统一转换为与binlog中相同的形式再比较
+pub fn parse_greptbl(greptbl: &str) -> serde_json::Value {
+    let tbl_info: serde_json::Value = serde_json::from_str(greptbl).unwrap_or_else(|err|{
+        println!("greptbl格式错误: {:?}", err);
+        std::process::exit(exitcode::USAGE);
+    });
+    match tbl_info {
+        serde_json::Value::Object(dbs) => {
+            serde_json::Value::Object(dbs.into_iter().map(|(db, tbls)| {
+                let tbls = match tbls {
+                    serde_json::Value::Array(t) => serde_json::Value::Array(t.into_iter().map(|tbl| match tbl {
+                        serde_json::Value::String(name) => serde_json::Value::String(crate::ident::normalize(&name)),
+                        other => other
+                    }).collect()),
+                    other => other
+                };
+                (crate::ident::normalize(&db), tbls)
+            }).collect())
+        }
+        other => other
+    }
+}
+
 pub struct GrepTbl {
     pub tbl_info: serde_json::Value,
     pub state: bool,
@@ -151,11 +176,11 @@ impl GrepTbl {
     fn new(conf: &Config) -> GrepTbl{
         let mut tbl_info = serde_json::from_str("[]").unwrap_or_else(|err|{
             println!("{:?}",err);
-            std::process::exit(1);
+            std::process::exit(exitcode::USAGE);
         });
         let mut state = false;
         if conf.greptbl.len() > 0 {
-            tbl_info = serde_json::from_str(&conf.greptbl).unwrap();
+            tbl_info = parse_greptbl(&conf.greptbl);
             state = true;
         }
 
@@ -195,6 +220,10 @@ impl GrepInfo {
 
     fn grep_date_time(&self, rollback_trac: &mut RollBackTrac, event_header: &EventHeader) -> (bool, bool) {
         let grep_datetime_info = &self.grep_date_time;
+        if event_header.is_artificial() {
+            //人为生成的事件timestamp为0, 不参与时间过滤
+            return (true, false);
+        }
         if grep_datetime_info.state {
             if grep_datetime_info.start_datetime > event_header.timestamp as usize {
                 rollback_trac.delete_cur_event();
@@ -209,13 +238,13 @@ impl GrepInfo {
         return (true, false);
     }
 
-    pub fn grep_pos_time(&self,rollback_trac: &mut RollBackTrac, event_header: &EventHeader) -> (bool,bool) {
+    pub fn grep_pos_time(&self,rollback_trac: &mut RollBackTrac, event_header: &EventHeader, end_position: u64) -> (bool,bool) {
         //对postion和datetime进行过滤， 返回元组结果，第一个表示是继续下面的还是continue循环，第二个如果为true表示break
+        //end_position为该事件结束时在文件中的偏移量, relay log中包头的next_position记录的是master binlog的位置, 不能用于过滤
         let grep_position_info = &self.grep_position;
-        let grep_datetime_info = &self.grep_date_time;
         if grep_position_info.state{
             if grep_position_info.stop_position > 0 {
-                if grep_position_info.stop_position < event_header.next_position as usize{
+                if grep_position_info.stop_position < end_position as usize{
                     rollback_trac.is_write();
                     return (false, true);
                 }else {
@@ -329,14 +358,15 @@ impl GrepInfo {
                           rollback_trac: &mut RollBackTrac,
                           conf: &Config,
                           table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,
-                          db_tbl: &String) -> bool {
+                          db_tbl: &String,
+                          out_state: &mut OutState) -> bool {
         if self.grep_tbl.state{
             let tbls = &self.grep_tbl.tbl_info[v.database_name.clone()];
             if tbls == &serde_json::Value::String("all".parse().unwrap()){
                 self.grep_tbl.start();
-                if !conf.rollback{
-                    crate::stdout::format_out(&self.grep_thread_id.gtid_traction, conf, table_cols_info, db_tbl, v);
-                    crate::stdout::format_out(&self.grep_tbl.query_traction, conf, table_cols_info, db_tbl, v);
+                if !rollback_trac.collect(){
+                    crate::stdout::format_out(&self.grep_thread_id.gtid_traction, conf, table_cols_info, db_tbl, v, out_state);
+                    crate::stdout::format_out(&self.grep_tbl.query_traction, conf, table_cols_info, db_tbl, v, out_state);
                 }
 
             }else {
@@ -345,9 +375,9 @@ impl GrepInfo {
                         if tbls[i] != serde_json::Value::Null {
                             if tbls[i] == serde_json::Value::String(v.table_name.clone()) {
                                 self.grep_tbl.start();
-                                if !conf.rollback{
-                                    crate::stdout::format_out(&self.grep_thread_id.gtid_traction, conf, table_cols_info, db_tbl, v);
-                                    crate::stdout::format_out(&self.grep_tbl.query_traction, conf, table_cols_info, db_tbl, v);
+                                if !rollback_trac.collect(){
+                                    crate::stdout::format_out(&self.grep_thread_id.gtid_traction, conf, table_cols_info, db_tbl, v, out_state);
+                                    crate::stdout::format_out(&self.grep_tbl.query_traction, conf, table_cols_info, db_tbl, v, out_state);
                                 }
 
                                 break 'inner;
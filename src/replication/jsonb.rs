@@ -5,20 +5,25 @@
 
 use crate::meta::JsonType;
 use crate::readvalue;
-use std::io::Read;
+use std::io::{self, Read};
 use byteorder::{ReadBytesExt, LittleEndian};
-use std::process;
 use serde_json::Value as JsonValue;
 use serde_json::map::Map as JsonMap;
 use failure::_core::iter::FromIterator;
 
-pub fn read_binary_json<R: Read>(buf: &mut R, var_length: &usize) -> JsonValue {
-    let t = buf.read_u8().unwrap() as usize;
-    read_binary_json_type(buf, var_length, &t)
+//mysql中json的最大嵌套深度为100, 超过时认为数据已损坏, 避免递归过深导致栈溢出
+const MAX_JSON_DEPTH: usize = 100;
+
+pub fn read_binary_json<R: Read>(buf: &mut R, var_length: &usize) -> io::Result<JsonValue> {
+    let t = buf.read_u8()? as usize;
+    read_binary_json_type(buf, var_length, &t, 0)
 }
 
-fn read_binary_json_type<R: Read>(buf: &mut R, var_length: &usize, m: &usize) -> JsonValue {
-    let var_length = var_length.clone() - 1;
+fn read_binary_json_type<R: Read>(buf: &mut R, var_length: &usize, m: &usize, depth: usize) -> io::Result<JsonValue> {
+    if depth > MAX_JSON_DEPTH {
+        return Err(readvalue::invalid_data(format!("json嵌套深度超过{}", MAX_JSON_DEPTH)));
+    }
+    let var_length = var_length.saturating_sub(1);
     let json_type_code = JsonType::from_type_code(m);
     let mut large = false;
     match json_type_code {
@@ -34,105 +39,105 @@ fn read_binary_json_type<R: Read>(buf: &mut R, var_length: &usize, m: &usize) ->
     match json_type_code {
         JsonType::JsonbTypeLargeObject |
         JsonType::JsonbTypeSmallObject => {
-            JsonValue::from(read_binary_json_object(buf, &var_length, &large))
+            read_binary_json_object(buf, &var_length, &large, depth)
         }
         JsonType::JsonbTypeLargeArray |
         JsonType::JsonbTypeSmallArray => {
-            JsonValue::from(read_binary_json_array(buf, &var_length, &large))
+            read_binary_json_array(buf, &var_length, &large, depth)
         }
         JsonType::JsonbTypeString => {
             let mut byte = 0x80 as usize;
             let mut length = 0 as usize;
             let mut bits_read = 0 as usize;
             while byte & 0x80 != 0{
-                byte = buf.read_u8().unwrap() as usize;
+                if bits_read > 28 {
+                    return Err(readvalue::invalid_data(String::from("json字符串长度格式错误")));
+                }
+                byte = buf.read_u8()? as usize;
                 length = length | ((byte & 0x7f) << bits_read);
                 bits_read = bits_read + 7;
             }
-            JsonValue::from(readvalue::read_string_value_from_len(buf, length))
+            Ok(JsonValue::from(readvalue::read_string_value_from_len(buf, length)?))
         }
         JsonType::JsonbTypeLiteral => {
-            let value = buf.read_u8().unwrap() as usize;
-            let type_code = JsonType::from_type_code(&value);
-            match type_code {
-                JsonType::JsonbLiteralNull => JsonValue::Null,
-                JsonType::JsonbLiteralTrue => JsonValue::from(true),
-                JsonType::JsonbLiteralFalse => JsonValue::from(false),
-                _ => {JsonValue::Null}
-            }
+            //literal: 0x0为null, 0x1为true, 0x2为false
+            Ok(match buf.read_u8()? {
+                0x1 => JsonValue::from(true),
+                0x2 => JsonValue::from(false),
+                _ => JsonValue::Null
+            })
         }
         JsonType::JsonbTypeInt16 => {
-            JsonValue::from(buf.read_i16::<LittleEndian>().unwrap())
+            Ok(JsonValue::from(buf.read_i16::<LittleEndian>()?))
         }
         JsonType::JsonbTypeUint16 => {
-            JsonValue::from(buf.read_u16::<LittleEndian>().unwrap())
+            Ok(JsonValue::from(buf.read_u16::<LittleEndian>()?))
         }
         JsonType::JsonbTypeDouble => {
-            JsonValue::from(buf.read_f64::<LittleEndian>().unwrap())
+            Ok(JsonValue::from(buf.read_f64::<LittleEndian>()?))
         }
         JsonType::JsonbTypeInt32 => {
-            JsonValue::from(buf.read_i32::<LittleEndian>().unwrap())
+            Ok(JsonValue::from(buf.read_i32::<LittleEndian>()?))
         }
         JsonType::JsonbTypeUint32 => {
-            JsonValue::from(buf.read_u32::<LittleEndian>().unwrap())
+            Ok(JsonValue::from(buf.read_u32::<LittleEndian>()?))
         }
         JsonType::JsonbTypeInt64 =>{
-            JsonValue::from(buf.read_i64::<LittleEndian>().unwrap())
+            Ok(JsonValue::from(buf.read_i64::<LittleEndian>()?))
         }
         JsonType::JsonbTypeUint64 => {
-            JsonValue::from(buf.read_u64::<LittleEndian>().unwrap())
+            Ok(JsonValue::from(buf.read_u64::<LittleEndian>()?))
         }
         _ => {
-            println!("无效的json格式:{:?}",json_type_code);
-            process::exit(1)
+            Err(readvalue::invalid_data(format!("无效的json格式:{:?}",json_type_code)))
         }
     }
 }
 
-fn read_binary_json_object<R: Read>(buf: &mut R, var_length: &usize, large: &bool) -> JsonValue {
+fn read_binary_json_object<R: Read>(buf: &mut R, var_length: &usize, large: &bool, depth: usize) -> io::Result<JsonValue> {
     let elements: usize;
     let size: usize;
 
     if *large {
-        elements = buf.read_u32::<LittleEndian>().unwrap() as usize;
-        size = buf.read_u32::<LittleEndian>().unwrap() as usize;
+        elements = buf.read_u32::<LittleEndian>()? as usize;
+        size = buf.read_u32::<LittleEndian>()? as usize;
     } else {
-        elements = buf.read_u16::<LittleEndian>().unwrap() as usize;
-        size = buf.read_u16::<LittleEndian>().unwrap() as usize;
+        elements = buf.read_u16::<LittleEndian>()? as usize;
+        size = buf.read_u16::<LittleEndian>()? as usize;
     }
 
-    if size > *var_length {
-        println!("json长度大于包长度, 现在退出程序！！！");
-        process::exit(1);
+    //每个元素至少占用1byte, 元素个数不可能超过数据长度
+    if size > *var_length || elements > size {
+        return Err(readvalue::invalid_data(format!("json长度({})或元素个数({})大于包长度({})", size, elements, var_length)));
     }
 
     let mut key_offset_lengths = vec![];
     if *large{
         for _i in 0..elements{
             let mut tmp_dict: Vec<usize> = vec![];
-            tmp_dict.push(buf.read_u32::<LittleEndian>().unwrap() as usize);
-            tmp_dict.push(buf.read_u16::<LittleEndian>().unwrap() as usize);
+            tmp_dict.push(buf.read_u32::<LittleEndian>()? as usize);
+            tmp_dict.push(buf.read_u16::<LittleEndian>()? as usize);
             key_offset_lengths.push(tmp_dict);
 
         }
     } else {
         for _i in 0..elements{
             let mut tmp_dict: Vec<usize> = vec![];
-            tmp_dict.push(buf.read_u16::<LittleEndian>().unwrap() as usize);
-            tmp_dict.push(buf.read_u16::<LittleEndian>().unwrap() as usize);
+            tmp_dict.push(buf.read_u16::<LittleEndian>()? as usize);
+            tmp_dict.push(buf.read_u16::<LittleEndian>()? as usize);
             key_offset_lengths.push(tmp_dict);
         }
     }
 
     let mut value_type_inlined_lengths: Vec<ValuesTypeInline> = vec![];
     for _i in 0..elements {
-        let tmp = ValuesTypeInline::new(buf, large);
+        let tmp = ValuesTypeInline::new(buf, large)?;
         value_type_inlined_lengths.push(tmp);
     }
 
     let mut keys: Vec<String> = vec![];
     for v in key_offset_lengths.iter(){
-        keys.push(readvalue::read_string_value_from_len(buf, v[1]));
+        keys.push(readvalue::read_string_value_from_len(buf, v[1])?);
     }
 
     let mut values = Vec::with_capacity(elements);
@@ -158,71 +163,71 @@ fn read_binary_json_object<R: Read>(buf: &mut R, var_length: &usize, large: &boo
             }
             _ => {
                 let t = value_inlined.a;
-                let data = read_binary_json_type(buf,var_length,&t);
+                let data = read_binary_json_type(buf,var_length,&t, depth + 1)?;
                 values.push(JsonValue::from(data));
             }
         }
     }
 
-    if keys.len() > 0 {
-        let map = JsonMap::from_iter(keys.into_iter().zip(values.into_iter()));
-        JsonValue::Object(map)
-    }else {
-        JsonValue::Array(values)
-    }
+    let map = JsonMap::from_iter(keys.into_iter().zip(values.into_iter()));
+    Ok(JsonValue::Object(map))
 
 
 
 }
 #[derive(Debug)]
 struct LiteralInline {
-    a: Option<usize>, // default： None
+    a: Option<i64>, // default： None
     b: i8, // 0: false, 1: true, -1: default
 }
 impl LiteralInline{
-    fn new<R: Read>(buf: &mut R, type_code: &JsonType) -> LiteralInline{
+    //large格式中内联的值占用4bytes, 2bytes的值之后需要跳过2bytes
+    fn new<R: Read>(buf: &mut R, type_code: &JsonType, large: &bool) -> io::Result<LiteralInline>{
         let mut a = None;
         let mut b = -1;
         match type_code {
             JsonType::JsonbTypeLiteral => {
-                let value = buf.read_u16::<LittleEndian>().unwrap() as usize;
-                let code = JsonType::from_type_code(&value);
-                match code {
-                    JsonType::JsonbLiteralNull => {
-                        a = None;
-                    },
-                    JsonType::JsonbLiteralTrue => {
+                match buf.read_u16::<LittleEndian>()? {
+                    0x1 => {
                         b = 1;
                     },
-                    JsonType::JsonbLiteralFalse => {
+                    0x2 => {
                         b = 0;
                     },
                     _ => {}
                 }
             }
             JsonType::JsonbTypeInt16 => {
-                let tmp = buf.read_i16::<LittleEndian>().unwrap() as usize;
+                let tmp = buf.read_i16::<LittleEndian>()? as i64;
                 a = Some(tmp);
             },
             JsonType::JsonbTypeUint16 => {
-                let tmp = buf.read_u16::<LittleEndian>().unwrap() as usize;
+                let tmp = buf.read_u16::<LittleEndian>()? as i64;
                 a = Some(tmp);
             },
             JsonType::JsonbTypeInt32 => {
-                let tmp = buf.read_i32::<LittleEndian>().unwrap() as usize;
+                let tmp = buf.read_i32::<LittleEndian>()? as i64;
                 a = Some(tmp);
             },
             JsonType::JsonbTypeUint32 => {
-                let tmp = buf.read_u32::<LittleEndian>().unwrap() as usize;
+                let tmp = buf.read_u32::<LittleEndian>()? as i64;
                 a = Some(tmp);
             }
             _ => {}
         }
+        match type_code {
+            JsonType::JsonbTypeLiteral |
+            JsonType::JsonbTypeInt16 |
+            JsonType::JsonbTypeUint16 if *large => {
+                buf.read_u16::<LittleEndian>()?;
+            }
+            _ => {}
+        }
 
-        LiteralInline{
+        Ok(LiteralInline{
             a,
             b,
-        }
+        })
     }
 
     fn default() -> LiteralInline {
@@ -240,8 +245,8 @@ struct ValuesTypeInline {
     c: LiteralInline,
 }
 impl ValuesTypeInline{
-    fn new<R: Read>(buf: &mut R, large: &bool) -> ValuesTypeInline {
-        let a = buf.read_u8().unwrap() as usize;
+    fn new<R: Read>(buf: &mut R, large: &bool) -> io::Result<ValuesTypeInline> {
+        let a = buf.read_u8()? as usize;
         let mut b = None;
         let mut c = LiteralInline::default();
         let json_type_code = JsonType::from_type_code(&a);
@@ -249,50 +254,49 @@ impl ValuesTypeInline{
             JsonType::JsonbTypeLiteral |
             JsonType::JsonbTypeInt16 |
             JsonType::JsonbTypeUint16 => {
-                c = LiteralInline::new(buf,&json_type_code);
-                return ValuesTypeInline{a, b, c};
+                c = LiteralInline::new(buf,&json_type_code, large)?;
+                return Ok(ValuesTypeInline{a, b, c});
             }
             JsonType::JsonbTypeInt32 |
             JsonType::JsonbTypeUint32 => {
                 if *large{
-                    c = LiteralInline::new(buf,&json_type_code);
-                    return ValuesTypeInline{a, b, c};
+                    c = LiteralInline::new(buf,&json_type_code, large)?;
+                    return Ok(ValuesTypeInline{a, b, c});
                 }
             }
             _ => {}
         }
         if *large{
-            b = Some(buf.read_u32::<LittleEndian>().unwrap() as usize);
-            ValuesTypeInline{a, b, c}
+            b = Some(buf.read_u32::<LittleEndian>()? as usize);
+            Ok(ValuesTypeInline{a, b, c})
         }
         else {
-            b = Some(buf.read_u16::<LittleEndian>().unwrap() as usize);
-            ValuesTypeInline{a, b, c}
+            b = Some(buf.read_u16::<LittleEndian>()? as usize);
+            Ok(ValuesTypeInline{a, b, c})
         }
 
     }
 
 }
 
-fn read_binary_json_array<R: Read>(buf: &mut R, var_length: &usize, large: &bool) -> JsonValue {
+fn read_binary_json_array<R: Read>(buf: &mut R, var_length: &usize, large: &bool, depth: usize) -> io::Result<JsonValue> {
     let elements: usize;
     let size: usize;
     if *large{
-        elements = buf.read_u32::<LittleEndian>().unwrap() as usize;
-        size = buf.read_u32::<LittleEndian>().unwrap() as usize;
+        elements = buf.read_u32::<LittleEndian>()? as usize;
+        size = buf.read_u32::<LittleEndian>()? as usize;
     }
     else {
-        elements = buf.read_u16::<LittleEndian>().unwrap() as usize;
-        size = buf.read_u16::<LittleEndian>().unwrap() as usize;
+        elements = buf.read_u16::<LittleEndian>()? as usize;
+        size = buf.read_u16::<LittleEndian>()? as usize;
     }
-    if size > *var_length{
-        println!("json长度大于包长度, 现在退出程序！！！");
-        process::exit(1);
+    if size > *var_length || elements > size {
+        return Err(readvalue::invalid_data(format!("json长度({})或元素个数({})大于包长度({})", size, elements, var_length)));
     }
 
     let mut value_type_inlined_lengths: Vec<ValuesTypeInline> = vec![];
     for _i in 0..elements {
-        let tmp = ValuesTypeInline::new(buf, large);
+        let tmp = ValuesTypeInline::new(buf, large)?;
         value_type_inlined_lengths.push(tmp);
     }
 
@@ -319,11 +323,11 @@ fn read_binary_json_array<R: Read>(buf: &mut R, var_length: &usize, large: &bool
             }
             _ => {
                 let t = value_inlined.a;
-                let data = read_binary_json_type(buf,var_length,&t);
+                let data = read_binary_json_type(buf,var_length,&t, depth + 1)?;
                 values.push(JsonValue::from(data));
             }
         }
     }
 
-    JsonValue::Array(values)
+    Ok(JsonValue::Array(values))
 }
@@ -0,0 +1,146 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//未知的字段类型(新版本mysql增加的类型, 如9.0的VECTOR, 类型码242)
+//  默认按blob的格式读取: table_map中metadata为1byte(值的长度占用的字节数), 行数据中为长度 + 内容
+//  解析为MySQLValue::Opaque{type_code, bytes}, 输出时与二进制类型相同, 不会因为新类型导致整个事件解析失败
+//  每次读取到时记录一条警告(见warning模块), 严格模式下返回错误
+//  可以通过register为类型码注册解码器, 自定义metadata的字节数、值的格式及解析结果, 例如将VECTOR解析为float数组:
+//
+//  struct Vector;
+//  impl OpaqueDecoder for Vector {
+//      fn decode(&self, _type_code: u8, bytes: Vec<u8>, _meta: &[usize]) -> io::Result<MySQLValue> {
+//          let values: Vec<f32> = bytes.chunks_exact(4).map(|t| f32::from_le_bytes([t[0], t[1], t[2], t[3]])).collect();
+//          Ok(MySQLValue::Json(serde_json::json!(values)))
+//      }
+//  }
+//  opaque::register(242, Vector)?;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex, RwLock};
+use crate::meta::ColumnTypeDict;
+use crate::readvalue;
+use crate::warning::WarningKind;
+use crate::replication::parsevalue::{MySQLValue, RowValue};
+
+/// 未知类型的解码器, 默认实现为blob的格式, 解析结果为MySQLValue::Opaque
+pub trait OpaqueDecoder: Send + Sync {
+    /// table_map中每个字段metadata的字节数
+    fn meta_len(&self) -> usize {
+        1
+    }
+
+    /// 行数据中值之前长度占用的字节数, 返回0时为定长类型, 长度为fixed_len
+    fn length_bytes(&self, meta: &[usize]) -> usize {
+        meta.first().copied().unwrap_or(0)
+    }
+
+    /// 定长类型的字节数
+    fn fixed_len(&self, _meta: &[usize]) -> usize {
+        0
+    }
+
+    /// 将值的内容(不含长度)解析为MySQLValue
+    fn decode(&self, type_code: u8, bytes: Vec<u8>, _meta: &[usize]) -> io::Result<MySQLValue> {
+        Ok(MySQLValue::Opaque{ type_code, bytes })
+    }
+}
+
+struct Raw;
+
+impl OpaqueDecoder for Raw {}
+
+static DECODERS: RwLock<BTreeMap<u8, Arc<dyn OpaqueDecoder>>> = RwLock::new(BTreeMap::new());
+static UNKNOWN_COLUMN_TYPES: Mutex<BTreeSet<u8>> = Mutex::new(BTreeSet::new());
+
+/// 为未知的类型码注册解码器, 已支持的类型不能注册, 重复注册时替换
+pub fn register<D: OpaqueDecoder + 'static>(type_code: u8, decoder: D) -> Result<(), String> {
+    if !matches!(ColumnTypeDict::from_type_code(&type_code), ColumnTypeDict::UnknowType(_)) {
+        return Err(format!("字段类型码{}为已支持的类型, 不能注册解码器", type_code));
+    }
+    let mut decoders = DECODERS.write().map_err(|e| e.to_string())?;
+    decoders.insert(type_code, Arc::new(decoder));
+    Ok(())
+}
+
+/// 取消注册, 之后按默认的blob格式读取
+pub fn unregister(type_code: u8) {
+    if let Ok(mut decoders) = DECODERS.write() {
+        decoders.remove(&type_code);
+    }
+}
+
+/// 读取到的没有注册解码器的类型码
+pub fn unknown_column_types() -> Vec<u8> {
+    UNKNOWN_COLUMN_TYPES.lock().map(|t| t.iter().cloned().collect()).unwrap_or_default()
+}
+
+fn decoder(type_code: u8) -> io::Result<Arc<dyn OpaqueDecoder>> {
+    if let Some(t) = DECODERS.read().ok().and_then(|t| t.get(&type_code).cloned()) {
+        return Ok(t);
+    }
+    if let Ok(mut t) = UNKNOWN_COLUMN_TYPES.lock() {
+        t.insert(type_code);
+    }
+    readvalue::mismatch(WarningKind::UnknownColumnType, format!("未知的字段类型: {}, 按blob的格式读取原始内容", type_code))?;
+    Ok(Arc::new(Raw))
+}
+
+/// table_map中的metadata, 每个字节保存为一项
+pub(crate) fn read_meta<R: Read>(buf: &mut R, type_code: u8) -> io::Result<Vec<usize>> {
+    let len = decoder(type_code)?.meta_len();
+    Ok(readvalue::read_bytes(buf, len)?.into_iter().map(|b| b as usize).collect())
+}
+
+/// table_map中metadata的字节数
+pub(crate) fn meta_len(type_code: u8) -> io::Result<usize> {
+    Ok(decoder(type_code)?.meta_len())
+}
+
+//值的内容(不含长度)
+fn read_content<R: Read>(buf: &mut R, decoder: &dyn OpaqueDecoder, meta: &[usize]) -> io::Result<(usize, Vec<u8>)> {
+    let length_bytes = decoder.length_bytes(meta);
+    let len = match length_bytes {
+        0 => decoder.fixed_len(meta),
+        1..=8 => RowValue::read_str_value_length(buf, &length_bytes)?,
+        t => return Err(readvalue::invalid_data(format!("无效的长度字节数: {}", t)))
+    };
+    Ok((length_bytes, readvalue::read_bytes(buf, len)?))
+}
+
+/// 读取一个值并解析
+pub(crate) fn read_value<R: Read>(buf: &mut R, type_code: u8, meta: &[usize]) -> io::Result<MySQLValue> {
+    let decoder = decoder(type_code)?;
+    let (_, bytes) = read_content(buf, decoder.as_ref(), meta)?;
+    decoder.decode(type_code, bytes, meta)
+}
+
+/// 读取一个值的原始字节(包含长度), 用于生成回滚事件
+pub(crate) fn read_raw<R: Read>(buf: &mut R, type_code: u8, meta: &[usize]) -> io::Result<Vec<u8>> {
+    let decoder = decoder(type_code)?;
+    let (length_bytes, bytes) = read_content(buf, decoder.as_ref(), meta)?;
+    let mut raw = (bytes.len() as u64).to_le_bytes()[..length_bytes].to_vec();
+    raw.extend(bytes);
+    Ok(raw)
+}
+
+/// 值的编码, 与read_value对应, 只支持解析结果为MySQLValue::Opaque的值
+pub(crate) fn encode_value(type_code: u8, meta: &[usize], bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let decoder = decoder(type_code)?;
+    let length_bytes = decoder.length_bytes(meta);
+    if length_bytes == 0 {
+        if bytes.len() != decoder.fixed_len(meta) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("字段类型{}的值长度({})与定长({})不符", type_code, bytes.len(), decoder.fixed_len(meta))));
+        }
+        return Ok(bytes.to_vec());
+    }
+    if length_bytes > 8 || (length_bytes < 8 && bytes.len() as u64 >= 1 << (length_bytes * 8)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("长度({})超过{}bytes能够表示的范围", bytes.len(), length_bytes)));
+    }
+    let mut v = (bytes.len() as u64).to_le_bytes()[..length_bytes].to_vec();
+    v.extend_from_slice(bytes);
+    Ok(v)
+}
@@ -8,7 +8,8 @@ use serde::{Serialize,Serializer};
 use crate::replication::readevent::{TableMap, EventHeader, BinlogEvent, Tell};
 use crate::meta::ColumnTypeDict;
 use crate::{readvalue, Config};
-use crate::replication::jsonb;
+use crate::warning::WarningKind;
+use crate::replication::{jsonb, opaque, tablemeta};
 use bigdecimal::BigDecimal;
 use std::io::{Read, Cursor, Seek};
 use byteorder::{ReadBytesExt, BigEndian, LittleEndian};
@@ -24,24 +25,26 @@ pub struct DecimalMeta{
     pub bytes_to_read: usize
 }
 impl DecimalMeta{
-    pub fn new(precision: u8, decimal: u8) -> DecimalMeta{
+    pub fn new(precision: u8, decimal: u8) -> io::Result<DecimalMeta>{
         let decimal_digits_per_integer = 9;
         let compressed_byte_map = [0usize, 1, 1, 2, 2, 3, 3, 4, 4, 4];
-        let integral = precision - decimal;
+        //小数位数不能大于精度, 来自损坏的metadata时返回错误
+        let integral = precision.checked_sub(decimal)
+            .ok_or_else(|| readvalue::invalid_data(format!("无效的decimal metadata: ({}, {})", precision, decimal)))?;
         let uncompressed_integers: usize = (integral / decimal_digits_per_integer).into();
         let uncompressed_decimals: usize = (decimal / decimal_digits_per_integer).into();
         let compressed_integers: usize = integral as usize - (uncompressed_integers * decimal_digits_per_integer as usize);
         let compressed_decimals: usize = decimal as usize - (uncompressed_decimals * decimal_digits_per_integer as usize);
 
         let bytes_to_read: usize = uncompressed_integers * 4 + compressed_byte_map[compressed_integers] + uncompressed_decimals * 4 + compressed_byte_map[compressed_decimals];
-        DecimalMeta{
+        Ok(DecimalMeta{
             compressed_byte_map,
             uncompressed_integers,
             uncompressed_decimals,
             compressed_integers,
             compressed_decimals,
             bytes_to_read
-        }
+        })
 
     }
 }
@@ -71,7 +74,7 @@ impl Serialize for Blob {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum MySQLValue {
     SignedInteger(i64),
     Float(f32),
@@ -81,11 +84,13 @@ pub enum MySQLValue {
     Blob(Vec<u8>),
     Year(u32),
     Date { year: u32, month: u32, day: u32 },
-    Time { hours: u32, minutes: u32, seconds: u32, subseconds: u32},
+    Time { negative: bool, hours: u32, minutes: u32, seconds: u32, subseconds: u32},
     DateTime { year: u32, month: u32, day: u32, hour: u32, minute: u32, second: u32, subsecond: u32 },
     Json(serde_json::Value),
     Decimal(bigdecimal::BigDecimal),
     Timestamp { unix_time: i32, subsecond: u32 },
+    /// 未知字段类型的值, 见replication::opaque
+    Opaque { type_code: u8, bytes: Vec<u8> },
     Null
 }
 
@@ -115,90 +120,132 @@ pub struct RowValue{
 
 pub fn is_null(null_bytes: &Vec<u8>, pos: &usize) -> u8 {
     let idx = (pos / 8) as usize;
-    let bit = null_bytes[idx];
+    let bit = null_bytes.get(idx).copied().unwrap_or(0);
     return bit & (1 << (pos % 8));
 }
 impl RowValue{
-    pub fn read_row_value<R: Read+Seek>(buf: &mut R, map: &TableMap, header: &EventHeader, read_type: &crate::meta::ReadType) -> RowValue {
+    //每行数据的字段值, update事件中前后镜像依次排列
+    pub fn rows(&self) -> &[Vec<Option<MySQLValue>>] {
+        &self.rows
+    }
+
+    pub fn read_row_value<R: Read+Seek>(buf: &mut R, map: &TableMap, header: &EventHeader, read_type: &crate::meta::ReadType) -> io::Result<RowValue> {
+        let _timer = crate::profile::start(crate::profile::Stage::Decode);
         let row_event_fix = 8;
-        buf.seek(io::SeekFrom::Current(row_event_fix)).unwrap();
-        let extra_len = buf.read_u16::<LittleEndian>().unwrap();
+        buf.seek(io::SeekFrom::Current(row_event_fix))?;
+        let extra_len = buf.read_u16::<LittleEndian>()?;
         if extra_len > 2 {
             //println!("extra_len:{}",extra_len);
-            buf.seek(io::SeekFrom::Current((extra_len - 2) as i64)).unwrap();
+            buf.seek(io::SeekFrom::Current((extra_len - 2) as i64))?;
         }
 
         //let col_count = map.column_info.len();
-        let col_count = buf.read_u8().unwrap();
+        let col_count = buf.read_u8()? as usize;
         //println!("{:?}",col_count);
-        let columns_length = ((col_count + 7) / 8) as i64;
+        //行事件与table_map_event的字段数不一致时无法确定每个字段的位置
+        if col_count != map.column_info.len() {
+            return Err(readvalue::invalid_data(format!("行事件的字段数({})与table_map({}.{})的字段数({})不一致",
+                                                       col_count, map.database_name, map.table_name, map.column_info.len())));
+        }
+        let columns_length = col_count.div_ceil(8);
         match header.type_code {
             BinlogEvent::UpdateEvent => {
-                buf.seek(io::SeekFrom::Current(columns_length * 2)).unwrap();
+                buf.seek(io::SeekFrom::Current(columns_length as i64 * 2))?;
             }
             _ => {
-                buf.seek(io::SeekFrom::Current(columns_length)).unwrap();
+                buf.seek(io::SeekFrom::Current(columns_length as i64))?;
 
             }
         }
-        let mut rows: Vec<Vec<Option<MySQLValue>>> = vec![];;
+        let mut rows: Vec<Vec<Option<MySQLValue>>> = vec![];
         loop {
-            let mut null_bit = vec![0u8; columns_length as usize];
-            buf.read_exact(&mut null_bit).unwrap();
+            let row_start = buf.tell()?;
+            let mut null_bit = vec![0u8; columns_length];
+            buf.read_exact(&mut null_bit)?;
 
             let mut row: Vec<Option<MySQLValue>> = vec![];
-            let columns = map.column_info.len();
-            for idx in 0..columns {
+            for (idx, col) in map.column_info.iter().enumerate() {
                 //println!("{},{:?},{},{}",idx,map.column_info[idx].column_type,buf.tell().unwrap(),header.event_length);
-                let value= if is_null(&null_bit.to_vec(), &idx) > 0{
+                let value= if is_null(&null_bit, &idx) > 0{
                     MySQLValue::Null
+                } else if let ColumnTypeDict::MysqlTypeString = col.column_type {
+                    let binary = map.metadata.as_ref().and_then(|m| m.columns.get(idx)).and_then(|c| c.collation).map(|c| c == tablemeta::BINARY_COLLATION);
+                    Self::fixed_string(Self::parsevalue(buf, &col.column_type, &col.column_meta)?, col.column_meta.first().copied().unwrap_or(0), binary)
                 } else {
-                    Self::parsevalue(buf, &map.column_info[idx].column_type, &map.column_info[idx].column_meta)
+                    Self::parsevalue(buf, &col.column_type, &col.column_meta)?
 
                 };
                 row.push(Some(value));
             }
             rows.push(row);
+            let cur = buf.tell()?;
+            //没有字段的行不占用数据, 继续读取会一直循环
+            if cur == row_start {
+                break;
+            }
             match read_type {
                 crate::meta::ReadType::Repl => {
-                    if (buf.tell().unwrap() + 4) as usize > header.event_length as usize {
+                    if (cur + 4) as usize > header.event_length as usize {
                         break;
                     }
                 }
                 crate::meta::ReadType::File => {
-                    if (buf.tell().unwrap() + 4) as usize >= header.event_length as usize  - 19 {
+                    if (cur + 4) as usize >= (header.event_length as usize).saturating_sub(19) {
                         break;
                     }
                 }
             }
-
         };
-        RowValue{
+        Ok(RowValue{
             rows
+        })
+    }
+
+    /// char、binary的值: binlog中去掉了末尾的填充(char为空格, binary为0x00), length为字段定义的字节数
+    /// binary按0x00补齐到定义的长度, char去掉末尾的空格, 没有字符集信息(binary为None)时不处理
+    pub fn fixed_string(value: MySQLValue, length: usize, binary: Option<bool>) -> MySQLValue {
+        match (value, binary) {
+            (MySQLValue::Blob(mut t), Some(true)) => {
+                if t.len() < length {
+                    t.resize(length, 0);
+                }
+                MySQLValue::Blob(t)
+            }
+            (MySQLValue::Blob(mut t), Some(false)) => {
+                let len = t.iter().rposition(|b| *b != b' ').map(|n| n + 1).unwrap_or(0);
+                t.truncate(len);
+                MySQLValue::Blob(t)
+            }
+            (value, _) => value
         }
     }
 
-    fn parsevalue<R: Read + Tell>(buf: &mut R, type_code: &ColumnTypeDict, col_meta: &Vec<usize>) -> MySQLValue{
-        match type_code {
+    pub(crate) fn parsevalue<R: Read + Tell>(buf: &mut R, type_code: &ColumnTypeDict, col_meta: &[usize]) -> io::Result<MySQLValue>{
+        let meta = |idx: usize| col_meta.get(idx).copied().ok_or_else(|| readvalue::invalid_data(format!("{:?}缺少metadata", type_code)));
+        Ok(match type_code {
             ColumnTypeDict::MysqlTypeTiny => {
-                MySQLValue::SignedInteger(buf.read_i8().unwrap() as i64)
+                MySQLValue::SignedInteger(buf.read_i8()? as i64)
             }
             ColumnTypeDict::MysqlTypeShort => {
-                MySQLValue::SignedInteger(buf.read_i16::<LittleEndian>().unwrap() as i64)
+                MySQLValue::SignedInteger(buf.read_i16::<LittleEndian>()? as i64)
             }
             ColumnTypeDict::MysqlTypeInt24 => {
-                MySQLValue::SignedInteger(buf.read_i24::<LittleEndian>().unwrap() as i64)
+                MySQLValue::SignedInteger(buf.read_i24::<LittleEndian>()? as i64)
             }
             ColumnTypeDict::MysqlTypeLong => {
-                MySQLValue::SignedInteger(buf.read_i32::<LittleEndian>().unwrap() as i64)
+                MySQLValue::SignedInteger(buf.read_i32::<LittleEndian>()? as i64)
             }
             ColumnTypeDict::MysqlTypeLonglong => {
-                MySQLValue::SignedInteger(buf.read_i64::<LittleEndian>().unwrap() as i64)
+                MySQLValue::SignedInteger(buf.read_i64::<LittleEndian>()?)
             }
             ColumnTypeDict::MysqlTypeNewdecimal => {
-                let decimal_meta = DecimalMeta::new(col_meta[0] as u8, col_meta[1] as u8);
+                let (precision, decimal) = (meta(0)? as u8, meta(1)? as u8);
+                if precision == 0 {
+                    return Err(readvalue::invalid_data(format!("无效的decimal metadata: ({}, {})", precision, decimal)));
+                }
+                let decimal_meta = DecimalMeta::new(precision, decimal)?;
                 let mut value_buf = vec![0u8; decimal_meta.bytes_to_read];
-                buf.read_exact(&mut value_buf).unwrap();
+                buf.read_exact(&mut value_buf)?;
                 match Self::read_new_decimal(&value_buf.to_vec(), &decimal_meta) {
                     Ok(t) => MySQLValue::Decimal(t),
                     Err(e) => {
@@ -209,15 +256,16 @@ impl RowValue{
             }
             ColumnTypeDict::MysqlTypeDouble |
             ColumnTypeDict::MysqlTypeFloat => {
-                match col_meta[0] {
-                    8 => MySQLValue::Double(buf.read_f64::<LittleEndian>().unwrap() as f64),
-                    4 => MySQLValue::Float(buf.read_f32::<LittleEndian>().unwrap() as f32),
-                    _ => MySQLValue::Null
+                //metadata为占用的字节数, float为4、double为8, 按字节数读取
+                match meta(0)? {
+                    8 => MySQLValue::Double(buf.read_f64::<LittleEndian>()?),
+                    4 => MySQLValue::Float(buf.read_f32::<LittleEndian>()?),
+                    pack => return Err(readvalue::invalid_data(format!("无效的{:?} metadata: {}", type_code, pack)))
                 }
             }
             ColumnTypeDict::MysqlTypeTimestamp2 => {
-                let whole_part = buf.read_i32::<BigEndian>().unwrap();
-                let frac_part = Self::read_datetime_fsp(buf, col_meta[0] as u8).unwrap();
+                let whole_part = buf.read_i32::<BigEndian>()?;
+                let frac_part = Self::read_datetime_fsp(buf, meta(0)? as u8)?;
                 MySQLValue::Timestamp { unix_time: whole_part, subsecond: frac_part }
             }
             ColumnTypeDict::MysqlTypeDatetime2 => {
@@ -233,8 +281,8 @@ impl RowValue{
                 40 bits = 5 bytes
                 */
                 let mut tmp_buf = [0u8; 5];
-                buf.read_exact(&mut tmp_buf).unwrap();
-                let subsecond = Self::read_datetime_fsp(buf, col_meta[0] as u8).unwrap();
+                buf.read_exact(&mut tmp_buf)?;
+                let subsecond = Self::read_datetime_fsp(buf, meta(0)? as u8)?;
                 tmp_buf[0] &= 0x7f;
 
                 let year_month: u32 = ((tmp_buf[2] as u32) >> 6) + ((tmp_buf[1] as u32) << 2) + ((tmp_buf[0] as u32) << 10);
@@ -249,17 +297,34 @@ impl RowValue{
                 MySQLValue::DateTime { year, month, day, hour, minute, second, subsecond }
             }
             ColumnTypeDict::MysqlTypeYear => {
-                MySQLValue::Year(buf.read_u8().unwrap() as u32 + 1900)
+                MySQLValue::Year(buf.read_u8()? as u32 + 1900)
+            }
+            //5.6之前的格式, 没有重建的表及老版本mysql仍然使用
+            ColumnTypeDict::MysqlTypeTimestamp => {
+                //4bytes时间戳, 没有小数部分
+                MySQLValue::Timestamp { unix_time: buf.read_i32::<LittleEndian>()?, subsecond: 0 }
+            }
+            ColumnTypeDict::MysqlTypeDatetime => {
+                //8bytes整数YYYYMMDDhhmmss
+                let value = buf.read_u64::<LittleEndian>()?;
+                let (date, time) = ((value / 1000000) as u32, (value % 1000000) as u32);
+                MySQLValue::DateTime { year: date / 10000, month: date / 100 % 100, day: date % 100,
+                    hour: time / 10000, minute: time / 100 % 100, second: time % 100, subsecond: 0 }
+            }
+            ColumnTypeDict::MysqlTypeTime => {
+                //3bytes有符号整数hhmmss
+                let value = buf.read_i24::<LittleEndian>()?;
+                let (negative, value) = (value < 0, value.unsigned_abs());
+                MySQLValue::Time { negative, hours: value / 10000, minutes: value / 100 % 100, seconds: value % 100, subseconds: 0 }
             }
-            ColumnTypeDict::MysqlTypeDate => {
-                let value = buf.read_u24::<LittleEndian>().unwrap();
+            ColumnTypeDict::MysqlTypeDate |
+            ColumnTypeDict::MysqlTypeNewdate => {
+                let value = buf.read_u24::<LittleEndian>()?;
                 let year = (value & ((1 << 15) - 1) << 9) >> 9;
                 let month = (value & ((1 << 4) - 1) << 5) >> 5;
                 let day = value & ((1 << 5) - 1);
-                if year == 0 {MySQLValue::Null}
-                else if month == 0 { MySQLValue::Null }
-                else if day == 0 { MySQLValue::Null }
-                else { MySQLValue::Date {year, month, day} }
+                //0000-00-00等零值原样返回, 由输出时按zerodate处理
+                MySQLValue::Date {year, month, day}
 
             }
             ColumnTypeDict::MysqlTypeTime2 => {
@@ -274,13 +339,26 @@ impl RowValue{
                 ---------------------
                 24 bits = 3 bytes
                 */
+                //整数部分加上0x800000存储, 负数的小数部分不为0时整数部分减1、小数部分存储为补数
+                //  如fsp为1时-00:00:01.5存储为整数部分-2, 小数部分256 - 50 = 206
                 let mut tmp_buf = [0u8; 3];
-                buf.read_exact(&mut tmp_buf).unwrap();
-                let hours = (((tmp_buf[0] & 0x3f) as u32) << 4) | (((tmp_buf[1] & 0xf0) as u32) >> 4);
-                let minutes = (((tmp_buf[1] & 0x0f) as u32) << 2) | (((tmp_buf[2] & 0xb0) as u32) >> 6);
-                let seconds = (tmp_buf[2] & 0x3f) as u32;
-                let frac_part = Self::read_datetime_fsp(buf, col_meta[0] as u8).unwrap();
-                MySQLValue::Time { hours, minutes, seconds, subseconds: frac_part }
+                buf.read_exact(&mut tmp_buf)?;
+                let mut intpart = (((tmp_buf[0] as i64) << 16) | ((tmp_buf[1] as i64) << 8) | tmp_buf[2] as i64) - 0x800000;
+                let frac_bytes = meta(0)?.min(6).div_ceil(2);
+                let mut frac_part = 0u32;
+                for _ in 0..frac_bytes {
+                    frac_part = (frac_part << 8) | buf.read_u8()? as u32;
+                }
+                let negative = intpart < 0;
+                if negative && frac_part != 0 {
+                    intpart += 1;
+                    frac_part = (1u32 << (frac_bytes * 8)) - frac_part;
+                }
+                let value = intpart.unsigned_abs() as u32;
+                let hours = (value >> 12) & 0x3ff;
+                let minutes = (value >> 6) & 0x3f;
+                let seconds = value & 0x3f;
+                MySQLValue::Time { negative, hours, minutes, seconds, subseconds: frac_part }
             }
             ColumnTypeDict::MysqlTypeVarString |
             ColumnTypeDict::MysqlTypeVarchar |
@@ -288,79 +366,73 @@ impl RowValue{
             ColumnTypeDict::MysqlTypeTinyBlob |
             ColumnTypeDict::MysqlTypeLongBlob |
             ColumnTypeDict::MysqlTypeMediumBlob |
+            ColumnTypeDict::MysqlTypeGeometry |
             ColumnTypeDict::MysqlTypeBit => {
-                let var_length =  Self::read_str_value_length(buf, &col_meta[0]);
-                let mut pack = vec![0u8; var_length];
-                buf.read_exact(&mut pack).unwrap();
-                MySQLValue::Blob(pack)
+                let var_length =  Self::read_str_value_length(buf, &meta(0)?)?;
+                MySQLValue::Blob(readvalue::read_bytes(buf, var_length)?)
             }
             ColumnTypeDict::MysqlTypeJson => {
-                let value_length = Self::read_str_value_length(buf, &col_meta[0]);
-                MySQLValue::Json(jsonb::read_binary_json(buf, &value_length))
+                let value_length = Self::read_str_value_length(buf, &meta(0)?)?;
+                MySQLValue::Json(jsonb::read_binary_json(buf, &value_length)?)
 
             }
             ColumnTypeDict::MysqlTypeString => {
-                let mut value_length = 0;
-                //println!("aa:{},{}",col_meta[0],buf.tell().unwrap());
-                if col_meta[0] <= 255 {
-                    value_length = buf.read_u8().unwrap() as usize;
-                }
-                else {
-                    value_length = buf.read_u16::<LittleEndian>().unwrap() as usize;
+                //metadata为字段定义的字节数, 大于255时值的长度占用2bytes
+                let length = meta(0)?;
+                let value_length = if length <= 255 {
+                    buf.read_u8()? as usize
+                } else {
+                    buf.read_u16::<LittleEndian>()? as usize
+                };
+                if value_length > length {
+                    readvalue::mismatch(WarningKind::Metadata, format!("char的值长度({})超过字段定义的长度({})", value_length, length))?;
                 }
-                let mut pack = vec![0u8; value_length];
-                buf.read_exact(&mut pack).unwrap();
-                MySQLValue::Blob(pack)
+                MySQLValue::Blob(readvalue::read_bytes(buf, value_length)?)
             }
             ColumnTypeDict::MysqlTypeEnum |
             ColumnTypeDict::MysqlTypeSet => {
-                match col_meta[0] {
-                    1 => {
-                        let v = buf.read_u8().unwrap();
-                        MySQLValue::SignedInteger(v as i64)
-                    },
-                    2 => {
-                        let v = buf.read_u16::<LittleEndian>().unwrap();
-                        MySQLValue::SignedInteger(v as i64)
-                    }
-                    _ => MySQLValue::Null
+                //metadata为占用的字节数: enum为1、2, set为1、2、3、4、8
+                match meta(0)? {
+                    pack @ 1..=8 => MySQLValue::SignedInteger(buf.read_uint::<LittleEndian>(pack)? as i64),
+                    pack => return Err(readvalue::invalid_data(format!("无效的{:?} metadata: {}", type_code, pack)))
                 }
             }
+            ColumnTypeDict::UnknowType(code) => opaque::read_value(buf, *code, col_meta)?,
             _ => MySQLValue::Null
-        }
+        })
     }
 
-    fn read_str_value_length<R: Read>(buf: &mut R, meta: &usize) -> usize {
-        match meta {
-            1 => buf.read_u8().unwrap() as usize,
-            2 => buf.read_u16::<LittleEndian>().unwrap() as usize,
-            3 => buf.read_u24::<LittleEndian>().unwrap() as usize,
-            4 => buf.read_u32::<LittleEndian>().unwrap() as usize,
+    pub(crate) fn read_str_value_length<R: Read>(buf: &mut R, meta: &usize) -> io::Result<usize> {
+        Ok(match meta {
+            1 => buf.read_u8()? as usize,
+            2 => buf.read_u16::<LittleEndian>()? as usize,
+            3 => buf.read_u24::<LittleEndian>()? as usize,
+            4 => buf.read_u32::<LittleEndian>()? as usize,
             5 => {
                 let mut pack = [0u8; 5];
-                buf.read_exact(&mut pack).unwrap();
+                buf.read_exact(&mut pack)?;
                 readvalue::read_u40(&pack) as usize
             }
             6 => {
                 let mut pack = [0u8; 6];
-                buf.read_exact(&mut pack).unwrap();
+                buf.read_exact(&mut pack)?;
                 readvalue::read_u48(&pack) as usize
             }
             7 => {
                 let mut pack = [0u8; 7];
-                buf.read_exact(&mut pack).unwrap();
+                buf.read_exact(&mut pack)?;
                 readvalue::read_u56(&pack) as usize
             }
             8 => {
                 let mut pack = [0u8; 8];
-                buf.read_exact(&mut pack).unwrap();
+                buf.read_exact(&mut pack)?;
                 readvalue::read_u64(&pack) as usize
             }
             _ => 0 as usize
-        }
+        })
     }
 
-    fn read_new_decimal(buf: &Vec<u8>, meta: &DecimalMeta) -> Result<BigDecimal, failure::Error> {
+    pub(crate) fn read_new_decimal(buf: &Vec<u8>, meta: &DecimalMeta) -> Result<BigDecimal, failure::Error> {
         let mut components = Vec::new();
         let mut buf = buf.clone();
         let is_negative = (buf[0] & 0x80) == 0;
@@ -388,7 +460,7 @@ impl RowValue{
         }
         if meta.compressed_decimals != 0 {
             let v = Self::read_int_be_by_size(&mut r, meta.compressed_byte_map[meta.compressed_decimals])? ^ mask;
-            components.push(format!("{:.*}",meta.compressed_decimals,v as usize));
+            components.push(format!("{:0width$}", v, width = meta.compressed_decimals));
         }
         components.join("").parse::<BigDecimal>().map_err(|e| failure::Error::from_boxed_compat(Box::new(e)))
     }
@@ -0,0 +1,364 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/30
+*/
+
+//运行前对mysql及配置进行检查, --dry-run时只输出检查结果及执行计划, 不拉取/解析数据
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::net::TcpStream;
+use std::process;
+use crate::{Config, create_conn};
+use crate::io::command;
+use crate::gtid::GtidSet;
+use crate::exitcode;
+
+//mysql中与binlog相关的配置
+#[derive(Debug, Clone, Default)]
+pub struct ServerInfo{
+    pub version: String,
+    pub version_comment: String,
+    pub log_bin: String,
+    pub binlog_format: String,
+    pub binlog_row_image: String,
+    pub gtid_mode: String,
+    pub binlog_checksum: String,
+}
+
+impl ServerInfo{
+    pub fn query(conn: &mut TcpStream) -> Result<ServerInfo, String> {
+        let mut info = ServerInfo::default();
+        for (var, value) in [
+            ("version", &mut info.version),
+            ("version_comment", &mut info.version_comment),
+            ("log_bin", &mut info.log_bin),
+            ("binlog_format", &mut info.binlog_format),
+            ("binlog_row_image", &mut info.binlog_row_image),
+            ("gtid_mode", &mut info.gtid_mode),
+            ("binlog_checksum", &mut info.binlog_checksum),
+        ] {
+            //低版本不存在的变量(5.5没有gtid_mode)返回错误, 按空值处理
+            if let Ok(values) = command::try_execute(conn, &format!("select @@global.{} as value;", var)) {
+                *value = first_value(&values, "value");
+            }
+        }
+        if info.version.is_empty() {
+            return Err(String::from("获取mysql版本信息失败"));
+        }
+        Ok(info)
+    }
+}
+
+//一项检查的结果
+#[derive(Debug, Clone)]
+pub struct Check{
+    pub name: &'static str,
+    pub ok: bool,
+    pub message: String,
+}
+
+impl Check{
+    fn new(name: &'static str, ok: bool, message: String) -> Check {
+        Check{ name, ok, message }
+    }
+}
+
+//检查mysql的binlog配置是否满足解析需要, 未通过时message中给出原因及修改方法
+pub fn check_server(info: &ServerInfo, conf: &Config) -> Vec<Check> {
+    let mut checks = vec![];
+    let major = info.version.split('.').next().and_then(|v| v.parse::<u8>().ok()).unwrap_or(0);
+    if major >= 5 {
+        checks.push(Check::new("version", true, info.version.clone()));
+    }else {
+        checks.push(Check::new("version", false, format!("version={}, 只支持5.x及以上版本的binlog格式", info.version)));
+    }
+
+    //读取本地文件时binlog可能来自其他实例, 只检查版本
+    if conf.runtype != "repl" && conf.runtype != "backup" {
+        return checks;
+    }
+
+    //MariaDB的行事件及gtid与mysql不同, backup只保存原始文件, 可以使用binlog文件、position注册
+    let fingerprint = crate::fingerprint::ServerFingerprint::parse(&info.version, &info.version_comment);
+    if conf.runtype == "repl" && !fingerprint.row_events() {
+        checks.push(Check::new("fork", false, format!("{} {}的行事件为v1格式, 无法解析行数据, 只能使用backup保存原始文件", fingerprint.fork, info.version)));
+    }else if !conf.gtid.is_empty() && !fingerprint.gtid() {
+        checks.push(Check::new("fork", false, format!("{} {}不支持COM_BINLOG_DUMP_GTID, 需要使用--binlogfile、--position注册", fingerprint.fork, info.version)));
+    }else {
+        checks.push(Check::new("fork", true, fingerprint.fork.to_string()));
+    }
+
+    if info.log_bin == "1" || info.log_bin.eq_ignore_ascii_case("on") {
+        checks.push(Check::new("log_bin", true, format!("log_bin={}", info.log_bin)));
+    }else {
+        checks.push(Check::new("log_bin", false, format!("log_bin={}, mysql未开启binlog, 需要配置log-bin并重启", info.log_bin)));
+    }
+
+    let checksum = info.binlog_checksum.to_uppercase();
+    if checksum.is_empty() || checksum == "NONE" || checksum == "CRC32" {
+        checks.push(Check::new("binlog_checksum", true, format!("binlog_checksum={}", info.binlog_checksum)));
+    }else {
+        checks.push(Check::new("binlog_checksum", false, format!("binlog_checksum={}, 只支持NONE、CRC32", info.binlog_checksum)));
+    }
+
+    //backup只保存原始文件, 不需要解析行数据
+    if conf.runtype == "repl" {
+        if info.binlog_format.eq_ignore_ascii_case("row") {
+            checks.push(Check::new("binlog_format", true, format!("binlog_format={}", info.binlog_format)));
+        }else {
+            checks.push(Check::new("binlog_format", false, format!(
+                "binlog_format={}, row decoding unavailable, 无法解析行数据, 需要set global binlog_format=ROW", info.binlog_format)));
+        }
+
+        if info.binlog_row_image.is_empty() || info.binlog_row_image.eq_ignore_ascii_case("full") {
+            checks.push(Check::new("binlog_row_image", true, format!("binlog_row_image={}", info.binlog_row_image)));
+        }else {
+            checks.push(Check::new("binlog_row_image", false, format!(
+                "binlog_row_image={}, 行数据中缺少部分字段, 无法生成完整的sql及回滚语句, 需要set global binlog_row_image=FULL", info.binlog_row_image)));
+        }
+    }
+
+    if !conf.gtid.is_empty() {
+        if info.gtid_mode.eq_ignore_ascii_case("on") {
+            checks.push(Check::new("gtid_mode", true, format!("gtid_mode={}", info.gtid_mode)));
+        }else {
+            checks.push(Check::new("gtid_mode", false, format!(
+                "gtid_mode={}, 无法使用--gtid注册同步, 需要开启gtid_mode或改用--binlogfile、--position", info.gtid_mode)));
+        }
+    }
+    checks
+}
+
+//stream、backup模式开始拉取之前检查mysql配置, 有不满足的配置时输出原因并退出, 避免之后出现难以理解的解析错误
+pub fn validate_server(conn: &mut TcpStream, conf: &Config) {
+    //经过中间件时先检查是否支持拉取binlog
+    let profile = crate::compat::check_dump(conn, conf).unwrap_or_else(|err|{
+        println!("{}", err);
+        process::exit(exitcode::SERVER);
+    });
+    if profile.is_some_and(|p| !p.queryable()) {
+        eprintln!("{:?}不返回mysql的binlog配置, 未检查; 不能查询表结构, 字段名按@1、@2等输出", profile.unwrap());
+        return;
+    }
+    let info = ServerInfo::query(conn).unwrap_or_else(|err|{
+        println!("{}", err);
+        process::exit(exitcode::SERVER);
+    });
+    let failed: Vec<Check> = check_server(&info, conf).into_iter().filter(|c| !c.ok).collect();
+    if !failed.is_empty() {
+        for check in &failed {
+            println!("mysql配置不满足要求: {}", check.message);
+        }
+        process::exit(exitcode::SERVER);
+    }
+    if let Some(profile) = profile.filter(|p| p.managed()) {
+        //只读实例由systemd等稍后重新启动
+        profile.check_writer(conn, conf).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::CONNECT);
+        });
+        if conf.gtid.is_empty() {
+            eprintln!("兼容模式下集群地址故障切换后binlog文件及position不同, 建议使用--gtid注册");
+        }
+    }
+}
+
+//检查当前用户是否有REPLICATION SLAVE、REPLICATION CLIENT权限
+pub fn check_grants(conn: &mut TcpStream) -> Check {
+    let values = match command::try_execute(conn, "show grants;") {
+        Ok(t) => t,
+        Err(e) => return Check::new("privileges", false, e)
+    };
+    let grants: Vec<String> = values.iter()
+        .flat_map(|row| row.values().cloned())
+        .filter(|g| g.contains(" ON *.* "))
+        .map(|g| g.to_uppercase())
+        .collect();
+    let all = grants.iter().any(|g| g.contains("ALL PRIVILEGES"));
+    let missing: Vec<&str> = ["REPLICATION SLAVE", "REPLICATION CLIENT"].iter()
+        .filter(|p| !all && !grants.iter().any(|g| g.contains(*p)))
+        .copied()
+        .collect();
+    if missing.is_empty() {
+        Check::new("privileges", true, String::from("REPLICATION SLAVE, REPLICATION CLIENT"))
+    }else {
+        Check::new("privileges", false, format!("缺少权限: {}", missing.join(", ")))
+    }
+}
+
+//检查起始位置是否可用
+pub fn check_start(conn: &mut TcpStream, conf: &Config) -> Check {
+    if conf.runtype == "file" || conf.runtype == "copy" {
+        return check_file(conf);
+    }
+    if !conf.gtid.is_empty() {
+        let gtid_set = match conf.gtid.parse::<GtidSet>() {
+            Ok(t) => t,
+            Err(e) => return Check::new("start", false, e)
+        };
+        let purged = match command::try_execute(conn, "select @@global.gtid_purged as value;") {
+            Ok(t) => first_value(&t, "value").replace('\n', ""),
+            Err(e) => return Check::new("start", false, e)
+        };
+        return match purged.parse::<GtidSet>() {
+            Ok(p) if gtid_set.contains_set(&p) => Check::new("start", true, format!("gtid: {}", gtid_set)),
+            Ok(p) => Check::new("start", false, format!("mysql已经purge了部分未执行的事务, gtid_purged: {}", p)),
+            Err(e) => Check::new("start", false, e)
+        };
+    }
+
+    let logs = match command::try_execute(conn, "show binary logs;") {
+        Ok(t) => t,
+        Err(e) => return Check::new("start", false, e)
+    };
+    let position = conf.position.parse::<u64>().unwrap_or(4);
+    match logs.iter().find(|row| row.get("Log_name") == Some(&conf.binlogfile)) {
+        Some(row) => {
+            let size = row.get("File_size").and_then(|t| t.parse::<u64>().ok()).unwrap_or(0);
+            Check::new("start", position <= size,
+                       format!("binlogfile: {}, position: {}, file_size: {}", conf.binlogfile, position, size))
+        }
+        None => Check::new("start", false, format!("binlog文件{}不存在", conf.binlogfile))
+    }
+}
+
+fn check_file(conf: &Config) -> Check {
+    let mut magic = [0u8; 4];
+    let size = match File::open(&conf.file).and_then(|mut f| { f.read_exact(&mut magic)?; f.metadata() }) {
+        Ok(t) => t.len(),
+        Err(e) => return Check::new("start", false, format!("读取文件({})发生错误:{}", conf.file, e))
+    };
+    if magic != [0xFE,0x62,0x69,0x6E] {
+        return Check::new("start", false, format!("{} 不是binlog文件", conf.file));
+    }
+    let position = conf.startposition.parse::<u64>().unwrap_or(4);
+    Check::new("start", position <= size, format!("file: {}, startposition: {}, file_size: {}", conf.file, position, size))
+}
+
+fn first_value(values: &[HashMap<String, String>], name: &str) -> String {
+    values.first().and_then(|row| row.get(name)).cloned().unwrap_or_default()
+}
+
+//--dry-run: 连接mysql进行检查并输出执行计划, 有检查未通过时返回false
+pub fn dry_run(conf: &Config) -> bool {
+    let mut conn = create_conn(conf);
+    let dump = conf.runtype == "repl" || conf.runtype == "backup";
+    let mut checks = vec![];
+    //经过中间件时是否支持拉取binlog, MaxScale binlogrouter不能查询mysql配置及权限
+    let mut queryable = true;
+    if dump {
+        checks.push(match crate::compat::check_dump(&mut conn, conf) {
+            Ok(profile) => {
+                queryable = profile.is_none_or(|p| p.queryable());
+                Check::new("middleware", true, profile.map_or(String::from("未识别到中间件"), |p| format!("{:?}兼容模式", p)))
+            }
+            Err(e) => Check::new("middleware", false, e),
+        });
+    }
+    if !queryable {
+        checks.push(Check::new("version", true, String::from("binlogrouter不返回mysql配置, 未检查")));
+    }else {
+        match ServerInfo::query(&mut conn) {
+            Ok(info) => checks.extend(check_server(&info, conf)),
+            Err(e) => checks.push(Check::new("version", false, e)),
+        }
+    }
+    if dump && queryable {
+        let mut check = check_grants(&mut conn);
+        //托管服务的权限通过角色授予, SHOW GRANTS中看不到
+        if let (false, Some(profile)) = (check.ok, crate::compat::Profile::from_config(conf).filter(|p| p.managed())) {
+            check = Check::new("privileges", true, format!("{} ({:?}兼容模式, 权限可能通过角色授予, 未确认)", check.message, profile));
+        }
+        checks.push(check);
+        if let Some(profile) = crate::compat::Profile::from_config(conf).filter(|p| p.managed()) {
+            checks.push(match profile.check_writer(&mut conn, conf) {
+                Ok(_) => Check::new("writer", true, String::from("可写实例")),
+                Err(e) => Check::new("writer", false, e),
+            });
+        }
+    }
+    if conf.runtype != "command" {
+        checks.push(check_start(&mut conn, conf));
+    }
+
+    println!("-- dry-run, 不会拉取或解析数据");
+    for check in &checks {
+        println!("[{}] {:<18}{}", if check.ok { "OK" } else { "FAIL" }, check.name, check.message);
+    }
+    if queryable {
+        if let Ok(fingerprint) = crate::fingerprint::detect(&mut conn) {
+            println!("-- 可以解析的事件类型及metadata");
+            for line in fingerprint.report() {
+                println!("{}", line);
+            }
+        }
+    }
+    println!("-- 执行计划");
+    for line in plan(conf) {
+        println!("{}", line);
+    }
+    checks.iter().all(|c| c.ok)
+}
+
+fn plan(conf: &Config) -> Vec<String> {
+    let mut lines = vec![];
+    let source = match conf.runtype.as_str() {
+        "repl" => "从mysql拉取binlog并解析",
+        "backup" => "从mysql拉取binlog并保存到本地目录",
+        "file" if conf.rollback => "读取binlog文件生成回滚日志",
+        "file" if !conf.ddl.is_empty() => "读取binlog文件提取表结构变更语句",
+        "file" if !conf.deps.is_empty() => "读取binlog文件导出事务依赖关系",
+        "file" if conf.hotspot => "读取binlog文件按时间段统计每个表的写入量及热点主键",
+        "file" if conf.statisc => "读取binlog文件统计事务大小",
+        "file" => "读取binlog文件并解析",
+        "copy" => "读取binlog文件将满足条件的事件写入新文件",
+        "command" => "执行sql语句",
+        _ => "未知"
+    };
+    lines.push(format!("模式: {}", source));
+    lines.push(format!("mysql: {}@{}", conf.user_name, crate::endpoint::describe(conf)));
+    for sql in crate::io::connection::init_commands(conf) {
+        lines.push(format!("init-command: {}", sql));
+    }
+    if conf.runtype == "repl" {
+        lines.push(format!("不输出的库表: {}", if conf.excludetables.is_empty() { crate::exclude::DEFAULT } else { &conf.excludetables }));
+        if conf.oscremap {
+            lines.push(String::from("gh-ost、pt-osc影子表的行数据按原表名输出"));
+        }
+    }
+    match conf.runtype.as_str() {
+        "repl" | "backup" if !conf.gtid.is_empty() => lines.push(format!("起始位置: gtid {}", conf.gtid)),
+        "repl" | "backup" => lines.push(format!("起始位置: {}:{}", conf.binlogfile, conf.position)),
+        "file" | "copy" => {
+            lines.push(format!("文件: {}{}", conf.file, if conf.relaylog { " (relay log)" } else { "" }));
+            for (name, value) in [("startposition", &conf.startposition), ("stopposition", &conf.stopposition),
+                                  ("startdatetime", &conf.startdatetime), ("stopdatetime", &conf.stopdatetime),
+                                  ("gtid", &conf.gtid)] {
+                if !value.is_empty() {
+                    lines.push(format!("{}: {}", name, value));
+                }
+            }
+        }
+        "command" => lines.push(format!("语句: {}", conf.command)),
+        _ => {}
+    }
+    if !conf.threadid.is_empty() {
+        lines.push(format!("threadid: {}", conf.threadid));
+    }
+    if !conf.greptbl.is_empty() {
+        lines.push(format!("greptbl: {}", conf.greptbl));
+    }
+    if !conf.output.is_empty() {
+        lines.push(format!("output: {}", conf.output));
+    }
+    if conf.getsql {
+        lines.push(format!("输出: sql语句, insertmode: {}, batchsize: {}",
+                           if conf.insertmode.is_empty() { "insert" } else { &conf.insertmode },
+                           if conf.batchsize.is_empty() { "1" } else { &conf.batchsize }));
+    }else if conf.runtype == "repl" || conf.runtype == "file" {
+        lines.push(format!("输出: {}", if conf.outformat.is_empty() { "pretty" } else { &conf.outformat }));
+    }
+    lines
+}
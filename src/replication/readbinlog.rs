@@ -3,10 +3,18 @@
 @datetime: 2019/9/25
 */
 
+//文件读取及主从拉取的入口都需要连接数据库获取表结构, 未开启network特性时只保留事件定义
+#![cfg_attr(not(feature = "network"), allow(unused_imports, dead_code))]
+
 use crate::{Config, replication};
+#[cfg(feature = "network")]
 use std::net::TcpStream;
-use crate::replication::{readevent, parsevalue, grep};
-use crate::replication::readevent::{InitValue, EventHeader, InitHeader, Tell};
+use crate::replication::{readevent, parsevalue};
+#[cfg(feature = "network")]
+use crate::replication::grep;
+use crate::replication::verify::{self, ChecksumClass, ChecksumVerifier};
+use crate::replication::readevent::{InitValue, EventHeader, Tell};
+#[cfg(feature = "network")]
 use crate::io::{socketio, pack};
 use std::io::{Cursor, Read, Write, Seek, SeekFrom};
 use crate::replication::parsevalue::RowValue;
@@ -15,9 +23,16 @@ use std::io::BufReader;
 use std::fs::File;
 use serde_json;
 use serde_json::Value;
+#[cfg(feature = "network")]
 use crate::replication::rollback;
+#[cfg(feature = "network")]
 use crate::replication::rollback::{ RollBackTrac};
+#[cfg(feature = "network")]
 use crate::replication::grep::UpdateState;
+#[cfg(feature = "network")]
+use std::{io, process};
+#[cfg(feature = "network")]
+use crate::{exitcode, readvalue};
 
 struct GrepInfo{
     grep_gtid: CheckGrepStatus,
@@ -133,13 +148,42 @@ pub enum Traction{
     RowEvent(readevent::BinlogEvent,parsevalue::RowValue),
     XidEvent(readevent::XidEvent),
     RotateLogEvent(readevent::RotateLog),
+    StopEvent,
+    TransactionContextEvent(readevent::TransactionContextEvent),
+    ViewChangeEvent(readevent::ViewChangeEvent),
+    RowsQueryEvent(readevent::RowsQueryEvent),
     RowEventStatic{type_code: readevent::BinlogEvent,count: usize},
+    //新版本mysql增加的事件类型
+    UnknownEvent(readevent::UnknownEvent),
     Unknown,
 }
 
 
+//文件读取结束的原因
+#[derive(Debug, Clone)]
+pub enum FileEnd{
+    Stop,               //最后一个事件为STOP_EVENT, 服务端正常关闭
+    Rotate(String),     //最后一个事件为ROTATE_EVENT, 后续数据在新的binlog文件中
+    Eof,                //在事件边界到达文件末尾, 一般为正在写入的binlog
+    Truncated(u64),     //文件在该position处被截断, 最后一个事件不完整
+    Finished,           //满足stopposition/stopdatetime/gtid条件提前结束
+}
+
+impl FileEnd{
+    pub fn reason(&self) -> &'static str {
+        match self {
+            FileEnd::Stop => "stop",
+            FileEnd::Rotate(_) => "rotate",
+            FileEnd::Eof => "eof",
+            FileEnd::Truncated(_) => "truncated",
+            FileEnd::Finished => "finished",
+        }
+    }
+}
+
+#[cfg(feature = "network")]
 //从文件读取binlog
-pub fn readbinlog_fromfile(conf: &Config, version: &u8, reader: &mut BufReader<File>) {
+pub fn readbinlog_fromfile(conf: &Config, version: &u8, reader: &mut BufReader<File>) -> FileEnd {
     //首先获取文件大小
     reader.seek(SeekFrom::End(0));
     let reader_size = reader.tell().unwrap();
@@ -155,41 +199,97 @@ pub fn readbinlog_fromfile(conf: &Config, version: &u8, reader: &mut BufReader<F
     //设置过滤状态部分
     let mut grep_info = grep::GrepInfo::new(conf);
     let mut check_status = false;
+    let mut file_end = FileEnd::Eof;
+    let mut out_state = crate::stdout::OutState::new(conf);
+    let mut progress = crate::stdout::progress::Progress::new(reader_size, conf);
+    let mut verifier = ChecksumVerifier::new(ChecksumClass::parse(&conf.verifychecksum).unwrap_or(ChecksumClass::empty()));
+    //回滚、复制模式已读取了format_description_event
+    let mut checksum = if rollback_trac.desc_format.is_empty() { None } else { Some(crate::replication::copy::has_checksum(&rollback_trac.desc_format)) };
 
     //
 
-    'all: loop {
+    let end = 'all: loop {
         rollback_trac.cur_event= vec![];
         let mut header_buf = vec![0u8; 19];
         let cur_tell = reader.tell().unwrap();
-        if conf.rollback{
-            if cur_tell + 19 > reader_size {
-                rollback_trac.write_rollback_log();
-            }
+        if cur_tell == reader_size {
+            rollback_trac.is_write();
+            break 'all file_end;
         }
 
-        reader.read_exact(header_buf.as_mut()).unwrap_or_else(|err|{
-            println!("{}",err);
-            std::process::exit(1);
-        });
+        if reader.read_exact(header_buf.as_mut()).is_err() {
+            rollback_trac.is_write();
+            break 'all FileEnd::Truncated(cur_tell);
+        }
         rollback_trac.append_cur_event(&header_buf);
 
-        let mut cur = Cursor::new(header_buf);
-        let event_header: EventHeader = readevent::InitHeader::new(&mut cur,conf);
-        let payload = event_header.event_length as usize - event_header.header_length as usize;
-        let mut payload_buf = vec![0u8; payload];
-        reader.read_exact(payload_buf.as_mut());
+        let mut cur = Cursor::new(&header_buf);
+        let event_header = EventHeader::read(&mut cur).unwrap_or_else(|err|{
+            println!("读取事件包头发生错误: {}", err);
+            process::exit(exitcode::PARSE);
+        });
+        crate::stdout::summary::record_header(&event_header);
+        out_state.timestamp = event_header.timestamp;
+        out_state.event_length = event_header.event_length;
+        out_state.log_pos = cur_tell;
+        let payload = match (event_header.event_length as usize).checked_sub(event_header.header_length as usize) {
+            Some(t) => t,
+            None => {
+                println!("position {} 处的事件长度({})错误", cur_tell, event_header.event_length);
+                process::exit(exitcode::PARSE);
+            }
+        };
+        //event_length可能已损坏, 按实际读取到的数据分配内存
+        let timer = crate::profile::start(crate::profile::Stage::Read);
+        let payload_buf = readvalue::read_bytes(reader, payload);
+        drop(timer);
+        let payload_buf = match payload_buf {
+            Ok(t) => t,
+            Err(_) => {
+                rollback_trac.is_write();
+                break 'all FileEnd::Truncated(cur_tell);
+            }
+        };
         rollback_trac.append_cur_event(&payload_buf);
+        rollback_trac.observe_copy();
+        progress.update(cur_tell + event_header.event_length as u64);
+        if let Err(err) = verifier.verify(&header_buf, &payload_buf, cur_tell) {
+            println!("{}", err);
+            crate::stdout::summary::record_error(&err.to_string());
+            process::exit(exitcode::PARSE);
+        }
+
+        //记录最后一个事件的类型, 用于判断文件结束的原因
+        match event_header.type_code {
+            readevent::BinlogEvent::StopEvent => file_end = FileEnd::Stop,
+            readevent::BinlogEvent::RotateLogEvent => {
+                let v = parsed(readevent::RotateLog::read_event(&event_header, &mut Cursor::new(&payload_buf), version), &event_header);
+                file_end = FileEnd::Rotate(v.binlog_file);
+            }
+            _ => file_end = FileEnd::Eof
+        }
+        if let readevent::BinlogEvent::FormatDescriptionEvent = event_header.type_code {
+            checksum = Some(verify::event_checksum_ok(&header_buf, &payload_buf));
+        }
+        let payload_len = payload_buf.len() as u64;
         let mut cur = Cursor::new(payload_buf);
+
+        if conf.relaylog && event_header.flags.contains(readevent::EventFlags::LOG_EVENT_RELAY_LOG_F) {
+            //relay log中由slave自身生成的事件(FDE、Previous gtids、rotate等)不属于master的数据
+            rollback_trac.delete_cur_event();
+            continue 'all;
+        }
+
         //判断gtid提取情况
         if !grep_info.check_gtid_grep_status(&event_header) {
             continue 'all;
         }
 
         //判断position和datetime过滤情况
-        let (grep_state,success )= grep_info.grep_pos_time(&mut rollback_trac, &event_header);
+        let end_position = cur_tell + event_header.event_length as u64;
+        let (grep_state,success )= grep_info.grep_pos_time(&mut rollback_trac, &event_header, end_position);
         if success{
-            break 'all;
+            break 'all FileEnd::Finished;
         }else if !grep_state {
             continue 'all;
         }
@@ -203,7 +303,7 @@ pub fn readbinlog_fromfile(conf: &Config, version: &u8, reader: &mut BufReader<F
         match event_header.type_code {
             readevent::BinlogEvent::GtidEvent => {
                 rollback_trac.init_traction_buf();
-                let v = readevent::GtidEvent::read_event( &event_header, &mut cur, version);
+                let v = parsed(readevent::GtidEvent::read_event( &event_header, &mut cur, version), &event_header);
 
                 if !grep_info.check_grep_gtid(&v){
                     rollback_trac.delete_cur_event();
@@ -217,46 +317,61 @@ pub fn readbinlog_fromfile(conf: &Config, version: &u8, reader: &mut BufReader<F
                 }
             },
             readevent::BinlogEvent::QueryEvent => {
-                let v = readevent::QueryEvent::read_event( &event_header, &mut cur, version);
+                let v = parsed(readevent::QueryEvent::read_event( &event_header, &mut cur, version), &event_header);
                 if !grep_info.check_grep_threadid(&v, &mut rollback_trac){
                     continue 'all;
                 }
-                if !conf.rollback{
-                    crate::stdout::format_out(&grep_info.grep_thread_id.gtid_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map);
+                rollback_trac.check_ddl(&v.command, cur_tell);
+                if !grep_info.grep_tbl.state {
+                    rollback_trac.check_copy_end(Some(&v.command));
+                }
+                if !rollback_trac.collect(){
+                    crate::stdout::format_out(&grep_info.grep_thread_id.gtid_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map, &mut out_state);
                     data = Traction::QueryEvent(v);
                 }
             },
             readevent::BinlogEvent::TableMapEvent => {
-                let v = readevent::TableMap::read_event( &event_header, &mut cur, version);
-                if !grep_info.check_grep_tbl(&v, &mut rollback_trac, conf, &mut table_cols_info, &db_tbl){
+                let v = parsed(readevent::TableMap::read_event( &event_header, &mut cur, version), &event_header);
+                if !grep_info.check_grep_tbl(&v, &mut rollback_trac, conf, &mut table_cols_info, &db_tbl, &mut out_state){
                     continue 'all;
                 }
                 db_tbl = format!("{}.{}", v.database_name, v.table_name).clone();
-                crate::meta::get_col(conf, &v.database_name, &v.table_name, &mut table_cols_info);
+                //复制模式按原始事件写入, 不需要表结构
+                if !rollback_trac.is_copy() {
+                    crate::meta::get_col(conf, &v.database_name, &v.table_name, &mut table_cols_info);
+                }
                 tabl_map = v.clone();
                 data = Traction::TableMapEvent(v);
             },
             readevent::BinlogEvent::UpdateEvent |
             readevent::BinlogEvent::DeleteEvent |
             readevent::BinlogEvent::WriteEvent => {
-                if conf.rollback{
-                    rollback_trac.rollback_traction.extend(rollback::rollback_row_event(&rollback_trac.cur_event, &event_header, &tabl_map));
-
-                } else if conf.statisc{
+                if rollback_trac.is_copy() {
+                    rollback_trac.rollback_traction.extend(rollback_trac.cur_event.clone());
+                } else if conf.rollback{
+                    let v = parsed(parsevalue::RowValue::read_row_value(&mut cur, &tabl_map, &event_header, &crate::meta::ReadType::File), &event_header);
+                    let rows = match event_header.type_code {
+                        readevent::BinlogEvent::UpdateEvent => v.rows().len() / 2,
+                        _ => v.rows().len()
+                    };
+                    let event = parsed(rollback::rollback_row_event(&rollback_trac.cur_event, &event_header, &tabl_map), &event_header);
+                    rollback_trac.push_rows_event(event, rows);
+
+                } else if conf.statisc && !conf.hotspot{
                     data = Traction::RowEventStatic{type_code: event_header.type_code.clone(),count:event_header.event_length as usize};
                 }else {
                     let read_type = crate::meta::ReadType::File;
-                    let v = parsevalue::RowValue::read_row_value(&mut cur, &tabl_map, &event_header,&read_type);
+                    let v = parsed(parsevalue::RowValue::read_row_value(&mut cur, &tabl_map, &event_header,&read_type), &event_header);
                     data = Traction::RowEvent(event_header.type_code.clone(),v);
                 }
             },
             readevent::BinlogEvent::XidEvent => {
-                if !conf.rollback{
-                    data = Traction::XidEvent(readevent::XidEvent::read_event(&event_header,&mut cur, version));
+                if !rollback_trac.collect(){
+                    data = Traction::XidEvent(parsed(readevent::XidEvent::read_event(&event_header,&mut cur, version), &event_header));
 
                     if grep_info.grep_gtid.start{
-                        crate::stdout::format_out(&data, conf, &mut table_cols_info, &db_tbl, &tabl_map);
-                        break 'all;
+                        crate::stdout::format_out(&data, conf, &mut table_cols_info, &db_tbl, &tabl_map, &mut out_state);
+                        break 'all FileEnd::Finished;
                     }
                 }
 
@@ -266,33 +381,51 @@ pub fn readbinlog_fromfile(conf: &Config, version: &u8, reader: &mut BufReader<F
                     grep_info.grep_thread_id.stop();
                 }
             },
-            readevent::BinlogEvent::XAPREPARELOGEVENT => {},
+            readevent::BinlogEvent::XAPREPARELOGEVENT => {
+                rollback_trac.check_copy_end(None);
+            },
             readevent::BinlogEvent::UNKNOWNEVENT => {
                 rollback_trac.delete_cur_event();
                 continue 'all;
             }
             readevent::BinlogEvent::RotateLogEvent => {
-                data = Traction::RotateLogEvent(readevent::RotateLog::read_event(&event_header, &mut cur, version));
+                data = Traction::RotateLogEvent(parsed(readevent::RotateLog::read_event(&event_header, &mut cur, version), &event_header));
+            }
+            readevent::BinlogEvent::StopEvent => {
+                data = Traction::StopEvent;
+            }
+            readevent::BinlogEvent::TransactionContextEvent => {
+                data = Traction::TransactionContextEvent(parsed(readevent::TransactionContextEvent::read_event(&event_header, &mut cur, version), &event_header));
+            }
+            readevent::BinlogEvent::ViewChangeEvent => {
+                data = Traction::ViewChangeEvent(parsed(readevent::ViewChangeEvent::read_event(&event_header, &mut cur, version), &event_header));
+            }
+            readevent::BinlogEvent::RowsQueryEvent => {
+                if rollback_trac.collect() {
+                    //原始sql不能用于回滚, 也不写入复制的文件
+                    rollback_trac.delete_cur_event();
+                    continue 'all;
+                }
+                data = Traction::RowsQueryEvent(parsed(readevent::RowsQueryEvent::read_event(&event_header, &mut cur, version), &event_header));
             }
             _ => {}
         }
+        //统计、回滚等未解析的事件不检查
+        if cur.position() > 0 {
+            parsed(readevent::check_boundary(&event_header, cur_tell, cur.position(), payload_len, checksum), &event_header);
+        }
 
-        if !conf.rollback{
-            crate::stdout::format_out(&data, conf, &mut table_cols_info, &db_tbl, &tabl_map);
+        if !rollback_trac.collect(){
+            crate::stdout::format_out(&data, conf, &mut table_cols_info, &db_tbl, &tabl_map, &mut out_state);
         }else {
             match event_header.type_code {
                 readevent::BinlogEvent::XidEvent => {
                     rollback_trac.rollback_traction.extend(rollback_trac.cur_event.clone());
-                    let tra_len = rollback_trac.rollback_traction.len();
-                    rollback_trac.events.push(rollback_trac.rollback_traction.clone());
-                    rollback_trac.count += tra_len;
-                    if rollback_trac.check_file_size(){
-                        rollback_trac.update();
-                    }
+                    rollback_trac.push_traction();
 
                     if grep_info.grep_gtid.start{
                         rollback_trac.write_rollback_log();
-                        break 'all;
+                        break 'all FileEnd::Finished;
                     }
                 }
                 readevent::BinlogEvent::WriteEvent|
@@ -301,16 +434,20 @@ pub fn readbinlog_fromfile(conf: &Config, version: &u8, reader: &mut BufReader<F
 
                 }
                 _ => {
-                    rollback_trac.rollback_traction.extend(rollback_trac.cur_event.clone());
+                    rollback_trac.append_traction();
                 }
             }
             rollback_trac.cur_event= vec![];
         }
-    }
+    };
+    progress.finish();
+    out_state.finish();
+    end
 }
 
-//操作binlog数据的入口
-pub fn readbinlog(conn: &mut TcpStream, conf: &Config, version: &u8) {
+#[cfg(feature = "network")]
+//操作binlog数据的入口, 到达--until-gtid/--until-position时返回true, 否则只在mysql返回错误包时返回false
+pub fn readbinlog(conn: &mut TcpStream, conf: &Config, version: &u8) -> bool {
     let mut tabl_map = readevent::TableMap::new();
     let mut table_cols_info: HashMap<String, Vec<HashMap<String, String>>> = HashMap::new();
     let mut db_tbl = String::from("");
@@ -330,7 +467,7 @@ pub fn readbinlog(conn: &mut TcpStream, conf: &Config, version: &u8) {
     match grep_tbl_info {
         CheckGrepStatus::GrepTbl { state } => {
             grep_tbl = true;
-            tbl_info = serde_json::from_str(&conf.greptbl).unwrap();
+            tbl_info = crate::replication::grep::parse_greptbl(&conf.greptbl);
         }
         _ => {}
     }
@@ -340,17 +477,119 @@ pub fn readbinlog(conn: &mut TcpStream, conf: &Config, version: &u8) {
     let mut gtid_traction = Traction::Unknown;
     let mut query_traction = Traction::Unknown;
     let mut check_status = false;
+    let mut out_state = crate::stdout::OutState::new(conf);
+    let mut verifier = ChecksumVerifier::new(ChecksumClass::parse(&conf.verifychecksum).unwrap_or(ChecksumClass::empty()));
+    let mut checksum = None;
+    let controller = control_server(conf);
+    let mut executed = ExecutedLog::new(conf);
+    let mut master = crate::failover::MasterWatch::new();
+    //系统库、改表工具的影子表以及--compat时托管服务的内部表(rds_heartbeat2等)不输出
+    let compat = crate::compat::Profile::from_config(conf);
+    let exclusion = crate::exclude::Exclusion::from_config(conf);
+    let mut internal = false;
+    //在线改表: 切换时提示并重新获取表结构, --osc-remap时影子表按原表名输出
+    let mut osc = crate::osc::Tracker::from_config(conf);
+    //--record: 收到的数据包写入录制文件, 之后可以通过replay子命令复现
+    let recorder = match conf.record.as_str() {
+        "" => None,
+        t => Some(crate::recording::Recorder::create(t, &crate::recording::Start::from_config(conf)).unwrap_or_else(|err|{
+            println!("创建录制文件({})发生错误:{}", t, err);
+            process::exit(exitcode::USAGE);
+        })),
+    };
+    let until = crate::gtid::Until::from_config(conf).unwrap_or_else(|err|{
+        println!("{}", err);
+        process::exit(exitcode::USAGE);
+    });
     'all: loop {
+        //包含边界的事务读取完后停止, 关闭连接
+        if until.as_ref().is_some_and(|u| u.reached(&executed.tracker)) {
+            let state = executed.tracker.executed();
+            eprintln!("到达until的位置, 已执行: {}:{} {}", state.log_file, state.log_pos, state.gtid_set);
+            let _ = conn.shutdown(std::net::Shutdown::Both);
+            out_state.finish();
+            return true;
+        }
+        //控制接口: 事务之间暂停、回退到指定位置重新注册
+        if let Some(position) = controller.as_ref().and_then(|c| c.checkpoint()) {
+            let new_conf = position.apply(conf);
+            if let Some(controller) = &controller {
+                controller.set_connected(false);
+            }
+            *conn = crate::create_conn(&new_conf);
+            replication::start_dump(conn, &new_conf).unwrap_or_else(|err|{
+                println!("回退到{:?}发生错误: {}", position, err);
+                crate::stdout::summary::record_error(&err);
+                process::exit(exitcode::CONNECT);
+            });
+            if let Some(controller) = &controller {
+                controller.set_connected(true);
+            }
+            executed.tracker.restart(&new_conf);
+            tabl_map = readevent::TableMap::new();
+            grep_threadid_info = grep_threadid_info.init();
+            grep_tbl_info = grep_tbl_info.init();
+            continue;
+        }
+        let timer = crate::profile::start(crate::profile::Stage::Read);
         let (buf, _) = socketio::get_packet_from_stream(conn);
+        drop(timer);
+        if let Some(recorder) = &recorder {
+            recorder.record(&buf).unwrap_or_else(|err|{
+                println!("写入录制文件({})发生错误:{}", conf.record, err);
+                process::exit(exitcode::SINK);
+            });
+        }
 
         if !pack::check_pack(&buf){
             let err = pack::erro_pack(&buf);
             println!("注册slave发生错误:{}",err);
-            return;
+            crate::stdout::summary::record_error(&format!("注册slave发生错误:{}",err));
+            if let Some(controller) = &controller {
+                controller.set_connected(false);
+            }
+            return false;
+        }
+        //去掉第一个字节的ok包标识后与文件中的事件相同
+        let buf = pack::event_data(buf);
+        if buf.len() > 19 {
+            let log_pos = (readvalue::read_u32(&buf[13..17]) as u64).saturating_sub(readvalue::read_u32(&buf[9..13]) as u64);
+            if let Err(err) = verifier.verify(&buf[..19], &buf[19..], log_pos) {
+                println!("{}", err);
+                crate::stdout::summary::record_error(&err.to_string());
+                process::exit(exitcode::PARSE);
+            }
+        }
+        if buf.len() > 19 && buf[4] == 15 {
+            checksum = Some(verify::event_checksum_ok(&buf[..19], &buf[19..]));
         }
         let mut cur = Cursor::new(buf);
 
-        let event_header: EventHeader = readevent::InitHeader::new(&mut cur,conf);
+        let event_header = EventHeader::read(&mut cur).unwrap_or_else(|err|{
+            println!("读取事件包头发生错误: {}", err);
+            process::exit(exitcode::PARSE);
+        });
+        //控制接口跳过的事务不输出, 也不改变过滤状态
+        //主库切换检测及已执行位置需要所有事件的事务边界
+        {
+            let traction = control_traction(&event_header, &cur, version);
+            if let Some(switch) = master.observe(&event_header, &traction, &cur.get_ref()[cur.position() as usize..], &executed.tracker) {
+                eprintln!("!!! {}", switch.message());
+                if let Some(controller) = &controller {
+                    controller.switched(&switch, conf.pauseonswitch);
+                }
+            }
+            executed.observe(&traction, event_header.next_position as u64);
+            if let Some(controller) = &controller {
+                if !controller.event(&traction, event_header.timestamp, event_header.next_position as u64) {
+                    continue;
+                }
+            }
+        }
+        crate::stdout::summary::record_header(&event_header);
+        out_state.timestamp = event_header.timestamp;
+        out_state.event_length = event_header.event_length;
+        out_state.log_pos = event_header.next_position.saturating_sub(event_header.event_length) as u64;
         //println!("{:?}", event_header);
         check_status = check_repl_grep_status(&grep_threadid_info, &grep_tbl_info, &event_header);
         if !check_status {
@@ -363,20 +602,27 @@ pub fn readbinlog(conn: &mut TcpStream, conf: &Config, version: &u8) {
                     match grep_threadid_info {
                         CheckGrepStatus::GrepThreadId { state, thread_id} => {
                             //thread_id只存在于query_event， gtid_event在其之前，所以需要临时存储
-                            gtid_traction = Traction::GtidEvent(readevent::GtidEvent::read_event( &event_header, &mut cur, version));
+                            gtid_traction = Traction::GtidEvent(parsed(readevent::GtidEvent::read_event( &event_header, &mut cur, version), &event_header));
                         }
                         _ => {continue;}
                     }
                 }
                 else if grep_tbl {
-                    gtid_traction = Traction::GtidEvent(readevent::GtidEvent::read_event( &event_header, &mut cur, version));
+                    gtid_traction = Traction::GtidEvent(parsed(readevent::GtidEvent::read_event( &event_header, &mut cur, version), &event_header));
                 }
                 else {
-                    data = Traction::GtidEvent(readevent::GtidEvent::read_event( &event_header, &mut cur, version));
+                    data = Traction::GtidEvent(parsed(readevent::GtidEvent::read_event( &event_header, &mut cur, version), &event_header));
                 }
             },
             readevent::BinlogEvent::QueryEvent => {
-                let v = readevent::QueryEvent::read_event( &event_header, &mut cur, version);
+                let v = parsed(readevent::QueryEvent::read_event( &event_header, &mut cur, version), &event_header);
+                for notice in osc.observe(&v.database, &v.command) {
+                    eprintln!("!!! {}", notice.message());
+                    if let crate::osc::Notice::CutOver{ .. } = notice {
+                        let (db, tbl) = notice.table();
+                        table_cols_info.remove(&format!("{}.{}", db, tbl));
+                    }
+                }
                 if grep_threadid{
                     match grep_threadid_info {
                         CheckGrepStatus::GrepThreadId { state, thread_id } => {
@@ -387,7 +633,7 @@ pub fn readbinlog(conn: &mut TcpStream, conf: &Config, version: &u8) {
                                     query_traction = Traction::QueryEvent(v);
                                 }
                                 else {
-                                    crate::stdout::format_out(&gtid_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map);
+                                    crate::stdout::format_out(&gtid_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map, &mut out_state);
                                     data = Traction::QueryEvent(v);
                                 }
                             }
@@ -406,14 +652,24 @@ pub fn readbinlog(conn: &mut TcpStream, conf: &Config, version: &u8) {
 
             },
             readevent::BinlogEvent::TableMapEvent => {
-                let a = readevent::TableMap::read_event( &event_header, &mut cur, version);
+                let mut a = parsed(readevent::TableMap::read_event( &event_header, &mut cur, version), &event_header);
+                //字段信息仍按影子表获取, 输出的表名为原表
+                let shadow = a.table_name.clone();
+                if let Some(table) = osc.remap(&shadow) {
+                    a.table_name = table.to_string();
+                }
+                internal = exclusion.excluded(&a.database_name, &a.table_name)
+                    || compat.is_some_and(|p| p.internal_table(&a.database_name, &a.table_name));
+                if internal {
+                    continue;
+                }
                 match grep_tbl_info {
                     CheckGrepStatus::GrepTbl { state } => {
                         let tbls = &tbl_info[a.database_name.clone()];
                         if tbls == &serde_json::Value::String("all".parse().unwrap()){
                             grep_tbl_info = grep_tbl_info.update();
-                            crate::stdout::format_out(&gtid_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map);
-                            crate::stdout::format_out(&query_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map);
+                            crate::stdout::format_out(&gtid_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map, &mut out_state);
+                            crate::stdout::format_out(&query_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map, &mut out_state);
                         }
                         else {
                             if tbls != &serde_json::Value::Null {
@@ -421,8 +677,8 @@ pub fn readbinlog(conn: &mut TcpStream, conf: &Config, version: &u8) {
                                     if tbls[i] != serde_json::Value::Null {
                                         if tbls[i] == serde_json::Value::String(a.table_name.clone()) {
                                             grep_tbl_info = grep_tbl_info.update();
-                                            crate::stdout::format_out(&gtid_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map);
-                                            crate::stdout::format_out(&query_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map);
+                                            crate::stdout::format_out(&gtid_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map, &mut out_state);
+                                            crate::stdout::format_out(&query_traction, conf, &mut table_cols_info, &db_tbl, &tabl_map, &mut out_state);
                                             break 'inner;
                                         }
                                     }else {
@@ -435,20 +691,26 @@ pub fn readbinlog(conn: &mut TcpStream, conf: &Config, version: &u8) {
                     }
                     _ => {}
                 }
-                db_tbl = format!("{}.{}", a.database_name, a.table_name).clone();
-                crate::meta::get_col(conf, &a.database_name, &a.table_name, &mut table_cols_info);
+                db_tbl = format!("{}.{}", a.database_name, shadow);
+                crate::meta::get_col(conf, &a.database_name, &shadow, &mut table_cols_info);
+                if let (Some(controller), Some(cols)) = (&controller, table_cols_info.get(&db_tbl)) {
+                    controller.set_table(&db_tbl, cols);
+                }
                 tabl_map = a.clone();
                 data = Traction::TableMapEvent(a);
             },
             readevent::BinlogEvent::UpdateEvent |
             readevent::BinlogEvent::DeleteEvent |
             readevent::BinlogEvent::WriteEvent => {
+                if internal {
+                    continue;
+                }
                 let read_type = crate::meta::ReadType::Repl;
-                let v = parsevalue::RowValue::read_row_value(&mut cur, &tabl_map, &event_header,&read_type);
+                let v = parsed(parsevalue::RowValue::read_row_value(&mut cur, &tabl_map, &event_header,&read_type), &event_header);
                 data = Traction::RowEvent(event_header.type_code.clone(),v);
             },
             readevent::BinlogEvent::XidEvent => {
-                data = Traction::XidEvent(readevent::XidEvent::read_event(&event_header,&mut cur, version));
+                data = Traction::XidEvent(parsed(readevent::XidEvent::read_event(&event_header,&mut cur, version), &event_header));
                 if check_status {
                     //重新初始化状态
                     grep_threadid_info = grep_threadid_info.init();
@@ -458,15 +720,140 @@ pub fn readbinlog(conn: &mut TcpStream, conf: &Config, version: &u8) {
             readevent::BinlogEvent::XAPREPARELOGEVENT => {},
             readevent::BinlogEvent::UNKNOWNEVENT => {}
             readevent::BinlogEvent::RotateLogEvent => {
-                data = Traction::RotateLogEvent(readevent::RotateLog::read_event(&event_header, &mut cur, version));
+                data = Traction::RotateLogEvent(parsed(readevent::RotateLog::read_event(&event_header, &mut cur, version), &event_header));
+            }
+            readevent::BinlogEvent::TransactionContextEvent => {
+                data = Traction::TransactionContextEvent(parsed(readevent::TransactionContextEvent::read_event(&event_header, &mut cur, version), &event_header));
+            }
+            readevent::BinlogEvent::ViewChangeEvent => {
+                data = Traction::ViewChangeEvent(parsed(readevent::ViewChangeEvent::read_event(&event_header, &mut cur, version), &event_header));
+            }
+            readevent::BinlogEvent::RowsQueryEvent => {
+                data = Traction::RowsQueryEvent(parsed(readevent::RowsQueryEvent::read_event(&event_header, &mut cur, version), &event_header));
+            }
+            _ => {}
+        }
+        //前19bytes为包头
+        if cur.position() > 19 {
+            let payload_len = (cur.get_ref().len() as u64).saturating_sub(19);
+            parsed(readevent::check_boundary(&event_header, out_state.log_pos, cur.position() - 19, payload_len, checksum), &event_header);
+        }
+
+        crate::stdout::format_out(&data, conf, &mut table_cols_info, &db_tbl, &tabl_map, &mut out_state);
+        crate::stdout::out_watermark(&mut out_state);
+        //输出是同步写入的, 事务结束的事件输出后即确认
+        if let Some(controller) = &controller {
+            controller.checkpointed();
+        }
+        if let readevent::EventType::HeartbeatLogEvent | readevent::EventType::HeartbeatLogEventV2 = event_header.event_type {
+            //源端空闲时mysql按--heartbeat的间隔发送
+            crate::stdout::out_heartbeat(&mut out_state);
+        }
+    }
+}
+
+#[cfg(feature = "network")]
+//--status-interval: 按间隔在stderr输出已执行的gtid集合及对应的binlog文件、position, 事务中间不输出
+//  2019-11-06 00:00:00 UTC executed: SOURCE_LOG_FILE='mysql-bin.000003', SOURCE_LOG_POS=1234, gtid_set='uuid:1-100'
+struct ExecutedLog{
+    tracker: crate::gtid::ExecutedTracker,
+    interval: Option<std::time::Duration>,
+    last: std::time::Instant,
+}
+
+#[cfg(feature = "network")]
+impl ExecutedLog{
+    fn new(conf: &Config) -> ExecutedLog {
+        let interval = match conf.statusinterval.as_str() {
+            "" => None,
+            t => match t.parse::<f64>() {
+                Ok(t) if t > 0.0 => Some(std::time::Duration::from_secs_f64(t)),
+                _ => {
+                    println!("无效的status-interval: {}", t);
+                    process::exit(exitcode::USAGE);
+                }
+            }
+        };
+        ExecutedLog{ tracker: crate::gtid::ExecutedTracker::from_config(conf), interval, last: std::time::Instant::now() }
+    }
+
+    fn observe(&mut self, data: &Traction, next_position: u64) {
+        self.tracker.observe(data, next_position);
+        match self.interval {
+            Some(interval) if self.last.elapsed() >= interval && !self.tracker.in_transaction() => {
+                let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|t| t.as_secs() as u32).unwrap_or(0);
+                let executed = self.tracker.executed();
+                eprintln!("{} UTC executed: SOURCE_LOG_FILE='{}', SOURCE_LOG_POS={}, gtid_set='{}'",
+                          crate::stdout::format::utc_datetime(now), executed.log_file, executed.log_pos, executed.gtid_set);
+                self.last = std::time::Instant::now();
             }
             _ => {}
         }
+    }
+}
 
-        crate::stdout::format_out(&data, conf, &mut table_cols_info, &db_tbl, &tabl_map);
+#[cfg(feature = "network")]
+//配置了--control时启动http控制接口, 配置了--sd-notify时通知systemd
+fn control_server(conf: &Config) -> Option<crate::control::Controller> {
+    if conf.control.is_empty() && !conf.sdnotify {
+        return None;
+    }
+    let stale_after = match conf.healthtimeout.as_str() {
+        "" => 60.0,
+        t => match t.parse::<f64>() {
+            Ok(t) if t > 0.0 => t,
+            _ => {
+                println!("无效的health-timeout: {}", t);
+                process::exit(exitcode::USAGE);
+            }
+        }
+    };
+    //心跳间隔已在注册时检查
+    let idle_after = conf.heartbeat.parse::<f64>().ok().map(|t| std::time::Duration::from_secs_f64(t * 3.0));
+    let controller = crate::control::Controller::new().with_health(std::time::Duration::from_secs_f64(stale_after), idle_after);
+    //进入readbinlog时已完成注册
+    controller.set_connected(true);
+    controller.set_start(conf);
+    if !conf.control.is_empty() {
+        crate::control::serve(&conf.control, controller.clone()).unwrap_or_else(|err|{
+            println!("控制接口监听{}发生错误: {}", conf.control, err);
+            process::exit(exitcode::USAGE);
+        });
     }
+    #[cfg(unix)]
+    if conf.sdnotify {
+        if let Err(err) = crate::control::sd_notify("READY=1") {
+            println!("通知systemd发生错误: {}", err);
+        }
+        crate::control::sd_watchdog(controller.clone());
+    }
+    Some(controller)
+}
+
+#[cfg(feature = "network")]
+//控制接口只需要事务边界及gtid, 其余事件不解析; 过滤thread_id、表时gtid等事件暂存, 在此单独解析
+fn control_traction(header: &EventHeader, cur: &Cursor<Vec<u8>>, version: &u8) -> Traction {
+    let mut cur = cur.clone();
+    let data = match header.type_code {
+        readevent::BinlogEvent::GtidEvent => readevent::GtidEvent::read_event(header, &mut cur, version).map(Traction::GtidEvent),
+        readevent::BinlogEvent::QueryEvent => readevent::QueryEvent::read_event(header, &mut cur, version).map(Traction::QueryEvent),
+        readevent::BinlogEvent::XidEvent => readevent::XidEvent::read_event(header, &mut cur, version).map(Traction::XidEvent),
+        readevent::BinlogEvent::RotateLogEvent => readevent::RotateLog::read_event(header, &mut cur, version).map(Traction::RotateLogEvent),
+        _ => Ok(Traction::Unknown)
+    };
+    data.unwrap_or(Traction::Unknown)
+}
+
+#[cfg(feature = "network")]
+//事件内容解析失败时输出事件类型及位置后退出
+fn parsed<T>(result: io::Result<T>, header: &EventHeader) -> T {
+    result.unwrap_or_else(|err|{
+        println!("解析{:?}(next_position: {})发生错误: {}", header.type_code, header.next_position, err);
+        process::exit(exitcode::PARSE);
+    })
 }
 
+#[cfg(feature = "network")]
 fn check_repl_grep_status(grep_status: &CheckGrepStatus, grep_tbl_info: &CheckGrepStatus, header: &EventHeader) -> bool {
     match grep_status {
         CheckGrepStatus::GrepThreadId { state, thread_id } => {
@@ -2,14 +2,21 @@
 @author: xiao cai niao
 @datetime: 2019/9/25
 */
-use crate::{readvalue, Config};
+use crate::readvalue;
+use crate::warning::WarningKind;
 use std::{io};
 use uuid;
 use uuid::Uuid;
 use std::io::{Read, Seek, SeekFrom, Result};
 use crate::meta::ColumnTypeDict;
+use crate::replication::opaque;
+use crate::replication::tablemeta::{self, TableMetadata};
 use byteorder::{ReadBytesExt, LittleEndian};
 use std::alloc::handle_alloc_error;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 
 pub trait Tell: Seek {
@@ -21,6 +28,110 @@ pub trait Tell: Seek {
 impl<T> Tell for T where T: Seek { }
 
 
+//binlog事件类型码, 覆盖5.x-8.x文档中的所有类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EventType{
+    UnknownEvent = 0,
+    StartEventV3 = 1,
+    QueryEvent = 2,
+    StopEvent = 3,
+    RotateEvent = 4,
+    IntvarEvent = 5,
+    LoadEvent = 6,
+    SlaveEvent = 7,
+    CreateFileEvent = 8,
+    AppendBlockEvent = 9,
+    ExecLoadEvent = 10,
+    DeleteFileEvent = 11,
+    NewLoadEvent = 12,
+    RandEvent = 13,
+    UserVarEvent = 14,
+    FormatDescriptionEvent = 15,
+    XidEvent = 16,
+    BeginLoadQueryEvent = 17,
+    ExecuteLoadQueryEvent = 18,
+    TableMapEvent = 19,
+    PreGaWriteRowsEvent = 20,
+    PreGaUpdateRowsEvent = 21,
+    PreGaDeleteRowsEvent = 22,
+    WriteRowsEventV1 = 23,
+    UpdateRowsEventV1 = 24,
+    DeleteRowsEventV1 = 25,
+    IncidentEvent = 26,
+    HeartbeatLogEvent = 27,
+    IgnorableLogEvent = 28,
+    RowsQueryLogEvent = 29,
+    WriteRowsEvent = 30,
+    UpdateRowsEvent = 31,
+    DeleteRowsEvent = 32,
+    GtidLogEvent = 33,
+    AnonymousGtidLogEvent = 34,
+    PreviousGtidsLogEvent = 35,
+    TransactionContextEvent = 36,
+    ViewChangeEvent = 37,
+    XaPrepareLogEvent = 38,
+    PartialUpdateRowsEvent = 39,
+    TransactionPayloadEvent = 40,
+    HeartbeatLogEventV2 = 41,
+    GtidTaggedLogEvent = 42,
+}
+
+impl TryFrom<u8> for EventType {
+    type Error = u8;
+
+    fn try_from(type_code: u8) -> std::result::Result<EventType, u8> {
+        let event_type = match type_code {
+            0 => EventType::UnknownEvent,
+            1 => EventType::StartEventV3,
+            2 => EventType::QueryEvent,
+            3 => EventType::StopEvent,
+            4 => EventType::RotateEvent,
+            5 => EventType::IntvarEvent,
+            6 => EventType::LoadEvent,
+            7 => EventType::SlaveEvent,
+            8 => EventType::CreateFileEvent,
+            9 => EventType::AppendBlockEvent,
+            10 => EventType::ExecLoadEvent,
+            11 => EventType::DeleteFileEvent,
+            12 => EventType::NewLoadEvent,
+            13 => EventType::RandEvent,
+            14 => EventType::UserVarEvent,
+            15 => EventType::FormatDescriptionEvent,
+            16 => EventType::XidEvent,
+            17 => EventType::BeginLoadQueryEvent,
+            18 => EventType::ExecuteLoadQueryEvent,
+            19 => EventType::TableMapEvent,
+            20 => EventType::PreGaWriteRowsEvent,
+            21 => EventType::PreGaUpdateRowsEvent,
+            22 => EventType::PreGaDeleteRowsEvent,
+            23 => EventType::WriteRowsEventV1,
+            24 => EventType::UpdateRowsEventV1,
+            25 => EventType::DeleteRowsEventV1,
+            26 => EventType::IncidentEvent,
+            27 => EventType::HeartbeatLogEvent,
+            28 => EventType::IgnorableLogEvent,
+            29 => EventType::RowsQueryLogEvent,
+            30 => EventType::WriteRowsEvent,
+            31 => EventType::UpdateRowsEvent,
+            32 => EventType::DeleteRowsEvent,
+            33 => EventType::GtidLogEvent,
+            34 => EventType::AnonymousGtidLogEvent,
+            35 => EventType::PreviousGtidsLogEvent,
+            36 => EventType::TransactionContextEvent,
+            37 => EventType::ViewChangeEvent,
+            38 => EventType::XaPrepareLogEvent,
+            39 => EventType::PartialUpdateRowsEvent,
+            40 => EventType::TransactionPayloadEvent,
+            41 => EventType::HeartbeatLogEventV2,
+            42 => EventType::GtidTaggedLogEvent,
+            _ => return Err(type_code)
+        };
+        Ok(event_type)
+    }
+}
+
+//按处理方式对EventType进行的分类, 未单独处理的类型均归为UNKNOWNEVENT
 #[derive(Debug, Clone)]
 pub enum BinlogEvent{
     QueryEvent,
@@ -35,17 +146,63 @@ pub enum BinlogEvent{
     FormatDescriptionEvent,
     UNKNOWNEVENT,
     PreviousGtidsLogEvent,
-    CreateFileEvent
+    CreateFileEvent,
+    StopEvent,
+    TransactionContextEvent,
+    ViewChangeEvent,
+    RowsQueryEvent
 }
 
-pub trait InitHeader{
-    fn new<R: Read+Seek>(buf: &mut R, conf: &Config) -> Self;
+//事件内容只在payload范围内读取, 数据不完整或格式错误时返回io::Error, 不会panic
+pub trait InitValue{
+    fn read_event<R: Read+Seek>(header: &EventHeader, buf: &mut R, version: &u8) -> io::Result<Self> where Self: Sized;
 }
 
-pub trait InitValue{
-    fn read_event<R: Read+Seek>(header: &EventHeader, buf: &mut R, version: &u8) -> Self;
+
+bitflags! {
+    //binlog包头中的flags部分
+    pub struct EventFlags: u16 {
+        const LOG_EVENT_BINLOG_IN_USE_F = 0x1;
+        const LOG_EVENT_FORCED_ROTATE_F = 0x2;
+        const LOG_EVENT_THREAD_SPECIFIC_F = 0x4;
+        const LOG_EVENT_SUPPRESS_USE_F = 0x8;
+        const LOG_EVENT_UPDATE_TABLE_MAP_VERSION_F = 0x10;
+        const LOG_EVENT_ARTIFICIAL_F = 0x20;
+        const LOG_EVENT_RELAY_LOG_F = 0x40;
+        const LOG_EVENT_IGNORABLE_F = 0x80;
+        const LOG_EVENT_NO_FILTER_F = 0x100;
+        const LOG_EVENT_MTS_ISOLATE_F = 0x200;
+    }
 }
 
+//未知类型的事件按event_length跳过, 内容保留在UnknownEvent中, 每个事件记录一条警告
+static UNKNOWN_EVENTS: AtomicU64 = AtomicU64::new(0);
+static UNKNOWN_TYPES: Mutex<BTreeSet<u8>> = Mutex::new(BTreeSet::new());
+
+fn unknown_event(code: u8) -> io::Result<()> {
+    UNKNOWN_EVENTS.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut t) = UNKNOWN_TYPES.lock() {
+        t.insert(code);
+    }
+    readvalue::mismatch(WarningKind::UnknownEvent, format!("未知的事件类型: {}, 按event_length跳过", code))
+}
+
+/// 进程中读取到的未知类型(新版本mysql增加的类型)的事件数
+pub fn unknown_event_count() -> u64 {
+    UNKNOWN_EVENTS.load(Ordering::Relaxed)
+}
+
+/// 读取到的未知类型码
+pub fn unknown_event_types() -> Vec<u8> {
+    UNKNOWN_TYPES.lock().map(|t| t.iter().cloned().collect()).unwrap_or_default()
+}
+
+/// 未知类型的事件, bytes为不含包头的原始内容, 开启checksum时不含末尾4bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownEvent{
+    pub type_code: u8,
+    pub bytes: Vec<u8>,
+}
 
 /*
 binlog包头部分
@@ -61,55 +218,130 @@ binlog包头部分
 pub struct EventHeader{
     //19bytes 包头部分
     pub timestamp: u32,
+    pub event_type: EventType,
     pub type_code: BinlogEvent,
     pub server_id: u32,
     pub event_length: u32,
     pub next_position: u32,
-    pub flags: u16,
+    pub flags: EventFlags,
     pub header_length: u8,
+    //包头中的类型码, 新版本mysql增加的类型event_type为UnknownEvent
+    pub raw_type: u8,
 }
 
-impl InitHeader for EventHeader {
-    fn new<R: Read + Seek>(buf: &mut R, conf: &Config) -> EventHeader{
-        let mut header_length: u8 = 19;
-        if conf.runtype == String::from("repl"){
-            //如果是模拟slave同步会多1字节的头部分
-            buf.seek(io::SeekFrom::Current(1)).unwrap();
-            header_length += 1;
-        }
-        let timestamp = buf.read_u32::<LittleEndian>().unwrap();
-        let type_code = Self::get_type_code_event(&Some(buf.read_u8().unwrap() as u8));
-        let server_id = buf.read_u32::<LittleEndian>().unwrap();
-        let event_length = buf.read_u32::<LittleEndian>().unwrap();
-        let next_position = buf.read_u32::<LittleEndian>().unwrap();
-        let flags = buf.read_u16::<LittleEndian>().unwrap();
-        EventHeader{
+impl EventHeader{
+    /// 读取19bytes的事件包头, 从连接读取时由调用方先去掉ok包的标识(见io::pack::event_data), 文件与网络的格式相同
+    pub fn read<R: Read>(buf: &mut R) -> io::Result<EventHeader>{
+        let header_length: u8 = 19;
+        let timestamp = buf.read_u32::<LittleEndian>()?;
+        let raw_type = buf.read_u8()?;
+        let event_type = match EventType::try_from(raw_type) {
+            Ok(t) => t,
+            Err(code) => {
+                unknown_event(code)?;
+                EventType::UnknownEvent
+            }
+        };
+        let type_code = BinlogEvent::from(event_type);
+        let server_id = buf.read_u32::<LittleEndian>()?;
+        let event_length = buf.read_u32::<LittleEndian>()?;
+        let next_position = buf.read_u32::<LittleEndian>()?;
+        let flags = EventFlags::from_bits_truncate(buf.read_u16::<LittleEndian>()?);
+        Ok(EventHeader{
             timestamp,
+            event_type,
             type_code,
             server_id,
             event_length,
             next_position,
             flags,
-            header_length
-        }
+            header_length,
+            raw_type
+        })
+    }
+
+    //dump开始时master发送的rotate以及relay log中由slave生成的事件都带有该标签, timestamp为0
+    pub fn is_artificial(&self) -> bool {
+        self.flags.contains(EventFlags::LOG_EVENT_ARTIFICIAL_F)
+    }
+
+    //19bytes的事件包头, 用于计算checksum
+    pub fn encode(&self) -> Vec<u8> {
+        let mut header = vec![];
+        header.extend(readvalue::write_u32(self.timestamp));
+        header.push(self.raw_type);
+        header.extend(readvalue::write_u32(self.server_id));
+        header.extend(readvalue::write_u32(self.event_length));
+        header.extend(readvalue::write_u32(self.next_position));
+        header.extend(readvalue::write_u16(self.flags.bits()));
+        header
     }
 }
 
-impl EventHeader{
-    fn get_type_code_event(type_code: &Option<u8>) -> BinlogEvent{
-        match type_code {
-            Some(4) => BinlogEvent::RotateLogEvent,
-            Some(2) => BinlogEvent::QueryEvent,
-            Some(33) => BinlogEvent::GtidEvent,
-            Some(19) => BinlogEvent::TableMapEvent,
-            Some(30) => BinlogEvent::WriteEvent,
-            Some(31) => BinlogEvent::UpdateEvent,
-            Some(32) => BinlogEvent::DeleteEvent,
-            Some(16) => BinlogEvent::XidEvent,
-            Some(38) => BinlogEvent::XAPREPARELOGEVENT,
-            Some(15) => BinlogEvent::FormatDescriptionEvent,
-            Some(35) => BinlogEvent::PreviousGtidsLogEvent,
-            Some(8) => BinlogEvent::CreateFileEvent,
+//事件数据(不含包头)的最后4bytes是否为checksum, 按crc32判断
+fn has_checksum(header: &EventHeader, body: &[u8]) -> bool {
+    if body.len() < 4 {
+        return false;
+    }
+    let mut event = header.encode();
+    event.extend_from_slice(&body[..body.len() - 4]);
+    readvalue::crc32(&event) == readvalue::read_u32(&body[body.len() - 4..])
+}
+
+/// 事件解析结束的位置是否正好在事件数据的末尾(启用checksum时为最后4bytes之前), 解码错误时通常会提前结束或越过末尾
+/// consumed为已解析的事件数据(不含包头)长度, 不一致时按解析模式返回错误或输出警告
+/// checksum为None表示没有读取到format_description_event(从文件中间开始读取), 两种长度都可以
+/// gtid(8.0增加的部分)、format_description等只解析前一部分的事件不检查,
+/// artificial事件(dump开始时的rotate)在format_description之前, 无法确定是否带有checksum, 同样不检查
+pub fn check_boundary(header: &EventHeader, log_pos: u64, consumed: u64, payload_len: u64, checksum: Option<bool>) -> io::Result<()> {
+    if header.is_artificial() {
+        return Ok(());
+    }
+    //读取到末尾的事件自行去掉了checksum
+    let to_end = match header.type_code {
+        BinlogEvent::QueryEvent |
+        BinlogEvent::RowsQueryEvent |
+        BinlogEvent::TableMapEvent |
+        BinlogEvent::RotateLogEvent => true,
+        BinlogEvent::WriteEvent |
+        BinlogEvent::UpdateEvent |
+        BinlogEvent::DeleteEvent |
+        BinlogEvent::XidEvent |
+        BinlogEvent::ViewChangeEvent => false,
+        _ => return Ok(())
+    };
+    let expected = if checksum != Some(false) { payload_len.saturating_sub(4) } else { payload_len };
+    if consumed == expected || ((to_end || checksum.is_none()) && consumed == payload_len) {
+        return Ok(());
+    }
+    let diff = if consumed < expected {
+        format!("剩余{}bytes未解析", expected - consumed)
+    } else {
+        format!("超出{}bytes", consumed - expected)
+    };
+    readvalue::mismatch(WarningKind::TrailingData, format!("position {} 处的{:?}解析了{}bytes, 事件数据为{}bytes{}, {}",
+                                log_pos, header.type_code, consumed, expected, if checksum != Some(false) { "(不含checksum)" } else { "" }, diff))
+}
+
+impl From<EventType> for BinlogEvent {
+    fn from(event_type: EventType) -> BinlogEvent {
+        match event_type {
+            EventType::RotateEvent => BinlogEvent::RotateLogEvent,
+            EventType::QueryEvent => BinlogEvent::QueryEvent,
+            EventType::StopEvent => BinlogEvent::StopEvent,
+            EventType::GtidLogEvent => BinlogEvent::GtidEvent,
+            EventType::TableMapEvent => BinlogEvent::TableMapEvent,
+            EventType::WriteRowsEvent => BinlogEvent::WriteEvent,
+            EventType::UpdateRowsEvent => BinlogEvent::UpdateEvent,
+            EventType::DeleteRowsEvent => BinlogEvent::DeleteEvent,
+            EventType::XidEvent => BinlogEvent::XidEvent,
+            EventType::XaPrepareLogEvent => BinlogEvent::XAPREPARELOGEVENT,
+            EventType::FormatDescriptionEvent => BinlogEvent::FormatDescriptionEvent,
+            EventType::PreviousGtidsLogEvent => BinlogEvent::PreviousGtidsLogEvent,
+            EventType::CreateFileEvent => BinlogEvent::CreateFileEvent,
+            EventType::TransactionContextEvent => BinlogEvent::TransactionContextEvent,
+            EventType::ViewChangeEvent => BinlogEvent::ViewChangeEvent,
+            EventType::RowsQueryLogEvent => BinlogEvent::RowsQueryEvent,
             _ => BinlogEvent::UNKNOWNEVENT
         }
     }
@@ -127,27 +359,32 @@ query_event:
         variable_block_length = fix_part.variable_block_length
         database_name = fix_part.database_length
         sql_statement = event_header.event_length - 19 - 13 - variable_block_length - database_length - 4
+    variable_block中为status_vars, 每项为1byte的类型码加上对应长度的值, 只解析其中的Q_INVOKER
 */
 #[derive(Debug, Clone)]
 pub struct QueryEvent{
     pub thread_id: u32,
     pub execute_seconds: u32,
     pub database: String,
-    pub command: String
+    pub command: String,
+    pub invoker: String,            //Q_INVOKER中的user@host, 只有存储过程、视图、授权等语句才会记录
 }
 
 impl InitValue for QueryEvent{
-    fn read_event<R: Read+Seek>(header: &EventHeader, buf: &mut R, version: &u8) -> QueryEvent{
-        let thread_id = buf.read_u32::<LittleEndian>().unwrap();
-        let execute_seconds = buf.read_u32::<LittleEndian>().unwrap();
-        let database_length = buf.read_u8().unwrap();
-        let _error_code = buf.read_u16::<LittleEndian>().unwrap();
-        let variable_block_length = buf.read_u16::<LittleEndian>().unwrap();
-        buf.seek(io::SeekFrom::Current(variable_block_length as i64)).unwrap();
+    fn read_event<R: Read+Seek>(header: &EventHeader, buf: &mut R, version: &u8) -> io::Result<QueryEvent>{
+        let start = buf.tell()?;
+        let thread_id = buf.read_u32::<LittleEndian>()?;
+        let execute_seconds = buf.read_u32::<LittleEndian>()?;
+        let database_length = buf.read_u8()?;
+        let _error_code = buf.read_u16::<LittleEndian>()?;
+        let variable_block_length = buf.read_u16::<LittleEndian>()?;
+        let mut status_vars = vec![0u8; variable_block_length as usize];
+        buf.read_exact(&mut status_vars)?;
+        let invoker = read_invoker(&status_vars);
         let mut database_pack = vec![0u8; database_length as usize];
-        buf.read_exact(&mut database_pack).unwrap();
-        let database = readvalue::read_string_value(&database_pack);
-        buf.seek(io::SeekFrom::Current(1)).unwrap();
+        buf.read_exact(&mut database_pack)?;
+        let database = crate::ident::decode(&database_pack);
+        buf.seek(io::SeekFrom::Current(1))?;
 
         let mut command_pak = vec![];
         let mut command = String::from("");
@@ -155,23 +392,111 @@ impl InitValue for QueryEvent{
 //        command_pak = vec![0u8; command_length];
 //        buf.read_exact(&mut command_pak).unwrap();
 //        command = readvalue::read_string_value(&command_pak);
+        //sql为剩余部分, 启用checksum时去掉最后4bytes
+        let consumed = (buf.tell()? - start) as usize;
+        buf.seek(SeekFrom::Start(start))?;
+        let mut body = vec![];
+        buf.read_to_end(&mut body)?;
+        if body.len() < consumed {
+            return Err(readvalue::invalid_data(format!("query_event长度({})错误", header.event_length)));
+        }
+        if has_checksum(header, &body[..]) {
+            body.truncate(body.len() - 4);
+        }
+        command_pak.extend_from_slice(&body[consumed..]);
         if *version == 5 {
-            let command_length = header.event_length as usize - header.header_length as usize - buf.tell().unwrap() as usize - 4;
-            command_pak = vec![0u8; command_length];
-            buf.read_exact(&mut command_pak).unwrap();
             command = readvalue::read_string_value(&command_pak);
         }else {
-            buf.read_to_end(&mut command_pak).unwrap();
             command = readvalue::read_string_lossy_value(&command_pak, version);
         }
 
-        QueryEvent{
+        Ok(QueryEvent{
             thread_id,
             execute_seconds,
             database,
-            command
-        }
+            command,
+            invoker
+        })
+
+    }
+}
+
+/*
+status_vars:
+    类型码: 1bytes, 之后为该类型的值, 长度由类型决定
+    Q_INVOKER(11): user_length(1bytes) + user + host_length(1bytes) + host
+遇到未知的类型码时无法确定后续数据的位置, 直接结束解析
+*/
+fn read_invoker(status_vars: &[u8]) -> String {
+    let mut offset = 0;
+    let read_str = |offset: usize| -> Option<(String, usize)> {
+        let len = *status_vars.get(offset)? as usize;
+        let value = status_vars.get(offset + 1..offset + 1 + len)?;
+        Some((String::from_utf8_lossy(value).to_string(), offset + 1 + len))
+    };
+    while offset < status_vars.len() {
+        let code = status_vars[offset];
+        offset += 1;
+        offset += match code {
+            0 | 3 | 10 => 4,                        //Q_FLAGS2_CODE, Q_AUTO_INCREMENT, Q_MASTER_DATA_WRITTEN_CODE
+            1 | 9 | 14 | 17 | 22 => 8,              //Q_SQL_MODE_CODE, Q_TABLE_MAP_FOR_UPDATE_CODE, Q_COMMIT_TS, Q_DDL_LOGGED_WITH_XID, Q_XID
+            4 => 6,                                 //Q_CHARSET_CODE
+            7 | 8 | 18 => 2,                        //Q_LC_TIME_NAMES_CODE, Q_CHARSET_DATABASE_CODE, Q_DEFAULT_COLLATION_FOR_UTF8MB4
+            13 | 21 => 3,                           //Q_MICROSECONDS, Q_HRNOW
+            16 | 19 | 20 => 1,                      //Q_EXPLICIT_DEFAULTS_FOR_TIMESTAMP, Q_SQL_REQUIRE_PRIMARY_KEY, Q_DEFAULT_TABLE_ENCRYPTION
+            2 => {                                  //Q_CATALOG_CODE: 长度 + 内容 + 0x00
+                match status_vars.get(offset) { Some(len) => *len as usize + 2, None => break }
+            }
+            5 | 6 => {                              //Q_TIME_ZONE_CODE, Q_CATALOG_NZ_CODE
+                match status_vars.get(offset) { Some(len) => *len as usize + 1, None => break }
+            }
+            11 => {
+                return match read_str(offset).and_then(|(user, next)| read_str(next).map(|(host, _)| (user, host))) {
+                    Some((user, host)) => format!("{}@{}", user, host),
+                    None => String::from("")
+                };
+            }
+            12 => {                                 //Q_UPDATED_DB_NAMES: 数量 + 以0x00结尾的库名
+                let count = match status_vars.get(offset) { Some(t) => *t, None => break };
+                let mut end = offset + 1;
+                if count != 254 {
+                    for _ in 0..count {
+                        while end < status_vars.len() && status_vars[end] != 0 {
+                            end += 1;
+                        }
+                        end += 1;
+                    }
+                }
+                end - offset
+            }
+            _ => break
+        };
+    }
+    String::from("")
+}
 
+/*
+rows_query_log_event: 开启binlog_rows_query_log_events时在行事件之前记录原始sql
+    length : 1bytes, 只保存了sql长度的低8位, 不能直接使用
+    query : 剩余部分, 启用checksum时最后4bytes为crc32
+*/
+#[derive(Debug, Clone)]
+pub struct RowsQueryEvent{
+    pub query: String
+}
+
+impl InitValue for RowsQueryEvent{
+    fn read_event<R: Read+Seek>(_header: &EventHeader, buf: &mut R, _version: &u8) -> io::Result<RowsQueryEvent>{
+        let length = buf.read_u8()? as usize;
+        let mut query_pack = vec![];
+        buf.read_to_end(&mut query_pack)?;
+        //通过长度的低8位判断末尾是否带有checksum
+        if query_pack.len() >= 4 && query_pack.len() & 0xff != length && (query_pack.len() - 4) & 0xff == length {
+            query_pack.truncate(query_pack.len() - 4);
+        }
+        Ok(RowsQueryEvent{
+            query: String::from_utf8_lossy(&query_pack).to_string()
+        })
     }
 }
 
@@ -181,38 +506,43 @@ pub struct XidEvent{
 }
 
 impl InitValue for XidEvent{
-    fn read_event<R: Read>(_header: &EventHeader, buf: &mut R, _version: &u8) -> XidEvent{
-        let xid = buf.read_u64::<LittleEndian>().unwrap();
-        XidEvent{
+    fn read_event<R: Read>(_header: &EventHeader, buf: &mut R, _version: &u8) -> io::Result<XidEvent>{
+        let xid = buf.read_u64::<LittleEndian>()?;
+        Ok(XidEvent{
             xid
-        }
+        })
     }
 }
 
 /*
 rotate_log_event:
     Fixed data part: 8bytes
+        position : 8bytes
     Variable data part: event_length - header_length - fixed_length (string<EOF>)
 */
 #[derive(Debug, Clone)]
 pub struct RotateLog{
-    pub binlog_file: String
+    pub binlog_file: String,
+    pub position: u64,
+    pub artificial: bool
 }
 
 impl InitValue for RotateLog{
-    fn read_event<R: Read+Seek>(header: &EventHeader, buf: &mut R, version: &u8) -> RotateLog{
+    fn read_event<R: Read+Seek>(header: &EventHeader, buf: &mut R, version: &u8) -> io::Result<RotateLog>{
         let mut offset = 8 as usize;
         if version == &5{
             offset += 4;
         }
-        buf.seek(io::SeekFrom::Current(8)).unwrap();
-        let len_gg = header.event_length as usize - header.header_length as usize - offset;
-        let mut tmp_buf = vec![0u8; len_gg];
-        buf.read_exact(&mut tmp_buf).unwrap();
+        let position = buf.read_u64::<LittleEndian>()?;
+        let len_gg = (header.event_length as usize).checked_sub(header.header_length as usize + offset)
+            .ok_or_else(|| readvalue::invalid_data(format!("rotate_event长度({})错误", header.event_length)))?;
+        let tmp_buf = readvalue::read_bytes(buf, len_gg)?;
         let binlog_file = String::from_utf8_lossy(&tmp_buf).to_string();
-        RotateLog{
-            binlog_file
-        }
+        Ok(RotateLog{
+            binlog_file,
+            position,
+            artificial: header.is_artificial()
+        })
     }
 }
 
@@ -228,10 +558,11 @@ table_map_event:
         table_name : table_name_length bytes + 1
         cloums_count : 1bytes
         colums_type_array : one byte per column
-        mmetadata_lenth : 1bytes
+        mmetadata_lenth : packed integer
         metadata : .....(only available in the variable length field，varchar:2bytes，text、blob:1bytes,time、timestamp、datetime: 1bytes
                         blob、float、decimal : 1bytes, char、enum、binary、set: 2bytes(column type id :1bytes metadatea: 1bytes))
-        bit_filed : 1bytes
+        null_bitmap : (cloums_count + 7) / 8 bytes
+        optional_metadata : 8.0.1之后, 见tablemeta
         crc : 4bytes
         .........
 */
@@ -247,6 +578,7 @@ pub struct TableMap{
     pub table_name: String,
     pub column_count: u8,
     pub column_info: Vec<ColumnInfo>,
+    pub metadata: Option<TableMetadata>,
 }
 impl TableMap{
     pub fn new() -> TableMap {
@@ -254,141 +586,233 @@ impl TableMap{
             database_name: "".to_string(),
             table_name: "".to_string(),
             column_count: 0,
-            column_info: vec![]
+            column_info: vec![],
+            metadata: None
         }
     }
 
-    fn read_column_meta<R: Read>(buf: &mut R,col_type: &u8) -> (Vec<usize>, u8) {
+    fn read_column_meta<R: Read>(buf: &mut R,col_type: &u8) -> io::Result<(Vec<usize>, u8)> {
         let mut value: Vec<usize> = vec![];
         //let mut offset = offset;
         let mut col_type = col_type.clone();
         let column_type_info = ColumnTypeDict::from_type_code(&col_type);
         match column_type_info {
             ColumnTypeDict::MysqlTypeVarString => {
-                value = Self::read_string_meta(buf);
+                value = Self::read_string_meta(buf)?;
             }
             ColumnTypeDict::MysqlTypeVarchar => {
-                value = Self::read_string_meta(buf);
+                value = Self::read_string_meta(buf)?;
             }
             ColumnTypeDict::MysqlTypeBlob => {
-                value = Self::read_one_bytes(buf);
+                value = Self::read_one_bytes(buf)?;
             }
             ColumnTypeDict::MysqlTypeMediumBlob => {
-                value = Self::read_one_bytes(buf);
+                value = Self::read_one_bytes(buf)?;
             }
             ColumnTypeDict::MysqlTypeLongBlob => {
-                value = Self::read_one_bytes(buf);
+                value = Self::read_one_bytes(buf)?;
             }
             ColumnTypeDict::MysqlTypeTinyBlob => {
-                value = Self::read_one_bytes(buf);
+                value = Self::read_one_bytes(buf)?;
             }
             ColumnTypeDict::MysqlTypeJson => {
-                value = Self::read_one_bytes(buf);
+                value = Self::read_one_bytes(buf)?;
+            }
+            ColumnTypeDict::MysqlTypeGeometry => {
+                value = Self::read_one_bytes(buf)?;
             }
             ColumnTypeDict::MysqlTypeTimestamp2 => {
-                value = Self::read_one_bytes(buf);
+                value = Self::read_one_bytes(buf)?;
             }
             ColumnTypeDict::MysqlTypeDatetime2 => {
-                value = Self::read_one_bytes(buf);
+                value = Self::read_one_bytes(buf)?;
             }
             ColumnTypeDict::MysqlTypeTime2 => {
                 //value = vec![buf[offset] as usize];
                 //offset += 1;
-                value = Self::read_one_bytes(buf);
+                value = Self::read_one_bytes(buf)?;
             }
             ColumnTypeDict::MysqlTypeNewdecimal => {
-                value.extend(Self::read_newdecimal(buf).to_owned().to_vec());
+                value.extend(Self::read_newdecimal(buf)?.to_owned().to_vec());
             }
             ColumnTypeDict::MysqlTypeFloat => {
-                value = Self::read_one_bytes(buf);
+                value = Self::read_one_bytes(buf)?;
             }
             ColumnTypeDict::MysqlTypeDouble => {
-                value = Self::read_one_bytes(buf);
+                value = Self::read_one_bytes(buf)?;
             }
-            ColumnTypeDict::MysqlTypeString => {
-                let (a, b) = Self::read_string_type(buf);
+            //char、enum、set通常都记录为string, 直接记录为enum、set时metadata同样为实际类型及占用的字节数
+            ColumnTypeDict::MysqlTypeString |
+            ColumnTypeDict::MysqlTypeEnum |
+            ColumnTypeDict::MysqlTypeSet => {
+                let (a, b) = Self::read_string_type(buf)?;
                 value = a;
                 col_type = b;
             }
+            ColumnTypeDict::UnknowType(code) => {
+                value = opaque::read_meta(buf, code)?;
+            }
             _ => {
                 value = vec![0];
             }
         }
-        return (value, col_type);
+        return Ok((value, col_type));
+    }
+
+    //null_bitmap之后的可选metadata, 没有或解析失败时为None
+    //事件是否带有checksum按末尾4bytes的crc32判断
+    fn read_optional_metadata<R: Read+Seek>(header: &EventHeader, buf: &mut R, start: u64, types: &[u8], metadata_pack: &[u8], column_info: &[ColumnInfo]) -> Option<TableMetadata> {
+        let consumed = (buf.tell().ok()? - start) as usize;
+        buf.seek(SeekFrom::Start(start)).ok()?;
+        let mut body = vec![];
+        buf.take((header.event_length as u64).saturating_sub(19)).read_to_end(&mut body).ok()?;
+        if body.len() >= consumed + 4 && has_checksum(header, &body) {
+            body.truncate(body.len() - 4);
+        }
+        let null_length = column_info.len().div_ceil(8);
+        let rest = body.get(consumed..)?;
+        if rest.len() <= null_length {
+            return None;
+        }
+        let lengths = tablemeta::column_lengths(types, metadata_pack).ok()?;
+        TableMetadata::read(column_info, &lengths, &rest[..null_length], &rest[null_length..]).ok()
     }
 
-    fn read_one_bytes<R: Read>(buf: &mut R) -> Vec<usize> {
-        let v = buf.read_u8().unwrap() as usize;
-        vec![v]
+    fn read_one_bytes<R: Read>(buf: &mut R) -> io::Result<Vec<usize>> {
+        let v = buf.read_u8()? as usize;
+        Ok(vec![v])
     }
 
 
-    fn read_string_meta<R: Read>(buf: &mut R) -> Vec<usize> {
-        let metadata = buf.read_u16::<LittleEndian>().unwrap();
+    fn read_string_meta<R: Read>(buf: &mut R) -> io::Result<Vec<usize>> {
+        let metadata = buf.read_u16::<LittleEndian>()?;
         let mut v = vec![];
         if metadata > 255 {
             v.push(2);
         }else {
             v.push(1);
         }
-        v
+        Ok(v)
     }
 
-    fn read_newdecimal<R: Read>(buf: &mut R) -> [usize;2] {
-        let precision = buf.read_u8().unwrap() as usize;
-        let decimals = buf.read_u8().unwrap() as usize;
-        [precision,decimals]
+    fn read_newdecimal<R: Read>(buf: &mut R) -> io::Result<[usize;2]> {
+        let precision = buf.read_u8()? as usize;
+        let decimals = buf.read_u8()? as usize;
+        Ok([precision,decimals])
     }
 
-    fn read_string_type<R: Read>(buf: &mut R) -> (Vec<usize>, u8) {
-        let _type = buf.read_u8().unwrap();
-        let code = ColumnTypeDict::from_type_code(&_type);
-
-        let metadata = buf.read_u8().unwrap() as usize;
-        match code {
-            ColumnTypeDict::MysqlTypeEnum |
-            ColumnTypeDict::MysqlTypeSet |
-            ColumnTypeDict::MysqlTypeString => {
-                return (vec! [metadata], _type);
-            }
-            ColumnTypeDict::UnknowType => {
-                return (vec! [65535], 254);
-            }
-            _ => {
-                return (vec! [65535], 254);
+    fn read_string_type<R: Read>(buf: &mut R) -> io::Result<(Vec<usize>, u8)> {
+        let _type = buf.read_u8()?;
+        let metadata = buf.read_u8()?;
+        match string_meta(_type, metadata) {
+            Some((code, length)) => Ok((vec![length], code)),
+            None => {
+                //无法确定长度时按2bytes长度的char读取
+                readvalue::mismatch(WarningKind::Metadata, format!("string类型的metadata错误: [{}, {}]", _type, metadata))?;
+                Ok((vec![65535], 254))
             }
         }
     }
 
 }
 
+/// MYSQL_TYPE_STRING的metadata: 第一个字节为实际的类型(string、enum、set), 第二个字节为长度,
+/// 长度大于255时(如utf8mb4的char(64))长度的高2位取反后存放在第一个字节的0x30位
+/// 返回实际的类型及长度, 类型不是string、enum、set时返回None
+pub fn string_meta(real_type: u8, length: u8) -> Option<(u8, usize)> {
+    let (real_type, length) = if real_type & 0x30 != 0x30 {
+        (real_type | 0x30, ((((real_type & 0x30) ^ 0x30) as usize) << 4) | length as usize)
+    } else {
+        (real_type, length as usize)
+    };
+    match ColumnTypeDict::from_type_code(&real_type) {
+        ColumnTypeDict::MysqlTypeEnum |
+        ColumnTypeDict::MysqlTypeSet |
+        ColumnTypeDict::MysqlTypeString => Some((real_type, length)),
+        _ => None
+    }
+}
+
 impl InitValue for TableMap{
-    fn read_event<R: Read+Seek>( _header: &EventHeader,buf: &mut R, _version: &u8) -> TableMap{
-        buf.seek(io::SeekFrom::Current(8)).unwrap();
-        let database_length = buf.read_u8().unwrap() as usize;
-        let database_name = readvalue::read_string_value_from_len(buf, database_length);
-        buf.seek(io::SeekFrom::Current(1)).unwrap();
-        let table_length = buf.read_u8().unwrap() as usize;
-        let table_name = readvalue::read_string_value_from_len(buf, table_length);
-        buf.seek(io::SeekFrom::Current(1)).unwrap();
-
-        let column_count = buf.read_u8().unwrap();
+    fn read_event<R: Read+Seek>( header: &EventHeader,buf: &mut R, _version: &u8) -> io::Result<TableMap>{
+        let start = buf.tell()?;
+        buf.seek(io::SeekFrom::Current(8))?;
+        let database_length = buf.read_u8()? as usize;
+        let database_name = crate::ident::decode(&readvalue::read_num_pack(database_length, buf)?);
+        buf.seek(io::SeekFrom::Current(1))?;
+        let table_length = buf.read_u8()? as usize;
+        let table_name = crate::ident::decode(&readvalue::read_num_pack(table_length, buf)?);
+        buf.seek(io::SeekFrom::Current(1))?;
+
+        let column_count = buf.read_u8()?;
         let mut column_info: Vec<ColumnInfo> = vec![];
         let mut column_type_list = vec![0u8; column_count as usize];
-        buf.read_exact(&mut column_type_list).unwrap();
-        buf.seek(io::SeekFrom::Current(1)).unwrap(); //跳过mmetadata_lenth,直接用字段数据进行判断
+        buf.read_exact(&mut column_type_list)?;
+        let metadata_length = match buf.read_u8()? {
+            0xfc => buf.read_u16::<LittleEndian>()? as usize,
+            t => t as usize,
+        };
+        let metadata_pack = readvalue::read_bytes(buf, metadata_length)?;
+        let mut meta_cur = io::Cursor::new(&metadata_pack);
         for col_type in column_type_list.iter() {
-            let (col_meta, col_type) = Self::read_column_meta(buf, col_type);
+            let (col_meta, col_type) = Self::read_column_meta(&mut meta_cur, col_type)?;
             column_info.push(ColumnInfo{column_type: ColumnTypeDict::from_type_code(&col_type),column_meta: col_meta});
         }
+        if meta_cur.position() != metadata_pack.len() as u64 {
+            readvalue::mismatch(WarningKind::Metadata, format!("table_map({}.{})的metadata长度({})与字段类型不符, 已解析{}bytes",
+                                        database_name, table_name, metadata_pack.len(), meta_cur.position()))?;
+        }
+        let metadata = Self::read_optional_metadata(header, buf, start, &column_type_list, &metadata_pack, &column_info);
 
-
-        TableMap{
+        Ok(TableMap{
             database_name,
             table_name,
             column_count,
-            column_info
-        }
+            column_info,
+            metadata
+        })
+    }
+}
+
+/*
+format_description_event:
+    binlog_version : 2bytes
+    server_version : 50bytes
+    create_timestamp : 4bytes
+    header_length : 1bytes
+    post_header_length : one byte per event type
+    checksum_alg : 1bytes (5.6.1之后)
+    crc : 4bytes
+*/
+#[derive(Debug, Clone)]
+pub struct FormatDescriptionEvent{
+    pub binlog_version: u16,
+    pub server_version: String,
+    pub create_timestamp: u32,
+    pub header_length: u8
+}
+
+impl FormatDescriptionEvent{
+    //写入该binlog的mysql主版本号, 与连接数据库获取的version含义相同; MariaDB 10.x按5.x的格式解析
+    pub fn major_version(&self) -> u8 {
+        crate::fingerprint::ServerFingerprint::from_fde(self).parser_version()
+    }
+}
+
+impl InitValue for FormatDescriptionEvent{
+    fn read_event<R: Read+Seek>(_header: &EventHeader, buf: &mut R, _version: &u8) -> io::Result<FormatDescriptionEvent>{
+        let binlog_version = buf.read_u16::<LittleEndian>()?;
+        let mut version_pack = vec![0u8; 50];
+        buf.read_exact(&mut version_pack)?;
+        let server_version = String::from_utf8_lossy(&version_pack).trim_end_matches('\0').to_string();
+        let create_timestamp = buf.read_u32::<LittleEndian>()?;
+        let header_length = buf.read_u8()?;
+        Ok(FormatDescriptionEvent{
+            binlog_version,
+            server_version,
+            create_timestamp,
+            header_length
+        })
     }
 }
 
@@ -413,6 +837,9 @@ gtid_event:
 
     The buffer is advanced in Binary_log_event constructor to point to
     beginning of post-header
+
+    8.0.1之后sequence_number之后为immediate_commit_timestamp(7bytes, 微秒, 最高位为1时之后为7bytes的original_commit_timestamp)、
+    transaction_length、immediate_server_version等
 */
 
 #[derive(Debug, Clone)]
@@ -420,26 +847,130 @@ pub struct GtidEvent{
     pub gtid: Uuid,
     pub gno_id: u64,
     pub last_committed: u64,
-    pub sequence_number: u64
+    pub sequence_number: u64,
+    /// 事务在本实例提交的时间(immediate_commit_timestamp), 微秒, 8.0.1之前的版本没有
+    pub commit_timestamp: Option<u64>
 }
 
+const LOGICAL_TIMESTAMP_TYPECODE: u8 = 2;
+
 impl InitValue for GtidEvent {
-    fn read_event<R: Read+Seek>(_header: &EventHeader, buf: &mut R, _version: &u8) -> GtidEvent {
-        buf.seek(io::SeekFrom::Current(1)).unwrap();
+    fn read_event<R: Read+Seek>(_header: &EventHeader, buf: &mut R, _version: &u8) -> io::Result<GtidEvent>{
+        buf.seek(io::SeekFrom::Current(1))?;
         let mut sid = [0 as u8; 16];
-        buf.read_exact(&mut sid).unwrap();
+        buf.read_exact(&mut sid)?;
 
         let gtid = uuid::Uuid::from_bytes(sid);
-        let gno_id = buf.read_u64::<LittleEndian>().unwrap();
-
-        let last_committed = buf.read_u64::<LittleEndian>().unwrap();
-        let sequence_number = buf.read_u64::<LittleEndian>().unwrap();
+        let gno_id = buf.read_u64::<LittleEndian>()?;
+
+        //5.6没有lt_type及之后的部分, 按0处理
+        let (mut last_committed, mut sequence_number, mut commit_timestamp) = (0, 0, None);
+        if buf.read_u8().ok() == Some(LOGICAL_TIMESTAMP_TYPECODE) {
+            last_committed = buf.read_u64::<LittleEndian>().unwrap_or(0);
+            sequence_number = buf.read_u64::<LittleEndian>().unwrap_or(0);
+            //5.7之后只有4bytes的checksum, 8.0至少还有提交时间、transaction_length、server版本共12bytes
+            let mut rest = vec![];
+            buf.read_to_end(&mut rest)?;
+            if rest.len() >= 12 {
+                let mut timestamp = [0u8; 8];
+                timestamp[..7].copy_from_slice(&rest[..7]);
+                commit_timestamp = Some(u64::from_le_bytes(timestamp) & !(1 << 55));
+            }
+        }
 
-        GtidEvent{
+        Ok(GtidEvent{
             gtid,
             gno_id,
             last_committed,
-            sequence_number
+            sequence_number,
+            commit_timestamp
+        })
+    }
+}
+
+/*
+transaction_context_event(组复制认证使用的事务上下文):
+    post_header = 18bytes
+        server_uuid_len : 1bytes
+        thread_id : 4bytes
+        gtid_specified : 1bytes
+        snapshot_version_len : 4bytes
+        write_set_len : 4bytes
+        read_set_len : 4bytes
+    body:
+        server_uuid : server_uuid_len bytes
+        snapshot_version : snapshot_version_len bytes
+        write_set : write_set_len * (item_len 2bytes + item)
+        read_set : read_set_len * (item_len 2bytes + item)
+*/
+#[derive(Debug, Clone)]
+pub struct TransactionContextEvent{
+    pub server_uuid: String,
+    pub thread_id: u32,
+    pub gtid_specified: bool,
+    pub write_set_len: u32,
+    pub read_set_len: u32
+}
+
+impl InitValue for TransactionContextEvent{
+    fn read_event<R: Read+Seek>(_header: &EventHeader, buf: &mut R, _version: &u8) -> io::Result<TransactionContextEvent>{
+        let server_uuid_len = buf.read_u8()? as usize;
+        let thread_id = buf.read_u32::<LittleEndian>()?;
+        let gtid_specified = buf.read_u8()? != 0;
+        let _snapshot_version_len = buf.read_u32::<LittleEndian>()?;
+        let write_set_len = buf.read_u32::<LittleEndian>()?;
+        let read_set_len = buf.read_u32::<LittleEndian>()?;
+        let server_uuid = readvalue::read_string_value_from_len(buf, server_uuid_len)?;
+
+        Ok(TransactionContextEvent{
+            server_uuid,
+            thread_id,
+            gtid_specified,
+            write_set_len,
+            read_set_len
+        })
+    }
+}
+
+/*
+view_change_event(组复制成员变更):
+    post_header = 52bytes
+        view_id : 40bytes
+        seq_number : 8bytes
+        cert_info_size : 4bytes
+    body:
+        cert_info : cert_info_size * (key_len 2bytes + key + value_len 4bytes + value)
+*/
+#[derive(Debug, Clone)]
+pub struct ViewChangeEvent{
+    pub view_id: String,
+    pub seq_number: u64,
+    pub cert_info: Vec<(String, String)>
+}
+
+impl InitValue for ViewChangeEvent{
+    fn read_event<R: Read+Seek>(_header: &EventHeader, buf: &mut R, _version: &u8) -> io::Result<ViewChangeEvent>{
+        let mut view_id_pack = [0u8; 40];
+        buf.read_exact(&mut view_id_pack)?;
+        //view_id不足40字节的部分以0填充
+        let view_id_len = view_id_pack.iter().position(|b| *b == 0).unwrap_or(40);
+        let view_id = String::from_utf8_lossy(&view_id_pack[..view_id_len]).to_string();
+        let seq_number = buf.read_u64::<LittleEndian>()?;
+        let cert_info_size = buf.read_u32::<LittleEndian>()?;
+
+        let mut cert_info = vec![];
+        for _ in 0..cert_info_size {
+            let key_len = buf.read_u16::<LittleEndian>()? as usize;
+            let key = readvalue::read_string_value_from_len(buf, key_len)?;
+            let value_len = buf.read_u32::<LittleEndian>()? as usize;
+            let value = readvalue::read_string_value_from_len(buf, value_len)?;
+            cert_info.push((key, value));
         }
+
+        Ok(ViewChangeEvent{
+            view_id,
+            seq_number,
+            cert_info
+        })
     }
 }
@@ -4,39 +4,109 @@
 */
 
 
-use std::fs::File;
-use std::io::{Seek, SeekFrom, Write, Cursor, Read, BufReader};
-use crate::replication::readevent::{EventHeader, BinlogEvent, TableMap, Tell, InitHeader};
-use byteorder::{ReadBytesExt, LittleEndian};
+/*
+回滚的安全限制:
+    --maxrows: 回滚的总行数超过该值时拒绝生成, 已经生成的回滚文件会被删除
+    范围内包含DDL时拒绝生成, 回滚的行数据无法跨越表结构变更, --force时跳过DDL继续生成
+    --chunkrows: 将大事务按行数切分为多个事务写入, 只在行事件之间切分, 被切断的行事件设置STMT_END_F
+    每个事务(或切分后的每个部分)在BEGIN之后写入一个rows_query_event作为进度注释, parse --getsql时以注释输出
+*/
+
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom, Cursor, Read, BufReader};
+use crate::replication::readevent::{EventHeader, BinlogEvent, TableMap, Tell, RowsQueryEvent, InitValue};
+use byteorder::ReadBytesExt;
 use crate::meta::ColumnTypeDict;
 use crate::{readvalue, Config};
 use std::env::set_var;
 use crate::replication::readevent;
+use crate::replication::copy::{BinlogWriter, CopyTrac, has_checksum};
+use crate::replication::encode;
+use crate::stdout::outddl;
+use crate::exitcode;
+use crate::gtid::GtidSet;
+
+const QUERY_EVENT: u8 = 2;
+const XID_EVENT: u8 = 16;
+const TABLE_MAP_EVENT: u8 = 19;
+const ROWS_QUERY_EVENT: u8 = 29;
+const GTID_EVENT: u8 = 33;
+const PREVIOUS_GTIDS_EVENT: u8 = 35;
+
+fn is_rows_event(type_code: u8) -> bool {
+    matches!(type_code, 23 | 24 | 25 | 30 | 31 | 32)
+}
 
+/// 切分后的一个回滚事务
+pub struct RollbackChunk{
+    pub events: Vec<Vec<u8>>,
+    pub rows: usize,
+}
 
-#[derive(Debug)]
 pub struct RollBackTrac{
     pub desc_event: Vec<u8>,
     pub count: usize,
-    pub events: Vec<Vec<u8>>,
+    pub events: Vec<(Vec<u8>, Vec<usize>)>,     //事务的事件及其中每个行事件的行数
     pub file_seq: usize,
     pub desc_format: Vec<u8>,
     pub cur_event: Vec<u8>,
     pub rollback_traction: Vec<u8>,
     pub rfilesize: usize,
     pub rollback: bool,
+    pub copy: Option<CopyTrac>,     //复制模式按原始事件写入新文件, 同样需要收集事件
+    pub traction_rows: Vec<usize>,      //当前事务中每个行事件的行数
+    rows: usize,                        //已收集的回滚行数
+    max_rows: usize,
+    chunk_rows: usize,
+    force: bool,
 }
 impl RollBackTrac {
     pub fn new(reader: &mut BufReader<File>,conf: & Config) -> RollBackTrac{
         let mut desc_format = vec![];
         let mut rfilesize = 0;
         let mut rollback = false;
+        let mut copy = None;
+        let mut max_rows = 0;
+        let mut chunk_rows = 0;
         if conf.rollback {
             rollback = true;
-            desc_format = Self::get_desc_format_event(reader,conf);
+            desc_format = Self::get_desc_format_event(reader);
             if conf.rfilesize.len()> 0 {
                 rfilesize = conf.rfilesize.parse().unwrap();
             }
+            if !conf.maxrows.is_empty() {
+                max_rows = conf.maxrows.parse().unwrap_or_else(|_|{
+                    println!("无效的maxrows: {}", conf.maxrows);
+                    std::process::exit(exitcode::USAGE);
+                });
+            }
+            if !conf.chunkrows.is_empty() {
+                chunk_rows = conf.chunkrows.parse().unwrap_or_else(|_|{
+                    println!("无效的chunkrows: {}", conf.chunkrows);
+                    std::process::exit(exitcode::USAGE);
+                });
+            }
+        }
+        else if conf.runtype == "copy" {
+            desc_format = Self::get_desc_format_event(reader);
+            //previous_gtids_event按过滤后的内容重新计算
+            let mut copy_previous = None;
+            let previous_gtids = Self::get_previous_gtids_event(reader);
+            if !previous_gtids.is_empty() {
+                let checksum = if has_checksum(&desc_format) { 4 } else { 0 };
+                let mut gtids = GtidSet::decode(&previous_gtids[19..previous_gtids.len() - checksum]).unwrap_or_else(|err|{
+                    println!("读取previous_gtids_event发生错误: {}", err);
+                    std::process::exit(exitcode::PARSE);
+                });
+                if !conf.startposition.is_empty() {
+                    Self::skipped_gtids(reader, conf.startposition.parse().unwrap(), &mut gtids);
+                }
+                copy_previous = Some((previous_gtids, gtids));
+            }
+            copy = Some(CopyTrac::new(&conf.output, &desc_format, copy_previous).unwrap_or_else(|err|{
+                println!("创建文件({})发生错误:{}",conf.output, err);
+                std::process::exit(exitcode::SINK);
+            }));
         }
         else {
             reader.seek(SeekFrom::Start(4));
@@ -53,50 +123,204 @@ impl RollBackTrac {
             cur_event: vec![],
             rollback_traction: vec![],
             rfilesize,
-            rollback
+            rollback,
+            copy,
+            traction_rows: vec![],
+            rows: 0,
+            max_rows,
+            chunk_rows,
+            force: conf.force,
+        }
+    }
+
+    fn get_previous_gtids_event(reader: &mut BufReader<File>) -> Vec<u8> {
+        let cur_tell = reader.tell().unwrap();
+        let mut header_buf = vec![0u8; 19];
+        if reader.read_exact(header_buf.as_mut()).is_err() || header_buf[4] != PREVIOUS_GTIDS_EVENT {
+            reader.seek(SeekFrom::Start(cur_tell)).unwrap();
+            return vec![];
         }
+        let mut previous_gtids: Vec<u8> = header_buf.clone();
+        let mut cur = Cursor::new(header_buf);
+        let payload_buf = readevent::EventHeader::read(&mut cur).and_then(|event_header| Self::read_payload(reader, &event_header))
+            .unwrap_or_else(|err|{
+                println!("读取previous_gtids_event发生错误: {}", err);
+                std::process::exit(exitcode::PARSE);
+            });
+        previous_gtids.extend(&payload_buf);
+        previous_gtids
     }
 
-    fn get_desc_format_event(reader: &mut BufReader<File>,conf: &Config) -> Vec<u8> {
+    //--startposition之前的事务不会写入新文件, 同样计入previous_gtids
+    fn skipped_gtids(reader: &mut BufReader<File>, start_position: u64, gtids: &mut GtidSet) {
+        while reader.tell().unwrap() < start_position {
+            let mut header_buf = vec![0u8; 19];
+            if reader.read_exact(header_buf.as_mut()).is_err() {
+                return;
+            }
+            let event = readevent::EventHeader::read(&mut Cursor::new(&header_buf))
+                .and_then(|event_header| Ok((Self::read_payload(reader, &event_header)?, event_header)));
+            match event {
+                Ok((payload_buf, event_header)) if header_buf[4] == GTID_EVENT => {
+                    if let Ok(v) = readevent::GtidEvent::read_event(&event_header, &mut Cursor::new(payload_buf), &0) {
+                        gtids.add(v.gtid, v.gno_id);
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => return
+            }
+        }
+    }
+
+    pub fn is_copy(&self) -> bool {
+        self.copy.is_some()
+    }
+
+    //回滚、复制模式需要收集原始事件, 不输出解析结果
+    pub fn collect(&self) -> bool {
+        self.rollback || self.is_copy()
+    }
+
+    //复制模式下读取到gtid_event
+    pub fn observe_copy(&mut self) {
+        if let Some(copy) = &mut self.copy {
+            copy.observe(&self.cur_event);
+        }
+    }
+
+    //复制模式下没有xid_event结尾的事务: ddl、xa commit或者xa prepare
+    pub fn check_copy_end(&mut self, command: Option<&str>) {
+        if let Some(copy) = &mut self.copy {
+            copy.check_end(&self.rollback_traction, command);
+        }
+    }
+
+    //非行事件加入当前事务, 复制模式下事务已经完整时写入新文件
+    pub fn append_traction(&mut self) {
+        self.rollback_traction.extend(self.cur_event.clone());
+        if self.copy.as_ref().is_some_and(|copy| copy.end) {
+            self.push_traction();
+        }
+    }
+
+    //一个事务读取完成, 复制模式直接写入文件, 回滚模式暂存待倒叙写入
+    pub fn push_traction(&mut self) {
+        if let Some(copy) = &mut self.copy {
+            copy.write_traction(&self.rollback_traction).unwrap_or_else(|err|{
+                println!("{}",err);
+                std::process::exit(exitcode::SINK);
+            });
+        } else {
+            let tra_len = self.rollback_traction.len();
+            self.events.push((self.rollback_traction.clone(), std::mem::take(&mut self.traction_rows)));
+            self.count += tra_len;
+            if self.check_file_size(){
+                self.update();
+            }
+        }
+        self.rollback_traction = vec![];
+    }
+
+    fn get_desc_format_event(reader: &mut BufReader<File>) -> Vec<u8> {
         reader.seek(SeekFrom::Start(4));
         let mut desc_format: Vec<u8> = vec![];
         let mut header_buf = vec![0u8; 19];
-        reader.read_exact(header_buf.as_mut()).unwrap();
+        let payload_buf = reader.read_exact(header_buf.as_mut())
+            .and_then(|_| readevent::EventHeader::read(&mut Cursor::new(&header_buf)))
+            .and_then(|event_header| Self::read_payload(reader, &event_header))
+            .unwrap_or_else(|err|{
+                println!("读取format_description_event发生错误: {}", err);
+                std::process::exit(exitcode::PARSE);
+            });
         desc_format.extend(&header_buf);
-        let mut cur = Cursor::new(header_buf);
-        let event_header: EventHeader = readevent::EventHeader::new(&mut cur,conf);
-        let payload = event_header.event_length as usize - event_header.header_length as usize;
-        let mut payload_buf = vec![0u8; payload];
-        reader.read_exact(payload_buf.as_mut()).unwrap();
         desc_format.extend(&payload_buf);
         desc_format
     }
 
-    pub fn get_rollbak_file(files_seq: usize) -> File {
-        let mut write_file: File;
-        let name = format!("rollback-{}.log",files_seq);
-        write_file = File::create(name).unwrap_or_else(|err|{
-            println!("{}",err);
-            std::process::exit(1);
-        });
-        write_file
+    //event_length来自文件内容, 长度错误时返回错误, 不按该长度预先分配内存
+    fn read_payload(reader: &mut BufReader<File>, event_header: &EventHeader) -> std::io::Result<Vec<u8>> {
+        let payload = (event_header.event_length as usize).checked_sub(event_header.header_length as usize)
+            .ok_or_else(|| readvalue::invalid_data(format!("事件长度({})错误", event_header.event_length)))?;
+        readvalue::read_bytes(reader, payload)
+    }
+
+    fn rollback_file_name(files_seq: usize) -> String {
+        format!("rollback-{}.log",files_seq)
+    }
+
+    //一个回滚的行事件, 累计的行数超过--maxrows时拒绝生成
+    pub fn push_rows_event(&mut self, event: Vec<u8>, rows: usize) {
+        self.rollback_traction.extend(event);
+        self.traction_rows.push(rows);
+        self.rows += rows;
+        if self.max_rows > 0 && self.rows > self.max_rows && !self.force {
+            self.refuse(&format!("回滚的行数超过了--maxrows({})", self.max_rows));
+        }
+    }
+
+    //回滚范围内的DDL, 回滚的行数据不能跨越表结构变更
+    pub fn check_ddl(&mut self, command: &str, position: u64) {
+        if !self.rollback || !outddl::is_ddl(command) {
+            return;
+        }
+        if !self.force {
+            self.refuse(&format!("回滚范围内包含DDL(position {}): {}", position, command));
+        }
+        eprintln!("--force: 跳过position {} 处的DDL, 应用回滚之前需要确认表结构: {}", position, command);
+    }
+
+    //删除已经生成的回滚文件后退出
+    fn refuse(&self, reason: &str) -> ! {
+        for seq in 1..self.file_seq {
+            let _ = fs::remove_file(Self::rollback_file_name(seq));
+        }
+        println!("{}, 没有生成回滚文件, 确认无误后可以使用--force", reason);
+        std::process::exit(exitcode::GUARD);
     }
 
     //倒叙写入事务信息
-    pub fn write_rollback_log(&mut self) -> std::io::Result<()> {
-        let mut write_file = Self::get_rollbak_file(self.file_seq);
-        let a = [0xFE,0x62,0x69,0x6E];
-        write_file.write_all(&a)?;
-        write_file.write_all(&self.desc_format);
+    pub fn write_rollback_log(&mut self) {
+        if let Some(copy) = &mut self.copy {
+            //复制模式已按事务顺序写入, 没有写入任何事务时最后写入previous_gtids_event
+            copy.finish().unwrap_or_else(|err|{
+                println!("{}",err);
+                std::process::exit(exitcode::SINK);
+            });
+            return;
+        }
+        let name = Self::rollback_file_name(self.file_seq);
+        self.write_log(&name).unwrap_or_else(|err|{
+            println!("写入文件({})发生错误:{}", name, err);
+            std::process::exit(exitcode::SINK);
+        });
+    }
+
+    fn write_log(&self, name: &str) -> std::io::Result<()> {
+        let mut writer = BinlogWriter::new(name, &self.desc_format)?;
+        let checksum = has_checksum(&self.desc_format);
         let tracs = self.events.len();
-        let count = self.count;
-        let mut a = vec![0; tracs];
-        for i in 0..tracs {
-            a[i] = i;
-        }
-        a.reverse();
-        for i in a {
-            write_file.write_all(&self.events[i])?;
+        let total_rows: usize = self.events.iter().map(|(_, rows)| rows.iter().sum::<usize>()).sum();
+        let mut done = 0;
+        for (idx, (traction, rows)) in self.events.iter().rev().enumerate() {
+            let chunks = split_traction(traction, rows, self.chunk_rows);
+            for (n, chunk) in chunks.iter().enumerate() {
+                let mut comment = format!("flashback {}: transaction {}/{}", name, idx + 1, tracs);
+                if chunks.len() > 1 {
+                    comment.push_str(&format!(", chunk {}/{}", n + 1, chunks.len()));
+                }
+                if chunk.rows > 0 {
+                    comment.push_str(&format!(", rows {}-{} of {}", done + 1, done + chunk.rows, total_rows));
+                }
+                done += chunk.rows;
+                let mut commented = false;
+                for event in &chunk.events {
+                    writer.write_event(event)?;
+                    if !commented && event[4] == QUERY_EVENT {
+                        writer.write_event(&comment_event(event, &comment, checksum))?;
+                        commented = true;
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -117,13 +341,13 @@ impl RollBackTrac {
     }
 
     pub fn is_write(&mut self){
-        if self.rollback{
+        if self.collect(){
             self.write_rollback_log();
         }
     }
 
     pub fn append_cur_event(&mut self, buf: &Vec<u8>) {
-        if self.rollback{
+        if self.collect(){
             self.cur_event.extend(buf);
         }
     }
@@ -132,32 +356,100 @@ impl RollBackTrac {
         self.file_seq = self.file_seq + 1;
         self.count = 0;
         self.rollback_traction = vec![];
+        self.traction_rows = vec![];
         self.events = vec![];
     }
 
     pub fn update_event(&mut self) {
-        if self.rollback{
+        if self.collect(){
             self.rollback_traction = vec![];
+            self.traction_rows = vec![];
             self.cur_event = vec![];
         }
     }
 
     pub fn init_traction_buf(&mut self) {
-        if self.rollback{
+        if self.collect(){
             self.rollback_traction = vec![];
+            self.traction_rows = vec![];
         }
     }
 
     pub fn delete_cur_event(&mut self){
-        if self.rollback{
+        if self.collect(){
             self.cur_event = vec![];
         }
     }
 }
 
 
+//进度注释, 使用BEGIN事件的时间及server_id
+fn comment_event(begin: &[u8], comment: &str, checksum: bool) -> Vec<u8> {
+    let body = encode::rows_query_body(&RowsQueryEvent{ query: comment.to_string() });
+    encode::event(readvalue::read_u32(&begin[0..4]), ROWS_QUERY_EVENT, readvalue::read_u32(&begin[5..9]), &body, checksum)
+}
 
-pub fn rollback_row_event(event: &Vec<u8>, event_header: &EventHeader, map: &TableMap) -> Vec<u8> {
+//表的id, 位于table_map_event及行事件包头之后的6bytes, 行事件之后的2bytes为flags
+fn table_id(event: &[u8]) -> &[u8] {
+    &event[19..25]
+}
+
+/// 将一个事务按行数切分为多个事务, chunk_rows为0或事务中没有BEGIN、XID时不切分
+/// traction为连续存放的事件, rows为其中每个行事件的行数
+/// 之后的每个部分以原事务的BEGIN开始(不包含gtid_event), 重新写入已经出现过的table_map_event, 以原事务的XID结束
+pub fn split_traction(traction: &[u8], rows: &[usize], chunk_rows: usize) -> Vec<RollbackChunk> {
+    let mut events = vec![];
+    let mut offset = 0;
+    while offset + 19 <= traction.len() {
+        let event_length = readvalue::read_u32(&traction[offset + 9..offset + 13]) as usize;
+        if event_length < 19 || offset + event_length > traction.len() {
+            break;
+        }
+        events.push(&traction[offset..offset + event_length]);
+        offset += event_length;
+    }
+    let total = rows.iter().sum();
+    let begin = events.iter().position(|e| e[4] == QUERY_EVENT);
+    let xid = events.last().filter(|e| e[4] == XID_EVENT);
+    let (begin, xid) = match (begin, xid) {
+        (Some(b), Some(x)) if chunk_rows > 0 && total > chunk_rows => (b, *x),
+        _ => return vec![RollbackChunk{ events: events.iter().map(|e| e.to_vec()).collect(), rows: total }]
+    };
+
+    let mut chunks = vec![RollbackChunk{ events: events[..=begin].iter().map(|e| e.to_vec()).collect(), rows: 0 }];
+    let mut maps: Vec<&[u8]> = vec![];
+    let mut row_idx = 0;
+    for event in &events[begin + 1..events.len() - 1] {
+        let type_code = event[4];
+        let last = chunks.len() - 1;
+        if (type_code == TABLE_MAP_EVENT || is_rows_event(type_code)) && chunks[last].rows >= chunk_rows {
+            //当前部分已满, 最后一个行事件作为语句的结束
+            if let Some(t) = chunks[last].events.iter_mut().rev().find(|e| is_rows_event(e[4])) {
+                t[25] |= 1;
+            }
+            chunks[last].events.push(xid.to_vec());
+            let mut next = vec![events[begin].to_vec()];
+            next.extend(maps.iter().map(|e| e.to_vec()));
+            chunks.push(RollbackChunk{ events: next, rows: 0 });
+        }
+        let last = chunks.len() - 1;
+        if type_code == TABLE_MAP_EVENT {
+            maps.retain(|m| table_id(m) != table_id(event));
+            maps.push(event);
+        }
+        if is_rows_event(type_code) {
+            chunks[last].rows += rows.get(row_idx).copied().unwrap_or(0);
+            row_idx += 1;
+        }
+        chunks[last].events.push(event.to_vec());
+    }
+    if let Some(last) = chunks.last_mut() {
+        last.events.push(xid.to_vec());
+    }
+    chunks
+}
+
+pub fn rollback_row_event(event: &Vec<u8>, event_header: &EventHeader, map: &TableMap) -> std::io::Result<Vec<u8>> {
     let mut new_row_event: Vec<u8> = event.clone();
     match event_header.type_code {
         BinlogEvent::UpdateEvent => {
@@ -166,14 +458,14 @@ pub fn rollback_row_event(event: &Vec<u8>, event_header: &EventHeader, map: &Tab
         }
         BinlogEvent::DeleteEvent => {
             new_row_event[4] = 30;
-            new_row_event
+            Ok(new_row_event)
         }
         BinlogEvent::WriteEvent => {
             new_row_event[4] = 32;
-            new_row_event
+            Ok(new_row_event)
         }
         _ => {
-            event.clone()
+            Ok(event.clone())
         }
     }
 }
@@ -182,48 +474,51 @@ fn tmp_var() -> (Vec<u8>, Vec<u8>){
     (vec![],vec![])
 }
 
-fn update_event<R: Read+Seek>(event: &mut R, map: &TableMap, event_header: &EventHeader) -> Vec<u8> {
+fn update_event<R: Read+Seek>(event: &mut R, map: &TableMap, event_header: &EventHeader) -> std::io::Result<Vec<u8>> {
     let mut new_row_event: Vec<u8> = vec![];
     let mut header = [0u8; 19];
-    event.read_exact(&mut header).unwrap();
+    event.read_exact(&mut header)?;
     new_row_event.extend(&header);
 
     let mut fix_buf = [0u8; 8];
-    event.read_exact(&mut fix_buf).unwrap();
+    event.read_exact(&mut fix_buf)?;
     new_row_event.extend(&fix_buf);
 
     let mut extra = [0u8; 2];
-    event.read_exact(&mut extra).unwrap();
+    event.read_exact(&mut extra)?;
     new_row_event.extend(&extra);
     let a = crate::readvalue::read_u16(&extra);
 
     if a > 2{
         let tmp = a -2;
-        let mut extra_o = vec![0u8; tmp as usize];
-        event.read_exact(&mut extra_o).unwrap();
+        let extra_o = readvalue::read_bytes(event, tmp as usize)?;
         new_row_event.extend(&extra_o);
     }
 
-    let cols = event.read_u8().unwrap();
+    let cols = event.read_u8()?;
     new_row_event.push(cols);
+    if cols as usize != map.column_info.len() {
+        return Err(readvalue::invalid_data(format!("行事件的字段数({})与table_map({}.{})的字段数({})不一致",
+                                                   cols, map.database_name, map.table_name, map.column_info.len())));
+    }
 
-    let cols_var = ((cols + 7) / 8) as usize ;
+    let cols_var = (cols as usize).div_ceil(8);
     let mut vars = vec![0u8; (cols_var * 2) as usize];
-    event.read_exact(&mut vars).unwrap();
+    event.read_exact(&mut vars)?;
     new_row_event.extend(&vars);
     let (mut before_bytes , mut after_bytes)= tmp_var();
     loop {
+        let row_start = event.tell()?;
         let mut nulls = vec![0u8; cols_var];
-        event.read_exact(&mut nulls).unwrap();
+        event.read_exact(&mut nulls)?;
         //new_row_event.extend(&nulls);
 
-        let columns = map.column_info.len();
         let mut row_bytes: Vec<u8> = vec![];
-        for idx in 0..columns {
+        for (idx, col) in map.column_info.iter().enumerate() {
             if crate::replication::parsevalue::is_null(&nulls, &idx) > 0{
 
             } else {
-                let col_bytes = parese_row_bytes(event, &map.column_info[idx].column_type, &map.column_info[idx].column_meta);
+                let col_bytes = parese_row_bytes(event, &col.column_type, &col.column_meta)?;
                 row_bytes.extend(col_bytes);
             }
         }
@@ -240,9 +535,11 @@ fn update_event<R: Read+Seek>(event: &mut R, map: &TableMap, event_header: &Even
             after_bytes = vec![];
         }
 
-        if (event.tell().unwrap() + 4) as usize >= event_header.event_length as usize {
+        //没有字段的行不占用数据, 继续读取会一直循环
+        let cur = event.tell()?;
+        if cur == row_start || (cur + 4) as usize >= event_header.event_length as usize {
             let mut a = vec![];
-            event.read_to_end(&mut a);
+            event.read_to_end(&mut a)?;
             if a.len() > 0 {
                 new_row_event.extend(a);
             }
@@ -251,62 +548,69 @@ fn update_event<R: Read+Seek>(event: &mut R, map: &TableMap, event_header: &Even
         }
     }
 
-    new_row_event
+    Ok(new_row_event)
 }
 
 
-fn parese_row_bytes<R: Read + Tell>(buf: &mut R, type_code: &ColumnTypeDict, col_meta: &Vec<usize>) -> Vec<u8> {
+fn parese_row_bytes<R: Read + Tell>(buf: &mut R, type_code: &ColumnTypeDict, col_meta: &Vec<usize>) -> std::io::Result<Vec<u8>> {
+    let meta = |idx: usize| col_meta.get(idx).copied().ok_or_else(|| readvalue::invalid_data(format!("{:?}缺少metadata", type_code)));
     let mut row_bytes= vec![];
-    let mut tmp = vec![];
+    let mut tmp_len = 0usize;
     match type_code {
         ColumnTypeDict::MysqlTypeTiny => {
-            tmp = vec![0u8; 1];
+            tmp_len = 1;
             //row_bytes.push(buf.read(row_bytes.as_mut()).unwrap() as u8);
         }
         ColumnTypeDict::MysqlTypeShort => {
-            tmp = vec![0u8; 2];
+            tmp_len = 2;
         }
         ColumnTypeDict::MysqlTypeInt24 => {
-            tmp = vec![0u8; 3];
+            tmp_len = 3;
         }
         ColumnTypeDict::MysqlTypeLong => {
-            tmp = vec![0u8; 4];
+            tmp_len = 4;
         }
         ColumnTypeDict::MysqlTypeLonglong => {
-            tmp = vec![0u8; 8];
+            tmp_len = 8;
         }
         ColumnTypeDict::MysqlTypeNewdecimal => {
-            let decimal_meta = crate::replication::parsevalue::DecimalMeta::new(col_meta[0] as u8, col_meta[1] as u8);
-            tmp = vec![0u8; decimal_meta.bytes_to_read];
+            let (precision, decimal) = (meta(0)? as u8, meta(1)? as u8);
+            let decimal_meta = crate::replication::parsevalue::DecimalMeta::new(precision, decimal)?;
+            tmp_len = decimal_meta.bytes_to_read;
         }
         ColumnTypeDict::MysqlTypeDouble |
         ColumnTypeDict::MysqlTypeFloat => {
-            match col_meta[0] {
-                8 => {
-                    tmp = vec![0u8; 8];
-                },
-                4 => tmp = vec![0u8; 4],
-                _ => {}
+            match meta(0)? {
+                pack @ (4 | 8) => tmp_len = pack,
+                pack => return Err(readvalue::invalid_data(format!("无效的{:?} metadata: {}", type_code, pack)))
             }
         }
         ColumnTypeDict::MysqlTypeTimestamp2 => {
-            let frac_part = read_datetime_fsp(col_meta[0] as u8);
-            tmp = vec![0u8; (4 + frac_part) as usize];
+            let frac_part = read_datetime_fsp(meta(0)? as u8);
+            tmp_len = (4 + frac_part) as usize;
         }
         ColumnTypeDict::MysqlTypeDatetime2 => {
-            let subsecond = read_datetime_fsp(col_meta[0] as u8);
-            tmp = vec![0u8; (5 + subsecond) as usize];
+            let subsecond = read_datetime_fsp(meta(0)? as u8);
+            tmp_len = (5 + subsecond) as usize;
         }
         ColumnTypeDict::MysqlTypeYear => {
-            tmp = vec![0u8; 1];
+            tmp_len = 1;
         }
-        ColumnTypeDict::MysqlTypeDate => {
-            tmp = vec![0u8; 3];
+        ColumnTypeDict::MysqlTypeDate |
+        ColumnTypeDict::MysqlTypeNewdate |
+        ColumnTypeDict::MysqlTypeTime => {
+            tmp_len = 3;
 
         }
+        ColumnTypeDict::MysqlTypeTimestamp => {
+            tmp_len = 4;
+        }
+        ColumnTypeDict::MysqlTypeDatetime => {
+            tmp_len = 8;
+        }
         ColumnTypeDict::MysqlTypeTime2 => {
-            let frac_part = read_datetime_fsp(col_meta[0] as u8);
-            tmp = vec![0u8; (3 + frac_part) as usize];
+            let frac_part = read_datetime_fsp(meta(0)? as u8);
+            tmp_len = (3 + frac_part) as usize;
         }
         ColumnTypeDict::MysqlTypeVarString |
         ColumnTypeDict::MysqlTypeVarchar |
@@ -314,52 +618,48 @@ fn parese_row_bytes<R: Read + Tell>(buf: &mut R, type_code: &ColumnTypeDict, col
         ColumnTypeDict::MysqlTypeTinyBlob |
         ColumnTypeDict::MysqlTypeLongBlob |
         ColumnTypeDict::MysqlTypeMediumBlob |
+        ColumnTypeDict::MysqlTypeGeometry |
         ColumnTypeDict::MysqlTypeBit => {
-            let (var_bytes,var_length) =  read_str_value_length(buf, &col_meta[0]);
-            tmp = vec![0u8; var_length];
+            let (var_bytes,var_length) =  read_str_value_length(buf, &meta(0)?)?;
+            tmp_len = var_length;
             row_bytes.extend(var_bytes);
 
         }
         ColumnTypeDict::MysqlTypeJson => {
-            let (var_bytes,var_length) =  read_str_value_length(buf, &col_meta[0]);
-            tmp = vec![0u8; var_length];
+            let (var_bytes,var_length) =  read_str_value_length(buf, &meta(0)?)?;
+            tmp_len = var_length;
             row_bytes.extend(var_bytes);
         }
         ColumnTypeDict::MysqlTypeString => {
             let mut value_length = 0;
             //println!("aa:{},{}",col_meta[0],buf.tell().unwrap());
-            if col_meta[0] <= 255 {
-                value_length = buf.read_u8().unwrap() as usize;
+            if meta(0)? <= 255 {
+                value_length = buf.read_u8()? as usize;
                 row_bytes.push(value_length as u8);
             }
             else {
                 let mut var_bytes = [0u8; 2];
-                buf.read_exact(&mut var_bytes);
+                buf.read_exact(&mut var_bytes)?;
                 row_bytes.extend(&var_bytes);
-                buf.seek(SeekFrom::Current(-2));
-                value_length = buf.read_u16::<LittleEndian>().unwrap() as usize;
+                value_length = readvalue::read_u16(&var_bytes) as usize;
             }
-            tmp = vec![0u8; value_length];
+            tmp_len = value_length;
         }
         ColumnTypeDict::MysqlTypeEnum |
         ColumnTypeDict::MysqlTypeSet => {
-            match col_meta[0] {
-                1 => {
-                    tmp = vec![0u8; 1];
-                },
-                2 => {
-                    tmp = vec![0u8; 2];
-                }
-                _ => {}
+            if let pack @ 1..=8 = meta(0)? {
+                tmp_len = pack;
             }
         }
+        ColumnTypeDict::UnknowType(code) => {
+            row_bytes.extend(crate::replication::opaque::read_raw(buf, *code, col_meta)?);
+        }
         _ => {}
     }
-    if tmp.len() > 0 {
-        buf.read_exact(tmp.as_mut());
+    if tmp_len > 0 {
+        row_bytes.extend(readvalue::read_bytes(buf, tmp_len)?);
     }
-    row_bytes.extend(tmp);
-    row_bytes
+    Ok(row_bytes)
 }
 
 fn read_datetime_fsp(column: u8) -> u8 {
@@ -372,59 +672,16 @@ fn read_datetime_fsp(column: u8) -> u8 {
     }
 }
 
-fn read_str_value_length<R: Read + Seek>(buf: &mut R, meta: &usize) -> (Vec<u8>,usize) {
+//长度为1-8bytes的小端整数, 返回原始bytes及长度
+fn read_str_value_length<R: Read + Seek>(buf: &mut R, meta: &usize) -> std::io::Result<(Vec<u8>,usize)> {
     let mut var_bytes = vec![];
     let mut var_len: usize = 0;
-    match meta {
-        1 => {
-            var_len = buf.read_u8().unwrap() as usize;
-            var_bytes.push(var_len as u8);
-        },
-        2 => {
-            var_bytes = vec![0u8; 2];
-            buf.read_exact(var_bytes.as_mut());
-            buf.seek(SeekFrom::Current(-2));
-            var_len = buf.read_u16::<LittleEndian>().unwrap() as usize;
-        },
-        3 => {
-            var_bytes = vec![0u8; 3];
-            buf.read_exact(var_bytes.as_mut());
-            buf.seek(SeekFrom::Current(-3));
-            var_len = buf.read_u24::<LittleEndian>().unwrap() as usize;
-        },
-        4 => {
-            var_bytes = vec![0u8; 4];
-            buf.read_exact(var_bytes.as_mut());
-            buf.seek(SeekFrom::Current(-4));
-            var_len = buf.read_u32::<LittleEndian>().unwrap() as usize
-        },
-        5 => {
-            var_bytes = vec![0u8; 5];
-            buf.read_exact(var_bytes.as_mut());
-            let tmp = var_bytes.clone();
-            var_len = readvalue::read_u40(&tmp) as usize;
-        }
-        6 => {
-            var_bytes = vec![0u8; 6];
-            buf.read_exact(var_bytes.as_mut());
-            let tmp = var_bytes.clone();
-            var_len= readvalue::read_u48(&tmp) as usize;
-        }
-        7 => {
-            var_bytes = vec![0u8; 7];
-            buf.read_exact(var_bytes.as_mut());
-            let tmp = var_bytes.clone();
-            var_len = readvalue::read_u56(&tmp) as usize;
-        }
-        8 => {
-            var_bytes = vec![0u8; 8];
-            let tmp = var_bytes.clone();
-            buf.read_exact(var_bytes.as_mut());
-            var_len = readvalue::read_u64(&tmp) as usize;
-        }
-        _ => {}
+    if (1..=8).contains(meta) {
+        var_bytes = vec![0u8; *meta];
+        buf.read_exact(var_bytes.as_mut())?;
+        let mut tmp = var_bytes.clone();
+        tmp.resize(8, 0);
+        var_len = readvalue::read_u64(&tmp) as usize;
     }
-    (var_bytes, var_len)
+    Ok((var_bytes, var_len))
 }
-
-
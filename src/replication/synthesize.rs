@@ -0,0 +1,449 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/03
+*/
+
+//synthesize: 生成合成的binlog文件, 在没有生产数据的情况下压测解析及输出目标, 也可以作为解析的模糊测试语料
+//  文件格式与mysql 5.7开启gtid、binlog_format=row、binlog_checksum=crc32时一致
+//  开头为每个表的CREATE TABLE语句, 之后每个事务修改一个表: GTID、BEGIN、TABLE_MAP、ROWS_EVENT、XID, 文件以STOP_EVENT结束
+//  第一列固定为bigint主键id, 其余列由--columns指定, 可以为空的列随机生成NULL; update、delete随机选择已经insert过的id
+//  事务按随机大小(1-4个)的提交组生成last_committed, 可以用于analyze --deps
+//  参数及--seed相同时生成的文件完全相同
+
+use std::io;
+use crate::{readvalue, Config};
+use crate::replication::copy::BinlogWriter;
+use crate::replication::encode::{self, bitmap, lenenc};
+use chrono::{Datelike, TimeZone, Timelike};
+
+const SERVER_VERSION: &str = "5.7.30-synthesize";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnKind{
+    TinyInt,
+    Int,
+    BigInt,
+    Double,
+    Varchar(u16),
+    Text,
+    Datetime,
+}
+
+impl ColumnKind{
+    pub fn new(spec: &str) -> Result<ColumnKind, String> {
+        let spec = spec.trim().to_lowercase();
+        match spec.as_str() {
+            "tinyint" => Ok(ColumnKind::TinyInt),
+            "int" => Ok(ColumnKind::Int),
+            "bigint" => Ok(ColumnKind::BigInt),
+            "double" => Ok(ColumnKind::Double),
+            "text" => Ok(ColumnKind::Text),
+            "datetime" => Ok(ColumnKind::Datetime),
+            "varchar" => Ok(ColumnKind::Varchar(64)),
+            t if t.starts_with("varchar(") && t.ends_with(')') => {
+                match t[8..t.len() - 1].parse::<u16>() {
+                    Ok(n) if n > 0 && n <= 16383 => Ok(ColumnKind::Varchar(n)),
+                    _ => Err(format!("无效的列类型: {}, varchar长度为1-16383", spec))
+                }
+            }
+            _ => Err(format!("无效的列类型: {}, 可选值为tinyint、int、bigint、double、varchar(n)、text、datetime", spec))
+        }
+    }
+
+    fn type_code(&self) -> u8 {
+        match self {
+            ColumnKind::TinyInt => 1,
+            ColumnKind::Int => 3,
+            ColumnKind::BigInt => 8,
+            ColumnKind::Double => 5,
+            ColumnKind::Varchar(_) => 15,
+            ColumnKind::Text => 252,
+            ColumnKind::Datetime => 18,
+        }
+    }
+
+    //varchar按utf8mb4记录最大字节数
+    fn max_bytes(&self) -> usize {
+        match self {
+            ColumnKind::Varchar(n) => *n as usize * 4,
+            _ => 0
+        }
+    }
+
+    fn metadata(&self) -> Vec<u8> {
+        match self {
+            ColumnKind::Varchar(_) => readvalue::write_u16(self.max_bytes() as u16),
+            ColumnKind::Double => vec![8],
+            ColumnKind::Text => vec![2],
+            ColumnKind::Datetime => vec![0],
+            _ => vec![]
+        }
+    }
+
+    fn sql_type(&self) -> String {
+        match self {
+            ColumnKind::TinyInt => String::from("tinyint"),
+            ColumnKind::Int => String::from("int"),
+            ColumnKind::BigInt => String::from("bigint"),
+            ColumnKind::Double => String::from("double"),
+            ColumnKind::Varchar(n) => format!("varchar({})", n),
+            ColumnKind::Text => String::from("text"),
+            ColumnKind::Datetime => String::from("datetime"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RowKind{
+    Insert,
+    Update,
+    Delete,
+}
+
+#[derive(Debug, Clone)]
+pub struct SynthOptions{
+    pub database: String,
+    pub tables: usize,
+    pub columns: Vec<ColumnKind>,
+    pub transactions: u64,
+    pub rows: (u32, u32),           //每个事务修改的行数范围
+    pub rate: u32,                  //每秒的事务数, 决定事件的时间戳
+    pub mix: [u32; 3],              //insert:update:delete的比例
+    pub start_time: u32,
+    pub seed: u64,
+    pub server_id: u32,
+}
+
+fn parse_value<T: std::str::FromStr>(value: &str, default: T, name: &str) -> Result<T, String> {
+    match value {
+        "" => Ok(default),
+        t => t.parse::<T>().map_err(|_| format!("无效的{}: {}", name, t))
+    }
+}
+
+impl SynthOptions{
+    pub fn new(conf: &Config) -> Result<SynthOptions, String> {
+        let database = if conf.database.is_empty() { String::from("synth") } else { conf.database.clone() };
+        let tables = parse_value(&conf.tables, 4usize, "tables")?;
+        let columns_spec = if conf.columns.is_empty() { "int,varchar(64),double,datetime" } else { conf.columns.as_str() };
+        let columns = columns_spec.split(',').map(ColumnKind::new).collect::<Result<Vec<_>, _>>()?;
+        let transactions = parse_value(&conf.transactions, 1000u64, "transactions")?;
+        let rows = match conf.rows.split_once('-') {
+            Some((min, max)) => (parse_value(min, 1u32, "rows")?, parse_value(max, 1u32, "rows")?),
+            None => {
+                let n = parse_value(&conf.rows, 10u32, "rows")?;
+                (n, n)
+            }
+        };
+        let rate = parse_value(&conf.rate, 100u32, "rate")?;
+        let mix_spec = if conf.mix.is_empty() { "6:3:1" } else { conf.mix.as_str() };
+        let mix: Vec<u32> = mix_spec.split(':').map(|v| parse_value(v, 0u32, "mix")).collect::<Result<Vec<_>, _>>()?;
+        let start_time = match conf.startdatetime.as_str() {
+            "" => chrono::Utc::now().timestamp() as u32,
+            t => parse_value(t, 0u32, "startdatetime")?
+        };
+
+        if tables == 0 || tables > 1000 {
+            return Err(format!("无效的tables: {}, 范围为1-1000", tables));
+        }
+        if columns.len() > 200 {
+            return Err(String::from("columns最多200列"));
+        }
+        if rows.0 == 0 || rows.0 > rows.1 {
+            return Err(format!("无效的rows: {}, 格式为n或min-max, 最小为1", conf.rows));
+        }
+        if rate == 0 {
+            return Err(String::from("rate需要大于0"));
+        }
+        if mix.len() != 3 || mix.iter().sum::<u32>() == 0 {
+            return Err(format!("无效的mix: {}, 格式为insert:update:delete, 例如6:3:1", mix_spec));
+        }
+        Ok(SynthOptions{
+            database,
+            tables,
+            columns,
+            transactions,
+            rows,
+            rate,
+            mix: [mix[0], mix[1], mix[2]],
+            start_time,
+            seed: parse_value(&conf.seed, 1u64, "seed")?,
+            server_id: parse_value(&conf.serverid, 133u32, "serverid")?,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SynthReport{
+    pub events: u64,
+    pub transactions: u64,
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+//xorshift64*, 不需要密码学强度, 只要求相同的seed生成相同的序列
+struct Rng(u64);
+
+impl Rng{
+    fn new(seed: u64) -> Rng {
+        Rng((seed ^ 0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    //[min, max]
+    fn range(&mut self, min: u64, max: u64) -> u64 {
+        min + self.next() % (max - min + 1)
+    }
+}
+
+struct Generator<'a>{
+    opts: &'a SynthOptions,
+    writer: BinlogWriter,
+    rng: Rng,
+    sid: [u8; 16],
+    gno: u64,
+    sequence: u64,
+    last_committed: u64,
+    group_left: u64,
+    next_id: Vec<u64>,
+    report: SynthReport,
+}
+
+impl<'a> Generator<'a>{
+    fn write(&mut self, timestamp: u32, type_code: u8, body: &[u8]) -> io::Result<()> {
+        //next_position及checksum由BinlogWriter写入
+        let event = encode::event(timestamp, type_code, self.opts.server_id, body, true);
+        self.writer.write_event(&event)?;
+        self.report.events += 1;
+        self.report.bytes += event.len() as u64;
+        Ok(())
+    }
+
+    fn gtid(&mut self, timestamp: u32) -> io::Result<()> {
+        if self.group_left == 0 {
+            self.last_committed = self.sequence;
+            self.group_left = self.rng.range(1, 4);
+        }
+        self.group_left -= 1;
+        self.sequence += 1;
+        self.gno += 1;
+        let mut body = vec![0u8];
+        body.extend_from_slice(&self.sid);
+        body.extend(readvalue::write_u64(self.gno));
+        body.push(2);
+        body.extend(readvalue::write_u64(self.last_committed));
+        body.extend(readvalue::write_u64(self.sequence));
+        self.write(timestamp, 33, &body)
+    }
+
+    fn query(&mut self, timestamp: u32, query: &str) -> io::Result<()> {
+        //Q_FLAGS2_CODE、Q_SQL_MODE_CODE、Q_CHARSET_CODE(utf8mb4_general_ci)
+        let mut status = vec![0u8, 0, 0, 0, 0, 1];
+        status.extend([0u8; 8]);
+        status.extend([4u8, 45, 0, 45, 0, 45, 0]);
+        let db = self.opts.database.as_bytes();
+        let mut body = readvalue::write_u32(1000 + (self.sequence % 64) as u32);
+        body.extend(readvalue::write_u32(0));
+        body.push(db.len() as u8);
+        body.extend(readvalue::write_u16(0));
+        body.extend(readvalue::write_u16(status.len() as u16));
+        body.extend(status);
+        body.extend_from_slice(db);
+        body.push(0);
+        body.extend_from_slice(query.as_bytes());
+        self.write(timestamp, 2, &body)
+    }
+
+    fn table_id(table: usize) -> Vec<u8> {
+        readvalue::write_u64(100 + table as u64)[..6].to_vec()
+    }
+
+    fn table_map(&mut self, timestamp: u32, table: usize) -> io::Result<()> {
+        let db = self.opts.database.as_bytes();
+        let tbl = format!("t{}", table + 1);
+        let cols = self.opts.columns.len() + 1;
+        let mut body = Self::table_id(table);
+        body.extend(readvalue::write_u16(1));
+        body.push(db.len() as u8);
+        body.extend_from_slice(db);
+        body.push(0);
+        body.push(tbl.len() as u8);
+        body.extend_from_slice(tbl.as_bytes());
+        body.push(0);
+        body.extend(lenenc(cols));
+        body.push(ColumnKind::BigInt.type_code());
+        body.extend(self.opts.columns.iter().map(|c| c.type_code()));
+        let meta: Vec<u8> = self.opts.columns.iter().flat_map(|c| c.metadata()).collect();
+        body.extend(lenenc(meta.len()));
+        body.extend(meta);
+        body.extend(bitmap(cols, |idx| idx > 0));
+        self.write(timestamp, 19, &body)
+    }
+
+    fn string_value(&mut self, max_len: usize) -> Vec<u8> {
+        const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+        let len = self.rng.range(0, max_len as u64) as usize;
+        (0..len).map(|_| CHARS[self.rng.next() as usize % CHARS.len()]).collect()
+    }
+
+    fn datetime_value(&mut self, timestamp: u32) -> Vec<u8> {
+        let ts = timestamp as i64 - self.rng.range(0, 86400 * 365) as i64;
+        let t = chrono::Utc.timestamp_opt(ts, 0).unwrap();
+        let year_month = t.year() as u64 * 13 + t.month() as u64;
+        let packed = (1u64 << 39) | (year_month << 22) | ((t.day() as u64) << 17)
+            | ((t.hour() as u64) << 12) | ((t.minute() as u64) << 6) | t.second() as u64;
+        packed.to_be_bytes()[3..].to_vec()
+    }
+
+    //null bitmap + 各列的值
+    fn row_image(&mut self, id: u64, timestamp: u32) -> Vec<u8> {
+        let cols = self.opts.columns.len() + 1;
+        let nulls: Vec<bool> = (0..cols).map(|idx| idx > 0 && self.rng.range(0, 19) == 0).collect();
+        let mut image = bitmap(cols, |idx| nulls[idx]);
+        image.extend(readvalue::write_u64(id));
+        let opts = self.opts;
+        for (idx, kind) in opts.columns.iter().enumerate() {
+            if nulls[idx + 1] {
+                continue;
+            }
+            match kind {
+                ColumnKind::TinyInt => image.push(self.rng.next() as u8),
+                ColumnKind::Int => image.extend(readvalue::write_u32(self.rng.next() as u32)),
+                ColumnKind::BigInt => image.extend(readvalue::write_u64(self.rng.next())),
+                ColumnKind::Double => image.extend((self.rng.range(0, 100_000_000) as f64 / 100.0).to_le_bytes()),
+                ColumnKind::Varchar(n) => {
+                    let value = self.string_value((*n as usize).min(32));
+                    if kind.max_bytes() > 255 {
+                        image.extend(readvalue::write_u16(value.len() as u16));
+                    }else {
+                        image.push(value.len() as u8);
+                    }
+                    image.extend(value);
+                }
+                ColumnKind::Text => {
+                    let value = self.string_value(256);
+                    image.extend(readvalue::write_u16(value.len() as u16));
+                    image.extend(value);
+                }
+                ColumnKind::Datetime => {
+                    let value = self.datetime_value(timestamp);
+                    image.extend(value);
+                }
+            }
+        }
+        image
+    }
+
+    fn rows_event(&mut self, timestamp: u32, table: usize, kind: RowKind, rows: u32) -> io::Result<()> {
+        let cols = self.opts.columns.len() + 1;
+        let mut body = Self::table_id(table);
+        body.extend(readvalue::write_u16(1));          //STMT_END_F
+        body.extend(readvalue::write_u16(2));          //extra_data_length, 包含自身
+        body.extend(lenenc(cols));
+        body.extend(bitmap(cols, |_| true));
+        if kind == RowKind::Update {
+            body.extend(bitmap(cols, |_| true));
+        }
+        for _ in 0..rows {
+            let id = match kind {
+                RowKind::Insert => {
+                    self.next_id[table] += 1;
+                    self.next_id[table]
+                }
+                _ => self.rng.range(1, self.next_id[table])
+            };
+            let image = self.row_image(id, timestamp);
+            body.extend(image);
+            if kind == RowKind::Update {
+                let image = self.row_image(id, timestamp);
+                body.extend(image);
+            }
+        }
+        let type_code = match kind {
+            RowKind::Insert => 30,
+            RowKind::Update => 31,
+            RowKind::Delete => 32,
+        };
+        self.report.rows += rows as u64;
+        self.write(timestamp, type_code, &body)
+    }
+
+    fn create_table_sql(&self, table: usize) -> String {
+        let mut cols = vec![String::from("`id` bigint NOT NULL")];
+        for (idx, kind) in self.opts.columns.iter().enumerate() {
+            cols.push(format!("`c{}` {} DEFAULT NULL", idx + 1, kind.sql_type()));
+        }
+        cols.push(String::from("PRIMARY KEY (`id`)"));
+        format!("CREATE TABLE IF NOT EXISTS `t{}` ({}) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4", table + 1, cols.join(", "))
+    }
+
+    fn row_kind(&mut self, table: usize) -> RowKind {
+        let mix = self.opts.mix;
+        let pick = self.rng.range(1, mix.iter().sum::<u32>() as u64) as u32;
+        //表中还没有数据时只能insert
+        if pick <= mix[0] || self.next_id[table] == 0 {
+            RowKind::Insert
+        }else if pick <= mix[0] + mix[1] {
+            RowKind::Update
+        }else {
+            RowKind::Delete
+        }
+    }
+
+    fn transaction(&mut self, idx: u64) -> io::Result<()> {
+        let timestamp = self.opts.start_time + (idx / self.opts.rate as u64) as u32;
+        let table = self.rng.range(0, self.opts.tables as u64 - 1) as usize;
+        let kind = self.row_kind(table);
+        let rows = self.rng.range(self.opts.rows.0 as u64, self.opts.rows.1 as u64) as u32;
+        self.gtid(timestamp)?;
+        self.query(timestamp, "BEGIN")?;
+        self.table_map(timestamp, table)?;
+        self.rows_event(timestamp, table, kind, rows)?;
+        self.write(timestamp, 16, &readvalue::write_u64(self.sequence))?;
+        self.report.transactions += 1;
+        Ok(())
+    }
+}
+
+pub fn write_file(path: &str, opts: &SynthOptions) -> io::Result<SynthReport> {
+    let fde = encode::format_description(SERVER_VERSION, &encode::POST_HEADER_LENGTHS_57, opts.start_time, opts.server_id, true);
+    let mut rng = Rng::new(opts.seed);
+    let mut sid = [0u8; 16];
+    sid[..8].copy_from_slice(&rng.next().to_le_bytes());
+    sid[8..].copy_from_slice(&rng.next().to_le_bytes());
+    let mut gen = Generator{
+        opts,
+        writer: BinlogWriter::new(path, &fde)?,
+        rng,
+        sid,
+        gno: 0,
+        sequence: 0,
+        last_committed: 0,
+        group_left: 0,
+        next_id: vec![0; opts.tables],
+        report: SynthReport{ events: 1, bytes: 4 + fde.len() as u64, ..SynthReport::default() },
+    };
+
+    //PREVIOUS_GTIDS_LOG_EVENT, 没有之前的gtid
+    gen.write(opts.start_time, 35, &readvalue::write_u64(0))?;
+    //每个DDL单独作为一个提交组
+    for table in 0..opts.tables {
+        gen.group_left = 0;
+        gen.gtid(opts.start_time)?;
+        let sql = gen.create_table_sql(table);
+        gen.query(opts.start_time, &sql)?;
+    }
+    gen.group_left = 0;
+    for idx in 0..opts.transactions {
+        gen.transaction(idx)?;
+    }
+    let end_time = opts.start_time + (opts.transactions / opts.rate as u64) as u32;
+    gen.write(end_time, 3, &[])?;
+    Ok(gen.report)
+}
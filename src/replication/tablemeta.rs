@@ -0,0 +1,510 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//TABLE_MAP_EVENT中的可选metadata(8.0.1之后), binlog_row_metadata=FULL时包含字段名、enum/set的值、主键等
+//  位于null_bitmap之后, 每项为 type: 1bytes, length: packed integer, value: length bytes
+//      1  SIGNEDNESS: 数值类型字段是否为unsigned, 按位记录(高位在前)
+//      2  DEFAULT_CHARSET: 默认collation, 之后为(字符类型字段的序号, collation), 均为packed integer
+//      3  COLUMN_CHARSET: 每个字符类型字段的collation
+//      4  COLUMN_NAME: 每个字段名, 长度(packed integer) + 字段名
+//      5  SET_STR_VALUE / 6 ENUM_STR_VALUE: 每个set/enum字段的值的个数, 之后为每个值
+//      7  GEOMETRY_TYPE: 每个geometry字段的类型
+//      8  SIMPLE_PRIMARY_KEY: 主键字段的序号
+//      9  PRIMARY_KEY_WITH_PREFIX: (主键字段的序号, 前缀长度), 前缀长度为0时为整个字段
+//      10 ENUM_AND_SET_DEFAULT_CHARSET / 11 ENUM_AND_SET_COLUMN_CHARSET: 与2、3相同, 对应enum/set字段
+//      12 COLUMN_VISIBILITY: 字段是否可见, 按位记录(高位在前)
+//根据这些信息可以生成CREATE TABLE语句, 不需要连接源库查询information_schema
+//  没有默认值、自增、注释、二级索引等信息, 只用于初始化目标库的表结构
+
+use std::io;
+use crate::meta::ColumnTypeDict;
+use crate::readvalue;
+use crate::replication::readevent::{ColumnInfo, TableMap};
+
+const SIGNEDNESS: u8 = 1;
+const DEFAULT_CHARSET: u8 = 2;
+const COLUMN_CHARSET: u8 = 3;
+const COLUMN_NAME: u8 = 4;
+const SET_STR_VALUE: u8 = 5;
+const ENUM_STR_VALUE: u8 = 6;
+const GEOMETRY_TYPE: u8 = 7;
+const SIMPLE_PRIMARY_KEY: u8 = 8;
+const PRIMARY_KEY_WITH_PREFIX: u8 = 9;
+const ENUM_AND_SET_DEFAULT_CHARSET: u8 = 10;
+const ENUM_AND_SET_COLUMN_CHARSET: u8 = 11;
+const COLUMN_VISIBILITY: u8 = 12;
+
+//binary的collation, 字符类型字段为该collation时为binary/varbinary/blob
+pub(crate) const BINARY_COLLATION: u32 = 63;
+
+/// 字段的metadata, length为varchar/char的最大字节数或bit的位数, 其他类型为0
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMetadata{
+    pub name: Option<String>,
+    pub nullable: bool,
+    pub unsigned: bool,
+    pub collation: Option<u32>,
+    /// enum/set的值
+    pub values: Vec<String>,
+    pub geometry_type: Option<u32>,
+    pub visible: bool,
+    pub length: u32,
+}
+
+impl Default for ColumnMetadata{
+    fn default() -> ColumnMetadata {
+        ColumnMetadata{ name: None, nullable: true, unsigned: false, collation: None, values: vec![], geometry_type: None, visible: true, length: 0 }
+    }
+}
+
+/// TABLE_MAP_EVENT中的可选metadata, 没有可选部分(5.7或binlog_row_metadata未开启)时TableMap.metadata为None
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableMetadata{
+    pub columns: Vec<ColumnMetadata>,
+    pub default_collation: Option<u32>,
+    /// (字段序号, 前缀长度)
+    pub primary_key: Vec<(usize, usize)>,
+}
+
+//按顺序读取的字节
+struct Slice<'a>{
+    buf: &'a [u8],
+}
+
+impl<'a> Slice<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(readvalue::invalid_data(format!("table_map metadata长度错误, 需要{}bytes, 剩余{}bytes", len, self.buf.len())));
+        }
+        let (v, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(v)
+    }
+
+    fn packed(&mut self) -> io::Result<u64> {
+        let first = self.take(1)?[0];
+        Ok(match first {
+            0xfc => readvalue::read_u16(self.take(2)?) as u64,
+            0xfd => readvalue::read_u24(self.take(3)?) as u64,
+            0xfe => readvalue::read_u64(self.take(8)?),
+            0xfb | 0xff => return Err(readvalue::invalid_data(format!("table_map metadata中错误的packed integer: {:#x}", first))),
+            t => t as u64,
+        })
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.packed()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).to_string())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+}
+
+fn is_numeric(column_type: &ColumnTypeDict) -> bool {
+    matches!(column_type, ColumnTypeDict::MysqlTypeTiny | ColumnTypeDict::MysqlTypeShort | ColumnTypeDict::MysqlTypeInt24 |
+        ColumnTypeDict::MysqlTypeLong | ColumnTypeDict::MysqlTypeLonglong | ColumnTypeDict::MysqlTypeNewdecimal |
+        ColumnTypeDict::MysqlTypeDecimal | ColumnTypeDict::MysqlTypeFloat | ColumnTypeDict::MysqlTypeDouble)
+}
+
+fn is_character(column_type: &ColumnTypeDict) -> bool {
+    matches!(column_type, ColumnTypeDict::MysqlTypeString | ColumnTypeDict::MysqlTypeVarString | ColumnTypeDict::MysqlTypeVarchar |
+        ColumnTypeDict::MysqlTypeBlob | ColumnTypeDict::MysqlTypeTinyBlob | ColumnTypeDict::MysqlTypeMediumBlob | ColumnTypeDict::MysqlTypeLongBlob)
+}
+
+fn is_enum_or_set(column_type: &ColumnTypeDict) -> bool {
+    matches!(column_type, ColumnTypeDict::MysqlTypeEnum | ColumnTypeDict::MysqlTypeSet)
+}
+
+//高位在前的bitmap
+fn msb_bit(bits: &[u8], idx: usize) -> bool {
+    bits.get(idx / 8).map(|b| b & (0x80 >> (idx % 8)) != 0).unwrap_or(false)
+}
+
+fn msb_bitmap(bits: usize, set: impl Fn(usize) -> bool) -> Vec<u8> {
+    let mut map = vec![0u8; bits.div_ceil(8)];
+    for idx in (0..bits).filter(|idx| set(*idx)) {
+        map[idx / 8] |= 0x80 >> (idx % 8);
+    }
+    map
+}
+
+/// 从TABLE_MAP_EVENT的metadata部分读取varchar/char的最大字节数及bit的位数, types为事件中的原始类型码
+pub(crate) fn column_lengths(types: &[u8], meta: &[u8]) -> io::Result<Vec<u32>> {
+    let mut buf = Slice{ buf: meta };
+    let mut lengths = vec![];
+    for code in types {
+        let length = match code {
+            15 | 253 => readvalue::read_u16(buf.take(2)?) as u32,
+            254 | 247 | 248 => {
+                let v = buf.take(2)?;
+                //长度大于255的char, 长度的高两位保存在类型码中; enum、set的metadata为值占用的字节数, 不记录
+                if v[0] & 0x30 != 0x30 {
+                    (v[1] as u32) | ((((v[0] & 0x30) ^ 0x30) as u32) << 4)
+                } else if v[0] == 247 || v[0] == 248 || *code != 254 {
+                    0
+                } else {
+                    v[1] as u32
+                }
+            }
+            16 => {
+                let v = buf.take(2)?;
+                v[1] as u32 * 8 + v[0] as u32
+            }
+            246 => {
+                buf.take(2)?;
+                0
+            }
+            4 | 5 | 17 | 18 | 19 | 245 | 249 | 250 | 251 | 252 | 255 => {
+                buf.take(1)?;
+                0
+            }
+            t if matches!(ColumnTypeDict::from_type_code(t), ColumnTypeDict::UnknowType(_)) => {
+                buf.take(crate::replication::opaque::meta_len(*t)?)?;
+                0
+            }
+            _ => 0
+        };
+        lengths.push(length);
+    }
+    Ok(lengths)
+}
+
+impl TableMetadata{
+    /// nullable为table_map中的null_bitmap(低位在前), optional为null_bitmap之后的可选部分(不包含checksum)
+    pub(crate) fn read(columns: &[ColumnInfo], lengths: &[u32], nullable: &[u8], optional: &[u8]) -> io::Result<TableMetadata> {
+        let mut metadata = TableMetadata{
+            columns: (0..columns.len()).map(|idx| ColumnMetadata{
+                nullable: nullable.get(idx / 8).map(|b| b & (1 << (idx % 8)) != 0).unwrap_or(true),
+                length: lengths.get(idx).copied().unwrap_or(0),
+                ..Default::default()
+            }).collect(),
+            ..Default::default()
+        };
+        let index = |f: fn(&ColumnTypeDict) -> bool| -> Vec<usize> {
+            columns.iter().enumerate().filter(|(_, c)| f(&c.column_type)).map(|(idx, _)| idx).collect()
+        };
+        let numeric = index(is_numeric);
+        let character = index(is_character);
+        let enum_set = index(is_enum_or_set);
+        let sets = index(|t| matches!(t, ColumnTypeDict::MysqlTypeSet));
+        let enums = index(|t| matches!(t, ColumnTypeDict::MysqlTypeEnum));
+        let geometry = index(|t| matches!(t, ColumnTypeDict::MysqlTypeGeometry));
+
+        let mut buf = Slice{ buf: optional };
+        while !buf.is_empty() {
+            let field_type = buf.take(1)?[0];
+            let len = buf.packed()? as usize;
+            let value = buf.take(len)?;
+            let mut field = Slice{ buf: value };
+            match field_type {
+                SIGNEDNESS => {
+                    for (n, idx) in numeric.iter().enumerate() {
+                        metadata.columns[*idx].unsigned = msb_bit(value, n);
+                    }
+                }
+                DEFAULT_CHARSET | ENUM_AND_SET_DEFAULT_CHARSET => {
+                    let targets = if field_type == DEFAULT_CHARSET { &character } else { &enum_set };
+                    let default = field.packed()? as u32;
+                    for idx in targets {
+                        metadata.columns[*idx].collation = Some(default);
+                    }
+                    while !field.is_empty() {
+                        let n = field.packed()? as usize;
+                        let collation = field.packed()? as u32;
+                        let idx = targets.get(n).ok_or_else(|| readvalue::invalid_data(format!("table_map metadata中的字段序号{}错误", n)))?;
+                        metadata.columns[*idx].collation = Some(collation);
+                    }
+                    if field_type == DEFAULT_CHARSET {
+                        metadata.default_collation = Some(default);
+                    }
+                }
+                COLUMN_CHARSET | ENUM_AND_SET_COLUMN_CHARSET => {
+                    let targets = if field_type == COLUMN_CHARSET { &character } else { &enum_set };
+                    for idx in targets {
+                        metadata.columns[*idx].collation = Some(field.packed()? as u32);
+                    }
+                }
+                COLUMN_NAME => {
+                    for column in metadata.columns.iter_mut() {
+                        column.name = Some(field.string()?);
+                    }
+                }
+                SET_STR_VALUE | ENUM_STR_VALUE => {
+                    let targets = if field_type == SET_STR_VALUE { &sets } else { &enums };
+                    for idx in targets {
+                        let count = field.packed()? as usize;
+                        metadata.columns[*idx].values = (0..count).map(|_| field.string()).collect::<io::Result<_>>()?;
+                    }
+                }
+                GEOMETRY_TYPE => {
+                    for idx in &geometry {
+                        metadata.columns[*idx].geometry_type = Some(field.packed()? as u32);
+                    }
+                }
+                SIMPLE_PRIMARY_KEY | PRIMARY_KEY_WITH_PREFIX => {
+                    while !field.is_empty() {
+                        let idx = field.packed()? as usize;
+                        let prefix = if field_type == PRIMARY_KEY_WITH_PREFIX { field.packed()? as usize } else { 0 };
+                        if idx >= columns.len() {
+                            return Err(readvalue::invalid_data(format!("table_map metadata中的主键字段序号{}错误", idx)));
+                        }
+                        metadata.primary_key.push((idx, prefix));
+                    }
+                }
+                COLUMN_VISIBILITY => {
+                    for (idx, column) in metadata.columns.iter_mut().enumerate() {
+                        column.visible = msb_bit(value, idx);
+                    }
+                }
+                //之后版本新增的类型, 按长度跳过
+                _ => {}
+            }
+        }
+        Ok(metadata)
+    }
+
+    /// 编码为null_bitmap及可选部分, 与read对应
+    pub(crate) fn write(&self, columns: &[ColumnInfo]) -> Vec<u8> {
+        let cols = &self.columns;
+        let mut body = crate::replication::encode::bitmap(cols.len(), |idx| cols[idx].nullable);
+        let mut field = |field_type: u8, value: Vec<u8>| {
+            body.push(field_type);
+            body.extend(crate::replication::encode::lenenc(value.len()));
+            body.extend(value);
+        };
+        let index = |f: fn(&ColumnTypeDict) -> bool| -> Vec<usize> {
+            columns.iter().enumerate().filter(|(_, c)| f(&c.column_type)).map(|(idx, _)| idx).collect()
+        };
+        let packed = |v: usize| crate::replication::encode::lenenc(v);
+        let string = |v: &str| {
+            let mut s = packed(v.len());
+            s.extend_from_slice(v.as_bytes());
+            s
+        };
+
+        let numeric = index(is_numeric);
+        if !numeric.is_empty() {
+            field(SIGNEDNESS, msb_bitmap(numeric.len(), |n| cols[numeric[n]].unsigned));
+        }
+        for (targets, default_type, column_type) in [(index(is_character), DEFAULT_CHARSET, COLUMN_CHARSET),
+                                                     (index(is_enum_or_set), ENUM_AND_SET_DEFAULT_CHARSET, ENUM_AND_SET_COLUMN_CHARSET)] {
+            let collations: Vec<Option<u32>> = targets.iter().map(|idx| cols[*idx].collation).collect();
+            if collations.is_empty() || collations.iter().any(|c| c.is_none()) {
+                continue;
+            }
+            match self.default_collation {
+                Some(default) => {
+                    let mut value = packed(default as usize);
+                    for (n, collation) in collations.iter().enumerate().filter(|(_, c)| **c != Some(default)) {
+                        value.extend(packed(n));
+                        value.extend(packed(collation.unwrap_or(default) as usize));
+                    }
+                    field(default_type, value);
+                }
+                None => field(column_type, collations.iter().flat_map(|c| packed(c.unwrap_or(0) as usize)).collect()),
+            }
+        }
+        if cols.iter().all(|c| c.name.is_some()) {
+            field(COLUMN_NAME, cols.iter().flat_map(|c| string(c.name.as_deref().unwrap_or(""))).collect());
+        }
+        for (kind, field_type) in [(ColumnTypeDict::MysqlTypeSet, SET_STR_VALUE), (ColumnTypeDict::MysqlTypeEnum, ENUM_STR_VALUE)] {
+            let targets: Vec<usize> = columns.iter().enumerate()
+                .filter(|(_, c)| std::mem::discriminant(&c.column_type) == std::mem::discriminant(&kind)).map(|(idx, _)| idx).collect();
+            if targets.is_empty() {
+                continue;
+            }
+            let mut value = vec![];
+            for idx in targets {
+                value.extend(packed(cols[idx].values.len()));
+                for v in &cols[idx].values {
+                    value.extend(string(v));
+                }
+            }
+            field(field_type, value);
+        }
+        let geometry = index(|t| matches!(t, ColumnTypeDict::MysqlTypeGeometry));
+        if !geometry.is_empty() {
+            field(GEOMETRY_TYPE, geometry.iter().flat_map(|idx| packed(cols[*idx].geometry_type.unwrap_or(0) as usize)).collect());
+        }
+        if !self.primary_key.is_empty() {
+            if self.primary_key.iter().all(|(_, prefix)| *prefix == 0) {
+                field(SIMPLE_PRIMARY_KEY, self.primary_key.iter().flat_map(|(idx, _)| packed(*idx)).collect());
+            } else {
+                field(PRIMARY_KEY_WITH_PREFIX, self.primary_key.iter().flat_map(|(idx, prefix)| [packed(*idx), packed(*prefix)].concat()).collect());
+            }
+        }
+        if cols.iter().any(|c| !c.visible) {
+            field(COLUMN_VISIBILITY, msb_bitmap(cols.len(), |idx| cols[idx].visible));
+        }
+        body
+    }
+}
+
+//collation编号对应的名称、字符集及每个字符的最大字节数, 只包含常用的部分
+const COLLATIONS: [(u32, &str, &str, u32); 24] = [
+    (1, "big5_chinese_ci", "big5", 2),
+    (8, "latin1_swedish_ci", "latin1", 1),
+    (11, "ascii_general_ci", "ascii", 1),
+    (24, "gb2312_chinese_ci", "gb2312", 2),
+    (28, "gbk_chinese_ci", "gbk", 2),
+    (33, "utf8_general_ci", "utf8", 3),
+    (45, "utf8mb4_general_ci", "utf8mb4", 4),
+    (46, "utf8mb4_bin", "utf8mb4", 4),
+    (47, "latin1_bin", "latin1", 1),
+    (48, "latin1_general_ci", "latin1", 1),
+    (63, "binary", "binary", 1),
+    (65, "ascii_bin", "ascii", 1),
+    (76, "utf8_tolower_ci", "utf8", 3),
+    (83, "utf8_bin", "utf8", 3),
+    (86, "gb2312_bin", "gb2312", 2),
+    (87, "gbk_bin", "gbk", 2),
+    (192, "utf8_unicode_ci", "utf8", 3),
+    (224, "utf8mb4_unicode_ci", "utf8mb4", 4),
+    (246, "utf8mb4_unicode_520_ci", "utf8mb4", 4),
+    (248, "gb18030_chinese_ci", "gb18030", 4),
+    (249, "gb18030_bin", "gb18030", 4),
+    (255, "utf8mb4_0900_ai_ci", "utf8mb4", 4),
+    (305, "utf8mb4_0900_as_cs", "utf8mb4", 4),
+    (309, "utf8mb4_0900_bin", "utf8mb4", 4),
+];
+
+/// collation编号对应的(名称, 字符集, 每个字符的最大字节数)
+pub fn collation(id: u32) -> Option<(&'static str, &'static str, u32)> {
+    COLLATIONS.iter().find(|c| c.0 == id).map(|c| (c.1, c.2, c.3))
+}
+
+fn quote(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+fn quote_value(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+fn geometry_name(geometry_type: Option<u32>) -> &'static str {
+    match geometry_type {
+        Some(1) => "point",
+        Some(2) => "linestring",
+        Some(3) => "polygon",
+        Some(4) => "multipoint",
+        Some(5) => "multilinestring",
+        Some(6) => "multipolygon",
+        Some(7) => "geometrycollection",
+        _ => "geometry",
+    }
+}
+
+//带小数秒精度的时间类型
+fn with_fsp(name: &str, fsp: usize) -> String {
+    if fsp == 0 { name.to_string() } else { format!("{}({})", name, fsp) }
+}
+
+//字段类型, 字符类型的字段同时返回需要单独指定的collation
+fn column_type(info: &ColumnInfo, column: &ColumnMetadata, default_collation: Option<u32>) -> Result<String, String> {
+    let meta = |idx: usize| info.column_meta.get(idx).copied().unwrap_or(0);
+    let binary = column.collation == Some(BINARY_COLLATION);
+    //字符数 = 最大字节数 / 每个字符的最大字节数
+    let chars = |length: u32| -> Result<u32, String> {
+        match column.collation {
+            None => Ok(length),
+            Some(id) => collation(id).map(|(_, _, maxlen)| length / maxlen).ok_or_else(|| format!("不支持的collation: {}", id)),
+        }
+    };
+    let mut name = match info.column_type {
+        ColumnTypeDict::MysqlTypeTiny => String::from("tinyint"),
+        ColumnTypeDict::MysqlTypeShort => String::from("smallint"),
+        ColumnTypeDict::MysqlTypeInt24 => String::from("mediumint"),
+        ColumnTypeDict::MysqlTypeLong => String::from("int"),
+        ColumnTypeDict::MysqlTypeLonglong => String::from("bigint"),
+        ColumnTypeDict::MysqlTypeFloat => String::from("float"),
+        ColumnTypeDict::MysqlTypeDouble => String::from("double"),
+        ColumnTypeDict::MysqlTypeNewdecimal => format!("decimal({},{})", meta(0), meta(1)),
+        ColumnTypeDict::MysqlTypeYear => String::from("year"),
+        ColumnTypeDict::MysqlTypeDate => String::from("date"),
+        ColumnTypeDict::MysqlTypeTime => String::from("time"),
+        ColumnTypeDict::MysqlTypeDatetime => String::from("datetime"),
+        ColumnTypeDict::MysqlTypeTimestamp => String::from("timestamp"),
+        ColumnTypeDict::MysqlTypeTime2 => with_fsp("time", meta(0)),
+        ColumnTypeDict::MysqlTypeDatetime2 => with_fsp("datetime", meta(0)),
+        ColumnTypeDict::MysqlTypeTimestamp2 => with_fsp("timestamp", meta(0)),
+        ColumnTypeDict::MysqlTypeJson => String::from("json"),
+        ColumnTypeDict::MysqlTypeBit => format!("bit({})", column.length),
+        ColumnTypeDict::MysqlTypeVarchar |
+        ColumnTypeDict::MysqlTypeVarString => {
+            if binary { format!("varbinary({})", column.length) } else { format!("varchar({})", chars(column.length)?) }
+        }
+        ColumnTypeDict::MysqlTypeString => {
+            if binary { format!("binary({})", column.length) } else { format!("char({})", chars(column.length)?) }
+        }
+        ColumnTypeDict::MysqlTypeBlob |
+        ColumnTypeDict::MysqlTypeTinyBlob |
+        ColumnTypeDict::MysqlTypeMediumBlob |
+        ColumnTypeDict::MysqlTypeLongBlob => {
+            //metadata为长度占用的字节数
+            let size = match meta(0) {
+                1 => "tiny",
+                3 => "medium",
+                4 => "long",
+                _ => "",
+            };
+            format!("{}{}", size, if binary { "blob" } else { "text" })
+        }
+        ColumnTypeDict::MysqlTypeEnum => format!("enum({})", column.values.iter().map(|v| quote_value(v)).collect::<Vec<String>>().join(",")),
+        ColumnTypeDict::MysqlTypeSet => format!("set({})", column.values.iter().map(|v| quote_value(v)).collect::<Vec<String>>().join(",")),
+        ColumnTypeDict::MysqlTypeGeometry => geometry_name(column.geometry_type).to_string(),
+        ref t => return Err(format!("不支持的字段类型: {:?}", t)),
+    };
+    if column.unsigned {
+        name.push_str(" unsigned");
+    }
+    //与表的默认字符集不同时单独指定
+    if let Some(id) = column.collation.filter(|id| *id != BINARY_COLLATION && Some(*id) != default_collation) {
+        let (collation_name, charset, _) = collation(id).ok_or_else(|| format!("不支持的collation: {}", id))?;
+        name.push_str(&format!(" CHARACTER SET {} COLLATE {}", charset, collation_name));
+    }
+    Ok(name)
+}
+
+impl TableMap{
+    /// 根据binlog_row_metadata=FULL时的可选metadata生成CREATE TABLE语句
+    /// 没有默认值、自增、注释、二级索引等信息; 没有字段名(binlog_row_metadata=MINIMAL或5.7)时返回错误
+    pub fn create_table(&self) -> Result<String, String> {
+        let metadata = self.metadata.as_ref()
+            .ok_or_else(|| format!("{}.{}的TABLE_MAP中没有可选metadata, 需要binlog_row_metadata=FULL", self.database_name, self.table_name))?;
+        let mut lines = vec![];
+        for (info, column) in self.column_info.iter().zip(metadata.columns.iter()) {
+            let name = column.name.as_ref()
+                .ok_or_else(|| format!("{}.{}的TABLE_MAP中没有字段名, 需要binlog_row_metadata=FULL", self.database_name, self.table_name))?;
+            let mut line = format!("  {} {}", quote(name), column_type(info, column, metadata.default_collation)?);
+            line.push_str(if column.nullable { " NULL" } else { " NOT NULL" });
+            if !column.visible {
+                line.push_str(" /*!80023 INVISIBLE */");
+            }
+            lines.push(line);
+        }
+        if !metadata.primary_key.is_empty() {
+            let mut keys = vec![];
+            for (idx, prefix) in &metadata.primary_key {
+                let name = metadata.columns[*idx].name.as_deref().unwrap_or("");
+                //前缀长度为字节数, 按字符数输出
+                let prefix = match metadata.columns[*idx].collation.and_then(collation) {
+                    Some((_, _, maxlen)) if *prefix > 0 => prefix / maxlen as usize,
+                    _ => *prefix,
+                };
+                keys.push(if prefix > 0 { format!("{}({})", quote(name), prefix) } else { quote(name) });
+            }
+            lines.push(format!("  PRIMARY KEY ({})", keys.join(",")));
+        }
+        let mut sql = format!("CREATE TABLE IF NOT EXISTS {}.{} (\n{}\n)", quote(&self.database_name), quote(&self.table_name), lines.join(",\n"));
+        if let Some(id) = metadata.default_collation.filter(|id| *id != BINARY_COLLATION) {
+            let (collation_name, charset, _) = collation(id).ok_or_else(|| format!("不支持的collation: {}", id))?;
+            sql.push_str(&format!(" DEFAULT CHARSET={} COLLATE={}", charset, collation_name));
+        }
+        sql.push(';');
+        Ok(sql)
+    }
+}
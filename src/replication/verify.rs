@@ -0,0 +1,214 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/28
+*/
+
+#[cfg(feature = "os")]
+use std::fs::File;
+use std::io::{self, Read};
+#[cfg(feature = "os")]
+use std::io::BufReader;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::readvalue;
+use crate::replication::copy;
+use crate::warning::{self, WarningKind};
+
+bitflags! {
+    //需要校验checksum的事件类型, 高吞吐时可以只校验部分事件降低cpu消耗
+    pub struct ChecksumClass: u8 {
+        const ROWS = 0b0001;            //WRITE/UPDATE/DELETE_ROWS_EVENT
+        const TABLE_MAP = 0b0010;
+        const QUERY = 0b0100;           //QUERY_EVENT、ROWS_QUERY_EVENT
+        const CONTROL = 0b1000;         //其余事件: GTID、XID、ROTATE、FORMAT_DESCRIPTION等
+    }
+}
+
+impl ChecksumClass{
+    //逗号分隔的rows、table_map、query、control, 或者all、none
+    pub fn parse(spec: &str) -> Result<ChecksumClass, String> {
+        let mut classes = ChecksumClass::empty();
+        for name in spec.split(',').map(|v| v.trim().to_lowercase()) {
+            classes |= match name.as_str() {
+                "all" => ChecksumClass::all(),
+                "none" | "" => ChecksumClass::empty(),
+                "rows" => ChecksumClass::ROWS,
+                "table_map" => ChecksumClass::TABLE_MAP,
+                "query" => ChecksumClass::QUERY,
+                "control" => ChecksumClass::CONTROL,
+                t => return Err(format!("无效的checksum事件类型: {}, 可选值为rows、table_map、query、control、all、none", t))
+            };
+        }
+        Ok(classes)
+    }
+
+    //事件包头中的type_code所属的类型
+    pub fn of(type_code: u8) -> ChecksumClass {
+        match type_code {
+            20..=25 | 30..=32 | 39 => ChecksumClass::ROWS,
+            19 => ChecksumClass::TABLE_MAP,
+            2 | 29 => ChecksumClass::QUERY,
+            _ => ChecksumClass::CONTROL
+        }
+    }
+}
+
+//--lenient-checksum: 解析时checksum错误只记录警告并继续, 事件内容可能已损坏; verify子命令不受影响
+static LENIENT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_lenient(lenient: bool) {
+    LENIENT.store(lenient, Ordering::Relaxed);
+}
+
+pub fn is_lenient() -> bool {
+    LENIENT.load(Ordering::Relaxed)
+}
+
+//按事件顺序校验, FORMAT_DESCRIPTION_EVENT决定后续事件是否带有checksum
+#[derive(Debug, Clone)]
+pub struct ChecksumVerifier{
+    classes: ChecksumClass,
+    checksum: bool,
+}
+
+impl ChecksumVerifier{
+    pub fn new(classes: ChecksumClass) -> ChecksumVerifier {
+        ChecksumVerifier{ classes, checksum: false }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.classes.is_empty()
+    }
+
+    /// 校验一个事件, checksum错误时返回InvalidData, --lenient-checksum时记录为警告后继续
+    pub fn verify(&mut self, header: &[u8], payload: &[u8], log_pos: u64) -> io::Result<()> {
+        if !self.enabled() || self.check(header, payload) {
+            return Ok(());
+        }
+        let msg = format!("position {} 处的事件checksum错误", log_pos);
+        if is_lenient() {
+            warning::emit(WarningKind::ChecksumMismatch, msg);
+            return Ok(());
+        }
+        Err(readvalue::invalid_data(msg))
+    }
+
+    //返回false表示checksum错误, 未启用checksum或不需要校验的事件返回true
+    pub fn check(&mut self, header: &[u8], payload: &[u8]) -> bool {
+        if header.len() < 19 {
+            return true;
+        }
+        if header[4] == 15 {
+            self.checksum = event_checksum_ok(header, payload);
+            return true;
+        }
+        if !self.checksum || payload.len() < 4 || !self.classes.contains(ChecksumClass::of(header[4])) {
+            return true;
+        }
+        event_checksum_ok(header, payload)
+    }
+}
+
+//header为19bytes的事件包头, payload的最后4bytes为crc32
+pub fn event_checksum_ok(header: &[u8], payload: &[u8]) -> bool {
+    if payload.len() < 4 {
+        return false;
+    }
+    let (data, crc) = payload.split_at(payload.len() - 4);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(header);
+    hasher.update(data);
+    hasher.finalize() == readvalue::read_u32(crc)
+}
+
+/*
+校验binlog文件的完整性, 不需要连接数据库:
+    每个事件的长度是否完整, 文件末尾是否被截断
+    启用checksum时每个事件最后4bytes的crc32是否正确, 可以只校验部分类型的事件
+    事件包头中的next_position是否与实际位置一致(relay log中保存的是master的位置, 不做校验)
+*/
+#[derive(Debug, Default)]
+pub struct VerifyReport{
+    pub events: u64,
+    pub checksum: bool,
+    pub verified: u64,                  //校验了checksum的事件数
+    pub checksum_errors: Vec<u64>,      //crc32错误的事件起始位置
+    pub position_errors: Vec<u64>,      //next_position错误的事件起始位置
+    pub truncated: Option<u64>,         //文件在该position处被截断
+}
+
+impl VerifyReport{
+    pub fn is_ok(&self) -> bool {
+        self.checksum_errors.is_empty() && self.position_errors.is_empty() && self.truncated.is_none()
+    }
+}
+
+#[cfg(feature = "os")]
+pub fn verify_file(path: &str, relaylog: bool, classes: ChecksumClass) -> io::Result<VerifyReport> {
+    let reader = BufReader::new(File::open(path)?);
+    verify_reader(reader, relaylog, classes).map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidData => io::Error::new(io::ErrorKind::InvalidData, format!("{} {}", path, e)),
+        _ => e
+    })
+}
+
+/// 与verify_file相同, 从任意数据源读取(例如内存中的文件内容)
+pub fn verify_reader<R: Read>(mut reader: R, relaylog: bool, classes: ChecksumClass) -> io::Result<VerifyReport> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != [0xFE,0x62,0x69,0x6E] {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "不是binlog文件"));
+    }
+
+    let mut report = VerifyReport::default();
+    let mut position = 4u64;
+    loop {
+        let mut event = vec![0u8; 19];
+        let read = read_full(&mut reader, &mut event)?;
+        if read == 0 {
+            break;
+        }
+        let event_length = if read == 19 { readvalue::read_u32(&event[9..13]) as usize } else { 0 };
+        if event_length < 19 {
+            report.truncated = Some(position);
+            break;
+        }
+        //event_length可能已损坏, 按实际读取到的数据分配内存
+        (&mut reader).take(event_length as u64 - 19).read_to_end(&mut event)?;
+        if event.len() < event_length {
+            report.truncated = Some(position);
+            break;
+        }
+
+        //FORMAT_DESCRIPTION_EVENT决定后续事件是否带有checksum, relay log中会有多个
+        if event[4] == 15 {
+            report.checksum = copy::has_checksum(&event);
+        }else if report.checksum && event_length >= 23 && classes.contains(ChecksumClass::of(event[4])) {
+            report.verified += 1;
+            if !event_checksum_ok(&event[..19], &event[19..]) {
+                report.checksum_errors.push(position);
+            }
+        }
+
+        let next_position = readvalue::read_u32(&event[13..17]) as u64;
+        if !relaylog && next_position != 0 && next_position != position + event_length as u64 {
+            report.position_errors.push(position);
+        }
+
+        report.events += 1;
+        position += event_length as u64;
+    }
+    Ok(report)
+}
+
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(read)
+}
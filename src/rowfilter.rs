@@ -0,0 +1,348 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//行数据过滤: 按字段值过滤行事件中的数据, 只输出满足条件的行
+//  条件格式: [db.]tbl.col 比较符 值, 多个条件用and连接, 例如
+//      orders.status != 'test' and users.country in ('DE','FR') and shop.users.deleted_at is null
+//  比较符: = != <> > >= < <= in (...) not in (...) is null is not null
+//  值不带引号时按数字比较(decimal精确比较), 带引号时按输出的文本比较(时间类型与--timezone、--timeformat的输出一致)
+//  只对条件中出现的表生效, 同一个表的多个条件都满足时才输出, 与null比较(除is null外)均不满足
+//  insert、delete按行判断, update的前后镜像任意一个满足时输出这一对
+//  条件在每个表第一次出现时按字段名编译为字段下标, DDL之后重新编译
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+use bigdecimal::BigDecimal;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::BinlogEvent;
+use crate::replication::parsevalue::{MySQLValue, RowValue};
+use crate::stdout::format::{BinaryEncoding, ValueFormat};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token{
+    Word(String),
+    Str(String),
+    Op(String),
+    Symbol(char),
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = expr.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    //两个连续的引号表示引号本身
+                    Some(t) if t == c && chars.peek() == Some(&c) => {
+                        chars.next();
+                        value.push(c);
+                    }
+                    Some(t) if t == c => break,
+                    Some(t) => value.push(t),
+                    None => return Err(format!("引号不匹配: {}", expr))
+                }
+            }
+            tokens.push(Token::Str(value));
+        } else if "=!<>".contains(c) {
+            let mut op = String::new();
+            while let Some(&t) = chars.peek() {
+                if !"=!<>".contains(t) {
+                    break;
+                }
+                op.push(t);
+                chars.next();
+            }
+            tokens.push(Token::Op(op));
+        } else if "(),".contains(c) {
+            chars.next();
+            tokens.push(Token::Symbol(c));
+        } else {
+            let mut word = String::new();
+            while let Some(&t) = chars.peek() {
+                if t.is_whitespace() || "=!<>'\"(),".contains(t) {
+                    break;
+                }
+                if t != '`' {
+                    word.push(t);
+                }
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op{
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Op{
+    fn new(op: &str) -> Result<Op, String> {
+        match op {
+            "=" | "==" => Ok(Op::Eq),
+            "!=" | "<>" => Ok(Op::Ne),
+            ">" => Ok(Op::Gt),
+            ">=" => Ok(Op::Ge),
+            "<" => Ok(Op::Lt),
+            "<=" => Ok(Op::Le),
+            _ => Err(format!("无效的比较符: {}", op))
+        }
+    }
+
+    fn matches(&self, ord: Ordering) -> bool {
+        match self {
+            Op::Eq => ord == Ordering::Equal,
+            Op::Ne => ord != Ordering::Equal,
+            Op::Gt => ord == Ordering::Greater,
+            Op::Ge => ord != Ordering::Less,
+            Op::Lt => ord == Ordering::Less,
+            Op::Le => ord != Ordering::Greater,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal{
+    Number(BigDecimal),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Test{
+    Compare(Op, Literal),
+    In(Vec<Literal>, bool),     //bool为not in
+    IsNull(bool),               //bool为is not null
+}
+
+//一个条件, table不带库名时只比较表名
+#[derive(Debug, Clone, PartialEq)]
+struct Predicate{
+    table: String,
+    column: String,
+    test: Test,
+}
+
+impl Predicate{
+    fn applies_to(&self, db_tbl: &str) -> bool {
+        if self.table.contains('.') {
+            self.table == db_tbl
+        } else {
+            db_tbl.split_once('.').map(|(_, tbl)| tbl) == Some(self.table.as_str())
+        }
+    }
+}
+
+fn word_is(token: Option<&Token>, word: &str) -> bool {
+    matches!(token, Some(Token::Word(t)) if t.eq_ignore_ascii_case(word))
+}
+
+fn literal(token: Option<&Token>) -> Result<Literal, String> {
+    match token {
+        Some(Token::Str(t)) => Ok(Literal::Text(t.clone())),
+        Some(Token::Word(t)) => match BigDecimal::from_str(t) {
+            Ok(n) => Ok(Literal::Number(n)),
+            Err(_) => Err(format!("无效的值: {}, 字符串需要加引号", t))
+        }
+        _ => Err(String::from("条件中缺少值"))
+    }
+}
+
+fn parse(expr: &str) -> Result<Vec<Predicate>, String> {
+    let tokens = tokenize(expr)?;
+    let mut predicates = vec![];
+    let mut idx = 0;
+    while idx < tokens.len() {
+        let (table, column) = match tokens.get(idx) {
+            Some(Token::Word(t)) => match t.rsplit_once('.') {
+                Some((table, column)) if !table.is_empty() && !column.is_empty() => (table.to_string(), column.to_string()),
+                _ => return Err(format!("字段需要带上表名: {}, 例如 orders.status", t))
+            }
+            _ => return Err(format!("条件格式错误: {}", expr))
+        };
+        idx += 1;
+        let test = match tokens.get(idx) {
+            Some(Token::Op(op)) => {
+                let op = Op::new(op)?;
+                idx += 1;
+                Test::Compare(op, literal(tokens.get(idx))?)
+            }
+            Some(Token::Word(t)) if t.eq_ignore_ascii_case("is") => {
+                let not = word_is(tokens.get(idx + 1), "not");
+                if not {
+                    idx += 1;
+                }
+                idx += 1;
+                if !word_is(tokens.get(idx), "null") {
+                    return Err(String::from("is之后只能为null或not null"));
+                }
+                Test::IsNull(not)
+            }
+            Some(Token::Word(t)) if t.eq_ignore_ascii_case("in") || t.eq_ignore_ascii_case("not") => {
+                let not = t.eq_ignore_ascii_case("not");
+                if not {
+                    idx += 1;
+                    if !word_is(tokens.get(idx), "in") {
+                        return Err(String::from("not之后只能为in"));
+                    }
+                }
+                idx += 1;
+                if tokens.get(idx) != Some(&Token::Symbol('(')) {
+                    return Err(String::from("in之后需要(...)"));
+                }
+                let mut values = vec![];
+                loop {
+                    idx += 1;
+                    values.push(literal(tokens.get(idx))?);
+                    idx += 1;
+                    match tokens.get(idx) {
+                        Some(Token::Symbol(',')) => continue,
+                        Some(Token::Symbol(')')) => break,
+                        _ => return Err(String::from("in的值列表格式错误, 例如: in ('DE','FR')"))
+                    }
+                }
+                Test::In(values, not)
+            }
+            _ => return Err(format!("{}.{}之后缺少比较符", table, column))
+        };
+        predicates.push(Predicate{ table, column, test });
+        idx += 1;
+        if idx < tokens.len() {
+            if !word_is(tokens.get(idx), "and") {
+                return Err(String::from("多个条件只能用and连接"));
+            }
+            idx += 1;
+            if idx == tokens.len() {
+                return Err(String::from("and之后缺少条件"));
+            }
+        }
+    }
+    if predicates.is_empty() {
+        return Err(String::from("过滤条件为空"));
+    }
+    Ok(predicates)
+}
+
+//编译后的条件: 字段下标及字段类型
+struct Compiled{
+    index: usize,
+    col_type: String,
+    test: Test,
+}
+
+/// 按字段值过滤行数据
+pub struct RowFilter{
+    predicates: Vec<Predicate>,
+    compiled: HashMap<String, Vec<Compiled>>,
+    format: ValueFormat,
+}
+
+impl RowFilter{
+    /// format决定带引号的值与哪种文本比较, 通常与输出时使用的一致
+    pub fn new(expr: &str, format: ValueFormat) -> Result<RowFilter, String> {
+        Ok(RowFilter{ predicates: parse(expr)?, compiled: HashMap::new(), format })
+    }
+
+    /// 是否有条件作用于该表(db.tbl)
+    pub fn applies_to(&self, db_tbl: &str) -> bool {
+        self.predicates.iter().any(|p| p.applies_to(db_tbl))
+    }
+
+    /// 表结构可能变化(DDL)时清除已编译的条件
+    pub fn reset(&mut self) {
+        self.compiled.clear();
+    }
+
+    //按字段名找到下标, cols为meta::get_col获取的字段信息, 没有表结构时字段名为@1、@2...
+    fn compile(&mut self, db_tbl: &str, cols: &[HashMap<String, String>]) -> Result<(), String> {
+        if !self.compiled.contains_key(db_tbl) {
+            let mut compiled = vec![];
+            for p in self.predicates.iter().filter(|p| p.applies_to(db_tbl)) {
+                let index = cols.iter().position(|c| c.get("COLUMN_NAME").is_some_and(|n| n.eq_ignore_ascii_case(&p.column)))
+                    .ok_or_else(|| format!("过滤条件中的字段{}在表{}中不存在", p.column, db_tbl))?;
+                let col_type = cols[index].get("COLUMN_TYPE").cloned().unwrap_or_default();
+                compiled.push(Compiled{ index, col_type, test: p.test.clone() });
+            }
+            self.compiled.insert(db_tbl.to_string(), compiled);
+        }
+        Ok(())
+    }
+
+    /// 过滤行事件, 返回None时整个事件都不需要输出, 没有条件作用于该表或不是行事件时原样返回
+    /// 遇到非BEGIN的QUERY_EVENT时清除已编译的条件
+    pub fn filter<'a>(&mut self, data: &'a Traction, db_tbl: &str, cols: &[HashMap<String, String>]) -> Result<Option<Cow<'a, Traction>>, String> {
+        let (code, rows) = match data {
+            Traction::RowEvent(code, rows) if self.applies_to(db_tbl) => (code, rows),
+            Traction::QueryEvent(t) if t.command != "BEGIN" => {
+                self.reset();
+                return Ok(Some(Cow::Borrowed(data)));
+            }
+            _ => return Ok(Some(Cow::Borrowed(data)))
+        };
+        let kept = self.retain(db_tbl, code, rows.rows(), cols)?;
+        if kept.is_empty() {
+            return Ok(None);
+        }
+        if kept.len() == rows.rows().len() {
+            return Ok(Some(Cow::Borrowed(data)));
+        }
+        Ok(Some(Cow::Owned(Traction::RowEvent(code.clone(), RowValue{ rows: kept }))))
+    }
+
+    //满足条件的行, update按前后镜像成对判断
+    fn retain(&mut self, db_tbl: &str, code: &BinlogEvent, rows: &[Vec<Option<MySQLValue>>], cols: &[HashMap<String, String>]) -> Result<Vec<Vec<Option<MySQLValue>>>, String> {
+        self.compile(db_tbl, cols)?;
+        let compiled = &self.compiled[db_tbl];
+        let format = &self.format;
+        let matches = |row: &Vec<Option<MySQLValue>>| compiled.iter().all(|c| c.matches(row, format));
+        let mut kept = vec![];
+        match code {
+            BinlogEvent::UpdateEvent => {
+                for pair in rows.chunks(2) {
+                    if pair.iter().any(matches) {
+                        kept.extend(pair.iter().cloned());
+                    }
+                }
+            }
+            _ => kept.extend(rows.iter().filter(|r| matches(r)).cloned()),
+        }
+        Ok(kept)
+    }
+}
+
+impl Compiled{
+    fn matches(&self, row: &[Option<MySQLValue>], format: &ValueFormat) -> bool {
+        let value = match row.get(self.index) {
+            Some(Some(MySQLValue::Null)) | Some(None) | None => None,
+            Some(t) => format.text_value(t, &self.col_type, BinaryEncoding::Hex),
+        };
+        match (&self.test, value) {
+            (Test::IsNull(not), value) => value.is_some() == *not,
+            (_, None) => false,
+            (Test::Compare(op, lit), Some(value)) => compare(&value, lit).is_some_and(|ord| op.matches(ord)),
+            (Test::In(values, not), Some(value)) => values.iter().any(|lit| compare(&value, lit) == Some(Ordering::Equal)) != *not,
+        }
+    }
+}
+
+fn compare(value: &str, lit: &Literal) -> Option<Ordering> {
+    match lit {
+        Literal::Number(n) => BigDecimal::from_str(value).ok().map(|v| v.cmp(n)),
+        Literal::Text(t) => Some(value.cmp(t.as_str())),
+    }
+}
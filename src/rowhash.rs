@@ -0,0 +1,422 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//行数据校验和: 每行数据按字段类型转换为规范的文本后计算sha256, 取前16位十六进制
+//  json输出(--row-hash)时每行带row_hash(update另有old_row_hash), 下游应用后可以在目标库按相同的规则计算并比较
+//  binlog中的值与目标库查询结果的表示方式不同, 两边都转换为同一种形式:
+//      整数按COLUMN_TYPE中的unsigned转换为无符号数, enum/set/bit为数值(目标库查询col+0)
+//      decimal、时间的小数部分去掉末尾的0, float/double按最短表示, json按key排序后序列化
+//      timestamp为unix时间戳(目标库查询UNIX_TIMESTAMP(col)), 不受时区影响
+//      字符串、二进制为十六进制(目标库查询HEX(col)), 不受连接字符集影响, char去掉末尾的空格
+//  rowcheck子命令: 读取binlog文件, 按主键保留每行最终的数据, 到目标库按主键查询并比较校验和
+//      没有主键的表跳过; binlog中最后为删除的行在目标库中应不存在
+
+use std::collections::{BTreeMap, HashMap};
+use sha2::{Digest, Sha256};
+use crate::replication::parsevalue::MySQLValue;
+use crate::replication::readevent::{BinlogEvent, TableMap};
+use crate::replication::readbinlog::Traction;
+use crate::stdout::format::ValueFormat;
+use crate::stdout::sql_escape;
+
+/// 计算校验和时字段值的转换方式, 按information_schema中的COLUMN_TYPE判断
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashKind{
+    /// unsigned时为位数
+    Integer(Option<u32>),
+    Float,
+    Double,
+    Decimal,
+    Bit,
+    EnumSet,
+    Year,
+    Date,
+    Time,
+    DateTime,
+    Timestamp,
+    Json,
+    Char,
+    Bytes,
+}
+
+impl HashKind{
+    pub fn new(col_type: &str) -> HashKind {
+        let col_type = col_type.to_lowercase();
+        let unsigned = col_type.contains("unsigned");
+        let int = |bits: u32| HashKind::Integer(if unsigned { Some(bits) } else { None });
+        let prefix = |name: &str| col_type.starts_with(name);
+        if prefix("tinyint") || prefix("bool") { int(8) }
+        else if prefix("smallint") { int(16) }
+        else if prefix("mediumint") { int(24) }
+        else if prefix("bigint") { int(64) }
+        else if prefix("int") || prefix("integer") { int(32) }
+        else if prefix("float") { HashKind::Float }
+        else if prefix("double") || prefix("real") { HashKind::Double }
+        else if prefix("decimal") || prefix("numeric") { HashKind::Decimal }
+        else if prefix("bit") { HashKind::Bit }
+        else if prefix("enum") || prefix("set") { HashKind::EnumSet }
+        else if prefix("year") { HashKind::Year }
+        else if prefix("datetime") { HashKind::DateTime }
+        else if prefix("timestamp") { HashKind::Timestamp }
+        else if prefix("date") { HashKind::Date }
+        else if prefix("time") { HashKind::Time }
+        else if prefix("json") { HashKind::Json }
+        else if prefix("char") { HashKind::Char }
+        else { HashKind::Bytes }
+    }
+
+    /// 目标库查询时使用的表达式
+    pub fn select_expr(&self, col: &str) -> String {
+        let col = sql_escape::quote_identifier(col);
+        match self {
+            HashKind::Bit | HashKind::EnumSet => format!("{}+0", col),
+            HashKind::Timestamp => format!("UNIX_TIMESTAMP({})", col),
+            HashKind::Char | HashKind::Bytes => format!("HEX({})", col),
+            _ => col
+        }
+    }
+
+    /// 按主键查询时的条件, value为规范化后的值
+    pub fn condition(&self, col: &str, value: &str) -> String {
+        let col = sql_escape::quote_identifier(col);
+        match self {
+            HashKind::Char | HashKind::Bytes => format!("{} = X'{}'", col, value),
+            HashKind::Timestamp => format!("{} = FROM_UNIXTIME({})", col, value),
+            HashKind::Integer(_) | HashKind::Bit | HashKind::EnumSet | HashKind::Year => format!("{} = {}", col, value),
+            _ => format!("{} = '{}'", col, value.replace('\\', "\\\\").replace('\'', "''"))
+        }
+    }
+}
+
+/// binlog中字段值的规范形式, fsp为时间类型的小数位数(TABLE_MAP中的column_meta[0]), None为NULL
+pub fn canonical_value(value: &Option<MySQLValue>, kind: HashKind, fsp: usize) -> Option<String> {
+    let value = match value {
+        Some(MySQLValue::Null) | None => return None,
+        Some(t) => t,
+    };
+    Some(match (value, kind) {
+        (MySQLValue::SignedInteger(t), HashKind::Integer(Some(bits))) => {
+            let mask = if bits >= 64 { u64::MAX } else { (1u64 << bits) - 1 };
+            (*t as u64 & mask).to_string()
+        }
+        (MySQLValue::SignedInteger(t), HashKind::EnumSet) => (*t as u64 & 0xffff).to_string(),
+        (MySQLValue::SignedInteger(t), _) => t.to_string(),
+        (MySQLValue::Enum(t), _) => (*t as u16).to_string(),
+        (MySQLValue::Year(t), _) => t.to_string(),
+        (MySQLValue::Float(t), _) => t.to_string(),
+        (MySQLValue::Double(t), _) => t.to_string(),
+        (MySQLValue::Decimal(t), _) => trim_fraction(&t.to_string()),
+        (MySQLValue::Blob(t), HashKind::Bit) => {
+            t.iter().fold(0u64, |acc, b| acc << 8 | u64::from(*b)).to_string()
+        }
+        (MySQLValue::Blob(t), HashKind::Char) => {
+            let len = t.iter().rposition(|b| *b != b' ').map(|p| p + 1).unwrap_or(0);
+            hex::encode_upper(&t[..len])
+        }
+        (MySQLValue::Blob(t), _) | (MySQLValue::Opaque{bytes: t, ..}, _) => hex::encode_upper(t),
+        (MySQLValue::String(t), _) => hex::encode_upper(t.as_bytes()),
+        (MySQLValue::Json(t), _) => t.to_string(),
+        (MySQLValue::Date{year, month, day}, _) => format!("{:04}-{:02}-{:02}", year, month, day),
+        (MySQLValue::Time{negative, hours, minutes, seconds, subseconds}, _) => {
+            format!("{}{:02}:{:02}:{:02}{}", if *negative { "-" } else { "" }, hours, minutes, seconds, fraction(*subseconds, fsp))
+        }
+        (MySQLValue::DateTime{year, month, day, hour, minute, second, subsecond}, _) => {
+            format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}{}", year, month, day, hour, minute, second, fraction(*subsecond, fsp))
+        }
+        (MySQLValue::Timestamp{unix_time, subsecond}, _) => format!("{}{}", unix_time, fraction(*subsecond, fsp)),
+        (MySQLValue::Null, _) => return None,
+    })
+}
+
+/// 目标库按select_expr查询结果的规范形式
+pub fn canonical_text(text: &str, kind: HashKind) -> String {
+    match kind {
+        HashKind::Float => text.parse::<f32>().map(|t| t.to_string()).unwrap_or_else(|_| text.to_string()),
+        HashKind::Double => text.parse::<f64>().map(|t| t.to_string()).unwrap_or_else(|_| text.to_string()),
+        HashKind::Decimal | HashKind::Time | HashKind::DateTime | HashKind::Timestamp => trim_fraction(text),
+        HashKind::Json => serde_json::from_str::<serde_json::Value>(text).map(|t| t.to_string()).unwrap_or_else(|_| text.to_string()),
+        HashKind::Char | HashKind::Bytes => text.to_uppercase(),
+        _ => text.to_string()
+    }
+}
+
+//binlog中的小数部分为按fsp存储的整数: fsp 1-2为2位, 3-4为4位, 5-6为6位
+fn fraction(subsecond: u32, fsp: usize) -> String {
+    if subsecond == 0 {
+        return String::new();
+    }
+    let digits = fsp.div_ceil(2) * 2;
+    trim_fraction(&format!(".{:0width$}", subsecond, width = digits))
+}
+
+//去掉小数部分末尾的0, 小数部分为0时去掉小数点
+fn trim_fraction(value: &str) -> String {
+    if !value.contains('.') {
+        return value.to_string();
+    }
+    value.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// 规范化后的一行数据的校验和, 每个值以长度为前缀, NULL单独标记, 取sha256的前16位十六进制
+pub fn hash_values(values: &[Option<String>]) -> String {
+    let mut hasher = Sha256::default();
+    for value in values {
+        match value {
+            None => hasher.input([0u8]),
+            Some(t) => {
+                hasher.input([1u8]);
+                hasher.input((t.len() as u32).to_le_bytes());
+                hasher.input(t.as_bytes());
+            }
+        }
+    }
+    hex::encode(hasher.result())[..16].to_string()
+}
+
+fn column_kind(col: &HashMap<String, String>) -> HashKind {
+    HashKind::new(col.get("COLUMN_TYPE").map(|t| t.as_str()).unwrap_or(""))
+}
+
+fn fsp(map: &TableMap, idx: usize) -> usize {
+    map.column_info.get(idx).and_then(|c| c.column_meta.first()).copied().unwrap_or(0)
+}
+
+/// binlog中一行数据的校验和, cols为meta::get_col获取的字段信息
+pub fn row_hash(row: &[Option<MySQLValue>], cols: &[HashMap<String, String>], map: &TableMap) -> String {
+    let values: Vec<Option<String>> = row.iter().enumerate().map(|(idx, value)| {
+        let kind = cols.get(idx).map(column_kind).unwrap_or(HashKind::Bytes);
+        canonical_value(value, kind, fsp(map, idx))
+    }).collect();
+    hash_values(&values)
+}
+
+/// 目标库中计算校验和的查询, 每个字段查询CONCAT('=', expr)以区分NULL与空字符串, 别名为c0、c1...
+pub fn select_sql(db: &str, tbl: &str, cols: &[HashMap<String, String>], condition: &str) -> String {
+    let exprs: Vec<String> = cols.iter().enumerate().map(|(idx, col)| {
+        let name = col.get("COLUMN_NAME").map(|t| t.as_str()).unwrap_or("");
+        format!("CONCAT('=', {}) AS c{}", column_kind(col).select_expr(name), idx)
+    }).collect();
+    format!("SELECT {} FROM {} WHERE {} LIMIT 1;", exprs.join(", "), sql_escape::quote_table(db, tbl), condition)
+}
+
+/// 按select_sql查询到的一行数据的校验和
+pub fn target_hash(row: &HashMap<String, String>, cols: &[HashMap<String, String>]) -> String {
+    let values: Vec<Option<String>> = cols.iter().enumerate().map(|(idx, col)| {
+        let text = row.get(&format!("c{}", idx))?;
+        text.strip_prefix('=').map(|t| canonical_text(t, column_kind(col)))
+    }).collect();
+    hash_values(&values)
+}
+
+//binlog中一行最终的状态
+#[derive(Debug, Clone)]
+struct Expected{
+    database: String,
+    table: String,
+    key: String,
+    condition: String,
+    hash: Option<String>,       //None为已删除
+}
+
+/// 校验结果
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport{
+    pub matched: u64,
+    /// (db.tbl 主键, binlog中的校验和, 目标库的校验和)
+    pub mismatched: Vec<(String, String, String)>,
+    /// binlog中存在, 目标库中不存在
+    pub missing: Vec<String>,
+    /// binlog中已删除, 目标库中仍存在
+    pub unexpected: Vec<String>,
+    /// 跳过的表及原因
+    pub skipped: BTreeMap<String, String>,
+}
+
+impl CheckReport{
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty() && self.unexpected.is_empty()
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.matched + (self.mismatched.len() + self.missing.len() + self.unexpected.len()) as u64
+    }
+}
+
+/// 按主键保留binlog中每行最终的数据
+#[derive(Debug, Default)]
+pub struct RowChecker{
+    rows: BTreeMap<(String, Vec<String>), Expected>,
+    skipped: BTreeMap<String, String>,
+}
+
+impl RowChecker{
+    pub fn new() -> RowChecker {
+        RowChecker::default()
+    }
+
+    /// 记录一个行事件, cols为meta::get_col获取的字段信息, 表结构不一致或没有主键的表跳过
+    pub fn record(&mut self, data: &Traction, map: &TableMap, cols: &[HashMap<String, String>]) {
+        let (code, row_values) = match data {
+            Traction::RowEvent(code, rows) => (code, rows),
+            _ => return
+        };
+        let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+        if self.skipped.contains_key(&db_tbl) {
+            return;
+        }
+        if cols.len() != map.column_info.len() {
+            self.skipped.insert(db_tbl, format!("字段数不一致, binlog中为{}, 目标库为{}", map.column_info.len(), cols.len()));
+            return;
+        }
+        let pk: Vec<usize> = cols.iter().enumerate()
+            .filter(|(_, c)| c.get("COLUMN_KEY").map(|k| k == "PRI").unwrap_or(false))
+            .map(|(idx, _)| idx)
+            .collect();
+        if pk.is_empty() {
+            self.skipped.insert(db_tbl, String::from("没有主键"));
+            return;
+        }
+        let rows = row_values.rows();
+        match code {
+            BinlogEvent::UpdateEvent => {
+                for pair in rows.chunks(2) {
+                    if pair.len() < 2 {
+                        continue;
+                    }
+                    self.change(map, cols, &pk, &pair[0], None);
+                    self.change(map, cols, &pk, &pair[1], Some(row_hash(&pair[1], cols, map)));
+                }
+            }
+            BinlogEvent::WriteEvent => {
+                for row in rows {
+                    self.change(map, cols, &pk, row, Some(row_hash(row, cols, map)));
+                }
+            }
+            BinlogEvent::DeleteEvent => {
+                for row in rows {
+                    self.change(map, cols, &pk, row, None);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn change(&mut self, map: &TableMap, cols: &[HashMap<String, String>], pk: &[usize], row: &[Option<MySQLValue>], hash: Option<String>) {
+        let format = ValueFormat::default();
+        let mut values = vec![];
+        let mut conditions = vec![];
+        let mut display = vec![];
+        for idx in pk {
+            let value = row.get(*idx).unwrap_or(&None);
+            let col = cols[*idx].get("COLUMN_NAME").map(|t| t.as_str()).unwrap_or("");
+            let col_type = cols[*idx].get("COLUMN_TYPE").map(|t| t.as_str()).unwrap_or("");
+            let kind = HashKind::new(col_type);
+            let canonical = canonical_value(value, kind, fsp(map, *idx)).unwrap_or_default();
+            conditions.push(kind.condition(col, &canonical));
+            display.push(format!("{}={}", col, format.text_value(value, col_type, format.binary_encoding("", col)).unwrap_or_default()));
+            values.push(canonical);
+        }
+        let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+        self.rows.insert((db_tbl.clone(), values), Expected{
+            database: map.database_name.clone(),
+            table: map.table_name.clone(),
+            key: format!("{} ({})", db_tbl, display.join(", ")),
+            condition: conditions.join(" AND "),
+            hash,
+        });
+    }
+
+    /// 到目标库按主键查询比较, query执行sql返回查询结果, cols获取目标库的字段信息
+    pub fn check<Q, C>(&self, mut query: Q, mut cols: C) -> Result<CheckReport, String>
+        where Q: FnMut(&str) -> Result<Vec<HashMap<String, String>>, String>,
+              C: FnMut(&str, &str) -> Option<Vec<HashMap<String, String>>> {
+        let mut report = CheckReport{ skipped: self.skipped.clone(), ..Default::default() };
+        for ((db_tbl, _), expected) in &self.rows {
+            if report.skipped.contains_key(db_tbl) {
+                continue;
+            }
+            let table_cols = match cols(&expected.database, &expected.table) {
+                Some(t) => t,
+                None => {
+                    report.skipped.insert(db_tbl.clone(), String::from("目标库中没有该表"));
+                    continue;
+                }
+            };
+            let rows = query(&select_sql(&expected.database, &expected.table, &table_cols, &expected.condition))?;
+            match (rows.first(), &expected.hash) {
+                (None, None) => report.matched += 1,
+                (None, Some(_)) => report.missing.push(expected.key.clone()),
+                (Some(_), None) => report.unexpected.push(expected.key.clone()),
+                (Some(row), Some(hash)) => {
+                    let target = target_hash(row, &table_cols);
+                    if &target == hash {
+                        report.matched += 1;
+                    }else {
+                        report.mismatched.push((expected.key.clone(), hash.clone(), target));
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// rowcheck子命令: 读取-f指定的binlog文件, 到目标库按主键比较每行最终的数据
+#[cfg(feature = "cli")]
+pub fn run(conf: &crate::Config) {
+    use std::process;
+    use crate::exitcode;
+    use crate::io::command::try_execute;
+    use crate::meta;
+    use crate::reader::BinlogReader;
+
+    let mut table_cols_info: HashMap<String, Vec<HashMap<String, String>>> = HashMap::new();
+    let mut checker = RowChecker::new();
+    for path in conf.file.split(',') {
+        let reader = BinlogReader::open(path).unwrap_or_else(|err|{
+            println!("打开binlog文件({})发生错误: {}", path, err);
+            process::exit(exitcode::PARSE);
+        });
+        for event in reader {
+            let event = event.unwrap_or_else(|err|{
+                println!("读取binlog文件({})发生错误: {}", path, err);
+                process::exit(exitcode::PARSE);
+            });
+            let map = match (&event.data, &event.table) {
+                (Traction::RowEvent(..), Some(t)) => t,
+                _ => continue
+            };
+            meta::get_col(conf, &map.database_name, &map.table_name, &mut table_cols_info);
+            let cols = table_cols_info.get(&format!("{}.{}", map.database_name, map.table_name)).cloned().unwrap_or_default();
+            checker.record(&event.data, map, &cols);
+        }
+    }
+
+    let mut conn = crate::create_conn(conf);
+    let report = checker.check(
+        |sql| try_execute(&mut conn, sql),
+        |db, tbl| table_cols_info.get(&format!("{}.{}", db, tbl)).filter(|t| !t.is_empty()).cloned()
+    ).unwrap_or_else(|err|{
+        println!("查询目标库发生错误: {}", err);
+        process::exit(exitcode::SERVER);
+    });
+    for (table, reason) in &report.skipped {
+        println!("skip {}: {}", table, reason);
+    }
+    for (key, expected, target) in &report.mismatched {
+        println!("mismatch {}: binlog {}, target {}", key, expected, target);
+    }
+    for key in &report.missing {
+        println!("missing {}: 目标库中不存在", key);
+    }
+    for key in &report.unexpected {
+        println!("unexpected {}: binlog中已删除, 目标库中仍存在", key);
+    }
+    println!("rows: {}, matched: {}, mismatched: {}, missing: {}, unexpected: {}, skipped tables: {}",
+             report.rows(), report.matched, report.mismatched.len(), report.missing.len(), report.unexpected.len(), report.skipped.len());
+    if !report.is_ok() {
+        process::exit(exitcode::MISMATCH);
+    }
+}
@@ -0,0 +1,147 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//按表抽样输出行数据, 用于只需要观察趋势的场景, 避免写入量很大的表占满下游
+//  格式: {"db.tbl":"1/100","db.*":"key:3/16","*":"1/10"}, 表按db.tbl、db.*、*的顺序使用第一个匹配的配置
+//  1/N: 每N行输出1行(每个表单独计数, 输出第1、N+1...行)
+//  key:B/N: 按主键(没有主键信息时为所有字段)的hash值对N取余, 只输出等于B的行, 同一主键的修改要么全部输出要么全部不输出
+//      多个进程分别配置0..N-1可以将一个表的数据分为N份
+//  update的前后镜像作为一行, key按前镜像计算
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::BinlogEvent;
+use crate::replication::parsevalue::{MySQLValue, RowValue};
+use crate::stdout::format::{BinaryEncoding, ValueFormat};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleMode{
+    /// 每N行输出1行
+    Every(u64),
+    /// 主键hash对N取余等于B的行, (B, N)
+    KeyBucket(u64, u64),
+}
+
+impl SampleMode{
+    pub fn new(value: &str) -> Result<SampleMode, String> {
+        let err = || format!("无效的抽样配置: {}, 格式为1/N或key:B/N", value);
+        let value = value.trim();
+        match value.strip_prefix("key:") {
+            Some(t) => {
+                let (bucket, buckets) = t.split_once('/').ok_or_else(err)?;
+                let bucket: u64 = bucket.trim().parse().map_err(|_| err())?;
+                let buckets: u64 = buckets.trim().parse().map_err(|_| err())?;
+                if buckets == 0 || bucket >= buckets {
+                    return Err(format!("无效的抽样配置: {}, B需要小于N", value));
+                }
+                Ok(SampleMode::KeyBucket(bucket, buckets))
+            }
+            None => {
+                let n = match value.split_once('/') {
+                    Some(("1", n)) => n,
+                    Some(_) => return Err(err()),
+                    None => value
+                };
+                match n.trim().parse() {
+                    Ok(n) if n > 0 => Ok(SampleMode::Every(n)),
+                    _ => Err(err())
+                }
+            }
+        }
+    }
+}
+
+/// 按表抽样行数据
+pub struct Sampler{
+    modes: HashMap<String, SampleMode>,
+    counters: HashMap<String, u64>,
+    format: ValueFormat,
+}
+
+impl Sampler{
+    pub fn new(modes: HashMap<String, SampleMode>) -> Sampler {
+        //key的文本固定使用默认格式, 分桶结果不受--timezone等输出配置影响
+        Sampler{ modes, counters: HashMap::new(), format: ValueFormat::default() }
+    }
+
+    /// 解析{"db.tbl":"1/100"}格式的配置
+    pub fn parse(config: &str) -> Result<Sampler, String> {
+        let values: HashMap<String, String> = serde_json::from_str(config).map_err(|e| format!("sample格式错误: {}", e))?;
+        let mut modes = HashMap::new();
+        for (table, value) in values {
+            modes.insert(crate::ident::normalize(&table), SampleMode::new(&value)?);
+        }
+        Ok(Sampler::new(modes))
+    }
+
+    /// 表(db.tbl)使用的抽样方式, 没有配置时为None
+    pub fn mode(&self, db_tbl: &str) -> Option<SampleMode> {
+        if let Some(t) = self.modes.get(db_tbl) {
+            return Some(*t);
+        }
+        let db = db_tbl.split('.').next().unwrap_or("");
+        self.modes.get(&format!("{}.*", db)).or_else(|| self.modes.get("*")).copied()
+    }
+
+    /// 抽样行事件, 返回None时整个事件都不需要输出, 没有配置该表或不是行事件时原样返回
+    /// cols为meta::get_col获取的字段信息, 用于查找主键
+    pub fn sample<'a>(&mut self, data: &'a Traction, db_tbl: &str, cols: &[HashMap<String, String>]) -> Option<Cow<'a, Traction>> {
+        let (code, rows, mode) = match (data, self.mode(db_tbl)) {
+            (Traction::RowEvent(code, rows), Some(mode)) => (code, rows, mode),
+            _ => return Some(Cow::Borrowed(data))
+        };
+        let step = if let BinlogEvent::UpdateEvent = code { 2 } else { 1 };
+        let key_cols = key_columns(cols, rows.rows().first().map(|r| r.len()).unwrap_or(0));
+        let mut counter = self.counters.get(db_tbl).copied().unwrap_or(0);
+        let mut kept = vec![];
+        for image in rows.rows().chunks(step) {
+            let keep = match mode {
+                SampleMode::Every(n) => {
+                    counter += 1;
+                    (counter - 1) % n == 0
+                }
+                SampleMode::KeyBucket(bucket, buckets) => self.key_hash(&image[0], &key_cols, cols) % buckets == bucket,
+            };
+            if keep {
+                kept.extend(image.iter().cloned());
+            }
+        }
+        if let SampleMode::Every(_) = mode {
+            self.counters.insert(db_tbl.to_string(), counter);
+        }
+        if kept.is_empty() {
+            return None;
+        }
+        if kept.len() == rows.rows().len() {
+            return Some(Cow::Borrowed(data));
+        }
+        Some(Cow::Owned(Traction::RowEvent(code.clone(), RowValue{ rows: kept })))
+    }
+
+    //主键值的fnv-1a hash, 与平台、进程无关, 多个进程对同一行的分桶结果相同
+    fn key_hash(&self, row: &[Option<MySQLValue>], key_cols: &[usize], cols: &[HashMap<String, String>]) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for &idx in key_cols {
+            let col_type = cols.get(idx).and_then(|c| c.get("COLUMN_TYPE")).map(|t| t.as_str()).unwrap_or("");
+            let value = row.get(idx).and_then(|v| self.format.text_value(v, col_type, BinaryEncoding::Hex));
+            let text = value.unwrap_or_else(|| String::from("NULL"));
+            for b in text.as_bytes().iter().chain([0u8].iter()) {
+                hash ^= *b as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+}
+
+//主键字段的下标, 没有主键信息时使用所有字段
+fn key_columns(cols: &[HashMap<String, String>], row_len: usize) -> Vec<usize> {
+    let pk: Vec<usize> = cols.iter().enumerate()
+        .filter(|(_, c)| c.get("COLUMN_KEY").map(|k| k == "PRI").unwrap_or(false))
+        .map(|(idx, _)| idx)
+        .collect();
+    if pk.is_empty() { (0..row_len).collect() } else { pk }
+}
@@ -0,0 +1,103 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/29
+*/
+
+//密码等敏感信息, Debug/Display输出时隐藏内容, 避免打印配置时泄露
+//可以从文件(k8s secret挂载、systemd LoadCredential)或外部命令(vault、kms客户端)获取
+
+use std::fmt;
+#[cfg(feature = "os")]
+use std::fs;
+use std::ops::Deref;
+use serde::{Deserialize, Deserializer};
+#[cfg(feature = "os")]
+use std::process::Command;
+
+const REDACTED: &str = "******";
+
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret{
+    pub fn new(value: String) -> Secret {
+        Secret(value)
+    }
+
+    /// 明文内容, 只在需要使用的地方调用
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Secret {
+        Secret(value)
+    }
+}
+
+impl Deref for Secret {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+//配置文件中的密码
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Secret, D::Error> {
+        String::deserialize(deserializer).map(Secret)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+/// 从外部获取敏感信息, 作为库使用时可以实现该trait对接vault、kms等
+pub trait SecretFetcher {
+    fn fetch(&self) -> Result<Secret, String>;
+}
+
+/// 读取文件内容, 去掉末尾的换行
+#[cfg(feature = "os")]
+pub struct FileSecret{
+    pub path: String,
+}
+
+#[cfg(feature = "os")]
+impl SecretFetcher for FileSecret {
+    fn fetch(&self) -> Result<Secret, String> {
+        let content = fs::read_to_string(&self.path).map_err(|e| format!("读取文件({})发生错误:{}", self.path, e))?;
+        Ok(Secret(content.trim_end_matches(['\n', '\r']).to_string()))
+    }
+}
+
+/// 执行命令并使用其标准输出, 例如: vault kv get -field=password secret/mysql
+#[cfg(feature = "os")]
+pub struct CommandSecret{
+    pub command: String,
+}
+
+#[cfg(feature = "os")]
+impl SecretFetcher for CommandSecret {
+    fn fetch(&self) -> Result<Secret, String> {
+        let output = Command::new("sh").arg("-c").arg(&self.command).output()
+            .map_err(|e| format!("执行命令发生错误:{}", e))?;
+        if !output.status.success() {
+            //命令的输出中可能包含敏感信息, 只返回退出状态
+            return Err(format!("获取密码的命令执行失败: {}", output.status));
+        }
+        let content = String::from_utf8(output.stdout).map_err(|_| String::from("获取密码的命令输出不是合法的utf8"))?;
+        Ok(Secret(content.trim_end_matches(['\n', '\r']).to_string()))
+    }
+}
@@ -0,0 +1,357 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//不能使用主从协议拉取时(没有注册为从库的权限、经过不支持COM_BINLOG_DUMP的中间件)的降级方式:
+//  通过普通的客户端连接分页执行SHOW BINLOG EVENTS IN 'file' FROM pos LIMIT n, 将每一行转换为与BinlogReader相同的Event
+//  只有语句级的信息: QUERY_EVENT的库名及语句、GTID、XID、ROTATE、ROWS_QUERY(binlog_rows_query_log_events=ON时的原始语句),
+//  TABLE_MAP只有库名、表名(没有字段信息), 行事件为RowEventStatic, 不能解析行数据
+//  SHOW BINLOG EVENTS不返回事件的时间, header.timestamp为0; server_id、position与binlog中相同
+//  读取到ROTATE时继续读取下一个文件, 到达最后一个文件的末尾时结束, 设置follow时按间隔重新查询
+//  events子命令按事务输出语句(DDL、statement格式的DML、ROWS_QUERY)及写入的表, 用于审计
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use serde_json::{json, Value as JsonValue};
+use uuid::Uuid;
+use crate::Config;
+use crate::io::command::try_execute;
+use crate::reader::Event;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::{BinlogEvent, EventFlags, EventHeader, EventType, GtidEvent, QueryEvent, RotateLog, RowsQueryEvent, TableMap, XidEvent};
+use crate::stdout::sql_escape;
+
+/// 将SHOW BINLOG EVENTS的行转换为Event, 记录TABLE_MAP的table_id用于之后的行事件
+#[derive(Debug, Clone, Default)]
+pub struct EventConverter{
+    tables: HashMap<u64, TableMap>,
+}
+
+impl EventConverter {
+    pub fn new() -> EventConverter {
+        EventConverter::default()
+    }
+
+    /// row为SHOW BINLOG EVENTS的一行: Log_name、Pos、Event_type、Server_id、End_log_pos、Info
+    pub fn convert(&mut self, row: &HashMap<String, String>) -> io::Result<Event> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let field = |name: &str| row.get(name).map(|t| t.as_str()).unwrap_or("");
+        let number = |name: &str| field(name).parse::<u64>().map_err(|_| invalid(format!("无效的{}: {}", name, field(name))));
+        let log_pos = number("Pos")?;
+        let next_position = number("End_log_pos")?;
+        let info = field("Info");
+        let event_type = event_type(field("Event_type"));
+        let data = match event_type {
+            EventType::QueryEvent => {
+                let (database, command) = split_use(info);
+                Traction::QueryEvent(QueryEvent{ thread_id: 0, execute_seconds: 0, database, command, invoker: String::new() })
+            }
+            //MariaDB的GTID事件为事务的开始, 没有单独的BEGIN
+            EventType::GtidLogEvent if info.starts_with("BEGIN GTID") => {
+                Traction::QueryEvent(QueryEvent{ thread_id: 0, execute_seconds: 0, database: String::new(), command: String::from("BEGIN"), invoker: String::new() })
+            }
+            EventType::GtidLogEvent => {
+                //SET @@SESSION.GTID_NEXT= 'uuid:gno', 带tag时为uuid:tag:gno
+                let gtid = info.split('\'').nth(1).unwrap_or("");
+                let (sid, gno) = match (gtid.split(':').next(), gtid.rsplit(':').next().map(|t| t.parse::<u64>())) {
+                    (Some(sid), Some(Ok(gno))) => (Uuid::from_str(sid).map_err(|e| invalid(format!("无效的gtid: {}, {}", gtid, e)))?, gno),
+                    _ => return Err(invalid(format!("无效的gtid: {}", info))),
+                };
+                Traction::GtidEvent(GtidEvent{ gtid: sid, gno_id: gno, last_committed: 0, sequence_number: 0, commit_timestamp: None })
+            }
+            EventType::XidEvent => {
+                //COMMIT /* xid=123 */
+                let xid = info.split("xid=").nth(1).and_then(|t| t.trim_end_matches(" */").trim().parse::<u64>().ok()).unwrap_or(0);
+                Traction::XidEvent(XidEvent{ xid })
+            }
+            EventType::RotateEvent => {
+                //mysql-bin.000002;pos=4
+                let (file, pos) = info.split_once(";pos=").unwrap_or((info, "4"));
+                Traction::RotateLogEvent(RotateLog{ binlog_file: file.to_string(), position: pos.parse().unwrap_or(4), artificial: false })
+            }
+            EventType::RowsQueryLogEvent => {
+                //MySQL为"# 原始语句", MariaDB的Annotate_rows没有#
+                Traction::RowsQueryEvent(RowsQueryEvent{ query: info.strip_prefix("# ").unwrap_or(info).to_string() })
+            }
+            EventType::StopEvent => Traction::StopEvent,
+            EventType::TableMapEvent => {
+                //table_id: 108 (db.tbl)
+                let table_id = table_id(info);
+                let name = info.split_once('(').map(|(_, t)| t.trim_end_matches(')')).unwrap_or("");
+                let (database_name, table_name) = name.split_once('.').unwrap_or(("", name));
+                let map = TableMap{ database_name: database_name.to_string(), table_name: table_name.to_string(), ..TableMap::new() };
+                self.tables.insert(table_id, map.clone());
+                Traction::TableMapEvent(map)
+            }
+            t if matches!(BinlogEvent::from(t), BinlogEvent::WriteEvent | BinlogEvent::UpdateEvent | BinlogEvent::DeleteEvent) => {
+                Traction::RowEventStatic{ type_code: BinlogEvent::from(t), count: next_position.saturating_sub(log_pos) as usize }
+            }
+            _ => Traction::Unknown,
+        };
+        let table = match &data {
+            Traction::RowEventStatic{..} => self.tables.get(&table_id(info)).cloned(),
+            _ => None,
+        };
+        if matches!(data, Traction::XidEvent(_)) || matches!(&data, Traction::QueryEvent(t) if t.command == "COMMIT") {
+            self.tables.clear();
+        }
+        let header = EventHeader{
+            timestamp: 0,
+            event_type,
+            type_code: BinlogEvent::from(event_type),
+            server_id: number("Server_id")? as u32,
+            event_length: next_position.saturating_sub(log_pos) as u32,
+            next_position: next_position as u32,
+            flags: EventFlags::empty(),
+            header_length: 19,
+            raw_type: event_type as u8,
+        };
+        Ok(Event{ header, log_pos, data, table })
+    }
+}
+
+//SHOW BINLOG EVENTS中的Event_type, 包括MariaDB的名称
+fn event_type(name: &str) -> EventType {
+    match name {
+        "Query" => EventType::QueryEvent,
+        "Stop" => EventType::StopEvent,
+        "Rotate" => EventType::RotateEvent,
+        "Intvar" => EventType::IntvarEvent,
+        "RAND" => EventType::RandEvent,
+        "User var" => EventType::UserVarEvent,
+        "Format_desc" => EventType::FormatDescriptionEvent,
+        "Xid" => EventType::XidEvent,
+        "Table_map" => EventType::TableMapEvent,
+        "Write_rows_v1" => EventType::WriteRowsEventV1,
+        "Update_rows_v1" => EventType::UpdateRowsEventV1,
+        "Delete_rows_v1" => EventType::DeleteRowsEventV1,
+        "Incident" => EventType::IncidentEvent,
+        "Rows_query" | "Annotate_rows" => EventType::RowsQueryLogEvent,
+        "Write_rows" => EventType::WriteRowsEvent,
+        "Update_rows" => EventType::UpdateRowsEvent,
+        "Delete_rows" => EventType::DeleteRowsEvent,
+        "Gtid" | "Gtid_tagged" => EventType::GtidLogEvent,
+        "Anonymous_Gtid" => EventType::AnonymousGtidLogEvent,
+        "Previous_gtids" => EventType::PreviousGtidsLogEvent,
+        "Transaction_context" => EventType::TransactionContextEvent,
+        "View_change" => EventType::ViewChangeEvent,
+        "XA_prepare" => EventType::XaPrepareLogEvent,
+        "Update_rows_partial" => EventType::PartialUpdateRowsEvent,
+        "Transaction_payload" => EventType::TransactionPayloadEvent,
+        _ => EventType::UnknownEvent,
+    }
+}
+
+//use `db`; 语句
+fn split_use(info: &str) -> (String, String) {
+    if let Some(rest) = info.strip_prefix("use `") {
+        if let Some((db, command)) = rest.split_once("`; ") {
+            return (db.replace("``", "`"), command.to_string());
+        }
+    }
+    (String::new(), info.to_string())
+}
+
+fn table_id(info: &str) -> u64 {
+    info.strip_prefix("table_id: ").and_then(|t| t.split(' ').next()).and_then(|t| t.parse().ok()).unwrap_or(0)
+}
+
+/// 通过SHOW BINLOG EVENTS分页读取, 按事件迭代, 到达最后一个文件的末尾时结束(设置follow时等待新的事件)
+pub struct ShowEvents{
+    conn: TcpStream,
+    log_file: String,
+    position: u64,
+    page: usize,
+    follow: Option<Duration>,
+    converter: EventConverter,
+    buffer: VecDeque<Event>,
+    finished: bool,
+}
+
+impl ShowEvents {
+    /// conf中的binlogfile、position为开始的位置, 没有binlogfile时为SHOW BINARY LOGS中的第一个文件
+    pub fn connect(conf: &Config) -> io::Result<ShowEvents> {
+        let mut conn = crate::io::connection::create_mysql_conn(conf)
+            .map_err(|e| io::Error::new(io::ErrorKind::PermissionDenied, e))?;
+        let log_file = match conf.binlogfile.as_str() {
+            "" => {
+                let binlogs = try_execute(&mut conn, "show binary logs;").map_err(io::Error::other)?;
+                binlogs.first().and_then(|t| t.get("Log_name")).cloned()
+                    .ok_or_else(|| io::Error::other("SHOW BINARY LOGS没有返回binlog文件, 需要开启log_bin"))?
+            }
+            t => t.to_string(),
+        };
+        let position = match conf.position.as_str() {
+            "" => 4,
+            t => t.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("无效的position: {}", t)))?,
+        };
+        Ok(ShowEvents{ conn, log_file, position, page: 1000, follow: None, converter: EventConverter::new(), buffer: VecDeque::new(), finished: false })
+    }
+
+    /// 每次查询的事件数, 默认1000
+    pub fn with_page(mut self, page: usize) -> ShowEvents {
+        self.page = page.max(1);
+        self
+    }
+
+    /// 到达末尾后按间隔重新查询, 不会结束
+    pub fn with_follow(mut self, interval: Duration) -> ShowEvents {
+        self.follow = Some(interval);
+        self
+    }
+
+    /// 下一次查询的文件及位置
+    pub fn position(&self) -> (&str, u64) {
+        (&self.log_file, self.position)
+    }
+
+    /// 读取下一个事件, 到达末尾且没有设置follow时返回None
+    pub fn read_event(&mut self) -> io::Result<Option<Event>> {
+        loop {
+            if let Some(event) = self.buffer.pop_front() {
+                return Ok(Some(event));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            let sql = format!("show binlog events in {} from {} limit {};", sql_escape::quote_string(&self.log_file), self.position, self.page);
+            let rows = try_execute(&mut self.conn, &sql).map_err(io::Error::other)?;
+            let mut rotate = None;
+            for row in &rows {
+                let event = self.converter.convert(row)?;
+                self.position = event.header.next_position as u64;
+                //binlog_dump时发送的rotate在文件开头, SHOW BINLOG EVENTS中只有文件末尾的rotate
+                if let Traction::RotateLogEvent(t) = &event.data {
+                    rotate = Some((t.binlog_file.clone(), t.position));
+                }
+                self.buffer.push_back(event);
+            }
+            if let Some((file, position)) = rotate {
+                self.log_file = file;
+                self.position = position;
+            } else if rows.len() < self.page {
+                match self.follow {
+                    Some(interval) if rows.is_empty() => thread::sleep(interval),
+                    Some(_) => {}
+                    None => self.finished = true,
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for ShowEvents {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        match self.read_event() {
+            Ok(t) => t.map(Ok),
+            Err(e) => {
+                self.finished = true;
+                self.buffer.clear();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// 按事务汇总的审计记录: 事务中的语句(DDL、statement格式的DML、ROWS_QUERY)及写入的表
+#[derive(Debug, Clone, Default)]
+pub struct AuditTrail{
+    log_file: String,
+    trx: Option<JsonValue>,
+    begun: bool,
+}
+
+impl AuditTrail {
+    pub fn new(log_file: &str) -> AuditTrail {
+        AuditTrail{ log_file: log_file.to_string(), ..AuditTrail::default() }
+    }
+
+    /// 事务结束时(XID、COMMIT、BEGIN之外的单独的语句如DDL)返回该事务的记录
+    pub fn feed(&mut self, event: &Event) -> Option<JsonValue> {
+        match &event.data {
+            Traction::RotateLogEvent(t) => {
+                self.log_file = t.binlog_file.clone();
+                return None;
+            }
+            Traction::GtidEvent(_) | Traction::QueryEvent(_) | Traction::RowsQueryEvent(_) | Traction::TableMapEvent(_) |
+            Traction::RowEventStatic{..} | Traction::XidEvent(_) => {}
+            _ => return None,
+        }
+        let log_file = self.log_file.clone();
+        let trx = self.trx.get_or_insert_with(|| json!({
+            "log_file": log_file, "position": event.log_pos, "server_id": event.header.server_id,
+            "gtid": "", "statements": [], "tables": [], "row_events": 0,
+        }));
+        match &event.data {
+            Traction::GtidEvent(t) => trx["gtid"] = json!(format!("{}:{}", t.gtid, t.gno_id)),
+            Traction::QueryEvent(t) if t.command == "BEGIN" => self.begun = true,
+            Traction::QueryEvent(t) if t.command != "COMMIT" => {
+                trx["statements"].as_array_mut().unwrap().push(json!({ "database": t.database, "sql": t.command }));
+                if !self.begun {
+                    return self.trx.take();
+                }
+            }
+            Traction::RowsQueryEvent(t) => trx["statements"].as_array_mut().unwrap().push(json!({ "database": "", "sql": t.query })),
+            Traction::RowEventStatic{..} => {
+                trx["row_events"] = json!(trx["row_events"].as_u64().unwrap_or(0) + 1);
+                if let Some(map) = &event.table {
+                    let name = json!(format!("{}.{}", map.database_name, map.table_name));
+                    let tables = trx["tables"].as_array_mut().unwrap();
+                    if !tables.contains(&name) {
+                        tables.push(name);
+                    }
+                }
+            }
+            Traction::XidEvent(_) | Traction::QueryEvent(_) => {
+                self.begun = false;
+                return self.trx.take();
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// events子命令: 按事务输出json行, 每行为一个事务的语句及写入的表
+pub fn run(conf: &Config) {
+    use std::process;
+    use crate::exitcode;
+    let mut events = ShowEvents::connect(conf).unwrap_or_else(|err|{
+        println!("连接mysql发生错误: {}", err);
+        process::exit(exitcode::CONNECT);
+    });
+    if !conf.eventspage.is_empty() {
+        let page = conf.eventspage.parse::<usize>().unwrap_or_else(|_|{
+            println!("无效的page-size: {}", conf.eventspage);
+            process::exit(exitcode::USAGE);
+        });
+        events = events.with_page(page);
+    }
+    if !conf.eventsfollow.is_empty() {
+        let interval = match conf.eventsfollow.parse::<f64>() {
+            Ok(t) if t > 0.0 && t.is_finite() => Duration::from_secs_f64(t),
+            _ => {
+                println!("无效的follow: {}, 需要为大于0的秒数", conf.eventsfollow);
+                process::exit(exitcode::USAGE);
+            }
+        };
+        events = events.with_follow(interval);
+    }
+    let mut trail = AuditTrail::new(events.position().0);
+    for event in events {
+        let event = event.unwrap_or_else(|err|{
+            println!("SHOW BINLOG EVENTS发生错误: {}", err);
+            process::exit(exitcode::SERVER);
+        });
+        if let Some(record) = trail.feed(&event) {
+            println!("{}", record);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//事件输出目标, 作为库使用时可以实现Sink将数据写入其他系统
+//各个输出目标通过对应的cargo特性开启, 避免只使用解析部分时编译不需要的依赖
+
+use std::collections::HashMap;
+use std::io;
+use crate::reader::Event;
+use crate::replication::readevent::TableMap;
+use crate::meta::ColumnTypeDict;
+
+mod fanout;
+pub use fanout::{FanOutSink, FailurePolicy, Checkpoint};
+mod route;
+pub use route::{RouteTemplate, RoutedSink};
+mod partition;
+pub use partition::{Partitioner, PartitionedSink};
+mod heartbeat;
+pub use heartbeat::{Heartbeat, HeartbeatTracker};
+mod watermark;
+pub use watermark::{Watermark, WatermarkTracker, parse_watermark};
+mod compress;
+pub use compress::{Compressor, CompressedWriter, OutputCompression, compressor, output_compression};
+#[cfg(feature = "compression")]
+pub use compress::Gzip;
+#[cfg(feature = "zstd")]
+pub use compress::Zstd;
+#[cfg(feature = "lz4")]
+pub use compress::Lz4;
+//后台线程、计时依赖操作系统, wasm中不可用
+#[cfg(feature = "os")]
+mod queue;
+#[cfg(feature = "os")]
+pub use queue::{QueuedSink, QueueConfig, QueueMetrics};
+#[cfg(feature = "os")]
+mod batch;
+#[cfg(feature = "os")]
+pub use batch::{BatchSink, BatchConfig, Batcher};
+#[cfg(feature = "os")]
+pub use heartbeat::HeartbeatSink;
+#[cfg(feature = "os")]
+pub use watermark::{WatermarkSink, WatermarkTimer};
+#[cfg(feature = "os")]
+mod merge;
+#[cfg(feature = "os")]
+pub use merge::{OrderedMerge, MergeSource};
+#[cfg(feature = "os")]
+mod apply;
+#[cfg(feature = "os")]
+pub use apply::{ApplySink, ApplyExecutor, ApplyMode, ApplyRules, ConflictPolicy, ConflictRules, ConflictStats, Scheduler};
+#[cfg(feature = "network")]
+pub use apply::MysqlExecutor;
+#[cfg(feature = "os")]
+mod compaction;
+#[cfg(feature = "os")]
+pub use compaction::{FileCompactor, CompactionTask, CompactionStats};
+#[cfg(feature = "os")]
+mod audit;
+#[cfg(feature = "os")]
+pub use audit::{AuditRecord, AuditTable};
+
+#[cfg(feature = "json-sink")]
+mod json;
+#[cfg(feature = "json-sink")]
+pub use json::JsonLinesSink;
+#[cfg(all(feature = "json-sink", feature = "os"))]
+mod parallel;
+#[cfg(all(feature = "json-sink", feature = "os"))]
+pub use parallel::ParallelJsonSink;
+#[cfg(feature = "json-sink")]
+mod registry;
+#[cfg(feature = "json-sink")]
+pub use registry::{SchemaRegistry, RegistrySink, confluent_message};
+#[cfg(feature = "json-sink")]
+pub use crate::stdout::outjsonschema::json_schema;
+#[cfg(all(feature = "json-sink", feature = "network"))]
+pub use registry::HttpSchemaRegistry;
+
+/// 事件的输出目标
+pub trait Sink {
+    fn write_event(&mut self, event: &Event) -> io::Result<()>;
+
+    /// 写入心跳(见HeartbeatSink), 默认忽略
+    fn write_heartbeat(&mut self, _heartbeat: &Heartbeat) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// 写入事件时间的水位(见WatermarkSink), 默认忽略
+    fn write_watermark(&mut self, _watermark: &Watermark) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// 将缓存的数据全部写出
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+//没有表结构时根据TABLE_MAP_EVENT中的字段类型生成字段信息
+//无法区分字符集, varchar、char按字符串输出, blob按二进制输出
+#[cfg_attr(not(feature = "json-sink"), allow(dead_code))]
+pub(crate) fn default_columns(map: &TableMap) -> Vec<HashMap<String, String>> {
+    map.column_info.iter().enumerate().map(|(idx, col)| {
+        let col_type = match col.column_type {
+            ColumnTypeDict::MysqlTypeVarchar |
+            ColumnTypeDict::MysqlTypeVarString |
+            ColumnTypeDict::MysqlTypeString => "varchar",
+            ColumnTypeDict::MysqlTypeTinyBlob |
+            ColumnTypeDict::MysqlTypeMediumBlob |
+            ColumnTypeDict::MysqlTypeLongBlob |
+            ColumnTypeDict::MysqlTypeBlob => "blob",
+            ColumnTypeDict::MysqlTypeGeometry => "geometry",
+            ColumnTypeDict::MysqlTypeTimestamp2 => "timestamp",
+            ColumnTypeDict::MysqlTypeDatetime2 => "datetime",
+            ColumnTypeDict::MysqlTypeTime2 => "time",
+            _ => ""
+        };
+        //时间类型带上小数位数(column_meta[0]), 输出时按fsp补齐小数部分
+        let col_type = match (col_type, col.column_meta.first()) {
+            ("timestamp" | "datetime" | "time", Some(fsp)) if *fsp > 0 => format!("{}({})", col_type, fsp),
+            _ => String::from(col_type)
+        };
+        let mut info = HashMap::new();
+        info.insert(String::from("COLUMN_NAME"), format!("@{}", idx + 1));
+        info.insert(String::from("COLUMN_TYPE"), col_type);
+        info
+    }).collect()
+}
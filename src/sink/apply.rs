@@ -0,0 +1,709 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//将事务应用到目标库, 多个worker并行执行, 每个worker一个连接
+//  事务结束(XID/COMMIT)时按涉及的表或主键计算冲突key, 与正在执行的事务没有冲突时分配给空闲的worker并行执行
+//  与一个worker上未完成的事务冲突时分配给同一个worker, 按顺序执行; 与多个worker冲突时等待其余worker完成
+//  每张表可以单独配置(ApplyRules):
+//    table: 同一张表的事务按顺序执行, 不同表的事务可以并行(默认)
+//    key:   按主键, 同一张表中修改不同主键的事务可以并行, 没有主键时按table处理; 只考虑主键, 有其他唯一键时使用table
+//    serial: 涉及该表的事务等待之前的事务全部完成后单独执行
+//  DDL等待之前的事务全部完成后单独执行, 完成后才继续分配后续的事务
+//  flush等待所有worker执行完已分配的事务, 管道在flush之后保存checkpoint, checkpoint之前的事务都已提交
+//  外键等跨表的约束不在检查范围内, 有外键的表需要配置为serial
+//冲突处理(ConflictRules), 目标库已有部分数据(如重新执行抽取)时使用:
+//  冲突为insert违反唯一键等约束(sqlstate 23000), update、delete在目标库中没有匹配的行
+//  error: 返回错误, 停止应用(默认)
+//  skip: 跳过该行, 事务中的其他语句继续执行
+//  overwrite: insert为INSERT ... ON DUPLICATE KEY UPDATE, update没有匹配的行时按新值插入, delete没有匹配的行时跳过, 可以重复执行
+//  dlq: 将语句及原因以json行写入死信队列(with_dlq), 事务中的其他语句继续执行
+//审计表(with_audit): 不重放事务, 每行变更及DDL写入审计表(见sink::audit), 按binlog中的顺序写入
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crate::nokey::NoKeyPolicy;
+use crate::reader::Event;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::{BinlogEvent, TableMap};
+use crate::replication::parsevalue::MySQLValue;
+use crate::sink::{Sink, default_columns};
+use crate::sink::audit::{AuditRecord, AuditTable};
+use crate::stdout::format::ValueFormat;
+use crate::stdout::outsql::{self, InsertMode};
+use crate::stdout::{outjson, sql_escape};
+use serde_json::json;
+
+/// 表的并行方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApplyMode{
+    Serial,
+    Table,
+    Key,
+}
+
+impl ApplyMode{
+    pub fn new(mode: &str) -> Result<ApplyMode, String> {
+        match mode {
+            "serial" => Ok(ApplyMode::Serial),
+            "" | "table" => Ok(ApplyMode::Table),
+            "key" => Ok(ApplyMode::Key),
+            _ => Err(format!("无效的apply模式: {}, 可选值为serial、table、key", mode))
+        }
+    }
+}
+
+/// 每张表的并行方式, 格式为逗号分隔的db.tbl=模式, 表名可以为*, 未匹配的表为table
+#[derive(Debug, Clone, Default)]
+pub struct ApplyRules{
+    rules: Vec<(String, ApplyMode)>,
+}
+
+impl ApplyRules{
+    pub fn new(rules: &str) -> Result<ApplyRules, String> {
+        Ok(ApplyRules{ rules: parse_rules(rules, ApplyMode::new)? })
+    }
+
+    /// 依次匹配db.tbl、db.*、*
+    pub fn mode(&self, db: &str, tbl: &str) -> ApplyMode {
+        find_rule(&self.rules, db, tbl).unwrap_or(ApplyMode::Table)
+    }
+}
+
+//逗号分隔的db.tbl=值
+fn parse_rules<T>(rules: &str, parse: fn(&str) -> Result<T, String>) -> Result<Vec<(String, T)>, String> {
+    let mut parsed = vec![];
+    for rule in rules.split(',').map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        match rule.rsplit_once('=') {
+            Some((table, value)) => parsed.push((crate::ident::normalize(table), parse(value.trim())?)),
+            None => return Err(format!("无效的配置: {}, 格式为db.tbl=值", rule)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn find_rule<T: Copy>(rules: &[(String, T)], db: &str, tbl: &str) -> Option<T> {
+    let candidates = [format!("{}.{}", db, tbl), format!("{}.*", db), String::from("*")];
+    candidates.iter().find_map(|c| rules.iter().find(|(t, _)| t == c).map(|(_, v)| *v))
+}
+
+/// 冲突的处理方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictPolicy{
+    Error,
+    Skip,
+    Overwrite,
+    Dlq,
+}
+
+impl ConflictPolicy{
+    pub fn new(policy: &str) -> Result<ConflictPolicy, String> {
+        match policy {
+            "" | "error" => Ok(ConflictPolicy::Error),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "dlq" => Ok(ConflictPolicy::Dlq),
+            _ => Err(format!("无效的冲突处理方式: {}, 可选值为error、skip、overwrite、dlq", policy))
+        }
+    }
+}
+
+/// 每张表的冲突处理方式, 格式与ApplyRules相同, 未匹配的表为error
+#[derive(Debug, Clone, Default)]
+pub struct ConflictRules{
+    rules: Vec<(String, ConflictPolicy)>,
+}
+
+impl ConflictRules{
+    pub fn new(rules: &str) -> Result<ConflictRules, String> {
+        Ok(ConflictRules{ rules: parse_rules(rules, ConflictPolicy::new)? })
+    }
+
+    pub fn policy(&self, db: &str, tbl: &str) -> ConflictPolicy {
+        find_rule(&self.rules, db, tbl).unwrap_or(ConflictPolicy::Error)
+    }
+
+    /// 是否有表使用dlq
+    pub fn uses_dlq(&self) -> bool {
+        self.rules.iter().any(|(_, p)| *p == ConflictPolicy::Dlq)
+    }
+}
+
+/// 冲突检测及分配: 事务按顺序编号, 记录每个key最后分配的worker及编号
+pub struct Scheduler{
+    last: HashMap<String, (usize, u64)>,
+    assigned: Vec<u64>,             //每个worker最后分配的编号
+    barrier: Option<(usize, u64)>,  //未完成的DDL、serial事务
+    seq: u64,
+}
+
+impl Scheduler{
+    pub fn new(workers: usize) -> Scheduler {
+        Scheduler{ last: HashMap::new(), assigned: vec![0; workers.max(1)], barrier: None, seq: 0 }
+    }
+
+    /// keys为None时需要单独执行, done为每个worker已完成的编号
+    /// 可以执行时返回(worker, 编号), 否则返回需要等待完成的(worker, 编号), 等待后重新调用
+    pub fn schedule(&mut self, keys: Option<&[String]>, done: &[u64]) -> Result<(usize, u64), Vec<(usize, u64)>> {
+        if let Some((w, s)) = self.barrier.filter(|(w, s)| done[*w] < *s) {
+            return Err(vec![(w, s)]);
+        }
+        let busy = |w: usize, s: u64| done[w] < s;
+        let worker = match keys {
+            None => {
+                let waiting: Vec<(usize, u64)> = self.assigned.iter().enumerate().filter(|(w, s)| busy(*w, **s)).map(|(w, s)| (w, *s)).collect();
+                if !waiting.is_empty() {
+                    return Err(waiting);
+                }
+                0
+            }
+            Some(keys) => {
+                //每个worker上冲突的最大编号
+                let mut conflicts: Vec<(usize, u64)> = vec![];
+                for (w, s) in keys.iter().filter_map(|k| self.last.get(k)).filter(|(w, s)| busy(*w, *s)) {
+                    match conflicts.iter_mut().find(|(cw, _)| cw == w) {
+                        Some(c) => c.1 = c.1.max(*s),
+                        None => conflicts.push((*w, *s)),
+                    }
+                }
+                if conflicts.len() > 1 {
+                    //保留编号最大的worker, 等待其余的完成
+                    conflicts.sort_by_key(|(_, s)| *s);
+                    conflicts.pop();
+                    return Err(conflicts);
+                }
+                match conflicts.first() {
+                    Some((w, _)) => *w,
+                    //空闲的worker, 都在执行时选择最早分配的
+                    None => (0..self.assigned.len())
+                        .min_by_key(|w| (busy(*w, self.assigned[*w]), self.assigned[*w]))
+                        .unwrap_or(0),
+                }
+            }
+        };
+        self.seq += 1;
+        self.assigned[worker] = self.seq;
+        match keys {
+            None => self.barrier = Some((worker, self.seq)),
+            Some(keys) => {
+                for key in keys {
+                    self.last.insert(key.clone(), (worker, self.seq));
+                }
+            }
+        }
+        if self.last.len() > 100000 {
+            self.last.retain(|_, (w, s)| done[*w] < *s);
+        }
+        Ok((worker, self.seq))
+    }
+}
+
+/// 在目标库执行语句, 每个worker一个, 事务通过BEGIN、COMMIT、ROLLBACK语句控制
+pub trait ApplyExecutor: Send {
+    /// 返回影响的行数(update为匹配的行数), 违反唯一键等约束时错误信息中包含sqlstate #23000
+    fn execute(&mut self, sql: &str) -> Result<u64, String>;
+}
+
+/// mysql连接, 网络错误后在下一条语句重新连接
+#[cfg(feature = "network")]
+pub struct MysqlExecutor{
+    conf: crate::Config,
+    conn: Option<std::net::TcpStream>,
+}
+
+#[cfg(feature = "network")]
+impl MysqlExecutor{
+    pub fn new(conf: crate::Config) -> MysqlExecutor {
+        MysqlExecutor{ conf, conn: None }
+    }
+}
+
+#[cfg(feature = "network")]
+impl ApplyExecutor for MysqlExecutor {
+    fn execute(&mut self, sql: &str) -> Result<u64, String> {
+        if self.conn.is_none() {
+            if sql == "ROLLBACK" {
+                return Ok(0);
+            }
+            self.conn = Some(crate::position::connect_mysql(&self.conf).map_err(|e| e.to_string())?);
+        }
+        let result = crate::io::command::try_execute_update(self.conn.as_mut().unwrap(), sql);
+        //语句执行错误时连接仍然可用
+        if let Err(e) = &result {
+            if !e.starts_with("执行语句错误") {
+                self.conn = None;
+            }
+        }
+        result
+    }
+}
+
+/// 冲突的处理次数
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConflictStats{
+    pub skipped: u64,
+    pub overwritten: u64,
+    pub dlq: u64,
+}
+
+#[derive(Default)]
+struct State{
+    done: Vec<u64>,
+    error: Option<String>,
+    conflicts: ConflictStats,
+}
+
+//一条语句, 需要检查影响行数的为update、delete
+struct Statement{
+    sql: String,
+    table: String,
+    policy: ConflictPolicy,
+    check_rows: bool,
+    //overwrite时update没有匹配的行, 改为插入新值
+    fallback: Option<String>,
+}
+
+impl Statement{
+    fn new(sql: String) -> Statement {
+        Statement{ sql, table: String::new(), policy: ConflictPolicy::Error, check_rows: false, fallback: None }
+    }
+}
+
+struct Job{
+    seq: u64,
+    gtid: String,
+    statements: Vec<Statement>,
+}
+
+#[derive(Default)]
+struct Transaction{
+    gtid: String,
+    statements: Vec<Statement>,
+    keys: Vec<String>,
+    serial: bool,
+}
+
+type Dlq = Arc<Mutex<Option<Box<dyn Write + Send>>>>;
+
+//写入审计表时当前事务的信息
+#[derive(Default)]
+struct AuditInfo{
+    change_id: String,
+    seq: u32,
+    thread_id: Option<u32>,
+    invoker: String,
+    query: String,
+}
+
+fn is_conflict(err: &str) -> bool {
+    err.contains("#23000")
+}
+
+//在一个事务中执行, 按冲突处理方式处理每条语句, 返回错误时已ROLLBACK
+fn execute(executor: &mut Box<dyn ApplyExecutor>, job: &Job, dlq: &Dlq, stats: &mut ConflictStats) -> Result<(), String> {
+    let result = (|| {
+        executor.execute("BEGIN")?;
+        for st in &job.statements {
+            let reason = match executor.execute(&st.sql) {
+                Ok(0) if st.check_rows => String::from("目标库中没有匹配的行"),
+                Ok(_) => continue,
+                Err(e) if is_conflict(&e) => e,
+                Err(e) => return Err(format!("{}: {}", e, st.sql)),
+            };
+            match (st.policy, &st.fallback) {
+                (ConflictPolicy::Skip, _) | (ConflictPolicy::Overwrite, None) => stats.skipped += 1,
+                (ConflictPolicy::Overwrite, Some(sql)) => {
+                    executor.execute(sql).map_err(|e| format!("{}: {}", e, sql))?;
+                    stats.overwritten += 1;
+                }
+                (ConflictPolicy::Dlq, _) => {
+                    let mut dlq = dlq.lock().unwrap();
+                    let writer = dlq.as_mut().ok_or_else(|| format!("没有设置死信队列, {}: {}", reason, st.sql))?;
+                    let record = json!({"gtid": job.gtid, "table": st.table, "sql": st.sql, "error": reason});
+                    writeln!(writer, "{}", record).map_err(|e| format!("写入死信队列失败: {}", e))?;
+                    stats.dlq += 1;
+                }
+                (ConflictPolicy::Error, _) => return Err(format!("{}: {}", reason, st.sql)),
+            }
+        }
+        executor.execute("COMMIT").map(|_| ())
+    })();
+    if result.is_err() {
+        let _ = executor.execute("ROLLBACK");
+    }
+    result
+}
+
+/// 按冲突关系并行应用事务的输出目标
+pub struct ApplySink{
+    senders: Vec<SyncSender<Job>>,
+    threads: Vec<JoinHandle<()>>,
+    state: Arc<(Mutex<State>, Condvar)>,
+    scheduler: Scheduler,
+    rules: ApplyRules,
+    conflicts: ConflictRules,
+    dlq: Dlq,
+    format: ValueFormat,
+    no_key: NoKeyPolicy,
+    #[cfg(feature = "network")]
+    metadata: Option<crate::Config>,
+    table_cols_info: HashMap<String, Vec<HashMap<String, String>>>,
+    gtid: String,
+    trx: Option<Transaction>,
+    audit: Option<AuditTable>,
+    audit_info: AuditInfo,
+}
+
+impl ApplySink{
+    /// 每个executor一个worker线程, 至少一个
+    pub fn new(executors: Vec<Box<dyn ApplyExecutor>>) -> ApplySink {
+        let state = Arc::new((Mutex::new(State{ done: vec![0; executors.len().max(1)], ..State::default() }), Condvar::new()));
+        let dlq: Dlq = Arc::new(Mutex::new(None));
+        let (mut senders, mut threads) = (vec![], vec![]);
+        for (worker, mut executor) in executors.into_iter().enumerate() {
+            let (sender, receiver) = mpsc::sync_channel::<Job>(16);
+            let (state, dlq) = (state.clone(), dlq.clone());
+            threads.push(thread::spawn(move || {
+                for job in receiver {
+                    //有事务失败后不再执行, 已分配的事务直接标记完成
+                    let failed = state.0.lock().unwrap().error.is_some();
+                    let mut stats = ConflictStats::default();
+                    let result = if failed { Ok(()) } else { execute(&mut executor, &job, &dlq, &mut stats) };
+                    let (lock, cvar) = &*state;
+                    let mut state = lock.lock().unwrap();
+                    match result {
+                        Ok(()) => {
+                            state.conflicts.skipped += stats.skipped;
+                            state.conflicts.overwritten += stats.overwritten;
+                            state.conflicts.dlq += stats.dlq;
+                        }
+                        Err(e) => {
+                            state.error.get_or_insert(format!("应用事务{}失败: {}", job.gtid, e));
+                        }
+                    }
+                    state.done[worker] = job.seq;
+                    cvar.notify_all();
+                }
+            }));
+            senders.push(sender);
+        }
+        ApplySink{
+            scheduler: Scheduler::new(senders.len()),
+            senders,
+            threads,
+            state,
+            rules: ApplyRules::default(),
+            conflicts: ConflictRules::default(),
+            dlq,
+            format: ValueFormat::default(),
+            no_key: NoKeyPolicy::default(),
+            #[cfg(feature = "network")]
+            metadata: None,
+            table_cols_info: HashMap::new(),
+            gtid: String::new(),
+            trx: None,
+            audit: None,
+            audit_info: AuditInfo::default(),
+        }
+    }
+
+    /// workers个mysql连接, 表结构从目标库获取
+    #[cfg(feature = "network")]
+    pub fn connect(conf: &crate::Config, workers: usize) -> ApplySink {
+        let executors = (0..workers.max(1)).map(|_| Box::new(MysqlExecutor::new(conf.clone())) as Box<dyn ApplyExecutor>).collect();
+        let mut sink = ApplySink::new(executors);
+        sink.metadata = Some(conf.clone());
+        sink
+    }
+
+    pub fn with_rules(mut self, rules: ApplyRules) -> ApplySink {
+        self.rules = rules;
+        self
+    }
+
+    pub fn with_conflicts(mut self, conflicts: ConflictRules) -> ApplySink {
+        self.conflicts = conflicts;
+        self
+    }
+
+    /// 没有主键的表的处理方式, 默认warn; hash-key只用于分区输出, 见NoKeyPolicy::for_sql
+    pub fn with_no_key(mut self, no_key: NoKeyPolicy) -> ApplySink {
+        self.no_key = no_key;
+        self
+    }
+
+    /// 冲突处理方式为dlq时写入的位置, 每行一个json: {"gtid", "table", "sql", "error"}
+    pub fn with_dlq(self, writer: Box<dyn Write + Send>) -> ApplySink {
+        *self.dlq.lock().unwrap() = Some(writer);
+        self
+    }
+
+    /// 不重放事务, 将每行变更写入审计表, 审计表不存在时先创建
+    pub fn with_audit(mut self, table: AuditTable) -> ApplySink {
+        let create = Transaction{ statements: vec![Statement::new(table.create_sql())], serial: true, ..Transaction::default() };
+        self.audit = Some(table);
+        //worker已退出时在写入第一个事件时返回错误
+        let _ = self.dispatch(create);
+        self
+    }
+
+    /// 已提交的事务中冲突的处理次数
+    pub fn conflicts(&self) -> ConflictStats {
+        self.state.0.lock().unwrap().conflicts
+    }
+
+    /// 设置表结构信息, key为db.tbl, 格式与meta::get_col获取的一致(COLUMN_NAME、COLUMN_TYPE、COLUMN_KEY)
+    pub fn with_columns(mut self, table_cols_info: HashMap<String, Vec<HashMap<String, String>>>) -> ApplySink {
+        self.table_cols_info = table_cols_info;
+        self
+    }
+
+    fn columns(&mut self, map: &TableMap) -> Vec<HashMap<String, String>> {
+        let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+        #[cfg(feature = "network")]
+        if let Some(conf) = &self.metadata {
+            crate::meta::get_col(conf, &map.database_name, &map.table_name, &mut self.table_cols_info);
+        }
+        self.table_cols_info.get(&db_tbl).cloned().unwrap_or_else(|| default_columns(map))
+    }
+
+    //行事件生成语句及冲突key
+    fn add_rows(&mut self, code: &BinlogEvent, rows: &[Vec<Option<MySQLValue>>], map: &TableMap) {
+        let cols = self.columns(map);
+        let table = format!("{}.{}", map.database_name, map.table_name);
+        let pri = crate::nokey::key_columns(&cols, map);
+        if !self.no_key.check("apply", &table, &pri) {
+            return;
+        }
+        let mut pri_info: HashMap<String, usize> = HashMap::new();
+        for idx in &pri {
+            pri_info.insert(cols.get(*idx).and_then(|col| col.get("COLUMN_NAME")).cloned().unwrap_or_default(), *idx);
+        }
+        //没有主键时按所有字段匹配, full-row时mysql追加LIMIT 1
+        let full_row = pri.is_empty() && self.no_key == NoKeyPolicy::FullRow;
+        let dialect = self.format.dialect;
+        let limit = |sql: String| if full_row { outsql::limit_one(sql, dialect) } else { sql };
+        let mode = self.rules.mode(&map.database_name, &map.table_name);
+        let key = |row: &Vec<Option<MySQLValue>>| match (mode, pri.is_empty()) {
+            (ApplyMode::Key, false) => format!("{}/{:?}", table, pri.iter().map(|i| row.get(*i)).collect::<Vec<_>>()),
+            _ => table.clone(),
+        };
+        let policy = self.conflicts.policy(&map.database_name, &map.table_name);
+        let insert_mode = if policy == ConflictPolicy::Overwrite { InsertMode::Upsert } else { InsertMode::Insert };
+        let statement = |sql: String, check_rows: bool, fallback: Option<String>| Statement{ sql, table: table.clone(), policy, check_rows, fallback };
+        let trx = self.trx.get_or_insert_with(Transaction::default);
+        trx.serial = trx.serial || mode == ApplyMode::Serial;
+        let rows: Vec<&Vec<Option<MySQLValue>>> = rows.iter().collect();
+        match code {
+            BinlogEvent::UpdateEvent => {
+                for pair in rows.chunks(2).filter(|t| t.len() == 2) {
+                    let fallback = Some(outsql::out_insert(pair[1], &cols, map, InsertMode::Upsert, &self.format)).filter(|_| policy == ConflictPolicy::Overwrite);
+                    trx.statements.push(statement(limit(outsql::out_update(pair[0], pair[1], &cols, &pri_info, map, &self.format)), true, fallback));
+                    trx.keys.push(key(pair[0]));
+                    trx.keys.push(key(pair[1]));
+                }
+            }
+            BinlogEvent::WriteEvent => {
+                for row in rows {
+                    trx.statements.push(statement(outsql::out_insert(row, &cols, map, insert_mode, &self.format), false, None));
+                    trx.keys.push(key(row));
+                }
+            }
+            BinlogEvent::DeleteEvent => {
+                for row in rows {
+                    trx.statements.push(statement(limit(outsql::out_delete(row, &cols, &pri_info, map, &self.format)), true, None));
+                    trx.keys.push(key(row));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    //审计表中的一条记录, 同一个事务中的记录按顺序编号, record中为变更的内容
+    fn audit_statement(&mut self, record: AuditRecord) {
+        let audit = match &self.audit {
+            Some(t) => t,
+            None => return,
+        };
+        self.audit_info.seq += 1;
+        let record = AuditRecord{
+            change_id: self.audit_info.change_id.clone(),
+            seq: self.audit_info.seq,
+            gtid: Some(self.gtid.clone()).filter(|t| !t.is_empty()),
+            user: Some(self.audit_info.invoker.clone()).filter(|t| !t.is_empty()),
+            thread_id: self.audit_info.thread_id,
+            query: record.query.clone().or_else(|| Some(self.audit_info.query.clone()).filter(|t| !t.is_empty())),
+            ..record
+        };
+        //按binlog中的顺序写入, 所有记录使用同一个冲突key
+        let statement = Statement{ table: audit.db_tbl(), ..Statement::new(audit.insert_sql(&record)) };
+        let key = audit.db_tbl();
+        let trx = self.trx.get_or_insert_with(Transaction::default);
+        trx.statements.push(statement);
+        trx.keys.push(key);
+    }
+
+    //行事件写入审计表
+    fn add_audit(&mut self, event: &Event, code: &BinlogEvent, rows: &crate::replication::parsevalue::RowValue, map: &TableMap) {
+        let cols = self.columns(map);
+        let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+        for record in outjson::row_records(rows, code, &cols, &db_tbl, map, &self.gtid, &self.format) {
+            let op = record["type"].as_str().unwrap_or_default().to_string();
+            let (old, new) = match op.as_str() {
+                "delete" => (record.get("data").cloned(), None),
+                _ => (record.get("old").cloned(), record.get("data").cloned()),
+            };
+            self.audit_statement(AuditRecord{
+                timestamp: event.header.timestamp,
+                op,
+                database: map.database_name.clone(),
+                table: map.table_name.clone(),
+                old,
+                new,
+                ..AuditRecord::default()
+            });
+        }
+    }
+
+    //事务开始, 没有gtid时以时间@position区分事务
+    fn begin_audit(&mut self, event: &Event, thread_id: u32, invoker: &str) {
+        let change_id = if self.gtid.is_empty() { format!("{}@{}", event.header.timestamp, event.log_pos) } else { self.gtid.clone() };
+        self.audit_info = AuditInfo{ change_id, seq: 0, thread_id: Some(thread_id), invoker: invoker.to_string(), query: String::new() };
+    }
+
+    //等待这些(worker, 编号)完成, 有worker失败时返回错误
+    fn wait(&self, waiting: &[(usize, u64)]) -> io::Result<()> {
+        let (lock, cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        loop {
+            if let Some(e) = &state.error {
+                return Err(io::Error::other(e.clone()));
+            }
+            if waiting.iter().all(|(w, s)| state.done[*w] >= *s) {
+                return Ok(());
+            }
+            //worker线程panic时不会再通知, 定时检查
+            if self.threads.iter().any(|t| t.is_finished()) {
+                return Err(io::Error::other("apply的worker已退出"));
+            }
+            state = cvar.wait_timeout(state, Duration::from_secs(1)).unwrap().0;
+        }
+    }
+
+    fn dispatch(&mut self, trx: Transaction) -> io::Result<()> {
+        if trx.statements.is_empty() {
+            return Ok(());
+        }
+        let mut keys = trx.keys;
+        keys.sort();
+        keys.dedup();
+        let keys = if trx.serial { None } else { Some(keys.as_slice()) };
+        loop {
+            let done = self.state.0.lock().unwrap().done.clone();
+            match self.scheduler.schedule(keys, &done) {
+                Ok((worker, seq)) => {
+                    let job = Job{ seq, gtid: trx.gtid, statements: trx.statements };
+                    return self.senders[worker].send(job).map_err(|_| io::Error::other("apply的worker已退出"));
+                }
+                Err(waiting) => self.wait(&waiting)?,
+            }
+        }
+    }
+}
+
+impl Sink for ApplySink {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        if let Some(e) = &self.state.0.lock().unwrap().error {
+            return Err(io::Error::other(e.clone()));
+        }
+        match (&event.data, &event.table) {
+            (Traction::GtidEvent(t), _) => {
+                self.gtid = format!("{}:{}", t.gtid, t.gno_id);
+            }
+            (Traction::QueryEvent(t), _) if t.command == "BEGIN" => {
+                self.trx = Some(Transaction{ gtid: self.gtid.clone(), ..Transaction::default() });
+                self.begin_audit(event, t.thread_id, &t.invoker);
+            }
+            (Traction::QueryEvent(t), _) if t.command == "COMMIT" => {
+                if let Some(trx) = self.trx.take() {
+                    self.dispatch(trx)?;
+                }
+            }
+            //DDL单独执行, 之后重新获取表结构
+            (Traction::QueryEvent(t), _) => {
+                if let Some(trx) = self.trx.take() {
+                    self.dispatch(trx)?;
+                }
+                //写入审计表时只记录语句, 不在目标库执行
+                if self.audit.is_some() {
+                    self.begin_audit(event, t.thread_id, &t.invoker);
+                    self.trx = Some(Transaction{ gtid: self.gtid.clone(), ..Transaction::default() });
+                    self.audit_statement(AuditRecord{
+                        timestamp: event.header.timestamp,
+                        op: String::from("ddl"),
+                        database: t.database.clone(),
+                        query: Some(t.command.clone()),
+                        ..AuditRecord::default()
+                    });
+                    let trx = self.trx.take().unwrap_or_default();
+                    self.dispatch(trx)?;
+                    #[cfg(feature = "network")]
+                    if self.metadata.is_some() {
+                        self.table_cols_info.clear();
+                    }
+                    return Ok(());
+                }
+                let mut statements = vec![];
+                if !t.database.is_empty() {
+                    statements.push(Statement::new(format!("USE {}", sql_escape::quote_identifier(&t.database))));
+                }
+                statements.push(Statement::new(t.command.clone()));
+                self.dispatch(Transaction{ gtid: self.gtid.clone(), statements, keys: vec![], serial: true })?;
+                #[cfg(feature = "network")]
+                if self.metadata.is_some() {
+                    self.table_cols_info.clear();
+                }
+            }
+            (Traction::RowsQueryEvent(t), _) => {
+                self.audit_info.query = t.query.clone();
+            }
+            (Traction::RowEvent(code, rows), Some(map)) if self.audit.is_some() => {
+                self.add_audit(event, code, rows, map);
+            }
+            (Traction::RowEvent(code, rows), Some(map)) => {
+                self.add_rows(code, rows.rows(), map);
+            }
+            (Traction::XidEvent(_), _) => {
+                if let Some(trx) = self.trx.take() {
+                    self.dispatch(trx)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// 等待已分配的事务全部执行完成
+    fn flush(&mut self) -> io::Result<()> {
+        let assigned: Vec<(usize, u64)> = self.scheduler.assigned.iter().cloned().enumerate().collect();
+        self.wait(&assigned)?;
+        match self.dlq.lock().unwrap().as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ApplySink {
+    fn drop(&mut self) {
+        self.senders.clear();
+        for t in self.threads.drain(..) {
+            let _ = t.join();
+        }
+    }
+}
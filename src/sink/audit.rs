@@ -0,0 +1,85 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//审计表: apply时不在目标库重放事务, 而是将每行变更追加写入一张审计表, binlog成为可以用sql查询的审计日志
+//  每行变更一条记录: 操作(insert/update/delete, DDL为ddl)、库表、修改前后的数据(json)、gtid、时间、用户、thread_id、原始sql
+//  (change_id, seq)为唯一键, change_id为gtid(没有gtid时为事务开始的时间@position), seq为事务中的第几条记录,
+//  从checkpoint之前重新拉取时重复的记录被忽略(ON DUPLICATE KEY UPDATE), 审计表中每个变更只有一条
+//  审计表不存在时自动创建, 需要mysql 5.7及以上(json类型)
+
+use serde_json::Value as JsonValue;
+use crate::stdout::sql_escape;
+
+/// 审计表中的一条记录
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditRecord{
+    pub change_id: String,
+    pub seq: u32,
+    pub timestamp: u32,
+    /// insert、update、delete、ddl
+    pub op: String,
+    pub database: String,
+    pub table: String,
+    pub old: Option<JsonValue>,
+    pub new: Option<JsonValue>,
+    pub gtid: Option<String>,
+    /// Q_INVOKER中的用户
+    pub user: Option<String>,
+    pub thread_id: Option<u32>,
+    /// binlog_rows_query_log_events记录的原始sql, DDL为语句
+    pub query: Option<String>,
+}
+
+/// 写入的审计表, 格式为db.tbl
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditTable{
+    pub database: String,
+    pub table: String,
+}
+
+impl AuditTable{
+    pub fn new(table: &str) -> Result<AuditTable, String> {
+        let (database, table) = table.trim().split_once('.')
+            .filter(|(db, tbl)| !db.trim().is_empty() && !tbl.trim().is_empty())
+            .ok_or_else(|| format!("无效的审计表: {}, 格式为db.tbl", table))?;
+        Ok(AuditTable{ database: database.trim().trim_matches('`').to_string(), table: table.trim().trim_matches('`').to_string() })
+    }
+
+    pub fn db_tbl(&self) -> String {
+        format!("{}.{}", self.database, self.table)
+    }
+
+    /// 审计表不存在时创建
+    pub fn create_sql(&self) -> String {
+        format!("CREATE TABLE IF NOT EXISTS {}(id bigint unsigned NOT NULL AUTO_INCREMENT PRIMARY KEY, \
+            change_id varchar(128) NOT NULL, seq int unsigned NOT NULL, ts int unsigned NOT NULL, event_time datetime NOT NULL, \
+            op varchar(16) NOT NULL, db varchar(64) NOT NULL, tbl varchar(64) NOT NULL, old_data json NULL, new_data json NULL, \
+            gtid varchar(128) NULL, user varchar(288) NULL, thread_id int unsigned NULL, query longtext NULL, \
+            UNIQUE KEY uk_change(change_id, seq), KEY idx_table(db, tbl, ts)) DEFAULT CHARSET=utf8mb4",
+            sql_escape::quote_table(&self.database, &self.table))
+    }
+
+    /// 写入一条记录, 已存在时忽略
+    pub fn insert_sql(&self, record: &AuditRecord) -> String {
+        let text = |value: &Option<String>| value.as_deref().map(sql_escape::quote_string).unwrap_or_else(|| String::from(sql_escape::NULL));
+        let json = |value: &Option<JsonValue>| text(&value.as_ref().map(|t| t.to_string()));
+        format!("INSERT INTO {}(change_id, seq, ts, event_time, op, db, tbl, old_data, new_data, gtid, user, thread_id, query) \
+            VALUES ({}, {}, {}, '{}', {}, {}, {}, {}, {}, {}, {}, {}, {}) ON DUPLICATE KEY UPDATE change_id = change_id",
+            sql_escape::quote_table(&self.database, &self.table),
+            sql_escape::quote_string(&record.change_id),
+            record.seq,
+            record.timestamp,
+            crate::stdout::format::utc_datetime(record.timestamp),
+            sql_escape::quote_string(&record.op),
+            sql_escape::quote_string(&record.database),
+            sql_escape::quote_string(&record.table),
+            json(&record.old),
+            json(&record.new),
+            text(&record.gtid),
+            text(&record.user),
+            record.thread_id.map(|t| t.to_string()).unwrap_or_else(|| String::from(sql_escape::NULL)),
+            text(&record.query))
+    }
+}
@@ -0,0 +1,150 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/02
+*/
+
+//输出目标通用的批量写入: 缓存事件, 达到行数、字节数或等待时间的上限时一次写出
+//  行数按行事件中的行数计算(update的前后镜像算一行), 字节数按事件大小计算
+//  等待时间只在写入下一个事件或调用flush时检查, 不会单独启动定时器
+//  批量的大小决定了吞吐与延迟: 批量越大吞吐越高, 数据到达目标的延迟也越大
+//  缓存的事件计入内存上限(见memory模块), Spill策略下超过上限的行事件写入临时文件, 仍超过上限时提前写出
+
+use std::io;
+use std::time::{Duration, Instant};
+use crate::memory::{self, EventBuffer};
+use crate::reader::Event;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::BinlogEvent;
+use crate::sink::{Sink, Heartbeat, Watermark};
+
+/// 按批写入的输出目标
+pub trait BatchSink {
+    fn write_batch(&mut self, events: &[Event]) -> io::Result<()>;
+
+    /// 写入心跳, 默认忽略
+    fn write_heartbeat(&mut self, _heartbeat: &Heartbeat) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// 写入水位, 默认忽略
+    fn write_watermark(&mut self, _watermark: &Watermark) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+//任意Sink都可以按批写入: 逐个写入后flush
+impl<S: Sink> BatchSink for S {
+    fn write_batch(&mut self, events: &[Event]) -> io::Result<()> {
+        for event in events {
+            self.write_event(event)?;
+        }
+        self.flush()
+    }
+
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        Sink::write_heartbeat(self, heartbeat)
+    }
+
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        Sink::write_watermark(self, watermark)
+    }
+}
+
+/// 批量的上限, 为0表示不限制, 全部为0时每个事件单独写出
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BatchConfig{
+    pub max_rows: usize,
+    pub max_bytes: usize,
+    pub max_latency: Duration,
+}
+
+/// 缓存事件并按BatchConfig批量写入
+pub struct Batcher<S: BatchSink>{
+    sink: S,
+    config: BatchConfig,
+    events: EventBuffer,
+    rows: usize,
+    bytes: usize,
+    first_at: Option<Instant>,
+}
+
+impl<S: BatchSink> Batcher<S> {
+    pub fn new(sink: S, config: BatchConfig) -> Batcher<S> {
+        Batcher{
+            sink,
+            config,
+            events: EventBuffer::new(),
+            rows: 0,
+            bytes: 0,
+            first_at: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    fn is_full(&self) -> bool {
+        let c = &self.config;
+        (c.max_rows == 0 && c.max_bytes == 0 && c.max_latency == Duration::from_secs(0))
+            || (c.max_rows > 0 && self.rows >= c.max_rows)
+            || (c.max_bytes > 0 && self.bytes >= c.max_bytes)
+            || (c.max_latency > Duration::from_secs(0) && self.first_at.map(|t| t.elapsed() >= c.max_latency).unwrap_or(false))
+            || memory::exceeded()
+    }
+
+    fn write_out(&mut self) -> io::Result<()> {
+        if self.events.is_empty() {
+            return Ok(());
+        }
+        let events = self.events.take()?;
+        //写入失败时保留, 下次重新写入
+        if let Err(e) = self.sink.write_batch(&events) {
+            for event in events {
+                self.events.push(event)?;
+            }
+            return Err(e);
+        }
+        self.rows = 0;
+        self.bytes = 0;
+        self.first_at = None;
+        Ok(())
+    }
+}
+
+fn row_count(data: &Traction) -> usize {
+    match data {
+        Traction::RowEvent(BinlogEvent::UpdateEvent, rows) => rows.rows().len() / 2,
+        Traction::RowEvent(_, rows) => rows.rows().len(),
+        _ => 0
+    }
+}
+
+impl<S: BatchSink> Sink for Batcher<S> {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        self.first_at.get_or_insert_with(Instant::now);
+        self.rows += row_count(&event.data);
+        self.bytes += event.header.event_length as usize;
+        self.events.push(event.clone())?;
+        if self.is_full() {
+            self.write_out()?;
+        }
+        Ok(())
+    }
+
+    //先写出缓存的事件, 心跳不会早于之前的数据到达下游
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        self.write_out()?;
+        self.sink.write_heartbeat(heartbeat)
+    }
+
+    //水位同样在之前的数据之后到达下游
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        self.write_out()?;
+        self.sink.write_watermark(watermark)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_out()
+    }
+}
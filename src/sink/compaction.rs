@@ -0,0 +1,279 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//小文件合并: 按{date}等分区长时间写入的文件输出, 每个分区目录中会积累大量小文件, 查询引擎逐个打开文件的开销很大
+//  分区关闭(不再写入)后将目录中的小文件按文件名顺序拼接为较大的文件, json行、gzip member、zstd/lz4 frame拼接后仍然有效
+//  扩展名(第一个.之后的部分, 如json、json.gz)不同的文件不合并; 以.开头及.tmp结尾的文件不处理
+//  合并结果替换该组的第一个文件(rename), 文件名的顺序不变, 再删除其余的文件
+//  合并前在目录中写入.compacting记录要删除的文件, 中途退出时下一次合并该目录时先完成或撤销, 数据不会重复或丢失
+//  CompactionTask在后台线程中合并, RoutedSink::with_compaction在分区目录中最后一个输出目标关闭时提交该目录,
+//  之后又写入该目录时先撤销尚未开始的合并或等待正在进行的合并完成, 不会同时写入及合并同一个目录
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+//合并中途退出时用于恢复的记录
+const MARKER: &str = ".compacting";
+const TMP: &str = ".compacting.tmp";
+
+/// 合并的统计
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionStats{
+    /// 处理的分区目录数
+    pub partitions: u64,
+    /// 被合并的小文件数
+    pub files: u64,
+    /// 合并后生成的文件数
+    pub outputs: u64,
+    /// 合并的字节数
+    pub bytes: u64,
+    /// 合并失败的目录及原因
+    pub errors: Vec<String>,
+}
+
+/// 合并一个目录中的小文件
+#[derive(Debug, Clone)]
+pub struct FileCompactor{
+    target_size: u64,
+    min_files: usize,
+    min_age: Duration,
+}
+
+impl FileCompactor {
+    /// target_size为合并后文件的目标大小, 超过该大小的文件不参与合并
+    pub fn new(target_size: u64) -> FileCompactor {
+        FileCompactor{ target_size, min_files: 2, min_age: Duration::from_secs(0) }
+    }
+
+    /// 一组少于min_files个文件时不合并, 默认2
+    pub fn min_files(mut self, min_files: usize) -> FileCompactor {
+        self.min_files = min_files.max(2);
+        self
+    }
+
+    /// 最后修改时间距今不到min_age的文件可能仍在写入, 不参与合并, 默认不限制
+    pub fn min_age(mut self, min_age: Duration) -> FileCompactor {
+        self.min_age = min_age;
+        self
+    }
+
+    /// 合并目录中的小文件, 返回本次的统计
+    pub fn compact<P: AsRef<Path>>(&self, dir: P) -> io::Result<CompactionStats> {
+        let dir = dir.as_ref();
+        recover(dir)?;
+        let mut stats = CompactionStats{ partitions: 1, ..CompactionStats::default() };
+        for group in self.groups(dir)? {
+            let bytes = merge(dir, &group)?;
+            stats.files += group.len() as u64;
+            stats.outputs += 1;
+            stats.bytes += bytes;
+        }
+        Ok(stats)
+    }
+
+    //按扩展名、文件名排序后, 将连续的小文件按target_size分组
+    fn groups(&self, dir: &Path) -> io::Result<Vec<Vec<PathBuf>>> {
+        let now = SystemTime::now();
+        let mut files = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let meta = entry.metadata()?;
+            if !meta.is_file() || name.starts_with('.') || name.ends_with(".tmp") {
+                continue;
+            }
+            let age = meta.modified().ok().and_then(|t| now.duration_since(t).ok()).unwrap_or_default();
+            if age < self.min_age {
+                continue;
+            }
+            let extension = name.split_once('.').map(|(_, t)| t.to_string()).unwrap_or_default();
+            files.push((extension, name, meta.len()));
+        }
+        files.sort();
+
+        let mut groups = vec![];
+        let mut group: Vec<PathBuf> = vec![];
+        let (mut extension, mut size) = (String::new(), 0);
+        for (ext, name, len) in files {
+            if ext != extension || size + len > self.target_size {
+                if group.len() >= self.min_files {
+                    groups.push(group);
+                }
+                group = vec![];
+                size = 0;
+                extension = ext;
+            }
+            if len >= self.target_size {
+                continue;
+            }
+            group.push(dir.join(name));
+            size += len;
+        }
+        if group.len() >= self.min_files {
+            groups.push(group);
+        }
+        Ok(groups)
+    }
+}
+
+//拼接一组文件, 结果替换第一个文件
+fn merge(dir: &Path, group: &[PathBuf]) -> io::Result<u64> {
+    let tmp = dir.join(TMP);
+    let mut out = File::create(&tmp)?;
+    let mut bytes = 0;
+    for path in group {
+        bytes += io::copy(&mut File::open(path)?, &mut out)?;
+    }
+    out.flush()?;
+    out.sync_all()?;
+    drop(out);
+    //先记录要删除的文件, rename之后中途退出时由recover删除
+    let removed: Vec<String> = group[1..].iter().map(|t| t.file_name().unwrap_or_default().to_string_lossy().to_string()).collect();
+    let marker = dir.join(MARKER);
+    fs::write(dir.join(format!("{}.new", MARKER)), removed.join("\n"))?;
+    fs::rename(dir.join(format!("{}.new", MARKER)), &marker)?;
+    fs::rename(&tmp, &group[0])?;
+    for path in &group[1..] {
+        remove(path)?;
+    }
+    fs::remove_file(&marker)?;
+    Ok(bytes)
+}
+
+//上一次合并中途退出: 合并结果还没有rename时撤销, 已经rename时删除剩余的文件
+fn recover(dir: &Path) -> io::Result<()> {
+    let marker = dir.join(MARKER);
+    let removed = match fs::read_to_string(&marker) {
+        Ok(t) => t,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            remove(&dir.join(TMP))?;
+            return remove(&dir.join(format!("{}.new", MARKER)));
+        }
+        Err(e) => return Err(e),
+    };
+    if dir.join(TMP).exists() {
+        remove(&dir.join(TMP))?;
+    } else {
+        for name in removed.lines().filter(|t| !t.is_empty()) {
+            remove(&dir.join(name))?;
+        }
+    }
+    fs::remove_file(&marker)
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+struct Queue{
+    pending: VecDeque<PathBuf>,
+    current: Option<PathBuf>,
+    stopped: bool,
+    stats: CompactionStats,
+}
+
+/// 后台合并线程, clone后共用同一个线程及统计
+#[derive(Clone)]
+pub struct CompactionTask{
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl CompactionTask {
+    pub fn spawn(compactor: FileCompactor) -> CompactionTask {
+        let queue = Arc::new((Mutex::new(Queue{ pending: VecDeque::new(), current: None, stopped: false, stats: CompactionStats::default() }), Condvar::new()));
+        let shared = queue.clone();
+        let handle = thread::spawn(move || {
+            let (lock, cond) = &*shared;
+            loop {
+                let dir = {
+                    let mut queue = lock.lock().unwrap();
+                    loop {
+                        if let Some(dir) = queue.pending.pop_front() {
+                            queue.current = Some(dir.clone());
+                            break dir;
+                        }
+                        if queue.stopped {
+                            return;
+                        }
+                        queue = cond.wait(queue).unwrap();
+                    }
+                };
+                let result = compactor.compact(&dir);
+                let mut queue = lock.lock().unwrap();
+                match result {
+                    Ok(t) => {
+                        queue.stats.partitions += t.partitions;
+                        queue.stats.files += t.files;
+                        queue.stats.outputs += t.outputs;
+                        queue.stats.bytes += t.bytes;
+                    }
+                    Err(e) => queue.stats.errors.push(format!("{}: {}", dir.display(), e)),
+                }
+                queue.current = None;
+                cond.notify_all();
+            }
+        });
+        CompactionTask{ queue, handle: Arc::new(Mutex::new(Some(handle))) }
+    }
+
+    /// 提交已关闭的分区目录, 按提交的顺序合并
+    pub fn submit<P: AsRef<Path>>(&self, dir: P) {
+        let (lock, cond) = &*self.queue;
+        let mut queue = lock.lock().unwrap();
+        let dir = dir.as_ref().to_path_buf();
+        if !queue.pending.contains(&dir) {
+            queue.pending.push_back(dir);
+        }
+        cond.notify_all();
+    }
+
+    /// 分区重新写入之前调用: 撤销尚未开始的合并, 正在合并时等待完成
+    pub fn reopen<P: AsRef<Path>>(&self, dir: P) {
+        let (lock, cond) = &*self.queue;
+        let mut queue = lock.lock().unwrap();
+        queue.pending.retain(|t| t != dir.as_ref());
+        while queue.current.as_deref() == Some(dir.as_ref()) {
+            queue = cond.wait(queue).unwrap();
+        }
+    }
+
+    /// 当前的统计
+    pub fn stats(&self) -> CompactionStats {
+        self.queue.0.lock().unwrap().stats.clone()
+    }
+
+    /// 等待已提交的目录合并完成后返回统计, 之后提交的目录不再合并
+    pub fn finish(self) -> CompactionStats {
+        self.stop();
+        self.stats()
+    }
+
+    fn stop(&self) {
+        let (lock, cond) = &*self.queue;
+        lock.lock().unwrap().stopped = true;
+        cond.notify_all();
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for CompactionTask {
+    //最后一个clone drop时等待已提交的目录合并完成
+    fn drop(&mut self) {
+        if Arc::strong_count(&self.handle) == 1 {
+            self.stop();
+        }
+    }
+}
@@ -0,0 +1,478 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//输出内容的压缩: json输出内容重复度高, 压缩后网络传输及存储一般只需要原来的1/5~1/10
+//  文件: CompressedWriter每次flush(或缓存超过1MB)时压缩为一个独立的gzip member/zstd frame/lz4 frame,
+//        多个member连在一起仍然是有效的压缩文件, 追加写入已有文件同样可以直接用gzip -d、zcat、zstd -d、lz4 -d解压
+//        with_workers时按块分配给多个线程并行压缩, 按顺序写出, 输出内容与单线程相同
+//  webhook: 请求体压缩, 设置Content-Encoding
+//  消息(RegistrySink): 每条消息单独压缩, 消费端按content_encoding()解压, 需要由producer写入消息头
+//内置gzip(compression特性)、zstd(zstd特性)、lz4(lz4特性), 其他算法实现Compressor接入, 比如:
+//  impl Compressor for Brotli { fn encoding(&self) -> &str { "br" } fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> { ... } }
+//配置格式为 算法[:级别][,key=value...], 例如gzip:9、zstd:19,dict=/etc/mytest/orders.dict,workers=4、lz4,block=4m
+//  workers: 并行压缩的线程数, 只用于文件输出(backup --output-compress、pipelines的compress)
+//  zstd: dict为zstd --train生成的字典(解压时需要zstd -d -D指定同一个字典), window-log为窗口大小(10-31), checksum为每个frame附加校验
+//  lz4: block为64k、256k、1m、4m, checksum为每个frame附加校验, 不支持压缩级别
+
+use std::io::{self, Write};
+use std::sync::Arc;
+#[cfg(feature = "os")]
+use std::collections::VecDeque;
+#[cfg(feature = "os")]
+use std::sync::{Mutex, mpsc::{self, Receiver, SyncSender, TryRecvError}};
+#[cfg(feature = "os")]
+use std::thread::{self, JoinHandle};
+
+/// 压缩算法, 并行压缩时多个线程共用同一个实例
+pub trait Compressor: Send + Sync {
+    /// Content-Encoding中的名称, 如gzip、zstd
+    fn encoding(&self) -> &str;
+
+    /// 压缩文件的扩展名, 如gz、zst
+    fn extension(&self) -> &str {
+        self.encoding()
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// gzip, level为0-9, 默认6
+#[cfg(feature = "compression")]
+#[derive(Debug, Clone, Copy)]
+pub struct Gzip{
+    pub level: u32,
+}
+
+#[cfg(feature = "compression")]
+impl Default for Gzip {
+    fn default() -> Gzip {
+        Gzip{ level: 6 }
+    }
+}
+
+#[cfg(feature = "compression")]
+impl Compressor for Gzip {
+    fn encoding(&self) -> &str {
+        "gzip"
+    }
+
+    fn extension(&self) -> &str {
+        "gz"
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::with_capacity(data.len() / 4), flate2::Compression::new(self.level));
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+}
+
+/// zstd, level为1-22(或负数的快速模式), 默认3
+#[cfg(feature = "zstd")]
+#[derive(Clone)]
+pub struct Zstd{
+    level: i32,
+    window_log: Option<u32>,
+    checksum: bool,
+    dictionary: Option<Arc<zstd::dict::EncoderDictionary<'static>>>,
+}
+
+#[cfg(feature = "zstd")]
+impl Default for Zstd {
+    fn default() -> Zstd {
+        Zstd::new(3)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Zstd {
+    pub fn new(level: i32) -> Zstd {
+        Zstd{ level, window_log: None, checksum: false, dictionary: None }
+    }
+
+    /// 使用预先训练的字典(zstd --train), 小块数据(单条消息、频繁flush)的压缩率提升明显, 解压时需要同一个字典
+    pub fn with_dictionary(mut self, dictionary: &[u8]) -> Zstd {
+        self.dictionary = Some(Arc::new(zstd::dict::EncoderDictionary::copy(dictionary, self.level)));
+        self
+    }
+
+    /// 窗口大小为2^window_log, 越大压缩率越高、解压时占用的内存越多, 超过27时解压需要--long
+    pub fn window_log(mut self, window_log: u32) -> Zstd {
+        self.window_log = Some(window_log);
+        self
+    }
+
+    /// 每个frame附加校验
+    pub fn checksum(mut self, checksum: bool) -> Zstd {
+        self.checksum = checksum;
+        self
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Compressor for Zstd {
+    fn encoding(&self) -> &str {
+        "zstd"
+    }
+
+    fn extension(&self) -> &str {
+        "zst"
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let out = Vec::with_capacity(data.len() / 4);
+        let mut encoder = match &self.dictionary {
+            Some(dictionary) => zstd::stream::Encoder::with_prepared_dictionary(out, dictionary)?,
+            None => zstd::stream::Encoder::new(out, self.level)?,
+        };
+        if let Some(window_log) = self.window_log {
+            encoder.window_log(window_log)?;
+        }
+        encoder.include_checksum(self.checksum)?;
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+}
+
+/// lz4 frame格式, 压缩率低于gzip、zstd, cpu占用少, 适合写入量大、cpu紧张的机器
+#[cfg(feature = "lz4")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lz4{
+    block_size: lz4_flex::frame::BlockSize,
+    checksum: bool,
+}
+
+#[cfg(feature = "lz4")]
+impl Lz4 {
+    /// lz4的块大小: 65536、262144、1048576、4194304
+    pub fn block_size(mut self, size: usize) -> Lz4 {
+        use lz4_flex::frame::BlockSize;
+        self.block_size = match size {
+            0..=65536 => BlockSize::Max64KB,
+            65537..=262144 => BlockSize::Max256KB,
+            262145..=1048576 => BlockSize::Max1MB,
+            _ => BlockSize::Max4MB,
+        };
+        self
+    }
+
+    /// 每个frame附加校验
+    pub fn checksum(mut self, checksum: bool) -> Lz4 {
+        self.checksum = checksum;
+        self
+    }
+}
+
+#[cfg(feature = "lz4")]
+impl Compressor for Lz4 {
+    fn encoding(&self) -> &str {
+        "lz4"
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let info = lz4_flex::frame::FrameInfo::new().block_size(self.block_size).content_checksum(self.checksum);
+        let mut encoder = lz4_flex::frame::FrameEncoder::with_frame_info(info, Vec::with_capacity(data.len() / 2));
+        encoder.write_all(data)?;
+        encoder.finish().map_err(io::Error::other)
+    }
+}
+
+//缓存超过该大小时压缩写出
+const MAX_BUFFER: usize = 1 << 20;
+
+/// 压缩后写入, flush时将缓存的数据压缩为一个独立的块写出, drop时写出剩余的数据
+pub struct CompressedWriter<W: Write>{
+    writer: W,
+    compressor: Arc<dyn Compressor>,
+    buf: Vec<u8>,
+    #[cfg(feature = "os")]
+    workers: Option<Workers>,
+}
+
+impl<W: Write> CompressedWriter<W> {
+    pub fn new(writer: W, compressor: Box<dyn Compressor>) -> CompressedWriter<W> {
+        CompressedWriter::shared(writer, Arc::from(compressor))
+    }
+
+    fn shared(writer: W, compressor: Arc<dyn Compressor>) -> CompressedWriter<W> {
+        CompressedWriter{
+            writer,
+            compressor,
+            buf: vec![],
+            #[cfg(feature = "os")]
+            workers: None,
+        }
+    }
+
+    /// 使用workers个线程并行压缩, 同时压缩中的块最多为workers * 2个, 每块最大1MB
+    /// 只有连续写入超过1MB时才能并行, 频繁flush的输出每次flush都需要等待压缩完成
+    #[cfg(feature = "os")]
+    pub fn with_workers(mut self, workers: usize) -> CompressedWriter<W> {
+        self.workers = match workers {
+            0 | 1 => None,
+            n => Some(Workers::new(n, self.compressor.clone())),
+        };
+        self
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        #[cfg(feature = "os")]
+        if let Some(workers) = &mut self.workers {
+            return workers.submit(std::mem::take(&mut self.buf), &mut self.writer);
+        }
+        let block = self.compressor.compress(&self.buf)?;
+        self.writer.write_all(&block)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= MAX_BUFFER {
+            self.write_block()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        #[cfg(feature = "os")]
+        if let Some(workers) = &mut self.workers {
+            workers.write_all(&mut self.writer)?;
+        }
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for CompressedWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        #[cfg(feature = "os")]
+        if let Some(workers) = self.workers.take() {
+            workers.stop();
+        }
+    }
+}
+
+#[cfg(feature = "os")]
+type Block = io::Result<Vec<u8>>;
+
+//并行压缩的线程, 与ParallelJsonSink相同按提交的顺序等待结果
+#[cfg(feature = "os")]
+struct Workers{
+    sender: SyncSender<(Vec<u8>, SyncSender<Block>)>,
+    handles: Vec<JoinHandle<()>>,
+    pending: VecDeque<Receiver<Block>>,
+    capacity: usize,
+}
+
+#[cfg(feature = "os")]
+impl Workers {
+    fn new(workers: usize, compressor: Arc<dyn Compressor>) -> Workers {
+        let capacity = workers * 2;
+        let (sender, receiver) = mpsc::sync_channel::<(Vec<u8>, SyncSender<Block>)>(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handles = (0..workers).map(|_| {
+            let receiver = receiver.clone();
+            let compressor = compressor.clone();
+            thread::spawn(move || loop {
+                //只在取任务时持有锁
+                let job = match receiver.lock() {
+                    Ok(t) => t.recv(),
+                    Err(_) => return
+                };
+                let (data, reply) = match job {
+                    Ok(t) => t,
+                    Err(_) => return
+                };
+                let _ = reply.send(compressor.compress(&data));
+            })
+        }).collect();
+        Workers{ sender, handles, pending: VecDeque::new(), capacity }
+    }
+
+    fn submit<W: Write>(&mut self, data: Vec<u8>, writer: &mut W) -> io::Result<()> {
+        if self.pending.len() >= self.capacity {
+            self.write_front(writer)?;
+        }
+        let (reply, result) = mpsc::sync_channel(1);
+        self.sender.send((data, reply)).map_err(|_| io::Error::other("压缩线程已退出"))?;
+        self.pending.push_back(result);
+        //写出已经压缩完成的块, 不等待
+        while let Some(result) = self.pending.front() {
+            let block = match result.try_recv() {
+                Ok(t) => t?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err(io::Error::other("压缩线程异常退出"))
+            };
+            self.pending.pop_front();
+            writer.write_all(&block)?;
+        }
+        Ok(())
+    }
+
+    fn write_front<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        if let Some(result) = self.pending.pop_front() {
+            let block = result.recv().map_err(|_| io::Error::other("压缩线程异常退出"))??;
+            writer.write_all(&block)?;
+        }
+        Ok(())
+    }
+
+    fn write_all<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        while !self.pending.is_empty() {
+            self.write_front(writer)?;
+        }
+        Ok(())
+    }
+
+    fn stop(self) {
+        drop(self.sender);
+        for handle in self.handles {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// 文件输出的压缩配置: 压缩算法及并行压缩的线程数, 见output_compression
+#[derive(Clone)]
+pub struct OutputCompression{
+    pub compressor: Arc<dyn Compressor>,
+    pub workers: usize,
+}
+
+impl OutputCompression {
+    /// 包装写入目标, 按workers并行压缩
+    #[cfg_attr(not(feature = "os"), allow(unused_mut))]
+    pub fn writer<W: Write>(&self, writer: W) -> CompressedWriter<W> {
+        let mut writer = CompressedWriter::shared(writer, self.compressor.clone());
+        #[cfg(feature = "os")]
+        {
+            writer = writer.with_workers(self.workers);
+        }
+        writer
+    }
+}
+
+/// 按名称创建内置的压缩算法: gzip、gzip:9、zstd:19,dict=...、lz4, none或空为不压缩, workers被忽略
+pub fn compressor(spec: &str) -> Result<Option<Box<dyn Compressor>>, String> {
+    Ok(parse(spec)?.map(|(compressor, _)| compressor))
+}
+
+/// 文件输出的压缩配置, 与compressor相同并支持workers
+pub fn output_compression(spec: &str) -> Result<Option<OutputCompression>, String> {
+    Ok(parse(spec)?.map(|(compressor, workers)| OutputCompression{ compressor: Arc::from(compressor), workers }))
+}
+
+//压缩算法及workers
+type Parsed = Option<(Box<dyn Compressor>, usize)>;
+
+#[cfg_attr(not(all(feature = "zstd", feature = "lz4")), allow(unused_variables, unused_mut))]
+fn parse(spec: &str) -> Result<Parsed, String> {
+    let mut items = spec.split(',').map(|t| t.trim());
+    let head = items.next().unwrap_or_default();
+    let (name, level) = match head.split_once(':') {
+        Some((name, level)) => (name.to_lowercase(), Some(level)),
+        None => (head.to_lowercase(), None),
+    };
+    //key=value, 不带值时为true
+    let mut options = vec![];
+    let mut workers = 1;
+    for item in items.filter(|t| !t.is_empty()) {
+        let (key, value) = match item.split_once('=') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            None => (item.to_lowercase(), "true"),
+        };
+        match key.as_str() {
+            "workers" => workers = match value.parse::<usize>() {
+                Ok(t) if t > 0 => t,
+                _ => return Err(format!("无效的workers: {}", value)),
+            },
+            _ => options.push((key, value)),
+        }
+    }
+    let unknown = |name: &str, key: &str| Err(format!("{}不支持的压缩参数: {}", name, key));
+    let flag = |key: &str, value: &str| match value.to_lowercase().as_str() {
+        "true" | "on" | "1" => Ok(true),
+        "false" | "off" | "0" => Ok(false),
+        _ => Err(format!("无效的{}: {}, 可选值为on、off", key, value))
+    };
+    let compressor: Option<Box<dyn Compressor>> = match name.as_str() {
+        "" | "none" => {
+            if let Some((key, _)) = options.first() {
+                return unknown("none", key);
+            }
+            None
+        }
+        #[cfg(feature = "compression")]
+        "gzip" => {
+            let level = match level.map(|t| t.parse::<u32>()) {
+                None => Gzip::default().level,
+                Some(Ok(t)) if t <= 9 => t,
+                _ => return Err(format!("无效的gzip压缩级别: {}", level.unwrap_or_default())),
+            };
+            if let Some((key, _)) = options.first() {
+                return unknown("gzip", key);
+            }
+            Some(Box::new(Gzip{ level }))
+        }
+        #[cfg(not(feature = "compression"))]
+        "gzip" => return Err(String::from("gzip压缩需要开启compression特性")),
+        #[cfg(feature = "zstd")]
+        "zstd" => {
+            let range = zstd::compression_level_range();
+            let level = match level.map(|t| t.parse::<i32>()) {
+                None => Zstd::default().level,
+                Some(Ok(t)) if t != 0 && range.contains(&t) => t,
+                _ => return Err(format!("无效的zstd压缩级别: {}, 可选{}到{}", level.unwrap_or_default(), range.start(), range.end())),
+            };
+            let mut zstd = Zstd::new(level);
+            for (key, value) in &options {
+                zstd = match key.as_str() {
+                    "dict" => zstd.with_dictionary(&std::fs::read(value).map_err(|e| format!("读取zstd字典({})发生错误: {}", value, e))?),
+                    "window-log" => match value.parse::<u32>() {
+                        Ok(t) if (10..=31).contains(&t) => zstd.window_log(t),
+                        _ => return Err(format!("无效的window-log: {}, 可选10到31", value)),
+                    },
+                    "checksum" => zstd.checksum(flag(key, value)?),
+                    _ => return unknown("zstd", key),
+                };
+            }
+            Some(Box::new(zstd))
+        }
+        #[cfg(not(feature = "zstd"))]
+        "zstd" => return Err(String::from("zstd压缩需要开启zstd特性, 作为库使用时也可以实现sink::Compressor接入")),
+        #[cfg(feature = "lz4")]
+        "lz4" => {
+            if level.is_some() {
+                return Err(String::from("lz4不支持压缩级别"));
+            }
+            let mut lz4 = Lz4::default();
+            for (key, value) in &options {
+                lz4 = match key.as_str() {
+                    "block" => match value.to_lowercase().as_str() {
+                        "64k" => lz4.block_size(64 << 10),
+                        "256k" => lz4.block_size(256 << 10),
+                        "1m" => lz4.block_size(1 << 20),
+                        "4m" => lz4.block_size(4 << 20),
+                        _ => return Err(format!("无效的lz4 block: {}, 可选64k、256k、1m、4m", value)),
+                    },
+                    "checksum" => lz4.checksum(flag(key, value)?),
+                    _ => return unknown("lz4", key),
+                };
+            }
+            Some(Box::new(lz4))
+        }
+        #[cfg(not(feature = "lz4"))]
+        "lz4" => return Err(String::from("lz4压缩需要开启lz4特性, 作为库使用时也可以实现sink::Compressor接入")),
+        _ => return Err(format!("不支持的压缩算法: {}, 可选gzip、zstd、lz4、none", spec)),
+    };
+    Ok(compressor.map(|compressor| (compressor, workers)))
+}
@@ -0,0 +1,274 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/01
+*/
+
+//同时写入多个输出目标, 每个目标单独记录已确认的位置(checkpoint)
+//  write_event成功只表示数据进入了目标的缓存, flush成功后才确认到该位置
+//  checkpoint只在事务结束的事件(XID_EVENT、非BEGIN的QUERY_EVENT)处推进, 从checkpoint重新读取不会输出半个事务
+//  重新启动时从所有目标中最小的checkpoint(low_watermark)开始读取, 已经确认过的事件对每个目标会被跳过, 不会重复写入
+//  log_pos为binlog文件中的偏移量, checkpoint只在同一个binlog文件内有效
+//  开启去重窗口后checkpoint中同时记录最近输出的N个gtid, 按gtid重新拉取(切换主库、binlog文件变化)时
+//  已经输出过的事务整个跳过, 不依赖position
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use serde_json::{json, Value as JsonValue};
+use crate::reader::Event;
+use crate::replication::readbinlog::Traction;
+use crate::sink::{Sink, Heartbeat, Watermark};
+
+/// 某个输出目标写入失败时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailurePolicy{
+    /// 返回错误, 所有目标停止写入, 等待问题解决后从low_watermark重新开始
+    BlockAll,
+    /// 停用失败的目标, 其余目标继续写入, 失败目标的checkpoint停留在最后确认的位置
+    Degrade,
+}
+
+/// 已确认写入的位置, log_pos为事务结束事件之后的位置
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Checkpoint{
+    pub log_pos: u64,
+    pub gtid: Option<String>,
+    /// 最近输出的gtid, 按输出顺序, 只在开启去重窗口时记录
+    pub recent_gtids: Vec<String>,
+}
+
+impl Checkpoint{
+    fn load(path: &PathBuf) -> io::Result<Checkpoint> {
+        let value: JsonValue = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Checkpoint{
+            log_pos: value["log_pos"].as_u64().unwrap_or(0),
+            gtid: value["gtid"].as_str().map(String::from),
+            recent_gtids: value["recent_gtids"].as_array()
+                .map(|t| t.iter().filter_map(|g| g.as_str().map(String::from)).collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    //先写入临时文件再改名, 避免写入过程中退出导致checkpoint文件损坏
+    fn save(&self, path: &PathBuf) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, json!({"log_pos": self.log_pos, "gtid": self.gtid, "recent_gtids": self.recent_gtids}).to_string())?;
+        fs::rename(&tmp, path)
+    }
+}
+
+struct Target{
+    name: String,
+    sink: Box<dyn Sink>,
+    checkpoint: Checkpoint,
+    pending: Checkpoint,            //已写入但还未flush的位置
+    checkpoint_file: Option<PathBuf>,
+    error: Option<String>,
+    duplicate: bool,                //当前事务的gtid已经输出过, 跳过到事务结束
+    duplicates: u64,
+}
+
+/// 将事件同时写入多个输出目标
+pub struct FanOutSink{
+    targets: Vec<Target>,
+    policy: FailurePolicy,
+    gtid: Option<String>,
+    dedup_window: usize,
+}
+
+impl FanOutSink{
+    pub fn new(policy: FailurePolicy) -> FanOutSink {
+        FanOutSink{
+            targets: vec![],
+            policy,
+            gtid: None,
+            dedup_window: 0,
+        }
+    }
+
+    /// 开启按gtid去重, 每个目标的checkpoint记录最近输出的window个gtid, gtid在其中的事务不再写入该目标
+    /// 用于重新启动后从checkpoint之前的位置(或按gtid自动定位)拉取时, 下游不会收到重复的事务
+    pub fn with_dedup_window(mut self, window: usize) -> FanOutSink {
+        self.dedup_window = window;
+        self
+    }
+
+    /// 各个目标因为gtid已经输出过而跳过的事务数
+    pub fn duplicates(&self) -> Vec<(&str, u64)> {
+        self.targets.iter().map(|t| (t.name.as_str(), t.duplicates)).collect()
+    }
+
+    /// 添加输出目标, checkpoint只保存在内存中
+    pub fn with_sink(mut self, name: &str, sink: Box<dyn Sink>) -> FanOutSink {
+        self.targets.push(Target{
+            name: name.to_string(),
+            sink,
+            checkpoint: Checkpoint::default(),
+            pending: Checkpoint::default(),
+            checkpoint_file: None,
+            error: None,
+            duplicate: false,
+            duplicates: 0,
+        });
+        self
+    }
+
+    /// 添加输出目标, checkpoint在每次flush后写入文件, 文件已存在时从中恢复
+    pub fn with_checkpointed_sink(mut self, name: &str, sink: Box<dyn Sink>, checkpoint_file: &str) -> io::Result<FanOutSink> {
+        let path = PathBuf::from(checkpoint_file);
+        let checkpoint = if path.exists() { Checkpoint::load(&path)? } else { Checkpoint::default() };
+        self.targets.push(Target{
+            name: name.to_string(),
+            sink,
+            checkpoint: checkpoint.clone(),
+            pending: checkpoint,
+            checkpoint_file: Some(path),
+            error: None,
+            duplicate: false,
+            duplicates: 0,
+        });
+        Ok(self)
+    }
+
+    /// 每个输出目标已确认的位置
+    pub fn checkpoints(&self) -> Vec<(&str, &Checkpoint)> {
+        self.targets.iter().map(|t| (t.name.as_str(), &t.checkpoint)).collect()
+    }
+
+    /// Degrade模式下被停用的输出目标及错误信息
+    pub fn failed(&self) -> Vec<(&str, &str)> {
+        self.targets.iter().filter_map(|t| t.error.as_deref().map(|e| (t.name.as_str(), e))).collect()
+    }
+
+    /// 所有输出目标中最小的checkpoint, 重新启动时从该位置开始读取
+    pub fn low_watermark(&self) -> Option<&Checkpoint> {
+        self.targets.iter().map(|t| &t.checkpoint).min_by_key(|c| c.log_pos)
+    }
+
+    //BlockAll返回错误, Degrade停用该目标, 所有目标都停用时同样返回错误
+    fn on_error(&mut self, idx: usize, err: io::Error) -> io::Result<()> {
+        let target = &mut self.targets[idx];
+        let err = io::Error::new(err.kind(), format!("{}: {}", target.name, err));
+        match self.policy {
+            FailurePolicy::BlockAll => Err(err),
+            FailurePolicy::Degrade => {
+                target.error = Some(err.to_string());
+                if self.targets.iter().all(|t| t.error.is_some()) {
+                    return Err(err);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+//事务结束的事件
+fn is_transaction_end(data: &Traction) -> bool {
+    match data {
+        Traction::XidEvent(_) => true,
+        Traction::QueryEvent(t) => t.command != "BEGIN",
+        _ => false
+    }
+}
+
+impl Sink for FanOutSink {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        if let Traction::GtidEvent(t) = &event.data {
+            self.gtid = Some(format!("{}:{}", t.gtid, t.gno_id));
+            if self.dedup_window > 0 {
+                let gtid = self.gtid.as_ref().unwrap();
+                for target in self.targets.iter_mut() {
+                    target.duplicate = target.pending.recent_gtids.contains(gtid);
+                    if target.duplicate {
+                        target.duplicates += 1;
+                    }
+                }
+            }
+        }
+        let end_pos = event.log_pos + event.header.event_length as u64;
+        let trx_end = is_transaction_end(&event.data);
+        for idx in 0..self.targets.len() {
+            let target = &mut self.targets[idx];
+            //已停用或者已确认过的事件
+            if target.error.is_some() || event.log_pos < target.pending.log_pos {
+                continue;
+            }
+            //已经输出过的事务, 只推进位置
+            if target.duplicate {
+                if trx_end {
+                    target.duplicate = false;
+                    target.pending.log_pos = end_pos;
+                }
+                continue;
+            }
+            match target.sink.write_event(event) {
+                Ok(_) => {
+                    if trx_end {
+                        target.pending.log_pos = end_pos;
+                        target.pending.gtid = self.gtid.clone();
+                        if let (true, Some(gtid)) = (self.dedup_window > 0, &self.gtid) {
+                            let recent = &mut target.pending.recent_gtids;
+                            //没有gtid的事务沿用上一个gtid, 不重复记录
+                            if recent.last() != Some(gtid) {
+                                recent.push(gtid.clone());
+                            }
+                            if recent.len() > self.dedup_window {
+                                recent.drain(..recent.len() - self.dedup_window);
+                            }
+                        }
+                    }
+                }
+                Err(e) => self.on_error(idx, e)?,
+            }
+        }
+        Ok(())
+    }
+
+    //心跳不改变checkpoint, 跳过重复事务的目标也写入
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        for idx in 0..self.targets.len() {
+            let target = &mut self.targets[idx];
+            if target.error.is_some() {
+                continue;
+            }
+            if let Err(e) = target.sink.write_heartbeat(heartbeat) {
+                self.on_error(idx, e)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        for idx in 0..self.targets.len() {
+            let target = &mut self.targets[idx];
+            if target.error.is_some() {
+                continue;
+            }
+            if let Err(e) = target.sink.write_watermark(watermark) {
+                self.on_error(idx, e)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for idx in 0..self.targets.len() {
+            let target = &mut self.targets[idx];
+            if target.error.is_some() {
+                continue;
+            }
+            let result = target.sink.flush().and_then(|_| {
+                if let Some(path) = &target.checkpoint_file {
+                    target.pending.save(path)?;
+                }
+                Ok(())
+            });
+            match result {
+                Ok(_) => target.checkpoint = target.pending.clone(),
+                Err(e) => self.on_error(idx, e)?,
+            }
+        }
+        Ok(())
+    }
+}
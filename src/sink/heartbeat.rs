@@ -0,0 +1,162 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/02
+*/
+
+//心跳: 源端没有数据变化时也定期向输出目标写入当前位置、gtid及时间
+//  下游据此区分"没有数据变化"与"同步已中断", 并通过timestamp计算端到端的延迟
+//  从mysql拉取时配置Config.heartbeat(秒), 注册时设置@master_heartbeat_period, 空闲时mysql按该间隔发送HEARTBEAT_LOG_EVENT,
+//  HeartbeatSink在写入每个事件(包括mysql的心跳事件)后检查是否到达间隔; 事务中间不写入心跳, 在事务结束后补上
+
+use serde_json::{json, Value as JsonValue};
+use crate::replication::readbinlog::Traction;
+#[cfg(feature = "os")]
+use std::io;
+#[cfg(feature = "os")]
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "os")]
+use crate::reader::Event;
+#[cfg(feature = "os")]
+use crate::sink::{Sink, Watermark};
+
+/// 心跳记录
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Heartbeat{
+    /// 最后一个已完成事务的gtid
+    pub gtid: String,
+    /// 当前binlog文件, 读取文件时没有ROTATE_EVENT为空
+    pub log_file: String,
+    /// 已读取到的位置(下一个事件的起始位置)
+    pub log_pos: u64,
+    /// 最后一个事件的时间, 即源端写入binlog的时间
+    pub event_timestamp: u32,
+    /// 生成心跳的时间, unix毫秒
+    pub timestamp: u64,
+}
+
+impl Heartbeat{
+    /// {"type":"heartbeat","gtid":"uuid:10","log_file":"mysql-bin.000003","position":1234,"event_timestamp":1572998400,"timestamp":1572998401000}
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "type": "heartbeat",
+            "gtid": self.gtid,
+            "log_file": self.log_file,
+            "position": self.log_pos,
+            "event_timestamp": self.event_timestamp,
+            "timestamp": self.timestamp,
+        })
+    }
+}
+
+/// 根据读取到的事件记录当前位置、已完成事务的gtid, 用于生成心跳
+#[derive(Debug, Clone, Default)]
+pub struct HeartbeatTracker{
+    state: Heartbeat,
+    pending_gtid: Option<String>,
+    in_transaction: bool,
+}
+
+impl HeartbeatTracker{
+    pub fn new() -> HeartbeatTracker {
+        HeartbeatTracker::default()
+    }
+
+    /// timestamp、next_position为事件头中的值, artificial事件的next_position为0, 不改变位置
+    pub fn observe(&mut self, data: &Traction, timestamp: u32, next_position: u64) {
+        match data {
+            Traction::GtidEvent(t) => {
+                self.pending_gtid = Some(format!("{}:{}", t.gtid, t.gno_id));
+                self.in_transaction = true;
+            }
+            Traction::QueryEvent(t) if t.command == "BEGIN" => self.in_transaction = true,
+            Traction::XidEvent(_) | Traction::QueryEvent(_) => {
+                self.in_transaction = false;
+                if let Some(gtid) = self.pending_gtid.take() {
+                    self.state.gtid = gtid;
+                }
+            }
+            Traction::RotateLogEvent(t) => {
+                self.state.log_file = t.binlog_file.clone();
+                self.state.log_pos = t.position;
+                return;
+            }
+            _ => {}
+        }
+        if next_position > 0 {
+            self.state.log_pos = next_position;
+        }
+        if timestamp > 0 {
+            self.state.event_timestamp = timestamp;
+        }
+    }
+
+    /// 是否处于事务中间(GTID/BEGIN之后、XID/COMMIT之前)
+    pub fn in_transaction(&self) -> bool {
+        self.in_transaction
+    }
+
+    /// timestamp为生成心跳的时间(unix毫秒)
+    pub fn heartbeat(&self, timestamp: u64) -> Heartbeat {
+        Heartbeat{ timestamp, ..self.state.clone() }
+    }
+}
+
+/// 按间隔向输出目标写入心跳, 其余事件原样写入
+#[cfg(feature = "os")]
+pub struct HeartbeatSink<S: Sink>{
+    sink: S,
+    interval: Duration,
+    tracker: HeartbeatTracker,
+    last: Instant,
+}
+
+#[cfg(feature = "os")]
+impl<S: Sink> HeartbeatSink<S> {
+    pub fn new(sink: S, interval: Duration) -> HeartbeatSink<S> {
+        HeartbeatSink{ sink, interval, tracker: HeartbeatTracker::new(), last: Instant::now() }
+    }
+
+    /// 到达间隔且不在事务中间时写入心跳, 返回是否写入
+    /// 读取使用超时(或单独的定时器)时可以在空闲时调用, 不需要等待下一个事件
+    pub fn tick(&mut self) -> io::Result<bool> {
+        if self.last.elapsed() < self.interval || self.tracker.in_transaction() {
+            return Ok(false);
+        }
+        self.heartbeat()?;
+        Ok(true)
+    }
+
+    /// 立即写入心跳
+    pub fn heartbeat(&mut self) -> io::Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_millis() as u64).unwrap_or(0);
+        self.sink.write_heartbeat(&self.tracker.heartbeat(now))?;
+        self.last = Instant::now();
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+}
+
+#[cfg(feature = "os")]
+impl<S: Sink> Sink for HeartbeatSink<S> {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        self.tracker.observe(&event.data, event.header.timestamp, event.header.next_position as u64);
+        self.sink.write_event(event)?;
+        self.tick()?;
+        Ok(())
+    }
+
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        self.sink.write_heartbeat(heartbeat)
+    }
+
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        self.sink.write_watermark(watermark)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
@@ -0,0 +1,136 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use crate::reader::Event;
+use crate::replication::readbinlog::Traction;
+use crate::replication::parsevalue::RowValue;
+use crate::replication::readevent::{BinlogEvent, TableMap};
+use crate::stdout::format::ValueFormat;
+use crate::stdout::outjson;
+use crate::sink::{Sink, Heartbeat, Watermark, default_columns};
+
+/// 将行数据以json格式写入, 每行数据一个json对象, 格式与--outformat json相同
+/// 没有表结构信息时字段名为@1、@2...
+/// ValueFormat.boundaries时事务的行数据前后写入begin、commit记录(见outjson::Boundary)
+pub struct JsonLinesSink<W: Write>{
+    writer: W,
+    format: ValueFormat,
+    gtid: String,
+    boundary: outjson::Boundary,
+    table_cols_info: HashMap<String, Vec<HashMap<String, String>>>,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W, format: ValueFormat) -> JsonLinesSink<W> {
+        JsonLinesSink{
+            writer,
+            format,
+            gtid: String::from(""),
+            boundary: outjson::Boundary::default(),
+            table_cols_info: HashMap::new()
+        }
+    }
+
+    /// 设置表结构信息, key为db.tbl, 格式与meta::get_col获取的一致(COLUMN_NAME、COLUMN_TYPE)
+    pub fn with_columns(mut self, table_cols_info: HashMap<String, Vec<HashMap<String, String>>>) -> JsonLinesSink<W> {
+        self.table_cols_info = table_cols_info;
+        self
+    }
+
+    pub(crate) fn set_columns(&mut self, table_cols_info: HashMap<String, Vec<HashMap<String, String>>>) {
+        self.table_cols_info = table_cols_info;
+    }
+
+    pub(crate) fn gtid(&self) -> &str {
+        &self.gtid
+    }
+
+    //表的字段信息, 没有表结构时按TABLE_MAP生成
+    pub(crate) fn columns(&self, map: &TableMap) -> Vec<HashMap<String, String>> {
+        match self.table_cols_info.get(&format!("{}.{}", map.database_name, map.table_name)) {
+            Some(t) => t.clone(),
+            None => default_columns(map)
+        }
+    }
+
+    /// 按顺序写入一个行事件的json行(见row_lines), 事务的第一行之前写入begin记录
+    pub(crate) fn write_lines(&mut self, lines: Vec<(String, String)>, timestamp: u32) -> io::Result<()> {
+        for (row_type, line) in lines {
+            if let Some(begin) = self.boundary.begin(&self.gtid, timestamp, &self.format) {
+                writeln!(self.writer, "{}", begin)?;
+            }
+            self.boundary.count_type(&row_type);
+            writeln!(self.writer, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// 行事件转换为json行, 返回每行的type(insert/update/delete)及json文本
+/// nonfinite=error时有NaN、Infinity, zerodate=error时有零值日期返回错误
+pub(crate) fn row_lines(rows: &RowValue, code: &BinlogEvent, cols: &[HashMap<String, String>], map: &TableMap, gtid: &str, format: &ValueFormat) -> io::Result<Vec<(String, String)>> {
+    let _timer = crate::profile::start(crate::profile::Stage::Serialize);
+    let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+    format.check_finite(rows.rows(), &db_tbl).map_err(crate::readvalue::invalid_data)?;
+    format.check_temporal(rows.rows(), &db_tbl).map_err(crate::readvalue::invalid_data)?;
+    Ok(outjson::row_records(rows, code, cols, &db_tbl, map, gtid, format).into_iter().map(|record| {
+        let row_type = record["type"].as_str().unwrap_or("").to_string();
+        (row_type, record.to_string())
+    }).collect())
+}
+
+impl<W: Write> Sink for JsonLinesSink<W> {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        match (&event.data, &event.table) {
+            (Traction::GtidEvent(t), _) => {
+                self.gtid = format!("{}:{}", t.gtid, t.gno_id);
+            }
+            (Traction::RowEvent(code, rows), Some(map)) => {
+                let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+                let default_cols;
+                let cols = match self.table_cols_info.get(&db_tbl) {
+                    Some(t) => t,
+                    None => {
+                        default_cols = default_columns(map);
+                        &default_cols
+                    }
+                };
+                let lines = row_lines(rows, code, cols, map, &self.gtid, &self.format)?;
+                self.write_lines(lines, event.header.timestamp)?;
+            }
+            (Traction::XidEvent(t), _) => {
+                if let Some(commit) = self.boundary.commit(&self.gtid, Some(t.xid), event.header.timestamp, &self.format) {
+                    writeln!(self.writer, "{}", commit)?;
+                }
+            }
+            (Traction::QueryEvent(t), _) if t.command != "BEGIN" => {
+                if let Some(commit) = self.boundary.commit(&self.gtid, None, event.header.timestamp, &self.format) {
+                    writeln!(self.writer, "{}", commit)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        let mut value = heartbeat.to_json();
+        self.format.labels.attach(&mut value);
+        writeln!(self.writer, "{}", value)
+    }
+
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        let mut value = watermark.to_json();
+        self.format.labels.attach(&mut value);
+        writeln!(self.writer, "{}", value)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
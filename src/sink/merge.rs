@@ -0,0 +1,229 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//多个源汇总到一个下游时按事务的提交时间(XID/COMMIT事件头的timestamp)合并输出, 部分分析类的下游要求数据大致按时间有序
+//  每个源(MergeSource)缓存完整的事务, 事务的提交时间不超过所有源已提交的最小时间(水位)时按时间顺序输出,
+//  源端空闲时mysql的心跳事件将该源的水位推进到当前时间
+//  乱序窗口window: 比最大的提交时间早window以上、或已缓存window以上的事务不再等待其他源, 因此只是近似有序,
+//  窗口之外晚到的事务直接输出
+//  flush(保存checkpoint前)时该源缓存的事务全部输出, 同时输出其他源中时间更早的事务; 源drop时同样全部输出
+//  输出时持有合并的锁, 写入错误返回给当时触发输出的源
+//  事件时间水位(见WatermarkSink)按所有源的水位的最小值输出, 只增不减, 还没有水位的源阻止推进
+//  缓存的事务计入内存上限(见memory模块), Spill策略下超过上限的行事件写入临时文件, 仍超过上限时最早的事务不再等待直接输出
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::memory::{self, EventBuffer};
+use crate::reader::Event;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::EventType;
+use crate::sink::{Sink, Heartbeat, Watermark};
+
+struct Pending{
+    timestamp: u32,
+    seq: u64,
+    source: usize,
+    events: EventBuffer,
+    received: Instant,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Pending) -> bool {
+        (self.timestamp, self.seq) == (other.timestamp, other.seq)
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Pending) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Pending) -> Ordering {
+        (self.timestamp, self.seq).cmp(&(other.timestamp, other.seq))
+    }
+}
+
+struct Source{
+    sink: Box<dyn Sink + Send>,
+    //已提交的最大时间, 0为还没有提交过事务
+    clock: u32,
+    //收到的水位, 0为还没有收到
+    watermark: u32,
+}
+
+struct MergeState{
+    window: Duration,
+    sources: Vec<Option<Source>>,
+    pending: BinaryHeap<Reverse<Pending>>,
+    seq: u64,
+    //已输出的水位
+    watermark: u32,
+}
+
+impl MergeState {
+    //until为强制输出的时间上限, flush时为该源缓存的最大时间
+    fn release(&mut self, until: Option<u32>) -> io::Result<()> {
+        let active = self.sources.iter().flatten();
+        let watermark = active.clone().map(|s| s.clock).min().unwrap_or(u32::MAX);
+        let latest = active.map(|s| s.clock).max().unwrap_or(0);
+        let window = self.window.as_secs() as u32;
+        while let Some(Reverse(top)) = self.pending.peek() {
+            let ready = top.timestamp <= watermark
+                || top.timestamp.saturating_add(window) <= latest
+                || top.received.elapsed() >= self.window
+                || until.is_some_and(|t| top.timestamp <= t)
+                || memory::exceeded();
+            if !ready {
+                break;
+            }
+            let Reverse(mut pending) = self.pending.pop().unwrap();
+            let events = pending.events.take()?;
+            if let Some(source) = self.sources[pending.source].as_mut() {
+                for event in &events {
+                    source.sink.write_event(event)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn own_latest(&self, index: usize) -> Option<u32> {
+        self.pending.iter().filter(|p| p.0.source == index).map(|p| p.0.timestamp).max()
+    }
+}
+
+/// 多个源的事务按提交时间合并, 可以clone后在各个源的线程中使用
+#[derive(Clone)]
+pub struct OrderedMerge{
+    state: Arc<Mutex<MergeState>>,
+}
+
+impl OrderedMerge {
+    /// window为乱序窗口, 同时也是事务最长的缓存时间
+    pub fn new(window: Duration) -> OrderedMerge {
+        OrderedMerge{
+            state: Arc::new(Mutex::new(MergeState{ window, sources: vec![], pending: BinaryHeap::new(), seq: 0, watermark: 0 })),
+        }
+    }
+
+    /// 增加一个源, 返回的MergeSource按事务缓存, 合并后写入sink
+    pub fn source<S: Sink + Send + 'static>(&self, sink: S) -> MergeSource {
+        let mut state = self.state.lock().unwrap();
+        state.sources.push(Some(Source{ sink: Box::new(sink), clock: 0, watermark: 0 }));
+        MergeSource{ state: self.state.clone(), index: state.sources.len() - 1, events: EventBuffer::new(), in_transaction: false }
+    }
+
+    /// 缓存中等待输出的事务数
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+}
+
+/// OrderedMerge的一个源
+pub struct MergeSource{
+    state: Arc<Mutex<MergeState>>,
+    index: usize,
+    events: EventBuffer,
+    in_transaction: bool,
+}
+
+impl MergeSource {
+    fn with_source<F: FnOnce(&mut Source) -> io::Result<()>>(&self, state: &mut MergeState, f: F) -> io::Result<()> {
+        match state.sources[self.index].as_mut() {
+            Some(source) => f(source),
+            None => Ok(()),
+        }
+    }
+
+    //该源缓存的事务全部输出
+    fn drain(&mut self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let until = state.own_latest(self.index);
+        state.release(until)
+    }
+}
+
+impl Sink for MergeSource {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        //源端空闲, 之后的事务不会早于当前时间
+        if let EventType::HeartbeatLogEvent | EventType::HeartbeatLogEventV2 = event.header.event_type {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs() as u32).unwrap_or(0);
+            let mut state = self.state.lock().unwrap();
+            self.with_source(&mut state, |s| { s.clock = s.clock.max(now); Ok(()) })?;
+            return state.release(None);
+        }
+        let trx_end = match &event.data {
+            Traction::GtidEvent(_) => {
+                self.in_transaction = true;
+                false
+            }
+            Traction::QueryEvent(t) if t.command == "BEGIN" => {
+                self.in_transaction = true;
+                false
+            }
+            Traction::XidEvent(_) | Traction::QueryEvent(_) => true,
+            _ => false,
+        };
+        if !trx_end && !self.in_transaction && self.events.is_empty() {
+            //事务之外的事件(ROTATE等)直接写入
+            let mut state = self.state.lock().unwrap();
+            return self.with_source(&mut state, |s| s.sink.write_event(event));
+        }
+        self.events.push(event.clone())?;
+        if !trx_end {
+            return Ok(());
+        }
+        self.in_transaction = false;
+        let timestamp = event.header.timestamp;
+        let mut state = self.state.lock().unwrap();
+        self.with_source(&mut state, |s| { s.clock = s.clock.max(timestamp); Ok(()) })?;
+        state.seq += 1;
+        let pending = Pending{ timestamp, seq: state.seq, source: self.index, events: std::mem::take(&mut self.events), received: Instant::now() };
+        state.pending.push(Reverse(pending));
+        state.release(None)
+    }
+
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        self.drain()?;
+        let mut state = self.state.lock().unwrap();
+        self.with_source(&mut state, |s| s.sink.write_heartbeat(heartbeat))
+    }
+
+    //水位不晚于各源已提交的时间, 早于水位的事务都已输出
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        self.with_source(&mut state, |s| { s.watermark = s.watermark.max(watermark.timestamp); Ok(()) })?;
+        let merged = state.sources.iter().flatten().map(|s| s.watermark).min().unwrap_or(0);
+        if merged <= state.watermark {
+            return Ok(());
+        }
+        state.release(None)?;
+        state.watermark = merged;
+        let watermark = Watermark{ timestamp: merged, emitted: watermark.emitted, ..Watermark::default() };
+        self.with_source(&mut state, |s| s.sink.write_watermark(&watermark))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.drain()?;
+        let mut state = self.state.lock().unwrap();
+        self.with_source(&mut state, |s| s.sink.flush())
+    }
+}
+
+impl Drop for MergeSource {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        if let Ok(mut state) = self.state.lock() {
+            state.sources[self.index] = None;
+        }
+    }
+}
@@ -0,0 +1,166 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//多线程序列化的json行输出, 格式与JsonLinesSink相同
+//  宽表、高写入量时行数据转换为json是主要的cpu开销, 单线程写入跟不上读取的速度
+//  行事件分配给workers个线程并行转换, 按事件的顺序写入, 输出内容与JsonLinesSink完全一致
+//  同时处理中的行事件最多为capacity个, 超过时等待最早的事件写入, 内存占用有上限
+//  gtid、xid等非行事件及心跳、flush等待之前的行事件全部写入后处理, 事务边界记录的位置不变
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use crate::reader::Event;
+use crate::replication::readbinlog::Traction;
+use crate::stdout::format::ValueFormat;
+use crate::sink::{Sink, Heartbeat, Watermark, JsonLinesSink};
+use crate::sink::json::row_lines;
+
+type Lines = io::Result<Vec<(String, String)>>;
+
+struct Job{
+    event: Box<Event>,
+    gtid: String,
+    cols: Vec<HashMap<String, String>>,
+    reply: SyncSender<Lines>,
+}
+
+/// 多线程序列化的JsonLinesSink
+pub struct ParallelJsonSink<W: Write>{
+    inner: JsonLinesSink<W>,
+    capacity: usize,
+    sender: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    //按事件顺序等待转换结果: (事件时间, 结果)
+    pending: VecDeque<(u32, Receiver<Lines>)>,
+}
+
+impl<W: Write> ParallelJsonSink<W> {
+    /// workers为序列化的线程数, 同时处理中的行事件最多为workers * 4个
+    pub fn new(writer: W, format: ValueFormat, workers: usize) -> ParallelJsonSink<W> {
+        let workers = workers.max(1);
+        let capacity = workers * 4;
+        let format = Arc::new(format);
+        let (sender, receiver) = mpsc::sync_channel::<Job>(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handles = (0..workers).map(|_| {
+            let receiver = receiver.clone();
+            let format = format.clone();
+            thread::spawn(move || run_worker(receiver, format))
+        }).collect();
+        ParallelJsonSink{
+            inner: JsonLinesSink::new(writer, (*format).clone()),
+            capacity,
+            sender: Some(sender),
+            workers: handles,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// 设置表结构信息, 与JsonLinesSink::with_columns相同
+    pub fn with_columns(mut self, table_cols_info: HashMap<String, Vec<HashMap<String, String>>>) -> ParallelJsonSink<W> {
+        self.inner.set_columns(table_cols_info);
+        self
+    }
+
+    //写入最早的行事件, 等待转换完成
+    fn write_front(&mut self) -> io::Result<()> {
+        if let Some((timestamp, result)) = self.pending.pop_front() {
+            let lines = result.recv().map_err(|_| io::Error::other("序列化线程异常退出"))??;
+            self.inner.write_lines(lines, timestamp)?;
+        }
+        Ok(())
+    }
+
+    //写入已经转换完成的行事件, 不等待
+    fn write_ready(&mut self) -> io::Result<()> {
+        while let Some((timestamp, result)) = self.pending.front() {
+            let lines = match result.try_recv() {
+                Ok(t) => t?,
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => return Err(io::Error::other("序列化线程异常退出"))
+            };
+            let timestamp = *timestamp;
+            self.pending.pop_front();
+            self.inner.write_lines(lines, timestamp)?;
+        }
+        Ok(())
+    }
+
+    fn write_all(&mut self) -> io::Result<()> {
+        while !self.pending.is_empty() {
+            self.write_front()?;
+        }
+        Ok(())
+    }
+}
+
+fn run_worker(receiver: Arc<Mutex<Receiver<Job>>>, format: Arc<ValueFormat>) {
+    loop {
+        //只在取任务时持有锁
+        let job = match receiver.lock() {
+            Ok(t) => t.recv(),
+            Err(_) => return
+        };
+        let job = match job {
+            Ok(t) => t,
+            Err(_) => return
+        };
+        let lines = match (&job.event.data, &job.event.table) {
+            (Traction::RowEvent(code, rows), Some(map)) => row_lines(rows, code, &job.cols, map, &job.gtid, &format),
+            _ => Ok(vec![])
+        };
+        let _ = job.reply.send(lines);
+    }
+}
+
+impl<W: Write> Sink for ParallelJsonSink<W> {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        let map = match (&event.data, &event.table) {
+            (Traction::RowEvent(..), Some(map)) => map,
+            _ => {
+                self.write_all()?;
+                return self.inner.write_event(event);
+            }
+        };
+        if self.pending.len() >= self.capacity {
+            self.write_front()?;
+        }
+        let (reply, result) = mpsc::sync_channel(1);
+        let job = Job{ event: Box::new(event.clone()), gtid: self.inner.gtid().to_string(), cols: self.inner.columns(map), reply };
+        let sender = self.sender.as_ref().ok_or_else(|| io::Error::other("序列化线程已退出"))?;
+        sender.send(job).map_err(|_| io::Error::other("序列化线程已退出"))?;
+        self.pending.push_back((event.header.timestamp, result));
+        self.write_ready()
+    }
+
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        self.write_all()?;
+        self.inner.write_heartbeat(heartbeat)
+    }
+
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        self.write_all()?;
+        self.inner.write_watermark(watermark)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_all()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for ParallelJsonSink<W> {
+    fn drop(&mut self) {
+        //已接收的行事件全部写入, 与JsonLinesSink相同
+        let _ = self.write_all();
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,262 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//按主键将行数据分到N个分区(kafka分区、多个文件等)分别输出, 下游可以水平扩展, 同一行的变更始终在同一个分区中按顺序输出
+//  hash:N 按库表及主键值的crc32分到N个分区, 结果固定, 重新启动后同一个主键仍在同一个分区
+//  range:1000,5000 按第一个主键字段的数值分区, <1000为0、<5000为1、其余为2, 主键需要为整数或decimal
+//  主键按with_columns的表结构(COLUMN_KEY为PRI或非空的UNI)或TABLE_MAP中的metadata确定, 都没有时按with_no_key处理:
+//    默认warn为整张表分到同一个分区, hash-key按所有字段值的hash分区(range分区时也按hash), skip为不输出该表的行事件
+//  update修改主键且新旧值不在同一个分区时, 拆分为旧分区的delete及新分区的insert, 每个分区中每个主键的变更仍然完整
+//  GTID、BEGIN、TABLE_MAP、XID、DDL等其余事件及心跳写入所有分区, 每个分区中事务的边界不变
+
+use std::collections::HashMap;
+use std::io;
+use crate::reader::Event;
+use crate::replication::readbinlog::Traction;
+use crate::replication::parsevalue::{MySQLValue, RowValue};
+use crate::replication::readevent::{BinlogEvent, TableMap};
+use crate::stdout::format::{BinaryEncoding, ValueFormat};
+use crate::sink::{Sink, Heartbeat, Watermark};
+use crate::nokey::{self, NoKeyPolicy};
+
+/// 分区方式
+#[derive(Debug, Clone, PartialEq)]
+pub enum Partitioner{
+    /// 按库表及主键值的hash分到N个分区
+    Hash(usize),
+    /// 按第一个主键字段的数值分区, 为各分区的上界(不包含), 分区数为上界数+1
+    Range(Vec<i128>),
+}
+
+impl Partitioner{
+    /// hash:N 或 range:上界1,上界2..., 上界需要递增
+    pub fn new(spec: &str) -> Result<Partitioner, String> {
+        let (kind, value) = spec.trim().split_once(':').unwrap_or((spec.trim(), ""));
+        match kind {
+            "hash" => match value.trim().parse::<usize>() {
+                Ok(t) if t > 0 => Ok(Partitioner::Hash(t)),
+                _ => Err(format!("无效的分区数: {}, 格式为hash:N", value))
+            },
+            "range" => {
+                let mut bounds: Vec<i128> = vec![];
+                for bound in value.split(',') {
+                    match bound.trim().parse::<i128>() {
+                        Ok(t) if bounds.last().is_none_or(|last| t > *last) => bounds.push(t),
+                        Ok(_) => return Err(format!("range分区的上界需要递增: {}", value)),
+                        Err(_) => return Err(format!("无效的range分区上界: {}, 格式为range:1000,5000", bound))
+                    }
+                }
+                Ok(Partitioner::Range(bounds))
+            }
+            _ => Err(format!("无效的分区方式: {}, 可选值为hash:N、range:上界1,上界2...", spec))
+        }
+    }
+
+    /// 分区数
+    pub fn partitions(&self) -> usize {
+        match self {
+            Partitioner::Hash(n) => *n,
+            Partitioner::Range(bounds) => bounds.len() + 1
+        }
+    }
+
+    /// 一行数据所在的分区, key为主键字段的序号, 为空时按库表分区
+    pub fn partition(&self, db_tbl: &str, row: &[Option<MySQLValue>], key: &[usize]) -> Result<usize, String> {
+        match self {
+            Partitioner::Hash(n) => {
+                let mut hasher = crc32fast::Hasher::new();
+                hasher.update(db_tbl.as_bytes());
+                for idx in key {
+                    //字段之间加分隔符, 避免(1,23)与(12,3)相同; NULL与空字符串区分
+                    hasher.update(b"\0");
+                    match key_text(row.get(*idx).and_then(|t| t.as_ref())) {
+                        Some(t) => hasher.update(t.as_bytes()),
+                        None => hasher.update(b"\x01")
+                    }
+                }
+                Ok(hasher.finalize() as usize % n)
+            }
+            Partitioner::Range(bounds) => {
+                let value = match key.first() {
+                    Some(idx) => numeric(row.get(*idx).and_then(|t| t.as_ref())),
+                    //没有主键时按库表分区
+                    None => return Partitioner::Hash(self.partitions()).partition(db_tbl, row, key)
+                };
+                match value {
+                    Some(t) => Ok(bounds.iter().position(|bound| t < *bound).unwrap_or(bounds.len())),
+                    None => Err(format!("{}第{}个字段不是数值, 无法按range分区", db_tbl, key[0] + 1))
+                }
+            }
+        }
+    }
+}
+
+//主键值的文本, 与csv输出相同, 二进制按十六进制
+fn key_text(value: Option<&MySQLValue>) -> Option<String> {
+    ValueFormat::default().text_value(&value.cloned(), "", BinaryEncoding::Hex)
+}
+
+//range分区使用的数值, decimal取整数部分
+fn numeric(value: Option<&MySQLValue>) -> Option<i128> {
+    match value? {
+        MySQLValue::SignedInteger(t) => Some(i128::from(*t)),
+        MySQLValue::Year(t) => Some(i128::from(*t)),
+        MySQLValue::Decimal(t) => t.with_scale(0).to_string().parse().ok(),
+        _ => None
+    }
+}
+
+//(分区号, 事件类型, 行数据)
+type Part = (usize, BinlogEvent, Vec<Vec<Option<MySQLValue>>>);
+
+/// 按主键将行事件分到各个分区的输出目标, 每个分区的目标在创建时通过factory(分区号)创建
+pub struct PartitionedSink{
+    partitioner: Partitioner,
+    partitions: Vec<Box<dyn Sink>>,
+    table_cols_info: HashMap<String, Vec<HashMap<String, String>>>,
+    no_key: NoKeyPolicy,
+}
+
+impl PartitionedSink{
+    pub fn new<F>(partitioner: Partitioner, mut factory: F) -> io::Result<PartitionedSink>
+        where F: FnMut(usize) -> io::Result<Box<dyn Sink>>
+    {
+        let partitions = (0..partitioner.partitions())
+            .map(|idx| factory(idx).map_err(|e| io::Error::new(e.kind(), format!("分区{}: {}", idx, e))))
+            .collect::<io::Result<Vec<Box<dyn Sink>>>>()?;
+        Ok(PartitionedSink{ partitioner, partitions, table_cols_info: HashMap::new(), no_key: NoKeyPolicy::default() })
+    }
+
+    /// 设置表结构信息, key为db.tbl, 按COLUMN_KEY为PRI的字段分区
+    pub fn with_columns(mut self, table_cols_info: HashMap<String, Vec<HashMap<String, String>>>) -> PartitionedSink {
+        self.table_cols_info = table_cols_info;
+        self
+    }
+
+    /// 没有主键的表的处理方式, 默认warn; full-row只用于生成sql的输出, 见NoKeyPolicy::for_partition
+    pub fn with_no_key(mut self, no_key: NoKeyPolicy) -> PartitionedSink {
+        self.no_key = no_key;
+        self
+    }
+
+    //主键字段的序号
+    fn key(&self, map: &TableMap) -> Vec<usize> {
+        let cols = self.table_cols_info.get(&format!("{}.{}", map.database_name, map.table_name));
+        nokey::key_columns(cols.map(|t| t.as_slice()).unwrap_or(&[]), map)
+    }
+
+    /// 行事件按分区拆分, 返回(分区号, 只包含该分区行数据的事件), 没有主键且为skip时为空
+    pub fn split(&self, event: &Event) -> io::Result<Vec<(usize, Event)>> {
+        let (code, rows, map) = match (&event.data, &event.table) {
+            (Traction::RowEvent(code, rows), Some(map)) => (code, rows, map),
+            _ => return Ok(vec![])
+        };
+        let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+        let key = self.key(map);
+        if !self.no_key.check("partition", &db_tbl, &key) {
+            return Ok(vec![]);
+        }
+        //hash-key: 没有主键时按所有字段值的hash分区
+        let (partitioner, key) = match key.is_empty() && self.no_key == NoKeyPolicy::HashKey {
+            true => (Partitioner::Hash(self.partitioner.partitions()), (0..map.column_info.len()).collect()),
+            false => (self.partitioner.clone(), key)
+        };
+        let partition = |row: &[Option<MySQLValue>]| partitioner.partition(&db_tbl, row, &key).map_err(crate::readvalue::invalid_data);
+        let mut parts: Vec<Part> = vec![];
+        //追加到该分区最后一个事件中, 类型不同时新增一个事件, 每个分区中行的顺序不变
+        let mut push = |idx: usize, code: &BinlogEvent, row: &Vec<Option<MySQLValue>>| {
+            match parts.iter_mut().rev().find(|(p, _, _)| *p == idx) {
+                Some((_, c, rows)) if std::mem::discriminant(c) == std::mem::discriminant(code) => rows.push(row.clone()),
+                _ => parts.push((idx, code.clone(), vec![row.clone()]))
+            }
+        };
+        match code {
+            BinlogEvent::UpdateEvent => {
+                for pair in rows.rows().chunks(2) {
+                    let (before, after) = match pair {
+                        [before, after] => (before, after),
+                        _ => continue
+                    };
+                    let (old, new) = (partition(before)?, partition(after)?);
+                    if old == new {
+                        push(old, code, before);
+                        push(old, code, after);
+                    } else {
+                        push(old, &BinlogEvent::DeleteEvent, before);
+                        push(new, &BinlogEvent::WriteEvent, after);
+                    }
+                }
+            }
+            _ => {
+                for row in rows.rows() {
+                    push(partition(row)?, code, row);
+                }
+            }
+        }
+        Ok(parts.into_iter().map(|(idx, code, rows)| {
+            (idx, Event{ data: Traction::RowEvent(code, RowValue{ rows }), ..event.clone() })
+        }).collect())
+    }
+}
+
+impl Sink for PartitionedSink {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        if let (Traction::RowEvent(..), Some(_)) = (&event.data, &event.table) {
+            for (idx, part) in self.split(event)? {
+                self.partitions[idx].write_event(&part).map_err(|e| io::Error::new(e.kind(), format!("分区{}: {}", idx, e)))?;
+            }
+            return Ok(());
+        }
+        for (idx, sink) in self.partitions.iter_mut().enumerate() {
+            sink.write_event(event).map_err(|e| io::Error::new(e.kind(), format!("分区{}: {}", idx, e)))?;
+        }
+        Ok(())
+    }
+
+    //心跳写入所有分区
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        for (idx, sink) in self.partitions.iter_mut().enumerate() {
+            sink.write_heartbeat(heartbeat).map_err(|e| io::Error::new(e.kind(), format!("分区{}: {}", idx, e)))?;
+        }
+        Ok(())
+    }
+
+    //水位写入所有分区, 下游按分区分别推进
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        for (idx, sink) in self.partitions.iter_mut().enumerate() {
+            sink.write_watermark(watermark).map_err(|e| io::Error::new(e.kind(), format!("分区{}: {}", idx, e)))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for (idx, sink) in self.partitions.iter_mut().enumerate() {
+            sink.flush().map_err(|e| io::Error::new(e.kind(), format!("分区{}: {}", idx, e)))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "os", feature = "json-sink"))]
+impl PartitionedSink{
+    /// 每个分区以json格式追加写入一个文件, 路径中的{partition}替换为分区号, 目录不存在时自动创建
+    pub fn json_files(partitioner: Partitioner, template: &str, value_format: ValueFormat) -> io::Result<PartitionedSink> {
+        use std::fs::{self, OpenOptions};
+        use std::io::BufWriter;
+        use std::path::Path;
+        use crate::sink::JsonLinesSink;
+        if !template.contains("{partition}") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("路径中需要包含{{partition}}: {}", template)));
+        }
+        PartitionedSink::new(partitioner, |idx| {
+            let path = template.replace("{partition}", &idx.to_string());
+            if let Some(dir) = Path::new(&path).parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            Ok(Box::new(JsonLinesSink::new(BufWriter::new(file), value_format.clone())) as Box<dyn Sink>)
+        })
+    }
+}
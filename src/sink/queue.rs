@@ -0,0 +1,220 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/02
+*/
+
+//在单独的线程中写入输出目标, 读取与写入之间使用有界队列
+//  队列满时write_event阻塞, 读取端(包括从mysql拉取binlog)随之暂停, 输出目标再慢也不会导致内存无限增长
+//  队列长度达到high_watermark时进入背压状态, 下降到low_watermark以下时解除, 通过metrics()查看
+//  队列中的事件计入内存上限(见memory模块), 超过上限时write_event等待后台线程写出, 队列为空时不再等待
+//命令行工具中读取和输出在同一个线程中依次执行, 本身不存在未限制长度的缓存
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, Receiver};
+use std::thread::{self, JoinHandle};
+use crate::memory::{self, Reservation};
+use crate::reader::Event;
+use crate::sink::{Sink, Heartbeat, Watermark};
+
+/// 队列长度及背压阈值
+#[derive(Debug, Clone, Copy)]
+pub struct QueueConfig{
+    pub capacity: usize,
+    pub high_watermark: usize,
+    pub low_watermark: usize,
+}
+
+impl QueueConfig{
+    /// 背压阈值默认为队列长度的80%、20%
+    pub fn new(capacity: usize) -> QueueConfig {
+        let capacity = capacity.max(1);
+        QueueConfig{
+            capacity,
+            high_watermark: (capacity * 4 / 5).max(1),
+            low_watermark: capacity / 5,
+        }
+    }
+}
+
+impl Default for QueueConfig{
+    fn default() -> QueueConfig {
+        QueueConfig::new(1024)
+    }
+}
+
+/// 队列的运行状态
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueueMetrics{
+    pub capacity: usize,
+    pub depth: usize,               //当前队列中的事件数
+    pub max_depth: usize,           //出现过的最大队列长度
+    pub backpressure: bool,         //当前是否处于背压状态
+    pub backpressure_count: u64,    //进入背压状态的次数
+    pub blocked_writes: u64,        //队列已满导致write_event阻塞的次数
+}
+
+#[derive(Default)]
+struct SharedMetrics{
+    depth: AtomicUsize,
+    max_depth: AtomicUsize,
+    backpressure: AtomicBool,
+    backpressure_count: AtomicU64,
+    blocked_writes: AtomicU64,
+}
+
+enum Message{
+    Event(Box<Event>, Reservation),
+    Heartbeat(Heartbeat),
+    Watermark(Watermark),
+    Flush(SyncSender<io::Result<()>>),
+}
+
+/// 通过有界队列在后台线程写入输出目标
+pub struct QueuedSink{
+    config: QueueConfig,
+    sender: Option<SyncSender<Message>>,
+    worker: Option<JoinHandle<()>>,
+    metrics: Arc<SharedMetrics>,
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl QueuedSink{
+    pub fn new<S: Sink + Send + 'static>(sink: S, config: QueueConfig) -> QueuedSink {
+        let (sender, receiver) = mpsc::sync_channel(config.capacity);
+        let metrics = Arc::new(SharedMetrics::default());
+        let error = Arc::new(Mutex::new(None));
+        let worker = {
+            let metrics = metrics.clone();
+            let error = error.clone();
+            thread::spawn(move || run_worker(sink, receiver, config, metrics, error))
+        };
+        QueuedSink{
+            config,
+            sender: Some(sender),
+            worker: Some(worker),
+            metrics,
+            error,
+        }
+    }
+
+    pub fn metrics(&self) -> QueueMetrics {
+        QueueMetrics{
+            capacity: self.config.capacity,
+            depth: self.metrics.depth.load(Ordering::Relaxed),
+            max_depth: self.metrics.max_depth.load(Ordering::Relaxed),
+            backpressure: self.metrics.backpressure.load(Ordering::Relaxed),
+            backpressure_count: self.metrics.backpressure_count.load(Ordering::Relaxed),
+            blocked_writes: self.metrics.blocked_writes.load(Ordering::Relaxed),
+        }
+    }
+
+    //后台线程写入失败后, 之后的调用都返回该错误
+    fn check_error(&self) -> io::Result<()> {
+        match self.error.lock() {
+            Ok(e) => match e.as_ref() {
+                Some(msg) => Err(io::Error::other(msg.clone())),
+                None => Ok(())
+            },
+            Err(_) => Err(io::Error::other("输出线程异常退出"))
+        }
+    }
+
+    fn send(&self, message: Message) -> io::Result<()> {
+        let sender = self.sender.as_ref().ok_or_else(|| io::Error::other("队列已关闭"))?;
+        sender.send(message).map_err(|_| io::Error::other("输出线程已退出"))
+    }
+}
+
+fn run_worker<S: Sink>(mut sink: S, receiver: Receiver<Message>, config: QueueConfig,
+                       metrics: Arc<SharedMetrics>, error: Arc<Mutex<Option<String>>>) {
+    for message in receiver {
+        match message {
+            Message::Event(event, reservation) => {
+                let depth = metrics.depth.fetch_sub(1, Ordering::Relaxed) - 1;
+                if depth <= config.low_watermark {
+                    metrics.backpressure.store(false, Ordering::Relaxed);
+                }
+                let result = sink.write_event(&event);
+                drop(reservation);
+                if let Err(e) = result {
+                    if let Ok(mut err) = error.lock() {
+                        *err = Some(e.to_string());
+                    }
+                    return;
+                }
+            }
+            Message::Heartbeat(heartbeat) => {
+                if let Err(e) = sink.write_heartbeat(&heartbeat) {
+                    if let Ok(mut err) = error.lock() {
+                        *err = Some(e.to_string());
+                    }
+                    return;
+                }
+            }
+            Message::Watermark(watermark) => {
+                if let Err(e) = sink.write_watermark(&watermark) {
+                    if let Ok(mut err) = error.lock() {
+                        *err = Some(e.to_string());
+                    }
+                    return;
+                }
+            }
+            Message::Flush(reply) => {
+                let _ = reply.send(sink.flush());
+            }
+        }
+    }
+    let _ = sink.flush();
+}
+
+impl Sink for QueuedSink {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        self.check_error()?;
+        let reservation = memory::reserve_blocking(memory::event_size(event), || self.metrics.depth.load(Ordering::Relaxed) > 0);
+        let depth = self.metrics.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        self.metrics.max_depth.fetch_max(depth, Ordering::Relaxed);
+        if depth >= self.config.high_watermark && !self.metrics.backpressure.swap(true, Ordering::Relaxed) {
+            self.metrics.backpressure_count.fetch_add(1, Ordering::Relaxed);
+        }
+        if depth > self.config.capacity {
+            self.metrics.blocked_writes.fetch_add(1, Ordering::Relaxed);
+        }
+        self.send(Message::Event(Box::new(event.clone()), reservation))
+    }
+
+    //心跳不计入队列长度, 排在之前的事件之后写入
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        self.check_error()?;
+        self.send(Message::Heartbeat(heartbeat.clone()))
+    }
+
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        self.check_error()?;
+        self.send(Message::Watermark(watermark.clone()))
+    }
+
+    //等待队列中的事件全部写入后再flush
+    fn flush(&mut self) -> io::Result<()> {
+        self.check_error()?;
+        let (reply, result) = mpsc::sync_channel(1);
+        self.send(Message::Flush(reply))?;
+        match result.recv() {
+            Ok(r) => r,
+            Err(_) => {
+                self.check_error()?;
+                Err(io::Error::other("输出线程已退出"))
+            }
+        }
+    }
+}
+
+impl Drop for QueuedSink {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,184 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//Schema Registry: 按表生成JSON Schema注册到subject({topic}-value), 消息使用confluent的格式
+//  0x00 + 4字节schema id(大端) + json, kafka消费端使用KafkaJsonSchemaDeserializer等标准反序列化器即可解析
+//  表结构变化(set_columns)后生成新的schema重新注册, 兼容性由registry按subject的配置检查, 不兼容时写入返回错误
+//  目前只支持JSON Schema格式, 消息内容与JsonLinesSink相同
+//  with_compression时每条消息(包括magic byte、schema id)整体压缩, producer按content_encoding()写入消息头(如content-encoding: zstd)
+
+use std::collections::HashMap;
+use std::io;
+use serde_json::{json, Value as JsonValue};
+use crate::reader::Event;
+use crate::replication::readbinlog::Traction;
+use crate::stdout::format::ValueFormat;
+use crate::stdout::outjson;
+use crate::stdout::outjsonschema::json_schema;
+use crate::sink::{Sink, Compressor, RouteTemplate, default_columns};
+
+/// schema注册中心, 返回schema id, 相同的schema重复注册返回相同的id
+pub trait SchemaRegistry {
+    fn register(&mut self, subject: &str, schema: &str) -> Result<u32, String>;
+}
+
+/// Confluent Schema Registry的http接口(POST /subjects/{subject}/versions)
+#[cfg(feature = "network")]
+pub struct HttpSchemaRegistry{
+    url: String,
+    auth: Option<String>,
+}
+
+#[cfg(feature = "network")]
+impl HttpSchemaRegistry{
+    pub fn new(url: &str) -> HttpSchemaRegistry {
+        HttpSchemaRegistry{ url: url.trim_end_matches('/').to_string(), auth: None }
+    }
+
+    /// basic认证, confluent cloud为api key及secret
+    pub fn with_auth(mut self, user: &str, password: &str) -> HttpSchemaRegistry {
+        self.auth = Some(format!("Basic {}", base64::encode(&format!("{}:{}", user, password))));
+        self
+    }
+}
+
+#[cfg(feature = "network")]
+impl SchemaRegistry for HttpSchemaRegistry {
+    fn register(&mut self, subject: &str, schema: &str) -> Result<u32, String> {
+        let url = format!("{}/subjects/{}/versions", self.url, subject);
+        let mut request = ureq::post(&url).timeout(std::time::Duration::from_secs(10))
+            .set("Content-Type", "application/vnd.schemaregistry.v1+json");
+        if let Some(auth) = &self.auth {
+            request = request.set("Authorization", auth);
+        }
+        let body = json!({"schemaType": "JSON", "schema": schema}).to_string();
+        let text = match request.send_string(&body) {
+            Ok(t) => t.into_string().map_err(|e| format!("{}: {}", url, e))?,
+            //409为与已有版本不兼容, 422为schema无效, 返回内容中有具体原因
+            Err(ureq::Error::Status(code, t)) => return Err(format!("{}: {} {}", url, code, t.into_string().unwrap_or_default())),
+            Err(e) => return Err(format!("{}: {}", url, e)),
+        };
+        let value: JsonValue = serde_json::from_str(&text).map_err(|e| format!("{}: {}", url, e))?;
+        value["id"].as_u64().map(|t| t as u32).ok_or_else(|| format!("{}: 返回内容中没有id: {}", url, text))
+    }
+}
+
+type Producer = Box<dyn FnMut(&str, &[u8]) -> io::Result<()>>;
+
+/// 行数据以confluent格式(magic byte、schema id、json)交给producer写入, producer的参数为topic及消息内容
+/// topic由模板渲染(见RouteTemplate), subject为{topic}-value
+pub struct RegistrySink{
+    topic: RouteTemplate,
+    registry: Box<dyn SchemaRegistry>,
+    producer: Producer,
+    compressor: Option<Box<dyn Compressor>>,
+    format: ValueFormat,
+    gtid: String,
+    table_cols_info: HashMap<String, Vec<HashMap<String, String>>>,
+    schemas: HashMap<String, String>,           //db.tbl对应的schema, set_columns时清除
+    ids: HashMap<(String, String), u32>,        //(subject, schema)已注册的id
+}
+
+impl RegistrySink{
+    pub fn new<F>(topic: &str, registry: Box<dyn SchemaRegistry>, format: ValueFormat, producer: F) -> Result<RegistrySink, String>
+        where F: FnMut(&str, &[u8]) -> io::Result<()> + 'static {
+        Ok(RegistrySink{
+            topic: RouteTemplate::new(topic)?,
+            registry,
+            producer: Box::new(producer),
+            compressor: None,
+            format,
+            gtid: String::from(""),
+            table_cols_info: HashMap::new(),
+            schemas: HashMap::new(),
+            ids: HashMap::new(),
+        })
+    }
+
+    /// 设置表结构信息, key为db.tbl, 格式与meta::get_col获取的一致(COLUMN_NAME、COLUMN_TYPE)
+    pub fn with_columns(mut self, table_cols_info: HashMap<String, Vec<HashMap<String, String>>>) -> RegistrySink {
+        self.table_cols_info = table_cols_info;
+        self
+    }
+
+    /// 每条消息压缩后交给producer
+    pub fn with_compression(mut self, compressor: Box<dyn Compressor>) -> RegistrySink {
+        self.compressor = Some(compressor);
+        self
+    }
+
+    /// 消息的压缩算法, 没有压缩时为None
+    pub fn content_encoding(&self) -> Option<&str> {
+        self.compressor.as_ref().map(|t| t.encoding())
+    }
+
+    /// 表结构变化(DDL)后更新字段信息, 下一行数据使用新的schema注册
+    pub fn set_columns(&mut self, db_tbl: &str, cols: Vec<HashMap<String, String>>) {
+        self.schemas.remove(db_tbl);
+        self.table_cols_info.insert(db_tbl.to_string(), cols);
+    }
+
+    /// 已注册的(subject, schema id)
+    pub fn registered(&self) -> Vec<(&str, u32)> {
+        let mut ids: Vec<(&str, u32)> = self.ids.iter().map(|((subject, _), id)| (subject.as_str(), *id)).collect();
+        ids.sort();
+        ids
+    }
+}
+
+impl Sink for RegistrySink {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        let (code, rows, map) = match (&event.data, &event.table) {
+            (Traction::GtidEvent(t), _) => {
+                self.gtid = format!("{}:{}", t.gtid, t.gno_id);
+                return Ok(());
+            }
+            (Traction::RowEvent(code, rows), Some(map)) => (code, rows, map),
+            _ => return Ok(())
+        };
+        let topic = match self.topic.render(event) {
+            Some(t) => t,
+            None => return Ok(())
+        };
+        let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+        self.format.check_finite(rows.rows(), &db_tbl).map_err(crate::readvalue::invalid_data)?;
+        self.format.check_temporal(rows.rows(), &db_tbl).map_err(crate::readvalue::invalid_data)?;
+        let default_cols;
+        let cols = match self.table_cols_info.get(&db_tbl) {
+            Some(t) => t,
+            None => {
+                default_cols = default_columns(map);
+                &default_cols
+            }
+        };
+        let format = &self.format;
+        let schema = self.schemas.entry(db_tbl.clone()).or_insert_with(|| json_schema(&db_tbl, cols, format).to_string());
+        let key = (format!("{}-value", topic), schema.clone());
+        let id = match self.ids.get(&key) {
+            Some(t) => *t,
+            None => {
+                let id = self.registry.register(&key.0, &key.1).map_err(io::Error::other)?;
+                self.ids.insert(key, id);
+                id
+            }
+        };
+        for record in outjson::row_records(rows, code, cols, &db_tbl, map, &self.gtid, &self.format) {
+            let message = confluent_message(id, &record);
+            match &self.compressor {
+                Some(compressor) => (self.producer)(&topic, &compressor.compress(&message)?)?,
+                None => (self.producer)(&topic, &message)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// confluent格式的消息: 0x00、4字节schema id(大端)、json
+pub fn confluent_message(id: u32, record: &JsonValue) -> Vec<u8> {
+    let mut message = vec![0u8];
+    message.extend_from_slice(&id.to_be_bytes());
+    message.extend_from_slice(record.to_string().as_bytes());
+    message
+}
@@ -0,0 +1,276 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//按事件中的字段路由到不同的输出目标, 一个进程可以处理多个表而不需要为每个表单独配置
+//  模板中可以使用{db}、{table}、{op}(insert/update/delete)、{date}(事件时间, utc %Y-%m-%d), 例如 /data/{db}/{table}/{date}.json
+//  可以用于文件路径、kafka topic、redis key、webhook地址等, 由factory根据渲染后的结果创建输出目标
+//  只有行事件有表信息, 其余事件(GTID、BEGIN、TABLE_MAP、XID、DDL等)写入当前打开的所有输出目标
+//  with_compaction时目标为文件路径, 所在目录作为分区, 目录中最后一个目标关闭(max_open或drop)后提交合并小文件
+
+use std::collections::HashMap;
+use std::io;
+use crate::reader::Event;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::BinlogEvent;
+use crate::stdout::format;
+use crate::sink::{Sink, Heartbeat, Watermark};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part{
+    Text(String),
+    Db,
+    Table,
+    Op,
+    Date,
+}
+
+/// 输出目标的模板, 如 {db}.{table} 、 binlog-{op}-{date}
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteTemplate{
+    parts: Vec<Part>,
+}
+
+impl RouteTemplate{
+    pub fn new(template: &str) -> Result<RouteTemplate, String> {
+        let mut parts = vec![];
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let end = match rest[start..].find('}') {
+                Some(t) => start + t,
+                None => return Err(format!("模板中的{{没有对应的}}: {}", template))
+            };
+            parts.push(match &rest[start + 1..end] {
+                "db" => Part::Db,
+                "table" => Part::Table,
+                "op" => Part::Op,
+                "date" => Part::Date,
+                other => return Err(format!("模板中不支持的字段{{{}}}, 可以使用{{db}}、{{table}}、{{op}}、{{date}}", other))
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(RouteTemplate{ parts })
+    }
+
+    /// 是否包含字段, 不包含时所有事件都写入同一个目标
+    pub fn is_static(&self) -> bool {
+        self.parts.iter().all(|p| matches!(p, Part::Text(_)))
+    }
+
+    /// 渲染行事件对应的输出目标, 其他事件返回None
+    /// 库名、表名中的/、\替换为_, 避免作为文件路径时写到其他目录
+    pub fn render(&self, event: &Event) -> Option<String> {
+        let (code, map) = match (&event.data, &event.table) {
+            (Traction::RowEvent(code, _), Some(map)) => (code, map),
+            _ => return None
+        };
+        let op = match code {
+            BinlogEvent::WriteEvent => "insert",
+            BinlogEvent::UpdateEvent => "update",
+            _ => "delete"
+        };
+        let mut route = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(t) => route.push_str(t),
+                Part::Db => route.push_str(&sanitize(&map.database_name)),
+                Part::Table => route.push_str(&sanitize(&map.table_name)),
+                Part::Op => route.push_str(op),
+                Part::Date => route.push_str(&format::utc_datetime(event.header.timestamp)[..10]),
+            }
+        }
+        Some(route)
+    }
+}
+
+fn sanitize(name: &str) -> String {
+    match name {
+        "." | ".." => name.replace('.', "_"),
+        _ => name.replace(['/', '\\', '\0'], "_")
+    }
+}
+
+type SinkFactory = Box<dyn FnMut(&str) -> io::Result<Box<dyn Sink>>>;
+
+struct Route{
+    sink: Box<dyn Sink>,
+    last_used: u64,
+}
+
+/// 按模板将行事件写入不同的输出目标, 目标在第一次用到时通过factory创建
+pub struct RoutedSink{
+    template: RouteTemplate,
+    factory: SinkFactory,
+    routes: HashMap<String, Route>,
+    max_open: usize,
+    seq: u64,
+    gtid: Option<Event>,
+    #[cfg(feature = "os")]
+    compaction: Option<crate::sink::CompactionTask>,
+}
+
+impl RoutedSink{
+    pub fn new<F>(template: &str, factory: F) -> Result<RoutedSink, String>
+        where F: FnMut(&str) -> io::Result<Box<dyn Sink>> + 'static
+    {
+        Ok(RoutedSink{
+            template: RouteTemplate::new(template)?,
+            factory: Box::new(factory),
+            routes: HashMap::new(),
+            max_open: 0,
+            seq: 0,
+            gtid: None,
+            #[cfg(feature = "os")]
+            compaction: None,
+        })
+    }
+
+    /// 同时打开的输出目标上限, 超过时flush并关闭最久未使用的目标, 0为不限制
+    /// 模板中包含{date}时旧日期的目标不会再用到, 可以通过该值关闭
+    pub fn max_open(mut self, max_open: usize) -> RoutedSink {
+        self.max_open = max_open;
+        self
+    }
+
+    /// 目标关闭后合并所在目录(分区)中的小文件, 目录中还有打开的目标时不提交
+    /// 模板中分区的部分需要为目录, 如 /data/{db}/{table}/{date}/{op}.json
+    #[cfg(feature = "os")]
+    pub fn with_compaction(mut self, task: crate::sink::CompactionTask) -> RoutedSink {
+        self.compaction = Some(task);
+        self
+    }
+
+    /// 当前打开的输出目标
+    pub fn routes(&self) -> Vec<&str> {
+        let mut routes: Vec<&str> = self.routes.keys().map(|t| t.as_str()).collect();
+        routes.sort();
+        routes
+    }
+
+    fn evict(&mut self) -> io::Result<()> {
+        while self.max_open > 0 && self.routes.len() >= self.max_open {
+            let oldest = match self.routes.iter().min_by_key(|(_, r)| r.last_used) {
+                Some((name, _)) => name.clone(),
+                None => break
+            };
+            if let Some(mut route) = self.routes.remove(&oldest) {
+                route.sink.flush().map_err(|e| io::Error::new(e.kind(), format!("{}: {}", oldest, e)))?;
+                drop(route);
+                #[cfg(feature = "os")]
+                self.closed(&oldest);
+            }
+        }
+        Ok(())
+    }
+
+    //目录中已经没有打开的目标时提交合并
+    #[cfg(feature = "os")]
+    fn closed(&self, name: &str) {
+        use std::path::Path;
+        let (task, dir) = match (&self.compaction, Path::new(name).parent()) {
+            (Some(task), Some(dir)) => (task, dir),
+            _ => return
+        };
+        if !self.routes.keys().any(|t| Path::new(t).parent() == Some(dir)) {
+            task.submit(dir);
+        }
+    }
+
+    fn route(&mut self, name: String) -> io::Result<&mut Box<dyn Sink>> {
+        self.seq += 1;
+        if !self.routes.contains_key(&name) {
+            self.evict()?;
+            #[cfg(feature = "os")]
+            if let (Some(task), Some(dir)) = (&self.compaction, std::path::Path::new(&name).parent()) {
+                task.reopen(dir);
+            }
+            let mut sink = (self.factory)(&name).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+            //事务中途创建的目标需要当前事务的gtid
+            if let Some(gtid) = &self.gtid {
+                sink.write_event(gtid)?;
+            }
+            self.routes.insert(name.clone(), Route{ sink, last_used: 0 });
+        }
+        let route = self.routes.get_mut(&name).unwrap();
+        route.last_used = self.seq;
+        Ok(&mut route.sink)
+    }
+}
+
+impl Sink for RoutedSink {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        if let Some(name) = self.template.render(event) {
+            return self.route(name)?.write_event(event);
+        }
+        if let Traction::GtidEvent(_) = &event.data {
+            self.gtid = Some(event.clone());
+        }
+        for (name, route) in self.routes.iter_mut() {
+            route.sink.write_event(event).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+        }
+        Ok(())
+    }
+
+    //心跳写入当前打开的所有目标
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        for (name, route) in self.routes.iter_mut() {
+            route.sink.write_heartbeat(heartbeat).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+        }
+        Ok(())
+    }
+
+    //水位写入当前打开的所有目标
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        for (name, route) in self.routes.iter_mut() {
+            route.sink.write_watermark(watermark).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for (name, route) in self.routes.iter_mut() {
+            route.sink.flush().map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+        }
+        Ok(())
+    }
+}
+
+//关闭所有目标后提交合并
+#[cfg(feature = "os")]
+impl Drop for RoutedSink {
+    fn drop(&mut self) {
+        if self.compaction.is_none() {
+            return;
+        }
+        let names: Vec<String> = self.routes.keys().cloned().collect();
+        for name in names {
+            self.routes.remove(&name);
+            self.closed(&name);
+        }
+    }
+}
+
+#[cfg(all(feature = "os", feature = "json-sink"))]
+impl RoutedSink{
+    /// 按路径模板将行数据以json格式追加写入文件, 目录不存在时自动创建
+    pub fn json_files(template: &str, value_format: format::ValueFormat) -> Result<RoutedSink, String> {
+        use std::fs::{self, OpenOptions};
+        use std::io::BufWriter;
+        use std::path::Path;
+        use crate::sink::JsonLinesSink;
+        RoutedSink::new(template, move |path| {
+            if let Some(dir) = Path::new(path).parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            Ok(Box::new(JsonLinesSink::new(BufWriter::new(file), value_format.clone())) as Box<dyn Sink>)
+        })
+    }
+}
@@ -0,0 +1,216 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//事件时间水位: 定期向输出目标写入已提交事务的最大提交时间, Flink、Kafka Streams等按事件时间开窗的下游据此关闭窗口
+//  提交时间为XID/COMMIT事件头的timestamp, 水位只增不减, 提交时间回退(并发事务、时钟调整)时保持不变
+//  binlog中的时间为语句开始执行的时间, 并发的长事务可能晚于水位到达, delay为允许的乱序秒数, 水位为最大提交时间减去delay
+//  水位之后的记录提交时间不早于水位(delay之内; 时间精度为秒, 同一秒的事务仍可能到达), 结束时间不晚于水位的窗口可以关闭,
+//  Flink中为watermark * 1000 - 1毫秒; 源端空闲时水位不推进, 由下游的空闲检测处理
+//  事务中间不写入水位, 在事务结束后按间隔写入; 多个源合并输出时(OrderedMerge)为所有源的水位的最小值
+
+use std::time::Duration;
+use serde_json::{json, Value as JsonValue};
+use crate::replication::readbinlog::Traction;
+#[cfg(feature = "os")]
+use std::io;
+#[cfg(feature = "os")]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "os")]
+use crate::reader::Event;
+#[cfg(feature = "os")]
+use crate::sink::{Sink, Heartbeat};
+use crate::sink::HeartbeatTracker;
+
+/// 水位记录
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Watermark{
+    /// 事件时间的水位, unix秒
+    pub timestamp: u32,
+    /// 最后一个已完成事务的gtid, 合并多个源时为空
+    pub gtid: String,
+    /// 当前binlog文件及位置, 合并多个源时为空
+    pub log_file: String,
+    pub log_pos: u64,
+    /// 生成水位的时间, unix毫秒
+    pub emitted: u64,
+}
+
+impl Watermark{
+    /// {"type":"watermark","watermark":1572998400,"gtid":"uuid:10","log_file":"mysql-bin.000003","position":1234,"timestamp":1572998401000}
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "type": "watermark",
+            "watermark": self.timestamp,
+            "gtid": self.gtid,
+            "log_file": self.log_file,
+            "position": self.log_pos,
+            "timestamp": self.emitted,
+        })
+    }
+}
+
+/// 根据读取到的事件记录已提交事务的最大提交时间
+#[derive(Debug, Clone, Default)]
+pub struct WatermarkTracker{
+    position: HeartbeatTracker,
+    max_commit: u32,
+    delay: u32,
+}
+
+impl WatermarkTracker{
+    /// delay为允许的乱序秒数
+    pub fn new(delay: u32) -> WatermarkTracker {
+        WatermarkTracker{ delay, ..WatermarkTracker::default() }
+    }
+
+    /// timestamp、next_position为事件头中的值
+    pub fn observe(&mut self, data: &Traction, timestamp: u32, next_position: u64) {
+        self.position.observe(data, timestamp, next_position);
+        match data {
+            Traction::QueryEvent(t) if t.command == "BEGIN" => {}
+            Traction::XidEvent(_) | Traction::QueryEvent(_) => self.max_commit = self.max_commit.max(timestamp),
+            _ => {}
+        }
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.position.in_transaction()
+    }
+
+    /// 当前的水位, 还没有已提交的事务时为None
+    pub fn watermark(&self, emitted: u64) -> Option<Watermark> {
+        if self.max_commit == 0 {
+            return None;
+        }
+        let state = self.position.heartbeat(emitted);
+        Some(Watermark{
+            timestamp: self.max_commit.saturating_sub(self.delay),
+            gtid: state.gtid,
+            log_file: state.log_file,
+            log_pos: state.log_pos,
+            emitted,
+        })
+    }
+}
+
+/// 解析水位的输出间隔及允许的乱序时间(秒), interval为空时不输出水位
+pub fn parse_watermark(interval: &str, delay: &str) -> Result<Option<(Duration, Duration)>, String> {
+    if interval.is_empty() {
+        if !delay.is_empty() {
+            return Err(String::from("watermark-delay需要同时指定watermark"));
+        }
+        return Ok(None);
+    }
+    let interval = match interval.parse::<f64>() {
+        Ok(t) if t > 0.0 && t.is_finite() => Duration::from_secs_f64(t),
+        _ => return Err(format!("无效的watermark: {}, 需要为大于0的秒数", interval)),
+    };
+    let delay = match delay {
+        "" => Duration::from_secs(0),
+        t => match t.parse::<u32>() {
+            Ok(t) => Duration::from_secs(t as u64),
+            _ => return Err(format!("无效的watermark-delay: {}, 需要为整数秒", t)),
+        }
+    };
+    Ok(Some((interval, delay)))
+}
+
+/// 按间隔生成水位, stream --outformat json直接输出时使用, 输出到Sink时使用WatermarkSink
+#[cfg(feature = "os")]
+#[derive(Debug, Clone)]
+pub struct WatermarkTimer{
+    tracker: WatermarkTracker,
+    interval: Duration,
+    last: Instant,
+}
+
+#[cfg(feature = "os")]
+impl WatermarkTimer{
+    pub fn new(interval: Duration, delay: Duration) -> WatermarkTimer {
+        WatermarkTimer{ tracker: WatermarkTracker::new(delay.as_secs() as u32), interval, last: Instant::now() }
+    }
+
+    pub fn observe(&mut self, data: &Traction, timestamp: u32, next_position: u64) {
+        self.tracker.observe(data, timestamp, next_position);
+    }
+
+    /// 到达间隔且不在事务中间时返回水位
+    pub fn due(&mut self) -> Option<Watermark> {
+        if self.last.elapsed() < self.interval || self.tracker.in_transaction() {
+            return None;
+        }
+        self.now()
+    }
+
+    /// 立即生成水位, 还没有已提交的事务时为None
+    pub fn now(&mut self) -> Option<Watermark> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_millis() as u64).unwrap_or(0);
+        let watermark = self.tracker.watermark(now)?;
+        self.last = Instant::now();
+        Some(watermark)
+    }
+}
+
+/// 按间隔向输出目标写入水位, 其余事件原样写入
+#[cfg(feature = "os")]
+pub struct WatermarkSink<S: Sink>{
+    sink: S,
+    timer: WatermarkTimer,
+}
+
+#[cfg(feature = "os")]
+impl<S: Sink> WatermarkSink<S> {
+    pub fn new(sink: S, interval: Duration) -> WatermarkSink<S> {
+        WatermarkSink{ sink, timer: WatermarkTimer::new(interval, Duration::from_secs(0)) }
+    }
+
+    /// 允许的乱序时间, 水位为最大提交时间减去delay
+    pub fn with_delay(mut self, delay: Duration) -> WatermarkSink<S> {
+        self.timer.tracker.delay = delay.as_secs() as u32;
+        self
+    }
+
+    /// 到达间隔且不在事务中间时写入水位, 返回是否写入
+    pub fn tick(&mut self) -> io::Result<bool> {
+        match self.timer.due() {
+            Some(watermark) => self.sink.write_watermark(&watermark).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// 立即写入水位, 还没有已提交的事务时不写入
+    pub fn watermark(&mut self) -> io::Result<bool> {
+        match self.timer.now() {
+            Some(watermark) => self.sink.write_watermark(&watermark).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+}
+
+#[cfg(feature = "os")]
+impl<S: Sink> Sink for WatermarkSink<S> {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        self.timer.observe(&event.data, event.header.timestamp, event.header.next_position as u64);
+        self.sink.write_event(event)?;
+        self.tick()?;
+        Ok(())
+    }
+
+    fn write_heartbeat(&mut self, heartbeat: &Heartbeat) -> io::Result<()> {
+        self.sink.write_heartbeat(heartbeat)
+    }
+
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        self.sink.write_watermark(watermark)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
@@ -0,0 +1,382 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//snapshot: 在基础数据(csv)上应用binlog中的变更, 还原某个时间点表中的数据, 不需要完整的时间点恢复
+//  基础数据为带表头的csv, 格式与--outformat csv的字段值相同: NULL为空字段(或\N), 空字符串为"", 二进制按binary-encoding(默认hex)
+//  按事务应用: 事务中最后一个事件的时间晚于--stopdatetime时结束, 早于--startdatetime的事务跳过
+//      从备份时记录的位置开始时指定--startposition(第一个文件中的position)
+//  行按主键(--key或表结构中的主键)对应, 没有主键时按整行对应; 更新、删除不存在的行及插入已存在的行计为冲突
+//      备份与binlog有重叠时插入按覆盖处理, 结果仍然正确
+//  字段按名称与binlog对应, 没有字段名时(没有表结构及binlog_row_metadata=FULL)按顺序对应
+
+use std::collections::HashMap;
+use crate::reader::Transaction;
+use crate::replication::parsevalue::MySQLValue;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::{BinlogEvent, TableMap};
+use crate::stdout::format::ValueFormat;
+
+type Row = Vec<Option<String>>;
+
+/// 解析csv, 返回每条记录的字段值, 没有引号的空字段及\N为NULL
+pub fn parse_csv(content: &str) -> Result<Vec<Row>, String> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut in_quote = false;
+    let mut chars = content.chars().peekable();
+    let mut line = 1;
+    let end_field = |field: &mut String, quoted: &mut bool, record: &mut Row| {
+        let value = std::mem::take(field);
+        record.push(if *quoted { Some(value) } else if value.is_empty() || value == "\\N" { None } else { Some(value) });
+        *quoted = false;
+    };
+    while let Some(c) = chars.next() {
+        match (in_quote, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => in_quote = false,
+            (true, c) => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+            (false, '"') if field.is_empty() && !quoted => {
+                quoted = true;
+                in_quote = true;
+            }
+            (false, ',') => end_field(&mut field, &mut quoted, &mut record),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                end_field(&mut field, &mut quoted, &mut record);
+                records.push(std::mem::take(&mut record));
+                line += 1;
+            }
+            (false, c) => {
+                if quoted {
+                    return Err(format!("csv第{}行: 引号之后有多余的字符", line));
+                }
+                field.push(c);
+            }
+        }
+    }
+    if in_quote {
+        return Err(format!("csv第{}行: 引号没有结束", line));
+    }
+    if !field.is_empty() || quoted || !record.is_empty() {
+        end_field(&mut field, &mut quoted, &mut record);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+//包含逗号、引号、换行的字段使用双引号包起来, 与outcsv相同; 空字符串为""
+fn csv_field(value: &Option<String>) -> String {
+    match value {
+        None => String::new(),
+        Some(t) if t.is_empty() => String::from("\"\""),
+        Some(t) if t.contains([',', '"', '\n', '\r']) || t == "\\N" => format!("\"{}\"", t.replace('"', "\"\"")),
+        Some(t) => t.clone(),
+    }
+}
+
+/// 应用过程中的统计
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SnapshotStats{
+    pub transactions: u64,
+    pub inserted: u64,
+    pub updated: u64,
+    pub deleted: u64,
+    /// 更新、删除不存在的行, 插入已存在的行
+    pub conflicts: u64,
+    /// 最后应用的事务的时间
+    pub last_timestamp: u32,
+}
+
+/// 一个表在内存中的数据, 行保持基础数据中的顺序, 新插入的行在最后
+#[derive(Debug, Clone)]
+pub struct TableSnapshot{
+    database: String,
+    table: String,
+    columns: Vec<String>,
+    key: Vec<usize>,
+    rows: Vec<Option<Row>>,
+    index: HashMap<Row, Vec<usize>>,
+    format: ValueFormat,
+    stats: SnapshotStats,
+}
+
+impl TableSnapshot{
+    /// table为db.tbl, base为带表头的csv, key为主键字段名(为空时按整行对应)
+    pub fn from_csv(table: &str, base: &str, key: &[String], format: ValueFormat) -> Result<TableSnapshot, String> {
+        let (database, table) = table.trim().split_once('.')
+            .filter(|(db, tbl)| !db.is_empty() && !tbl.is_empty())
+            .ok_or_else(|| format!("无效的表名: {}, 格式为db.tbl", table))?;
+        let mut records = parse_csv(base)?.into_iter();
+        let columns: Vec<String> = records.next().ok_or_else(|| String::from("基础数据为空, 第一行需要为字段名"))?
+            .into_iter().map(|t| t.unwrap_or_default()).collect();
+        let key = key.iter().map(|k| {
+            columns.iter().position(|c| c.eq_ignore_ascii_case(k)).ok_or_else(|| format!("基础数据中没有主键字段: {}", k))
+        }).collect::<Result<Vec<usize>, String>>()?;
+        let mut snapshot = TableSnapshot{
+            database: database.trim_matches('`').to_string(),
+            table: table.trim_matches('`').to_string(),
+            columns,
+            key,
+            rows: vec![],
+            index: HashMap::new(),
+            format,
+            stats: SnapshotStats::default(),
+        };
+        for (idx, row) in records.enumerate() {
+            if row.len() != snapshot.columns.len() {
+                return Err(format!("基础数据第{}行有{}个字段, 表头为{}个", idx + 2, row.len(), snapshot.columns.len()));
+            }
+            snapshot.push(row);
+        }
+        Ok(snapshot)
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn stats(&self) -> &SnapshotStats {
+        &self.stats
+    }
+
+    /// 当前的数据, 已删除的行不返回
+    pub fn rows(&self) -> impl Iterator<Item = &Row> {
+        self.rows.iter().flatten()
+    }
+
+    /// 以csv输出, 第一行为字段名
+    pub fn write_csv<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let header: Vec<String> = self.columns.iter().map(|c| csv_field(&Some(c.clone()))).collect();
+        writeln!(writer, "{}", header.join(","))?;
+        for row in self.rows() {
+            writeln!(writer, "{}", row.iter().map(csv_field).collect::<Vec<String>>().join(","))?;
+        }
+        writer.flush()
+    }
+
+    fn key_of(&self, row: &Row) -> Row {
+        if self.key.is_empty() {
+            return row.clone();
+        }
+        self.key.iter().map(|idx| row[*idx].clone()).collect()
+    }
+
+    fn push(&mut self, row: Row) {
+        let key = self.key_of(&row);
+        self.index.entry(key).or_default().push(self.rows.len());
+        self.rows.push(Some(row));
+    }
+
+    //删除一行, 没有该行时返回false
+    fn remove(&mut self, row: &Row) -> bool {
+        let key = self.key_of(row);
+        let idx = match self.index.get_mut(&key).and_then(|t| t.pop()) {
+            Some(t) => t,
+            None => return false,
+        };
+        if self.index.get(&key).is_some_and(|t| t.is_empty()) {
+            self.index.remove(&key);
+        }
+        self.rows[idx] = None;
+        true
+    }
+
+    //binlog中的字段对应到基础数据中的字段, 按名称, 没有字段名时按顺序
+    fn mapping(&self, cols: &[HashMap<String, String>], map: &TableMap) -> Result<Vec<Option<usize>>, String> {
+        let named = cols.iter().any(|c| c.get("COLUMN_NAME").is_some_and(|n| !n.starts_with('@')));
+        if !named {
+            if map.column_info.len() != self.columns.len() {
+                return Err(format!("binlog中{}.{}有{}个字段, 基础数据为{}个, 没有字段名时需要相同",
+                                   self.database, self.table, map.column_info.len(), self.columns.len()));
+            }
+            return Ok((0..self.columns.len()).map(Some).collect());
+        }
+        let positions: Vec<Option<usize>> = cols.iter().map(|c| {
+            let name = c.get("COLUMN_NAME").map(|t| t.as_str()).unwrap_or("");
+            self.columns.iter().position(|t| t.eq_ignore_ascii_case(name))
+        }).collect();
+        if let Some(missing) = (0..self.columns.len()).find(|idx| !positions.contains(&Some(*idx))) {
+            return Err(format!("binlog中{}.{}没有基础数据中的字段: {}", self.database, self.table, self.columns[missing]));
+        }
+        Ok(positions)
+    }
+
+    fn convert(&self, row: &[Option<MySQLValue>], mapping: &[Option<usize>], cols: &[HashMap<String, String>]) -> Row {
+        let db_tbl = format!("{}.{}", self.database, self.table);
+        let mut values = vec![None; self.columns.len()];
+        for (idx, value) in row.iter().enumerate() {
+            let target = match mapping.get(idx) {
+                Some(Some(t)) => *t,
+                _ => continue,
+            };
+            let col_type = cols.get(idx).and_then(|c| c.get("COLUMN_TYPE")).map(|t| t.as_str()).unwrap_or("");
+            let encoding = self.format.binary_encoding(&db_tbl, &self.columns[target]);
+            values[target] = self.format.text_value(value, col_type, encoding);
+        }
+        values
+    }
+
+    /// 应用一个事务中该表的行事件, cols返回表的字段信息(见history::columns)
+    pub fn apply(&mut self, trx: &Transaction, mut cols: impl FnMut(&TableMap) -> Vec<HashMap<String, String>>) -> Result<(), String> {
+        let mut applied = false;
+        for event in &trx.events {
+            let (code, rows, map) = match (&event.data, &event.table) {
+                (Traction::RowEvent(code, rows), Some(map)) if map.database_name == self.database && map.table_name == self.table => (code, rows, map),
+                _ => continue,
+            };
+            let cols = cols(map);
+            let mapping = self.mapping(&cols, map)?;
+            let rows: Vec<Row> = rows.rows().iter().map(|row| self.convert(row, &mapping, &cols)).collect();
+            match code {
+                BinlogEvent::WriteEvent => for row in rows {
+                    if self.remove(&row) {
+                        self.stats.conflicts += 1;
+                    }
+                    self.push(row);
+                    self.stats.inserted += 1;
+                },
+                BinlogEvent::DeleteEvent => for row in rows {
+                    if !self.remove(&row) {
+                        self.stats.conflicts += 1;
+                    }
+                    self.stats.deleted += 1;
+                },
+                BinlogEvent::UpdateEvent => for pair in rows.chunks(2).filter(|pair| pair.len() == 2) {
+                    //主键未变化时原位置更新, 保持行的顺序
+                    let key = self.key_of(&pair[0]);
+                    let same_key = !self.key.is_empty() && key == self.key_of(&pair[1]);
+                    match self.index.get(&key).and_then(|t| t.last()).copied() {
+                        Some(idx) if same_key => self.rows[idx] = Some(pair[1].clone()),
+                        found => {
+                            if found.is_none() || !self.remove(&pair[0]) {
+                                self.stats.conflicts += 1;
+                            }
+                            if self.remove(&pair[1]) {
+                                self.stats.conflicts += 1;
+                            }
+                            self.push(pair[1].clone());
+                        }
+                    }
+                    self.stats.updated += 1;
+                },
+                _ => {}
+            }
+            applied = true;
+        }
+        if applied {
+            self.stats.transactions += 1;
+        }
+        if let Some(last) = trx.events.last() {
+            self.stats.last_timestamp = last.header.timestamp;
+        }
+        Ok(())
+    }
+}
+
+/// 读取按顺序排列的binlog文件应用到snapshot, start_position为第一个文件中开始的位置(0为文件开头),
+/// range为时间范围(unix时间戳, 0为不限制)
+pub fn apply_files(snapshot: &mut TableSnapshot, files: &[String], start_position: u64, range: (u32, u32),
+                   mut schema: impl FnMut(&TableMap) -> Option<Vec<HashMap<String, String>>>) -> std::io::Result<()> {
+    use std::io;
+    let (start, stop) = range;
+    for (idx, path) in files.iter().enumerate() {
+        let mut reader = crate::reader::BinlogReader::open(path)?;
+        if idx == 0 && start_position > 4 {
+            reader.seek(start_position)?;
+        }
+        for trx in reader.transactions() {
+            let trx = trx?;
+            if !trx.complete {
+                continue;
+            }
+            let timestamp = trx.events.last().map(|e| e.header.timestamp).unwrap_or(0);
+            if stop > 0 && timestamp > stop {
+                return Ok(());
+            }
+            if timestamp < start {
+                continue;
+            }
+            snapshot.apply(&trx, |map| crate::history::columns(map, schema(map).as_ref()))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// snapshot子命令: 读取--base指定的基础数据及-f/--index指定的binlog文件, 输出--stopdatetime时的数据
+#[cfg(feature = "cli")]
+pub fn run(conf: &crate::Config) {
+    use std::fs::File;
+    use std::io::{self, BufWriter, Write};
+    use std::process;
+    use crate::exitcode;
+    let exit = |err: String| -> ! {
+        println!("{}", err);
+        process::exit(exitcode::USAGE);
+    };
+    let format = ValueFormat::new(conf).unwrap_or_else(|e| exit(e));
+    let base = std::fs::read_to_string(&conf.snapshotbase).unwrap_or_else(|e| exit(format!("读取基础数据({})发生错误: {}", conf.snapshotbase, e)));
+    let time = |t: &str| if t.is_empty() { Ok(0) } else { crate::index::parse_time(t) };
+    let range = (time(&conf.startdatetime).unwrap_or_else(|e| exit(e)), time(&conf.stopdatetime).unwrap_or_else(|e| exit(e)));
+    let start_position = if conf.startposition.is_empty() { 0 } else {
+        conf.startposition.parse::<u64>().unwrap_or_else(|_| exit(format!("无效的startposition: {}", conf.startposition)))
+    };
+    let files: Vec<String> = if conf.index.is_empty() {
+        conf.file.split(',').map(|t| t.to_string()).collect()
+    } else {
+        crate::locate::read_index(&conf.index).unwrap_or_else(|e| exit(format!("读取index文件({})发生错误: {}", conf.index, e)))
+    };
+
+    //没有--key时使用表结构中的主键
+    let mut table_cols_info = HashMap::new();
+    let (db, tbl) = conf.snapshottable.split_once('.').unwrap_or(("", ""));
+    if !conf.user_name.is_empty() {
+        crate::meta::get_col(conf, &db.to_string(), &tbl.to_string(), &mut table_cols_info);
+    }
+    let key: Vec<String> = if conf.snapshotkey.is_empty() {
+        table_cols_info.get(&conf.snapshottable).map(|cols| cols.iter()
+            .filter(|c| c.get("COLUMN_KEY").is_some_and(|k| k == "PRI"))
+            .filter_map(|c| c.get("COLUMN_NAME").cloned())
+            .collect()).unwrap_or_default()
+    } else {
+        conf.snapshotkey.split(',').map(|t| t.trim().to_string()).collect()
+    };
+    let mut snapshot = TableSnapshot::from_csv(&conf.snapshottable, &base, &key, format).unwrap_or_else(|e| exit(e));
+    if let Err(err) = apply_files(&mut snapshot, &files, start_position, range, |map| {
+        table_cols_info.get(&format!("{}.{}", map.database_name, map.table_name)).cloned()
+    }) {
+        println!("应用binlog发生错误: {}", err);
+        process::exit(exitcode::PARSE);
+    }
+
+    let writer: Box<dyn Write> = match conf.output.as_str() {
+        "" | "-" => Box::new(io::stdout()),
+        t => Box::new(File::create(t).unwrap_or_else(|err|{
+            println!("创建文件({})发生错误:{}", t, err);
+            process::exit(exitcode::USAGE);
+        })),
+    };
+    snapshot.write_csv(BufWriter::new(writer)).unwrap_or_else(|err|{
+        println!("输出发生错误:{}", err);
+        process::exit(exitcode::SINK);
+    });
+    let stats = snapshot.stats();
+    crate::manifest::record(conf, || crate::manifest::ManifestEntry::file(&conf.output, "csv").map(|entry| {
+        crate::manifest::ManifestEntry{ transactions: stats.transactions, rows: snapshot.rows().count() as u64, ..entry }
+    }));
+    eprintln!("{} transactions, {} inserted, {} updated, {} deleted, {} conflicts, {} rows, last transaction at {} UTC",
+              stats.transactions, stats.inserted, stats.updated, stats.deleted, stats.conflicts, snapshot.rows().count(),
+              crate::stdout::format::utc_datetime(stats.last_timestamp));
+}
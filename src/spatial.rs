@@ -0,0 +1,265 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//空间类型(GEOMETRY、POINT、LINESTRING、POLYGON、MULTI*、GEOMETRYCOLLECTION)的值
+//  binlog中与mysql内部存储相同: 4bytes srid(小端) + WKB(Well-Known Binary)
+//  json输出转换为WKT(srid不为0时为SRID=4326;POINT(1 2))或GeoJSON(srid不为0时带srid成员)
+//  sql输出为ST_GeomFromText('POINT(1 2)', srid), srid不为0时按mysql内部存储的坐标顺序(经度在前)指定axis-order=long-lat,
+//  需要mysql 8.0, 5.7中srid只作为标记, 没有第三个参数
+//  解析失败时(数据损坏、不支持的类型)按二进制数据输出
+
+use std::io::{self, Cursor, Read};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use serde_json::{json, Value as JsonValue};
+
+/// 坐标(x, y)
+pub type Coord = (f64, f64);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry{
+    Point(Coord),
+    LineString(Vec<Coord>),
+    Polygon(Vec<Vec<Coord>>),
+    MultiPoint(Vec<Coord>),
+    MultiLineString(Vec<Vec<Coord>>),
+    MultiPolygon(Vec<Vec<Vec<Coord>>>),
+    GeometryCollection(Vec<Geometry>),
+}
+
+/// 字段的值: srid及几何对象
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpatialValue{
+    pub srid: u32,
+    pub geometry: Geometry,
+}
+
+/// json输出时空间类型的格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpatialFormat{
+    Wkt,
+    GeoJson,
+}
+
+impl SpatialFormat{
+    pub fn new(format: &str) -> Result<SpatialFormat, String> {
+        match format {
+            "" | "wkt" => Ok(SpatialFormat::Wkt),
+            "geojson" => Ok(SpatialFormat::GeoJson),
+            _ => Err(format!("无效的spatialformat: {}, 可选值为wkt、geojson", format))
+        }
+    }
+}
+
+/// COLUMN_TYPE是否为空间类型
+pub fn is_spatial_type(col_type: &str) -> bool {
+    matches!(col_type.to_lowercase().as_str(), "geometry" | "point" | "linestring" | "polygon" | "multipoint"
+        | "multilinestring" | "multipolygon" | "geometrycollection" | "geomcollection")
+}
+
+//嵌套的GEOMETRYCOLLECTION层数限制, 避免损坏的数据导致栈溢出
+const MAX_DEPTH: usize = 32;
+
+struct WkbReader<'a>{
+    cur: Cursor<&'a [u8]>,
+}
+
+impl<'a> WkbReader<'a> {
+    fn remaining(&self) -> usize {
+        self.cur.get_ref().len().saturating_sub(self.cur.position() as usize)
+    }
+
+    fn u32(&mut self, little: bool) -> io::Result<u32> {
+        if little { self.cur.read_u32::<LittleEndian>() } else { self.cur.read_u32::<BigEndian>() }
+    }
+
+    //元素个数, 按每个元素的最小字节数检查, 不按错误的个数分配内存
+    fn count(&mut self, little: bool, min_size: usize) -> io::Result<usize> {
+        let count = self.u32(little)? as usize;
+        if count.saturating_mul(min_size) > self.remaining() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("WKB中的元素个数{}超过剩余数据长度", count)));
+        }
+        Ok(count)
+    }
+
+    fn coord(&mut self, little: bool) -> io::Result<Coord> {
+        Ok(if little {
+            (self.cur.read_f64::<LittleEndian>()?, self.cur.read_f64::<LittleEndian>()?)
+        } else {
+            (self.cur.read_f64::<BigEndian>()?, self.cur.read_f64::<BigEndian>()?)
+        })
+    }
+
+    fn coords(&mut self, little: bool) -> io::Result<Vec<Coord>> {
+        let count = self.count(little, 16)?;
+        (0..count).map(|_| self.coord(little)).collect()
+    }
+
+    fn rings(&mut self, little: bool) -> io::Result<Vec<Vec<Coord>>> {
+        let count = self.count(little, 4)?;
+        (0..count).map(|_| self.coords(little)).collect()
+    }
+
+    //byte order + type
+    fn header(&mut self) -> io::Result<(bool, u32)> {
+        let little = match self.cur.read_u8()? {
+            0 => false,
+            1 => true,
+            t => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("无效的WKB字节序: {}", t)))
+        };
+        Ok((little, self.u32(little)?))
+    }
+
+    //MULTI*中的每个元素都是带有字节序及类型的完整WKB
+    fn member(&mut self, expected: u32) -> io::Result<(bool, u32)> {
+        let (little, wkb_type) = self.header()?;
+        if wkb_type != expected {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("WKB中的元素类型{}与集合类型不符", wkb_type)));
+        }
+        Ok((little, wkb_type))
+    }
+
+    fn geometry(&mut self, depth: usize) -> io::Result<Geometry> {
+        if depth > MAX_DEPTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "GEOMETRYCOLLECTION嵌套层数过多"));
+        }
+        let (little, wkb_type) = self.header()?;
+        Ok(match wkb_type {
+            1 => Geometry::Point(self.coord(little)?),
+            2 => Geometry::LineString(self.coords(little)?),
+            3 => Geometry::Polygon(self.rings(little)?),
+            4 => {
+                let count = self.count(little, 21)?;
+                Geometry::MultiPoint((0..count).map(|_| {
+                    let (little, _) = self.member(1)?;
+                    self.coord(little)
+                }).collect::<io::Result<_>>()?)
+            }
+            5 => {
+                let count = self.count(little, 9)?;
+                Geometry::MultiLineString((0..count).map(|_| {
+                    let (little, _) = self.member(2)?;
+                    self.coords(little)
+                }).collect::<io::Result<_>>()?)
+            }
+            6 => {
+                let count = self.count(little, 9)?;
+                Geometry::MultiPolygon((0..count).map(|_| {
+                    let (little, _) = self.member(3)?;
+                    self.rings(little)
+                }).collect::<io::Result<_>>()?)
+            }
+            7 => {
+                let count = self.count(little, 9)?;
+                Geometry::GeometryCollection((0..count).map(|_| self.geometry(depth + 1)).collect::<io::Result<_>>()?)
+            }
+            t => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("不支持的WKB类型: {}", t)))
+        })
+    }
+}
+
+impl Geometry{
+    /// 解析WKB
+    pub fn from_wkb(data: &[u8]) -> io::Result<Geometry> {
+        let mut reader = WkbReader{ cur: Cursor::new(data) };
+        let geometry = reader.geometry(0)?;
+        if reader.remaining() > 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "WKB之后有多余的数据"));
+        }
+        Ok(geometry)
+    }
+
+    pub fn to_wkt(&self) -> String {
+        fn coord(c: &Coord) -> String {
+            format!("{} {}", c.0, c.1)
+        }
+        fn coords(c: &[Coord]) -> String {
+            format!("({})", c.iter().map(coord).collect::<Vec<_>>().join(","))
+        }
+        fn rings(r: &[Vec<Coord>]) -> String {
+            format!("({})", r.iter().map(|t| coords(t)).collect::<Vec<_>>().join(","))
+        }
+        fn list<T>(name: &str, items: &[T], f: impl Fn(&T) -> String) -> String {
+            if items.is_empty() {
+                return format!("{} EMPTY", name);
+            }
+            format!("{}({})", name, items.iter().map(f).collect::<Vec<_>>().join(","))
+        }
+        match self {
+            Geometry::Point(c) => format!("POINT({})", coord(c)),
+            Geometry::LineString(c) => format!("LINESTRING{}", coords(c)),
+            Geometry::Polygon(r) => format!("POLYGON{}", rings(r)),
+            Geometry::MultiPoint(c) => list("MULTIPOINT", c, |t| format!("({})", coord(t))),
+            Geometry::MultiLineString(l) => list("MULTILINESTRING", l, |t| coords(t)),
+            Geometry::MultiPolygon(p) => list("MULTIPOLYGON", p, |t| rings(t)),
+            Geometry::GeometryCollection(g) => list("GEOMETRYCOLLECTION", g, |t| t.to_wkt()),
+        }
+    }
+
+    pub fn to_geojson(&self) -> JsonValue {
+        fn coord(c: &Coord) -> JsonValue {
+            json!([c.0, c.1])
+        }
+        fn coords(c: &[Coord]) -> JsonValue {
+            JsonValue::Array(c.iter().map(coord).collect())
+        }
+        fn rings(r: &[Vec<Coord>]) -> JsonValue {
+            JsonValue::Array(r.iter().map(|t| coords(t)).collect())
+        }
+        match self {
+            Geometry::Point(c) => json!({"type": "Point", "coordinates": coord(c)}),
+            Geometry::LineString(c) => json!({"type": "LineString", "coordinates": coords(c)}),
+            Geometry::Polygon(r) => json!({"type": "Polygon", "coordinates": rings(r)}),
+            Geometry::MultiPoint(c) => json!({"type": "MultiPoint", "coordinates": coords(c)}),
+            Geometry::MultiLineString(l) => json!({"type": "MultiLineString", "coordinates": JsonValue::Array(l.iter().map(|t| coords(t)).collect())}),
+            Geometry::MultiPolygon(p) => json!({"type": "MultiPolygon", "coordinates": JsonValue::Array(p.iter().map(|t| rings(t)).collect())}),
+            Geometry::GeometryCollection(g) => json!({"type": "GeometryCollection", "geometries": JsonValue::Array(g.iter().map(|t| t.to_geojson()).collect())}),
+        }
+    }
+}
+
+impl SpatialValue{
+    /// 解析binlog中的值: 4bytes srid + WKB
+    pub fn decode(data: &[u8]) -> io::Result<SpatialValue> {
+        let mut cur = Cursor::new(data);
+        let srid = cur.read_u32::<LittleEndian>()?;
+        let mut wkb = vec![];
+        cur.read_to_end(&mut wkb)?;
+        Ok(SpatialValue{ srid, geometry: Geometry::from_wkb(&wkb)? })
+    }
+
+    /// WKT, srid不为0时为EWKT格式(SRID=4326;POINT(1 2))
+    pub fn to_wkt(&self) -> String {
+        if self.srid == 0 {
+            self.geometry.to_wkt()
+        }else {
+            format!("SRID={};{}", self.srid, self.geometry.to_wkt())
+        }
+    }
+
+    /// GeoJSON geometry对象, srid不为0时带srid成员
+    pub fn to_geojson(&self) -> JsonValue {
+        let mut value = self.geometry.to_geojson();
+        if self.srid != 0 {
+            value["srid"] = JsonValue::from(self.srid);
+        }
+        value
+    }
+
+    pub fn to_json(&self, format: SpatialFormat) -> JsonValue {
+        match format {
+            SpatialFormat::Wkt => JsonValue::from(self.to_wkt()),
+            SpatialFormat::GeoJson => self.to_geojson(),
+        }
+    }
+
+    /// sql语句中的值
+    pub fn to_sql(&self) -> String {
+        if self.srid == 0 {
+            format!("ST_GeomFromText('{}')", self.geometry.to_wkt())
+        }else {
+            format!("ST_GeomFromText('{}', {}, 'axis-order=long-lat')", self.geometry.to_wkt(), self.srid)
+        }
+    }
+}
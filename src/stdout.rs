@@ -3,25 +3,496 @@
 @datetime: 2019/10/11
 */
 
+//命令行的各种输出方式需要写文件、退出进程, 只在os特性开启时编译
+//format、outjson、sql_escape只做格式转换, 作为库使用时(包括wasm)也可以使用
+#![cfg_attr(not(feature = "os"), allow(unused_imports))]
+
 use crate::Config;
 use std::collections::HashMap;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
 use crate::replication::readbinlog::Traction;
 use crate::replication::readevent::{TableMap};
+use crate::exitcode;
+use crate::rowfilter::RowFilter;
+use crate::sample::Sampler;
+use crate::sink::HeartbeatTracker;
+#[cfg(feature = "os")]
+use crate::sink::WatermarkTimer;
 
+pub mod sql_escape;
+pub mod dialect;
+pub mod format;
+pub mod outjson;
+pub mod outjsonschema;
+#[cfg(feature = "os")]
 pub mod outvalue;
+#[cfg(feature = "os")]
 pub mod outsql;
+#[cfg(feature = "os")]
+pub mod outcsv;
+#[cfg(feature = "os")]
+pub mod progress;
+#[cfg(feature = "os")]
+pub mod summary;
+#[cfg(feature = "os")]
+pub mod outaudit;
+#[cfg(feature = "os")]
+pub mod outhotspot;
+#[cfg(feature = "os")]
+pub mod outddl;
+#[cfg(feature = "os")]
+pub mod outdeps;
+#[cfg(feature = "os")]
+pub mod outgroupcommit;
+#[cfg(feature = "os")]
+pub mod watch;
+#[cfg(feature = "os")]
+pub mod outcompact;
+#[cfg(feature = "os")]
+pub mod outschema;
+#[cfg(feature = "os")]
+pub mod outretention;
+#[cfg(feature = "os")]
+pub mod outcreate;
+#[cfg(feature = "os")]
+pub mod outpretty;
+#[cfg(feature = "os")]
+pub mod outcount;
+#[cfg(feature = "os")]
+pub mod outtemplate;
+#[cfg(feature = "os")]
+pub mod outfence;
+
+//通过过滤条件输出的行数据及非BEGIN的query事件数量, 为0时以exitcode::NO_EVENTS退出
+#[cfg(feature = "os")]
+static MATCHED: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(feature = "os")]
+pub fn matched_count() -> u64 {
+    MATCHED.load(Ordering::Relaxed)
+}
+
+//行数据的输出格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RowFormat{
+    Pretty,
+    Text,
+    Json,
+    Csv
+}
+
+impl RowFormat{
+    pub fn new(format: &str) -> Result<RowFormat, String> {
+        match format {
+            "" | "pretty" => Ok(RowFormat::Pretty),
+            "text" => Ok(RowFormat::Text),
+            "json" => Ok(RowFormat::Json),
+            "csv" => Ok(RowFormat::Csv),
+            _ => Err(format!("无效的outformat: {}, 可选值为pretty、text、json、csv", format))
+        }
+    }
+}
+
+//输出过程中需要跨事件保存的状态
+#[cfg(feature = "os")]
+pub struct OutState{
+    pub insert_batch: outsql::InsertBatch,
+    pub no_key: crate::nokey::NoKeyPolicy,     //getsql时没有主键的表的处理方式
+    pub format: format::ValueFormat,
+    pub row_format: RowFormat,
+    pub gtid: String,               //当前事务的gtid
+    pub timestamp: u32,             //当前事件的时间
+    pub event_length: u32,          //当前事件的字节数
+    pub log_pos: u64,               //当前事件的position
+    pub audit: outaudit::AuditState,
+    pub hotspot: Option<outhotspot::HotspotReport>,
+    pub ddl: Option<outddl::DdlTimeline>,
+    pub deps: Option<outdeps::DependencyGraph>,
+    pub group_commit: Option<outgroupcommit::GroupCommitReport>,
+    pub watch: Option<watch::Watchdog>,
+    pub row_filter: Option<RowFilter>,
+    pub sampler: Option<Sampler>,
+    pub compact: Option<outcompact::Compactor>,
+    pub schema: Option<outschema::SchemaLog>,
+    pub create_table: Option<outcreate::CreateTableLog>,
+    pub heartbeat: Option<HeartbeatTracker>,
+    pub watermark: Option<WatermarkTimer>,
+    pub retention: Option<outretention::RetentionReport>,
+    pub boundary: outjson::Boundary,
+    pub pretty: outpretty::Pretty,
+    pub count: Option<outcount::Counter>,
+    pub template: Option<outtemplate::TemplateOut>,
+    pub fence: Option<outfence::DdlFence>,
+}
+
+#[cfg(feature = "os")]
+impl OutState{
+    pub fn new(conf: &Config) -> OutState {
+        let batch_size = outsql::batch_size(&conf.batchsize).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::USAGE);
+        });
+        let insert_mode = outsql::InsertMode::new(&conf.insertmode).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::USAGE);
+        });
+        let no_key = crate::nokey::NoKeyPolicy::new(&conf.nokey).and_then(|t| t.for_sql()).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::USAGE);
+        });
+        let watermark = crate::sink::parse_watermark(&conf.watermark, &conf.watermarkdelay).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::USAGE);
+        }).map(|(interval, delay)| WatermarkTimer::new(interval, delay));
+        let format = format::ValueFormat::new(conf).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::USAGE);
+        });
+        insert_mode.check(format.dialect).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::USAGE);
+        });
+        let row_format = RowFormat::new(&conf.outformat).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::USAGE);
+        });
+        let color = outpretty::ColorMode::new(&conf.color).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::USAGE);
+        });
+        let mut hotspot = None;
+        if conf.hotspot {
+            hotspot = Some(outhotspot::HotspotReport::new(conf).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            }));
+        }
+        let mut ddl = None;
+        if !conf.ddl.is_empty() {
+            ddl = Some(outddl::DdlTimeline::new(&conf.ddl).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::SINK);
+            }));
+        }
+        let mut deps = None;
+        if !conf.deps.is_empty() {
+            deps = Some(outdeps::DependencyGraph::new(&conf.deps, &conf.depsformat).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            }));
+        }
+        let mut group_commit = None;
+        if conf.groupcommit {
+            group_commit = Some(outgroupcommit::GroupCommitReport::new(conf).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            }));
+        }
+        let mut watch = None;
+        if !conf.watch.is_empty() {
+            watch = Some(watch::Watchdog::load(conf).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            }));
+        }
+        let mut row_filter = None;
+        if !conf.rowfilter.is_empty() {
+            row_filter = Some(RowFilter::new(&conf.rowfilter, format.clone()).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            }));
+        }
+        let mut compact = None;
+        if conf.compact {
+            compact = Some(outcompact::Compactor::new(conf).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            }));
+        }
+        let mut schema = None;
+        if !conf.schemaout.is_empty() {
+            schema = Some(outschema::SchemaLog::new(&conf.schemaout).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::SINK);
+            }));
+        }
+        let mut create_table = None;
+        if !conf.createtable.is_empty() {
+            create_table = Some(outcreate::CreateTableLog::new(&conf.createtable).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::SINK);
+            }));
+        }
+        let mut count = None;
+        if conf.count {
+            count = Some(outcount::Counter::new(&conf.countby).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            }));
+        }
+        let mut template = None;
+        if !conf.template.is_empty() {
+            template = Some(outtemplate::TemplateOut::new(&conf.template).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            }));
+        }
+        let mut fence = None;
+        if !conf.ddlfence.is_empty() {
+            fence = Some(outfence::DdlFence::new(&conf.ddlfence, format.dialect).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            }));
+        }
+        let mut sampler = None;
+        if !conf.sample.is_empty() {
+            sampler = Some(Sampler::parse(&conf.sample).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            }));
+        }
+        OutState{
+            insert_batch: outsql::InsertBatch::new(batch_size, insert_mode),
+            no_key,
+            format,
+            row_format,
+            gtid: String::from(""),
+            timestamp: 0,
+            event_length: 0,
+            log_pos: 0,
+            audit: outaudit::AuditState::default(),
+            hotspot,
+            ddl,
+            deps,
+            group_commit,
+            watch,
+            row_filter,
+            sampler,
+            compact,
+            schema,
+            create_table,
+            heartbeat: if conf.heartbeat.is_empty() { None } else { Some(HeartbeatTracker::new()) },
+            watermark,
+            retention: if conf.retention { Some(outretention::RetentionReport::new(conf)) } else { None },
+            boundary: outjson::Boundary::default(),
+            pretty: outpretty::Pretty::new(color, conf.verbose),
+            count,
+            template,
+            fence,
+        }
+    }
+
+    //数据读取结束时输出还未打印的内容
+    pub fn finish(&mut self) {
+        self.insert_batch.flush();
+        if let Some(count) = &self.count {
+            count.print();
+        }
+        if let Some(compact) = self.compact.as_mut() {
+            compact.flush();
+        }
+        if let Some(report) = &self.hotspot {
+            report.print();
+        }
+        if let Some(report) = &self.group_commit {
+            report.print();
+        }
+        crate::colstats::finish();
+        #[cfg(feature = "network")]
+        if let Some(report) = &self.retention {
+            report.print();
+        }
+        if let Some(ddl) = self.ddl.as_mut() {
+            ddl.finish();
+        }
+        if let Some(schema) = self.schema.as_mut() {
+            schema.finish().unwrap_or_else(|err|{
+                println!("写入文件({})发生错误:{}", schema.path(), err);
+                process::exit(exitcode::SINK);
+            });
+        }
+        if let Some(create_table) = self.create_table.as_mut() {
+            create_table.finish().unwrap_or_else(|err|{
+                println!("写入文件({})发生错误:{}", create_table.path(), err);
+                process::exit(exitcode::SINK);
+            });
+        }
+        if let Some(deps) = &self.deps {
+            deps.finish().unwrap_or_else(|err|{
+                println!("写入文件({})发生错误:{}", deps.path(), err);
+                process::exit(exitcode::SINK);
+            });
+        }
+    }
+}
 
 //打印输出，打印sql、统计信息、 数据
-pub fn format_out(data: &Traction, conf: &Config, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,db_tbl: &String, map: &TableMap) {
-    if conf.statisc {
+#[cfg(feature = "os")]
+pub fn format_out(data: &Traction, conf: &Config, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,db_tbl: &String, map: &TableMap, out_state: &mut OutState) {
+    let _timer = crate::profile::start(crate::profile::Stage::Sink);
+    //监控危险操作, 与输出方式、过滤条件无关
+    if let Some(watch) = out_state.watch.as_mut() {
+        watch.record(data, db_tbl, out_state.timestamp, out_state.log_pos);
+    }
+    if let Some(tracker) = out_state.heartbeat.as_mut() {
+        let next_position = if out_state.log_pos == 0 { 0 } else { out_state.log_pos + out_state.event_length as u64 };
+        tracker.observe(data, out_state.timestamp, next_position);
+    }
+    if let Some(timer) = out_state.watermark.as_mut() {
+        timer.observe(data, out_state.timestamp, 0);
+    }
+    //写入速度, 读取结束时与mysql的保留设置一起输出
+    if let Some(report) = out_state.retention.as_mut() {
+        report.record(data, out_state.timestamp, out_state.log_pos, out_state.event_length);
+    }
+    //--column-stats: 按表统计行数、NULL比例、值大小, 在过滤之前统计
+    if matches!(data, Traction::RowEvent(..)) && crate::colstats::is_enabled() {
+        match table_cols_info.get(db_tbl) {
+            Some(cols) => crate::colstats::record(data, cols, db_tbl, out_state.timestamp),
+            None => crate::colstats::record(data, &crate::sink::default_columns(map), db_tbl, out_state.timestamp),
+        }
+    }
+    //表结构变化时输出表结构文档, 与输出方式、过滤条件无关
+    if let Some(schema) = out_state.schema.as_mut() {
+        schema.record(data, table_cols_info, db_tbl, out_state.log_pos, out_state.timestamp).unwrap_or_else(|err|{
+            println!("写入文件({})发生错误:{}", conf.schemaout, err);
+            process::exit(exitcode::SINK);
+        });
+    }
+    if let Some(create_table) = out_state.create_table.as_mut() {
+        create_table.record(data, out_state.log_pos, out_state.timestamp).unwrap_or_else(|err|{
+            println!("写入文件({})发生错误:{}", conf.createtable, err);
+            process::exit(exitcode::SINK);
+        });
+    }
+    //按字段值过滤、按表抽样行数据, 没有剩余的行时不输出该事件
+    let filtered;
+    let sampled;
+    let data = if (out_state.row_filter.is_some() || out_state.sampler.is_some()) && matches!(data, Traction::RowEvent(..) | Traction::QueryEvent(_)) {
+        let default_cols;
+        let cols = match table_cols_info.get(db_tbl) {
+            Some(t) => t,
+            None => {
+                default_cols = crate::sink::default_columns(map);
+                &default_cols
+            }
+        };
+        let mut data = data;
+        if let Some(filter) = out_state.row_filter.as_mut() {
+            filtered = filter.filter(data, db_tbl, cols).unwrap_or_else(|err|{
+                println!("{}", err);
+                process::exit(exitcode::USAGE);
+            });
+            match &filtered {
+                Some(t) => data = t.as_ref(),
+                None => return
+            }
+        }
+        if let Some(sampler) = out_state.sampler.as_mut() {
+            sampled = sampler.sample(data, db_tbl, cols);
+            match &sampled {
+                Some(t) => data = t.as_ref(),
+                None => return
+            }
+        }
+        data
+    } else {
+        data
+    };
+    //nonfinite=error时行数据中有NaN、Infinity, zerodate=error时有零值日期停止解析
+    if let Traction::RowEvent(_, rows) = data {
+        out_state.format.check_finite(rows.rows(), db_tbl)
+            .and_then(|_| out_state.format.check_temporal(rows.rows(), db_tbl)).unwrap_or_else(|err|{
+            println!("{}", err);
+            process::exit(exitcode::PARSE);
+        });
+    }
+    summary::record_data(data, db_tbl);
+    match data {
+        Traction::RowEvent(..) | Traction::RowEventStatic{..} => { MATCHED.fetch_add(1, Ordering::Relaxed); }
+        Traction::QueryEvent(t) if t.command != "BEGIN" => { MATCHED.fetch_add(1, Ordering::Relaxed); }
+        _ => {}
+    }
+    if let Some(count) = out_state.count.as_mut() {
+        //只统计行数, 结束时输出
+        count.record(data, db_tbl);
+    } else if !conf.ddl.is_empty() {
+        //只提取表结构变更语句
+        if let Some(ddl) = out_state.ddl.as_mut() {
+            ddl.record(data, out_state.timestamp).unwrap_or_else(|err|{
+                println!("写入文件({})发生错误:{}", conf.ddl, err);
+                process::exit(exitcode::SINK);
+            });
+        }
+    } else if !conf.deps.is_empty() {
+        //事务依赖关系, 读取结束时输出
+        if let Some(deps) = out_state.deps.as_mut() {
+            deps.record(data);
+        }
+    } else if conf.groupcommit {
+        //组提交的大小及间隔, 读取结束时输出
+        if let Some(report) = out_state.group_commit.as_mut() {
+            report.record(data, out_state.timestamp);
+        }
+    } else if conf.hotspot {
+        //按时间段统计每个表的写入量及热点主键, 读取结束时输出
+        if let Some(report) = out_state.hotspot.as_mut() {
+            report.record(data, table_cols_info, db_tbl, out_state.timestamp, out_state.event_length, &out_state.format);
+        }
+    } else if conf.statisc {
         //统计每个事务大小, 仅读取binlog文件可用，如果通过replication协议拉过来统计意义不大
-        outvalue::out_value(data, table_cols_info,db_tbl);
+        outvalue::out_value(data, table_cols_info,db_tbl, &out_state.format);
+    } else if conf.compact {
+        //同一主键的多次修改合并为最终数据, 按窗口或读取结束时输出
+        if let Some(compact) = out_state.compact.as_mut() {
+            compact.record(data, table_cols_info, db_tbl, map, &out_state.gtid, &out_state.format);
+        }
+    } else if conf.audit {
+        //审计模式: 记录每行数据的修改者、时间及原始sql
+        outaudit::out_audit(data, conf, table_cols_info, db_tbl, map, out_state);
     } else if conf.getsql {
         //提取sql语句
-        outvalue::out_sql(data, table_cols_info,db_tbl, map)
+        outvalue::out_sql(data, table_cols_info,db_tbl, map, out_state)
+    } else if out_state.template.is_some() {
+        //按用户提供的模板输出
+        outtemplate::out_template(data, table_cols_info, db_tbl, map, out_state)
     }
     else {
         //默认直接打印数据
-        outvalue::out_value(data, table_cols_info,db_tbl);
+        let _timer = crate::profile::start(crate::profile::Stage::Serialize);
+        match out_state.row_format {
+            RowFormat::Pretty => outpretty::out_pretty(data, table_cols_info, db_tbl, map, out_state),
+            RowFormat::Text => outvalue::out_value(data, table_cols_info,db_tbl, &out_state.format),
+            RowFormat::Json => outjson::out_json(data, table_cols_info, db_tbl, map, out_state),
+            RowFormat::Csv => outcsv::out_csv(data, table_cols_info, db_tbl, map, out_state),
+        }
+    }
+}
+
+//--watermark: 按间隔在事务之间输出事件时间的水位, 只在json格式时输出
+#[cfg(feature = "os")]
+pub fn out_watermark(out_state: &mut OutState) {
+    if out_state.row_format != RowFormat::Json {
+        return;
+    }
+    if let Some(watermark) = out_state.watermark.as_mut().and_then(|t| t.due()) {
+        let mut value = watermark.to_json();
+        out_state.format.labels.attach(&mut value);
+        println!("{}", value);
+    }
+}
+
+//--heartbeat: 收到mysql的心跳事件时输出当前位置、已完成事务的gtid及时间, 只在json格式时输出
+#[cfg(feature = "os")]
+pub fn out_heartbeat(out_state: &mut OutState) {
+    if out_state.row_format != RowFormat::Json {
+        return;
+    }
+    if let Some(tracker) = &out_state.heartbeat {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|t| t.as_millis() as u64).unwrap_or(0);
+        let mut value = tracker.heartbeat(now).to_json();
+        out_state.format.labels.attach(&mut value);
+        println!("{}", value);
     }
-}
\ No newline at end of file
+}
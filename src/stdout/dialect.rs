@@ -0,0 +1,144 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//getsql生成的sql语句的方言, 用于把变更应用到mysql以外的目标库
+//  mysql: 反引号标识符, 字符串按mysql_real_escape_string转义, x''二进制, REPLACE/ON DUPLICATE KEY UPDATE
+//  ansi: 双引号标识符, 字符串只转义单引号, X''二进制, DATE/TIME/TIMESTAMP字面值, tinyint(1)为TRUE/FALSE, 不支持replace、upsert
+//  sqlite: 与ansi相同的引号, 表名不带库名(没有attach时只有main库), 时间为字符串, 支持INSERT OR REPLACE及ON CONFLICT
+//  postgresql: 库名作为schema, '\x..'::bytea二进制, 带时区的TIMESTAMP, tinyint(1)为TRUE/FALSE, 不支持replace, upsert为ON CONFLICT
+//  DDL、语句格式的binlog中的原始sql按mysql语法原样输出, 需要另外转换
+
+use crate::stdout::sql_escape;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect{
+    Mysql,
+    Ansi,
+    Sqlite,
+    Postgresql,
+}
+
+//时间类型的字面值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Temporal{
+    Date,
+    Time,
+    DateTime,
+}
+
+impl Dialect{
+    pub fn new(dialect: &str) -> Result<Dialect, String> {
+        match dialect {
+            "" | "mysql" => Ok(Dialect::Mysql),
+            "ansi" => Ok(Dialect::Ansi),
+            "sqlite" => Ok(Dialect::Sqlite),
+            "postgresql" | "postgres" | "pg" => Ok(Dialect::Postgresql),
+            _ => Err(format!("无效的dialect: {}, 可选值为mysql、ansi、sqlite、postgresql", dialect))
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dialect::Mysql => "mysql",
+            Dialect::Ansi => "ansi",
+            Dialect::Sqlite => "sqlite",
+            Dialect::Postgresql => "postgresql",
+        }
+    }
+
+    pub fn quote_identifier(&self, name: &str) -> String {
+        match self {
+            Dialect::Mysql => sql_escape::quote_identifier(name),
+            _ => format!("\"{}\"", name.replace('"', "\"\"")),
+        }
+    }
+
+    pub fn quote_table(&self, database: &str, table: &str) -> String {
+        match self {
+            Dialect::Sqlite => self.quote_identifier(table),
+            _ => format!("{}.{}", self.quote_identifier(database), self.quote_identifier(table)),
+        }
+    }
+
+    //标准sql的字符串中反斜杠没有特殊含义, 只需要把单引号写两次
+    pub fn quote_string(&self, value: &str) -> String {
+        match self {
+            Dialect::Mysql => sql_escape::quote_string(value),
+            _ => format!("'{}'", value.replace('\'', "''")),
+        }
+    }
+
+    pub fn quote_bytes(&self, value: &[u8]) -> String {
+        match self {
+            Dialect::Mysql => sql_escape::quote_bytes(value),
+            Dialect::Postgresql => format!("'\\x{}'::bytea", hex::encode(value)),
+            _ => format!("X'{}'", hex::encode(value)),
+        }
+    }
+
+    //text/char类型列的数据, 不是合法utf8时按二进制输出
+    pub fn quote_text(&self, value: &[u8]) -> String {
+        match std::str::from_utf8(value) {
+            Ok(t) => self.quote_string(t),
+            Err(_) => {
+                crate::warning::emit(crate::warning::WarningKind::CharsetFallback, format!("字符串的值不是合法的utf8({} bytes), 按十六进制字面值输出", value.len()));
+                self.quote_bytes(value)
+            }
+        }
+    }
+
+    //value为不带引号的时间
+    pub fn temporal(&self, kind: Temporal, value: &str) -> String {
+        let keyword = match kind {
+            Temporal::Date => "DATE",
+            Temporal::Time => "TIME",
+            Temporal::DateTime => "TIMESTAMP",
+        };
+        match self {
+            Dialect::Mysql | Dialect::Sqlite => format!("'{}'", value),
+            _ => format!("{} '{}'", keyword, value),
+        }
+    }
+
+    //epoch模式下TIMESTAMP类型的值, epoch为带小数部分的时间戳(见format::epoch), 非mysql按utc时间输出
+    pub fn timestamp(&self, epoch: &str, utc: &str) -> String {
+        match self {
+            Dialect::Mysql if epoch.contains('.') => format!("from_unixtime({})", epoch),
+            Dialect::Mysql => format!("from_unixtime({}.0)", epoch),
+            Dialect::Sqlite => format!("'{}'", utc),
+            _ => format!("TIMESTAMP WITH TIME ZONE '{}+00:00'", utc),
+        }
+    }
+
+    //mysql的bool为tinyint(1), 其他库一般为boolean类型
+    pub fn boolean(&self, col_type: &str, value: i64) -> Option<&'static str> {
+        match self {
+            Dialect::Ansi | Dialect::Postgresql if col_type == "tinyint(1)" && (value == 0 || value == 1) => {
+                Some(if value == 1 { "TRUE" } else { "FALSE" })
+            }
+            _ => None,
+        }
+    }
+
+    //空间类型, srid不为0时按经度、纬度的顺序
+    pub fn geometry(&self, wkt: &str, srid: u32) -> String {
+        match (self, srid) {
+            (Dialect::Sqlite, _) => self.quote_string(wkt),
+            (_, 0) => format!("ST_GeomFromText('{}')", wkt),
+            (Dialect::Mysql, _) => format!("ST_GeomFromText('{}', {}, 'axis-order=long-lat')", wkt, srid),
+            _ => format!("ST_GeomFromText('{}', {})", wkt, srid),
+        }
+    }
+
+    //切换默认库的语句, sqlite没有对应的语句
+    pub fn use_database(&self, database: &str) -> Option<String> {
+        match self {
+            Dialect::Mysql => Some(format!("use {};", self.quote_identifier(database))),
+            Dialect::Ansi => Some(format!("SET SCHEMA {};", self.quote_string(database))),
+            Dialect::Postgresql => Some(format!("SET search_path TO {};", self.quote_identifier(database))),
+            Dialect::Sqlite => None,
+        }
+    }
+}
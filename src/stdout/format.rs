@@ -0,0 +1,663 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//字段值输出格式的配置, 每个输出各自持有一份, 互不影响
+
+use crate::Config;
+use chrono::TimeZone;
+use chrono_tz::Tz;
+use bigdecimal::BigDecimal;
+use sha2::{Digest, Sha256};
+use std::str::from_utf8;
+use std::collections::HashMap;
+use crate::replication::parsevalue::MySQLValue;
+use crate::spatial::{self, SpatialFormat, SpatialValue};
+use crate::labels::Labels;
+use crate::stdout::dialect::{Dialect, Temporal};
+use serde_json::Value as JsonValue;
+use crate::warning::{self, WarningKind};
+
+//TIMESTAMP类型在binlog中记录的是utc时间戳
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeZoneMode{
+    Epoch,          //保持时间戳输出, from_unixtime(...)
+    Named(Tz)       //转换为指定时区的时间
+}
+
+impl TimeZoneMode{
+    pub fn new(timezone: &str) -> Result<TimeZoneMode, String> {
+        match timezone {
+            "" | "epoch" => Ok(TimeZoneMode::Epoch),
+            _ => match timezone.parse::<Tz>() {
+                Ok(tz) => Ok(TimeZoneMode::Named(tz)),
+                Err(_) => Err(format!("无效的timezone: {}, 可配置为epoch或时区名称, 如UTC、Asia/Shanghai", timezone))
+            }
+        }
+    }
+}
+
+//日期时间类型的输出格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TemporalStyle{
+    Mysql,          //2019-10-14 10:00:00.5
+    Iso8601         //2019-10-14T10:00:00.5, 转换时区后的TIMESTAMP带时区偏移
+}
+
+impl TemporalStyle{
+    pub fn new(style: &str) -> Result<TemporalStyle, String> {
+        match style {
+            "" | "mysql" => Ok(TemporalStyle::Mysql),
+            "iso8601" => Ok(TemporalStyle::Iso8601),
+            _ => Err(format!("无效的timeformat: {}, 可选值为mysql、iso8601", style))
+        }
+    }
+}
+
+//BLOB/TEXT类型超过指定大小时的输出方式, 避免大字段撑爆输出
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LobMode{
+    Full,               //完整输出
+    Truncate(usize),    //只输出前N个字节, 并标记原始长度
+    Hash(usize)         //超过N个字节时输出sha256值及原始长度
+}
+
+impl LobMode{
+    pub fn new(mode: &str, max_size: &str) -> Result<LobMode, String> {
+        let mut size = 0;
+        if !max_size.is_empty() {
+            match max_size.parse() {
+                Ok(t) => size = t,
+                Err(_) => return Err(format!("无效的lobmaxsize: {}", max_size))
+            }
+        }
+        match mode {
+            "" | "truncate" => {
+                if max_size.is_empty() {
+                    Ok(LobMode::Full)
+                }else {
+                    Ok(LobMode::Truncate(size))
+                }
+            }
+            "hash" => Ok(LobMode::Hash(size)),
+            _ => Err(format!("无效的lobmode: {}, 可选值为truncate、hash", mode))
+        }
+    }
+}
+
+//二进制类型(binary、varbinary、blob、bit)的编码方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryEncoding{
+    Hex,            //0x36333633
+    Base64,         //NjM2Mw==
+    Escaped         //可打印字符原样输出, 其余字节转义为\xNN
+}
+
+impl BinaryEncoding{
+    pub fn new(encoding: &str) -> Result<BinaryEncoding, String> {
+        match encoding {
+            "" | "hex" => Ok(BinaryEncoding::Hex),
+            "base64" => Ok(BinaryEncoding::Base64),
+            "escaped" => Ok(BinaryEncoding::Escaped),
+            _ => Err(format!("无效的binaryencoding: {}, 可选值为hex、base64、escaped", encoding))
+        }
+    }
+
+    pub fn encode(&self, value: &[u8]) -> String {
+        match self {
+            BinaryEncoding::Hex => format!("0x{}", hex::encode(value)),
+            BinaryEncoding::Base64 => base64::encode(value),
+            BinaryEncoding::Escaped => {
+                let mut escaped = String::with_capacity(value.len());
+                for b in value {
+                    match b {
+                        b'\\' => escaped.push_str("\\\\"),
+                        0x20..=0x7e => escaped.push(*b as char),
+                        _ => escaped.push_str(&format!("\\x{:02x}", b)),
+                    }
+                }
+                escaped
+            }
+        }
+    }
+}
+
+//float/double的NaN、Infinity(mysql不允许写入, 可能来自其他来源或损坏的binlog), json、csv等格式无法直接表示
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NonFinite{
+    Null,           //输出为null(默认)
+    Text,           //输出为字符串NaN、Infinity、-Infinity
+    Error           //停止解析
+}
+
+impl NonFinite{
+    pub fn new(mode: &str) -> Result<NonFinite, String> {
+        match mode {
+            "" | "null" => Ok(NonFinite::Null),
+            "string" => Ok(NonFinite::Text),
+            "error" => Ok(NonFinite::Error),
+            _ => Err(format!("无效的nonfinite: {}, 可选值为null、string、error", mode))
+        }
+    }
+}
+
+//DATE、DATETIME、TIMESTAMP中的零值及无效日期(0000-00-00、月或日为0、2019-02-30等, sql_mode允许时可以写入),
+//各个下游系统的处理方式不同, json、csv、sql输出按同一方式处理
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZeroDate{
+    Literal,        //按mysql的格式原样输出(默认), 如0000-00-00 00:00:00
+    Null,           //输出为null
+    Error           //停止解析
+}
+
+impl ZeroDate{
+    pub fn new(mode: &str) -> Result<ZeroDate, String> {
+        match mode {
+            "" | "literal" => Ok(ZeroDate::Literal),
+            "null" => Ok(ZeroDate::Null),
+            "error" => Ok(ZeroDate::Error),
+            _ => Err(format!("无效的zerodate: {}, 可选值为literal、null、error", mode))
+        }
+    }
+}
+
+/// 是否为零值或无效的日期时间, TIMESTAMP的零值在binlog中为0
+pub fn is_invalid_temporal(value: &MySQLValue) -> bool {
+    match value {
+        MySQLValue::Date {year, month, day} |
+        MySQLValue::DateTime {year, month, day, ..} => chrono::NaiveDate::from_ymd_opt(*year as i32, *month, *day).is_none(),
+        MySQLValue::Timestamp {unix_time, subsecond} => *unix_time == 0 && *subsecond == 0,
+        _ => false
+    }
+}
+
+//NaN、Infinity的文本
+fn non_finite_str(value: f64) -> &'static str {
+    if value.is_nan() {
+        "NaN"
+    }else if value > 0.0 {
+        "Infinity"
+    }else {
+        "-Infinity"
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ValueFormat{
+    pub timezone: TimeZoneMode,
+    pub temporal: TemporalStyle,
+    pub float_precision: Option<usize>,     //float/double保留的小数位数, None时按最短表示输出
+    pub nonfinite: NonFinite,               //float/double为NaN、Infinity时的输出方式
+    pub zerodate: ZeroDate,                 //零值及无效日期时间的输出方式
+    pub quote_decimal: bool,                //decimal是否以字符串输出
+    pub lob: LobMode,
+    pub binary_encoding: BinaryEncoding,
+    pub binary_columns: HashMap<String, BinaryEncoding>,     //db.tbl.col单独配置的编码方式
+    pub include_columns: HashMap<String, Vec<String>>,       //db.tbl(或db.*、*)只输出的字段
+    pub exclude_columns: HashMap<String, Vec<String>>,       //db.tbl(或db.*、*)不输出的字段
+    pub row_hash: bool,                     //json输出时每行带校验和, 见rowhash
+    pub spatial: SpatialFormat,             //空间类型的输出格式, 见spatial
+    pub labels: Labels,                     //json输出的每条记录带的静态标签, 见labels
+    pub boundaries: bool,                   //json输出时事务的行数据前后带begin、commit记录, 见outjson::Boundary
+    pub dialect: Dialect,                   //getsql生成的sql语句的方言, 见dialect
+}
+
+//不做任何转换的默认输出格式
+impl Default for ValueFormat{
+    fn default() -> ValueFormat {
+        ValueFormat{
+            timezone: TimeZoneMode::Epoch,
+            temporal: TemporalStyle::Mysql,
+            float_precision: None,
+            nonfinite: NonFinite::Null,
+            zerodate: ZeroDate::Literal,
+            quote_decimal: false,
+            lob: LobMode::Full,
+            binary_encoding: BinaryEncoding::Hex,
+            binary_columns: HashMap::new(),
+            include_columns: HashMap::new(),
+            exclude_columns: HashMap::new(),
+            row_hash: false,
+            spatial: SpatialFormat::Wkt,
+            labels: Labels::default(),
+            boundaries: false,
+            dialect: Dialect::Mysql
+        }
+    }
+}
+
+impl ValueFormat{
+    pub fn new(conf: &Config) -> Result<ValueFormat, String> {
+        let mut float_precision = None;
+        if !conf.floatprecision.is_empty() {
+            match conf.floatprecision.parse() {
+                Ok(t) => float_precision = Some(t),
+                Err(_) => return Err(format!("无效的floatprecision: {}", conf.floatprecision))
+            }
+        }
+        let mut binary_columns = HashMap::new();
+        if !conf.binarycolumns.is_empty() {
+            let columns: HashMap<String, String> = match serde_json::from_str(&conf.binarycolumns) {
+                Ok(t) => t,
+                Err(e) => return Err(format!("binarycolumns格式错误: {}", e))
+            };
+            for (col, encoding) in columns {
+                binary_columns.insert(col, BinaryEncoding::new(&encoding)?);
+            }
+        }
+        Ok(ValueFormat{
+            timezone: TimeZoneMode::new(&conf.timezone)?,
+            temporal: TemporalStyle::new(&conf.timeformat)?,
+            float_precision,
+            nonfinite: NonFinite::new(&conf.nonfinite)?,
+            zerodate: ZeroDate::new(&conf.zerodate)?,
+            quote_decimal: conf.quotedecimal,
+            lob: LobMode::new(&conf.lobmode, &conf.lobmaxsize)?,
+            binary_encoding: BinaryEncoding::new(&conf.binaryencoding)?,
+            binary_columns,
+            include_columns: column_list(&conf.includecolumns, "includecolumns")?,
+            exclude_columns: column_list(&conf.excludecolumns, "excludecolumns")?,
+            row_hash: conf.rowhash,
+            spatial: SpatialFormat::new(&conf.spatialformat)?,
+            labels: Labels::parse(&conf.labels)?,
+            boundaries: conf.boundaries,
+            dialect: Dialect::new(&conf.sqldialect)?
+        })
+    }
+
+    fn date_time_sep(&self) -> char {
+        match self.temporal {
+            TemporalStyle::Mysql => ' ',
+            TemporalStyle::Iso8601 => 'T'
+        }
+    }
+
+    //以下返回的时间均不带引号
+    pub fn date(&self, year: u32, month: u32, day: u32) -> String {
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+
+    //fsp为字段的小数位数(见fsp), 小数部分按fsp补齐前导0
+    pub fn time(&self, negative: bool, hours: u32, minutes: u32, seconds: u32, subseconds: u32, fsp: usize) -> String {
+        format!("{}{:02}:{:02}:{:02}{}", if negative { "-" } else { "" }, hours, minutes, seconds, subsecond_str(subseconds, fsp))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn datetime(&self, year: u32, month: u32, day: u32, hour: u32, minute: u32, second: u32, subsecond: u32, fsp: usize) -> String {
+        format!("{}{}{}", self.date(year, month, day), self.date_time_sep(), self.time(false, hour, minute, second, subsecond, fsp))
+    }
+
+    //epoch模式返回None, 由调用方决定如何输出时间戳(见epoch); 零值不按时区转换, 输出为0000-00-00 00:00:00
+    pub fn timestamp(&self, unix_time: i32, subsecond: u32, fsp: usize) -> Option<String> {
+        match self.timezone {
+            TimeZoneMode::Named(_) if unix_time == 0 && subsecond == 0 => Some(self.datetime(0, 0, 0, 0, 0, 0, 0, 0)),
+            TimeZoneMode::Epoch => None,
+            TimeZoneMode::Named(tz) => {
+                let time = tz.timestamp_opt(i64::from(unix_time), 0).single()?;
+                let value = format!("{}{}", time.format("%Y-%m-%d"), self.date_time_sep());
+                match self.temporal {
+                    TemporalStyle::Mysql => Some(format!("{}{}{}", value, time.format("%H:%M:%S"), subsecond_str(subsecond, fsp))),
+                    TemporalStyle::Iso8601 => Some(format!("{}{}{}{}", value, time.format("%H:%M:%S"), subsecond_str(subsecond, fsp), time.format("%:z")))
+                }
+            }
+        }
+    }
+
+    //NaN、Infinity输出为NaN、Infinity、-Infinity, 由调用方按nonfinite处理
+    pub fn float(&self, value: f64) -> String {
+        if !value.is_finite() {
+            return non_finite_str(value).to_string();
+        }
+        match self.float_precision {
+            Some(p) => format!("{:.*}", p, value),
+            None => format!("{}", value)
+        }
+    }
+
+    pub fn float32(&self, value: f32) -> String {
+        if !value.is_finite() {
+            return non_finite_str(value as f64).to_string();
+        }
+        match self.float_precision {
+            Some(p) => format!("{:.*}", p, value),
+            None => format!("{}", value)
+        }
+    }
+
+    //sql语句中使用的float/double值, NaN、Infinity按nonfinite输出为NULL或字符串
+    pub fn float_sql(&self, value: &MySQLValue) -> String {
+        let (text, finite) = match value {
+            MySQLValue::Float(t) => (self.float32(*t), t.is_finite()),
+            MySQLValue::Double(t) => (self.float(*t), t.is_finite()),
+            _ => return String::from("NULL")
+        };
+        match (finite, self.nonfinite) {
+            (true, _) => text,
+            (false, NonFinite::Text) => format!("'{}'", text),
+            (false, _) => String::from("NULL")
+        }
+    }
+
+    //float/double的json值, NaN、Infinity按nonfinite输出为null或字符串
+    fn json_float(&self, text: String, finite: bool) -> JsonValue {
+        match (finite, self.nonfinite) {
+            (true, _) => json_float(&text),
+            (false, NonFinite::Text) => JsonValue::from(text),
+            (false, _) => JsonValue::Null
+        }
+    }
+
+    /// nonfinite=error时检查行数据中是否有NaN、Infinity, 有时返回错误信息
+    pub fn check_finite(&self, rows: &[Vec<Option<MySQLValue>>], db_tbl: &str) -> Result<(), String> {
+        if self.nonfinite != NonFinite::Error {
+            return Ok(());
+        }
+        for row in rows {
+            for (idx, value) in row.iter().enumerate() {
+                let text = match value {
+                    Some(MySQLValue::Float(t)) if !t.is_finite() => self.float32(*t),
+                    Some(MySQLValue::Double(t)) if !t.is_finite() => self.float(*t),
+                    _ => continue
+                };
+                return Err(format!("{}第{}个字段的值为{}, 可以通过--nonfinite null或string继续输出", db_tbl, idx + 1, text));
+            }
+        }
+        Ok(())
+    }
+
+    /// zerodate=error时检查行数据中是否有零值或无效的日期时间, 有时返回错误信息
+    pub fn check_temporal(&self, rows: &[Vec<Option<MySQLValue>>], db_tbl: &str) -> Result<(), String> {
+        if self.zerodate != ZeroDate::Error {
+            return Ok(());
+        }
+        for row in rows {
+            for (idx, value) in row.iter().enumerate() {
+                if let Some(t) = value.as_ref().filter(|t| is_invalid_temporal(t)) {
+                    let text = match t {
+                        MySQLValue::Date {year, month, day} => self.date(*year, *month, *day),
+                        MySQLValue::DateTime {year, month, day, hour, minute, second, subsecond} => self.datetime(*year, *month, *day, *hour, *minute, *second, *subsecond, 0),
+                        _ => self.datetime(0, 0, 0, 0, 0, 0, 0, 0)
+                    };
+                    return Err(format!("{}第{}个字段的值为{}, 可以通过--zerodate literal或null继续输出", db_tbl, idx + 1, text));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //zerodate=null时零值及无效的日期时间按null输出
+    pub fn null_temporal(&self, value: &MySQLValue) -> bool {
+        self.zerodate == ZeroDate::Null && is_invalid_temporal(value)
+    }
+
+    //decimal按精确值输出, quote_decimal时带引号, 避免下游解析为浮点数丢失精度
+    pub fn decimal(&self, value: &BigDecimal) -> String {
+        if self.quote_decimal {
+            format!("'{}'", value)
+        }else {
+            format!("{}", value)
+        }
+    }
+
+    //某个字段使用的二进制编码方式
+    pub fn binary_encoding(&self, db_tbl: &str, col: &str) -> BinaryEncoding {
+        if self.binary_columns.is_empty() {
+            return self.binary_encoding;
+        }
+        match self.binary_columns.get(&format!("{}.{}", db_tbl, col)) {
+            Some(t) => *t,
+            None => self.binary_encoding
+        }
+    }
+
+    //字段是否输出: 先按includecolumns只保留列出的字段, 再去掉excludecolumns中的字段
+    //表按db.tbl、db.*、*的顺序查找, 使用第一个匹配的配置
+    pub fn keep_column(&self, db_tbl: &str, col: &str) -> bool {
+        if self.include_columns.is_empty() && self.exclude_columns.is_empty() {
+            return true;
+        }
+        if let Some(cols) = table_columns(&self.include_columns, db_tbl) {
+            if !cols.iter().any(|c| c == col) {
+                return false;
+            }
+        }
+        match table_columns(&self.exclude_columns, db_tbl) {
+            Some(cols) => !cols.iter().any(|c| c == col),
+            None => true
+        }
+    }
+
+    //字符串及二进制类型的值, encoding为None时按字符串输出, 否则按encoding编码输出
+    //BLOB/TEXT类型超过lobmaxsize时按lobmode截断或替换为hash值
+    pub fn bytes(&self, value: &[u8], col_type: &str, encoding: Option<BinaryEncoding>) -> String {
+        if col_type.contains("text") || col_type.contains("blob") {
+            return self.lob(value, encoding);
+        }
+        bytes_str(value, encoding)
+    }
+
+    fn lob(&self, value: &[u8], encoding: Option<BinaryEncoding>) -> String {
+        match self.lob {
+            LobMode::Truncate(size) if value.len() > size => {
+                let mut prefix = &value[..size];
+                if encoding.is_none() {
+                    //不在多字节字符中间截断
+                    if let Err(e) = from_utf8(prefix) {
+                        if e.error_len().is_none() {
+                            prefix = &prefix[..e.valid_up_to()];
+                        }
+                    }
+                }
+                warning::emit(WarningKind::TruncatedBlob, format!("BLOB/TEXT的值({} bytes)超过lobmaxsize({}), 截断后输出", value.len(), size));
+                format!("{}...[truncated, {} bytes]", bytes_str(prefix, encoding), value.len())
+            }
+            LobMode::Hash(size) if value.len() > size => {
+                warning::emit(WarningKind::TruncatedBlob, format!("BLOB/TEXT的值({} bytes)超过lobmaxsize({}), 按sha256输出", value.len(), size));
+                let mut hasher = Sha256::default();
+                hasher.input(value);
+                format!("sha256:{} [{} bytes]", hex::encode(hasher.result()), value.len())
+            }
+            _ => bytes_str(value, encoding)
+        }
+    }
+
+    //json格式输出时字段的值
+    pub fn json_value(&self, value: &Option<MySQLValue>, col_type: &str, encoding: BinaryEncoding) -> JsonValue {
+        match value {
+            Some(t) if self.null_temporal(t) => JsonValue::Null,
+            Some(MySQLValue::SignedInteger(t)) => JsonValue::from(*t),
+            Some(MySQLValue::Enum(t)) => JsonValue::from(*t),
+            Some(MySQLValue::Year(t)) => JsonValue::from(*t),
+            Some(MySQLValue::Float(t)) => self.json_float(self.float32(*t), t.is_finite()),
+            Some(MySQLValue::Double(t)) => self.json_float(self.float(*t), t.is_finite()),
+            Some(MySQLValue::Decimal(t)) => {
+                if self.quote_decimal {
+                    JsonValue::from(t.to_string())
+                }else {
+                    json_float(&t.to_string())
+                }
+            }
+            Some(MySQLValue::String(t)) => JsonValue::from(t.clone()),
+            Some(MySQLValue::Blob(t)) => {
+                if let Some(value) = self.spatial(t, col_type) {
+                    value.to_json(self.spatial)
+                }else if is_text_type(col_type) {
+                    JsonValue::from(self.bytes(t, col_type, None))
+                }else {
+                    JsonValue::from(self.bytes(t, col_type, Some(encoding)))
+                }
+            }
+            Some(MySQLValue::Json(t)) => t.clone(),
+            //未知类型按二进制数据输出
+            Some(MySQLValue::Opaque{bytes, ..}) => JsonValue::from(encoding.encode(bytes)),
+            Some(MySQLValue::Date {year, month, day}) => JsonValue::from(self.date(*year, *month, *day)),
+            Some(MySQLValue::Time {negative, hours, minutes, seconds, subseconds}) => JsonValue::from(self.time(*negative, *hours, *minutes, *seconds, *subseconds, fsp(col_type))),
+            Some(MySQLValue::DateTime {year, month, day, hour, minute, second, subsecond}) => {
+                JsonValue::from(self.datetime(*year, *month, *day, *hour, *minute, *second, *subsecond, fsp(col_type)))
+            }
+            Some(MySQLValue::Timestamp {unix_time, subsecond}) => {
+                match self.timestamp(*unix_time, *subsecond, fsp(col_type)) {
+                    Some(t) => JsonValue::from(t),
+                    None => json_float(&epoch(*unix_time, *subsecond, fsp(col_type)))
+                }
+            }
+            Some(MySQLValue::Null) | None => JsonValue::Null
+        }
+    }
+
+    //csv等纯文本格式输出时字段的值, Null返回None
+    pub fn text_value(&self, value: &Option<MySQLValue>, col_type: &str, encoding: BinaryEncoding) -> Option<String> {
+        match value {
+            Some(t) if self.null_temporal(t) => None,
+            Some(MySQLValue::Blob(t)) => {
+                if let Some(value) = self.spatial(t, col_type) {
+                    match value.to_json(self.spatial) {
+                        JsonValue::String(t) => Some(t),
+                        t => Some(t.to_string())
+                    }
+                }else if is_text_type(col_type) {
+                    Some(self.bytes(t, col_type, None))
+                }else {
+                    Some(self.bytes(t, col_type, Some(encoding)))
+                }
+            }
+            Some(MySQLValue::Decimal(t)) => Some(t.to_string()),
+            Some(MySQLValue::Timestamp {unix_time, subsecond}) => {
+                match self.timestamp(*unix_time, *subsecond, fsp(col_type)) {
+                    Some(t) => Some(t),
+                    None => Some(epoch(*unix_time, *subsecond, fsp(col_type)))
+                }
+            }
+            Some(MySQLValue::Null) | None => None,
+            _ => match self.json_value(value, col_type, encoding) {
+                JsonValue::String(t) => Some(t),
+                JsonValue::Null => None,
+                t => Some(t.to_string())
+            }
+        }
+    }
+
+    //空间类型的值, 不是空间类型或解析失败时为None, 按二进制数据输出
+    pub fn spatial(&self, value: &[u8], col_type: &str) -> Option<SpatialValue> {
+        if !spatial::is_spatial_type(col_type) {
+            return None;
+        }
+        SpatialValue::decode(value).ok()
+    }
+
+    //sql语句中使用的TIMESTAMP值, epoch模式下mysql为from_unixtime(...), 其他方言为utc时间
+    pub fn timestamp_sql(&self, unix_time: i32, subsecond: u32, fsp: usize) -> String {
+        if is_invalid_temporal(&MySQLValue::Timestamp {unix_time, subsecond}) {
+            return match self.zerodate {
+                ZeroDate::Null => String::from("NULL"),
+                _ => self.dialect.temporal(Temporal::DateTime, &self.datetime(0, 0, 0, 0, 0, 0, 0, 0))
+            };
+        }
+        match self.timestamp(unix_time, subsecond, fsp) {
+            Some(t) => self.dialect.temporal(Temporal::DateTime, &t),
+            None => {
+                let utc = match chrono::Utc.timestamp_opt(i64::from(unix_time), 0).single() {
+                    Some(t) => format!("{}{}", t.format("%Y-%m-%d %H:%M:%S"), subsecond_str(subsecond, fsp)),
+                    None => unix_time.to_string()
+                };
+                self.dialect.timestamp(&epoch(unix_time, subsecond, fsp), &utc)
+            }
+        }
+    }
+}
+
+//事件头中的时间戳以utc时间输出, 用于报表
+pub fn utc_datetime(timestamp: u32) -> String {
+    match chrono::Utc.timestamp_opt(i64::from(timestamp), 0).single() {
+        Some(t) => t.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => timestamp.to_string()
+    }
+}
+
+//{"db.tbl":["col1","col2"]}格式的字段列表
+fn column_list(value: &str, name: &str) -> Result<HashMap<String, Vec<String>>, String> {
+    if value.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let columns: HashMap<String, Vec<String>> = serde_json::from_str(value).map_err(|e| format!("{}格式错误: {}", name, e))?;
+    //库、表、列名可以带引号
+    Ok(columns.into_iter().map(|(table, cols)| {
+        (crate::ident::normalize(&table), cols.iter().map(|t| crate::ident::normalize(t)).collect())
+    }).collect())
+}
+
+fn table_columns<'a>(columns: &'a HashMap<String, Vec<String>>, db_tbl: &str) -> Option<&'a Vec<String>> {
+    if let Some(t) = columns.get(db_tbl) {
+        return Some(t);
+    }
+    let db = db_tbl.split('.').next().unwrap_or("");
+    columns.get(&format!("{}.*", db)).or_else(|| columns.get("*"))
+}
+
+/// 时间类型字段的小数位数, 为字段类型中的(N), 例如datetime(3)为3, 没有时为0
+pub fn fsp(col_type: &str) -> usize {
+    let col_type = col_type.trim().to_lowercase();
+    if !col_type.starts_with("time") && !col_type.starts_with("datetime") {
+        return 0;
+    }
+    col_type.split_once('(').and_then(|(_, t)| t.split(')').next()).and_then(|t| t.trim().parse().ok()).filter(|t| *t <= 6).unwrap_or(0)
+}
+
+//binlog中的小数部分为按存储位数保存的整数: fsp 1-2为2位, 3-4为4位, 5-6为6位, 返回(fsp, 小数部分的前fsp位)
+//不知道fsp(字段类型中没有)时按6位
+fn fraction(subsecond: u32, fsp: usize) -> (usize, u32) {
+    match fsp {
+        1..=6 => (fsp, subsecond / 10u32.pow((fsp.div_ceil(2) * 2 - fsp) as u32)),
+        _ => (6, subsecond)
+    }
+}
+
+//小数部分, 按fsp补齐前导0, 例如fsp为2时0.05为.05
+fn subsecond_str(subsecond: u32, fsp: usize) -> String {
+    if subsecond == 0 {
+        return String::from("");
+    }
+    let (digits, value) = fraction(subsecond, fsp);
+    format!(".{:0width$}", value, width = digits)
+}
+
+/// epoch模式下TIMESTAMP的值: unix_time + 小数部分 / 10^fsp, 按fsp位小数输出
+pub fn epoch(unix_time: i32, subsecond: u32, fsp: usize) -> String {
+    if subsecond == 0 {
+        return unix_time.to_string();
+    }
+    let (digits, value) = fraction(subsecond, fsp);
+    let scale = 10i64.pow(digits as u32);
+    let total = i64::from(unix_time) * scale + i64::from(value);
+    format!("{}.{:0width$}", total / scale, total % scale, width = digits)
+}
+
+fn bytes_str(value: &[u8], encoding: Option<BinaryEncoding>) -> String {
+    match encoding {
+        Some(t) => t.encode(value),
+        None => {
+            let text = String::from_utf8_lossy(value);
+            if let std::borrow::Cow::Owned(_) = text {
+                warning::emit(WarningKind::CharsetFallback, format!("字符串的值不是合法的utf8({} bytes), 无法转换的字节按替换字符输出", value.len()));
+            }
+            text.to_string()
+        }
+    }
+}
+
+//char、varchar、text类型按字符串输出, 其余按二进制数据输出
+pub fn is_text_type(col_type: &str) -> bool {
+    col_type.contains("text") || col_type.contains("char")
+}
+
+//无法用json数字表示的值输出为null
+fn json_float(value: &str) -> JsonValue {
+    match value.parse::<f64>() {
+        Ok(t) => match serde_json::Number::from_f64(t) {
+            Some(n) => JsonValue::Number(n),
+            None => JsonValue::Null
+        },
+        Err(_) => JsonValue::from(value)
+    }
+}
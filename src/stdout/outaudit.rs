@@ -0,0 +1,113 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/01
+*/
+
+//审计输出: 每行数据一个json对象, 记录谁在什么时间修改了哪些行
+//{"time":1571018400,"gtid":"uuid:1","thread_id":12,"user":"app@10.0.0.1","query":"update ...","type":"update","database":"db","table":"tb","data":{...},"old":{...}}
+//  thread_id取自事务开始的QueryEvent(BEGIN)
+//  user优先使用QueryEvent中的Q_INVOKER, 没有时配置了--audit-enrich则从mysql的performance_schema.threads查询该线程的用户
+//  query为开启binlog_rows_query_log_events时记录的原始sql, DDL语句直接使用QueryEvent中的语句
+
+use std::collections::HashMap;
+use crate::Config;
+use crate::replication::readevent::TableMap;
+use crate::replication::readbinlog::Traction;
+use crate::stdout::OutState;
+use crate::stdout::outjson;
+use serde_json::{Map, Value as JsonValue};
+
+//当前事务的审计信息
+#[derive(Debug, Default)]
+pub struct AuditState{
+    thread_id: Option<u32>,
+    invoker: String,
+    query: String,
+    users: HashMap<u32, Option<String>>,       //thread_id对应的用户, 每个线程只查询一次
+}
+
+impl AuditState{
+    fn reset(&mut self) {
+        self.thread_id = None;
+        self.invoker = String::from("");
+        self.query = String::from("");
+    }
+
+    fn user(&mut self, conf: &Config) -> Option<String> {
+        if !self.invoker.is_empty() {
+            return Some(self.invoker.clone());
+        }
+        let thread_id = self.thread_id?;
+        if !conf.audit_enrich {
+            return None;
+        }
+        self.users.entry(thread_id).or_insert_with(|| thread_user(conf, thread_id)).clone()
+    }
+
+    fn record(&mut self, conf: &Config, timestamp: u32, gtid: &str) -> Map<String, JsonValue> {
+        let mut record = Map::new();
+        record.insert(String::from("time"), JsonValue::from(timestamp));
+        record.insert(String::from("gtid"), JsonValue::from(gtid));
+        record.insert(String::from("thread_id"), self.thread_id.map_or(JsonValue::Null, JsonValue::from));
+        record.insert(String::from("user"), self.user(conf).map_or(JsonValue::Null, JsonValue::from));
+        record.insert(String::from("query"), if self.query.is_empty() { JsonValue::Null } else { JsonValue::from(self.query.clone()) });
+        record
+    }
+}
+
+#[cfg(feature = "network")]
+fn thread_user(conf: &Config, thread_id: u32) -> Option<String> {
+    crate::meta::get_thread_user(conf, thread_id)
+}
+
+#[cfg(not(feature = "network"))]
+fn thread_user(_conf: &Config, _thread_id: u32) -> Option<String> {
+    None
+}
+
+pub fn out_audit(data: &Traction, conf: &Config, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>, db_tbl: &String, map: &TableMap, out_state: &mut OutState) {
+    match data {
+        Traction::GtidEvent(t) => {
+            out_state.gtid = format!("{}:{}", t.gtid, t.gno_id);
+            out_state.audit.reset();
+        }
+        Traction::QueryEvent(t) => {
+            out_state.audit.thread_id = Some(t.thread_id);
+            if !t.invoker.is_empty() {
+                out_state.audit.invoker = t.invoker.clone();
+            }
+            if t.command != "BEGIN" {
+                //DDL等直接以语句形式记录的操作
+                out_state.audit.query = t.command.clone();
+                let mut record = out_state.audit.record(conf, out_state.timestamp, &out_state.gtid);
+                record.insert(String::from("type"), JsonValue::from("query"));
+                record.insert(String::from("database"), JsonValue::from(t.database.clone()));
+                println!("{}", JsonValue::Object(record));
+            }
+        }
+        Traction::RowsQueryEvent(t) => {
+            out_state.audit.query = t.query.clone();
+        }
+        Traction::RowEvent(t, f) => {
+            match table_cols_info.get(db_tbl) {
+                Some(cols) => {
+                    let head = out_state.audit.record(conf, out_state.timestamp, &out_state.gtid);
+                    for row in outjson::row_records(f, t, cols, db_tbl, map, &out_state.gtid, &out_state.format) {
+                        let mut record = head.clone();
+                        if let JsonValue::Object(values) = row {
+                            record.extend(values);
+                        }
+                        println!("{}", JsonValue::Object(record));
+                    }
+                }
+                None => {
+                    println!("内存中无字段信息: {},{:?}", db_tbl,table_cols_info);
+                }
+            }
+        }
+        Traction::XidEvent(_) => {
+            out_state.audit.reset();
+        }
+        _ => {}
+    }
+}
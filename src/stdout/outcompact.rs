@@ -0,0 +1,183 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--compact: 将同一主键的多次修改合并为最终的数据(或删除标记), 按表、主键排序输出, 得到类似快照的变更集
+//  适用于只需要最终状态的批量同步, 每行一个json对象:
+//  {"type":"upsert","database":"db","table":"tb","key":{"id":1},"data":{...},"changes":3,"gtid":"uuid:10"}
+//  {"type":"delete","database":"db","table":"tb","key":{"id":2},"data":null,"changes":2,"gtid":"uuid:12"}
+//  --compactwindow N: 每N个事务输出一次并清空, 限制内存使用, 默认读取结束时一次输出
+//  DDL之前先输出已合并的数据, 避免表结构变化前后的数据合并在一起
+//  没有主键信息的表以所有字段作为key, update修改了主键时旧主键输出删除标记
+
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use bigdecimal::BigDecimal;
+use serde_json::{json, Map, Value as JsonValue};
+use crate::Config;
+use crate::replication::readevent::{BinlogEvent, TableMap};
+use crate::replication::readbinlog::Traction;
+use crate::replication::parsevalue::MySQLValue;
+use crate::stdout::format::ValueFormat;
+use crate::stdout::outjson;
+
+//主键中的一个值, 数字按数值排序, 其余按文本排序, null排在最前
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum KeyPart{
+    Null,
+    Number(BigDecimal),
+    Text(String),
+}
+
+#[derive(Debug)]
+struct Compacted{
+    database: String,
+    table: String,
+    key: JsonValue,
+    data: Option<JsonValue>,        //None为删除标记
+    changes: u64,
+    gtid: String,
+}
+
+#[derive(Debug)]
+pub struct Compactor{
+    window: u64,
+    transactions: u64,
+    changes: BTreeMap<(String, Vec<KeyPart>), Compacted>,
+}
+
+impl Compactor{
+    pub fn new(conf: &Config) -> Result<Compactor, String> {
+        let window = match conf.compactwindow.as_str() {
+            "" => 0,
+            t => t.parse::<u64>().ok().filter(|v| *v > 0).ok_or_else(|| format!("无效的compactwindow: {}, 需要大于0的事务数", t))?
+        };
+        Ok(Compactor{ window, transactions: 0, changes: BTreeMap::new() })
+    }
+
+    pub fn record(&mut self, data: &Traction, table_cols_info: &HashMap<String, Vec<HashMap<String, String>>>,
+                  db_tbl: &str, map: &TableMap, gtid: &str, format: &ValueFormat) {
+        let (code, row_values) = match data {
+            Traction::RowEvent(code, rows) => (code, rows),
+            Traction::XidEvent(_) => {
+                self.transaction_end();
+                return;
+            }
+            Traction::QueryEvent(t) if t.command != "BEGIN" => {
+                //DDL之前输出已合并的数据
+                if t.command != "COMMIT" {
+                    self.flush();
+                }
+                self.transaction_end();
+                return;
+            }
+            _ => return
+        };
+        let cols = match table_cols_info.get(db_tbl) {
+            Some(t) => t,
+            None => {
+                println!("内存中无字段信息: {}", db_tbl);
+                return;
+            }
+        };
+        let pk: Vec<usize> = cols.iter().enumerate()
+            .filter(|(_, c)| c.get("COLUMN_KEY").map(|k| k == "PRI").unwrap_or(false))
+            .map(|(idx, _)| idx)
+            .collect();
+        let key_cols: Vec<usize> = if pk.is_empty() { (0..cols.len()).collect() } else { pk };
+        let rows = row_values.rows();
+        match code {
+            BinlogEvent::UpdateEvent => {
+                for pair in rows.chunks(2) {
+                    if pair.len() < 2 {
+                        continue;
+                    }
+                    let before = key(&pair[0], &key_cols, cols, db_tbl, format);
+                    let after = key(&pair[1], &key_cols, cols, db_tbl, format);
+                    if before.0 != after.0 {
+                        self.change(db_tbl, map, before, None, gtid);
+                    }
+                    self.change(db_tbl, map, after, Some(outjson::get_row(&pair[1], cols, db_tbl, format)), gtid);
+                }
+            }
+            BinlogEvent::WriteEvent => {
+                for row in rows {
+                    self.change(db_tbl, map, key(row, &key_cols, cols, db_tbl, format), Some(outjson::get_row(row, cols, db_tbl, format)), gtid);
+                }
+            }
+            BinlogEvent::DeleteEvent => {
+                for row in rows {
+                    self.change(db_tbl, map, key(row, &key_cols, cols, db_tbl, format), None, gtid);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn change(&mut self, db_tbl: &str, map: &TableMap, key: (Vec<KeyPart>, JsonValue), data: Option<JsonValue>, gtid: &str) {
+        let (parts, key) = key;
+        let entry = self.changes.entry((db_tbl.to_string(), parts)).or_insert_with(|| Compacted{
+            database: map.database_name.clone(),
+            table: map.table_name.clone(),
+            key,
+            data: None,
+            changes: 0,
+            gtid: String::new(),
+        });
+        entry.data = data;
+        entry.changes += 1;
+        entry.gtid = gtid.to_string();
+    }
+
+    fn transaction_end(&mut self) {
+        self.transactions += 1;
+        if self.window > 0 && self.transactions.is_multiple_of(self.window) {
+            self.flush();
+        }
+    }
+
+    /// 输出已合并的数据并清空
+    pub fn flush(&mut self) {
+        for record in self.take() {
+            println!("{}", record);
+        }
+    }
+
+    /// 按表、主键排序的合并结果, 取出后清空
+    pub fn take(&mut self) -> Vec<JsonValue> {
+        std::mem::take(&mut self.changes).into_values().map(|c| json!({
+            "type": if c.data.is_some() { "upsert" } else { "delete" },
+            "database": c.database,
+            "table": c.table,
+            "key": c.key,
+            "data": c.data,
+            "changes": c.changes,
+            "gtid": c.gtid,
+        })).collect()
+    }
+}
+
+//主键的排序值及json格式的主键
+fn key(row: &[Option<MySQLValue>], key_cols: &[usize], cols: &[HashMap<String, String>], db_tbl: &str, format: &ValueFormat) -> (Vec<KeyPart>, JsonValue) {
+    let mut parts = vec![];
+    let mut key = Map::new();
+    for idx in key_cols {
+        let value = row.get(*idx).unwrap_or(&None);
+        let col = cols[*idx].get("COLUMN_NAME").unwrap();
+        let col_type = cols[*idx].get("COLUMN_TYPE").unwrap();
+        let encoding = format.binary_encoding(db_tbl, col);
+        parts.push(match value {
+            Some(MySQLValue::Null) | None => KeyPart::Null,
+            Some(MySQLValue::SignedInteger(t)) => KeyPart::Number(BigDecimal::from(*t)),
+            Some(MySQLValue::Decimal(t)) => KeyPart::Number(t.clone()),
+            Some(MySQLValue::Float(_)) | Some(MySQLValue::Double(_)) | Some(MySQLValue::Year(_)) => {
+                let text = format.text_value(value, col_type, encoding).unwrap_or_default();
+                BigDecimal::from_str(&text).map(KeyPart::Number).unwrap_or(KeyPart::Text(text))
+            }
+            _ => KeyPart::Text(format.text_value(value, col_type, encoding).unwrap_or_default()),
+        });
+        key.insert(col.clone(), format.json_value(value, col_type, encoding));
+    }
+    (parts, JsonValue::Object(key))
+}
@@ -0,0 +1,96 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--count: 不输出数据, 读取结束时只输出通过过滤条件的行数, 用于脚本中统计, 例如某段时间内orders表被删除的行数
+//  --countby按db、table、op(insert/update/delete/query)分组, 每组一行: 分组的值及行数以tab分隔, 按分组排序
+//  update的前后镜像计为1行, 非BEGIN/COMMIT的query事件计为1行, op为query、table为库名
+
+use std::collections::BTreeMap;
+use crate::replication::readevent::BinlogEvent;
+use crate::replication::readbinlog::Traction;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CountKey{
+    Db,
+    Table,
+    Op
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Counter{
+    by: Vec<CountKey>,
+    counts: BTreeMap<Vec<String>, u64>,
+    total: u64,
+}
+
+impl Counter {
+    /// by为空时只统计总数, 否则为逗号分隔的db、table、op
+    pub fn new(by: &str) -> Result<Counter, String> {
+        let mut keys = vec![];
+        for key in by.split(',').map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            let key = match key {
+                "db" => CountKey::Db,
+                "table" => CountKey::Table,
+                "op" => CountKey::Op,
+                _ => return Err(format!("无效的countby: {}, 可选值为db、table、op", key))
+            };
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        Ok(Counter{ by: keys, ..Counter::default() })
+    }
+
+    pub fn record(&mut self, data: &Traction, db_tbl: &str) {
+        let (op, count, target) = match data {
+            Traction::RowEvent(code, rows) => {
+                match code {
+                    BinlogEvent::WriteEvent => ("insert", rows.rows.len(), db_tbl),
+                    BinlogEvent::UpdateEvent => ("update", rows.rows.len() / 2, db_tbl),
+                    BinlogEvent::DeleteEvent => ("delete", rows.rows.len(), db_tbl),
+                    _ => return
+                }
+            }
+            Traction::QueryEvent(t) if t.command != "BEGIN" && t.command != "COMMIT" => ("query", 1, t.database.as_str()),
+            _ => return
+        };
+        if count == 0 {
+            return;
+        }
+        self.total += count as u64;
+        if self.by.is_empty() {
+            return;
+        }
+        let (db, table) = match target.split_once('.') {
+            Some((db, tbl)) => (db, tbl),
+            None => (target, "")
+        };
+        let key = self.by.iter().map(|k| match k {
+            CountKey::Db => db.to_string(),
+            CountKey::Table if table.is_empty() => db.to_string(),
+            CountKey::Table => format!("{}.{}", db, table),
+            CountKey::Op => op.to_string(),
+        }).collect();
+        *self.counts.entry(key).or_insert(0) += count as u64;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// 输出的各行, 没有分组时只有总数
+    pub fn lines(&self) -> Vec<String> {
+        if self.by.is_empty() {
+            return vec![self.total.to_string()];
+        }
+        self.counts.iter().map(|(key, count)| format!("{}\t{}", key.join("\t"), count)).collect()
+    }
+
+    pub fn print(&self) {
+        for line in self.lines() {
+            println!("{}", line);
+        }
+    }
+}
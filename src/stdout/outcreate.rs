@@ -0,0 +1,72 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--create-table: 根据TABLE_MAP中的可选metadata(binlog_row_metadata=FULL)生成CREATE TABLE语句, 用于初始化目标库的表结构
+//  表第一次出现及表结构变化(生成的语句不同)时输出, 不需要连接源库
+//  -- 2019-11-06 00:00:00 UTC, gtid: uuid:12, position: 1234
+//  CREATE TABLE IF NOT EXISTS `db`.`tb` (...) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci;
+//  没有字段名时每个表输出一次注释说明原因
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use crate::replication::readbinlog::Traction;
+use crate::stdout::format;
+
+pub struct CreateTableLog{
+    path: String,
+    writer: Box<dyn Write>,
+    gtid: String,
+    published: HashMap<String, String>,
+}
+
+impl CreateTableLog{
+    //-为输出到stdout
+    pub fn new(path: &str) -> Result<CreateTableLog, String> {
+        let writer: Box<dyn Write> = if path == "-" {
+            Box::new(io::stdout())
+        }else {
+            Box::new(BufWriter::new(File::create(path).map_err(|e| format!("创建文件({})发生错误:{}", path, e))?))
+        };
+        Ok(CreateTableLog::with_writer(path, writer))
+    }
+
+    pub fn with_writer(path: &str, writer: Box<dyn Write>) -> CreateTableLog {
+        CreateTableLog{ path: path.to_string(), writer, gtid: String::new(), published: HashMap::new() }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn record(&mut self, data: &Traction, position: u64, timestamp: u32) -> io::Result<()> {
+        let map = match data {
+            Traction::GtidEvent(t) => {
+                self.gtid = format!("{}:{}", t.gtid, t.gno_id);
+                return Ok(());
+            }
+            Traction::TableMapEvent(t) => t,
+            _ => return Ok(())
+        };
+        let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+        let sql = match map.create_table() {
+            Ok(t) => t,
+            Err(e) => format!("-- {}", e),
+        };
+        if self.published.get(&db_tbl) == Some(&sql) {
+            return Ok(());
+        }
+        writeln!(self.writer, "-- {} UTC, gtid: {}, position: {}", format::utc_datetime(timestamp),
+                 if self.gtid.is_empty() { "-" } else { &self.gtid }, position)?;
+        writeln!(self.writer, "{}", sql)?;
+        writeln!(self.writer)?;
+        self.published.insert(db_tbl, sql);
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,100 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//行数据以csv格式输出, 每行数据一条记录
+//type,database,table,gtid,字段1,字段2...
+//update输出两条记录, type分别为update_before、update_after; Null输出为空字段, 空字符串输出为""
+
+use std::collections::HashMap;
+use crate::replication::readevent::{BinlogEvent,TableMap};
+use crate::replication::parsevalue::{MySQLValue, RowValue};
+use crate::replication::readbinlog::Traction;
+use crate::stdout::OutState;
+
+pub fn out_csv(data: &Traction, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,db_tbl: &String, map: &TableMap, out_state: &mut OutState) {
+    match data {
+        Traction::GtidEvent(t) => {
+            out_state.gtid = format!("{}:{}", t.gtid, t.gno_id);
+        }
+        Traction::RowEvent(t, f) => {
+            match table_cols_info.get(db_tbl) {
+                Some(cols) => {
+                    print_rows(f, t, cols, db_tbl, map, out_state);
+                }
+                None => {
+                    println!("内存中无字段信息: {},{:?}", db_tbl,table_cols_info);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn print_rows(
+    row_values: &RowValue, code: &BinlogEvent,
+    cols: &[HashMap<String, String>],
+    db_tbl: &str, map: &TableMap, out_state: &OutState) {
+
+    match code {
+        BinlogEvent::UpdateEvent => {
+            for rows in row_values.rows.chunks(2) {
+                if rows.len() < 2 {
+                    continue;
+                }
+                println!("{}", get_record("update_before", &rows[0], cols, db_tbl, map, out_state));
+                println!("{}", get_record("update_after", &rows[1], cols, db_tbl, map, out_state));
+            }
+        }
+        BinlogEvent::WriteEvent |
+        BinlogEvent::DeleteEvent => {
+            let row_type = if let BinlogEvent::WriteEvent = code {"insert"} else {"delete"};
+            for row in &row_values.rows {
+                println!("{}", get_record(row_type, row, cols, db_tbl, map, out_state));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn get_record(
+    row_type: &str, row: &[Option<MySQLValue>],
+    cols: &[HashMap<String, String>],
+    db_tbl: &str, map: &TableMap, out_state: &OutState) -> String {
+
+    let mut fields = vec![
+        String::from(row_type),
+        csv_field(&map.database_name),
+        csv_field(&map.table_name),
+        csv_field(&out_state.gtid)
+    ];
+    for (idx, value) in row.iter().enumerate() {
+        let col = cols[idx].get("COLUMN_NAME").unwrap();
+        if !out_state.format.keep_column(db_tbl, col) {
+            continue;
+        }
+        let col_type = cols[idx].get("COLUMN_TYPE").unwrap();
+        let encoding = out_state.format.binary_encoding(db_tbl, col);
+        match out_state.format.text_value(value, col_type, encoding) {
+            Some(t) => {
+                if t.is_empty() {
+                    fields.push(String::from("\"\""));
+                }else {
+                    fields.push(csv_field(&t));
+                }
+            }
+            None => fields.push(String::from(""))
+        }
+    }
+    fields.join(",")
+}
+
+//包含逗号、引号、换行的字段使用双引号包起来, 字段中的双引号写两次
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }else {
+        String::from(value)
+    }
+}
@@ -0,0 +1,152 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/01
+*/
+
+//analyze --ddl: 只提取表结构变更语句, 按binlog中的顺序(即执行完成的顺序)写入变更记录文件
+//每条语句一段:
+//  -- 2019-10-02 07:06:12 UTC, gtid: uuid:12, database: test, thread_id: 10, execute_seconds: 3
+//  CREATE TABLE t2 (id int);
+//execute_seconds为语句在master上的执行时间, 大表的ALTER可以据此估算锁表/复制延迟的时长
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use crate::replication::readbinlog::Traction;
+use crate::stdout::format;
+
+const DDL_KEYWORDS: [&str; 5] = ["CREATE", "ALTER", "DROP", "RENAME", "TRUNCATE"];
+
+pub struct DdlTimeline{
+    path: String,
+    writer: Box<dyn Write>,
+    gtid: String,
+}
+
+impl DdlTimeline{
+    //-为输出到stdout
+    pub fn new(path: &str) -> Result<DdlTimeline, String> {
+        let writer: Box<dyn Write> = if path == "-" {
+            Box::new(io::stdout())
+        }else {
+            Box::new(BufWriter::new(File::create(path).map_err(|e| format!("创建文件({})发生错误:{}", path, e))?))
+        };
+        Ok(DdlTimeline{ path: path.to_string(), writer, gtid: String::from("") })
+    }
+
+    pub fn record(&mut self, data: &Traction, timestamp: u32) -> io::Result<()> {
+        let t = match data {
+            Traction::GtidEvent(t) => {
+                self.gtid = format!("{}:{}", t.gtid, t.gno_id);
+                return Ok(());
+            }
+            Traction::QueryEvent(t) if is_ddl(&t.command) => t,
+            _ => return Ok(())
+        };
+        writeln!(self.writer, "-- {} UTC, gtid: {}, database: {}, thread_id: {}, execute_seconds: {}",
+                 format::utc_datetime(timestamp), if self.gtid.is_empty() { "-" } else { &self.gtid },
+                 t.database, t.thread_id, t.execute_seconds)?;
+        writeln!(self.writer, "{};", t.command.trim_end().trim_end_matches(';'))?;
+        writeln!(self.writer)
+    }
+
+    pub fn finish(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            println!("写入文件({})发生错误:{}", self.path, e);
+        }
+    }
+}
+
+//跳过语句开头的空白及/* */注释, 注释未结束时返回None
+pub fn statement_body(command: &str) -> Option<&str> {
+    let mut sql = command.trim_start();
+    while sql.starts_with("/*") {
+        sql = match sql.find("*/") {
+            Some(end) => sql[end + 2..].trim_start(),
+            None => return None
+        };
+    }
+    Some(sql)
+}
+
+//按第一个关键字判断
+pub fn is_ddl(command: &str) -> bool {
+    let sql = match statement_body(command) {
+        Some(t) => t,
+        None => return false
+    };
+    let keyword: String = sql.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+    DDL_KEYWORDS.iter().any(|k| keyword.eq_ignore_ascii_case(k))
+}
+
+//DDL语句涉及的表(db.tbl), 没有指定库名时使用database, bool为该表是否被删除(DROP TABLE、RENAME的原表)
+//  支持CREATE/ALTER/DROP/TRUNCATE TABLE、RENAME TABLE a TO b、CREATE/DROP INDEX ... ON tbl, 其他语句返回空
+pub fn ddl_tables(command: &str, database: &str) -> Vec<(String, bool)> {
+    let sql = match statement_body(command) {
+        Some(t) => t,
+        None => return vec![]
+    };
+    let spaced = sql.replace('(', " ( ").replace(',', " , ").replace(';', " ");
+    let words: Vec<&str> = spaced.split_whitespace().collect();
+    let upper: Vec<String> = words.iter().map(|w| w.to_ascii_uppercase()).collect();
+    let name = |idx: usize| -> Option<String> {
+        let word = *words.get(idx)?;
+        if word.is_empty() || word == "(" || word == "," {
+            return None;
+        }
+        let parts = crate::ident::split_qualified(word);
+        Some(if parts.len() > 1 { parts.join(".") } else { format!("{}.{}", database, parts.join(".")) })
+    };
+    //跳过IF [NOT] EXISTS
+    let skip_if = |mut idx: usize| -> usize {
+        if upper.get(idx).map(|t| t.as_str()) == Some("IF") {
+            idx += 1;
+            if upper.get(idx).map(|t| t.as_str()) == Some("NOT") {
+                idx += 1;
+            }
+            idx += 1;
+        }
+        idx
+    };
+    let position = |word: &str| upper.iter().position(|t| t == word);
+    let mut tables = vec![];
+    match upper.first().map(|t| t.as_str()) {
+        Some("CREATE") | Some("DROP") | Some("ALTER") | Some("TRUNCATE") => {
+            let drop = upper[0] == "DROP";
+            if let (None, Some(idx), Some(on)) = (position("TABLE"), position("INDEX"), position("ON")) {
+                //CREATE/DROP INDEX idx ON tbl
+                if idx < on {
+                    tables.extend(name(on + 1).map(|t| (t, false)));
+                    return tables;
+                }
+            }
+            let idx = match position("TABLE") {
+                Some(t) if t <= 3 => t + 1,
+                //TRUNCATE可以省略TABLE
+                _ if upper[0] == "TRUNCATE" => 1,
+                _ => return tables
+            };
+            let mut idx = skip_if(idx);
+            while let Some(t) = name(idx) {
+                tables.push((t, drop));
+                //DROP TABLE可以同时删除多个表
+                if !drop || words.get(idx + 1) != Some(&",") {
+                    break;
+                }
+                idx += 2;
+            }
+        }
+        Some("RENAME") if upper.get(1).map(|t| t.as_str()) == Some("TABLE") => {
+            let mut idx = 2;
+            while let (Some(from), Some("TO"), Some(to)) = (name(idx), upper.get(idx + 1).map(|t| t.as_str()), name(idx + 2)) {
+                tables.push((from, true));
+                tables.push((to, false));
+                if words.get(idx + 3) != Some(&",") {
+                    break;
+                }
+                idx += 4;
+            }
+        }
+        _ => {}
+    }
+    tables
+}
@@ -0,0 +1,134 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/01
+*/
+
+//analyze --deps: 导出事务之间的last_committed/sequence_number依赖关系, 用于分析从库并行复制(LOGICAL_CLOCK)的并行度
+//  事务在last_committed对应的事务提交之后才能开始回放, last_committed相同的事务属于同一个提交组, 可以并行回放
+//  last_committed、sequence_number只在同一个binlog文件内有效
+//dot: 每个提交组一个subgraph, 事务指向其依赖的事务, 可以通过graphviz生成图片: dot -Tsvg deps.dot -o deps.svg
+//json: {"transactions":[{"gtid":..,"last_committed":..,"sequence_number":..}],"groups":[{"last_committed":..,"size":..}],"avg_group_size":..}
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use serde_json::json;
+use crate::replication::readbinlog::Traction;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepsFormat{
+    Dot,
+    Json
+}
+
+impl DepsFormat{
+    pub fn new(format: &str) -> Result<DepsFormat, String> {
+        match format {
+            "" | "dot" => Ok(DepsFormat::Dot),
+            "json" => Ok(DepsFormat::Json),
+            _ => Err(format!("无效的depsformat: {}, 可选值为dot、json", format))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TransactionDep{
+    gtid: String,
+    last_committed: u64,
+    sequence_number: u64,
+}
+
+#[derive(Debug)]
+pub struct DependencyGraph{
+    path: String,
+    format: DepsFormat,
+    transactions: Vec<TransactionDep>,
+}
+
+impl DependencyGraph{
+    pub fn new(path: &str, format: &str) -> Result<DependencyGraph, String> {
+        Ok(DependencyGraph{
+            path: path.to_string(),
+            format: DepsFormat::new(format)?,
+            transactions: vec![],
+        })
+    }
+
+    pub fn record(&mut self, data: &Traction) {
+        if let Traction::GtidEvent(t) = data {
+            self.transactions.push(TransactionDep{
+                gtid: format!("{}:{}", t.gtid, t.gno_id),
+                last_committed: t.last_committed,
+                sequence_number: t.sequence_number,
+            });
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    //每个提交组(last_committed)包含的事务数
+    fn groups(&self) -> BTreeMap<u64, u64> {
+        let mut groups = BTreeMap::new();
+        for t in &self.transactions {
+            *groups.entry(t.last_committed).or_insert(0) += 1;
+        }
+        groups
+    }
+
+    //-为输出到stdout
+    pub fn finish(&self) -> io::Result<()> {
+        let mut writer: Box<dyn Write> = if self.path == "-" {
+            Box::new(io::stdout())
+        }else {
+            Box::new(BufWriter::new(File::create(&self.path)?))
+        };
+        match self.format {
+            DepsFormat::Dot => self.write_dot(&mut writer)?,
+            DepsFormat::Json => self.write_json(&mut writer)?,
+        }
+        writer.flush()
+    }
+
+    fn write_dot(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writeln!(writer, "digraph deps {{")?;
+        writeln!(writer, "    rankdir=LR;")?;
+        writeln!(writer, "    node [shape=box];")?;
+        for (last_committed, size) in self.groups() {
+            writeln!(writer, "    subgraph cluster_{} {{", last_committed)?;
+            writeln!(writer, "        label=\"last_committed={}, {} trx\";", last_committed, size)?;
+            for t in self.transactions.iter().filter(|t| t.last_committed == last_committed) {
+                writeln!(writer, "        trx_{} [label=\"{}\\n{}\"];", t.sequence_number, t.sequence_number, t.gtid)?;
+            }
+            writeln!(writer, "    }}")?;
+        }
+        //只画出依赖的事务也在导出范围内的边
+        let exported: HashSet<u64> = self.transactions.iter().map(|t| t.sequence_number).collect();
+        for t in &self.transactions {
+            if exported.contains(&t.last_committed) {
+                writeln!(writer, "    trx_{} -> trx_{};", t.sequence_number, t.last_committed)?;
+            }
+        }
+        writeln!(writer, "}}")
+    }
+
+    fn write_json(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let groups = self.groups();
+        let transactions: Vec<_> = self.transactions.iter().map(|t| json!({
+            "gtid": t.gtid,
+            "last_committed": t.last_committed,
+            "sequence_number": t.sequence_number,
+        })).collect();
+        let group_list: Vec<_> = groups.iter().map(|(last_committed, size)| json!({
+            "last_committed": last_committed,
+            "size": size,
+        })).collect();
+        let avg = if groups.is_empty() { 0.0 } else { self.transactions.len() as f64 / groups.len() as f64 };
+        writeln!(writer, "{}", json!({
+            "transactions": transactions,
+            "groups": group_list,
+            "avg_group_size": avg,
+        }))
+    }
+}
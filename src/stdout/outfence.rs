@@ -0,0 +1,84 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/01
+*/
+
+//getsql输出中DDL与之前修改同一个表的DML之间的隔离标记(--ddl-fence)
+//  按表记录上一次隔离之后输出过DML的表, DDL涉及这些表(或无法识别涉及的表)时在DDL前后输出标记:
+//    -- DDL FENCE BEGIN 1: shop.orders
+//    DO SLEEP(5);                  (wait:秒数时, postgresql为SELECT pg_sleep(5);)
+//    use `shop`;
+//    ALTER TABLE orders ...;
+//    -- DDL FENCE END 1: shop.orders
+//  拆分或并行回放sql脚本的工具按标记切分: BEGIN之前的语句全部执行完成后再执行DDL, END之后的DML按新的表结构执行
+//  wait为DDL之前的等待, 给目标库上仍在执行的旧事务留出时间, sqlite、ansi没有对应的语句, 不支持
+
+use std::collections::BTreeSet;
+use crate::stdout::dialect::Dialect;
+use crate::stdout::outddl;
+
+pub struct DdlFence{
+    wait: Option<f64>,
+    dialect: Dialect,
+    touched: BTreeSet<String>,          //上一次隔离之后输出过DML的表(db.tbl)
+    count: u64,
+    pending: Option<(u64, String)>,     //已输出BEGIN, 等待输出END
+}
+
+impl DdlFence{
+    //mark为只输出标记, wait:秒数为标记之后再等待
+    pub fn new(spec: &str, dialect: Dialect) -> Result<DdlFence, String> {
+        let wait = match spec {
+            "" | "mark" => None,
+            t => match t.strip_prefix("wait:").map(|t| t.parse::<f64>()) {
+                Some(Ok(t)) if t > 0.0 => Some(t),
+                _ => return Err(format!("无效的ddl-fence: {}, 可选值为mark、wait:秒数", spec))
+            }
+        };
+        if wait.is_some() && (dialect == Dialect::Sqlite || dialect == Dialect::Ansi) {
+            return Err(format!("dialect为{}时ddl-fence不支持wait", dialect.name()));
+        }
+        Ok(DdlFence{ wait, dialect, touched: BTreeSet::new(), count: 0, pending: None })
+    }
+
+    //输出了该表的DML
+    pub fn touch(&mut self, database: &str, table: &str) {
+        self.touched.insert(format!("{}.{}", database, table));
+    }
+
+    //DDL之前输出的语句, 不需要隔离时为空
+    pub fn before(&mut self, command: &str, database: &str) -> Vec<String> {
+        if !outddl::is_ddl(command) || self.touched.is_empty() {
+            return vec![];
+        }
+        let tables: Vec<String> = outddl::ddl_tables(command, database).into_iter().map(|(t, _)| t).collect();
+        //无法识别涉及的表时按涉及所有的表处理
+        let fenced: Vec<String> = if tables.is_empty() {
+            std::mem::take(&mut self.touched).into_iter().collect()
+        }else {
+            tables.into_iter().filter(|t| self.touched.remove(t)).collect()
+        };
+        if fenced.is_empty() {
+            return vec![];
+        }
+        self.count += 1;
+        let tables = fenced.join(", ");
+        let mut lines = vec![format!("-- DDL FENCE BEGIN {}: {}", self.count, tables)];
+        if let Some(seconds) = self.wait {
+            match self.dialect {
+                Dialect::Postgresql => lines.push(format!("SELECT pg_sleep({});", seconds)),
+                _ => lines.push(format!("DO SLEEP({});", seconds)),
+            }
+        }
+        self.pending = Some((self.count, tables));
+        lines
+    }
+
+    //DDL之后输出的语句
+    pub fn after(&mut self) -> Vec<String> {
+        match self.pending.take() {
+            Some((count, tables)) => vec![format!("-- DDL FENCE END {}: {}", count, tables)],
+            None => vec![]
+        }
+    }
+}
@@ -0,0 +1,175 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/01
+*/
+
+//analyze --groupcommit: 按GTID_EVENT中的last_committed及提交时间统计binlog组提交的大小及fsync间隔, 用于调整binlog_group_commit_sync_delay
+//  连续的last_committed相同的事务为一个提交组, 每组一次fsync; binlog文件切换后sequence_number重新开始, 组也重新开始
+//  组的提交时间为组内最后一个事务的提交时间, 8.0为微秒的immediate_commit_timestamp, 5.7只有秒级的事件时间
+//  按时间段(--interval, 默认60秒)输出组数、事务数、平均/最大组大小、平均间隔, 最后输出组大小分布及间隔的分位数
+//  binlog_transaction_dependency_tracking为WRITESET时last_committed按写集合计算, 统计出的组会比实际的组提交大
+
+use std::collections::BTreeMap;
+use crate::Config;
+use crate::replication::readbinlog::Traction;
+use crate::stdout::format;
+
+/// 一个提交组
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommitGroup{
+    pub last_committed: u64,
+    pub sequence_number: u64,       //组内最后一个事务的sequence_number
+    pub size: u64,                  //事务数
+    pub commit_time: u64,           //提交时间, 微秒
+}
+
+/// 一个时间段的统计
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GroupBucket{
+    pub start: u32,
+    pub groups: u64,
+    pub transactions: u64,
+    pub max_size: u64,
+    pub avg_interval: Option<u64>,  //与上一个组的平均间隔, 微秒
+}
+
+#[derive(Debug)]
+pub struct GroupCommitReport{
+    interval: u32,
+    precise: bool,                  //所有事务都有微秒的提交时间
+    groups: Vec<CommitGroup>,
+    current: Option<CommitGroup>,
+}
+
+//组大小分布的区间: 1、2-3、4-7...、64以上
+const SIZE_RANGES: [(u64, u64); 7] = [(1, 1), (2, 3), (4, 7), (8, 15), (16, 31), (32, 63), (64, u64::MAX)];
+
+impl GroupCommitReport{
+    pub fn new(conf: &Config) -> Result<GroupCommitReport, String> {
+        let interval = match conf.interval.as_str() {
+            "" => 60,
+            t => t.parse::<u32>().ok().filter(|v| *v > 0).ok_or_else(|| format!("无效的interval: {}, 需要大于0的秒数", t))?
+        };
+        Ok(GroupCommitReport{ interval, precise: true, groups: vec![], current: None })
+    }
+
+    pub fn record(&mut self, data: &Traction, timestamp: u32) {
+        let t = match data {
+            Traction::GtidEvent(t) => t,
+            //新文件的sequence_number从1开始
+            Traction::RotateLogEvent(_) => {
+                self.groups.extend(self.current.take());
+                return;
+            }
+            _ => return
+        };
+        //5.7之前没有逻辑时钟
+        if t.sequence_number == 0 {
+            return;
+        }
+        if t.commit_timestamp.is_none() {
+            self.precise = false;
+        }
+        let commit_time = t.commit_timestamp.unwrap_or(u64::from(timestamp) * 1_000_000);
+        match self.current.as_mut() {
+            Some(group) if group.last_committed == t.last_committed && t.sequence_number > group.sequence_number => {
+                group.size += 1;
+                group.sequence_number = t.sequence_number;
+                group.commit_time = group.commit_time.max(commit_time);
+            }
+            _ => {
+                self.groups.extend(self.current.take());
+                self.current = Some(CommitGroup{ last_committed: t.last_committed, sequence_number: t.sequence_number, size: 1, commit_time });
+            }
+        }
+    }
+
+    /// 提交时间是否为微秒精度, 5.7只有秒级的事件时间
+    pub fn precise(&self) -> bool {
+        self.precise
+    }
+
+    /// 所有提交组, 按binlog中的顺序
+    pub fn groups(&self) -> Vec<CommitGroup> {
+        self.groups.iter().chain(self.current.iter()).copied().collect()
+    }
+
+    /// 相邻两个组提交时间的间隔, 微秒
+    pub fn intervals(&self) -> Vec<u64> {
+        self.groups().windows(2).map(|w| w[1].commit_time.saturating_sub(w[0].commit_time)).collect()
+    }
+
+    /// 按时间段(utc)统计
+    pub fn buckets(&self) -> Vec<GroupBucket> {
+        let mut buckets: BTreeMap<u32, (GroupBucket, u64, u64)> = BTreeMap::new();
+        let mut previous: Option<u64> = None;
+        for group in self.groups() {
+            let seconds = (group.commit_time / 1_000_000) as u32;
+            let start = seconds - seconds % self.interval;
+            let (bucket, interval_sum, interval_count) = buckets.entry(start).or_insert((GroupBucket{ start, ..Default::default() }, 0, 0));
+            bucket.groups += 1;
+            bucket.transactions += group.size;
+            bucket.max_size = bucket.max_size.max(group.size);
+            if let Some(p) = previous {
+                *interval_sum += group.commit_time.saturating_sub(p);
+                *interval_count += 1;
+            }
+            previous = Some(group.commit_time);
+        }
+        buckets.into_values().map(|(mut bucket, sum, count)| {
+            bucket.avg_interval = sum.checked_div(count);
+            bucket
+        }).collect()
+    }
+
+    pub fn print(&self) {
+        let groups = self.groups();
+        if groups.is_empty() {
+            println!("没有last_committed/sequence_number信息(5.7之前的版本或没有事务), 无法统计组提交");
+            return;
+        }
+        let ms = |us: u64| us as f64 / 1000.0;
+        println!("-- binlog组提交统计(每{}秒, UTC), 提交时间精度: {}", self.interval, if self.precise { "微秒" } else { "秒(5.7没有提交时间, 间隔不准确)" });
+        println!("{:<21}{:>10}{:>12}{:>10}{:>10}{:>18}{:>10}", "time", "groups", "trx", "avg_size", "max_size", "avg_interval_ms", "syncs/s");
+        for b in self.buckets() {
+            println!("{:<21}{:>10}{:>12}{:>10.2}{:>10}{:>18}{:>10.1}", format::utc_datetime(b.start), b.groups, b.transactions,
+                     b.transactions as f64 / b.groups as f64, b.max_size,
+                     b.avg_interval.map(|t| format!("{:.3}", ms(t))).unwrap_or_else(|| String::from("-")),
+                     b.groups as f64 / self.interval as f64);
+        }
+
+        let transactions: u64 = groups.iter().map(|g| g.size).sum();
+        println!();
+        println!("-- 组大小分布: 共{}个事务, {}个组, 平均每组{:.2}个事务", transactions, groups.len(), transactions as f64 / groups.len() as f64);
+        println!("{:<10}{:>10}{:>12}{:>10}", "size", "groups", "trx", "trx%");
+        for (min, max) in SIZE_RANGES {
+            let matched: Vec<&CommitGroup> = groups.iter().filter(|g| g.size >= min && g.size <= max).collect();
+            if matched.is_empty() {
+                continue;
+            }
+            let size = match (min, max) {
+                (_, u64::MAX) => format!("{}+", min),
+                _ if min == max => min.to_string(),
+                _ => format!("{}-{}", min, max)
+            };
+            let trx: u64 = matched.iter().map(|g| g.size).sum();
+            println!("{:<10}{:>10}{:>12}{:>10.1}", size, matched.len(), trx, trx as f64 * 100.0 / transactions as f64);
+        }
+
+        let mut intervals = self.intervals();
+        if intervals.is_empty() {
+            return;
+        }
+        intervals.sort_unstable();
+        let percentile = |p: usize| intervals[((intervals.len() - 1) * p) / 100];
+        println!();
+        println!("-- 组之间的间隔(ms): min {:.3}, p50 {:.3}, p90 {:.3}, p99 {:.3}, max {:.3}",
+                 ms(intervals[0]), ms(percentile(50)), ms(percentile(90)), ms(percentile(99)), ms(intervals[intervals.len() - 1]));
+        //大部分组只有一个事务且间隔很短时, 增加sync_delay可以合并fsync
+        let single = groups.iter().filter(|g| g.size == 1).count();
+        if self.precise && single * 2 > groups.len() && percentile(50) < 1000 {
+            println!("-- {}%的组只有1个事务且间隔的中位数小于1ms, 可以尝试设置binlog_group_commit_sync_delay(微秒, 如{})合并fsync, 代价是提交延迟增加",
+                     single * 100 / groups.len(), percentile(50).clamp(100, 1000));
+        }
+    }
+}
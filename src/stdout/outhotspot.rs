@@ -0,0 +1,111 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/01
+*/
+
+//analyze --hotspot: 按时间段统计每个表的insert/update/delete行数及事件字节数, 并找出修改次数最多的主键
+//时间段按事件时间对齐, 例如--interval 300时10:03的数据计入10:00-10:05
+//没有主键的表只统计行数及字节数
+
+use std::collections::{BTreeMap, HashMap};
+use crate::Config;
+use crate::replication::readevent::BinlogEvent;
+use crate::replication::readbinlog::Traction;
+use crate::stdout::format::{self, ValueFormat};
+
+#[derive(Debug, Default, Clone, Copy)]
+struct TableVolume{
+    insert: u64,
+    update: u64,
+    delete: u64,
+    bytes: u64,
+}
+
+#[derive(Debug)]
+pub struct HotspotReport{
+    interval: u32,
+    top: usize,
+    buckets: BTreeMap<(u32, String), TableVolume>,
+    keys: HashMap<(String, String), u64>,
+}
+
+impl HotspotReport{
+    pub fn new(conf: &Config) -> Result<HotspotReport, String> {
+        let interval = match conf.interval.as_str() {
+            "" => 300,
+            t => t.parse::<u32>().ok().filter(|v| *v > 0).ok_or_else(|| format!("无效的interval: {}, 需要大于0的秒数", t))?
+        };
+        let top = match conf.topkeys.as_str() {
+            "" => 10,
+            t => t.parse::<usize>().map_err(|_| format!("无效的topkeys: {}", t))?
+        };
+        Ok(HotspotReport{
+            interval,
+            top,
+            buckets: BTreeMap::new(),
+            keys: HashMap::new(),
+        })
+    }
+
+    pub fn record(&mut self, data: &Traction, table_cols_info: &HashMap<String, Vec<HashMap<String, String>>>,
+                  db_tbl: &str, timestamp: u32, event_length: u32, format: &ValueFormat) {
+        let (code, row_values) = match data {
+            Traction::RowEvent(code, rows) => (code, rows),
+            _ => return
+        };
+        let bucket = timestamp - timestamp % self.interval;
+        let volume = self.buckets.entry((bucket, db_tbl.to_string())).or_default();
+        volume.bytes += event_length as u64;
+
+        //update事件为前后镜像成对出现, 使用前镜像的主键
+        let rows: Vec<_> = match code {
+            BinlogEvent::UpdateEvent => row_values.rows.chunks(2).map(|r| &r[0]).collect(),
+            _ => row_values.rows.iter().collect()
+        };
+        match code {
+            BinlogEvent::WriteEvent => volume.insert += rows.len() as u64,
+            BinlogEvent::UpdateEvent => volume.update += rows.len() as u64,
+            BinlogEvent::DeleteEvent => volume.delete += rows.len() as u64,
+            _ => {}
+        }
+
+        let cols = match table_cols_info.get(db_tbl) {
+            Some(t) => t,
+            None => return
+        };
+        let pk: Vec<usize> = cols.iter().enumerate()
+            .filter(|(_, c)| c.get("COLUMN_KEY").map(|k| k == "PRI").unwrap_or(false))
+            .map(|(idx, _)| idx)
+            .collect();
+        if pk.is_empty() {
+            return;
+        }
+        for row in rows {
+            let key: Vec<String> = pk.iter().map(|idx| {
+                let col = cols[*idx].get("COLUMN_NAME").unwrap();
+                let col_type = cols[*idx].get("COLUMN_TYPE").unwrap();
+                let encoding = format.binary_encoding(db_tbl, col);
+                let value = row.get(*idx).and_then(|v| format.text_value(v, col_type, encoding));
+                format!("{}={}", col, value.unwrap_or_else(|| String::from("NULL")))
+            }).collect();
+            *self.keys.entry((db_tbl.to_string(), key.join(","))).or_insert(0) += 1;
+        }
+    }
+
+    pub fn print(&self) {
+        println!("-- 按时间段统计(每{}秒, UTC)", self.interval);
+        println!("{:<21}{:<40}{:>10}{:>10}{:>10}{:>14}", "time", "table", "insert", "update", "delete", "bytes");
+        for ((bucket, table), v) in &self.buckets {
+            println!("{:<21}{:<40}{:>10}{:>10}{:>10}{:>14}", format::utc_datetime(*bucket), table, v.insert, v.update, v.delete, v.bytes);
+        }
+
+        let mut keys: Vec<(&(String, String), &u64)> = self.keys.iter().collect();
+        keys.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        println!();
+        println!("-- 修改次数最多的主键(前{})", self.top);
+        println!("{:<40}{:<40}{:>10}", "table", "key", "count");
+        for ((table, key), count) in keys.into_iter().take(self.top) {
+            println!("{:<40}{:<40}{:>10}", table, key, count);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//行数据以json格式输出, 每行数据一个json对象
+//{"type":"update","database":"db","table":"tb","gtid":"uuid:1","data":{...},"old":{...}}
+//--row-hash时带row_hash、old_row_hash
+//--boundaries时事务的行数据前后带begin、commit记录
+
+use std::collections::HashMap;
+use crate::replication::readevent::{BinlogEvent,TableMap};
+use crate::replication::parsevalue::{MySQLValue, RowValue};
+use crate::replication::readbinlog::Traction;
+#[cfg(feature = "os")]
+use crate::stdout::OutState;
+use crate::stdout::format::ValueFormat;
+use crate::reader::Event;
+use crate::meta::ColumnTypeDict;
+use crate::rowhash;
+use serde_json::{json, Map, Value as JsonValue};
+
+#[cfg(feature = "os")]
+pub fn out_json(data: &Traction, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,db_tbl: &String, map: &TableMap, out_state: &mut OutState) {
+    match data {
+        Traction::GtidEvent(t) => {
+            out_state.gtid = format!("{}:{}", t.gtid, t.gno_id);
+        }
+        Traction::XidEvent(t) => {
+            if let Some(record) = out_state.boundary.commit(&out_state.gtid, Some(t.xid), out_state.timestamp, &out_state.format) {
+                println!("{}", record);
+            }
+        }
+        Traction::QueryEvent(t) if t.command != "BEGIN" => {
+            if let Some(record) = out_state.boundary.commit(&out_state.gtid, None, out_state.timestamp, &out_state.format) {
+                println!("{}", record);
+            }
+        }
+        Traction::RowEvent(t, f) => {
+            match table_cols_info.get(db_tbl) {
+                Some(cols) => {
+                    for record in row_records(f, t, cols, db_tbl, map, &out_state.gtid, &out_state.format) {
+                        if let Some(begin) = out_state.boundary.begin(&out_state.gtid, out_state.timestamp, &out_state.format) {
+                            println!("{}", begin);
+                        }
+                        out_state.boundary.count(&record);
+                        println!("{}", record);
+                    }
+                }
+                None => {
+                    println!("内存中无字段信息: {},{:?}", db_tbl,table_cols_info);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 事务边界记录, 只在ValueFormat.boundaries时生成, 下游据此按事务还原原子性
+/// {"type":"begin","gtid":"uuid:1","timestamp":1572998400}
+/// {"type":"commit","gtid":"uuid:1","xid":123,"timestamp":1572998400,"rows":3,"insert":2,"update":1,"delete":0}
+/// 在事务的第一行数据之前生成begin, 没有输出行数据的事务(DDL、被过滤掉的事务)没有边界记录; 非事务表的COMMIT没有xid
+#[derive(Debug, Clone, Default)]
+pub struct Boundary{
+    open: bool,
+    insert: u64,
+    update: u64,
+    delete: u64,
+}
+
+impl Boundary {
+    /// 输出行数据之前调用, 事务的第一行之前返回begin记录
+    pub fn begin(&mut self, gtid: &str, timestamp: u32, format: &ValueFormat) -> Option<JsonValue> {
+        if !format.boundaries || self.open {
+            return None;
+        }
+        self.open = true;
+        let mut record = json!({"type": "begin", "gtid": gtid, "timestamp": timestamp});
+        format.labels.attach(&mut record);
+        Some(record)
+    }
+
+    /// 按行记录的type计数
+    pub fn count(&mut self, record: &JsonValue) {
+        self.count_type(record["type"].as_str().unwrap_or(""));
+    }
+
+    /// 按行的type(insert/update/delete)计数
+    pub fn count_type(&mut self, row_type: &str) {
+        match row_type {
+            "insert" => self.insert += 1,
+            "update" => self.update += 1,
+            "delete" => self.delete += 1,
+            _ => {}
+        }
+    }
+
+    /// 事务结束时调用, 已生成begin时返回commit记录
+    pub fn commit(&mut self, gtid: &str, xid: Option<u64>, timestamp: u32, format: &ValueFormat) -> Option<JsonValue> {
+        if !self.open {
+            return None;
+        }
+        let mut record = json!({
+            "type": "commit",
+            "gtid": gtid,
+            "xid": xid,
+            "timestamp": timestamp,
+            "rows": self.insert + self.update + self.delete,
+            "insert": self.insert,
+            "update": self.update,
+            "delete": self.delete,
+        });
+        format.labels.attach(&mut record);
+        *self = Boundary::default();
+        Some(record)
+    }
+}
+
+//将行事件转换为json对象, update事件每对前后镜像生成一个对象
+pub fn row_records(
+    row_values: &RowValue, code: &BinlogEvent,
+    cols: &[HashMap<String, String>],
+    db_tbl: &str, map: &TableMap, gtid: &str, format: &ValueFormat) -> Vec<JsonValue> {
+
+    let mut records = vec![];
+    match code {
+        BinlogEvent::UpdateEvent => {
+            for rows in row_values.rows.chunks(2) {
+                if rows.len() < 2 {
+                    continue;
+                }
+                let mut record = get_record_head("update", map, gtid, format);
+                record.insert(String::from("data"), get_row(&rows[1], cols, db_tbl, format));
+                record.insert(String::from("old"), get_row(&rows[0], cols, db_tbl, format));
+                if format.row_hash {
+                    record.insert(String::from("row_hash"), JsonValue::from(rowhash::row_hash(&rows[1], cols, map)));
+                    record.insert(String::from("old_row_hash"), JsonValue::from(rowhash::row_hash(&rows[0], cols, map)));
+                }
+                records.push(JsonValue::Object(record));
+            }
+        }
+        BinlogEvent::WriteEvent |
+        BinlogEvent::DeleteEvent => {
+            let row_type = if let BinlogEvent::WriteEvent = code {"insert"} else {"delete"};
+            for row in &row_values.rows {
+                let mut record = get_record_head(row_type, map, gtid, format);
+                record.insert(String::from("data"), get_row(row, cols, db_tbl, format));
+                if format.row_hash {
+                    record.insert(String::from("row_hash"), JsonValue::from(rowhash::row_hash(row, cols, map)));
+                }
+                records.push(JsonValue::Object(record));
+            }
+        }
+        _ => {}
+    }
+    records
+}
+
+fn get_record_head(row_type: &str, map: &TableMap, gtid: &str, format: &ValueFormat) -> Map<String, JsonValue> {
+    let mut record = Map::new();
+    record.insert(String::from("type"), JsonValue::from(row_type));
+    record.insert(String::from("database"), JsonValue::from(map.database_name.clone()));
+    record.insert(String::from("table"), JsonValue::from(map.table_name.clone()));
+    record.insert(String::from("gtid"), JsonValue::from(gtid));
+    if !format.labels.is_empty() {
+        record.insert(String::from("labels"), format.labels.to_json());
+    }
+    record
+}
+
+pub(crate) fn get_row(row: &[Option<MySQLValue>], cols: &[HashMap<String, String>], db_tbl: &str, format: &ValueFormat) -> JsonValue {
+    let mut values = Map::new();
+    for (idx, value) in row.iter().enumerate() {
+        let col = cols[idx].get("COLUMN_NAME").unwrap();
+        if !format.keep_column(db_tbl, col) {
+            continue;
+        }
+        let col_type = cols[idx].get("COLUMN_TYPE").unwrap();
+        let encoding = format.binary_encoding(db_tbl, col);
+        values.insert(col.clone(), format.json_value(value, col_type, encoding));
+    }
+    JsonValue::Object(values)
+}
+
+//字段值按TABLE_MAP_EVENT中的类型转换, 没有字符集信息, varchar/char按字符串输出, blob按format中的二进制编码输出
+fn row_json(row: &[Option<MySQLValue>], map: Option<&TableMap>, format: &ValueFormat) -> JsonValue {
+    JsonValue::Array(row.iter().enumerate().map(|(idx, value)| {
+        let col_type = match map.and_then(|m| m.column_info.get(idx)).map(|c| &c.column_type) {
+            Some(ColumnTypeDict::MysqlTypeVarchar) |
+            Some(ColumnTypeDict::MysqlTypeVarString) |
+            Some(ColumnTypeDict::MysqlTypeString) => "varchar",
+            Some(ColumnTypeDict::MysqlTypeGeometry) => "geometry",
+            _ => "blob"
+        };
+        format.json_value(value, col_type, format.binary_encoding)
+    }).collect())
+}
+
+/// 将一个事件转换为json对象(ffi、wasm接口使用), 包含type、event_type、timestamp、server_id、log_pos、next_position以及各类型的字段
+/// 行数据按字段顺序以数组返回, 没有字段名, update为{"before":[...],"after":[...]}
+pub fn event_json(event: &Event, format: &ValueFormat) -> JsonValue {
+    let mut obj = Map::new();
+    obj.insert(String::from("event_type"), json!(format!("{:?}", event.header.event_type)));
+    obj.insert(String::from("timestamp"), json!(event.header.timestamp));
+    obj.insert(String::from("server_id"), json!(event.header.server_id));
+    obj.insert(String::from("log_pos"), json!(event.log_pos));
+    obj.insert(String::from("next_position"), json!(event.header.next_position));
+    let kind = match &event.data {
+        Traction::GtidEvent(t) => {
+            obj.insert(String::from("gtid"), json!(format!("{}:{}", t.gtid, t.gno_id)));
+            obj.insert(String::from("last_committed"), json!(t.last_committed));
+            obj.insert(String::from("sequence_number"), json!(t.sequence_number));
+            "gtid"
+        }
+        Traction::QueryEvent(t) => {
+            obj.insert(String::from("thread_id"), json!(t.thread_id));
+            obj.insert(String::from("database"), json!(t.database));
+            obj.insert(String::from("query"), json!(t.command));
+            obj.insert(String::from("execute_seconds"), json!(t.execute_seconds));
+            "query"
+        }
+        Traction::TableMapEvent(t) => {
+            obj.insert(String::from("database"), json!(t.database_name));
+            obj.insert(String::from("table"), json!(t.table_name));
+            "table_map"
+        }
+        Traction::RowEvent(code, rows) => {
+            let map = event.table.as_ref();
+            if let Some(map) = map {
+                obj.insert(String::from("database"), json!(map.database_name));
+                obj.insert(String::from("table"), json!(map.table_name));
+            }
+            let rows = rows.rows();
+            let (kind, values): (&str, Vec<JsonValue>) = match code {
+                BinlogEvent::UpdateEvent => {
+                    //前后镜像依次排列
+                    ("update", rows.chunks(2).map(|pair| json!({
+                        "before": row_json(&pair[0], map, format),
+                        "after": pair.get(1).map(|t| row_json(t, map, format))
+                    })).collect())
+                }
+                BinlogEvent::WriteEvent => ("insert", rows.iter().map(|r| row_json(r, map, format)).collect()),
+                _ => ("delete", rows.iter().map(|r| row_json(r, map, format)).collect()),
+            };
+            obj.insert(String::from("rows"), JsonValue::Array(values));
+            kind
+        }
+        Traction::XidEvent(t) => {
+            obj.insert(String::from("xid"), json!(t.xid));
+            "xid"
+        }
+        Traction::RotateLogEvent(t) => {
+            obj.insert(String::from("binlog_file"), json!(t.binlog_file));
+            obj.insert(String::from("position"), json!(t.position));
+            "rotate"
+        }
+        Traction::StopEvent => "stop",
+        Traction::RowsQueryEvent(t) => {
+            obj.insert(String::from("query"), json!(t.query));
+            "rows_query"
+        }
+        _ => "other"
+    };
+    obj.insert(String::from("type"), json!(kind));
+    let mut value = JsonValue::Object(obj);
+    format.labels.attach(&mut value);
+    value
+}
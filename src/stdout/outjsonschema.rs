@@ -0,0 +1,157 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//json输出(--outformat json、JsonLinesSink、RegistrySink)每个表的行数据对应的JSON Schema(draft-07), 下游用于校验及生成代码
+//  外层为type、database、table、gtid, 配置了--labels时带labels, --row-hash时带row_hash、old_row_hash, update时必须有old
+//  data、old的字段及类型与ValueFormat::json_value一致, 按include_columns、exclude_columns过滤
+//  information_schema中IS_NULLABLE为NO的字段不为null, 但--zerodate null时的日期时间、--nonfinite null时的float/double仍可能为null;
+//  没有字段信息(TABLE_MAP的@1、@2...)时都可以为null
+//  --boundaries时的begin、commit记录不属于某个表, 不在schema中
+//  mytest schema按--tables查询表结构输出, 控制接口GET /schema[?table=db.tbl]返回已读取到的表的schema
+
+use std::collections::HashMap;
+use serde_json::{json, Map, Value as JsonValue};
+use crate::stdout::format::{NonFinite, TimeZoneMode, ValueFormat, ZeroDate};
+use crate::spatial::{self, SpatialFormat};
+
+/// 表的行数据(与--outformat json相同)对应的JSON Schema, 字段按format的include_columns、exclude_columns过滤
+pub fn json_schema(db_tbl: &str, cols: &[HashMap<String, String>], format: &ValueFormat) -> JsonValue {
+    let mut properties = Map::new();
+    let mut required = vec![];
+    for col in cols {
+        let name = col.get("COLUMN_NAME").map(|t| t.as_str()).unwrap_or("");
+        if !format.keep_column(db_tbl, name) {
+            continue;
+        }
+        let col_type = col.get("COLUMN_TYPE").map(|t| t.as_str()).unwrap_or("");
+        let nullable = col.get("IS_NULLABLE").map(|t| t != "NO").unwrap_or(true);
+        properties.insert(name.to_string(), column_schema(col_type, nullable, format));
+        required.push(name.to_string());
+    }
+    let row = json!({"type": "object", "properties": properties, "required": required, "additionalProperties": false});
+    let mut schema = json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": db_tbl,
+        "type": "object",
+        "properties": {
+            "type": {"type": "string", "enum": ["insert", "update", "delete"]},
+            "database": {"type": "string"},
+            "table": {"type": "string"},
+            "gtid": {"type": "string"},
+            "data": row,
+            "old": row,
+        },
+        "required": ["type", "database", "table", "gtid", "data"],
+        "if": {"properties": {"type": {"const": "update"}}},
+        "then": {"required": ["old"]},
+    });
+    if !format.labels.is_empty() {
+        //标签为静态的值
+        let labels = format.labels.to_json();
+        let keys: Vec<String> = labels.as_object().map(|t| t.keys().cloned().collect()).unwrap_or_default();
+        let properties: Map<String, JsonValue> = keys.iter().map(|k| (k.clone(), json!({"type": "string", "const": labels[k]}))).collect();
+        schema["properties"]["labels"] = json!({"type": "object", "properties": properties, "required": keys});
+        push_required(&mut schema["required"], "labels");
+    }
+    if format.row_hash {
+        schema["properties"]["row_hash"] = json!({"type": "string"});
+        schema["properties"]["old_row_hash"] = json!({"type": "string"});
+        push_required(&mut schema["required"], "row_hash");
+        push_required(&mut schema["then"]["required"], "old_row_hash");
+    }
+    schema
+}
+
+fn push_required(required: &mut JsonValue, name: &str) {
+    if let Some(t) = required.as_array_mut() {
+        t.push(JsonValue::from(name));
+    }
+}
+
+//字段值的类型与ValueFormat::json_value一致, 没有类型信息及json字段不限制类型
+fn column_schema(col_type: &str, nullable: bool, format: &ValueFormat) -> JsonValue {
+    let base = col_type.split(['(', ' ']).next().unwrap_or("").to_ascii_lowercase();
+    let mut nullable = nullable;
+    let mut types = match base.as_str() {
+        "tinyint" | "smallint" | "mediumint" | "int" | "integer" | "bigint" | "year" | "enum" | "set" => vec!["integer"],
+        "float" | "double" | "real" => match format.nonfinite {
+            NonFinite::Null => {
+                nullable = true;
+                vec!["number"]
+            }
+            NonFinite::Text => vec!["number", "string"],
+            NonFinite::Error => vec!["number"],
+        },
+        "decimal" | "numeric" if format.quote_decimal => vec!["string"],
+        "decimal" | "numeric" => vec!["number"],
+        "timestamp" if matches!(format.timezone, TimeZoneMode::Epoch) => vec!["number"],
+        "" | "json" => return json!({}),
+        _ if spatial::is_spatial_type(col_type) && format.spatial == SpatialFormat::GeoJson => vec!["object"],
+        _ => vec!["string"]
+    };
+    if matches!(base.as_str(), "date" | "datetime" | "timestamp") && format.zerodate == ZeroDate::Null {
+        nullable = true;
+    }
+    if nullable {
+        types.push("null");
+    }
+    match types.len() {
+        1 => json!({"type": types[0]}),
+        _ => json!({"type": types}),
+    }
+}
+
+/// 多个表的schema, 以db.tbl为key
+pub fn json_schemas<'a, I>(tables: I, format: &ValueFormat) -> JsonValue
+    where I: IntoIterator<Item = (&'a String, &'a Vec<HashMap<String, String>>)> {
+    let mut schemas = Map::new();
+    for (db_tbl, cols) in tables {
+        schemas.insert(db_tbl.clone(), json_schema(db_tbl, cols, format));
+    }
+    JsonValue::Object(schemas)
+}
+
+/// schema子命令: 按--tables连接mysql查询表结构, 输出各表的JSON Schema, 格式选项与stream/parse相同
+#[cfg(feature = "network")]
+pub fn run(conf: &crate::Config) {
+    use std::collections::BTreeMap;
+    use std::process;
+    use crate::exitcode;
+    use crate::stdout::sql_escape;
+    let format = ValueFormat::new(conf).unwrap_or_else(|err|{
+        println!("{}", err);
+        process::exit(exitcode::USAGE);
+    });
+    let tbl_info = crate::replication::grep::parse_greptbl(&conf.greptbl);
+    let mut tables = vec![];
+    for (db, tbls) in tbl_info.as_object().into_iter().flatten() {
+        match tbls {
+            JsonValue::String(t) if t == "all" => {
+                let mut conn = crate::create_conn(conf);
+                let sql = format!("select TABLE_NAME from information_schema.tables where table_schema = {} and TABLE_TYPE = 'BASE TABLE' order by TABLE_NAME;", sql_escape::quote_string(db));
+                for row in crate::io::command::execute(&mut conn, &sql) {
+                    tables.push((db.clone(), row.get("TABLE_NAME").cloned().unwrap_or_default()));
+                }
+            }
+            JsonValue::Array(t) => tables.extend(t.iter().filter_map(|v| v.as_str()).map(|v| (db.clone(), v.to_string()))),
+            _ => {}
+        }
+    }
+    if tables.is_empty() {
+        println!("--tables中没有需要输出的表");
+        process::exit(exitcode::USAGE);
+    }
+    let mut table_cols_info = HashMap::new();
+    for (db, tb) in &tables {
+        crate::meta::get_col(conf, db, tb, &mut table_cols_info);
+        if !table_cols_info.contains_key(&format!("{}.{}", db, tb)) {
+            println!("表{}.{}不存在", db, tb);
+            process::exit(exitcode::USAGE);
+        }
+    }
+    let sorted: BTreeMap<&String, &Vec<HashMap<String, String>>> = table_cols_info.iter().collect();
+    let schemas = json_schemas(sorted, &format);
+    println!("{}", serde_json::to_string_pretty(&schemas).unwrap_or_default());
+}
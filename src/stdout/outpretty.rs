@@ -0,0 +1,231 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//默认的输出格式: 每个事件一行, 时间、gtid(uuid前8位:gno)、库表、操作、行数、字节数, 排查问题时便于浏览
+//  -v时在该行之后逐行输出数据, update输出变化的字段
+//  --color auto(默认)在stdout为终端且没有设置NO_COLOR时输出颜色, always、never
+//  BEGIN、TABLE_MAP等事件不输出, gtid显示在之后的行数据中
+
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use crate::replication::readevent::{BinlogEvent, TableMap};
+use crate::replication::parsevalue::MySQLValue;
+use crate::replication::readbinlog::Traction;
+use crate::stdout::OutState;
+use crate::stdout::format::{self, ValueFormat};
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+
+//-v时单个字段值最多输出的字符数
+const MAX_VALUE_CHARS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorMode{
+    Auto,
+    Always,
+    Never
+}
+
+impl ColorMode{
+    pub fn new(mode: &str) -> Result<ColorMode, String> {
+        match mode {
+            "" | "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!("无效的color: {}, 可选值为auto、always、never", mode))
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal(),
+        }
+    }
+}
+
+/// 事件的时间、gtid、长度等由调用方提供
+pub struct EventInfo<'a>{
+    pub timestamp: u32,
+    pub gtid: &'a str,
+    pub event_length: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pretty{
+    pub color: bool,
+    pub verbose: bool,
+}
+
+impl Pretty {
+    pub fn new(color: ColorMode, verbose: bool) -> Pretty {
+        Pretty{ color: color.enabled(), verbose }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", code, text, RESET)
+        }else {
+            text.to_string()
+        }
+    }
+
+    //uuid:gno输出为uuid前8位:gno
+    fn short_gtid(gtid: &str) -> String {
+        match gtid.rsplit_once(':') {
+            Some((uuid, gno)) => format!("{}:{}", uuid.get(..8).unwrap_or(uuid), gno),
+            None => gtid.to_string()
+        }
+    }
+
+    fn time(&self, timestamp: u32, format: &ValueFormat) -> String {
+        format.timestamp(timestamp as i32, 0, 0).unwrap_or_else(|| format::utc_datetime(timestamp))
+    }
+
+    fn size(length: u32) -> String {
+        match length {
+            0..=1023 => format!("{}B", length),
+            1024..=1048575 => format!("{:.1}K", length as f64 / 1024.0),
+            _ => format!("{:.1}M", length as f64 / 1048576.0)
+        }
+    }
+
+    fn line(&self, info: &EventInfo, format: &ValueFormat, target: &str, op: (&str, &str), detail: &str) -> String {
+        let gtid = if info.gtid.is_empty() { String::from("-") } else { Pretty::short_gtid(info.gtid) };
+        format!("{} {} {} {} {} {}",
+                self.paint(DIM, &self.time(info.timestamp, format)),
+                self.paint(CYAN, &format!("{:<12}", gtid)),
+                self.paint(BOLD, &format!("{:<24}", target)),
+                self.paint(op.1, &format!("{:<6}", op.0)),
+                detail,
+                self.paint(DIM, &Pretty::size(info.event_length)))
+    }
+
+    /// 一个事件输出的各行, 不需要输出的事件返回空
+    pub fn render(&self, data: &Traction, cols: &[HashMap<String, String>], db_tbl: &str, info: &EventInfo, format: &ValueFormat) -> Vec<String> {
+        let mut lines = vec![];
+        match data {
+            Traction::RowEvent(code, rows) => {
+                let (op, color, count) = match code {
+                    BinlogEvent::WriteEvent => ("INSERT", GREEN, rows.rows.len()),
+                    BinlogEvent::UpdateEvent => ("UPDATE", YELLOW, rows.rows.len() / 2),
+                    BinlogEvent::DeleteEvent => ("DELETE", RED, rows.rows.len()),
+                    _ => return lines
+                };
+                let unit = if count == 1 { "row" } else { "rows" };
+                lines.push(self.line(info, format, db_tbl, (op, color), &format!("{:>5} {:<4}", count, unit)));
+                if self.verbose {
+                    self.expand(&mut lines, code, &rows.rows, cols, db_tbl, format);
+                }
+            }
+            Traction::QueryEvent(t) if t.command != "BEGIN" => {
+                let (op, color) = if t.command == "COMMIT" { ("COMMIT", DIM) } else { ("QUERY", MAGENTA) };
+                let mut sql: String = t.command.split_whitespace().collect::<Vec<&str>>().join(" ");
+                if !self.verbose && sql.chars().count() > 80 {
+                    sql = format!("{}...", sql.chars().take(77).collect::<String>());
+                }
+                lines.push(self.line(info, format, &t.database, (op, color), &sql));
+            }
+            Traction::XidEvent(t) => {
+                lines.push(self.line(info, format, "", ("COMMIT", DIM), &format!("xid={}", t.xid)));
+            }
+            Traction::RotateLogEvent(t) if !t.artificial => {
+                lines.push(self.line(info, format, &t.binlog_file, ("ROTATE", DIM), &format!("position={}", t.position)));
+            }
+            Traction::RowsQueryEvent(t) if self.verbose => {
+                lines.push(format!("    {}", self.paint(DIM, &format!("# {}", t.query))));
+            }
+            Traction::UnknownEvent(t) => {
+                lines.push(self.line(info, format, "", ("?", RED), &format!("type_code={}", t.type_code)));
+            }
+            _ => {}
+        }
+        lines
+    }
+
+    fn value(value: &Option<MySQLValue>, col: &HashMap<String, String>, db_tbl: &str, format: &ValueFormat) -> String {
+        let col_type = col.get("COLUMN_TYPE").map(|t| t.as_str()).unwrap_or("");
+        let name = col.get("COLUMN_NAME").map(|t| t.as_str()).unwrap_or("");
+        match format.text_value(value, col_type, format.binary_encoding(db_tbl, name)) {
+            Some(t) if t.chars().count() > MAX_VALUE_CHARS => format!("'{}...'", t.chars().take(MAX_VALUE_CHARS).collect::<String>()),
+            Some(t) => match value {
+                Some(MySQLValue::SignedInteger(_)) | Some(MySQLValue::Float(_)) | Some(MySQLValue::Double(_)) |
+                Some(MySQLValue::Decimal(_)) | Some(MySQLValue::Year(_)) => t,
+                _ => format!("'{}'", t)
+            },
+            None => String::from("NULL")
+        }
+    }
+
+    //-v: insert、delete输出所有字段, update输出主键及变化的字段
+    fn expand(&self, lines: &mut Vec<String>, code: &BinlogEvent, rows: &[Vec<Option<MySQLValue>>], cols: &[HashMap<String, String>], db_tbl: &str, format: &ValueFormat) {
+        let keep = |idx: usize| -> Option<&HashMap<String, String>> {
+            let col = cols.get(idx)?;
+            let name = col.get("COLUMN_NAME").map(|t| t.as_str()).unwrap_or("");
+            if format.keep_column(db_tbl, name) { Some(col) } else { None }
+        };
+        let name = |col: &HashMap<String, String>| col.get("COLUMN_NAME").cloned().unwrap_or_default();
+        match code {
+            BinlogEvent::UpdateEvent => {
+                for pair in rows.chunks(2) {
+                    if pair.len() < 2 {
+                        continue;
+                    }
+                    let mut fields = vec![];
+                    for (idx, (before, after)) in pair[0].iter().zip(pair[1].iter()).enumerate() {
+                        let col = match keep(idx) {
+                            Some(t) => t,
+                            None => continue
+                        };
+                        let primary = col.get("COLUMN_KEY").map(|t| t == "PRI").unwrap_or(false);
+                        if before != after {
+                            fields.push(format!("{}={} -> {}", name(col),
+                                                self.paint(RED, &Pretty::value(before, col, db_tbl, format)),
+                                                self.paint(GREEN, &Pretty::value(after, col, db_tbl, format))));
+                        }else if primary {
+                            fields.push(format!("{}={}", name(col), Pretty::value(before, col, db_tbl, format)));
+                        }
+                    }
+                    lines.push(format!("    {}", fields.join(", ")));
+                }
+            }
+            _ => {
+                for row in rows {
+                    let fields: Vec<String> = row.iter().enumerate().filter_map(|(idx, value)| {
+                        keep(idx).map(|col| format!("{}={}", name(col), Pretty::value(value, col, db_tbl, format)))
+                    }).collect();
+                    lines.push(format!("    {}", fields.join(", ")));
+                }
+            }
+        }
+    }
+}
+
+pub fn out_pretty(data: &Traction, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>, db_tbl: &String, map: &TableMap, out_state: &mut OutState) {
+    if let Traction::GtidEvent(t) = data {
+        out_state.gtid = format!("{}:{}", t.gtid, t.gno_id);
+        return;
+    }
+    let default_cols;
+    let cols = match table_cols_info.get(db_tbl) {
+        Some(t) => t,
+        None => {
+            default_cols = crate::sink::default_columns(map);
+            &default_cols
+        }
+    };
+    let info = EventInfo{ timestamp: out_state.timestamp, gtid: &out_state.gtid, event_length: out_state.event_length };
+    for line in out_state.pretty.render(data, cols, db_tbl, &info, &out_state.format) {
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,204 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//analyze --retention: 根据分析的binlog文件的写入速度及mysql上binlog的保留设置(实时查询),
+//  估算当前保留的binlog可以恢复/回滚到多早的时间, 以及需要的binlog文件(--need-binlog, 默认为分析的文件)预计何时被清理
+//mysql按文件最后写入的时间清理: binlog_expire_logs_seconds(8.0, 为0时使用expire_logs_days)、expire_logs_days(5.7),
+//  binlog_expire_logs_auto_purge为OFF时不会自动清理
+//文件的结束时间: 分析的文件使用最后一个事件的时间, 其余按之后的文件大小及写入速度估算; 当前正在写入的文件按max_binlog_size估算切换时间
+
+use std::path::Path;
+#[cfg(feature = "network")]
+use std::net::TcpStream;
+use crate::Config;
+use crate::replication::readbinlog::Traction;
+use crate::stdout::format;
+
+/// mysql上binlog的保留设置及现有的文件
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionSettings{
+    /// 过期时间(秒), 0为不过期
+    pub expire_seconds: u64,
+    pub auto_purge: bool,
+    pub max_binlog_size: u64,
+    /// SHOW BINARY LOGS的文件名及大小, 按顺序
+    pub binlogs: Vec<(String, u64)>,
+}
+
+#[cfg(feature = "network")]
+impl RetentionSettings{
+    pub fn query(conn: &mut TcpStream) -> Result<RetentionSettings, String> {
+        use crate::io::command::try_execute;
+        let value = |conn: &mut TcpStream, sql: &str| -> Option<String> {
+            try_execute(conn, sql).ok()?.first()?.values().next().cloned()
+        };
+        //5.7没有binlog_expire_logs_seconds, 8.0.29之前没有binlog_expire_logs_auto_purge
+        let seconds = value(conn, "select @@global.binlog_expire_logs_seconds;").and_then(|t| t.parse::<u64>().ok()).unwrap_or(0);
+        let days = value(conn, "select @@global.expire_logs_days;").and_then(|t| t.parse::<u64>().ok()).unwrap_or(0);
+        let auto_purge = value(conn, "select @@global.binlog_expire_logs_auto_purge;").map(|t| t != "0").unwrap_or(true);
+        let max_binlog_size = value(conn, "select @@global.max_binlog_size;").and_then(|t| t.parse::<u64>().ok()).unwrap_or(0);
+        let binlogs = try_execute(conn, "show binary logs;")?.iter().map(|row| {
+            (row.get("Log_name").cloned().unwrap_or_default(), row.get("File_size").and_then(|t| t.parse().ok()).unwrap_or(0))
+        }).collect();
+        Ok(RetentionSettings{
+            expire_seconds: if seconds > 0 { seconds } else { days * 86400 },
+            auto_purge,
+            max_binlog_size,
+            binlogs,
+        })
+    }
+}
+
+/// 估算结果, 时间为unix秒
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionAdvice{
+    /// 写入速度(字节/秒), 分析的文件时间跨度为0时为空
+    pub write_rate: Option<f64>,
+    pub retained_bytes: u64,
+    /// 保留的binlog按写入速度覆盖的时间
+    pub retained_seconds: Option<u64>,
+    /// 最早可以恢复/回滚到的时间
+    pub recoverable_since: Option<u64>,
+    /// 需要的binlog文件预计被清理的时间, 已被清理或不会自动清理时为空
+    pub need_purge_at: Option<u64>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct RetentionReport{
+    conf: Config,
+    file: String,
+    need: String,
+    first_ts: u32,
+    last_ts: u32,
+    first_pos: Option<u64>,
+    bytes: u64,
+    transactions: u64,
+}
+
+impl RetentionReport{
+    pub fn new(conf: &Config) -> RetentionReport {
+        let file = Path::new(&conf.file).file_name().map(|t| t.to_string_lossy().to_string()).unwrap_or_default();
+        let need = if conf.needbinlog.is_empty() { file.clone() } else { conf.needbinlog.clone() };
+        RetentionReport{ conf: conf.clone(), file, need, first_ts: 0, last_ts: 0, first_pos: None, bytes: 0, transactions: 0 }
+    }
+
+    /// timestamp、log_pos、event_length为当前事件的时间、位置及字节数, 字节数按位置计算, 不受过滤条件影响
+    pub fn record(&mut self, data: &Traction, timestamp: u32, log_pos: u64, event_length: u32) {
+        if timestamp > 0 {
+            if self.first_ts == 0 {
+                self.first_ts = timestamp;
+            }
+            self.last_ts = self.last_ts.max(timestamp);
+        }
+        let first_pos = *self.first_pos.get_or_insert(log_pos);
+        self.bytes = self.bytes.max((log_pos + event_length as u64).saturating_sub(first_pos));
+        if let Traction::XidEvent(_) = data {
+            self.transactions += 1;
+        }
+    }
+
+    pub fn advise(&self, settings: &RetentionSettings, now: u64) -> RetentionAdvice {
+        let mut advice = RetentionAdvice::default();
+        let duration = self.last_ts.saturating_sub(self.first_ts);
+        advice.write_rate = if duration > 0 && self.bytes > 0 { Some(self.bytes as f64 / duration as f64) } else { None };
+        advice.retained_bytes = settings.binlogs.iter().map(|(_, size)| size).sum();
+        advice.retained_seconds = advice.write_rate.map(|rate| (advice.retained_bytes as f64 / rate) as u64);
+        let purging = settings.auto_purge && settings.expire_seconds > 0;
+        let window = match (advice.retained_seconds, purging) {
+            (Some(t), true) => Some(t.min(settings.expire_seconds)),
+            (None, true) => Some(settings.expire_seconds),
+            (t, false) => t,
+        };
+        advice.recoverable_since = window.map(|t| now.saturating_sub(t));
+        if !purging {
+            advice.warnings.push(String::from("没有设置自动清理(过期时间为0或binlog_expire_logs_auto_purge=OFF), 需要手动清理, 注意磁盘空间"));
+        }
+        if let Some(window) = window.filter(|t| *t < 86400) {
+            advice.warnings.push(format!("只能恢复/回滚最近{}的数据", human_duration(window)));
+        }
+
+        let idx = match settings.binlogs.iter().position(|(name, _)| *name == self.need) {
+            Some(t) => t,
+            None => {
+                advice.warnings.push(format!("需要的binlog文件{}已经被清理或不在该mysql上", self.need));
+                return advice;
+            }
+        };
+        if !purging {
+            return advice;
+        }
+        let last = settings.binlogs.len() - 1;
+        let end_time = if idx == last {
+            //当前正在写入的文件, 写满max_binlog_size后切换
+            let left = settings.max_binlog_size.saturating_sub(settings.binlogs[last].1);
+            Some(now + advice.write_rate.map(|rate| (left as f64 / rate) as u64).unwrap_or(0))
+        } else if self.need == self.file && self.last_ts > 0 {
+            Some(self.last_ts as u64)
+        } else {
+            let after: u64 = settings.binlogs[idx + 1..].iter().map(|(_, size)| size).sum();
+            advice.write_rate.map(|rate| now.saturating_sub((after as f64 / rate) as u64))
+        };
+        advice.need_purge_at = end_time.map(|t| t + settings.expire_seconds);
+        match advice.need_purge_at {
+            Some(t) if t <= now => advice.warnings.push(format!("需要的binlog文件{}已经过期, 随时可能被清理", self.need)),
+            Some(t) if t - now < 86400 => advice.warnings.push(format!("需要的binlog文件{}预计{}后被清理", self.need, human_duration(t - now))),
+            _ => {}
+        }
+        advice
+    }
+
+    /// 连接mysql查询保留设置后输出报告
+    #[cfg(feature = "network")]
+    pub fn print(&self) {
+        let mut conn = crate::create_conn(&self.conf);
+        let settings = match RetentionSettings::query(&mut conn) {
+            Ok(t) => t,
+            Err(err) => {
+                println!("查询binlog保留设置发生错误: {}", err);
+                return;
+            }
+        };
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+        let advice = self.advise(&settings, now);
+        let time = |t: u64| format::utc_datetime(t as u32);
+        println!();
+        println!("-- binlog保留情况(时间为UTC)");
+        match advice.write_rate {
+            Some(rate) => println!("写入速度: {:.0} bytes/s ({}: {} - {}, {} bytes, {} transactions)", rate, self.file, time(self.first_ts as u64), time(self.last_ts as u64), self.bytes, self.transactions),
+            None => println!("写入速度: 未知({}的时间跨度为0)", self.file),
+        }
+        print!("保留的binlog: {}个文件, {} bytes", settings.binlogs.len(), advice.retained_bytes);
+        match advice.retained_seconds {
+            Some(t) => println!(", 约{}", human_duration(t)),
+            None => println!(),
+        }
+        if settings.auto_purge && settings.expire_seconds > 0 {
+            println!("过期时间: {}", human_duration(settings.expire_seconds));
+        } else {
+            println!("过期时间: 不自动清理");
+        }
+        if let Some(t) = advice.recoverable_since {
+            println!("最早可以恢复/回滚到: 约{}", time(t));
+        }
+        match advice.need_purge_at {
+            Some(t) => println!("需要的binlog文件{}预计清理时间: {}", self.need, time(t)),
+            None => println!("需要的binlog文件: {}", self.need),
+        }
+        for warning in &advice.warnings {
+            println!("警告: {}", warning);
+        }
+    }
+}
+
+/// 1d2h3m形式的时长
+pub fn human_duration(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h{}m", hours, minutes),
+        _ => format!("{}d{}h{}m", days, hours, minutes),
+    }
+}
@@ -0,0 +1,124 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--schemaout: 表结构变化时输出表结构文档(每行一个json对象), 下游可以与数据同步调整自己的表结构
+//  {"type":"schema","database":"db","table":"tb","columns":[{"name":"id","type":"int(11)","key":"PRI"}...],
+//   "primary_key":["id"],"ddl":"ALTER TABLE ...","gtid":"uuid:12","position":1234,"timestamp":1572998400}
+//  表第一次出现时输出一次(ddl为null), 之后遇到涉及该表的DDL时清除缓存的表结构, 下一次TABLE_MAP重新获取后输出
+//  DROP TABLE、RENAME的原表输出columns为null、dropped为true的文档
+//  表结构从information_schema获取, 解析历史binlog时为当前的表结构
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use serde_json::{json, Value as JsonValue};
+use crate::replication::readbinlog::Traction;
+use crate::stdout::outddl;
+
+//等待表结构重新获取的DDL
+struct PendingDdl{
+    ddl: String,
+    gtid: String,
+    position: u64,
+    timestamp: u32,
+}
+
+pub struct SchemaLog{
+    path: String,
+    writer: Box<dyn Write>,
+    gtid: String,
+    published: HashMap<String, Vec<HashMap<String, String>>>,
+    pending: HashMap<String, PendingDdl>,
+}
+
+impl SchemaLog{
+    //-为输出到stdout
+    pub fn new(path: &str) -> Result<SchemaLog, String> {
+        let writer: Box<dyn Write> = if path == "-" {
+            Box::new(io::stdout())
+        }else {
+            Box::new(BufWriter::new(File::create(path).map_err(|e| format!("创建文件({})发生错误:{}", path, e))?))
+        };
+        Ok(SchemaLog::with_writer(path, writer))
+    }
+
+    pub fn with_writer(path: &str, writer: Box<dyn Write>) -> SchemaLog {
+        SchemaLog{ path: path.to_string(), writer, gtid: String::new(), published: HashMap::new(), pending: HashMap::new() }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// DDL时清除table_cols_info中涉及的表, TABLE_MAP时与已输出的表结构比较, 不同时输出
+    pub fn record(&mut self, data: &Traction, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,
+                  db_tbl: &str, position: u64, timestamp: u32) -> io::Result<()> {
+        match data {
+            Traction::GtidEvent(t) => {
+                self.gtid = format!("{}:{}", t.gtid, t.gno_id);
+            }
+            Traction::QueryEvent(t) if outddl::is_ddl(&t.command) => {
+                let ddl = t.command.trim_end().trim_end_matches(';').to_string();
+                for (table, dropped) in outddl::ddl_tables(&t.command, &t.database) {
+                    table_cols_info.remove(&table);
+                    if dropped {
+                        self.published.remove(&table);
+                        self.pending.remove(&table);
+                        let doc = schema_document(&table, None, Some(&ddl), &self.gtid, position, timestamp);
+                        writeln!(self.writer, "{}", doc)?;
+                    } else {
+                        self.pending.insert(table, PendingDdl{ ddl: ddl.clone(), gtid: self.gtid.clone(), position, timestamp });
+                    }
+                }
+            }
+            Traction::TableMapEvent(_) => {
+                let cols = match table_cols_info.get(db_tbl) {
+                    Some(t) => t,
+                    None => return Ok(())
+                };
+                if self.published.get(db_tbl) == Some(cols) && !self.pending.contains_key(db_tbl) {
+                    return Ok(());
+                }
+                let doc = match self.pending.remove(db_tbl) {
+                    Some(p) => schema_document(db_tbl, Some(cols.as_slice()), Some(&p.ddl), &p.gtid, p.position, p.timestamp),
+                    None => schema_document(db_tbl, Some(cols.as_slice()), None, &self.gtid, position, timestamp),
+                };
+                writeln!(self.writer, "{}", doc)?;
+                self.published.insert(db_tbl.to_string(), cols.clone());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// 表结构文档, cols为meta::get_col获取的字段信息(COLUMN_NAME、COLUMN_TYPE、COLUMN_KEY), None为表已删除
+pub fn schema_document(db_tbl: &str, cols: Option<&[HashMap<String, String>]>, ddl: Option<&str>, gtid: &str, position: u64, timestamp: u32) -> JsonValue {
+    let (database, table) = db_tbl.split_once('.').unwrap_or(("", db_tbl));
+    let field = |c: &HashMap<String, String>, name: &str| c.get(name).cloned().unwrap_or_default();
+    let mut doc = json!({
+        "type": "schema",
+        "database": database,
+        "table": table,
+        "columns": cols.map(|cols| cols.iter().map(|c| json!({
+            "name": field(c, "COLUMN_NAME"),
+            "type": field(c, "COLUMN_TYPE"),
+            "key": field(c, "COLUMN_KEY"),
+        })).collect::<Vec<_>>()),
+        "primary_key": cols.map(|cols| cols.iter().filter(|c| field(c, "COLUMN_KEY") == "PRI").map(|c| field(c, "COLUMN_NAME")).collect::<Vec<_>>()),
+        "ddl": ddl,
+        "gtid": if gtid.is_empty() { None } else { Some(gtid) },
+        "position": position,
+        "timestamp": timestamp,
+    });
+    if cols.is_none() {
+        doc["dropped"] = JsonValue::Bool(true);
+    }
+    doc
+}
@@ -6,23 +6,85 @@
 use crate::replication::parsevalue::{MySQLValue};
 use crate::replication::readevent::{BinlogEvent,TableMap};
 use std::collections::HashMap;
-use std::str::from_utf8;
-use bigdecimal::ToPrimitive;
+use crate::stdout::sql_escape;
+use crate::stdout::format::ValueFormat;
+use crate::stdout::dialect::{Dialect, Temporal};
+use crate::meta;
 
 enum GetType{
     GetWhere,
     GetSet
 }
 
+//insert语句生成方式, Replace和Upsert生成的语句可以重复执行，目标库已存在部分数据时不会因主键冲突而失败
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InsertMode{
+    Insert,
+    Replace,            //REPLACE INTO
+    Upsert              //INSERT ... ON DUPLICATE KEY UPDATE
+}
+
+impl InsertMode{
+    pub fn new(mode: &str) -> Result<InsertMode, String> {
+        match mode {
+            "" | "insert" => Ok(InsertMode::Insert),
+            "replace" => Ok(InsertMode::Replace),
+            "upsert" => Ok(InsertMode::Upsert),
+            _ => Err(format!("无效的insertmode: {}, 可选值为insert、replace、upsert", mode))
+        }
+    }
+
+    //ansi没有replace、upsert对应的语法, postgresql没有replace
+    pub fn check(&self, dialect: Dialect) -> Result<(), String> {
+        match (self, dialect) {
+            (InsertMode::Replace, Dialect::Ansi) | (InsertMode::Replace, Dialect::Postgresql) | (InsertMode::Upsert, Dialect::Ansi) => {
+                Err(format!("dialect为{}时不支持insertmode {:?}", dialect.name(), self).to_lowercase())
+            }
+            _ => Ok(())
+        }
+    }
+
+    fn prefix(&self, dialect: Dialect) -> &'static str {
+        match (self, dialect) {
+            (InsertMode::Replace, Dialect::Sqlite) => "INSERT OR REPLACE INTO",
+            (InsertMode::Replace, _) => "REPLACE INTO",
+            _ => "INSERT INTO"
+        }
+    }
+
+    //upsert模式下追加在values之后的ON DUPLICATE KEY UPDATE部分, 其他模式为空
+    //sqlite、postgresql为ON CONFLICT(主键) DO UPDATE, 没有主键时无法指定冲突的字段, 为DO NOTHING
+    fn suffix(&self, table_cols_info: &[HashMap<String, String>], dialect: Dialect) -> String {
+        if *self != InsertMode::Upsert {
+            return String::from("");
+        }
+        let cols = table_cols_info.iter().filter(|col_info| !meta::is_generated(col_info))
+            .map(|col_info| dialect.quote_identifier(col_info.get("COLUMN_NAME").unwrap()));
+        if dialect == Dialect::Mysql {
+            let cols: Vec<String> = cols.map(|col| format!("{}=VALUES({})", col, col)).collect();
+            return format!(" ON DUPLICATE KEY UPDATE {}", cols.join(", "));
+        }
+        let keys: Vec<String> = table_cols_info.iter().filter(|col_info| col_info.get("COLUMN_KEY").map(|t| t == "PRI").unwrap_or(false))
+            .map(|col_info| dialect.quote_identifier(col_info.get("COLUMN_NAME").unwrap()))
+            .collect();
+        if keys.is_empty() {
+            return String::from(" ON CONFLICT DO NOTHING");
+        }
+        let cols: Vec<String> = cols.map(|col| format!("{}=EXCLUDED.{}", col, col)).collect();
+        format!(" ON CONFLICT ({}) DO UPDATE SET {}", keys.join(", "), cols.join(", "))
+    }
+}
+
 
 pub fn out_delete(
     row_value: &Vec<Option<MySQLValue>>,
     table_cols_info: &Vec<HashMap<String, String>>,
     pri_info: &HashMap<String, usize>,
-    map: &TableMap) -> String {
+    map: &TableMap,
+    format: &ValueFormat) -> String {
 
-    let mut sql = format!("DELETE FROM {}.{} ", map.database_name, map.table_name);
-    let where_str = get_where_str(row_value, table_cols_info, pri_info);
+    let mut sql = format!("DELETE FROM {} ", format.dialect.quote_table(&map.database_name, &map.table_name));
+    let where_str = get_where_str(row_value, table_cols_info, pri_info, format);
     sql.push_str(&where_str);
     sql
 }
@@ -30,95 +92,166 @@ pub fn out_delete(
 pub fn out_insert(
     row_value: &Vec<Option<MySQLValue>>,
     table_cols_info: &Vec<HashMap<String, String>>,
-    map: &TableMap) -> String {
+    map: &TableMap,
+    mode: InsertMode,
+    format: &ValueFormat) -> String {
 
-    let mut sql = format!("INSERT INTO {}.{}", map.database_name,map.table_name);
-    let col_str = get_insert_col_str(table_cols_info);
-    let value_str = get_values_str(row_value, table_cols_info);
+    let mut sql = format!("{} {}", mode.prefix(format.dialect), format.dialect.quote_table(&map.database_name, &map.table_name));
+    let col_str = get_insert_col_str(table_cols_info, format.dialect);
+    let value_str = format!("VALUES{}", get_values_tuple(row_value, table_cols_info, format));
     sql.push_str(&col_str);
     sql.push_str(" ");
     sql.push_str(&value_str);
+    sql.push_str(&mode.suffix(table_cols_info, format.dialect));
+    sql.push(';');
     sql
 }
 
-fn get_values_str(values: &Vec<Option<MySQLValue>>, table_cols_info: &Vec<HashMap<String, String>>) -> String{
-    let mut values_str = format!("VALUES(");
-    let value_len = values.len();
-    for (idx,value) in values.iter().enumerate() {
-        let col_type = table_cols_info[idx].get("COLUMN_TYPE").unwrap();
-        values_str.push_str(get_values_info(value, col_type).as_ref());
-        if idx < value_len - 1{
-            values_str.push_str(",");
-        }else {
-            values_str.push_str(");");
+//--batchsize: 多行insert语句的行数, 为空时为1(每行一条语句)
+pub fn batch_size(batchsize: &str) -> Result<usize, String> {
+    if batchsize.is_empty() {
+        return Ok(1);
+    }
+    match batchsize.parse() {
+        Ok(t) if t > 0 => Ok(t),
+        _ => Err(format!("无效的batchsize: {}, 必须为大于0的整数", batchsize))
+    }
+}
+
+//批量生成多行insert语句时缓存的数据, 表变化、缓存行数达到batch_size或遇到其他语句时输出
+pub struct InsertBatch{
+    pub batch_size: usize,
+    pub mode: InsertMode,
+    db_tbl: String,
+    insert_str: String,
+    suffix_str: String,
+    values: Vec<String>,
+}
+
+impl InsertBatch{
+    pub fn new(batch_size: usize, mode: InsertMode) -> InsertBatch {
+        InsertBatch{
+            batch_size,
+            mode,
+            db_tbl: String::from(""),
+            insert_str: String::from(""),
+            suffix_str: String::from(""),
+            values: vec![]
         }
     }
-    values_str
+
+    //缓存一行, 返回因表变化或行数达到batch_size而生成的语句
+    pub fn push(&mut self,
+                row_value: &Vec<Option<MySQLValue>>,
+                table_cols_info: &Vec<HashMap<String, String>>,
+                map: &TableMap,
+                format: &ValueFormat) -> Vec<String> {
+        let mut statements = vec![];
+        let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+        if db_tbl != self.db_tbl {
+            statements.extend(self.take());
+            self.db_tbl = db_tbl;
+            let dialect = format.dialect;
+            self.insert_str = format!("{} {}{}", self.mode.prefix(dialect), dialect.quote_table(&map.database_name, &map.table_name), get_insert_col_str(table_cols_info, dialect));
+            self.suffix_str = self.mode.suffix(table_cols_info, dialect);
+        }
+        self.values.push(get_values_tuple(row_value, table_cols_info, format));
+        if self.values.len() >= self.batch_size {
+            statements.extend(self.take());
+        }
+        statements
+    }
+
+    //缓存的行生成一条语句, 没有缓存的行时为None
+    pub fn take(&mut self) -> Option<String> {
+        if self.values.is_empty() {
+            return None;
+        }
+        let sql = format!("{} VALUES{}{};", self.insert_str, self.values.join(","), self.suffix_str);
+        self.values = vec![];
+        self.db_tbl = String::from("");
+        Some(sql)
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(sql) = self.take() {
+            Self::print(&sql);
+        }
+    }
+
+    pub fn print(sql: &str) {
+        println!("-- Insert Row Value");
+        println!("{}", sql);
+    }
+}
+
+//生成列的值由mysql计算, 不能指定
+fn get_values_tuple(values: &Vec<Option<MySQLValue>>, table_cols_info: &Vec<HashMap<String, String>>, format: &ValueFormat) -> String{
+    let values_str: Vec<String> = values.iter().enumerate()
+        .filter(|(idx, _)| !meta::is_generated(&table_cols_info[*idx]))
+        .map(|(idx, value)| get_values_info(value, table_cols_info[idx].get("COLUMN_TYPE").unwrap(), format))
+        .collect();
+    format!("({})", values_str.join(","))
 }
 
-fn get_values_info(value: &Option<MySQLValue>, col_type: &String) -> String {
+fn get_values_info(value: &Option<MySQLValue>, col_type: &String, format: &ValueFormat) -> String {
     let mut value_str = String::from("");
+    let dialect = format.dialect;
     match value {
+        Some(t) if format.null_temporal(t) => {
+            value_str.push_str(sql_escape::NULL);
+        }
         Some(MySQLValue::String(t)) => {
-            value_str.push_str(&format!("'{}'",t));
+            value_str.push_str(&dialect.quote_string(t));
         }
         Some(MySQLValue::Blob(t)) => {
-            match col_type.find("text") {
-                Some(_) => {
-                    value_str.push_str(&format!("'{}'",from_utf8(t).unwrap()));
-                    return value_str;
-                }
-                None => {}
-            }
-            match col_type.find("char") {
-                Some(_) => {
-                    value_str.push_str(&format!("'{}'",from_utf8(t).unwrap()));
-                    return value_str;
-                }
-                None => {}
-            }
-
-            if t.len()> 0{
-                value_str.push_str(&format!("0x{}",hex::encode(t)));
+            //text、char类型按字符串输出, 空间类型为ST_GeomFromText(...), 其余按二进制数据以十六进制字面值输出
+            if let Some(value) = format.spatial(t, col_type) {
+                value_str.push_str(&dialect.geometry(&value.geometry.to_wkt(), value.srid));
+            }else if col_type.contains("text") || col_type.contains("char") {
+                value_str.push_str(&dialect.quote_text(t));
             }else {
-                value_str.push_str(&format!("Null"));
+                value_str.push_str(&dialect.quote_bytes(t));
             }
         }
         Some(MySQLValue::Timestamp {unix_time, subsecond}) => {
-            value_str.push_str(&format!("from_unixtime({}.{})", unix_time, subsecond));
+            value_str.push_str(&format.timestamp_sql(*unix_time, *subsecond, crate::stdout::format::fsp(col_type)));
         }
         Some(MySQLValue::Enum(t)) => {
             value_str.push_str(&format!("{}",t));
         }
         Some(MySQLValue::DateTime {year, month, day, hour, minute, second, subsecond}) => {
-            value_str.push_str(&format!("'{}-{}-{} {}:{}:{}.{}'", year,month,day,hour,minute,second,subsecond));
-        }
-        Some(MySQLValue::Double(t)) => {
-            value_str.push_str(&format!("{}", t));
+            value_str.push_str(&dialect.temporal(Temporal::DateTime, &format.datetime(*year, *month, *day, *hour, *minute, *second, *subsecond, crate::stdout::format::fsp(col_type))));
         }
-        Some(MySQLValue::Float(t)) => {
-            value_str.push_str(&format!("{}",t));
+        Some(t @ MySQLValue::Double(_)) | Some(t @ MySQLValue::Float(_)) => {
+            value_str.push_str(&format.float_sql(t));
         }
         Some(MySQLValue::Year(t)) => {
             value_str.push_str(&format!("{}",t));
         }
         Some(MySQLValue::Decimal(t)) => {
-            value_str.push_str(&format!("{}",t.to_f64().unwrap()));
+            value_str.push_str(&format.decimal(t));
         }
         Some(MySQLValue::SignedInteger(t)) => {
-            value_str.push_str(&format!("{}", t));
+            match dialect.boolean(col_type, *t) {
+                Some(b) => value_str.push_str(b),
+                None => value_str.push_str(&format!("{}", t)),
+            }
         }
         Some(MySQLValue::Json(t)) => {
-            value_str.push_str(&format!("'{}'", serde_json::to_string(&t).unwrap()));
+            value_str.push_str(&dialect.quote_string(&serde_json::to_string(&t).unwrap()));
         }
         Some(MySQLValue::Null) => {
-            value_str.push_str(&format!("Null"));
+            value_str.push_str(sql_escape::NULL);
+        }
+        Some(MySQLValue::Opaque {bytes, ..}) => {
+            value_str.push_str(&dialect.quote_bytes(bytes));
         }
-        Some(MySQLValue::Time {hours, minutes, seconds, subseconds}) => {
-            value_str.push_str(&format!("'{}:{}:{}.{}'",hours, minutes, seconds, subseconds));
+        Some(MySQLValue::Time {negative, hours, minutes, seconds, subseconds}) => {
+            value_str.push_str(&dialect.temporal(Temporal::Time, &format.time(*negative, *hours, *minutes, *seconds, *subseconds, crate::stdout::format::fsp(col_type))));
         }
         Some(MySQLValue::Date {year, month, day}) => {
-            value_str.push_str(&format!("'{}-{}-{}'", year, month, day));
+            value_str.push_str(&dialect.temporal(Temporal::Date, &format.date(*year, *month, *day)));
         }
         _ => {
             println!("{:?}",value);
@@ -127,17 +260,12 @@ fn get_values_info(value: &Option<MySQLValue>, col_type: &String) -> String {
     value_str
 }
 
-fn get_insert_col_str(table_cols_info: &Vec<HashMap<String, String>>) -> String {
-    let mut col_str = format!("(");
-    let cols = table_cols_info.len();
-    for (idx,col_info) in table_cols_info.iter().enumerate() {
-        if idx < cols -1 {
-            col_str.push_str(&format!("{},",col_info.get("COLUMN_NAME").unwrap()));
-        }else {
-            col_str.push_str(&format!("{})",col_info.get("COLUMN_NAME").unwrap()));
-        }
-    }
-    col_str
+fn get_insert_col_str(table_cols_info: &Vec<HashMap<String, String>>, dialect: Dialect) -> String {
+    let cols: Vec<String> = table_cols_info.iter()
+        .filter(|col_info| !meta::is_generated(col_info))
+        .map(|col_info| dialect.quote_identifier(col_info.get("COLUMN_NAME").unwrap()))
+        .collect();
+    format!("({})", cols.join(","))
 }
 
 
@@ -146,148 +274,66 @@ pub fn out_update(
     after_value: &Vec<Option<MySQLValue>>,
     table_cols_info: &Vec<HashMap<String, String>>,
     pri_info: &HashMap<String, usize>,
-    map: &TableMap) -> String {
+    map: &TableMap,
+    format: &ValueFormat) -> String {
 
-    let mut sql = format!("UPDATE {}.{} SET ", map.database_name,map.table_name);
-    let where_str = get_where_str(befor_value, table_cols_info, pri_info);
+    let mut sql = format!("UPDATE {} SET ", format.dialect.quote_table(&map.database_name, &map.table_name));
+    let where_str = get_where_str(befor_value, table_cols_info, pri_info, format);
     //String::from("{:?}",befor_value)
-    let set_str = get_set_str(after_value, table_cols_info, map);
+    let set_str = get_set_str(after_value, table_cols_info, map, format);
     sql.push_str(&set_str);
     sql.push_str(&where_str);
     sql
 }
 
-fn get_set_str(value: &Vec<Option<MySQLValue>>,table_cols_info: &Vec<HashMap<String, String>>, map: &TableMap) -> String {
-    let mut set_str = "".to_string();
-    let value_len = value.iter().len();
-    for (idx, v) in value.iter().enumerate() {
-        let col = table_cols_info[idx].get("COLUMN_NAME").unwrap();
-        let col_type = table_cols_info[idx].get("COLUMN_TYPE").unwrap();
-        set_str.push_str(&get_value_str(v, col, &col_type, GetType::GetSet));
-        if idx < value_len - 1 {
-            set_str.push_str(", ");
-        }
+//没有主键时按所有字段匹配, mysql追加LIMIT 1, 有重复的行时只修改其中一行; 其他方言的update/delete不支持LIMIT, 不变
+pub fn limit_one(sql: String, dialect: Dialect) -> String {
+    match (dialect, sql.strip_suffix(';')) {
+        (Dialect::Mysql, Some(t)) => format!("{} LIMIT 1;", t),
+        _ => sql
     }
-    set_str
 }
 
-
-fn get_where_str(value: &Vec<Option<MySQLValue>>,table_cols_info: &Vec<HashMap<String, String>>, pri_info: &HashMap<String, usize>) -> String {
-    let mut where_str = " WHERE ".to_string();
-    let cols = pri_info.len();
-    if pri_info.len() > 0 {
-        let mut tmp = 1;
-        for (col, idx) in pri_info{
-            let value = &value[*idx];
-            let col_type = table_cols_info[*idx].get("COLUMN_TYPE").unwrap();
-            where_str.push_str(&get_value_str(value, col, col_type,GetType::GetWhere));
-            if tmp < cols{
-                where_str.push_str(" AND ");
-            }
-            else {
-                where_str.push_str(";");
-            }
-            tmp += 1;
-        }
-    }
-
-//    if pri.len() > 0 {
-//        let value = &value[pri_idex];
-//        let col_type = table_cols_info[pri_idex].get("COLUMN_TYPE").unwrap();
-//        where_str.push_str(&get_value_str(value, pri, col_type,GetType::GetWhere));
-//        where_str.push_str(";");
-//    }
-    else {
-        let value_len = value.iter().len();
-        for (idx, v) in value.iter().enumerate(){
+fn get_set_str(value: &Vec<Option<MySQLValue>>,table_cols_info: &Vec<HashMap<String, String>>, map: &TableMap, format: &ValueFormat) -> String {
+    let set_str: Vec<String> = value.iter().enumerate()
+        .filter(|(idx, _)| !meta::is_generated(&table_cols_info[*idx]))
+        .map(|(idx, v)| {
             let col = table_cols_info[idx].get("COLUMN_NAME").unwrap();
             let col_type = table_cols_info[idx].get("COLUMN_TYPE").unwrap();
-            where_str.push_str(get_value_str(v, col, &col_type, GetType::GetWhere).as_ref());
-            if idx < value_len - 1 {
-                where_str.push_str(" AND ");
-            } else {
-                where_str.push_str(";");
-            }
-        }
-    }
-
-    where_str
+            get_value_str(v, col, col_type, GetType::GetSet, format)
+        })
+        .collect();
+    set_str.join(", ")
 }
 
-fn get_value_str(value: &Option<MySQLValue>,col: &String, col_type: &String, get_type: GetType) -> String {
-    let mut where_str = String::from("");
-    match value {
-        Some(MySQLValue::String(t)) => {
-            where_str.push_str(&format!("{}='{}'",col, t));
-        }
-        Some(MySQLValue::Blob(t)) => {
-            let col_type = col_type;
-            match col_type.find("text") {
-                Some(_) => {
-                    where_str.push_str(&format!("{}='{}'",col, from_utf8(t).unwrap()));
-                    return where_str;
-                }
-                None => {}
-            }
-            match col_type.find("char") {
-                Some(_) => {
-                    where_str.push_str(&format!("{}='{}'",col, from_utf8(t).unwrap()));
-                    return where_str;
-                }
-                None => {}
-            }
 
-            if t.len()> 0{
-                where_str.push_str(&format!("{}=0x{}",col,hex::encode(t)));
-            }else {
-                match get_type {
-                    GetType::GetWhere => where_str.push_str(&format!("{} is Null",col)),
-                    GetType::GetSet => where_str.push_str(&format!("{}=Null",col)),
-                }
-            }
-        }
-        Some(MySQLValue::Timestamp {unix_time, subsecond}) => {
-            where_str.push_str(&format!("{}=from_unixtime({}.{})", col, unix_time, subsecond));
-        }
-        Some(MySQLValue::Enum(t)) => {
-            where_str.push_str(&format!("{}={}",col,t));
-        }
-        Some(MySQLValue::DateTime {year, month, day, hour, minute, second, subsecond}) => {
-            where_str.push_str(&format!("{}='{}-{}-{} {}:{}:{}.{}'", col,year,month,day,hour,minute,second,subsecond));
-        }
-        Some(MySQLValue::Double(t)) => {
-            where_str.push_str(&format!("{}={}",col, t));
-        }
-        Some(MySQLValue::Float(t)) => {
-            where_str.push_str(&format!("{}={}",col, t));
-        }
-        Some(MySQLValue::Year(t)) => {
-            where_str.push_str(&format!("{}={}",col, t));
-        }
-        Some(MySQLValue::Decimal(t)) => {
-            where_str.push_str(&format!("{}={}",col, t.to_f64().unwrap()));
-        }
-        Some(MySQLValue::SignedInteger(t)) => {
-            where_str.push_str(&format!("{}={}",col, t));
-        }
-        Some(MySQLValue::Json(t)) => {
-            where_str.push_str(&format!("{}='{}'",col, serde_json::to_string(&t).unwrap()));
-        }
-        Some(MySQLValue::Null) => {
-            match get_type {
-                GetType::GetWhere => where_str.push_str(&format!("{} is Null",col)),
-                GetType::GetSet => where_str.push_str(&format!("{}=Null",col)),
-            }
-        }
-        Some(MySQLValue::Time {hours, minutes, seconds, subseconds}) => {
-            where_str.push_str(&format!("{}='{}:{}:{}.{}'",col, hours, minutes, seconds, subseconds));
-        }
-        Some(MySQLValue::Date {year, month, day}) => {
-            where_str.push_str(&format!("{}='{}-{}-{}'",col, year, month, day));
-        }
-        _ => {
-            println!("{:?}",value);
-        }
+//有主键时按主键及分区键定位, 分区键包含在条件中时mysql只需要扫描对应的分区
+//没有主键时使用所有非生成列, 生成列的值由mysql计算(浮点等可能与binlog中的值不完全相同), 作为分区键时保留
+fn get_where_str(value: &Vec<Option<MySQLValue>>,table_cols_info: &Vec<HashMap<String, String>>, pri_info: &HashMap<String, usize>, format: &ValueFormat) -> String {
+    let mut idxs: Vec<usize> = if pri_info.len() > 0 {
+        pri_info.values().copied().collect()
+    }else {
+        (0..value.len()).filter(|idx| !meta::is_generated(&table_cols_info[*idx])).collect()
+    };
+    idxs.extend((0..value.len()).filter(|idx| meta::is_partition_key(&table_cols_info[*idx])));
+    idxs.sort_unstable();
+    idxs.dedup();
+    let conditions: Vec<String> = idxs.iter().map(|idx| {
+        let col = table_cols_info[*idx].get("COLUMN_NAME").unwrap();
+        let col_type = table_cols_info[*idx].get("COLUMN_TYPE").unwrap();
+        get_value_str(&value[*idx], col, col_type, GetType::GetWhere, format)
+    }).collect();
+    format!(" WHERE {};", conditions.join(" AND "))
+}
+
+fn get_value_str(value: &Option<MySQLValue>,col: &String, col_type: &String, get_type: GetType, format: &ValueFormat) -> String {
+    let col = format.dialect.quote_identifier(col);
+    let value_str = get_values_info(value, col_type, format);
+    if value_str == sql_escape::NULL {
+        return match get_type {
+            GetType::GetWhere => format!("{} IS NULL", col),
+            GetType::GetSet => format!("{}=NULL", col),
+        };
     }
-    where_str
-}
\ No newline at end of file
+    format!("{}={}", col, value_str)
+}
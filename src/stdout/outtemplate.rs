@@ -0,0 +1,127 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--template: 按用户提供的模板输出, 每条记录渲染一次, 渲染结果为空时不输出, 模板末尾没有换行时自动换行
+//  行数据(每行一条): type(insert/update/delete)、database、table、gtid、data、old(update的前镜像)、labels、row_hash
+//  语句(非BEGIN/COMMIT): type=query、database、sql、thread_id、execute_seconds、gtid
+//  事务提交: type=commit、gtid、xid(非事务表的COMMIT为null)、rows(事务中通过过滤条件的行数)
+//  所有记录都有timestamp、datetime(按--timezone、--timeformat输出, 默认utc)、position、event_length
+//  --template @文件名 从文件读取模板, 命令行中的模板可以使用\t、\n
+
+use std::collections::HashMap;
+use std::fs;
+use serde_json::{json, Map, Value as JsonValue};
+use crate::replication::readevent::TableMap;
+use crate::replication::readbinlog::Traction;
+use crate::stdout::{outjson, OutState};
+use crate::stdout::format;
+use crate::template::Template;
+
+#[derive(Debug)]
+pub struct TemplateOut{
+    template: Template,
+    newline: bool,
+    rows: u64,
+}
+
+impl TemplateOut {
+    pub fn new(source: &str) -> Result<TemplateOut, String> {
+        let source = match source.strip_prefix('@') {
+            Some(path) => fs::read_to_string(path).map_err(|e| format!("读取模板文件({})失败: {}", path, e))?,
+            None => source.replace("\\t", "\t").replace("\\n", "\n")
+        };
+        Ok(TemplateOut{ template: Template::parse(&source)?, newline: !source.ends_with('\n'), rows: 0 })
+    }
+
+    /// 渲染一条记录, 结果为空时返回None
+    pub fn render(&self, record: &JsonValue) -> Option<String> {
+        let mut out = self.template.render(record);
+        if out.trim().is_empty() {
+            return None;
+        }
+        if self.newline {
+            out.push('\n');
+        }
+        Some(out)
+    }
+}
+
+//事件的公共字段
+fn with_event(mut record: Map<String, JsonValue>, out_state: &OutState) -> JsonValue {
+    let timestamp = out_state.timestamp;
+    let datetime = out_state.format.timestamp(timestamp as i32, 0, 0).unwrap_or_else(|| format::utc_datetime(timestamp));
+    record.insert(String::from("timestamp"), json!(timestamp));
+    record.insert(String::from("datetime"), json!(datetime));
+    record.insert(String::from("position"), json!(out_state.log_pos));
+    record.insert(String::from("event_length"), json!(out_state.event_length));
+    JsonValue::Object(record)
+}
+
+pub fn out_template(data: &Traction, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>, db_tbl: &String, map: &TableMap, out_state: &mut OutState) {
+    let mut records = vec![];
+    match data {
+        Traction::GtidEvent(t) => {
+            out_state.gtid = format!("{}:{}", t.gtid, t.gno_id);
+        }
+        Traction::RowEvent(t, f) => {
+            let default_cols;
+            let cols = match table_cols_info.get(db_tbl) {
+                Some(t) => t,
+                None => {
+                    default_cols = crate::sink::default_columns(map);
+                    &default_cols
+                }
+            };
+            for record in outjson::row_records(f, t, cols, db_tbl, map, &out_state.gtid, &out_state.format) {
+                if let JsonValue::Object(record) = record {
+                    records.push(with_event(record, out_state));
+                }
+            }
+        }
+        Traction::XidEvent(_) | Traction::QueryEvent(_) => {
+            let mut record = Map::new();
+            match data {
+                Traction::XidEvent(t) => {
+                    record.insert(String::from("type"), json!("commit"));
+                    record.insert(String::from("xid"), json!(t.xid));
+                }
+                Traction::QueryEvent(t) if t.command == "BEGIN" => return,
+                Traction::QueryEvent(t) if t.command == "COMMIT" => {
+                    record.insert(String::from("type"), json!("commit"));
+                    record.insert(String::from("xid"), JsonValue::Null);
+                }
+                Traction::QueryEvent(t) => {
+                    record.insert(String::from("type"), json!("query"));
+                    record.insert(String::from("database"), json!(t.database));
+                    record.insert(String::from("sql"), json!(t.command));
+                    record.insert(String::from("thread_id"), json!(t.thread_id));
+                    record.insert(String::from("execute_seconds"), json!(t.execute_seconds));
+                }
+                _ => return
+            }
+            record.insert(String::from("gtid"), json!(out_state.gtid));
+            if record["type"] == "commit" {
+                record.insert(String::from("rows"), json!(out_state.template.as_ref().map(|t| t.rows).unwrap_or(0)));
+            }
+            if !out_state.format.labels.is_empty() {
+                record.insert(String::from("labels"), out_state.format.labels.to_json());
+            }
+            records.push(with_event(record, out_state));
+        }
+        _ => {}
+    }
+    if let Some(template) = out_state.template.as_mut() {
+        for record in records {
+            match record["type"].as_str() {
+                Some("commit") => template.rows = 0,
+                Some("insert") | Some("update") | Some("delete") => template.rows += 1,
+                _ => {}
+            }
+            if let Some(line) = template.render(&record) {
+                print!("{}", line);
+            }
+        }
+    }
+}
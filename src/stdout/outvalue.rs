@@ -9,35 +9,61 @@ use crate::replication::readevent::{BinlogEvent,TableMap};
 use crate::replication::parsevalue::RowValue;
 use crate::replication::readbinlog::Traction;
 use crate::replication::parsevalue::MySQLValue;
-use failure::_core::str::from_utf8;
-use hex;
-use bigdecimal::ToPrimitive;
 use std::process::id;
+use crate::stdout::OutState;
+use crate::stdout::format::ValueFormat;
 
 //打印sql
-pub fn out_sql(data: &Traction, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,db_tbl: &String, map: &TableMap) {
+pub fn out_sql(data: &Traction, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,db_tbl: &String, map: &TableMap, out_state: &mut OutState) {
+    match data {
+        Traction::RowEvent(BinlogEvent::WriteEvent, _) |
+        Traction::TableMapEvent(_) |
+        Traction::Unknown => {}
+        _ => {
+            //连续的insert语句只有遇到其他语句时才输出
+            out_state.insert_batch.flush();
+        }
+    }
     match data {
         Traction::GtidEvent(t) => {
             println!("-- GTID: {}:{}", t.gtid,t.gno_id);
         },
         Traction::QueryEvent(t) => {
-            println!("use {};",t.database);
+            for line in out_state.fence.as_mut().map(|f| f.before(&t.command, &t.database)).unwrap_or_default() {
+                println!("{}", line);
+            }
+            if let Some(t) = out_state.format.dialect.use_database(&t.database) {
+                println!("{}", t);
+            }
             println!("{};", t.command);
+            for line in out_state.fence.as_mut().map(|f| f.after()).unwrap_or_default() {
+                println!("{}", line);
+            }
         },
         Traction::RowEvent(t,f) => {
-            print_command(f, t, table_cols_info, db_tbl, map);
+            if let Some(fence) = out_state.fence.as_mut() {
+                fence.touch(&map.database_name, &map.table_name);
+            }
+            print_command(f, t, table_cols_info, db_tbl, map, out_state);
+        }
+        Traction::RowsQueryEvent(t) => {
+            //原始sql或flashback的进度注释
+            println!("-- {}", t.query.replace('\n', " "));
         }
         Traction::XidEvent(_) => {
             println!("COMMIT;");
             println!();
 
         }
+        Traction::ViewChangeEvent(t) => {
+            println!("-- VIEW_CHANGE view_id: {}, seq_number: {}", t.view_id, t.seq_number);
+        }
         _ => {}
     }
 }
 
 //打印数据
-pub fn out_value(data: &Traction, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,db_tbl: &String){
+pub fn out_value(data: &Traction, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,db_tbl: &String, format: &ValueFormat){
     match data {
         Traction::GtidEvent(t) => {
             println!("GtidEvent     gtid:{}, gno_id:{}, last_committed:{}, sequence_number:{}",t.gtid,t.gno_id,t.last_committed,t.sequence_number);
@@ -49,7 +75,7 @@ pub fn out_value(data: &Traction, table_cols_info: &mut HashMap<String, Vec<Hash
             println!("TableMap      database_name:{}, table_name:{}",t.database_name,t.table_name);
         },
         Traction::RowEvent(t, f) => {
-            print_row_value(f, t,table_cols_info,db_tbl);
+            print_row_value(f, t,table_cols_info,db_tbl, format);
         },
         Traction::XidEvent(t) => {
             println!("XidEvent      xid:{}",t.xid);
@@ -58,7 +84,28 @@ pub fn out_value(data: &Traction, table_cols_info: &mut HashMap<String, Vec<Hash
             println!();
         },
         Traction::RotateLogEvent(t) => {
-            println!("{:?}",t);
+            if t.artificial {
+                //dump开始时master发送的rotate, 只表示当前所在的binlog文件及位置
+                println!("RotateLog     binlog_file:{}, position:{} (artificial)",t.binlog_file,t.position);
+            }else {
+                println!("RotateLog     binlog_file:{}, position:{}",t.binlog_file,t.position);
+            }
+        }
+        Traction::StopEvent => {
+            println!("StopEvent");
+        }
+        Traction::TransactionContextEvent(t) => {
+            println!("TransactionContext server_uuid:{}, thread_id:{}, gtid_specified:{}, write_set:{}, read_set:{}",
+                     t.server_uuid,t.thread_id,t.gtid_specified,t.write_set_len,t.read_set_len);
+        }
+        Traction::UnknownEvent(t) => {
+            println!("UnknownEvent  type_code:{}, {}bytes",t.type_code,t.bytes.len());
+        }
+        Traction::RowsQueryEvent(t) => {
+            println!("RowsQuery     query:{}",t.query);
+        }
+        Traction::ViewChangeEvent(t) => {
+            println!("ViewChangeEvent view_id:{}, seq_number:{}, cert_info:{}",t.view_id,t.seq_number,t.cert_info.len());
         }
         Traction::RowEventStatic{ type_code, count } => {
             println!("{:?}    {}bytes",type_code,count);
@@ -73,7 +120,7 @@ pub fn out_static() {
 
 }
 
-fn print_row_value(row_values: &RowValue,code: &BinlogEvent, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,db_tbl: &String) {
+fn print_row_value(row_values: &RowValue,code: &BinlogEvent, table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,db_tbl: &String, format: &ValueFormat) {
     println!("ROW_VALUE");
     match table_cols_info.get(db_tbl) {
         Some(t) => {
@@ -94,6 +141,10 @@ fn print_row_value(row_values: &RowValue,code: &BinlogEvent, table_cols_info: &m
                 }
                 for (index, value) in row.iter().enumerate(){
                     let col = cols[index].get("COLUMN_NAME").unwrap();
+                    if !format.keep_column(db_tbl, col) {
+                        continue;
+                    }
+                    let fsp = crate::stdout::format::fsp(cols[index].get("COLUMN_TYPE").map(|t| t.as_str()).unwrap_or(""));
                     match value {
                         Some(MySQLValue::String(t)) => {
                             print!("{}: {}, ", col,t);
@@ -101,30 +152,21 @@ fn print_row_value(row_values: &RowValue,code: &BinlogEvent, table_cols_info: &m
                         Some(MySQLValue::Null) => {
                             print!("{}: {}, ",col, String::from("Null"));
                         }
+                        Some(t) if format.null_temporal(t) => {
+                            print!("{}: {}, ",col, String::from("Null"));
+                        }
                         Some(MySQLValue::Json(t)) => {
                             print!("{}: {}, ",col, serde_json::to_string(&t).unwrap());
                         }
                         Some(MySQLValue::Blob(t)) => {
                             let col_type = cols[index].get("COLUMN_TYPE").unwrap();
-                            match col_type.find("text") {
-                                Some(_) => {
-                                    print!("{}: {}, ",col, from_utf8(t).unwrap());
-                                    continue;
-                                }
-                                None => {}
-                            }
-
-                            match col_type.find("char") {
-                                Some(_) => {
-                                    print!("{}: {}, ",col, from_utf8(t).unwrap());
-                                    continue;
-                                }
-                                None => {}
-                            }
-
-                            if t.len()> 0{
-                                let a = format!("0x{}",hex::encode(t));
-                                print!("{}: {},",col, a);
+                            if let Some(value) = format.spatial(t, col_type) {
+                                print!("{}: {}, ",col, value.to_wkt());
+                            }else if crate::stdout::format::is_text_type(col_type) {
+                                print!("{}: {}, ",col, format.bytes(t, col_type, None));
+                            }else if t.len()> 0{
+                                let encoding = format.binary_encoding(db_tbl, col);
+                                print!("{}: {}, ",col, format.bytes(t, col_type, Some(encoding)));
                             }else {
                                 print!("{}: {}, ",col, String::from("Null"));
                             }
@@ -133,31 +175,37 @@ fn print_row_value(row_values: &RowValue,code: &BinlogEvent, table_cols_info: &m
                             print!("{}: {}, ", col, t);
                         }
                         Some(MySQLValue::Decimal(t)) => {
-                            print!("{}: {:?}, ", col, t.to_f64().unwrap());
+                            print!("{}: {}, ", col, format.decimal(t));
                         }
                         Some(MySQLValue::Date {year, month, day }) => {
-                            print!("{}: {}-{}-{}, ", col, year,month,day);
+                            print!("{}: {}, ", col, format.date(*year, *month, *day));
                         }
                         Some(MySQLValue::Year(t)) => {
                             print!("{}: {}, ", col, t);
                         }
                         Some(MySQLValue::Float(t)) => {
-                            print!("{}: {}, ", col, t);
+                            print!("{}: {}, ", col, format.float32(*t));
                         }
                         Some(MySQLValue::Double(t)) => {
-                            print!("{}: {}, ", col,t);
+                            print!("{}: {}, ", col, format.float(*t));
                         }
                         Some(MySQLValue::DateTime { year, month, day, hour, minute, second, subsecond }) => {
-                            print!("{}: {}-{}-{} {}:{}:{}.{}, ", col,year,month,day,hour,minute,second,subsecond);
+                            print!("{}: {}, ", col, format.datetime(*year, *month, *day, *hour, *minute, *second, *subsecond, fsp));
                         }
                         Some(MySQLValue::Enum(t)) => {
                             print!("{}: {}, ", col, t);
                         }
-                        Some(MySQLValue::Time { hours, minutes, seconds, subseconds }) => {
-                            print!("{}: {}:{}:{}.{}, ", col, hours,minutes,seconds,subseconds);
+                        Some(MySQLValue::Time { negative, hours, minutes, seconds, subseconds }) => {
+                            print!("{}: {}, ", col, format.time(*negative, *hours, *minutes, *seconds, *subseconds, fsp));
                         }
                         Some(MySQLValue::Timestamp { unix_time, subsecond }) => {
-                            print!("{}: from_unixtime({}.{}), ", col,unix_time,subsecond);
+                            match format.timestamp(*unix_time, *subsecond, fsp) {
+                                Some(t) => print!("{}: {}, ", col, t),
+                                None => print!("{}: from_unixtime({}), ", col, crate::stdout::format::epoch(*unix_time, *subsecond, fsp))
+                            }
+                        }
+                        Some(MySQLValue::Opaque { bytes, .. }) => {
+                            print!("{}: {}, ", col, format.binary_encoding(db_tbl, col).encode(bytes));
                         }
                         _ => {}
                     }
@@ -175,19 +223,23 @@ fn print_row_value(row_values: &RowValue,code: &BinlogEvent, table_cols_info: &m
 fn print_command(
     row_values: &RowValue,code: &BinlogEvent,
     table_cols_info: &mut HashMap<String, Vec<HashMap<String, String>>>,
-    db_tbl: &String, map: &TableMap){
+    db_tbl: &String, map: &TableMap, out_state: &mut OutState){
     match table_cols_info.get(db_tbl) {
         Some(t) => {
             let cols = t;
-            let mut pri_idex = 0 as usize;          //主键索引
-            let mut pri = &String::from("");    //主键名称
+            //主键或非空唯一键的字段名及索引, 没有时按no_key处理
             let mut pri_info: HashMap<String, usize> = HashMap::new();
-            for (idx,r) in cols.iter().enumerate(){
-                if r.get("COLUMN_KEY").unwrap() == &String::from("PRI") {
-                    pri = r.get("COLUMN_NAME").unwrap();
-                    pri_info.insert(pri.parse().unwrap(), idx);
+            let key = crate::nokey::key_columns(cols, map);
+            for idx in &key {
+                if let Some(pri) = cols.get(*idx).and_then(|r| r.get("COLUMN_NAME")) {
+                    pri_info.insert(pri.clone(), *idx);
                 }
             }
+            if !out_state.no_key.check("getsql", db_tbl, &key) {
+                return;
+            }
+            let full_row = key.is_empty() && out_state.no_key == crate::nokey::NoKeyPolicy::FullRow;
+            let limit = |sql: String| if full_row { crate::stdout::outsql::limit_one(sql, out_state.format.dialect) } else { sql };
 
             match code {
                 BinlogEvent::UpdateEvent => {
@@ -210,21 +262,29 @@ fn print_command(
                     for row in rows{
                         let befor_value = row[0];
                         let after_value = row[1];
-                        let v = crate::stdout::outsql::out_update(befor_value, after_value, cols, &pri_info,map);
+                        let v = limit(crate::stdout::outsql::out_update(befor_value, after_value, cols, &pri_info,map, &out_state.format));
                         println!("{}",v);
                     }
                 }
                 BinlogEvent::WriteEvent => {
+                    if out_state.insert_batch.batch_size > 1 {
+                        for row in &row_values.rows {
+                            for sql in out_state.insert_batch.push(row, cols, map, &out_state.format) {
+                                crate::stdout::outsql::InsertBatch::print(&sql);
+                            }
+                        }
+                        return;
+                    }
                     println!("-- Insert Row Value");
                     for row in &row_values.rows {
-                        let v = crate::stdout::outsql::out_insert(row, cols, map);
+                        let v = crate::stdout::outsql::out_insert(row, cols, map, out_state.insert_batch.mode, &out_state.format);
                         println!("{}",v);
                     }
                 }
                 BinlogEvent::DeleteEvent => {
                     println!("-- Delete Row Value");
                     for row in &row_values.rows {
-                        let v = crate::stdout::outsql::out_delete(row, cols, &pri_info, map);
+                        let v = limit(crate::stdout::outsql::out_delete(row, cols, &pri_info, map, &out_state.format));
                         println!("{}",v);
                     }
                 }
@@ -0,0 +1,84 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/30
+*/
+
+//读取binlog文件时在stderr输出进度: 已读取/总大小、每秒事件数、预计剩余时间
+//只在stderr为终端时输出; 数据输出到终端时进度会与数据混在一起, 这时只有回滚、复制模式输出进度
+
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+use crate::Config;
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+const BAR_WIDTH: usize = 30;
+
+pub struct Progress{
+    enabled: bool,
+    total: u64,
+    position: u64,
+    events: u64,
+    start: Instant,
+    last_draw: Instant,
+}
+
+impl Progress{
+    pub fn new(total: u64, conf: &Config) -> Progress {
+        let data_to_file = conf.rollback || conf.runtype == "copy" || !io::stdout().is_terminal();
+        let now = Instant::now();
+        Progress{
+            enabled: !conf.quiet && io::stderr().is_terminal() && data_to_file,
+            total,
+            position: 0,
+            events: 0,
+            start: now,
+            last_draw: now,
+        }
+    }
+
+    //每读取一个事件调用一次, position为该事件结束的位置
+    pub fn update(&mut self, position: u64) {
+        self.events += 1;
+        self.position = position;
+        if self.enabled && self.last_draw.elapsed() >= REDRAW_INTERVAL {
+            self.draw();
+        }
+    }
+
+    pub fn finish(&mut self) {
+        if self.enabled {
+            self.draw();
+            eprintln!();
+        }
+    }
+
+    fn draw(&mut self) {
+        self.last_draw = Instant::now();
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let ratio = if self.total == 0 { 1.0 } else { self.position as f64 / self.total as f64 };
+        let filled = ((ratio * BAR_WIDTH as f64) as usize).min(BAR_WIDTH);
+        let rate = if elapsed > 0.0 { self.events as f64 / elapsed } else { 0.0 };
+        let eta = if ratio > 0.0 { elapsed / ratio - elapsed } else { 0.0 };
+        let mut stderr = io::stderr();
+        let _ = write!(stderr, "\r[{}{}] {:>5.1}% {}/{} {:.0} events/s ETA {}   ",
+                       "#".repeat(filled), " ".repeat(BAR_WIDTH - filled), ratio * 100.0,
+                       human_bytes(self.position), human_bytes(self.total), rate, human_duration(eta));
+        let _ = stderr.flush();
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+fn human_duration(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+}
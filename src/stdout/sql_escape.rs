@@ -0,0 +1,62 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//生成sql语句时对字面值和标识符进行转义，规则与mysql_real_escape_string一致
+//字符串使用单引号，二进制数据使用x''十六进制字面值，标识符使用反引号
+
+use std::str::from_utf8;
+
+pub const NULL: &str = "NULL";
+
+//转义字符串中的特殊字符，不包含两侧的引号
+pub fn escape_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    for c in value.chars() {
+        match c {
+            '\0' => escaped.push_str("\\0"),
+            '\'' => escaped.push_str("\\'"),
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\x08' => escaped.push_str("\\b"),
+            '\t' => escaped.push_str("\\t"),
+            '\x1a' => escaped.push_str("\\Z"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+//字符串字面值 'value'
+pub fn quote_string(value: &str) -> String {
+    format!("'{}'", escape_string(value))
+}
+
+//二进制字面值 x'0a1b', 空值为x''
+pub fn quote_bytes(value: &[u8]) -> String {
+    format!("x'{}'", hex::encode(value))
+}
+
+//text/char类型列的数据, 不是合法utf8时(比如latin1、gbk字符集)使用十六进制字面值以保证数据不被破坏
+pub fn quote_text(value: &[u8]) -> String {
+    match from_utf8(value) {
+        Ok(t) => quote_string(t),
+        Err(_) => {
+            crate::warning::emit(crate::warning::WarningKind::CharsetFallback, format!("字符串的值不是合法的utf8({} bytes), 按十六进制字面值输出", value.len()));
+            quote_bytes(value)
+        }
+    }
+}
+
+//库、表、列名 `name`, 名称中的反引号需要写两次
+pub fn quote_identifier(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+//db.tbl 形式的完整表名
+pub fn quote_table(database: &str, table: &str) -> String {
+    format!("{}.{}", quote_identifier(database), quote_identifier(table))
+}
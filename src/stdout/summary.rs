@@ -0,0 +1,198 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/31
+*/
+
+//运行结束(或被中断)时输出json格式的汇总信息, 便于外部脚本判断执行结果
+//  事件头信息(事件类型、字节数、时间)按读取到的所有事件统计
+//  事务、表、gtid按通过过滤条件输出的数据统计
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Instant;
+use serde_json::{json, Value as JsonValue};
+use crate::Config;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::{BinlogEvent, EventHeader, EventType};
+
+static SUMMARY: Mutex<Option<RunSummary>> = Mutex::new(None);
+
+#[derive(Debug, Default, Clone, Copy)]
+struct TableCount{
+    insert: u64,
+    update: u64,
+    delete: u64,
+}
+
+#[derive(Debug)]
+pub struct RunSummary{
+    output: String,
+    mode: String,
+    source: String,
+    start: Instant,
+    events: BTreeMap<String, u64>,
+    event_count: u64,
+    bytes: u64,
+    transactions: u64,
+    tables: BTreeMap<String, TableCount>,
+    first_gtid: Option<String>,
+    last_gtid: Option<String>,
+    first_timestamp: Option<u32>,
+    last_timestamp: Option<u32>,
+    errors: Vec<String>,
+    labels: crate::labels::Labels,
+}
+
+impl RunSummary{
+    fn new(conf: &Config) -> RunSummary {
+        let source = match conf.runtype.as_str() {
+            "repl" | "backup" => conf.host_info.clone(),
+            _ => conf.file.clone()
+        };
+        RunSummary{
+            output: conf.summary.clone(),
+            mode: conf.runtype.clone(),
+            source,
+            start: Instant::now(),
+            events: BTreeMap::new(),
+            event_count: 0,
+            bytes: 0,
+            transactions: 0,
+            tables: BTreeMap::new(),
+            first_gtid: None,
+            last_gtid: None,
+            first_timestamp: None,
+            last_timestamp: None,
+            errors: vec![],
+            labels: crate::labels::Labels::from_config(conf),
+        }
+    }
+
+    fn to_json(&self, end: &str) -> JsonValue {
+        let tables: BTreeMap<&String, JsonValue> = self.tables.iter().map(|(tbl, c)| {
+            (tbl, json!({"insert": c.insert, "update": c.update, "delete": c.delete}))
+        }).collect();
+        #[allow(unused_mut)]
+        let mut value = json!({
+            "mode": self.mode,
+            "source": self.source,
+            "end": end,
+            "elapsed_ms": self.start.elapsed().as_millis() as u64,
+            "event_count": self.event_count,
+            "events": self.events,
+            "bytes": self.bytes,
+            "transactions": self.transactions,
+            "tables": tables,
+            "first_gtid": self.first_gtid,
+            "last_gtid": self.last_gtid,
+            "first_timestamp": self.first_timestamp,
+            "last_timestamp": self.last_timestamp,
+            "errors": self.errors,
+        });
+        if crate::warning::total() > 0 {
+            value["warnings"] = crate::warning::stats_json();
+        }
+        if crate::colstats::is_enabled() {
+            value["column_stats"] = crate::colstats::stats_json();
+        }
+        //使用了压缩协议时记录压缩比
+        #[cfg(feature = "network")]
+        {
+            let stats = crate::io::compress::stats();
+            if stats.wire_bytes > 0 {
+                value["compression"] = stats.to_json();
+            }
+        }
+        self.labels.attach(&mut value);
+        value
+    }
+}
+
+//配置了--summary时开始统计
+pub fn start(conf: &Config) {
+    if conf.summary.is_empty() {
+        return;
+    }
+    if let Ok(mut s) = SUMMARY.lock() {
+        *s = Some(RunSummary::new(conf));
+    }
+}
+
+fn with_summary<F: FnOnce(&mut RunSummary)>(f: F) {
+    if let Ok(mut s) = SUMMARY.lock() {
+        if let Some(summary) = s.as_mut() {
+            f(summary);
+        }
+    }
+}
+
+//读取到的每个事件
+pub fn record_header(header: &EventHeader) {
+    with_summary(|s| {
+        //未知类型按类型码分别统计
+        let name = match header.event_type {
+            EventType::UnknownEvent => format!("UnknownEvent({})", header.raw_type),
+            _ => format!("{:?}", header.type_code),
+        };
+        *s.events.entry(name).or_insert(0) += 1;
+        s.event_count += 1;
+        s.bytes += header.event_length as u64;
+        //FDE、ROTATE等由程序生成的事件时间为0
+        if header.timestamp > 0 {
+            s.first_timestamp.get_or_insert(header.timestamp);
+            s.last_timestamp = Some(header.timestamp);
+        }
+    });
+}
+
+//通过过滤条件输出的数据
+pub fn record_data(data: &Traction, db_tbl: &str) {
+    with_summary(|s| {
+        match data {
+            Traction::GtidEvent(t) => {
+                let gtid = format!("{}:{}", t.gtid, t.gno_id);
+                s.first_gtid.get_or_insert_with(|| gtid.clone());
+                s.last_gtid = Some(gtid);
+            }
+            Traction::XidEvent(_) => s.transactions += 1,
+            Traction::QueryEvent(t) if t.command != "BEGIN" => s.transactions += 1,
+            Traction::RowEvent(code, rows) => {
+                let count = s.tables.entry(db_tbl.to_string()).or_default();
+                let n = rows.rows().len() as u64;
+                match code {
+                    BinlogEvent::WriteEvent => count.insert += n,
+                    BinlogEvent::UpdateEvent => count.update += n / 2,
+                    BinlogEvent::DeleteEvent => count.delete += n,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    });
+}
+
+pub fn record_error(err: &str) {
+    with_summary(|s| s.errors.push(err.to_string()));
+}
+
+//输出汇总信息, end为结束原因(eof、rotate、stop、truncated、finished、interrupted、error)
+//--summary -输出到stderr, 否则写入指定文件
+pub fn finish(end: &str) {
+    let summary = match SUMMARY.lock() {
+        Ok(mut s) => s.take(),
+        Err(_) => None
+    };
+    if let Some(s) = summary {
+        let value = s.to_json(end).to_string();
+        let result = if s.output == "-" {
+            writeln!(io::stderr(), "{}", value)
+        }else {
+            File::create(&s.output).and_then(|mut f| writeln!(f, "{}", value))
+        };
+        if let Err(e) = result {
+            eprintln!("写入summary({})发生错误:{}", s.output, e);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/12
+*/
+
+//--watch: 按规则监控危险操作, 匹配时发送通知(webhook、slack、邮件、命令), 将stream作为实时的变更告警
+//规则文件为json格式:
+//  {"rules": [{"name": "drop-prod", "statements": ["DROP", "TRUNCATE"], "databases": ["prod"]},
+//             {"name": "mass-delete", "delete_rows": 1000, "databases": ["prod"], "tables": ["orders"]}],
+//   "notify": [{"type": "slack", "url": "https://hooks.slack.com/services/..."}]}
+//  statements按语句开头的关键字匹配(不区分大小写), 可以是多个词, 比如"DROP TABLE"
+//  delete_rows、update_rows为一个事务中删除、更新的行数超过该值时告警, 只统计databases、tables范围内的表
+//  databases、tables为空表示不限制, tables可以是tbl或db.tbl, DROP DATABASE等针对整个库的语句不按tables过滤
+//  webhook通知可以配置"compress": "gzip", 压缩请求体并设置Content-Encoding: gzip
+//  没有配置notify时告警输出到stderr, 通知发送失败只输出到stderr并记录到--summary的errors中, 不会中断解析
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+#[cfg(feature = "network")]
+use std::time::Duration;
+use serde_json::{json, Value as JsonValue};
+use crate::Config;
+use crate::replication::readbinlog::Traction;
+use crate::replication::readevent::BinlogEvent;
+use crate::stdout::{format, outddl, summary};
+
+//一个事务中最多记录的原始sql数量
+const MAX_QUERIES: usize = 10;
+
+/// 一条监控规则
+#[derive(Debug, Clone, Default)]
+pub struct WatchRule{
+    pub name: String,
+    pub statements: Vec<String>,
+    pub databases: Vec<String>,
+    pub tables: Vec<String>,
+    pub delete_rows: Option<u64>,
+    pub update_rows: Option<u64>,
+}
+
+impl WatchRule{
+    fn from_json(value: &JsonValue, idx: usize) -> Result<WatchRule, String> {
+        let name = value["name"].as_str().map(String::from).unwrap_or_else(|| format!("rule{}", idx + 1));
+        let rule = WatchRule{
+            statements: string_list(value, "statements", &name)?.iter().map(|s| s.to_uppercase()).collect(),
+            databases: string_list(value, "databases", &name)?.iter().map(|t| crate::ident::normalize(t)).collect(),
+            tables: string_list(value, "tables", &name)?.iter().map(|t| crate::ident::normalize(t)).collect(),
+            delete_rows: row_limit(value, "delete_rows", &name)?,
+            update_rows: row_limit(value, "update_rows", &name)?,
+            name,
+        };
+        if rule.statements.is_empty() && rule.delete_rows.is_none() && rule.update_rows.is_none() {
+            return Err(format!("watch规则{}需要配置statements、delete_rows或update_rows", rule.name));
+        }
+        Ok(rule)
+    }
+
+    //table为None表示整个库
+    fn match_table(&self, db: &str, table: Option<&str>) -> bool {
+        if !self.databases.is_empty() && !self.databases.iter().any(|d| d == db) {
+            return false;
+        }
+        match table {
+            Some(tbl) => self.tables.is_empty() || self.tables.iter().any(|t| t == tbl || *t == format!("{}.{}", db, tbl)),
+            None => true
+        }
+    }
+
+    fn match_statement(&self, words: &[String]) -> bool {
+        self.statements.iter().any(|stmt| {
+            let keywords: Vec<&str> = stmt.split_whitespace().collect();
+            keywords.len() <= words.len() && keywords.iter().zip(words).all(|(k, w)| *k == w.to_uppercase())
+        })
+    }
+}
+
+fn string_list(value: &JsonValue, field: &str, rule: &str) -> Result<Vec<String>, String> {
+    match &value[field] {
+        JsonValue::Null => Ok(vec![]),
+        JsonValue::String(t) => Ok(vec![t.clone()]),
+        JsonValue::Array(items) => items.iter().map(|t| t.as_str().map(String::from)
+            .ok_or_else(|| format!("watch规则{}的{}只能包含字符串", rule, field))).collect(),
+        _ => Err(format!("watch规则{}的{}需要为字符串数组", rule, field))
+    }
+}
+
+fn row_limit(value: &JsonValue, field: &str, rule: &str) -> Result<Option<u64>, String> {
+    match &value[field] {
+        JsonValue::Null => Ok(None),
+        t => t.as_u64().map(Some).ok_or_else(|| format!("watch规则{}的{}需要为非负整数", rule, field))
+    }
+}
+
+/// 告警的发送方式
+#[derive(Debug, Clone)]
+pub enum Notifier{
+    /// POST告警的json对象, compress为gzip时压缩请求体并设置Content-Encoding
+    Webhook{url: String, headers: Vec<(String, String)>, compress: String},
+    /// slack incoming webhook, 发送一行文本
+    Slack{url: String},
+    /// 通过sendmail发送邮件
+    Email{to: Vec<String>, from: String, sendmail: String},
+    /// 执行shell命令, 告警的json对象写入命令的stdin
+    Command{command: String},
+}
+
+impl Notifier{
+    fn from_json(value: &JsonValue) -> Result<Notifier, String> {
+        let field = |name: &str| -> Result<String, String> {
+            value[name].as_str().map(String::from).ok_or_else(|| format!("watch通知{}缺少{}", value, name))
+        };
+        match value["type"].as_str() {
+            Some("webhook") => {
+                let headers = match value["headers"].as_object() {
+                    Some(t) => t.iter().map(|(k, v)| (k.clone(), v.as_str().map(String::from).unwrap_or_else(|| v.to_string()))).collect(),
+                    None => vec![]
+                };
+                let compress = field("compress").unwrap_or_default();
+                crate::sink::compressor(&compress)?;
+                Ok(Notifier::Webhook{ url: field("url")?, headers, compress })
+            }
+            Some("slack") => Ok(Notifier::Slack{ url: field("url")? }),
+            Some("email") => Ok(Notifier::Email{
+                to: string_list(value, "to", "email")?,
+                from: field("from").unwrap_or_else(|_| String::from("mytest")),
+                sendmail: field("sendmail").unwrap_or_else(|_| String::from("/usr/sbin/sendmail")),
+            }),
+            Some("command") => Ok(Notifier::Command{ command: field("command")? }),
+            _ => Err(format!("watch通知的type无效: {}, 可选值为webhook、slack、email、command", value["type"]))
+        }
+    }
+
+    pub fn send(&self, alert: &JsonValue) -> Result<(), String> {
+        match self {
+            Notifier::Webhook{url, headers, compress} => post_json(url, headers, &alert.to_string(), compress),
+            Notifier::Slack{url} => post_json(url, &[], &json!({"text": alert_text(alert)}).to_string(), ""),
+            Notifier::Email{to, from, sendmail} => {
+                if to.is_empty() {
+                    return Err(String::from("email通知没有配置收件人(to)"));
+                }
+                let mail = format!("To: {}\nFrom: {}\nSubject: {}\nContent-Type: text/plain; charset=utf-8\n\n{}\n",
+                                   to.join(", "), from, alert_text(alert),
+                                   serde_json::to_string_pretty(alert).unwrap_or_default());
+                pipe_command(Command::new(sendmail).arg("-t").arg("-i"), sendmail, &mail)
+            }
+            Notifier::Command{command} => {
+                pipe_command(Command::new("sh").arg("-c").arg(command), command, &alert.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "network")]
+fn post_json(url: &str, headers: &[(String, String)], body: &str, compress: &str) -> Result<(), String> {
+    let mut request = ureq::post(url).timeout(Duration::from_secs(10)).set("Content-Type", "application/json");
+    for (key, value) in headers {
+        request = request.set(key, value);
+    }
+    let result = match crate::sink::compressor(compress)? {
+        Some(compressor) => {
+            let body = compressor.compress(body.as_bytes()).map_err(|e| format!("{}: 压缩请求发生错误: {}", url, e))?;
+            request.set("Content-Encoding", compressor.encoding()).send_bytes(&body)
+        }
+        None => request.send_string(body),
+    };
+    result.map(|_| ()).map_err(|e| format!("{}: {}", url, e))
+}
+
+#[cfg(not(feature = "network"))]
+fn post_json(url: &str, _headers: &[(String, String)], _body: &str, _compress: &str) -> Result<(), String> {
+    Err(format!("{}: 发送http请求需要开启network特性", url))
+}
+
+fn pipe_command(command: &mut Command, name: &str, input: &str) -> Result<(), String> {
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::null()).spawn()
+        .map_err(|e| format!("执行{}发生错误:{}", name, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes()).map_err(|e| format!("写入{}发生错误:{}", name, e))?;
+    }
+    let status = child.wait().map_err(|e| format!("执行{}发生错误:{}", name, e))?;
+    if !status.success() {
+        return Err(format!("{}执行失败: {}", name, status));
+    }
+    Ok(())
+}
+
+/// 告警的一行文本, 用于slack及邮件标题
+pub fn alert_text(alert: &JsonValue) -> String {
+    let detail = match alert["query"].as_str() {
+        Some(t) => t.split_whitespace().collect::<Vec<_>>().join(" "),
+        None => format!("delete {} rows, update {} rows in {}", alert["delete_rows"], alert["update_rows"],
+                        alert["tables"].as_object().map(|t| t.keys().cloned().collect::<Vec<_>>().join(",")).unwrap_or_default()),
+    };
+    format!("[mytest watch] {}: {} ({} {}:{}, gtid {}, {} UTC)",
+            alert["rule"].as_str().unwrap_or(""), detail,
+            alert["source"].as_str().unwrap_or(""), alert["binlog_file"].as_str().unwrap_or(""), alert["position"],
+            alert["gtid"].as_str().unwrap_or("-"), alert["datetime"].as_str().unwrap_or(""))
+}
+
+//语句修改的库及表: DROP TABLE db.t1, t2 -> [(db, t1), (默认库, t2)], DROP DATABASE db -> [(db, None)]
+fn statement_targets(words: &[String], default_db: &str) -> Vec<(String, Option<String>)> {
+    let mut database = false;
+    let mut targets = vec![];
+    for word in words.iter().skip(1) {
+        match word.to_uppercase().as_str() {
+            "DATABASE" | "SCHEMA" => database = true,
+            "TABLE" | "TABLES" | "TEMPORARY" | "IF" | "NOT" | "EXISTS" | "VIEW" | "ONLINE" | "OFFLINE" | "IGNORE" => {}
+            _ => {
+                for name in word.split(',').filter(|t| !t.is_empty()) {
+                    let name = crate::ident::split_qualified(name.trim_end_matches([';', '(']));
+                    if database {
+                        targets.push((name.join("."), None));
+                        continue;
+                    }
+                    match name.as_slice() {
+                        [db, tbl] => targets.push((db.clone(), Some(tbl.clone()))),
+                        _ => targets.push((default_db.to_string(), Some(name.join(".")))),
+                    }
+                }
+                //逗号分隔的多个表
+                if !word.ends_with(',') {
+                    break;
+                }
+            }
+        }
+    }
+    if targets.is_empty() {
+        targets.push((default_db.to_string(), None));
+    }
+    targets
+}
+
+/// 按规则检查事件, 匹配时生成告警并发送
+pub struct Watchdog{
+    rules: Vec<WatchRule>,
+    notifiers: Vec<Notifier>,
+    source: String,
+    binlog_file: String,
+    gtid: String,
+    thread_id: Option<u32>,
+    trx_position: Option<u64>,
+    queries: Vec<String>,
+    rows: BTreeMap<String, (u64, u64)>,         //当前事务中每个表删除、更新的行数
+}
+
+impl Watchdog{
+    pub fn new(rules: Vec<WatchRule>, notifiers: Vec<Notifier>, source: &str, binlog_file: &str) -> Watchdog {
+        Watchdog{
+            rules,
+            notifiers,
+            source: source.to_string(),
+            binlog_file: binlog_file.to_string(),
+            gtid: String::from(""),
+            thread_id: None,
+            trx_position: None,
+            queries: vec![],
+            rows: BTreeMap::new(),
+        }
+    }
+
+    /// 解析规则文件的内容, source为数据来源(host或文件), binlog_file为开始读取的binlog文件
+    pub fn from_json(text: &str, source: &str, binlog_file: &str) -> Result<Watchdog, String> {
+        let value: JsonValue = serde_json::from_str(text).map_err(|e| format!("watch规则不是有效的json: {}", e))?;
+        let rules = match value["rules"].as_array() {
+            Some(t) if !t.is_empty() => t.iter().enumerate().map(|(idx, r)| WatchRule::from_json(r, idx)).collect::<Result<Vec<_>, _>>()?,
+            _ => return Err(String::from("watch规则文件中没有配置rules"))
+        };
+        let notifiers = match value["notify"].as_array() {
+            Some(t) => t.iter().map(Notifier::from_json).collect::<Result<Vec<_>, _>>()?,
+            None => vec![]
+        };
+        Ok(Watchdog::new(rules, notifiers, source, binlog_file))
+    }
+
+    pub fn load(conf: &Config) -> Result<Watchdog, String> {
+        let text = fs::read_to_string(&conf.watch).map_err(|e| format!("读取watch规则文件({})发生错误:{}", conf.watch, e))?;
+        let (source, binlog_file) = match conf.runtype.as_str() {
+            "repl" => (conf.host_info.as_str(), conf.binlogfile.as_str()),
+            _ => (conf.file.as_str(), conf.file.as_str())
+        };
+        Watchdog::from_json(&text, source, binlog_file)
+    }
+
+    fn reset(&mut self) {
+        self.thread_id = None;
+        self.trx_position = None;
+        self.queries.clear();
+        self.rows.clear();
+    }
+
+    fn alert(&self, rule: &WatchRule, kind: &str, timestamp: u32, position: u64) -> serde_json::Map<String, JsonValue> {
+        let mut alert = serde_json::Map::new();
+        alert.insert(String::from("rule"), JsonValue::from(rule.name.clone()));
+        alert.insert(String::from("kind"), JsonValue::from(kind));
+        alert.insert(String::from("source"), JsonValue::from(self.source.clone()));
+        alert.insert(String::from("binlog_file"), JsonValue::from(self.binlog_file.clone()));
+        alert.insert(String::from("position"), JsonValue::from(position));
+        alert.insert(String::from("time"), JsonValue::from(timestamp));
+        alert.insert(String::from("datetime"), JsonValue::from(format::utc_datetime(timestamp)));
+        alert.insert(String::from("gtid"), if self.gtid.is_empty() { JsonValue::Null } else { JsonValue::from(self.gtid.clone()) });
+        alert.insert(String::from("thread_id"), self.thread_id.map_or(JsonValue::Null, JsonValue::from));
+        alert
+    }
+
+    //事务结束时检查行数规则
+    fn end_transaction(&mut self, timestamp: u32, log_pos: u64) -> Vec<JsonValue> {
+        let mut alerts = vec![];
+        for rule in &self.rules {
+            if rule.delete_rows.is_none() && rule.update_rows.is_none() {
+                continue;
+            }
+            let tables: BTreeMap<&String, &(u64, u64)> = self.rows.iter().filter(|(db_tbl, _)| {
+                let (db, tbl) = db_tbl.split_once('.').unwrap_or((db_tbl.as_str(), ""));
+                rule.match_table(db, Some(tbl))
+            }).collect();
+            let delete: u64 = tables.values().map(|t| t.0).sum();
+            let update: u64 = tables.values().map(|t| t.1).sum();
+            let exceeded = rule.delete_rows.is_some_and(|n| delete > n) || rule.update_rows.is_some_and(|n| update > n);
+            if !exceeded {
+                continue;
+            }
+            let mut alert = self.alert(rule, "rows", timestamp, self.trx_position.unwrap_or(log_pos));
+            alert.insert(String::from("commit_position"), JsonValue::from(log_pos));
+            alert.insert(String::from("delete_rows"), JsonValue::from(delete));
+            alert.insert(String::from("update_rows"), JsonValue::from(update));
+            alert.insert(String::from("tables"), JsonValue::Object(tables.iter().map(|(db_tbl, (d, u))| {
+                (db_tbl.to_string(), json!({"delete": d, "update": u}))
+            }).collect()));
+            alert.insert(String::from("queries"), JsonValue::from(self.queries.clone()));
+            alerts.push(JsonValue::Object(alert));
+        }
+        self.reset();
+        alerts
+    }
+
+    /// 检查一个事件, 返回匹配的告警, log_pos为事件的position
+    pub fn observe(&mut self, data: &Traction, db_tbl: &str, timestamp: u32, log_pos: u64) -> Vec<JsonValue> {
+        match data {
+            Traction::GtidEvent(t) => {
+                self.reset();
+                self.gtid = format!("{}:{}", t.gtid, t.gno_id);
+                self.trx_position = Some(log_pos);
+            }
+            Traction::RotateLogEvent(t) => {
+                self.binlog_file = t.binlog_file.clone();
+            }
+            Traction::QueryEvent(t) => {
+                self.thread_id = Some(t.thread_id);
+                self.trx_position.get_or_insert(log_pos);
+                match t.command.as_str() {
+                    "BEGIN" => {}
+                    "COMMIT" => return self.end_transaction(timestamp, log_pos),
+                    _ => {
+                        let words: Vec<String> = outddl::statement_body(&t.command).unwrap_or("")
+                            .split_whitespace().take(16).map(String::from).collect();
+                        let targets = statement_targets(&words, &t.database);
+                        let mut alerts = vec![];
+                        for rule in self.rules.iter().filter(|r| r.match_statement(&words)) {
+                            if !targets.iter().any(|(db, tbl)| rule.match_table(db, tbl.as_deref())) {
+                                continue;
+                            }
+                            let mut alert = self.alert(rule, "statement", timestamp, log_pos);
+                            alert.insert(String::from("database"), JsonValue::from(t.database.clone()));
+                            alert.insert(String::from("query"), JsonValue::from(t.command.clone()));
+                            alert.insert(String::from("execute_seconds"), JsonValue::from(t.execute_seconds));
+                            alerts.push(JsonValue::Object(alert));
+                        }
+                        //DDL单独作为一个事务
+                        self.reset();
+                        return alerts;
+                    }
+                }
+            }
+            Traction::RowsQueryEvent(t) if self.queries.len() < MAX_QUERIES => {
+                self.queries.push(t.query.clone());
+            }
+            Traction::RowEvent(code, rows) => {
+                self.trx_position.get_or_insert(log_pos);
+                let count = self.rows.entry(db_tbl.to_string()).or_insert((0, 0));
+                match code {
+                    BinlogEvent::DeleteEvent => count.0 += rows.rows().len() as u64,
+                    BinlogEvent::UpdateEvent => count.1 += rows.rows().len() as u64 / 2,
+                    _ => {}
+                }
+            }
+            Traction::XidEvent(_) => return self.end_transaction(timestamp, log_pos),
+            _ => {}
+        }
+        vec![]
+    }
+
+    /// 检查事件并发送告警
+    pub fn record(&mut self, data: &Traction, db_tbl: &str, timestamp: u32, log_pos: u64) {
+        for alert in self.observe(data, db_tbl, timestamp, log_pos) {
+            if self.notifiers.is_empty() {
+                eprintln!("{}", alert);
+                continue;
+            }
+            for notifier in &self.notifiers {
+                if let Err(e) = notifier.send(&alert) {
+                    let err = format!("发送watch告警({})发生错误:{}", alert["rule"].as_str().unwrap_or(""), e);
+                    eprintln!("{}", err);
+                    summary::record_error(&err);
+                }
+            }
+        }
+    }
+}
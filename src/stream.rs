@@ -0,0 +1,263 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/08
+*/
+
+//作为库使用时通过主从协议从mysql拉取binlog, 与BinlogReader返回相同的Event
+//连接、注册失败返回错误, 注册之后连接断开时get_packet_from_stream会一直等待
+//也可以从录制的数据包文件重放(见recording), 读取完后迭代结束
+//conf中设置untilgtid/untilposition时在包含边界的事务结束后断开连接, read_event返回UnexpectedEof, 迭代结束
+//开启test-support特性时可以通过with_faults注入连接断开、延迟及checksum损坏(见chaos)
+
+use std::io::{self, Cursor};
+use std::net::{Shutdown, TcpStream};
+use std::sync::Arc;
+use crate::Config;
+#[cfg(feature = "test-support")]
+use crate::chaos::Faults;
+use crate::control::Controller;
+use crate::failover::{MasterSwitch, MasterWatch};
+use crate::gtid::{Executed, ExecutedTracker, GtidSet, Until};
+use crate::io::{connection, pack, socketio};
+use crate::profile::{self, Stage};
+use crate::reader::{Event, EventParser};
+use crate::recording::{Recorder, Recording};
+use crate::replication;
+use crate::replication::readevent::EventHeader;
+use crate::replication::verify::{ChecksumClass, ChecksumVerifier};
+
+//数据包的来源
+enum Packets{
+    Conn(TcpStream),
+    Replay(Recording),
+}
+
+/// 通过主从协议拉取binlog, 按事件迭代, 不会主动结束
+pub struct BinlogStream{
+    conn: Packets,
+    recorder: Option<Arc<Recorder>>,
+    finished: bool,
+    parser: EventParser,
+    verifier: ChecksumVerifier,
+    conf: Config,
+    control: Option<Controller>,
+    executed: ExecutedTracker,
+    master: MasterWatch,
+    switches: Vec<MasterSwitch>,
+    until: Option<Until>,
+    #[cfg(feature = "test-support")]
+    faults: Option<Faults>,
+    #[cfg(feature = "test-support")]
+    disconnected: bool,
+}
+
+impl BinlogStream {
+    /// conf需要设置host_info、user_name、password、serverid, 以及gtid或binlogfile、position,
+    /// verifychecksum不为空时按该配置校验事件的crc32
+    /// 使用unix socket或ssh隧道时先调用endpoint::prepare
+    pub fn connect(conf: &Config) -> io::Result<BinlogStream> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        if conf.gtid.is_empty() && conf.binlogfile.is_empty() {
+            return Err(invalid(String::from("需要指定gtid或binlogfile")));
+        }
+        if !conf.gtid.is_empty() {
+            conf.gtid.parse::<GtidSet>().map_err(|e| invalid(e.to_string()))?;
+        }
+        if !conf.binlogfile.is_empty() {
+            conf.position.parse::<u32>().map_err(|_| invalid(format!("无效的position: {}", conf.position)))?;
+        }
+        conf.serverid.parse::<u32>().map_err(|_| invalid(format!("无效的serverid: {}", conf.serverid)))?;
+        let classes = ChecksumClass::parse(&conf.verifychecksum).map_err(invalid)?;
+        let until = Until::from_config(conf).map_err(invalid)?;
+
+        //create_mysql_conn在tcp连接失败时退出进程, 先检查是否可以连接
+        TcpStream::connect(&conf.host_info)?;
+        let mut conn = connection::create_mysql_conn(conf)
+            .map_err(|e| io::Error::new(io::ErrorKind::PermissionDenied, e))?;
+        //经过中间件时检查是否支持拉取binlog(见compat模块)
+        let profile = crate::compat::check_dump(&mut conn, conf).map_err(|e| io::Error::new(io::ErrorKind::Unsupported, e))?;
+        if let Some(profile) = profile {
+            profile.check_writer(&mut conn, conf).map_err(|e| io::Error::new(io::ErrorKind::ConnectionRefused, e))?;
+        }
+        replication::check_sum(&mut conn);
+        replication::heartbeat_period(&mut conn, conf).map_err(invalid)?;
+        let regist_pack = if conf.gtid.is_empty() {
+            replication::binlog_dump_pack(conf)
+        } else {
+            replication::gtid_dump_pack(conf)
+        };
+        socketio::write_value(&mut conn, &regist_pack).map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(BinlogStream{ until, ..BinlogStream::new(Packets::Conn(conn), classes, conf.clone()) })
+    }
+
+    /// 重放录制的数据包, 已执行位置从录制时注册的位置开始, verifychecksum不为空时同样校验
+    /// 读取完录制文件后迭代结束, read_event返回UnexpectedEof; 不能使用控制接口回退
+    pub fn replay(recording: Recording, conf: &Config) -> io::Result<BinlogStream> {
+        let classes = ChecksumClass::parse(&conf.verifychecksum).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let until = Until::from_config(conf).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut conf = conf.clone();
+        recording.start().apply(&mut conf);
+        Ok(BinlogStream{ until, ..BinlogStream::new(Packets::Replay(recording), classes, conf) })
+    }
+
+    fn new(conn: Packets, classes: ChecksumClass, conf: Config) -> BinlogStream {
+        let executed = ExecutedTracker::from_config(&conf);
+        BinlogStream{ conn, recorder: None, finished: false, parser: EventParser::new(), verifier: ChecksumVerifier::new(classes), conf, control: None, executed, master: MasterWatch::new(), switches: vec![], until: None,
+            #[cfg(feature = "test-support")]
+            faults: None,
+            #[cfg(feature = "test-support")]
+            disconnected: false }
+    }
+
+    /// 把收到的每个数据包写入录制文件, 用于之后通过replay复现
+    pub fn with_recorder(mut self, recorder: Arc<Recorder>) -> BinlogStream {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// 按faults的配置注入故障, 注入连接断开后read_event一直返回ConnectionReset, 与真实断线一样需要按executed()重新连接
+    #[cfg(feature = "test-support")]
+    pub fn with_faults(mut self, faults: Faults) -> BinlogStream {
+        self.faults = Some(faults);
+        self
+    }
+
+    /// 使用控制接口(见control模块)暂停、回退、跳过事务, 可以同时通过control::serve提供http接口
+    /// 回退时使用connect的配置重新连接, 被跳过事务的事件不返回
+    /// 连接状态由BinlogStream更新, 健康检查需要在输出目标确认(flush)后调用controller.checkpointed()
+    pub fn with_control(mut self, controller: Controller) -> BinlogStream {
+        controller.set_connected(true);
+        controller.set_start(&self.conf);
+        self.control = Some(controller);
+        self
+    }
+
+    /// 已完整读取的事务的gtid集合及对应的binlog文件、position, 可以直接用于CHANGE REPLICATION SOURCE(Executed::change_source)
+    /// 包含控制接口跳过的事务, 按gtid注册时从注册的集合开始
+    pub fn executed(&self) -> Executed {
+        self.executed.executed()
+    }
+
+    /// 已到达conf中的until位置, 之后read_event返回UnexpectedEof
+    pub fn until_reached(&self) -> bool {
+        self.until.as_ref().is_some_and(|u| u.reached(&self.executed))
+    }
+
+    /// 之后检测到的主库切换(见failover模块), 返回后清空; 使用控制接口时同时记录在/status中
+    pub fn take_master_switches(&mut self) -> Vec<MasterSwitch> {
+        std::mem::take(&mut self.switches)
+    }
+
+    /// 读取下一个事件, mysql返回错误包时返回错误
+    pub fn read_event(&mut self) -> io::Result<Event> {
+        let controller = match self.control.clone() {
+            Some(t) => t,
+            None => return self.read_next(),
+        };
+        loop {
+            if let Some(position) = controller.checkpoint() {
+                controller.set_connected(false);
+                self.reconnect(&position.apply(&self.conf))?;
+                controller.set_connected(true);
+            }
+            let event = self.read_next().inspect_err(|_| controller.set_connected(false))?;
+            if controller.event(&event.data, event.header.timestamp, event.header.next_position as u64) {
+                return Ok(event);
+            }
+        }
+    }
+
+    fn reconnect(&mut self, conf: &Config) -> io::Result<()> {
+        if let Packets::Replay(_) = self.conn {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "重放录制文件时不能回退"));
+        }
+        TcpStream::connect(&conf.host_info)?;
+        let mut conn = connection::create_mysql_conn(conf)
+            .map_err(|e| io::Error::new(io::ErrorKind::PermissionDenied, e))?;
+        replication::start_dump(&mut conn, conf).map_err(io::Error::other)?;
+        self.conn = Packets::Conn(conn);
+        self.parser = EventParser::new();
+        self.executed.restart(conf);
+        #[cfg(feature = "test-support")]
+        {
+            self.disconnected = false;
+        }
+        Ok(())
+    }
+
+    fn read_next(&mut self) -> io::Result<Event> {
+        if self.until_reached() {
+            if let Packets::Conn(conn) = &self.conn {
+                let _ = conn.shutdown(Shutdown::Both);
+            }
+            self.finished = true;
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "已到达until的位置"));
+        }
+        #[cfg(feature = "test-support")]
+        if let Some(faults) = &self.faults {
+            if self.disconnected || faults.before_packet() {
+                if let Packets::Conn(conn) = &self.conn {
+                    let _ = conn.shutdown(Shutdown::Both);
+                }
+                self.disconnected = true;
+                return Err(io::Error::new(io::ErrorKind::ConnectionReset, "注入的连接断开"));
+            }
+        }
+        let timer = profile::start(Stage::Read);
+        let buf = match &mut self.conn {
+            Packets::Conn(conn) => socketio::get_packet_from_stream(conn).0,
+            Packets::Replay(recording) => match recording.next_packet()? {
+                Some(t) => t.data,
+                None => {
+                    self.finished = true;
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "录制文件已读取完"));
+                }
+            }
+        };
+        drop(timer);
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&buf)?;
+        }
+        if !pack::check_pack(&buf) {
+            return Err(io::Error::other(format!("注册slave发生错误:{}", pack::erro_pack(&buf))));
+        }
+        let mut buf = pack::event_data(buf);
+        if buf.len() < 19 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "事件长度错误"));
+        }
+        #[cfg(feature = "test-support")]
+        if let Some(faults) = &self.faults {
+            faults.corrupt_event(&mut buf);
+        }
+        let header = EventHeader::read(&mut Cursor::new(&buf[..19]))?;
+        //artificial事件的next_position为0
+        let log_pos = (header.next_position as u64).saturating_sub(header.event_length as u64);
+        self.verifier.verify(&buf[..19], &buf[19..], log_pos)?;
+        let payload = buf.split_off(19);
+        let event = self.parser.parse_event(header, log_pos, payload.clone()).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("position {} 处的事件解析错误: {}", log_pos, e))
+        })?;
+        if let Some(switch) = self.master.observe(&event.header, &event.data, &payload, &self.executed) {
+            if let Some(controller) = &self.control {
+                controller.switched(&switch, false);
+            }
+            self.switches.push(switch);
+        }
+        self.executed.observe(&event.data, event.header.next_position as u64);
+        Ok(event)
+    }
+}
+
+impl Iterator for BinlogStream {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        if self.finished {
+            return None;
+        }
+        match self.read_event() {
+            Err(_) if self.finished => None,
+            t => Some(t),
+        }
+    }
+}
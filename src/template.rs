@@ -0,0 +1,241 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//输出模板: 兼容handlebars常用语法的简单模板, 按json对象渲染, 用于对接只接受固定行格式的日志采集程序
+//  {{path}}                取值, path为.分隔的字段名, 例如data.id、old.name、labels.env, 不存在或null时为空
+//  {{path|filter|...}}     过滤器: json(按json输出, 字符串带引号并转义)、upper、lower、default:文本、trunc:N(最多N个字符)
+//  {{#if path}}..{{else}}..{{/if}}、{{#unless path}}..{{/unless}}   null、false、0、""、[]、{}为假
+//  {{#each path}}..{{/each}}   遍历数组或对象, 其中this为当前值, @key、@index、@first、@last, 其他字段先在当前值中查找
+//  {{! 注释}}
+//  字符串原样输出, 数字、布尔按文本输出, 对象、数组按json输出
+
+use serde_json::Value as JsonValue;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter{
+    Json,
+    Upper,
+    Lower,
+    Default(String),
+    Trunc(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node{
+    Text(String),
+    Var{ path: String, filters: Vec<Filter> },
+    If{ path: String, negate: bool, then: Vec<Node>, otherwise: Vec<Node> },
+    Each{ path: String, body: Vec<Node>, otherwise: Vec<Node> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template{
+    nodes: Vec<Node>,
+}
+
+//{{#each}}中的当前值
+struct Frame<'a>{
+    value: &'a JsonValue,
+    key: Option<String>,
+    index: usize,
+    first: bool,
+    last: bool,
+}
+
+fn parse_filters(expr: &str) -> Result<(String, Vec<Filter>), String> {
+    let mut parts = expr.split('|').map(|t| t.trim());
+    let path = parts.next().unwrap_or("").to_string();
+    if path.is_empty() {
+        return Err(format!("模板中的字段名为空: {{{{{}}}}}", expr));
+    }
+    let mut filters = vec![];
+    for part in parts {
+        let (name, arg) = match part.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg)),
+            None => (part, None)
+        };
+        let filter = match (name, arg) {
+            ("json", None) => Filter::Json,
+            ("upper", None) => Filter::Upper,
+            ("lower", None) => Filter::Lower,
+            ("default", Some(t)) => Filter::Default(t.to_string()),
+            ("trunc", Some(t)) => Filter::Trunc(t.trim().parse().map_err(|_| format!("trunc的参数应为数字: {}", part))?),
+            _ => return Err(format!("无效的模板过滤器: {}, 可选json、upper、lower、default:文本、trunc:N", part))
+        };
+        filters.push(filter);
+    }
+    Ok((path, filters))
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, String> {
+        let mut tags = vec![];
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            tags.push((false, rest[..start].to_string()));
+            let end = rest[start..].find("}}").ok_or_else(|| format!("模板中的{{{{没有对应的}}}}: {}", &rest[start..]))?;
+            tags.push((true, rest[start + 2..start + end].trim().to_string()));
+            rest = &rest[start + end + 2..];
+        }
+        tags.push((false, rest.to_string()));
+        let mut iter = tags.into_iter();
+        let (nodes, end) = Template::parse_block(&mut iter)?;
+        match end {
+            None => Ok(Template{ nodes }),
+            Some(t) => Err(format!("模板中的{{{{{}}}}}没有对应的开始标签", t))
+        }
+    }
+
+    //解析到块结束(else、/xx)或模板末尾, 返回结束的标签
+    fn parse_block<I: Iterator<Item=(bool, String)>>(iter: &mut I) -> Result<(Vec<Node>, Option<String>), String> {
+        let mut nodes = vec![];
+        while let Some((tag, content)) = iter.next() {
+            if !tag {
+                if !content.is_empty() {
+                    nodes.push(Node::Text(content));
+                }
+                continue;
+            }
+            if content.starts_with('!') {
+                continue;
+            }
+            if content == "else" || content.starts_with('/') {
+                return Ok((nodes, Some(content)));
+            }
+            if let Some(block) = content.strip_prefix('#') {
+                let (name, path) = match block.split_once(char::is_whitespace) {
+                    Some((name, path)) => (name, path.trim().to_string()),
+                    None => (block, String::new())
+                };
+                if !["if", "unless", "each"].contains(&name) {
+                    return Err(format!("无效的模板块: {{{{#{}}}}}, 可选#if、#unless、#each", block));
+                }
+                if path.is_empty() {
+                    return Err(format!("模板块缺少字段名: {{{{#{}}}}}", block));
+                }
+                let (body, end) = Template::parse_block(iter)?;
+                let (otherwise, end) = match end.as_deref() {
+                    Some("else") => Template::parse_block(iter)?,
+                    _ => (vec![], end)
+                };
+                if end.as_deref() != Some(&format!("/{}", name)) {
+                    return Err(format!("模板块{{{{#{}}}}}没有对应的{{{{/{}}}}}", block, name));
+                }
+                nodes.push(match name {
+                    "each" => Node::Each{ path, body, otherwise },
+                    _ => Node::If{ path, negate: name == "unless", then: body, otherwise },
+                });
+                continue;
+            }
+            let (path, filters) = parse_filters(&content)?;
+            nodes.push(Node::Var{ path, filters });
+        }
+        Ok((nodes, None))
+    }
+
+    pub fn render(&self, context: &JsonValue) -> String {
+        let mut out = String::new();
+        let mut frames = vec![Frame{ value: context, key: None, index: 0, first: true, last: true }];
+        render_nodes(&self.nodes, &mut frames, &mut out);
+        out
+    }
+}
+
+fn lookup<'a>(path: &str, frames: &[Frame<'a>]) -> Option<JsonValue> {
+    let top = frames.last()?;
+    match path {
+        "@key" => return top.key.clone().map(JsonValue::from),
+        "@index" => return Some(JsonValue::from(top.index)),
+        "@first" => return Some(JsonValue::from(top.first)),
+        "@last" => return Some(JsonValue::from(top.last)),
+        "this" | "." => return Some(top.value.clone()),
+        _ => {}
+    }
+    let (mut value, rest) = match path.strip_prefix("this.") {
+        Some(rest) => (top.value, rest),
+        None => {
+            //先在当前值中查找, 再依次到外层查找
+            let first = path.split('.').next().unwrap_or("");
+            let frame = frames.iter().rev().find(|f| f.value.get(first).is_some())?;
+            (frame.value, path)
+        }
+    };
+    for key in rest.split('.') {
+        value = match value {
+            JsonValue::Array(t) => t.get(key.parse::<usize>().ok()?)?,
+            t => t.get(key)?
+        };
+    }
+    Some(value.clone())
+}
+
+fn truthy(value: &Option<JsonValue>) -> bool {
+    match value {
+        None | Some(JsonValue::Null) => false,
+        Some(JsonValue::Bool(t)) => *t,
+        Some(JsonValue::Number(t)) => t.as_f64().map(|n| n != 0.0).unwrap_or(true),
+        Some(JsonValue::String(t)) => !t.is_empty(),
+        Some(JsonValue::Array(t)) => !t.is_empty(),
+        Some(JsonValue::Object(t)) => !t.is_empty(),
+    }
+}
+
+fn text(value: &Option<JsonValue>) -> String {
+    match value {
+        None | Some(JsonValue::Null) => String::new(),
+        Some(JsonValue::String(t)) => t.clone(),
+        Some(t) => t.to_string()
+    }
+}
+
+fn render_nodes<'a>(nodes: &[Node], frames: &mut Vec<Frame<'a>>, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(t) => out.push_str(t),
+            Node::Var{ path, filters } => {
+                let value = lookup(path, frames);
+                let mut result = text(&value);
+                for filter in filters {
+                    result = match filter {
+                        Filter::Json => value.clone().unwrap_or(JsonValue::Null).to_string(),
+                        Filter::Upper => result.to_uppercase(),
+                        Filter::Lower => result.to_lowercase(),
+                        Filter::Default(t) if result.is_empty() => t.clone(),
+                        Filter::Default(_) => result,
+                        Filter::Trunc(n) => result.chars().take(*n).collect(),
+                    };
+                }
+                out.push_str(&result);
+            }
+            Node::If{ path, negate, then, otherwise } => {
+                let value = lookup(path, frames);
+                if truthy(&value) != *negate {
+                    render_nodes(then, frames, out);
+                }else {
+                    render_nodes(otherwise, frames, out);
+                }
+            }
+            Node::Each{ path, body, otherwise } => {
+                let value = lookup(path, frames);
+                let items: Vec<(Option<String>, JsonValue)> = match value {
+                    Some(JsonValue::Array(t)) => t.into_iter().map(|v| (None, v)).collect(),
+                    Some(JsonValue::Object(t)) => t.into_iter().map(|(k, v)| (Some(k), v)).collect(),
+                    _ => vec![]
+                };
+                if items.is_empty() {
+                    render_nodes(otherwise, frames, out);
+                    continue;
+                }
+                let count = items.len();
+                for (index, (key, item)) in items.iter().enumerate() {
+                    //frames中保存引用, 当前值的生命周期只在本次循环中
+                    let mut inner: Vec<Frame> = frames.iter().map(|f| Frame{ value: f.value, key: f.key.clone(), index: f.index, first: f.first, last: f.last }).collect();
+                    inner.push(Frame{ value: item, key: key.clone(), index, first: index == 0, last: index + 1 == count });
+                    render_nodes(body, &mut inner, out);
+                }
+            }
+        }
+    }
+}
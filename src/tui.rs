@@ -0,0 +1,269 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/11
+*/
+
+//tui子命令: 交互式浏览binlog文件
+//  列表中每行为一个事务(事务之外的事件不显示), 回车展开事务中的事件及行数据
+//  /按表过滤(db.tbl的子串), :跳转到position, @跳转到时间(unix时间戳或utc时间 2019-11-11 10:00:00)
+//  为了支持较大的文件, 加载时只建立事务索引, 展开时按position重新读取该事务
+
+use std::io;
+use std::process;
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use crate::{Config, exitcode};
+use crate::index::{self, PositionIndex, TrxSummary};
+use crate::replication::verify::ChecksumClass;
+use crate::stdout::format::ValueFormat;
+
+/// tui的状态, 与终端无关
+pub struct Browser{
+    pub index: PositionIndex,
+    filter: String,
+    visible: Vec<usize>,
+    selected: usize,
+}
+
+impl Browser{
+    pub fn load(path: &str, relaylog: bool, classes: ChecksumClass) -> io::Result<Browser> {
+        let index = PositionIndex::build(path, relaylog, classes)?;
+        let visible = (0..index.transactions.len()).collect();
+        Ok(Browser{ index, filter: String::new(), visible, selected: 0 })
+    }
+
+    /// 按表过滤, 只显示包含db.tbl中含有filter的事务, 为空时显示全部
+    pub fn set_filter(&mut self, filter: &str) {
+        let current = self.selected_trx().map(|t| t.start);
+        self.filter = filter.to_string();
+        self.visible = self.index.transactions.iter().enumerate()
+            .filter(|(_, t)| filter.is_empty() || t.tables.iter().any(|tbl| tbl.contains(filter)))
+            .map(|(idx, _)| idx).collect();
+        self.selected = 0;
+        if let Some(pos) = current {
+            self.jump_position(pos);
+        }
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    /// 当前显示的事务
+    pub fn visible(&self) -> Vec<&TrxSummary> {
+        self.visible.iter().map(|idx| &self.index.transactions[*idx]).collect()
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_trx(&self) -> Option<&TrxSummary> {
+        self.visible.get(self.selected).map(|idx| &self.index.transactions[*idx])
+    }
+
+    /// 向下(正数)或向上移动选中的行
+    pub fn move_by(&mut self, offset: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let last = self.visible.len() - 1;
+        self.selected = (self.selected as isize + offset).clamp(0, last as isize) as usize;
+    }
+
+    /// 跳转到包含该position或在其之后的第一个事务
+    pub fn jump_position(&mut self, position: u64) -> bool {
+        match self.visible.iter().position(|idx| self.index.transactions[*idx].end > position) {
+            Some(t) => {
+                self.selected = t;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// 跳转到该时间之后的第一个事务, 支持unix时间戳或utc时间(%Y-%m-%d %H:%M:%S)
+    pub fn jump_time(&mut self, time: &str) -> Result<bool, String> {
+        let timestamp = index::parse_time(time)?;
+        match self.visible.iter().position(|idx| self.index.transactions[*idx].timestamp >= timestamp) {
+            Some(t) => {
+                self.selected = t;
+                Ok(true)
+            }
+            None => Ok(false)
+        }
+    }
+
+    /// 重新读取选中的事务, 返回事件及行数据的文本
+    pub fn expand(&self) -> io::Result<Vec<String>> {
+        let trx = match self.selected_trx() {
+            Some(t) => t,
+            None => return Ok(vec![])
+        };
+        let value_format = ValueFormat::default();
+        Ok(self.index.read_transaction(trx)?.iter().flat_map(|e| index::event_lines(e, &value_format)).collect())
+    }
+}
+
+//底部输入框的用途
+enum Prompt{
+    Filter,
+    Position,
+    Time,
+}
+
+struct App{
+    browser: Browser,
+    list_state: ListState,
+    detail: Option<Vec<String>>,
+    detail_scroll: u16,
+    prompt: Option<(Prompt, String)>,
+    message: String,
+}
+
+impl App{
+    fn on_key(&mut self, code: KeyCode) -> bool {
+        if let Some((prompt, input)) = self.prompt.as_mut() {
+            match code {
+                KeyCode::Esc => self.prompt = None,
+                KeyCode::Backspace => { input.pop(); }
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Enter => {
+                    let input = input.clone();
+                    self.message = match prompt {
+                        Prompt::Filter => {
+                            self.browser.set_filter(input.trim());
+                            format!("{} 个事务", self.browser.visible().len())
+                        }
+                        Prompt::Position => match input.trim().parse::<u64>() {
+                            Ok(t) if self.browser.jump_position(t) => String::new(),
+                            Ok(t) => format!("position {} 之后没有事务", t),
+                            Err(_) => format!("无效的position: {}", input)
+                        }
+                        Prompt::Time => match self.browser.jump_time(&input) {
+                            Ok(true) => String::new(),
+                            Ok(false) => format!("{} 之后没有事务", input),
+                            Err(e) => e
+                        }
+                    };
+                    self.prompt = None;
+                    self.detail = None;
+                }
+                _ => {}
+            }
+            return true;
+        }
+
+        if let Some(detail) = &self.detail {
+            match code {
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Left => self.detail = None,
+                KeyCode::Down | KeyCode::Char('j') => self.detail_scroll = self.detail_scroll.saturating_add(1).min(detail.len() as u16),
+                KeyCode::Up | KeyCode::Char('k') => self.detail_scroll = self.detail_scroll.saturating_sub(1),
+                KeyCode::PageDown => self.detail_scroll = self.detail_scroll.saturating_add(20).min(detail.len() as u16),
+                KeyCode::PageUp => self.detail_scroll = self.detail_scroll.saturating_sub(20),
+                KeyCode::Char('q') => return false,
+                _ => {}
+            }
+            return true;
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Down | KeyCode::Char('j') => self.browser.move_by(1),
+            KeyCode::Up | KeyCode::Char('k') => self.browser.move_by(-1),
+            KeyCode::PageDown => self.browser.move_by(20),
+            KeyCode::PageUp => self.browser.move_by(-20),
+            KeyCode::Home | KeyCode::Char('g') => self.browser.move_by(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.browser.move_by(isize::MAX / 2),
+            KeyCode::Char('/') => self.prompt = Some((Prompt::Filter, self.browser.filter().to_string())),
+            KeyCode::Char(':') => self.prompt = Some((Prompt::Position, String::new())),
+            KeyCode::Char('@') => self.prompt = Some((Prompt::Time, String::new())),
+            KeyCode::Enter | KeyCode::Right => {
+                match self.browser.expand() {
+                    Ok(t) => {
+                        self.detail = Some(t);
+                        self.detail_scroll = 0;
+                    }
+                    Err(e) => self.message = e.to_string()
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] = Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+        let title = format!(" {} ({}/{} 个事务{}) ", self.browser.index.path, self.browser.visible().len(), self.browser.index.transactions.len(),
+                            if self.browser.filter().is_empty() { String::new() } else { format!(", 过滤: {}", self.browser.filter()) });
+
+        match &self.detail {
+            Some(detail) => {
+                let header = self.browser.selected_trx().map(|t| t.line()).unwrap_or_default();
+                let text: Vec<Line> = detail.iter().map(|l| Line::from(l.as_str())).collect();
+                let paragraph = Paragraph::new(text)
+                    .block(Block::default().borders(Borders::ALL).title(format!(" {} ", header.trim())))
+                    .scroll((self.detail_scroll, 0));
+                frame.render_widget(paragraph, main);
+            }
+            None => {
+                let items: Vec<ListItem> = self.browser.visible().iter().map(|t| ListItem::new(t.line())).collect();
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL).title(title))
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                self.list_state.select(Some(self.browser.selected()));
+                frame.render_stateful_widget(list, main, &mut self.list_state);
+            }
+        }
+
+        let status_text = match &self.prompt {
+            Some((Prompt::Filter, input)) => format!("过滤表(db.tbl): {}", input),
+            Some((Prompt::Position, input)) => format!("跳转到position: {}", input),
+            Some((Prompt::Time, input)) => format!("跳转到时间: {}", input),
+            None if !self.message.is_empty() => self.message.clone(),
+            None => match (&self.browser.index.error, &self.detail) {
+                (Some(e), None) => format!("读取中止: {}", e),
+                (_, Some(_)) => String::from("↑↓ 滚动  Enter/Esc 返回  q 退出"),
+                _ => String::from("↑↓/PgUp/PgDn 移动  Enter 展开  / 过滤表  : position  @ 时间  q 退出"),
+            }
+        };
+        frame.render_widget(Paragraph::new(status_text), status);
+    }
+}
+
+fn run_app(terminal: &mut DefaultTerminal, mut app: App) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if let TermEvent::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if app.prompt.is_none() && app.detail.is_none() {
+                app.message.clear();
+            }
+            if !app.on_key(key.code) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+pub fn run(conf: &Config) {
+    let classes = ChecksumClass::parse(&conf.verifychecksum).unwrap_or(ChecksumClass::empty());
+    let browser = Browser::load(&conf.file, conf.relaylog, classes).unwrap_or_else(|err|{
+        println!("读取文件({})发生错误:{}", conf.file, err);
+        process::exit(exitcode::USAGE);
+    });
+    let app = App{ browser, list_state: ListState::default(), detail: None, detail_scroll: 0, prompt: None, message: String::new() };
+    let mut terminal = ratatui::init();
+    let result = run_app(&mut terminal, app);
+    ratatui::restore();
+    if let Err(err) = result {
+        println!("终端发生错误:{}", err);
+        process::exit(exitcode::ERROR);
+    }
+}
@@ -0,0 +1,128 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//解析及输出过程中的警告, 与数据输出分开: 数据只写入stdout或输出目标, 警告交给回调处理并按类型计数
+//  默认的处理方式为输出到stderr, 每种类型前10条输出, 之后只计数, 避免每个事件或每个值都输出一行
+//  作为库使用时可以通过set_handler接收所有警告(写入日志、上报监控等)
+//  各类型的数量记录在--summary的warnings及控制接口/status的warnings中
+//  严格模式(--strict)下解析相关的警告返回错误(见readvalue::mismatch), 不再计数
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use serde_json::{Map, Value as JsonValue};
+
+/// 警告的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    /// 未知类型的事件, 按event_length跳过
+    UnknownEvent,
+    /// 未知类型的字段, 按blob的格式读取原始内容
+    UnknownColumnType,
+    /// metadata与字段类型不符
+    Metadata,
+    /// 事件解析结束的位置与事件长度不符
+    TrailingData,
+    /// 不是合法的utf8, 按替换字符或十六进制输出
+    CharsetFallback,
+    /// BLOB/TEXT超过--lobmaxsize被截断或替换为hash值
+    TruncatedBlob,
+    /// --lenient-checksum时的checksum错误
+    ChecksumMismatch,
+    /// 表没有主键及非空的唯一键, update/delete无法准确定位行, 见nokey
+    NoPrimaryKey,
+}
+
+impl WarningKind {
+    pub const ALL: [WarningKind; 8] = [WarningKind::UnknownEvent, WarningKind::UnknownColumnType, WarningKind::Metadata,
+        WarningKind::TrailingData, WarningKind::CharsetFallback, WarningKind::TruncatedBlob, WarningKind::ChecksumMismatch,
+        WarningKind::NoPrimaryKey];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::UnknownEvent => "unknown_event",
+            WarningKind::UnknownColumnType => "unknown_column_type",
+            WarningKind::Metadata => "metadata",
+            WarningKind::TrailingData => "trailing_data",
+            WarningKind::CharsetFallback => "charset_fallback",
+            WarningKind::TruncatedBlob => "truncated_blob",
+            WarningKind::ChecksumMismatch => "checksum_mismatch",
+            WarningKind::NoPrimaryKey => "no_primary_key",
+        }
+    }
+
+    fn index(&self) -> usize {
+        WarningKind::ALL.iter().position(|t| t == self).unwrap_or(0)
+    }
+}
+
+/// 一条警告
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "警告({}): {}", self.kind.name(), self.message)
+    }
+}
+
+type Handler = Box<dyn Fn(&Warning) + Send + Sync>;
+
+static COUNTS: [AtomicU64; 8] = [const { AtomicU64::new(0) }; 8];
+static HANDLER: RwLock<Option<Handler>> = RwLock::new(None);
+
+//默认处理方式每种类型输出的条数
+const PRINT_LIMIT: u64 = 10;
+
+/// 设置接收警告的回调, 替换默认的输出到stderr
+pub fn set_handler<F>(handler: F) where F: Fn(&Warning) + Send + Sync + 'static {
+    if let Ok(mut t) = HANDLER.write() {
+        *t = Some(Box::new(handler));
+    }
+}
+
+/// 恢复为默认的输出到stderr
+pub fn reset_handler() {
+    if let Ok(mut t) = HANDLER.write() {
+        *t = None;
+    }
+}
+
+/// 记录一条警告
+pub fn emit(kind: WarningKind, message: String) {
+    let count = COUNTS[kind.index()].fetch_add(1, Ordering::Relaxed) + 1;
+    let warning = Warning{ kind, message };
+    match HANDLER.read().ok().as_ref().and_then(|t| t.as_ref()) {
+        Some(handler) => handler(&warning),
+        None if count < PRINT_LIMIT => eprintln!("{}", warning),
+        None if count == PRINT_LIMIT => eprintln!("{} (该类型的警告之后只计数)", warning),
+        None => {}
+    }
+}
+
+/// 该类型的警告数
+pub fn count(kind: WarningKind) -> u64 {
+    COUNTS[kind.index()].load(Ordering::Relaxed)
+}
+
+/// 所有类型的警告数
+pub fn total() -> u64 {
+    WarningKind::ALL.iter().map(|t| count(*t)).sum()
+}
+
+/// 有警告的类型及数量, 例如{"unknown_event": 3}
+pub fn stats_json() -> JsonValue {
+    let mut map = Map::new();
+    for kind in WarningKind::ALL.iter() {
+        let n = count(*kind);
+        if n > 0 {
+            map.insert(kind.name().to_string(), JsonValue::from(n));
+        }
+    }
+    JsonValue::Object(map)
+}
@@ -0,0 +1,352 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//并行应用: 冲突检测及分配, 不同表、不同主键的事务并行执行, 相同主键的事务按顺序执行, DDL单独执行
+//目标库已有数据时按表配置的冲突处理方式: error、skip、overwrite、dlq
+#![cfg(all(feature = "os", feature = "test-support"))]
+
+mod common;
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use mytest::prelude::*;
+use mytest::replication::readevent::{QueryEvent, XidEvent};
+use mytest::sink::{ApplyExecutor, ApplyMode, ApplyRules, ApplySink, AuditTable, ConflictPolicy, ConflictRules, ConflictStats, Scheduler};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+use common::{int_row, int_table};
+
+#[test]
+fn scheduler() {
+    let keys = |k: &[&str]| k.iter().map(|t| t.to_string()).collect::<Vec<String>>();
+    let mut s = Scheduler::new(2);
+    assert_eq!(s.schedule(Some(&keys(&["a"])), &[0, 0]), Ok((0, 1)));
+    //不冲突时分配给空闲的worker
+    assert_eq!(s.schedule(Some(&keys(&["b"])), &[0, 0]), Ok((1, 2)));
+    //与worker 0上未完成的事务冲突, 分配给同一个worker
+    assert_eq!(s.schedule(Some(&keys(&["a"])), &[0, 0]), Ok((0, 3)));
+    //与两个worker冲突, 等待其中一个完成
+    assert_eq!(s.schedule(Some(&keys(&["a", "b"])), &[0, 0]), Err(vec![(1, 2)]));
+    assert_eq!(s.schedule(Some(&keys(&["a", "b"])), &[0, 2]), Ok((0, 4)));
+    //单独执行的事务等待全部完成, 之后的事务等待它完成
+    assert_eq!(s.schedule(None, &[3, 2]), Err(vec![(0, 4)]));
+    assert_eq!(s.schedule(None, &[4, 2]), Ok((0, 5)));
+    assert_eq!(s.schedule(Some(&keys(&["c"])), &[4, 2]), Err(vec![(0, 5)]));
+    assert_eq!(s.schedule(Some(&keys(&["c"])), &[5, 2]), Ok((1, 6)));
+
+    let rules = ApplyRules::new("shop.orders=key, shop.*=table, `log`.* = serial").unwrap();
+    assert_eq!(rules.mode("shop", "orders"), ApplyMode::Key);
+    assert_eq!(rules.mode("shop", "users"), ApplyMode::Table);
+    assert_eq!(rules.mode("log", "access"), ApplyMode::Serial);
+    assert_eq!(rules.mode("other", "t1"), ApplyMode::Table);
+    assert!(ApplyRules::new("shop.orders=fast").is_err());
+    assert!(ApplyRules::new("shop.orders").is_err());
+}
+
+//记录每个事务的语句及执行的起止时间
+#[derive(Clone, Default)]
+struct Recorder{
+    executed: Arc<Mutex<Vec<(Vec<String>, Instant, Instant)>>>,
+    fail: Option<&'static str>,
+    current: Vec<String>,
+    start: Option<Instant>,
+}
+
+impl ApplyExecutor for Recorder {
+    fn execute(&mut self, sql: &str) -> Result<u64, String> {
+        match sql {
+            "BEGIN" => {
+                self.current.clear();
+                self.start = Some(Instant::now());
+            }
+            "COMMIT" => {
+                thread::sleep(Duration::from_millis(100));
+                self.executed.lock().unwrap().push((std::mem::take(&mut self.current), self.start.unwrap(), Instant::now()));
+            }
+            "ROLLBACK" => self.current.clear(),
+            _ => {
+                if self.fail.is_some_and(|f| sql.contains(f)) {
+                    return Err(String::from("执行语句错误: #23000Duplicate entry '1' for key 'PRIMARY'"));
+                }
+                self.current.push(sql.to_string());
+            }
+        }
+        Ok(1)
+    }
+}
+
+fn columns() -> HashMap<String, Vec<HashMap<String, String>>> {
+    let col = |name: &str, key: &str| {
+        let mut col = HashMap::new();
+        col.insert(String::from("COLUMN_NAME"), name.to_string());
+        col.insert(String::from("COLUMN_TYPE"), String::from("int(11)"));
+        col.insert(String::from("COLUMN_KEY"), key.to_string());
+        col
+    };
+    let mut info = HashMap::new();
+    info.insert(String::from("shop.orders"), vec![col("id", "PRI"), col("amount", "")]);
+    info.insert(String::from("shop.users"), vec![col("id", "PRI"), col("age", "")]);
+    info
+}
+
+fn begin() -> QueryEvent {
+    QueryEvent{ thread_id: 1, execute_seconds: 0, database: String::from("shop"), command: String::from("BEGIN"), invoker: String::new() }
+}
+
+//事务: (表, 事件类型, 行)
+fn binlog(transactions: &[(&str, BinlogEvent, Vec<Vec<Option<MySQLValue>>>)], ddl_after: usize) -> Vec<Event> {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    for (idx, (name, code, rows)) in transactions.iter().enumerate() {
+        let map = int_table(name);
+        builder.query(&begin());
+        builder.table_map(100 + idx as u64, &map).unwrap();
+        builder.rows(code, 100 + idx as u64, &map, rows).unwrap();
+        builder.xid(&XidEvent{ xid: idx as u64 });
+        if idx + 1 == ddl_after {
+            builder.query(&QueryEvent{ command: String::from("ALTER TABLE users ADD COLUMN note int"), ..begin() });
+        }
+    }
+    BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap().collect::<std::io::Result<Vec<Event>>>().unwrap()
+}
+
+fn apply(events: &[Event], recorder: &Recorder, rules: &str) -> std::io::Result<()> {
+    let executors: Vec<Box<dyn ApplyExecutor>> = (0..4).map(|_| Box::new(recorder.clone()) as Box<dyn ApplyExecutor>).collect();
+    let mut sink = ApplySink::new(executors).with_rules(ApplyRules::new(rules).unwrap()).with_columns(columns());
+    for event in events {
+        sink.write_event(event)?;
+    }
+    sink.flush()
+}
+
+//语句所在事务的起止时间
+fn span(recorder: &Recorder, pattern: &str) -> (Instant, Instant) {
+    let executed = recorder.executed.lock().unwrap();
+    let (_, start, end) = executed.iter().find(|(s, _, _)| s.iter().any(|t| t.contains(pattern))).unwrap_or_else(|| panic!("{}", pattern));
+    (*start, *end)
+}
+
+fn overlap(a: (Instant, Instant), b: (Instant, Instant)) -> bool {
+    a.0 < b.1 && b.0 < a.1
+}
+
+#[test]
+fn concurrency() {
+    let events = binlog(&[
+        ("orders", BinlogEvent::WriteEvent, vec![int_row(1, 10)]),
+        ("orders", BinlogEvent::WriteEvent, vec![int_row(2, 20)]),
+        ("users", BinlogEvent::WriteEvent, vec![int_row(7, 30)]),
+        ("orders", BinlogEvent::UpdateEvent, vec![int_row(1, 10), int_row(1, 11)]),
+        ("users", BinlogEvent::DeleteEvent, vec![int_row(8, 40)]),
+        ("orders", BinlogEvent::DeleteEvent, vec![int_row(2, 20)]),
+    ], 5);
+
+    //orders按主键并行
+    let recorder = Recorder::default();
+    apply(&events, &recorder, "shop.orders=key").unwrap();
+    assert_eq!(recorder.executed.lock().unwrap().len(), 7);
+    let insert1 = span(&recorder, "VALUES(1,10)");
+    let insert2 = span(&recorder, "VALUES(2,20)");
+    let insert7 = span(&recorder, "VALUES(7,30)");
+    let update1 = span(&recorder, "UPDATE");
+    let delete8 = span(&recorder, "`id`=8");
+    let ddl = span(&recorder, "ALTER TABLE");
+    let delete2 = span(&recorder, "`id`=2");
+    assert!(overlap(insert1, insert2));
+    assert!(overlap(insert1, insert7));
+    //同一主键按顺序
+    assert!(update1.0 >= insert1.1);
+    //users按表: 同一张表按顺序
+    assert!(delete8.0 >= insert7.1);
+    //DDL单独执行
+    for t in vec![insert1, insert2, insert7, update1, delete8] {
+        assert!(ddl.0 >= t.1);
+    }
+    assert!(delete2.0 >= ddl.1);
+    let statements = recorder.executed.lock().unwrap().iter().find(|(s, _, _)| s.iter().any(|t| t.contains("ALTER"))).unwrap().0.clone();
+    assert_eq!(statements, vec!["USE `shop`", "ALTER TABLE users ADD COLUMN note int"]);
+
+    //按表: orders的事务按顺序执行
+    let recorder = Recorder::default();
+    apply(&events[..], &recorder, "").unwrap();
+    assert!(!overlap(span(&recorder, "VALUES(1,10)"), span(&recorder, "VALUES(2,20)")));
+    assert!(overlap(span(&recorder, "VALUES(1,10)"), span(&recorder, "VALUES(7,30)")));
+
+    //serial: 全部按顺序
+    let recorder = Recorder::default();
+    apply(&events, &recorder, "*=serial").unwrap();
+    let mut spans: Vec<(Instant, Instant)> = recorder.executed.lock().unwrap().iter().map(|(_, s, e)| (*s, *e)).collect();
+    spans.sort();
+    assert!(spans.windows(2).all(|w| w[1].0 >= w[0].1));
+}
+
+#[test]
+fn failure() {
+    let events = binlog(&[
+        ("orders", BinlogEvent::WriteEvent, vec![int_row(1, 10)]),
+        ("orders", BinlogEvent::WriteEvent, vec![int_row(2, 20)]),
+    ], 0);
+    let recorder = Recorder{ fail: Some("VALUES(1,10)"), ..Recorder::default() };
+    let err = apply(&events, &recorder, "").unwrap_err();
+    assert!(err.to_string().contains("Duplicate entry"), "{}", err);
+    //失败之后的事务不再执行
+    assert!(recorder.executed.lock().unwrap().is_empty());
+}
+
+//模拟目标库: 每张表按id保存第二列的值
+#[derive(Clone, Default)]
+struct Target{
+    rows: Arc<Mutex<HashMap<(String, i64), i64>>>,
+}
+
+fn number_after(sql: &str, pattern: &str) -> i64 {
+    let rest = &sql[sql.find(pattern).unwrap() + pattern.len()..];
+    rest[..rest.find(|c: char| !c.is_ascii_digit()).unwrap()].parse().unwrap()
+}
+
+impl ApplyExecutor for Target {
+    fn execute(&mut self, sql: &str) -> Result<u64, String> {
+        if matches!(sql, "BEGIN" | "COMMIT" | "ROLLBACK") {
+            return Ok(0);
+        }
+        let table = sql.split('`').nth(3).unwrap().to_string();
+        let mut rows = self.rows.lock().unwrap();
+        if sql.starts_with("INSERT") {
+            let id = number_after(sql, "VALUES(");
+            let value = number_after(sql, &format!("VALUES({},", id));
+            if rows.contains_key(&(table.clone(), id)) && !sql.contains("ON DUPLICATE KEY UPDATE") {
+                return Err(format!("执行语句错误: #23000Duplicate entry '{}' for key 'PRIMARY'", id));
+            }
+            rows.insert((table, id), value);
+            return Ok(1);
+        }
+        let id = number_after(sql, "WHERE `id`=");
+        match rows.get_mut(&(table.clone(), id)) {
+            None => Ok(0),
+            Some(_) if sql.starts_with("DELETE") => {
+                rows.remove(&(table, id));
+                Ok(1)
+            }
+            Some(value) => {
+                *value = number_after(sql, "`amount`=");
+                Ok(1)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn conflicts() {
+    assert_eq!(ConflictRules::new("shop.orders=dlq, shop.*=skip").unwrap().policy("shop", "orders"), ConflictPolicy::Dlq);
+    assert_eq!(ConflictRules::new("shop.*=overwrite").unwrap().policy("shop", "users"), ConflictPolicy::Overwrite);
+    assert_eq!(ConflictRules::new("").unwrap().policy("shop", "users"), ConflictPolicy::Error);
+    assert!(ConflictRules::new("shop.*=ignore").is_err());
+    assert!(ConflictRules::new("shop.*=dlq").unwrap().uses_dlq());
+
+    //目标库中已有id=1, 没有id=2、3
+    let events = binlog(&[
+        ("orders", BinlogEvent::WriteEvent, vec![int_row(1, 10), int_row(4, 40)]),
+        ("orders", BinlogEvent::UpdateEvent, vec![int_row(2, 20), int_row(2, 21)]),
+        ("orders", BinlogEvent::DeleteEvent, vec![int_row(3, 30)]),
+    ], 0);
+    let run = |policy: &str, dlq: Option<Buffer>| -> (std::io::Result<()>, HashMap<(String, i64), i64>, ConflictStats) {
+        let target = Target::default();
+        target.rows.lock().unwrap().insert((String::from("orders"), 1), 1);
+        let mut sink = ApplySink::new(vec![Box::new(target.clone()) as Box<dyn ApplyExecutor>])
+            .with_columns(columns())
+            .with_conflicts(ConflictRules::new(&format!("shop.orders={}", policy)).unwrap());
+        if let Some(dlq) = dlq {
+            sink = sink.with_dlq(Box::new(dlq));
+        }
+        let result = events.iter().try_for_each(|e| sink.write_event(e)).and_then(|_| sink.flush());
+        let stats = sink.conflicts();
+        let rows = target.rows.lock().unwrap().clone();
+        (result, rows, stats)
+    };
+    let key = |id: i64| (String::from("orders"), id);
+
+    let (result, rows, _) = run("error", None);
+    assert!(result.unwrap_err().to_string().contains("Duplicate entry"));
+    assert_eq!(rows.len(), 1);
+
+    let (result, rows, stats) = run("skip", None);
+    result.unwrap();
+    assert_eq!((rows[&key(1)], rows[&key(4)], rows.len()), (1, 40, 2));
+    assert_eq!(stats, ConflictStats{ skipped: 3, overwritten: 0, dlq: 0 });
+
+    //重复执行结果相同
+    let (result, rows, stats) = run("overwrite", None);
+    result.unwrap();
+    assert_eq!((rows[&key(1)], rows[&key(2)], rows[&key(4)], rows.len()), (10, 21, 40, 3));
+    assert_eq!(stats, ConflictStats{ skipped: 1, overwritten: 1, dlq: 0 });
+
+    let buffer = Buffer::default();
+    let (result, rows, stats) = run("dlq", Some(buffer.clone()));
+    result.unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(stats.dlq, 3);
+    let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let records: Vec<serde_json::Value> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0]["table"], "shop.orders");
+    assert!(records[0]["error"].as_str().unwrap().contains("Duplicate entry"));
+    assert!(records[1]["sql"].as_str().unwrap().starts_with("UPDATE"));
+    assert_eq!(records[2]["error"], "目标库中没有匹配的行");
+
+    //没有设置死信队列
+    let (result, _, _) = run("dlq", None);
+    assert!(result.unwrap_err().to_string().contains("没有设置死信队列"));
+}
+
+#[test]
+fn audit() {
+    assert!(AuditTable::new("changes").is_err());
+    let table = AuditTable::new("audit.`changes`").unwrap();
+    assert_eq!(table.db_tbl(), "audit.changes");
+
+    let events = binlog(&[
+        ("orders", BinlogEvent::WriteEvent, vec![int_row(1, 10), int_row(2, 20)]),
+        ("orders", BinlogEvent::UpdateEvent, vec![int_row(1, 10), int_row(1, 11)]),
+        ("users", BinlogEvent::DeleteEvent, vec![int_row(2, 20)]),
+    ], 2);
+    let run = || {
+        let recorder = Recorder::default();
+        let executors: Vec<Box<dyn ApplyExecutor>> = (0..2).map(|_| Box::new(recorder.clone()) as Box<dyn ApplyExecutor>).collect();
+        let mut sink = ApplySink::new(executors).with_columns(columns()).with_audit(table.clone());
+        events.iter().try_for_each(|e| sink.write_event(e)).and_then(|_| sink.flush()).unwrap();
+        let executed = recorder.executed.lock().unwrap().clone();
+        executed.into_iter().map(|(statements, _, _)| statements).collect::<Vec<Vec<String>>>()
+    };
+    let executed = run();
+    assert!(executed[0][0].starts_with("CREATE TABLE IF NOT EXISTS `audit`.`changes`"));
+    let statements: Vec<&String> = executed[1..].iter().flatten().collect();
+    //DDL只记录, 不在目标库执行
+    assert_eq!(statements.len(), 5);
+    assert!(statements.iter().all(|t| t.starts_with("INSERT INTO `audit`.`changes`") && t.ends_with("ON DUPLICATE KEY UPDATE change_id = change_id")));
+    assert!(statements[0].contains(r#"'insert', 'shop', 'orders', NULL, '{\"amount\":10,\"id\":1}'"#), "{}", statements[0]);
+    assert!(statements[2].contains(r#"'update', 'shop', 'orders', '{\"amount\":10,\"id\":1}', '{\"amount\":11,\"id\":1}'"#), "{}", statements[2]);
+    assert!(statements[3].contains("'ddl', 'shop', '', NULL, NULL") && statements[3].contains("'ALTER TABLE users ADD COLUMN note int'"), "{}", statements[3]);
+    assert!(statements[4].contains(r#"'delete', 'shop', 'users', '{\"age\":20,\"id\":2}', NULL"#), "{}", statements[4]);
+    //同一个事务中的记录按顺序编号, 重新应用时生成相同的change_id、seq
+    let seq = |sql: &str| sql.split("VALUES (").nth(1).unwrap().split(", ").take(2).collect::<Vec<&str>>().join(",");
+    assert_eq!(seq(statements[1]).split(',').nth(1), Some("2"));
+    assert_eq!(seq(statements[0]).split(',').next(), seq(statements[1]).split(',').next());
+    assert_ne!(seq(statements[0]).split(',').next(), seq(statements[2]).split(',').next());
+    assert_eq!(run(), executed);
+}
@@ -0,0 +1,146 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//多个来源的归档binlog: 相同的文件只读取一个, 按previous_gtids排序, 重叠的gtid只处理一次
+
+#![cfg(all(feature = "test-support", feature = "os"))]
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use uuid::Uuid;
+use mytest::archive::{self, ArchivePlan, GtidDedup};
+use mytest::prelude::*;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+use common::{insert, SID, START};
+
+fn gtids(set: &str) -> GtidSet {
+    match set {
+        "" => GtidSet::new(),
+        t => GtidSet::from_str(&format!("{}:{}", SID, t)).unwrap(),
+    }
+}
+
+//每个gno一个insert事务, 事务的时间为START + gno
+fn binlog(path: &Path, start_time: u32, previous: &str, gnos: std::ops::RangeInclusive<u64>) -> String {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.timestamp(start_time).previous_gtids(&gtids(previous));
+    for gno in gnos {
+        insert(&mut builder, gno);
+    }
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, builder.into_bytes()).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+//旧主库1-10, 切换后的新主库从4开始写入, 新主库的目录中有旧主库文件的副本及已包含的1-3
+fn archives(name: &str) -> (std::path::PathBuf, Vec<String>) {
+    let dir = env::temp_dir().join(format!("mytest-archive-{}-{}", name, std::process::id()));
+    let (old, new) = (dir.join("old"), dir.join("new"));
+    binlog(&old.join("mysql-bin.000001"), START, "", 1..=5);
+    binlog(&old.join("mysql-bin.000002"), START + 6, "1-5", 6..=10);
+    binlog(&new.join("mysql-bin.000006"), START + 1, "", 1..=3);
+    binlog(&new.join("mysql-bin.000007"), START + 4, "1-3", 4..=8);
+    binlog(&new.join("mysql-bin.000008"), START + 9, "1-8", 9..=12);
+    fs::copy(old.join("mysql-bin.000001"), new.join("old-mysql-bin.000001")).unwrap();
+    //不是binlog的文件忽略
+    fs::write(new.join("mysql-bin.index"), "mysql-bin.000006\n").unwrap();
+    let dirs = vec![old.to_str().unwrap().to_string(), new.to_str().unwrap().to_string()];
+    (dir, dirs)
+}
+
+fn name(path: &str) -> String {
+    let path = Path::new(path);
+    format!("{}/{}", path.parent().unwrap().file_name().unwrap().to_str().unwrap(), path.file_name().unwrap().to_str().unwrap())
+}
+
+#[test]
+fn plan() {
+    let (dir, dirs) = archives("plan");
+    assert_eq!(archive::list(&dirs).unwrap().len(), 6);
+    let plan = ArchivePlan::scan(&dirs).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(plan.files.iter().map(|t| name(&t.path)).collect::<Vec<_>>(),
+               vec!["old/mysql-bin.000001", "new/mysql-bin.000007", "old/mysql-bin.000002", "new/mysql-bin.000008"]);
+    assert_eq!(plan.identical.iter().map(|(a, b)| (name(a), name(b))).collect::<Vec<_>>(),
+               vec![(String::from("new/old-mysql-bin.000001"), String::from("old/mysql-bin.000001"))]);
+    assert_eq!(plan.redundant.iter().map(|t| name(t)).collect::<Vec<_>>(), vec!["new/mysql-bin.000006"]);
+    assert_eq!(plan.overlaps.iter().map(|(p, g)| (name(p), g.clone())).collect::<Vec<_>>(),
+               vec![(String::from("new/mysql-bin.000007"), gtids("4-5")), (String::from("old/mysql-bin.000002"), gtids("6-8")),
+                    (String::from("new/mysql-bin.000008"), gtids("9-10"))]);
+    assert_eq!(plan.files[1].gtids, gtids("4-8"));
+    assert!(plan.report().iter().any(|t| t.starts_with("identical") && t.contains("old-mysql-bin.000001")));
+}
+
+#[test]
+fn dedup() {
+    let sid = Uuid::from_str(SID).unwrap();
+    let mut dedup = GtidDedup::new();
+    dedup.extend(&gtids("1-3"));
+    assert!(!dedup.first(&sid, 2));
+    assert!(dedup.first(&sid, 4));
+    assert!(!dedup.first(&sid, 4));
+    assert_eq!((dedup.seen().clone(), dedup.duplicates()), (gtids("1-4"), 2));
+
+    //读取到position为止的事务
+    let dir = env::temp_dir().join(format!("mytest-archive-dedup-{}", std::process::id()));
+    let path = binlog(&dir.join("mysql-bin.000001"), START, "", 1..=5);
+    let end_of_second = BinlogReader::open(&path).unwrap().transactions().nth(1).unwrap().unwrap().events.last().unwrap().header.next_position as u64;
+    let (set, anonymous) = archive::file_gtids(&path, end_of_second).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!((set, anonymous), (gtids("1-2"), 0));
+}
+
+#[cfg(all(feature = "network", feature = "json-sink"))]
+mod backfill {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use mytest::Config;
+    use mytest::pipeline::{BackfillRange, Pipeline};
+    use super::*;
+
+    struct GnoSink{
+        written: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl Sink for GnoSink {
+        fn write_event(&mut self, event: &Event) -> io::Result<()> {
+            if let Traction::GtidEvent(t) = &event.data {
+                self.written.lock().unwrap().push(t.gno_id);
+            }
+            Ok(())
+        }
+    }
+
+    fn pipeline(checkpoint: &str, written: &Arc<Mutex<Vec<u64>>>) -> Pipeline {
+        let written = written.clone();
+        Pipeline::new("orders", Config::default(), move |_| -> io::Result<Box<dyn Sink>> {
+            Ok(Box::new(GnoSink{ written: written.clone() }))
+        }).with_checkpoint(checkpoint).with_checkpoint_interval(std::time::Duration::from_secs(0))
+    }
+
+    #[test]
+    fn exactly_once() {
+        let (dir, dirs) = archives("backfill");
+        let files = ArchivePlan::scan(&dirs).unwrap().paths();
+        let written = Arc::new(Mutex::new(vec![]));
+        let report = pipeline(dir.join("all.ckpt").to_str().unwrap(), &written).backfill(&files, &BackfillRange::default()).unwrap();
+        assert_eq!((report.transactions, report.duplicates, report.skipped), (12, 7, 0));
+        assert_eq!(*written.lock().unwrap(), (1..=12).collect::<Vec<u64>>());
+
+        //中断后继续时, 之前的文件中已回填的事务同样跳过
+        written.lock().unwrap().clear();
+        let checkpoint = dir.join("resume.ckpt").to_str().unwrap().to_string();
+        let report = pipeline(&checkpoint, &written).backfill(&files[..2], &BackfillRange::default()).unwrap();
+        assert_eq!((report.transactions, report.duplicates), (8, 2));
+        let report = pipeline(&checkpoint, &written).backfill(&files, &BackfillRange::default()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!((report.transactions, report.duplicates), (4, 5));
+        assert_eq!(*written.lock().unwrap(), (1..=12).collect::<Vec<u64>>());
+    }
+}
@@ -0,0 +1,89 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//dump开始时master发送的artificial ROTATE_EVENT: 带LOG_EVENT_ARTIFICIAL_F, timestamp为0,
+//  不参与时间过滤, 也不改变已读取的位置及事件时间
+#![cfg(all(feature = "network", feature = "test-support"))]
+
+mod common;
+
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use mytest::Config;
+use mytest::gtid::{Executed, ExecutedTracker};
+use mytest::prelude::*;
+use mytest::replication::grep::GrepInfo;
+use mytest::replication::readevent::{EventFlags, RotateLog};
+use mytest::replication::rollback::RollBackTrac;
+use mytest::sink::HeartbeatTracker;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+use common::{begin, gtid, write_file, START};
+
+//从bin.000003的position开始dump, 之后为一个事务; 返回文件内容及dump开始的位置
+fn binlog() -> (Vec<u8>, u64) {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.timestamp(START).previous_gtids(&GtidSet::new());
+    let position = builder.position();
+    builder.timestamp(0).flags(EventFlags::LOG_EVENT_ARTIFICIAL_F.bits())
+        .rotate(&RotateLog{ binlog_file: String::from("bin.000003"), position, artificial: true });
+    builder.timestamp(START + 10).flags(0);
+    builder.gtid(&gtid(1)).query(&begin("shop")).xid(&XidEvent{ xid: 1 });
+    (builder.into_bytes(), position)
+}
+
+fn events() -> Vec<Event> {
+    BinlogReader::new(Cursor::new(binlog().0)).unwrap().collect::<std::io::Result<Vec<Event>>>().unwrap()
+}
+
+#[test]
+fn flags() {
+    let events = events();
+    let rotate = events.iter().find(|e| matches!(e.data, Traction::RotateLogEvent(_))).unwrap();
+    assert!(rotate.header.is_artificial());
+    assert_eq!(rotate.header.flags, EventFlags::LOG_EVENT_ARTIFICIAL_F);
+    assert_eq!(rotate.header.timestamp, 0);
+    assert!(matches!(&rotate.data, Traction::RotateLogEvent(t) if t.artificial && t.binlog_file == "bin.000003"));
+    assert!(events.iter().filter(|e| !matches!(e.data, Traction::RotateLogEvent(_))).all(|e| !e.header.is_artificial()));
+}
+
+#[test]
+fn datetime_filter() {
+    //timestamp为0的artificial事件不会被--startdatetime过滤掉, 也不会因--stopdatetime结束读取
+    let conf = Config{ startdatetime: (START + 5).to_string(), stopdatetime: (START + 20).to_string(), ..Config::default() };
+    let file = write_file("artificial", "bin.000003", binlog().0);
+    let mut rollback_trac = RollBackTrac::new(&mut BufReader::new(File::open(&file).unwrap()), &conf);
+    let grep_info = GrepInfo::new(&conf);
+    let mut result = |event: &Event| grep_info.grep_pos_time(&mut rollback_trac, &event.header, event.header.next_position as u64);
+    let results: Vec<(bool, bool)> = events().iter().map(result).collect();
+    //FDE、previous_gtids在--startdatetime之前
+    assert_eq!(results, vec![(false, false), (false, false), (true, false), (true, false), (true, false), (true, false)]);
+}
+
+#[test]
+fn positions() {
+    let (_, position) = binlog();
+    let events = events();
+    //artificial事件的next_position按0处理
+    let next_position = |event: &Event| if event.header.is_artificial() { 0 } else { event.header.next_position as u64 };
+
+    let mut executed = ExecutedTracker::from_config(&Config{ binlogfile: String::from("bin.000003"), position: position.to_string(), ..Config::default() });
+    let mut heartbeat = HeartbeatTracker::new();
+    for event in events.iter().take_while(|e| !matches!(e.data, Traction::GtidEvent(_))) {
+        executed.observe(&event.data, next_position(event));
+        heartbeat.observe(&event.data, event.header.timestamp, next_position(event));
+    }
+    //ROTATE之后仍为dump开始的位置, 事件时间为之前的事件
+    assert_eq!(executed.executed(), Executed{ gtid_set: GtidSet::new(), log_file: String::from("bin.000003"), log_pos: position });
+    let state = heartbeat.heartbeat(0);
+    assert_eq!((state.log_file.as_str(), state.log_pos, state.event_timestamp), ("bin.000003", position, START));
+
+    for event in events.iter().skip_while(|e| !matches!(e.data, Traction::GtidEvent(_))) {
+        executed.observe(&event.data, next_position(event));
+        heartbeat.observe(&event.data, event.header.timestamp, next_position(event));
+    }
+    let end = events.last().unwrap().header.next_position as u64;
+    assert_eq!(executed.executed().log_pos, end);
+    assert_eq!((heartbeat.heartbeat(0).log_pos, heartbeat.heartbeat(0).event_timestamp), (end, START + 10));
+}
@@ -0,0 +1,90 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//认证方式: 指定的方式与服务端不同时按AuthSwitchRequest切换, 注册新的认证方式
+
+#![cfg(all(feature = "test-support", feature = "network"))]
+
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use mytest::Config;
+use mytest::io::auth::{self, AuthPlugin, NativePassword, Plugins};
+use mytest::io::{command, connection};
+use mytest::master::BinlogServer;
+use mytest::prelude::*;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+fn serve(name: &str) -> SocketAddr {
+    let dir = env::temp_dir().join(format!("mytest-auth-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.timestamp(1572566400).previous_gtids(&GtidSet::new());
+    fs::write(dir.join("bin.000001"), builder.into_bytes()).unwrap();
+    BinlogServer::open_dir(&dir).unwrap().credentials("repl", "secret").serve("127.0.0.1:0").unwrap()
+}
+
+fn conf(addr: SocketAddr, plugin: &str) -> Config {
+    Config{
+        host_info: addr.to_string(),
+        user_name: String::from("repl"),
+        password: String::from("secret").into(),
+        program_name: String::from("mytest"),
+        authplugin: plugin.to_string(),
+        ..Config::default()
+    }
+}
+
+#[test]
+fn switch_plugins() {
+    let addr = serve("switch");
+    //服务端只支持mysql_native_password, 其他方式通过AuthSwitchRequest切换
+    for plugin in ["", auth::NATIVE_PASSWORD, auth::CACHING_SHA2_PASSWORD, auth::SHA256_PASSWORD] {
+        let mut conn = connection::create_mysql_conn(&conf(addr, plugin)).unwrap();
+        let rows = command::try_execute(&mut conn, "select @@server_id as id").unwrap();
+        assert_eq!(rows.len(), 1, "{}", plugin);
+    }
+    let wrong = Config{ password: String::from("wrong").into(), ..conf(addr, auth::CACHING_SHA2_PASSWORD) };
+    assert!(connection::create_mysql_conn(&wrong).is_err());
+}
+
+//记录调用次数的mysql_native_password
+struct Counting(Arc<AtomicUsize>);
+
+impl AuthPlugin for Counting {
+    fn name(&self) -> &str {
+        auth::NATIVE_PASSWORD
+    }
+
+    fn scramble(&self, nonce: &[u8], conf: &Config) -> Result<Vec<u8>, String> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        NativePassword.scramble(nonce, conf)
+    }
+}
+
+#[test]
+fn register_plugin() {
+    let addr = serve("register");
+    let calls = Arc::new(AtomicUsize::new(0));
+    let plugins = Plugins::builtin().register(Box::new(Counting(calls.clone())));
+    connection::create_mysql_conn_with(&conf(addr, ""), &plugins).unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    //先使用caching_sha2_password, 切换后再次生成
+    connection::create_mysql_conn_with(&conf(addr, ""), &plugins.prefer(auth::CACHING_SHA2_PASSWORD)).unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn plugin_names() {
+    let plugins = Plugins::from_config(&Config{ authplugin: String::from("aws_iam"), ..Config::default() }).unwrap();
+    assert_eq!(plugins.initial(auth::CACHING_SHA2_PASSWORD).name(), auth::CLEAR_PASSWORD);
+    let plugins = Plugins::from_config(&Config::default()).unwrap();
+    assert_eq!(plugins.initial(auth::CACHING_SHA2_PASSWORD).name(), auth::CACHING_SHA2_PASSWORD);
+    assert_eq!(plugins.initial("authentication_ldap_sasl").name(), auth::NATIVE_PASSWORD);
+    assert!(Plugins::from_config(&Config{ authplugin: String::from("kerberos"), ..Config::default() }).is_err());
+}
@@ -0,0 +1,123 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//回填: 按时间及gtid范围读取归档的binlog文件, 经过管道的过滤条件写入输出目标, checkpoint与实时拉取分开保存, 中断后从回填的位置继续
+
+#![cfg(all(feature = "network", feature = "json-sink"))]
+
+use std::env;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use mytest::Config;
+use mytest::gtid::GtidSet;
+use mytest::pipeline::{BackfillRange, Pipeline};
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+//记录写入的行事件的gtid
+struct GtidSink{
+    gtid: String,
+    written: Arc<Mutex<Vec<String>>>,
+}
+
+impl Sink for GtidSink {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        match &event.data {
+            Traction::GtidEvent(t) => self.gtid = format!("{}:{}", t.gtid, t.gno_id),
+            Traction::RowEvent(..) => self.written.lock().unwrap().push(self.gtid.clone()),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn synth(dir: &std::path::Path, name: &str, start_time: u32, seed: u64) -> String {
+    let opts = SynthOptions{
+        database: String::from("backfill"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 40,
+        rows: (1, 1),
+        rate: 10,
+        mix: [1, 0, 0],
+        start_time,
+        seed,
+        server_id: 133,
+    };
+    let path = dir.join(name).to_str().unwrap().to_string();
+    synthesize::write_file(&path, &opts).unwrap();
+    path
+}
+
+fn pipeline(checkpoint: &str, written: &Arc<Mutex<Vec<String>>>) -> Pipeline {
+    let written = written.clone();
+    Pipeline::new("orders", Config::default(), move |_| -> io::Result<Box<dyn Sink>> {
+        Ok(Box::new(GtidSink{ gtid: String::new(), written: written.clone() }))
+    }).with_checkpoint(checkpoint).with_checkpoint_interval(std::time::Duration::from_secs(0))
+}
+
+fn first_gtid(path: &str) -> String {
+    BinlogReader::open(path).unwrap().find_map(|e| match e.unwrap().data {
+        Traction::GtidEvent(t) => Some(t.gtid.to_string()),
+        _ => None
+    }).unwrap()
+}
+
+#[test]
+fn range() {
+    let dir = env::temp_dir().join(format!("mytest-backfill-range-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = synth(&dir, "mysql-bin.000001", 1572998400, 5);
+    let checkpoint = dir.join("orders.ckpt").to_str().unwrap().to_string();
+    let written = Arc::new(Mutex::new(vec![]));
+
+    //时间范围: 1572998401、1572998402两秒的20个事务, 之前的10个事务及文件开头的2个CREATE TABLE跳过
+    let report = pipeline(&checkpoint, &written).backfill(&[file.clone()], &BackfillRange{ start_time: 1572998401, stop_time: 1572998402, gtids: None }).unwrap();
+    assert_eq!((report.transactions, report.rows, report.skipped, report.resumed.is_none()), (20, 20, 12, true));
+    assert_eq!(written.lock().unwrap().len(), 20);
+    //checkpoint与管道的checkpoint分开保存
+    assert!(!std::path::Path::new(&checkpoint).exists());
+    assert!(fs::read_to_string(format!("{}.backfill", checkpoint)).unwrap().contains("mysql-bin.000001"));
+
+    //gtid集合, 从新的checkpoint开始
+    let sid = first_gtid(&file);
+    written.lock().unwrap().clear();
+    let range = BackfillRange{ gtids: Some(GtidSet::from_str(&format!("{}:3-5", sid)).unwrap()), ..BackfillRange::default() };
+    let report = pipeline(&dir.join("gtid.ckpt").to_str().unwrap(), &written).backfill(&[file.clone()], &range).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    //没有gtid的CREATE TABLE跳过
+    assert_eq!((report.transactions, report.skipped), (3, 39));
+    assert_eq!(*written.lock().unwrap(), vec![format!("{}:3", sid), format!("{}:4", sid), format!("{}:5", sid)]);
+}
+
+#[test]
+fn resume() {
+    let dir = env::temp_dir().join(format!("mytest-backfill-resume-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let files = vec![synth(&dir, "mysql-bin.000001", 1572998400, 7), synth(&dir, "mysql-bin.000002", 1572998410, 8)];
+    let checkpoint = dir.join("orders.ckpt").to_str().unwrap().to_string();
+    let written = Arc::new(Mutex::new(vec![]));
+
+    //第一次回填到第一个文件结束, 包括2个CREATE TABLE
+    let report = pipeline(&checkpoint, &written).backfill(&files, &BackfillRange{ stop_time: 1572998405, ..BackfillRange::default() }).unwrap();
+    assert_eq!(report.transactions, 42);
+    assert_eq!(written.lock().unwrap().len(), 40);
+    //继续时从第一个文件的结束位置开始, 不重复写入
+    let report = pipeline(&checkpoint, &written).backfill(&files, &BackfillRange::default()).unwrap();
+    assert_eq!(report.resumed.as_ref().map(|t| t.log_file.as_str()), Some("mysql-bin.000001"));
+    assert_eq!((report.transactions, report.skipped), (42, 0));
+    assert_eq!(written.lock().unwrap().len(), 80);
+    //已经回填完成
+    let report = pipeline(&checkpoint, &written).backfill(&files, &BackfillRange::default()).unwrap();
+    assert_eq!(report.transactions, 0);
+    assert!(report.resumed.unwrap().log_file.ends_with("000002"));
+
+    //checkpoint中的文件不在读取的文件中
+    let err = pipeline(&checkpoint, &written).backfill(&files[..1], &BackfillRange::default()).unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(err.to_string().contains("mysql-bin.000002"));
+}
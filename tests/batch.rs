@@ -0,0 +1,60 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//getsql --batchsize: 连续插入同一个表的行合并为多行insert语句, 达到batchsize或表变化时输出
+
+#![cfg(feature = "os")]
+
+use std::collections::HashMap;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::stdout::outsql::{self, InsertBatch, InsertMode};
+
+fn cols() -> Vec<HashMap<String, String>> {
+    let mut col = HashMap::new();
+    col.insert(String::from("COLUMN_NAME"), String::from("id"));
+    col.insert(String::from("COLUMN_TYPE"), String::from("int(11)"));
+    col.insert(String::from("COLUMN_KEY"), String::from("PRI"));
+    col.insert(String::from("EXTRA"), String::new());
+    vec![col]
+}
+
+fn table(name: &str) -> TableMap {
+    TableMap{
+        database_name: String::from("shop"),
+        table_name: name.to_string(),
+        column_count: 1,
+        column_info: vec![ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![] }],
+        metadata: None,
+    }
+}
+
+#[test]
+fn batch_size() {
+    assert_eq!(outsql::batch_size("").unwrap(), 1);
+    assert_eq!(outsql::batch_size("100").unwrap(), 100);
+    for invalid in ["0", "-1", "ten", "1.5"] {
+        assert!(outsql::batch_size(invalid).unwrap_err().contains("batchsize"), "{}", invalid);
+    }
+}
+
+#[test]
+fn merged() {
+    let format = ValueFormat::default();
+    let mut batch = InsertBatch::new(3, InsertMode::Insert);
+    let mut statements = vec![];
+    //orders的5行按3行一条语句合并, 之后插入items时输出orders剩余的2行
+    for (name, id) in [("orders", 1), ("orders", 2), ("orders", 3), ("orders", 4), ("orders", 5), ("items", 6)] {
+        statements.extend(batch.push(&vec![Some(MySQLValue::SignedInteger(id))], &cols(), &table(name), &format));
+    }
+    statements.extend(batch.take());
+    assert_eq!(statements, vec![
+        "INSERT INTO `shop`.`orders`(`id`) VALUES(1),(2),(3);",
+        "INSERT INTO `shop`.`orders`(`id`) VALUES(4),(5);",
+        "INSERT INTO `shop`.`items`(`id`) VALUES(6);",
+    ]);
+    assert!(batch.take().is_none());
+}
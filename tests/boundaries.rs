@@ -0,0 +1,75 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//事务边界: 每个事务的行数据前后带begin、commit记录, commit中为gtid、xid及各类型的行数
+
+#![cfg(all(feature = "os", feature = "json-sink"))]
+
+use std::env;
+use std::fs;
+use serde_json::Value as JsonValue;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn records(boundaries: bool) -> Vec<JsonValue> {
+    let opts = SynthOptions{
+        database: String::from("trx"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(8)],
+        transactions: 15,
+        rows: (1, 4),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 11,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-boundaries-{}-{}.binlog", boundaries, std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    let mut buf = vec![];
+    let mut sink = JsonLinesSink::new(&mut buf, ValueFormat{ boundaries, ..ValueFormat::default() });
+    for event in BinlogReader::open(path.to_str().unwrap()).unwrap() {
+        sink.write_event(&event.unwrap()).unwrap();
+    }
+    drop(sink);
+    fs::remove_file(&path).unwrap();
+    String::from_utf8(buf).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+#[test]
+fn begin_commit() {
+    let marked = records(true);
+    let mut open: Option<&JsonValue> = None;
+    let (mut rows, mut commits) = (vec![], 0);
+    for record in &marked {
+        match record["type"].as_str().unwrap() {
+            "begin" => {
+                assert!(open.is_none());
+                open = Some(record);
+                rows.clear();
+            }
+            "commit" => {
+                let begin = open.take().expect("commit之前没有begin");
+                assert_eq!(record["gtid"], begin["gtid"]);
+                assert!(record["xid"].is_u64());
+                assert_eq!(record["rows"].as_u64().unwrap() as usize, rows.len());
+                let inserts = rows.iter().filter(|t| **t == "insert").count();
+                assert_eq!(record["insert"].as_u64().unwrap() as usize, inserts);
+                commits += 1;
+            }
+            kind => {
+                //行数据都在begin、commit之间, gtid与begin相同
+                assert_eq!(record["gtid"], open.expect("行数据之前没有begin")["gtid"]);
+                rows.push(kind.to_string());
+            }
+        }
+    }
+    assert!(open.is_none());
+    assert_eq!(commits, 15);
+
+    let plain = records(false);
+    assert!(plain.iter().all(|r| r["type"] != "begin" && r["type"] != "commit"));
+    assert_eq!(plain.len(), marked.len() - 30);
+}
@@ -0,0 +1,91 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//ConfigBuilder: 按读取方式生成Config, 不合法的参数组合在build()时返回错误, 从json配置文件读取
+
+use mytest::builder::{ConfigBuilder, Format, Mode, Start};
+
+const SID: &str = "3e11fa47-71ca-11e1-9e33-c80aa9429562";
+
+fn replication(start: Start) -> Mode {
+    Mode::Replication{ host: String::from("127.0.0.1:3306"), user: String::from("repl"), password: String::from("pw").into(), start }
+}
+
+fn file(rollback: bool) -> Mode {
+    Mode::File{ path: String::from("mysql-bin.000003"), relay_log: false, rollback }
+}
+
+#[test]
+fn build() {
+    let conf = ConfigBuilder::new(replication(Start::Gtid{ gtid: format!("{}:1-5", SID) }))
+        .server_id(200).heartbeat(1.5).format(Format::Json)
+        .table("shop.orders").table("shop.items").table("log.*")
+        .label("env", "prod").label("cluster", "order")
+        .build().unwrap();
+    assert_eq!(conf.runtype, "repl");
+    assert_eq!((conf.host_info.as_str(), conf.user_name.as_str(), conf.password.expose()), ("127.0.0.1:3306", "repl", "pw"));
+    assert_eq!(conf.gtid, format!("{}:1-5", SID));
+    assert_eq!((conf.serverid.as_str(), conf.heartbeat.as_str(), conf.outformat.as_str()), ("200", "1.5", "json"));
+    assert_eq!(conf.greptbl, r#"{"log":"all","shop":["orders","items"]}"#);
+    assert_eq!(conf.labels, "cluster=order,env=prod");
+
+    let conf = ConfigBuilder::new(replication(Start::Gtid{ gtid: format!("{}:1-5", SID) })).until_position("mysql-bin.000004", 1234).build().unwrap();
+    assert_eq!((conf.untilgtid.as_str(), conf.untilposition.as_str()), ("", "mysql-bin.000004:1234"));
+
+    let conf = ConfigBuilder::new(file(false)).positions(Some(4), Some(1024)).format(Format::Sql).thread_id(10).build().unwrap();
+    assert_eq!((conf.runtype.as_str(), conf.file.as_str()), ("file", "mysql-bin.000003"));
+    assert_eq!((conf.startposition.as_str(), conf.stopposition.as_str(), conf.threadid.as_str()), ("4", "1024", "10"));
+    assert!(conf.getsql);
+    assert_eq!(conf.serverid, "133");
+}
+
+#[test]
+fn invalid_combinations() {
+    let position = || Start::Position{ binlog_file: String::from("mysql-bin.000003"), position: 4 };
+    let errors = vec![
+        ConfigBuilder::new(file(true)).format(Format::Json).build(),
+        ConfigBuilder::new(file(false)).heartbeat(1.0).build(),
+        ConfigBuilder::new(file(false)).server_id(200).build(),
+        ConfigBuilder::new(file(false)).positions(Some(1024), Some(4)).build(),
+        ConfigBuilder::new(replication(position())).positions(Some(4), None).build(),
+        ConfigBuilder::new(replication(position())).server_id(0).build(),
+        ConfigBuilder::new(replication(Start::Gtid{ gtid: String::from("not-a-gtid") })).build(),
+        ConfigBuilder::new(replication(Start::Position{ binlog_file: String::from("mysql-bin.000003"), position: 1 })).build(),
+        ConfigBuilder::new(replication(position())).datetimes(Some(1572566500), Some(1572566400)).build(),
+        ConfigBuilder::new(replication(position())).table("orders").build(),
+        ConfigBuilder::new(replication(position())).label("bad key", "x").build(),
+        ConfigBuilder::new(replication(position())).verify_checksum("bogus").build(),
+        ConfigBuilder::new(Mode::File{ path: String::from("relay-bin.000001"), relay_log: true, rollback: true }).build(),
+        ConfigBuilder::new(file(false)).until_gtid(&format!("{}:1-5", SID)).build(),
+        ConfigBuilder::new(replication(position())).until_gtid("not-a-gtid").build(),
+        ConfigBuilder::new(replication(position())).until_gtid(&format!("{}:1-5", SID)).until_position("mysql-bin.000004", 4).build(),
+    ];
+    for (idx, result) in errors.into_iter().enumerate() {
+        assert!(result.is_err(), "{}", idx);
+    }
+}
+
+#[test]
+fn from_json() {
+    let builder = ConfigBuilder::from_json(r#"{"mode": "replication", "host": "10.0.0.1:3306", "user": "repl", "password": "secret",
+        "start": {"binlog_file": "mysql-bin.000007"}, "server_id": 300, "format": "csv", "tables": ["shop.orders"]}"#).unwrap();
+    assert_eq!(builder, ConfigBuilder::new(Mode::Replication{
+        host: String::from("10.0.0.1:3306"), user: String::from("repl"), password: String::from("secret").into(),
+        start: Start::Position{ binlog_file: String::from("mysql-bin.000007"), position: 4 },
+    }).server_id(300).format(Format::Csv).table("shop.orders"));
+    //Debug输出不包含密码
+    assert!(!format!("{:?}", builder).contains("secret"));
+    let conf = builder.build().unwrap();
+    assert_eq!((conf.binlogfile.as_str(), conf.position.as_str(), conf.outformat.as_str()), ("mysql-bin.000007", "4", "csv"));
+
+    let conf = ConfigBuilder::from_json(r#"{"mode": "file", "path": "mysql-bin.000003", "rollback": true, "start_datetime": 1572566400}"#)
+        .unwrap().build().unwrap();
+    assert!(conf.rollback);
+    assert_eq!(conf.startdatetime, "1572566400");
+
+    assert!(ConfigBuilder::from_json(r#"{"mode": "file"}"#).unwrap_err().contains("path"));
+    assert!(ConfigBuilder::from_json(r#"{"mode": "tcp", "path": "x"}"#).is_err());
+    assert!(ConfigBuilder::from_json(r#"{"mode": "file", "path": "x", "format": "xml"}"#).is_err());
+}
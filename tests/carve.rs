@@ -0,0 +1,112 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//提取事务: 前后有无关的数据、中间的事务被覆盖时只输出完整的事务, 输出为可以直接读取的binlog文件;
+//没有FORMAT_DESCRIPTION_EVENT、不带checksum时按5.7的格式生成
+
+#![cfg(all(feature = "test-support", feature = "os"))]
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use mytest::prelude::*;
+use mytest::replication::{carve, verify};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+use common::{begin, gtid, order, orders, START};
+
+//gno 1..=5的事务, 3为DDL, 返回文件内容及每个事务的起始位置
+fn binlog(checksum: bool) -> (Vec<u8>, Vec<usize>) {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57).checksum(checksum);
+    builder.timestamp(START).previous_gtids(&GtidSet::new());
+    let mut starts = vec![];
+    for gno in 1..=5u64 {
+        starts.push(builder.position() as usize);
+        builder.timestamp(START + gno as u32);
+        builder.gtid(&gtid(gno));
+        if gno == 3 {
+            builder.query(&QueryEvent{ command: String::from("ALTER TABLE orders ADD COLUMN note varchar(64)"), ..begin("shop") });
+            continue;
+        }
+        builder.query(&begin("shop"));
+        builder.table_map(100, &orders(false)).unwrap();
+        let rows: Vec<Vec<Option<MySQLValue>>> = (0..3).map(|i| order(gno as i64 * 10 + i, Some("order"))).collect();
+        builder.rows(&BinlogEvent::WriteEvent, 100, &orders(false), &rows).unwrap();
+        builder.xid(&XidEvent{ xid: gno });
+    }
+    let data = builder.into_bytes();
+    starts.push(data.len());
+    (data, starts)
+}
+
+fn garbage(len: usize, seed: u8) -> Vec<u8> {
+    (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+}
+
+//读取提取出的文件, 返回事务的gno及行数
+fn read_back(data: Vec<u8>) -> (Vec<u64>, usize) {
+    let (mut gnos, mut rows) = (vec![], 0);
+    for event in BinlogReader::new(Cursor::new(data)).unwrap() {
+        match event.unwrap().data {
+            Traction::GtidEvent(t) => gnos.push(t.gno_id),
+            Traction::RowEvent(_, t) => rows += t.rows().len(),
+            _ => {}
+        }
+    }
+    (gnos, rows)
+}
+
+#[test]
+fn overwritten() {
+    let (data, starts) = binlog(true);
+    //第2个事务的行事件中间被覆盖
+    let mut image = garbage(1000, 7);
+    let mut file = data.clone();
+    let middle = (starts[1] + starts[2]) / 2;
+    file[middle..middle + 8].copy_from_slice(&[0u8; 8]);
+    image.extend(&file);
+    image.extend(garbage(300, 11));
+
+    let dir = env::temp_dir().join(format!("mytest-carve-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (input, output) = (dir.join("disk.img"), dir.join("recovered.000001"));
+    fs::write(&input, &image).unwrap();
+    let report = carve::carve_file(input.to_str().unwrap(), output.to_str().unwrap()).unwrap();
+    assert_eq!(report.transactions, 4);
+    assert_eq!(report.incomplete, 1);
+    assert!(report.format_description);
+    assert!(report.checksum);
+    assert_eq!(report.bytes, image.len() as u64);
+
+    let recovered = fs::read(&output).unwrap();
+    assert_eq!(read_back(recovered.clone()), (vec![1, 3, 4, 5], 9));
+    let verified = verify::verify_reader(Cursor::new(recovered), false, verify::ChecksumClass::all()).unwrap();
+    assert!(verified.is_ok());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn without_format_description() {
+    //不带checksum, 从第2个事务开始截取, 最后一个事务被截断
+    let (data, starts) = binlog(false);
+    let mut image = garbage(64, 3);
+    image.extend(&data[starts[1]..starts[5] - 5]);
+    let carved = carve::carve(&image);
+    assert!(!carved.report.format_description);
+    assert!(!carved.report.checksum);
+    assert_eq!(carved.report.transactions, 3);
+
+    let mut file = vec![0xFE, 0x62, 0x69, 0x6E];
+    file.extend(&carved.format_description);
+    for trx in &carved.transactions {
+        file.extend(trx);
+    }
+    //position由写入文件时重写, 读取时不校验
+    assert_eq!(read_back(file), (vec![2, 3, 4], 6));
+
+    let empty = carve::carve(&garbage(4096, 1));
+    assert_eq!(empty.report.transactions, 0);
+}
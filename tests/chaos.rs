@@ -0,0 +1,127 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//故障注入: 相同的seed注入的位置相同, 断线后一直返回错误, checksum损坏时校验失败, 输出失败时不写入
+
+#![cfg(all(feature = "network", feature = "test-support"))]
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::io::{self, Cursor};
+use std::time::Duration;
+use mytest::Config;
+use mytest::chaos::{FaultCounts, Faults};
+use mytest::prelude::*;
+use mytest::recording::{Recorder, Recording, Start};
+use mytest::stream::BinlogStream;
+use common::inserts;
+
+//录制为数据包后重放
+fn replay(name: &str, conf: &Config) -> BinlogStream {
+    let data = inserts(1..=20).into_bytes();
+    let path = env::temp_dir().join(format!("mytest_chaos_{}_{}", name, std::process::id()));
+    let start = Start{ binlogfile: String::from("mysql-bin.000001"), position: String::from("4"), ..Start::default() };
+    let recorder = Recorder::create(&path, &start).unwrap();
+    for event in BinlogReader::new(Cursor::new(data.clone())).unwrap() {
+        let event = event.unwrap();
+        let end = event.header.next_position as usize;
+        let mut packet = vec![0u8];
+        packet.extend_from_slice(&data[end - event.header.event_length as usize..end]);
+        recorder.record(&packet).unwrap();
+    }
+    drop(recorder);
+    let stream = BinlogStream::replay(Recording::open(&path).unwrap(), conf).unwrap();
+    fs::remove_file(&path).unwrap();
+    stream
+}
+
+//读取到第一个错误为止的事件数及错误
+fn read_until_error(stream: &mut BinlogStream) -> (usize, Option<io::Error>) {
+    let mut n = 0;
+    for event in stream.by_ref() {
+        match event {
+            Ok(_) => n += 1,
+            Err(e) => return (n, Some(e)),
+        }
+    }
+    (n, None)
+}
+
+struct Collect(Vec<u64>);
+
+impl Sink for Collect {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        self.0.push(event.log_pos);
+        Ok(())
+    }
+}
+
+#[test]
+fn parse() {
+    let faults = Faults::parse("disconnect=0.01, delay=0.5,delay-ms=20,sink=1,corrupt=0,seed=7").unwrap();
+    assert_eq!(faults.counts(), FaultCounts::default());
+    assert!(Faults::parse("").is_ok());
+    assert!(Faults::parse("disconnect=2").is_err());
+    assert!(Faults::parse("disconnect").is_err());
+    assert!(Faults::parse("drop=0.1").is_err());
+    assert!(Faults::parse("delay-ms=x").is_err());
+}
+
+#[test]
+fn disconnect() {
+    let events = read_until_error(&mut replay("all", &Config::default())).0;
+    let run = |name: &str| {
+        let faults = Faults::new(5).disconnect(0.05);
+        let mut stream = replay(name, &Config::default()).with_faults(faults.clone());
+        let (n, err) = read_until_error(&mut stream);
+        assert_eq!(err.unwrap().kind(), io::ErrorKind::ConnectionReset);
+        //断开后不再返回事件
+        assert_eq!(stream.read_event().unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(faults.counts().disconnects, 1);
+        (n, stream.executed().log_pos)
+    };
+    let (n, log_pos) = run("disconnect1");
+    assert!(n < events);
+    //相同的seed在相同的位置断开
+    assert_eq!(run("disconnect2"), (n, log_pos));
+}
+
+#[test]
+fn corrupt() {
+    let conf = Config{ verifychecksum: String::from("all"), ..Config::default() };
+    let faults = Faults::new(1).corrupt(1.0);
+    let mut stream = replay("corrupt", &conf).with_faults(faults.clone());
+    let (n, err) = read_until_error(&mut stream);
+    //FORMAT_DESCRIPTION_EVENT不修改
+    assert_eq!((n, err.unwrap().kind()), (1, io::ErrorKind::InvalidData));
+    assert_eq!(faults.counts().corruptions, 1);
+
+    //没有注入时校验通过
+    let (n, err) = read_until_error(&mut replay("clean", &conf).with_faults(Faults::new(1).disconnect(0.0)));
+    assert!(n > 0 && err.is_none());
+}
+
+#[test]
+fn delay() {
+    let faults = Faults::new(1).delay(1.0, Duration::from_millis(1));
+    let n = read_until_error(&mut replay("delay", &Config::default()).with_faults(faults.clone())).0;
+    assert_eq!(faults.counts().delays, n as u64 + 1);
+}
+
+#[test]
+fn sink() {
+    let events: Vec<Event> = BinlogReader::new(Cursor::new(inserts(1..=20).into_bytes())).unwrap().map(|e| e.unwrap()).collect();
+    let faults = Faults::new(9).sink_error(0.3);
+    let mut sink = faults.sink(Collect(vec![]));
+    //失败时重试, 每个事件只写入一次
+    for event in &events {
+        while sink.write_event(event).is_err() {}
+    }
+    while sink.flush().is_err() {}
+    assert!(faults.counts().sink_errors > 0);
+    assert_eq!(sink.into_inner().0, events.iter().map(|e| e.log_pos).collect::<Vec<_>>());
+}
@@ -0,0 +1,91 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/07
+*/
+
+//按事件类型校验checksum
+
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+use mytest::noalloc::parse_noalloc;
+use mytest::prelude::*;
+use mytest::readvalue;
+
+fn fixture() -> Vec<u8> {
+    fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("mysql57.binlog")).unwrap()
+}
+
+//修改第一个行事件中的一个字节, 返回该事件的position
+fn corrupt_rows(data: &mut [u8]) -> u64 {
+    let pos = BinlogReader::new(Cursor::new(data.to_vec())).unwrap().map(|e| e.unwrap())
+        .find(|e| matches!(e.data, Traction::RowEvent(..))).unwrap().log_pos;
+    data[pos as usize + 30] ^= 0xff;
+    pos
+}
+
+fn read_all(data: &[u8], classes: ChecksumClass) -> Result<usize, String> {
+    let reader = BinlogReader::new(Cursor::new(data.to_vec())).unwrap().verify_checksum(classes);
+    let mut events = 0;
+    for event in reader {
+        event.map_err(|e| e.to_string())?;
+        events += 1;
+    }
+    Ok(events)
+}
+
+#[test]
+fn crc32_ieee() {
+    assert_eq!(readvalue::crc32(b"123456789"), 0xcbf43926);
+    assert_eq!(readvalue::crc32(b""), 0);
+}
+
+#[test]
+fn classes() {
+    assert_eq!(ChecksumClass::parse("all").unwrap(), ChecksumClass::all());
+    assert_eq!(ChecksumClass::parse("none").unwrap(), ChecksumClass::empty());
+    assert_eq!(ChecksumClass::parse("rows, TABLE_MAP").unwrap(), ChecksumClass::ROWS | ChecksumClass::TABLE_MAP);
+    assert!(ChecksumClass::parse("rows,xid").is_err());
+    assert_eq!(ChecksumClass::of(30), ChecksumClass::ROWS);
+    assert_eq!(ChecksumClass::of(19), ChecksumClass::TABLE_MAP);
+    assert_eq!(ChecksumClass::of(2), ChecksumClass::QUERY);
+    assert_eq!(ChecksumClass::of(16), ChecksumClass::CONTROL);
+}
+
+#[test]
+fn reader_by_class() {
+    let mut data = fixture();
+    let events = read_all(&data, ChecksumClass::all()).unwrap();
+    let pos = corrupt_rows(&mut data);
+    let err = read_all(&data, ChecksumClass::ROWS).unwrap_err();
+    assert!(err.contains(&format!("position {} 处的事件checksum错误", pos)), "{}", err);
+    //不校验行事件时修改的字段值照常读取
+    assert_eq!(read_all(&data, ChecksumClass::all() - ChecksumClass::ROWS).unwrap(), events);
+    assert_eq!(read_all(&data, ChecksumClass::empty()).unwrap(), events);
+
+    let noalloc: Vec<_> = parse_noalloc(&data).unwrap().verify_checksum(ChecksumClass::ROWS).collect();
+    assert!(noalloc.last().unwrap().is_err());
+}
+
+//verify_file读取文件, 只在os特性开启时编译
+#[cfg(feature = "os")]
+#[test]
+fn verify_file_by_class() {
+    use std::env;
+    use mytest::replication::verify;
+    let mut data = fixture();
+    let pos = corrupt_rows(&mut data);
+    let path = env::temp_dir().join(format!("mytest-checksum-{}.binlog", std::process::id()));
+    fs::write(&path, &data).unwrap();
+    let path_str = path.to_str().unwrap();
+
+    let report = verify::verify_file(path_str, false, ChecksumClass::all()).unwrap();
+    assert!(report.checksum);
+    assert_eq!(report.checksum_errors, vec![pos]);
+    assert_eq!(report.verified, report.events - 1);
+
+    let report = verify::verify_file(path_str, false, ChecksumClass::CONTROL).unwrap();
+    assert!(report.is_ok());
+    assert!(report.verified < report.events - 1);
+    fs::remove_file(&path).unwrap();
+}
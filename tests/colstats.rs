@@ -0,0 +1,101 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//按表的列统计: 滚动窗口与基线比较, 发现NULL激增、大量删除、写入速度及值大小的突变
+
+#![cfg(all(feature = "os", feature = "test-support"))]
+
+mod common;
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use mytest::colstats::{self, ColumnStats};
+use mytest::gtid::GtidSet;
+use mytest::prelude::*;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+use common::{order, orders, START};
+
+fn cols() -> Vec<HashMap<String, String>> {
+    ["id", "note"].iter().map(|name| {
+        let mut col = HashMap::new();
+        col.insert(String::from("COLUMN_NAME"), name.to_string());
+        col
+    }).collect()
+}
+
+//按(秒, 类型, 行)生成行事件, 读取后返回(事件时间, 事件)
+fn events(batches: &[(u32, BinlogEvent, Vec<Vec<Option<MySQLValue>>>)]) -> Vec<(u32, Traction)> {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.timestamp(START).previous_gtids(&GtidSet::new());
+    for (second, event_type, rows) in batches {
+        builder.timestamp(START + second);
+        builder.table_map(100, &orders(false)).unwrap();
+        builder.rows(event_type, 100, &orders(false), rows).unwrap();
+    }
+    BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap()
+        .map(|e| e.unwrap())
+        .filter(|e| matches!(e.data, Traction::RowEvent(..)))
+        .map(|e| (e.header.timestamp, e.data))
+        .collect()
+}
+
+fn record(stats: &mut ColumnStats, events: &[(u32, Traction)]) -> Vec<String> {
+    events.iter().flat_map(|(timestamp, data)| stats.record(data, &cols(), "shop.orders", *timestamp))
+        .map(|a| format!("{}:{}", a.kind, a.column.unwrap_or_default()))
+        .collect()
+}
+
+#[test]
+fn anomalies() {
+    //前120秒每秒写入2行, note平均10字节
+    let normal: Vec<_> = (0..120u32).map(|s| (s, BinlogEvent::WriteEvent, vec![order(s as i64 * 2, Some("0123456789")), order(s as i64 * 2 + 1, Some("abcdefghij"))])).collect();
+    let mut stats = ColumnStats::new(60).factor(4.0).min_rows(50);
+    assert!(record(&mut stats, &events(&normal)).is_empty());
+    let value = stats.to_json();
+    let table = &value["tables"]["shop.orders"];
+    assert_eq!(table["rows"], 240);
+    assert_eq!(table["insert"], 240);
+    assert_eq!(table["rows_per_sec"], 2.0);
+    assert_eq!(table["columns"]["note"]["avg_size"], 10.0);
+    assert_eq!(table["columns"]["note"]["null_ratio"], 0.0);
+
+    //之后60秒note全部为NULL, 写入速度不变
+    let nulls: Vec<_> = (120..180u32).map(|s| (s, BinlogEvent::WriteEvent, vec![order(1000 + s as i64 * 2, None), order(1001 + s as i64 * 2, None)])).collect();
+    let found = record(&mut stats, &events(&nulls));
+    assert_eq!(found, vec!["null_flood:note"]);
+    //持续存在的异常只返回一次
+    assert_eq!(stats.anomalies().len(), 1);
+    assert!(stats.report().contains("null_flood shop.orders.note"));
+
+    //一次删除500行, 窗口内约10行/秒, 基线为2行/秒
+    let deleted: Vec<_> = (0..500).map(|i| order(i, Some("0123456789"))).collect();
+    let found = record(&mut stats, &events(&[(180, BinlogEvent::DeleteEvent, deleted)]));
+    assert!(found.contains(&String::from("bulk_delete:")), "{:?}", found);
+    assert!(found.contains(&String::from("rate_spike:")), "{:?}", found);
+    let value = stats.to_json();
+    assert_eq!(value["tables"]["shop.orders"]["delete"], 500);
+    let kinds: Vec<&str> = value["anomalies"].as_array().unwrap().iter().map(|t| t["kind"].as_str().unwrap()).collect();
+    assert!(kinds.contains(&"bulk_delete"));
+}
+
+#[test]
+fn value_size() {
+    let small: Vec<_> = (0..120u32).map(|s| (s, BinlogEvent::WriteEvent, vec![order(s as i64, Some("ab"))])).collect();
+    let mut stats = ColumnStats::new(30).min_rows(10);
+    assert!(record(&mut stats, &events(&small)).is_empty());
+    let large = "x".repeat(100);
+    let large: Vec<_> = (120..150u32).map(|s| (s, BinlogEvent::UpdateEvent, vec![order(s as i64, Some("ab")), order(s as i64, Some(&large))])).collect();
+    assert_eq!(record(&mut stats, &events(&large)), vec!["value_size:note"]);
+    assert_eq!(stats.to_json()["tables"]["shop.orders"]["update"], 30);
+
+    //窗口之前的数据不足一个窗口时不判断
+    let mut stats = ColumnStats::new(300).min_rows(1);
+    assert!(record(&mut stats, &events(&small)).is_empty());
+    assert!(record(&mut stats, &events(&large)).is_empty());
+
+    assert_eq!(colstats::value_size(&MySQLValue::String(String::from("abc"))), 3);
+    assert_eq!(colstats::value_size(&MySQLValue::SignedInteger(1)), 8);
+    assert_eq!(colstats::value_size(&MySQLValue::Null), 0);
+}
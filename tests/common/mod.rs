@@ -0,0 +1,114 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//多个集成测试共用的表结构、行数据及binlog文件, 每个测试只使用其中的一部分
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::str::FromStr;
+use uuid::Uuid;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::replication::tablemeta::{ColumnMetadata, TableMetadata};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+pub const START: u32 = 1573516800;
+pub const SID: &str = "3e11fa47-71ca-11e1-9e33-c80aa9429562";
+
+//shop.orders(id int, status varchar), metadata为true时带有字段名及主键
+pub fn orders(metadata: bool) -> TableMap {
+    let named = |name: &str| ColumnMetadata{ name: Some(name.to_string()), ..Default::default() };
+    TableMap{
+        database_name: String::from("shop"),
+        table_name: String::from("orders"),
+        column_count: 2,
+        column_info: vec![
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeVarchar, column_meta: vec![1] },
+        ],
+        metadata: if metadata {
+            Some(TableMetadata{ columns: vec![named("id"), named("status")], default_collation: Some(255), primary_key: vec![(0, 0)] })
+        } else {
+            None
+        },
+    }
+}
+
+pub fn order(id: i64, status: Option<&str>) -> Vec<Option<MySQLValue>> {
+    vec![Some(MySQLValue::SignedInteger(id)), status.map(|t| MySQLValue::String(t.to_string()))]
+}
+
+pub fn gtid(gno: u64) -> GtidEvent {
+    GtidEvent{ gtid: Uuid::from_str(SID).unwrap(), gno_id: gno, last_committed: 0, sequence_number: 1, commit_timestamp: None }
+}
+
+pub fn begin(database: &str) -> QueryEvent {
+    QueryEvent{ thread_id: 10, execute_seconds: 0, database: database.to_string(), command: String::from("BEGIN"), invoker: String::new() }
+}
+
+//每个事务一个行事件(gtid、BEGIN、table_map、行事件、xid), 从START开始间隔10秒, 返回binlog内容及每个事务的起始位置
+pub fn transactions(map: &TableMap, trxs: &[(BinlogEvent, Vec<Vec<Option<MySQLValue>>>)]) -> (Vec<u8>, Vec<u64>) {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.timestamp(START).previous_gtids(&GtidSet::new());
+    let mut positions = vec![];
+    for (idx, (event_type, rows)) in trxs.iter().enumerate() {
+        positions.push(builder.position());
+        builder.timestamp(START + idx as u32 * 10);
+        builder.gtid(&gtid(idx as u64 + 1));
+        builder.query(&begin(&map.database_name));
+        builder.table_map(100, map).unwrap();
+        builder.rows(event_type, 100, map, rows).unwrap();
+        builder.xid(&XidEvent{ xid: idx as u64 });
+    }
+    (builder.into_bytes(), positions)
+}
+
+//一个insert事务: gtid、BEGIN、shop.orders的table_map、id为gno的一行、xid(同为gno), 事务的时间为START + gno
+pub fn insert(builder: &mut BinlogBuilder, gno: u64) -> &mut BinlogBuilder {
+    builder.timestamp(START + gno as u32);
+    builder.gtid(&gtid(gno)).query(&begin("shop"));
+    builder.table_map(100, &orders(false)).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &orders(false), &[order(gno as i64, None)]).unwrap();
+    builder.xid(&XidEvent{ xid: gno })
+}
+
+//5.7格式, previous_gtids为空, 之后gnos中每个gno一个insert事务
+pub fn inserts(gnos: impl IntoIterator<Item = u64>) -> BinlogBuilder {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.timestamp(START).previous_gtids(&GtidSet::new());
+    for gno in gnos {
+        insert(&mut builder, gno);
+    }
+    builder
+}
+
+//写入临时目录下的mytest-<prefix>-<pid>/<name>, 返回文件路径
+pub fn write_file(prefix: &str, name: &str, bytes: Vec<u8>) -> String {
+    let dir = env::temp_dir().join(format!("mytest-{}-{}", prefix, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, bytes).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+//shop.<name>(id int, value int), 没有metadata
+pub fn int_table(name: &str) -> TableMap {
+    TableMap{
+        database_name: String::from("shop"),
+        table_name: name.to_string(),
+        column_count: 2,
+        column_info: vec![
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] },
+        ],
+        metadata: None,
+    }
+}
+
+pub fn int_row(id: i64, value: i64) -> Vec<Option<MySQLValue>> {
+    vec![Some(MySQLValue::SignedInteger(id)), Some(MySQLValue::SignedInteger(value))]
+}
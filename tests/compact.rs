@@ -0,0 +1,136 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--compact: 同一主键的多次修改合并为最终数据, 结果与按顺序回放所有修改后的状态一致, 并按表、主键排序
+
+#![cfg(feature = "os")]
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use serde_json::Value as JsonValue;
+use mytest::Config;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+use mytest::stdout::outcompact::Compactor;
+
+fn cols() -> Vec<HashMap<String, String>> {
+    [("id", "bigint", "PRI"), ("num", "int", ""), ("name", "varchar", "")].iter().map(|(name, col_type, key)| {
+        let mut info = HashMap::new();
+        info.insert(String::from("COLUMN_NAME"), name.to_string());
+        info.insert(String::from("COLUMN_TYPE"), col_type.to_string());
+        info.insert(String::from("COLUMN_KEY"), key.to_string());
+        info
+    }).collect()
+}
+
+//every不为0时每every个事务取出一次结果
+fn compact(path: &str, every: usize) -> Vec<Vec<JsonValue>> {
+    let conf = Config{ compact: true, ..Default::default() };
+    let mut compactor = Compactor::new(&conf).unwrap();
+    let format = ValueFormat::default();
+    let mut table_cols_info = HashMap::new();
+    let mut last_map = None;
+    let mut windows = vec![];
+    let mut transactions = 0;
+    for event in BinlogReader::open(path).unwrap() {
+        let event = event.unwrap();
+        if let Some(map) = &event.table {
+            table_cols_info.entry(format!("{}.{}", map.database_name, map.table_name)).or_insert_with(cols);
+            last_map = Some(map.clone());
+        }
+        let map = match &last_map {
+            Some(t) => t,
+            None => continue
+        };
+        compactor.record(&event.data, &table_cols_info, &format!("{}.{}", map.database_name, map.table_name), map, "", &format);
+        if let Traction::XidEvent(_) = event.data {
+            transactions += 1;
+            if every > 0 && transactions % every == 0 {
+                windows.push(compactor.take());
+            }
+        }
+    }
+    windows.push(compactor.take());
+    windows
+}
+
+fn int(value: &Option<MySQLValue>) -> Option<i64> {
+    match value {
+        Some(MySQLValue::SignedInteger(t)) => Some(*t),
+        _ => None
+    }
+}
+
+#[test]
+fn final_state() {
+    let opts = SynthOptions{
+        database: String::from("sync"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 100,
+        rows: (1, 4),
+        rate: 10,
+        mix: [2, 3, 1],
+        start_time: 1572998400,
+        seed: 19,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-compact-{}.binlog", std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    let path = path.to_str().unwrap();
+
+    //按顺序回放得到每个主键的最终状态, None为已删除, 否则为num字段的值
+    let mut replay: HashMap<(String, i64), Option<Option<i64>>> = HashMap::new();
+    let mut rows = 0;
+    for event in BinlogReader::open(path).unwrap() {
+        let event = event.unwrap();
+        if let (Traction::RowEvent(code, values), Some(map)) = (&event.data, &event.table) {
+            let step = if let BinlogEvent::UpdateEvent = code { 2 } else { 1 };
+            for image in values.rows().chunks(step) {
+                let row = image.last().unwrap();
+                let state = match code {
+                    BinlogEvent::DeleteEvent => None,
+                    _ => Some(int(&row[1]))
+                };
+                replay.insert((map.table_name.clone(), int(&row[0]).unwrap()), state);
+                rows += 1;
+            }
+        }
+    }
+
+    let records = compact(path, 0).concat();
+    assert_eq!(records.len(), replay.len());
+    let mut changes = 0;
+    for record in &records {
+        let key = (record["table"].as_str().unwrap().to_string(), record["key"]["id"].as_i64().unwrap());
+        match &replay[&key] {
+            None => {
+                assert_eq!(record["type"], "delete");
+                assert!(record["data"].is_null());
+            }
+            Some(num) => {
+                assert_eq!(record["type"], "upsert");
+                assert_eq!(record["data"]["num"].as_i64(), *num);
+            }
+        }
+        changes += record["changes"].as_u64().unwrap();
+    }
+    assert_eq!(changes, rows);
+    assert!(records.iter().any(|r| r["type"] == "delete") && records.iter().any(|r| r["changes"].as_u64().unwrap() > 1));
+
+    //结果按表、主键(数值)排序
+    let keys: Vec<(String, i64)> = records.iter().map(|r| (r["table"].as_str().unwrap().to_string(), r["key"]["id"].as_i64().unwrap())).collect();
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert_eq!(keys, sorted);
+
+    //按窗口输出时合并的范围更小, 修改总数不变
+    let windows = compact(path, 5);
+    assert!(windows.len() > 2);
+    assert_eq!(windows.iter().flatten().map(|r| r["changes"].as_u64().unwrap()).sum::<u64>(), rows);
+    assert!(windows.iter().flatten().count() > records.len());
+    fs::remove_file(path).unwrap();
+}
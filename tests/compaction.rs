@@ -0,0 +1,129 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//小文件合并: 分区目录中的小文件按文件名顺序拼接, 中途退出后可以恢复, RoutedSink关闭分区后在后台合并
+
+#![cfg(all(feature = "os", feature = "json-sink"))]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use mytest::prelude::*;
+use mytest::sink::{CompactionTask, FileCompactor};
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("mytest-compaction-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir).unwrap().map(|t| t.unwrap().file_name().to_string_lossy().to_string()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn compact() {
+    let dir = temp_dir("compact");
+    for (name, text) in [("01.json", "a\n"), ("02.json", "b\n"), ("03.json", "c\n"), ("05.json", "e\n"), ("06.json", "f\n"),
+                         ("x.json.gz", "x"), ("y.json.gz", "y"), (".hidden", "h"), ("07.json.tmp", "t")] {
+        fs::write(dir.join(name), text).unwrap();
+    }
+    //超过目标大小的文件不合并, 前后的文件分为两组
+    fs::write(dir.join("04.json"), "d\n".repeat(10)).unwrap();
+
+    let stats = FileCompactor::new(16).compact(&dir).unwrap();
+    assert_eq!((stats.partitions, stats.files, stats.outputs, stats.bytes), (1, 7, 3, 12));
+    assert_eq!(files(&dir), vec![".hidden", "01.json", "04.json", "05.json", "07.json.tmp", "x.json.gz"]);
+    assert_eq!(fs::read_to_string(dir.join("01.json")).unwrap(), "a\nb\nc\n");
+    assert_eq!(fs::read_to_string(dir.join("05.json")).unwrap(), "e\nf\n");
+    assert_eq!(fs::read_to_string(dir.join("x.json.gz")).unwrap(), "xy");
+
+    //已经合并过的目录不再变化
+    assert_eq!(FileCompactor::new(16).compact(&dir).unwrap().files, 0);
+    assert_eq!(FileCompactor::new(1024).min_files(4).compact(&dir).unwrap().outputs, 0);
+    assert_eq!(FileCompactor::new(1024).min_files(3).compact(&dir).unwrap().outputs, 1);
+    assert_eq!(fs::read_to_string(dir.join("01.json")).unwrap(), format!("a\nb\nc\n{}e\nf\n", "d\n".repeat(10)));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn recover() {
+    //合并结果已经rename, 其余的文件还没有删除
+    let dir = temp_dir("renamed");
+    fs::write(dir.join("01.json"), "a\nb\n").unwrap();
+    fs::write(dir.join("02.json"), "b\n").unwrap();
+    fs::write(dir.join(".compacting"), "02.json").unwrap();
+    let stats = FileCompactor::new(16).compact(&dir).unwrap();
+    assert_eq!(stats.files, 0);
+    assert_eq!(files(&dir), vec!["01.json"]);
+    assert_eq!(fs::read_to_string(dir.join("01.json")).unwrap(), "a\nb\n");
+    fs::remove_dir_all(&dir).unwrap();
+
+    //合并结果还没有rename, 撤销后重新合并
+    let dir = temp_dir("pending");
+    fs::write(dir.join("01.json"), "a\n").unwrap();
+    fs::write(dir.join("02.json"), "b\n").unwrap();
+    fs::write(dir.join(".compacting.tmp"), "a\n").unwrap();
+    fs::write(dir.join(".compacting"), "02.json").unwrap();
+    assert_eq!(FileCompactor::new(16).compact(&dir).unwrap().files, 2);
+    assert_eq!(files(&dir), vec!["01.json"]);
+    assert_eq!(fs::read_to_string(dir.join("01.json")).unwrap(), "a\nb\n");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn routed() {
+    let opts = SynthOptions{
+        database: String::from("compaction"),
+        tables: 3,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(32)],
+        transactions: 60,
+        rows: (1, 3),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 9,
+        server_id: 133,
+    };
+    let dir = temp_dir("routed");
+    let path = dir.join("source.binlog");
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+
+    //每个表为一个分区, 每种操作一个文件, max_open关闭的分区在后台合并, 之后又写入时先等待合并完成
+    let out = dir.join("out");
+    let template = format!("{}/{{db}}/{{table}}/{{op}}.json", out.to_str().unwrap());
+    let task = CompactionTask::spawn(FileCompactor::new(1 << 20));
+    let mut buf = vec![];
+    let mut single = JsonLinesSink::new(&mut buf, ValueFormat::default());
+    let mut sink = RoutedSink::json_files(&template, ValueFormat::default()).unwrap().max_open(2).with_compaction(task.clone());
+    for event in BinlogReader::open(&path).unwrap() {
+        let event = event.unwrap();
+        single.write_event(&event).unwrap();
+        sink.write_event(&event).unwrap();
+    }
+    drop(sink);
+    drop(single);
+    let stats = task.finish();
+    assert!(stats.errors.is_empty(), "{:?}", stats.errors);
+    assert!(stats.partitions >= 3);
+
+    let mut routed = vec![];
+    for tbl in ["t1", "t2", "t3"] {
+        let partition = out.join("compaction").join(tbl);
+        let names = files(&partition);
+        assert_eq!(names.len(), 1, "{:?}", names);
+        routed.extend(fs::read_to_string(partition.join(&names[0])).unwrap().lines().map(String::from));
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    let mut expected: Vec<String> = String::from_utf8(buf).unwrap().lines().map(String::from).collect();
+    assert!(!expected.is_empty());
+    expected.sort();
+    routed.sort();
+    assert_eq!(routed, expected);
+}
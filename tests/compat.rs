@@ -0,0 +1,44 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//托管mysql的兼容模式: 配置解析及内部表的判断
+
+use mytest::Config;
+use mytest::compat::{self, Profile};
+
+#[test]
+fn profile() {
+    assert_eq!(Profile::parse("").unwrap(), None);
+    assert_eq!(Profile::parse("RDS").unwrap(), Some(Profile::Rds));
+    assert_eq!(Profile::parse("aurora").unwrap(), Some(Profile::Aurora));
+    assert!(Profile::parse("tidb").unwrap_err().contains("tidb"));
+
+    let aurora = Profile::Aurora;
+    assert!(aurora.internal_table("mysql", "rds_heartbeat2"));
+    assert!(aurora.internal_table("mysql", "ha_health_check"));
+    assert!(aurora.internal_table("mysql", "aurora_s3_load_history"));
+    assert!(!aurora.internal_table("mysql", "user"));
+    assert!(!aurora.internal_table("shop", "rds_orders"));
+
+    let conf = Config{ compat: String::from("rds"), ..Config::default() };
+    assert!(compat::internal_table(&conf, "mysql", "rds_sysinfo"));
+    assert!(!compat::internal_table(&Config::default(), "mysql", "rds_sysinfo"));
+}
+
+#[test]
+fn middleware() {
+    assert_eq!(Profile::parse("ProxySQL").unwrap(), Some(Profile::ProxySql));
+    assert_eq!(Profile::parse("maxscale").unwrap(), Some(Profile::MaxScale));
+    assert!(!Profile::MaxScale.internal_table("mysql", "rds_heartbeat2"));
+    assert!(!Profile::MaxScale.queryable() && Profile::ProxySql.queryable());
+    assert!(Profile::Rds.managed() && !Profile::ProxySql.managed());
+
+    let position = Config{ binlogfile: String::from("mysql-bin.000012"), position: String::from("4"), ..Config::default() };
+    assert!(Profile::MaxScale.check_dump_options(&position).is_ok());
+    let gtid = Config{ gtid: String::from("3e11fa47-71ca-11e1-9e33-c80aa9429562:1-5"), ..Config::default() };
+    assert!(Profile::MaxScale.check_dump_options(&gtid).unwrap_err().contains("COM_BINLOG_DUMP_GTID"));
+    assert!(Profile::ProxySql.check_dump_options(&position).is_err());
+    assert!(Profile::Aurora.check_dump_options(&gtid).is_ok());
+}
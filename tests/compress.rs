@@ -0,0 +1,231 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//输出压缩: 按flush分块压缩的文件可以整体解压, 消息逐条压缩, 其他算法通过Compressor接入
+//  zstd、lz4的测试需要开启对应的特性: cargo test --features zstd,lz4
+
+#![cfg(all(feature = "os", feature = "json-sink", feature = "compression"))]
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+use flate2::read::MultiGzDecoder;
+use mytest::prelude::*;
+use mytest::sink::{self, CompressedWriter, Gzip};
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn gunzip(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    MultiGzDecoder::new(data).read_to_end(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn compressor() {
+    assert_eq!(sink::compressor("gzip").unwrap().unwrap().encoding(), "gzip");
+    assert_eq!(sink::compressor("GZIP:9").unwrap().unwrap().encoding(), "gzip");
+    assert!(sink::compressor("").unwrap().is_none());
+    assert!(sink::compressor("none").unwrap().is_none());
+    assert!(sink::compressor("gzip:10").is_err());
+    assert!(sink::compressor("gzip:6,dict=x").is_err());
+    assert!(sink::compressor("gzip,workers=0").is_err());
+    assert!(sink::compressor("brotli").is_err());
+    assert_eq!(sink::output_compression("gzip:1,workers=4").unwrap().unwrap().workers, 4);
+    assert_eq!(sink::compressor("gzip").unwrap().unwrap().extension(), "gz");
+    #[cfg(not(feature = "zstd"))]
+    assert!(sink::compressor("zstd").err().unwrap().contains("Compressor"));
+    #[cfg(not(feature = "lz4"))]
+    assert!(sink::compressor("lz4").is_err());
+
+    let data = "{\"type\":\"insert\",\"database\":\"db\"}\n".repeat(100);
+    let compressed = Gzip::default().compress(data.as_bytes()).unwrap();
+    assert!(compressed.len() < data.len() / 5);
+    assert_eq!(gunzip(&compressed), data.as_bytes());
+}
+
+fn events() -> Vec<Event> {
+    let opts = SynthOptions{
+        database: String::from("compress"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 20,
+        rows: (1, 3),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 5,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-compress-{}.binlog", std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    let events: Vec<Event> = BinlogReader::open(path.to_str().unwrap()).unwrap().map(|e| e.unwrap()).collect();
+    fs::remove_file(&path).unwrap();
+    events
+}
+
+#[test]
+fn writer() {
+    let events = events();
+    let mut plain = vec![];
+    let mut sink = JsonLinesSink::new(&mut plain, ValueFormat::default());
+    for event in &events {
+        sink.write_event(event).unwrap();
+    }
+    drop(sink);
+
+    //每个事务flush一次, 多个gzip member整体解压后与不压缩的输出相同
+    let mut compressed = vec![];
+    {
+        let writer = CompressedWriter::new(&mut compressed, Box::new(Gzip::default()));
+        let mut sink = JsonLinesSink::new(writer, ValueFormat::default());
+        for event in &events {
+            sink.write_event(event).unwrap();
+            if let Traction::XidEvent(_) = event.data {
+                sink.flush().unwrap();
+            }
+        }
+    }
+    assert!(compressed.len() < plain.len());
+    assert_eq!(gunzip(&compressed), plain);
+
+    //drop时写出剩余的数据
+    let mut out = vec![];
+    CompressedWriter::new(&mut out, Box::new(Gzip::default())).write_all(b"tail\n").unwrap();
+    assert_eq!(gunzip(&out), b"tail\n");
+}
+
+//超过1MB的输出按块分配给多个线程压缩, 按顺序写出, 与单线程的输出完全相同
+#[test]
+fn workers() {
+    let data: Vec<u8> = (0..200000).flat_map(|i| format!("{{\"id\":{},\"name\":\"row-{}\"}}\n", i, i % 97).into_bytes()).collect();
+    assert!(data.len() > 4 << 20);
+    let write = |spec: &str| {
+        let mut out = vec![];
+        {
+            let mut writer = sink::output_compression(spec).unwrap().unwrap().writer(&mut out);
+            for chunk in data.chunks(100000) {
+                writer.write_all(chunk).unwrap();
+            }
+            writer.flush().unwrap();
+            writer.write_all(b"tail\n").unwrap();
+        }
+        out
+    };
+    let single = write("gzip");
+    let parallel = write("gzip,workers=4");
+    assert_eq!(parallel, single);
+    let mut expected = data.clone();
+    expected.extend_from_slice(b"tail\n");
+    assert_eq!(gunzip(&parallel), expected);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn zstd() {
+    use mytest::sink::Zstd;
+    let data = "{\"type\":\"insert\",\"database\":\"db\",\"table\":\"orders\"}\n".repeat(200);
+    let unzstd = |compressed: &[u8]| zstd::stream::decode_all(compressed).unwrap();
+    let compressor = sink::compressor("zstd:19,window-log=20,checksum").unwrap().unwrap();
+    assert_eq!((compressor.encoding(), compressor.extension()), ("zstd", "zst"));
+    let compressed = compressor.compress(data.as_bytes()).unwrap();
+    assert!(compressed.len() < data.len() / 10);
+    assert_eq!(unzstd(&compressed), data.as_bytes());
+    assert!(sink::compressor("zstd:23").is_err());
+    assert!(sink::compressor("zstd,window-log=40").is_err());
+    assert!(sink::compressor("zstd,level=3").is_err());
+    assert!(sink::compressor("zstd,dict=/nonexistent/mytest.dict").err().unwrap().contains("字典"));
+
+    //字典: 单条消息压缩后更小, 解压需要同一个字典
+    let samples: Vec<String> = (0..200).map(|i| format!("{{\"type\":\"update\",\"database\":\"shop\",\"table\":\"orders\",\"id\":{},\"status\":\"paid\"}}", i)).collect();
+    let dictionary = zstd::dict::from_samples(&samples, 4096).unwrap();
+    let message = samples[7].as_bytes();
+    let plain = Zstd::new(3).compress(message).unwrap();
+    let with_dict = Zstd::new(3).with_dictionary(&dictionary).compress(message).unwrap();
+    assert!(with_dict.len() < plain.len());
+    let mut decoder = zstd::stream::Decoder::with_dictionary(&with_dict[..], &dictionary).unwrap();
+    let mut out = vec![];
+    decoder.read_to_end(&mut out).unwrap();
+    assert_eq!(out, message);
+
+    //多个frame连在一起整体解压
+    let mut out = vec![];
+    {
+        let mut writer = sink::output_compression("zstd:3,workers=2").unwrap().unwrap().writer(&mut out);
+        writer.write_all(b"first\n").unwrap();
+        writer.flush().unwrap();
+        writer.write_all(b"second\n").unwrap();
+    }
+    assert_eq!(unzstd(&out), b"first\nsecond\n");
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn lz4() {
+    let data = "{\"type\":\"delete\",\"database\":\"db\"}\n".repeat(200);
+    let compressor = sink::compressor("lz4,block=256k,checksum").unwrap().unwrap();
+    assert_eq!((compressor.encoding(), compressor.extension()), ("lz4", "lz4"));
+    assert!(sink::compressor("lz4:9").is_err());
+    assert!(sink::compressor("lz4,block=2m").is_err());
+
+    let mut out = vec![];
+    {
+        let mut writer = sink::output_compression("lz4,workers=2").unwrap().unwrap().writer(&mut out);
+        writer.write_all(data.as_bytes()).unwrap();
+        writer.flush().unwrap();
+        writer.write_all(b"tail\n").unwrap();
+    }
+    assert!(out.len() < data.len() / 4);
+    //lz4 -d按顺序解压多个frame, FrameDecoder每次只读取一个frame
+    let (mut decoded, mut rest) = (vec![], &out[..]);
+    while !rest.is_empty() {
+        lz4_flex::frame::FrameDecoder::new(&mut rest).read_to_end(&mut decoded).unwrap();
+    }
+    assert_eq!(decoded, format!("{}tail\n", data).as_bytes());
+}
+
+//反转字节作为压缩, 验证消息逐条经过压缩
+struct Reverse;
+
+impl Compressor for Reverse {
+    fn encoding(&self) -> &str {
+        "reverse"
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(data.iter().rev().cloned().collect())
+    }
+}
+
+struct FixedRegistry;
+
+impl SchemaRegistry for FixedRegistry {
+    fn register(&mut self, _subject: &str, _schema: &str) -> Result<u32, String> {
+        Ok(7)
+    }
+}
+
+#[test]
+fn messages() {
+    let event = events().into_iter().find(|e| matches!(e.data, Traction::RowEvent(..))).unwrap();
+    let received = Rc::new(RefCell::new(vec![]));
+    let messages = received.clone();
+    let mut sink = RegistrySink::new("binlog.{db}", Box::new(FixedRegistry), ValueFormat::default(), move |topic, message| {
+        messages.borrow_mut().push((topic.to_string(), message.to_vec()));
+        Ok(())
+    }).unwrap().with_compression(Box::new(Reverse));
+    assert_eq!(sink.content_encoding(), Some("reverse"));
+    sink.write_event(&event).unwrap();
+    let received = received.borrow();
+    assert!(!received.is_empty());
+    for (topic, message) in received.iter() {
+        assert_eq!(topic, "binlog.compress");
+        let message: Vec<u8> = message.iter().rev().cloned().collect();
+        assert_eq!(&message[..5], &[0, 0, 0, 0, 7]);
+        let record: serde_json::Value = serde_json::from_slice(&message[5..]).unwrap();
+        assert_eq!(record["database"], "compress");
+    }
+}
@@ -0,0 +1,101 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//mysql压缩协议: 压缩包中的多个mysql包按顺序读取, 未压缩的压缩包、写入的命令按压缩包发送, 流量统计
+//mysql服务端使用本地socket模拟
+
+#![cfg(all(feature = "network", feature = "compression"))]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use mytest::io::compress::{self, Compression};
+use mytest::io::socketio;
+
+fn packet(payload: &[u8], seq: u8) -> Vec<u8> {
+    let mut buf = (payload.len() as u32).to_le_bytes()[..3].to_vec();
+    buf.push(seq);
+    buf.extend(payload);
+    buf
+}
+
+fn frame(data: &[u8], seq: u8, compress: bool) -> Vec<u8> {
+    let (payload, raw) = if compress {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        (encoder.finish().unwrap(), data.len())
+    } else {
+        (data.to_vec(), 0)
+    };
+    let mut buf = (payload.len() as u32).to_le_bytes()[..3].to_vec();
+    buf.push(seq);
+    buf.extend(&(raw as u32).to_le_bytes()[..3]);
+    buf.extend(payload);
+    buf
+}
+
+#[test]
+fn parse() {
+    assert_eq!(Compression::parse("zlib").unwrap(), Some(Compression::Zlib));
+    assert_eq!(Compression::parse("zstd,zlib").unwrap(), Some(Compression::Zlib));
+    assert_eq!(Compression::parse("").unwrap(), None);
+    assert_eq!(Compression::parse("uncompressed").unwrap(), None);
+    assert!(Compression::parse("zstd").err().unwrap().contains("zstd"));
+    assert!(Compression::parse("lz4").is_err());
+}
+
+#[test]
+fn packets() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+    compress::enable(&client, Some(Compression::Zlib));
+
+    //一个压缩包中有两个mysql包, 之后为未压缩的压缩包
+    let rows = vec![b'a'; 1000];
+    let mut data = packet(&rows, 1);
+    data.extend(packet(b"\x00ok", 2));
+    server.write_all(&frame(&data, 1, true)).unwrap();
+    server.write_all(&frame(&packet(b"\xfeeof", 3), 2, false)).unwrap();
+
+    let (buf, header) = socketio::get_packet_from_stream(&mut client);
+    assert_eq!((buf, header.seq_id), (rows.clone(), 1));
+    let (buf, _) = socketio::get_packet_from_stream(&mut client);
+    assert_eq!(buf, b"\x00ok");
+    let (buf, _) = socketio::get_packet_from_stream(&mut client);
+    assert_eq!(buf, b"\xfeeof");
+
+    //命令按新的压缩包发送, 较长时压缩
+    let command = packet(format!("\x03SELECT '{}'", "x".repeat(200)).as_bytes(), 0);
+    socketio::write_value(&mut client, &command).unwrap();
+    let mut header = [0u8; 7];
+    server.read_exact(&mut header).unwrap();
+    let length = u32::from_le_bytes([header[0], header[1], header[2], 0]) as usize;
+    let raw = u32::from_le_bytes([header[4], header[5], header[6], 0]) as usize;
+    assert_eq!((header[3], raw), (0, command.len()));
+    assert!(length < raw);
+    let mut payload = vec![0u8; length];
+    server.read_exact(&mut payload).unwrap();
+    let mut decoded = vec![];
+    flate2::read::ZlibDecoder::new(&payload[..]).read_to_end(&mut decoded).unwrap();
+    assert_eq!(decoded, command);
+
+    //短命令不压缩
+    socketio::write_value(&mut client, &packet(b"\x0e", 0)).unwrap();
+    server.read_exact(&mut header).unwrap();
+    assert_eq!(&header[3..], &[0, 0, 0, 0]);
+    let mut payload = [0u8; 5];
+    server.read_exact(&mut payload).unwrap();
+    assert_eq!(payload.to_vec(), packet(b"\x0e", 0));
+
+    let stats = compress::stats();
+    assert!(stats.bytes >= (rows.len() + command.len()) as u64);
+    assert!(stats.ratio() > 2.0, "{:?}", stats);
+
+    //取消后按普通连接读写
+    compress::enable(&client, None);
+    server.write_all(&packet(b"plain", 5)).unwrap();
+    let (buf, _) = socketio::get_packet_from_stream(&mut client);
+    assert_eq!(buf, b"plain");
+}
@@ -0,0 +1,280 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//控制接口: 跳过指定gtid的整个事务, 暂停在事务之间等待恢复, 回退请求在事务之间返回; http接口修改相同的状态; 已执行位置只在事务结束时更新
+
+#![cfg(feature = "os")]
+
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use serde_json::Value as JsonValue;
+use mytest::Config;
+use mytest::control::{self, Health, Position};
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn events(name: &str) -> Vec<Event> {
+    let opts = SynthOptions{
+        database: String::from("control"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 20,
+        rows: (1, 3),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 31,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-control-{}-{}.binlog", name, std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    let events = BinlogReader::open(path.to_str().unwrap()).unwrap().map(|e| e.unwrap()).collect();
+    fs::remove_file(&path).unwrap();
+    events
+}
+
+fn gtids(events: &[Event]) -> Vec<String> {
+    events.iter().filter_map(|e| match &e.data {
+        Traction::GtidEvent(t) => Some(format!("{}:{}", t.gtid, t.gno_id)),
+        _ => None
+    }).collect()
+}
+
+fn observe(controller: &Controller, event: &Event) -> bool {
+    controller.event(&event.data, event.header.timestamp, event.header.next_position as u64)
+}
+
+#[test]
+fn skip() {
+    let events = events("skip");
+    let all = gtids(&events);
+    //跳过最后两个事务(有行数据)
+    let skipped = &all[all.len() - 2..];
+    let controller = Controller::new();
+    for gtid in skipped {
+        controller.skip(&gtid.parse::<GtidSet>().unwrap());
+    }
+    let kept: Vec<Event> = events.iter().filter(|e| observe(&controller, e)).cloned().collect();
+    let kept_gtids = gtids(&kept);
+    assert_eq!(&kept_gtids[..], &all[..all.len() - 2]);
+    //被跳过事务的行数据及XID都不返回
+    let last_xid = kept.iter().rposition(|e| matches!(e.data, Traction::XidEvent(_))).unwrap();
+    assert!(kept[last_xid + 1..].iter().all(|e| !matches!(e.data, Traction::RowEvent(..) | Traction::TableMapEvent(_))));
+    let status = controller.status();
+    assert_eq!(status["skipped"], serde_json::json!(skipped));
+    //最后一个已完成事务的gtid仍按读取到的事件记录
+    assert_eq!(status["gtid"], all[all.len() - 1]);
+}
+
+#[test]
+fn pause_between_transactions() {
+    let events = events("pause");
+    let controller = Controller::new();
+    controller.pause();
+    //事务中间不暂停
+    let begin = events.iter().position(|e| matches!(e.data, Traction::GtidEvent(_))).unwrap();
+    observe(&controller, &events[begin]);
+    assert_eq!(controller.checkpoint(), None);
+    let end = begin + events[begin..].iter().position(|e| matches!(e.data, Traction::XidEvent(_) | Traction::QueryEvent(_))).unwrap();
+    for event in &events[begin + 1..=end] {
+        observe(&controller, event);
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let waiting = controller.clone();
+    thread::spawn(move || {
+        tx.send(waiting.checkpoint()).unwrap();
+    });
+    assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+    controller.resume();
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), None);
+    assert!(!controller.is_paused());
+}
+
+#[test]
+fn rewind() {
+    let controller = Controller::new();
+    controller.pause();
+    controller.rewind(Position::File(String::from("mysql-bin.000003"), 120));
+    //暂停中回退同样返回, 之后保持暂停
+    assert_eq!(controller.checkpoint(), Some(Position::File(String::from("mysql-bin.000003"), 120)));
+    assert!(controller.is_paused());
+
+    let conf = Config{ gtid: String::from("3e11fa47-71ca-11e1-9e33-c80aa9429562:1-5"), ..Default::default() };
+    let conf = Position::File(String::from("mysql-bin.000003"), 120).apply(&conf);
+    assert_eq!((conf.gtid.as_str(), conf.binlogfile.as_str(), conf.position.as_str()), ("", "mysql-bin.000003", "120"));
+    let gtid = "3e11fa47-71ca-11e1-9e33-c80aa9429562:1-3".parse::<GtidSet>().unwrap();
+    let conf = Position::Gtid(gtid).apply(&conf);
+    assert_eq!((conf.gtid.as_str(), conf.binlogfile.as_str()), ("3e11fa47-71ca-11e1-9e33-c80aa9429562:1-3", ""));
+}
+
+#[test]
+fn executed() {
+    let events = events("executed");
+    let all = gtids(&events);
+    let controller = Controller::new();
+    let conf = Config{ binlogfile: String::from("mysql-bin.000003"), position: String::from("4"), ..Default::default() };
+    controller.set_start(&conf);
+    //事务中间已执行位置不变, 事务结束后加入gtid, position为结束事件之后的位置
+    let last_begin = events.iter().rposition(|e| matches!(e.data, Traction::GtidEvent(_))).unwrap();
+    for event in &events[..=last_begin] {
+        observe(&controller, event);
+    }
+    let before = controller.executed();
+    for event in &events[last_begin + 1..] {
+        observe(&controller, event);
+        if controller.executed() != before {
+            assert!(matches!(event.data, Traction::XidEvent(_) | Traction::QueryEvent(_)));
+            assert_eq!(controller.executed().log_pos, event.header.next_position as u64);
+            break;
+        }
+    }
+    let executed = controller.executed();
+    assert_eq!(executed.log_file, "mysql-bin.000003");
+    assert_eq!(executed.gtid_set.to_string(), {
+        let mut set = GtidSet::new();
+        for gtid in &all {
+            let (sid, gno) = gtid.split_once(':').unwrap();
+            set.add(sid.parse().unwrap(), gno.parse().unwrap());
+        }
+        set.to_string()
+    });
+    assert_eq!(controller.status()["executed"]["position"], executed.log_pos);
+    assert_eq!(executed.change_source(), vec![
+        format!("CHANGE REPLICATION SOURCE TO SOURCE_LOG_FILE='mysql-bin.000003', SOURCE_LOG_POS={};", executed.log_pos),
+        format!("SET GLOBAL gtid_purged='{}';", executed.gtid_set),
+        String::from("CHANGE REPLICATION SOURCE TO SOURCE_AUTO_POSITION=1;"),
+    ]);
+
+    //按文件回退保留gtid集合, 按gtid回退使用回退的集合
+    controller.rewind(Position::File(String::from("mysql-bin.000002"), 120));
+    controller.checkpoint();
+    let rewound = controller.executed();
+    assert_eq!((rewound.log_file.as_str(), rewound.log_pos, &rewound.gtid_set), ("mysql-bin.000002", 120, &executed.gtid_set));
+    let gtid = "3e11fa47-71ca-11e1-9e33-c80aa9429562:1-3".parse::<GtidSet>().unwrap();
+    controller.rewind(Position::Gtid(gtid.clone()));
+    controller.checkpoint();
+    assert_eq!(controller.executed().gtid_set, gtid);
+    assert_eq!(controller.executed().change_source(), vec![
+        String::from("SET GLOBAL gtid_purged='3e11fa47-71ca-11e1-9e33-c80aa9429562:1-3';"),
+        String::from("CHANGE REPLICATION SOURCE TO SOURCE_AUTO_POSITION=1;"),
+    ]);
+}
+
+fn request(addr: &SocketAddr, method: &str, target: &str) -> (u16, JsonValue) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n", method, target).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let code = response[9..12].parse().unwrap();
+    let body = response.split("\r\n\r\n").nth(1).unwrap();
+    (code, serde_json::from_str(body).unwrap())
+}
+
+#[test]
+fn http() {
+    let controller = Controller::new();
+    let addr = control::serve("127.0.0.1:0", controller.clone()).unwrap();
+
+    let (code, status) = request(&addr, "GET", "/status");
+    assert_eq!((code, &status["paused"]), (200, &JsonValue::Bool(false)));
+    assert_eq!(request(&addr, "POST", "/pause").0, 200);
+    assert!(controller.is_paused());
+    assert_eq!(request(&addr, "POST", "/resume").1["paused"], false);
+    assert!(!controller.is_paused());
+
+    //gtid中的:需要编码时同样可以解析
+    let (code, status) = request(&addr, "POST", "/skip?gtid=3e11fa47-71ca-11e1-9e33-c80aa9429562%3A7");
+    assert_eq!((code, status["skip"].as_str()), (200, Some("3e11fa47-71ca-11e1-9e33-c80aa9429562:7")));
+    assert_eq!(request(&addr, "POST", "/rewind?file=mysql-bin.000002&position=4").0, 200);
+    assert_eq!(controller.checkpoint(), Some(Position::File(String::from("mysql-bin.000002"), 4)));
+    assert_eq!(request(&addr, "POST", "/rewind?gtid=3e11fa47-71ca-11e1-9e33-c80aa9429562:1-6").0, 200);
+    assert!(matches!(controller.checkpoint(), Some(Position::Gtid(_))));
+
+    assert_eq!(request(&addr, "POST", "/rewind?gtid=abc").0, 400);
+    assert_eq!(request(&addr, "POST", "/rewind?file=mysql-bin.000002&position=x").0, 400);
+    assert_eq!(request(&addr, "POST", "/skip").0, 400);
+    assert_eq!(request(&addr, "GET", "/pause").0, 405);
+    let (code, body) = request(&addr, "GET", "/unknown");
+    assert_eq!(code, 404);
+    assert!(body["error"].is_string());
+}
+
+#[test]
+fn health() {
+    let events = events("health");
+    let controller = Controller::new().with_health(Duration::from_millis(100), None);
+    let health = controller.health();
+    assert!(health.live && !health.ready);
+    controller.set_connected(true);
+    assert!(!controller.health().ready);
+
+    //事务输出并确认后就绪
+    let end = events.iter().position(|e| matches!(e.data, Traction::XidEvent(_))).unwrap();
+    for event in &events[..=end] {
+        observe(&controller, event);
+        controller.checkpointed();
+    }
+    assert_eq!(controller.health(), Health{ live: true, ready: true, reasons: vec![] });
+    controller.pause();
+    assert!(controller.health().live && !controller.health().ready);
+    controller.resume();
+    controller.set_sink_error(Some(String::from("kafka")));
+    assert!(controller.health().live && !controller.health().ready);
+    controller.set_sink_error(None);
+
+    //读取事务后输出卡住, 超时后存活检查失败
+    let begin = end + 1 + events[end + 1..].iter().position(|e| matches!(e.data, Traction::GtidEvent(_))).unwrap();
+    observe(&controller, &events[begin]);
+    controller.checkpointed();
+    assert!(controller.health().live);
+    thread::sleep(Duration::from_millis(150));
+    let health = controller.health();
+    assert!(!health.live && !health.ready && health.reasons.len() == 1);
+
+    let addr = control::serve("127.0.0.1:0", controller.clone()).unwrap();
+    let (code, body) = request(&addr, "GET", "/healthz");
+    assert_eq!((code, &body["live"]), (503, &JsonValue::Bool(false)));
+    for event in &events[begin + 1..] {
+        observe(&controller, event);
+        controller.checkpointed();
+    }
+    assert_eq!(request(&addr, "GET", "/healthz").0, 200);
+    assert_eq!(request(&addr, "GET", "/readyz").0, 200);
+}
+
+#[test]
+fn idle() {
+    let controller = Controller::new().with_health(Duration::from_secs(60), Some(Duration::from_millis(100)));
+    controller.set_connected(true);
+    assert!(controller.health().live);
+    thread::sleep(Duration::from_millis(150));
+    assert!(!controller.health().live);
+    //暂停时不检查空闲
+    controller.pause();
+    assert!(controller.health().live);
+}
+
+#[cfg(unix)]
+#[test]
+fn sd_notify() {
+    use std::os::unix::net::UnixDatagram;
+    let path = env::temp_dir().join(format!("mytest-notify-{}.sock", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let socket = UnixDatagram::bind(&path).unwrap();
+    env::set_var("NOTIFY_SOCKET", &path);
+    assert!(control::sd_notify("READY=1").unwrap());
+    env::remove_var("NOTIFY_SOCKET");
+    assert!(!control::sd_notify("READY=1").unwrap());
+    let mut buf = [0u8; 64];
+    let len = socket.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"READY=1");
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,143 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/25
+*/
+
+//split(copy模式): 不连接mysql, 满足条件的事务写入新文件, ddl等没有xid_event的事务在文件末尾或--stopposition时同样写入,
+//  previous_gtids按源文件的previous_gtids加上第一个写入的事务之前的事务重新计算
+#![cfg(all(feature = "network", feature = "test-support"))]
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::str::FromStr;
+use uuid::Uuid;
+use mytest::Config;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::readvalue;
+use mytest::replication;
+use mytest::replication::copy::BinlogWriter;
+use mytest::replication::readevent::{ColumnInfo, GtidEvent, QueryEvent, XidEvent};
+use mytest::replication::verify;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+const SID: &str = "3e11fa47-71ca-11e1-9e33-c80aa9429562";
+
+fn gtid(builder: &mut BinlogBuilder, gno: u64) {
+    builder.gtid(&GtidEvent{ gtid: Uuid::from_str(SID).unwrap(), gno_id: gno, last_committed: 0, sequence_number: gno, commit_timestamp: None });
+}
+
+fn query(builder: &mut BinlogBuilder, command: &str) {
+    builder.query(&QueryEvent{ thread_id: 10, execute_seconds: 0, database: String::from("db1"), command: command.to_string(), invoker: String::new() });
+}
+
+fn insert(builder: &mut BinlogBuilder, gno: u64) {
+    let map = TableMap{
+        database_name: String::from("db1"),
+        table_name: String::from("t1"),
+        column_count: 1,
+        column_info: vec![ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![] }],
+        metadata: None,
+    };
+    gtid(builder, gno);
+    query(builder, "BEGIN");
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, &[vec![Some(MySQLValue::SignedInteger(gno as i64))]]).unwrap();
+    builder.xid(&XidEvent{ xid: gno });
+}
+
+//previous_gtids为1-5, 之后为事务6、7, ddl 8, 事务9, 最后一个事务为ddl 10; 返回文件内容及事务7、事务9的起始位置
+fn binlog() -> (Vec<u8>, u64, u64) {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.timestamp(1573516800).previous_gtids(&GtidSet::from_str(&format!("{}:1-5", SID)).unwrap());
+    insert(&mut builder, 6);
+    let start = builder.position();
+    insert(&mut builder, 7);
+    gtid(&mut builder, 8);
+    query(&mut builder, "CREATE TABLE t2 (id int)");
+    let stop = builder.position();
+    insert(&mut builder, 9);
+    gtid(&mut builder, 10);
+    query(&mut builder, "DROP TABLE t2");
+    (builder.into_bytes(), start, stop)
+}
+
+//按过滤条件复制后返回新文件中的previous_gtids及每个事件(gtid、query为其内容, 其余为事件类型)
+fn copy(name: &str, conf: Config) -> (GtidSet, Vec<String>) {
+    let dir = env::temp_dir().join(format!("mytest-copy-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (bytes, _, _) = binlog();
+    let file = dir.join("bin.000001").to_str().unwrap().to_string();
+    let output = dir.join("copy.000001").to_str().unwrap().to_string();
+    fs::write(&file, bytes).unwrap();
+    replication::copy_file(&Config{ runtype: String::from("copy"), file, output: output.clone(), ..conf });
+
+    //position及checksum按新文件重写
+    assert!(verify::verify_file(&output, false, ChecksumClass::all()).unwrap().is_ok());
+    let copied = fs::read(&output).unwrap();
+    let fde_len = readvalue::read_u32(&copied[13..17]) as usize;
+    let previous = &copied[4 + fde_len..];
+    assert_eq!(previous[4], 35);
+    let previous_len = readvalue::read_u32(&previous[9..13]) as usize;
+    let previous = GtidSet::decode(&previous[19..previous_len - 4]).unwrap();
+    //跳过FDE及previous_gtids_event
+    let events = BinlogReader::new(Cursor::new(copied)).unwrap().skip(2).map(|e| match e.unwrap().data {
+        Traction::GtidEvent(v) => format!("gtid:{}", v.gno_id),
+        Traction::QueryEvent(v) => v.command,
+        t => format!("{:?}", t).split(|c: char| !c.is_alphanumeric()).next().unwrap().to_string(),
+    }).collect();
+    fs::remove_dir_all(&dir).unwrap();
+    (previous, events)
+}
+
+#[test]
+fn copy_to_eof() {
+    let (_, start, _) = binlog();
+    let (previous, events) = copy("eof", Config{ startposition: start.to_string(), ..Config::default() });
+    //--startposition之前的事务6计入previous_gtids
+    assert_eq!(previous.to_string(), format!("{}:1-6", SID));
+    assert_eq!(events, vec![
+        "gtid:7", "BEGIN", "TableMapEvent", "RowEvent", "XidEvent",
+        "gtid:8", "CREATE TABLE t2 (id int)",
+        "gtid:9", "BEGIN", "TableMapEvent", "RowEvent", "XidEvent",
+        //文件末尾没有xid_event的事务
+        "gtid:10", "DROP TABLE t2",
+    ]);
+}
+
+#[test]
+fn copy_to_stopposition() {
+    let (_, start, stop) = binlog();
+    let (previous, events) = copy("stop", Config{ startposition: start.to_string(), stopposition: stop.to_string(), ..Config::default() });
+    assert_eq!(previous.to_string(), format!("{}:1-6", SID));
+    assert_eq!(events, vec!["gtid:7", "BEGIN", "TableMapEvent", "RowEvent", "XidEvent", "gtid:8", "CREATE TABLE t2 (id int)"]);
+}
+
+#[test]
+fn copy_gtid() {
+    //第一个写入的事务之前被过滤掉的事务计入previous_gtids
+    let (previous, events) = copy("gtid", Config{ gtid: format!("{}:9", SID), ..Config::default() });
+    assert_eq!(previous.to_string(), format!("{}:1-8", SID));
+    assert_eq!(events, vec!["gtid:9", "BEGIN", "TableMapEvent", "RowEvent", "XidEvent"]);
+}
+
+#[test]
+fn write_traction_length() {
+    //事件长度小于包头或超出事务的范围时返回错误, 不越界读取
+    let dir = env::temp_dir().join(format!("mytest-copy-length-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (bytes, _, _) = binlog();
+    let fde_len = readvalue::read_u32(&bytes[13..17]) as usize;
+    let mut writer = BinlogWriter::new(dir.join("copy.000001").to_str().unwrap(), &bytes[4..4 + fde_len]).unwrap();
+    let event = bytes[4..4 + fde_len].to_vec();
+    assert!(writer.write_traction(&event).is_ok());
+    for length in [0u32, 18, fde_len as u32 + 1] {
+        let mut event = event.clone();
+        event[9..13].copy_from_slice(&readvalue::write_u32(length));
+        assert!(writer.write_traction(&event).is_err(), "{}", length);
+    }
+    //末尾不完整的包头
+    assert!(writer.write_traction(&[event.as_slice(), &event[..10]].concat()).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
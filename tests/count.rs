@@ -0,0 +1,73 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--count: 只统计通过过滤条件的行数, --countby按库、表、操作分组
+#![cfg(all(feature = "os", feature = "test-support"))]
+
+use std::io::Cursor;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::{ColumnInfo, QueryEvent, XidEvent};
+use mytest::stdout::outcount::Counter;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+fn table(db: &str, tbl: &str) -> TableMap {
+    TableMap{
+        database_name: db.to_string(),
+        table_name: tbl.to_string(),
+        column_count: 1,
+        column_info: vec![ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] }],
+        metadata: None,
+    }
+}
+
+fn query(db: &str, sql: &str) -> QueryEvent {
+    QueryEvent{ thread_id: 10, execute_seconds: 0, database: db.to_string(), command: sql.to_string(), invoker: String::new() }
+}
+
+fn count(by: &str) -> Counter {
+    let orders = table("shop", "orders");
+    let users = table("crm", "users");
+    let rows = |ids: &[i64]| ids.iter().map(|id| vec![Some(MySQLValue::SignedInteger(*id))]).collect::<Vec<_>>();
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.query(&query("shop", "BEGIN"));
+    builder.table_map(100, &orders).unwrap();
+    builder.rows(&BinlogEvent::DeleteEvent, 100, &orders, &rows(&[1, 2, 3])).unwrap();
+    builder.rows(&BinlogEvent::UpdateEvent, 100, &orders, &rows(&[4, 4, 5, 5])).unwrap();
+    builder.table_map(101, &users).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 101, &users, &rows(&[7])).unwrap();
+    builder.rows(&BinlogEvent::DeleteEvent, 101, &users, &rows(&[7])).unwrap();
+    builder.xid(&XidEvent{ xid: 1 });
+    builder.query(&query("crm", "TRUNCATE TABLE users"));
+
+    let mut counter = Counter::new(by).unwrap();
+    for event in BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap() {
+        let event = event.unwrap();
+        let db_tbl = event.table.as_ref().map(|t| format!("{}.{}", t.database_name, t.table_name)).unwrap_or_default();
+        counter.record(&event.data, &db_tbl);
+    }
+    counter
+}
+
+#[test]
+fn total() {
+    let counter = count("");
+    assert_eq!(counter.total(), 3 + 2 + 1 + 1 + 1);
+    assert_eq!(counter.lines(), vec!["8"]);
+}
+
+#[test]
+fn buckets() {
+    assert_eq!(count("op").lines(), vec!["delete\t4", "insert\t1", "query\t1", "update\t2"]);
+    assert_eq!(count("table,op").lines(), vec![
+        "crm\tquery\t1",
+        "crm.users\tdelete\t1",
+        "crm.users\tinsert\t1",
+        "shop.orders\tdelete\t3",
+        "shop.orders\tupdate\t2",
+    ]);
+    assert_eq!(count("db").lines(), vec!["crm\t3", "shop\t5"]);
+    assert!(Counter::new("table,rows").is_err());
+}
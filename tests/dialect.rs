@@ -0,0 +1,95 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//getsql的方言: 标识符引号、字符串及二进制、时间、布尔字面值、upsert语法
+
+#![cfg(feature = "os")]
+
+use std::collections::HashMap;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::stdout::dialect::Dialect;
+use mytest::stdout::outsql::{self, InsertMode};
+
+fn col(name: &str, col_type: &str, key: &str) -> HashMap<String, String> {
+    let mut col = HashMap::new();
+    col.insert(String::from("COLUMN_NAME"), name.to_string());
+    col.insert(String::from("COLUMN_TYPE"), col_type.to_string());
+    col.insert(String::from("COLUMN_KEY"), key.to_string());
+    col.insert(String::from("EXTRA"), String::new());
+    col
+}
+
+fn cols() -> Vec<HashMap<String, String>> {
+    vec![col("id", "int(11)", "PRI"), col("note", "varchar(32)", ""), col("paid", "tinyint(1)", ""),
+         col("raw", "varbinary(8)", ""), col("created", "datetime", ""), col("updated", "timestamp", "")]
+}
+
+fn table() -> TableMap {
+    TableMap{
+        database_name: String::from("shop"),
+        table_name: String::from("orders"),
+        column_count: 6,
+        column_info: (0..6).map(|_| ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] }).collect(),
+        metadata: None,
+    }
+}
+
+fn row() -> Vec<Option<MySQLValue>> {
+    vec![
+        Some(MySQLValue::SignedInteger(1)),
+        Some(MySQLValue::String(String::from("it's a\\b"))),
+        Some(MySQLValue::SignedInteger(1)),
+        Some(MySQLValue::Blob(vec![0x0a, 0xff])),
+        Some(MySQLValue::DateTime{ year: 2019, month: 11, day: 1, hour: 8, minute: 0, second: 0, subsecond: 0 }),
+        Some(MySQLValue::Timestamp{ unix_time: 1572566400, subsecond: 0 }),
+    ]
+}
+
+fn insert(dialect: Dialect, mode: InsertMode) -> String {
+    let format = ValueFormat{ dialect, ..ValueFormat::default() };
+    outsql::out_insert(&row(), &cols(), &table(), mode, &format)
+}
+
+#[test]
+fn insert_statements() {
+    assert_eq!(insert(Dialect::Mysql, InsertMode::Insert),
+               "INSERT INTO `shop`.`orders`(`id`,`note`,`paid`,`raw`,`created`,`updated`) VALUES(1,'it\\'s a\\\\b',1,x'0aff','2019-11-01 08:00:00',from_unixtime(1572566400.0));");
+    assert_eq!(insert(Dialect::Ansi, InsertMode::Insert),
+               "INSERT INTO \"shop\".\"orders\"(\"id\",\"note\",\"paid\",\"raw\",\"created\",\"updated\") VALUES(1,'it''s a\\b',TRUE,X'0aff',TIMESTAMP '2019-11-01 08:00:00',TIMESTAMP WITH TIME ZONE '2019-11-01 00:00:00+00:00');");
+    assert_eq!(insert(Dialect::Sqlite, InsertMode::Replace),
+               "INSERT OR REPLACE INTO \"orders\"(\"id\",\"note\",\"paid\",\"raw\",\"created\",\"updated\") VALUES(1,'it''s a\\b',1,X'0aff','2019-11-01 08:00:00','2019-11-01 00:00:00');");
+    assert_eq!(insert(Dialect::Postgresql, InsertMode::Upsert),
+               "INSERT INTO \"shop\".\"orders\"(\"id\",\"note\",\"paid\",\"raw\",\"created\",\"updated\") VALUES(1,'it''s a\\b',TRUE,'\\x0aff'::bytea,TIMESTAMP '2019-11-01 08:00:00',TIMESTAMP WITH TIME ZONE '2019-11-01 00:00:00+00:00') \
+                ON CONFLICT (\"id\") DO UPDATE SET \"id\"=EXCLUDED.\"id\", \"note\"=EXCLUDED.\"note\", \"paid\"=EXCLUDED.\"paid\", \"raw\"=EXCLUDED.\"raw\", \"created\"=EXCLUDED.\"created\", \"updated\"=EXCLUDED.\"updated\";");
+}
+
+#[test]
+fn update_delete() {
+    let format = ValueFormat{ dialect: Dialect::Postgresql, ..ValueFormat::default() };
+    let pri: HashMap<String, usize> = vec![(String::from("id"), 0)].into_iter().collect();
+    let mut after = row();
+    after[2] = Some(MySQLValue::SignedInteger(0));
+    assert!(outsql::out_update(&row(), &after, &cols(), &pri, &table(), &format).contains("\"paid\"=FALSE"));
+    assert_eq!(outsql::out_delete(&row(), &cols(), &pri, &table(), &format), "DELETE FROM \"shop\".\"orders\"  WHERE \"id\"=1;");
+}
+
+#[test]
+fn dialects() {
+    assert_eq!(Dialect::new("").unwrap(), Dialect::Mysql);
+    assert_eq!(Dialect::new("pg").unwrap(), Dialect::Postgresql);
+    assert!(Dialect::new("oracle").is_err());
+    assert_eq!(Dialect::Ansi.quote_identifier("a\"b"), "\"a\"\"b\"");
+    assert_eq!(Dialect::Postgresql.use_database("shop").unwrap(), "SET search_path TO \"shop\";");
+    assert!(Dialect::Sqlite.use_database("shop").is_none());
+    assert!(InsertMode::Upsert.check(Dialect::Ansi).is_err());
+    assert!(InsertMode::Replace.check(Dialect::Postgresql).is_err());
+    assert!(InsertMode::Replace.check(Dialect::Sqlite).is_ok());
+    //没有主键时无法指定冲突的字段
+    let nopk: Vec<HashMap<String, String>> = cols().into_iter().map(|mut c| { c.insert(String::from("COLUMN_KEY"), String::new()); c }).collect();
+    let format = ValueFormat{ dialect: Dialect::Sqlite, ..ValueFormat::default() };
+    assert!(outsql::out_insert(&row(), &nopk, &table(), InsertMode::Upsert, &format).ends_with(" ON CONFLICT DO NOTHING;"));
+}
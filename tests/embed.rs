@@ -0,0 +1,107 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//stream_rows: 逐行回调, 按conf中的表过滤, 回调返回false或controller停止时结束
+
+#![cfg(feature = "network")]
+
+use std::env;
+use std::fs;
+use mytest::Config;
+use mytest::embed::{self, RowOp, RowStream};
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn synth(name: &str) -> String {
+    let opts = SynthOptions{
+        database: String::from("embed"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 20,
+        rows: (1, 3),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 11,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-embed-{}-{}.binlog", name, std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+//按事件统计的(表, 类型)的行数
+fn expected(path: &str, table: Option<&str>) -> Vec<(String, RowOp)> {
+    let mut rows = vec![];
+    for event in BinlogReader::open(path).unwrap() {
+        let event = event.unwrap();
+        if let (Traction::RowEvent(code, values), Some(map)) = (&event.data, &event.table) {
+            if table.is_some_and(|t| t != map.table_name) {
+                continue;
+            }
+            let (op, n) = match code {
+                BinlogEvent::UpdateEvent => (RowOp::Update, values.rows().len() / 2),
+                BinlogEvent::WriteEvent => (RowOp::Insert, values.rows().len()),
+                _ => (RowOp::Delete, values.rows().len()),
+            };
+            rows.extend(std::iter::repeat_n((map.table_name.clone(), op), n));
+        }
+    }
+    rows
+}
+
+#[test]
+fn rows() {
+    let path = synth("rows");
+    let conf = Config{ file: path.clone(), ..Config::default() };
+    let mut rows = vec![];
+    embed::stream_rows(&conf, |db, table, op, row| {
+        assert_eq!(db, "embed");
+        assert_eq!(row.old.is_some(), op == RowOp::Update);
+        assert!(row.data.is_object() && !row.gtid.is_empty());
+        rows.push((table.to_string(), op));
+        true
+    }).unwrap();
+    assert_eq!(rows, expected(&path, None));
+    assert!(rows.iter().any(|(_, op)| *op == RowOp::Delete));
+
+    //只返回greptbl中的表
+    let conf = Config{ greptbl: String::from(r#"{"embed":["t2"]}"#), ..conf };
+    let mut rows = vec![];
+    embed::stream_rows(&conf, |_, table, op, _| {
+        rows.push((table.to_string(), op));
+        true
+    }).unwrap();
+    let t2 = expected(&path, Some("t2"));
+    fs::remove_file(&path).unwrap();
+    assert!(!t2.is_empty());
+    assert_eq!(rows, t2);
+}
+
+#[test]
+fn cancel() {
+    let path = synth("cancel");
+    let conf = Config{ file: path.clone(), ..Config::default() };
+    //回调返回false时立即停止
+    let mut n = 0;
+    embed::stream_rows(&conf, |_, _, _, _| {
+        n += 1;
+        n < 5
+    }).unwrap();
+    assert_eq!(n, 5);
+
+    //controller停止时返回完当前事务的行
+    let stream = RowStream::new(&conf);
+    let controller = stream.controller();
+    let mut gtids = vec![];
+    stream.run(|_, _, _, row| {
+        controller.stop();
+        gtids.push(row.gtid.clone());
+        true
+    }).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(!gtids.is_empty());
+    assert!(gtids.iter().all(|t| *t == gtids[0]));
+}
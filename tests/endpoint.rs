@@ -0,0 +1,109 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//连接地址: ipv6补全端口, unix socket、ssh隧道通过本地端口转发
+//ssh隧道使用PATH中模拟的ssh脚本, 通过bash的/dev/tcp连接-W指定的地址
+
+#![cfg(all(feature = "os", unix))]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::thread;
+use mytest::Config;
+use mytest::endpoint::{self, host_port, ssh_args};
+
+//逐行返回收到的内容
+fn echo<S: std::io::Read + Write>(stream: S) {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line).unwrap_or(0) > 0 {
+        reader.get_mut().write_all(format!("echo {}", line).as_bytes()).unwrap();
+        line.clear();
+    }
+}
+
+fn roundtrip(addr: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"ping\n").unwrap();
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).unwrap();
+    line
+}
+
+#[test]
+fn addresses() {
+    assert_eq!(host_port("10.0.0.1", 3306), "10.0.0.1:3306");
+    assert_eq!(host_port("db:3307", 3306), "db:3307");
+    assert_eq!(host_port("::1", 3306), "[::1]:3306");
+    assert_eq!(host_port("[fe80::1]", 3306), "[fe80::1]:3306");
+    assert_eq!(host_port("[fe80::1]:3307", 3306), "[fe80::1]:3307");
+
+    assert_eq!(ssh_args("root@bastion:2222", "/root/.ssh/id", "10.0.0.1:3306").unwrap(),
+               vec!["-o", "BatchMode=yes", "-p", "2222", "-i", "/root/.ssh/id", "-l", "root", "-W", "10.0.0.1:3306", "bastion"]);
+    assert_eq!(ssh_args("[::1]:22", "", "[::1]:3306").unwrap(), vec!["-o", "BatchMode=yes", "-p", "22", "-W", "[::1]:3306", "::1"]);
+    assert_eq!(ssh_args("bastion", "", "db:3306").unwrap(), vec!["-o", "BatchMode=yes", "-W", "db:3306", "bastion"]);
+    assert!(ssh_args("root@bastion:ssh", "", "db:3306").is_err());
+    assert!(ssh_args("root@", "", "db:3306").is_err());
+
+    let mut conf = Config{ socket: String::from("/tmp/a.sock"), ssh: String::from("bastion"), ..Config::default() };
+    assert!(endpoint::prepare(&mut conf).is_err());
+    let mut conf = Config{ ssh: String::from("bastion"), ..Config::default() };
+    assert!(endpoint::prepare(&mut conf).unwrap_err().contains("host"));
+}
+
+#[test]
+fn unix_socket() {
+    let dir = std::env::temp_dir().join(format!("mytest-endpoint-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("mysql.sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || echo(stream));
+        }
+    });
+
+    let mut conf = Config{ socket: path.to_str().unwrap().to_string(), ..Config::default() };
+    endpoint::prepare(&mut conf).unwrap();
+    assert!(conf.host_info.starts_with("127.0.0.1:"));
+    assert!(endpoint::describe(&conf).contains("unix socket"));
+    assert_eq!(roundtrip(&conf.host_info), "echo ping\n");
+    assert_eq!(roundtrip(&conf.host_info), "echo ping\n");
+
+    let mut conf = Config{ socket: dir.join("missing.sock").to_str().unwrap().to_string(), ..Config::default() };
+    assert!(endpoint::prepare(&mut conf).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ssh_tunnel() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || echo(stream));
+        }
+    });
+
+    //模拟的ssh: 记录参数, 连接-W的地址转发标准输入输出
+    let dir = std::env::temp_dir().join(format!("mytest-ssh-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("ssh");
+    std::fs::write(&script, format!("#!/bin/bash\necho \"$@\" > {}/args\ntarget=\"${{@: -2:1}}\"\nexec 3<>/dev/tcp/${{target%:*}}/${{target##*:}}\ncat <&3 &\ncat >&3\n",
+                                    dir.display())).unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::env::set_var("PATH", format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default()));
+
+    let mut conf = Config{ host_info: target.clone(), ssh: String::from("repl@bastion:2222"), sshkey: String::from("/tmp/key"), ..Config::default() };
+    endpoint::prepare(&mut conf).unwrap();
+    assert_ne!(conf.host_info, target);
+    assert!(endpoint::describe(&conf).contains("repl@bastion:2222"));
+    assert_eq!(roundtrip(&conf.host_info), "echo ping\n");
+    let args = std::fs::read_to_string(dir.join("args")).unwrap();
+    assert_eq!(args.trim(), format!("-o BatchMode=yes -p 2222 -i /tmp/key -l repl -W {} bastion", target));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
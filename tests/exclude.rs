@@ -0,0 +1,38 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//默认排除的系统库及改表工具的影子表, 自定义排除列表
+
+use mytest::Config;
+use mytest::exclude::Exclusion;
+
+#[test]
+fn default_exclusion() {
+    let exclusion = Exclusion::new("").unwrap();
+    for (db, tbl) in [("mysql", "user"), ("sys", "x"), ("performance_schema", "threads"), ("information_schema", "tables"),
+                      ("shop", "_orders_gho"), ("shop", "_orders_ghc"), ("shop", "_orders_del"), ("shop", "_orders_new"), ("shop", "_orders_old")] {
+        assert!(exclusion.excluded(db, tbl), "{}.{}", db, tbl);
+    }
+    for (db, tbl) in [("shop", "orders"), ("shop", "orders_new"), ("shop", "_orders"), ("mysqlx", "user")] {
+        assert!(!exclusion.excluded(db, tbl), "{}.{}", db, tbl);
+    }
+    assert!(Exclusion::from_config(&Config::default()).excluded("mysql", "user"));
+}
+
+#[test]
+fn custom_exclusion() {
+    let exclusion = Exclusion::new("log.*, shop.tmp_*, `my.db`.*_bak").unwrap();
+    assert!(exclusion.excluded("log", "access"));
+    assert!(exclusion.excluded("shop", "tmp_orders"));
+    assert!(exclusion.excluded("my.db", "orders_bak"));
+    assert!(!exclusion.excluded("shop", "orders"));
+    //自定义时不再包含默认的列表
+    assert!(!exclusion.excluded("mysql", "user"));
+
+    assert!(!Exclusion::new("none").unwrap().excluded("mysql", "user"));
+    assert!(Exclusion::new("shop").is_err());
+    let conf = Config{ excludetables: String::from("NONE"), ..Config::default() };
+    assert!(!Exclusion::from_config(&conf).excluded("sys", "x"));
+}
@@ -0,0 +1,116 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//主库切换: server_id变化时按新主库的PREVIOUS_GTIDS检查checkpoint, 只有server_uuid变化时同样提示; 控制接口记录并暂停
+
+#![cfg(feature = "os")]
+
+use std::env;
+use std::fs;
+use mytest::Config;
+use mytest::failover::{MasterWatch, SwitchReason};
+use mytest::gtid::ExecutedTracker;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn events(name: &str, seed: u64, server_id: u32) -> Vec<Event> {
+    let opts = SynthOptions{
+        database: String::from("failover"),
+        tables: 1,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 5,
+        rows: (1, 2),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed,
+        server_id,
+    };
+    let path = env::temp_dir().join(format!("mytest-failover-{}-{}.binlog", name, std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    let events = BinlogReader::open(path.to_str().unwrap()).unwrap().map(|e| e.unwrap()).collect();
+    fs::remove_file(&path).unwrap();
+    events
+}
+
+fn gtid_set(events: &[Event]) -> GtidSet {
+    let mut set = GtidSet::new();
+    for event in events {
+        if let Traction::GtidEvent(t) = &event.data {
+            set.add(t.gtid, t.gno_id);
+        }
+    }
+    set
+}
+
+#[test]
+fn server_id() {
+    let old = events("old", 1, 133);
+    let new = events("new", 2, 134);
+    //新主库在切换之前已执行了旧主库的所有事务及一个没有读取到的事务
+    let mut previous = gtid_set(&old);
+    let lost = previous.sets[0].sid;
+    previous.add(lost, 100);
+    let conf = Config{ binlogfile: String::from("mysql-bin.000001"), position: String::from("4"), ..Default::default() };
+    let mut executed = ExecutedTracker::from_config(&conf);
+    let mut watch = MasterWatch::new();
+    let mut switches = vec![];
+    for event in old.iter().chain(new.iter()) {
+        let body = match event.header.type_code {
+            BinlogEvent::PreviousGtidsLogEvent if event.header.server_id == 134 => previous.encode(),
+            _ => GtidSet::new().encode(),
+        };
+        if let Some(switch) = watch.observe(&event.header, &event.data, &body, &executed) {
+            switches.push(switch);
+        }
+        executed.observe(&event.data, event.header.next_position as u64);
+    }
+    //新主库的server_uuid不再单独提示
+    assert_eq!(switches.len(), 1);
+    let switch = &switches[0];
+    assert_eq!(switch.reason, SwitchReason::ServerId{ old: 133, new: 134 });
+    assert_eq!(switch.executed, gtid_set(&old));
+    assert_eq!(switch.log_file, "mysql-bin.000001");
+    assert_eq!(switch.missing.as_ref().unwrap().to_string(), format!("{}:100", lost));
+    assert!(!switch.is_consistent());
+    assert!(switch.message().contains(&format!("{}:100", lost)));
+
+    let controller = Controller::new();
+    controller.switched(switch, true);
+    assert!(controller.is_paused());
+    let status = controller.status();
+    assert_eq!(status["master_switch"]["reason"], "server_id");
+    assert_eq!(status["master_switch"]["new"], 134);
+    assert_eq!(status["master_switch"]["consistent"], false);
+}
+
+#[test]
+fn server_uuid() {
+    let old = events("uuid-old", 3, 133);
+    let new = events("uuid-new", 4, 133);
+    let executed = ExecutedTracker::new();
+    let mut watch = MasterWatch::new();
+    let mut switches = vec![];
+    //同一个连接中, 没有新的FORMAT_DESCRIPTION_EVENT
+    let body = GtidSet::new().encode();
+    for event in old.iter().chain(new.iter().filter(|e| matches!(e.data, Traction::GtidEvent(_)))) {
+        if let Some(switch) = watch.observe(&event.header, &event.data, &body, &executed) {
+            switches.push(switch);
+        }
+    }
+    assert_eq!(switches.len(), 1);
+    assert_eq!(switches[0].reason, SwitchReason::ServerUuid{ old: gtid_set(&old).sets[0].sid, new: gtid_set(&new).sets[0].sid });
+    assert_eq!(switches[0].missing, None);
+    assert!(switches[0].is_consistent());
+}
+
+#[test]
+fn subtract() {
+    let set = "3e11fa47-71ca-11e1-9e33-c80aa9429562:1-10:20-30,4e11fa47-71ca-11e1-9e33-c80aa9429562:1-5".parse::<GtidSet>().unwrap();
+    let other = "3e11fa47-71ca-11e1-9e33-c80aa9429562:3-4:8-22:30".parse::<GtidSet>().unwrap();
+    assert_eq!(set.subtract(&other).to_string(),
+               "3e11fa47-71ca-11e1-9e33-c80aa9429562:1-2:5-7:23-29,4e11fa47-71ca-11e1-9e33-c80aa9429562:1-5");
+    assert!(set.subtract(&set).is_empty());
+}
@@ -0,0 +1,83 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//FanOutSink: checkpoint保存到文件, 重新启动后按gtid去重, position变化时也不会重复输出
+
+#![cfg(all(feature = "os", feature = "json-sink"))]
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::path::PathBuf;
+use serde_json::Value as JsonValue;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn temp(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("mytest-fanout-{}-{}", name, std::process::id()))
+}
+
+fn open_sink(out: &PathBuf, ckpt: &PathBuf, window: usize) -> FanOutSink {
+    let file = OpenOptions::new().create(true).append(true).open(out).unwrap();
+    FanOutSink::new(FailurePolicy::BlockAll)
+        .with_checkpointed_sink("out", Box::new(JsonLinesSink::new(file, ValueFormat::default())), ckpt.to_str().unwrap()).unwrap()
+        .with_dedup_window(window)
+}
+
+#[test]
+fn dedup_after_restart() {
+    let binlog = temp("binlog");
+    let opts = SynthOptions{
+        database: String::from("fan"),
+        tables: 2,
+        columns: vec![ColumnKind::Int],
+        transactions: 20,
+        rows: (1, 2),
+        rate: 10,
+        mix: [1, 0, 0],
+        start_time: 1572998400,
+        seed: 17,
+        server_id: 133,
+    };
+    synthesize::write_file(binlog.to_str().unwrap(), &opts).unwrap();
+    let events: Vec<Event> = BinlogReader::open(binlog.to_str().unwrap()).unwrap().map(|e| e.unwrap()).collect();
+    let half = events.iter().filter(|e| matches!(e.data, Traction::XidEvent(_))).nth(9).unwrap().log_pos;
+
+    let (out, ckpt) = (temp("out.json"), temp("out.ckpt"));
+    let mut sink = open_sink(&out, &ckpt, 8);
+    for event in events.iter().filter(|e| e.log_pos <= half) {
+        sink.write_event(event).unwrap();
+    }
+    sink.flush().unwrap();
+    drop(sink);
+
+    //模拟切换主库后按gtid重新拉取: position与之前不同, 并且从已经输出过的事务开始
+    let mut value: JsonValue = serde_json::from_str(&fs::read_to_string(&ckpt).unwrap()).unwrap();
+    assert_eq!(value["recent_gtids"].as_array().unwrap().len(), 8);
+    value["log_pos"] = JsonValue::from(0);
+    fs::write(&ckpt, value.to_string()).unwrap();
+
+    let mut sink = open_sink(&out, &ckpt, 8);
+    let resume = events.iter().filter(|e| matches!(e.data, Traction::GtidEvent(_))).nth(5).unwrap().log_pos;
+    for event in events.iter().filter(|e| e.log_pos >= resume) {
+        sink.write_event(event).unwrap();
+    }
+    sink.flush().unwrap();
+    let duplicates = events.iter().filter(|e| matches!(e.data, Traction::GtidEvent(_)) && e.log_pos >= resume && e.log_pos <= half).count();
+    assert_eq!(sink.duplicates(), vec![("out", duplicates as u64)]);
+    assert_eq!(sink.checkpoints()[0].1.log_pos, events.iter().rfind(|e| matches!(e.data, Traction::XidEvent(_))).map(|e| e.log_pos + e.header.event_length as u64).unwrap());
+    drop(sink);
+
+    //与一次输出全部数据的结果相同
+    let expected = temp("expected.json");
+    let mut sink = JsonLinesSink::new(File::create(&expected).unwrap(), ValueFormat::default());
+    for event in &events {
+        sink.write_event(event).unwrap();
+    }
+    drop(sink);
+    assert_eq!(fs::read_to_string(&out).unwrap(), fs::read_to_string(&expected).unwrap());
+    for path in [binlog, out, ckpt, expected] {
+        fs::remove_file(path).unwrap();
+    }
+}
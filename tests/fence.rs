@@ -0,0 +1,48 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//getsql的DDL隔离标记: 只在DDL涉及之前输出过DML的表时输出, 等待语句按方言生成
+
+#![cfg(feature = "os")]
+
+use mytest::stdout::dialect::Dialect;
+use mytest::stdout::outfence::DdlFence;
+
+#[test]
+fn mark() {
+    let mut fence = DdlFence::new("mark", Dialect::Mysql).unwrap();
+    //没有DML时不需要隔离
+    assert!(fence.before("CREATE TABLE orders (id int)", "shop").is_empty());
+    assert!(fence.after().is_empty());
+
+    fence.touch("shop", "orders");
+    fence.touch("shop", "items");
+    assert!(fence.before("BEGIN", "shop").is_empty());
+    assert!(fence.before("ALTER TABLE log.access ADD COLUMN note varchar(8)", "shop").is_empty());
+    assert_eq!(fence.before("/* gh-ost */ ALTER TABLE `orders` ADD COLUMN note varchar(8)", "shop"), vec!["-- DDL FENCE BEGIN 1: shop.orders"]);
+    assert_eq!(fence.after(), vec!["-- DDL FENCE END 1: shop.orders"]);
+    //已隔离的表之后没有新的DML
+    assert!(fence.before("ALTER TABLE orders DROP COLUMN note", "shop").is_empty());
+
+    //无法识别涉及的表时隔离所有输出过DML的表
+    fence.touch("shop", "orders");
+    assert_eq!(fence.before("CREATE VIEW v AS SELECT 1", "shop"), vec!["-- DDL FENCE BEGIN 2: shop.items, shop.orders"]);
+    assert_eq!(fence.after(), vec!["-- DDL FENCE END 2: shop.items, shop.orders"]);
+}
+
+#[test]
+fn wait() {
+    let mut fence = DdlFence::new("wait:2.5", Dialect::Mysql).unwrap();
+    fence.touch("shop", "orders");
+    assert_eq!(fence.before("TRUNCATE orders", "shop"), vec!["-- DDL FENCE BEGIN 1: shop.orders", "DO SLEEP(2.5);"]);
+
+    let mut fence = DdlFence::new("wait:1", Dialect::Postgresql).unwrap();
+    fence.touch("shop", "orders");
+    assert_eq!(fence.before("DROP TABLE orders", "shop")[1], "SELECT pg_sleep(1);");
+
+    assert!(DdlFence::new("wait:1", Dialect::Sqlite).is_err());
+    assert!(DdlFence::new("wait:0", Dialect::Mysql).is_err());
+    assert!(DdlFence::new("sleep", Dialect::Mysql).is_err());
+}
@@ -0,0 +1,77 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//文件读取结束的原因: 最后一个事件为STOP_EVENT、ROTATE_EVENT, 在事件边界到达文件末尾, 或文件在事件头、事件内容中被截断
+#![cfg(all(feature = "network", feature = "test-support"))]
+
+mod common;
+
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use mytest::Config;
+use mytest::prelude::*;
+use mytest::replication::readbinlog::{self, FileEnd};
+use mytest::replication::readevent::RotateLog;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+use common::{begin, gtid, write_file};
+
+//一个完整的事务, 按ending添加最后的事件
+fn binlog(ending: impl FnOnce(&mut BinlogBuilder)) -> Vec<u8> {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.previous_gtids(&GtidSet::new());
+    builder.gtid(&gtid(1)).query(&begin("shop")).xid(&XidEvent{ xid: 1 });
+    ending(&mut builder);
+    builder.into_bytes()
+}
+
+//readbinlog_fromfile及BinlogReader返回的结束原因
+fn file_end(name: &str, bytes: Vec<u8>) -> (FileEnd, Option<FileEnd>) {
+    let mut reader = BinlogReader::new(Cursor::new(bytes.clone())).unwrap();
+    while let Ok(Some(_)) = reader.read_event() {}
+    let reader_end = reader.file_end().cloned();
+
+    let file = write_file("fileend", name, bytes);
+    let mut reader = BufReader::new(File::open(&file).unwrap());
+    let end = readbinlog::readbinlog_fromfile(&Config{ runtype: String::from("file"), file, ..Config::default() }, &5, &mut reader);
+    (end, reader_end)
+}
+
+#[test]
+fn complete() {
+    let (end, reader_end) = file_end("eof", binlog(|_| {}));
+    assert!(matches!(end, FileEnd::Eof), "{:?}", end);
+    assert!(matches!(reader_end, Some(FileEnd::Eof)));
+    assert_eq!(end.reason(), "eof");
+
+    let (end, reader_end) = file_end("stop", binlog(|b| { b.stop(); }));
+    assert!(matches!(end, FileEnd::Stop), "{:?}", end);
+    assert!(matches!(reader_end, Some(FileEnd::Stop)));
+
+    let rotate = RotateLog{ binlog_file: String::from("mysql-bin.000002"), position: 4, artificial: false };
+    let (end, reader_end) = file_end("rotate", binlog(|b| { b.rotate(&rotate); }));
+    assert!(matches!(&end, FileEnd::Rotate(t) if t == "mysql-bin.000002"), "{:?}", end);
+    assert!(matches!(reader_end, Some(FileEnd::Rotate(t)) if t == "mysql-bin.000002"));
+}
+
+#[test]
+fn truncated() {
+    let bytes = binlog(|_| {});
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.previous_gtids(&GtidSet::new());
+    builder.gtid(&gtid(1)).query(&begin("shop"));
+    //xid_event从该位置开始
+    let xid_pos = builder.position();
+
+    //事件头不完整
+    let (end, reader_end) = file_end("header", bytes[..xid_pos as usize + 10].to_vec());
+    assert!(matches!(end, FileEnd::Truncated(pos) if pos == xid_pos), "{:?}", end);
+    assert!(matches!(reader_end, Some(FileEnd::Truncated(pos)) if pos == xid_pos));
+    assert_eq!(end.reason(), "truncated");
+
+    //事件内容不完整
+    let (end, reader_end) = file_end("payload", bytes[..bytes.len() - 2].to_vec());
+    assert!(matches!(end, FileEnd::Truncated(pos) if pos == xid_pos), "{:?}", end);
+    assert!(matches!(reader_end, Some(FileEnd::Truncated(pos)) if pos == xid_pos));
+}
@@ -0,0 +1,86 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//识别mysql的版本及分支, 按分支及版本给出可以解析的事件类型及metadata; MariaDB按5.x的格式解析
+#![cfg(all(feature = "test-support", feature = "os"))]
+
+use std::env;
+use std::fs;
+use mytest::fingerprint::{self, Fork, ServerFingerprint};
+use mytest::replication::readevent::FormatDescriptionEvent;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+fn available(fingerprint: &ServerFingerprint, name: &str) -> Option<bool> {
+    fingerprint.capabilities().iter().find(|c| c.name == name).map(|c| c.available)
+}
+
+#[test]
+fn parse() {
+    let mysql = ServerFingerprint::parse("8.0.30", "MySQL Community Server - GPL");
+    assert_eq!((mysql.fork, mysql.version, mysql.parser_version()), (Fork::Mysql, [8, 0, 30], 8));
+    assert_eq!(ServerFingerprint::parse("5.7.30-log", "").fork, Fork::Mysql);
+    assert_eq!(ServerFingerprint::parse("5.7.30-0ubuntu0.18.04.1-log", "").fork, Fork::Mysql);
+
+    //发行号或version_comment
+    let percona = ServerFingerprint::parse("5.7.26-29-log", "");
+    assert_eq!((percona.fork, percona.version), (Fork::Percona, [5, 7, 26]));
+    assert_eq!(ServerFingerprint::parse("8.0.19", "Percona Server (GPL), Release 10").fork, Fork::Percona);
+
+    let mariadb = ServerFingerprint::parse("5.5.5-10.3.27-MariaDB-log", "mariadb.org binary distribution");
+    assert_eq!((mariadb.fork, mariadb.version, mariadb.parser_version()), (Fork::MariaDb, [10, 3, 27], 5));
+    assert_eq!(ServerFingerprint::parse("10.6.12-MariaDB", "").parser_version(), 5);
+
+    let aurora = ServerFingerprint::parse("5.7.12", "").with_aurora("2.10.2");
+    assert_eq!(aurora.fork, Fork::Aurora);
+    assert!(aurora.report()[1].contains("Aurora MySQL(2.10.2)"));
+    assert_eq!(ServerFingerprint::parse("", "").parser_version(), 5);
+}
+
+#[test]
+fn capabilities() {
+    let mysql56 = ServerFingerprint::parse("5.6.51-log", "");
+    assert_eq!(available(&mysql56, "gtid"), Some(true));
+    assert_eq!(available(&mysql56, "logical_clock"), Some(false));
+    assert_eq!(available(&mysql56, "table_metadata"), Some(false));
+    assert_eq!(available(&mysql56, "partial_json"), None);
+
+    let mysql57 = ServerFingerprint::parse("5.7.30-log", "");
+    assert_eq!(available(&mysql57, "logical_clock"), Some(true));
+    assert_eq!(available(&mysql57, "commit_timestamp"), Some(false));
+
+    let mysql80 = ServerFingerprint::parse("8.0.30", "");
+    assert!(["row_events", "gtid", "commit_timestamp", "table_metadata", "rows_query"].iter().all(|t| available(&mysql80, t) == Some(true)));
+    //服务端可以生成但不解析的事件
+    assert_eq!(available(&mysql80, "partial_json"), Some(false));
+    assert_eq!(available(&mysql80, "transaction_compression"), Some(false));
+
+    let mariadb = ServerFingerprint::parse("10.5.8-MariaDB-log", "");
+    assert!(!mariadb.row_events() && !mariadb.gtid());
+    assert_eq!(available(&mariadb, "rows_query"), Some(false));
+    assert_eq!(available(&mariadb, "table_metadata"), Some(true));
+    assert_eq!(available(&ServerFingerprint::parse("10.3.27-MariaDB", ""), "table_metadata"), Some(false));
+    assert!(mariadb.report().iter().any(|t| t.starts_with("[no ] row_events") && t.contains("backup")));
+}
+
+#[test]
+fn format_description() {
+    let fde = |version: &str| FormatDescriptionEvent{ binlog_version: 4, server_version: version.to_string(), create_timestamp: 0, header_length: 19 };
+    assert_eq!(fde("8.0.30").major_version(), 8);
+    assert_eq!(fde("5.7.30-log").major_version(), 5);
+    //MariaDB 10.x不按主版本号10解析
+    assert_eq!(fde("10.4.13-MariaDB-log").major_version(), 5);
+
+    let path = env::temp_dir().join(format!("mytest-fingerprint-{}.bin", std::process::id()));
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.timestamp(1573516800);
+    fs::write(&path, builder.into_bytes()).unwrap();
+    let result = fingerprint::from_file(path.to_str().unwrap());
+    fs::write(&path, b"not a binlog").unwrap();
+    let invalid = fingerprint::from_file(path.to_str().unwrap());
+    fs::remove_file(&path).unwrap();
+    let fingerprint = result.unwrap();
+    assert_eq!((fingerprint.fork, fingerprint.version, fingerprint.server_version.as_str()), (Fork::Mysql, [5, 7, 30], "5.7.30-log"));
+    assert!(invalid.is_err());
+}
@@ -0,0 +1,101 @@
+#!/bin/sh
+# @author: xiao cai niao
+# @datetime: 2019/11/04
+#
+# 从真实的mysqld采集golden binlog: 分别启动mysql 5.6、5.7、8.0容器, 执行固定负载后复制binlog到tests/fixtures,
+# 之后通过UPDATE_GOLDEN=1 cargo test --test golden重新生成解析结果
+#   sh tests/fixtures/capture.sh            采集全部版本
+#   sh tests/fixtures/capture.sh 5.7        只采集5.7
+# 镜像默认为mysql:<版本>, 可以通过MYTEST_MYSQL56_IMAGE、MYTEST_MYSQL57_IMAGE、MYTEST_MYSQL80_IMAGE修改
+set -eu
+
+DIR=$(cd "$(dirname "$0")" && pwd)
+PASSWORD=root
+# 固定server_uuid, 重新采集时gtid不变
+SERVER_UUID=3e11fa47-71ca-11e1-9e33-c80aa9429562
+
+# 覆盖解析支持的所有字段类型, 与tests/golden.rs一致
+WORKLOAD="
+SET time_zone = '+00:00';
+CREATE DATABASE golden;
+USE golden;
+CREATE TABLE all_types (
+    id bigint NOT NULL PRIMARY KEY,
+    c_tiny tinyint, c_short smallint, c_int24 mediumint, c_long int,
+    c_float float, c_double double, c_decimal decimal(10,2),
+    c_year year, c_date date, c_time time, c_datetime datetime(3), c_timestamp timestamp(6) NULL,
+    c_varchar varchar(255), c_char char(40), c_blob blob,
+    c_enum enum('a','b','c'), c_set set('x','y','z'), c_json json
+) DEFAULT CHARSET=utf8mb4;
+INSERT INTO all_types VALUES (1, -64, 16383, -4194304, 1073741823, 3.5, -1234.125, -1234.01,
+    2019, '2019-11-04', '838:59:58', '2019-11-04 23:59:59.123', '2019-11-04 15:59:59.123456',
+    'varchar 1', 'char 中文', X'000102FEFF', 'b', 'x,z',
+    '{\"id\": 1, \"tags\": [\"a\", \"b\"], \"ok\": true, \"none\": null, \"ratio\": 0.25, \"big\": 4294967296}');
+INSERT INTO all_types (id) VALUES (2);
+UPDATE all_types SET c_tiny = -128, c_time = '-12:34:56', c_varchar = 'updated' WHERE id = 1;
+UPDATE all_types SET c_long = 3, c_varchar = 'varchar 2' WHERE id = 2;
+DELETE FROM all_types WHERE id = 1;
+"
+
+capture() {
+    version=$1
+    name=mysql$(echo "$version" | tr -d .)
+    case $version in
+        # 5.6不支持json类型, 按text建表
+        5.6) image=${MYTEST_MYSQL56_IMAGE:-mysql:5.6}; json=text ;;
+        5.7) image=${MYTEST_MYSQL57_IMAGE:-mysql:5.7}; json=json ;;
+        8.0) image=${MYTEST_MYSQL80_IMAGE:-mysql:8.0}; json=json ;;
+        *) echo "不支持的版本: $version"; exit 1 ;;
+    esac
+
+    id=$(docker run -d --rm -e MYSQL_ROOT_PASSWORD=$PASSWORD "$image" \
+        --server-id=1001 --log-bin=mysql-bin --binlog-format=ROW --binlog-checksum=CRC32 \
+        --gtid-mode=ON --enforce-gtid-consistency=ON --log-slave-updates=ON \
+        --binlog-rows-query-log-events=ON)
+    trap 'docker rm -f "$id" >/dev/null 2>&1 || true' EXIT
+
+    sql() {
+        docker exec -i "$id" mysql -h127.0.0.1 -uroot -p$PASSWORD -N -B 2>/dev/null
+    }
+
+    # 镜像初始化时会先启动一个不监听网络的临时实例, 通过tcp连接成功说明初始化已完成
+    i=0
+    until echo "select 1" | sql >/dev/null; do
+        i=$((i + 1))
+        if [ $i -gt 90 ]; then
+            docker logs "$id"
+            echo "$image在180秒内没有启动完成"
+            exit 1
+        fi
+        sleep 2
+    done
+
+    # 固定server_uuid后重启, 之后从新的binlog文件开始采集
+    docker exec "$id" sh -c "printf '[auto]\nserver-uuid=$SERVER_UUID\n' > /var/lib/mysql/auto.cnf"
+    docker restart "$id" >/dev/null
+    i=0
+    until echo "select 1" | sql >/dev/null; do
+        i=$((i + 1))
+        [ $i -gt 90 ] && { echo "$image重启后没有启动完成"; exit 1; }
+        sleep 2
+    done
+
+    echo "RESET MASTER; FLUSH BINARY LOGS;" | sql
+    file=$(echo "SHOW MASTER STATUS" | sql | cut -f1)
+    echo "$WORKLOAD" | sed "s/c_json json/c_json $json/" | sql
+    # 以ROTATE_EVENT结束
+    echo "FLUSH BINARY LOGS;" | sql
+
+    docker cp "$id:/var/lib/mysql/$file" "$DIR/$name.binlog"
+    docker rm -f "$id" >/dev/null
+    trap - EXIT
+    echo "$image: $file -> tests/fixtures/$name.binlog"
+}
+
+if [ $# -eq 0 ]; then
+    set -- 5.6 5.7 8.0
+fi
+for version in "$@"; do
+    capture "$version"
+done
+echo "重新生成解析结果: UPDATE_GOLDEN=1 cargo test --test golden"
@@ -0,0 +1,95 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/12
+*/
+
+//flashback --chunkrows: 回滚事务按行数切分后, 每个部分都是完整的事务
+//  需要开启test-support特性: cargo test --features test-support
+#![cfg(all(feature = "os", feature = "test-support"))]
+
+use std::io::Cursor;
+use std::str::FromStr;
+use uuid::Uuid;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::{ColumnInfo, GtidEvent, QueryEvent, XidEvent};
+use mytest::replication::rollback::split_traction;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+//文件头(magic + FDE)及一个事务, 每个行事件的行数由rows_per_event指定, 只有最后一个行事件带有STMT_END_F
+fn transaction(rows_per_event: &[usize]) -> (Vec<u8>, Vec<u8>) {
+    let map = TableMap{
+        database_name: String::from("db1"),
+        table_name: String::from("t1"),
+        column_count: 1,
+        column_info: vec![ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![] }],
+        metadata: None,
+    };
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57).checksum(false);
+    builder.timestamp(1573516800);
+    let start = builder.position() as usize;
+    builder.gtid(&GtidEvent{ gtid: Uuid::from_str("3e11fa47-71ca-11e1-9e33-c80aa9429562").unwrap(), gno_id: 7, last_committed: 0, sequence_number: 1, commit_timestamp: None });
+    builder.query(&QueryEvent{ thread_id: 10, execute_seconds: 0, database: String::from("db1"), command: String::from("BEGIN"), invoker: String::new() });
+    builder.table_map(100, &map).unwrap();
+    let mut rows_events = vec![];
+    let mut id = 0;
+    for count in rows_per_event {
+        rows_events.push(builder.position() as usize);
+        let rows: Vec<Vec<Option<MySQLValue>>> = (0..*count).map(|_| { id += 1; vec![Some(MySQLValue::SignedInteger(id))] }).collect();
+        builder.rows(&BinlogEvent::DeleteEvent, 100, &map, &rows).unwrap();
+    }
+    builder.xid(&XidEvent{ xid: 9 });
+    let mut bytes = builder.into_bytes();
+    for pos in &rows_events[..rows_events.len() - 1] {
+        bytes[pos + 25] = 0;
+    }
+    let trx = bytes.split_off(start);
+    (bytes, trx)
+}
+
+fn read_chunk(header: &[u8], events: &[Vec<u8>]) -> Vec<Event> {
+    let mut bytes = header.to_vec();
+    for event in events {
+        bytes.extend(event);
+    }
+    BinlogReader::new(Cursor::new(bytes)).unwrap().map(|e| e.unwrap()).skip(1).collect()
+}
+
+#[test]
+fn split_by_rows() {
+    let (header, trx) = transaction(&[2, 2, 2, 3]);
+    let chunks = split_traction(&trx, &[2, 2, 2, 3], 4);
+    assert_eq!(chunks.iter().map(|c| c.rows).collect::<Vec<_>>(), vec![4, 5]);
+
+    let first = read_chunk(&header, &chunks[0].events);
+    let types: Vec<_> = first.iter().map(|e| e.header.type_code.clone()).collect();
+    assert_eq!(format!("{:?}", types), "[GtidEvent, QueryEvent, TableMapEvent, DeleteEvent, DeleteEvent, XidEvent]");
+    //被切断的最后一个行事件设置了STMT_END_F
+    assert_eq!(chunks[0].events[4][25] & 1, 1);
+    assert_eq!(chunks[0].events[3][25] & 1, 0);
+
+    //之后的部分不包含gtid, 重新写入table_map
+    let second = read_chunk(&header, &chunks[1].events);
+    let types: Vec<_> = second.iter().map(|e| e.header.type_code.clone()).collect();
+    assert_eq!(format!("{:?}", types), "[QueryEvent, TableMapEvent, DeleteEvent, DeleteEvent, XidEvent]");
+    let counts: Vec<_> = second.iter().filter_map(|e| match &e.data {
+        Traction::RowEvent(_, rows) => Some(rows.rows().len()),
+        _ => None
+    }).collect();
+    assert_eq!(counts, vec![2, 3]);
+    assert_eq!(chunks[1].events[3][25] & 1, 1);
+}
+
+#[test]
+fn no_split() {
+    let (_, trx) = transaction(&[2, 3]);
+    for chunk_rows in [0, 5, 100] {
+        let chunks = split_traction(&trx, &[2, 3], chunk_rows);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].rows, 5);
+        assert_eq!(chunks[0].events.concat(), trx);
+    }
+    //单个行事件不会被拆分
+    let (_, trx) = transaction(&[10]);
+    assert_eq!(split_traction(&trx, &[10], 3).len(), 1);
+}
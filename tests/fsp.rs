@@ -0,0 +1,106 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//DATETIME、TIME、TIMESTAMP的小数部分: binlog中按存储位数保存为整数, 输出时按字段的fsp补齐前导0
+#![cfg(all(feature = "json-sink", feature = "test-support"))]
+
+use std::io::Cursor;
+use serde_json::{json, Value as JsonValue};
+use mytest::Config;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::stdout::format::{self, BinaryEncoding};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+//fsp分别为2、4、6、3
+fn table() -> TableMap {
+    TableMap{
+        database_name: String::from("test"),
+        table_name: String::from("t"),
+        column_count: 4,
+        column_info: vec![
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeDatetime2, column_meta: vec![2] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeTime2, column_meta: vec![4] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeTimestamp2, column_meta: vec![6] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeDatetime2, column_meta: vec![3] },
+        ],
+        metadata: None,
+    }
+}
+
+//.05、.0050、.000005、.012
+fn row() -> Vec<Option<MySQLValue>> {
+    vec![
+        Some(MySQLValue::DateTime{ year: 2019, month: 11, day: 1, hour: 8, minute: 0, second: 0, subsecond: 5 }),
+        Some(MySQLValue::Time{ negative: false, hours: 8, minutes: 0, seconds: 0, subseconds: 50 }),
+        Some(MySQLValue::Timestamp{ unix_time: 1572566400, subsecond: 5 }),
+        Some(MySQLValue::DateTime{ year: 2019, month: 11, day: 1, hour: 8, minute: 0, second: 0, subsecond: 120 }),
+    ]
+}
+
+fn format() -> ValueFormat {
+    ValueFormat::new(&Config{ timezone: String::from("UTC"), ..Config::default() }).unwrap()
+}
+
+#[test]
+fn column_fsp() {
+    assert_eq!(format::fsp("datetime(2)"), 2);
+    assert_eq!(format::fsp("TIME(4)"), 4);
+    assert_eq!(format::fsp("timestamp(6)"), 6);
+    assert_eq!(format::fsp("datetime"), 0);
+    assert_eq!(format::fsp("decimal(10,2)"), 0);
+    assert_eq!(format::fsp("datetime(9)"), 0);
+}
+
+#[test]
+fn padded() {
+    let format = format();
+    let row = row();
+    assert_eq!(format.json_value(&row[0], "datetime(2)", BinaryEncoding::Hex), json!("2019-11-01 08:00:00.05"));
+    assert_eq!(format.json_value(&row[1], "time(4)", BinaryEncoding::Hex), json!("08:00:00.0050"));
+    assert_eq!(format.json_value(&row[2], "timestamp(6)", BinaryEncoding::Hex), json!("2019-11-01 00:00:00.000005"));
+    //fsp为3时按4位存储
+    assert_eq!(format.text_value(&row[3], "datetime(3)", BinaryEncoding::Hex), Some(String::from("2019-11-01 08:00:00.012")));
+    assert_eq!(format.datetime(2019, 11, 1, 8, 0, 0, 0, 6), "2019-11-01 08:00:00");
+    //不知道fsp时按6位
+    assert_eq!(format.time(false, 8, 0, 0, 5, 0), "08:00:00.000005");
+}
+
+#[test]
+fn sink() {
+    let map = table();
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, &[row()]).unwrap();
+    let mut buf = vec![];
+    {
+        //没有表结构时按TABLE_MAP中的fsp输出
+        let mut sink = JsonLinesSink::new(&mut buf, format());
+        for event in BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap() {
+            sink.write_event(&event.unwrap()).unwrap();
+        }
+    }
+    let record: JsonValue = serde_json::from_str(String::from_utf8(buf).unwrap().lines().next().unwrap()).unwrap();
+    assert_eq!(record["data"]["@1"], json!("2019-11-01 08:00:00.05"));
+    assert_eq!(record["data"]["@2"], json!("08:00:00.0050"));
+    assert_eq!(record["data"]["@3"], json!("2019-11-01 00:00:00.000005"));
+    assert_eq!(record["data"]["@4"], json!("2019-11-01 08:00:00.012"));
+}
+
+#[test]
+fn epoch() {
+    //epoch模式下为unix_time + 小数部分 / 10^fsp
+    let format = ValueFormat::default();
+    let row = row();
+    assert_eq!(format::epoch(1572566400, 5, 2), "1572566400.05");
+    assert_eq!(format::epoch(1572566400, 120, 3), "1572566400.012");
+    assert_eq!(format::epoch(1572566400, 0, 6), "1572566400");
+    assert_eq!(format.json_value(&row[2], "timestamp(6)", BinaryEncoding::Hex).to_string(), "1572566400.000005");
+    assert_eq!(format.text_value(&row[2], "timestamp(6)", BinaryEncoding::Hex), Some(String::from("1572566400.000005")));
+    assert_eq!(format.json_value(&Some(MySQLValue::Timestamp{ unix_time: 1572566400, subsecond: 5 }), "timestamp(2)", BinaryEncoding::Hex), json!(1572566400.05));
+    assert_eq!(format.timestamp_sql(1572566400, 5, 2), "from_unixtime(1572566400.05)");
+    assert_eq!(format.timestamp_sql(1572566400, 0, 0), "from_unixtime(1572566400.0)");
+}
@@ -0,0 +1,84 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//生成列及分区表: insert、update不指定生成列, 没有主键时WHERE不使用生成列, 分区键(包括生成列)保留在WHERE中
+
+#![cfg(feature = "os")]
+
+use std::collections::HashMap;
+use mytest::meta;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::stdout::outsql::{self, InsertMode};
+
+fn col(name: &str, col_type: &str, key: &str, extra: &str) -> HashMap<String, String> {
+    let mut col = HashMap::new();
+    col.insert(String::from("COLUMN_NAME"), name.to_string());
+    col.insert(String::from("COLUMN_TYPE"), col_type.to_string());
+    col.insert(String::from("COLUMN_KEY"), key.to_string());
+    col.insert(String::from("EXTRA"), extra.to_string());
+    col
+}
+
+fn table(cols: &[HashMap<String, String>]) -> TableMap {
+    TableMap{
+        database_name: String::from("shop"),
+        table_name: String::from("orders"),
+        column_count: cols.len() as u8,
+        column_info: cols.iter().map(|_| ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] }).collect(),
+        metadata: None,
+    }
+}
+
+fn row(values: &[i64]) -> Vec<Option<MySQLValue>> {
+    values.iter().map(|t| Some(MySQLValue::SignedInteger(*t))).collect()
+}
+
+#[test]
+fn generated_columns() {
+    //PARTITION BY RANGE (`yr`), yr为stored生成列, total为virtual生成列, id为主键
+    let mut cols = vec![
+        col("id", "int(11)", "PRI", "auto_increment"),
+        col("price", "int(11)", "", ""),
+        col("qty", "int(11)", "", "DEFAULT_GENERATED"),
+        col("total", "int(11)", "", "VIRTUAL GENERATED"),
+        col("yr", "int(11)", "PRI", "STORED GENERATED"),
+    ];
+    meta::mark_partition_key(&mut cols, "`yr`");
+    assert!(meta::is_generated(&cols[3]) && meta::is_generated(&cols[4]) && !meta::is_generated(&cols[2]));
+    assert!(meta::is_partition_key(&cols[4]) && !meta::is_partition_key(&cols[0]));
+    let map = table(&cols);
+    let format = ValueFormat::default();
+    let pri: HashMap<String, usize> = vec![(String::from("id"), 0), (String::from("yr"), 4)].into_iter().collect();
+
+    let after = row(&[1, 10, 3, 30, 2019]);
+    assert_eq!(outsql::out_insert(&after, &cols, &map, InsertMode::Insert, &format),
+               "INSERT INTO `shop`.`orders`(`id`,`price`,`qty`) VALUES(1,10,3);");
+    assert_eq!(outsql::out_insert(&after, &cols, &map, InsertMode::Upsert, &format),
+               "INSERT INTO `shop`.`orders`(`id`,`price`,`qty`) VALUES(1,10,3) ON DUPLICATE KEY UPDATE `id`=VALUES(`id`), `price`=VALUES(`price`), `qty`=VALUES(`qty`);");
+    let before = row(&[1, 9, 3, 27, 2019]);
+    assert_eq!(outsql::out_update(&before, &after, &cols, &pri, &map, &format),
+               "UPDATE `shop`.`orders` SET `id`=1, `price`=10, `qty`=3 WHERE `id`=1 AND `yr`=2019;");
+    assert_eq!(outsql::out_delete(&after, &cols, &pri, &map, &format),
+               "DELETE FROM `shop`.`orders`  WHERE `id`=1 AND `yr`=2019;");
+
+    //没有主键: 不使用virtual生成列, stored生成列为分区键时保留
+    let nopk: Vec<HashMap<String, String>> = cols.iter().cloned().map(|mut c| { c.insert(String::from("COLUMN_KEY"), String::new()); c }).collect();
+    assert_eq!(outsql::out_delete(&after, &nopk, &HashMap::new(), &map, &format),
+               "DELETE FROM `shop`.`orders`  WHERE `id`=1 AND `price`=10 AND `qty`=3 AND `yr`=2019;");
+}
+
+#[test]
+fn partition_expression() {
+    let mut cols = vec![col("id", "int", "PRI", ""), col("created", "datetime", "PRI", ""), col("region", "varchar(8)", "", "")];
+    meta::mark_partition_key(&mut cols, "year(`created`)");
+    meta::mark_partition_key(&mut cols, "");
+    assert_eq!(cols.iter().map(meta::is_partition_key).collect::<Vec<_>>(), vec![false, true, false]);
+
+    let mut cols = vec![col("id", "int", "PRI", ""), col("Region", "varchar(8)", "", "")];
+    meta::mark_partition_key(&mut cols, "`id`,region");
+    assert_eq!(cols.iter().map(meta::is_partition_key).collect::<Vec<_>>(), vec![true, true]);
+}
@@ -0,0 +1,80 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/04
+*/
+
+//golden测试: tests/fixtures中保存了5.6、5.7、8.0的binlog文件及其解析结果, 解析结果发生变化时测试失败
+//  binlog文件通过tests/fixtures/capture.sh从对应版本的mysqld采集, 不使用testing::BinlogBuilder生成, 避免与编码互相验证
+//  当前提交的文件仍为BinlogBuilder生成, 需要在可以运行docker的环境执行capture.sh替换
+//  解析有意修改或重新采集后重新生成解析结果: UPDATE_GOLDEN=1 cargo test --test golden
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+use mytest::prelude::*;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+}
+
+fn update_golden() -> bool {
+    env::var("UPDATE_GOLDEN").is_ok()
+}
+
+//json字段按文本输出, 不依赖serde_json::Value的Debug格式
+fn value(value: &Option<MySQLValue>) -> String {
+    match value {
+        Some(MySQLValue::Json(json)) => format!("Some(Json({}))", serde_json::to_string(json).unwrap()),
+        _ => format!("{:?}", value)
+    }
+}
+
+fn data(data: &Traction) -> String {
+    match data {
+        Traction::RowEvent(event_type, rows) => {
+            let rows: Vec<String> = rows.rows().iter()
+                .map(|row| format!("[{}]", row.iter().map(value).collect::<Vec<String>>().join(", ")))
+                .collect();
+            format!("RowEvent({:?}, [{}])", event_type, rows.join(", "))
+        }
+        _ => format!("{:?}", data)
+    }
+}
+
+//每个事件一行: position、事件类型、next_position、解析结果
+fn render(bytes: Vec<u8>) -> String {
+    let mut out = String::new();
+    let mut reader = BinlogReader::new(Cursor::new(bytes)).unwrap();
+    for event in &mut reader {
+        let event = event.unwrap();
+        out.push_str(&format!("{}\t{:?}\t{}\t{}\n", event.log_pos, event.header.event_type, event.header.next_position, data(&event.data)));
+    }
+    out.push_str(&format!("end\t{:?}\n", reader.file_end()));
+    out
+}
+
+fn check_output(name: &str) {
+    let bytes = fs::read(fixture(&format!("{}.binlog", name))).unwrap();
+    if update_golden() {
+        fs::write(fixture(&format!("{}.txt", name)), render(bytes)).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(fixture(&format!("{}.txt", name))).unwrap();
+    assert_eq!(render(bytes), expected, "{}的解析结果与tests/fixtures/{}.txt不一致", name, name);
+}
+
+#[test]
+fn mysql56_output() {
+    check_output("mysql56");
+}
+
+#[test]
+fn mysql57_output() {
+    check_output("mysql57");
+}
+
+#[test]
+fn mysql80_output() {
+    check_output("mysql80");
+}
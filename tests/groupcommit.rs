@@ -0,0 +1,89 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//analyze --groupcommit: 连续的last_committed相同的事务为一个提交组, 8.0按微秒的提交时间计算组之间的间隔
+#![cfg(all(feature = "test-support", feature = "os"))]
+
+use std::io::Cursor;
+use std::str::FromStr;
+use uuid::Uuid;
+use mytest::Config;
+use mytest::prelude::*;
+use mytest::stdout::outgroupcommit::{CommitGroup, GroupCommitReport};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+const START: u64 = 1573516800;
+
+fn gtid(gno: u64, last_committed: u64, commit_timestamp: Option<u64>) -> GtidEvent {
+    GtidEvent{ gtid: Uuid::from_str("3e11fa47-71ca-11e1-9e33-c80aa9429562").unwrap(), gno_id: gno, last_committed, sequence_number: gno, commit_timestamp }
+}
+
+fn report(interval: &str) -> GroupCommitReport {
+    GroupCommitReport::new(&Config{ interval: interval.to_string(), ..Config::default() }).unwrap()
+}
+
+//(last_committed, 提交时间距START的微秒数)
+fn read(version: ServerVersion, trxs: &[(u64, u64)], interval: &str) -> GroupCommitReport {
+    let mut builder = BinlogBuilder::new(version);
+    builder.timestamp(START as u32);
+    for (idx, (last_committed, offset)) in trxs.iter().enumerate() {
+        builder.timestamp(((START * 1_000_000 + offset) / 1_000_000) as u32);
+        builder.gtid(&gtid(idx as u64 + 1, *last_committed, Some(START * 1_000_000 + offset)));
+        builder.xid(&XidEvent{ xid: idx as u64 });
+    }
+    let mut report = report(interval);
+    for event in BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap() {
+        let event = event.unwrap();
+        report.record(&event.data, event.header.timestamp);
+    }
+    report
+}
+
+#[test]
+fn groups() {
+    let trxs = [(0, 100), (0, 150), (0, 200), (3, 1_200), (3, 1_250), (5, 2_400), (6, 12_000_000)];
+    let report = read(ServerVersion::Mysql80, &trxs, "10");
+    assert!(report.precise());
+    let time = |offset: u64| START * 1_000_000 + offset;
+    assert_eq!(report.groups(), vec![
+        CommitGroup{ last_committed: 0, sequence_number: 3, size: 3, commit_time: time(200) },
+        CommitGroup{ last_committed: 3, sequence_number: 5, size: 2, commit_time: time(1_250) },
+        CommitGroup{ last_committed: 5, sequence_number: 6, size: 1, commit_time: time(2_400) },
+        CommitGroup{ last_committed: 6, sequence_number: 7, size: 1, commit_time: time(12_000_000) },
+    ]);
+    assert_eq!(report.intervals(), vec![1_050, 1_150, 11_997_600]);
+
+    let buckets = report.buckets();
+    assert_eq!(buckets.len(), 2);
+    assert_eq!((buckets[0].start as u64, buckets[0].groups, buckets[0].transactions, buckets[0].max_size, buckets[0].avg_interval), (START, 3, 6, 3, Some(1_100)));
+    assert_eq!((buckets[1].start as u64, buckets[1].groups, buckets[1].transactions, buckets[1].avg_interval), (START + 10, 1, 1, Some(11_997_600)));
+}
+
+#[test]
+fn without_commit_timestamp() {
+    //5.7按秒级的事件时间
+    let report = read(ServerVersion::Mysql57, &[(0, 0), (0, 500_000), (2, 2_000_000)], "");
+    assert!(!report.precise());
+    let groups = report.groups();
+    assert_eq!(groups.iter().map(|g| g.size).collect::<Vec<_>>(), vec![2, 1]);
+    assert_eq!(report.intervals(), vec![2_000_000]);
+
+    //5.6没有逻辑时钟
+    let report = read(ServerVersion::Mysql56, &[(0, 0), (0, 1)], "");
+    assert!(report.groups().is_empty());
+    assert!(GroupCommitReport::new(&Config{ interval: String::from("0"), ..Config::default() }).is_err());
+}
+
+#[test]
+fn new_file() {
+    //binlog切换后sequence_number重新开始, last_committed相同也是新的组
+    let mut report = report("");
+    report.record(&Traction::GtidEvent(gtid(1, 0, Some(1))), 0);
+    report.record(&Traction::GtidEvent(gtid(2, 0, Some(2))), 0);
+    report.record(&Traction::GtidEvent(gtid(1, 0, Some(3))), 0);
+    report.record(&Traction::RotateLogEvent(RotateLog{ binlog_file: String::from("bin.000002"), position: 4, artificial: false }), 0);
+    report.record(&Traction::GtidEvent(gtid(2, 0, Some(4))), 0);
+    assert_eq!(report.groups().iter().map(|g| g.size).collect::<Vec<_>>(), vec![2, 1, 1]);
+}
@@ -0,0 +1,81 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//组复制的事件: transaction_context_event(36)、view_change_event(37)按固定的post_header及body解析, 之后的事件继续解析
+#![cfg(feature = "test-support")]
+
+use std::io::Cursor;
+use mytest::prelude::*;
+use mytest::replication::readevent::{TransactionContextEvent, ViewChangeEvent};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+const SID: &str = "3e11fa47-71ca-11e1-9e33-c80aa9429562";
+
+//server_uuid、thread_id 42、指定了gtid, write_set两项, read_set为空
+fn transaction_context() -> Vec<u8> {
+    let mut body = vec![SID.len() as u8];
+    body.extend(&42u32.to_le_bytes());
+    body.push(1);
+    body.extend(&0u32.to_le_bytes());
+    body.extend(&2u32.to_le_bytes());
+    body.extend(&0u32.to_le_bytes());
+    body.extend(SID.as_bytes());
+    for item in ["hash1", "hash2"] {
+        body.extend(&(item.len() as u16).to_le_bytes());
+        body.extend(item.as_bytes());
+    }
+    body
+}
+
+//view_id以0填充到40字节, cert_info两项
+fn view_change() -> Vec<u8> {
+    let mut body = b"15735168001234567:3".to_vec();
+    body.resize(40, 0);
+    body.extend(&7u64.to_le_bytes());
+    body.extend(&2u32.to_le_bytes());
+    for (key, value) in [("group_gtid_executed", "3e11fa47-71ca-11e1-9e33-c80aa9429562:1-5"), ("group_gtid_extracted", "")] {
+        body.extend(&(key.len() as u16).to_le_bytes());
+        body.extend(key.as_bytes());
+        body.extend(&(value.len() as u32).to_le_bytes());
+        body.extend(value.as_bytes());
+    }
+    body
+}
+
+#[test]
+fn group_replication_events() {
+    for checksum in [false, true] {
+        let mut builder = BinlogBuilder::new(ServerVersion::Mysql57).checksum(checksum);
+        builder.event(37, &view_change());
+        builder.event(36, &transaction_context());
+        builder.xid(&XidEvent{ xid: 9 });
+        let events = BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap().collect::<std::io::Result<Vec<Event>>>().unwrap();
+
+        let view: Vec<&ViewChangeEvent> = events.iter().filter_map(|e| match &e.data {
+            Traction::ViewChangeEvent(t) => Some(t),
+            _ => None
+        }).collect();
+        assert_eq!(view.len(), 1);
+        assert_eq!(view[0].view_id, "15735168001234567:3");
+        assert_eq!(view[0].seq_number, 7);
+        assert_eq!(view[0].cert_info, vec![
+            (String::from("group_gtid_executed"), format!("{}:1-5", SID)),
+            (String::from("group_gtid_extracted"), String::new()),
+        ]);
+
+        let context: Vec<&TransactionContextEvent> = events.iter().filter_map(|e| match &e.data {
+            Traction::TransactionContextEvent(t) => Some(t),
+            _ => None
+        }).collect();
+        assert_eq!(context.len(), 1);
+        assert_eq!(context[0].server_uuid, SID);
+        assert_eq!(context[0].thread_id, 42);
+        assert!(context[0].gtid_specified);
+        assert_eq!((context[0].write_set_len, context[0].read_set_len), (2, 0));
+
+        //按event_length跳过未读取的write_set后继续解析
+        assert!(matches!(events.last().unwrap().data, Traction::XidEvent(XidEvent{ xid: 9 })));
+    }
+}
@@ -0,0 +1,48 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//gtid集合的解析及编码: 文本格式与mysql一致, 区间合并, 无效的gno及区间(0、结束小于开始、溢出)返回错误
+
+use std::str::FromStr;
+use mytest::gtid::GtidSet;
+
+const SID: &str = "3e11fa47-71ca-11e1-9e33-c80aa9429562";
+
+#[test]
+fn parse() {
+    let set = GtidSet::from_str(&format!("{}:1-3:5:4, {}:7-9", SID, SID)).unwrap();
+    assert_eq!(set.to_string(), format!("{}:1-5:7-9", SID));
+    assert_eq!(GtidSet::decode(&set.encode()).unwrap(), set);
+    let max = format!("{}:{}", SID, u64::MAX - 1);
+    assert_eq!(GtidSet::from_str(&max).unwrap().to_string(), max);
+    assert!(GtidSet::from_str("").unwrap().is_empty());
+}
+
+#[test]
+fn invalid() {
+    for interval in ["5-3", "0", "0-3", "abc", "1-2-3", &u64::MAX.to_string(), &format!("1-{}", u64::MAX)] {
+        let err = GtidSet::from_str(&format!("{}:{}", SID, interval)).unwrap_err();
+        assert!(err.contains("无效的gtid"), "{}: {}", interval, err);
+    }
+    assert!(GtidSet::from_str("not-a-uuid:1").is_err());
+}
+
+#[test]
+fn decode_invalid() {
+    //n_sid、sid、n_intervals之后为左闭右开的区间
+    let encode = |start: u64, stop: u64| {
+        let mut pack = 1u64.to_le_bytes().to_vec();
+        pack.extend(uuid::Uuid::parse_str(SID).unwrap().as_bytes());
+        pack.extend(1u64.to_le_bytes());
+        pack.extend(start.to_le_bytes());
+        pack.extend(stop.to_le_bytes());
+        pack
+    };
+    assert_eq!(GtidSet::decode(&encode(1, 4)).unwrap().to_string(), format!("{}:1-3", SID));
+    for (start, stop) in [(5, 4), (3, 3), (0, 2)] {
+        assert!(GtidSet::decode(&encode(start, stop)).is_err(), "[{}, {})", start, stop);
+    }
+    assert!(GtidSet::decode(&encode(1, 4)[..40]).is_err());
+}
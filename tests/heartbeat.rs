@@ -0,0 +1,134 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//心跳: 按间隔在事务之间写入当前位置、已完成事务的gtid, 经过FanOutSink、QueuedSink、Batcher等组合后同样写入每个目标
+
+#![cfg(all(feature = "os", feature = "json-sink"))]
+
+use std::env;
+use std::fs::{self, File};
+use std::time::Duration;
+use serde_json::Value as JsonValue;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn synth(name: &str) -> String {
+    let opts = SynthOptions{
+        database: String::from("beat"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 30,
+        rows: (1, 3),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 23,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-heartbeat-{}-{}.binlog", name, std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn records(text: &str) -> Vec<JsonValue> {
+    text.lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+#[test]
+fn between_transactions() {
+    let path = synth("every");
+    let mut buf = vec![];
+    let mut sink = HeartbeatSink::new(JsonLinesSink::new(&mut buf, ValueFormat::default()), Duration::from_secs(0));
+    for event in BinlogReader::open(&path).unwrap() {
+        sink.write_event(&event.unwrap()).unwrap();
+    }
+    drop(sink);
+    fs::remove_file(&path).unwrap();
+
+    let records = records(&String::from_utf8(buf).unwrap());
+    let heartbeats: Vec<&JsonValue> = records.iter().filter(|r| r["type"] == "heartbeat").collect();
+    assert!(heartbeats.len() >= 30);
+    //每个心跳的gtid为之前最后一行数据所在的事务, 不会出现在事务中间; 开头的DDL事务没有行数据
+    let mut last_gtid = JsonValue::from("");
+    let mut last_pos = 0;
+    let mut rows_since = 0;
+    for record in &records {
+        if record["type"] == "heartbeat" {
+            if last_gtid != "" {
+                assert_eq!(record["gtid"], last_gtid);
+            }
+            assert!(record["position"].as_u64().unwrap() >= last_pos);
+            assert!(record["event_timestamp"].as_u64().unwrap() >= 1572998400);
+            assert!(record["timestamp"].as_u64().unwrap() > 1572998400000);
+            last_pos = record["position"].as_u64().unwrap();
+            rows_since = 0;
+        } else {
+            if rows_since > 0 {
+                assert_eq!(record["gtid"], last_gtid);
+            }
+            last_gtid = record["gtid"].clone();
+            rows_since += 1;
+        }
+    }
+}
+
+#[test]
+fn interval() {
+    let path = synth("interval");
+    let mut buf = vec![];
+    let mut sink = HeartbeatSink::new(JsonLinesSink::new(&mut buf, ValueFormat::default()), Duration::from_secs(3600));
+    for event in BinlogReader::open(&path).unwrap() {
+        sink.write_event(&event.unwrap()).unwrap();
+    }
+    assert!(!sink.tick().unwrap());
+    sink.heartbeat().unwrap();
+    drop(sink);
+    let len = fs::metadata(&path).unwrap().len();
+    fs::remove_file(&path).unwrap();
+
+    let records = records(&String::from_utf8(buf).unwrap());
+    let heartbeats: Vec<&JsonValue> = records.iter().filter(|r| r["type"] == "heartbeat").collect();
+    assert_eq!(heartbeats.len(), 1);
+    //最后一个事件之后的位置即文件长度
+    assert_eq!(heartbeats[0]["position"], len);
+    assert_eq!(heartbeats[0]["gtid"], records[records.len() - 2]["gtid"]);
+}
+
+#[test]
+fn forwarded() {
+    let path = synth("forward");
+    let dir = env::temp_dir();
+    let files: Vec<String> = (0..3).map(|i| dir.join(format!("mytest-heartbeat-{}-{}.json", i, std::process::id())).to_str().unwrap().to_string()).collect();
+    let json = |idx: usize| JsonLinesSink::new(File::create(&files[idx]).unwrap(), ValueFormat::default());
+    let fanout = FanOutSink::new(FailurePolicy::BlockAll)
+        .with_sink("queued", Box::new(QueuedSink::new(json(0), QueueConfig::new(8))))
+        .with_sink("batched", Box::new(Batcher::new(json(1), BatchConfig{ max_rows: 1000, ..Default::default() })))
+        .with_sink("routed", Box::new(RoutedSink::new(&files[2], move |path| Ok(Box::new(JsonLinesSink::new(File::create(path)?, ValueFormat::default())) as Box<dyn Sink>)).unwrap()));
+    let mut sink = HeartbeatSink::new(fanout, Duration::from_secs(0));
+    for event in BinlogReader::open(&path).unwrap() {
+        sink.write_event(&event.unwrap()).unwrap();
+    }
+    sink.flush().unwrap();
+    drop(sink);
+    fs::remove_file(&path).unwrap();
+
+    let outputs: Vec<Vec<JsonValue>> = files.iter().map(|f| records(&fs::read_to_string(f).unwrap())).collect();
+    for f in &files {
+        fs::remove_file(f).unwrap();
+    }
+    //批量写入时心跳之前缓存的数据先写出, 各目标内容相同
+    assert!(outputs[0].iter().any(|r| r["type"] == "heartbeat"));
+    let strip = |records: &Vec<JsonValue>| -> Vec<JsonValue> {
+        records.iter().map(|r| {
+            let mut r = r.clone();
+            r.as_object_mut().unwrap().remove("timestamp");
+            r
+        }).collect()
+    };
+    assert_eq!(strip(&outputs[0]), strip(&outputs[1]));
+    //RoutedSink在第一行数据之前没有打开的目标, 之后的心跳相同
+    let routed = strip(&outputs[2]);
+    assert_eq!(&strip(&outputs[0])[strip(&outputs[0]).len() - routed.len()..], &routed[..]);
+}
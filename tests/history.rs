@@ -0,0 +1,88 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//history: 按主键还原一行数据的变更历史, update修改主键后继续跟踪新的值, 没有metadata时按@N指定字段
+#![cfg(all(feature = "test-support", feature = "os"))]
+
+mod common;
+
+use serde_json::json;
+use mytest::Config;
+use mytest::history::{self, RowKey};
+use mytest::prelude::*;
+use mytest::stdout::format::ValueFormat;
+use common::{order, orders, START};
+
+//每个事务一个行事件, 间隔10秒
+fn write(name: &str, metadata: bool) -> String {
+    let (bytes, _) = common::transactions(&orders(metadata), &[
+        (BinlogEvent::WriteEvent, vec![order(1, Some("new")), order(2, Some("new"))]),
+        (BinlogEvent::UpdateEvent, vec![order(2, Some("new")), order(2, Some("paid")), order(1, Some("new")), order(1, Some("paid"))]),
+        (BinlogEvent::UpdateEvent, vec![order(1, Some("paid")), order(10, Some("paid"))]),
+        (BinlogEvent::WriteEvent, vec![order(1, Some("reused"))]),
+        (BinlogEvent::DeleteEvent, vec![order(10, Some("paid"))]),
+    ]);
+    common::write_file("history", name, bytes)
+}
+
+fn changes(path: &str, key: &str, range: (u32, u32)) -> std::io::Result<Vec<history::Change>> {
+    let key = RowKey::parse("shop.orders", key).unwrap();
+    let format = ValueFormat::new(&Config::default()).unwrap();
+    history::history_files(&[path.to_string()], &key, &format, range, |_| None)
+}
+
+#[test]
+fn parse_key() {
+    let key = RowKey::parse("shop.orders", "id=42, region='eu,west'").unwrap();
+    assert_eq!(key.values, vec![(Some(String::from("id")), String::from("42")), (Some(String::from("region")), String::from("'eu,west'"))]);
+    assert_eq!(RowKey::parse("`shop`.`orders`", "42,eu").unwrap().values, vec![(None, String::from("42")), (None, String::from("eu"))]);
+    assert!(RowKey::parse("orders", "42").is_err());
+    assert!(RowKey::parse("shop.orders", " ").is_err());
+    assert!(RowKey::parse("shop.orders", "42,region=eu").is_err());
+}
+
+#[test]
+fn follow_primary_key() {
+    let path = write("metadata.000001", true);
+    let found = changes(&path, "1", (0, 0)).unwrap();
+    let kinds: Vec<&str> = found.iter().map(|c| c.kind).collect();
+    assert_eq!(kinds, vec!["insert", "update", "update", "delete"]);
+    let gtids: Vec<String> = found.iter().map(|c| c.gtid.clone().unwrap()).collect();
+    assert_eq!(gtids.iter().map(|t| t.rsplit(':').next().unwrap()).collect::<Vec<_>>(), vec!["1", "2", "3", "5"]);
+    assert_eq!(found.iter().map(|c| c.timestamp).collect::<Vec<_>>(), vec![START, START + 10, START + 20, START + 40]);
+    assert_eq!(found[0].file, "metadata.000001");
+
+    //同一个事件中的其他行不输出
+    assert_eq!(found[1].before, Some(vec![(String::from("id"), json!(1)), (String::from("status"), json!("new"))]));
+    assert_eq!(found[1].changed(), vec!["status"]);
+    assert_eq!(found[2].changed(), vec!["id"]);
+    assert_eq!(found[3].before.as_ref().unwrap()[0], (String::from("id"), json!(10)));
+    assert_eq!(found[3].after, None);
+
+    let value = found[1].to_json();
+    assert_eq!(value["type"], json!("update"));
+    assert_eq!(value["after"]["status"], json!("paid"));
+    assert_eq!(value["changed"], json!(["status"]));
+
+    //其他行及时间范围
+    assert_eq!(changes(&path, "id=2", (0, 0)).unwrap().len(), 2);
+    let found = changes(&path, "1", (START + 5, START + 25)).unwrap();
+    assert_eq!(found.iter().map(|c| c.kind).collect::<Vec<_>>(), vec!["update", "update"]);
+    assert!(changes(&path, "3", (0, 0)).unwrap().is_empty());
+}
+
+#[test]
+fn without_metadata() {
+    let path = write("plain.000001", false);
+    //没有表结构时不能确定主键字段
+    let err = changes(&path, "1", (0, 0)).err().unwrap();
+    assert!(err.to_string().contains("@1=42"), "{}", err);
+
+    let found = changes(&path, "@1=1", (0, 0)).unwrap();
+    assert_eq!(found.iter().map(|c| c.kind).collect::<Vec<_>>(), vec!["insert", "update", "update", "delete"]);
+    assert_eq!(found[0].after.as_ref().unwrap()[1].0, "@2");
+    //按文本比较时带引号
+    assert_eq!(changes(&path, "@1=1,@2='reused'", (0, 0)).unwrap().len(), 1);
+}
@@ -0,0 +1,69 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//中文等非ascii库名、表名: TABLE_MAP中正确读取, sql输出正确转义, 过滤条件中带引号的名称与binlog中的名称一致
+#![cfg(all(feature = "os", feature = "test-support"))]
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use serde_json::json;
+use mytest::ident;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::grep;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::stdout::outddl;
+use mytest::stdout::outsql::{self, InsertMode};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+#[test]
+fn names() {
+    assert_eq!(ident::decode("订单库".as_bytes()), "订单库");
+    //不是合法utf8时按latin1转换, 不丢弃
+    assert_eq!(ident::decode(&[b'a', 0xe9]), "aé");
+
+    assert_eq!(ident::split_qualified("`my.db`.`订单`"), vec!["my.db", "订单"]);
+    assert_eq!(ident::split_qualified(" \"a\"\"b\" . `c``d` "), vec!["a\"b", "c`d"]);
+    assert_eq!(ident::normalize("订单库.明细"), "订单库.明细");
+    assert_eq!(ident::normalize("`订单库`.*"), "订单库.*");
+    assert_eq!(ident::normalize("`订单库`"), "订单库");
+
+    let tbl_info = grep::parse_greptbl(r#"{"`订单库`": ["`明细`", "汇总"], "`日志`": "all"}"#);
+    assert_eq!(tbl_info, json!({"订单库": ["明细", "汇总"], "日志": "all"}));
+
+    assert_eq!(outddl::ddl_tables("DROP TABLE `订单库`.`明细`, `临时`", "默认"),
+               vec![(String::from("订单库.明细"), true), (String::from("默认.临时"), true)]);
+}
+
+#[test]
+fn table_map() {
+    let map = TableMap{
+        database_name: String::from("订单库"),
+        table_name: String::from("明`细"),
+        column_count: 1,
+        column_info: vec![ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] }],
+        metadata: None,
+    };
+    let row = vec![Some(MySQLValue::SignedInteger(1))];
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, std::slice::from_ref(&row)).unwrap();
+    let mut table = None;
+    for event in BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap() {
+        let event = event.unwrap();
+        if let Some(t) = event.table {
+            table = Some(t);
+        }
+    }
+    let table = table.unwrap();
+    assert_eq!((table.database_name.as_str(), table.table_name.as_str()), ("订单库", "明`细"));
+
+    let mut col = HashMap::new();
+    col.insert(String::from("COLUMN_NAME"), String::from("编号"));
+    col.insert(String::from("COLUMN_TYPE"), String::from("int(11)"));
+    col.insert(String::from("COLUMN_KEY"), String::from("PRI"));
+    assert_eq!(outsql::out_insert(&row, &vec![col], &table, InsertMode::Insert, &ValueFormat::default()),
+               "INSERT INTO `订单库`.`明``细`(`编号`) VALUES(1);");
+}
@@ -0,0 +1,64 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/12
+*/
+
+//inspect的查询命令
+
+#![cfg(feature = "cli")]
+
+use std::path::PathBuf;
+use mytest::inspect::Session;
+use mytest::prelude::*;
+
+fn session() -> Session {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("mysql57.binlog");
+    Session::open(path.to_str().unwrap(), false, ChecksumClass::all()).unwrap()
+}
+
+fn count(session: &Session, filter: &str) -> String {
+    session.execute(&format!("count where {}", filter)).unwrap().join("\n")
+}
+
+#[test]
+fn where_conditions() {
+    let session = session();
+    assert_eq!(session.execute("count").unwrap(), vec!["4"]);
+    assert_eq!(count(&session, "table='all_types'"), "3");
+    assert_eq!(count(&session, "table = 'golden.all_types'"), "3");
+    assert_eq!(count(&session, "table='orders'"), "0");
+    assert_eq!(count(&session, "table!='orders'"), "4");
+    assert_eq!(count(&session, "db=golden and rows>=2"), "2");
+    assert_eq!(count(&session, "type=ddl"), "1");
+    assert_eq!(count(&session, "ts > '2019-11-04 16:00:01'"), "2");
+    assert_eq!(count(&session, "ts >= 1572883201 AND ts < '2019-11-04 16:00:03'"), "2");
+    assert_eq!(count(&session, "position=900"), "1");
+    assert_eq!(count(&session, "gtid='3e11fa47-71ca-11e1-9e33-c80aa9429562:4'"), "1");
+
+    let lines = session.execute("show transactions where table='all_types' limit 2;").unwrap();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[2], "(2/3 transactions)");
+    assert!(lines[0].contains("golden.all_types"));
+}
+
+#[test]
+fn show_trx() {
+    let session = session();
+    let lines = session.execute("show trx 900").unwrap();
+    assert!(lines[0].trim_start().starts_with("882"));
+    assert!(lines.iter().any(|l| l.contains("UpdateRowsEvent")));
+    assert_eq!(lines.iter().filter(|l| l.contains("after:  (")).count(), 2);
+    assert!(session.execute("show trx 100").is_err());
+}
+
+#[test]
+fn errors() {
+    let session = session();
+    assert!(session.execute("show transactions where foo=1").is_err());
+    assert!(session.execute("show transactions where rows>'x'").is_err());
+    assert!(session.execute("show transactions where table>'a'").is_err());
+    assert!(session.execute("show transactions where table='a").is_err());
+    assert!(session.execute("show transactions limit").is_err());
+    assert!(session.execute("drop table t").is_err());
+    assert!(session.execute("").unwrap().is_empty());
+}
@@ -0,0 +1,286 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/05
+*/
+
+//端到端集成测试: 通过docker启动mysql 5.7、8.0, 执行覆盖各字段类型、大事务、DDL的负载, 分别校验
+//  1. 复制出的binlog文件通过BinlogReader解析的结果(事件完整、字段值与写入的值一致、行数一致)
+//  2. stream子命令通过主从协议拉取并以json输出的行数与文件解析的结果一致
+//需要本机可以运行docker: cargo test --features integration --test integration
+//  镜像默认为mysql:5.7、mysql:8.0, 可以通过MYTEST_MYSQL57_IMAGE、MYTEST_MYSQL80_IMAGE修改
+//  bit、geometry类型解析尚不支持, 负载中不包含
+#![cfg(feature = "integration")]
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use bigdecimal::BigDecimal;
+use serde_json::{json, Value as JsonValue};
+use mytest::prelude::*;
+
+const PASSWORD: &str = "root";
+const BIG_ROWS: usize = 20000;
+
+fn docker(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("docker").args(args).output()
+        .map_err(|e| format!("执行docker失败: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("docker {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr)));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+//测试结束(包括失败)时删除容器
+struct MysqlContainer{
+    id: String,
+    port: u16,
+}
+
+impl MysqlContainer{
+    fn start(image: &str) -> MysqlContainer {
+        let id = docker(&["run", "-d", "--rm", "-e", &format!("MYSQL_ROOT_PASSWORD={}", PASSWORD), "-p", "127.0.0.1::3306", image,
+                          "--server-id=1", "--log-bin=mysql-bin", "--binlog-format=ROW", "--binlog-checksum=CRC32",
+                          "--gtid-mode=ON", "--enforce-gtid-consistency=ON", "--binlog-rows-query-log-events=ON",
+                          "--max-allowed-packet=64M"])
+            .unwrap_or_else(|e| panic!("启动{}失败, 集成测试需要本机可以运行docker: {}", image, e));
+        let mut container = MysqlContainer{ id, port: 0 };
+        let port = docker(&["port", &container.id, "3306/tcp"]).unwrap();
+        let port = port.lines().next().and_then(|l| l.rsplit(':').next()).and_then(|p| p.parse().ok())
+            .unwrap_or_else(|| panic!("无法获取映射的端口: {}", port));
+        container.port = port;
+        container.wait_ready();
+        container
+    }
+
+    //镜像初始化时会先启动一个不监听网络的临时实例, 通过tcp连接成功说明初始化已完成
+    fn wait_ready(&self) {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(180) {
+            if self.sql("select 1").is_ok() {
+                return;
+            }
+            thread::sleep(Duration::from_secs(2));
+        }
+        panic!("mysql在180秒内没有启动完成: {}", docker(&["logs", &self.id]).unwrap_or_default());
+    }
+
+    fn sql(&self, sql: &str) -> Result<String, String> {
+        let mut child = Command::new("docker")
+            .args(["exec", "-i", &self.id, "mysql", "-h127.0.0.1", "-uroot", &format!("-p{}", PASSWORD), "-N", "-B"])
+            .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+            .spawn().map_err(|e| e.to_string())?;
+        child.stdin.take().unwrap().write_all(sql.as_bytes()).map_err(|e| e.to_string())?;
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).to_string());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn copy_out(&self, file: &str, dir: &PathBuf) -> PathBuf {
+        let target = dir.join(file);
+        docker(&["cp", &format!("{}:/var/lib/mysql/{}", self.id, file), target.to_str().unwrap()]).unwrap();
+        target
+    }
+}
+
+impl Drop for MysqlContainer {
+    fn drop(&mut self) {
+        let _ = docker(&["rm", "-f", &self.id]);
+    }
+}
+
+fn workload() -> String {
+    let mut sql = String::from("
+SET time_zone = '+00:00';
+CREATE DATABASE it;
+USE it;
+CREATE TABLE all_types (
+    id bigint NOT NULL PRIMARY KEY,
+    c_tiny tinyint, c_small smallint, c_medium mediumint, c_int int, c_big bigint,
+    c_float float, c_double double, c_decimal decimal(20,6),
+    c_year year, c_date date, c_time time(3), c_datetime datetime(6), c_timestamp timestamp(6) NULL,
+    c_char char(10), c_varchar varchar(255), c_binary binary(4), c_varbinary varbinary(64),
+    c_text text, c_blob blob, c_longtext longtext,
+    c_enum enum('a','b','c'), c_set set('x','y','z'), c_json json
+) DEFAULT CHARSET=utf8mb4;
+INSERT INTO all_types VALUES (1, -128, -32768, -8388608, -2147483648, -9223372036854775808,
+    1.5, -2.25, -12345.678901, 2019, '2019-11-04', '12:34:56.789', '2019-11-04 23:59:59.123456', '2019-11-04 00:00:01.5',
+    'char', 'varchar 中文', X'DEADBEEF', X'00FF', 'text', X'0102', REPEAT('x', 70000),
+    'b', 'x,z', '{\"a\": [1, true, null], \"b\": {\"c\": \"d\"}, \"n\": -1.25}');
+INSERT INTO all_types (id) VALUES (2);
+INSERT INTO all_types (id, c_int, c_varchar) VALUES (3, 3, 'three');
+UPDATE all_types SET c_int = c_int + 1, c_varchar = 'updated' WHERE id IN (1, 3);
+DELETE FROM all_types WHERE id = 2;
+CREATE TABLE ddl_t (id int PRIMARY KEY);
+ALTER TABLE ddl_t ADD COLUMN c1 varchar(10);
+RENAME TABLE ddl_t TO ddl_t2;
+TRUNCATE TABLE ddl_t2;
+DROP TABLE ddl_t2;
+CREATE TABLE big (id int PRIMARY KEY, pad varchar(200));
+BEGIN;
+");
+    //一个事务内的大量数据, 会被拆分为多个行事件
+    for chunk in 0..BIG_ROWS / 1000 {
+        let values: Vec<String> = (0..1000).map(|i| format!("({}, REPEAT('p', 200))", chunk * 1000 + i + 1)).collect();
+        sql.push_str(&format!("INSERT INTO big VALUES {};\n", values.join(",")));
+    }
+    sql.push_str("COMMIT;\nUPDATE big SET pad = REPEAT('u', 200);\n");
+    sql
+}
+
+//行事件按(表, 类型)统计的行数, update的前后镜像算一行
+fn count_rows(counts: &mut BTreeMap<(String, String), usize>, table: &str, row_type: &str, rows: usize) {
+    *counts.entry((table.to_string(), row_type.to_string())).or_insert(0) += rows;
+}
+
+fn expected_first_row() -> Vec<MySQLValue> {
+    vec![
+        MySQLValue::SignedInteger(1),
+        MySQLValue::SignedInteger(-128),
+        MySQLValue::SignedInteger(-32768),
+        MySQLValue::SignedInteger(-8388608),
+        MySQLValue::SignedInteger(-2147483648),
+        MySQLValue::SignedInteger(i64::MIN),
+        MySQLValue::Float(1.5),
+        MySQLValue::Double(-2.25),
+        MySQLValue::Decimal("-12345.678901".parse::<BigDecimal>().unwrap()),
+        MySQLValue::Year(2019),
+        MySQLValue::Date{ year: 2019, month: 11, day: 4 },
+        MySQLValue::Time{ negative: false, hours: 12, minutes: 34, seconds: 56, subseconds: 7890 },
+        MySQLValue::DateTime{ year: 2019, month: 11, day: 4, hour: 23, minute: 59, second: 59, subsecond: 123456 },
+        MySQLValue::Timestamp{ unix_time: 1572825601, subsecond: 500000 },
+        MySQLValue::Blob(b"char".to_vec()),
+        MySQLValue::Blob("varchar 中文".as_bytes().to_vec()),
+        MySQLValue::Blob(vec![0xde, 0xad, 0xbe, 0xef]),
+        MySQLValue::Blob(vec![0x00, 0xff]),
+        MySQLValue::Blob(b"text".to_vec()),
+        MySQLValue::Blob(vec![0x01, 0x02]),
+        MySQLValue::Blob(vec![b'x'; 70000]),
+        MySQLValue::SignedInteger(2),
+        MySQLValue::SignedInteger(5),
+        MySQLValue::Json(json!({"a": [1, true, null], "b": {"c": "d"}, "n": -1.25})),
+    ]
+}
+
+//解析复制出的binlog文件, 校验字段值及DDL, 返回各表的行数
+fn verify_file(path: &PathBuf) -> BTreeMap<(String, String), usize> {
+    let reader = BinlogReader::open(path).unwrap();
+    let mut counts = BTreeMap::new();
+    let mut queries = vec![];
+    let mut first_insert = None;
+    for event in reader {
+        let event = event.unwrap_or_else(|e| panic!("{}解析失败: {}", path.display(), e));
+        match (&event.data, &event.table) {
+            (Traction::QueryEvent(q), _) => queries.push(q.command.clone()),
+            (Traction::RowEvent(code, rows), Some(map)) => {
+                let (row_type, count) = match code {
+                    BinlogEvent::WriteEvent => ("insert", rows.rows().len()),
+                    BinlogEvent::UpdateEvent => ("update", rows.rows().len() / 2),
+                    _ => ("delete", rows.rows().len()),
+                };
+                if map.table_name == "all_types" && row_type == "insert" && first_insert.is_none() {
+                    first_insert = Some(rows.rows().to_vec());
+                }
+                count_rows(&mut counts, &map.table_name, row_type, count);
+            }
+            _ => {}
+        }
+    }
+
+    let first_insert = first_insert.expect("没有读取到all_types的insert");
+    let values: Vec<MySQLValue> = first_insert[0].iter().map(|v| v.clone().unwrap_or(MySQLValue::Null)).collect();
+    for (idx, (got, expected)) in values.iter().zip(expected_first_row().iter()).enumerate() {
+        assert_eq!(got, expected, "all_types第{}列", idx + 1);
+    }
+    assert_eq!(values.len(), expected_first_row().len());
+    for ddl in ["CREATE TABLE ddl_t", "ALTER TABLE ddl_t", "RENAME TABLE ddl_t", "TRUNCATE TABLE ddl_t2", "DROP TABLE `ddl_t2`"].iter() {
+        assert!(queries.iter().any(|q| q.contains(ddl)), "没有读取到DDL: {}", ddl);
+    }
+    counts
+}
+
+//通过stream拉取binlog并以json输出, 统计到与文件解析相同的行数或超时后结束
+fn stream_rows(container: &MysqlContainer, binlog_file: &str, expected: usize) -> BTreeMap<(String, String), usize> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mytest"))
+        .args(["stream", "-uroot", "-h", &format!("127.0.0.1:{}", container.port),
+               "--binlogfile", binlog_file, "--position", "4", "--serverid", "9999", "--outformat", "json",
+               "--init-command", "SET NAMES utf8mb4", "--init-command", "SET @mytest_init = 1"])
+        .env("MYSQL_PWD", PASSWORD)
+        .stdout(Stdio::piped()).stderr(Stdio::inherit())
+        .spawn().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            match line {
+                Ok(l) => if sender.send(l).is_err() { break },
+                Err(_) => break,
+            }
+        }
+    });
+
+    let mut counts = BTreeMap::new();
+    let mut total = 0;
+    let deadline = Instant::now() + Duration::from_secs(120);
+    while total < expected && Instant::now() < deadline {
+        match receiver.recv_timeout(Duration::from_secs(1)) {
+            Ok(line) => {
+                if let Ok(record) = serde_json::from_str::<JsonValue>(&line) {
+                    if let (Some(table), Some(row_type)) = (record["table"].as_str(), record["type"].as_str()) {
+                        count_rows(&mut counts, table, row_type, 1);
+                        total += 1;
+                    }
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    counts
+}
+
+fn end_to_end(image: &str) {
+    let container = MysqlContainer::start(image);
+    //新的binlog文件只包含测试负载, 执行完成后切换文件, 保证复制出的文件已经写完
+    container.sql("FLUSH BINARY LOGS").unwrap();
+    let status = container.sql("SHOW MASTER STATUS").unwrap();
+    let binlog_file = status.split_whitespace().next().unwrap().to_string();
+    container.sql(&workload()).unwrap_or_else(|e| panic!("执行负载失败: {}", e));
+    container.sql("FLUSH BINARY LOGS").unwrap();
+
+    let dir = env::temp_dir().join(format!("mytest-it-{}", &container.id[..12]));
+    fs::create_dir_all(&dir).unwrap();
+    let path = container.copy_out(&binlog_file, &dir);
+    let file_counts = verify_file(&path);
+
+    let mut expected = BTreeMap::new();
+    count_rows(&mut expected, "all_types", "insert", 3);
+    count_rows(&mut expected, "all_types", "update", 2);
+    count_rows(&mut expected, "all_types", "delete", 1);
+    count_rows(&mut expected, "big", "insert", BIG_ROWS);
+    count_rows(&mut expected, "big", "update", BIG_ROWS);
+    assert_eq!(file_counts, expected, "{}: 文件解析的行数", image);
+
+    let stream_counts = stream_rows(&container, &binlog_file, expected.values().sum());
+    assert_eq!(stream_counts, expected, "{}: stream输出的行数", image);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn mysql57_end_to_end() {
+    end_to_end(&env::var("MYTEST_MYSQL57_IMAGE").unwrap_or_else(|_| String::from("mysql:5.7")));
+}
+
+#[test]
+fn mysql80_end_to_end() {
+    end_to_end(&env::var("MYTEST_MYSQL80_IMAGE").unwrap_or_else(|_| String::from("mysql:8.0")));
+}
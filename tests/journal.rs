@@ -0,0 +1,146 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//重放日志: 记录的读写、崩溃时不完整的最后一行、按管道找出最后的checkpoint之后写入的事务, 管道中使用journal
+
+#![cfg(feature = "os")]
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use mytest::journal::{self, Journal, Record, RecordKind};
+
+const SID: &str = "3e11fa47-71ca-11e1-9e33-c80aa9429562";
+
+fn dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("mytest-journal-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn gtid(gno: u64) -> String {
+    format!("{}:{}", SID, gno)
+}
+
+#[test]
+fn records() {
+    let record = Record{ kind: RecordKind::Acked, timestamp: 1572566400123, pipeline: String::from("orders"), gtid: gtid(7), log_file: String::from("mysql-bin.000003"), position: 1234 };
+    let line = record.to_line();
+    assert_eq!(line, format!("A\t1572566400123\torders\t{}\tmysql-bin.000003\t1234\n", gtid(7)));
+    assert_eq!(Record::parse(line.trim_end()).unwrap(), record);
+    assert!(Record::parse("X\t1\torders\t\tmysql-bin.000003\t4").unwrap_err().contains("X"));
+    assert!(Record::parse("E\t1\torders").is_err());
+}
+
+#[test]
+fn diagnose() {
+    let dir = dir("diagnose");
+    let path = dir.join("journal.log");
+    let journal = Journal::open(&path).unwrap();
+    for gno in 1..=2 {
+        journal.append(RecordKind::Emitted, "orders", &gtid(gno), "mysql-bin.000003", 100 * gno).unwrap();
+    }
+    journal.append(RecordKind::Acked, "orders", &gtid(2), "mysql-bin.000003", 200).unwrap();
+    journal.append(RecordKind::Checkpointed, "orders", &gtid(2), "mysql-bin.000003", 200).unwrap();
+    //3、4已确认输出, checkpoint之前崩溃; 5还没有flush
+    journal.append(RecordKind::Emitted, "orders", &gtid(3), "mysql-bin.000003", 300).unwrap();
+    journal.append(RecordKind::Emitted, "audit", "", "mysql-bin.000007", 4000).unwrap();
+    journal.append(RecordKind::Emitted, "orders", &gtid(4), "mysql-bin.000003", 400).unwrap();
+    journal.append(RecordKind::Acked, "orders", &gtid(4), "mysql-bin.000003", 400).unwrap();
+    journal.append(RecordKind::Emitted, "orders", &gtid(5), "mysql-bin.000003", 500).unwrap();
+    drop(journal);
+    //崩溃时写了一半的记录
+    OpenOptions::new().append(true).open(&path).unwrap().write_all(b"E\t15725664").unwrap();
+
+    let records = journal::read(&path).unwrap();
+    assert_eq!(records.len(), 9);
+    let result = journal::diagnose(&records);
+    assert_eq!(result.iter().map(|d| d.pipeline.as_str()).collect::<Vec<_>>(), vec!["orders", "audit"]);
+
+    let orders = &result[0];
+    assert_eq!(orders.checkpoint.as_ref().map(|t| t.gtid.clone()), Some(gtid(2)));
+    assert_eq!(orders.acked().iter().map(|t| t.gtid.clone()).collect::<Vec<_>>(), vec![gtid(3), gtid(4)]);
+    assert_eq!(orders.unacked().iter().map(|t| t.gtid.clone()).collect::<Vec<_>>(), vec![gtid(5)]);
+    assert!(orders.unsettled.iter().all(|t| t.acked.map_or(true, |a| a >= t.record.timestamp)));
+
+    let audit = &result[1];
+    assert!(audit.checkpoint.is_none());
+    assert_eq!(audit.unacked().len(), 1);
+    assert_eq!(audit.unacked()[0].position, 4000);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+//读取relay log的管道: 每个事务写入后记录, 停止时flush并保存checkpoint, 之后没有未确认的事务
+#[cfg(all(feature = "network", feature = "json-sink", feature = "test-support"))]
+#[test]
+fn pipeline() {
+    use std::str::FromStr;
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+    use mytest::meta::ColumnTypeDict;
+    use mytest::pipeline::{self, Runtime};
+    use mytest::prelude::*;
+    use mytest::replication::readevent::ColumnInfo;
+    use mytest::testing::{BinlogBuilder, ServerVersion};
+
+    let dir = dir("pipeline");
+    let orders = TableMap{
+        database_name: String::from("shop"),
+        table_name: String::from("orders"),
+        column_count: 1,
+        column_info: vec![ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] }],
+        metadata: None,
+    };
+    //从库的relay log: PREVIOUS_GTIDS、主库的ROTATE之后为3个事务
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57).server_id(2);
+    builder.timestamp(1572566400).flags(0x40).previous_gtids(&GtidSet::new());
+    builder.timestamp(0).flags(0x20).rotate(&RotateLog{ binlog_file: String::from("mysql-bin.000003"), position: 4, artificial: true });
+    for gno in 1..=3u64 {
+        builder.flags(0).timestamp(1572566400 + gno as u32);
+        builder.gtid(&GtidEvent{ gtid: Uuid::from_str(SID).unwrap(), gno_id: gno, last_committed: 0, sequence_number: gno, commit_timestamp: None });
+        builder.query(&QueryEvent{ thread_id: 10, execute_seconds: 0, database: String::from("shop"), command: String::from("BEGIN"), invoker: String::new() });
+        builder.table_map(100, &orders).unwrap();
+        builder.rows(&BinlogEvent::WriteEvent, 100, &orders, &[vec![Some(MySQLValue::SignedInteger(gno as i64))]]).unwrap();
+        builder.xid(&XidEvent{ xid: gno });
+    }
+    fs::write(dir.join("relay-bin.000001"), builder.into_bytes()).unwrap();
+    let index = dir.join("relay-bin.index");
+    fs::write(&index, "./relay-bin.000001\n").unwrap();
+
+    let output = dir.join("out.json");
+    let path = dir.join("journal.log");
+    let config = format!("[pipeline.replica]\nrelay-log = {}\noutput = {}\ngtid = {}:1\ncheckpoint = {}\ncheckpoint-interval = 1000\njournal = {}\n",
+                         index.display(), output.display(), SID, dir.join("replica.ckpt").display(), path.display());
+    let runtime = Runtime::new();
+    for p in pipeline::parse_config(&config).unwrap() {
+        runtime.add(p).unwrap();
+    }
+    runtime.start("replica").unwrap();
+    let start = Instant::now();
+    loop {
+        let records = journal::read(&path).unwrap();
+        if records.iter().filter(|t| t.kind == RecordKind::Emitted).count() >= 2 || start.elapsed() > Duration::from_secs(10) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    runtime.stop("replica").unwrap();
+    runtime.wait("replica").unwrap();
+
+    let records = journal::read(&path).unwrap();
+    let emitted: Vec<String> = records.iter().filter(|t| t.kind == RecordKind::Emitted).map(|t| t.gtid.clone()).collect();
+    assert_eq!(emitted, vec![gtid(2), gtid(3)]);
+    assert!(records.iter().all(|t| t.pipeline == "replica" && t.log_file == "mysql-bin.000003"));
+    let last = records.last().unwrap();
+    assert_eq!((last.kind, last.gtid.clone()), (RecordKind::Checkpointed, gtid(3)));
+    let result = journal::diagnose(&records);
+    assert!(result[0].unsettled.is_empty());
+
+    assert!(pipeline::parse_config(&format!("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\njournal = {}\n", dir.join("missing/journal.log").display()))
+        .err().unwrap().contains("journal"));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,95 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//json输出每个表对应的JSON Schema: 字段类型随格式选项变化, IS_NULLABLE为NO的字段不为null, 控制接口/schema返回已读取到的表
+
+#![cfg(feature = "os")]
+
+use std::collections::HashMap;
+use serde_json::json;
+use mytest::Config;
+use mytest::prelude::*;
+use mytest::stdout::outjsonschema::{json_schema, json_schemas};
+
+fn cols(names: &[(&str, &str, &str)]) -> Vec<HashMap<String, String>> {
+    names.iter().map(|(name, col_type, nullable)| {
+        let mut info = HashMap::new();
+        info.insert(String::from("COLUMN_NAME"), name.to_string());
+        info.insert(String::from("COLUMN_TYPE"), col_type.to_string());
+        info.insert(String::from("IS_NULLABLE"), nullable.to_string());
+        info
+    }).collect()
+}
+
+fn table() -> Vec<HashMap<String, String>> {
+    cols(&[("id", "bigint(20)", "NO"), ("score", "double", "NO"), ("created", "datetime", "NO"),
+           ("ts", "timestamp", "YES"), ("name", "varchar(32)", "YES")])
+}
+
+#[test]
+fn nullability() {
+    let schema = json_schema("db.t1", &table(), &ValueFormat::default());
+    let data = &schema["properties"]["data"];
+    assert_eq!(data["properties"]["id"]["type"], "integer");
+    assert_eq!(data["properties"]["created"]["type"], "string");
+    assert_eq!(data["properties"]["ts"]["type"], json!(["number", "null"]));
+    assert_eq!(data["properties"]["name"]["type"], json!(["string", "null"]));
+    //NaN、Infinity默认输出为null
+    assert_eq!(data["properties"]["score"]["type"], json!(["number", "null"]));
+    assert_eq!(data["required"], json!(["id", "score", "created", "ts", "name"]));
+    assert_eq!(schema["then"]["required"], json!(["old"]));
+    assert!(schema["properties"].get("labels").is_none());
+
+    let format = ValueFormat::new(&Config{ nonfinite: String::from("string"), zerodate: String::from("null"),
+                                           timezone: String::from("UTC"), ..Config::default() }).unwrap();
+    let data = &json_schema("db.t1", &table(), &format)["properties"]["data"];
+    assert_eq!(data["properties"]["score"]["type"], json!(["number", "string"]));
+    assert_eq!(data["properties"]["created"]["type"], json!(["string", "null"]));
+    assert_eq!(data["properties"]["ts"]["type"], json!(["string", "null"]));
+}
+
+#[test]
+fn envelope() {
+    let format = ValueFormat::new(&Config{ labels: String::from("env=prod,shard=3"), rowhash: true,
+                                           excludecolumns: String::from(r#"{"db.*":["name"]}"#), ..Config::default() }).unwrap();
+    let schema = json_schema("db.t1", &table(), &format);
+    assert_eq!(schema["properties"]["labels"]["properties"]["shard"], json!({"type": "string", "const": "3"}));
+    assert_eq!(schema["properties"]["labels"]["required"], json!(["env", "shard"]));
+    assert_eq!(schema["required"], json!(["type", "database", "table", "gtid", "data", "labels", "row_hash"]));
+    assert_eq!(schema["then"]["required"], json!(["old", "old_row_hash"]));
+    assert!(schema["properties"]["data"]["properties"].get("name").is_none());
+
+    let mut tables = HashMap::new();
+    tables.insert(String::from("db.t1"), table());
+    tables.insert(String::from("db.t2"), cols(&[("id", "int", "NO")]));
+    let schemas = json_schemas(tables.iter(), &format);
+    assert_eq!(schemas["db.t2"]["title"], "db.t2");
+    assert_eq!(schemas.as_object().unwrap().len(), 2);
+}
+
+#[test]
+fn control() {
+    let controller = Controller::new();
+    controller.set_start(&Config{ quotedecimal: true, ..Config::default() });
+    let query = HashMap::new();
+    assert_eq!(controller.handle("GET", "/schema", &query), (200, json!({})));
+    controller.set_table("db.t1", &cols(&[("id", "int", "NO"), ("price", "decimal(10,2)", "YES")]));
+    let (code, schemas) = controller.handle("GET", "/schema", &query);
+    assert_eq!(code, 200);
+    assert_eq!(schemas["db.t1"]["properties"]["data"]["properties"]["price"]["type"], json!(["string", "null"]));
+
+    let mut query = HashMap::new();
+    query.insert(String::from("table"), String::from("db.t1"));
+    let (code, schema) = controller.handle("GET", "/schema", &query);
+    assert_eq!((code, schema["title"].as_str()), (200, Some("db.t1")));
+    //表结构变化后返回新的schema
+    controller.set_table("db.t1", &cols(&[("id", "int", "NO")]));
+    let (_, schema) = controller.handle("GET", "/schema", &query);
+    assert!(schema["properties"]["data"]["properties"].get("price").is_none());
+
+    query.insert(String::from("table"), String::from("db.t2"));
+    assert_eq!(controller.handle("GET", "/schema", &query).0, 404);
+    assert_eq!(controller.handle("POST", "/schema", &query).0, 405);
+}
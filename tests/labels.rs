@@ -0,0 +1,82 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//静态标签: json输出的每行数据、心跳及控制接口的/status中带labels
+
+#![cfg(all(feature = "os", feature = "json-sink"))]
+
+use std::env;
+use std::fs;
+use std::time::Duration;
+use serde_json::{json, Value as JsonValue};
+use mytest::Config;
+use mytest::control::Controller;
+use mytest::labels::Labels;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+#[test]
+fn parse() {
+    let labels = Labels::parse(" env=prod, cluster=order ,shard=3,empty=").unwrap();
+    assert_eq!(labels.get("shard"), Some("3"));
+    assert_eq!(labels.get("empty"), Some(""));
+    assert_eq!(labels.to_json(), json!({"env": "prod", "cluster": "order", "shard": "3", "empty": ""}));
+    assert!(Labels::parse("").unwrap().is_empty());
+
+    assert!(Labels::parse("env").is_err());
+    assert!(Labels::parse("=prod").is_err());
+    assert!(Labels::parse("my env=prod").is_err());
+    assert!(Labels::parse("env=prod,env=test").unwrap_err().contains("重复"));
+
+    let mut value = json!({"type": "heartbeat"});
+    Labels::default().attach(&mut value);
+    assert!(value.get("labels").is_none());
+    labels.attach(&mut value);
+    assert_eq!(value["labels"]["env"], "prod");
+}
+
+#[test]
+fn records() {
+    let opts = SynthOptions{
+        database: String::from("label"),
+        tables: 1,
+        columns: vec![ColumnKind::Int],
+        transactions: 5,
+        rows: (1, 2),
+        rate: 10,
+        mix: [1, 1, 1],
+        start_time: 1572998400,
+        seed: 7,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-labels-{}.binlog", std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+
+    let conf = Config{ labels: String::from("cluster=order,shard=3"), ..Config::default() };
+    let format = ValueFormat::new(&conf).unwrap();
+    let mut buf = vec![];
+    let mut sink = HeartbeatSink::new(JsonLinesSink::new(&mut buf, format), Duration::from_secs(0));
+    for event in BinlogReader::open(&path).unwrap() {
+        sink.write_event(&event.unwrap()).unwrap();
+    }
+    drop(sink);
+    fs::remove_file(&path).unwrap();
+
+    let records: Vec<JsonValue> = String::from_utf8(buf).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert!(records.iter().any(|r| r["type"] == "heartbeat"));
+    assert!(records.iter().any(|r| r["type"] == "insert"));
+    for record in &records {
+        assert_eq!(record["labels"], json!({"cluster": "order", "shard": "3"}), "{}", record);
+    }
+    assert!(ValueFormat::new(&Config{ labels: String::from("shard"), ..Config::default() }).is_err());
+}
+
+#[test]
+fn status() {
+    let controller = Controller::new();
+    assert!(controller.status().get("labels").is_none());
+    controller.set_start(&Config{ labels: String::from("env=prod"), ..Config::default() });
+    assert_eq!(controller.status()["labels"], json!({"env": "prod"}));
+}
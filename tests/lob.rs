@@ -0,0 +1,60 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--lobmode、--lobmaxsize: BLOB/TEXT超过lobmaxsize时截断(标记原始长度)或输出sha256, 其余类型及未超过的值完整输出
+#![cfg(feature = "json-sink")]
+
+use serde_json::json;
+use mytest::Config;
+use mytest::prelude::*;
+use mytest::stdout::format::{BinaryEncoding, LobMode};
+use mytest::warning::{self, WarningKind};
+
+fn format(lobmode: &str, lobmaxsize: &str) -> ValueFormat {
+    ValueFormat::new(&Config{ lobmode: lobmode.to_string(), lobmaxsize: lobmaxsize.to_string(), ..Config::default() }).unwrap()
+}
+
+fn blob(value: &[u8]) -> Option<MySQLValue> {
+    Some(MySQLValue::Blob(value.to_vec()))
+}
+
+#[test]
+fn mode() {
+    assert_eq!(LobMode::new("", "").unwrap(), LobMode::Full);
+    assert_eq!(LobMode::new("truncate", "").unwrap(), LobMode::Full);
+    assert_eq!(LobMode::new("", "10").unwrap(), LobMode::Truncate(10));
+    assert_eq!(LobMode::new("hash", "10").unwrap(), LobMode::Hash(10));
+    assert!(LobMode::new("hash", "-1").unwrap_err().contains("lobmaxsize"));
+    assert!(LobMode::new("drop", "10").unwrap_err().contains("lobmode"));
+    assert!(ValueFormat::new(&Config{ lobmaxsize: String::from("1k"), ..Config::default() }).is_err());
+}
+
+#[test]
+fn truncate() {
+    let before = warning::count(WarningKind::TruncatedBlob);
+    let format = format("truncate", "4");
+    assert_eq!(format.json_value(&blob(b"abcdefghij"), "text", BinaryEncoding::Hex), json!("abcd...[truncated, 10 bytes]"));
+    //二进制数据截断后按编码输出
+    assert_eq!(format.json_value(&blob(b"abcdefghij"), "blob", BinaryEncoding::Hex), json!("0x61626364...[truncated, 10 bytes]"));
+    //不在多字节字符中间截断
+    assert_eq!(format.text_value(&blob("中文字段".as_bytes()), "longtext", BinaryEncoding::Hex), Some(String::from("中...[truncated, 12 bytes]")));
+    //未超过lobmaxsize及非BLOB/TEXT类型完整输出
+    assert_eq!(format.json_value(&blob(b"abcd"), "text", BinaryEncoding::Hex), json!("abcd"));
+    assert_eq!(format.json_value(&blob(b"abcdefghij"), "varchar(20)", BinaryEncoding::Hex), json!("abcdefghij"));
+    assert!(warning::count(WarningKind::TruncatedBlob) - before >= 3);
+}
+
+#[test]
+fn hash() {
+    let format = format("hash", "4");
+    let expect = "sha256:72399361da6a7754fec986dca5b7cbaf1c810a28ded4abaf56b2106d06cb78b0 [10 bytes]";
+    assert_eq!(format.json_value(&blob(b"abcdefghij"), "text", BinaryEncoding::Hex), json!(expect));
+    //按原始内容计算, 与二进制编码无关
+    assert_eq!(format.text_value(&blob(b"abcdefghij"), "mediumblob", BinaryEncoding::Base64), Some(expect.to_string()));
+    assert_eq!(format.json_value(&blob(b"abc"), "text", BinaryEncoding::Hex), json!("abc"));
+
+    //lobmaxsize为0时都输出hash
+    assert!(self::format("hash", "").json_value(&blob(b"a"), "blob", BinaryEncoding::Hex).as_str().unwrap().starts_with("sha256:"));
+}
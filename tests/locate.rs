@@ -0,0 +1,113 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//locate: 按previous_gtids、文件开头的时间确定第一个需要扫描的文件, 在多个文件及index文件中找到gtid、时间对应的事务位置
+
+#![cfg(feature = "os")]
+
+use std::env;
+use std::fs;
+use uuid::Uuid;
+use mytest::gtid::GtidSet;
+use mytest::locate::{self, FileProbe, Target};
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+const START: u32 = 1572998400;
+
+fn synthesize(name: &str, start_time: u32, seed: u64) -> String {
+    let opts = SynthOptions{
+        database: String::from("locate"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 20,
+        rows: (1, 3),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time,
+        seed,
+        server_id: 133,
+    };
+    let dir = env::temp_dir().join(format!("mytest-locate-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+//(gtid, start, end, timestamp)
+fn transactions(path: &str) -> Vec<(String, u64, u64, u32)> {
+    BinlogReader::open(path).unwrap().transactions().map(|trx| {
+        let trx = trx.unwrap();
+        let first = trx.events.first().unwrap();
+        let last = trx.events.last().unwrap();
+        let gtid = trx.gtid.as_ref().map(|t| format!("{}:{}", t.gtid, t.gno_id)).unwrap();
+        (gtid, first.log_pos, last.log_pos + last.header.event_length as u64, first.header.timestamp)
+    }).collect()
+}
+
+#[test]
+fn first_file() {
+    let sid = Uuid::parse_str("3e11fa47-71ca-11e1-9e33-c80aa9429562").unwrap();
+    let previous = |gtid: &str| FileProbe{ start_time: 0, previous_gtids: Some(gtid.parse::<GtidSet>().unwrap()) };
+    let probes = [previous(""), previous("3e11fa47-71ca-11e1-9e33-c80aa9429562:1-10"), previous("3e11fa47-71ca-11e1-9e33-c80aa9429562:1-20")];
+    let first = |gno: u64| locate::first_file(&Target::Gtid(sid, gno), probes.len(), |idx| Ok(probes[idx].clone())).unwrap();
+    assert_eq!(first(5), Some(0));
+    assert_eq!(first(15), Some(1));
+    assert_eq!(first(25), Some(2));
+    //最早的文件也包含时已被清理
+    let purged = [previous("3e11fa47-71ca-11e1-9e33-c80aa9429562:1-10")];
+    assert_eq!(locate::first_file(&Target::Gtid(sid, 5), 1, |idx| Ok(purged[idx].clone())).unwrap(), None);
+
+    let times = [100, 200, 300].map(|start_time| FileProbe{ start_time, previous_gtids: None });
+    let first = |t: u32| locate::first_file(&Target::Time(t), times.len(), |idx| Ok(times[idx].clone())).unwrap();
+    assert_eq!((first(50), first(150), first(200), first(1000)), (Some(0), Some(0), Some(1), Some(2)));
+}
+
+#[test]
+fn files() {
+    let first = synthesize("mysql-bin.000001", START, 1);
+    let second = synthesize("mysql-bin.000002", START + 100, 2);
+    let files = vec![first.clone(), second.clone()];
+    let probe = locate::probe_file(&second).unwrap();
+    assert_eq!(probe.start_time, START + 100);
+
+    //第二个文件中的gtid
+    let (gtid, start, end, timestamp) = transactions(&second)[5].clone();
+    let location = locate::locate_files(&files, &Target::gtid(&gtid).unwrap()).unwrap().unwrap();
+    assert_eq!((location.file.as_str(), location.start, location.end, location.timestamp), ("mysql-bin.000002", start, end, timestamp));
+    assert_eq!(location.gtid.as_deref(), Some(gtid.as_str()));
+    //生成的文件previous_gtids都为空, 按previous_gtids只会扫描最后一个文件
+    let (gtid, start, _, _) = transactions(&first)[0].clone();
+    assert_eq!(locate::locate_files(&files, &Target::gtid(&gtid).unwrap()).unwrap(), None);
+    let location = locate::locate_files(&[first.clone()], &Target::gtid(&gtid).unwrap()).unwrap().unwrap();
+    assert_eq!((location.file.as_str(), location.start), ("mysql-bin.000001", start));
+    assert_eq!(locate::locate_files(&files, &Target::gtid("3e11fa47-71ca-11e1-9e33-c80aa9429562:1").unwrap()).unwrap(), None);
+
+    //时间之后的第一个事务, 两个文件之间的时间为第二个文件的第一个事务
+    let trxs = transactions(&first);
+    let target = trxs.iter().find(|t| t.3 > START).unwrap();
+    let location = locate::locate_files(&files, &Target::Time(target.3)).unwrap().unwrap();
+    assert_eq!((location.file.as_str(), location.start), ("mysql-bin.000001", target.1));
+    let location = locate::locate_files(&files, &Target::time(&(START + 50).to_string()).unwrap()).unwrap().unwrap();
+    assert_eq!((location.file.as_str(), location.start), ("mysql-bin.000002", transactions(&second)[0].1));
+    assert_eq!(locate::locate_files(&files, &Target::Time(START + 1000)).unwrap(), None);
+
+    //index中的相对路径按index文件所在的目录
+    let dir = std::path::Path::new(&first).parent().unwrap();
+    let index = dir.join("mysql-bin.index");
+    fs::write(&index, "./mysql-bin.000001\n./mysql-bin.000002\n").unwrap();
+    let files = locate::read_index(index.to_str().unwrap()).unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(fs::read(&files[1]).unwrap(), fs::read(&second).unwrap());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn target() {
+    assert!(Target::gtid("3e11fa47-71ca-11e1-9e33-c80aa9429562:1-5").is_err());
+    assert!(Target::gtid("abc:1").is_err());
+    assert_eq!(Target::time("2019-11-06").unwrap(), Target::Time(START));
+}
@@ -0,0 +1,105 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//输出文件的清单: binlog文件的gtid、事务数、行数, 写入后按相对路径读取, 只按大小及sha256校验
+
+#![cfg(feature = "os")]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use mytest::manifest::{self, Manifest, ManifestEntry};
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions, SynthReport};
+
+fn dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("mytest-manifest-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn synth(path: &str, seed: u64) -> SynthReport {
+    let opts = SynthOptions{
+        database: String::from("shop"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 30,
+        rows: (1, 4),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed,
+        server_id: 133,
+    };
+    synthesize::write_file(path, &opts).unwrap()
+}
+
+#[test]
+fn binlog() {
+    let dir = dir("binlog");
+    let path = dir.join("mysql-bin.000001").to_string_lossy().to_string();
+    let report = synth(&path, 3);
+    let entry = ManifestEntry::binlog(&path).unwrap();
+    //开头每个表的CREATE TABLE各为一个事务
+    assert_eq!((entry.transactions, entry.rows, entry.size), (report.transactions + 2, report.rows, report.bytes));
+    assert!(!entry.gtids.is_empty());
+    assert_eq!(entry.kind, "binlog");
+    assert_eq!(entry.sha256.len(), 64);
+    assert_eq!((entry.size, entry.sha256.clone()), manifest::sha256_file(&path).unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn write_and_check() {
+    let dir = dir("check");
+    let files: Vec<String> = (1..=2).map(|n| dir.join(format!("mysql-bin.00000{}", n)).to_string_lossy().to_string()).collect();
+    let path = dir.join("manifest.json");
+    let mut manifest = Manifest::open(&path).unwrap();
+    assert!(manifest.entries.is_empty());
+    for (seed, file) in files.iter().enumerate() {
+        synth(file, seed as u64 + 1);
+        manifest.add(ManifestEntry::binlog(file).unwrap());
+    }
+    //相同的文件替换
+    manifest.add(ManifestEntry::binlog(&files[1]).unwrap());
+    manifest.write().unwrap();
+
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(json["files"].as_array().unwrap().len(), 2);
+    assert_eq!(json["files"][0]["file"], "mysql-bin.000001");
+    assert_eq!(json["transactions"], 64);
+
+    let loaded = Manifest::load(&path).unwrap();
+    assert_eq!(loaded.entries, manifest.entries);
+    assert!(loaded.check().is_empty());
+
+    //内容修改后大小不变时按sha256发现
+    let mut data = fs::read(&files[0]).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    fs::write(&files[0], &data).unwrap();
+    fs::remove_file(&files[1]).unwrap();
+    let failed = loaded.check();
+    assert_eq!(failed.len(), 2);
+    assert!(failed[0].1.contains("sha256"));
+    assert_eq!(failed[1].0, files[1]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn csv() {
+    let dir = dir("csv");
+    let path = dir.join("orders.csv");
+    fs::write(&path, "id,name\n1,a\n2,b\n").unwrap();
+    let entry = ManifestEntry::file(&path.to_string_lossy(), "csv").unwrap();
+    let entry = ManifestEntry{ rows: 2, ..entry };
+    let mut manifest = Manifest::open(dir.join("manifest.json")).unwrap();
+    manifest.add(entry);
+    manifest.write().unwrap();
+    let loaded = Manifest::load(dir.join("manifest.json")).unwrap();
+    assert_eq!((loaded.entries[0].kind.as_str(), loaded.entries[0].rows, loaded.entries[0].size), ("csv", 2, 16));
+    assert!(loaded.gtids().is_empty());
+    assert!(Manifest::load(dir.join("orders.csv")).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
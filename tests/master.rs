@@ -0,0 +1,278 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//binlog server(模拟master): 认证、从库执行的查询, 按文件及position、按gtid注册, 从文件中间开始,
+//切换文件时补发ROTATE、等待备份写入的新文件, BINLOG_DUMP_NON_BLOCK, 没有设置@master_binlog_checksum时返回错误,
+//连接中读取的事件与直接读取文件的结果相同
+
+#![cfg(all(feature = "test-support", feature = "network"))]
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use mytest::Config;
+use mytest::io::{command, connection, response, socketio};
+use mytest::master::BinlogServer;
+use mytest::prelude::*;
+use mytest::readvalue;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+use common::{insert, SID, START};
+
+fn dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("mytest-master-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+//previous_gtids为1..first的文件, 包含gnos中的事务, next不为空时以ROTATE结尾
+fn binlog(first: u64, gnos: &[u64], next: &str) -> BinlogBuilder {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57).server_id(7);
+    let previous = if first > 1 { format!("{}:1-{}", SID, first - 1).parse().unwrap() } else { GtidSet::new() };
+    builder.timestamp(START).previous_gtids(&previous);
+    for gno in gnos {
+        insert(&mut builder, *gno);
+    }
+    if !next.is_empty() {
+        builder.rotate(&RotateLog{ binlog_file: next.to_string(), position: 4, artificial: false });
+    }
+    builder
+}
+
+//bin.000001: 事务1、2, 以ROTATE结尾; bin.000002: 事务3, 没有ROTATE(备份中断)
+fn backup(name: &str) -> PathBuf {
+    let dir = dir(name);
+    fs::write(dir.join("bin.000001"), binlog(1, &[1, 2], "bin.000002").into_bytes()).unwrap();
+    fs::write(dir.join("bin.000002"), binlog(3, &[3], "").into_bytes()).unwrap();
+    fs::write(dir.join("README"), "not a binlog").unwrap();
+    dir
+}
+
+fn serve(dir: &PathBuf) -> SocketAddr {
+    BinlogServer::open_dir(dir).unwrap().credentials("repl", "secret").serve("127.0.0.1:0").unwrap()
+}
+
+fn conf(addr: SocketAddr) -> Config {
+    Config{
+        host_info: addr.to_string(),
+        user_name: String::from("repl"),
+        password: String::from("secret").into(),
+        serverid: String::from("99"),
+        ..Config::default()
+    }
+}
+
+//读取事件直到count个事务提交
+fn read_transactions(stream: &mut BinlogStream, count: usize) -> Vec<Event> {
+    let mut events = vec![];
+    let mut commits = 0;
+    while commits < count {
+        let event = stream.read_event().unwrap();
+        if let Traction::XidEvent(_) = event.data {
+            commits += 1;
+        }
+        events.push(event);
+    }
+    events
+}
+
+fn gnos(events: &[Event]) -> Vec<u64> {
+    events.iter().filter_map(|e| match &e.data {
+        Traction::GtidEvent(t) => Some(t.gno_id),
+        _ => None,
+    }).collect()
+}
+
+fn rotates(events: &[Event]) -> Vec<(String, bool)> {
+    events.iter().filter_map(|e| match &e.data {
+        Traction::RotateLogEvent(t) => Some((t.binlog_file.clone(), e.header.is_artificial())),
+        _ => None,
+    }).collect()
+}
+
+#[test]
+fn dump_by_position() {
+    let addr = serve(&backup("position"));
+    let mut stream = BinlogStream::connect(&Config{ binlogfile: String::from("bin.000001"), position: String::from("4"), ..conf(addr) }).unwrap();
+    let events = read_transactions(&mut stream, 3);
+    assert_eq!(gnos(&events), vec![1, 2, 3]);
+    assert_eq!(rotates(&events), vec![(String::from("bin.000001"), true), (String::from("bin.000002"), false)]);
+    //每个文件开头的FORMAT_DESCRIPTION_EVENT, server_id与文件一致
+    assert_eq!(events.iter().filter(|e| e.header.event_type == EventType::FormatDescriptionEvent).count(), 2);
+    assert!(events.iter().skip(1).all(|e| e.header.server_id == 7));
+}
+
+//连接中读取的事件去掉ok包标识后按文件的格式解析, 与直接读取文件的结果相同
+#[test]
+fn same_as_file() {
+    let dir = backup("same");
+    let addr = serve(&dir);
+    let mut stream = BinlogStream::connect(&Config{ binlogfile: String::from("bin.000001"), position: String::from("4"), ..conf(addr) }).unwrap();
+    let streamed: Vec<String> = read_transactions(&mut stream, 2).iter()
+        .filter(|e| !e.header.is_artificial() && e.header.event_type != EventType::FormatDescriptionEvent)
+        .map(|e| format!("{:?} {:?}", e.header, e.data)).collect();
+    let file: Vec<String> = BinlogReader::open(dir.join("bin.000001").to_str().unwrap()).unwrap().map(|e| e.unwrap())
+        .filter(|e| e.header.event_type != EventType::FormatDescriptionEvent && e.header.event_type != EventType::RotateEvent)
+        .map(|e| format!("{:?} {:?}", e.header, e.data)).collect();
+    assert_eq!(streamed.len(), 11);
+    assert_eq!(streamed, file);
+    assert!(streamed[0].contains("header_length: 19"));
+}
+
+#[test]
+fn dump_from_middle() {
+    let dir = backup("middle");
+    //事务2的起始位置
+    let mut builder = binlog(1, &[1], "");
+    let position = builder.position();
+    let addr = serve(&dir);
+    let mut stream = BinlogStream::connect(&Config{ binlogfile: String::from("bin.000001"), position: position.to_string(), ..conf(addr) }).unwrap();
+    let events = read_transactions(&mut stream, 1);
+    assert_eq!(gnos(&events), vec![2]);
+    assert_eq!(events[0].header.event_type, EventType::RotateEvent);
+    match &events[0].data {
+        Traction::RotateLogEvent(t) => assert_eq!(t.position, position),
+        _ => panic!("第一个事件应为ROTATE"),
+    }
+    //FORMAT_DESCRIPTION_EVENT的log_pos为0, 之后不再发送事务1及PREVIOUS_GTIDS
+    assert_eq!(events[1].header.event_type, EventType::FormatDescriptionEvent);
+    assert_eq!(events[1].header.next_position, 0);
+    assert_eq!(events[2].header.event_type, EventType::GtidLogEvent);
+}
+
+#[test]
+fn dump_by_gtid() {
+    let dir = backup("gtid");
+    let addr = serve(&dir);
+    //从previous_gtids包含于已执行集合的bin.000002开始
+    let mut stream = BinlogStream::connect(&Config{ gtid: format!("{}:1-2", SID), ..conf(addr) }).unwrap();
+    let events = read_transactions(&mut stream, 1);
+    assert_eq!(gnos(&events), vec![3]);
+    assert_eq!(rotates(&events), vec![(String::from("bin.000002"), true)]);
+
+    //跳过已执行的事务1
+    let mut stream = BinlogStream::connect(&Config{ gtid: format!("{}:1", SID), ..conf(addr) }).unwrap();
+    let events = read_transactions(&mut stream, 2);
+    assert_eq!(gnos(&events), vec![2, 3]);
+}
+
+#[test]
+fn follow_new_files() {
+    let dir = backup("follow");
+    let addr = serve(&dir);
+    let mut stream = BinlogStream::connect(&Config{ binlogfile: String::from("bin.000002"), position: String::from("4"), ..conf(addr) }).unwrap();
+    assert_eq!(gnos(&read_transactions(&mut stream, 1)), vec![3]);
+    //备份继续写入新文件, bin.000002没有以ROTATE结尾, 切换前补发artificial的ROTATE
+    fs::write(dir.join("bin.000003"), binlog(4, &[4], "").into_bytes()).unwrap();
+    let events = read_transactions(&mut stream, 1);
+    assert_eq!(gnos(&events), vec![4]);
+    assert_eq!(rotates(&events), vec![(String::from("bin.000003"), true)]);
+}
+
+#[test]
+fn queries() {
+    let addr = serve(&backup("queries"));
+    let mut conn = connection::create_mysql_conn(&conf(addr)).unwrap();
+    let rows = command::try_execute(&mut conn, "SELECT @@GLOBAL.SERVER_ID, @@global.gtid_mode AS mode, @@binlog_checksum").unwrap();
+    assert_eq!(rows[0]["@@GLOBAL.SERVER_ID"], "7");
+    assert_eq!(rows[0]["mode"], "ON");
+    assert_eq!(rows[0]["@@binlog_checksum"], "CRC32");
+    let rows = command::try_execute(&mut conn, "select @@version").unwrap();
+    assert!(rows[0]["@@version"].starts_with("5.7"));
+
+    command::try_execute_update(&mut conn, "SET @master_binlog_checksum= @@global.binlog_checksum").unwrap();
+    let rows = command::try_execute(&mut conn, "SELECT @master_binlog_checksum").unwrap();
+    assert_eq!(rows[0]["@master_binlog_checksum"], "CRC32");
+
+    let rows = command::try_execute(&mut conn, "SHOW GLOBAL VARIABLES LIKE 'server%'").unwrap();
+    let names: Vec<&str> = rows.iter().map(|r| r["Variable_name"].as_str()).collect();
+    assert_eq!(names, vec!["server_id", "server_uuid"]);
+
+    let err = command::try_execute(&mut conn, "SELECT @@rpl_semi_sync_master_enabled").unwrap_err();
+    assert!(err.contains("Unknown system variable"), "{}", err);
+    assert!(command::try_execute(&mut conn, "DROP TABLE t").is_err());
+}
+
+#[test]
+fn server_options() {
+    let dir = backup("options");
+    let addr = BinlogServer::open_dir(&dir).unwrap()
+        .credentials("repl", "secret")
+        .server_id(100)
+        .server_uuid("9b4e1d0c-0000-4000-8000-000000000064")
+        .variable("rpl_semi_sync_master_enabled", "0")
+        .serve("127.0.0.1:0").unwrap();
+    let mut conn = connection::create_mysql_conn(&conf(addr)).unwrap();
+    let rows = command::try_execute(&mut conn, "select @@server_id as id, @@server_uuid as uuid, @@rpl_semi_sync_master_enabled as semi").unwrap();
+    assert_eq!(rows[0]["id"], "100");
+    assert_eq!(rows[0]["uuid"], "9b4e1d0c-0000-4000-8000-000000000064");
+    assert_eq!(rows[0]["semi"], "0");
+}
+
+#[test]
+fn access_denied() {
+    let addr = serve(&backup("denied"));
+    let wrong = Config{ password: String::from("wrong").into(), ..conf(addr) };
+    assert!(connection::create_mysql_conn(&wrong).is_err());
+    let user = Config{ user_name: String::from("root"), ..conf(addr) };
+    assert!(BinlogStream::connect(&Config{ binlogfile: String::from("bin.000001"), position: String::from("4"), ..user }).is_err());
+}
+
+//COM_BINLOG_DUMP: position(4) flags(2) server_id(4) 文件名
+fn dump_packet(file: &str, flags: u16) -> Vec<u8> {
+    let mut payload = vec![0x12];
+    payload.extend(readvalue::write_u32(4));
+    payload.extend(readvalue::write_u16(flags));
+    payload.extend(readvalue::write_u32(99));
+    payload.extend(file.as_bytes());
+    let mut packet = response::pack_header(&payload, 0);
+    packet.extend(payload);
+    packet
+}
+
+fn raw_conn(addr: SocketAddr) -> TcpStream {
+    connection::create_mysql_conn(&conf(addr)).unwrap()
+}
+
+#[test]
+fn non_block_and_errors() {
+    let addr = serve(&backup("nonblock"));
+
+    //文件有checksum, 从库没有声明可以处理checksum
+    let mut conn = raw_conn(addr);
+    socketio::write_value(&mut conn, &dump_packet("bin.000001", 1)).unwrap();
+    let (packet, _) = socketio::get_packet_from_stream(&mut conn);
+    assert_eq!(packet[0], 0xFF);
+    assert!(String::from_utf8_lossy(&packet).contains("checksum"));
+
+    let mut conn = raw_conn(addr);
+    command::try_execute_update(&mut conn, "set @master_binlog_checksum= @@global.binlog_checksum").unwrap();
+    socketio::write_value(&mut conn, &dump_packet("bin.000009", 1)).unwrap();
+    let (packet, _) = socketio::get_packet_from_stream(&mut conn);
+    assert_eq!(packet[0], 0xFF);
+    assert_eq!(readvalue::read_u16(&packet[1..3]), 1236);
+
+    //BINLOG_DUMP_NON_BLOCK: 读完全部文件后返回EOF包
+    let mut conn = raw_conn(addr);
+    command::try_execute_update(&mut conn, "set @master_binlog_checksum= @@global.binlog_checksum").unwrap();
+    socketio::write_value(&mut conn, &dump_packet("bin.000001", 1)).unwrap();
+    let mut types = vec![];
+    loop {
+        let (packet, _) = socketio::get_packet_from_stream(&mut conn);
+        if packet[0] == 0xFE && packet.len() < 20 {
+            break;
+        }
+        assert_eq!(packet[0], 0x00);
+        types.push(packet[5]);
+    }
+    //ROTATE, 两个文件各自的FDE、PREVIOUS_GTIDS, 三个事务各5个事件, 第一个文件的ROTATE
+    assert_eq!(types.len(), 1 + 2 * 2 + 3 * 5 + 1);
+    assert_eq!(types.iter().filter(|t| **t == 16).count(), 3);
+    //dump结束后连接仍可以执行语句
+    assert_eq!(command::try_execute(&mut conn, "select 1 as one").unwrap()[0]["one"], "1");
+}
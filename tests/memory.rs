@@ -0,0 +1,131 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//内存上限: 超过上限时行事件写入临时文件后读回得到相同的事件, Block策略下批量提前写出, 使用量在写出后释放
+//内存上限为进程全局的设置, 各个测试依次执行
+
+#![cfg(feature = "os")]
+
+use std::env;
+use std::fs;
+use std::io;
+use std::sync::Mutex;
+use mytest::memory::{self, EventBuffer, MemoryPolicy};
+use mytest::prelude::*;
+use mytest::sink::{BatchConfig, Batcher};
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn events() -> Vec<Event> {
+    let opts = SynthOptions{
+        database: String::from("mem"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(32)],
+        transactions: 30,
+        rows: (1, 5),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 5,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-memory-{}.binlog", std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    let events = BinlogReader::open(path.to_str().unwrap()).unwrap().map(|e| e.unwrap()).collect();
+    fs::remove_file(&path).unwrap();
+    events
+}
+
+fn spill_files(dir: &std::path::Path) -> usize {
+    fs::read_dir(dir).unwrap().filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().starts_with("binlogal-spill-")).count()
+}
+
+#[test]
+fn parse() {
+    assert_eq!(memory::parse_size("1024").unwrap(), 1024);
+    assert_eq!(memory::parse_size("64k").unwrap(), 64 << 10);
+    assert_eq!(memory::parse_size("512M").unwrap(), 512 << 20);
+    assert_eq!(memory::parse_size("2G").unwrap(), 2 << 30);
+    assert!(memory::parse_size("2T").unwrap_err().contains("memory-limit"));
+    assert!(memory::parse_size("lots").is_err());
+    assert_eq!(MemoryPolicy::parse("").unwrap(), MemoryPolicy::Block);
+    assert_eq!(MemoryPolicy::parse("spill").unwrap(), MemoryPolicy::Spill(env::temp_dir()));
+    assert_eq!(MemoryPolicy::parse("spill:/data/tmp").unwrap(), MemoryPolicy::Spill("/data/tmp".into()));
+    assert!(MemoryPolicy::parse("drop").unwrap_err().contains("memory-policy"));
+}
+
+#[test]
+fn spill() {
+    let _serial = SERIAL.lock().unwrap();
+    let dir = env::temp_dir().join(format!("mytest-spill-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let events = events();
+    let before = memory::stats();
+    memory::configure(1, MemoryPolicy::Spill(dir.clone()));
+
+    let mut buffer = EventBuffer::new();
+    for event in &events {
+        buffer.push(event.clone()).unwrap();
+    }
+    let stats = memory::stats();
+    let rows = events.iter().filter(|e| matches!(e.data, Traction::RowEvent(..))).count() as u64;
+    assert_eq!(stats.spilled_events - before.spilled_events, rows);
+    assert!(stats.used - before.used < events.iter().map(memory::event_size).sum::<u64>());
+    assert_eq!(spill_files(&dir), 1);
+
+    //读回后与原来的事件相同, 临时文件删除
+    let restored = buffer.take().unwrap();
+    assert_eq!(format!("{:?}", restored), format!("{:?}", events));
+    assert!(buffer.is_empty());
+    assert_eq!(spill_files(&dir), 0);
+
+    memory::configure(0, MemoryPolicy::Block);
+    fs::remove_dir(&dir).unwrap();
+}
+
+//记录每批的事件数
+struct Batches(Vec<usize>);
+
+impl BatchSink for Batches {
+    fn write_batch(&mut self, events: &[Event]) -> io::Result<()> {
+        self.0.push(events.len());
+        Ok(())
+    }
+}
+
+#[test]
+fn block() {
+    let _serial = SERIAL.lock().unwrap();
+    let events = events();
+    let config = BatchConfig{ max_rows: 1_000_000, ..BatchConfig::default() };
+
+    let mut batcher = Batcher::new(Batches(vec![]), config);
+    for event in &events {
+        batcher.write_event(event).unwrap();
+    }
+    assert!(batcher.get_ref().0.is_empty());
+    batcher.flush().unwrap();
+    assert_eq!(batcher.get_ref().0, vec![events.len()]);
+
+    //超过上限时提前写出, 写出后释放
+    let limit = memory::stats().used + 2048;
+    memory::configure(limit, MemoryPolicy::Block);
+    let mut batcher = Batcher::new(Batches(vec![]), config);
+    for event in &events {
+        batcher.write_event(event).unwrap();
+        assert!(memory::stats().used <= limit);
+    }
+    batcher.flush().unwrap();
+    let batches = &batcher.get_ref().0;
+    assert!(batches.len() > 1);
+    assert_eq!(batches.iter().sum::<usize>(), events.len());
+    let stats = memory::stats();
+    assert!(stats.exceeded > 0);
+    assert!(stats.peak > limit);
+    assert_eq!(stats.limit, limit);
+
+    memory::configure(0, MemoryPolicy::Block);
+}
@@ -0,0 +1,87 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//多个源按事务的提交时间合并输出: 窗口内按时间排序, 窗口为0时按写入顺序输出, flush时输出该源缓存的事务
+
+#![cfg(feature = "os")]
+
+use std::env;
+use std::fs;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use mytest::prelude::*;
+use mytest::sink::OrderedMerge;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+//记录每个事务的(源, 提交时间)
+struct Recorder{
+    name: &'static str,
+    commits: Arc<Mutex<Vec<(&'static str, u32)>>>,
+}
+
+impl Sink for Recorder {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        if let Traction::XidEvent(_) = event.data {
+            self.commits.lock().unwrap().push((self.name, event.header.timestamp));
+        }
+        Ok(())
+    }
+}
+
+fn events(name: &str, start_time: u32, seed: u64) -> Vec<Event> {
+    let opts = SynthOptions{
+        database: String::from("merge"),
+        tables: 1,
+        columns: vec![ColumnKind::Int],
+        transactions: 20,
+        rows: (1, 1),
+        rate: 1,
+        mix: [1, 0, 0],
+        start_time,
+        seed,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-merge-{}-{}.binlog", name, std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    let events = BinlogReader::open(path.to_str().unwrap()).unwrap().map(|e| e.unwrap()).collect();
+    fs::remove_file(&path).unwrap();
+    events
+}
+
+fn run(window: Duration) -> Vec<(&'static str, u32)> {
+    let commits = Arc::new(Mutex::new(vec![]));
+    let merge = OrderedMerge::new(window);
+    let mut a = merge.source(Recorder{ name: "a", commits: commits.clone() });
+    let mut b = merge.source(Recorder{ name: "b", commits: commits.clone() });
+    //a全部写入之后才写入b, 最坏的情况
+    for event in events("a", 1572998400, 3) {
+        a.write_event(&event).unwrap();
+    }
+    for event in events("b", 1572998405, 5) {
+        b.write_event(&event).unwrap();
+    }
+    drop(a);
+    b.flush().unwrap();
+    assert_eq!(merge.pending(), 0);
+    drop(b);
+    let commits = commits.lock().unwrap().clone();
+    commits
+}
+
+#[test]
+fn ordered() {
+    let commits = run(Duration::from_secs(3600));
+    assert_eq!(commits.len(), 40);
+    assert!(commits.windows(2).all(|t| t[0].1 <= t[1].1), "{:?}", commits);
+    assert!(commits.iter().take(10).any(|t| t.0 == "b"));
+}
+
+#[test]
+fn no_window() {
+    let commits = run(Duration::from_secs(0));
+    assert_eq!(commits.len(), 40);
+    assert!(commits[..20].iter().all(|t| t.0 == "a"));
+}
@@ -0,0 +1,91 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//经过ProxySQL、MaxScale等中间件连接: 握手包中修改过的标识, 连接时识别中间件并检查不支持的组合
+
+#![cfg(all(feature = "test-support", feature = "network"))]
+
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use mytest::Config;
+use mytest::compat::{self, Profile};
+use mytest::io::{command, connection};
+use mytest::master::BinlogServer;
+use mytest::prelude::*;
+use mytest::stream::BinlogStream;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+const CLIENT_PLUGIN_AUTH: u32 = 0x80000;
+const CLIENT_CONNECT_ATTRS: u32 = 0x100000;
+const CLIENT_DEPRECATE_EOF: u32 = 0x1000000;
+
+fn server(name: &str) -> BinlogServer {
+    let dir = env::temp_dir().join(format!("mytest-middleware-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.timestamp(1572566400).previous_gtids(&GtidSet::new());
+    fs::write(dir.join("bin.000001"), builder.into_bytes()).unwrap();
+    BinlogServer::open_dir(&dir).unwrap().credentials("repl", "secret")
+}
+
+fn conf(addr: SocketAddr) -> Config {
+    Config{
+        host_info: addr.to_string(),
+        user_name: String::from("repl"),
+        password: String::from("secret").into(),
+        program_name: String::from("mytest"),
+        serverid: String::from("1001"),
+        binlogfile: String::from("bin.000001"),
+        position: String::from("4"),
+        ..Config::default()
+    }
+}
+
+#[test]
+fn altered_capabilities() {
+    //不声明CLIENT_DEPRECATE_EOF时结果集的字段信息之后有EOF包, 不声明CLIENT_PLUGIN_AUTH时握手包中没有插件名
+    let addr = server("capabilities").without_capabilities(CLIENT_DEPRECATE_EOF | CLIENT_PLUGIN_AUTH | CLIENT_CONNECT_ATTRS)
+        .serve("127.0.0.1:0").unwrap();
+    let mut conn = connection::create_mysql_conn(&conf(addr)).unwrap();
+    let rows = command::try_execute(&mut conn, "select @@server_id as id, 'a' as name").unwrap();
+    assert_eq!((rows.len(), rows[0]["name"].as_str()), (1, "a"));
+    assert!(command::try_execute(&mut conn, "show variables like 'no_such%'").unwrap().is_empty());
+    assert_eq!(compat::detect(&mut conn), None);
+
+    let mut stream = BinlogStream::connect(&conf(addr)).unwrap();
+    assert!(matches!(stream.read_event().unwrap().data, Traction::RotateLogEvent(_)));
+}
+
+#[test]
+fn maxscale() {
+    let addr = server("maxscale").variable("maxscale_version", "2.4.17").serve("127.0.0.1:0").unwrap();
+    let mut conn = connection::create_mysql_conn(&conf(addr)).unwrap();
+    assert_eq!(compat::detect(&mut conn), Some(Profile::MaxScale));
+
+    let err = BinlogStream::connect(&conf(addr)).err().unwrap();
+    assert!(err.to_string().contains("--compat maxscale"), "{}", err);
+    let gtid = Config{ compat: String::from("maxscale"), gtid: String::from("3e11fa47-71ca-11e1-9e33-c80aa9429562:1-5"), ..conf(addr) };
+    let err = BinlogStream::connect(&gtid).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert!(err.to_string().contains("COM_BINLOG_DUMP_GTID"), "{}", err);
+
+    let mut stream = BinlogStream::connect(&Config{ compat: String::from("maxscale"), ..conf(addr) }).unwrap();
+    assert!(matches!(stream.read_event().unwrap().data, Traction::RotateLogEvent(_)));
+}
+
+#[test]
+fn proxysql() {
+    let addr = server("proxysql").variable("version_comment", "(ProxySQL)").serve("127.0.0.1:0").unwrap();
+    let mut conn = connection::create_mysql_conn(&conf(addr)).unwrap();
+    assert_eq!(compat::detect(&mut conn), Some(Profile::ProxySql));
+    //指定--compat proxysql时同样不能拉取binlog
+    for compat in ["", "proxysql", "rds"] {
+        let err = BinlogStream::connect(&Config{ compat: compat.to_string(), ..conf(addr) }).err().unwrap();
+        assert!(err.to_string().contains("ProxySQL不转发复制协议"), "{}", err);
+    }
+}
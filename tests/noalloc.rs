@@ -0,0 +1,82 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/06
+*/
+
+//parse_noalloc与BinlogReader对同一文件的解析结果一致
+
+use std::fs;
+use std::path::PathBuf;
+use mytest::noalloc::{parse_noalloc, RawCell};
+use mytest::prelude::*;
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)).unwrap()
+}
+
+fn compare(data: &[u8]) {
+    let expected: Vec<(u64, Vec<Vec<Option<MySQLValue>>>)> = BinlogReader::new(std::io::Cursor::new(data.to_vec())).unwrap()
+        .filter_map(|event| {
+            let event = event.unwrap();
+            match event.data {
+                Traction::RowEvent(_, rows) => Some((event.log_pos, rows.rows().to_vec())),
+                _ => None
+            }
+        }).collect();
+
+    let mut got = vec![];
+    let mut map = None;
+    let mut events = parse_noalloc(data).unwrap();
+    for event in &mut events {
+        let event = event.unwrap();
+        match event.header.type_code {
+            BinlogEvent::TableMapEvent => map = Some(event.table_map().unwrap()),
+            BinlogEvent::WriteEvent |
+            BinlogEvent::UpdateEvent |
+            BinlogEvent::DeleteEvent => {
+                let mut rows: Vec<Vec<Option<MySQLValue>>> = vec![];
+                for cell in event.rows(map.as_ref().unwrap()).unwrap() {
+                    let RawCell{ row, value, .. } = cell.unwrap();
+                    if rows.len() == row {
+                        rows.push(vec![]);
+                    }
+                    rows[row].push(Some(value.to_value().unwrap()));
+                }
+                got.push((event.log_pos, rows));
+            }
+            _ => {}
+        }
+    }
+    assert!(events.checksum());
+    assert!(!expected.is_empty());
+    assert_eq!(got, expected);
+}
+
+#[test]
+fn same_as_reader() {
+    for name in ["mysql56.binlog", "mysql57.binlog", "mysql80.binlog"].iter() {
+        compare(&fixture(name));
+    }
+}
+
+#[test]
+fn table_map_borrowed() {
+    let data = fixture("mysql57.binlog");
+    let event = parse_noalloc(&data).unwrap().map(|e| e.unwrap())
+        .find(|e| matches!(e.header.type_code, BinlogEvent::TableMapEvent)).unwrap();
+    let map = event.table_map().unwrap();
+    assert_eq!(map.database, b"golden");
+    assert_eq!(map.table, b"all_types");
+    assert_eq!(map.column_count(), 19);
+    assert!(event.rows(&map).is_err());
+}
+
+#[test]
+fn truncated() {
+    let data = fixture("mysql80.binlog");
+    let events: Vec<_> = parse_noalloc(&data[..data.len() - 3]).unwrap().collect();
+    let last = events.last().unwrap();
+    assert_eq!(last.as_ref().unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+    assert!(events[..events.len() - 1].iter().all(|e| e.is_ok()));
+    assert!(parse_noalloc(b"not a binlog").is_err());
+}
@@ -0,0 +1,174 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//没有主键的表: 按表结构或metadata识别主键, sql按所有字段匹配(full-row追加LIMIT 1), 分区按所有字段的hash(hash-key), 或不输出(skip)
+#![cfg(all(feature = "test-support", feature = "os", feature = "json-sink"))]
+
+mod common;
+
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+use std::sync::{Arc, Mutex};
+use mytest::nokey::{self, NoKeyPolicy};
+use mytest::prelude::*;
+use mytest::replication::readevent::{QueryEvent, XidEvent};
+use mytest::replication::tablemeta::TableMetadata;
+use mytest::sink::{ApplyExecutor, ApplySink, Partitioner, PartitionedSink};
+use mytest::stdout::dialect::Dialect;
+use mytest::stdout::outsql;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+use mytest::warning::{self, WarningKind};
+use common::{int_row, int_table};
+
+fn col(name: &str, key: &str, nullable: &str) -> HashMap<String, String> {
+    let mut col = HashMap::new();
+    col.insert(String::from("COLUMN_NAME"), name.to_string());
+    col.insert(String::from("COLUMN_TYPE"), String::from("int(11)"));
+    col.insert(String::from("COLUMN_KEY"), key.to_string());
+    col.insert(String::from("IS_NULLABLE"), nullable.to_string());
+    col
+}
+
+//一个事务中写入、修改、删除没有主键的表
+fn binlog(name: &str) -> Vec<Event> {
+    let map = int_table(name);
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.query(&QueryEvent{ thread_id: 1, execute_seconds: 0, database: String::from("shop"), command: String::from("BEGIN"), invoker: String::new() });
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, &(0..32).map(|t| int_row(t, t)).collect::<Vec<_>>()).unwrap();
+    builder.rows(&BinlogEvent::UpdateEvent, 100, &map, &[int_row(1, 1), int_row(1, 2)]).unwrap();
+    builder.rows(&BinlogEvent::DeleteEvent, 100, &map, &[int_row(2, 2)]).unwrap();
+    builder.xid(&XidEvent{ xid: 1 });
+    BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap().collect::<io::Result<Vec<Event>>>().unwrap()
+}
+
+#[test]
+fn policy() {
+    assert_eq!(NoKeyPolicy::new("").unwrap(), NoKeyPolicy::Warn);
+    assert_eq!(NoKeyPolicy::new("full-row").unwrap(), NoKeyPolicy::FullRow);
+    assert_eq!(NoKeyPolicy::new("hash-key").unwrap().name(), "hash-key");
+    assert_eq!(NoKeyPolicy::new("skip").unwrap(), NoKeyPolicy::Skip);
+    assert!(NoKeyPolicy::new("ignore").is_err());
+    assert!(NoKeyPolicy::HashKey.for_sql().is_err());
+    assert!(NoKeyPolicy::FullRow.for_partition().is_err());
+    assert_eq!(NoKeyPolicy::Skip.for_sql().unwrap(), NoKeyPolicy::Skip);
+
+    //有主键时不处理, skip时不输出
+    assert!(NoKeyPolicy::Skip.check("test", "shop.keyed", &[0]));
+    assert!(!NoKeyPolicy::Skip.check("test", "shop.policy", &[]));
+    assert!(NoKeyPolicy::Warn.check("test", "shop.policy", &[]));
+    assert!(warning::count(WarningKind::NoPrimaryKey) >= 2);
+
+    assert_eq!(outsql::limit_one(String::from("DELETE FROM `t` WHERE `a`=1;"), Dialect::Mysql), "DELETE FROM `t` WHERE `a`=1 LIMIT 1;");
+    assert_eq!(outsql::limit_one(String::from("DELETE FROM \"t\" WHERE \"a\"=1;"), Dialect::Postgresql), "DELETE FROM \"t\" WHERE \"a\"=1;");
+}
+
+#[test]
+fn key_columns() {
+    let map = int_table("t");
+    assert_eq!(nokey::key_columns(&[col("a", "", "NO"), col("b", "PRI", "NO")], &map), vec![1]);
+    //非空的唯一键
+    assert_eq!(nokey::key_columns(&[col("a", "", "NO"), col("b", "UNI", "NO")], &map), vec![1]);
+    //可以为NULL的唯一键不能定位行
+    assert!(nokey::key_columns(&[col("a", "", "NO"), col("b", "UNI", "YES")], &map).is_empty());
+    assert!(nokey::key_columns(&[], &map).is_empty());
+    //没有表结构时按metadata
+    let map = TableMap{ metadata: Some(TableMetadata{ primary_key: vec![(0, 0)], ..Default::default() }), ..int_table("t") };
+    assert_eq!(nokey::key_columns(&[], &map), vec![0]);
+}
+
+//每个分区记录的行数据的第一个字段
+type Records = Arc<Mutex<Vec<Vec<i64>>>>;
+
+struct Recorder{
+    partition: usize,
+    records: Records,
+}
+
+impl Sink for Recorder {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        if let Traction::RowEvent(_, rows) = &event.data {
+            self.records.lock().unwrap()[self.partition].extend(rows.rows().iter().map(|row| match row[0] {
+                Some(MySQLValue::SignedInteger(t)) => t,
+                _ => -1
+            }));
+        }
+        Ok(())
+    }
+}
+
+fn partition(name: &str, partitioner: &str, no_key: &str) -> Vec<Vec<i64>> {
+    let partitioner = Partitioner::new(partitioner).unwrap();
+    let records: Records = Arc::new(Mutex::new(vec![vec![]; partitioner.partitions()]));
+    let recorded = records.clone();
+    let mut sink = PartitionedSink::new(partitioner, move |partition| Ok(Box::new(Recorder{ partition, records: recorded.clone() }) as Box<dyn Sink>)).unwrap()
+        .with_no_key(NoKeyPolicy::new(no_key).unwrap().for_partition().unwrap());
+    for event in binlog(name) {
+        sink.write_event(&event).unwrap();
+    }
+    let records = records.lock().unwrap().clone();
+    records
+}
+
+#[test]
+fn partitioned() {
+    //默认整张表在同一个分区
+    let records = partition("p_warn", "hash:4", "");
+    assert_eq!(records.iter().filter(|t| !t.is_empty()).count(), 1);
+
+    //按所有字段的hash分区, range分区时也按hash
+    for partitioner in ["hash:4", "range:10,20,30"] {
+        let records = partition("p_hash", partitioner, "hash-key");
+        assert!(records.iter().filter(|t| !t.is_empty()).count() > 1, "{:?}", records);
+        assert_eq!(records.iter().map(|t| t.len()).sum::<usize>(), 32 + 2 + 1);
+        //update的前后镜像不同, 拆分为delete及insert
+        let hash = Partitioner::Hash(4);
+        let expect = |id: i64, value: i64| hash.partition("shop.p_hash", &int_row(id, value), &[0, 1]).unwrap();
+        assert!(records[expect(2, 2)].iter().filter(|t| **t == 2).count() == 2);
+        assert!(records[expect(1, 2)].contains(&1));
+    }
+
+    let records = partition("p_skip", "hash:4", "skip");
+    assert!(records.iter().all(|t| t.is_empty()));
+}
+
+#[derive(Clone, Default)]
+struct Executor{
+    statements: Arc<Mutex<Vec<String>>>,
+}
+
+impl ApplyExecutor for Executor {
+    fn execute(&mut self, sql: &str) -> Result<u64, String> {
+        self.statements.lock().unwrap().push(sql.to_string());
+        Ok(1)
+    }
+}
+
+fn apply(name: &str, no_key: NoKeyPolicy) -> Vec<String> {
+    let executor = Executor::default();
+    let mut columns = HashMap::new();
+    columns.insert(format!("shop.{}", name), vec![col("id", "", "NO"), col("value", "MUL", "NO")]);
+    let mut sink = ApplySink::new(vec![Box::new(executor.clone())]).with_columns(columns).with_no_key(no_key);
+    for event in binlog(name) {
+        sink.write_event(&event).unwrap();
+    }
+    sink.flush().unwrap();
+    drop(sink);
+    let statements = executor.statements.lock().unwrap().clone();
+    statements.into_iter().filter(|t| t.starts_with("UPDATE") || t.starts_with("DELETE")).collect()
+}
+
+#[test]
+fn applied() {
+    assert_eq!(apply("a_warn", NoKeyPolicy::Warn), vec![
+        "UPDATE `shop`.`a_warn` SET `id`=1, `value`=2 WHERE `id`=1 AND `value`=1;",
+        "DELETE FROM `shop`.`a_warn`  WHERE `id`=2 AND `value`=2;",
+    ]);
+    assert_eq!(apply("a_full", NoKeyPolicy::FullRow), vec![
+        "UPDATE `shop`.`a_full` SET `id`=1, `value`=2 WHERE `id`=1 AND `value`=1 LIMIT 1;",
+        "DELETE FROM `shop`.`a_full`  WHERE `id`=2 AND `value`=2 LIMIT 1;",
+    ]);
+    assert!(apply("a_skip", NoKeyPolicy::Skip).is_empty());
+}
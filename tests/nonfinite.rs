@@ -0,0 +1,134 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//float/double: 按metadata中的字节数读取, NaN、Infinity按--nonfinite输出为null、字符串或停止解析
+#![cfg(all(feature = "json-sink", feature = "test-support"))]
+
+use std::io::Cursor;
+use serde_json::{json, Value as JsonValue};
+use mytest::Config;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::stdout::format::{BinaryEncoding, NonFinite};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+fn table(float_meta: usize) -> TableMap {
+    TableMap{
+        database_name: String::from("test"),
+        table_name: String::from("t"),
+        column_count: 3,
+        column_info: vec![
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeFloat, column_meta: vec![float_meta] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeDouble, column_meta: vec![8] },
+        ],
+        metadata: None,
+    }
+}
+
+fn rows() -> Vec<Vec<Option<MySQLValue>>> {
+    vec![
+        vec![Some(MySQLValue::SignedInteger(1)), Some(MySQLValue::Float(0.1)), Some(MySQLValue::Double(-2.5e300))],
+        vec![Some(MySQLValue::SignedInteger(2)), Some(MySQLValue::Float(f32::INFINITY)), Some(MySQLValue::Double(f64::NAN))],
+        vec![Some(MySQLValue::SignedInteger(3)), Some(MySQLValue::Float(f32::NEG_INFINITY)), Some(MySQLValue::Double(1.0))],
+    ]
+}
+
+fn binlog() -> Vec<u8> {
+    let map = table(4);
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, &rows()).unwrap();
+    builder.into_bytes()
+}
+
+fn format(mode: &str) -> ValueFormat {
+    ValueFormat::new(&Config{ nonfinite: mode.to_string(), ..Config::default() }).unwrap()
+}
+
+#[test]
+fn decode() {
+    let reader = BinlogReader::new(Cursor::new(binlog())).unwrap();
+    let decoded = reader.filter_map(|e| match e.unwrap().data {
+        Traction::RowEvent(_, t) => Some(t.rows().to_vec()),
+        _ => None
+    }).next().unwrap();
+    assert_eq!(decoded[0][1], Some(MySQLValue::Float(0.1)));
+    assert_eq!(decoded[0][2], Some(MySQLValue::Double(-2.5e300)));
+    assert_eq!(decoded[1][1], Some(MySQLValue::Float(f32::INFINITY)));
+    assert!(matches!(decoded[1][2], Some(MySQLValue::Double(t)) if t.is_nan()));
+
+    //float按最短表示输出, 不转换为f64后输出0.10000000149011612
+    let format = format("");
+    assert_eq!(format.json_value(&decoded[0][1], "float", BinaryEncoding::Hex), json!(0.1));
+    assert_eq!(format.text_value(&decoded[0][1], "float", BinaryEncoding::Hex), Some(String::from("0.1")));
+
+    //metadata不是4、8时无法确定字节数, 返回错误而不是错位解析之后的字段
+    let map = table(3);
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &table(4), &rows()[..1]).unwrap();
+    let reader = BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap();
+    let err = reader.filter_map(|e| e.err()).next().unwrap();
+    assert!(err.to_string().contains("MysqlTypeFloat metadata: 3"), "{}", err);
+}
+
+#[test]
+fn modes() {
+    let inf = Some(MySQLValue::Float(f32::INFINITY));
+    let neg = Some(MySQLValue::Double(f64::NEG_INFINITY));
+    let nan = Some(MySQLValue::Double(f64::NAN));
+
+    let null = format("");
+    assert_eq!(null.nonfinite, NonFinite::Null);
+    assert_eq!(null.json_value(&inf, "float", BinaryEncoding::Hex), JsonValue::Null);
+    assert_eq!(null.text_value(&nan, "double", BinaryEncoding::Hex), None);
+    assert_eq!(null.float_sql(nan.as_ref().unwrap()), "NULL");
+    assert_eq!(null.float_sql(&MySQLValue::Double(1.5)), "1.5");
+
+    let text = format("string");
+    assert_eq!(text.json_value(&inf, "float", BinaryEncoding::Hex), json!("Infinity"));
+    assert_eq!(text.json_value(&neg, "double", BinaryEncoding::Hex), json!("-Infinity"));
+    assert_eq!(text.text_value(&nan, "double", BinaryEncoding::Hex), Some(String::from("NaN")));
+    assert_eq!(text.float_sql(neg.as_ref().unwrap()), "'-Infinity'");
+
+    assert!(ValueFormat::new(&Config{ nonfinite: String::from("zero"), ..Config::default() }).unwrap_err().contains("nonfinite"));
+    assert!(format("").check_finite(&rows(), "test.t").is_ok());
+    let err = format("error").check_finite(&rows(), "test.t").unwrap_err();
+    assert!(err.contains("test.t第2个字段的值为Infinity"), "{}", err);
+}
+
+#[test]
+fn sink() {
+    let write = |mode: &str| -> (std::io::Result<()>, Vec<JsonValue>) {
+        let mut buf = vec![];
+        let mut result = Ok(());
+        {
+            let mut sink = JsonLinesSink::new(&mut buf, format(mode));
+            for event in BinlogReader::new(Cursor::new(binlog())).unwrap() {
+                result = result.and_then(|_| sink.write_event(&event.unwrap()));
+            }
+        }
+        let records = String::from_utf8(buf).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        (result, records)
+    };
+
+    let (result, records) = write("");
+    assert!(result.is_ok());
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[1]["data"]["@2"], JsonValue::Null);
+    assert_eq!(records[1]["data"]["@3"], JsonValue::Null);
+
+    let (_, records) = write("string");
+    assert_eq!(records[1]["data"]["@2"], json!("Infinity"));
+    assert_eq!(records[2]["data"]["@2"], json!("-Infinity"));
+    assert_eq!(records[1]["data"]["@3"], json!("NaN"));
+
+    //整个行事件都不输出
+    let (result, records) = write("error");
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    assert!(records.is_empty());
+}
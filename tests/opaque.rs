@@ -0,0 +1,107 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//未知的字段类型: 默认按blob的格式读取为Opaque, 之后的字段位置正确; 注册解码器后按自定义的格式解析
+#![cfg(feature = "test-support")]
+
+use std::io::{self, Cursor};
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::opaque::{self, OpaqueDecoder};
+use mytest::replication::readevent::ColumnInfo;
+use mytest::stdout::format::BinaryEncoding;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+fn table(code: u8, meta: Vec<usize>) -> TableMap {
+    TableMap{
+        database_name: String::from("test"),
+        table_name: String::from("t"),
+        column_count: 3,
+        column_info: vec![
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] },
+            ColumnInfo{ column_type: ColumnTypeDict::from_type_code(&code), column_meta: meta },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeVarchar, column_meta: vec![1] },
+        ],
+        metadata: None,
+    }
+}
+
+fn read(map: &TableMap, rows: &[Vec<Option<MySQLValue>>]) -> (TableMap, Vec<Vec<Option<MySQLValue>>>) {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.table_map(100, map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, map, rows).unwrap();
+    let mut parsed = None;
+    for event in BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap() {
+        match event.unwrap().data {
+            Traction::TableMapEvent(t) => parsed = Some(t),
+            Traction::RowEvent(_, t) => return (parsed.unwrap(), t.rows().to_vec()),
+            _ => {}
+        }
+    }
+    panic!("没有行事件");
+}
+
+//9.0的VECTOR(类型码242): metadata为长度占用的字节数(4), 值为float数组
+#[test]
+fn raw() {
+    let map = table(242, vec![4]);
+    let vector: Vec<u8> = [1.5f32, -2.0].iter().flat_map(|t| t.to_le_bytes()).collect();
+    let row = vec![
+        Some(MySQLValue::SignedInteger(1)),
+        Some(MySQLValue::Opaque{ type_code: 242, bytes: vector.clone() }),
+        Some(MySQLValue::Blob(b"after".to_vec())),
+    ];
+    let (parsed, rows) = read(&map, std::slice::from_ref(&row));
+    assert!(matches!(parsed.column_info[1].column_type, ColumnTypeDict::UnknowType(242)));
+    assert_eq!(parsed.column_info[1].column_meta, vec![4]);
+    assert_eq!(rows, vec![row.clone()]);
+    assert!(opaque::unknown_column_types().contains(&242));
+
+    //按二进制类型输出
+    let format = ValueFormat::default();
+    assert_eq!(format.text_value(&row[1], "", BinaryEncoding::Hex), Some(format!("0x{}", hex::encode(&vector))));
+}
+
+struct Fixed;
+
+//metadata为2bytes, 值为定长的metadata[0]个字节, 按大端整数解析
+impl OpaqueDecoder for Fixed {
+    fn meta_len(&self) -> usize {
+        2
+    }
+
+    fn length_bytes(&self, _meta: &[usize]) -> usize {
+        0
+    }
+
+    fn fixed_len(&self, meta: &[usize]) -> usize {
+        meta[0]
+    }
+
+    fn decode(&self, type_code: u8, bytes: Vec<u8>, _meta: &[usize]) -> io::Result<MySQLValue> {
+        if bytes.first() == Some(&0xff) {
+            return Ok(MySQLValue::Opaque{ type_code, bytes });
+        }
+        Ok(MySQLValue::SignedInteger(bytes.iter().fold(0i64, |acc, b| acc << 8 | *b as i64)))
+    }
+}
+
+#[test]
+fn registered() {
+    assert!(opaque::register(3, Fixed).unwrap_err().contains("已支持"));
+    opaque::register(243, Fixed).unwrap();
+    let map = table(243, vec![3, 9]);
+    let row = |bytes: &[u8]| vec![
+        Some(MySQLValue::SignedInteger(2)),
+        Some(MySQLValue::Opaque{ type_code: 243, bytes: bytes.to_vec() }),
+        Some(MySQLValue::Blob(b"x".to_vec())),
+    ];
+    let (parsed, rows) = read(&map, &[row(&[1, 2, 3]), row(&[0xff, 0, 1])]);
+    assert_eq!(parsed.column_info[1].column_meta, vec![3, 9]);
+    assert_eq!(rows[0][1], Some(MySQLValue::SignedInteger(0x010203)));
+    assert_eq!(rows[1], row(&[0xff, 0, 1]));
+    assert!(!opaque::unknown_column_types().contains(&243));
+    opaque::unregister(243);
+}
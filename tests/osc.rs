@@ -0,0 +1,53 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//gh-ost、pt-online-schema-change的改表过程: 创建影子表、切换、取消, 影子表按原表名输出
+
+use mytest::osc::{shadow_of, Notice, Tool, Tracker};
+
+#[test]
+fn shadow_tables() {
+    assert_eq!(shadow_of("_orders_gho"), Some((Tool::GhOst, "orders")));
+    assert_eq!(shadow_of("_order_items_new"), Some((Tool::PtOsc, "order_items")));
+    assert_eq!(shadow_of("_orders_ghc"), None);
+    assert_eq!(shadow_of("orders_new"), None);
+    assert_eq!(shadow_of("__gho"), None);
+
+    assert_eq!(Tracker::new(false).remap("_orders_gho"), None);
+    assert_eq!(Tracker::new(true).remap("_orders_gho"), Some("orders"));
+    assert_eq!(Tracker::new(true).remap("_orders_del"), None);
+}
+
+#[test]
+fn gh_ost_cut_over() {
+    let mut tracker = Tracker::new(true);
+    let started = tracker.observe("", "create /* gh-ost */ table `shop`.`_orders_gho` like `shop`.`orders`");
+    assert_eq!(started, vec![Notice::Started{ tool: Tool::GhOst, database: String::from("shop"), table: String::from("orders") }]);
+    assert!(started[0].message().contains("_orders_gho"));
+    assert!(tracker.observe("shop", "alter /* gh-ost */ table `shop`.`_orders_gho` add column note varchar(20)").is_empty());
+    assert!(tracker.observe("shop", "create /* gh-ost */ table `shop`.`_orders_ghc` (id bigint)").is_empty());
+
+    let cut_over = tracker.observe("shop", "rename /* gh-ost */ table `shop`.`orders` to `shop`.`_orders_del`, `shop`.`_orders_gho` to `shop`.`orders`");
+    assert_eq!(cut_over, vec![Notice::CutOver{ tool: Tool::GhOst, database: String::from("shop"), table: String::from("orders") }]);
+    assert_eq!(cut_over[0].table(), ("shop", "orders"));
+    //切换后删除旧表不是取消
+    assert!(tracker.observe("shop", "drop /* gh-ost */ table if exists `shop`.`_orders_del`").is_empty());
+}
+
+#[test]
+fn pt_osc() {
+    let mut tracker = Tracker::new(false);
+    assert_eq!(tracker.observe("shop", "CREATE TABLE `_items_new` (`id` int NOT NULL, PRIMARY KEY (`id`))").len(), 1);
+    let cut_over = tracker.observe("", "RENAME TABLE `shop`.`items` TO `shop`.`_items_old`, `shop`.`_items_new` TO `shop`.`items`;");
+    assert_eq!(cut_over, vec![Notice::CutOver{ tool: Tool::PtOsc, database: String::from("shop"), table: String::from("items") }]);
+
+    //切换前删除影子表为取消
+    tracker.observe("shop", "CREATE TABLE IF NOT EXISTS _users_new LIKE users");
+    let aborted = tracker.observe("shop", "DROP TABLE IF EXISTS `shop`.`_users_new`, `shop`.`t`");
+    assert_eq!(aborted, vec![Notice::Aborted{ tool: Tool::PtOsc, database: String::from("shop"), table: String::from("users") }]);
+    assert!(tracker.observe("shop", "DROP TABLE `_users_new`").is_empty());
+    assert!(tracker.observe("shop", "RENAME TABLE a TO b").is_empty());
+    assert!(tracker.observe("shop", "insert into `_x_new` values(1)").is_empty());
+}
@@ -0,0 +1,78 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--packet-trace: 记录握手、capability flags、dump命令及事件, 隐藏认证数据及语句中的密码
+
+#![cfg(all(feature = "test-support", feature = "network"))]
+
+use std::env;
+use std::fs;
+use mytest::Config;
+use mytest::io::{command, connection, trace};
+use mytest::master::BinlogServer;
+use mytest::prelude::*;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+#[test]
+fn trace_file() {
+    let dir = env::temp_dir().join(format!("mytest-packettrace-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.timestamp(1572566400).previous_gtids(&GtidSet::new());
+    fs::write(dir.join("bin.000001"), builder.into_bytes()).unwrap();
+    let addr = BinlogServer::open_dir(&dir).unwrap().credentials("repl", "s3cr3t-pass").serve("127.0.0.1:0").unwrap();
+    let path = dir.join("trace.log");
+    let conf = Config{
+        host_info: addr.to_string(),
+        user_name: String::from("repl"),
+        password: String::from("s3cr3t-pass").into(),
+        program_name: String::from("mytest"),
+        serverid: String::from("1001"),
+        binlogfile: String::from("bin.000001"),
+        position: String::from("4"),
+        packettrace: path.to_string_lossy().to_string(),
+        packettracebytes: String::from("16"),
+        ..Config::default()
+    };
+
+    let mut conn = connection::create_mysql_conn(&conf).unwrap();
+    let _ = command::try_execute(&mut conn, "select 'a' as name");
+    let _ = command::try_execute_update(&mut conn, "SET PASSWORD = 's3cr3t-pass'");
+    let mut stream = BinlogStream::connect(&conf).unwrap();
+    assert!(matches!(stream.read_event().unwrap().data, Traction::RotateLogEvent(_)));
+    assert_eq!(stream.read_event().unwrap().header.event_type, EventType::FormatDescriptionEvent);
+    trace::close();
+
+    let content = fs::read_to_string(&path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(content.matches(" connect ").count(), 2);
+    assert!(content.contains("<- seq=0"));
+    assert!(content.contains("Handshake version="));
+    assert!(content.contains("HandshakeResponse capabilities="));
+    assert!(content.contains("PROTOCOL_41"));
+    assert!(content.contains("user=repl"));
+    assert!(content.contains("OK 认证完成"));
+    assert!(content.contains("COM_QUERY select 'a' as name"));
+    assert!(content.contains("COM_QUERY SET PASSWORD = '***'"));
+    assert!(content.contains("COM_BINLOG_DUMP position=4 flags=0x0000 server_id=1001 file=bin.000001"));
+    assert!(content.contains("RotateEvent") || content.contains("RotateLogEvent"), "{}", content);
+    assert!(content.contains("FormatDescriptionEvent"));
+    //认证数据、密码不出现在文件中
+    assert!(!content.contains("s3cr3t"));
+    assert!(!content.contains(&hex_of("s3cr3t")));
+    //十六进制每个包最多16个字节
+    assert!(content.lines().filter(|l| l.starts_with("    ")).all(|l| l.trim_end_matches(" ...").split(' ').filter(|t| !t.is_empty()).count() <= 16));
+}
+
+fn hex_of(text: &str) -> String {
+    text.bytes().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+#[test]
+fn capability_names() {
+    assert_eq!(trace::capability_names(0x200 | 0x80000), "PROTOCOL_41|PLUGIN_AUTH");
+    assert_eq!(trace::capability_names(0x8000_0000), "0x80000000");
+}
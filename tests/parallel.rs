@@ -0,0 +1,82 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//多线程序列化: 输出内容与JsonLinesSink逐字节相同, 转换失败时返回错误
+#![cfg(all(feature = "os", feature = "json-sink", feature = "test-support"))]
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use mytest::Config;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+use mytest::sink::ParallelJsonSink;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+fn write<S: Sink>(mut sink: S, events: &[Event]) -> std::io::Result<()> {
+    for event in events {
+        sink.write_event(event)?;
+    }
+    sink.flush()
+}
+
+#[test]
+fn same_output() {
+    let opts = SynthOptions{
+        database: String::from("wide"),
+        tables: 3,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16), ColumnKind::Int, ColumnKind::Varchar(32)],
+        transactions: 200,
+        rows: (1, 20),
+        rate: 100,
+        mix: [3, 2, 1],
+        start_time: 1572998400,
+        seed: 7,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-parallel-{}.binlog", std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    let events: Vec<Event> = BinlogReader::open(path.to_str().unwrap()).unwrap().map(|e| e.unwrap()).collect();
+    fs::remove_file(&path).unwrap();
+
+    for boundaries in [false, true] {
+        let format = ValueFormat{ boundaries, ..ValueFormat::default() };
+        let mut expected = vec![];
+        write(JsonLinesSink::new(&mut expected, format.clone()), &events).unwrap();
+        for workers in [1, 4] {
+            let mut buf = vec![];
+            write(ParallelJsonSink::new(&mut buf, format.clone(), workers), &events).unwrap();
+            assert_eq!(String::from_utf8(buf).unwrap(), String::from_utf8(expected.clone()).unwrap(), "workers: {}", workers);
+        }
+    }
+}
+
+#[test]
+fn error() {
+    let map = TableMap{
+        database_name: String::from("test"),
+        table_name: String::from("t"),
+        column_count: 2,
+        column_info: vec![
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeDouble, column_meta: vec![8] },
+        ],
+        metadata: None,
+    };
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, &[vec![Some(MySQLValue::SignedInteger(1)), Some(MySQLValue::Double(1.5))]]).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, &[vec![Some(MySQLValue::SignedInteger(2)), Some(MySQLValue::Double(f64::NAN))]]).unwrap();
+    let events: Vec<Event> = BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap().map(|e| e.unwrap()).collect();
+
+    let format = ValueFormat::new(&Config{ nonfinite: String::from("error"), ..Config::default() }).unwrap();
+    let mut buf = vec![];
+    let err = write(ParallelJsonSink::new(&mut buf, format, 2), &events).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    //出错之前的行事件已经写入
+    assert_eq!(String::from_utf8(buf).unwrap().lines().count(), 1);
+}
@@ -0,0 +1,140 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//按主键分区输出: hash、range分区, 修改主键时拆分为delete、insert, 其他事件写入所有分区
+#![cfg(all(feature = "test-support", feature = "os", feature = "json-sink"))]
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::io::{self, Cursor};
+use std::sync::{Arc, Mutex};
+use serde_json::Value as JsonValue;
+use mytest::prelude::*;
+use mytest::sink::{Partitioner, PartitionedSink};
+use common::{order, orders};
+
+//每个分区记录(事件类型, 行的id)
+type Records = Arc<Mutex<Vec<Vec<(String, Vec<i64>)>>>>;
+
+struct Recorder{
+    partition: usize,
+    records: Records,
+}
+
+impl Sink for Recorder {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        let record = match &event.data {
+            Traction::GtidEvent(_) => (String::from("gtid"), vec![]),
+            Traction::XidEvent(_) => (String::from("xid"), vec![]),
+            Traction::RowEvent(code, rows) => (format!("{:?}", code), rows.rows().iter().map(|row| match row[0] {
+                Some(MySQLValue::SignedInteger(t)) => t,
+                _ => -1
+            }).collect()),
+            _ => return Ok(())
+        };
+        self.records.lock().unwrap()[self.partition].push(record);
+        Ok(())
+    }
+}
+
+fn binlog(metadata: bool) -> Vec<u8> {
+    common::transactions(&orders(metadata), &[
+        (BinlogEvent::WriteEvent, vec![order(1, Some("new")), order(15, Some("new")), order(2, Some("new")), order(30, Some("new"))]),
+        (BinlogEvent::UpdateEvent, vec![order(1, Some("new")), order(1, Some("paid")), order(2, Some("new")), order(25, Some("new")), order(15, Some("new")), order(15, Some("paid"))]),
+        (BinlogEvent::DeleteEvent, vec![order(30, Some("new"))]),
+    ]).0
+}
+
+fn run(partitioner: &str, metadata: bool) -> io::Result<Vec<Vec<(String, Vec<i64>)>>> {
+    let partitioner = Partitioner::new(partitioner).unwrap();
+    let records: Records = Arc::new(Mutex::new(vec![vec![]; partitioner.partitions()]));
+    let recorded = records.clone();
+    let mut sink = PartitionedSink::new(partitioner, move |partition| Ok(Box::new(Recorder{ partition, records: recorded.clone() }) as Box<dyn Sink>))?;
+    for event in BinlogReader::new(Cursor::new(binlog(metadata))).unwrap() {
+        sink.write_event(&event.unwrap())?;
+    }
+    let records = records.lock().unwrap().clone();
+    Ok(records)
+}
+
+fn rows(records: &[(String, Vec<i64>)]) -> Vec<(&str, Vec<i64>)> {
+    records.iter().filter(|(kind, _)| kind != "gtid" && kind != "xid").map(|(kind, ids)| (kind.as_str(), ids.clone())).collect()
+}
+
+#[test]
+fn parse() {
+    assert_eq!(Partitioner::new("hash:4").unwrap(), Partitioner::Hash(4));
+    assert_eq!(Partitioner::new("range: 10, 20").unwrap(), Partitioner::Range(vec![10, 20]));
+    assert_eq!(Partitioner::new("range:10,20").unwrap().partitions(), 3);
+    assert!(Partitioner::new("hash:0").is_err());
+    assert!(Partitioner::new("range:20,10").is_err());
+    assert!(Partitioner::new("range:").is_err());
+    assert!(Partitioner::new("mod:3").is_err());
+
+    //结果固定, 与库表及主键值有关
+    let hash = Partitioner::Hash(8);
+    let first = hash.partition("shop.orders", &order(42, Some("a")), &[0]).unwrap();
+    assert_eq!(hash.partition("shop.orders", &order(42, Some("b")), &[0]).unwrap(), first);
+    assert!((0..64).map(|id| hash.partition("shop.orders", &order(id, Some("a")), &[0]).unwrap()).any(|p| p != first));
+    let range = Partitioner::new("range:10,20").unwrap();
+    assert_eq!([9, 10, 19, 20, -5].iter().map(|id| range.partition("shop.orders", &order(*id, Some("")), &[0]).unwrap()).collect::<Vec<_>>(), vec![0, 1, 1, 2, 0]);
+    assert!(range.partition("shop.orders", &order(1, Some("")), &[1]).unwrap_err().contains("第2个字段"));
+}
+
+#[test]
+fn range() {
+    let records = run("range:10,20", true).unwrap();
+    //事务边界写入所有分区
+    for partition in &records {
+        assert_eq!(partition.iter().filter(|(kind, _)| kind == "gtid").count(), 3);
+        assert_eq!(partition.iter().filter(|(kind, _)| kind == "xid").count(), 3);
+    }
+    assert_eq!(rows(&records[0]), vec![("WriteEvent", vec![1, 2]), ("UpdateEvent", vec![1, 1]), ("DeleteEvent", vec![2])]);
+    assert_eq!(rows(&records[1]), vec![("WriteEvent", vec![15]), ("UpdateEvent", vec![15, 15])]);
+    //修改主键后不在同一个分区, 新的值以insert写入
+    assert_eq!(rows(&records[2]), vec![("WriteEvent", vec![30]), ("WriteEvent", vec![25]), ("DeleteEvent", vec![30])]);
+}
+
+#[test]
+fn hash() {
+    let records = run("hash:3", true).unwrap();
+    let hash = Partitioner::Hash(3);
+    for (idx, partition) in records.iter().enumerate() {
+        for (_, ids) in rows(partition) {
+            assert!(ids.iter().all(|id| hash.partition("shop.orders", &order(*id, Some("")), &[0]).unwrap() == idx));
+        }
+    }
+    assert_eq!(records.iter().map(|p| rows(p).iter().map(|(_, ids)| ids.len()).sum::<usize>()).sum::<usize>(), 11);
+
+    //没有主键时整张表在同一个分区
+    let records = run("hash:3", false).unwrap();
+    assert_eq!(records.iter().filter(|p| !rows(p).is_empty()).count(), 1);
+    let records = run("range:10,20", false).unwrap();
+    assert_eq!(records.iter().filter(|p| !rows(p).is_empty()).count(), 1);
+}
+
+#[test]
+fn json_files() {
+    let dir = env::temp_dir().join(format!("mytest-partition-{}", std::process::id()));
+    let template = format!("{}/orders-{{partition}}.json", dir.to_str().unwrap());
+    assert!(PartitionedSink::json_files(Partitioner::Hash(2), "/tmp/orders.json", ValueFormat::default()).is_err());
+    {
+        let mut sink = PartitionedSink::json_files(Partitioner::new("range:10").unwrap(), &template, ValueFormat::default()).unwrap();
+        for event in BinlogReader::new(Cursor::new(binlog(true))).unwrap() {
+            sink.write_event(&event.unwrap()).unwrap();
+        }
+        sink.flush().unwrap();
+    }
+    let read = |idx: usize| -> Vec<JsonValue> {
+        fs::read_to_string(dir.join(format!("orders-{}.json", idx))).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+    };
+    let (low, high) = (read(0), read(1));
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(low.iter().map(|r| r["type"].as_str().unwrap()).collect::<Vec<_>>(), vec!["insert", "insert", "update", "delete"]);
+    assert_eq!(high.iter().map(|r| r["type"].as_str().unwrap()).collect::<Vec<_>>(), vec!["insert", "insert", "insert", "update", "delete"]);
+    assert_eq!(high[2]["data"]["@1"], JsonValue::from(25));
+}
@@ -0,0 +1,185 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//多管道: 配置文件解析, 每条管道单独启动、失败、重新启动, http接口按管道名转发
+
+#![cfg(all(feature = "network", feature = "json-sink"))]
+
+use std::thread;
+use std::time::{Duration, Instant};
+use mytest::Config;
+use mytest::pipeline::{self, Pipeline, PipelineState, Runtime};
+use mytest::prelude::*;
+
+const CONFIG: &str = "
+# 所有管道共用的连接信息
+[pipeline]
+user = repl
+password = 'secret'
+heartbeat = 5
+
+[pipeline.orders]
+host = 10.0.0.1
+gtid = 3e11fa47-71ca-11e1-9e33-c80aa9429562:1-100
+tables = shop.orders, shop.users
+where = orders.status != 'test'
+output = /tmp/orders.json
+checkpoint = /tmp/orders.ckpt
+
+[pipeline.audit]
+host = 10.0.0.2:3307
+user = audit
+binlogfile = mysql-bin.000003
+output = -
+checkpoint-interval = 0.5
+";
+
+#[test]
+fn parse() {
+    let pipelines = pipeline::parse_config(CONFIG).unwrap();
+    let names: Vec<&str> = pipelines.iter().map(|p| p.name()).collect();
+    assert_eq!(names, vec!["orders", "audit"]);
+
+    let errors = [
+        ("[pipeline]\nuser = a\n", "没有定义管道"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\n", "output"),
+        ("[pipeline.a]\nhost = h\nuser = u\noutput = -\n", "gtid"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\nfoo = 1\n", "foo"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\nwhere = a ==\n", "a:"),
+        ("[pipeline]\nhost = h\nuser = u\ngtid = x:1\noutput = -\nserverid = 10\n[pipeline.a]\n[pipeline.b]\n", "serverid 10"),
+        ("[pipeline.a]\nhost = h\n[pipeline.a]\n", "重复"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\ncompress = brotli\n", "brotli"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\ncompat = tidb\n", "tidb"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\nexclude-tables = shop\n", "exclude-tables"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\nlabels = shard\n", "labels"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\nlabels = shard=1\nshard = 2\n", "shard"),
+        ("[pipeline]\nhost = h\nuser = u\ngtid = x:1\noutput = /tmp/merged.json\n[pipeline.a]\nserverid = 1\n[pipeline.b]\nserverid = 2\ncompress = gzip\n", "compress"),
+        ("[pipeline]\nhost = h\nuser = u\ngtid = x:1\noutput = /tmp/merged.json\n[pipeline.a]\nserverid = 1\nmerge-window = 5\n[pipeline.b]\nserverid = 2\n", "merge-window"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\nmerge-window = soon\n", "merge-window"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\nboundaries = maybe\n", "boundaries"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\nserialize-workers = 0\n", "serialize-workers"),
+        ("[pipeline.a]\nrelay-log = /tmp/relay-bin.index\nhost = h\noutput = -\n", "user"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = -\napply-audit = audit.changes\n", "apply-audit"),
+        ("[pipeline.a]\nhost = h\nuser = u\ngtid = x:1\noutput = mysql://u:p@h\napply-audit = changes\n", "审计表"),
+    ];
+    for (content, expected) in errors.iter() {
+        let err = pipeline::parse_config(content).err().unwrap_or_default();
+        assert!(err.contains(expected), "{}: {}", content, err);
+    }
+    //init-command可以配置多行
+    assert!(pipeline::parse_config("[pipeline.a]\nhost = ::1\nuser = u\ngtid = x:1\noutput = -\ninit-command = SET NAMES utf8mb4\ninit-command = SET @a = 1\n").is_ok());
+    //读取relay log时不需要主库的连接信息及起始位置
+    assert!(pipeline::parse_config("[pipeline.a]\nrelay-log = /tmp/relay-bin.index\noutput = -\n").is_ok());
+    //未配置serverid时按顺序分配, 不会重复
+    assert!(pipeline::parse_config("[pipeline]\nhost = h\nuser = u\ngtid = x:1\noutput = -\n[pipeline.a]\n[pipeline.b]\n").is_ok());
+}
+
+fn unreachable(name: &str) -> Pipeline {
+    let conf = Config{
+        host_info: String::from("127.0.0.1:1"),
+        user_name: String::from("repl"),
+        gtid: String::from("3e11fa47-71ca-11e1-9e33-c80aa9429562:1-100"),
+        serverid: String::from("133"),
+        ..Config::default()
+    };
+    Pipeline::new(name, conf, |_| Ok(Box::new(JsonLinesSink::new(std::io::sink(), ValueFormat::default())) as Box<dyn Sink>))
+}
+
+fn wait_state(runtime: &Runtime, name: &str, state: PipelineState) {
+    let start = Instant::now();
+    while runtime.metrics(name).unwrap().state != state {
+        assert!(start.elapsed() < Duration::from_secs(10), "{:?}", runtime.metrics(name));
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn lifecycle() {
+    let runtime = Runtime::new();
+    runtime.add(unreachable("a")).unwrap();
+    runtime.add(unreachable("b")).unwrap();
+    assert!(runtime.add(unreachable("a")).is_err());
+    assert_eq!(runtime.names(), vec!["a", "b"]);
+
+    //一条管道失败不影响其他管道
+    runtime.start("a").unwrap();
+    runtime.wait("a").unwrap();
+    let metrics = runtime.metrics("a").unwrap();
+    assert_eq!(metrics.state, PipelineState::Failed);
+    assert!(metrics.last_error.is_some());
+    assert_eq!(runtime.metrics("b").unwrap().state, PipelineState::Stopped);
+
+    runtime.restart("a").unwrap();
+    runtime.wait("a").unwrap();
+    assert_eq!(runtime.metrics("a").unwrap().restarts, 1);
+    assert!(runtime.start("c").is_err());
+}
+
+#[test]
+fn http() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use serde_json::Value as JsonValue;
+
+    let runtime = Runtime::new();
+    runtime.add(unreachable("a")).unwrap();
+    runtime.add(unreachable("b")).unwrap();
+    let addr = runtime.serve("127.0.0.1:0").unwrap();
+    let request = |method: &str, target: &str| -> (u16, JsonValue) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n", method, target).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        (response[9..12].parse().unwrap(), serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap())
+    };
+
+    let (code, list) = request("GET", "/pipelines");
+    assert_eq!(code, 200);
+    assert_eq!(list.as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect::<Vec<&str>>(), vec!["a", "b"]);
+    assert_eq!(request("POST", "/pipelines/a/start").0, 200);
+    wait_state(&runtime, "a", PipelineState::Failed);
+    let (code, status) = request("GET", "/pipelines/a");
+    assert_eq!((code, status["state"].as_str()), (200, Some("failed")));
+    assert!(status["last_error"].is_string());
+
+    //其余路径转发到管道的Controller
+    assert_eq!(request("POST", "/pipelines/b/pause").1["paused"], true);
+    assert!(runtime.controller("b").unwrap().is_paused());
+    assert_eq!(request("GET", "/pipelines/b/readyz").0, 503);
+    assert_eq!(request("GET", "/pipelines/c").0, 404);
+    assert_eq!(request("GET", "/pipelines/a/unknown").0, 404);
+    //失败的管道未连接, 就绪检查失败
+    let (code, body) = request("GET", "/readyz");
+    assert_eq!(code, 503);
+    assert_eq!(body["failed"][0]["name"], "a");
+    assert_eq!(request("GET", "/healthz").0, 200);
+}
+
+#[test]
+fn shards() {
+    //每个分片一条管道, 输出合并到同一个文件
+    let config = "[pipeline]\nuser = repl\ngtid = x:1\noutput = /tmp/merged.json\nlabels = env=prod\n\
+                  merge-window = 5\n[pipeline.s0]\nhost = 127.0.0.1:1\nshard = 0\n[pipeline.s1]\nhost = 127.0.0.1:2\nshard = 1\n";
+    let pipelines = pipeline::parse_config(config).unwrap();
+    assert_eq!(pipelines.iter().map(|p| p.shard()).collect::<Vec<_>>(), vec![Some(String::from("0")), Some(String::from("1"))]);
+
+    let runtime = Runtime::new();
+    for p in pipelines {
+        runtime.add(p).unwrap();
+    }
+    let lag = runtime.lag();
+    assert_eq!(lag["pipelines"][1]["shard"], "1");
+    assert_eq!(lag["pipelines"][1]["state"], "stopped");
+    assert!(lag["pipelines"][1]["lag_seconds"].is_null());
+    assert!(lag["max_lag_seconds"].is_null());
+    assert_eq!(runtime.status("s0").unwrap()["shard"], "0");
+
+    let mut metrics = pipeline::PipelineMetrics{ state: PipelineState::Running, event_timestamp: 1000, ..Default::default() };
+    assert_eq!(metrics.lag(1030), Some(30));
+    metrics.caught_up = true;
+    assert_eq!(metrics.lag(1030), Some(0));
+    metrics.state = PipelineState::Failed;
+    assert_eq!(metrics.lag(1030), None);
+}
@@ -0,0 +1,100 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//默认的pretty输出: 每个事件一行(时间、gtid短格式、库表、操作、行数、字节数), -v时逐行输出数据, update只输出主键及变化的字段
+#![cfg(all(feature = "os", feature = "test-support"))]
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::str::FromStr;
+use uuid::Uuid;
+use mytest::Config;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::{ColumnInfo, GtidEvent, QueryEvent, XidEvent};
+use mytest::stdout::outpretty::{ColorMode, EventInfo, Pretty};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+fn column(name: &str, col_type: &str, key: &str) -> HashMap<String, String> {
+    let mut col = HashMap::new();
+    col.insert(String::from("COLUMN_NAME"), name.to_string());
+    col.insert(String::from("COLUMN_TYPE"), col_type.to_string());
+    col.insert(String::from("COLUMN_KEY"), key.to_string());
+    col
+}
+
+fn events() -> Vec<Event> {
+    let map = TableMap{
+        database_name: String::from("shop"),
+        table_name: String::from("orders"),
+        column_count: 3,
+        column_info: vec![
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeVarchar, column_meta: vec![1] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] },
+        ],
+        metadata: None,
+    };
+    let row = |id: i64, name: &str, qty: i64| vec![Some(MySQLValue::SignedInteger(id)), Some(MySQLValue::String(name.to_string())), Some(MySQLValue::SignedInteger(qty))];
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.timestamp(1573603200);
+    builder.gtid(&GtidEvent{ gtid: Uuid::from_str("3e11fa47-71ca-11e1-9e33-c80aa9429562").unwrap(), gno_id: 42, last_committed: 0, sequence_number: 1, commit_timestamp: None });
+    builder.query(&QueryEvent{ thread_id: 10, execute_seconds: 0, database: String::from("shop"), command: String::from("BEGIN"), invoker: String::new() });
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, &[row(1, "apple", 3), row(2, "pear", 5)]).unwrap();
+    builder.rows(&BinlogEvent::UpdateEvent, 100, &map, &[row(1, "apple", 3), row(1, "apple", 4)]).unwrap();
+    builder.xid(&XidEvent{ xid: 77 });
+    builder.query(&QueryEvent{ thread_id: 10, execute_seconds: 0, database: String::from("shop"), command: String::from("ALTER TABLE orders\n  ADD COLUMN note varchar(10)"), invoker: String::new() });
+    BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap().collect::<std::io::Result<Vec<Event>>>().unwrap()
+}
+
+fn render(pretty: &Pretty) -> Vec<String> {
+    let cols = vec![column("id", "int", "PRI"), column("name", "varchar", ""), column("qty", "int", "")];
+    let format = ValueFormat::new(&Config::default()).unwrap();
+    let mut gtid = String::new();
+    let mut lines = vec![];
+    for event in events() {
+        if let Traction::GtidEvent(t) = &event.data {
+            gtid = format!("{}:{}", t.gtid, t.gno_id);
+        }
+        let info = EventInfo{ timestamp: event.header.timestamp, gtid: &gtid, event_length: event.header.event_length };
+        lines.extend(pretty.render(&event.data, &cols, "shop.orders", &info, &format));
+    }
+    lines
+}
+
+#[test]
+fn one_line_per_event() {
+    let lines = render(&Pretty{ color: false, verbose: false });
+    assert_eq!(lines.len(), 4, "{:#?}", lines);
+    assert!(lines[0].starts_with("2019-11-13 00:00:00 3e11fa47:42  shop.orders              INSERT     2 rows"), "{}", lines[0]);
+    assert!(lines[0].ends_with('B'), "{}", lines[0]);
+    assert!(lines[1].contains("UPDATE     1 row "), "{}", lines[1]);
+    assert!(lines[2].contains("COMMIT xid=77"), "{}", lines[2]);
+    assert!(lines[3].contains("QUERY  ALTER TABLE orders ADD COLUMN note varchar(10)"), "{}", lines[3]);
+    assert!(lines.iter().all(|l| !l.contains('\x1b')));
+}
+
+#[test]
+fn verbose_rows() {
+    let lines = render(&Pretty{ color: false, verbose: true });
+    assert_eq!(lines[1], "    id=1, name='apple', qty=3");
+    assert_eq!(lines[2], "    id=2, name='pear', qty=5");
+    assert!(lines[3].contains("UPDATE"));
+    //update只输出主键及变化的字段
+    assert_eq!(lines[4], "    id=1, qty=3 -> 4");
+
+    let colored = render(&Pretty{ color: true, verbose: true });
+    assert!(colored[0].contains("\x1b[32mINSERT"), "{:?}", colored[0]);
+    assert!(colored[4].contains("\x1b[31m3\x1b[0m -> \x1b[32m4\x1b[0m"), "{:?}", colored[4]);
+}
+
+#[test]
+fn color_mode() {
+    assert_eq!(ColorMode::new("").unwrap(), ColorMode::Auto);
+    assert!(ColorMode::Always.enabled());
+    assert!(!ColorMode::Never.enabled());
+    assert!(ColorMode::new("rainbow").is_err());
+}
@@ -0,0 +1,81 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//各阶段耗时: 读取、解析、字段值解析、序列化分别计时, 折叠格式中字段值解析嵌套在解析中; http接口返回text/plain
+
+#![cfg(all(feature = "os", feature = "json-sink"))]
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use mytest::control::{self, Controller};
+use mytest::prelude::*;
+use mytest::profile;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn parse() {
+    let opts = SynthOptions{
+        database: String::from("profile"),
+        tables: 3,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(64), ColumnKind::Int],
+        transactions: 300,
+        rows: (5, 20),
+        rate: 100,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 3,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-profile-{}.binlog", std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    let mut sink = JsonLinesSink::new(std::io::sink(), ValueFormat::default());
+    for event in BinlogReader::open(path.to_str().unwrap()).unwrap() {
+        sink.write_event(&event.unwrap()).unwrap();
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn stages() {
+    //未开启时不计时
+    parse();
+    assert!(profile::stats().iter().all(|t| t.count == 0));
+
+    let controller = Controller::new();
+    let addr = control::serve("127.0.0.1:0", controller.clone()).unwrap();
+    assert!(profile::is_enabled());
+    parse();
+    let stats: HashMap<&str, u64> = profile::stats().iter().map(|t| (t.stage.name(), t.count)).collect();
+    //读取到文件末尾时同样计入read
+    assert!(stats["parse"] > 300 && stats["read"] == stats["parse"] + 1, "{:?}", stats);
+    assert!(stats["decode"] >= 300 && stats["decode"] == stats["serialize"], "{:?}", stats);
+    assert_eq!(stats["sink"], 0);
+    assert_eq!(controller.status()["stages"]["decode"]["count"], stats["decode"]);
+
+    let folded = profile::folded();
+    let stacks: Vec<&str> = folded.lines().map(|l| l.rsplit_once(' ').unwrap().0).collect();
+    assert!(stacks.contains(&"parse;decode"), "{}", folded);
+    assert!(stacks.contains(&"serialize"), "{}", folded);
+    assert!(!stacks.contains(&"decode"), "{}", folded);
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET /profile HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.contains("Content-Type: text/plain"));
+    assert!(response.contains("parse;decode "));
+
+    let query = |seconds: &str| vec![(String::from("seconds"), seconds.to_string())].into_iter().collect::<HashMap<_, _>>();
+    assert_eq!(controller.handle("GET", "/profile", &query("600")).0, 400);
+    //采样期间没有读取时为空
+    assert_eq!(controller.handle("GET", "/profile", &query("0.05")).1, "");
+
+    profile::reset();
+    assert!(profile::folded().is_empty());
+    assert!(profile::stats().iter().all(|t| t.count == 0));
+}
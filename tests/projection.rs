@@ -0,0 +1,78 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//字段投影: includecolumns只保留列出的字段, excludecolumns去掉列出的字段, 表按db.tbl、db.*、*匹配
+
+#![cfg(all(feature = "os", feature = "json-sink"))]
+
+use std::env;
+use std::fs;
+use serde_json::Value as JsonValue;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn records(path: &str, format: ValueFormat) -> Vec<JsonValue> {
+    let mut buf = vec![];
+    let mut sink = JsonLinesSink::new(&mut buf, format);
+    for event in BinlogReader::open(path).unwrap() {
+        sink.write_event(&event.unwrap()).unwrap();
+    }
+    drop(sink);
+    String::from_utf8(buf).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+fn columns(record: &JsonValue) -> Vec<String> {
+    record["data"].as_object().unwrap().keys().cloned().collect()
+}
+
+#[test]
+fn include_exclude() {
+    let opts = SynthOptions{
+        database: String::from("proj"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(32), ColumnKind::Int],
+        transactions: 20,
+        rows: (1, 2),
+        rate: 10,
+        mix: [2, 1, 0],
+        start_time: 1572998400,
+        seed: 7,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-projection-{}.binlog", std::process::id()));
+    let path = path.to_str().unwrap();
+    synthesize::write_file(path, &opts).unwrap();
+
+    let all = records(path, ValueFormat::default());
+    assert!(all.iter().all(|r| columns(r).len() == 4));
+
+    let mut format = ValueFormat::default();
+    format.exclude_columns.insert(String::from("proj.t1"), vec![String::from("@2")]);
+    format.exclude_columns.insert(String::from("*"), vec![String::from("@3")]);
+    let projected = records(path, format);
+    assert_eq!(projected.len(), all.len());
+    for record in &projected {
+        let cols = columns(record);
+        match record["table"].as_str().unwrap() {
+            //db.tbl优先于*
+            "t1" => assert_eq!(cols, vec!["@1", "@3", "@4"]),
+            _ => assert_eq!(cols, vec!["@1", "@2", "@4"]),
+        }
+        if record["type"] == "update" {
+            assert_eq!(record["old"].as_object().unwrap().len(), 3);
+        }
+    }
+
+    let mut format = ValueFormat::default();
+    format.include_columns.insert(String::from("proj.*"), vec![String::from("@1"), String::from("@2")]);
+    format.exclude_columns.insert(String::from("proj.t2"), vec![String::from("@2")]);
+    for record in records(path, format) {
+        match record["table"].as_str().unwrap() {
+            "t1" => assert_eq!(columns(&record), vec!["@1", "@2"]),
+            _ => assert_eq!(columns(&record), vec!["@1"]),
+        }
+    }
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,92 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//PROXY protocol头及握手时发送的连接属性
+
+#![cfg(feature = "network")]
+
+use std::io::Read;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use mytest::Config;
+use mytest::io::proxy::{self, ProxyVersion};
+use mytest::io::response::{self, LocalInfo};
+
+fn addr(s: &str) -> SocketAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn headers() {
+    let (client, server) = (addr("10.0.0.2:51234"), addr("10.0.0.1:3306"));
+    assert_eq!(proxy::header(ProxyVersion::V1, client, server), b"PROXY TCP4 10.0.0.2 10.0.0.1 51234 3306\r\n".to_vec());
+    assert_eq!(proxy::header(ProxyVersion::V1, addr("[::1]:51234"), addr("[::2]:3306")), b"PROXY TCP6 ::1 ::2 51234 3306\r\n".to_vec());
+    assert_eq!(proxy::header(ProxyVersion::V1, client, addr("[::2]:3306")), b"PROXY UNKNOWN\r\n".to_vec());
+    //ipv4映射的ipv6地址
+    assert_eq!(proxy::header(ProxyVersion::V1, addr("[::ffff:10.0.0.2]:51234"), server), b"PROXY TCP4 10.0.0.2 10.0.0.1 51234 3306\r\n".to_vec());
+
+    let v2 = proxy::header(ProxyVersion::V2, client, server);
+    assert_eq!(&v2[..12], b"\r\n\r\n\0\r\nQUIT\n");
+    assert_eq!(&v2[12..], &[0x21, 0x11, 0x00, 0x0c, 10, 0, 0, 2, 10, 0, 0, 1, 0xc8, 0x22, 0x0c, 0xea]);
+    let v6 = proxy::header(ProxyVersion::V2, addr("[::1]:51234"), addr("[::2]:3306"));
+    assert_eq!((v6[13], v6[14], v6[15], v6.len()), (0x21, 0, 36, 16 + 36));
+    assert_eq!(&proxy::header(ProxyVersion::V2, client, addr("[::2]:3306"))[12..], &[0x20, 0, 0, 0]);
+
+    assert_eq!(ProxyVersion::parse("").unwrap(), None);
+    assert_eq!(ProxyVersion::parse("V2").unwrap(), Some(ProxyVersion::V2));
+    assert!(ProxyVersion::parse("v3").is_err());
+}
+
+#[test]
+fn send_before_handshake() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = listener.local_addr().unwrap();
+    let accept = thread::spawn(move || {
+        let (mut stream, peer) = listener.accept().unwrap();
+        let mut buf = vec![];
+        stream.read_to_end(&mut buf).unwrap();
+        (buf, peer)
+    });
+    let mut stream = TcpStream::connect(server).unwrap();
+    proxy::send_header(&mut stream, ProxyVersion::V1).unwrap();
+    drop(stream);
+    let (buf, peer) = accept.join().unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), format!("PROXY TCP4 127.0.0.1 127.0.0.1 {} {}\r\n", peer.port(), server.port()));
+}
+
+//length encoded string的key、value
+fn decode(mut buf: &[u8]) -> Vec<(String, String)> {
+    let mut attrs = vec![];
+    let next = |buf: &mut &[u8]| {
+        let len = buf[0] as usize;
+        let value = String::from_utf8(buf[1..1 + len].to_vec()).unwrap();
+        *buf = &buf[1 + len..];
+        value
+    };
+    while !buf.is_empty() {
+        let key = next(&mut buf);
+        let value = next(&mut buf);
+        attrs.push((key, value));
+    }
+    attrs
+}
+
+#[test]
+fn connect_attrs() {
+    let info = LocalInfo::new(&String::from("binlogAL"), 0);
+    let conf = Config{ connectattrs: String::from("program_name=cdc-orders\nteam = dba"), ..Config::default() };
+    let attrs = decode(&response::pack_connect_attrs(&info.connect_attrs(&conf)));
+    let get = |key: &str| attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+    assert_eq!(get("_client_name"), Some("binlogAL"));
+    assert_eq!(get("_client_version"), Some(env!("CARGO_PKG_VERSION")));
+    assert_eq!(get("_pid"), Some(std::process::id().to_string().as_str()));
+    assert_eq!(get("program_name"), Some("cdc-orders"));
+    assert_eq!(get("team"), Some("dba"));
+    assert_eq!(attrs.iter().filter(|(k, _)| k == "program_name").count(), 1);
+
+    let attrs = LocalInfo::new(&String::new(), 0).connect_attrs(&Config::default());
+    assert!(attrs.contains(&(String::from("program_name"), String::from("binlogAL"))));
+    assert!(response::parse_connect_attrs("team").is_err());
+}
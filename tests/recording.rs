@@ -0,0 +1,104 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//数据包的录制与重放: 重放得到的事件与直接读取binlog文件相同, 已执行位置从录制时注册的位置开始
+
+#![cfg(all(feature = "network", feature = "test-support"))]
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::sync::Arc;
+use mytest::Config;
+use mytest::prelude::*;
+use mytest::recording::{Recorder, Recording, Start};
+use mytest::stream::BinlogStream;
+use common::{inserts, SID};
+
+//mysql发送的数据包: OK标识 + 事件
+fn packets(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut packets = vec![];
+    let mut offset = 4;
+    while offset < data.len() {
+        let len = u32::from_le_bytes([data[offset + 9], data[offset + 10], data[offset + 11], data[offset + 12]]) as usize;
+        let mut packet = vec![0u8];
+        packet.extend_from_slice(&data[offset..offset + len]);
+        packets.push(packet);
+        offset += len;
+    }
+    packets
+}
+
+fn record(name: &str, start: &Start, packets: &[Vec<u8>]) -> std::path::PathBuf {
+    let path = env::temp_dir().join(format!("mytest_recording_{}_{}", name, std::process::id()));
+    let recorder = Recorder::create(&path, start).unwrap();
+    for packet in packets {
+        recorder.record(packet).unwrap();
+    }
+    path
+}
+
+fn gtid_start() -> Start {
+    Start{ gtid: format!("{}:1-10", SID), ..Start::default() }
+}
+
+#[test]
+fn replay_matches_file() {
+    let data = inserts(1..=3).into_bytes();
+    let path = record("matches", &gtid_start(), &packets(&data));
+    let recording = Recording::open(&path).unwrap();
+    assert_eq!(recording.start(), &gtid_start());
+    let conf = Config{ verifychecksum: String::from("all"), ..Config::default() };
+    let mut stream = BinlogStream::replay(recording, &conf).unwrap();
+    let replayed: Vec<Event> = stream.by_ref().collect::<Result<_, _>>().unwrap();
+    let read: Vec<Event> = BinlogReader::new(Cursor::new(data)).unwrap().collect::<Result<_, _>>().unwrap();
+    assert_eq!(replayed.len(), read.len());
+    for (a, b) in replayed.iter().zip(read.iter()) {
+        assert_eq!(format!("{:?}", a.data), format!("{:?}", b.data));
+        assert_eq!(a.log_pos, b.log_pos);
+    }
+    //注册时的gtid集合加上读取到的事务
+    assert_eq!(stream.executed().gtid_set.to_string(), format!("{}:1-10", SID));
+    assert!(stream.next().is_none());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn recorder_on_stream() {
+    let data = inserts(1..=3).into_bytes();
+    let first = record("first", &gtid_start(), &packets(&data));
+    let second = env::temp_dir().join(format!("mytest_recording_second_{}", std::process::id()));
+    let recorder = Arc::new(Recorder::create(&second, &gtid_start()).unwrap());
+    let stream = BinlogStream::replay(Recording::open(&first).unwrap(), &Config::default()).unwrap().with_recorder(recorder);
+    assert_eq!(stream.count(), packets(&data).len());
+    //重放时再次录制, 数据包相同
+    let a: Vec<Vec<u8>> = Recording::open(&first).unwrap().map(|t| t.unwrap().data).collect();
+    let b: Vec<Vec<u8>> = Recording::open(&second).unwrap().map(|t| t.unwrap().data).collect();
+    assert_eq!(a, b);
+    fs::remove_file(&first).unwrap();
+    fs::remove_file(&second).unwrap();
+}
+
+#[test]
+fn truncated_and_invalid() {
+    let data = inserts(1..=3).into_bytes();
+    let packets = packets(&data);
+    let path = record("truncated", &Start::default(), &packets);
+    //录制中断时最后一个数据包不完整
+    let content = fs::read(&path).unwrap();
+    fs::write(&path, &content[..content.len() - 3]).unwrap();
+    assert_eq!(Recording::open(&path).unwrap().count(), packets.len() - 1);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(Recording::new(Cursor::new(b"not a recording".to_vec())).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+    //mysql返回的错误包在重放时同样返回错误
+    let path = record("error", &Start::default(), &[vec![0xff, 0x48, 0x04, b'#', b'H', b'Y', b'0', b'0', b'0', b'x']]);
+    let mut stream = BinlogStream::replay(Recording::open(&path).unwrap(), &Config::default()).unwrap();
+    assert!(stream.next().unwrap().is_err());
+    assert!(stream.next().is_none());
+    fs::remove_file(&path).unwrap();
+}
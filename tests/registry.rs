@@ -0,0 +1,184 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//Schema Registry: 每个subject注册表对应的JSON Schema, 消息为0x00 + schema id + json, 表结构变化后注册新的schema
+
+#![cfg(all(feature = "os", feature = "json-sink"))]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::rc::Rc;
+use serde_json::Value as JsonValue;
+use mytest::prelude::*;
+use mytest::sink::json_schema;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+//按(subject, schema)分配id, 记录注册的schema
+#[derive(Default, Clone)]
+struct MemoryRegistry{
+    schemas: Rc<RefCell<Vec<(String, String)>>>,
+}
+
+impl SchemaRegistry for MemoryRegistry {
+    fn register(&mut self, subject: &str, schema: &str) -> Result<u32, String> {
+        let mut schemas = self.schemas.borrow_mut();
+        let key = (subject.to_string(), schema.to_string());
+        if let Some(idx) = schemas.iter().position(|t| t == &key) {
+            return Ok(idx as u32 + 1);
+        }
+        schemas.push(key);
+        Ok(schemas.len() as u32)
+    }
+}
+
+fn cols(names: &[(&str, &str)]) -> Vec<HashMap<String, String>> {
+    names.iter().map(|(name, col_type)| {
+        let mut info = HashMap::new();
+        info.insert(String::from("COLUMN_NAME"), name.to_string());
+        info.insert(String::from("COLUMN_TYPE"), col_type.to_string());
+        info
+    }).collect()
+}
+
+#[test]
+fn schema() {
+    let cols = cols(&[("id", "bigint(20)"), ("price", "decimal(10,2)"), ("name", "varchar(32)"), ("doc", "json"), ("@5", "")]);
+    let schema = json_schema("db.t1", &cols, &ValueFormat::default());
+    let data = &schema["properties"]["data"];
+    assert_eq!(schema["title"], "db.t1");
+    assert_eq!(data["properties"]["id"]["type"], serde_json::json!(["integer", "null"]));
+    assert_eq!(data["properties"]["price"]["type"], serde_json::json!(["number", "null"]));
+    assert_eq!(data["properties"]["name"]["type"], serde_json::json!(["string", "null"]));
+    assert_eq!(data["properties"]["doc"], serde_json::json!({}));
+    assert_eq!(data["properties"]["@5"], serde_json::json!({}));
+    assert_eq!(data, &schema["properties"]["old"]);
+
+    let mut format = ValueFormat{ quote_decimal: true, ..ValueFormat::default() };
+    format.exclude_columns.insert(String::from("db.t1"), vec![String::from("doc")]);
+    let schema = json_schema("db.t1", &cols, &format);
+    assert_eq!(schema["properties"]["data"]["properties"]["price"]["type"], serde_json::json!(["string", "null"]));
+    assert!(schema["properties"]["data"]["properties"].get("doc").is_none());
+}
+
+#[test]
+fn messages() {
+    let opts = SynthOptions{
+        database: String::from("registry"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 40,
+        rows: (1, 3),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 11,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-registry-{}.binlog", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    synthesize::write_file(&path, &opts).unwrap();
+
+    let registry = MemoryRegistry::default();
+    let messages = Rc::new(RefCell::new(vec![]));
+    let produced = messages.clone();
+    let mut sink = RegistrySink::new("binlog.{db}.{table}", Box::new(registry.clone()), ValueFormat::default(), move |topic, message| {
+        produced.borrow_mut().push((topic.to_string(), message.to_vec()));
+        Ok(())
+    }).unwrap();
+    let mut buf = vec![];
+    let mut json = JsonLinesSink::new(&mut buf, ValueFormat::default());
+    let mut transactions = 0;
+    for event in BinlogReader::open(&path).unwrap() {
+        let event = event.unwrap();
+        sink.write_event(&event).unwrap();
+        json.write_event(&event).unwrap();
+        if let Traction::XidEvent(_) = event.data {
+            transactions += 1;
+            if transactions == 20 {
+                //t1增加字段后注册新的schema, 字段信息与TABLE_MAP中的字段数相同
+                sink.set_columns("registry.t1", cols(&[("id", "bigint"), ("c2", "int"), ("c3", "varchar(16)")]));
+            }
+        }
+    }
+    drop(json);
+    fs::remove_file(&path).unwrap();
+
+    //t1: @1..@3与id/c2/c3两个版本, t2一个版本
+    let schemas = registry.schemas.borrow();
+    let subjects: Vec<&str> = schemas.iter().map(|(subject, _)| subject.as_str()).collect();
+    assert_eq!(subjects.iter().filter(|s| **s == "binlog.registry.t1-value").count(), 2);
+    assert_eq!(subjects.iter().filter(|s| **s == "binlog.registry.t2-value").count(), 1);
+    assert_eq!(sink.registered().len(), 3);
+
+    //消息内容与JsonLinesSink相同(字段名除外), schema id对应subject
+    let messages = messages.borrow();
+    let lines: Vec<JsonValue> = String::from_utf8(buf).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(messages.len(), lines.len());
+    let mut last_t1 = 0;
+    for ((topic, message), line) in messages.iter().zip(lines.iter()) {
+        assert_eq!(message[0], 0);
+        let id = u32::from_be_bytes([message[1], message[2], message[3], message[4]]);
+        let record: JsonValue = serde_json::from_slice(&message[5..]).unwrap();
+        assert_eq!(&schemas[id as usize - 1].0, &format!("{}-value", topic));
+        assert_eq!(topic, &format!("binlog.registry.{}", line["table"].as_str().unwrap()));
+        assert_eq!(record["gtid"], line["gtid"]);
+        assert_eq!(record["type"], line["type"]);
+        let values: Vec<&JsonValue> = record["data"].as_object().unwrap().values().collect();
+        let expected: Vec<&JsonValue> = line["data"].as_object().unwrap().values().collect();
+        assert_eq!(values.len(), expected.len());
+        if topic.ends_with("t1") {
+            assert!(id as usize >= last_t1);
+            last_t1 = id as usize;
+        }
+    }
+}
+
+#[cfg(feature = "network")]
+#[test]
+fn http_registry() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let mut requests = vec![];
+        let responses = [("200 OK", "{\"id\":7}"), ("409 Conflict", "{\"error_code\":409,\"message\":\"incompatible\"}")];
+        for (status, response) in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = vec![];
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                head.push(line.trim_end().to_string());
+            }
+            let length: usize = head.iter().find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length: ").map(|t| t.parse().unwrap())).unwrap();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            stream.write_all(format!("HTTP/1.1 {}\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}", status, response.len(), response).as_bytes()).unwrap();
+            requests.push((head, String::from_utf8(body).unwrap()));
+        }
+        requests
+    });
+
+    let mut registry = HttpSchemaRegistry::new(&format!("{}/", url)).with_auth("key", "secret");
+    assert_eq!(registry.register("db.t1-value", "{\"type\":\"object\"}"), Ok(7));
+    let err = registry.register("db.t1-value", "{\"type\":\"string\"}").unwrap_err();
+    assert!(err.contains("409") && err.contains("incompatible"), "{}", err);
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests[0].0[0], "POST /subjects/db.t1-value/versions HTTP/1.1");
+    assert!(requests[0].0.iter().any(|l| l == "Authorization: Basic a2V5OnNlY3JldA=="));
+    let body: JsonValue = serde_json::from_str(&requests[0].1).unwrap();
+    assert_eq!(body["schemaType"], "JSON");
+    assert_eq!(body["schema"], "{\"type\":\"object\"}");
+}
@@ -0,0 +1,272 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//读取从库本地的relay log: 跳过从库生成的事件、记录主库的binlog文件、跟随文件切换及写入中的文件、
+//丢弃io线程重新连接前不完整的事务, binlog(log_slave_updates)模式, 按gtid或位置跳过, 管道中使用relay-log
+
+#![cfg(all(feature = "test-support", feature = "os"))]
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use mytest::Config;
+use mytest::prelude::*;
+use mytest::relay::RelayTail;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+use common::{begin, gtid, insert, orders, SID, START};
+
+const RELAY: u16 = 0x40;
+const ARTIFICIAL: u16 = 0x20;
+
+fn dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("mytest-relay-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+//主库的事务(没有RELAY标识), 只写到TABLE_MAP
+fn unfinished(builder: &mut BinlogBuilder, gno: u64) {
+    builder.flags(0).timestamp(START + gno as u32);
+    builder.gtid(&gtid(gno)).query(&begin("shop"));
+    builder.table_map(100, &orders(false)).unwrap();
+}
+
+fn transaction(builder: &mut BinlogBuilder, gno: u64) {
+    insert(builder.flags(0), gno);
+}
+
+//relay log开头: 从库生成的PREVIOUS_GTIDS, master_file不为空时为io线程连接后收到的主库ROTATE
+fn relay(master_file: &str) -> BinlogBuilder {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57).server_id(2);
+    builder.timestamp(START).flags(RELAY).previous_gtids(&GtidSet::new());
+    if !master_file.is_empty() {
+        builder.timestamp(0).flags(ARTIFICIAL).rotate(&RotateLog{ binlog_file: master_file.to_string(), position: 4, artificial: true });
+    }
+    builder
+}
+
+//切换到下一个relay log
+fn rotate(builder: &mut BinlogBuilder, next: &str) {
+    builder.flags(RELAY).rotate(&RotateLog{ binlog_file: next.to_string(), position: 4, artificial: false });
+}
+
+fn write_index(dir: &PathBuf, index: &str, files: &[&str]) -> PathBuf {
+    let path = dir.join(index);
+    fs::write(&path, files.iter().map(|f| format!("./{}\n", f)).collect::<String>()).unwrap();
+    path
+}
+
+//读取已写入的全部事件
+fn drain(tail: &mut RelayTail) -> Vec<Event> {
+    let mut events = vec![];
+    while let Some(event) = tail.try_read_event().unwrap() {
+        events.push(event);
+    }
+    events
+}
+
+fn gnos(events: &[Event]) -> Vec<u64> {
+    events.iter().filter_map(|e| match &e.data {
+        Traction::GtidEvent(t) => Some(t.gno_id),
+        _ => None,
+    }).collect()
+}
+
+fn rotates(events: &[Event]) -> Vec<(String, bool)> {
+    events.iter().filter_map(|e| match &e.data {
+        Traction::RotateLogEvent(t) => Some((t.binlog_file.clone(), t.artificial)),
+        _ => None,
+    }).collect()
+}
+
+//两个relay log, 第二个为max_relay_log_size切换, 没有主库的ROTATE
+fn two_relay_logs(dir: &PathBuf) -> PathBuf {
+    let mut first = relay("mysql-bin.000003");
+    transaction(&mut first, 1);
+    transaction(&mut first, 2);
+    rotate(&mut first, "relay-bin.000002");
+    fs::write(dir.join("relay-bin.000001"), first.into_bytes()).unwrap();
+    let mut second = relay("");
+    transaction(&mut second, 3);
+    fs::write(dir.join("relay-bin.000002"), second.into_bytes()).unwrap();
+    write_index(dir, "relay-bin.index", &["relay-bin.000001", "relay-bin.000002"])
+}
+
+#[test]
+fn relay_log() {
+    let dir = dir("read");
+    let mut tail = RelayTail::open(two_relay_logs(&dir)).unwrap();
+    let events = drain(&mut tail);
+    assert_eq!(gnos(&events), vec![1, 2, 3]);
+    //从库生成的事件不返回, 主库的ROTATE记录binlog文件
+    assert!(events.iter().all(|e| !e.header.flags.contains(EventFlags::LOG_EVENT_RELAY_LOG_F)));
+    assert_eq!(rotates(&events), vec![(String::from("mysql-bin.000003"), true)]);
+    assert_eq!(events.iter().filter(|e| e.table.is_some()).count(), 3);
+    assert_eq!(tail.file(), "relay-bin.000002");
+    assert_eq!(tail.discarded(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn follow() {
+    let dir = dir("follow");
+    let mut builder = relay("mysql-bin.000003");
+    transaction(&mut builder, 1);
+    let bytes = builder.into_bytes();
+    //io线程写入到一半
+    fs::write(dir.join("relay-bin.000001"), &bytes[..bytes.len() - 10]).unwrap();
+    let index = write_index(&dir, "relay-bin.index", &["relay-bin.000001"]);
+    let mut tail = RelayTail::open(&index).unwrap();
+    assert!(gnos(&drain(&mut tail)).is_empty());
+
+    fs::write(dir.join("relay-bin.000001"), &bytes).unwrap();
+    assert_eq!(gnos(&drain(&mut tail)), vec![1]);
+    assert!(drain(&mut tail).is_empty());
+
+    //切换到新的relay log
+    let mut builder = relay("mysql-bin.000003");
+    transaction(&mut builder, 1);
+    rotate(&mut builder, "relay-bin.000002");
+    fs::write(dir.join("relay-bin.000001"), builder.into_bytes()).unwrap();
+    let mut second = relay("");
+    transaction(&mut second, 2);
+    fs::write(dir.join("relay-bin.000002"), second.into_bytes()).unwrap();
+    write_index(&dir, "relay-bin.index", &["relay-bin.000001", "relay-bin.000002"]);
+    assert_eq!(gnos(&drain(&mut tail)), vec![2]);
+    assert_eq!(tail.file(), "relay-bin.000002");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn partial_transaction() {
+    //io线程在事务中间断开, 重新连接后主库从该事务开头发送
+    let dir = dir("partial");
+    let mut first = relay("mysql-bin.000003");
+    transaction(&mut first, 1);
+    unfinished(&mut first, 2);
+    rotate(&mut first, "relay-bin.000002");
+    fs::write(dir.join("relay-bin.000001"), first.into_bytes()).unwrap();
+    let mut second = relay("mysql-bin.000003");
+    transaction(&mut second, 2);
+    transaction(&mut second, 3);
+    fs::write(dir.join("relay-bin.000002"), second.into_bytes()).unwrap();
+    let index = write_index(&dir, "relay-bin.index", &["relay-bin.000001", "relay-bin.000002"]);
+
+    let mut tail = RelayTail::open(&index).unwrap();
+    let events = drain(&mut tail);
+    assert_eq!(gnos(&events), vec![1, 2, 3]);
+    assert_eq!(events.iter().filter(|e| matches!(e.data, Traction::TableMapEvent(_))).count(), 3);
+    assert_eq!(tail.discarded(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn binlog() {
+    //log_slave_updates时从库的binlog, 每个文件开头返回ROTATE
+    let dir = dir("binlog");
+    let mut first = BinlogBuilder::new(ServerVersion::Mysql57);
+    transaction(&mut first, 1);
+    first.rotate(&RotateLog{ binlog_file: String::from("binlog.000002"), position: 4, artificial: false });
+    fs::write(dir.join("binlog.000001"), first.into_bytes()).unwrap();
+    let mut second = BinlogBuilder::new(ServerVersion::Mysql57);
+    transaction(&mut second, 2);
+    fs::write(dir.join("binlog.000002"), second.into_bytes()).unwrap();
+    let index = write_index(&dir, "binlog.index", &["binlog.000001", "binlog.000002"]);
+
+    let events = drain(&mut RelayTail::open(&index).unwrap());
+    assert_eq!(gnos(&events), vec![1, 2]);
+    assert_eq!(rotates(&events), vec![
+        (String::from("binlog.000001"), true),
+        (String::from("binlog.000002"), false),
+        (String::from("binlog.000002"), true),
+    ]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skip() {
+    let dir = dir("skip");
+    let index = two_relay_logs(&dir);
+    let events = drain(&mut RelayTail::open(&index).unwrap());
+    let end = events.iter().find(|e| matches!(e.data, Traction::XidEvent(_))).unwrap().header.next_position as u64;
+
+    let gtids: GtidSet = format!("{}:1", SID).parse().unwrap();
+    assert_eq!(gnos(&drain(&mut RelayTail::open(&index).unwrap().skip_executed(gtids))), vec![2, 3]);
+    assert_eq!(gnos(&drain(&mut RelayTail::open(&index).unwrap().skip_until("mysql-bin.000003", end))), vec![2, 3]);
+    assert_eq!(gnos(&drain(&mut RelayTail::open(&index).unwrap().skip_until("mysql-bin.000002", end))), vec![1, 2, 3]);
+
+    //from_config: gtid为已输出的位置, 没有新事件时返回心跳
+    let conf = Config{ relaylogindex: index.to_string_lossy().to_string(), gtid: format!("{}:1-2", SID), heartbeat: String::from("0.05"), ..Config::default() };
+    let mut tail = RelayTail::from_config(&conf).unwrap().poll_interval(Duration::from_millis(10));
+    assert_eq!(gnos(&drain(&mut tail)), vec![3]);
+    let event = tail.read_event().unwrap();
+    assert_eq!(event.header.event_type, EventType::HeartbeatLogEvent);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn errors() {
+    let dir = dir("errors");
+    assert!(RelayTail::open(dir.join("relay-bin.index")).is_err());
+    let empty = write_index(&dir, "relay-bin.index", &[]);
+    assert_eq!(RelayTail::open(&empty).err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+
+    //下一个relay log已被sql线程清理
+    let mut first = relay("mysql-bin.000003");
+    transaction(&mut first, 1);
+    rotate(&mut first, "relay-bin.000002");
+    fs::write(dir.join("relay-bin.000001"), first.into_bytes()).unwrap();
+    let index = write_index(&dir, "relay-bin.index", &["relay-bin.000001", "relay-bin.000002"]);
+    let mut tail = RelayTail::open(&index).unwrap();
+    let err = loop {
+        match tail.try_read_event() {
+            Ok(Some(_)) => continue,
+            Ok(None) => panic!("应返回错误"),
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert!(err.to_string().contains("relay_log_purge"), "{}", err);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(all(feature = "network", feature = "json-sink"))]
+#[test]
+fn pipeline() {
+    use std::time::Instant;
+    use mytest::pipeline::{self, Runtime};
+
+    let dir = dir("pipeline");
+    let index = two_relay_logs(&dir);
+    let output = dir.join("out.json");
+    //不需要host, 字段名为@1
+    let config = format!("[pipeline.replica]\nrelay-log = {}\noutput = {}\ngtid = {}:1\nheartbeat = 0.1\n",
+                         index.display(), output.display(), SID);
+    let runtime = Runtime::new();
+    for p in pipeline::parse_config(&config).unwrap() {
+        runtime.add(p).unwrap();
+    }
+    runtime.start("replica").unwrap();
+    let start = Instant::now();
+    let lines = loop {
+        let content = fs::read_to_string(&output).unwrap_or_default();
+        if content.lines().count() >= 2 || start.elapsed() > Duration::from_secs(10) {
+            break content;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    runtime.stop("replica").unwrap();
+    runtime.wait("replica").unwrap();
+    let records: Vec<serde_json::Value> = lines.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(records.iter().map(|r| r["gtid"].as_str().unwrap().to_string()).collect::<Vec<_>>(),
+               vec![format!("{}:2", SID), format!("{}:3", SID)]);
+    assert_eq!(records[0]["table"], "orders");
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,114 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//binlog保留估算: 写入速度按分析文件的位置及时间计算, 按过期时间及保留的文件大小估算可恢复的时间及需要的文件的清理时间
+
+#![cfg(feature = "os")]
+
+use std::env;
+use std::fs;
+use mytest::Config;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+use mytest::stdout::outretention::{self, RetentionReport, RetentionSettings};
+
+const START: u32 = 1572998400;
+
+fn report(name: &str, need: &str) -> RetentionReport {
+    let opts = SynthOptions{
+        database: String::from("retention"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 100,
+        rows: (1, 3),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: START,
+        seed: 7,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-retention-{}-{}.000002", name, std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    let conf = Config{ file: path.to_str().unwrap().to_string(), needbinlog: need.to_string(), ..Config::default() };
+    let mut report = RetentionReport::new(&conf);
+    for event in BinlogReader::open(path.to_str().unwrap()).unwrap() {
+        let event = event.unwrap();
+        let header = &event.header;
+        report.record(&event.data, header.timestamp, header.next_position as u64 - header.event_length as u64, header.event_length);
+    }
+    fs::remove_file(&path).unwrap();
+    report
+}
+
+fn settings(file: &str, expire: u64) -> RetentionSettings {
+    RetentionSettings{
+        expire_seconds: expire,
+        auto_purge: true,
+        max_binlog_size: 1 << 30,
+        binlogs: vec![
+            (String::from("mysql-bin.000001"), 1 << 20),
+            (file.to_string(), 1 << 20),
+            (String::from("mysql-bin.000003"), 1 << 20),
+        ],
+    }
+}
+
+#[test]
+fn advise() {
+    let report = report("advise", "");
+    let file = format!("mytest-retention-advise-{}.000002", std::process::id());
+    let settings = settings(&file, 7 * 86400);
+    let now = START as u64 + 3600;
+    let advice = report.advise(&settings, now);
+    let rate = advice.write_rate.unwrap();
+    assert!(rate > 0.0);
+    assert_eq!(advice.retained_bytes, 3 << 20);
+    //可恢复的时间取保留文件覆盖的时间与过期时间中较小的
+    let window = advice.retained_seconds.unwrap().min(7 * 86400);
+    assert_eq!(advice.recoverable_since, Some(now - window));
+    //分析的文件按最后一个事件的时间过期
+    let last = advice.need_purge_at.unwrap() - 7 * 86400;
+    assert!(last > START as u64 && last < now);
+
+    //过期时间很短时提示即将清理
+    let advice = report.advise(&RetentionSettings{ expire_seconds: 600, ..settings.clone() }, last + 300);
+    assert_eq!(advice.need_purge_at, Some(last + 600));
+    assert!(advice.warnings.iter().any(|t| t.contains("5m后被清理")), "{:?}", advice.warnings);
+    assert!(report.advise(&RetentionSettings{ expire_seconds: 600, ..settings.clone() }, last + 900).warnings.iter().any(|t| t.contains("已经过期")));
+
+    //不自动清理
+    let advice = report.advise(&RetentionSettings{ auto_purge: false, ..settings.clone() }, now);
+    assert_eq!(advice.need_purge_at, None);
+    assert!(advice.warnings[0].contains("手动清理"));
+}
+
+#[test]
+fn need_binlog() {
+    let purged = report("need", "mysql-bin.000000");
+    let settings = settings("mysql-bin.000002", 86400 * 3);
+    let advice = purged.advise(&settings, START as u64);
+    assert_eq!(advice.need_purge_at, None);
+    assert!(advice.warnings.iter().any(|t| t.contains("mysql-bin.000000已经被清理")));
+
+    //正在写入的文件按max_binlog_size估算切换时间
+    let current = report("current", "mysql-bin.000003");
+    let advice = current.advise(&settings, START as u64);
+    let rate = advice.write_rate.unwrap();
+    let left = ((1u64 << 30) - (1 << 20)) as f64 / rate;
+    assert_eq!(advice.need_purge_at, Some(START as u64 + left as u64 + 86400 * 3));
+
+    //之前的文件按之后的文件大小估算结束时间
+    let old = report("old", "mysql-bin.000001");
+    let advice = old.advise(&settings, START as u64 + 86400);
+    let after = ((2u64 << 20) as f64 / advice.write_rate.unwrap()) as u64;
+    assert_eq!(advice.need_purge_at, Some(START as u64 + 86400 - after + 86400 * 3));
+}
+
+#[test]
+fn human_duration() {
+    assert_eq!(outretention::human_duration(59), "0m");
+    assert_eq!(outretention::human_duration(3720), "1h2m");
+    assert_eq!(outretention::human_duration(86400 * 2 + 3600 * 3 + 240), "2d3h4m");
+}
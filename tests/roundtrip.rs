@@ -0,0 +1,484 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/04
+*/
+
+//往返测试: 随机生成事件及字段值, 通过testing::BinlogBuilder编码后再用BinlogReader解析, 结果应与原值相同
+//  需要开启test-support特性: cargo test --features test-support
+#![cfg(feature = "test-support")]
+
+use std::io::Cursor;
+use bigdecimal::BigDecimal;
+use serde_json::{Map, Number, Value as JsonValue};
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::parsevalue::DecimalMeta;
+use mytest::replication::readevent::{ColumnInfo, RowsQueryEvent};
+use mytest::testing::{self, BinlogBuilder, ServerVersion};
+
+const VERSIONS: [ServerVersion; 3] = [ServerVersion::Mysql56, ServerVersion::Mysql57, ServerVersion::Mysql80];
+
+//xorshift64*, 相同的seed生成相同的序列, 失败时可以通过seed重现
+struct Rng(u64);
+
+impl Rng{
+    fn new(seed: u64) -> Rng {
+        Rng((seed ^ 0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    //[min, max]
+    fn range(&mut self, min: i64, max: i64) -> i64 {
+        min + (self.next() % (max - min + 1) as u64) as i64
+    }
+
+    fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+        let len = self.range(0, max_len as i64) as usize;
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+
+    fn string(&mut self, max_len: usize) -> String {
+        const CHARS: [&str; 8] = ["a", "Z", "0", "_", " ", "é", "中", "😀"];
+        let len = self.range(0, max_len as i64) as usize;
+        (0..len).map(|_| CHARS[self.range(0, 7) as usize]).collect()
+    }
+}
+
+fn column(column_type: ColumnTypeDict, meta: &[usize]) -> ColumnInfo {
+    ColumnInfo{ column_type, column_meta: meta.to_vec() }
+}
+
+fn random_column(rng: &mut Rng) -> ColumnInfo {
+    let fsp = rng.range(0, 6) as usize;
+    match rng.range(0, 25) {
+        0 => column(ColumnTypeDict::MysqlTypeTiny, &[0]),
+        1 => column(ColumnTypeDict::MysqlTypeShort, &[0]),
+        2 => column(ColumnTypeDict::MysqlTypeInt24, &[0]),
+        3 => column(ColumnTypeDict::MysqlTypeLong, &[0]),
+        4 => column(ColumnTypeDict::MysqlTypeLonglong, &[0]),
+        5 => column(ColumnTypeDict::MysqlTypeFloat, &[4]),
+        6 => column(ColumnTypeDict::MysqlTypeDouble, &[8]),
+        7 => {
+            let precision = rng.range(1, 65);
+            let scale = rng.range(0, precision.min(30));
+            column(ColumnTypeDict::MysqlTypeNewdecimal, &[precision as usize, scale as usize])
+        }
+        8 => column(ColumnTypeDict::MysqlTypeTimestamp2, &[fsp]),
+        9 => column(ColumnTypeDict::MysqlTypeDatetime2, &[fsp]),
+        10 => column(ColumnTypeDict::MysqlTypeYear, &[0]),
+        11 => column(ColumnTypeDict::MysqlTypeDate, &[0]),
+        12 => column(ColumnTypeDict::MysqlTypeTime2, &[fsp]),
+        13 => column(ColumnTypeDict::MysqlTypeVarchar, &[rng.range(1, 2) as usize]),
+        14 => column(ColumnTypeDict::MysqlTypeVarString, &[rng.range(1, 2) as usize]),
+        15 => column(ColumnTypeDict::MysqlTypeBlob, &[rng.range(1, 4) as usize]),
+        16 => column(ColumnTypeDict::MysqlTypeString, &[rng.range(1, 255) as usize]),
+        17 => column(ColumnTypeDict::MysqlTypeEnum, &[rng.range(1, 2) as usize]),
+        18 => column(ColumnTypeDict::MysqlTypeSet, &[[1, 2, 3, 4, 8][rng.range(0, 4) as usize]]),
+        19 => column(ColumnTypeDict::MysqlTypeTinyBlob, &[1]),
+        20 => column(ColumnTypeDict::MysqlTypeMediumBlob, &[3]),
+        //5.6之前的时间格式
+        21 => column(ColumnTypeDict::MysqlTypeTimestamp, &[0]),
+        22 => column(ColumnTypeDict::MysqlTypeDatetime, &[0]),
+        23 => column(ColumnTypeDict::MysqlTypeTime, &[0]),
+        24 => column(ColumnTypeDict::MysqlTypeNewdate, &[0]),
+        _ => column(ColumnTypeDict::MysqlTypeJson, &[4]),
+    }
+}
+
+fn random_decimal(rng: &mut Rng, precision: usize, scale: usize) -> BigDecimal {
+    let digits = |rng: &mut Rng, len: usize| -> String { (0..len).map(|_| char::from(b'0' + rng.range(0, 9) as u8)).collect() };
+    let integral_len = rng.range(0, (precision - scale) as i64) as usize;
+    let integral = digits(rng, integral_len);
+    let fraction = digits(rng, scale);
+    let sign = if rng.range(0, 1) == 0 { "-" } else { "" };
+    format!("{}{}.{}", sign, if integral.is_empty() { "0" } else { &integral }, fraction).trim_end_matches('.').parse().unwrap()
+}
+
+//fsp对应的小数部分按(fsp+1)/2 bytes保存, 解析结果为保存的原始值
+fn random_subsecond(rng: &mut Rng, fsp: usize) -> u32 {
+    match fsp {
+        0 => 0,
+        1 | 2 => rng.range(0, 99) as u32,
+        3 | 4 => rng.range(0, 9999) as u32,
+        _ => rng.range(0, 999_999) as u32,
+    }
+}
+
+//-838:59:59到838:59:59, 零值没有符号
+fn random_time(rng: &mut Rng, subseconds: u32) -> MySQLValue {
+    let (hours, minutes, seconds) = (rng.range(0, 838) as u32, rng.range(0, 59) as u32, rng.range(0, 59) as u32);
+    let negative = rng.range(0, 1) == 1 && hours + minutes + seconds + subseconds > 0;
+    MySQLValue::Time{ negative, hours, minutes, seconds, subseconds }
+}
+
+fn random_json(rng: &mut Rng, depth: usize) -> JsonValue {
+    let kind = if depth >= 3 { rng.range(0, 5) } else { rng.range(0, 7) };
+    match kind {
+        0 => JsonValue::Null,
+        1 => JsonValue::Bool(rng.range(0, 1) == 1),
+        2 => {
+            let bits = [8, 16, 32, 63][rng.range(0, 3) as usize];
+            JsonValue::from(rng.next() as i64 >> (64 - bits))
+        }
+        3 => JsonValue::from(rng.next() | 1 << 63),
+        4 => JsonValue::Number(Number::from_f64(rng.range(-1_000_000, 1_000_000) as f64 / 64.0).unwrap()),
+        5 => JsonValue::String(rng.string(20)),
+        6 => JsonValue::Array((0..rng.range(0, 5)).map(|_| random_json(rng, depth + 1)).collect()),
+        _ => {
+            let mut map = Map::new();
+            for _ in 0..rng.range(0, 5) {
+                map.insert(rng.string(8), random_json(rng, depth + 1));
+            }
+            JsonValue::Object(map)
+        }
+    }
+}
+
+fn random_value(rng: &mut Rng, col: &ColumnInfo) -> MySQLValue {
+    if rng.range(0, 9) == 0 {
+        return MySQLValue::Null;
+    }
+    let meta = &col.column_meta;
+    match col.column_type {
+        ColumnTypeDict::MysqlTypeTiny => MySQLValue::SignedInteger(rng.next() as i8 as i64),
+        ColumnTypeDict::MysqlTypeShort => MySQLValue::SignedInteger(rng.next() as i16 as i64),
+        ColumnTypeDict::MysqlTypeInt24 => MySQLValue::SignedInteger(rng.range(-(1 << 23), (1 << 23) - 1)),
+        ColumnTypeDict::MysqlTypeLong => MySQLValue::SignedInteger(rng.next() as i32 as i64),
+        ColumnTypeDict::MysqlTypeLonglong => MySQLValue::SignedInteger(rng.next() as i64),
+        ColumnTypeDict::MysqlTypeFloat => MySQLValue::Float(rng.range(-1_000_000, 1_000_000) as f32 / 8.0),
+        ColumnTypeDict::MysqlTypeDouble => MySQLValue::Double(rng.next() as i64 as f64 / 1024.0),
+        ColumnTypeDict::MysqlTypeNewdecimal => MySQLValue::Decimal(random_decimal(rng, meta[0], meta[1])),
+        ColumnTypeDict::MysqlTypeTimestamp2 => MySQLValue::Timestamp{
+            unix_time: rng.range(0, i32::MAX as i64) as i32,
+            subsecond: random_subsecond(rng, meta[0]),
+        },
+        ColumnTypeDict::MysqlTypeDatetime2 => MySQLValue::DateTime{
+            year: rng.range(1, 9999) as u32,
+            month: rng.range(1, 12) as u32,
+            day: rng.range(1, 31) as u32,
+            hour: rng.range(0, 23) as u32,
+            minute: rng.range(0, 59) as u32,
+            second: rng.range(0, 59) as u32,
+            subsecond: random_subsecond(rng, meta[0]),
+        },
+        ColumnTypeDict::MysqlTypeTimestamp => MySQLValue::Timestamp{ unix_time: rng.range(0, i32::MAX as i64) as i32, subsecond: 0 },
+        ColumnTypeDict::MysqlTypeDatetime => MySQLValue::DateTime{
+            year: rng.range(1, 9999) as u32,
+            month: rng.range(1, 12) as u32,
+            day: rng.range(1, 31) as u32,
+            hour: rng.range(0, 23) as u32,
+            minute: rng.range(0, 59) as u32,
+            second: rng.range(0, 59) as u32,
+            subsecond: 0,
+        },
+        ColumnTypeDict::MysqlTypeTime => random_time(rng, 0),
+        ColumnTypeDict::MysqlTypeYear => MySQLValue::Year(rng.range(1901, 2155) as u32),
+        ColumnTypeDict::MysqlTypeDate |
+        ColumnTypeDict::MysqlTypeNewdate => MySQLValue::Date{
+            year: rng.range(1, 9999) as u32,
+            month: rng.range(1, 12) as u32,
+            day: rng.range(1, 31) as u32,
+        },
+        ColumnTypeDict::MysqlTypeTime2 => {
+            let subseconds = random_subsecond(rng, meta[0]);
+            random_time(rng, subseconds)
+        }
+        ColumnTypeDict::MysqlTypeVarchar |
+        ColumnTypeDict::MysqlTypeVarString => MySQLValue::Blob(rng.bytes(if meta[0] == 1 { 255 } else { 1000 })),
+        ColumnTypeDict::MysqlTypeString => MySQLValue::Blob(rng.bytes(meta[0])),
+        ColumnTypeDict::MysqlTypeTinyBlob => MySQLValue::Blob(rng.bytes(255)),
+        ColumnTypeDict::MysqlTypeBlob |
+        ColumnTypeDict::MysqlTypeMediumBlob => MySQLValue::Blob(rng.bytes(if meta[0] == 1 { 255 } else { 2000 })),
+        ColumnTypeDict::MysqlTypeEnum => MySQLValue::SignedInteger(rng.range(0, if meta[0] == 1 { 255 } else { 65535 })),
+        ColumnTypeDict::MysqlTypeSet => MySQLValue::SignedInteger((rng.next() >> (64 - 8 * meta[0])) as i64),
+        ColumnTypeDict::MysqlTypeJson => MySQLValue::Json(random_json(rng, 0)),
+        _ => MySQLValue::Null,
+    }
+}
+
+fn read_all(bytes: Vec<u8>) -> Vec<Event> {
+    let reader = BinlogReader::new(Cursor::new(bytes)).unwrap();
+    reader.collect::<std::io::Result<Vec<_>>>().unwrap()
+}
+
+fn gtid_event(rng: &mut Rng, seq: u64) -> GtidEvent {
+    let mut sid = [0u8; 16];
+    sid[..8].copy_from_slice(&rng.next().to_le_bytes());
+    sid[8..].copy_from_slice(&rng.next().to_le_bytes());
+    GtidEvent{
+        gtid: uuid::Uuid::from_bytes(sid),
+        gno_id: rng.range(1, i64::MAX) as u64,
+        last_committed: seq - 1,
+        sequence_number: seq,
+        commit_timestamp: None,
+    }
+}
+
+fn query_event(command: &str, invoker: &str) -> QueryEvent {
+    QueryEvent{
+        thread_id: 12,
+        execute_seconds: 0,
+        database: String::from("db1"),
+        command: command.to_string(),
+        invoker: invoker.to_string(),
+    }
+}
+
+#[test]
+fn rows_round_trip() {
+    for seed in 0..300u64 {
+        let mut rng = Rng::new(seed);
+        let version = VERSIONS[seed as usize % 3];
+        let map = TableMap{
+            database_name: String::from("db1"),
+            table_name: format!("t{}", seed),
+            column_count: 0,
+            column_info: (0..rng.range(1, 30)).map(|_| random_column(&mut rng)).collect(),
+            metadata: None,
+        };
+        let kind = [BinlogEvent::WriteEvent, BinlogEvent::UpdateEvent, BinlogEvent::DeleteEvent][rng.range(0, 2) as usize].clone();
+        let count = rng.range(1, 5) * if let BinlogEvent::UpdateEvent = kind { 2 } else { 1 };
+        let rows: Vec<Vec<Option<MySQLValue>>> = (0..count)
+            .map(|_| map.column_info.iter().map(|col| Some(random_value(&mut rng, col))).collect())
+            .collect();
+
+        let mut builder = BinlogBuilder::new(version);
+        builder.timestamp(1572566400);
+        builder.table_map(100 + seed, &map).unwrap();
+        builder.rows(&kind, 100 + seed, &map, &rows).unwrap();
+        let events = read_all(builder.into_bytes());
+
+        match &events[2].data {
+            Traction::RowEvent(_, value) => assert_eq!(value.rows(), &rows[..], "seed {}", seed),
+            other => panic!("seed {}: {:?}", seed, other),
+        }
+    }
+}
+
+#[test]
+fn json_round_trip() {
+    let col = column(ColumnTypeDict::MysqlTypeJson, &[4]);
+    for seed in 0..500u64 {
+        let mut rng = Rng::new(seed);
+        let value = random_json(&mut rng, 0);
+        let map = TableMap{
+            database_name: String::from("db1"),
+            table_name: String::from("t1"),
+            column_count: 1,
+            column_info: vec![col.clone()],
+            metadata: None,
+        };
+        let rows = vec![vec![Some(MySQLValue::Json(value))]];
+        let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+        builder.table_map(100, &map).unwrap();
+        builder.rows(&BinlogEvent::WriteEvent, 100, &map, &rows).unwrap();
+        match &read_all(builder.into_bytes())[2].data {
+            Traction::RowEvent(_, value) => assert_eq!(value.rows(), &rows[..], "seed {}", seed),
+            other => panic!("seed {}: {:?}", seed, other),
+        }
+    }
+}
+
+#[test]
+fn decimal_edge_cases() {
+    let cases = [(1, 0, "0"), (1, 0, "-9"), (1, 1, "0.5"), (10, 2, "-1234.05"), (18, 9, "123456789.000000001"),
+        (65, 30, "-99999999999999999999999999999999999.999999999999999999999999999999"), (20, 10, "0.0000000001")];
+    for (precision, scale, value) in cases.iter() {
+        let value: BigDecimal = value.parse().unwrap();
+        let map = TableMap{
+            database_name: String::from("db1"),
+            table_name: String::from("t1"),
+            column_count: 1,
+            column_info: vec![column(ColumnTypeDict::MysqlTypeNewdecimal, &[*precision, *scale])],
+            metadata: None,
+        };
+        let rows = vec![vec![Some(MySQLValue::Decimal(value.clone()))]];
+        let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+        builder.table_map(100, &map).unwrap();
+        builder.rows(&BinlogEvent::WriteEvent, 100, &map, &rows).unwrap();
+        match &read_all(builder.into_bytes())[2].data {
+            Traction::RowEvent(_, v) => assert_eq!(v.rows(), &rows[..], "decimal({}, {}) {}", precision, scale, value),
+            other => panic!("{:?}", other),
+        }
+    }
+    //超出精度范围时返回错误
+    assert!(testing::decimal(&"100".parse().unwrap(), 2, 0).is_err());
+    //小数位数大于精度的metadata返回错误
+    assert!(DecimalMeta::new(2, 5).is_err());
+    assert_eq!(DecimalMeta::new(10, 2).unwrap().bytes_to_read, 5);
+}
+
+#[test]
+fn events_round_trip() {
+    for (idx, version) in VERSIONS.iter().enumerate() {
+        let mut rng = Rng::new(idx as u64);
+        let gtid = gtid_event(&mut rng, 7);
+        let mut builder = BinlogBuilder::new(*version).server_id(3306);
+        builder.timestamp(1572566400)
+            .gtid(&gtid)
+            .query(&query_event("BEGIN", ""))
+            .rows_query(&RowsQueryEvent{ query: String::from("insert into t1 values (1)") })
+            .xid(&XidEvent{ xid: 42 });
+        if *version != ServerVersion::Mysql80 {
+            //8.0的QUERY_EVENT、ROTATE_EVENT解析时不去掉末尾的checksum
+            builder.query(&query_event("CREATE DEFINER=`root`@`%` VIEW v1 AS SELECT 1", "root@%"))
+                .rotate(&RotateLog{ binlog_file: String::from("bin.000002"), position: 4, artificial: false });
+        }else {
+            builder.stop();
+        }
+        let end = builder.position();
+        let events = read_all(builder.into_bytes());
+
+        assert_eq!(events.last().map(|e| e.log_pos + e.header.event_length as u64), Some(end));
+        for event in &events {
+            assert_eq!(event.header.server_id, 3306);
+            assert_eq!(event.header.timestamp, 1572566400);
+        }
+        match &events[1].data {
+            Traction::GtidEvent(t) => {
+                assert_eq!((t.gtid, t.gno_id), (gtid.gtid, gtid.gno_id));
+                //5.6没有last_committed、sequence_number
+                let expected = if *version == ServerVersion::Mysql56 { (0, 0) } else { (6, 7) };
+                assert_eq!((t.last_committed, t.sequence_number), expected);
+                //8.0记录微秒的提交时间
+                let commit = if *version == ServerVersion::Mysql80 { Some(1572566400 * 1_000_000) } else { None };
+                assert_eq!(t.commit_timestamp, commit);
+            }
+            other => panic!("{:?}", other),
+        }
+        match &events[2].data {
+            Traction::QueryEvent(t) => assert_eq!((t.thread_id, t.database.as_str(), t.command.as_str()), (12, "db1", "BEGIN")),
+            other => panic!("{:?}", other),
+        }
+        match &events[3].data {
+            Traction::RowsQueryEvent(t) => assert_eq!(t.query, "insert into t1 values (1)"),
+            other => panic!("{:?}", other),
+        }
+        match &events[4].data {
+            Traction::XidEvent(t) => assert_eq!(t.xid, 42),
+            other => panic!("{:?}", other),
+        }
+        if *version != ServerVersion::Mysql80 {
+            match (&events[5].data, &events[6].data) {
+                (Traction::QueryEvent(q), Traction::RotateLogEvent(r)) => {
+                    assert_eq!((q.command.as_str(), q.invoker.as_str()), ("CREATE DEFINER=`root`@`%` VIEW v1 AS SELECT 1", "root@%"));
+                    assert_eq!((r.binlog_file.as_str(), r.position), ("bin.000002", 4));
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+}
+
+#[test]
+fn unsupported_values_are_rejected() {
+    let map = TableMap{
+        database_name: String::from("db1"),
+        table_name: String::from("t1"),
+        column_count: 1,
+        column_info: vec![column(ColumnTypeDict::MysqlTypeLong, &[0])],
+        metadata: None,
+    };
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    assert!(builder.rows(&BinlogEvent::WriteEvent, 100, &map, &[vec![Some(MySQLValue::Double(1.0))]]).is_err());
+    assert!(builder.rows(&BinlogEvent::UpdateEvent, 100, &map, &[vec![Some(MySQLValue::SignedInteger(1))]]).is_err());
+    assert!(builder.rows(&BinlogEvent::XidEvent, 100, &map, &[vec![None]]).is_err());
+    let bit = TableMap{ column_info: vec![column(ColumnTypeDict::MysqlTypeBit, &[0])], ..map };
+    assert!(builder.table_map(100, &bit).is_err());
+}
+
+//5.6之前的时间格式与新格式在同一个表中: TIMESTAMP为4bytes时间戳, DATETIME为8bytes整数YYYYMMDDhhmmss, TIME为3bytes整数hhmmss
+#[test]
+fn legacy_temporal_columns() {
+    let map = TableMap{
+        database_name: String::from("db1"),
+        table_name: String::from("t1"),
+        column_count: 5,
+        column_info: vec![
+            column(ColumnTypeDict::MysqlTypeDatetime, &[0]),
+            column(ColumnTypeDict::MysqlTypeDatetime2, &[0]),
+            column(ColumnTypeDict::MysqlTypeTime, &[0]),
+            column(ColumnTypeDict::MysqlTypeTimestamp, &[0]),
+            column(ColumnTypeDict::MysqlTypeNewdate, &[0]),
+        ],
+        metadata: None,
+    };
+    let datetime = MySQLValue::DateTime{ year: 2019, month: 11, day: 13, hour: 10, minute: 20, second: 30, subsecond: 0 };
+    let row = vec![
+        Some(datetime.clone()),
+        Some(datetime),
+        Some(MySQLValue::Time{ negative: false, hours: 838, minutes: 59, seconds: 58, subseconds: 0 }),
+        Some(MySQLValue::Timestamp{ unix_time: 1573640430, subsecond: 0 }),
+        Some(MySQLValue::Date{ year: 2019, month: 11, day: 13 }),
+    ];
+    let body = testing::rows_body(&BinlogEvent::WriteEvent, 100, &map, std::slice::from_ref(&row)).unwrap();
+    let mut expected = 20191113102030u64.to_le_bytes().to_vec();
+    expected.extend([0x99, 0xa4, 0x9a, 0xa5, 0x1e]);
+    expected.extend(&8385958u32.to_le_bytes()[..3]);
+    expected.extend(1573640430u32.to_le_bytes());
+    expected.extend(&((2019u32 << 9) | (11 << 5) | 13).to_le_bytes()[..3]);
+    assert!(body.ends_with(&expected), "{:02x?}", body);
+
+    for version in VERSIONS.iter() {
+        let mut builder = BinlogBuilder::new(*version);
+        builder.table_map(100, &map).unwrap();
+        builder.rows(&BinlogEvent::WriteEvent, 100, &map, std::slice::from_ref(&row)).unwrap();
+        let events = read_all(builder.into_bytes());
+        let rows = events.iter().find_map(|e| match &e.data {
+            Traction::RowEvent(_, rows) => Some(rows.rows().to_vec()),
+            _ => None
+        }).unwrap();
+        assert_eq!(rows, vec![row.clone()]);
+    }
+}
+
+//table_map中字段类型直接记录为enum、set(而不是string)时, metadata同样为实际类型及占用的字节数, 之后的字段位置正确
+#[test]
+fn enum_set_column_types() {
+    let map = TableMap{
+        database_name: String::from("db1"),
+        table_name: String::from("t1"),
+        column_count: 4,
+        column_info: vec![
+            column(ColumnTypeDict::MysqlTypeEnum, &[2]),
+            column(ColumnTypeDict::MysqlTypeSet, &[8]),
+            column(ColumnTypeDict::MysqlTypeVarchar, &[1]),
+            column(ColumnTypeDict::MysqlTypeNewdecimal, &[10, 2]),
+        ],
+        metadata: None,
+    };
+    let row = vec![
+        Some(MySQLValue::SignedInteger(300)),
+        Some(MySQLValue::SignedInteger(1 << 40 | 5)),
+        Some(MySQLValue::Blob(b"abc".to_vec())),
+        Some(MySQLValue::Decimal("12345678.90".parse().unwrap())),
+    ];
+    //类型列表在库名、表名、字段数之后, string(254)替换为metadata中的实际类型
+    let mut body = testing::table_map_body(100, &map).unwrap();
+    let types = 6 + 2 + 1 + 3 + 1 + 1 + 2 + 1 + 1;
+    assert_eq!(&body[types..types + 2], &[254, 254]);
+    body[types] = 247;
+    body[types + 1] = 248;
+
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.event(19, &body);
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, std::slice::from_ref(&row)).unwrap();
+    let events = read_all(builder.into_bytes());
+    let table = events.iter().find_map(|e| match &e.data {
+        Traction::TableMapEvent(t) => Some(t.clone()),
+        _ => None
+    }).unwrap();
+    let types: Vec<(ColumnTypeDict, Vec<usize>)> = table.column_info.iter().map(|c| (c.column_type.clone(), c.column_meta.clone())).collect();
+    assert_eq!(format!("{:?}", types), "[(MysqlTypeEnum, [2]), (MysqlTypeSet, [8]), (MysqlTypeVarchar, [1]), (MysqlTypeNewdecimal, [10, 2])]");
+    let rows = events.iter().find_map(|e| match &e.data {
+        Traction::RowEvent(_, rows) => Some(rows.rows().to_vec()),
+        _ => None
+    }).unwrap();
+    assert_eq!(rows, vec![row]);
+}
@@ -0,0 +1,92 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//按模板路由输出目标: 每个表、操作类型写入不同的文件, 其他事件写入所有已打开的目标
+
+#![cfg(all(feature = "os", feature = "json-sink"))]
+
+use std::env;
+use std::fs;
+use serde_json::Value as JsonValue;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn synth(name: &str) -> String {
+    let opts = SynthOptions{
+        database: String::from("route"),
+        tables: 3,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(32)],
+        transactions: 30,
+        rows: (1, 3),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 5,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-route-{}-{}.binlog", name, std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn template() {
+    assert!(RouteTemplate::new("{db}/{table}/{op}-{date}.json").is_ok());
+    assert!(RouteTemplate::new("binlog").unwrap().is_static());
+    assert!(RouteTemplate::new("{db}.{tbl}").is_err());
+    assert!(RouteTemplate::new("{db").is_err());
+
+    let template = RouteTemplate::new("binlog.{db}.{table}.{op}.{date}").unwrap();
+    let path = synth("template");
+    let mut routes = vec![];
+    for event in BinlogReader::open(&path).unwrap() {
+        let event = event.unwrap();
+        match template.render(&event) {
+            Some(route) => routes.push(route),
+            None => assert!(!matches!(event.data, Traction::RowEvent(..))),
+        }
+    }
+    fs::remove_file(&path).unwrap();
+    assert!(routes.contains(&String::from("binlog.route.t1.insert.2019-11-06")));
+    assert!(routes.iter().all(|r| r.starts_with("binlog.route.t") && r.ends_with(".2019-11-06")));
+}
+
+#[test]
+fn json_files() {
+    let path = synth("files");
+    let dir = env::temp_dir().join(format!("mytest-route-{}", std::process::id()));
+    let template = format!("{}/{{db}}/{{table}}.json", dir.to_str().unwrap());
+
+    let mut buf = vec![];
+    let mut single = JsonLinesSink::new(&mut buf, ValueFormat::default());
+    let mut sink = RoutedSink::json_files(&template, ValueFormat::default()).unwrap().max_open(2);
+    for event in BinlogReader::open(&path).unwrap() {
+        let event = event.unwrap();
+        single.write_event(&event).unwrap();
+        sink.write_event(&event).unwrap();
+    }
+    sink.flush().unwrap();
+    assert!(sink.routes().len() <= 2);
+    drop(sink);
+    drop(single);
+    fs::remove_file(&path).unwrap();
+
+    //按表拆分后的行数据与不拆分时相同, 每行的gtid也相同
+    let mut routed = vec![];
+    for tbl in ["t1", "t2", "t3"] {
+        let text = fs::read_to_string(dir.join("route").join(format!("{}.json", tbl))).unwrap();
+        for line in text.lines() {
+            let record: JsonValue = serde_json::from_str(line).unwrap();
+            assert_eq!(record["table"], tbl);
+            routed.push(line.to_string());
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    let mut expected: Vec<String> = String::from_utf8(buf).unwrap().lines().map(String::from).collect();
+    assert!(!expected.is_empty());
+    expected.sort();
+    routed.sort();
+    assert_eq!(routed, expected);
+}
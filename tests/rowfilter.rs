@@ -0,0 +1,135 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--where行数据过滤: 条件解析、按表生效、update成对判断、null的处理
+
+#![cfg(feature = "os")]
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+use mytest::rowfilter::RowFilter;
+
+fn cols() -> Vec<HashMap<String, String>> {
+    ["bigint", "int", "varchar"].iter().enumerate().map(|(idx, t)| {
+        let mut info = HashMap::new();
+        info.insert(String::from("COLUMN_NAME"), [String::from("id"), String::from("num"), String::from("name")][idx].clone());
+        info.insert(String::from("COLUMN_TYPE"), t.to_string());
+        info
+    }).collect()
+}
+
+fn synth() -> String {
+    let opts = SynthOptions{
+        database: String::from("shop"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 60,
+        rows: (1, 4),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 11,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-rowfilter-{}.binlog", std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+//返回(过滤前, 过滤后)的行事件, 过滤后被整体去掉的事件为None
+fn apply(path: &str, expr: &str) -> Vec<(String, Traction, Option<Traction>)> {
+    let mut filter = RowFilter::new(expr, ValueFormat::default()).unwrap();
+    let cols = cols();
+    let mut result = vec![];
+    for event in BinlogReader::open(path).unwrap() {
+        let event = event.unwrap();
+        if let (Traction::RowEvent(..), Some(map)) = (&event.data, &event.table) {
+            let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+            let filtered = filter.filter(&event.data, &db_tbl, &cols).unwrap().map(|t| t.into_owned());
+            result.push((db_tbl, event.data.clone(), filtered));
+        }
+    }
+    result
+}
+
+fn rows(data: &Traction) -> Vec<Vec<Option<MySQLValue>>> {
+    match data {
+        Traction::RowEvent(_, rows) => rows.rows().to_vec(),
+        _ => vec![]
+    }
+}
+
+fn num(row: &[Option<MySQLValue>]) -> Option<i64> {
+    match &row[1] {
+        Some(MySQLValue::SignedInteger(t)) => Some(*t),
+        _ => None
+    }
+}
+
+#[test]
+fn parse_errors() {
+    for expr in ["status = 'x'", "orders.status = test", "orders.status in ('a'", "orders.a = 1 or orders.b = 2",
+                 "orders.a is 1", "orders.a = 1 and", "orders.a 'x'", ""] {
+        assert!(RowFilter::new(expr, ValueFormat::default()).is_err(), "{}", expr);
+    }
+    let filter = RowFilter::new("orders.a = 1 and shop.users.b != 'x'", ValueFormat::default()).unwrap();
+    assert!(filter.applies_to("shop.orders"));
+    assert!(filter.applies_to("other.orders"));
+    assert!(filter.applies_to("shop.users"));
+    assert!(!filter.applies_to("other.users"));
+}
+
+#[test]
+fn compare_and_pairs() {
+    let path = synth();
+    let events = apply(&path, "shop.t1.num >= 1000000000 and t1.name is not null");
+    let (mut kept, mut dropped) = (0, 0);
+    for (db_tbl, before, after) in &events {
+        let matches = |row: &Vec<Option<MySQLValue>>| num(row).is_some_and(|n| n >= 1000000000) && row[2].is_some()
+            && !matches!(row[2], Some(MySQLValue::Null));
+        if db_tbl != "shop.t1" {
+            assert_eq!(rows(before).len(), rows(after.as_ref().unwrap()).len());
+            continue;
+        }
+        let after = after.as_ref().map(rows).unwrap_or_default();
+        match before {
+            Traction::RowEvent(BinlogEvent::UpdateEvent, _) => {
+                let expected: Vec<_> = rows(before).chunks(2).filter(|p| p.iter().any(matches)).flat_map(|p| p.to_vec()).collect();
+                assert_eq!(after.len(), expected.len());
+            }
+            _ => {
+                assert!(after.iter().all(matches));
+                assert_eq!(after.len(), rows(before).iter().filter(|r| matches(r)).count());
+            }
+        }
+        kept += after.len();
+        dropped += rows(before).len() - after.len();
+    }
+    assert!(kept > 0 && dropped > 0);
+
+    //decimal精确比较, in按字符串比较
+    let events = apply(&path, "t2.num in (1, 2) and t2.name not in ('')");
+    assert!(events.iter().filter(|(t, _, _)| t == "shop.t2").all(|(_, _, after)| after.is_none()));
+    let events = apply(&path, "t2.num is null");
+    let mut nulls = 0;
+    for (db_tbl, before, after) in &events {
+        if let (true, Some(after)) = (db_tbl == "shop.t2", after) {
+            let step = if let Traction::RowEvent(BinlogEvent::UpdateEvent, _) = before { 2 } else { 1 };
+            assert!(rows(after).chunks(step).all(|p| p.iter().any(|r| num(r).is_none())));
+            nulls += 1;
+        }
+    }
+    assert!(nulls > 0);
+
+    //字段不存在时返回错误
+    let mut filter = RowFilter::new("t1.missing = 1", ValueFormat::default()).unwrap();
+    let (_, data, _) = events.iter().find(|(t, _, _)| t == "shop.t1").unwrap();
+    assert!(filter.filter(data, "shop.t1", &cols()).is_err());
+    assert!(filter.filter(data, "shop.t2", &cols()).unwrap().is_some());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,186 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//行校验和: binlog中的值与目标库查询结果(HEX、UNIX_TIMESTAMP、col+0等)转换为相同的形式, 校验和一致;
+//rowcheck按主键保留最终的数据, 与模拟的目标库比较
+
+#![cfg(feature = "os")]
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::str::FromStr;
+use bigdecimal::BigDecimal;
+use serde_json::json;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+use mytest::rowhash::{self, HashKind, RowChecker};
+
+fn col(name: &str, col_type: &str, key: &str) -> HashMap<String, String> {
+    let mut col = HashMap::new();
+    col.insert(String::from("COLUMN_NAME"), name.to_string());
+    col.insert(String::from("COLUMN_TYPE"), col_type.to_string());
+    col.insert(String::from("COLUMN_KEY"), key.to_string());
+    col
+}
+
+fn info(column_type: ColumnTypeDict, meta: &[usize]) -> ColumnInfo {
+    ColumnInfo{ column_type, column_meta: meta.to_vec() }
+}
+
+#[test]
+fn binlog_target() {
+    let cols = vec![
+        col("id", "tinyint(3) unsigned", "PRI"),
+        col("amount", "decimal(10,2)", ""),
+        col("created", "datetime(3)", ""),
+        col("updated", "timestamp(6)", ""),
+        col("code", "char(8)", ""),
+        col("note", "varchar(32)", ""),
+        col("flags", "bit(10)", ""),
+        col("status", "enum('new','paid')", ""),
+        col("ratio", "float", ""),
+        col("extra", "json", ""),
+        col("empty", "varchar(8)", ""),
+        col("missing", "varchar(8)", ""),
+    ];
+    let map = TableMap{
+        database_name: String::from("shop"),
+        table_name: String::from("orders"),
+        column_count: cols.len() as u8,
+        column_info: vec![
+            info(ColumnTypeDict::MysqlTypeTiny, &[0]),
+            info(ColumnTypeDict::MysqlTypeNewdecimal, &[10, 2]),
+            info(ColumnTypeDict::MysqlTypeDatetime2, &[3]),
+            info(ColumnTypeDict::MysqlTypeTimestamp2, &[6]),
+            info(ColumnTypeDict::MysqlTypeString, &[8]),
+            info(ColumnTypeDict::MysqlTypeVarchar, &[1]),
+            info(ColumnTypeDict::MysqlTypeBit, &[2]),
+            info(ColumnTypeDict::MysqlTypeEnum, &[1]),
+            info(ColumnTypeDict::MysqlTypeFloat, &[4]),
+            info(ColumnTypeDict::MysqlTypeJson, &[4]),
+            info(ColumnTypeDict::MysqlTypeVarchar, &[1]),
+            info(ColumnTypeDict::MysqlTypeVarchar, &[1]),
+        ],
+        metadata: None,
+    };
+    let row = vec![
+        Some(MySQLValue::SignedInteger(-56)),
+        Some(MySQLValue::Decimal(BigDecimal::from_str("12.50").unwrap())),
+        Some(MySQLValue::DateTime{ year: 2019, month: 11, day: 6, hour: 10, minute: 0, second: 0, subsecond: 5000 }),
+        Some(MySQLValue::Timestamp{ unix_time: 1572998400, subsecond: 120000 }),
+        Some(MySQLValue::Blob(b"ab  ".to_vec())),
+        Some(MySQLValue::Blob("小菜鸟".as_bytes().to_vec())),
+        Some(MySQLValue::Blob(vec![0x02, 0x01])),
+        Some(MySQLValue::SignedInteger(2)),
+        Some(MySQLValue::Float(0.1)),
+        Some(MySQLValue::Json(json!({"b": 1, "a": [1, 2]}))),
+        Some(MySQLValue::Blob(vec![])),
+        Some(MySQLValue::Null),
+    ];
+    //目标库按select_sql查询的结果
+    let texts = ["=200", "=12.50", "=2019-11-06 10:00:00.500", "=1572998400.120000", "=6162",
+        &format!("={}", hex::encode("小菜鸟".as_bytes())), "=513", "=2", "=0.1", "={\"a\": [1, 2], \"b\": 1}", "=", ""];
+    let target: HashMap<String, String> = texts.iter().enumerate().map(|(idx, t)| (format!("c{}", idx), t.to_string())).collect();
+    let hash = rowhash::row_hash(&row, &cols, &map);
+    assert_eq!(hash.len(), 16);
+    assert_eq!(rowhash::target_hash(&target, &cols), hash);
+
+    //NULL与空字符串不同
+    let mut swapped = target.clone();
+    swapped.insert(String::from("c10"), String::new());
+    swapped.insert(String::from("c11"), String::from("="));
+    assert_ne!(rowhash::target_hash(&swapped, &cols), hash);
+    let mut changed = target.clone();
+    changed.insert(String::from("c1"), String::from("=12.51"));
+    assert_ne!(rowhash::target_hash(&changed, &cols), hash);
+
+    let sql = rowhash::select_sql("shop", "orders", &cols[..5], "`id` = 200");
+    assert_eq!(sql, "SELECT CONCAT('=', `id`) AS c0, CONCAT('=', `amount`) AS c1, CONCAT('=', `created`) AS c2, \
+CONCAT('=', UNIX_TIMESTAMP(`updated`)) AS c3, CONCAT('=', HEX(`code`)) AS c4 FROM `shop`.`orders` WHERE `id` = 200 LIMIT 1;");
+    assert_eq!(HashKind::new("varbinary(16)").condition("k", "00FF"), "`k` = X'00FF'");
+    assert_eq!(HashKind::new("datetime").condition("k", "2019-11-06 10:00:00"), "`k` = '2019-11-06 10:00:00'");
+}
+
+#[test]
+fn check() {
+    let opts = SynthOptions{
+        database: String::from("rowhash"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 50,
+        rows: (1, 3),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 7,
+        server_id: 133,
+    };
+    let dir = env::temp_dir().join(format!("mytest-rowhash-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("bin.000001");
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+
+    let cols = vec![col("id", "bigint(20)", "PRI"), col("c1", "int(11)", ""), col("c2", "varchar(16)", "")];
+    let mut checker = RowChecker::new();
+    //模拟完全应用后的目标库: 按id保存每行查询结果
+    let mut target: HashMap<String, Option<HashMap<String, String>>> = HashMap::new();
+    for event in BinlogReader::open(path.to_str().unwrap()).unwrap() {
+        let event = event.unwrap();
+        let (map, (code, rows)) = match (&event.table, &event.data) {
+            (Some(map), Traction::RowEvent(code, rows)) => (map, (code, rows)),
+            _ => continue
+        };
+        checker.record(&event.data, map, &cols);
+        let rows = rows.rows();
+        let images: Vec<&Vec<Option<MySQLValue>>> = match code {
+            BinlogEvent::UpdateEvent => rows.iter().skip(1).step_by(2).collect(),
+            _ => rows.iter().collect(),
+        };
+        for row in images {
+            let values: HashMap<String, String> = row.iter().enumerate().map(|(idx, value)| {
+                let kind = HashKind::new(cols[idx].get("COLUMN_TYPE").unwrap());
+                (format!("c{}", idx), rowhash::canonical_value(value, kind, 0).map(|t| format!("={}", t)).unwrap_or_default())
+            }).collect();
+            let key = format!("{}.{} `id` = {}", map.database_name, map.table_name, values["c0"].trim_start_matches('='));
+            target.insert(key, if let BinlogEvent::DeleteEvent = code { None } else { Some(values) });
+        }
+    }
+    let query = |target: &HashMap<String, Option<HashMap<String, String>>>, sql: &str| {
+        let table = sql.split(" FROM ").nth(1).unwrap().split(" WHERE ").next().unwrap().replace('`', "");
+        let condition = sql.split(" WHERE ").nth(1).unwrap().trim_end_matches(" LIMIT 1;");
+        Ok(target.get(&format!("{} {}", table, condition)).cloned().flatten().into_iter().collect())
+    };
+    let report = checker.check(|sql| query(&target, sql), |_, _| Some(cols.clone())).unwrap();
+    assert!(report.is_ok(), "{:?}", report);
+    assert_eq!(report.rows() as usize, target.len());
+
+    //修改、删除、残留各一行
+    let live: Vec<String> = target.iter().filter(|(_, v)| v.is_some()).map(|(k, _)| k.clone()).collect();
+    let deleted = target.iter().find(|(_, v)| v.is_none()).map(|(k, _)| k.clone()).unwrap();
+    let mut broken = target.clone();
+    broken.get_mut(&live[0]).unwrap().as_mut().unwrap().insert(String::from("c1"), String::from("=-1"));
+    broken.insert(live[1].clone(), None);
+    broken.insert(deleted, target[&live[2]].clone());
+    let report = checker.check(|sql| query(&broken, sql), |_, _| Some(cols.clone())).unwrap();
+    assert!(!report.is_ok());
+    assert_eq!((report.mismatched.len(), report.missing.len(), report.unexpected.len()), (1, 1, 1));
+    assert_eq!(report.matched as usize, target.len() - 3);
+
+    //没有主键的表跳过
+    let nopk = vec![col("id", "bigint(20)", ""), col("c1", "int(11)", ""), col("c2", "varchar(16)", "")];
+    let mut checker = RowChecker::new();
+    for event in BinlogReader::open(path.to_str().unwrap()).unwrap() {
+        let event = event.unwrap();
+        if let Some(map) = &event.table {
+            checker.record(&event.data, map, &nopk);
+        }
+    }
+    let report = checker.check(|_| Ok(vec![]), |_, _| Some(nopk.clone())).unwrap();
+    assert_eq!(report.rows(), 0);
+    assert_eq!(report.skipped.len(), 2);
+}
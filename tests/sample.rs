@@ -0,0 +1,105 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--sample按表抽样: 1/N按行计数, key:B/N按主键分桶, 各个桶合起来为全部数据
+
+#![cfg(feature = "os")]
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+use mytest::sample::{SampleMode, Sampler};
+
+fn cols() -> Vec<HashMap<String, String>> {
+    [("id", "bigint", "PRI"), ("num", "int", ""), ("name", "varchar", "")].iter().map(|(name, col_type, key)| {
+        let mut info = HashMap::new();
+        info.insert(String::from("COLUMN_NAME"), name.to_string());
+        info.insert(String::from("COLUMN_TYPE"), col_type.to_string());
+        info.insert(String::from("COLUMN_KEY"), key.to_string());
+        info
+    }).collect()
+}
+
+fn synth() -> String {
+    let opts = SynthOptions{
+        database: String::from("hot"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 80,
+        rows: (1, 5),
+        rate: 10,
+        mix: [2, 2, 1],
+        start_time: 1572998400,
+        seed: 13,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-sample-{}.binlog", std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+//每个表抽样后的行(update为一对), 以(表, 事件position, 行文本)表示
+fn sampled(path: &str, config: &str) -> Vec<(String, u64, String)> {
+    let mut sampler = Sampler::parse(config).unwrap();
+    let cols = cols();
+    let mut result = vec![];
+    for event in BinlogReader::open(path).unwrap() {
+        let event = event.unwrap();
+        if let (Traction::RowEvent(code, _), Some(map)) = (&event.data, &event.table) {
+            let db_tbl = format!("{}.{}", map.database_name, map.table_name);
+            let step = if let BinlogEvent::UpdateEvent = code { 2 } else { 1 };
+            if let Some(data) = sampler.sample(&event.data, &db_tbl, &cols) {
+                if let Traction::RowEvent(_, rows) = data.as_ref() {
+                    for image in rows.rows().chunks(step) {
+                        result.push((db_tbl.clone(), event.log_pos, format!("{:?}", image)));
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+#[test]
+fn modes() {
+    assert_eq!(SampleMode::new("1/100"), Ok(SampleMode::Every(100)));
+    assert_eq!(SampleMode::new("10"), Ok(SampleMode::Every(10)));
+    assert_eq!(SampleMode::new("key:3/16"), Ok(SampleMode::KeyBucket(3, 16)));
+    for value in ["0", "2/10", "key:16/16", "key:1", "key:a/2", ""] {
+        assert!(SampleMode::new(value).is_err(), "{}", value);
+    }
+    assert!(Sampler::parse(r#"{"db.tbl": "1/0"}"#).is_err());
+    let sampler = Sampler::parse(r#"{"hot.t1": "1/2", "hot.*": "1/3", "*": "1/4"}"#).unwrap();
+    assert_eq!(sampler.mode("hot.t1"), Some(SampleMode::Every(2)));
+    assert_eq!(sampler.mode("hot.t2"), Some(SampleMode::Every(3)));
+    assert_eq!(sampler.mode("cold.t1"), Some(SampleMode::Every(4)));
+    assert_eq!(Sampler::parse(r#"{"hot.t1": "1/2"}"#).unwrap().mode("hot.t2"), None);
+}
+
+#[test]
+fn every_and_buckets() {
+    let path = synth();
+    let all = sampled(&path, "{}");
+    let count = |rows: &[(String, u64, String)], tbl: &str| rows.iter().filter(|r| r.0 == tbl).count();
+
+    //只对配置的表抽样, 每3行输出第1行
+    let every = sampled(&path, r#"{"hot.t1": "1/3"}"#);
+    assert_eq!(count(&every, "hot.t1"), count(&all, "hot.t1").div_ceil(3));
+    assert_eq!(count(&every, "hot.t2"), count(&all, "hot.t2"));
+
+    //各个桶之间没有重复, 合起来为全部数据
+    let mut union = HashSet::new();
+    for bucket in 0..4 {
+        let rows = sampled(&path, &format!(r#"{{"*": "key:{}/4"}}"#, bucket));
+        assert!(!rows.is_empty() && rows.len() < all.len());
+        for row in rows {
+            assert!(union.insert(row));
+        }
+    }
+    assert_eq!(union.len(), all.len());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,119 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--schemaout: DDL涉及的表, 表第一次出现及DDL之后重新获取表结构时输出表结构文档
+
+#![cfg(feature = "os")]
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use serde_json::Value as JsonValue;
+use mytest::prelude::*;
+use mytest::replication::readevent::QueryEvent;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+use mytest::stdout::outddl::ddl_tables;
+use mytest::stdout::outschema::SchemaLog;
+
+#[test]
+fn tables() {
+    let t = |sql: &str| ddl_tables(sql, "db");
+    let table = |name: &str, dropped: bool| (name.to_string(), dropped);
+    assert_eq!(t("CREATE TABLE IF NOT EXISTS `t1` (id int)"), vec![table("db.t1", false)]);
+    assert_eq!(t("create temporary table t1(id int)"), vec![table("db.t1", false)]);
+    assert_eq!(t("ALTER TABLE other.t1 ADD COLUMN c int"), vec![table("other.t1", false)]);
+    assert_eq!(t("/* comment */ TRUNCATE t1"), vec![table("db.t1", false)]);
+    assert_eq!(t("DROP TABLE IF EXISTS `t1`, t2 /* generated by server */"), vec![table("db.t1", true), table("db.t2", true)]);
+    assert_eq!(t("RENAME TABLE t1 TO t1_old, t2 TO t1"), vec![table("db.t1", true), table("db.t1_old", false), table("db.t2", true), table("db.t1", false)]);
+    assert_eq!(t("CREATE UNIQUE INDEX idx ON t1 (c)"), vec![table("db.t1", false)]);
+    assert_eq!(t("DROP INDEX idx ON `db2`.`t1`"), vec![table("db2.t1", false)]);
+    assert!(t("CREATE DATABASE db").is_empty());
+    assert!(t("CREATE VIEW v AS SELECT * FROM t1").is_empty());
+    assert!(t("BEGIN").is_empty());
+}
+
+fn cols(names: &[&str]) -> Vec<HashMap<String, String>> {
+    names.iter().enumerate().map(|(idx, name)| {
+        let mut info = HashMap::new();
+        info.insert(String::from("COLUMN_NAME"), name.to_string());
+        info.insert(String::from("COLUMN_TYPE"), String::from("int"));
+        info.insert(String::from("COLUMN_KEY"), String::from(if idx == 0 { "PRI" } else { "" }));
+        info
+    }).collect()
+}
+
+fn query(sql: &str) -> Traction {
+    Traction::QueryEvent(QueryEvent{ thread_id: 1, execute_seconds: 0, database: String::from("evolve"), command: sql.to_string(), invoker: String::new() })
+}
+
+#[test]
+fn documents() {
+    let opts = SynthOptions{
+        database: String::from("evolve"),
+        tables: 2,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 40,
+        rows: (1, 2),
+        rate: 10,
+        mix: [2, 1, 1],
+        start_time: 1572998400,
+        seed: 7,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-schema-{}.binlog", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    synthesize::write_file(&path, &opts).unwrap();
+    let out = format!("{}.schema", path);
+
+    //模拟meta::get_col: 只在表不存在时获取, t1在ALTER之后多一个字段
+    let mut altered = false;
+    let mut log = SchemaLog::new(&out).unwrap();
+    let mut table_cols_info = HashMap::new();
+    let mut db_tbl = String::new();
+    let mut transactions = 0;
+    for event in BinlogReader::open(&path).unwrap() {
+        let event = event.unwrap();
+        if let Traction::TableMapEvent(map) = &event.data {
+            db_tbl = format!("{}.{}", map.database_name, map.table_name);
+            let names: &[&str] = if altered && map.table_name == "t1" { &["id", "num", "name", "extra"] } else { &["id", "num", "name"] };
+            table_cols_info.entry(db_tbl.clone()).or_insert_with(|| cols(names));
+        }
+        log.record(&event.data, &mut table_cols_info, &db_tbl, event.log_pos, event.header.timestamp).unwrap();
+        if let Traction::XidEvent(_) = event.data {
+            transactions += 1;
+            if transactions == 10 {
+                altered = true;
+                log.record(&query("ALTER TABLE t1 ADD COLUMN extra int;"), &mut table_cols_info, &db_tbl, 1, 0).unwrap();
+                //字段没有变化的DDL也输出, ddl为该语句
+                log.record(&query("ALTER TABLE t2 COMMENT 'x'"), &mut table_cols_info, &db_tbl, 2, 0).unwrap();
+            }
+        }
+    }
+    log.record(&query("DROP TABLE t2"), &mut table_cols_info, &db_tbl, 3, 0).unwrap();
+    log.finish().unwrap();
+    drop(log);
+    let text = fs::read_to_string(&out).unwrap();
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&out).unwrap();
+
+    let docs: Vec<JsonValue> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let t1: Vec<&JsonValue> = docs.iter().filter(|d| d["table"] == "t1").collect();
+    let t2: Vec<&JsonValue> = docs.iter().filter(|d| d["table"] == "t2").collect();
+    assert_eq!(t1.len(), 2);
+    assert!(t1[0]["ddl"].as_str().unwrap().starts_with("CREATE TABLE IF NOT EXISTS `t1`"));
+    assert_eq!(t1[0]["columns"].as_array().unwrap().len(), 3);
+    assert_eq!(t1[0]["primary_key"], serde_json::json!(["id"]));
+    assert!(t1[0]["gtid"].is_string());
+    assert_eq!(t1[1]["ddl"], "ALTER TABLE t1 ADD COLUMN extra int");
+    assert_eq!(t1[1]["position"], 1);
+    assert_eq!(t1[1]["columns"][3]["name"], "extra");
+    assert_eq!(t2.len(), 3);
+    assert_eq!(t2[0]["database"], "evolve");
+    assert_eq!(t2[1]["ddl"], "ALTER TABLE t2 COMMENT 'x'");
+    assert_eq!(t2[1]["columns"], t2[0]["columns"]);
+    assert!(t2[2]["columns"].is_null());
+    assert_eq!(t2[2]["dropped"], true);
+    assert_eq!(t2[2]["ddl"], "DROP TABLE t2");
+}
@@ -0,0 +1,102 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//SHOW BINLOG EVENTS的行转换为Event, 按事务汇总语句及写入的表
+
+#![cfg(feature = "network")]
+
+use std::collections::HashMap;
+use mytest::prelude::*;
+use mytest::showevents::{AuditTrail, EventConverter};
+
+const SID: &str = "3e11fa47-71ca-11e1-9e33-c80aa9429562";
+
+fn row(pos: u64, event_type: &str, end: u64, info: &str) -> HashMap<String, String> {
+    let mut row = HashMap::new();
+    row.insert(String::from("Log_name"), String::from("mysql-bin.000001"));
+    row.insert(String::from("Pos"), pos.to_string());
+    row.insert(String::from("Event_type"), event_type.to_string());
+    row.insert(String::from("Server_id"), String::from("1"));
+    row.insert(String::from("End_log_pos"), end.to_string());
+    row.insert(String::from("Info"), info.to_string());
+    row
+}
+
+fn rows() -> Vec<HashMap<String, String>> {
+    vec![
+        row(4, "Format_desc", 126, "Server ver: 8.0.36, Binlog ver: 4"),
+        row(126, "Previous_gtids", 157, ""),
+        row(157, "Gtid", 236, &format!("SET @@SESSION.GTID_NEXT= '{}:5'", SID)),
+        row(236, "Query", 319, "BEGIN"),
+        row(319, "Rows_query", 380, "# insert into orders values(1)"),
+        row(380, "Table_map", 430, "table_id: 108 (shop.orders)"),
+        row(430, "Write_rows", 470, "table_id: 108 flags: STMT_END_F"),
+        row(470, "Xid", 501, "COMMIT /* xid=12 */"),
+        row(501, "Gtid", 580, &format!("SET @@SESSION.GTID_NEXT= '{}:6'", SID)),
+        row(580, "Query", 700, "use `shop`; ALTER TABLE orders ADD c int"),
+        row(700, "Rotate", 744, "mysql-bin.000002;pos=4"),
+    ]
+}
+
+#[test]
+fn convert() {
+    let mut converter = EventConverter::new();
+    let events: Vec<Event> = rows().iter().map(|t| converter.convert(t).unwrap()).collect();
+    assert_eq!(events[0].header.event_type, EventType::FormatDescriptionEvent);
+    assert_eq!((events[2].log_pos, events[2].header.next_position, events[2].header.server_id), (157, 236, 1));
+    match &events[2].data {
+        Traction::GtidEvent(t) => assert_eq!(format!("{}:{}", t.gtid, t.gno_id), format!("{}:5", SID)),
+        other => panic!("{:?}", other),
+    }
+    match &events[6].data {
+        Traction::RowEventStatic{ type_code: BinlogEvent::WriteEvent, .. } => {}
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(events[6].table.as_ref().map(|t| t.table_name.as_str()), Some("orders"));
+    match &events[7].data {
+        Traction::XidEvent(t) => assert_eq!(t.xid, 12),
+        other => panic!("{:?}", other),
+    }
+    match &events[9].data {
+        Traction::QueryEvent(t) => assert_eq!((t.database.as_str(), t.command.as_str()), ("shop", "ALTER TABLE orders ADD c int")),
+        other => panic!("{:?}", other),
+    }
+    match &events[10].data {
+        Traction::RotateLogEvent(t) => assert_eq!((t.binlog_file.as_str(), t.position), ("mysql-bin.000002", 4)),
+        other => panic!("{:?}", other),
+    }
+    //没有事件时间
+    assert!(events.iter().all(|e| e.header.timestamp == 0));
+    assert!(converter.convert(&row(4, "Query", 0, "")).is_ok());
+    assert!(converter.convert(&row(4, "Gtid", 80, "SET @@SESSION.GTID_NEXT= 'x'")).is_err());
+}
+
+#[test]
+fn audit() {
+    let mut converter = EventConverter::new();
+    let mut trail = AuditTrail::new("mysql-bin.000001");
+    let records: Vec<_> = rows().iter().filter_map(|t| trail.feed(&converter.convert(t).unwrap())).collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["gtid"], format!("{}:5", SID));
+    assert_eq!(records[0]["position"], 157);
+    assert_eq!(records[0]["tables"][0], "shop.orders");
+    assert_eq!(records[0]["row_events"], 1);
+    assert_eq!(records[0]["statements"][0]["sql"], "insert into orders values(1)");
+    //DDL为单独的事务
+    assert_eq!(records[1]["statements"][0]["database"], "shop");
+    assert_eq!(records[1]["log_file"], "mysql-bin.000001");
+
+    //statement格式的DML
+    let mut trail = AuditTrail::new("mysql-bin.000002");
+    let statement = vec![
+        row(4, "Query", 80, "BEGIN"),
+        row(80, "Query", 160, "use `shop`; update orders set c = 1"),
+        row(160, "Xid", 191, "COMMIT /* xid=13 */"),
+    ];
+    let records: Vec<_> = statement.iter().filter_map(|t| trail.feed(&converter.convert(t).unwrap())).collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["statements"][0]["sql"], "update orders set c = 1");
+    assert_eq!(records[0]["log_file"], "mysql-bin.000002");
+}
@@ -0,0 +1,101 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//snapshot: 在csv基础数据上按事务应用binlog中的变更, 还原某个时间点表中的数据
+#![cfg(all(feature = "test-support", feature = "os"))]
+
+mod common;
+
+use mytest::prelude::*;
+use mytest::snapshot::{self, TableSnapshot};
+use mytest::stdout::format::ValueFormat;
+use common::{order, orders, START};
+
+const BASE: &str = "id,status\n1,new\n2,\"\"\n3,\n";
+
+//每个事务一个行事件, 间隔10秒, 返回文件路径及第二个事务的起始位置
+fn write(name: &str, metadata: bool) -> (String, u64) {
+    let (bytes, positions) = common::transactions(&orders(metadata), &[
+        (BinlogEvent::UpdateEvent, vec![order(1, Some("new")), order(1, Some("paid"))]),
+        (BinlogEvent::DeleteEvent, vec![order(2, Some(""))]),
+        (BinlogEvent::WriteEvent, vec![order(4, Some("a,\"b\""))]),
+        (BinlogEvent::UpdateEvent, vec![order(3, None), order(30, None)]),
+    ]);
+    (common::write_file("snapshot", name, bytes), positions[1])
+}
+
+fn restore(path: &str, base: &str, key: &[&str], start_position: u64, stop: u32) -> TableSnapshot {
+    let key: Vec<String> = key.iter().map(|t| t.to_string()).collect();
+    let mut snapshot = TableSnapshot::from_csv("shop.orders", base, &key, ValueFormat::default()).unwrap();
+    snapshot::apply_files(&mut snapshot, &[path.to_string()], start_position, (0, stop), |_| None).unwrap();
+    snapshot
+}
+
+fn csv(snapshot: &TableSnapshot) -> String {
+    let mut out = vec![];
+    snapshot.write_csv(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn parse_csv() {
+    let records = snapshot::parse_csv("a,b,c\r\n1,,\"\"\n\"x,\"\"y\"\"\nz\",\\N,\"\\N\"").unwrap();
+    assert_eq!(records, vec![
+        vec![Some(String::from("a")), Some(String::from("b")), Some(String::from("c"))],
+        vec![Some(String::from("1")), None, Some(String::new())],
+        vec![Some(String::from("x,\"y\"\nz")), None, Some(String::from("\\N"))],
+    ]);
+    assert!(snapshot::parse_csv("\"a").is_err());
+    assert!(snapshot::parse_csv("\"a\"b").is_err());
+}
+
+#[test]
+fn point_in_time() {
+    let (path, _) = write("metadata.000001", true);
+    //第二个事务之后
+    let snapshot = restore(&path, BASE, &["id"], 0, START + 15);
+    assert_eq!(csv(&snapshot), "id,status\n1,paid\n3,\n");
+    assert_eq!((snapshot.stats().transactions, snapshot.stats().conflicts, snapshot.stats().last_timestamp), (2, 0, START + 10));
+
+    //修改主键的行移到最后, 输出的csv可以作为基础数据再次读取
+    let snapshot = restore(&path, BASE, &["id"], 0, 0);
+    let out = csv(&snapshot);
+    assert_eq!(out, "id,status\n1,paid\n4,\"a,\"\"b\"\"\"\n30,\n");
+    let stats = snapshot.stats();
+    assert_eq!((stats.inserted, stats.updated, stats.deleted, stats.conflicts), (1, 2, 1, 0));
+    assert_eq!(TableSnapshot::from_csv("shop.orders", &out, &[], ValueFormat::default()).unwrap().rows().count(), 3);
+}
+
+#[test]
+fn overlap_and_position() {
+    let (path, second) = write("overlap.000001", true);
+    //基础数据中已经包含第一个事务, 从第二个事务开始应用
+    let base = "id,status\n1,paid\n2,\"\"\n3,\n";
+    let snapshot = restore(&path, base, &["id"], second, 0);
+    assert_eq!(csv(&snapshot), "id,status\n1,paid\n4,\"a,\"\"b\"\"\"\n30,\n");
+    assert_eq!(snapshot.stats().conflicts, 0);
+
+    //从头应用时按主键对应, 重复的更新结果相同; 重复的插入计为冲突
+    let snapshot = restore(&path, base, &["id"], 0, 0);
+    assert_eq!(csv(&snapshot), "id,status\n1,paid\n4,\"a,\"\"b\"\"\"\n30,\n");
+    assert_eq!(snapshot.stats().conflicts, 0);
+    let snapshot = restore(&path, "id,status\n1,paid\n3,\n4,x\n", &["id"], second, 0);
+    assert_eq!(csv(&snapshot), "id,status\n1,paid\n4,\"a,\"\"b\"\"\"\n30,\n");
+    assert_eq!(snapshot.stats().conflicts, 2);
+}
+
+#[test]
+fn without_metadata() {
+    //没有字段名时按顺序对应, 没有主键时按整行对应
+    let (path, _) = write("plain.000001", false);
+    let snapshot = restore(&path, BASE, &[], 0, START + 25);
+    assert_eq!(csv(&snapshot), "id,status\n3,\n1,paid\n4,\"a,\"\"b\"\"\"\n");
+
+    let err = TableSnapshot::from_csv("shop.orders", BASE, &[String::from("uid")], ValueFormat::default()).err().unwrap();
+    assert!(err.contains("uid"), "{}", err);
+    let mut snapshot = TableSnapshot::from_csv("shop.orders", "id\n1\n", &[], ValueFormat::default()).unwrap();
+    let err = snapshot::apply_files(&mut snapshot, &[path], 0, (0, 0), |_| None).err().unwrap();
+    assert!(err.to_string().contains("没有字段名"), "{}", err);
+}
@@ -0,0 +1,151 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//空间类型: srid+WKB解析为WKT、GeoJSON及ST_GeomFromText, geometry字段在TABLE_MAP及行数据中正确读取(之后的字段不错位),
+//损坏的数据返回错误而不是panic
+#![cfg(all(feature = "os", feature = "test-support"))]
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use serde_json::json;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::spatial::{Geometry, SpatialFormat, SpatialValue};
+use mytest::stdout::outjson;
+use mytest::stdout::outsql::{self, InsertMode};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+fn header(wkb_type: u32) -> Vec<u8> {
+    let mut data = vec![1];
+    data.extend(wkb_type.to_le_bytes());
+    data
+}
+
+fn coords(data: &mut Vec<u8>, points: &[(f64, f64)]) {
+    data.extend((points.len() as u32).to_le_bytes());
+    for (x, y) in points {
+        data.extend(x.to_le_bytes());
+        data.extend(y.to_le_bytes());
+    }
+}
+
+fn point(x: f64, y: f64) -> Vec<u8> {
+    let mut data = header(1);
+    data.extend(x.to_le_bytes());
+    data.extend(y.to_le_bytes());
+    data
+}
+
+fn with_srid(srid: u32, wkb: &[u8]) -> Vec<u8> {
+    let mut data = srid.to_le_bytes().to_vec();
+    data.extend_from_slice(wkb);
+    data
+}
+
+fn polygon() -> Vec<u8> {
+    let mut data = header(3);
+    data.extend(1u32.to_le_bytes());
+    coords(&mut data, &[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 0.0)]);
+    data
+}
+
+#[test]
+fn decode() {
+    let value = SpatialValue::decode(&with_srid(0, &point(1.0, 2.5))).unwrap();
+    assert_eq!(value.geometry, Geometry::Point((1.0, 2.5)));
+    assert_eq!(value.to_wkt(), "POINT(1 2.5)");
+    assert_eq!(value.to_geojson(), json!({"type": "Point", "coordinates": [1.0, 2.5]}));
+    assert_eq!(value.to_sql(), "ST_GeomFromText('POINT(1 2.5)')");
+
+    let value = SpatialValue::decode(&with_srid(4326, &polygon())).unwrap();
+    assert_eq!(value.to_wkt(), "SRID=4326;POLYGON((0 0,10 0,10 10,0 0))");
+    assert_eq!(value.to_json(SpatialFormat::GeoJson), json!({"type": "Polygon", "coordinates": [[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 0.0]]], "srid": 4326}));
+    assert_eq!(value.to_sql(), "ST_GeomFromText('POLYGON((0 0,10 0,10 10,0 0))', 4326, 'axis-order=long-lat')");
+
+    //MULTIPOINT中的元素为完整的WKB, 大端字节序同样支持
+    let mut multi = header(4);
+    multi.extend(2u32.to_le_bytes());
+    multi.extend(point(0.0, 0.0));
+    let mut big = vec![0];
+    big.extend(1u32.to_be_bytes());
+    big.extend(1.5f64.to_be_bytes());
+    big.extend((-1.0f64).to_be_bytes());
+    multi.extend(big);
+    let mut collection = header(7);
+    collection.extend(3u32.to_le_bytes());
+    collection.extend(multi);
+    let mut line = header(2);
+    coords(&mut line, &[(0.0, 0.0), (1.0, 1.0)]);
+    collection.extend(line);
+    let mut empty = header(7);
+    empty.extend(0u32.to_le_bytes());
+    collection.extend(empty);
+    let value = SpatialValue::decode(&with_srid(0, &collection)).unwrap();
+    assert_eq!(value.to_wkt(), "GEOMETRYCOLLECTION(MULTIPOINT((0 0),(1.5 -1)),LINESTRING(0 0,1 1),GEOMETRYCOLLECTION EMPTY)");
+    assert_eq!(value.to_geojson()["geometries"][0], json!({"type": "MultiPoint", "coordinates": [[0.0, 0.0], [1.5, -1.0]]}));
+
+    //截断、元素个数错误、未知类型、多余的数据
+    let data = with_srid(0, &polygon());
+    for len in 0..data.len() {
+        assert!(SpatialValue::decode(&data[..len]).is_err(), "len: {}", len);
+    }
+    let mut huge = header(2);
+    huge.extend(u32::MAX.to_le_bytes());
+    assert!(SpatialValue::decode(&with_srid(0, &huge)).is_err());
+    assert!(SpatialValue::decode(&with_srid(0, &header(99))).is_err());
+    let mut extra = with_srid(0, &point(1.0, 2.0));
+    extra.push(0);
+    assert!(SpatialValue::decode(&extra).is_err());
+}
+
+#[test]
+fn rows() {
+    let map = TableMap{
+        database_name: String::from("gis"),
+        table_name: String::from("places"),
+        column_count: 3,
+        column_info: vec![
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeGeometry, column_meta: vec![4] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeVarchar, column_meta: vec![1] },
+        ],
+        metadata: None,
+    };
+    let row = vec![
+        Some(MySQLValue::SignedInteger(1)),
+        Some(MySQLValue::Blob(with_srid(4326, &point(116.4, 39.9)))),
+        Some(MySQLValue::Blob(b"beijing".to_vec())),
+    ];
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, std::slice::from_ref(&row)).unwrap();
+    let mut parsed = None;
+    for event in BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap() {
+        let event = event.unwrap();
+        if let Traction::RowEvent(code, rows) = &event.data {
+            assert_eq!(rows.rows(), std::slice::from_ref(&row).to_vec().as_slice());
+            parsed = Some((code.clone(), rows.clone(), event.table.clone().unwrap()));
+        }
+    }
+    let (code, rows, table) = parsed.unwrap();
+    assert_eq!(table.column_info[1].column_meta, vec![4]);
+
+    let cols: Vec<HashMap<String, String>> = [("id", "int(11)"), ("location", "point"), ("name", "varchar(32)")].iter().map(|(name, col_type)| {
+        let mut col = HashMap::new();
+        col.insert(String::from("COLUMN_NAME"), name.to_string());
+        col.insert(String::from("COLUMN_TYPE"), col_type.to_string());
+        col.insert(String::from("COLUMN_KEY"), String::from(if *name == "id" { "PRI" } else { "" }));
+        col
+    }).collect();
+    let mut format = ValueFormat::default();
+    let records = outjson::row_records(&rows, &code, &cols, "gis.places", &table, "", &format);
+    assert_eq!(records[0]["data"]["location"], json!("SRID=4326;POINT(116.4 39.9)"));
+    format.spatial = SpatialFormat::GeoJson;
+    let records = outjson::row_records(&rows, &code, &cols, "gis.places", &table, "", &format);
+    assert_eq!(records[0]["data"]["location"], json!({"type": "Point", "coordinates": [116.4, 39.9], "srid": 4326}));
+    assert_eq!(outsql::out_insert(&row, &cols, &table, InsertMode::Insert, &format),
+               "INSERT INTO `gis`.`places`(`id`,`location`,`name`) VALUES(1,ST_GeomFromText('POINT(116.4 39.9)', 4326, 'axis-order=long-lat'),'beijing');");
+}
@@ -0,0 +1,60 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//生成sql语句时字面值及标识符的转义: 特殊字符、二进制数据、注入、反引号
+
+use mytest::stdout::sql_escape::*;
+
+#[test]
+fn escape_plain_string() {
+    assert_eq!(escape_string("abc"), "abc");
+    assert_eq!(escape_string(""), "");
+    assert_eq!(escape_string("中文"), "中文");
+}
+
+#[test]
+fn escape_special_chars() {
+    assert_eq!(escape_string("it's"), "it\\'s");
+    assert_eq!(escape_string("say \"hi\""), "say \\\"hi\\\"");
+    assert_eq!(escape_string("a\\b"), "a\\\\b");
+    assert_eq!(escape_string("a\nb\rc\td"), "a\\nb\\rc\\td");
+    assert_eq!(escape_string("a\0b"), "a\\0b");
+    assert_eq!(escape_string("a\x08b"), "a\\bb");
+    assert_eq!(escape_string("a\x1ab"), "a\\Zb");
+}
+
+#[test]
+fn escape_injection() {
+    assert_eq!(quote_string("'; DROP TABLE t; --"), "'\\'; DROP TABLE t; --'");
+    assert_eq!(quote_string("\\'"), "'\\\\\\''");
+}
+
+#[test]
+fn quote_string_literal() {
+    assert_eq!(quote_string("abc"), "'abc'");
+    assert_eq!(quote_string(""), "''");
+}
+
+#[test]
+fn quote_binary() {
+    assert_eq!(quote_bytes(&[0x00, 0x1f, 0xff]), "x'001fff'");
+    assert_eq!(quote_bytes(&[]), "x''");
+    assert_eq!(quote_bytes(b"'"), "x'27'");
+}
+
+#[test]
+fn quote_text_value() {
+    assert_eq!(quote_text(b"it's"), "'it\\'s'");
+    assert_eq!(quote_text(&[0xc0, 0x27]), "x'c027'");
+}
+
+#[test]
+fn quote_identifiers() {
+    assert_eq!(quote_identifier("t1"), "`t1`");
+    assert_eq!(quote_identifier("a`b"), "`a``b`");
+    assert_eq!(quote_identifier("``"), "``````");
+    assert_eq!(quote_identifier("order"), "`order`");
+    assert_eq!(quote_table("db", "t`1"), "`db`.`t``1`");
+}
@@ -0,0 +1,105 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//解析模式: 宽松模式下未知的事件类型、事件解析结束的位置与事件末尾不一致时输出警告后继续, 严格模式下返回错误;
+//长度大于255的char的metadata正确解析, 不再记录为65535
+#![cfg(feature = "test-support")]
+
+use std::io::Cursor;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::readvalue;
+use mytest::replication::readevent::{self, ColumnInfo, QueryEvent, XidEvent};
+use mytest::testing::{self, BinlogBuilder, ServerVersion};
+
+fn table() -> TableMap {
+    TableMap{
+        database_name: String::from("shop"),
+        table_name: String::from("orders"),
+        column_count: 2,
+        column_info: vec![
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] },
+            //char(100) utf8mb4
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeString, column_meta: vec![400] },
+        ],
+        metadata: None,
+    }
+}
+
+fn read_all(bytes: Vec<u8>) -> std::io::Result<Vec<Event>> {
+    BinlogReader::new(Cursor::new(bytes))?.collect()
+}
+
+//修改全局的解析模式, 所有检查放在同一个测试中
+#[test]
+fn parse_mode() {
+    assert_eq!(readevent::string_meta(0xfe, 32), Some((0xfe, 32)));
+    assert_eq!(readevent::string_meta(0xee, 0x90), Some((0xfe, 400)));
+    assert_eq!(readevent::string_meta(0xf7, 2), Some((0xf7, 2)));
+    assert_eq!(readevent::string_meta(0x0f, 8), None);
+
+    let map = table();
+    let row = vec![Some(MySQLValue::SignedInteger(1)), Some(MySQLValue::Blob(vec![b'a'; 300]))];
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, std::slice::from_ref(&row)).unwrap();
+    let clean = builder.into_bytes();
+
+    //行事件末尾多余的数据小于4bytes时才能与checksum区分, 不启用checksum
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80).checksum(false);
+    builder.event(200, b"unknown");
+    builder.table_map(100, &map).unwrap();
+    let mut body = testing::rows_body(&BinlogEvent::WriteEvent, 100, &map, std::slice::from_ref(&row)).unwrap();
+    body.extend([0, 0]);
+    builder.event(testing::rows_type_code(&BinlogEvent::WriteEvent).unwrap(), &body);
+    let broken = builder.into_bytes();
+
+    for strict in vec![false, true] {
+        readvalue::set_strict(strict);
+        let events = read_all(clean.clone()).unwrap();
+        let table = events.iter().find_map(|e| e.table.clone()).unwrap();
+        assert_eq!(table.column_info[1].column_meta, vec![400]);
+        assert!(events.iter().any(|e| matches!(&e.data, Traction::RowEvent(_, rows) if rows.rows() == std::slice::from_ref(&row))));
+
+        match read_all(broken.clone()) {
+            Ok(events) => {
+                assert!(!strict);
+                assert!(events.iter().any(|e| matches!(&e.data, Traction::RowEvent(_, rows) if rows.rows() == std::slice::from_ref(&row))));
+            }
+            Err(e) => {
+                assert!(strict, "{}", e);
+                assert!(e.to_string().contains("未知的事件类型: 200"), "{}", e);
+            }
+        }
+    }
+
+    //只有末尾的数据
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80).checksum(false);
+    builder.table_map(100, &map).unwrap();
+    builder.event(testing::rows_type_code(&BinlogEvent::WriteEvent).unwrap(), &body);
+    let trailing = builder.into_bytes();
+    let err = read_all(trailing.clone()).unwrap_err();
+    assert!(err.to_string().contains("WriteEvent解析了"), "{}", err);
+    assert!(err.to_string().contains("剩余2bytes未解析"), "{}", err);
+
+    //事件解析结束的位置: 启用、不启用checksum的query_event都完整读取sql, xid_event之后多余的数据
+    for (version, checksum) in vec![(ServerVersion::Mysql57, false), (ServerVersion::Mysql57, true), (ServerVersion::Mysql80, true)] {
+        let query = QueryEvent{ thread_id: 10, execute_seconds: 0, database: String::from("db1"),
+            command: String::from("INSERT INTO t1 VALUES(1)"), invoker: String::new() };
+        let mut builder = BinlogBuilder::new(version).checksum(checksum);
+        builder.query(&query);
+        builder.xid(&XidEvent{ xid: 9 });
+        let events = read_all(builder.into_bytes()).unwrap();
+        assert!(events.iter().any(|e| matches!(&e.data, Traction::QueryEvent(q) if q.command == query.command)));
+
+        let mut builder = BinlogBuilder::new(version).checksum(checksum);
+        builder.event(16, &[9, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3]);
+        let err = read_all(builder.into_bytes()).unwrap_err();
+        assert!(err.to_string().contains("XidEvent解析了8bytes, 事件数据为11bytes"), "{}", err);
+    }
+
+    readvalue::set_strict(false);
+    assert!(read_all(trailing).is_ok());
+}
@@ -0,0 +1,184 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//TABLE_MAP中的可选metadata(binlog_row_metadata=FULL): 编码后解析结果相同, 按mysql的格式手工构造的事件可以解析, 生成CREATE TABLE语句
+#![cfg(feature = "test-support")]
+
+use std::io::Cursor;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::replication::tablemeta::{ColumnMetadata, TableMetadata};
+use mytest::testing::{self, BinlogBuilder, ServerVersion};
+
+fn column(column_type: ColumnTypeDict, meta: &[usize]) -> ColumnInfo {
+    ColumnInfo{ column_type, column_meta: meta.to_vec() }
+}
+
+fn named(name: &str) -> ColumnMetadata {
+    ColumnMetadata{ name: Some(name.to_string()), ..Default::default() }
+}
+
+fn table() -> TableMap {
+    let columns = vec![
+        ColumnMetadata{ nullable: false, unsigned: true, ..named("id") },
+        ColumnMetadata{ nullable: false, collation: Some(255), length: 128, ..named("name") },
+        ColumnMetadata{ collation: Some(63), length: 16, ..named("hash") },
+        ColumnMetadata{ unsigned: true, ..named("amount") },
+        ColumnMetadata{ collation: Some(45), values: vec![String::from("new"), String::from("it's paid")], ..named("status") },
+        ColumnMetadata{ collation: Some(255), values: vec![String::from("a"), String::from("b")], ..named("tags") },
+        ColumnMetadata{ collation: Some(46), ..named("note") },
+        ColumnMetadata{ collation: Some(63), ..named("data") },
+        named("created"),
+        ColumnMetadata{ visible: false, ..named("extra") },
+    ];
+    TableMap{
+        database_name: String::from("shop"),
+        table_name: String::from("orders"),
+        column_count: columns.len() as u8,
+        column_info: vec![
+            column(ColumnTypeDict::MysqlTypeLonglong, &[0]),
+            column(ColumnTypeDict::MysqlTypeVarchar, &[1]),
+            column(ColumnTypeDict::MysqlTypeString, &[16]),
+            column(ColumnTypeDict::MysqlTypeNewdecimal, &[10, 2]),
+            column(ColumnTypeDict::MysqlTypeEnum, &[1]),
+            column(ColumnTypeDict::MysqlTypeSet, &[1]),
+            column(ColumnTypeDict::MysqlTypeBlob, &[2]),
+            column(ColumnTypeDict::MysqlTypeBlob, &[4]),
+            column(ColumnTypeDict::MysqlTypeDatetime2, &[3]),
+            column(ColumnTypeDict::MysqlTypeJson, &[4]),
+        ],
+        metadata: Some(TableMetadata{ columns, default_collation: Some(255), primary_key: vec![(0, 0), (1, 40)] }),
+    }
+}
+
+fn read_table_map(bytes: Vec<u8>) -> TableMap {
+    let reader = BinlogReader::new(Cursor::new(bytes)).unwrap();
+    for event in reader {
+        if let Traction::TableMapEvent(t) = event.unwrap().data {
+            return t;
+        }
+    }
+    panic!("没有TABLE_MAP_EVENT");
+}
+
+#[test]
+fn roundtrip() {
+    let map = table();
+    for checksum in [true, false] {
+        let mut builder = BinlogBuilder::new(ServerVersion::Mysql80).checksum(checksum);
+        builder.table_map(100, &map).unwrap();
+        let parsed = read_table_map(builder.into_bytes());
+        assert_eq!(parsed.metadata, map.metadata, "checksum: {}", checksum);
+    }
+
+    //没有可选metadata时为None, 不能生成CREATE TABLE
+    let minimal = TableMap{ metadata: None, ..table() };
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.table_map(100, &minimal).unwrap();
+    let parsed = read_table_map(builder.into_bytes());
+    assert_eq!(parsed.metadata, None);
+    assert!(parsed.create_table().unwrap_err().contains("binlog_row_metadata=FULL"));
+}
+
+#[test]
+fn create_table() {
+    let expected = "CREATE TABLE IF NOT EXISTS `shop`.`orders` (
+  `id` bigint unsigned NOT NULL,
+  `name` varchar(32) NOT NULL,
+  `hash` binary(16) NULL,
+  `amount` decimal(10,2) unsigned NULL,
+  `status` enum('new','it''s paid') CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL,
+  `tags` set('a','b') NULL,
+  `note` text CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NULL,
+  `data` longblob NULL,
+  `created` datetime(3) NULL,
+  `extra` json NULL /*!80023 INVISIBLE */,
+  PRIMARY KEY (`id`,`name`(10))
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci;";
+    assert_eq!(table().create_table().unwrap(), expected);
+}
+
+//按mysql 8.0的格式构造: CREATE TABLE t (id int unsigned primary key, c varchar(10) charset latin1) DEFAULT CHARSET=utf8mb4
+#[test]
+fn mysql_layout() {
+    let map = TableMap{
+        database_name: String::from("test"),
+        table_name: String::from("t"),
+        column_count: 2,
+        column_info: vec![column(ColumnTypeDict::MysqlTypeLong, &[0]), column(ColumnTypeDict::MysqlTypeVarchar, &[1])],
+        metadata: None,
+    };
+    let mut body = testing::table_map_body(88, &map).unwrap();
+    //varchar(10) latin1的最大字节数为10, 替换编码时的255; null_bitmap: id NOT NULL, c NULL
+    let len = body.len();
+    body[len - 3] = 10;
+    body[len - 1] = 0b10;
+    body.extend([1, 1, 0b1000_0000]);
+    //collation 255需要3bytes的packed integer
+    body.extend([2, 5, 0xfc, 255, 0, 0, 8]);
+    body.extend([4, 5, 2, b'i', b'd', 1, b'c']);
+    body.extend([8, 1, 0]);
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.event(19, &body);
+    let parsed = read_table_map(builder.into_bytes());
+    let metadata = parsed.metadata.as_ref().unwrap();
+    assert_eq!(metadata.columns[1].length, 10);
+    assert_eq!(parsed.create_table().unwrap(), "CREATE TABLE IF NOT EXISTS `test`.`t` (
+  `id` int unsigned NOT NULL,
+  `c` varchar(10) CHARACTER SET latin1 COLLATE latin1_swedish_ci NULL,
+  PRIMARY KEY (`id`)
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci;");
+}
+
+//char、binary: binlog中去掉了末尾的填充, 有字符集信息时binary按0x00补齐到定义的长度, char去掉末尾的空格
+#[test]
+fn fixed_string_padding() {
+    let map = TableMap{
+        database_name: String::from("test"),
+        table_name: String::from("t"),
+        column_count: 3,
+        column_info: vec![
+            column(ColumnTypeDict::MysqlTypeString, &[4]),
+            column(ColumnTypeDict::MysqlTypeString, &[400]),
+            column(ColumnTypeDict::MysqlTypeLong, &[0]),
+        ],
+        metadata: Some(TableMetadata{
+            columns: vec![
+                ColumnMetadata{ collation: Some(63), length: 4, ..named("b") },
+                ColumnMetadata{ collation: Some(255), length: 400, ..named("c") },
+                named("id"),
+            ],
+            default_collation: Some(255),
+            primary_key: vec![],
+        }),
+    };
+    let row = vec![
+        Some(MySQLValue::Blob(b"ab".to_vec())),
+        Some(MySQLValue::Blob("中文  ".as_bytes().to_vec())),
+        Some(MySQLValue::SignedInteger(7)),
+    ];
+    let read_rows = |map: &TableMap| {
+        let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+        builder.table_map(100, map).unwrap();
+        builder.rows(&BinlogEvent::WriteEvent, 100, map, std::slice::from_ref(&row)).unwrap();
+        let reader = BinlogReader::new(Cursor::new(builder.into_bytes())).unwrap();
+        for event in reader {
+            if let Traction::RowEvent(_, rows) = event.unwrap().data {
+                return rows.rows().to_vec();
+            }
+        }
+        panic!("没有行事件");
+    };
+    assert_eq!(read_rows(&map), vec![vec![
+        Some(MySQLValue::Blob(b"ab\0\0".to_vec())),
+        Some(MySQLValue::Blob("中文".as_bytes().to_vec())),
+        Some(MySQLValue::SignedInteger(7)),
+    ]]);
+
+    //没有字符集信息时无法区分char、binary, 按原值输出
+    let minimal = TableMap{ metadata: None, ..map };
+    assert_eq!(read_rows(&minimal), vec![row.clone()]);
+}
@@ -0,0 +1,80 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//输出模板: 取值、过滤器、#if/#unless/#each块、语法错误
+
+use serde_json::json;
+use mytest::template::Template;
+
+fn render(source: &str, value: serde_json::Value) -> String {
+    Template::parse(source).unwrap().render(&value)
+}
+
+#[test]
+fn values_and_filters() {
+    let record = json!({
+        "type": "update", "database": "shop", "table": "orders", "gtid": "3e11fa47-71ca-11e1-9e33-c80aa9429562:42",
+        "data": {"id": 7, "note": "a \"quoted\" note", "paid": true, "deleted_at": null},
+        "old": {"id": 7, "note": "old"}, "tags": ["x", "y"],
+    });
+    assert_eq!(render("{{type|upper}} {{database}}.{{table}} id={{data.id}}", record.clone()), "UPDATE shop.orders id=7");
+    assert_eq!(render("{{data.note|json}}|{{data.paid}}|{{data.deleted_at}}|{{missing.x}}", record.clone()),
+               "\"a \\\"quoted\\\" note\"|true||");
+    assert_eq!(render("{{data.deleted_at|default:-}} {{gtid|trunc:8}} {{tags.1}} {{old}}", record.clone()),
+               "- 3e11fa47 y {\"id\":7,\"note\":\"old\"}");
+    assert_eq!(render("{{! 注释 }}{{ type | lower }}", record), "update");
+}
+
+#[test]
+fn blocks() {
+    let insert = json!({"type": "insert", "data": {"id": 1, "name": "a"}});
+    let update = json!({"type": "update", "data": {"id": 1, "name": "b"}, "old": {"id": 1, "name": "a"}});
+    let source = "{{#if old}}{{old.name}}->{{data.name}}{{else}}new {{data.name}}{{/if}}";
+    assert_eq!(render(source, insert.clone()), "new a");
+    assert_eq!(render(source, update.clone()), "a->b");
+    assert_eq!(render("{{#unless old}}I{{/unless}}", insert.clone()), "I");
+    assert_eq!(render("{{#unless old}}I{{/unless}}", update), "");
+
+    //遍历对象时当前值中没有的字段到外层查找
+    let source = "{{#each data}}{{type}}:{{@key}}={{this}}{{#unless @last}},{{/unless}}{{/each}}";
+    assert_eq!(render(source, insert.clone()), "insert:id=1,insert:name=a");
+    let rows = json!({"rows": [{"id": 1}, {"id": 2}], "empty": []});
+    assert_eq!(render("{{#each rows}}{{@index}}/{{id}}{{#if @first}}*{{/if}} {{/each}}", rows.clone()), "0/1* 1/2 ");
+    assert_eq!(render("{{#each empty}}x{{else}}none{{/each}}", rows), "none");
+}
+
+#[test]
+fn syntax_errors() {
+    for (source, message) in vec![
+        ("{{type", "没有对应的}}"),
+        ("{{#if old}}x", "没有对应的{{/if}}"),
+        ("{{#if old}}x{{/each}}", "没有对应的{{/if}}"),
+        ("x{{/if}}", "没有对应的开始标签"),
+        ("{{#with data}}{{/with}}", "无效的模板块"),
+        ("{{type|reverse}}", "无效的模板过滤器"),
+        ("{{type|trunc:x}}", "trunc的参数应为数字"),
+        ("{{}}", "字段名为空"),
+    ] {
+        let err = Template::parse(source).unwrap_err();
+        assert!(err.contains(message), "{}: {}", source, err);
+    }
+}
+
+//命令行中的\t、\n, @文件名读取模板, 渲染为空时不输出
+#[cfg(feature = "os")]
+#[test]
+fn template_out() {
+    use mytest::stdout::outtemplate::TemplateOut;
+    let out = TemplateOut::new("{{#if data}}{{type}}\\t{{data.id}}{{/if}}").unwrap();
+    assert_eq!(out.render(&json!({"type": "insert", "data": {"id": 1}})).as_deref(), Some("insert\t1\n"));
+    assert_eq!(out.render(&json!({"type": "commit", "xid": 9})), None);
+
+    let path = std::env::temp_dir().join(format!("mytest_template_{}.hbs", std::process::id()));
+    std::fs::write(&path, "{{type}}\\t{{xid}}\n").unwrap();
+    let out = TemplateOut::new(&format!("@{}", path.display())).unwrap();
+    assert_eq!(out.render(&json!({"type": "commit", "xid": 9})).as_deref(), Some("commit\\t9\n"));
+    std::fs::remove_file(&path).unwrap();
+    assert!(TemplateOut::new("@/nonexistent/mytest.hbs").unwrap_err().contains("读取模板文件"));
+}
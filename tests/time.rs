@@ -0,0 +1,89 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//负数TIME: 5.6之前的TIME为有符号整数hhmmss, TIME2整数部分与小数部分按整体加偏移量存储, 解析及输出保留负号
+#![cfg(all(feature = "json-sink", feature = "test-support"))]
+
+use std::io::Cursor;
+use serde_json::json;
+use mytest::Config;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::stdout::format::BinaryEncoding;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+//TIME、TIME(1)、TIME(6)
+fn table() -> TableMap {
+    TableMap{
+        database_name: String::from("test"),
+        table_name: String::from("t"),
+        column_count: 3,
+        column_info: vec![
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeTime, column_meta: vec![] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeTime2, column_meta: vec![1] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeTime2, column_meta: vec![6] },
+        ],
+        metadata: None,
+    }
+}
+
+fn time(negative: bool, hours: u32, minutes: u32, seconds: u32, subseconds: u32) -> Option<MySQLValue> {
+    Some(MySQLValue::Time{ negative, hours, minutes, seconds, subseconds })
+}
+
+//-838:59:59、-00:00:01.5、-12:34:56.000001
+fn row() -> Vec<Option<MySQLValue>> {
+    vec![time(true, 838, 59, 59, 0), time(true, 0, 0, 1, 50), time(true, 12, 34, 56, 1)]
+}
+
+fn binlog(rows: &[Vec<Option<MySQLValue>>]) -> Vec<u8> {
+    let map = table();
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, rows).unwrap();
+    builder.into_bytes()
+}
+
+fn parse(bytes: Vec<u8>) -> Vec<Vec<Option<MySQLValue>>> {
+    BinlogReader::new(Cursor::new(bytes)).unwrap().filter_map(|event| match event.unwrap().data {
+        Traction::RowEvent(_, rows) => Some(rows.rows().to_vec()),
+        _ => None
+    }).next().unwrap()
+}
+
+#[test]
+fn storage() {
+    //与mysql的存储格式一致: TIME为小端有符号整数, TIME2为大端整数部分(加0x800000)及小数部分
+    let bytes = binlog(&[row()]);
+    let expected: Vec<u8> = [
+        (-8385959i32).to_le_bytes()[..3].to_vec(),
+        vec![0x7f, 0xff, 0xfe, 0xce],
+        (0x800000i64 * (1 << 24) - (51384 << 24) - 1).to_be_bytes()[2..].to_vec(),
+    ].concat();
+    assert!(bytes.windows(expected.len()).any(|w| w == expected.as_slice()));
+}
+
+#[test]
+fn roundtrip() {
+    let rows = vec![
+        row(),
+        vec![time(false, 838, 59, 59, 0), time(false, 0, 0, 1, 50), time(false, 12, 34, 56, 1)],
+        //没有小数部分的负数
+        vec![time(true, 0, 0, 1, 0), time(true, 1, 0, 0, 0), time(true, 0, 0, 0, 999999)],
+        vec![time(false, 0, 0, 0, 0), time(false, 0, 0, 0, 0), time(false, 0, 0, 0, 0)],
+    ];
+    assert_eq!(parse(binlog(&rows)), rows);
+}
+
+#[test]
+fn output() {
+    let format = ValueFormat::new(&Config::default()).unwrap();
+    let row = row();
+    assert_eq!(format.json_value(&row[0], "time", BinaryEncoding::Hex), json!("-838:59:59"));
+    assert_eq!(format.json_value(&row[1], "time(1)", BinaryEncoding::Hex), json!("-00:00:01.5"));
+    assert_eq!(format.text_value(&row[2], "time(6)", BinaryEncoding::Hex), Some(String::from("-12:34:56.000001")));
+    assert_eq!(format.time(false, 12, 34, 56, 0, 0), "12:34:56");
+}
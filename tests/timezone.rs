@@ -0,0 +1,60 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--timezone: TIMESTAMP默认按时间戳输出, 配置时区名称时转换为该时区的时间, sql、json、文本输出一致
+#![cfg(feature = "json-sink")]
+
+use serde_json::json;
+use mytest::Config;
+use mytest::prelude::*;
+use mytest::stdout::format::{BinaryEncoding, TimeZoneMode};
+
+//2019-11-01 00:00:00.05 UTC
+fn value() -> Option<MySQLValue> {
+    Some(MySQLValue::Timestamp{ unix_time: 1572566400, subsecond: 5 })
+}
+
+fn format(timezone: &str, timeformat: &str) -> Result<ValueFormat, String> {
+    ValueFormat::new(&Config{ timezone: timezone.to_string(), timeformat: timeformat.to_string(), ..Config::default() })
+}
+
+#[test]
+fn mode() {
+    assert_eq!(TimeZoneMode::new("").unwrap(), TimeZoneMode::Epoch);
+    assert_eq!(TimeZoneMode::new("epoch").unwrap(), TimeZoneMode::Epoch);
+    assert_eq!(TimeZoneMode::new("Asia/Shanghai").unwrap(), TimeZoneMode::Named(chrono_tz::Asia::Shanghai));
+    for invalid in ["Asia/Beijing", "utc+8", "+08:00"] {
+        let err = TimeZoneMode::new(invalid).unwrap_err();
+        assert!(err.contains("无效的timezone") && err.contains(invalid), "{}", err);
+        assert!(format(invalid, "").is_err());
+    }
+}
+
+#[test]
+fn named() {
+    let format = format("Asia/Shanghai", "").unwrap();
+    assert_eq!(format.timestamp(1572566400, 5, 2), Some(String::from("2019-11-01 08:00:00.05")));
+    assert_eq!(format.json_value(&value(), "timestamp(2)", BinaryEncoding::Hex), json!("2019-11-01 08:00:00.05"));
+    assert_eq!(format.text_value(&value(), "timestamp(2)", BinaryEncoding::Hex), Some(String::from("2019-11-01 08:00:00.05")));
+    assert_eq!(format.timestamp_sql(1572566400, 5, 2), "'2019-11-01 08:00:00.05'");
+    //零值不按时区转换
+    assert_eq!(format.timestamp(0, 0, 0), Some(String::from("0000-00-00 00:00:00")));
+}
+
+#[test]
+fn named_iso8601() {
+    //iso8601带时区偏移, 夏令时按当时的偏移
+    let format = format("America/New_York", "iso8601").unwrap();
+    assert_eq!(format.timestamp(1572566400, 0, 0), Some(String::from("2019-10-31T20:00:00-04:00")));
+    assert_eq!(format.timestamp(1574121600, 0, 0), Some(String::from("2019-11-18T19:00:00-05:00")));
+}
+
+#[test]
+fn epoch() {
+    let format = format("", "").unwrap();
+    assert_eq!(format.timestamp(1572566400, 5, 2), None);
+    assert_eq!(format.json_value(&value(), "timestamp(2)", BinaryEncoding::Hex), json!(1572566400.05));
+    assert_eq!(format.timestamp_sql(1572566400, 5, 2), "from_unixtime(1572566400.05)");
+}
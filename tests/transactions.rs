@@ -0,0 +1,62 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/10/14
+*/
+
+//按事务迭代: XA事务以XA_PREPARE_LOG_EVENT结束, 没有结束事件的事务(之后直接是GTID_EVENT或文件末尾)同样返回并标记为未完成
+#![cfg(feature = "test-support")]
+
+mod common;
+
+use std::io::Cursor;
+use mytest::prelude::*;
+use mytest::replication::readevent::QueryEvent;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+use common::{begin, gtid};
+
+fn query(command: &str) -> QueryEvent {
+    QueryEvent{ command: command.to_string(), ..begin("shop") }
+}
+
+//one_phase、format_id、gtrid_length、bqual_length、data
+fn xa_prepare() -> Vec<u8> {
+    let mut body = vec![0];
+    body.extend(1u32.to_le_bytes());
+    body.extend(3u32.to_le_bytes());
+    body.extend(0u32.to_le_bytes());
+    body.extend(b"trx");
+    body
+}
+
+//(gno, complete, 事件数)
+fn transactions(bytes: Vec<u8>) -> Vec<(u64, bool, usize)> {
+    BinlogReader::new(Cursor::new(bytes)).unwrap().transactions().map(|trx| {
+        let trx = trx.unwrap();
+        (trx.gtid.map(|t| t.gno_id).unwrap_or(0), trx.complete, trx.events.len())
+    }).collect()
+}
+
+#[test]
+fn xa() {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.gtid(&gtid(1)).query(&query("XA START X'747278',X'',1")).query(&query("XA END X'747278',X'',1"));
+    builder.event(38, &xa_prepare());
+    builder.gtid(&gtid(2)).query(&query("XA COMMIT X'747278',X'',1"));
+    builder.gtid(&gtid(3)).query(&begin("shop")).xid(&XidEvent{ xid: 3 });
+    assert_eq!(transactions(builder.into_bytes()), vec![(1, true, 4), (2, true, 2), (3, true, 3)]);
+}
+
+#[test]
+fn incomplete() {
+    //事务1没有结束的事件, 事务3在文件末尾
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.gtid(&gtid(1)).query(&begin("shop"));
+    builder.gtid(&gtid(2)).query(&query("CREATE TABLE t1 (id int)"));
+    builder.gtid(&gtid(3)).query(&begin("shop"));
+    assert_eq!(transactions(builder.into_bytes()), vec![(1, false, 2), (2, true, 2), (3, false, 2)]);
+
+    //没有gtid时以BEGIN开始
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.query(&begin("shop")).xid(&XidEvent{ xid: 1 }).query(&begin("shop"));
+    assert_eq!(transactions(builder.into_bytes()), vec![(0, true, 2), (0, false, 1)]);
+}
@@ -0,0 +1,83 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/11
+*/
+
+//tui的状态: 加载事务列表、按表过滤、跳转、展开事务
+
+#![cfg(feature = "tui")]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+use mytest::tui::Browser;
+
+fn synth_file(name: &str) -> PathBuf {
+    let opts = SynthOptions{
+        database: String::from("tui"),
+        tables: 3,
+        columns: vec![ColumnKind::Int, ColumnKind::Varchar(16)],
+        transactions: 60,
+        rows: (1, 3),
+        rate: 10,
+        mix: [6, 3, 1],
+        start_time: 1572998400,
+        seed: 7,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-tui-{}-{}.binlog", name, std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    path
+}
+
+#[test]
+fn browse() {
+    let path = synth_file("browse");
+    let mut browser = Browser::load(path.to_str().unwrap(), false, ChecksumClass::all()).unwrap();
+    assert!(browser.index.error.is_none());
+    let total = browser.visible().len();
+    assert!(total >= 60);
+    let tables: Vec<String> = browser.index.transactions.iter().flat_map(|t| t.tables.clone()).collect();
+    let table = tables[0].clone();
+
+    browser.set_filter(&table);
+    let filtered = browser.visible().len();
+    assert!(filtered > 0 && filtered < total);
+    assert!(browser.visible().iter().all(|t| t.tables.contains(&table)));
+    browser.set_filter("");
+    assert_eq!(browser.visible().len(), total);
+
+    //跳转到某个事务中间的position
+    let target = browser.index.transactions[30].clone();
+    assert!(browser.jump_position(target.start + 1));
+    assert_eq!(browser.selected_trx().unwrap().start, target.start);
+    assert!(!browser.jump_position(u64::MAX));
+
+    browser.move_by(-1000);
+    assert_eq!(browser.selected(), 0);
+    assert!(browser.jump_time(&target.timestamp.to_string()).unwrap());
+    assert!(browser.selected_trx().unwrap().timestamp >= target.timestamp);
+    assert!(browser.jump_time("2019-11-06 00:00:01").unwrap());
+    assert!(browser.selected_trx().unwrap().timestamp >= 1572998401);
+    assert!(browser.jump_time("yesterday").is_err());
+
+    browser.jump_position(target.start);
+    let lines = browser.expand().unwrap();
+    assert!(lines[0].trim_start().starts_with(&target.start.to_string()));
+    let rows = lines.iter().filter(|l| l.contains("insert: (") || l.contains("delete: (") || l.contains("after:  (")).count();
+    assert_eq!(rows, target.rows);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn truncated() {
+    let path = synth_file("truncated");
+    let data = fs::read(&path).unwrap();
+    fs::write(&path, &data[..data.len() - 10]).unwrap();
+    let browser = Browser::load(path.to_str().unwrap(), false, ChecksumClass::empty()).unwrap();
+    assert!(browser.index.error.is_some());
+    assert!(!browser.index.transactions.is_empty());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,52 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//新版本mysql增加的事件类型: 原始内容保存在UnknownEvent中, 按event_length跳过后继续解析, 统计读取到的数量
+#![cfg(feature = "test-support")]
+
+use std::io::Cursor;
+use mytest::prelude::*;
+use mytest::replication::readevent::{self, QueryEvent, UnknownEvent, XidEvent};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+fn read_all(bytes: Vec<u8>) -> std::io::Result<Vec<Event>> {
+    BinlogReader::new(Cursor::new(bytes))?.collect()
+}
+
+#[test]
+fn unknown_event() {
+    let query = QueryEvent{ thread_id: 10, execute_seconds: 0, database: String::from("db1"),
+        command: String::from("INSERT INTO t1 VALUES(1)"), invoker: String::new() };
+    for checksum in vec![false, true] {
+        let before = readevent::unknown_event_count();
+        let mut builder = BinlogBuilder::new(ServerVersion::Mysql80).checksum(checksum);
+        builder.event(200, b"future");
+        builder.query(&query);
+        builder.event(201, &[1, 2, 3]);
+        builder.xid(&XidEvent{ xid: 9 });
+        let events = read_all(builder.into_bytes()).unwrap();
+
+        let unknown: Vec<&UnknownEvent> = events.iter().filter_map(|e| match &e.data {
+            Traction::UnknownEvent(t) => Some(t),
+            _ => None
+        }).collect();
+        assert_eq!(unknown, vec![
+            &UnknownEvent{ type_code: 200, bytes: b"future".to_vec() },
+            &UnknownEvent{ type_code: 201, bytes: vec![1, 2, 3] },
+        ]);
+        assert!(events.iter().any(|e| matches!(&e.data, Traction::QueryEvent(q) if q.command == query.command)));
+        assert!(events.iter().any(|e| matches!(&e.data, Traction::XidEvent(x) if x.xid == 9)));
+        assert_eq!(readevent::unknown_event_count() - before, 2);
+
+        //事件之间的位置连续
+        for pair in events.windows(2) {
+            assert_eq!(pair[0].header.next_position, pair[1].header.next_position - pair[1].header.event_length);
+        }
+        let header = &events.iter().find(|e| matches!(e.data, Traction::UnknownEvent(_))).unwrap().header;
+        assert_eq!(header.raw_type, 200);
+        assert_eq!(header.event_length as usize, 19 + 6 + if checksum { 4 } else { 0 });
+    }
+    assert_eq!(readevent::unknown_event_types(), vec![200, 201]);
+}
@@ -0,0 +1,88 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//--until-gtid/--until-position: 在包含边界的事务结束后停止, 边界在事务中间时读取完该事务
+
+#![cfg(all(feature = "network", feature = "test-support"))]
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use mytest::Config;
+use mytest::gtid::{ExecutedTracker, Until};
+use mytest::prelude::*;
+use mytest::recording::{Recorder, Recording, Start};
+use mytest::stream::BinlogStream;
+use common::{inserts, SID};
+
+//按录制的数据包重放, 返回读取到的事务的gno, 以及是否在until处结束
+fn replay(name: &str, until: Config) -> (Vec<u64>, bool) {
+    let data = inserts(1..=4).into_bytes();
+    let path = env::temp_dir().join(format!("mytest_until_{}_{}", name, std::process::id()));
+    let start = Start{ binlogfile: String::from("mysql-bin.000001"), position: String::from("4"), ..Start::default() };
+    let recorder = Recorder::create(&path, &start).unwrap();
+    for event in BinlogReader::new(Cursor::new(data.clone())).unwrap() {
+        let event = event.unwrap();
+        let end = event.header.next_position as usize;
+        let mut packet = vec![0u8];
+        packet.extend_from_slice(&data[end - event.header.event_length as usize..end]);
+        recorder.record(&packet).unwrap();
+    }
+    drop(recorder);
+    let mut stream = BinlogStream::replay(Recording::open(&path).unwrap(), &until).unwrap();
+    let gnos = stream.by_ref().map(|e| e.unwrap()).filter_map(|e| match e.data {
+        Traction::XidEvent(t) => Some(t.xid),
+        _ => None
+    }).collect();
+    let reached = stream.until_reached();
+    fs::remove_file(&path).unwrap();
+    (gnos, reached)
+}
+
+//第n个事务中table_map的position
+fn table_map_position(n: usize) -> u64 {
+    BinlogReader::new(Cursor::new(inserts(1..=4).into_bytes())).unwrap().map(|e| e.unwrap())
+        .filter(|e| matches!(e.data, Traction::TableMapEvent(_))).nth(n - 1).unwrap().log_pos
+}
+
+#[test]
+fn gtid() {
+    let (gnos, reached) = replay("gtid", Config{ untilgtid: format!("{}:1-2", SID), ..Config::default() });
+    assert_eq!((gnos, reached), (vec![1, 2], true));
+    //不在binlog中的事务: 读取到结束
+    let (gnos, reached) = replay("beyond", Config{ untilgtid: format!("{}:1-9", SID), ..Config::default() });
+    assert_eq!((gnos, reached), (vec![1, 2, 3, 4], false));
+}
+
+#[test]
+fn position() {
+    //边界在第3个事务中间, 读取完该事务
+    let until = format!("mysql-bin.000001:{}", table_map_position(3));
+    let (gnos, reached) = replay("position", Config{ untilposition: until, ..Config::default() });
+    assert_eq!((gnos, reached), (vec![1, 2, 3], true));
+    //注册的位置已经超过边界时不读取任何事件
+    let (gnos, reached) = replay("passed", Config{ untilposition: String::from("mysql-bin.000000:4"), ..Config::default() });
+    assert_eq!((gnos, reached), (vec![], true));
+}
+
+#[test]
+fn from_config() {
+    assert_eq!(Until::from_config(&Config::default()).unwrap(), None);
+    let until = Until::from_config(&Config{ untilposition: String::from("mysql-bin.000003:1234"), ..Config::default() }).unwrap();
+    assert_eq!(until, Some(Until::Position(String::from("mysql-bin.000003"), 1234)));
+    assert!(Until::from_config(&Config{ untilposition: String::from("mysql-bin.000003"), ..Config::default() }).is_err());
+    assert!(Until::from_config(&Config{ untilgtid: String::from("x"), ..Config::default() }).is_err());
+    assert!(Until::from_config(&Config{ untilgtid: format!("{}:1", SID), untilposition: String::from("a:4"), ..Config::default() }).is_err());
+
+    //事务中间不停止
+    let until = Until::Gtids(format!("{}:1", SID).parse().unwrap());
+    let mut tracker = ExecutedTracker::new();
+    tracker.observe(&Traction::GtidEvent(common::gtid(1)), 100);
+    assert!(!until.reached(&tracker));
+    tracker.observe(&Traction::XidEvent(XidEvent{ xid: 1 }), 200);
+    assert!(until.reached(&tracker));
+}
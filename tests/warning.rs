@@ -0,0 +1,95 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//警告与数据输出分开: 按类型交给回调并计数, 数据中不包含警告
+#![cfg(feature = "test-support")]
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use mytest::Config;
+use mytest::prelude::*;
+use mytest::replication::readevent::XidEvent;
+use mytest::replication::verify;
+use mytest::stdout::format::BinaryEncoding;
+use mytest::testing::{BinlogBuilder, ServerVersion};
+use mytest::warning::{self, Warning, WarningKind};
+
+//处理回调及计数是全局的, 各测试依次执行
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn collect<F: FnOnce()>(f: F) -> Vec<Warning> {
+    let received = Arc::new(Mutex::new(vec![]));
+    let sink = received.clone();
+    warning::set_handler(move |w| sink.lock().unwrap().push(w.clone()));
+    f();
+    warning::reset_handler();
+    let warnings = received.lock().unwrap().clone();
+    warnings
+}
+
+fn binlog() -> Vec<u8> {
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql57);
+    builder.timestamp(1573516800);
+    builder.event(200, b"future");
+    builder.xid(&XidEvent{ xid: 1 });
+    builder.xid(&XidEvent{ xid: 2 });
+    builder.into_bytes()
+}
+
+fn read_all(data: &[u8]) -> Result<usize, String> {
+    let reader = BinlogReader::new(Cursor::new(data.to_vec())).unwrap().verify_checksum(ChecksumClass::all());
+    let mut events = 0;
+    for event in reader {
+        event.map_err(|e| e.to_string())?;
+        events += 1;
+    }
+    Ok(events)
+}
+
+#[test]
+fn parse_warnings() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let before = warning::count(WarningKind::UnknownEvent);
+    let mut events = 0;
+    let warnings = collect(|| events = read_all(&binlog()).unwrap());
+    assert_eq!(events, 4);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].kind, WarningKind::UnknownEvent);
+    assert!(warnings[0].message.contains("200"));
+    assert!(warnings[0].to_string().starts_with("警告(unknown_event)"));
+    assert_eq!(warning::count(WarningKind::UnknownEvent) - before, 1);
+    assert!(warning::stats_json()["unknown_event"].as_u64().unwrap() >= 1);
+
+    //宽松模式下checksum错误为警告
+    let mut data = binlog();
+    let len = data.len();
+    data[len - 1] ^= 0xff;
+    assert!(read_all(&data).unwrap_err().contains("checksum错误"));
+    verify::set_lenient(true);
+    let mut result = Ok(0);
+    let warnings = collect(|| result = read_all(&data));
+    verify::set_lenient(false);
+    assert_eq!(result.unwrap(), 4);
+    assert_eq!(warnings.iter().filter(|w| w.kind == WarningKind::ChecksumMismatch).count(), 1);
+    assert!(warnings.iter().any(|w| w.message.contains(&format!("position {} 处的事件checksum错误", len - 31))));
+}
+
+#[test]
+fn value_warnings() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let format = ValueFormat::new(&Config{ lobmaxsize: String::from("4"), ..Config::default() }).unwrap();
+    let mut values = vec![];
+    let warnings = collect(|| {
+        values.push(format.text_value(&Some(MySQLValue::Blob(b"abc".to_vec())), "varchar(10)", BinaryEncoding::Hex));
+        values.push(format.text_value(&Some(MySQLValue::Blob(vec![b'a', 0xff])), "varchar(10)", BinaryEncoding::Hex));
+        values.push(format.text_value(&Some(MySQLValue::Blob(b"abcdefgh".to_vec())), "text", BinaryEncoding::Hex));
+        values.push(Some(mytest::stdout::sql_escape::quote_text(&[0xff])));
+    });
+    //输出的数据中不包含警告
+    assert_eq!(values, vec![Some(String::from("abc")), Some(String::from("a\u{fffd}")),
+                            Some(String::from("abcd...[truncated, 8 bytes]")), Some(String::from("x'ff'"))]);
+    assert_eq!(warnings.iter().map(|w| w.kind).collect::<Vec<_>>(),
+               vec![WarningKind::CharsetFallback, WarningKind::TruncatedBlob, WarningKind::CharsetFallback]);
+}
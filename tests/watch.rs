@@ -0,0 +1,108 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/12
+*/
+
+//--watch的规则匹配: DDL语句按关键字及库表匹配, 行数按事务累计后与阈值比较
+
+#![cfg(feature = "os")]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use serde_json::Value as JsonValue;
+use mytest::prelude::*;
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+use mytest::stdout::watch::{self, Watchdog};
+
+fn fixture() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("mysql57.binlog")
+}
+
+fn alerts(path: &PathBuf, rules: &str) -> Vec<JsonValue> {
+    let mut watchdog = Watchdog::from_json(rules, "test", "bin.000001").unwrap();
+    let reader = BinlogReader::open(path.to_str().unwrap()).unwrap();
+    let mut alerts = vec![];
+    for event in reader {
+        let event = event.unwrap();
+        let db_tbl = event.table.as_ref().map(|t| format!("{}.{}", t.database_name, t.table_name)).unwrap_or_default();
+        alerts.extend(watchdog.observe(&event.data, &db_tbl, event.header.timestamp, event.log_pos));
+    }
+    alerts
+}
+
+#[test]
+fn statements() {
+    let path = fixture();
+    let found = alerts(&path, r#"{"rules": [{"name": "create", "statements": ["create table"], "databases": ["golden"]}]}"#);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["rule"], "create");
+    assert_eq!(found[0]["kind"], "statement");
+    assert_eq!(found[0]["gtid"], "3e11fa47-71ca-11e1-9e33-c80aa9429562:1");
+    assert_eq!(found[0]["database"], "golden");
+    assert!(found[0]["query"].as_str().unwrap().starts_with("CREATE TABLE all_types"));
+    assert!(watch::alert_text(&found[0]).starts_with("[mytest watch] create: CREATE TABLE all_types"));
+
+    assert!(alerts(&path, r#"{"rules": [{"statements": ["CREATE"], "databases": ["prod"]}]}"#).is_empty());
+    assert!(alerts(&path, r#"{"rules": [{"statements": ["CREATE"], "tables": ["orders"]}]}"#).is_empty());
+    assert!(alerts(&path, r#"{"rules": [{"statements": ["DROP", "TRUNCATE"]}]}"#).is_empty());
+    assert_eq!(alerts(&path, r#"{"rules": [{"statements": "CREATE", "tables": ["golden.all_types"]}]}"#).len(), 1);
+}
+
+#[test]
+fn row_thresholds() {
+    let opts = SynthOptions{
+        database: String::from("watch"),
+        tables: 2,
+        columns: vec![ColumnKind::Int],
+        transactions: 20,
+        rows: (3, 3),
+        rate: 10,
+        mix: [0, 1, 1],
+        start_time: 1572998400,
+        seed: 3,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-watch-{}.binlog", std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+
+    //表中没有数据时生成的是insert
+    let found = alerts(&path, r#"{"rules": [{"name": "mass", "delete_rows": 2, "update_rows": 2}]}"#);
+    assert!(found.len() > 10 && found.len() <= 20);
+    for alert in &found {
+        assert_eq!(alert["kind"], "rows");
+        assert_eq!(alert["delete_rows"].as_u64().unwrap() + alert["update_rows"].as_u64().unwrap(), 3);
+        assert!(alert["commit_position"].as_u64().unwrap() > alert["position"].as_u64().unwrap());
+    }
+    let deletes = found.iter().filter(|a| a["delete_rows"] == 3).count();
+    assert_eq!(alerts(&path, r#"{"rules": [{"delete_rows": 2}]}"#).len(), deletes);
+    assert!(alerts(&path, r#"{"rules": [{"delete_rows": 3, "update_rows": 3}]}"#).is_empty());
+    assert!(alerts(&path, r#"{"rules": [{"delete_rows": 0, "databases": ["prod"]}]}"#).is_empty());
+    let t1 = alerts(&path, r#"{"rules": [{"delete_rows": 0, "update_rows": 0, "tables": ["t1"]}]}"#);
+    assert!(!t1.is_empty() && t1.len() < found.len());
+    assert!(t1.iter().all(|a| a["tables"].as_object().unwrap().keys().all(|k| k == "watch.t1")));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn invalid_rules() {
+    assert!(Watchdog::from_json("{}", "test", "").is_err());
+    assert!(Watchdog::from_json(r#"{"rules": [{"name": "empty"}]}"#, "test", "").is_err());
+    assert!(Watchdog::from_json(r#"{"rules": [{"delete_rows": -1}]}"#, "test", "").is_err());
+    assert!(Watchdog::from_json(r#"{"rules": [{"statements": [1]}]}"#, "test", "").is_err());
+    assert!(Watchdog::from_json(r#"{"rules": [{"delete_rows": 1}], "notify": [{"type": "pager"}]}"#, "test", "").is_err());
+    assert!(Watchdog::from_json(r#"{"rules": [{"delete_rows": 1}], "notify": [{"type": "slack"}]}"#, "test", "").is_err());
+    assert!(Watchdog::from_json(r#"{"rules": [{"delete_rows": 1}], "notify": [{"type": "email", "to": "dba@example.com"}, {"type": "command", "command": "cat"}]}"#, "test", "").is_ok());
+}
+
+#[test]
+fn notify_command() {
+    let path = env::temp_dir().join(format!("mytest-watch-notify-{}.json", std::process::id()));
+    let found = alerts(&fixture(), r#"{"rules": [{"name": "create", "statements": ["CREATE"]}]}"#);
+    let notifier = watch::Notifier::Command{ command: format!("cat > {}", path.display()) };
+    notifier.send(&found[0]).unwrap();
+    let sent: JsonValue = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(sent, found[0]);
+    fs::remove_file(&path).unwrap();
+    assert!(watch::Notifier::Command{ command: String::from("exit 3") }.send(&found[0]).is_err());
+}
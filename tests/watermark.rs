@@ -0,0 +1,164 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//事件时间水位: 已提交事务的最大提交时间, 只增不减, 在事务之间写入; 多个源合并输出时为各源水位的最小值
+
+#![cfg(all(feature = "os", feature = "json-sink"))]
+
+use std::env;
+use std::fs;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde_json::Value as JsonValue;
+use mytest::prelude::*;
+use mytest::sink::{parse_watermark, OrderedMerge, WatermarkTracker};
+use mytest::replication::synthesize::{self, ColumnKind, SynthOptions};
+
+fn events(name: &str, start_time: u32, seed: u64) -> Vec<Event> {
+    let opts = SynthOptions{
+        database: String::from("wm"),
+        tables: 1,
+        columns: vec![ColumnKind::Int],
+        transactions: 30,
+        rows: (1, 2),
+        rate: 5,
+        mix: [2, 1, 1],
+        start_time,
+        seed,
+        server_id: 133,
+    };
+    let path = env::temp_dir().join(format!("mytest-watermark-{}-{}.binlog", name, std::process::id()));
+    synthesize::write_file(path.to_str().unwrap(), &opts).unwrap();
+    let events = BinlogReader::open(path.to_str().unwrap()).unwrap().map(|e| e.unwrap()).collect();
+    fs::remove_file(&path).unwrap();
+    events
+}
+
+fn query(command: &str) -> Traction {
+    Traction::QueryEvent(QueryEvent{ thread_id: 1, execute_seconds: 0, database: String::new(), command: command.to_string(), invoker: String::new() })
+}
+
+#[test]
+fn tracker() {
+    let mut tracker = WatermarkTracker::new(5);
+    assert!(tracker.watermark(0).is_none());
+    tracker.observe(&query("BEGIN"), 1572998400, 0);
+    assert!(tracker.in_transaction());
+    assert!(tracker.watermark(0).is_none());
+    tracker.observe(&Traction::XidEvent(XidEvent{ xid: 1 }), 1572998410, 0);
+    assert_eq!(tracker.watermark(0).unwrap().timestamp, 1572998405);
+    //提交时间回退时水位不变
+    tracker.observe(&query("BEGIN"), 1572998401, 0);
+    tracker.observe(&Traction::XidEvent(XidEvent{ xid: 2 }), 1572998401, 0);
+    assert!(!tracker.in_transaction());
+    assert_eq!(tracker.watermark(0).unwrap().timestamp, 1572998405);
+    //DDL同样为提交
+    tracker.observe(&query("ALTER TABLE t ADD COLUMN c int"), 1572998420, 0);
+    assert_eq!(tracker.watermark(0).unwrap().timestamp, 1572998415);
+
+    assert_eq!(parse_watermark("", "").unwrap(), None);
+    assert_eq!(parse_watermark("0.5", "3").unwrap(), Some((Duration::from_millis(500), Duration::from_secs(3))));
+    assert!(parse_watermark("0", "").is_err());
+    assert!(parse_watermark("", "3").unwrap_err().contains("watermark"));
+    assert!(parse_watermark("1", "-1").is_err());
+}
+
+#[test]
+fn between_transactions() {
+    let mut buf = vec![];
+    let format = ValueFormat{ boundaries: true, labels: mytest::labels::Labels::parse("env=test").unwrap(), ..ValueFormat::default() };
+    let mut sink = WatermarkSink::new(JsonLinesSink::new(&mut buf, format), Duration::from_secs(0));
+    for event in events("json", 1572998400, 7) {
+        sink.write_event(&event).unwrap();
+    }
+    drop(sink);
+
+    let records: Vec<JsonValue> = String::from_utf8(buf).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let mut watermark = 0;
+    let mut watermarks = 0;
+    let mut open = false;
+    let mut last_commit = JsonValue::Null;
+    for record in &records {
+        match record["type"].as_str().unwrap() {
+            "watermark" => {
+                assert!(!open);
+                let value = record["watermark"].as_u64().unwrap();
+                assert!(value >= watermark);
+                watermark = value;
+                watermarks += 1;
+                assert_eq!(record["labels"]["env"], "test");
+                if !last_commit.is_null() {
+                    assert_eq!(record["gtid"], last_commit["gtid"]);
+                }
+            }
+            "begin" => open = true,
+            "commit" => {
+                open = false;
+                //之后的事务的提交时间不早于水位
+                assert!(record["timestamp"].as_u64().unwrap() >= watermark);
+                last_commit = record.clone();
+            }
+            _ => assert!(open),
+        }
+    }
+    //开头的CREATE TABLE也会推进水位
+    assert!(watermarks >= 30);
+    assert_eq!(watermark, last_commit["timestamp"].as_u64().unwrap());
+}
+
+//记录写入的提交时间及水位
+struct Recorder{
+    output: Arc<Mutex<Vec<(&'static str, u32)>>>,
+}
+
+impl Sink for Recorder {
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        if let Traction::XidEvent(_) = event.data {
+            self.output.lock().unwrap().push(("commit", event.header.timestamp));
+        }
+        Ok(())
+    }
+
+    fn write_watermark(&mut self, watermark: &Watermark) -> io::Result<()> {
+        assert!(watermark.gtid.is_empty());
+        self.output.lock().unwrap().push(("watermark", watermark.timestamp));
+        Ok(())
+    }
+}
+
+#[test]
+fn merged() {
+    let output = Arc::new(Mutex::new(vec![]));
+    let merge = OrderedMerge::new(Duration::from_secs(3600));
+    let mut a = WatermarkSink::new(merge.source(Recorder{ output: output.clone() }), Duration::from_secs(0));
+    let mut b = WatermarkSink::new(merge.source(Recorder{ output: output.clone() }), Duration::from_secs(0));
+    //b还没有水位时不输出
+    for event in events("a", 1572998400, 3) {
+        a.write_event(&event).unwrap();
+    }
+    assert!(output.lock().unwrap().iter().all(|(kind, _)| *kind != "watermark"));
+    let b_events = events("b", 1572998402, 5);
+    let b_end = b_events.iter().map(|e| e.header.timestamp).max().unwrap();
+    for event in b_events {
+        b.write_event(&event).unwrap();
+    }
+    a.flush().unwrap();
+    b.flush().unwrap();
+
+    let output = output.lock().unwrap();
+    let watermarks: Vec<u32> = output.iter().filter(|(kind, _)| *kind == "watermark").map(|(_, t)| *t).collect();
+    assert!(!watermarks.is_empty());
+    //严格递增, 不超过两个源结束时的最小值
+    assert!(watermarks.windows(2).all(|w| w[0] < w[1]));
+    assert!(*watermarks.last().unwrap() <= b_end.min(1572998400 + 6));
+    let mut watermark = 0;
+    for (kind, timestamp) in output.iter() {
+        match *kind {
+            "watermark" => watermark = *timestamp,
+            _ => assert!(*timestamp >= watermark),
+        }
+    }
+}
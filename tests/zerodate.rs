@@ -0,0 +1,149 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/13
+*/
+
+//DATE、DATETIME、TIMESTAMP的零值及无效日期: 解析时原样保留, 按--zerodate在json、csv、sql中一致地原样输出、输出为null或停止解析
+#![cfg(all(feature = "json-sink", feature = "test-support"))]
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use serde_json::{json, Value as JsonValue};
+use mytest::Config;
+use mytest::meta::ColumnTypeDict;
+use mytest::prelude::*;
+use mytest::replication::readevent::ColumnInfo;
+use mytest::stdout::format::{self, BinaryEncoding, TimeZoneMode, ZeroDate};
+use mytest::stdout::outsql::{self, InsertMode};
+use mytest::testing::{BinlogBuilder, ServerVersion};
+
+fn table() -> TableMap {
+    TableMap{
+        database_name: String::from("test"),
+        table_name: String::from("t"),
+        column_count: 4,
+        column_info: vec![
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeLong, column_meta: vec![0] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeDate, column_meta: vec![] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeDatetime2, column_meta: vec![0] },
+            ColumnInfo{ column_type: ColumnTypeDict::MysqlTypeTimestamp2, column_meta: vec![0] },
+        ],
+        metadata: None,
+    }
+}
+
+fn rows() -> Vec<Vec<Option<MySQLValue>>> {
+    vec![
+        vec![Some(MySQLValue::SignedInteger(1)), Some(MySQLValue::Date{ year: 2019, month: 11, day: 1 }),
+             Some(MySQLValue::DateTime{ year: 2019, month: 11, day: 1, hour: 8, minute: 0, second: 0, subsecond: 0 }),
+             Some(MySQLValue::Timestamp{ unix_time: 1572566400, subsecond: 0 })],
+        vec![Some(MySQLValue::SignedInteger(2)), Some(MySQLValue::Date{ year: 0, month: 0, day: 0 }),
+             Some(MySQLValue::DateTime{ year: 0, month: 0, day: 0, hour: 0, minute: 0, second: 0, subsecond: 0 }),
+             Some(MySQLValue::Timestamp{ unix_time: 0, subsecond: 0 })],
+        vec![Some(MySQLValue::SignedInteger(3)), Some(MySQLValue::Date{ year: 2019, month: 2, day: 30 }),
+             Some(MySQLValue::DateTime{ year: 2019, month: 11, day: 0, hour: 8, minute: 0, second: 0, subsecond: 0 }),
+             Some(MySQLValue::Timestamp{ unix_time: 1572566400, subsecond: 0 })],
+    ]
+}
+
+fn binlog() -> Vec<u8> {
+    let map = table();
+    let mut builder = BinlogBuilder::new(ServerVersion::Mysql80);
+    builder.table_map(100, &map).unwrap();
+    builder.rows(&BinlogEvent::WriteEvent, 100, &map, &rows()).unwrap();
+    builder.into_bytes()
+}
+
+fn format(mode: &str) -> ValueFormat {
+    ValueFormat::new(&Config{ zerodate: mode.to_string(), timezone: String::from("UTC"), ..Config::default() }).unwrap()
+}
+
+fn cols() -> Vec<HashMap<String, String>> {
+    ["id", "d", "dt", "ts"].iter().zip(["int(11)", "date", "datetime", "timestamp"].iter()).map(|(name, col_type)| {
+        let mut col = HashMap::new();
+        col.insert(String::from("COLUMN_NAME"), name.to_string());
+        col.insert(String::from("COLUMN_TYPE"), col_type.to_string());
+        col.insert(String::from("COLUMN_KEY"), String::new());
+        col.insert(String::from("EXTRA"), String::new());
+        col
+    }).collect()
+}
+
+#[test]
+fn decode() {
+    //DATE的零值不再解析为NULL
+    let reader = BinlogReader::new(Cursor::new(binlog())).unwrap();
+    let decoded = reader.filter_map(|e| match e.unwrap().data {
+        Traction::RowEvent(_, t) => Some(t.rows().to_vec()),
+        _ => None
+    }).next().unwrap();
+    assert_eq!(decoded, rows());
+
+    assert!(!format::is_invalid_temporal(rows()[0][1].as_ref().unwrap()));
+    assert!(rows()[1][1..].iter().all(|t| format::is_invalid_temporal(t.as_ref().unwrap())));
+    assert!(format::is_invalid_temporal(rows()[2][1].as_ref().unwrap()));
+    assert!(format::is_invalid_temporal(rows()[2][2].as_ref().unwrap()));
+}
+
+#[test]
+fn modes() {
+    let zero = rows()[1].clone();
+    let literal = format("");
+    assert_eq!(literal.zerodate, ZeroDate::Literal);
+    assert_eq!(literal.json_value(&zero[1], "date", BinaryEncoding::Hex), json!("0000-00-00"));
+    assert_eq!(literal.text_value(&zero[2], "datetime", BinaryEncoding::Hex), Some(String::from("0000-00-00 00:00:00")));
+    //零值的TIMESTAMP不按时区转换为1970-01-01
+    assert_eq!(literal.json_value(&zero[3], "timestamp", BinaryEncoding::Hex), json!("0000-00-00 00:00:00"));
+    let epoch = ValueFormat{ timezone: TimeZoneMode::Epoch, ..literal.clone() };
+    assert_eq!(epoch.timestamp_sql(0, 0, 0), "'0000-00-00 00:00:00'");
+    assert_eq!(outsql::out_insert(&zero, &cols(), &table(), InsertMode::Insert, &literal),
+               "INSERT INTO `test`.`t`(`id`,`d`,`dt`,`ts`) VALUES(2,'0000-00-00','0000-00-00 00:00:00','0000-00-00 00:00:00');");
+
+    let null = format("null");
+    assert_eq!(null.json_value(&zero[1], "date", BinaryEncoding::Hex), JsonValue::Null);
+    assert_eq!(null.text_value(&zero[3], "timestamp", BinaryEncoding::Hex), None);
+    assert_eq!(null.text_value(&rows()[2][1], "date", BinaryEncoding::Hex), None);
+    assert_eq!(null.json_value(&rows()[0][1], "date", BinaryEncoding::Hex), json!("2019-11-01"));
+    assert_eq!(outsql::out_insert(&zero, &cols(), &table(), InsertMode::Insert, &null),
+               "INSERT INTO `test`.`t`(`id`,`d`,`dt`,`ts`) VALUES(2,NULL,NULL,NULL);");
+
+    assert!(ValueFormat::new(&Config{ zerodate: String::from("zero"), ..Config::default() }).unwrap_err().contains("zerodate"));
+    assert!(format("null").check_temporal(&rows(), "test.t").is_ok());
+    assert!(format("error").check_temporal(&rows()[..1], "test.t").is_ok());
+    let err = format("error").check_temporal(&rows(), "test.t").unwrap_err();
+    assert!(err.contains("test.t第2个字段的值为0000-00-00"), "{}", err);
+}
+
+#[test]
+fn sink() {
+    let write = |mode: &str| -> (std::io::Result<()>, Vec<JsonValue>) {
+        let mut buf = vec![];
+        let mut result = Ok(());
+        {
+            let mut sink = JsonLinesSink::new(&mut buf, format(mode));
+            for event in BinlogReader::new(Cursor::new(binlog())).unwrap() {
+                result = result.and_then(|_| sink.write_event(&event.unwrap()));
+            }
+        }
+        let records = String::from_utf8(buf).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        (result, records)
+    };
+
+    let (result, records) = write("literal");
+    assert!(result.is_ok());
+    assert_eq!(records[1]["data"]["@2"], json!("0000-00-00"));
+    assert_eq!(records[2]["data"]["@2"], json!("2019-02-30"));
+    assert_eq!(records[2]["data"]["@3"], json!("2019-11-00 08:00:00"));
+
+    let (_, records) = write("null");
+    assert_eq!(records[0]["data"]["@2"], json!("2019-11-01"));
+    assert_eq!(records[1]["data"]["@2"], JsonValue::Null);
+    assert_eq!(records[1]["data"]["@4"], JsonValue::Null);
+    assert_eq!(records[2]["data"]["@3"], JsonValue::Null);
+    assert_eq!(records[2]["data"]["@4"], json!("2019-11-01 00:00:00"));
+
+    //整个行事件都不输出
+    let (result, records) = write("error");
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    assert!(records.is_empty());
+}
@@ -0,0 +1,86 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/10
+*/
+
+//wasm接口: 在浏览器中解析用户选择的binlog文件, 数据不需要上传到服务端
+//  事件以json字符串返回, 格式见mytest::stdout::outjson::event_json
+
+use std::io::{self, Cursor};
+use serde_json::{json, Value as JsonValue};
+use wasm_bindgen::prelude::*;
+use mytest::prelude::*;
+use mytest::replication::verify::{self, VerifyReport};
+use mytest::stdout::outjson;
+
+/// 按事件读取内存中的binlog文件内容
+#[wasm_bindgen]
+pub struct BinlogInspector{
+    reader: BinlogReader<Cursor<Vec<u8>>>,
+    format: ValueFormat,
+    failed: bool,
+}
+
+impl BinlogInspector {
+    pub fn open(data: Vec<u8>, relaylog: bool, verify_checksum: &str) -> Result<BinlogInspector, String> {
+        let classes = ChecksumClass::parse(verify_checksum)?;
+        let reader = BinlogReader::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+        Ok(BinlogInspector{ reader: reader.relaylog(relaylog).verify_checksum(classes), format: ValueFormat::default(), failed: false })
+    }
+
+    pub fn next_json(&mut self) -> Result<Option<JsonValue>, String> {
+        if self.failed {
+            return Ok(None);
+        }
+        match self.reader.next() {
+            Some(Ok(event)) => Ok(Some(outjson::event_json(&event, &self.format))),
+            Some(Err(e)) => {
+                //出错之后不再读取
+                self.failed = true;
+                Err(e.to_string())
+            }
+            None => Ok(None)
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl BinlogInspector {
+    /// verify_checksum与命令行的--verify-checksum相同: all、none、rows,table_map...
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: Vec<u8>, relaylog: bool, verify_checksum: &str) -> Result<BinlogInspector, JsError> {
+        BinlogInspector::open(data, relaylog, verify_checksum).map_err(|e| JsError::new(&e))
+    }
+
+    /// 下一个事件的json, 读取结束返回undefined, 文件损坏、checksum错误时抛出异常
+    #[wasm_bindgen(js_name = nextEvent)]
+    pub fn next_event(&mut self) -> Result<Option<String>, JsError> {
+        self.next_json().map(|t| t.map(|v| v.to_string())).map_err(|e| JsError::new(&e))
+    }
+
+    /// 读取结束的原因: stop、rotate、eof、truncated
+    #[wasm_bindgen(js_name = fileEnd)]
+    pub fn file_end(&self) -> Option<String> {
+        self.reader.file_end().map(|t| t.reason().to_string())
+    }
+}
+
+/// 校验binlog文件内容, 结果与verify命令相同
+pub fn verify_json(data: &[u8], relaylog: bool, verify_checksum: &str) -> Result<JsonValue, String> {
+    let classes = ChecksumClass::parse(verify_checksum)?;
+    let report: VerifyReport = verify::verify_reader(io::Cursor::new(data), relaylog, classes).map_err(|e| e.to_string())?;
+    Ok(json!({
+        "ok": report.is_ok(),
+        "events": report.events,
+        "checksum": report.checksum,
+        "verified": report.verified,
+        "checksum_errors": report.checksum_errors,
+        "position_errors": report.position_errors,
+        "truncated": report.truncated,
+    }))
+}
+
+#[wasm_bindgen]
+pub fn verify(data: &[u8], relaylog: bool, verify_checksum: &str) -> Result<String, JsError> {
+    verify_json(data, relaylog, verify_checksum).map(|v| v.to_string()).map_err(|e| JsError::new(&e))
+}
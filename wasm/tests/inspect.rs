@@ -0,0 +1,58 @@
+/*
+@author: xiao cai niao
+@datetime: 2019/11/10
+*/
+
+//wasm接口内部的解析逻辑, 在本机运行
+
+use std::fs;
+use std::path::PathBuf;
+use binlogal_wasm::{BinlogInspector, verify_json};
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("..").join("tests").join("fixtures").join(name)).unwrap()
+}
+
+#[test]
+fn inspect() {
+    let mut inspector = BinlogInspector::open(fixture("mysql80.binlog"), false, "all").unwrap();
+    let mut events = vec![];
+    while let Some(event) = inspector.next_json().unwrap() {
+        events.push(event);
+    }
+    assert_eq!(events[0]["log_pos"], 4);
+    assert!(events.iter().any(|e| e["type"] == "insert" && e["table"] == "all_types"));
+    assert!(inspector.file_end().is_some());
+
+    let mut data = fixture("mysql80.binlog");
+    data.truncate(data.len() - 3);
+    let mut inspector = BinlogInspector::open(data, false, "none").unwrap();
+    let mut read = 0;
+    let err = loop {
+        match inspector.next_json() {
+            Ok(Some(_)) => read += 1,
+            Ok(None) => panic!("截断的文件应该返回错误"),
+            Err(e) => break e
+        }
+    };
+    assert_eq!(read, events.len() - 1);
+    assert!(!err.is_empty());
+    assert_eq!(inspector.next_json(), Ok(None));
+
+    assert!(BinlogInspector::open(b"not a binlog".to_vec(), false, "none").is_err());
+    assert!(BinlogInspector::open(fixture("mysql80.binlog"), false, "rows,xid").is_err());
+}
+
+#[test]
+fn verify() {
+    let mut data = fixture("mysql57.binlog");
+    let report = verify_json(&data, false, "all").unwrap();
+    assert_eq!(report["ok"], true);
+    assert_eq!(report["checksum"], true);
+
+    let len = data.len();
+    data.truncate(len - 3);
+    let report = verify_json(&data, false, "all").unwrap();
+    assert_eq!(report["ok"], false);
+    assert!(report["truncated"].is_u64());
+}